@@ -0,0 +1,324 @@
+//! Agent Context Attachments
+//!
+//! This module models the context a user attaches to an agent query: command
+//! blocks, file paths, images and text selections. Attachments are shown as chips in
+//! the agent input and serialized into the request under a token budget, so
+//! that large outputs or files are truncated rather than crowding out the
+//! question itself.
+
+use std::path::{Path, PathBuf};
+
+/// Rough characters-per-token ratio used for budgeting.
+const CHARS_PER_TOKEN: usize = 4;
+/// Longest label shown on a chip.
+const CHIP_LABEL_LEN: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attachment {
+    Block { command: String, output: String },
+    File { path: PathBuf },
+    /// Sent as an image to models that accept them, not as text.
+    Image { path: PathBuf },
+    Selection { text: String, source: Option<String> },
+}
+
+/// A compact label for an attachment, shown in the agent input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip {
+    pub icon: &'static str,
+    pub label: String,
+}
+
+impl std::fmt::Display for Chip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{} {}]", self.icon, self.label)
+    }
+}
+
+impl Attachment {
+    pub fn chip(&self) -> Chip {
+        match self {
+            Attachment::Block { command, .. } => Chip {
+                icon: "▤",
+                label: shorten(command),
+            },
+            Attachment::File { path } => Chip {
+                icon: "📄",
+                label: shorten(&file_name(path)),
+            },
+            Attachment::Image { path } => Chip {
+                icon: "🖼",
+                label: shorten(&file_name(path)),
+            },
+            Attachment::Selection { text, .. } => Chip {
+                icon: "✂",
+                label: shorten(text.lines().next().unwrap_or_default()),
+            },
+        }
+    }
+
+    fn header(&self) -> String {
+        match self {
+            Attachment::Block { command, .. } => format!("Command block `{}`", command),
+            Attachment::File { path } => format!("File `{}`", path.display()),
+            Attachment::Image { path } => format!("Image `{}`", path.display()),
+            Attachment::Selection { source: Some(source), .. } => format!("Selection from {}", source),
+            Attachment::Selection { source: None, .. } => "Selection".to_string(),
+        }
+    }
+
+    fn content(&self) -> String {
+        match self {
+            Attachment::Block { output, .. } => output.clone(),
+            Attachment::File { path } => std::fs::read_to_string(path)
+                .unwrap_or_else(|e| format!("<unable to read file: {}>", e)),
+            Attachment::Image { path } => match std::fs::metadata(path) {
+                Ok(metadata) => format!("<image, {} bytes>", metadata.len()),
+                Err(e) => format!("<unable to read image: {}>", e),
+            },
+            Attachment::Selection { text, .. } => text.clone(),
+        }
+    }
+
+    /// Block output is truncated from the front because errors and summaries
+    /// tend to be at the end; files and selections keep their beginning.
+    fn keeps_tail(&self) -> bool {
+        matches!(self, Attachment::Block { .. })
+    }
+}
+
+/// The attachments on the current agent query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttachmentSet {
+    items: Vec<Attachment>,
+}
+
+/// Attachments rendered for a request, with what had to be cut to fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializedContext {
+    pub text: String,
+    pub estimated_tokens: usize,
+    /// Indices of attachments whose content was truncated.
+    pub truncated: Vec<usize>,
+}
+
+impl AttachmentSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an attachment unless an identical one is already attached.
+    pub fn attach(&mut self, attachment: Attachment) -> bool {
+        if self.items.contains(&attachment) {
+            return false;
+        }
+        self.items.push(attachment);
+        true
+    }
+
+    pub fn detach(&mut self, index: usize) -> Option<Attachment> {
+        (index < self.items.len()).then(|| self.items.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Attachment> {
+        self.items.iter()
+    }
+
+    pub fn chips(&self) -> Vec<Chip> {
+        self.items.iter().map(Attachment::chip).collect()
+    }
+
+    /// Paths of attached images, for providers that send them with the request.
+    pub fn images(&self) -> impl Iterator<Item = &Path> {
+        self.items.iter().filter_map(|attachment| match attachment {
+            Attachment::Image { path } => Some(path.as_path()),
+            _ => None,
+        })
+    }
+
+    /// Renders every attachment into at most `budget_tokens` tokens.
+    ///
+    /// The budget is shared evenly; attachments smaller than their share give
+    /// the remainder to the ones after them.
+    pub fn serialize(&self, budget_tokens: usize) -> SerializedContext {
+        let mut text = String::new();
+        let mut truncated = Vec::new();
+        let mut remaining = budget_tokens;
+
+        for (index, attachment) in self.items.iter().enumerate() {
+            let header = format!("### {}\n```\n", attachment.header());
+            let footer = "\n```\n\n";
+            let overhead = estimate_tokens(&header) + estimate_tokens(footer);
+            let share = remaining / (self.items.len() - index);
+            let content_budget = share.saturating_sub(overhead);
+
+            let content = attachment.content();
+            let (content, was_truncated) = truncate_to_tokens(&content, content_budget, attachment.keeps_tail());
+            if was_truncated {
+                truncated.push(index);
+            }
+
+            let section = format!("{}{}{}", header, content, footer);
+            remaining = remaining.saturating_sub(estimate_tokens(&section));
+            text.push_str(&section);
+        }
+
+        SerializedContext {
+            estimated_tokens: estimate_tokens(&text),
+            text,
+            truncated,
+        }
+    }
+}
+
+/// Builds the prompt sent to the model: attached context followed by the query.
+pub fn build_request(query: &str, attachments: &AttachmentSet, budget_tokens: usize) -> String {
+    if attachments.is_empty() {
+        return query.to_string();
+    }
+    let context = attachments.serialize(budget_tokens.saturating_sub(estimate_tokens(query)));
+    format!("## Attached context\n\n{}## Question\n\n{}", context.text, query)
+}
+
+/// Fills the "Ask AI" question template with `selection` as a markdown quote.
+/// Templates without a `{{selection}}` placeholder get the quote appended.
+pub fn render_ask_ai_prompt(template: &str, selection: &str) -> String {
+    let quoted = selection
+        .trim_end()
+        .lines()
+        .map(|line| format!("> {}", line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if template.contains("{{selection}}") {
+        template.replace("{{selection}}", &quoted)
+    } else {
+        format!("{}\n\n{}", template.trim_end(), quoted)
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Cuts `text` to fit in `tokens`, marker and its line break included.
+fn truncate_to_tokens(text: &str, tokens: usize, keep_tail: bool) -> (String, bool) {
+    let max_chars = tokens * CHARS_PER_TOKEN;
+    let total = text.chars().count();
+    if total <= max_chars {
+        return (text.to_string(), false);
+    }
+    let marker = "… [truncated] …";
+    let Some(keep) = max_chars.checked_sub(marker.chars().count() + 1) else {
+        return (marker.chars().take(max_chars).collect(), true);
+    };
+    let kept: String = if keep_tail {
+        text.chars().skip(total - keep).collect()
+    } else {
+        text.chars().take(keep).collect()
+    };
+    let result = if keep_tail {
+        format!("{}\n{}", marker, kept)
+    } else {
+        format!("{}\n{}", kept, marker)
+    };
+    (result, true)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn shorten(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() <= CHIP_LABEL_LEN {
+        text.to_string()
+    } else {
+        let head: String = text.chars().take(CHIP_LABEL_LEN - 1).collect();
+        format!("{}…", head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chips_and_deduplication() {
+        let mut set = AttachmentSet::new();
+        let block = Attachment::Block {
+            command: "cargo build --release --all-features".to_string(),
+            output: "error[E0308]".to_string(),
+        };
+        assert!(set.attach(block.clone()));
+        assert!(!set.attach(block));
+        set.attach(Attachment::File { path: PathBuf::from("/src/main.rs") });
+        set.attach(Attachment::Image { path: PathBuf::from("/tmp/paste.png") });
+
+        let chips: Vec<String> = set.chips().iter().map(ToString::to_string).collect();
+        assert_eq!(chips, vec!["[▤ cargo build --release -…]", "[📄 main.rs]", "[🖼 paste.png]"]);
+        assert_eq!(set.images().collect::<Vec<_>>(), vec![Path::new("/tmp/paste.png")]);
+        assert!(set.detach(5).is_none());
+        assert!(set.detach(0).is_some());
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_serialize_respects_budget_and_keeps_block_tail() {
+        let mut set = AttachmentSet::new();
+        let output = format!("{}\nerror: the real problem", "noise line\n".repeat(500));
+        set.attach(Attachment::Block { command: "make".to_string(), output });
+        set.attach(Attachment::Selection { text: "short".to_string(), source: None });
+
+        let context = set.serialize(200);
+        assert!(context.estimated_tokens <= 200);
+        assert_eq!(context.truncated, vec![0]);
+        assert!(context.text.contains("error: the real problem"));
+        assert!(context.text.contains("short"));
+    }
+
+    #[test]
+    fn test_truncation_fits_marker_in_budget() {
+        let text = "x".repeat(1000);
+        for tokens in [0, 2, 4, 5, 10, 100] {
+            for keep_tail in [false, true] {
+                let (truncated, was_truncated) = truncate_to_tokens(&text, tokens, keep_tail);
+                assert!(was_truncated);
+                assert!(estimate_tokens(&truncated) <= tokens, "{} tokens: {:?}", tokens, truncated);
+            }
+        }
+        assert_eq!(truncate_to_tokens(&text, 5, false).0, "xxxx\n… [truncated] …");
+    }
+
+    #[test]
+    fn test_render_ask_ai_prompt() {
+        assert_eq!(
+            render_ask_ai_prompt("Explain:\n\n{{selection}}", "error: x\n  at y\n"),
+            "Explain:\n\n> error: x\n>   at y"
+        );
+        assert_eq!(render_ask_ai_prompt("Why?", "oops"), "Why?\n\n> oops");
+    }
+
+    #[test]
+    fn test_build_request_without_attachments_is_query() {
+        assert_eq!(build_request("why?", &AttachmentSet::new(), 100), "why?");
+        let mut set = AttachmentSet::new();
+        set.attach(Attachment::Selection { text: "panic at main.rs:3".to_string(), source: Some("block `cargo run`".to_string()) });
+        let request = build_request("why?", &set, 100);
+        assert!(request.starts_with("## Attached context"));
+        assert!(request.ends_with("## Question\n\nwhy?"));
+    }
+}
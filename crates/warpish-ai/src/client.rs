@@ -1,16 +1,14 @@
-use crate::agent::model::ModelId; // Import the new enum
+use crate::model::ModelId;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-use std::fs;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FileDiff {
     pub file_path: String,
     pub new_content: String,
 }
 
-#[derive(Debug, Clone)]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AgentResponse {
     SuggestCommand {
         explanation: String,
@@ -27,6 +25,18 @@ pub enum AgentResponse {
     },
 }
 
+/// Anything that can answer agent queries: the simulated agent, a scripted
+/// replay, or a real model.
+pub trait AgentProvider: Send + Sync {
+    fn process_query(
+        &self,
+        query: &str,
+        history: &[(String, AgentResponse)],
+        block_context: &[String],
+        model: ModelId,
+    ) -> AgentResponse;
+}
+
 pub struct SimulatedAgent {
     matcher: SkimMatcherV2,
 }
@@ -40,7 +50,7 @@ impl SimulatedAgent {
     pub fn process_query(
         &self,
         query: &str,
-        history: &[(String, AgentResponse)],
+        _history: &[(String, AgentResponse)],
         block_context: &[String],
         model: ModelId, // Add model parameter
     ) -> AgentResponse {
@@ -97,4 +107,16 @@ impl SimulatedAgent {
         }
         AgentResponse::Clarification("I'm not sure how to help with that. Could you be more specific?".into())
     }
-} 
\ No newline at end of file
+} 
+
+impl AgentProvider for SimulatedAgent {
+    fn process_query(
+        &self,
+        query: &str,
+        history: &[(String, AgentResponse)],
+        block_context: &[String],
+        model: ModelId,
+    ) -> AgentResponse {
+        SimulatedAgent::process_query(self, query, history, block_context, model)
+    }
+}
@@ -0,0 +1,11 @@
+//! Warpish AI
+//!
+//! The model catalogue, agent client and context attachments used by Agent
+//! Mode, plus a scripted agent that replays fixtures for tests and demos.
+//! Tool execution stays in the application, which owns the panes the agent
+//! runs commands in.
+
+pub mod attachments;
+pub mod client;
+pub mod model;
+pub mod scripted;
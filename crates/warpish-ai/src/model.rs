@@ -31,4 +31,9 @@ impl ModelId {
             ModelId::Gemini2_5Pro => "Gemini 2.5 Pro",
         }
     }
+
+    /// Whether the model accepts images alongside text. o3-mini is text only.
+    pub fn supports_images(&self) -> bool {
+        !matches!(self, ModelId::O3Mini)
+    }
 }
@@ -0,0 +1,231 @@
+//! Scripted Agent
+//!
+//! A deterministic `AgentProvider` that replays canned conversations from
+//! YAML fixtures, for integration tests, screenshots and offline demos. A
+//! fixture lists exchanges; the first one whose `query` regex matches the
+//! query (and whose optional `context` regex matches an attached block)
+//! answers. An exchange marked `once` answers a single time, so a fixture can
+//! script a conversation that moves on when the same question is asked again.
+//!
+//! ```yaml
+//! fallback: "I only know the demo script."
+//! exchanges:
+//!   - query: "(?i)fix this"
+//!     context: "error"
+//!     once: true
+//!     response:
+//!       SuggestCommand:
+//!         explanation: The build cache looks stale.
+//!         command: cargo clean && cargo build
+//!   - query: "(?i)docker"
+//!     response:
+//!       Clarification: Which containers, running or all?
+//! ```
+
+use crate::client::{AgentProvider, AgentResponse};
+use crate::model::ModelId;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+const DEFAULT_FALLBACK: &str = "No scripted response matches this query.";
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("Failed to read agent script {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid agent script {0}: {1}")]
+    Yaml(String, serde_yaml::Error),
+    #[error("Invalid pattern '{0}' in agent script: {1}")]
+    Pattern(String, regex::Error),
+}
+
+#[derive(Deserialize)]
+struct ScriptFile {
+    #[serde(default)]
+    fallback: Option<String>,
+    #[serde(default)]
+    exchanges: Vec<ExchangeFile>,
+}
+
+#[derive(Deserialize)]
+struct ExchangeFile {
+    query: String,
+    #[serde(default)]
+    context: Option<String>,
+    #[serde(default)]
+    once: bool,
+    /// Written as `Variant: fields` rather than with a YAML `!Variant` tag.
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    response: AgentResponse,
+}
+
+struct Exchange {
+    query: Regex,
+    context: Option<Regex>,
+    once: bool,
+    response: AgentResponse,
+}
+
+impl Exchange {
+    fn matches(&self, query: &str, block_context: &[String]) -> bool {
+        self.query.is_match(query)
+            && self
+                .context
+                .as_ref()
+                .is_none_or(|pattern| block_context.iter().any(|block| pattern.is_match(block)))
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, ScriptError> {
+    Regex::new(pattern).map_err(|e| ScriptError::Pattern(pattern.to_string(), e))
+}
+
+/// Answers queries from canned exchanges, in the order they were loaded.
+pub struct ScriptedAgent {
+    exchanges: Vec<Exchange>,
+    fallback: String,
+    /// Which `once` exchanges have already answered.
+    used: Mutex<Vec<bool>>,
+}
+
+impl ScriptedAgent {
+    /// Parses one fixture; `name` is used in error messages.
+    pub fn from_yaml(name: &str, text: &str) -> Result<Self, ScriptError> {
+        let mut agent = Self { exchanges: Vec::new(), fallback: DEFAULT_FALLBACK.to_string(), used: Mutex::new(Vec::new()) };
+        agent.extend(name, text)?;
+        Ok(agent)
+    }
+
+    /// Loads a fixture file, or every `.yaml`/`.yml` file in a directory in
+    /// name order. The last fixture that sets a fallback wins.
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let mut files = if path.is_dir() {
+            std::fs::read_dir(path)
+                .map_err(|e| ScriptError::Io(path.to_path_buf(), e))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.extension().is_some_and(|ext| ext == "yaml" || ext == "yml"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        files.sort();
+        let mut agent = Self::from_yaml("", "")?;
+        for file in files {
+            let text = std::fs::read_to_string(&file).map_err(|e| ScriptError::Io(file.clone(), e))?;
+            agent.extend(&file.display().to_string(), &text)?;
+        }
+        Ok(agent)
+    }
+
+    fn extend(&mut self, name: &str, text: &str) -> Result<(), ScriptError> {
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+        let script: ScriptFile = serde_yaml::from_str(text).map_err(|e| ScriptError::Yaml(name.to_string(), e))?;
+        if let Some(fallback) = script.fallback {
+            self.fallback = fallback;
+        }
+        for exchange in script.exchanges {
+            self.exchanges.push(Exchange {
+                query: compile(&exchange.query)?,
+                context: exchange.context.as_deref().map(compile).transpose()?,
+                once: exchange.once,
+                response: exchange.response,
+            });
+        }
+        self.used.get_mut().unwrap().resize(self.exchanges.len(), false);
+        Ok(())
+    }
+}
+
+impl AgentProvider for ScriptedAgent {
+    fn process_query(
+        &self,
+        query: &str,
+        _history: &[(String, AgentResponse)],
+        block_context: &[String],
+        _model: ModelId,
+    ) -> AgentResponse {
+        let mut used = self.used.lock().unwrap();
+        let found = self
+            .exchanges
+            .iter()
+            .enumerate()
+            .find(|(idx, exchange)| !(exchange.once && used[*idx]) && exchange.matches(query, block_context));
+        match found {
+            Some((idx, exchange)) => {
+                used[idx] = true;
+                exchange.response.clone()
+            }
+            None => AgentResponse::Clarification(self.fallback.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+fallback: "I only know the demo script."
+exchanges:
+  - query: "(?i)fix this"
+    context: "error"
+    once: true
+    response:
+      SuggestCommand:
+        explanation: The build cache looks stale.
+        command: cargo clean && cargo build
+  - query: "(?i)fix this"
+    response:
+      Clarification: Still failing? Share the full error.
+  - query: "(?i)my ip"
+    response:
+      RequestToRunCommand:
+        explanation: I need to ask a server.
+        command_to_run: curl ifconfig.me
+"#;
+
+    #[test]
+    fn test_exchanges_replay_in_order() {
+        let agent = ScriptedAgent::from_yaml("demo", SCRIPT).unwrap();
+        let ask = |query: &str, context: &[&str]| {
+            let context: Vec<String> = context.iter().map(|block| block.to_string()).collect();
+            agent.process_query(query, &[], &context, ModelId::Auto)
+        };
+
+        assert_eq!(
+            ask("please fix this", &["error[E0425]: cannot find value"]),
+            AgentResponse::SuggestCommand {
+                explanation: "The build cache looks stale.".into(),
+                command: "cargo clean && cargo build".into(),
+            }
+        );
+        // The `once` exchange has answered; the conversation moves on.
+        assert_eq!(
+            ask("please fix this", &["error[E0425]: cannot find value"]),
+            AgentResponse::Clarification("Still failing? Share the full error.".into())
+        );
+        assert!(matches!(ask("what is my IP", &[]), AgentResponse::RequestToRunCommand { .. }));
+        assert_eq!(ask("tell me a joke", &[]), AgentResponse::Clarification("I only know the demo script.".into()));
+    }
+
+    #[test]
+    fn test_load_reads_fixture_directories() {
+        let dir = std::env::temp_dir().join(format!("warpish-agent-script-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("01-demo.yaml"), SCRIPT).unwrap();
+        std::fs::write(dir.join("02-docker.yml"), "exchanges:\n  - query: docker\n    response:\n      Clarification: Running or all?\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a fixture").unwrap();
+
+        let agent = ScriptedAgent::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(agent.exchanges.len(), 4);
+        let demo = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../tests/fixtures/agent");
+        assert!(ScriptedAgent::load(&demo).is_ok_and(|agent| !agent.exchanges.is_empty()));
+        assert!(matches!(ScriptedAgent::from_yaml("bad", "exchanges:\n  - query: '('\n    response:\n      Clarification: x\n"), Err(ScriptError::Pattern(..))));
+    }
+}
@@ -0,0 +1,169 @@
+//! Warpish CLI
+//!
+//! Controls a running terminal from editors, scripts and CI through its
+//! local JSON-RPC socket. The terminal must have `[lpc] enabled = true`.
+
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use warpish_terminal::config::load_config;
+use warpish_terminal::lpc::client::LpcClient;
+use warpish_terminal::lpc::protocol::{BlockInfo, PaneInfo};
+use warpish_terminal::lpc::{socket_path, LpcError};
+
+const USAGE: &str = "\
+Usage: warpish-cli [--json] <command>
+
+Commands:
+  panes                                     List panes; the focused one is marked with *
+  open [--profile NAME] [--cwd DIR] [CMD..] Open a pane, optionally running CMD
+  run [--pane ID] CMD..                     Run CMD in a pane, the focused one by default
+  blocks [--pane ID] [--limit N]            Print a pane's commands and their output
+  focus ID                                  Focus a pane
+
+--json prints the raw result.";
+
+/// A command line turned into a method call.
+struct Invocation {
+    method: &'static str,
+    params: Value,
+}
+
+fn usage_error(message: &str) -> String {
+    format!("{}\n\n{}", message, USAGE)
+}
+
+/// Takes `--name value` options off the front of `args`.
+fn take_options<'a>(args: &mut &'a [String], names: &[&str]) -> Result<Vec<(String, &'a str)>, String> {
+    let mut options = Vec::new();
+    while let Some(name) = args.first().and_then(|arg| arg.strip_prefix("--")) {
+        if !names.contains(&name) {
+            return Err(usage_error(&format!("Unknown option --{}", name)));
+        }
+        let value = args.get(1).ok_or_else(|| usage_error(&format!("--{} needs a value", name)))?;
+        options.push((name.to_string(), value.as_str()));
+        *args = &args[2..];
+    }
+    Ok(options)
+}
+
+fn option<'a>(options: &[(String, &'a str)], name: &str) -> Option<&'a str> {
+    options.iter().find(|(option, _)| option == name).map(|(_, value)| *value)
+}
+
+fn parse(args: &[String]) -> Result<Invocation, String> {
+    let (command, mut rest) = args.split_first().ok_or_else(|| USAGE.to_string())?;
+    let invocation = match command.as_str() {
+        "panes" => Invocation { method: "list_panes", params: Value::Null },
+        "open" => {
+            let options = take_options(&mut rest, &["profile", "cwd"])?;
+            let command = (!rest.is_empty()).then(|| rest.join(" "));
+            Invocation {
+                method: "open_tab",
+                params: json!({ "profile": option(&options, "profile"), "cwd": option(&options, "cwd"), "command": command }),
+            }
+        }
+        "run" => {
+            let options = take_options(&mut rest, &["pane"])?;
+            if rest.is_empty() {
+                return Err(usage_error("run needs a command"));
+            }
+            Invocation { method: "run_command", params: json!({ "command": rest.join(" "), "pane_id": option(&options, "pane") }) }
+        }
+        "blocks" => {
+            let options = take_options(&mut rest, &["pane", "limit"])?;
+            let limit = option(&options, "limit")
+                .map(|limit| limit.parse::<usize>().map_err(|_| usage_error("--limit needs a number")))
+                .transpose()?;
+            Invocation { method: "get_blocks", params: json!({ "pane_id": option(&options, "pane"), "limit": limit }) }
+        }
+        "focus" => match rest {
+            [pane_id] => Invocation { method: "focus_pane", params: json!({ "pane_id": pane_id }) },
+            _ => return Err(usage_error("focus needs a pane id")),
+        },
+        "help" | "--help" | "-h" => return Err(USAGE.to_string()),
+        other => return Err(usage_error(&format!("Unknown command {}", other))),
+    };
+    Ok(invocation)
+}
+
+/// The result as people read it.
+fn render(method: &str, result: &Value) -> String {
+    match method {
+        "list_panes" => serde_json::from_value::<Vec<PaneInfo>>(result.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|pane| {
+                let cwd = pane.cwd.as_ref().map(|cwd| cwd.display().to_string()).unwrap_or_default();
+                format!("{} {}  {}\n", if pane.focused { "*" } else { " " }, pane.id, cwd)
+            })
+            .collect(),
+        "get_blocks" => serde_json::from_value::<Vec<BlockInfo>>(result.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|block| format!("$ {}\n{}\n", block.command, block.output.trim_end()))
+            .collect(),
+        _ => match result.get("pane_id").and_then(Value::as_str) {
+            Some(pane_id) => format!("{}\n", pane_id),
+            None => String::new(),
+        },
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let json_output = args.first().is_some_and(|arg| arg == "--json");
+    if json_output {
+        args.remove(0);
+    }
+    let invocation = match parse(&args) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+
+    let path: PathBuf = socket_path(&load_config().map(|config| config.lpc).unwrap_or_default());
+    let result = LpcClient::connect(&path).and_then(|mut client| client.call(invocation.method, invocation.params));
+    match result {
+        Ok(result) if json_output => {
+            println!("{}", serde_json::to_string_pretty(&result).unwrap_or_default());
+            ExitCode::SUCCESS
+        }
+        Ok(result) => {
+            print!("{}", render(invocation.method, &result));
+            ExitCode::SUCCESS
+        }
+        Err(LpcError::Io(e)) => {
+            eprintln!("Could not reach Warpish at {}: {}. Is it running with [lpc] enabled = true?", path.display(), e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_command_lines_become_calls() {
+        let run = parse(&args("run --pane 42 cargo test --workspace")).unwrap();
+        assert_eq!((run.method, run.params), ("run_command", json!({ "command": "cargo test --workspace", "pane_id": "42" })));
+        let open = parse(&args("open --cwd /tmp")).unwrap();
+        assert_eq!(open.params, json!({ "profile": null, "cwd": "/tmp", "command": null }));
+        assert_eq!(parse(&args("blocks --limit 3")).unwrap().params, json!({ "pane_id": null, "limit": 3 }));
+        assert!(parse(&args("blocks --limit many")).is_err());
+        assert!(parse(&args("run")).is_err());
+        assert!(parse(&args("open --tab 2")).is_err());
+        assert!(parse(&[]).is_err());
+    }
+}
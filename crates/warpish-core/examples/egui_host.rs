@@ -0,0 +1,112 @@
+//! A minimal egui app hosting a Warpish terminal.
+//!
+//! Commands typed in the input line run as blocks, shown above the live
+//! screen. Click the screen (or press Escape in the input line) to type
+//! straight into the shell, which interactive programs need.
+//!
+//! Run with `cargo run -p warpish-core --example egui_host`.
+
+use eframe::egui;
+use warpish_core::{KeyModifiers, TerminalKey, TerminalOptions, TerminalView};
+
+struct Host {
+    view: TerminalView,
+    input: String,
+    size: (u16, u16),
+}
+
+fn terminal_key(key: egui::Key) -> Option<TerminalKey> {
+    Some(match key {
+        egui::Key::Enter => TerminalKey::Enter,
+        egui::Key::Backspace => TerminalKey::Backspace,
+        egui::Key::Tab => TerminalKey::Tab,
+        egui::Key::Escape => TerminalKey::Escape,
+        egui::Key::ArrowUp => TerminalKey::Up,
+        egui::Key::ArrowDown => TerminalKey::Down,
+        egui::Key::ArrowLeft => TerminalKey::Left,
+        egui::Key::ArrowRight => TerminalKey::Right,
+        egui::Key::Home => TerminalKey::Home,
+        egui::Key::End => TerminalKey::End,
+        egui::Key::PageUp => TerminalKey::PageUp,
+        egui::Key::PageDown => TerminalKey::PageDown,
+        egui::Key::Delete => TerminalKey::Delete,
+        _ => return None,
+    })
+}
+
+impl eframe::App for Host {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let input_focused = egui::TopBottomPanel::bottom("input").show(ctx, |ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut self.input).hint_text("Type a command").desired_width(f32::INFINITY));
+            if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) && !self.input.is_empty() {
+                let command = std::mem::take(&mut self.input);
+                if let Err(e) = self.view.submit_command(&command) {
+                    eprintln!("{}", e);
+                }
+                response.request_focus();
+            }
+            response.has_focus()
+        });
+
+        if !input_focused.inner {
+            let events = ctx.input(|i| i.events.clone());
+            for event in events {
+                let result = match event {
+                    egui::Event::Text(text) => self.view.send_text(&text),
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => match terminal_key(key) {
+                        Some(key) => self.view.handle_key(key, KeyModifiers { ctrl: modifiers.ctrl, alt: modifiers.alt }),
+                        None => Ok(()),
+                    },
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let font = egui::TextStyle::Monospace.resolve(ui.style());
+            let cell = ui.fonts(|fonts| egui::vec2(fonts.glyph_width(&font, 'M'), fonts.row_height(&font)));
+            let size = ui.available_size();
+            let cells = ((size.x / cell.x).max(1.0) as u16, (size.y / cell.y).max(1.0) as u16);
+            if cells != self.size {
+                self.size = cells;
+                if let Err(e) = self.view.resize(cells.0, cells.1) {
+                    eprintln!("{}", e);
+                }
+            }
+
+            egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for block in self.view.blocks() {
+                    ui.group(|ui| {
+                        ui.label(egui::RichText::new(format!("$ {}", block.command)).monospace().strong());
+                        ui.label(egui::RichText::new(&block.output).monospace());
+                    });
+                }
+                if let Some(command) = self.view.active_command() {
+                    ui.label(egui::RichText::new(format!("$ {}", command)).monospace().strong());
+                }
+                let screen = self.view.screen().get_grid().as_ref().to_string();
+                ui.label(egui::RichText::new(screen).monospace());
+            });
+        });
+
+        if !self.view.is_alive() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+}
+
+fn main() -> eframe::Result<()> {
+    eframe::run_native(
+        "Warpish in egui",
+        eframe::NativeOptions::default(),
+        Box::new(|cc| {
+            let ctx = cc.egui_ctx.clone();
+            let view = TerminalView::spawn(TerminalOptions::default(), move || ctx.request_repaint())
+                .expect("failed to start shell");
+            Box::new(Host { view, input: String::new(), size: (0, 0) })
+        }),
+    )
+}
@@ -0,0 +1,35 @@
+//! Warpish Core
+//!
+//! The terminal engine behind Warpish, without any windowing, AI or
+//! persistence. It turns the byte stream from a shell into grid state that a
+//! front end can draw, and reports what the shell says about itself along the
+//! way (such as its working directory via OSC 7).
+//!
+//! Embedding it takes three calls: create a [`VteState`] sized to the view,
+//! feed it everything read from the PTY with [`VteState::process`], and read
+//! the grid back with [`VteState::get_grid`] when drawing.
+//!
+//! ```no_run
+//! use warpish_core::VteState;
+//!
+//! let mut state = VteState::new(80, 24);
+//! state.process(b"\x1b]7;file://host/home/me\x07$ ");
+//! assert_eq!(state.take_cwd().unwrap().to_str(), Some("/home/me"));
+//! let grid = state.get_grid();
+//! println!("{}x{}", grid.cols, grid.rows);
+//! ```
+//!
+//! Hosts that want a complete terminal rather than just the parser can use
+//! [`TerminalView`], which also runs the shell on a PTY, encodes keyboard
+//! input and groups output into command blocks. It does no window management
+//! or drawing, so it fits into egui, iced or any other toolkit; see
+//! `examples/egui_host.rs`.
+
+#![warn(missing_docs)]
+
+pub mod pty;
+pub mod view;
+
+pub use pty::encoding::{EncodingWriter, OutputDecoder, PaneEncoding, PaneEncodings, SharedEncodings};
+pub use pty::vte_handler::{vte_color_to_ratatui, vte_flags_to_ratatui_style, Grid, GridCoords, VteState};
+pub use view::{encode_key, KeyModifiers, TerminalBlock, TerminalKey, TerminalOptions, TerminalView, ViewError};
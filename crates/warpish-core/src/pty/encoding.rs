@@ -0,0 +1,246 @@
+//! Character encodings for talking to shells on legacy systems.
+//!
+//! The VTE parser and everything above it work in UTF-8. A pane whose shell
+//! speaks another encoding decodes PTY output with an [`OutputDecoder`]
+//! before parsing it, and encodes what it sends with an [`EncodingWriter`].
+
+use encoding_rs::{CoderResult, EncoderResult, SHIFT_JIS};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Replaces characters the target encoding cannot represent.
+const UNMAPPABLE: u8 = b'?';
+
+/// An encoding a pane can read or write in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaneEncoding {
+    /// UTF-8, passed through untouched.
+    #[default]
+    Utf8,
+    /// ISO 8859-1: every byte is the code point of the same value.
+    Latin1,
+    /// Shift_JIS, common on older Japanese systems.
+    ShiftJis,
+}
+
+impl PaneEncoding {
+    /// Every supported encoding, UTF-8 first.
+    pub const ALL: [PaneEncoding; 3] = [PaneEncoding::Utf8, PaneEncoding::Latin1, PaneEncoding::ShiftJis];
+
+    /// Parses a name such as `utf-8`, `latin1`, `iso-8859-1` or `sjis`,
+    /// ignoring case.
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(PaneEncoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" | "iso8859-1" => Some(PaneEncoding::Latin1),
+            "shift-jis" | "shiftjis" | "sjis" | "ms-kanji" => Some(PaneEncoding::ShiftJis),
+            _ => None,
+        }
+    }
+
+    /// The name shown to the user.
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaneEncoding::Utf8 => "UTF-8",
+            PaneEncoding::Latin1 => "Latin-1",
+            PaneEncoding::ShiftJis => "Shift_JIS",
+        }
+    }
+
+    /// Encodes UTF-8 `text`, writing `?` for characters it has no code for.
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self {
+            PaneEncoding::Utf8 => text.as_bytes().to_vec(),
+            PaneEncoding::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(u32::from(c)).unwrap_or(UNMAPPABLE))
+                .collect(),
+            PaneEncoding::ShiftJis => {
+                let mut encoder = SHIFT_JIS.new_encoder();
+                let mut output = Vec::with_capacity(text.len());
+                let mut buffer = [0u8; 1024];
+                let mut rest = text;
+                loop {
+                    let (result, read, written) = encoder.encode_from_utf8_without_replacement(rest, &mut buffer, true);
+                    output.extend_from_slice(&buffer[..written]);
+                    rest = &rest[read..];
+                    match result {
+                        EncoderResult::InputEmpty => break,
+                        EncoderResult::Unmappable(_) => output.push(UNMAPPABLE),
+                        EncoderResult::OutputFull => {}
+                    }
+                }
+                output
+            }
+        }
+    }
+}
+
+/// The encodings a pane reads output in and writes input in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PaneEncodings {
+    /// What is sent to the shell.
+    pub input: PaneEncoding,
+    /// What the shell prints.
+    pub output: PaneEncoding,
+}
+
+impl PaneEncodings {
+    /// Whether both directions are UTF-8, the default.
+    pub fn is_utf8(&self) -> bool {
+        self.input == PaneEncoding::Utf8 && self.output == PaneEncoding::Utf8
+    }
+
+    /// A short description such as `Shift_JIS`, or `in Latin-1 / out UTF-8`
+    /// when the directions differ.
+    pub fn label(&self) -> String {
+        if self.input == self.output {
+            self.output.label().to_string()
+        } else {
+            format!("in {} / out {}", self.input.label(), self.output.label())
+        }
+    }
+}
+
+/// Encodings shared between a pane and its PTY reader and writer, so they can
+/// be changed while the shell runs.
+pub type SharedEncodings = Arc<Mutex<PaneEncodings>>;
+
+/// Decodes PTY output into UTF-8, keeping characters that are split across
+/// reads.
+pub struct OutputDecoder {
+    encoding: PaneEncoding,
+    decoder: Option<encoding_rs::Decoder>,
+}
+
+impl OutputDecoder {
+    /// A decoder for output in `encoding`.
+    pub fn new(encoding: PaneEncoding) -> Self {
+        let decoder = match encoding {
+            PaneEncoding::ShiftJis => Some(SHIFT_JIS.new_decoder_without_bom_handling()),
+            PaneEncoding::Utf8 | PaneEncoding::Latin1 => None,
+        };
+        Self { encoding, decoder }
+    }
+
+    /// The encoding this decoder reads.
+    pub fn encoding(&self) -> PaneEncoding {
+        self.encoding
+    }
+
+    /// Decodes the next chunk of output. UTF-8 is returned as it is, since
+    /// the parser copes with split and invalid sequences itself.
+    pub fn decode<'a>(&mut self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        match (self.encoding, &mut self.decoder) {
+            (PaneEncoding::Latin1, _) => {
+                let text: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+                Cow::Owned(text.into_bytes())
+            }
+            (PaneEncoding::ShiftJis, Some(decoder)) => {
+                let capacity = decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 16);
+                let mut output = vec![0u8; capacity];
+                let (result, _, written, _) = decoder.decode_to_utf8(bytes, &mut output, false);
+                debug_assert_eq!(result, CoderResult::InputEmpty);
+                output.truncate(written);
+                Cow::Owned(output)
+            }
+            _ => Cow::Borrowed(bytes),
+        }
+    }
+}
+
+/// Wraps a PTY writer, encoding the UTF-8 written to it into the pane's
+/// input encoding.
+pub struct EncodingWriter<W> {
+    inner: W,
+    encodings: SharedEncodings,
+    /// The start of a UTF-8 sequence whose remaining bytes are still to come.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    /// Encodes into whatever input encoding `encodings` holds at the time of
+    /// each write.
+    pub fn new(inner: W, encodings: SharedEncodings) -> Self {
+        Self { inner, encodings, pending: Vec::new() }
+    }
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encoding = self.encodings.lock().unwrap().input;
+        if encoding == PaneEncoding::Utf8 && self.pending.is_empty() {
+            self.inner.write_all(buf)?;
+            return Ok(buf.len());
+        }
+        self.pending.extend_from_slice(buf);
+        let mut encoded = Vec::with_capacity(self.pending.len());
+        let mut rest = self.pending.as_slice();
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    encoded.extend(encoding.encode(text));
+                    rest = &[];
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    encoded.extend(encoding.encode(std::str::from_utf8(valid).unwrap_or_default()));
+                    let Some(len) = e.error_len() else {
+                        rest = after;
+                        break;
+                    };
+                    // Bytes that are not UTF-8 at all are sent as they are.
+                    encoded.extend_from_slice(&after[..len]);
+                    rest = &after[len..];
+                }
+            }
+        }
+        let consumed = self.pending.len() - rest.len();
+        self.pending.drain(..consumed);
+        self.inner.write_all(&encoded)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder_keeps_split_characters() {
+        // "日本" in Shift_JIS, split in the middle of the second character.
+        let mut decoder = OutputDecoder::new(PaneEncoding::ShiftJis);
+        let mut text = decoder.decode(b"\x1b[1m\x93\xfa\x96").into_owned();
+        text.extend_from_slice(&decoder.decode(b"\x7b\r\n"));
+        assert_eq!(String::from_utf8(text).unwrap(), "\x1b[1m日本\r\n");
+
+        let mut latin1 = OutputDecoder::new(PaneEncoding::Latin1);
+        assert_eq!(latin1.decode(b"caf\xe9").as_ref(), "café".as_bytes());
+        assert_eq!(PaneEncoding::from_label("Shift_JIS"), Some(PaneEncoding::ShiftJis));
+        assert_eq!(PaneEncoding::from_label("ebcdic"), None);
+        let encodings = PaneEncodings { input: PaneEncoding::Latin1, output: PaneEncoding::ShiftJis };
+        assert_eq!(encodings.label(), "in Latin-1 / out Shift_JIS");
+    }
+
+    #[test]
+    fn test_writer_encodes_input() {
+        let encodings: SharedEncodings = Arc::new(Mutex::new(PaneEncodings::default()));
+        let mut writer = EncodingWriter::new(Vec::new(), encodings.clone());
+        writer.write_all("é".as_bytes()).unwrap();
+
+        encodings.lock().unwrap().input = PaneEncoding::Latin1;
+        writer.write_all("café €".as_bytes()).unwrap();
+        encodings.lock().unwrap().input = PaneEncoding::ShiftJis;
+        // A character split across two writes is encoded once it is complete.
+        let bytes = "日".as_bytes();
+        writer.write_all(&bytes[..1]).unwrap();
+        writer.write_all(&bytes[1..]).unwrap();
+
+        assert_eq!(writer.inner, [&b"\xc3\xa9caf\xe9 ?"[..], b"\x93\xfa"].concat());
+    }
+}
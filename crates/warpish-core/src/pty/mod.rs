@@ -0,0 +1,5 @@
+//! Escape-sequence handling and transcoding for output read from a
+//! pseudo-terminal.
+
+pub mod encoding;
+pub mod vte_handler;
@@ -1,31 +1,88 @@
+//! Parses terminal output with `vte` and applies it to a [`Grid`].
+
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use vte::{Parser, Perform, ansi};
 
-// Define our own Grid and GridCoords
+/// The visible screen: a fixed number of rows and columns of cells.
 pub struct Grid {
-    // Add necessary fields
+    /// Height in cells.
     pub rows: usize,
+    /// Width in cells.
     pub cols: usize,
 }
 
+/// A cell position on the [`Grid`].
 pub struct GridCoords {
+    /// Column, from the left.
     pub x: usize,
+    /// Row, from the top.
     pub y: usize,
 }
 use ratatui::style::{Color as RatatuiColor, Modifier, Style};
 
+/// Bash `PROMPT_COMMAND` that reports the exit status of the command that
+/// just finished, and its history number, through OSC 133;D. It has to run
+/// first in `PROMPT_COMMAND`, before anything else overwrites `$?`.
+pub const BASH_EXIT_STATUS_PROMPT_COMMAND: &str = r#"printf '\e]133;D;%s;%s\a' "$?" "$HISTCMD""#;
+
+/// A command the shell reported as finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandExit {
+    /// The exit status.
+    pub status: i32,
+    /// The shell's history number for the command. An empty prompt reports
+    /// the same number again, which tells it apart from a new command.
+    pub history_id: Option<String>,
+}
+
 /// A VTE event-handler that updates a grid.
 #[derive(Debug)]
 struct VteActor {
     grid: Arc<Mutex<Grid>>,
+    cwd: Arc<Mutex<Option<PathBuf>>>,
+    cwd_host: Arc<Mutex<Option<String>>>,
+    command_exit: Arc<Mutex<Option<CommandExit>>>,
 }
 
 impl VteActor {
-    fn new(grid: Arc<Mutex<Grid>>) -> Self {
-        VteActor { grid }
+    fn new(
+        grid: Arc<Mutex<Grid>>,
+        cwd: Arc<Mutex<Option<PathBuf>>>,
+        cwd_host: Arc<Mutex<Option<String>>>,
+        command_exit: Arc<Mutex<Option<CommandExit>>>,
+    ) -> Self {
+        VteActor { grid, cwd, cwd_host, command_exit }
     }
 }
 
+/// Parses the `file://host/path` URL a shell sends in an OSC 7 report into
+/// the host, if one is named, and the path.
+fn parse_osc7(url: &[u8]) -> Option<(Option<String>, PathBuf)> {
+    let url = std::str::from_utf8(url).ok()?;
+    let rest = url.strip_prefix("file://")?;
+    let (host, path) = rest.split_at(rest.find('/')?);
+    let path = percent_encoding::percent_decode_str(path).decode_utf8().ok()?;
+    let host = (!host.is_empty()).then(|| host.to_string());
+    Some((host, PathBuf::from(path.as_ref())))
+}
+
+/// Parses the `D;<status>[;<history number>]` an OSC 133 command-finished
+/// report carries after the `133`.
+fn parse_osc133_exit(params: &[&[u8]]) -> Option<CommandExit> {
+    let [b"D", status, rest @ ..] = params else {
+        return None;
+    };
+    let status = std::str::from_utf8(status).ok()?.trim().parse().ok()?;
+    let history_id = rest
+        .first()
+        .and_then(|id| std::str::from_utf8(id).ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string);
+    Some(CommandExit { status, history_id })
+}
+
 /// Implement the vte::Perform trait to handle escape sequences.
 /// The parser will call these methods as it processes the byte stream.
 impl Perform for VteActor {
@@ -54,30 +111,60 @@ impl Perform for VteActor {
         let mut grid = self.grid.lock().unwrap();
         grid.esc_dispatch(intermediates, ignore, byte);
     }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]], _bell_terminated: bool) {
+        // OSC 7 reports the shell's working directory.
+        if let [b"7", url, ..] = params {
+            if let Some((host, dir)) = parse_osc7(url) {
+                *self.cwd.lock().unwrap() = Some(dir);
+                *self.cwd_host.lock().unwrap() = host;
+            }
+        }
+        // OSC 133;D reports that a command finished, with its exit status.
+        if let [b"133", rest @ ..] = params {
+            if let Some(exit) = parse_osc133_exit(rest) {
+                *self.command_exit.lock().unwrap() = Some(exit);
+            }
+        }
+    }
 }
 
 /// The main struct that holds the terminal state.
 pub struct VteState {
     parser: Parser,
     grid: Arc<Mutex<Grid>>,
+    cwd: Arc<Mutex<Option<PathBuf>>>,
+    cwd_host: Arc<Mutex<Option<String>>>,
+    command_exit: Arc<Mutex<Option<CommandExit>>>,
 }
 
 impl VteState {
+    /// Creates an empty screen of `cols` by `rows` cells.
     pub fn new(cols: u16, rows: u16) -> Self {
         let grid = Arc::new(Mutex::new(Grid::new(
             rows as usize,
             cols as usize,
             0, // No scrollback buffer in the grid itself
         )));
-        let performer = VteActor::new(grid.clone());
         let parser = Parser::new();
 
-        VteState { parser, grid }
+        VteState {
+            parser,
+            grid,
+            cwd: Arc::new(Mutex::new(None)),
+            cwd_host: Arc::new(Mutex::new(None)),
+            command_exit: Arc::new(Mutex::new(None)),
+        }
     }
 
     /// Process incoming bytes from the PTY.
     pub fn process(&mut self, data: &[u8]) {
-        let mut performer = VteActor::new(self.grid.clone());
+        let mut performer = VteActor::new(
+            self.grid.clone(),
+            self.cwd.clone(),
+            self.cwd_host.clone(),
+            self.command_exit.clone(),
+        );
         for byte in data {
             self.parser.advance(&mut performer, *byte);
         }
@@ -89,6 +176,24 @@ impl VteState {
         grid.resize(rows as usize, cols as usize);
     }
 
+    /// Takes the working directory the shell reported through OSC 7 since
+    /// the last call, if any.
+    pub fn take_cwd(&self) -> Option<PathBuf> {
+        self.cwd.lock().unwrap().take()
+    }
+
+    /// The host named in the most recent OSC 7 report. It differs from the
+    /// local machine's name while the shell is running over SSH.
+    pub fn cwd_host(&self) -> Option<String> {
+        self.cwd_host.lock().unwrap().clone()
+    }
+
+    /// Takes the command the shell last reported as finished through
+    /// OSC 133;D since the last call, if any.
+    pub fn take_command_exit(&self) -> Option<CommandExit> {
+        self.command_exit.lock().unwrap().take()
+    }
+
     /// Provides locked access to the grid for rendering.
     pub fn get_grid(&self) -> std::sync::MutexGuard<'_, Grid> {
         self.grid.lock().unwrap()
@@ -0,0 +1,276 @@
+//! An embeddable terminal: a shell on a PTY, its screen state, keyboard
+//! input and the command blocks it has produced. Hosts own the window and
+//! drawing; they forward input, call [`TerminalView::resize`] when their
+//! layout changes, and repaint when the `on_output` callback fires.
+
+use crate::VteState;
+use portable_pty::{Child, ChildKiller, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use thiserror::Error;
+
+/// Errors from starting or talking to the shell.
+#[derive(Error, Debug)]
+pub enum ViewError {
+    /// The PTY could not be opened or the shell could not be spawned.
+    #[error("Failed to start shell: {0}")]
+    Spawn(String),
+    /// Writing to or resizing the PTY failed.
+    #[error("PTY I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// How to start the shell behind a [`TerminalView`].
+#[derive(Debug, Clone)]
+pub struct TerminalOptions {
+    /// The program to run; defaults to `$SHELL`, then `/bin/sh`.
+    pub shell: Option<String>,
+    /// Arguments passed to the shell.
+    pub args: Vec<String>,
+    /// Working directory; defaults to the host's.
+    pub cwd: Option<PathBuf>,
+    /// Extra environment variables for the shell.
+    pub env: Vec<(String, String)>,
+    /// Initial width in cells.
+    pub cols: u16,
+    /// Initial height in cells.
+    pub rows: u16,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        Self {
+            shell: None,
+            args: Vec::new(),
+            cwd: None,
+            env: Vec::new(),
+            cols: 80,
+            rows: 24,
+        }
+    }
+}
+
+/// A key the host's toolkit reported, independent of that toolkit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalKey {
+    /// A printable character.
+    Char(char),
+    /// Return.
+    Enter,
+    /// Backspace.
+    Backspace,
+    /// Tab.
+    Tab,
+    /// Escape.
+    Escape,
+    /// Up arrow.
+    Up,
+    /// Down arrow.
+    Down,
+    /// Left arrow.
+    Left,
+    /// Right arrow.
+    Right,
+    /// Home.
+    Home,
+    /// End.
+    End,
+    /// Page Up.
+    PageUp,
+    /// Page Down.
+    PageDown,
+    /// Forward delete.
+    Delete,
+}
+
+/// Modifier keys held with a [`TerminalKey`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyModifiers {
+    /// Control.
+    pub ctrl: bool,
+    /// Alt (Option on macOS); sent as an `ESC` prefix.
+    pub alt: bool,
+}
+
+/// Encodes a key press as the bytes an xterm-compatible terminal sends.
+pub fn encode_key(key: TerminalKey, modifiers: KeyModifiers) -> Vec<u8> {
+    let mut bytes = match key {
+        TerminalKey::Char(c) if modifiers.ctrl && c.is_ascii_alphabetic() => {
+            vec![c.to_ascii_lowercase() as u8 - b'a' + 1]
+        }
+        TerminalKey::Char(c) => c.to_string().into_bytes(),
+        TerminalKey::Enter => vec![b'\r'],
+        TerminalKey::Backspace => vec![0x7f],
+        TerminalKey::Tab => vec![b'\t'],
+        TerminalKey::Escape => vec![0x1b],
+        TerminalKey::Up => b"\x1b[A".to_vec(),
+        TerminalKey::Down => b"\x1b[B".to_vec(),
+        TerminalKey::Right => b"\x1b[C".to_vec(),
+        TerminalKey::Left => b"\x1b[D".to_vec(),
+        TerminalKey::Home => b"\x1b[H".to_vec(),
+        TerminalKey::End => b"\x1b[F".to_vec(),
+        TerminalKey::PageUp => b"\x1b[5~".to_vec(),
+        TerminalKey::PageDown => b"\x1b[6~".to_vec(),
+        TerminalKey::Delete => b"\x1b[3~".to_vec(),
+    };
+    if modifiers.alt {
+        bytes.insert(0, 0x1b);
+    }
+    bytes
+}
+
+/// A finished command and the output it printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalBlock {
+    /// The command line as submitted.
+    pub command: String,
+    /// Everything the command printed.
+    pub output: String,
+}
+
+/// A running shell with its screen and command history, ready to be drawn
+/// by any GUI toolkit.
+pub struct TerminalView {
+    vte: Arc<Mutex<VteState>>,
+    writer: Box<dyn Write + Send>,
+    pty_pair: PtyPair,
+    child: Box<dyn Child + Send + Sync>,
+    blocks: Vec<TerminalBlock>,
+    active_command: Option<String>,
+}
+
+impl TerminalView {
+    /// Starts the shell. `on_output` is called from a background thread
+    /// whenever new output has been parsed; hosts typically request a
+    /// repaint from it.
+    pub fn spawn(options: TerminalOptions, on_output: impl Fn() + Send + 'static) -> Result<Self, ViewError> {
+        let pty_pair = NativePtySystem::default()
+            .openpty(PtySize { rows: options.rows, cols: options.cols, ..Default::default() })
+            .map_err(|e| ViewError::Spawn(e.to_string()))?;
+
+        let shell = options
+            .shell
+            .or_else(|| std::env::var("SHELL").ok())
+            .unwrap_or_else(|| "/bin/sh".to_string());
+        let mut cmd = CommandBuilder::new(shell);
+        cmd.args(&options.args);
+        for (name, value) in &options.env {
+            cmd.env(name, value);
+        }
+        if let Some(cwd) = options.cwd.or_else(|| std::env::current_dir().ok()) {
+            cmd.cwd(cwd);
+        }
+        let child = pty_pair.slave.spawn_command(cmd).map_err(|e| ViewError::Spawn(e.to_string()))?;
+
+        let writer = pty_pair.master.take_writer().map_err(|e| ViewError::Spawn(e.to_string()))?;
+        let mut reader = pty_pair.master.try_clone_reader().map_err(|e| ViewError::Spawn(e.to_string()))?;
+        let vte = Arc::new(Mutex::new(VteState::new(options.cols, options.rows)));
+        let vte_clone = vte.clone();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 8192];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        vte_clone.lock().unwrap().process(&buffer[..n]);
+                        on_output();
+                    }
+                }
+            }
+        });
+
+        Ok(Self { vte, writer, pty_pair, child, blocks: Vec::new(), active_command: None })
+    }
+
+    /// Sends a key press to the shell.
+    pub fn handle_key(&mut self, key: TerminalKey, modifiers: KeyModifiers) -> Result<(), ViewError> {
+        self.write(&encode_key(key, modifiers))
+    }
+
+    /// Sends text to the shell as typed, e.g. from a paste or an IME commit.
+    pub fn send_text(&mut self, text: &str) -> Result<(), ViewError> {
+        self.write(text.as_bytes())
+    }
+
+    /// Runs a command as a new block. The screen so far becomes the previous
+    /// command's block, and is cleared for this command's output.
+    pub fn submit_command(&mut self, command: &str) -> Result<(), ViewError> {
+        self.seal_block();
+        self.active_command = Some(command.to_string());
+        self.write(format!("{}\n", command).as_bytes())
+    }
+
+    /// Moves the running command and its output into [`Self::blocks`].
+    pub fn seal_block(&mut self) {
+        let Some(command) = self.active_command.take() else {
+            return;
+        };
+        let output = {
+            let mut vte = self.vte.lock().unwrap();
+            let output = vte.get_grid().as_ref().to_string();
+            vte.clear_all();
+            output
+        };
+        self.blocks.push(TerminalBlock { command, output });
+    }
+
+    /// Completed blocks, oldest first.
+    pub fn blocks(&self) -> &[TerminalBlock] {
+        &self.blocks
+    }
+
+    /// The command whose output is on screen now, if one was submitted.
+    pub fn active_command(&self) -> Option<&str> {
+        self.active_command.as_deref()
+    }
+
+    /// The live screen, locked for drawing. Hold it only while painting.
+    pub fn screen(&self) -> std::sync::MutexGuard<'_, VteState> {
+        self.vte.lock().unwrap()
+    }
+
+    /// Resizes the screen and tells the shell its new size.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<(), ViewError> {
+        self.vte.lock().unwrap().resize(cols, rows);
+        self.pty_pair
+            .master
+            .resize(PtySize { rows, cols, ..Default::default() })
+            .map_err(|e| ViewError::Spawn(e.to_string()))
+    }
+
+    /// Whether the shell is still running.
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ViewError> {
+        self.writer.write_all(bytes)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for TerminalView {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_key() {
+        let none = KeyModifiers::default();
+        let ctrl = KeyModifiers { ctrl: true, alt: false };
+        let alt = KeyModifiers { ctrl: false, alt: true };
+        assert_eq!(encode_key(TerminalKey::Char('é'), none), "é".as_bytes());
+        assert_eq!(encode_key(TerminalKey::Char('C'), ctrl), vec![3]);
+        assert_eq!(encode_key(TerminalKey::Char('b'), alt), b"\x1bb");
+        assert_eq!(encode_key(TerminalKey::Up, none), b"\x1b[A");
+        assert_eq!(encode_key(TerminalKey::Enter, none), b"\r");
+    }
+}
@@ -25,22 +25,21 @@ use serde::{Deserialize, Serialize};
 use shellwords;
 use similar::{ChangeTag, TextDiff};
 use std::fs;
-use std::io::Write as _;
 use std::path::Path;
 use std::time::SystemTime;
 use std::{
-    io::{self, Read, Write},
+    io::{self, Read},
     path::PathBuf,
-    sync::{Arc, Mutex},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::sync::mpsc;
 use uuid::Uuid;
-use warpish_terminal_v2::{
-    agent::client::{AgentResponse, SimulatedAgent},
+use warpish_terminal::{
+    agent::client::{AgentProvider, AgentResponse},
     app::{
+        input::{crossterm_key, ImeState, InputModifiers},
         pane::Pane,
         state::{AgentState, App, AppMode, CursorShape, InputPosition, PaletteItem, PromptMode},
     },
@@ -51,20 +50,21 @@ use warpish_terminal_v2::{
     db::establish_connection,
     drive::{DriveManager, DriveObject, Notebook, Prompt, WorkflowBrowserState},
     error::AppError,
-    event::UserAppEvent,
+    event::AppEvent,
     input_handler::handle_input,
     keybindings::{load_keymap_from_yaml, KeyBinding, Keymap},
     pty::vte_handler::VteState,
     rules::{Rule, RuleAction},
     ui::{
         renderer::Renderer,
+        scroll::ScrollInput,
         theme::{load_theme, Theme, ThemeManager}, // Added load_theme here
     },
     vim::VimState,
     workflows::Workflow,
 };
 use winit::{
-    event::{ElementState, Event, KeyEvent as WinitKeyEvent, Modifiers, WindowEvent},
+    event::{ElementState, Event, KeyEvent as WinitKeyEvent, Modifiers, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::WindowBuilder,
@@ -80,11 +80,26 @@ fn default_true() -> bool {
     true
 }
 
+/// How often background work is collected when no events arrive.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
 pub fn main() -> Result<()> {
-    env_logger::init();
+    warpish_terminal::crash::init_logging();
+
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        let report = warpish_terminal::doctor::run();
+        print!("{}", report.render());
+        std::process::exit(if report.healthy() { 0 } else { 1 });
+    }
     info!("Starting Warpish Terminal");
 
     let mut config = load_config().unwrap_or_default();
+    if config.crash_reports.enabled {
+        warpish_terminal::crash::install_panic_hook(
+            warpish_terminal::crash::default_crash_dir(),
+            warpish_terminal::crash::config_hash(&config),
+        );
+    }
 
     // Load theme based on config
     let theme_path = config
@@ -120,7 +135,7 @@ pub fn main() -> Result<()> {
         winit::dpi::PhysicalSize::new(900, 600)
     };
 
-    let event_loop: EventLoop<UserAppEvent> = EventLoop::with_user_event();
+    let event_loop: EventLoop<AppEvent> = EventLoop::with_user_event();
     let window = WindowBuilder::new()
         .with_title("Warpish Terminal")
         .with_inner_size(initial_size)
@@ -144,7 +159,7 @@ pub fn main() -> Result<()> {
     let mut db_conn = establish_connection();
     info!("Database connection established.");
 
-    match crate::rules::load_rules_from_yaml(Path::new("rules.yaml")) {
+    match warpish_terminal::rules::load_rules_from_yaml(Path::new("rules.yaml")) {
         Ok(loaded_rules) => {
             info!(
                 "Successfully loaded {} rules from rules.yaml.",
@@ -184,7 +199,7 @@ pub fn main() -> Result<()> {
         let receiver = GlobalHotKeyEvent::receiver();
         while let Ok(event) = receiver.recv() {
             event_loop_proxy
-                .send_event(UserAppEvent::GlobalHotkey(event.id))
+                .send_event(AppEvent::GlobalHotkey(event.id))
                 .ok();
         }
     });
@@ -193,7 +208,6 @@ pub fn main() -> Result<()> {
     let window_size = window.inner_size();
     let (grid_cols, grid_rows) = renderer.resize(window_size);
 
-    let agent = SimulatedAgent::new(); // Initialize SimulatedAgent here
 
     let mut app = App::new(
         vec![Pane::new(
@@ -215,32 +229,52 @@ pub fn main() -> Result<()> {
         db_conn,
         completions_manager,
     );
+    app.event_proxy = Some(event_loop.create_proxy());
 
     let tokio_runtime = tokio::runtime::Runtime::new().unwrap();
-    let arc_completions_manager = Arc::new(Mutex::new(app.completions_manager.clone()));
+    let suggestions_proxy = event_loop.create_proxy();
+    app.completions_manager.on_results(move |results| {
+        suggestions_proxy.send_event(AppEvent::Suggestions(results)).ok();
+    });
+    // Pointer state for dragging the timeline scrubber and clicking minimaps
+    let mut cursor_x = 0.0;
+    let mut cursor_y = 0.0;
+    let mut dragging = false;
+    let mut modifiers = Modifiers::default();
+    let mut next_tick = Instant::now() + TICK_INTERVAL;
 
     event_loop
         .run(move |event, elwt| {
-            elwt.set_control_flow(ControlFlow::Wait);
+            // Wake for the next tick even when no input arrives.
+            elwt.set_control_flow(ControlFlow::WaitUntil(next_tick));
 
             match event {
                 Event::UserEvent(app_event) => match app_event {
-                    UserAppEvent::PtyOutput => {
+                    AppEvent::PtyOutput => {
                         window.request_redraw();
                     }
-                    UserAppEvent::AgentCompleted { pane_id, response } => {
-                        if let Some(pane) = app.panes.iter_mut().find(|p| p.id == pane_id) {
-                            if let Some(agent_state) = &mut pane.agent_state {
-                                agent_state.status = crate::app::pane::AgentStatus::WaitingForInput;
-                                agent_state.conversation.last_mut().unwrap().1 = response;
-                            }
+                    AppEvent::Suggestions(results) => {
+                        app.completions_manager.receive(results);
+                        window.request_redraw();
+                    }
+                    AppEvent::AgentCompleted { pane_id, prompt, response } => {
+                        app.complete_agent_query(pane_id, prompt, response);
+                        window.request_redraw();
+                    }
+                    other => {
+                        if let Err(e) = app.handle_event(other) {
+                            warn!("Failed to handle event: {}", e);
                         }
+                        window.request_redraw();
                     }
-                    _ => {}
                 },
                 Event::WindowEvent { window_id, event } if window_id == window.id() => {
                     match event {
-                        WindowEvent::CloseRequested => elwt.exit(),
+                        // Asks first if jobs are running; the app quits once confirmed.
+                        WindowEvent::CloseRequested => {
+                            app.request_quit();
+                            window.request_redraw();
+                        }
                         WindowEvent::Resized(physical_size) => {
                             let (new_cols, new_rows) = renderer.resize(physical_size);
                             for pane in &mut app.panes {
@@ -248,7 +282,42 @@ pub fn main() -> Result<()> {
                             }
                             window.request_redraw();
                         }
+                        // Dragged to a monitor with another DPI: re-shape text at the new
+                        // scale. The Resized event that follows sizes the surface.
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                            let (new_cols, new_rows) = renderer.set_scale_factor(scale_factor);
+                            for pane in &mut app.panes {
+                                pane.resize(new_cols, new_rows);
+                            }
+                            window.request_redraw();
+                        }
+                        WindowEvent::Ime(ime) => {
+                            // Keys belong to the input method while it shows pre-edit text.
+                            app.ime_state = match ime {
+                                winit::event::Ime::Preedit(text, _) if !text.is_empty() => ImeState::Composing,
+                                winit::event::Ime::Commit(text) => {
+                                    app.input_editor.insert_string(&text, None);
+                                    ImeState::Inactive
+                                }
+                                _ => ImeState::Inactive,
+                            };
+                            window.request_redraw();
+                        }
+                        // Typed into the command line, or attached in agent mode.
+                        WindowEvent::DroppedFile(path) => {
+                            app.drop_file(path);
+                            window.request_redraw();
+                        }
+                        WindowEvent::ModifiersChanged(new_modifiers) => modifiers = new_modifiers,
                         WindowEvent::KeyboardInput { event: key, .. } => {
+                            // Locked panes, idle tracking and Alt+P come before any mode.
+                            if key.state == ElementState::Pressed {
+                                let (code, term_modifiers) = crossterm_key(key.physical_key, InputModifiers::from(modifiers.state()));
+                                if app.handle_global_key(code, term_modifiers) {
+                                    window.request_redraw();
+                                    return;
+                                }
+                            }
                             if let PhysicalKey::Code(key_code) = key.physical_key {
                                 let active_pane = &mut app.panes[app.active_pane_idx];
                                 match app.mode {
@@ -260,25 +329,39 @@ pub fn main() -> Result<()> {
                                             {
                                                 let query = agent_state.current_input.clone();
                                                 let model_to_use = agent_state.model_used.clone();
+                                                let mut block_context = if agent_state.attachments.is_empty() {
+                                                    vec![]
+                                                } else {
+                                                    vec![agent_state.attachments.serialize(config.ai.context_token_budget).text]
+                                                };
+                                                // Answers come back in the language the question was asked in.
+                                                block_context.extend(warpish_terminal::agent::language_instruction(&query));
                                                 let event_proxy = event_loop.create_proxy();
                                                 let pane_id = active_pane.id;
 
-                                                let agent_clone = agent.clone(); // Clone agent for async use
+                                                // Clone agent for async use, metered under this conversation.
+                                                let agent_clone = app.usage.wrap(app.agent.clone(), &agent_state.id.to_string());
                                                 tokio_runtime.spawn(async move {
                                                     let response = agent_clone.process_query(
                                                         &query,
                                                         &[],
-                                                        &[],
+                                                        &block_context,
                                                         model_to_use,
                                                     );
                                                     event_proxy
-                                                        .send_event(UserAppEvent::AgentCompleted {
+                                                        .send_event(AppEvent::AgentCompleted {
                                                             pane_id,
+                                                            prompt: query,
                                                             response,
                                                         })
                                                         .ok();
                                                 });
                                             }
+                                        } else if key.state == ElementState::Pressed
+                                            && key_code == KeyCode::Backspace
+                                            && app.detach_last_context()
+                                        {
+                                            window.request_redraw();
                                         }
                                     }
                                     AppMode::Normal => {
@@ -297,15 +380,12 @@ pub fn main() -> Result<()> {
 
                                         if let Some(input_text) = input_result {
                                             if !input_text.is_empty() {
-                                                active_pane
-                                                    .pty_writer
-                                                    .write_all(input_text.as_bytes())
-                                                    .unwrap();
+                                                if let Err(e) = active_pane.run_command(&input_text) {
+                                                    warn!("Failed to send command to the shell: {}", e);
+                                                }
 
                                                 // Add to completions history
-                                                arc_completions_manager
-                                                    .lock()
-                                                    .unwrap()
+                                                app.completions_manager
                                                     .add_to_history(input_text.clone());
                                             }
                                         }
@@ -322,20 +402,9 @@ pub fn main() -> Result<()> {
                                             let cursor_pos =
                                                 app.input_editor.buffer().cursor().index;
 
-                                            // Spawn async task to update completions
-                                            let completions_manager_clone =
-                                                arc_completions_manager.clone();
-                                            let current_text_clone = current_text.clone();
-                                            tokio_runtime.spawn(async move {
-                                                completions_manager_clone
-                                                    .lock()
-                                                    .unwrap()
-                                                    .update_suggestions(
-                                                        &current_text_clone,
-                                                        cursor_pos,
-                                                    )
-                                                    .await;
-                                            });
+                                            // The suggestion service answers through the event loop.
+                                            app.completions_manager
+                                                .update_suggestions(&current_text, cursor_pos);
                                         }
 
                                         // Handle autosuggestions
@@ -347,16 +416,46 @@ pub fn main() -> Result<()> {
                                 }
                             }
                         }
+                        WindowEvent::MouseWheel { delta, phase, .. } => {
+                            // winit reports positive deltas when scrolling up, so they are negated.
+                            let input = match (delta, phase) {
+                                (_, TouchPhase::Ended) => ScrollInput::GestureEnd,
+                                (MouseScrollDelta::LineDelta(_, y), _) => ScrollInput::Lines(-y),
+                                (MouseScrollDelta::PixelDelta(position), _) => ScrollInput::Pixels(-position.y as f32),
+                            };
+                            app.scroll_active_pane(input, std::time::Instant::now());
+                            window.request_redraw();
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            cursor_x = position.x;
+                            cursor_y = position.y;
+                            if dragging && matches!(app.mode, AppMode::Scrub(_)) {
+                                app.scrub_to_fraction((cursor_x / window.inner_size().width.max(1) as f64) as f32);
+                                window.request_redraw();
+                            }
+                        }
+                        WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                            dragging = state == ElementState::Pressed;
+                            if dragging && matches!(app.mode, AppMode::Scrub(_)) {
+                                app.scrub_to_fraction((cursor_x / window.inner_size().width.max(1) as f64) as f32);
+                                window.request_redraw();
+                            } else if dragging && matches!(app.mode, AppMode::Normal) && app.config.blocks.show_minimap {
+                                // A click on a pane's minimap strip jumps to the block there
+                                let size = window.inner_size();
+                                let pane_width = size.width as f64 / app.panes.len().max(1) as f64;
+                                let pane_idx = (cursor_x / pane_width) as usize;
+                                let strip_x = (pane_idx + 1) as f64 * pane_width
+                                    - renderer.char_width as f64 * warpish_terminal::ui::minimap::STRIP_COLUMNS as f64;
+                                if pane_idx < app.panes.len() && cursor_x >= strip_x {
+                                    app.jump_to_minimap(pane_idx, (cursor_y / size.height.max(1) as f64) as f32, std::time::Instant::now());
+                                    window.request_redraw();
+                                }
+                            }
+                        }
                         WindowEvent::RedrawRequested => {
-                            // Render the completions UI if visible
-                            // This part might need more detailed integration within renderer.render
-                            // For now, it just requests redraw, which is handled by the main render call.
-                            if app.completions_manager.ui.is_visible {
-                                // The main render call in renderer.render(&mut app, ...) should ideally
-                                // handle rendering the completions UI based on app.completions_manager state.
-                                window.request_redraw(); // Ensure a redraw happens
+                            if app.tick_scroll(std::time::Instant::now()) {
+                                window.request_redraw();
                             }
-
                             match renderer.render(&mut app, Duration::from_secs(0)) {
                                 Ok(_) => {}
                                 Err(wgpu::SurfaceError::Lost) => {
@@ -369,6 +468,24 @@ pub fn main() -> Result<()> {
                         _ => {}
                     }
                 }
+                Event::AboutToWait if app.should_quit => {
+                    app.shutdown();
+                    elwt.exit();
+                }
+                // Background work (shell events, listeners, git status and the
+                // like) is collected on the tick.
+                Event::AboutToWait => {
+                    let now = Instant::now();
+                    if now >= next_tick {
+                        next_tick = now + TICK_INTERVAL;
+                        if let Err(e) = app.handle_event(AppEvent::Tick) {
+                            warn!("Tick failed: {}", e);
+                        }
+                        window.request_redraw();
+                    }
+                    elwt.set_control_flow(ControlFlow::WaitUntil(next_tick));
+                }
+                Event::LoopExiting => app.shutdown(),
                 _ => {}
             }
         })
@@ -0,0 +1,212 @@
+//! Cursors and iterators over a `SumTree`.
+
+use super::{Bias, Dimension, Item, Node, SeekTarget, SumTree};
+use std::cmp::Ordering;
+
+/// A position in a tree: an item, or the end, and where it starts in
+/// dimension `D`. Seeking descends from the root, so it costs the same
+/// whichever way the cursor moves.
+pub struct Cursor<'a, T: Item, D> {
+    tree: &'a SumTree<T>,
+    /// The nodes from the root down to the current leaf, with the child or
+    /// item taken in each. Empty at the end.
+    stack: Vec<(&'a SumTree<T>, usize)>,
+    index: usize,
+    start: D,
+}
+
+impl<'a, T: Item, D: Dimension<T::Summary>> Cursor<'a, T, D> {
+    pub(super) fn new(tree: &'a SumTree<T>) -> Self {
+        let mut cursor = Self { tree, stack: Vec::new(), index: 0, start: D::default() };
+        cursor.locate(0);
+        cursor
+    }
+
+    /// The item under the cursor; `None` at the end.
+    pub fn item(&self) -> Option<&'a T> {
+        let (tree, index) = self.stack.last()?;
+        match &*tree.0 {
+            Node::Leaf { items, .. } => items.get(*index),
+            Node::Internal { .. } => None,
+        }
+    }
+
+    pub fn item_summary(&self) -> Option<&'a T::Summary> {
+        let (tree, index) = self.stack.last()?;
+        match &*tree.0 {
+            Node::Leaf { item_summaries, .. } => item_summaries.get(*index),
+            Node::Internal { .. } => None,
+        }
+    }
+
+    /// How many items come before the cursor.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Where the current item starts.
+    pub fn start(&self) -> &D {
+        &self.start
+    }
+
+    /// Where the current item ends; the start at the end of the tree.
+    pub fn end(&self) -> D {
+        let mut end = self.start.clone();
+        if let Some(summary) = self.item_summary() {
+            end.add_summary(summary);
+        }
+        end
+    }
+
+    /// Moves to the item at `index`, or the end.
+    fn locate(&mut self, index: usize) {
+        self.stack.clear();
+        self.start = D::default();
+        self.index = index.min(self.tree.len());
+        if index >= self.tree.len() {
+            self.start = self.tree.extent();
+            return;
+        }
+        let mut tree = self.tree;
+        let mut remaining = index;
+        loop {
+            match &*tree.0 {
+                Node::Leaf { item_summaries, .. } => {
+                    for summary in &item_summaries[..remaining] {
+                        self.start.add_summary(summary);
+                    }
+                    self.stack.push((tree, remaining));
+                    return;
+                }
+                Node::Internal { child_summaries, child_trees, .. } => {
+                    for (i, child) in child_trees.iter().enumerate() {
+                        if remaining < child.len() {
+                            self.stack.push((tree, i));
+                            tree = child;
+                            break;
+                        }
+                        remaining -= child.len();
+                        self.start.add_summary(&child_summaries[i]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves to the first item that ends past `target`; with `Bias::Left`
+    /// an item ending exactly at `target` counts. Returns whether `target`
+    /// falls exactly on the edge of the item found: its end with
+    /// `Bias::Left`, its start with `Bias::Right`.
+    pub fn seek<Target: SeekTarget<T::Summary, D>>(&mut self, target: &Target, bias: Bias) -> bool {
+        let past = |end: &D| match target.cmp(end) {
+            Ordering::Less => true,
+            Ordering::Equal => bias == Bias::Left,
+            Ordering::Greater => false,
+        };
+        self.stack.clear();
+        self.start = D::default();
+        self.index = 0;
+        let mut tree = self.tree;
+        'descend: loop {
+            match &*tree.0 {
+                Node::Leaf { item_summaries, .. } => {
+                    for (i, summary) in item_summaries.iter().enumerate() {
+                        let mut end = self.start.clone();
+                        end.add_summary(summary);
+                        if past(&end) {
+                            self.stack.push((tree, i));
+                            break 'descend;
+                        }
+                        self.start = end;
+                        self.index += 1;
+                    }
+                    break;
+                }
+                Node::Internal { child_summaries, child_trees, .. } => {
+                    for (i, child) in child_trees.iter().enumerate() {
+                        let mut end = self.start.clone();
+                        end.add_summary(&child_summaries[i]);
+                        if past(&end) {
+                            self.stack.push((tree, i));
+                            tree = child;
+                            continue 'descend;
+                        }
+                        self.start = end;
+                        self.index += child.len();
+                    }
+                    break;
+                }
+            }
+        }
+        if self.item().is_none() {
+            // Past every item: the end of the tree.
+            self.stack.clear();
+        }
+        let edge = if bias == Bias::Left { self.end() } else { self.start.clone() };
+        target.cmp(&edge) == Ordering::Equal
+    }
+
+    /// Seeks forward to `target` and returns the items passed over.
+    pub fn slice<Target: SeekTarget<T::Summary, D>>(&mut self, target: &Target, bias: Bias) -> SumTree<T> {
+        let from = self.index;
+        self.seek(target, bias);
+        self.tree.slice(from..self.index.max(from))
+    }
+
+    /// The items from the cursor to the end, which it moves to.
+    pub fn suffix(&mut self) -> SumTree<T> {
+        let suffix = self.tree.slice(self.index..self.tree.len());
+        self.locate(self.tree.len());
+        suffix
+    }
+
+    pub fn next(&mut self) {
+        let Some(summary) = self.item_summary() else {
+            return;
+        };
+        let (tree, index) = self.stack.last_mut().expect("an item is under the cursor");
+        if *index + 1 < tree.len() {
+            self.start.add_summary(summary);
+            *index += 1;
+            self.index += 1;
+        } else {
+            self.locate(self.index + 1);
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if self.index > 0 {
+            self.locate(self.index - 1);
+        }
+    }
+}
+
+/// The items of a tree in order, by reference.
+pub struct Iter<'a, T: Item> {
+    stack: Vec<(&'a SumTree<T>, usize)>,
+}
+
+impl<'a, T: Item> Iter<'a, T> {
+    pub(super) fn new(tree: &'a SumTree<T>) -> Self {
+        Self { stack: vec![(tree, 0)] }
+    }
+}
+
+impl<'a, T: Item> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (tree, index) = self.stack.last_mut()?;
+            let (tree, i): (&'a SumTree<T>, usize) = (*tree, *index);
+            *index += 1;
+            match &*tree.0 {
+                Node::Leaf { items, .. } if i < items.len() => return Some(&items[i]),
+                Node::Internal { child_trees, .. } if i < child_trees.len() => self.stack.push((&child_trees[i], 0)),
+                _ => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
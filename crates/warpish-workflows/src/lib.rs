@@ -0,0 +1,6 @@
+//! Warpish Workflows
+//!
+//! Warpish Drive: the workflows, notebooks, prompts and environment variable
+//! sets a user keeps on disk, and the workspaces that hold them.
+
+pub mod drive;
@@ -0,0 +1,286 @@
+//! Abbreviations
+//!
+//! Fish-style abbreviations: a short word such as `gs` is replaced by its
+//! expansion (`git status`) when Space or Enter follows it in command
+//! position, so history and shared blocks show the full command. They are
+//! kept in `abbreviations.yaml` in the config directory, can be imported from
+//! the aliases in existing shell startup files, and can be exported as
+//! aliases for a shell, so they also work in a plain terminal.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AbbreviationError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML parsing error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("'{0}' is not a valid abbreviation; use a single word")]
+    InvalidName(String),
+}
+
+/// Abbreviations by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AbbreviationSet {
+    abbreviations: BTreeMap<String, String>,
+}
+
+impl AbbreviationSet {
+    /// Loads `abbreviations.yaml`, or an empty set when there is none.
+    pub fn load() -> Self {
+        let path = abbreviations_path();
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_yaml::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), AbbreviationError> {
+        let path = abbreviations_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, name: &str, expansion: &str) -> Result<(), AbbreviationError> {
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || "'\"`$;|&()<>=\\".contains(c)) {
+            return Err(AbbreviationError::InvalidName(name.to_string()));
+        }
+        self.abbreviations.insert(name.to_string(), expansion.trim().to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.abbreviations.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.abbreviations.get(name).map(String::as_str)
+    }
+
+    /// Abbreviations in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.abbreviations.iter().map(|(name, expansion)| (name.as_str(), expansion.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.abbreviations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.abbreviations.is_empty()
+    }
+
+    /// The abbreviation ending at `offset` in `text`, with the range it
+    /// replaces. Only a word in command position expands, as in fish, so
+    /// `echo gs` stays as typed.
+    pub fn expansion_at(&self, text: &str, offset: usize) -> Option<(Range<usize>, &str)> {
+        let before = &text[..offset];
+        let word = before.rsplit(|c: char| c.is_whitespace() || ";|&(".contains(c)).next()?;
+        // The cursor has to be at the end of the word.
+        if text[offset..].chars().next().is_some_and(|c| !c.is_whitespace() && !";|&)".contains(c)) {
+            return None;
+        }
+        let start = offset - word.len();
+        let preceding = before[..start].trim_end_matches([' ', '\t']);
+        if !(preceding.is_empty() || preceding.ends_with(['|', ';', '&', '(', '\n'])) {
+            return None;
+        }
+        Some((start..offset, self.get(word)?))
+    }
+
+    /// Adds imported aliases that do not clash with an existing
+    /// abbreviation, returning how many were added.
+    pub fn import(&mut self, aliases: Vec<(String, String)>) -> usize {
+        let mut added = 0;
+        for (name, expansion) in aliases {
+            if !self.abbreviations.contains_key(&name) && self.insert(&name, &expansion).is_ok() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    /// The abbreviations as aliases, or `abbr`s for fish, in the syntax of
+    /// `shell`, a path or a name.
+    pub fn export(&self, shell: &str) -> String {
+        let kind = ShellKind::of(shell);
+        let mut script = "# Abbreviations exported from warpish\n".to_string();
+        for (name, expansion) in self.iter() {
+            script.push_str(&match kind {
+                ShellKind::Posix => format!("alias {}={}\n", name, single_quote(expansion)),
+                ShellKind::Fish => format!("abbr --add {} {}\n", name, single_quote(expansion)),
+                ShellKind::Nu => format!("alias {} = {}\n", name, expansion),
+                ShellKind::PowerShell => format!("function {} {{ {} @args }}\n", name, expansion),
+            });
+        }
+        script
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellKind {
+    Posix,
+    Fish,
+    Nu,
+    PowerShell,
+}
+
+impl ShellKind {
+    fn of(shell: &str) -> Self {
+        match Path::new(shell).file_stem().and_then(|name| name.to_str()) {
+            Some("fish") => ShellKind::Fish,
+            Some("nu") => ShellKind::Nu,
+            Some("pwsh" | "powershell") => ShellKind::PowerShell,
+            _ => ShellKind::Posix,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ShellKind::Posix => "sh",
+            ShellKind::Fish => "fish",
+            ShellKind::Nu => "nu",
+            ShellKind::PowerShell => "ps1",
+        }
+    }
+}
+
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Reads `alias` lines from bash, zsh, fish and nushell startup files, and
+/// fish's `abbr` lines.
+pub fn parse_aliases(content: &str) -> Vec<(String, String)> {
+    let mut aliases = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let (is_abbr, rest) = match (line.strip_prefix("alias "), line.strip_prefix("abbr ")) {
+            (Some(rest), _) => (false, rest),
+            (_, Some(rest)) => (true, rest),
+            _ => continue,
+        };
+        let Ok(words) = shellwords::split(rest) else {
+            continue;
+        };
+        // Flags such as zsh's `alias -g` and fish's `abbr --add`.
+        let words: Vec<&str> = words.iter().map(String::as_str).skip_while(|word| word.starts_with('-')).collect();
+        match words.as_slice() {
+            [] => {}
+            // nushell: `alias gs = git status`
+            [name, "=", expansion @ ..] if !expansion.is_empty() => aliases.push((name.to_string(), expansion.join(" "))),
+            // bash and zsh, several to a line: `alias gs='git status' ll='ls -l'`
+            [first, ..] if !is_abbr && first.contains('=') => {
+                for word in &words {
+                    if let Some((name, expansion)) = word.split_once('=') {
+                        aliases.push((name.to_string(), expansion.to_string()));
+                    }
+                }
+            }
+            // fish: `alias gs 'git status'` and `abbr -a gs git status`
+            [name, expansion @ ..] if !expansion.is_empty() => aliases.push((name.to_string(), expansion.join(" "))),
+            _ => {}
+        }
+    }
+    aliases
+}
+
+/// Shell startup files under `home` that exist and may define aliases.
+pub fn alias_files(home: &Path) -> Vec<PathBuf> {
+    [".bashrc", ".bash_aliases", ".zshrc", ".zsh_aliases", ".config/fish/config.fish", ".config/nushell/config.nu"]
+        .iter()
+        .map(|name| home.join(name))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+pub fn abbreviations_path() -> PathBuf {
+    config_dir().join("abbreviations.yaml")
+}
+
+/// Where the aliases exported for `shell` are written.
+pub fn export_path(shell: &str) -> PathBuf {
+    config_dir().join(format!("abbreviations.{}", ShellKind::of(shell).extension()))
+}
+
+/// The line that loads an exported file in `shell`'s startup file.
+pub fn source_line(shell: &str, path: &Path) -> String {
+    match ShellKind::of(shell) {
+        ShellKind::PowerShell => format!(". '{}'", path.display()),
+        _ => format!("source {}", single_quote(&path.display().to_string())),
+    }
+}
+
+fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")).join("warpish")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set() -> AbbreviationSet {
+        let mut set = AbbreviationSet::default();
+        set.insert("gs", "git status").unwrap();
+        set.insert("k", "kubectl").unwrap();
+        set
+    }
+
+    #[test]
+    fn test_expands_only_in_command_position() {
+        let set = set();
+        assert_eq!(set.expansion_at("gs", 2), Some((0..2, "git status")));
+        assert_eq!(set.expansion_at("make && k", 9), Some((8..9, "kubectl")));
+        assert_eq!(set.expansion_at("ls | gs", 7), Some((5..7, "git status")));
+        assert_eq!(set.expansion_at("echo gs", 7), None);
+        assert_eq!(set.expansion_at("gsx", 3), None);
+        assert_eq!(set.expansion_at("gs x", 1), None);
+        assert!(set.clone().insert("g s", "x").is_err());
+    }
+
+    #[test]
+    fn test_aliases_are_imported() {
+        let content = "\
+# aliases
+alias gs='git status'
+alias -g ll=\"ls -l\" la='ls -A'
+alias gd 'git diff'
+abbr -a gco git checkout
+alias k = kubectl
+export PATH=$PATH:~/bin
+";
+        let aliases = parse_aliases(content);
+        let pairs: Vec<(&str, &str)> = aliases.iter().map(|(name, expansion)| (name.as_str(), expansion.as_str())).collect();
+        assert_eq!(
+            pairs,
+            vec![("gs", "git status"), ("ll", "ls -l"), ("la", "ls -A"), ("gd", "git diff"), ("gco", "git checkout"), ("k", "kubectl")]
+        );
+
+        let mut set = set();
+        assert_eq!(set.import(aliases), 4);
+        assert_eq!(set.get("k"), Some("kubectl"));
+    }
+
+    #[test]
+    fn test_export_per_shell() {
+        let mut set = AbbreviationSet::default();
+        set.insert("say", "echo 'hi'").unwrap();
+        assert_eq!(set.export("/bin/zsh").lines().nth(1), Some("alias say='echo '\\''hi'\\'''"));
+        assert_eq!(set.export("fish").lines().nth(1), Some("abbr --add say 'echo '\\''hi'\\'''"));
+        assert_eq!(set.export("nu").lines().nth(1), Some("alias say = echo 'hi'"));
+        assert_eq!(set.export("pwsh").lines().nth(1), Some("function say { echo 'hi' @args }"));
+        assert!(export_path("/usr/bin/fish").ends_with("abbreviations.fish"));
+    }
+}
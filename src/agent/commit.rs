@@ -0,0 +1,211 @@
+//! Commit Messages From Staged Changes
+//!
+//! Sends `git diff --staged` to the agent with a commit message prompt and
+//! turns the reply into a `git commit -m` command for the user to review.
+//! The prompt is a library prompt, so a Drive prompt named "Commit Message"
+//! replaces the built-in one. When the repository uses Conventional Commits
+//! — set in the config, or detected from a commitlint or commitizen file —
+//! the prompt asks for that form and replies not in it are flagged.
+
+use crate::agent::client::AgentProvider;
+use crate::agent::model::ModelId;
+use crate::agent::translate::response_text;
+use crate::drive::{DriveError, Prompt};
+use crate::git::{self, GitError};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use thiserror::Error;
+
+/// The library prompt that writes commit messages.
+pub const TEMPLATE_NAME: &str = "Commit Message";
+
+const TEMPLATE: &str = "Write a git commit message for the staged changes below. Reply with the message only: a subject \
+line of at most 72 characters in the imperative mood, then, if the change needs explaining, a blank line and a short \
+body.\n{{style}}\n\n{{staged_diff}}";
+
+/// How much of the diff the agent sees; the start of each large diff says
+/// the most about it.
+const MAX_DIFF_CHARS: usize = 12_000;
+
+/// Files whose presence means the repository lints commit messages as
+/// Conventional Commits.
+const CONVENTIONAL_MARKERS: &[&str] = &[
+    ".commitlintrc",
+    ".commitlintrc.json",
+    ".commitlintrc.yaml",
+    ".commitlintrc.yml",
+    ".commitlintrc.js",
+    "commitlint.config.js",
+    "commitlint.config.ts",
+    ".czrc",
+    ".cz.toml",
+];
+
+#[derive(Error, Debug)]
+pub enum CommitMessageError {
+    #[error(transparent)]
+    Git(#[from] GitError),
+    #[error("Not in a git repository")]
+    NotARepository,
+    #[error("Nothing is staged")]
+    NothingStaged,
+    #[error(transparent)]
+    Prompt(#[from] DriveError),
+    #[error("The agent replied without a message")]
+    EmptyReply,
+}
+
+/// A generated message and the command that commits with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitMessage {
+    pub message: String,
+    pub command: String,
+    /// Set when Conventional Commits are expected but the subject is not one.
+    pub unconventional: bool,
+}
+
+/// The built-in commit message prompt.
+pub fn template() -> Prompt {
+    Prompt {
+        name: TEMPLATE_NAME.to_string(),
+        content: TEMPLATE.to_string(),
+        description: "Write a commit message for the staged changes".to_string(),
+    }
+}
+
+/// Whether the repository at `root` has a commitlint or commitizen config.
+pub fn detect_conventional(root: &Path) -> bool {
+    CONVENTIONAL_MARKERS.iter().any(|marker| root.join(marker).exists())
+}
+
+fn conventional_subject() -> &'static Regex {
+    static SUBJECT: OnceLock<Regex> = OnceLock::new();
+    SUBJECT.get_or_init(|| {
+        Regex::new(r"^(feat|fix|docs|style|refactor|perf|test|build|ci|chore|revert)(\([\w./-]+\))?!?: \S").unwrap()
+    })
+}
+
+pub fn is_conventional(subject: &str) -> bool {
+    conventional_subject().is_match(subject)
+}
+
+/// Fills in `prompt` with the diff, cut to `MAX_DIFF_CHARS`, and the style
+/// to write in.
+pub fn render_prompt(prompt: &Prompt, diff: &str, conventional: bool) -> Result<String, DriveError> {
+    let diff = match diff.char_indices().nth(MAX_DIFF_CHARS) {
+        Some((end, _)) => format!("{}\n[diff truncated]", &diff[..end]),
+        None => diff.to_string(),
+    };
+    let style = if conventional {
+        "Follow Conventional Commits: start the subject with a type such as feat, fix, docs, refactor, test or chore, \
+         an optional scope in parentheses, and a colon, e.g. `fix(parser): handle empty input`."
+    } else {
+        "Do not prefix the subject with a type or scope."
+    };
+    let values: HashMap<String, String> =
+        [("staged_diff".to_string(), diff), ("style".to_string(), style.to_string())].into_iter().collect();
+    prompt.render(&values)
+}
+
+/// The message in the agent's reply, without code fences, quotes around
+/// the whole of it or trailing whitespace.
+pub fn parse_message(reply: &str) -> Result<String, CommitMessageError> {
+    let lines: Vec<&str> = reply.lines().filter(|line| !line.trim_start().starts_with("```")).map(str::trim_end).collect();
+    let message = lines.join("\n");
+    let mut message = message.trim();
+    if message.len() >= 2 && message.starts_with('"') && message.ends_with('"') {
+        message = message[1..message.len() - 1].trim();
+    }
+    if message.is_empty() {
+        return Err(CommitMessageError::EmptyReply);
+    }
+    Ok(message.to_string())
+}
+
+/// `git commit` with one `-m` per paragraph of `message`, each quoted for
+/// POSIX shells.
+pub fn commit_command(message: &str) -> String {
+    let mut command = "git commit".to_string();
+    for paragraph in message.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        let quoted = paragraph.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$").replace('`', "\\`");
+        command.push_str(&format!(" -m \"{}\"", quoted));
+    }
+    command
+}
+
+/// Asks the agent for commit messages on a background thread, which also
+/// reads the diff.
+pub struct CommitMessageGenerator {
+    agent: Arc<dyn AgentProvider>,
+    result_tx: Sender<Result<CommitMessage, CommitMessageError>>,
+    results: Receiver<Result<CommitMessage, CommitMessageError>>,
+}
+
+impl CommitMessageGenerator {
+    pub fn new(agent: Arc<dyn AgentProvider>) -> Self {
+        let (result_tx, results) = channel();
+        Self { agent, result_tx, results }
+    }
+
+    /// Writes a message for the changes staged in the repository containing
+    /// `dir`. `conventional` overrides detection when set.
+    pub fn generate(&self, dir: PathBuf, prompt: Prompt, conventional: Option<bool>, model: ModelId) {
+        let agent = self.agent.clone();
+        let result_tx = self.result_tx.clone();
+        thread::spawn(move || {
+            let result = (|| {
+                let root = git::repo_root(&dir).ok_or(CommitMessageError::NotARepository)?;
+                let diff = git::staged_diff(&root)?;
+                if diff.trim().is_empty() {
+                    return Err(CommitMessageError::NothingStaged);
+                }
+                let conventional = conventional.unwrap_or_else(|| detect_conventional(&root));
+                let query = render_prompt(&prompt, &diff, conventional)?;
+                let message = parse_message(&response_text(agent.process_query(&query, &[], &[], model)))?;
+                let subject = message.lines().next().unwrap_or_default();
+                Ok(CommitMessage {
+                    command: commit_command(&message),
+                    unconventional: conventional && !is_conventional(subject),
+                    message,
+                })
+            })();
+            result_tx.send(result).ok();
+        });
+    }
+
+    /// Takes the finished generations.
+    pub fn poll(&self) -> Vec<Result<CommitMessage, CommitMessageError>> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_follows_the_commit_style() {
+        let diff = format!("diff --git a/src/lib.rs b/src/lib.rs\n+{}", "x".repeat(MAX_DIFF_CHARS));
+        let prompt = render_prompt(&template(), &diff, true).unwrap();
+        assert!(prompt.contains("Follow Conventional Commits"));
+        assert!(prompt.contains("diff --git a/src/lib.rs") && prompt.ends_with("[diff truncated]"));
+        assert!(render_prompt(&template(), "+x", false).unwrap().contains("Do not prefix the subject"));
+
+        assert!(is_conventional("fix(parser): handle empty input"));
+        assert!(is_conventional("feat!: drop the old config format"));
+        assert!(!is_conventional("Handle empty input in the parser"));
+    }
+
+    #[test]
+    fn test_replies_become_quoted_commands() {
+        let reply = "```\n\"Fix the \"$HOME\" lookup\n\nUse `dirs` instead.\"\n```\n";
+        let message = parse_message(reply).unwrap();
+        assert_eq!(message, "Fix the \"$HOME\" lookup\n\nUse `dirs` instead.");
+        assert_eq!(commit_command(&message), r#"git commit -m "Fix the \"\$HOME\" lookup" -m "Use \`dirs\` instead.""#);
+        assert!(matches!(parse_message("```\n```"), Err(CommitMessageError::EmptyReply)));
+    }
+}
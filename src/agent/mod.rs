@@ -1,2 +1,47 @@
-pub mod client;
-pub mod model;
\ No newline at end of file
+pub use warpish_ai::{attachments, client, model, scripted};
+pub mod commit;
+pub mod prompts;
+pub mod quiz;
+pub mod tools;
+pub mod translate;
+
+use crate::config::AiConfig;
+use crate::natural_language_detection::language::{detect_language, Language};
+use client::{AgentProvider, SimulatedAgent};
+use scripted::ScriptedAgent;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Overrides `ai.agent_script`, so demos and tests can script the agent
+/// without touching the config file.
+pub const AGENT_SCRIPT_ENV: &str = "WARPISH_AGENT_SCRIPT";
+
+/// The agent queries go to: a scripted replay when one is configured,
+/// otherwise the simulated agent.
+pub fn provider(config: &AiConfig) -> Arc<dyn AgentProvider> {
+    let script = std::env::var_os(AGENT_SCRIPT_ENV).map(PathBuf::from).or_else(|| config.agent_script.clone());
+    let Some(script) = script else {
+        return Arc::new(SimulatedAgent::new());
+    };
+    match ScriptedAgent::load(&script) {
+        Ok(agent) => {
+            log::info!("Replaying agent script {}", script.display());
+            Arc::new(agent)
+        }
+        Err(e) => {
+            log::error!("{}; falling back to the simulated agent", e);
+            Arc::new(SimulatedAgent::new())
+        }
+    }
+}
+
+/// Context asking the agent to answer in the language `query` is written
+/// in, when that is not English.
+pub fn language_instruction(query: &str) -> Option<String> {
+    let language = detect_language(query).filter(|language| *language != Language::English)?;
+    Some(format!(
+        "The user wrote in {0} ({1}). Reply in {0}, but keep commands, code, paths and error messages as they are.",
+        language.name(),
+        language.code()
+    ))
+}
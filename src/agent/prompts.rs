@@ -0,0 +1,126 @@
+//! Prompt Library
+//!
+//! Reusable agent prompts: a few built in, and any kept in Drive as YAML
+//! files with a `prompt` key. Their `{{variables}}` are filled in from where
+//! the user is — the text selected in the input, the output of the last
+//! block and the working directory. A Drive prompt with the name of a
+//! built-in one replaces it, so teams can tune the defaults.
+
+use crate::drive::{DriveError, Prompt};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// How much of the last block's output goes into a prompt. Errors are
+/// usually at the end, so the tail is kept.
+const MAX_OUTPUT_CHARS: usize = 4000;
+
+const BUILTIN: &[(&str, &str, &str)] = &[
+    (
+        "Explain This Error",
+        "Explain why the last command failed and how to fix it",
+        "This command failed in {{cwd}}:\n\n{{last_block_output}}\n\nExplain what went wrong and how to fix it.",
+    ),
+    (
+        "Explain Selection",
+        "Explain the text selected in the input",
+        "Explain what this does, step by step:\n\n{{selection}}",
+    ),
+    (
+        "Summarize Output",
+        "Summarize the output of the last command",
+        "Summarize this output in a few bullet points, calling out anything that needs attention:\n\n{{last_block_output}}",
+    ),
+];
+
+/// Where a prompt is used, for filling in its variables.
+#[derive(Debug, Clone, Default)]
+pub struct PromptContext {
+    pub selection: Option<String>,
+    pub last_block_output: Option<String>,
+    pub cwd: Option<PathBuf>,
+}
+
+impl PromptContext {
+    /// The variable values, leaving out the ones not known here.
+    pub fn values(&self) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        if let Some(selection) = &self.selection {
+            values.insert("selection".to_string(), selection.clone());
+        }
+        if let Some(output) = &self.last_block_output {
+            values.insert("last_block_output".to_string(), tail(output, MAX_OUTPUT_CHARS).to_string());
+        }
+        if let Some(cwd) = &self.cwd {
+            values.insert("cwd".to_string(), cwd.display().to_string());
+        }
+        values
+    }
+}
+
+/// The last `max` characters of `text`.
+fn tail(text: &str, max: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max {
+        return text;
+    }
+    let start = text.char_indices().nth(count - max).map_or(text.len(), |(start, _)| start);
+    &text[start..]
+}
+
+pub fn builtin() -> Vec<Prompt> {
+    BUILTIN
+        .iter()
+        .map(|(name, description, content)| Prompt {
+            name: name.to_string(),
+            content: content.to_string(),
+            description: description.to_string(),
+        })
+        .collect()
+}
+
+/// The built-in prompts followed by those from Drive, a Drive prompt
+/// replacing the built-in of the same name.
+pub fn all(drive: Vec<Prompt>) -> Vec<Prompt> {
+    let mut prompts: Vec<Prompt> = builtin().into_iter().filter(|prompt| !drive.iter().any(|own| own.name == prompt.name)).collect();
+    prompts.extend(drive);
+    prompts
+}
+
+pub fn render(prompt: &Prompt, context: &PromptContext) -> Result<String, DriveError> {
+    prompt.render(&context.values())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drive_prompts_replace_builtins() {
+        let own = Prompt {
+            name: "Explain This Error".to_string(),
+            content: "Why? {{last_block_output}}".to_string(),
+            description: String::new(),
+        };
+        let prompts = all(vec![own.clone()]);
+        assert_eq!(prompts.len(), BUILTIN.len());
+        assert_eq!(prompts.last(), Some(&own));
+        assert_eq!(prompts.iter().filter(|prompt| prompt.name == own.name).count(), 1);
+    }
+
+    #[test]
+    fn test_context_fills_variables() {
+        let explain = &builtin()[0];
+        let context = PromptContext {
+            selection: None,
+            last_block_output: Some(format!("{}error: linker failed", "x".repeat(MAX_OUTPUT_CHARS))),
+            cwd: Some(PathBuf::from("/src/app")),
+        };
+        let rendered = render(explain, &context).unwrap();
+        assert!(rendered.starts_with("This command failed in /src/app:\n\nxxx"));
+        assert!(rendered.contains("error: linker failed\n\nExplain"));
+        assert!(rendered.len() < MAX_OUTPUT_CHARS + 200);
+
+        let error = render(&builtin()[1], &context).unwrap_err();
+        assert!(matches!(error, DriveError::MissingVariable(ref name) if name == "selection"));
+    }
+}
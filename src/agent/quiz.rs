@@ -0,0 +1,219 @@
+//! Quizzes From History
+//!
+//! Samples the commands of the tools used most in the shell history — git,
+//! docker and the like — and asks the agent for multiple-choice questions
+//! about those tools as JSON. Replies are checked question by question, so a
+//! reply with one malformed question still yields the rest, and the
+//! questions are given ids derived from their text, so asking again does not
+//! fill the bank with duplicates.
+
+use crate::agent::client::AgentProvider;
+use crate::agent::model::ModelId;
+use crate::agent::translate::{output_hash, response_text};
+use crate::mcq::bank::QuestionSet;
+use crate::mcq::{MultipleChoiceQuestion, QuestionDifficulty};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use thiserror::Error;
+
+/// Commands too basic to ask about.
+const IGNORED_TOOLS: &[&str] = &["cd", "ls", "pwd", "clear", "exit", "echo", "history", "sudo"];
+
+/// Seconds to answer a generated question.
+const TIME_LIMIT: u32 = 30;
+
+#[derive(Error, Debug)]
+pub enum QuizGenerationError {
+    #[error("the agent's reply has no JSON object")]
+    NoJson,
+    #[error("the agent's reply is not a question list: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("none of the agent's questions were usable")]
+    NoValidQuestions,
+}
+
+/// The commands of the `tools` tools run most, up to `per_tool` each,
+/// most frequent tool first. `history` is newest first, as is each sample.
+pub fn sample_history(history: &[String], tools: usize, per_tool: usize) -> Vec<(String, Vec<String>)> {
+    let mut by_tool: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut order = Vec::new();
+    for command in history {
+        let command = command.trim();
+        let Some(tool) = command.split_whitespace().next() else {
+            continue;
+        };
+        if IGNORED_TOOLS.contains(&tool) || tool.contains('=') {
+            continue;
+        }
+        let commands = by_tool.entry(tool).or_insert_with(|| {
+            order.push(tool);
+            Vec::new()
+        });
+        commands.push(command);
+    }
+    // Most used first; the tool used most recently wins a tie.
+    order.sort_by_key(|tool| std::cmp::Reverse(by_tool[tool].len()));
+    order
+        .into_iter()
+        .take(tools)
+        .map(|tool| {
+            let mut commands: Vec<String> = Vec::new();
+            for command in &by_tool[tool] {
+                if commands.len() < per_tool && !commands.iter().any(|seen| seen == command) {
+                    commands.push(command.to_string());
+                }
+            }
+            (tool.to_string(), commands)
+        })
+        .collect()
+}
+
+pub fn generation_prompt(samples: &[(String, Vec<String>)], count: usize) -> String {
+    let mut history = String::new();
+    for (tool, commands) in samples {
+        history.push_str(&format!("{}:\n", tool));
+        for command in commands {
+            history.push_str(&format!("  {}\n", command));
+        }
+    }
+    format!(
+        "Write {} multiple-choice questions that test how well someone knows the tools in this shell history, \
+         about the flags, subcommands and behaviour they use or would find useful next. Reply with JSON only, \
+         in this shape:\n\
+         {{\"questions\": [{{\"tool\": \"git\", \"question\": \"...\", \"options\": [\"...\", \"...\", \"...\", \"...\"], \
+         \"correct_answer\": 0, \"difficulty\": \"Easy\", \"explanation\": \"...\"}}]}}\n\
+         correct_answer is the index of the right option; difficulty is Easy, Medium or Hard; the explanation \
+         says why the answer is right.\n\n{}",
+        count, history
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct GeneratedQuiz {
+    questions: Vec<GeneratedQuestion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeneratedQuestion {
+    tool: String,
+    question: String,
+    options: Vec<String>,
+    correct_answer: usize,
+    difficulty: QuestionDifficulty,
+    explanation: String,
+}
+
+impl GeneratedQuestion {
+    fn into_question(self) -> Option<MultipleChoiceQuestion> {
+        let usable = !self.question.trim().is_empty()
+            && !self.explanation.trim().is_empty()
+            && self.options.len() >= 2
+            && self.correct_answer < self.options.len()
+            && self.options.iter().all(|option| !option.trim().is_empty());
+        if !usable {
+            return None;
+        }
+        let tool = if self.tool.trim().is_empty() { "shell".to_string() } else { self.tool.trim().to_lowercase() };
+        let id = format!("history.{}.{}", tool, &output_hash(self.question.trim())[..8]);
+        Some(
+            MultipleChoiceQuestion::new(id, self.question.trim().to_string(), self.options, self.correct_answer, self.difficulty, tool)
+                .with_explanation(self.explanation.trim().to_string())
+                .with_time_limit(TIME_LIMIT),
+        )
+    }
+}
+
+/// The usable questions in the agent's reply, which may wrap the JSON in
+/// prose or a code fence.
+pub fn parse_questions(reply: &str) -> Result<QuestionSet, QuizGenerationError> {
+    let start = reply.find('{').ok_or(QuizGenerationError::NoJson)?;
+    let end = reply.rfind('}').filter(|end| *end > start).ok_or(QuizGenerationError::NoJson)?;
+    let generated: GeneratedQuiz = serde_json::from_str(&reply[start..=end])?;
+    let mut questions: Vec<MultipleChoiceQuestion> = Vec::new();
+    for question in generated.questions.into_iter().filter_map(GeneratedQuestion::into_question) {
+        if !questions.iter().any(|kept| kept.id == question.id) {
+            questions.push(question);
+        }
+    }
+    if questions.is_empty() {
+        return Err(QuizGenerationError::NoValidQuestions);
+    }
+    Ok(QuestionSet { name: "history".to_string(), questions })
+}
+
+/// Asks the agent for questions on a background thread.
+pub struct QuizGenerator {
+    agent: Arc<dyn AgentProvider>,
+    result_tx: Sender<Result<QuestionSet, QuizGenerationError>>,
+    results: Receiver<Result<QuestionSet, QuizGenerationError>>,
+}
+
+impl QuizGenerator {
+    pub fn new(agent: Arc<dyn AgentProvider>) -> Self {
+        let (result_tx, results) = channel();
+        Self { agent, result_tx, results }
+    }
+
+    pub fn generate(&self, samples: &[(String, Vec<String>)], count: usize, model: ModelId) {
+        let prompt = generation_prompt(samples, count);
+        let agent = self.agent.clone();
+        let result_tx = self.result_tx.clone();
+        thread::spawn(move || {
+            let response = agent.process_query(&prompt, &[], &[], model);
+            result_tx.send(parse_questions(&response_text(response))).ok();
+        });
+    }
+
+    /// Takes the finished generations.
+    pub fn poll(&self) -> Vec<Result<QuestionSet, QuizGenerationError>> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_sampled_by_tool() {
+        let history: Vec<String> = ["git push", "docker ps", "cd src", "git status", "git push", "FOO=1 make", "docker ps -a", "kubectl get pods"]
+            .iter()
+            .map(|command| command.to_string())
+            .collect();
+        assert_eq!(
+            sample_history(&history, 2, 2),
+            vec![
+                ("git".to_string(), vec!["git push".to_string(), "git status".to_string()]),
+                ("docker".to_string(), vec!["docker ps".to_string(), "docker ps -a".to_string()]),
+            ]
+        );
+        assert!(generation_prompt(&sample_history(&history, 1, 1), 5).contains("git:\n  git push\n"));
+    }
+
+    #[test]
+    fn test_replies_are_validated_per_question() {
+        let reply = r#"Here you go:
+```json
+{"questions": [
+  {"tool": "Git", "question": "What does `git push -u` set?", "options": ["The upstream branch", "The user name"],
+   "correct_answer": 0, "difficulty": "Medium", "explanation": "`-u` records the remote branch as upstream."},
+  {"tool": "docker", "question": "Which lists stopped containers?", "options": ["docker ps -a"],
+   "correct_answer": 0, "difficulty": "Easy", "explanation": "Only one option."},
+  {"tool": "docker", "question": "Out of range?", "options": ["a", "b"],
+   "correct_answer": 2, "difficulty": "Easy", "explanation": "No."}
+]}
+```"#;
+        let set = parse_questions(reply).unwrap();
+        assert_eq!(set.questions.len(), 1);
+        assert_eq!(set.questions[0].category, "git");
+        assert!(set.questions[0].id.starts_with("history.git."));
+        assert!(set.validate().is_ok());
+
+        assert!(matches!(parse_questions("I can't help with that."), Err(QuizGenerationError::NoJson)));
+        assert!(matches!(parse_questions(r#"{"questions": 3}"#), Err(QuizGenerationError::Json(_))));
+        assert!(matches!(parse_questions(r#"{"questions": []}"#), Err(QuizGenerationError::NoValidQuestions)));
+    }
+}
@@ -0,0 +1,509 @@
+//! Agent Tool Use
+//!
+//! This module drives an agentic loop in which the model proposes actions
+//! such as running a shell command. Every proposed command is checked against
+//! a `RuleSet` and, unless already trusted, held for user approval before it
+//! is executed. Command output is fed back into the transcript so the model
+//! can plan its next step until the task is complete.
+
+use crate::agent::client::{AgentProvider, AgentResponse};
+use crate::agent::model::ModelId;
+use crate::permissions::Capability;
+use crate::rules::{Action as RuleAction, Rule, RuleSet};
+use crate::syntax_parser;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const DEFAULT_MAX_STEPS: usize = 10;
+
+/// An action proposed by the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    RunCommand { command: String, explanation: String },
+    Finish { summary: String },
+}
+
+/// One entry in the loop transcript that is shown back to the model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Turn {
+    Task(String),
+    Proposed(Action),
+    CommandOutput { command: String, output: String },
+    Denied { command: String, reason: String },
+}
+
+/// The user's answer to an approval prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    Approve,
+    Deny,
+    AlwaysAllow,
+}
+
+impl ApprovalDecision {
+    pub const ALL: [ApprovalDecision; 3] = [
+        ApprovalDecision::Approve,
+        ApprovalDecision::Deny,
+        ApprovalDecision::AlwaysAllow,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ApprovalDecision::Approve => "Approve",
+            ApprovalDecision::Deny => "Deny",
+            ApprovalDecision::AlwaysAllow => "Always allow",
+        }
+    }
+}
+
+/// A command waiting for the user to approve or deny it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingCommand {
+    pub command: String,
+    pub explanation: String,
+    /// Set when a `Warn` rule matched; such commands are never auto-approved.
+    pub warning: Option<String>,
+}
+
+/// What the caller has to do next to keep the loop moving.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentStep {
+    /// Show the approval UI for this command.
+    AwaitingApproval(PendingCommand),
+    /// Run this command in the PTY and report its output with `record_output`.
+    Execute(String),
+    /// The task is complete.
+    Finished(String),
+}
+
+/// Produces the next action from the task transcript.
+pub trait ActionPlanner: Send {
+    fn next_action(&self, transcript: &[Turn]) -> Action;
+}
+
+/// Drives the propose → approve → execute → observe cycle.
+pub struct AgentLoop {
+    planner: Box<dyn ActionPlanner>,
+    rules: RuleSet,
+    always_allowed: HashSet<String>,
+    transcript: Vec<Turn>,
+    pending: Option<PendingCommand>,
+    running: Option<String>,
+    steps: usize,
+    max_steps: usize,
+}
+
+impl AgentLoop {
+    pub fn new(task: String, planner: Box<dyn ActionPlanner>, rules: RuleSet) -> Self {
+        Self {
+            planner,
+            rules,
+            always_allowed: HashSet::new(),
+            transcript: vec![Turn::Task(task)],
+            pending: None,
+            running: None,
+            steps: 0,
+            max_steps: DEFAULT_MAX_STEPS,
+        }
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn transcript(&self) -> &[Turn] {
+        &self.transcript
+    }
+
+    pub fn pending(&self) -> Option<&PendingCommand> {
+        self.pending.as_ref()
+    }
+
+    /// The command currently executing in the PTY, if any.
+    pub fn running_command(&self) -> Option<&str> {
+        self.running.as_deref()
+    }
+
+    /// Asks the planner for its next action and vets any proposed command.
+    ///
+    /// Commands denied by a rule are fed back to the model as a denial and the
+    /// planner is asked again, so callers never see a blocked step.
+    pub fn advance(&mut self) -> AgentStep {
+        loop {
+            if self.steps >= self.max_steps {
+                return AgentStep::Finished(format!("Stopped after {} steps without completing the task.", self.max_steps));
+            }
+            self.steps += 1;
+
+            let action = self.planner.next_action(&self.transcript);
+            self.transcript.push(Turn::Proposed(action.clone()));
+
+            match action {
+                Action::Finish { summary } => return AgentStep::Finished(summary),
+                Action::RunCommand { command, explanation } => match vet_command(&self.rules, &command) {
+                    RuleAction::Deny => {
+                        self.transcript.push(Turn::Denied {
+                            command,
+                            reason: "blocked by a command rule".to_string(),
+                        });
+                    }
+                    RuleAction::Warn => {
+                        let pending = PendingCommand {
+                            warning: Some("This command matches a warning rule.".to_string()),
+                            command,
+                            explanation,
+                        };
+                        self.pending = Some(pending.clone());
+                        return AgentStep::AwaitingApproval(pending);
+                    }
+                    RuleAction::Allow if self.always_allowed.contains(&command) => {
+                        return self.start_command(command);
+                    }
+                    RuleAction::Allow => {
+                        let pending = PendingCommand { command, explanation, warning: None };
+                        self.pending = Some(pending.clone());
+                        return AgentStep::AwaitingApproval(pending);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Applies the user's decision to the pending command.
+    pub fn resolve(&mut self, decision: ApprovalDecision) -> AgentStep {
+        let Some(pending) = self.pending.take() else {
+            return self.advance();
+        };
+        match decision {
+            ApprovalDecision::Approve => self.start_command(pending.command),
+            ApprovalDecision::AlwaysAllow => {
+                self.always_allowed.insert(pending.command.clone());
+                self.start_command(pending.command)
+            }
+            ApprovalDecision::Deny => {
+                self.transcript.push(Turn::Denied {
+                    command: pending.command,
+                    reason: "denied by the user".to_string(),
+                });
+                self.advance()
+            }
+        }
+    }
+
+    /// Feeds the output of the running command back to the model.
+    pub fn record_output(&mut self, output: &str) -> AgentStep {
+        if let Some(command) = self.running.take() {
+            self.transcript.push(Turn::CommandOutput {
+                command,
+                output: output.to_string(),
+            });
+        }
+        self.advance()
+    }
+
+    /// Drops the command the loop was about to run, telling the model why.
+    pub fn refuse(&mut self, reason: &str) -> AgentStep {
+        if let Some(command) = self.running.take() {
+            self.transcript.push(Turn::Denied { command, reason: reason.to_string() });
+        }
+        self.advance()
+    }
+
+    fn start_command(&mut self, command: String) -> AgentStep {
+        self.running = Some(command.clone());
+        AgentStep::Execute(command)
+    }
+}
+
+/// Rules applied to agent-proposed commands when none are configured.
+///
+/// Deletions, `sudo` and force pushes are recognised from the command's argv
+/// by `vet_argv`; only patterns that are not a program name stay here.
+pub fn default_command_rules() -> RuleSet {
+    let mut rules = RuleSet::new();
+    rules.add_rule(Rule {
+        name: "no_fork_bomb".to_string(),
+        description: "Shell fork bomb".to_string(),
+        action: RuleAction::Deny,
+        target: ":(){".to_string(),
+    });
+    rules
+}
+
+/// Checks a command line against `rules` and by its argv, returning the
+/// stricter of the two verdicts.
+pub fn vet_command(rules: &RuleSet, command: &str) -> RuleAction {
+    stricter(rules.evaluate(command), vet_argv(command))
+}
+
+/// Vets every simple command of a command line by its program and arguments,
+/// so `rm -rf /` is denied while `rm -rf /tmp/build` only warns and
+/// `terraform apply` passes. Commands inside `$(...)`, backticks, subshells
+/// and `bash -c` scripts are vetted too. Lines that do not parse, and
+/// programs only known at run time, are held with a warning.
+fn vet_argv(command: &str) -> RuleAction {
+    let Some(commands) = syntax_parser::simple_commands(command) else {
+        return RuleAction::Warn;
+    };
+    commands
+        .iter()
+        .map(|command| vet_simple_command(&command.argv.iter().map(String::as_str).collect::<Vec<_>>()))
+        .fold(RuleAction::Allow, stricter)
+}
+
+fn vet_simple_command(argv: &[&str]) -> RuleAction {
+    let Some((program, args)) = argv.split_first() else {
+        return RuleAction::Allow;
+    };
+    if program.contains(['$', '`']) {
+        return RuleAction::Warn;
+    }
+    match program.rsplit('/').next().unwrap_or(program) {
+        "sudo" => {
+            let elevated: Vec<&str> = args.iter().copied().skip_while(|arg| arg.starts_with('-')).collect();
+            stricter(RuleAction::Warn, vet_simple_command(&elevated))
+        }
+        "env" | "nohup" | "nice" | "exec" | "command" | "time" | "xargs" => {
+            let wrapped: Vec<&str> = args.iter().copied().skip_while(|arg| arg.starts_with('-') || arg.contains('=')).collect();
+            vet_simple_command(&wrapped)
+        }
+        "eval" => vet_argv(&args.join(" ")),
+        "bash" | "sh" | "zsh" | "dash" | "ksh" if args.contains(&"-c") => {
+            match args.iter().skip_while(|arg| **arg != "-c").nth(1) {
+                Some(script) => vet_argv(script),
+                None => RuleAction::Warn,
+            }
+        }
+        "rm" => {
+            let recursive = args.iter().any(|arg| {
+                *arg == "--recursive" || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains(['r', 'R']))
+            });
+            if recursive && args.iter().any(|arg| matches!(*arg, "/" | "/*")) {
+                RuleAction::Deny
+            } else {
+                RuleAction::Warn
+            }
+        }
+        "git" if args.contains(&"push") && args.iter().any(|arg| *arg == "-f" || arg.starts_with("--force")) => RuleAction::Warn,
+        _ => RuleAction::Allow,
+    }
+}
+
+/// The capabilities a command needs beyond running in the pane: writing files
+/// or reaching the network. Agent commands ask for them before they run.
+/// A line that does not parse is assumed to need both.
+pub fn required_capabilities(command: &str) -> Vec<Capability> {
+    let mut capabilities = Vec::new();
+    add_required_capabilities(command, &mut capabilities);
+    capabilities
+}
+
+fn add_required_capabilities(command: &str, capabilities: &mut Vec<Capability>) {
+    let Some(commands) = syntax_parser::simple_commands(command) else {
+        for capability in [Capability::FilesystemWrite, Capability::Network] {
+            if !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+        return;
+    };
+    for command in &commands {
+        let argv: Vec<&str> = command
+            .argv
+            .iter()
+            .map(String::as_str)
+            .skip_while(|word| matches!(*word, "sudo" | "env" | "nohup" | "nice" | "exec" | "command" | "time" | "xargs"))
+            .collect();
+        let Some((program, args)) = argv.split_first() else {
+            continue;
+        };
+        let program = program.rsplit('/').next().unwrap_or(program);
+        if matches!(program, "bash" | "sh" | "zsh" | "dash" | "ksh") {
+            if let Some(script) = args.iter().skip_while(|arg| **arg != "-c").nth(1) {
+                add_required_capabilities(script, capabilities);
+            }
+        }
+        let writes = command.redirects.iter().any(|redirect| is_output_redirect(redirect))
+            || match program {
+                "rm" | "rmdir" | "mv" | "cp" | "touch" | "mkdir" | "tee" | "ln" | "chmod" | "chown" | "dd" | "truncate" => true,
+                "sed" => args.iter().any(|arg| arg.starts_with("-i")),
+                _ => false,
+            };
+        let networked = match program {
+            "curl" | "wget" | "ssh" | "scp" | "rsync" | "nc" | "ftp" => true,
+            "git" => args.iter().any(|arg| matches!(*arg, "clone" | "fetch" | "pull" | "push")),
+            "npm" | "pip" | "pip3" | "cargo" | "apt" | "apt-get" | "brew" => args.contains(&"install"),
+            _ => false,
+        };
+        for (needed, capability) in [(writes, Capability::FilesystemWrite), (networked, Capability::Network)] {
+            if needed && !capabilities.contains(&capability) {
+                capabilities.push(capability);
+            }
+        }
+    }
+}
+
+/// Whether a redirect writes to a file, as `>out.log`, `>>out.log` or
+/// `2>errors`, rather than to another descriptor as `2>&1`.
+fn is_output_redirect(word: &str) -> bool {
+    let redirect = word.trim_start_matches(|c: char| c.is_ascii_digit());
+    redirect.starts_with('>') && !redirect.trim_start_matches('>').starts_with('&')
+}
+
+/// The more restrictive of two rule actions.
+fn stricter(a: RuleAction, b: RuleAction) -> RuleAction {
+    match (a, b) {
+        (RuleAction::Deny, _) | (_, RuleAction::Deny) => RuleAction::Deny,
+        (RuleAction::Warn, _) | (_, RuleAction::Warn) => RuleAction::Warn,
+        _ => RuleAction::Allow,
+    }
+}
+
+/// Adapts a simulated or scripted agent to the tool loop: it proposes the
+/// command the agent would suggest for the task, then finishes once that
+/// command has run.
+pub struct SimulatedPlanner {
+    agent: Arc<dyn AgentProvider>,
+    model: ModelId,
+}
+
+impl SimulatedPlanner {
+    pub fn new(agent: Arc<dyn AgentProvider>, model: ModelId) -> Self {
+        Self { agent, model }
+    }
+}
+
+impl ActionPlanner for SimulatedPlanner {
+    fn next_action(&self, transcript: &[Turn]) -> Action {
+        let task = transcript
+            .iter()
+            .find_map(|turn| match turn {
+                Turn::Task(task) => Some(task.as_str()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        // Asked in another language, the task is answered in it.
+        let context: Vec<String> = crate::agent::language_instruction(task).into_iter().collect();
+
+        match transcript.iter().rev().find(|turn| !matches!(turn, Turn::Proposed(_))) {
+            Some(Turn::CommandOutput { command, output }) => Action::Finish {
+                summary: format!("Ran `{}`:\n{}", command, output.trim_end()),
+            },
+            Some(Turn::Denied { command, reason }) => Action::Finish {
+                summary: format!("Did not run `{}` ({}).", command, reason),
+            },
+            _ => match self.agent.process_query(task, &[], &context, self.model.clone()) {
+                AgentResponse::SuggestCommand { explanation, command }
+                | AgentResponse::RequestToRunCommand { explanation, command_to_run: command } => {
+                    Action::RunCommand { command, explanation }
+                }
+                AgentResponse::Clarification(message) => Action::Finish { summary: message },
+                AgentResponse::ProposeCodeChange { explanation, .. } => Action::Finish { summary: explanation },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Replays a fixed list of actions.
+    struct ScriptedPlanner(Mutex<Vec<Action>>);
+
+    impl ScriptedPlanner {
+        fn new(mut actions: Vec<Action>) -> Box<Self> {
+            actions.reverse();
+            Box::new(Self(Mutex::new(actions)))
+        }
+    }
+
+    impl ActionPlanner for ScriptedPlanner {
+        fn next_action(&self, _transcript: &[Turn]) -> Action {
+            self.0.lock().unwrap().pop().unwrap_or(Action::Finish { summary: "done".to_string() })
+        }
+    }
+
+    fn run(command: &str) -> Action {
+        Action::RunCommand { command: command.to_string(), explanation: String::new() }
+    }
+
+    #[test]
+    fn test_command_requires_approval_then_output_is_fed_back() {
+        let planner = ScriptedPlanner::new(vec![run("ls"), Action::Finish { summary: "listed".to_string() }]);
+        let mut agent = AgentLoop::new("list files".to_string(), planner, RuleSet::new());
+
+        assert!(matches!(agent.advance(), AgentStep::AwaitingApproval(p) if p.command == "ls"));
+        assert_eq!(agent.resolve(ApprovalDecision::Approve), AgentStep::Execute("ls".to_string()));
+        assert_eq!(agent.record_output("a.txt\n"), AgentStep::Finished("listed".to_string()));
+        assert!(agent.transcript().contains(&Turn::CommandOutput {
+            command: "ls".to_string(),
+            output: "a.txt\n".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_denied_rule_is_reported_to_model() {
+        let planner = ScriptedPlanner::new(vec![run("rm -rf / --no-preserve-root")]);
+        let mut agent = AgentLoop::new("cleanup".to_string(), planner, default_command_rules());
+
+        assert_eq!(agent.advance(), AgentStep::Finished("done".to_string()));
+        assert!(agent.transcript().iter().any(|turn| matches!(turn, Turn::Denied { .. })));
+    }
+
+    #[test]
+    fn test_rules_match_argv_not_substrings() {
+        assert_eq!(vet_argv("rm -rf / --no-preserve-root"), RuleAction::Deny);
+        assert_eq!(vet_argv("make && sudo rm -fr /"), RuleAction::Deny);
+        assert_eq!(vet_argv("rm -rf /tmp/build"), RuleAction::Warn);
+        assert_eq!(vet_argv("git push --force origin main"), RuleAction::Warn);
+        assert_eq!(vet_argv("terraform apply"), RuleAction::Allow);
+        assert_eq!(vet_argv("git push origin main"), RuleAction::Allow);
+        // Operators without spaces, substitutions and scripts are split up too
+        assert_eq!(vet_argv("true;rm -rf /"), RuleAction::Deny);
+        assert_eq!(vet_argv("echo $(rm -rf /)"), RuleAction::Deny);
+        assert_eq!(vet_argv("echo `rm -rf /`"), RuleAction::Deny);
+        assert_eq!(vet_argv("bash -c 'cd /tmp && rm -rf /'"), RuleAction::Deny);
+        assert_eq!(vet_argv("(cd build && make)"), RuleAction::Allow);
+        // Programs only known at run time, and lines that do not parse, warn
+        assert_eq!(vet_argv("$(echo rm) -rf /tmp"), RuleAction::Warn);
+        assert_eq!(vet_argv("echo \"unterminated"), RuleAction::Warn);
+    }
+
+    #[test]
+    fn test_required_capabilities() {
+        assert!(required_capabilities("ls -la | grep src").is_empty());
+        assert_eq!(required_capabilities("echo hi > notes.txt"), vec![Capability::FilesystemWrite]);
+        assert_eq!(
+            required_capabilities("curl -sL https://example.com && sudo cp a b"),
+            vec![Capability::Network, Capability::FilesystemWrite]
+        );
+        assert_eq!(required_capabilities("make 2>&1 | less"), Vec::new());
+        assert_eq!(required_capabilities("ls;curl example.com"), vec![Capability::Network]);
+        assert_eq!(required_capabilities("sh -c 'date >> log.txt'"), vec![Capability::FilesystemWrite]);
+    }
+
+    #[test]
+    fn test_refused_command_is_reported_to_model() {
+        let planner = ScriptedPlanner::new(vec![run("curl example.com")]);
+        let mut agent = AgentLoop::new("fetch".to_string(), planner, RuleSet::new());
+
+        assert!(matches!(agent.advance(), AgentStep::AwaitingApproval(_)));
+        assert!(matches!(agent.resolve(ApprovalDecision::Approve), AgentStep::Execute(_)));
+        assert_eq!(agent.refuse("network access was denied"), AgentStep::Finished("done".to_string()));
+        assert!(agent.transcript().iter().any(|turn| matches!(turn, Turn::Denied { reason, .. } if reason == "network access was denied")));
+    }
+
+    #[test]
+    fn test_always_allow_skips_later_prompts_but_not_warnings() {
+        let planner = ScriptedPlanner::new(vec![run("make"), run("make"), run("sudo make install")]);
+        let mut agent = AgentLoop::new("build".to_string(), planner, default_command_rules());
+
+        assert!(matches!(agent.advance(), AgentStep::AwaitingApproval(_)));
+        assert_eq!(agent.resolve(ApprovalDecision::AlwaysAllow), AgentStep::Execute("make".to_string()));
+        assert_eq!(agent.record_output(""), AgentStep::Execute("make".to_string()));
+        assert!(matches!(agent.record_output(""), AgentStep::AwaitingApproval(p) if p.warning.is_some()));
+    }
+}
@@ -0,0 +1,175 @@
+//! Output Translation
+//!
+//! Asks the agent to restate a block's output — foreign-language text or a
+//! localized error message — as a short summary in the user's language.
+//! Summaries are cached per output hash and locale, so translating the same
+//! output again never calls the agent twice.
+
+use crate::agent::client::{AgentProvider, AgentResponse};
+use crate::agent::model::ModelId;
+use crate::db;
+use rusqlite::Connection;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use uuid::Uuid;
+
+/// Output beyond this many characters is cut before it is sent.
+const MAX_OUTPUT_CHARS: usize = 8000;
+
+/// A summary of a block's output in the user's language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub locale: String,
+    pub text: String,
+    pub folded: bool,
+}
+
+impl Translation {
+    /// The footer drawn under the block, collapsed to its heading when folded.
+    pub fn footer(&self) -> String {
+        let heading = format!("Translation ({})", self.locale);
+        if self.folded {
+            return format!("▸ {}", heading);
+        }
+        let body: Vec<String> = self.text.lines().map(|line| format!("  {}", line)).collect();
+        format!("▾ {}\n{}", heading, body.join("\n"))
+    }
+}
+
+/// A stable 64-bit FNV-1a hash of `output`, as hex, for the cache key.
+pub fn output_hash(output: &str) -> String {
+    let hash = output.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// The configured locale, or the one `LANG` names (`de_DE.UTF-8` becomes
+/// `de-DE`), or English.
+pub fn user_locale(configured: Option<&str>, lang: Option<&str>) -> String {
+    configured
+        .or_else(|| lang.and_then(|lang| lang.split(['.', '@']).next()))
+        .map(|locale| locale.replace('_', "-"))
+        .filter(|locale| !locale.is_empty() && locale != "C" && locale != "POSIX")
+        .unwrap_or_else(|| "en".to_string())
+}
+
+pub fn translation_prompt(command: &str, output: &str, locale: &str) -> String {
+    let output: String = output.chars().take(MAX_OUTPUT_CHARS).collect();
+    format!(
+        "Translate and summarize the output of `{}` for a reader whose language is {}. \
+         Keep error codes, file paths and identifiers as they are, and say what went wrong \
+         if it is an error.\n\n```\n{}\n```",
+        command, locale, output
+    )
+}
+
+/// The prose of a reply, whichever kind it is.
+pub fn response_text(response: AgentResponse) -> String {
+    match response {
+        AgentResponse::Clarification(text) => text,
+        AgentResponse::SuggestCommand { explanation, .. }
+        | AgentResponse::RequestToRunCommand { explanation, .. }
+        | AgentResponse::ProposeCodeChange { explanation, .. } => explanation,
+    }
+}
+
+/// A finished translation for the block `block_id`.
+pub struct TranslationResult {
+    pub block_id: Uuid,
+    pub hash: String,
+    pub translation: Translation,
+}
+
+/// Looks translations up in the cache and asks the agent for the rest on a
+/// background thread.
+pub struct Translator {
+    agent: Arc<dyn AgentProvider>,
+    result_tx: Sender<TranslationResult>,
+    results: Receiver<TranslationResult>,
+}
+
+impl Translator {
+    pub fn new(agent: Arc<dyn AgentProvider>) -> Self {
+        let (result_tx, results) = channel();
+        Self { agent, result_tx, results }
+    }
+
+    /// Returns the cached translation, or starts one and returns `None`.
+    pub fn translate(
+        &self,
+        conn: &Connection,
+        block_id: Uuid,
+        command: &str,
+        output: &str,
+        locale: &str,
+        model: ModelId,
+    ) -> Option<Translation> {
+        let hash = output_hash(output);
+        match db::load_translation(conn, &hash, locale) {
+            Ok(Some(text)) => return Some(Translation { locale: locale.to_string(), text, folded: false }),
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to read cached translation: {}", e),
+        }
+        let prompt = translation_prompt(command, output, locale);
+        let locale = locale.to_string();
+        let agent = self.agent.clone();
+        let result_tx = self.result_tx.clone();
+        thread::spawn(move || {
+            let response = agent.process_query(&prompt, &[], &[], model);
+            let translation = Translation { locale, text: response_text(response), folded: false };
+            result_tx.send(TranslationResult { block_id, hash, translation }).ok();
+        });
+        None
+    }
+
+    /// Takes finished translations and caches them.
+    pub fn poll(&self, conn: &Connection) -> Vec<TranslationResult> {
+        let results: Vec<TranslationResult> = self.results.try_iter().collect();
+        for result in &results {
+            if let Err(e) = db::save_translation(conn, &result.hash, &result.translation.locale, &result.translation.text) {
+                log::warn!("Failed to cache translation: {}", e);
+            }
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_and_hash() {
+        assert_eq!(user_locale(None, Some("de_DE.UTF-8")), "de-DE");
+        assert_eq!(user_locale(Some("ja"), Some("de_DE.UTF-8")), "ja");
+        assert_eq!(user_locale(None, Some("C")), "en");
+        assert_eq!(output_hash(""), "cbf29ce484222325");
+        let translation = Translation { locale: "en".to_string(), text: "File not found".to_string(), folded: true };
+        assert_eq!(translation.footer(), "▸ Translation (en)");
+        assert_ne!(output_hash("Fehler: Datei nicht gefunden"), output_hash("Fehler: Datei gefunden"));
+    }
+
+    #[test]
+    fn test_translations_are_cached_per_output_and_locale() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::create_translation_tables(&conn).unwrap();
+        let translator = Translator::new(Arc::new(crate::agent::client::SimulatedAgent::new()));
+        let block_id = Uuid::new_v4();
+        let output = "Fehler: Datei nicht gefunden";
+
+        assert!(translator.translate(&conn, block_id, "cat x", output, "en", ModelId::Auto).is_none());
+        let result = loop {
+            if let Some(result) = translator.poll(&conn).pop() {
+                break result;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(result.block_id, block_id);
+
+        let cached = translator.translate(&conn, Uuid::new_v4(), "cat x", output, "en", ModelId::Auto);
+        assert_eq!(cached.map(|translation| translation.text), Some(result.translation.text));
+        assert!(translator.translate(&conn, block_id, "cat x", output, "fr", ModelId::Auto).is_none());
+    }
+}
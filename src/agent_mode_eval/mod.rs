@@ -1,27 +1,79 @@
 //! Enhanced Agent Mode Evaluation
-//! 
+//!
 //! This module handles evaluation and assessment of AI agent performance,
 //! supporting detailed metrics collection, response quality analysis, and performance optimization.
+//!
+//! A response is scored by what became of it: whether the command it
+//! proposed ran cleanly, was denied, or whether its patch was accepted,
+//! blended with the user's satisfaction when they gave it. Metrics are kept
+//! per model, and an [`AbTest`] alternates queries between two models so
+//! their metrics can be compared side by side.
 
+pub mod usage;
+
+use crate::agent::model::ModelId;
+use crate::mcq::survey::SurveyStats;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::Instant;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Quality above which a response counts as a success.
+const SUCCESS_QUALITY: f32 = 0.7;
+
+/// How much of the quality comes from outcomes when satisfaction is known
+/// too; the rest is satisfaction.
+const OUTCOME_WEIGHT: f32 = 0.7;
+
+/// What became of a response: the task-level signal it is scored by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// A command the agent proposed ran and exited with status 0.
+    CommandSucceeded,
+    /// It ran and exited with another status.
+    CommandFailed,
+    /// The user denied running it.
+    CommandDenied,
+    PatchAccepted,
+    PatchRejected,
+}
+
+impl Outcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Outcome::CommandSucceeded | Outcome::PatchAccepted)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AgentEvaluation {
-    pub agent_id: String,
+    pub model: String,
     pub session_id: String,
-    pub response_quality: f32,
+    /// `None` until an outcome or satisfaction score comes in.
+    pub response_quality: Option<f32>,
     pub execution_time: u64, // in milliseconds
     pub user_satisfaction: Option<f32>,
-    pub error_count: u32,
+    pub outcomes: Vec<Outcome>,
     pub timestamp: u64,
     pub context: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl AgentEvaluation {
+    fn rescore(&mut self) {
+        let outcome_score = (!self.outcomes.is_empty())
+            .then(|| self.outcomes.iter().filter(|outcome| outcome.is_success()).count() as f32 / self.outcomes.len() as f32);
+        self.response_quality = match (outcome_score, self.user_satisfaction) {
+            (Some(score), Some(satisfaction)) => Some(OUTCOME_WEIGHT * score + (1.0 - OUTCOME_WEIGHT) * satisfaction),
+            (Some(score), None) => Some(score),
+            (None, satisfaction) => satisfaction,
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EvaluationMetrics {
+    /// The share of outcomes that were successes.
     pub accuracy: f32,
     pub average_response_time: u64,
+    /// The share of scored responses above the success quality.
     pub success_rate: f32,
     pub user_engagement: f32,
     pub sessions_evaluated: u32,
@@ -30,6 +82,7 @@ pub struct EvaluationMetrics {
 pub struct AgentEvaluator {
     evaluations: Vec<AgentEvaluation>,
     start_time: Instant,
+    survey: SurveyStats,
 }
 
 impl AgentEvaluator {
@@ -37,67 +90,199 @@ impl AgentEvaluator {
         Self {
             evaluations: Vec::new(),
             start_time: Instant::now(),
+            survey: SurveyStats::default(),
         }
     }
 
+    /// Uses the stored survey responses for the engagement metrics.
+    pub fn with_survey_stats(mut self, survey: SurveyStats) -> Self {
+        self.survey = survey;
+        self
+    }
+
+    /// Continues from evaluations stored by an earlier run.
+    pub fn with_evaluations(mut self, evaluations: Vec<AgentEvaluation>) -> Self {
+        self.evaluations = evaluations;
+        self
+    }
+
     pub fn start_evaluation(&mut self) {
         self.start_time = Instant::now();
     }
 
-    pub fn evaluate_response(&mut self, response: &str, context: &str, satisfaction: Option<f32>) -> AgentEvaluation {
+    /// Starts tracking a response from `model`, timed from the last
+    /// `start_evaluation`. Its outcomes arrive later by `session_id`.
+    pub fn evaluate_response(&mut self, model: &ModelId, context: &str, satisfaction: Option<f32>) -> AgentEvaluation {
         let execution_time = self.start_time.elapsed().as_millis() as u64;
-        
-        let evaluation = AgentEvaluation {
-            agent_id: "agent-001".to_string(),
+
+        let mut evaluation = AgentEvaluation {
+            model: String::from(model.to_string()),
             session_id: uuid::Uuid::new_v4().to_string(),
-            response_quality: self.calculate_quality(response),
+            response_quality: None,
             execution_time,
             user_satisfaction: satisfaction,
-            error_count: self.count_errors(response),
+            outcomes: Vec::new(),
             timestamp: chrono::Utc::now().timestamp() as u64,
             context: context.to_string(),
         };
+        evaluation.rescore();
 
         self.evaluations.push(evaluation.clone());
         evaluation
     }
 
-    fn calculate_quality(&self, response: &str) -> f32 {
-        // Basic example logic: longer responses considered higher quality
-        (response.len() as f32 / 1000.0).min(1.0)
+    /// Adds an outcome to a tracked response and returns it rescored.
+    pub fn record_outcome(&mut self, session_id: &str, outcome: Outcome) -> Option<&AgentEvaluation> {
+        let evaluation = self.evaluations.iter_mut().find(|evaluation| evaluation.session_id == session_id)?;
+        evaluation.outcomes.push(outcome);
+        evaluation.rescore();
+        Some(evaluation)
     }
 
-    fn count_errors(&self, response: &str) -> u32 {
-        // Example logic to count occurrences of the word "error"
-        response.matches("error").count() as u32
+    pub fn get_metrics(&self) -> EvaluationMetrics {
+        self.metrics_of(self.evaluations.iter().collect())
     }
 
-    pub fn get_metrics(&self) -> EvaluationMetrics {
-        let total_sessions = self.evaluations.len() as u32;
-        let total_time: u64 = self.evaluations.iter().map(|e| e.execution_time).sum();
+    /// Metrics for the responses from each model, by model name.
+    pub fn metrics_by_model(&self) -> BTreeMap<String, EvaluationMetrics> {
+        let mut by_model: BTreeMap<String, Vec<&AgentEvaluation>> = BTreeMap::new();
+        for evaluation in &self.evaluations {
+            by_model.entry(evaluation.model.clone()).or_default().push(evaluation);
+        }
+        by_model.into_iter().map(|(model, evaluations)| (model, self.metrics_of(evaluations))).collect()
+    }
+
+    pub fn metrics_for(&self, model: &ModelId) -> EvaluationMetrics {
+        self.metrics_of(self.evaluations.iter().filter(|evaluation| evaluation.model == model.to_string()).collect())
+    }
+
+    fn metrics_of(&self, evaluations: Vec<&AgentEvaluation>) -> EvaluationMetrics {
+        let total_sessions = evaluations.len() as u32;
+        let total_time: u64 = evaluations.iter().map(|e| e.execution_time).sum();
 
         EvaluationMetrics {
-            accuracy: self.calculate_accuracy(),
-            average_response_time: if total_sessions > 0 { total_time / total_sessions } else { 0 },
-            success_rate: self.calculate_success_rate(),
+            accuracy: calculate_accuracy(&evaluations),
+            average_response_time: if total_sessions > 0 { total_time / u64::from(total_sessions) } else { 0 },
+            success_rate: calculate_success_rate(&evaluations),
             user_engagement: self.calculate_engagement(),
             sessions_evaluated: total_sessions,
         }
     }
 
-    fn calculate_accuracy(&self) -> f32 {
-        // Fake accuracy calculation for demonstration
-        0.95
+    /// A table of the metrics of each model.
+    pub fn render(&self) -> String {
+        let by_model = self.metrics_by_model();
+        let width = by_model.keys().map(|model| model.chars().count()).max().unwrap_or(0).max(5);
+        let mut text = format!("{:<width$}  {:>9}  {:>8}  {:>7}  {:>8}\n", "model", "responses", "accuracy", "success", "avg time", width = width);
+        for (model, metrics) in &by_model {
+            text.push_str(&format!(
+                "{:<width$}  {:>9}  {:>7.0}%  {:>6.0}%  {:>5} ms\n",
+                model,
+                metrics.sessions_evaluated,
+                metrics.accuracy * 100.0,
+                metrics.success_rate * 100.0,
+                metrics.average_response_time,
+                width = width
+            ));
+        }
+        text.push_str(&format!("survey engagement: {:.0}%", self.calculate_engagement() * 100.0));
+        text
     }
 
-    fn calculate_success_rate(&self) -> f32 {
-        let successful_sessions = self.evaluations.iter().filter(|e| e.response_quality > 0.7).count() as f32;
-        successful_sessions / self.evaluations.len() as f32
+    /// The share of survey prompts the user answered rather than dismissed.
+    fn calculate_engagement(&self) -> f32 {
+        self.survey.response_rate()
     }
+}
 
-    fn calculate_engagement(&self) -> f32 {
-        // Example engagement calculation
-        0.85
+/// The share of outcomes that were successes.
+fn calculate_accuracy(evaluations: &[&AgentEvaluation]) -> f32 {
+    let outcomes: Vec<&Outcome> = evaluations.iter().flat_map(|e| &e.outcomes).collect();
+    if outcomes.is_empty() {
+        return 0.0;
+    }
+    outcomes.iter().filter(|outcome| outcome.is_success()).count() as f32 / outcomes.len() as f32
+}
+
+fn calculate_success_rate(evaluations: &[&AgentEvaluation]) -> f32 {
+    let scored: Vec<f32> = evaluations.iter().filter_map(|e| e.response_quality).collect();
+    if scored.is_empty() {
+        return 0.0;
+    }
+    scored.iter().filter(|quality| **quality > SUCCESS_QUALITY).count() as f32 / scored.len() as f32
+}
+
+/// Sends queries to two models in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbTest {
+    pub models: [ModelId; 2],
+    routed: usize,
+}
+
+impl AbTest {
+    pub fn new(model_a: ModelId, model_b: ModelId) -> Self {
+        Self { models: [model_a, model_b], routed: 0 }
+    }
+
+    /// The model for the next query.
+    pub fn route(&mut self) -> ModelId {
+        let model = self.models[self.routed % 2].clone();
+        self.routed += 1;
+        model
+    }
+
+    pub fn report(&self, evaluator: &AgentEvaluator) -> AbReport {
+        AbReport {
+            models: self.models.clone().map(|model| (String::from(model.to_string()), evaluator.metrics_for(&model))),
+        }
+    }
+}
+
+/// Two models' metrics side by side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbReport {
+    pub models: [(String, EvaluationMetrics); 2],
+}
+
+impl AbReport {
+    /// The model with the higher accuracy, then success rate, once both
+    /// have responses; `None` on a tie.
+    pub fn leader(&self) -> Option<&str> {
+        let [(a, a_metrics), (b, b_metrics)] = &self.models;
+        if a_metrics.sessions_evaluated == 0 || b_metrics.sessions_evaluated == 0 {
+            return None;
+        }
+        let key = |metrics: &EvaluationMetrics| (metrics.accuracy, metrics.success_rate);
+        match key(a_metrics).partial_cmp(&key(b_metrics))? {
+            std::cmp::Ordering::Greater => Some(a),
+            std::cmp::Ordering::Less => Some(b),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let [(a, a_metrics), (b, b_metrics)] = &self.models;
+        let width = a.chars().count().max(b.chars().count()).max(12);
+        let percent = |value: f32| format!("{:.0}%", value * 100.0);
+        let rows = [
+            ("responses", a_metrics.sessions_evaluated.to_string(), b_metrics.sessions_evaluated.to_string()),
+            ("accuracy", percent(a_metrics.accuracy), percent(b_metrics.accuracy)),
+            ("success rate", percent(a_metrics.success_rate), percent(b_metrics.success_rate)),
+            (
+                "avg time",
+                format!("{} ms", a_metrics.average_response_time),
+                format!("{} ms", b_metrics.average_response_time),
+            ),
+        ];
+        let mut text = format!("{:<14}{:<width$}  {}\n", "", a, b, width = width);
+        for (label, a_value, b_value) in rows {
+            text.push_str(&format!("{:<14}{:<width$}  {}\n", label, a_value, b_value, width = width));
+        }
+        text.push_str(&match self.leader() {
+            Some(leader) => format!("ahead: {}", leader),
+            None => "ahead: neither yet".to_string(),
+        });
+        text
     }
 }
 
@@ -112,24 +297,65 @@ mod tests {
         let mut evaluator = AgentEvaluator::new();
         evaluator.start_evaluation();
         sleep(Duration::from_millis(100));
-        let eval = evaluator.evaluate_response("This response is fine.", "test context", Some(0.9));
+        let eval = evaluator.evaluate_response(&ModelId::Auto, "test context", Some(0.9));
         assert_eq!(eval.execution_time > 0, true);
+        assert_eq!(eval.response_quality, Some(0.9));
+
+        let scored = evaluator.record_outcome(&eval.session_id, Outcome::CommandSucceeded).unwrap();
+        assert!((scored.response_quality.unwrap() - 0.97).abs() < 1e-6);
+        evaluator.record_outcome(&eval.session_id, Outcome::CommandFailed);
 
         let metrics = evaluator.get_metrics();
-        assert!(metrics.accuracy >= 0.0 && metrics.accuracy <= 1.0);
+        assert_eq!(metrics.accuracy, 0.5);
+        assert_eq!(metrics.success_rate, 0.0);
     }
 
     #[test]
     fn test_evaluator_metrics() {
         let mut evaluator = AgentEvaluator::new();
-        for _ in 0..10 {
+        for i in 0..10 {
             evaluator.start_evaluation();
             sleep(Duration::from_millis(50));
-            evaluator.evaluate_response("Another response with error", "sample context", Some(0.8));
+            let eval = evaluator.evaluate_response(&ModelId::Gpt4o, "sample context", None);
+            let outcome = if i < 8 { Outcome::PatchAccepted } else { Outcome::PatchRejected };
+            evaluator.record_outcome(&eval.session_id, outcome);
         }
+        evaluator.evaluate_response(&ModelId::O3, "unscored", None);
 
         let metrics = evaluator.get_metrics();
-        assert_eq!(metrics.sessions_evaluated, 10);
+        assert_eq!(metrics.sessions_evaluated, 11);
         assert!(metrics.average_response_time > 0);
+        assert_eq!(metrics.success_rate, 0.8);
+        let by_model = evaluator.metrics_by_model();
+        assert_eq!(by_model[ModelId::Gpt4o.to_string()].accuracy, 0.8);
+        assert_eq!(by_model[ModelId::O3.to_string()].sessions_evaluated, 1);
+    }
+
+    #[test]
+    fn test_engagement_from_survey() {
+        let survey = SurveyStats { shown: 4, answered: 3, satisfaction: Some(0.8) };
+        let evaluator = AgentEvaluator::new().with_survey_stats(survey);
+
+        let metrics = evaluator.get_metrics();
+        assert_eq!(metrics.user_engagement, 0.75);
+        assert_eq!(metrics.accuracy, 0.0);
+    }
+
+    #[test]
+    fn test_ab_test_alternates_and_compares() {
+        let mut test = AbTest::new(ModelId::Gpt4o, ModelId::ClaudeSonnet4);
+        let mut evaluator = AgentEvaluator::new();
+        for i in 0..4 {
+            let model = test.route();
+            assert_eq!(model, test.models[i % 2]);
+            let eval = evaluator.evaluate_response(&model, "task", None);
+            let outcome = if model == ModelId::ClaudeSonnet4 { Outcome::CommandSucceeded } else { Outcome::CommandDenied };
+            evaluator.record_outcome(&eval.session_id, outcome);
+        }
+
+        let report = test.report(&evaluator);
+        assert_eq!(report.leader(), Some(ModelId::ClaudeSonnet4.to_string()));
+        assert_eq!(report.models[0].1.sessions_evaluated, 2);
+        assert!(report.render().lines().nth(2).unwrap().starts_with("accuracy      0%"));
     }
 }
@@ -0,0 +1,311 @@
+//! Token Usage and Cost
+//!
+//! Every agent request goes through a [`MeteredAgent`], which counts the
+//! prompt and completion tokens and prices them at the model's list price.
+//! Providers here do not report usage, so tokens are estimated from the text
+//! the same way attachments are budgeted. Records are tagged with the
+//! conversation they belong to — an agent conversation's id, or the feature
+//! that asked, such as translation — and the app stores them and sums them
+//! into daily, weekly and per-conversation totals.
+
+use crate::agent::attachments::estimate_tokens;
+use crate::agent::client::{AgentProvider, AgentResponse};
+use crate::agent::model::ModelId;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Share of the monthly budget at which the first warning is shown.
+pub const BUDGET_WARNING_SHARE: f64 = 0.8;
+
+/// List prices in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input: f64,
+    pub output: f64,
+}
+
+pub fn pricing(model: &ModelId) -> Pricing {
+    let (input, output) = match model {
+        ModelId::Gpt4o => (2.5, 10.0),
+        ModelId::Gpt4_1 | ModelId::O3 => (2.0, 8.0),
+        ModelId::O4Mini | ModelId::O3Mini => (1.1, 4.4),
+        ModelId::Auto | ModelId::ClaudeSonnet4 | ModelId::ClaudeSonnet3_7 | ModelId::ClaudeSonnet3_5 => (3.0, 15.0),
+        ModelId::ClaudeOpus4 => (15.0, 75.0),
+        ModelId::ClaudeHaiku3_5 => (0.8, 4.0),
+        ModelId::Gemini2_0Flash => (0.1, 0.4),
+        ModelId::Gemini2_5Pro => (1.25, 10.0),
+    };
+    Pricing { input, output }
+}
+
+pub fn provider(model: &ModelId) -> &'static str {
+    match model {
+        ModelId::Gpt4o | ModelId::Gpt4_1 | ModelId::O4Mini | ModelId::O3 | ModelId::O3Mini => "OpenAI",
+        ModelId::Gemini2_0Flash | ModelId::Gemini2_5Pro => "Google",
+        _ => "Anthropic",
+    }
+}
+
+/// One agent request's tokens and estimated cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub conversation: String,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// In US dollars.
+    pub cost: f64,
+    pub created_at: i64,
+}
+
+impl UsageRecord {
+    pub fn new(conversation: &str, model: &ModelId, prompt_tokens: u32, completion_tokens: u32, created_at: i64) -> Self {
+        let pricing = pricing(model);
+        Self {
+            conversation: conversation.to_string(),
+            provider: provider(model).to_string(),
+            model: model.to_string().to_string(),
+            prompt_tokens,
+            completion_tokens,
+            cost: (f64::from(prompt_tokens) * pricing.input + f64::from(completion_tokens) * pricing.output) / 1_000_000.0,
+            created_at,
+        }
+    }
+}
+
+/// Collects the usage of every metered agent until the app takes it.
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter {
+    pending: Arc<Mutex<Vec<UsageRecord>>>,
+}
+
+impl UsageMeter {
+    /// `agent`, with its requests recorded under `conversation`.
+    pub fn wrap(&self, agent: Arc<dyn AgentProvider>, conversation: &str) -> Arc<dyn AgentProvider> {
+        Arc::new(MeteredAgent { inner: agent, conversation: conversation.to_string(), meter: self.clone() })
+    }
+
+    /// Takes the records made since the last call.
+    pub fn take(&self) -> Vec<UsageRecord> {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+}
+
+pub struct MeteredAgent {
+    inner: Arc<dyn AgentProvider>,
+    conversation: String,
+    meter: UsageMeter,
+}
+
+impl AgentProvider for MeteredAgent {
+    fn process_query(
+        &self,
+        query: &str,
+        history: &[(String, AgentResponse)],
+        block_context: &[String],
+        model: ModelId,
+    ) -> AgentResponse {
+        let mut prompt_tokens = estimate_tokens(query);
+        prompt_tokens += block_context.iter().map(|context| estimate_tokens(context)).sum::<usize>();
+        prompt_tokens += history.iter().map(|(query, response)| estimate_tokens(query) + response_tokens(response)).sum::<usize>();
+        let response = self.inner.process_query(query, history, block_context, model.clone());
+        let record = UsageRecord::new(
+            &self.conversation,
+            &model,
+            prompt_tokens as u32,
+            response_tokens(&response) as u32,
+            chrono::Utc::now().timestamp(),
+        );
+        self.meter.pending.lock().unwrap().push(record);
+        response
+    }
+}
+
+fn response_tokens(response: &AgentResponse) -> usize {
+    match response {
+        AgentResponse::SuggestCommand { explanation, command } => estimate_tokens(explanation) + estimate_tokens(command),
+        AgentResponse::RequestToRunCommand { explanation, command_to_run } => {
+            estimate_tokens(explanation) + estimate_tokens(command_to_run)
+        }
+        AgentResponse::Clarification(text) => estimate_tokens(text),
+        AgentResponse::ProposeCodeChange { diffs, explanation } => {
+            estimate_tokens(explanation) + diffs.iter().map(|diff| estimate_tokens(&diff.new_content)).sum::<usize>()
+        }
+    }
+}
+
+/// Tokens and cost added up.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Totals {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl Totals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.prompt_tokens += u64::from(record.prompt_tokens);
+        self.completion_tokens += u64::from(record.completion_tokens);
+        self.cost += record.cost;
+    }
+}
+
+/// Usage by day, by week and by conversation, and the month so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageReport {
+    /// The last seven days, oldest first.
+    pub days: Vec<(NaiveDate, Totals)>,
+    /// The last four weeks by the Monday they start on, oldest first.
+    pub weeks: Vec<(NaiveDate, Totals)>,
+    /// Conversations by title, most expensive first.
+    pub conversations: Vec<(String, Totals)>,
+    pub month: Totals,
+    pub monthly_budget: Option<f64>,
+}
+
+/// The first day of the month `now` is in, where month-to-date usage starts.
+pub fn month_start(now: DateTime<Local>) -> i64 {
+    let first = now.date_naive().with_day(1).unwrap_or(now.date_naive());
+    local_midnight(first)
+}
+
+/// How far back records have to go for a report at `now`.
+pub fn report_start(now: DateTime<Local>) -> i64 {
+    let four_weeks = monday_of(now.date_naive()) - Duration::weeks(3);
+    local_midnight(four_weeks).min(month_start(now))
+}
+
+fn monday_of(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(i64::from(date.weekday().num_days_from_monday()))
+}
+
+fn local_midnight(date: NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local.from_local_datetime(&midnight).earliest().map_or(midnight.and_utc().timestamp(), |time| time.timestamp())
+}
+
+impl UsageReport {
+    /// Sums `records`, each with its conversation's title, as of `now`.
+    pub fn new(records: &[(String, UsageRecord)], now: DateTime<Local>, monthly_budget: Option<f64>) -> Self {
+        let today = now.date_naive();
+        let this_week = monday_of(today);
+        let mut days: BTreeMap<NaiveDate, Totals> = (0..7).map(|back| (today - Duration::days(back), Totals::default())).collect();
+        let mut weeks: BTreeMap<NaiveDate, Totals> = (0..4).map(|back| (this_week - Duration::weeks(back), Totals::default())).collect();
+        let mut conversations: BTreeMap<&str, Totals> = BTreeMap::new();
+        let mut month = Totals::default();
+        let month_start = month_start(now);
+        for (title, record) in records {
+            let Some(date) = Local.timestamp_opt(record.created_at, 0).single().map(|time| time.date_naive()) else {
+                continue;
+            };
+            if let Some(day) = days.get_mut(&date) {
+                day.add(record);
+            }
+            if let Some(week) = weeks.get_mut(&monday_of(date)) {
+                week.add(record);
+            }
+            if record.created_at >= month_start {
+                month.add(record);
+            }
+            conversations.entry(title).or_default().add(record);
+        }
+        let mut conversations: Vec<(String, Totals)> = conversations.into_iter().map(|(title, totals)| (title.to_string(), totals)).collect();
+        conversations.sort_by(|a, b| b.1.cost.total_cmp(&a.1.cost));
+        Self {
+            days: days.into_iter().collect(),
+            weeks: weeks.into_iter().collect(),
+            conversations,
+            month,
+            monthly_budget,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let line = |label: &str, totals: &Totals| {
+            format!(
+                "  {:<28} {:>4} req  {:>8} in  {:>8} out  ${:.4}\n",
+                label, totals.requests, totals.prompt_tokens, totals.completion_tokens, totals.cost
+            )
+        };
+        let mut text = String::from("Daily\n");
+        for (day, totals) in self.days.iter().rev() {
+            text.push_str(&line(&day.format("%a %Y-%m-%d").to_string(), totals));
+        }
+        text.push_str("Weekly\n");
+        for (monday, totals) in self.weeks.iter().rev() {
+            text.push_str(&line(&format!("week of {}", monday.format("%Y-%m-%d")), totals));
+        }
+        text.push_str("By conversation\n");
+        for (title, totals) in &self.conversations {
+            let title: String = title.chars().take(28).collect();
+            text.push_str(&line(&title, totals));
+        }
+        text.push_str(&match self.monthly_budget {
+            Some(budget) => format!("This month: ${:.2} of ${:.2} budget", self.month.cost, budget),
+            None => format!("This month: ${:.2}", self.month.cost),
+        });
+        text
+    }
+}
+
+/// How much of the budget has been used, as the level of warning due:
+/// 0 below the warning share, 1 past it and 2 once the budget is spent.
+pub fn budget_level(month_cost: f64, budget: f64) -> u8 {
+    if budget <= 0.0 || month_cost < budget * BUDGET_WARNING_SHARE {
+        0
+    } else if month_cost < budget {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::client::SimulatedAgent;
+
+    #[test]
+    fn test_metered_agent_records_tokens_and_cost() {
+        let meter = UsageMeter::default();
+        let agent = meter.wrap(Arc::new(SimulatedAgent::new()), "conversation-1");
+        agent.process_query("list all docker containers", &[], &["x".repeat(400)], ModelId::Gpt4o);
+
+        let records = meter.take();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!((record.conversation.as_str(), record.provider.as_str()), ("conversation-1", "OpenAI"));
+        assert_eq!(record.prompt_tokens, 107);
+        assert!(record.completion_tokens > 0);
+        let expected = (107.0 * 2.5 + f64::from(record.completion_tokens) * 10.0) / 1_000_000.0;
+        assert!((record.cost - expected).abs() < 1e-12);
+        assert!(meter.take().is_empty());
+    }
+
+    #[test]
+    fn test_report_totals_by_day_week_and_conversation() {
+        let now = Local.with_ymd_and_hms(2025, 3, 12, 15, 0, 0).unwrap();
+        let at = |date: (i32, u32, u32)| Local.with_ymd_and_hms(date.0, date.1, date.2, 10, 0, 0).unwrap().timestamp();
+        let records = vec![
+            ("Fix the build".to_string(), UsageRecord::new("a", &ModelId::ClaudeOpus4, 1000, 1000, at((2025, 3, 12)))),
+            ("Fix the build".to_string(), UsageRecord::new("a", &ModelId::ClaudeOpus4, 1000, 1000, at((2025, 3, 10)))),
+            ("translation".to_string(), UsageRecord::new("translation", &ModelId::Gpt4o, 1000, 0, at((2025, 3, 3)))),
+            ("translation".to_string(), UsageRecord::new("translation", &ModelId::Gpt4o, 1000, 0, at((2025, 2, 28)))),
+        ];
+        let report = UsageReport::new(&records, now, Some(0.2));
+
+        assert_eq!(report.days.last().unwrap().1.requests, 1);
+        assert_eq!(report.days.iter().map(|(_, totals)| totals.requests).sum::<u32>(), 2);
+        let weeks: Vec<u32> = report.weeks.iter().map(|(_, totals)| totals.requests).collect();
+        assert_eq!(weeks, vec![0, 1, 1, 2]);
+        assert_eq!(report.conversations[0].0, "Fix the build");
+        assert!((report.month.cost - 0.1825).abs() < 1e-9);
+        assert!(report.render().ends_with("This month: $0.18 of $0.20 budget"));
+        assert_eq!(budget_level(report.month.cost, 0.2), 1);
+        assert_eq!((budget_level(0.1, 0.2), budget_level(0.2, 0.2)), (0, 2));
+    }
+}
@@ -0,0 +1,159 @@
+//! How much of a block's output is shown.
+//!
+//! A block can show its whole output, be collapsed to its first few lines,
+//! or show only its last lines. Huge outputs start out showing their tail,
+//! so scrolling past them stays fast; successful commands that printed a
+//! lot start out collapsed when `blocks.auto_collapse_lines` is set, and
+//! every block does with `blocks.fold_by_default`. A pane keeps the views
+//! picked from the block menu or the fold keys in `Pane::output_views`.
+
+use crate::config::BlocksConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputView {
+    Full,
+    /// The first `fold_lines` lines.
+    Collapsed,
+    /// The last `tail_lines` lines.
+    Tail,
+}
+
+/// The part of an output shown in a view, and how many lines were left
+/// out before and after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibleOutput<'a> {
+    pub text: &'a str,
+    pub hidden_before: usize,
+    pub hidden_after: usize,
+}
+
+impl VisibleOutput<'_> {
+    /// Rows taken: the shown lines plus a row for each marker.
+    pub fn rows(&self) -> usize {
+        self.text.lines().count()
+            + usize::from(self.hidden_before > 0)
+            + usize::from(self.hidden_after > 0)
+    }
+
+    /// The marker above the shown lines, e.g. `… 4900 earlier lines`.
+    pub fn before_marker(&self) -> Option<String> {
+        (self.hidden_before > 0).then(|| format!("… {} earlier lines\n", self.hidden_before))
+    }
+
+    /// The marker below the shown lines, e.g. `… 240 more lines`.
+    pub fn after_marker(&self) -> Option<String> {
+        (self.hidden_after > 0).then(|| format!("… {} more lines", self.hidden_after))
+    }
+}
+
+/// Whether an output is long enough to offer showing only its tail.
+pub fn is_huge(line_count: usize, config: &BlocksConfig) -> bool {
+    line_count > config.huge_output_lines
+}
+
+/// The view a block starts in. `succeeded` is `None` until the shell
+/// reports how the command ended.
+pub fn initial_view(
+    line_count: usize,
+    succeeded: Option<bool>,
+    config: &BlocksConfig,
+) -> OutputView {
+    if is_huge(line_count, config) {
+        return OutputView::Tail;
+    }
+    let verbose = config.auto_collapse_lines > 0 && line_count > config.auto_collapse_lines;
+    if line_count > config.fold_lines
+        && (config.fold_by_default || (verbose && succeeded == Some(true)))
+    {
+        OutputView::Collapsed
+    } else {
+        OutputView::Full
+    }
+}
+
+pub fn visible_output<'a>(
+    output: &'a str,
+    view: OutputView,
+    config: &BlocksConfig,
+) -> VisibleOutput<'a> {
+    let total = output.lines().count();
+    let (start, end) = match view {
+        OutputView::Full => (0, total),
+        OutputView::Collapsed => (0, total.min(config.fold_lines)),
+        OutputView::Tail => (total.saturating_sub(config.tail_lines), total),
+    };
+    let line_start = |line: usize| {
+        output
+            .split_inclusive('\n')
+            .take(line)
+            .map(str::len)
+            .sum::<usize>()
+    };
+    VisibleOutput {
+        text: &output[line_start(start)..line_start(end)],
+        hidden_before: start,
+        hidden_after: total - end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BlocksConfig {
+        BlocksConfig {
+            fold_lines: 3,
+            auto_collapse_lines: 5,
+            tail_lines: 2,
+            huge_output_lines: 10,
+            ..BlocksConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_initial_views() {
+        let config = config();
+        assert_eq!(initial_view(4, Some(true), &config), OutputView::Full);
+        // Verbose commands collapse only once they are known to have succeeded
+        assert_eq!(initial_view(6, Some(true), &config), OutputView::Collapsed);
+        assert_eq!(initial_view(6, Some(false), &config), OutputView::Full);
+        assert_eq!(initial_view(6, None, &config), OutputView::Full);
+        assert_eq!(initial_view(11, Some(false), &config), OutputView::Tail);
+
+        let folded = BlocksConfig {
+            fold_by_default: true,
+            ..config.clone()
+        };
+        assert_eq!(initial_view(4, None, &folded), OutputView::Collapsed);
+        assert_eq!(initial_view(3, None, &folded), OutputView::Full);
+        let never = BlocksConfig {
+            auto_collapse_lines: 0,
+            ..config
+        };
+        assert_eq!(initial_view(6, Some(true), &never), OutputView::Full);
+    }
+
+    #[test]
+    fn test_visible_output() {
+        let config = config();
+        let output = "a\nb\nc\nd\ne";
+        let full = visible_output(output, OutputView::Full, &config);
+        assert_eq!((full.text, full.rows()), (output, 5));
+        assert_eq!(full.after_marker(), None);
+
+        let collapsed = visible_output(output, OutputView::Collapsed, &config);
+        assert_eq!(collapsed.text, "a\nb\nc\n");
+        assert_eq!((collapsed.hidden_before, collapsed.hidden_after), (0, 2));
+        assert_eq!(collapsed.rows(), 4);
+        assert_eq!(collapsed.after_marker().as_deref(), Some("… 2 more lines"));
+
+        let tail = visible_output(output, OutputView::Tail, &config);
+        assert_eq!(tail.text, "d\ne");
+        assert_eq!((tail.hidden_before, tail.hidden_after), (3, 0));
+        assert_eq!(tail.before_marker().as_deref(), Some("… 3 earlier lines\n"));
+        assert_eq!(
+            visible_output("x", OutputView::Tail, &config).hidden_before,
+            0
+        );
+    }
+}
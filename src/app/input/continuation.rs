@@ -0,0 +1,322 @@
+//! Command Completeness
+//!
+//! Enter runs the command line only when a POSIX shell would run it as
+//! typed. An unclosed quote, a trailing pipe or backslash, an open heredoc
+//! or an unfinished `if`, loop or `case` continues onto a new line instead,
+//! as the shell's own `PS2` prompt would. The scan is lexical: it tracks
+//! quotes, groups and keywords in command position without building a tree,
+//! so it stays cheap enough to run on every Enter.
+
+/// What an incomplete command line is waiting for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Continuation {
+    /// A `'`, `"` or `` ` `` that has not been closed.
+    Quote(char),
+    /// A heredoc whose delimiter line has not been typed yet.
+    Heredoc(String),
+    /// A line ending in `\`.
+    Backslash,
+    /// A line ending in `|`, `&&` or `||`.
+    Operator(&'static str),
+    /// An open `(`, `$(` or `{`, waiting for its closer.
+    Group(char),
+    /// An `if`, loop or `case`, waiting for `fi`, `done` or `esac`.
+    Keyword(&'static str),
+}
+
+impl Continuation {
+    /// A short hint shown while the command continues.
+    pub fn describe(&self) -> String {
+        match self {
+            Continuation::Quote(quote) => format!("closing {}", quote),
+            Continuation::Heredoc(delimiter) => format!("heredoc ending {}", delimiter),
+            Continuation::Backslash => "next line".to_string(),
+            Continuation::Operator(operator) => format!("command after {}", operator),
+            Continuation::Group(closer) => format!("closing {}", closer),
+            Continuation::Keyword(keyword) => keyword.to_string(),
+        }
+    }
+}
+
+/// Where a word is in the command it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    /// Starts a command, so reserved words count.
+    Command,
+    /// An argument, or the name after `for` and `case`.
+    Argument,
+}
+
+struct Scanner {
+    /// Closers of the open groups and compound commands, innermost last.
+    open: Vec<Closer>,
+    /// Heredocs introduced on the current line, with whether `<<-` strips
+    /// leading tabs from their delimiter line.
+    pending_heredocs: Vec<(String, bool)>,
+    word: String,
+    /// Whether the current word had quotes, which stops it being a keyword.
+    word_quoted: bool,
+    position: Position,
+    trailing: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Closer {
+    Char(char),
+    Keyword(&'static str),
+}
+
+/// Returns what the command line is waiting for, or `None` when Enter
+/// should run it.
+pub fn continuation(text: &str) -> Option<Continuation> {
+    let mut scanner = Scanner {
+        open: Vec::new(),
+        pending_heredocs: Vec::new(),
+        word: String::new(),
+        word_quoted: false,
+        position: Position::Command,
+        trailing: None,
+    };
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                if i + 1 == chars.len() {
+                    return Some(Continuation::Backslash);
+                }
+                // An escaped newline joins the lines.
+                if chars[i + 1] != '\n' {
+                    scanner.word.push(chars[i + 1]);
+                    scanner.word_quoted = true;
+                }
+                i += 2;
+                continue;
+            }
+            '\'' | '"' | '`' => {
+                let Some(end) = closing_quote(&chars, i) else {
+                    return Some(Continuation::Quote(c));
+                };
+                scanner.word.extend(&chars[i + 1..end]);
+                scanner.word_quoted = true;
+                scanner.trailing = None;
+                i = end + 1;
+                continue;
+            }
+            '#' if scanner.word.is_empty() && !scanner.word_quoted => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '\n' => {
+                scanner.end_word();
+                scanner.position = Position::Command;
+                let heredocs = std::mem::take(&mut scanner.pending_heredocs);
+                for (delimiter, strip_tabs) in heredocs {
+                    match heredoc_end(&chars, i + 1, &delimiter, strip_tabs) {
+                        Some(end) => i = end,
+                        None => return Some(Continuation::Heredoc(delimiter)),
+                    }
+                }
+            }
+            c if c.is_whitespace() => scanner.end_word(),
+            ';' | '&' | '|' => {
+                scanner.end_word();
+                let doubled = chars.get(i + 1) == Some(&c);
+                scanner.trailing = match (c, doubled) {
+                    ('|', true) => Some("||"),
+                    ('|', false) => Some("|"),
+                    ('&', true) => Some("&&"),
+                    _ => None,
+                };
+                scanner.position = Position::Command;
+                if doubled {
+                    i += 1;
+                }
+            }
+            '(' => {
+                // `$(` is part of a word; a bare `(` starts a subshell.
+                if scanner.word.ends_with('$') {
+                    scanner.word.push(c);
+                } else {
+                    scanner.end_word();
+                }
+                scanner.open.push(Closer::Char(')'));
+                scanner.position = Position::Command;
+                scanner.trailing = None;
+            }
+            ')' => {
+                // In `case`, `)` ends a pattern rather than a group.
+                if scanner.open.last() == Some(&Closer::Char(')')) {
+                    scanner.open.pop();
+                }
+                scanner.end_word();
+                scanner.position = Position::Command;
+                scanner.trailing = None;
+            }
+            '<' if chars.get(i + 1) == Some(&'<') && chars.get(i + 2) != Some(&'<') => {
+                scanner.end_word();
+                let strip_tabs = chars.get(i + 2) == Some(&'-');
+                let (delimiter, end) = heredoc_delimiter(&chars, i + if strip_tabs { 3 } else { 2 });
+                if !delimiter.is_empty() {
+                    scanner.pending_heredocs.push((delimiter, strip_tabs));
+                }
+                scanner.trailing = None;
+                i = end;
+                continue;
+            }
+            c => {
+                scanner.word.push(c);
+                scanner.trailing = None;
+            }
+        }
+        i += 1;
+    }
+    scanner.end_word();
+
+    if let Some((delimiter, _)) = scanner.pending_heredocs.first() {
+        return Some(Continuation::Heredoc(delimiter.clone()));
+    }
+    if let Some(operator) = scanner.trailing {
+        return Some(Continuation::Operator(operator));
+    }
+    scanner.open.last().map(|closer| match closer {
+        Closer::Char(c) => Continuation::Group(*c),
+        Closer::Keyword(keyword) => Continuation::Keyword(keyword),
+    })
+}
+
+impl Scanner {
+    fn end_word(&mut self) {
+        if self.word.is_empty() && !self.word_quoted {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        let quoted = std::mem::replace(&mut self.word_quoted, false);
+        if quoted || self.position != Position::Command {
+            self.position = Position::Argument;
+            return;
+        }
+        self.position = match word.as_str() {
+            "if" => self.open_with(Closer::Keyword("fi"), Position::Command),
+            "while" | "until" => self.open_with(Closer::Keyword("done"), Position::Command),
+            "for" | "select" => self.open_with(Closer::Keyword("done"), Position::Argument),
+            "case" => self.open_with(Closer::Keyword("esac"), Position::Argument),
+            "{" => self.open_with(Closer::Char('}'), Position::Command),
+            "fi" | "done" | "esac" | "}" => {
+                self.close(&word);
+                Position::Argument
+            }
+            "then" | "else" | "elif" | "do" | "!" => Position::Command,
+            _ => Position::Argument,
+        };
+    }
+
+    fn open_with(&mut self, closer: Closer, next: Position) -> Position {
+        self.open.push(closer);
+        next
+    }
+
+    fn close(&mut self, word: &str) {
+        let matches = match self.open.last() {
+            Some(Closer::Keyword(keyword)) => *keyword == word,
+            Some(Closer::Char(c)) => word.len() == 1 && word.starts_with(*c),
+            None => false,
+        };
+        if matches {
+            self.open.pop();
+        }
+    }
+}
+
+/// The index of the quote closing the one at `start`. Backslashes escape
+/// inside double quotes and backticks, but not inside single quotes.
+fn closing_quote(chars: &[char], start: usize) -> Option<usize> {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote != '\'' => i += 1,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Reads the delimiter after `<<`, without its quotes, and where it ends.
+fn heredoc_delimiter(chars: &[char], mut i: usize) -> (String, usize) {
+    while i < chars.len() && (chars[i] == ' ' || chars[i] == '\t') {
+        i += 1;
+    }
+    let mut delimiter = String::new();
+    while i < chars.len() && !chars[i].is_whitespace() && !";&|<>()".contains(chars[i]) {
+        if chars[i] != '\'' && chars[i] != '"' && chars[i] != '\\' {
+            delimiter.push(chars[i]);
+        }
+        i += 1;
+    }
+    (delimiter, i)
+}
+
+/// Finds the line that ends a heredoc body starting at `start`, returning
+/// the index of the newline after it (or the end of the text).
+fn heredoc_end(chars: &[char], start: usize, delimiter: &str, strip_tabs: bool) -> Option<usize> {
+    let mut line_start = start;
+    while line_start <= chars.len() {
+        let line_end = chars[line_start..].iter().position(|c| *c == '\n').map_or(chars.len(), |offset| line_start + offset);
+        let line: String = chars[line_start..line_end].iter().collect();
+        let line = if strip_tabs { line.trim_start_matches('\t') } else { line.as_str() };
+        if line == delimiter {
+            return Some(line_end);
+        }
+        if line_end == chars.len() {
+            return None;
+        }
+        line_start = line_end + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_commands_submit() {
+        for command in [
+            "ls -la",
+            "echo 'it''s' \"a \\\"b\\\"\" | wc -c",
+            "if true; then echo yes; fi",
+            "for f in *.rs; do echo $f; done",
+            "case $x in a) echo a;; esac",
+            "echo $(date) # done",
+            "echo done if",
+            "cat <<EOF\nhello\nEOF",
+            "cat <<-'END' | sort\n\tb\n\ta\n\tEND",
+            "a \\\n  && b",
+            "",
+        ] {
+            assert_eq!(continuation(command), None, "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn test_incomplete_commands_continue() {
+        assert_eq!(continuation("echo 'unclosed"), Some(Continuation::Quote('\'')));
+        assert_eq!(continuation("echo \"a \\\" b"), Some(Continuation::Quote('"')));
+        assert_eq!(continuation("ls |"), Some(Continuation::Operator("|")));
+        assert_eq!(continuation("make &&\n"), Some(Continuation::Operator("&&")));
+        assert_eq!(continuation("ls \\"), Some(Continuation::Backslash));
+        assert_eq!(continuation("cat <<EOF\nhello"), Some(Continuation::Heredoc("EOF".to_string())));
+        assert_eq!(continuation("cat <<EOF"), Some(Continuation::Heredoc("EOF".to_string())));
+        assert_eq!(continuation("if true; then"), Some(Continuation::Keyword("fi")));
+        assert_eq!(continuation("while read line; do\n  echo $line"), Some(Continuation::Keyword("done")));
+        assert_eq!(continuation("case $x in\n  a)"), Some(Continuation::Keyword("esac")));
+        assert_eq!(continuation("(cd src"), Some(Continuation::Group(')')));
+        assert_eq!(continuation("f() {"), Some(Continuation::Group('}')));
+    }
+}
@@ -0,0 +1,288 @@
+//! Input Resolution
+//!
+//! This module turns key presses into editor commands, separately from
+//! applying them to the `cosmic_text` editor. Keeping resolution pure lets the
+//! modern and Vim keybindings be checked against a table of modifier and IME
+//! combinations without a window or font system. The bindings are the same on
+//! every platform: clipboard shortcuts use Super (Cmd), and Ctrl chords edit
+//! the line as in a shell.
+
+pub mod continuation;
+
+use crate::vim::{VimAction, VimMode, VimMotion, VimState};
+use crossterm::event::{KeyCode as TermKeyCode, KeyModifiers};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+/// Whether an input method is composing text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImeState {
+    #[default]
+    Inactive,
+    /// Pre-edit text is showing; keys belong to the IME until it commits.
+    Composing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InputModifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub super_key: bool,
+}
+
+impl InputModifiers {
+    pub const NONE: Self = Self { ctrl: false, alt: false, shift: false, super_key: false };
+    pub const CTRL: Self = Self { ctrl: true, ..Self::NONE };
+    pub const ALT: Self = Self { alt: true, ..Self::NONE };
+    pub const SHIFT: Self = Self { shift: true, ..Self::NONE };
+    pub const SUPER: Self = Self { super_key: true, ..Self::NONE };
+    pub const CTRL_SHIFT: Self = Self { ctrl: true, shift: true, ..Self::NONE };
+    pub const CTRL_ALT: Self = Self { ctrl: true, alt: true, ..Self::NONE };
+    pub const ALT_SHIFT: Self = Self { alt: true, shift: true, ..Self::NONE };
+}
+
+impl From<ModifiersState> for InputModifiers {
+    fn from(state: ModifiersState) -> Self {
+        Self {
+            ctrl: state.control_key(),
+            alt: state.alt_key(),
+            shift: state.shift_key(),
+            super_key: state.super_key(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+}
+
+/// A single change to the input editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorCommand {
+    Move(Motion),
+    Select(Motion),
+    SelectAll,
+    DeleteBack,
+    DeleteForward,
+    DeleteWordBack,
+    DeleteWordForward,
+    DeleteToLineEnd,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Newline,
+    Tab,
+    /// Inserts the text the key produced.
+    InsertText,
+    Submit,
+    Ignore,
+}
+
+/// Resolves a key press in the default (non-Vim) editing mode. Arms are
+/// tried in order, so Home, End and Ctrl+A/E/K win over any other modifiers
+/// held with them.
+pub fn resolve_modern_key(key: PhysicalKey, mods: InputModifiers, ime: ImeState) -> EditorCommand {
+    if ime == ImeState::Composing {
+        return EditorCommand::Ignore;
+    }
+    let PhysicalKey::Code(code) = key else {
+        return EditorCommand::InsertText;
+    };
+    let InputModifiers { ctrl, alt, shift, super_key } = mods;
+
+    // --- High-priority actions (copy, paste, cut) ---
+    if super_key {
+        match code {
+            KeyCode::KeyC => return EditorCommand::Copy,
+            KeyCode::KeyX => return EditorCommand::Cut,
+            KeyCode::KeyV => return EditorCommand::Paste,
+            _ => {}
+        }
+    }
+
+    match (code, ctrl, alt, shift) {
+        // --- Cursor Movement ---
+        (KeyCode::ArrowLeft, false, false, false) => EditorCommand::Move(Motion::Left),
+        (KeyCode::ArrowRight, false, false, false) => EditorCommand::Move(Motion::Right),
+        (KeyCode::ArrowUp, false, false, false) => EditorCommand::Move(Motion::Up),
+        (KeyCode::ArrowDown, false, false, false) => EditorCommand::Move(Motion::Down),
+        (KeyCode::ArrowLeft, false, true, false) => EditorCommand::Move(Motion::WordLeft),
+        (KeyCode::ArrowRight, false, true, false) => EditorCommand::Move(Motion::WordRight),
+        (KeyCode::KeyA, true, _, _) | (KeyCode::Home, _, _, _) => EditorCommand::Move(Motion::LineStart),
+        (KeyCode::KeyE, true, _, _) | (KeyCode::End, _, _, _) => EditorCommand::Move(Motion::LineEnd),
+
+        // --- Deletion ---
+        (KeyCode::Backspace, false, false, false) => EditorCommand::DeleteBack,
+        (KeyCode::Delete, false, false, false) => EditorCommand::DeleteForward,
+        (KeyCode::Backspace, false, true, false) => EditorCommand::DeleteWordBack,
+        (KeyCode::KeyD, false, true, false) => EditorCommand::DeleteWordForward,
+        (KeyCode::KeyK, true, _, _) => EditorCommand::DeleteToLineEnd,
+
+        // --- Selection ---
+        (KeyCode::KeyA, _, _, _) if super_key => EditorCommand::SelectAll,
+        (KeyCode::ArrowLeft, false, false, true) => EditorCommand::Select(Motion::Left),
+        (KeyCode::ArrowRight, false, false, true) => EditorCommand::Select(Motion::Right),
+        (KeyCode::ArrowLeft, false, true, true) => EditorCommand::Select(Motion::WordLeft),
+        (KeyCode::ArrowRight, false, true, true) => EditorCommand::Select(Motion::WordRight),
+
+        // --- Enter / Newline ---
+        (KeyCode::Enter, _, _, true) => EditorCommand::Newline,
+        (KeyCode::Enter, false, false, false) => EditorCommand::Submit,
+
+        // --- Tab for completions ---
+        (KeyCode::Tab, false, false, false) => EditorCommand::Tab,
+
+        // --- Text Input ---
+        _ => EditorCommand::InsertText,
+    }
+}
+
+/// Resolves a key press in Vim mode, updating `state` and returning the
+/// editor commands to apply in order.
+pub fn resolve_vim_key(state: &mut VimState, key: PhysicalKey, ime: ImeState) -> Vec<EditorCommand> {
+    if ime == ImeState::Composing {
+        return Vec::new();
+    }
+    let mut commands = match state.handle_physical_key(key) {
+        VimAction::EnterInsertMode => {
+            state.mode = VimMode::Insert;
+            Vec::new()
+        }
+        VimAction::EnterNormalMode => {
+            state.mode = VimMode::Normal;
+            Vec::new()
+        }
+        VimAction::EnterVisualMode => {
+            state.mode = VimMode::Visual;
+            vec![EditorCommand::SelectAll] // Simplified for now
+        }
+        VimAction::Move(motion) => vec![EditorCommand::Move(vim_motion(motion))],
+        VimAction::Delete(_motion) => vec![EditorCommand::DeleteWordForward], // Simplified example
+        VimAction::Paste => vec![EditorCommand::Paste],
+        VimAction::Undo => vec![EditorCommand::Undo],
+        VimAction::EnterInsertModeAtEnd | VimAction::Yank(_) | VimAction::Redo | VimAction::NoOp => Vec::new(),
+    };
+
+    // If we're in Insert mode, handle text input like normal
+    if state.mode == VimMode::Insert {
+        commands.push(EditorCommand::InsertText);
+        if key == PhysicalKey::Code(KeyCode::Backspace) {
+            commands.push(EditorCommand::DeleteBack);
+        }
+    }
+    // In Vim mode, Enter only executes from Normal mode (simplified)
+    if state.mode == VimMode::Normal && key == PhysicalKey::Code(KeyCode::Enter) {
+        commands.push(EditorCommand::Submit);
+    }
+    commands
+}
+
+/// How much of the autosuggestion, the ghost text after the end of the
+/// input, a motion from there accepts: all of it for Right and End, and
+/// through the next word or path segment for Alt+Right. `None` when the
+/// motion only moves the cursor.
+pub fn accept_autosuggestion(suggestion: &str, motion: Motion) -> Option<&str> {
+    match motion {
+        Motion::Right | Motion::LineEnd => Some(suggestion),
+        Motion::WordRight => {
+            let start = suggestion.len() - suggestion.trim_start().len();
+            let end = suggestion[start..]
+                .find(|c: char| c.is_whitespace() || c == '/')
+                .map_or(suggestion.len(), |i| {
+                    let end = start + i;
+                    if suggestion[end..].starts_with('/') {
+                        end + 1
+                    } else {
+                        end
+                    }
+                });
+            Some(&suggestion[..end])
+        }
+        _ => None,
+    }
+}
+
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+];
+
+const DIGIT_KEYS: [KeyCode; 10] = [
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+];
+
+/// Names a window key press the way the terminal front end does, so the
+/// window can share the app's crossterm key handling. Letters are lower
+/// case, with Shift left in the modifiers; keys it has no name for are
+/// `Null`.
+pub fn crossterm_key(key: PhysicalKey, mods: InputModifiers) -> (TermKeyCode, KeyModifiers) {
+    let term_code = if let PhysicalKey::Code(code) = key {
+        key_code_name(code)
+    } else {
+        TermKeyCode::Null
+    };
+    let mut modifiers = KeyModifiers::NONE;
+    for (held, modifier) in [
+        (mods.ctrl, KeyModifiers::CONTROL),
+        (mods.alt, KeyModifiers::ALT),
+        (mods.shift, KeyModifiers::SHIFT),
+        (mods.super_key, KeyModifiers::SUPER),
+    ] {
+        if held {
+            modifiers |= modifier;
+        }
+    }
+    (term_code, modifiers)
+}
+
+fn key_code_name(code: KeyCode) -> TermKeyCode {
+    if let Some(i) = LETTER_KEYS.iter().position(|letter| *letter == code) {
+        TermKeyCode::Char((b'a' + i as u8) as char)
+    } else if let Some(i) = DIGIT_KEYS.iter().position(|digit| *digit == code) {
+        TermKeyCode::Char((b'0' + i as u8) as char)
+    } else {
+        match code {
+            KeyCode::Enter | KeyCode::NumpadEnter => TermKeyCode::Enter,
+            KeyCode::Escape => TermKeyCode::Esc,
+            KeyCode::Backspace => TermKeyCode::Backspace,
+            KeyCode::Tab => TermKeyCode::Tab,
+            KeyCode::Space => TermKeyCode::Char(' '),
+            KeyCode::ArrowLeft => TermKeyCode::Left,
+            KeyCode::ArrowRight => TermKeyCode::Right,
+            KeyCode::ArrowUp => TermKeyCode::Up,
+            KeyCode::ArrowDown => TermKeyCode::Down,
+            KeyCode::Home => TermKeyCode::Home,
+            KeyCode::End => TermKeyCode::End,
+            KeyCode::Delete => TermKeyCode::Delete,
+            _ => TermKeyCode::Null,
+        }
+    }
+}
+
+fn vim_motion(motion: VimMotion) -> Motion {
+    match motion {
+        VimMotion::Left => Motion::Left,
+        VimMotion::Right => Motion::Right,
+        VimMotion::Up => Motion::Up,
+        VimMotion::Down => Motion::Down,
+        VimMotion::WordForward => Motion::WordRight,
+        VimMotion::WordBackward => Motion::WordLeft,
+        VimMotion::LineStart => Motion::LineStart,
+        VimMotion::LineEnd => Motion::LineEnd,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,363 @@
+//! Table-driven tests for input resolution
+//!
+//! Each case starts from an editor state written as text with `|` marking
+//! the cursor (and `^` the selection anchor), presses a sequence of keys
+//! under a given IME state and editing mode, and compares the resulting
+//! state. The bindings do not depend on the platform. Commands are applied
+//! to a small editor model that mirrors the `cosmic_text` operations used by
+//! the app.
+
+use super::*;
+
+#[derive(Debug, Clone, Copy)]
+struct Press {
+    key: KeyCode,
+    mods: InputModifiers,
+    text: Option<&'static str>,
+}
+
+fn key(key: KeyCode) -> Press {
+    Press { key, mods: InputModifiers::NONE, text: None }
+}
+
+fn chord(mods: InputModifiers, key: KeyCode) -> Press {
+    Press { key, mods, text: None }
+}
+
+fn typed(text: &'static str, key: KeyCode) -> Press {
+    Press { key, mods: InputModifiers::NONE, text: Some(text) }
+}
+
+/// Just enough of an editor to observe cursor, selection and text changes.
+#[derive(Debug, Default)]
+struct EditorModel {
+    text: Vec<char>,
+    cursor: usize,
+    anchor: Option<usize>,
+    clipboard: String,
+    undo: Vec<Vec<char>>,
+    submitted: Option<String>,
+}
+
+impl EditorModel {
+    fn parse(state: &str) -> Self {
+        let mut model = Self::default();
+        for c in state.chars() {
+            match c {
+                '|' => model.cursor = model.text.len(),
+                '^' => model.anchor = Some(model.text.len()),
+                c => model.text.push(c),
+            }
+        }
+        model
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for i in 0..=self.text.len() {
+            if self.anchor == Some(i) {
+                out.push('^');
+            }
+            if self.cursor == i {
+                out.push('|');
+            }
+            if let Some(c) = self.text.get(i) {
+                out.push(*c);
+            }
+        }
+        out
+    }
+
+    fn selection(&self) -> Option<(usize, usize)> {
+        self.anchor
+            .filter(|anchor| *anchor != self.cursor)
+            .map(|anchor| (anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..].iter().position(|c| *c == '\n').map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    fn target(&self, motion: Motion) -> usize {
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+        match motion {
+            Motion::Left => self.cursor.saturating_sub(1),
+            Motion::Right => (self.cursor + 1).min(self.text.len()),
+            Motion::LineStart => self.line_start(),
+            Motion::LineEnd => self.line_end(),
+            Motion::WordLeft => {
+                let mut i = self.cursor;
+                while i > 0 && !is_word(&self.text[i - 1]) {
+                    i -= 1;
+                }
+                while i > 0 && is_word(&self.text[i - 1]) {
+                    i -= 1;
+                }
+                i
+            }
+            Motion::WordRight => {
+                let mut i = self.cursor;
+                while i < self.text.len() && !is_word(&self.text[i]) {
+                    i += 1;
+                }
+                while i < self.text.len() && is_word(&self.text[i]) {
+                    i += 1;
+                }
+                i
+            }
+            Motion::Up | Motion::Down => {
+                let column = self.cursor - self.line_start();
+                let (start, end) = if motion == Motion::Up {
+                    let start = self.line_start();
+                    if start == 0 {
+                        return self.cursor;
+                    }
+                    let end = start - 1;
+                    let start = self.text[..end].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+                    (start, end)
+                } else {
+                    let end = self.line_end();
+                    if end == self.text.len() {
+                        return self.cursor;
+                    }
+                    let start = end + 1;
+                    let end = self.text[start..].iter().position(|c| *c == '\n').map_or(self.text.len(), |i| start + i);
+                    (start, end)
+                };
+                (start + column).min(end)
+            }
+        }
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        self.text.drain(start..end);
+        self.cursor = start;
+        self.anchor = None;
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((start, end)) => {
+                self.delete_range(start, end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        for c in text.chars() {
+            self.text.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+        self.anchor = None;
+    }
+
+    fn apply(&mut self, command: EditorCommand, text: Option<&str>) {
+        if matches!(
+            command,
+            EditorCommand::DeleteBack
+                | EditorCommand::DeleteForward
+                | EditorCommand::DeleteWordBack
+                | EditorCommand::DeleteWordForward
+                | EditorCommand::DeleteToLineEnd
+                | EditorCommand::Cut
+                | EditorCommand::Paste
+                | EditorCommand::InsertText
+                | EditorCommand::Newline
+                | EditorCommand::Tab
+        ) {
+            self.undo.push(self.text.clone());
+        }
+        match command {
+            EditorCommand::Move(motion) => {
+                self.cursor = self.target(motion);
+                self.anchor = None;
+            }
+            EditorCommand::Select(motion) => {
+                self.anchor.get_or_insert(self.cursor);
+                self.cursor = self.target(motion);
+            }
+            EditorCommand::SelectAll => {
+                self.anchor = Some(0);
+                self.cursor = self.text.len();
+            }
+            EditorCommand::DeleteBack => {
+                if !self.delete_selection() && self.cursor > 0 {
+                    self.delete_range(self.cursor - 1, self.cursor);
+                }
+            }
+            EditorCommand::DeleteForward => {
+                if !self.delete_selection() && self.cursor < self.text.len() {
+                    self.delete_range(self.cursor, self.cursor + 1);
+                }
+            }
+            EditorCommand::DeleteWordBack => self.delete_range(self.target(Motion::WordLeft), self.cursor),
+            EditorCommand::DeleteWordForward => self.delete_range(self.cursor, self.target(Motion::WordRight)),
+            EditorCommand::DeleteToLineEnd => self.delete_range(self.cursor, self.line_end()),
+            EditorCommand::Copy => {
+                if let Some((start, end)) = self.selection() {
+                    self.clipboard = self.text[start..end].iter().collect();
+                }
+            }
+            EditorCommand::Cut => {
+                self.apply(EditorCommand::Copy, None);
+                self.delete_selection();
+            }
+            EditorCommand::Paste => {
+                let clipboard = self.clipboard.clone();
+                self.insert(&clipboard);
+            }
+            EditorCommand::Undo => {
+                if let Some(text) = self.undo.pop() {
+                    self.cursor = self.cursor.min(text.len());
+                    self.text = text;
+                    self.anchor = None;
+                }
+            }
+            EditorCommand::Newline => self.insert("\n"),
+            EditorCommand::Tab => self.insert("\t"),
+            EditorCommand::InsertText => self.insert(text.unwrap_or_default()),
+            EditorCommand::Submit => {
+                self.submitted = Some(self.text.iter().collect());
+                self.text.clear();
+                self.cursor = 0;
+                self.anchor = None;
+            }
+            EditorCommand::Ignore => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    Modern,
+    /// Vim starting in the given mode.
+    Vim(VimMode),
+}
+
+struct Case {
+    name: &'static str,
+    ime: ImeState,
+    mode: Mode,
+    before: &'static str,
+    keys: Vec<Press>,
+    after: &'static str,
+    submitted: Option<&'static str>,
+}
+
+const IDLE: ImeState = ImeState::Inactive;
+const MODERN: Mode = Mode::Modern;
+
+fn run(case: &Case) {
+    let mut model = EditorModel::parse(case.before);
+    model.clipboard = "clip".to_string();
+    let mut vim = match case.mode {
+        Mode::Modern => None,
+        Mode::Vim(mode) => {
+            let mut state = VimState::default();
+            state.mode = mode;
+            Some(state)
+        }
+    };
+    for press in &case.keys {
+        let physical = PhysicalKey::Code(press.key);
+        let commands = match &mut vim {
+            None => vec![resolve_modern_key(physical, press.mods, case.ime)],
+            Some(state) => resolve_vim_key(state, physical, case.ime),
+        };
+        for command in commands {
+            model.apply(command, press.text);
+        }
+    }
+    assert_eq!(model.render(), case.after, "case '{}'", case.name);
+    assert_eq!(model.submitted.as_deref(), case.submitted, "case '{}' submission", case.name);
+}
+
+#[test]
+fn test_modern_input_matrix() {
+    let cases = [
+        Case { name: "type text", ime: IDLE, mode: MODERN, before: "ls|", keys: vec![typed(" ", KeyCode::Space), typed("-", KeyCode::Minus), typed("l", KeyCode::KeyL)], after: "ls -l|", submitted: None },
+        Case { name: "arrow left", ime: IDLE, mode: MODERN, before: "abc|", keys: vec![key(KeyCode::ArrowLeft)], after: "ab|c", submitted: None },
+        Case { name: "alt word left", ime: IDLE, mode: MODERN, before: "git commit|", keys: vec![chord(InputModifiers::ALT, KeyCode::ArrowLeft)], after: "git |commit", submitted: None },
+        Case { name: "ctrl-a line start", ime: IDLE, mode: MODERN, before: "echo hi|", keys: vec![chord(InputModifiers::CTRL, KeyCode::KeyA)], after: "|echo hi", submitted: None },
+        Case { name: "ctrl-alt-a is still line start", ime: IDLE, mode: MODERN, before: "echo hi|", keys: vec![chord(InputModifiers::CTRL_ALT, KeyCode::KeyA)], after: "|echo hi", submitted: None },
+        Case { name: "alt-end is still line end", ime: IDLE, mode: MODERN, before: "|echo hi", keys: vec![chord(InputModifiers::ALT, KeyCode::End)], after: "echo hi|", submitted: None },
+        Case { name: "ctrl-e line end", ime: IDLE, mode: MODERN, before: "|echo hi", keys: vec![chord(InputModifiers::CTRL, KeyCode::KeyE)], after: "echo hi|", submitted: None },
+        Case { name: "ctrl-k kill line", ime: IDLE, mode: MODERN, before: "echo| hi", keys: vec![chord(InputModifiers::CTRL, KeyCode::KeyK)], after: "echo|", submitted: None },
+        Case { name: "ctrl-shift-k kills line too", ime: IDLE, mode: MODERN, before: "echo| hi", keys: vec![chord(InputModifiers::CTRL_SHIFT, KeyCode::KeyK)], after: "echo|", submitted: None },
+        Case { name: "alt-backspace word", ime: IDLE, mode: MODERN, before: "cargo build|", keys: vec![chord(InputModifiers::ALT, KeyCode::Backspace)], after: "cargo |", submitted: None },
+        Case { name: "alt-d word forward", ime: IDLE, mode: MODERN, before: "|cargo build", keys: vec![chord(InputModifiers::ALT, KeyCode::KeyD)], after: "| build", submitted: None },
+        Case { name: "shift select then type", ime: IDLE, mode: MODERN, before: "abc|", keys: vec![chord(InputModifiers::SHIFT, KeyCode::ArrowLeft), chord(InputModifiers::SHIFT, KeyCode::ArrowLeft), typed("X", KeyCode::KeyX)], after: "aX|", submitted: None },
+        Case { name: "shift-home moves to line start", ime: IDLE, mode: MODERN, before: "abc|", keys: vec![chord(InputModifiers::SHIFT, KeyCode::Home)], after: "|abc", submitted: None },
+        Case { name: "super-a selects all", ime: IDLE, mode: MODERN, before: "a|bc", keys: vec![chord(InputModifiers::SUPER, KeyCode::KeyA)], after: "^abc|", submitted: None },
+        Case { name: "ctrl-shift-a is line start", ime: IDLE, mode: MODERN, before: "a|bc", keys: vec![chord(InputModifiers::CTRL_SHIFT, KeyCode::KeyA)], after: "|abc", submitted: None },
+        Case { name: "super-v pastes", ime: IDLE, mode: MODERN, before: "x|", keys: vec![chord(InputModifiers::SUPER, KeyCode::KeyV)], after: "xclip|", submitted: None },
+        Case { name: "ctrl-shift-v does not paste", ime: IDLE, mode: MODERN, before: "x|", keys: vec![chord(InputModifiers::CTRL_SHIFT, KeyCode::KeyV)], after: "x|", submitted: None },
+        Case { name: "cut then paste", ime: IDLE, mode: MODERN, before: "^ab|c", keys: vec![chord(InputModifiers::SUPER, KeyCode::KeyX), key(KeyCode::End), chord(InputModifiers::SUPER, KeyCode::KeyV)], after: "cab|", submitted: None },
+        Case { name: "ctrl-c types the text it produced", ime: IDLE, mode: MODERN, before: "a|", keys: vec![Press { key: KeyCode::KeyC, mods: InputModifiers::CTRL, text: Some("c") }], after: "ac|", submitted: None },
+        Case { name: "shift-backspace does not delete", ime: IDLE, mode: MODERN, before: "ab|", keys: vec![chord(InputModifiers::SHIFT, KeyCode::Backspace)], after: "ab|", submitted: None },
+        Case { name: "shift-enter newline", ime: IDLE, mode: MODERN, before: "a|", keys: vec![chord(InputModifiers::SHIFT, KeyCode::Enter), typed("b", KeyCode::KeyB), key(KeyCode::ArrowUp)], after: "a|\nb", submitted: None },
+        Case { name: "enter submits", ime: IDLE, mode: MODERN, before: "ls -la|", keys: vec![key(KeyCode::Enter)], after: "|", submitted: Some("ls -la") },
+        Case { name: "ime composing swallows keys", ime: ImeState::Composing, mode: MODERN, before: "a|", keys: vec![typed("k", KeyCode::KeyK), key(KeyCode::Enter), key(KeyCode::Backspace)], after: "a|", submitted: None },
+        Case { name: "alt text is typed", ime: IDLE, mode: MODERN, before: "|", keys: vec![Press { key: KeyCode::KeyL, mods: InputModifiers::ALT, text: Some("¬") }], after: "¬|", submitted: None },
+    ];
+    for case in &cases {
+        run(case);
+    }
+}
+
+#[test]
+fn test_vim_input_matrix() {
+    let normal = Mode::Vim(VimMode::Normal);
+    let insert = Mode::Vim(VimMode::Insert);
+    let cases = [
+        Case { name: "insert mode types", ime: IDLE, mode: insert, before: "|", keys: vec![typed("l", KeyCode::KeyL), typed("s", KeyCode::KeyS)], after: "ls|", submitted: None },
+        Case { name: "escape then h moves", ime: IDLE, mode: insert, before: "abc|", keys: vec![key(KeyCode::Escape), typed("h", KeyCode::KeyH)], after: "ab|c", submitted: None },
+        Case { name: "i enters insert mode and is typed", ime: IDLE, mode: normal, before: "a|b", keys: vec![typed("i", KeyCode::KeyI), typed("x", KeyCode::KeyX)], after: "aix|b", submitted: None },
+        Case { name: "a stays in normal mode", ime: IDLE, mode: normal, before: "|ab", keys: vec![typed("a", KeyCode::KeyA), typed("c", KeyCode::KeyC)], after: "|ab", submitted: None },
+        Case { name: "backspace in insert deletes", ime: IDLE, mode: insert, before: "ab|", keys: vec![key(KeyCode::Backspace)], after: "a|", submitted: None },
+        Case { name: "w and b move by word", ime: IDLE, mode: normal, before: "|git push origin", keys: vec![typed("w", KeyCode::KeyW), typed("w", KeyCode::KeyW), typed("b", KeyCode::KeyB)], after: "git |push origin", submitted: None },
+        Case { name: "dw deletes word", ime: IDLE, mode: normal, before: "|rm -rf", keys: vec![typed("d", KeyCode::KeyD), typed("w", KeyCode::KeyW)], after: "| -rf", submitted: None },
+        Case { name: "dd deletes the next word", ime: IDLE, mode: normal, before: "echo| hi", keys: vec![typed("d", KeyCode::KeyD), typed("d", KeyCode::KeyD)], after: "echo|", submitted: None },
+        Case { name: "u undoes", ime: IDLE, mode: normal, before: "|rm -rf", keys: vec![typed("d", KeyCode::KeyD), typed("w", KeyCode::KeyW), typed("u", KeyCode::KeyU)], after: "|rm -rf", submitted: None },
+        Case { name: "p pastes", ime: IDLE, mode: normal, before: "x|", keys: vec![typed("p", KeyCode::KeyP)], after: "xclip|", submitted: None },
+        Case { name: "enter in insert types its text", ime: IDLE, mode: insert, before: "a|", keys: vec![typed("\r", KeyCode::Enter)], after: "a\r|", submitted: None },
+        Case { name: "enter in normal submits", ime: IDLE, mode: insert, before: "make|", keys: vec![key(KeyCode::Escape), key(KeyCode::Enter)], after: "|", submitted: Some("make") },
+        Case { name: "ime composing in insert", ime: ImeState::Composing, mode: insert, before: "|", keys: vec![typed("n", KeyCode::KeyN), key(KeyCode::Escape)], after: "|", submitted: None },
+    ];
+    for case in &cases {
+        run(case);
+    }
+}
+
+#[test]
+fn test_autosuggestion_accept() {
+    let suggestion = " push origin feature/search";
+    assert_eq!(accept_autosuggestion(suggestion, Motion::Right), Some(suggestion));
+    assert_eq!(accept_autosuggestion(suggestion, Motion::LineEnd), Some(suggestion));
+    assert_eq!(accept_autosuggestion(suggestion, Motion::WordRight), Some(" push"));
+    assert_eq!(accept_autosuggestion(" feature/search", Motion::WordRight), Some(" feature/"));
+    assert_eq!(accept_autosuggestion("search", Motion::WordRight), Some("search"));
+    assert_eq!(accept_autosuggestion(suggestion, Motion::Left), None);
+    assert_eq!(accept_autosuggestion(suggestion, Motion::WordLeft), None);
+}
+
+#[test]
+fn test_crossterm_key_names() {
+    assert_eq!(
+        crossterm_key(PhysicalKey::Code(KeyCode::KeyP), InputModifiers::ALT),
+        (TermKeyCode::Char('p'), KeyModifiers::ALT)
+    );
+    assert_eq!(
+        crossterm_key(PhysicalKey::Code(KeyCode::Digit3), InputModifiers::CTRL_SHIFT),
+        (TermKeyCode::Char('3'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+    );
+    assert_eq!(crossterm_key(PhysicalKey::Code(KeyCode::Escape), InputModifiers::NONE), (TermKeyCode::Esc, KeyModifiers::NONE));
+    assert_eq!(crossterm_key(PhysicalKey::Code(KeyCode::F13), InputModifiers::NONE), (TermKeyCode::Null, KeyModifiers::NONE));
+}
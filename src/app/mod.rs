@@ -1,2 +1,7 @@
+pub mod input;
 pub mod state;
-pub mod pane;
\ No newline at end of file
+pub mod pane;
+pub mod selection;
+pub mod timeline;
+pub mod paste;
+pub mod fold;
\ No newline at end of file
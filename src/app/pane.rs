@@ -1,11 +1,31 @@
 use crate::agent::client::AgentResponse;
+use crate::agent::attachments::AttachmentSet;
 use crate::agent::model::ModelId;
+use crate::agent::tools::{AgentLoop, AgentStep};
+use crate::agent::translate::Translation;
+use crate::dirhistory::{cd_command, DirHistory, BASH_OSC7_PROMPT_COMMAND};
 use crate::event::AppEvent;
-use crate::pty::vte_handler::VteState;
-use portable_pty::{CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+use crate::hooks::OutputLines;
+use crate::config::{BlocksConfig, ProfileConfig};
+use crate::profiles;
+use crate::pty::encoding::{EncodingWriter, OutputDecoder, PaneEncodings, SharedEncodings};
+use crate::pty::vte_handler::{CommandExit, VteState, BASH_EXIT_STATUS_PROMPT_COMMAND};
+use crate::scratch::ScratchSession;
+use crate::share::live::OutputTap;
+use crate::ssh::{self, RemoteSession, SshDetector};
+use crate::structured::csv::CsvSummary;
+use crate::structured::stacktrace::{self, StackTrace};
+use crate::structured::{StructuredCapture, StructuredData};
+use crate::ui::scroll::SmoothScroll;
+use crate::wsl;
+use super::fold::{self, OutputView};
+use super::timeline::Timeline;
+use portable_pty::{Child, CommandBuilder, NativePtySystem, PtyPair, PtySize, PtySystem};
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use winit::event_loop::EventLoopProxy;
 
@@ -25,16 +45,79 @@ pub struct AgentState {
     pub conversation: Vec<(String, AgentResponse)>,
     pub current_input: String,
     pub is_follow_up: bool,
-    pub attached_context_blocks: Vec<usize>, // Indices into the VTE handler's block list
+    pub attachments: AttachmentSet, // Blocks, files and selections attached as context
     pub model_used: ModelId, // Track the model for this conversation
 }
 
+/// Where a pane's shell runs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PaneTarget {
+    #[default]
+    Local,
+    /// A WSL distro, started through `wsl.exe` on Windows.
+    Wsl { distro: String },
+}
+
+impl PaneTarget {
+    pub fn wsl_distro(&self) -> Option<&str> {
+        match self {
+            PaneTarget::Local => None,
+            PaneTarget::Wsl { distro } => Some(distro),
+        }
+    }
+}
+
 // A new struct to represent one atomic command/output unit.
 #[derive(Debug, Clone)]
 pub struct Block {
     pub id: Uuid,
     pub command: String,
     pub output: String,
+    // Machine-readable output for the table/JSON viewers, filled in the background
+    pub structured: Arc<Mutex<Option<StructuredData>>>,
+    // Column statistics shown as a footer once requested for CSV output
+    pub csv_summary: Option<CsvSummary>,
+    pub csv_summary_folded: bool,
+    // The output summarized in the user's language, once requested
+    pub translation: Option<Translation>,
+    // Stack traces in the output; library frames are hidden while folded
+    pub stack_traces: Vec<StackTrace>,
+    pub library_frames_folded: bool,
+    // The SSH session the command ran in, if it ran on another machine
+    pub remote: Option<RemoteSession>,
+    // The local directory the command ran in, when known
+    pub cwd: Option<std::path::PathBuf>,
+    // Labels added by the user from the block list, or by the tagger
+    pub tags: Vec<String>,
+}
+
+impl Block {
+    /// A block for `command` and its output, with nothing derived from the
+    /// output yet.
+    pub fn new(command: String, output: String) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            command,
+            output,
+            structured: Arc::new(Mutex::new(None)),
+            csv_summary: None,
+            csv_summary_folded: false,
+            translation: None,
+            stack_traces: Vec::new(),
+            library_frames_folded: true,
+            remote: None,
+            cwd: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// How a block's command ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockExit {
+    pub status: i32,
+    /// How long it ran, when it was sent from the app's input.
+    pub duration: Option<Duration>,
 }
 
 pub struct Pane {
@@ -46,8 +129,51 @@ pub struct Pane {
     // The command that is currently running or was just entered
     pub active_command: String,
     pub pty_writer: Box<dyn Write + Send>,
+    // What the shell is written to and read from in; UTF-8 unless changed
+    pub encodings: SharedEncodings,
     pty_pair: PtyPair,
+    // The shell, hung up on when the pane closes
+    shell: Box<dyn Child + Send + Sync>,
     pub agent_state: Option<AgentState>,
+    // The tool-use loop for an agent task that runs commands in this pane
+    pub agent_loop: Option<AgentLoop>,
+    // An agent command held until the user grants the capabilities it needs
+    pub agent_command_held: Option<String>,
+    // Re-runs known CLIs with machine-readable flags when set
+    pub structured_capture: Option<Arc<StructuredCapture>>,
+    // Marked by the user as a privacy zone; locked when the terminal is idle
+    pub privacy_zone: bool,
+    // Set for scratch panes; its temp directory is deleted with the pane
+    pub scratch: Option<ScratchSession>,
+    // What the shell runs on; paths it reports are translated for a WSL distro
+    pub target: PaneTarget,
+    // The launch profile the pane was opened with
+    pub profile: Option<String>,
+    // Directories this pane's shell has visited, shared with Lua plugins
+    pub dir_history: Arc<Mutex<DirHistory>>,
+    // Pixel scroll position over the block history
+    pub scroll: SmoothScroll,
+    // Notices when the shell is connected to another machine over SSH
+    pub ssh: SshDetector,
+    // Receives a copy of the PTY output while the pane is shared live
+    pub output_tap: OutputTap,
+    // Block boundaries and screen snapshots for scrubbing back in time
+    pub timeline: Timeline,
+    // Excluded by the user from jumping here when a command fails
+    pub mute_failure_focus: bool,
+    // How each block's command ended, once the shell reported it
+    pub block_exits: HashMap<Uuid, BlockExit>,
+    // Output views picked from the block menu; other blocks follow the config
+    pub output_views: HashMap<Uuid, OutputView>,
+    // When the command now running was sent, to time it
+    command_started_at: Option<Instant>,
+    // The last command the shell reported finished, to tell repeats apart
+    last_exit: Option<CommandExit>,
+    // Commands sent to the shell since hooks were last told about them
+    started_commands: Vec<String>,
+    // PTY output not yet scanned by output hooks
+    hook_output: Arc<Mutex<Vec<u8>>>,
+    hook_lines: OutputLines,
 }
 
 impl Pane {
@@ -57,6 +183,77 @@ impl Pane {
         shell_str: &str,
         event_proxy: EventLoopProxy<AppEvent>,
     ) -> Self {
+        let mut cmd = CommandBuilder::new(shell_str);
+        cmd.env("TERM_PROGRAM", "WarpishTerminal");
+        cmd.cwd(std::env::current_dir().unwrap());
+        Self::spawn(cols, rows, cmd, event_proxy)
+    }
+
+    /// Opens a pane whose shell starts in `cwd`, as when restoring a session.
+    pub fn new_in_dir(
+        cols: u16,
+        rows: u16,
+        shell_str: &str,
+        cwd: &std::path::Path,
+        event_proxy: EventLoopProxy<AppEvent>,
+    ) -> Self {
+        let mut cmd = CommandBuilder::new(shell_str);
+        cmd.env("TERM_PROGRAM", "WarpishTerminal");
+        cmd.cwd(cwd);
+        Self::spawn(cols, rows, cmd, event_proxy)
+    }
+
+    /// Opens a scratch pane whose shell runs inside `session`'s temp directory.
+    pub fn new_scratch(
+        cols: u16,
+        rows: u16,
+        shell_str: &str,
+        session: ScratchSession,
+        event_proxy: EventLoopProxy<AppEvent>,
+    ) -> Self {
+        let mut pane = Self::spawn(cols, rows, session.command(shell_str), event_proxy);
+        pane.scratch = Some(session);
+        pane
+    }
+
+    /// Opens a pane the way `profile` describes, then types its startup
+    /// command into the shell.
+    pub fn new_from_profile(
+        cols: u16,
+        rows: u16,
+        profile: &ProfileConfig,
+        default_shell: &str,
+        event_proxy: EventLoopProxy<AppEvent>,
+    ) -> Self {
+        let mut pane = Self::spawn(cols, rows, profiles::command(profile, default_shell), event_proxy);
+        pane.profile = Some(profile.name.clone());
+        if let Some(command) = &profile.startup_command {
+            if let Err(e) = pane.run_command(command) {
+                log::warn!("Failed to run the startup command of profile '{}': {}", profile.name, e);
+            }
+        }
+        pane
+    }
+
+    /// Opens a pane whose shell runs in a WSL distro, in `cwd` given as a
+    /// Linux path, or the distro user's home directory.
+    pub fn new_wsl(
+        cols: u16,
+        rows: u16,
+        distro: &str,
+        cwd: Option<&std::path::Path>,
+        event_proxy: EventLoopProxy<AppEvent>,
+    ) -> Self {
+        let mut cmd = CommandBuilder::new("wsl.exe");
+        cmd.args(wsl::shell_args(distro, cwd));
+        cmd.env("TERM_PROGRAM", "WarpishTerminal");
+        cmd.env("WSLENV", wsl::WSLENV);
+        let mut pane = Self::spawn(cols, rows, cmd, event_proxy);
+        pane.target = PaneTarget::Wsl { distro: distro.to_string() };
+        pane
+    }
+
+    fn spawn(cols: u16, rows: u16, mut cmd: CommandBuilder, event_proxy: EventLoopProxy<AppEvent>) -> Self {
         let pty_system = NativePtySystem::default();
         let pty_pair = pty_system
             .openpty(PtySize {
@@ -66,30 +263,47 @@ impl Pane {
             })
             .expect("Failed to open PTY");
 
-        let mut cmd = CommandBuilder::new(shell_str);
-        cmd.env("TERM_PROGRAM", "WarpishTerminal");
-        cmd.cwd(std::env::current_dir().unwrap());
-
-        let _child = pty_pair
+        // The exit status report goes first, while `$?` is still the command's.
+        cmd.env("PROMPT_COMMAND", format!("{}; {}", BASH_EXIT_STATUS_PROMPT_COMMAND, BASH_OSC7_PROMPT_COMMAND));
+        let shell = pty_pair
             .slave
             .spawn_command(cmd)
             .expect("Failed to spawn shell");
 
-        let pty_writer = pty_pair.master.take_writer().unwrap();
+        let encodings: SharedEncodings = Arc::new(Mutex::new(PaneEncodings::default()));
+        let pty_writer: Box<dyn Write + Send> =
+            Box::new(EncodingWriter::new(pty_pair.master.take_writer().unwrap(), encodings.clone()));
         let pty_reader = pty_pair.master.try_clone_reader().unwrap();
 
         let current_vte = Arc::new(Mutex::new(VteState::new(cols, rows)));
         let vte_clone = current_vte.clone();
+        let output_tap = OutputTap::default();
+        let tap_clone = output_tap.clone();
+        let hook_output = Arc::new(Mutex::new(Vec::new()));
+        let hook_output_clone = hook_output.clone();
+        let encodings_clone = encodings.clone();
 
         // The reader thread now only writes to the current VTE
         thread::spawn(move || {
             let mut reader = pty_reader;
             let mut buffer = [0u8; 8192];
+            let mut decoder = OutputDecoder::new(encodings_clone.lock().unwrap().output);
             loop {
                 match reader.read(&mut buffer) {
                     Ok(0) | Err(_) => break,
                     Ok(n) => {
-                        vte_clone.lock().unwrap().process(&buffer[..n]);
+                        crate::metrics::METRICS.pty_bytes.inc_by(n as u64);
+                        let output_encoding = encodings_clone.lock().unwrap().output;
+                        if decoder.encoding() != output_encoding {
+                            decoder = OutputDecoder::new(output_encoding);
+                        }
+                        // Everything past this point works in UTF-8.
+                        let output = decoder.decode(&buffer[..n]);
+                        vte_clone.lock().unwrap().process(&output);
+                        if let Some(tap) = &*tap_clone.lock().unwrap() {
+                            tap.send(output.to_vec()).ok();
+                        }
+                        hook_output_clone.lock().unwrap().extend_from_slice(&output);
                         event_proxy.send_event(AppEvent::PtyOutput).ok();
                     }
                 }
@@ -102,22 +316,267 @@ impl Pane {
             history: Vec::new(),
             active_command: String::new(),
             pty_writer,
+            encodings,
             pty_pair,
+            shell,
             agent_state: None,
+            agent_loop: None,
+            agent_command_held: None,
+            structured_capture: None,
+            privacy_zone: false,
+            scratch: None,
+            target: PaneTarget::Local,
+            profile: None,
+            dir_history: Arc::new(Mutex::new(DirHistory::new())),
+            scroll: SmoothScroll::new(),
+            ssh: SshDetector::new(ssh::local_hostname()),
+            output_tap,
+            timeline: Timeline::new(),
+            mute_failure_focus: false,
+            block_exits: HashMap::new(),
+            output_views: HashMap::new(),
+            command_started_at: None,
+            last_exit: None,
+            started_commands: Vec::new(),
+            hook_output,
+            hook_lines: OutputLines::default(),
         }
     }
 
-    /// "Seals" the current VTE state into a historical block.
-    pub fn new_block(&mut self) {
-        let mut vte = self.current_vte.lock().unwrap();
-        let output = vte.get_grid().as_ref().to_string();
-        vte.clear_all(); // Clear the VTE for the next command
+    /// "Seals" the current VTE state into a historical block. Called when the
+    /// shell reports the command finished; known CLIs are re-run for
+    /// structured output from here.
+    ///
+    /// If the block finishes a command run by the agent loop, its output is fed
+    /// back to the loop and the loop's next step is returned.
+    pub fn new_block(&mut self) -> Option<AgentStep> {
+        let output = {
+            let mut vte = self.current_vte.lock().unwrap();
+            let output = vte.get_grid().as_ref().to_string();
+            vte.clear_all(); // Clear the VTE for the next command
+            output
+        };
         let block = Block {
-            id: Uuid::new_v4(),
-            command: self.active_command.clone(),
-            output,
+            stack_traces: stacktrace::analyze(&output),
+            remote: self.ssh.session(),
+            cwd: self.local_cwd(),
+            ..Block::new(self.active_command.clone(), output)
+        };
+        if !block.command.is_empty() {
+            self.spawn_structured_capture(&block);
+        }
+        crate::metrics::METRICS.blocks_created.inc();
+        self.timeline.mark_block(block.id, chrono::Utc::now().timestamp_millis());
+        let step = match &mut self.agent_loop {
+            Some(agent_loop) if agent_loop.running_command() == Some(block.command.as_str()) => {
+                Some(agent_loop.record_output(&block.output))
+            }
+            _ => None,
         };
         self.history.push(block);
+        step
+    }
+
+    /// Runs the machine-readable variant of the block's command, if an adapter
+    /// knows one, and stores the parsed result on the block when it arrives.
+    /// It runs in the shell's directory, so not for remote sessions.
+    fn spawn_structured_capture(&self, block: &Block) {
+        let Some(capture) = self.structured_capture.clone() else {
+            return;
+        };
+        if capture.plan(&block.command).is_none() {
+            return;
+        }
+        let command = block.command.clone();
+        let slot = block.structured.clone();
+        let working_directory = match &self.scratch {
+            Some(session) => session.dir().to_path_buf(),
+            None => match self.local_cwd() {
+                Some(dir) => dir,
+                None => return,
+            },
+        }
+        .display()
+        .to_string();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::warn!("Failed to start structured capture runtime: {}", e);
+                    return;
+                }
+            };
+            if let Some(Ok(data)) = runtime.block_on(capture.capture(&command, &working_directory)) {
+                *slot.lock().unwrap() = Some(data);
+            }
+        });
+    }
+
+    /// Snapshots the live screen for the timeline if it has changed.
+    pub fn capture_timeline(&mut self, now: i64) {
+        if !self.timeline.is_due(now) {
+            return;
+        }
+        let screen = self.current_vte.lock().unwrap().get_grid().as_ref().to_string();
+        self.timeline.capture(now, &screen);
+    }
+
+    /// Records the shell's latest reported directory, and `cd`s to a
+    /// directory chosen from the history by the user or a plugin. Remote
+    /// directories are tracked by the SSH session instead of the history.
+    /// Returns the new directory when the shell moved.
+    pub fn sync_dir_history(&mut self) -> std::io::Result<Option<std::path::PathBuf>> {
+        let (cwd, host) = {
+            let vte = self.current_vte.lock().unwrap();
+            (vte.take_cwd(), vte.cwd_host())
+        };
+        let (pending, moved) = {
+            let mut history = self.dir_history.lock().unwrap();
+            let mut moved = None;
+            if let Some(cwd) = cwd {
+                if self.ssh.observe_cwd(host.as_deref(), &cwd) {
+                    if history.current() != Some(cwd.as_path()) {
+                        moved = Some(cwd.clone());
+                    }
+                    history.visit(&cwd);
+                }
+            }
+            (history.take_pending(), moved)
+        };
+        if let Some(dir) = pending {
+            self.pty_writer.write_all(format!("{}\n", cd_command(&dir)).as_bytes())?;
+            self.pty_writer.flush()?;
+        }
+        Ok(moved)
+    }
+
+    /// The shell's directory on this machine; `None` in a remote session.
+    /// In a WSL pane this is the Windows path of the distro's directory.
+    pub fn local_cwd(&self) -> Option<std::path::PathBuf> {
+        if self.ssh.session().is_some() {
+            return None;
+        }
+        let cwd = self.dir_history.lock().unwrap().current().map(std::path::Path::to_path_buf)?;
+        match &self.target {
+            PaneTarget::Local => Some(cwd),
+            PaneTarget::Wsl { distro } => wsl::to_windows_path(&cwd.to_string_lossy(), distro).map(std::path::PathBuf::from),
+        }
+    }
+
+    /// Records how the last block's command ended, and how long it ran.
+    pub fn record_exit(&mut self, status: i32, now: Instant) {
+        let duration = self.command_started_at.take().map(|started| now.saturating_duration_since(started));
+        if let Some(block) = self.history.last() {
+            self.block_exits.insert(block.id, BlockExit { status, duration });
+        }
+    }
+
+    /// How much of a block's output is shown.
+    pub fn output_view(&self, block: &Block, config: &BlocksConfig) -> OutputView {
+        self.output_views.get(&block.id).copied().unwrap_or_else(|| {
+            let succeeded = self.block_exits.get(&block.id).map(|exit| exit.status == 0);
+            fold::initial_view(block.output.lines().count(), succeeded, config)
+        })
+    }
+
+    /// Takes the command the shell reported finished since the last call.
+    /// A prompt redrawn without running anything repeats the previous
+    /// report, which is skipped; with `HISTCONTROL=ignoredups` a repeated
+    /// command looks the same and is skipped too.
+    pub fn take_finished_command(&mut self) -> Option<CommandExit> {
+        let exit = self.current_vte.lock().unwrap().take_command_exit()?;
+        let repeat = exit.history_id.is_some()
+            && self.last_exit.as_ref().is_some_and(|last| last.history_id == exit.history_id);
+        self.last_exit = Some(exit.clone());
+        (!repeat).then_some(exit)
+    }
+
+    /// Checks whether `ssh` is running in the foreground, at most once per
+    /// probe interval.
+    pub fn sync_ssh_session(&mut self, now: std::time::Instant) {
+        if !self.ssh.probe_due(now) {
+            return;
+        }
+        if let Some(command) = self.foreground_command() {
+            self.ssh.observe_foreground(&command);
+        }
+    }
+
+    /// The command line of the process group in the foreground of the pane's
+    /// terminal.
+    fn foreground_command(&self) -> Option<Vec<String>> {
+        #[cfg(unix)]
+        {
+            let pid = self.pty_pair.master.process_group_leader()?;
+            ssh::process_command_line(pid as u32)
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// The job in the foreground if it is not the shell itself, e.g. a build
+    /// or an editor, which closing the pane would interrupt.
+    pub fn running_job(&self) -> Option<String> {
+        #[cfg(unix)]
+        {
+            let pid = self.pty_pair.master.process_group_leader()? as u32;
+            if Some(pid) == self.shell.process_id() {
+                return None;
+            }
+            Some(ssh::process_command_line(pid).map_or_else(|| format!("process {}", pid), |argv| argv.join(" ")))
+        }
+        #[cfg(not(unix))]
+        {
+            None
+        }
+    }
+
+    /// The shell's pid, whose children are the pane's jobs.
+    pub fn shell_pid(&self) -> Option<u32> {
+        self.shell.process_id()
+    }
+
+    /// The shell process, for shutdown to hang up on.
+    pub fn shell_mut(&mut self) -> &mut (dyn Child + Send + Sync) {
+        self.shell.as_mut()
+    }
+
+    /// Sends `command` to the shell as a new command line.
+    pub fn run_command(&mut self, command: &str) -> std::io::Result<()> {
+        self.active_command = command.to_string();
+        self.started_commands.push(command.to_string());
+        self.command_started_at = Some(Instant::now());
+        self.pty_writer.write_all(format!("{}\n", command).as_bytes())?;
+        self.pty_writer.flush()
+    }
+
+    /// Types a command into the shell on behalf of the agent.
+    pub fn run_agent_command(&mut self, command: &str) -> std::io::Result<()> {
+        self.run_command(command)
+    }
+
+    /// Takes the commands sent to the shell since the last call.
+    pub fn take_started_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.started_commands)
+    }
+
+    /// Takes the complete output lines that arrived since the last call,
+    /// without escape sequences.
+    pub fn take_output_lines(&mut self) -> Vec<String> {
+        let bytes = std::mem::take(&mut *self.hook_output.lock().unwrap());
+        self.hook_lines.push(&bytes)
+    }
+
+    pub fn encodings(&self) -> PaneEncodings {
+        *self.encodings.lock().unwrap()
+    }
+
+    /// Switches the encodings used for the shell's input and output. Output
+    /// already read is not decoded again.
+    pub fn set_encodings(&self, encodings: PaneEncodings) {
+        *self.encodings.lock().unwrap() = encodings;
     }
 
     pub fn resize(&self, cols: u16, rows: u16) {
@@ -132,6 +591,15 @@ impl Pane {
             .ok();
     }
 
+    /// The pane's size in columns and rows.
+    pub fn size(&self) -> (u16, u16) {
+        self.pty_pair
+            .master
+            .get_size()
+            .map(|size| (size.cols, size.rows))
+            .unwrap_or((80, 24))
+    }
+
     pub fn enter_agent_mode(&mut self, initial_query: String, model: ModelId) {
         if self.agent_state.is_none() {
             self.agent_state = Some(AgentState {
@@ -141,7 +609,7 @@ impl Pane {
                 conversation: vec![],
                 current_input: initial_query,
                 is_follow_up: false,
-                attached_context_blocks: vec![],
+                attachments: AttachmentSet::new(),
                 model_used: model, // Store the model
             });
         }
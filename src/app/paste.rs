@@ -0,0 +1,62 @@
+//! Files dropped on the window and images pasted from the clipboard.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Extensions attached to agent queries as images rather than as text.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// A dropped path as the shell should see it, with a space after so
+/// several dropped files end up as separate arguments.
+pub fn escaped_path(path: &Path) -> String {
+    format!("{} ", shellwords::escape(&path.to_string_lossy()))
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Where pasted images are saved.
+pub fn paste_dir() -> PathBuf {
+    std::env::temp_dir().join("warpish-paste")
+}
+
+/// Saves clipboard pixels, which arrive as RGBA rows, as a PNG in `dir`.
+pub fn save_image(width: usize, height: usize, rgba: &[u8], dir: &Path) -> io::Result<PathBuf> {
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, rgba.to_vec())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "image data does not match its size"))?;
+    fs::create_dir_all(dir)?;
+    let id = Uuid::new_v4().simple().to_string();
+    let path = dir.join(format!("paste-{}-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S"), &id[..8]));
+    image.save(&path).map_err(io::Error::other)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dropped_paths_are_escaped() {
+        assert_eq!(escaped_path(Path::new("/tmp/report.pdf")), "/tmp/report.pdf ");
+        assert_eq!(escaped_path(Path::new("/tmp/my notes.txt")), "/tmp/my\\ notes.txt ");
+        assert!(is_image(Path::new("/tmp/Screenshot.PNG")));
+        assert!(!is_image(Path::new("/tmp/notes.txt")));
+    }
+
+    #[test]
+    fn test_pasted_image_is_saved_as_png() {
+        let dir = std::env::temp_dir().join(format!("warpish-paste-test-{}", Uuid::new_v4()));
+        let path = save_image(2, 1, &[255, 0, 0, 255, 0, 0, 255, 255], &dir).unwrap();
+        assert_eq!(path.extension().unwrap(), "png");
+        let saved = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(saved.dimensions(), (2, 1));
+        assert_eq!(saved.get_pixel(1, 0).0, [0, 0, 255, 255]);
+        assert!(save_image(2, 2, &[0; 4], &dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! Multi-select over a pane's blocks, for batch actions, and the record of
+//! batch deletes kept so they can be undone.
+
+use super::pane::Block;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// How moving the cursor changes the selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Selects only the block moved to.
+    Replace,
+    /// Selects every block between the anchor and the cursor (Shift).
+    Extend,
+    /// Leaves the selection alone (Ctrl).
+    Keep,
+}
+
+/// The selected blocks, the cursor, and the anchor a Shift range starts from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BlockSelection {
+    cursor: usize,
+    anchor: usize,
+    selected: BTreeSet<usize>,
+}
+
+impl BlockSelection {
+    /// Starts with only the block at `cursor` selected.
+    pub fn new(cursor: usize) -> Self {
+        Self { cursor, anchor: cursor, selected: BTreeSet::from([cursor]) }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        self.selected.contains(&idx)
+    }
+
+    /// The selected block indices, oldest first.
+    pub fn indices(&self) -> Vec<usize> {
+        self.selected.iter().copied().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    /// Moves the cursor by `delta`, staying within `count` blocks.
+    pub fn move_cursor(&mut self, delta: isize, count: usize, mode: SelectMode) {
+        if count == 0 {
+            return;
+        }
+        self.cursor = self.cursor.saturating_add_signed(delta).min(count - 1);
+        match mode {
+            SelectMode::Replace => {
+                self.anchor = self.cursor;
+                self.selected = BTreeSet::from([self.cursor]);
+            }
+            SelectMode::Extend => {
+                let (start, end) = (self.anchor.min(self.cursor), self.anchor.max(self.cursor));
+                self.selected = (start..=end).collect();
+            }
+            SelectMode::Keep => {}
+        }
+    }
+
+    /// Adds or removes the block under the cursor (Ctrl+Space), which
+    /// becomes the anchor for the next Shift range.
+    pub fn toggle(&mut self) {
+        if !self.selected.remove(&self.cursor) {
+            self.selected.insert(self.cursor);
+        }
+        self.anchor = self.cursor;
+    }
+
+    pub fn select_all(&mut self, count: usize) {
+        self.selected = (0..count).collect();
+    }
+
+    /// Keeps the cursor in range after blocks were removed or restored, and
+    /// selects only the block under it.
+    pub fn reset(&mut self, count: usize) {
+        *self = Self::new(self.cursor.min(count.saturating_sub(1)));
+    }
+}
+
+/// Blocks removed from a pane by one batch delete, with the positions they
+/// had, so the delete can be undone.
+#[derive(Debug, Clone)]
+pub struct DeletedBlocks {
+    pub pane_id: Uuid,
+    pub blocks: Vec<(usize, Block)>,
+}
+
+/// Removes the items at `indices` and returns them with their positions,
+/// lowest first.
+pub fn remove_indices<T>(items: &mut Vec<T>, indices: &[usize]) -> Vec<(usize, T)> {
+    let mut indices: Vec<usize> = indices.iter().copied().filter(|idx| *idx < items.len()).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    let mut removed: Vec<(usize, T)> = indices.into_iter().rev().map(|idx| (idx, items.remove(idx))).collect();
+    removed.reverse();
+    removed
+}
+
+/// Puts items taken out by [`remove_indices`] back where they were.
+pub fn restore_indices<T>(items: &mut Vec<T>, removed: Vec<(usize, T)>) {
+    for (idx, item) in removed {
+        items.insert(idx.min(items.len()), item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_and_ctrl_selection() {
+        let mut selection = BlockSelection::new(4);
+        selection.move_cursor(-2, 5, SelectMode::Extend);
+        assert_eq!(selection.indices(), vec![2, 3, 4]);
+
+        // Ctrl moves without selecting; Ctrl+Space adds the block.
+        selection.move_cursor(-2, 5, SelectMode::Keep);
+        selection.toggle();
+        assert_eq!(selection.indices(), vec![0, 2, 3, 4]);
+
+        // Shift extends from the toggled block, replacing the old range.
+        selection.move_cursor(1, 5, SelectMode::Extend);
+        assert_eq!(selection.indices(), vec![0, 1]);
+
+        selection.move_cursor(10, 5, SelectMode::Replace);
+        assert_eq!((selection.cursor(), selection.indices()), (4, vec![4]));
+    }
+
+    #[test]
+    fn test_batch_delete_is_undone_in_place() {
+        let mut items = vec!["a", "b", "c", "d", "e"];
+        let removed = remove_indices(&mut items, &[3, 1, 3, 9]);
+        assert_eq!(items, vec!["a", "c", "e"]);
+        assert_eq!(removed, vec![(1, "b"), (3, "d")]);
+        restore_indices(&mut items, removed);
+        assert_eq!(items, vec!["a", "b", "c", "d", "e"]);
+    }
+}
@@ -1,6 +1,15 @@
-use crate::agent::client::AgentResponse;
+use crate::agent::client::{AgentProvider, AgentResponse};
 use crate::agent::reasoning::ChainOfThought;
-use crate::config::{Config, CursorShape, InputPosition, PromptMode, TextConfig};
+use crate::agent::attachments::{render_ask_ai_prompt, Attachment, Chip};
+use crate::agent::commit::CommitMessageGenerator;
+use crate::explain::{AgentExplainer, ExplainedPart, HelpCache};
+use crate::markdown_parser::{task_items, toggle_task, TaskItem};
+use crate::agent::quiz::{sample_history, QuizGenerator};
+use crate::agent_mode_eval::usage::{budget_level, month_start, report_start, UsageMeter, UsageReport, BUDGET_WARNING_SHARE};
+use crate::agent_mode_eval::{AbTest, AgentEvaluator, Outcome};
+use crate::agent::translate::{user_locale, Translator};
+use crate::agent::tools::{default_command_rules, AgentLoop, AgentStep, ApprovalDecision, PendingCommand, SimulatedPlanner};
+use crate::config::{BlocksConfig, Config, CursorShape, EncodingConfig, FailureFocusAction, InputPosition, PromptMode, TextConfig};
 
 // Temporary placeholder for WorkflowBrowserState
 #[derive(Debug, Clone)]
@@ -8,48 +17,167 @@ use crate::config::{Config, CursorShape, InputPosition, PromptMode, TextConfig};
 pub struct WorkflowBrowserState {
     pub placeholder: String,
 }
-use crate::drive::{DriveManager, Notebook, Workflow};
+use crate::completions::{Completer, FilePathCompleter};
+use crate::drive::{ArgumentType, DriveError, DriveManager, Notebook, Prompt, Workflow};
 use crate::error::AppError;
+use crate::git::GitMonitor;
+use crate::focus::{FailureFocus, FinishedCommand};
+use crate::hooks::{HookEvent, Hooks};
+use crate::command::http::{self, HttpRunner};
+use crate::github::{GitHubItem, GitHubItemKind, GitHubMonitor};
+use crate::graphql::explorer::{self as graphql_explorer, GraphQLExplorer, SavedQuery};
+use crate::graphql::schema;
+use crate::websocket::console::{self as ws_console, ConsoleEvent, ConsoleInput, Frame, WebSocketConsole};
+use crate::integration::docker::{self, DockerMonitor};
+use crate::integration::editor::{self, EditorLaunch, FileLocation};
+use crate::integration::format::{self as formatter, Formatted};
+use crate::code::CodeBlock;
+use crate::virtual_fs::browser::{self, FileTree, Preview, TreeRow};
+use crate::virtual_fs::MountTable;
+use crate::integration::Integration;
+use crate::k8s::KubeMonitor;
+use crate::ports::{PaneScan, PortDetector};
+use crate::prefetch::{PrefetchSource, Prefetcher};
+use crate::prompt_chips::PromptChips;
 use crate::event::AppEvent;
+use crate::idle::{IdleMonitor, PaneActivity, SystemAuthenticator};
+use crate::jobs::{JobKind, JobManager, JobSignal};
+use crate::mcq::survey::{SurveyScheduler, AWARENESS};
+use crate::mcq::bank::{QuestionBank, QuestionSet};
+use crate::mcq::{Quiz, QuizSession, QuizSummary};
+use crate::mcq::MultipleChoiceQuestion;
+use crate::pair::{PairProgramming, PairSession};
+use crate::ollama::{record_progress, InstalledModel, ModelRole, OllamaEvent, OllamaManager};
+use crate::permissions::{Capability, PermissionChoice, PermissionRequest, PermissionService, PermissionStatus, Requester};
+use crate::scratch::{blocks_to_notebook, is_interesting, ScratchSession};
+use crate::snippets::{Builtins, SnippetLibrary, SnippetSession, Step, Template};
+use crate::abbreviations::{self, AbbreviationSet};
+use crate::natural_language_detection::{Correction, NaturalLanguageDetector};
+use crate::crash::{self, PendingReport};
+use crate::perf::{default_profile_dir, ProfileCapture};
+use crate::session::journal::{default_journal_path, Journal, JournaledBlock, JournaledPane, SessionJournal, MAX_JOURNALED_BLOCKS};
+use crate::session::{Session, LAST_SESSION_ID};
+use crate::shutdown::{hang_up, BackgroundTasks};
+use crate::ssh::corpus::HostCorpora;
+use crate::search::{search, search_history, PaletteRanking, PendingSearch, SearchDomain, SearchResult, SearchSources, SearchTarget, APP_ACTIONS};
+use crate::string_offset::clip_to_grapheme;
+use crate::structured::stacktrace;
+use crate::structured::StructuredData;
+use crate::sum_tree::Bias;
+use crate::syntax_parser::{CommandPart, Grammar, SyntaxParserPool};
+use crate::syntax_tree::{self, DiffPatch, ExtractFunction, RenameSymbol, SyntaxError};
+use crate::tagging::{AutoTagger, BackgroundTagger};
+use super::pane::{Block, Pane};
+use super::selection::{remove_indices, restore_indices, BlockSelection, DeletedBlocks, SelectMode};
+use super::paste;
+use super::fold::{self, OutputView};
 use crate::keybindings::{KeyBinding, Keymap};
+use crate::doctor::DoctorReport;
+use crate::pdf::{PdfDocument, PdfStyle};
+use crate::pty::encoding::{PaneEncoding, PaneEncodings};
 use crate::pty::vte_handler::VteState;
 use crate::rules::{Rule, RuleAction};
+use crate::keychain::Keychain;
+use crate::share::live::LiveShare;
+use crate::share::export::{self, ExportFormat, HtmlStyle};
+use crate::share::{Redactor, ShareContent, ShareService};
+use crate::stats::{ActivityRecord, ActivitySummary, ReportPeriod, ReportScheduler};
+use crate::watcher::watch::CommandWatch;
+use crate::lpc::protocol::{self as lpc, BlockInfo, Method, PaneInfo, RpcError};
+use crate::webhooks::{self, WebhookEvent};
+use crate::websearch::{SearchStarted, WebResult, WebSearch};
+use crate::ui::bidi::{self, BidiLine, VisualDirection};
+use crate::ui::minimap::{Minimap, MinimapBlock};
+use crate::ui::sticky::{self, StickyHeader};
+use crate::ui::scroll::{ScrollInput, ScrollSettings};
 use crate::ui::theme::{Theme, ThemeManager};
-use cosmic_text::{Attrs, AttrsList, Buffer, Color, Cursor, CursorMove, Editor, FontSystem, Metrics, Shaping, SwashCache, Weight, Style as FontStyle, Edit};
+use cosmic_text::{Attrs, AttrsList, Buffer, Color, Cursor, CursorMove, Editor, FontSystem, Metrics, Selection, Shaping, SwashCache, Weight, Style as FontStyle, Edit};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
 use portable_pty::{CommandBuilder, MasterPty, NativePtySystem, PtyPair, PtySize, PtySystem};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeMap, HashMap},
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread,
     time::Duration,
 };
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use winit::event_loop::EventLoopProxy;
 use crate::completions_ui::CompletionsManager;
+use crate::db::{AgentConversationSummary, AgentMessageRecord};
 use crate::completions_ui::CompletionsAction;
 use crate::config::EditorConfig;
-use crate::vim::{VimAction, VimMode, VimMotion, VimState};
+use crate::vim::VimState;
+use super::input::continuation::{continuation, Continuation};
+use super::input::{accept_autosuggestion, resolve_modern_key, resolve_vim_key, EditorCommand, ImeState, InputModifiers, Motion};
 use arboard::Clipboard;
 use winit::keyboard::PhysicalKey;
 
+/// Palette items remembered as recently used.
+const MAX_PALETTE_RECENT: usize = 50;
+/// Commands offered when entering a directory, each on Alt+1 and up.
+const MAX_DIRECTORY_SUGGESTIONS: usize = 3;
+/// Questions asked in one drill from the quiz bank.
+const DRILL_LENGTH: usize = 10;
+/// Questions the agent is asked for about the tools in the history.
+const GENERATED_QUESTIONS: usize = 5;
+/// The syntax parser pool's key for the input editor's command line.
+pub const INPUT_DOCUMENT: &str = "input";
+/// Tags the blocks a WebSocket console shows frames in.
+const WS_FRAME_TAG: &str = "websocket";
+
 /// Defines the current operational mode of the application.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum AppMode {
     Normal,
-    HistorySearch(HistorySearchState),
+    Search(SearchState),
     AiPrompt,
     Agent(AgentState),
     BlockMenu(BlockMenuState),
     Settings(SettingsState),
-    CommandPalette(CommandPaletteState),
     Drive(WorkflowBrowserState),
-    AgentManagement,
+    AgentManagement(AgentManagementState),
     CodeReview(CodeReviewState),
+    ActivityReport(ActivityReportState),
+    AgentApproval(AgentApprovalState),
+    ScratchClose(ScratchCloseState),
+    DirHistory(DirHistoryMenuState),
+    PermissionPrompt(PermissionPromptState),
+    PermissionAudit(PermissionAuditState),
+    Survey(SurveyPromptState),
+    WorkflowFill(WorkflowFillState),
+    BlockSelect(BlockSelectState),
+    ShareActivity(ShareActivityState),
+    Scrub(ScrubState),
+    FailureJump(FailureJumpState),
+    Ollama(OllamaPanelState),
+    EncodingPicker(EncodingPickerState),
+    WebSearch(WebSearchState),
+    ConfirmClose(ConfirmCloseState),
+    ConfirmWebhook(ConfirmWebhookState),
+    RestoreSession(RestoreSessionState),
+    CrashReport(CrashReportState),
+    WslPicker(WslPickerState),
+    NewPane(NewPaneMenuState),
+    WatchGlob(WatchGlobState),
+    Jobs(JobsPanelState),
+    Ports(PortsPanelState),
+    Docker(DockerPanelState),
+    PullRequestPicker(PullRequestPickerState),
+    LocationPicker(LocationPickerState),
+    StackTrace(StackTraceState),
+    GraphQLExplorer(GraphQLExplorerState),
+    FileBrowser(FileBrowserState),
+    Abbreviations(AbbreviationsState),
+    Quiz(QuizState),
+    ExplainCommand(ExplainCommandState),
+    Notebook(NotebookState),
+    Refactor(RefactorState),
+    DiffReview(DiffReviewState),
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -57,26 +185,77 @@ pub struct BlockMenuState {
     pub pane_idx: usize,
     pub block_idx: usize,
     pub selected_action_idx: usize,
+    /// Text selected inside the block when the menu was opened.
+    pub selection: Option<String>,
+    /// Whether the block's output parses as CSV.
+    pub is_csv: bool,
+    /// Whether the block already shows a CSV summary footer.
+    pub has_csv_summary: bool,
+    /// Whether the block already shows a translation footer.
+    pub has_translation: bool,
+    /// Whether the pane re-runs a command on file changes.
+    pub watching: bool,
+    /// Whether the block is an `http` request.
+    pub is_http: bool,
+    /// Whether the output mentions `file:line` locations.
+    pub has_locations: bool,
+    /// Whether the output has stack traces.
+    pub has_stack_trace: bool,
+    pub library_frames_folded: bool,
+    pub output_view: OutputView,
+    /// Whether the output is longer than a collapsed block shows.
+    pub foldable: bool,
+    /// Whether the output is long enough to show only its last lines.
+    pub huge_output: bool,
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub struct SettingsState {
-    pub selected_idx: usize,
-    pub filtered_list: Vec<PaletteItem>,
+impl BlockMenuState {
+    pub fn items(&self) -> Vec<&'static str> {
+        let ask_ai = if self.selection.is_some() { "Ask AI about Selection" } else { "Ask AI" };
+        let mut items = vec!["Copy Command", "Copy Output", "Re-input Command", ask_ai];
+        if self.selection.is_some() {
+            items.extend(["Attach Selection to Agent", "Search Web for Selection"]);
+        }
+        if self.has_locations {
+            items.push("Open in Editor");
+        }
+        if self.has_stack_trace {
+            items.push("Step Through Stack Trace");
+            items.push(if self.library_frames_folded { "Show Library Frames" } else { "Hide Library Frames" });
+        }
+        if self.foldable {
+            items.push(if self.output_view == OutputView::Collapsed { "Expand Output" } else { "Collapse Output" });
+        }
+        if self.huge_output {
+            items.push(if self.output_view == OutputView::Tail { "Show Full Output" } else { "Show Last Lines" });
+        }
+        items.extend(["Share", "Export Markdown", "Export HTML", "Upload to Gist", "Comment on Pull Request"]);
+        if self.has_csv_summary {
+            items.push("Toggle CSV Summary");
+        } else if self.is_csv {
+            items.push("Summarize CSV");
+        }
+        items.push(if self.has_translation { "Toggle Translation" } else { "Translate Output" });
+        items.push(if self.watching { "Stop Watching" } else { "Watch and Re-run" });
+        if self.is_http {
+            items.extend(["Re-run Request", "Save to Collection"]);
+        }
+        items
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub struct CommandPaletteState {
-    pub query: String,
+pub struct SettingsState {
     pub selected_idx: usize,
     pub filtered_list: Vec<PaletteItem>,
 }
 
+/// The search overlay. The query keeps its domain prefix, e.g. `@src/`.
 #[derive(PartialEq, Eq, Clone, Debug)]
-pub struct HistorySearchState {
+pub struct SearchState {
     pub query: String,
     pub selected_idx: usize,
-    pub filtered_list: Vec<String>,
+    pub results: Vec<SearchResult>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -87,11 +266,482 @@ pub struct CodeReviewState {
     pub hunks: Vec<Vec<similar::TextDiff<'static, 'static, 'static, str>>>
 }
 
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ActivityReportState {
+    pub period: ReportPeriod,
+    pub summary: ActivitySummary,
+    pub markdown: String,
+    pub scroll: usize,
+    pub exported_to: Option<PathBuf>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AgentApprovalState {
+    pub pane_idx: usize,
+    pub pending: PendingCommand,
+    pub selected_idx: usize,
+}
+
+/// Asks which blocks of a closing scratch pane to save to Drive.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ScratchCloseState {
+    pub pane_idx: usize,
+    /// Indices into the pane's history of the blocks offered for saving.
+    pub block_indices: Vec<usize>,
+    pub selected: Vec<bool>,
+    pub cursor: usize,
+}
+
+/// Asks before closing a pane, or quitting, while jobs are running in it.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ConfirmCloseState {
+    /// The pane to close, or `None` to quit.
+    pub pane_idx: Option<usize>,
+    /// The command line of each running job.
+    pub jobs: Vec<String>,
+}
+
+/// Asks before running a webhook-triggered workflow that a warning rule matched.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ConfirmWebhookState {
+    pub name: String,
+    pub command: String,
+}
+
+/// Offers to restore the session of a run that crashed or was killed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RestoreSessionState {
+    pub journal: SessionJournal,
+}
+
+/// Offers to view or submit the reports of crashes since the last launch.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct CrashReportState {
+    /// Newest first; the first is the one viewed or submitted.
+    pub reports: Vec<PendingReport>,
+}
+
+/// The directory history dropdown shown under the cwd prompt chip.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DirHistoryMenuState {
+    pub pane_idx: usize,
+    pub entries: Vec<PathBuf>,
+    pub selected_idx: usize,
+}
+
+/// Asks whether a plugin or agent tool may use a capability.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PermissionPromptState {
+    pub request: PermissionRequest,
+    pub selected_idx: usize,
+}
+
+/// Lists permission decisions so they can be reviewed and revoked.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PermissionAuditState {
+    pub selected_idx: usize,
+}
+
+/// Shows a pane as it was at an earlier moment of its timeline.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ScrubState {
+    pub pane_idx: usize,
+    /// Milliseconds since the Unix epoch.
+    pub at: i64,
+}
+
+/// Offers to focus a pane where a command just failed.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct FailureJumpState {
+    pub pane_id: Uuid,
+    pub command: String,
+    pub status: i32,
+}
+
+/// The local Ollama models, their disk usage and role assignments.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct OllamaPanelState {
+    /// `None` until the first listing arrives.
+    pub models: Option<Result<Vec<InstalledModel>, String>>,
+    pub selected_idx: usize,
+    /// The name of a model to pull, while one is being typed.
+    pub pull_input: Option<String>,
+}
+
+/// Chooses the character encodings a pane's shell is spoken to in.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct EncodingPickerState {
+    pub pane_idx: usize,
+    /// An index into `PaneEncoding::ALL`.
+    pub selected_idx: usize,
+}
+
+/// The new pane menu: the default shell, then each launch profile.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct NewPaneMenuState {
+    pub selected_idx: usize,
+}
+
+/// Asks which files re-run a block's command when they change.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct WatchGlobState {
+    pub pane_idx: usize,
+    pub command: String,
+    pub glob: String,
+}
+
+/// The jobs panel: processes started in panes, with their CPU and memory.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct JobsPanelState {
+    pub selected_idx: usize,
+}
+
+/// Local services found in the panes, to open in the browser or stop.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PortsPanelState {
+    pub selected_idx: usize,
+}
+
+/// Which listing the Docker panel shows.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum DockerView {
+    Containers,
+    Images,
+}
+
+/// Containers and images on the local Docker daemon.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DockerPanelState {
+    pub view: DockerView,
+    pub selected_idx: usize,
+}
+
+/// Chooses the pull request a block's output is posted to as a comment.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PullRequestPickerState {
+    pub pane_idx: usize,
+    pub block_idx: usize,
+    pub selected_idx: usize,
+}
+
+/// Chooses which of a block's `file:line` locations to open in the editor.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LocationPickerState {
+    pub pane_idx: usize,
+    pub locations: Vec<FileLocation>,
+    pub selected_idx: usize,
+}
+
+/// Steps through the frames of a block's stack traces; `frame_idx` counts
+/// frames across all of them.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct StackTraceState {
+    pub pane_idx: usize,
+    pub block_idx: usize,
+    pub frame_idx: usize,
+}
+
+/// What the file browser asks before it acts on the selected entry.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum FileBrowserPrompt {
+    /// The new path, as typed so far.
+    Rename(String),
+    Copy(String),
+    /// A directory to browse instead, such as `sftp://host/` or `release.zip!/`.
+    Root(String),
+    ConfirmDelete,
+    /// The formatted file, written once its diff is accepted.
+    ConfirmFormat(Formatted),
+}
+
+/// Browses any filesystem the mount table can list, with a preview of the
+/// selected entry.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct FileBrowserState {
+    pub tree: FileTree,
+    pub rows: Vec<TreeRow>,
+    pub selected_idx: usize,
+    pub preview: Option<Preview>,
+    pub prompt: Option<FileBrowserPrompt>,
+}
+
+/// What the abbreviations list is asking for.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum AbbreviationPrompt {
+    /// `name=expansion`, as typed so far.
+    Add(String),
+    /// A changed `name=expansion` for the abbreviation called `name`.
+    Edit { name: String, input: String },
+    ConfirmDelete,
+}
+
+/// Lists, edits, imports and exports abbreviations.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AbbreviationsState {
+    pub selected_idx: usize,
+    pub prompt: Option<AbbreviationPrompt>,
+}
+
+/// A quiz in progress, with the option under the cursor.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct QuizState {
+    pub session: QuizSession,
+    pub selected_idx: usize,
+}
+
+/// The command line broken down part by part.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ExplainCommandState {
+    pub command: String,
+    pub parts: Vec<CommandPart>,
+    pub explained: Vec<ExplainedPart>,
+    /// The agent's explanations of parts nothing else explained, by text.
+    pub agent: HashMap<String, String>,
+    pub asking_agent: bool,
+}
+
+/// A Drive notebook open for reading, with its task list items ticked off
+/// in place.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct NotebookState {
+    pub notebook: Notebook,
+    pub tasks: Vec<TaskItem>,
+    /// The task under the cursor.
+    pub selected_idx: usize,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RefactorKind {
+    RenameSymbol,
+    ExtractFunction,
+}
+
+impl RefactorKind {
+    /// What each of the form's fields asks for.
+    pub fn labels(self) -> [&'static str; 3] {
+        match self {
+            RefactorKind::RenameSymbol => ["File", "Symbol", "New name"],
+            RefactorKind::ExtractFunction => ["File", "Lines (e.g. 12-18)", "Function name"],
+        }
+    }
+}
+
+/// A rename or extract-function refactor being filled in.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct RefactorState {
+    pub kind: RefactorKind,
+    /// One value per label, as typed.
+    pub values: Vec<String>,
+    pub selected_idx: usize,
+    /// Why the last attempt to refactor failed.
+    pub error: Option<String>,
+}
+
+impl RefactorState {
+    pub fn new(kind: RefactorKind) -> Self {
+        Self { kind, values: vec![String::new(); 3], selected_idx: 0, error: None }
+    }
+}
+
+/// Patches waiting to be reviewed a hunk at a time before they are
+/// written.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct DiffReviewState {
+    pub explanation: String,
+    pub files: Vec<DiffPatch>,
+    pub current_file_idx: usize,
+    pub current_hunk_idx: usize,
+    /// The refactor that made the patches, to go back to and change.
+    pub refactor: Option<RefactorState>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum GraphQLFocus {
+    Endpoint,
+    Query,
+    /// The nth variable the query declares.
+    Variable(usize),
+}
+
+/// The GraphQL query explorer: an endpoint, a query with completions from
+/// the endpoint's schema, and a form for the query's variables.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct GraphQLExplorerState {
+    pub endpoint: String,
+    pub query: String,
+    pub variables: BTreeMap<String, String>,
+    pub focus: GraphQLFocus,
+    pub selected_completion: usize,
+    /// The saved query last opened, for cycling through them.
+    pub saved_idx: Option<usize>,
+}
+
+impl GraphQLExplorerState {
+    pub fn variable_definitions(&self) -> Vec<schema::VariableDefinition> {
+        schema::variable_definitions(&self.query)
+    }
+}
+
+/// Chooses the WSL distro a new pane opens into.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct WslPickerState {
+    pub distros: Vec<String>,
+    pub selected_idx: usize,
+}
+
+/// Looks a selection up on the web: first a provider is chosen, then its
+/// results are listed if it has a search API.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct WebSearchState {
+    /// The redacted query sent to the provider.
+    pub query: String,
+    pub selected_idx: usize,
+    pub view: WebSearchView,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum WebSearchView {
+    Providers,
+    Loading,
+    Results(Result<Vec<WebResult>, String>),
+}
+
+/// Collaborators in the live shared session and its activity log.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ShareActivityState {
+    pub selected_idx: usize,
+}
+
+/// The block list with several blocks selected for a batch action.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct BlockSelectState {
+    pub pane_idx: usize,
+    pub selection: BlockSelection,
+    /// The tag being typed for the selected blocks, if any.
+    pub tag_input: Option<String>,
+}
+
+/// Fills in a workflow's arguments before it goes on the command line.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct WorkflowFillState {
+    pub workflow: Workflow,
+    /// One value per argument, as typed or toggled.
+    pub values: Vec<String>,
+    pub selected_idx: usize,
+    /// Paths matching the selected path argument.
+    pub suggestions: Vec<String>,
+    /// Why the last attempt to fill in the command failed.
+    pub error: Option<String>,
+}
+
+impl WorkflowFillState {
+    pub fn new(workflow: Workflow) -> Self {
+        let values = workflow.arguments.iter().map(|argument| argument.initial_value()).collect();
+        let mut state = Self { workflow, values, selected_idx: 0, suggestions: Vec::new(), error: None };
+        state.refresh_suggestions();
+        state
+    }
+
+    fn refresh_suggestions(&mut self) {
+        self.suggestions.clear();
+        let Some(argument) = self.workflow.arguments.get(self.selected_idx) else {
+            return;
+        };
+        if argument.arg_type == ArgumentType::Path {
+            let mut paths: Vec<String> = FilePathCompleter
+                .suggest(&self.values[self.selected_idx])
+                .into_iter()
+                .map(|suggestion| suggestion.replacement)
+                .collect();
+            paths.sort();
+            paths.truncate(8);
+            self.suggestions = paths;
+        }
+    }
+
+    fn select(&mut self, idx: usize) {
+        self.selected_idx = idx.min(self.values.len().saturating_sub(1));
+        self.refresh_suggestions();
+    }
+
+    /// An argument's value drawn as its control: a toggle, a choice or a
+    /// number with its range.
+    pub fn display_value(&self, idx: usize) -> String {
+        let (Some(argument), Some(value)) = (self.workflow.arguments.get(idx), self.values.get(idx)) else {
+            return String::new();
+        };
+        match argument.arg_type {
+            ArgumentType::Boolean => if value == "true" { "[x]".to_string() } else { "[ ]".to_string() },
+            ArgumentType::Enum => format!("< {} >", value),
+            ArgumentType::Integer => match (argument.min, argument.max) {
+                (Some(min), Some(max)) => format!("{} ({}..{})", value, min, max),
+                (Some(min), None) => format!("{} (>= {})", value, min),
+                (None, Some(max)) => format!("{} (<= {})", value, max),
+                (None, None) => value.clone(),
+            },
+            ArgumentType::Text | ArgumentType::Path => value.clone(),
+        }
+    }
+}
+
+/// A single survey question.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct SurveyPromptState {
+    pub question: MultipleChoiceQuestion,
+    pub selected_idx: usize,
+    /// Whether the app quits once the question is answered or dismissed.
+    pub quit_after: bool,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct AgentManagementState {
+    pub query: String,
+    pub selected_idx: usize,
+    pub conversations: Vec<AgentConversationSummary>,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum PaletteItem {
     Workflow(Workflow),
     Notebook(Notebook),
     Action { name: String, description: String, action: String },
+    /// Opens a pane with a launch profile.
+    Profile { name: String, description: String },
+    /// Checks out a pull request or opens an issue.
+    GitHub(GitHubItem),
+    /// Asks the agent with a prompt from the library.
+    Prompt(Prompt),
+}
+
+impl PaletteItem {
+    /// Identifies the item in pinned favorites and usage history.
+    pub fn key(&self) -> String {
+        match self {
+            PaletteItem::Workflow(workflow) => format!("workflow:{}", workflow.name),
+            PaletteItem::Notebook(notebook) => format!("notebook:{}", notebook.name),
+            PaletteItem::Action { action, .. } => format!("action:{}", action),
+            PaletteItem::Profile { name, .. } => format!("profile:{}", name),
+            PaletteItem::GitHub(item) => format!("github:{}", item.url),
+            PaletteItem::Prompt(prompt) => format!("prompt:{}", prompt.name),
+        }
+    }
+
+    /// The palette categories the item is browsed under: a workflow's
+    /// lowercased tags, or its kind for everything else.
+    pub fn categories(&self) -> Vec<String> {
+        match self {
+            PaletteItem::Workflow(workflow) if !workflow.tags.is_empty() => {
+                workflow.tags.iter().map(|tag| tag.trim().to_lowercase()).collect()
+            }
+            PaletteItem::Workflow(_) => vec!["workflows".to_string()],
+            PaletteItem::Notebook(_) => vec!["notebooks".to_string()],
+            PaletteItem::Action { .. } => vec!["actions".to_string()],
+            PaletteItem::Profile { .. } => vec!["profiles".to_string()],
+            PaletteItem::GitHub(item) if item.kind == GitHubItemKind::PullRequest => vec!["pull requests".to_string()],
+            PaletteItem::GitHub(_) => vec!["issues".to_string()],
+            PaletteItem::Prompt(_) => vec!["prompts".to_string()],
+        }
+    }
 }
 
 pub struct App {
@@ -103,6 +753,9 @@ pub struct App {
     pub active_theme: Theme,
     pub config: Config,
     pub autosuggestion: Option<String>,
+    /// The commands usually run in the directory the active pane just
+    /// entered, offered while the input is empty.
+    pub directory_suggestions: Vec<String>,
     pub vim_state: Option<crate::vim::VimState>,
     pub input_editor: Editor<'static>,
     pub should_quit: bool,
@@ -110,6 +763,140 @@ pub struct App {
     pub undo_stack: Vec<String>,
     pub redo_stack: Vec<String>,
     pub completions_manager: CompletionsManager,
+    pub notifications: Arc<Mutex<Vec<String>>>,
+    pub idle_monitor: IdleMonitor,
+    /// Decides whether a command failing in another pane moves focus there.
+    pub failure_focus: FailureFocus,
+    /// The pane a command last failed in while another pane was focused.
+    last_failure: Option<Uuid>,
+    /// Shell hooks, Lua scripts and plugins subscribed to pane events.
+    pub hooks: Hooks,
+    pub ime_state: ImeState,
+    /// Lets the app spawn new panes; set once the event loop exists.
+    pub event_proxy: Option<EventLoopProxy<AppEvent>>,
+    /// Scroll behaviour, with the system's reduced-motion preference applied.
+    pub scroll_settings: ScrollSettings,
+    /// Capability decisions for plugins and agent tools, shared with the Lua API.
+    pub permissions: Arc<Mutex<PermissionService>>,
+    /// Git status of the directories the panes are in.
+    pub git: GitMonitor,
+    /// Values of the user's own prompt chips, refreshed in the background.
+    pub prompt_chips: PromptChips,
+    /// Answers agent queries; a scripted replay in tests and demos.
+    pub agent: Arc<dyn AgentProvider>,
+    /// Agent summaries of block output in the user's language.
+    pub translator: Translator,
+    /// Tags finished blocks from the config's rules and the AI classifier.
+    pub tagger: BackgroundTagger,
+    /// Exports the activity report on the configured schedule.
+    pub report_scheduler: ReportScheduler,
+    /// Agent-written quiz questions about the tools in the history.
+    pub quiz_generator: QuizGenerator,
+    /// Agent-written commit messages for the staged changes.
+    pub commit_messages: CommitMessageGenerator,
+    /// Man pages and `--help` output, parsed, for explaining commands.
+    pub help_cache: HelpCache,
+    /// Agent explanations of command parts the help pages miss.
+    pub command_explainer: AgentExplainer,
+    /// Scores agent responses by what became of them, per model.
+    pub agent_eval: AgentEvaluator,
+    /// The two models agent tasks alternate between, when comparing them.
+    ab_test: Option<AbTest>,
+    /// The evaluation of the task each agent pane is working on, by pane id.
+    agent_sessions: HashMap<Uuid, String>,
+    /// Counts the tokens of every agent request, by conversation.
+    pub usage: UsageMeter,
+    /// The budget warning last shown: 1 near the monthly budget, 2 past it.
+    budget_warned: u8,
+    /// Lists, pulls and deletes local Ollama models in the background.
+    pub ollama: OllamaManager,
+    /// Agent panes watching shell panes, and the kill switch that silences them.
+    pub pair: PairProgramming,
+    /// The directory whose branches completions currently offer.
+    git_branches_for: Option<PathBuf>,
+    /// Commands known on the remote hosts panes connect to.
+    pub host_corpora: HostCorpora,
+    /// The host whose commands completions currently offer.
+    corpus_for: Option<String>,
+    /// Opt-in feature discovery and satisfaction prompts.
+    pub survey: SurveyScheduler,
+    /// Imported quiz questions and when each is next due.
+    pub quiz_bank: QuestionBank,
+    /// Batch deletes that can still be undone, most recent last.
+    deleted_blocks: Vec<DeletedBlocks>,
+    /// The pane being shared live with collaborators, if any.
+    pub live_share: Option<LiveShare>,
+    /// The block a running doctor check reports into, and its result.
+    doctor: Option<(Uuid, std::sync::mpsc::Receiver<DoctorReport>)>,
+    /// The search overlay's history query, still being matched.
+    pending_search: Option<PendingSearch>,
+    /// Looks selections up with the configured search providers.
+    pub web_search: WebSearch,
+    /// Fetches slow completion lists while the user is idle.
+    pub prefetcher: Prefetcher,
+    /// Prefetched lists not yet handed to completions.
+    prefetched_pending: Vec<PrefetchSource>,
+    /// Keys of the palette items used most recently, newest first.
+    palette_recent: Vec<String>,
+    /// Incrementally parsed documents, for syntax highlighting.
+    pub syntax: SyntaxParserPool,
+    /// Cancelled on shutdown to stop fetches still in flight.
+    background: BackgroundTasks,
+    /// The theme in use when no profile overrides it.
+    base_theme: Theme,
+    /// The profile theme applied for the focused pane, if any.
+    profile_theme: Option<String>,
+    /// Commands re-run on file changes, at most one per pane.
+    pub watches: Vec<CommandWatch>,
+    /// Processes started in panes, and detached commands.
+    pub jobs: JobManager,
+    /// Local services the panes are serving, such as dev servers.
+    pub ports: PortDetector,
+    /// Containers and images on the local Docker daemon.
+    pub docker: DockerMonitor,
+    /// The current Kubernetes context and its resources.
+    pub kube: KubeMonitor,
+    /// The user's open pull requests and assigned issues, for the palette.
+    pub github: GitHubMonitor,
+    /// Sends `http` requests and reports responses for their blocks.
+    pub http: HttpRunner,
+    /// Schemas and queries for the GraphQL explorer.
+    pub graphql: GraphQLExplorer,
+    /// The explorer as it was left, restored when it opens again.
+    graphql_draft: Option<GraphQLExplorerState>,
+    /// WebSocket consoles, by the id of the pane they were opened in.
+    pub ws_consoles: HashMap<Uuid, WebSocketConsole>,
+    /// Rules for `kubectl` commands that need confirming in production.
+    kube_rules: crate::rules::RuleSet,
+    /// A destructive `kubectl` command already warned about, which runs on
+    /// the next Enter.
+    kube_confirmed: Option<String>,
+    /// SFTP hosts, archives and other filesystems, shared with completions.
+    pub mounts: Arc<MountTable>,
+    /// Images pasted into the command line as temp files, shown as chips
+    /// while their paths are still there.
+    pub pasted_images: Vec<PathBuf>,
+    /// Snippets expanded with prefix+Tab or from completions.
+    pub snippets: SnippetLibrary,
+    /// The expanded snippet whose tab stops Tab is moving through.
+    snippet_session: Option<SnippetSession>,
+    /// Short words expanded in command position on Space and Enter.
+    pub abbreviations: AbbreviationSet,
+    /// Tells questions for the agent from commands on Enter.
+    pub nl_detector: NaturalLanguageDetector,
+    /// The last input sent to the agent as a question, which Alt+C runs as
+    /// a command instead.
+    detected_query: Option<String>,
+    /// Snapshots of the session, kept in case the app doesn't shut down cleanly.
+    journal: Journal,
+    /// Crash reports found at startup, offered once no other prompt is up.
+    crash_reports: Vec<PendingReport>,
+    /// Draws FPS, frame time by phase, PTY throughput and memory over the panes.
+    pub show_perf_hud: bool,
+    /// A flamegraph capture in progress.
+    profile_capture: Option<ProfileCapture>,
+    /// Requests accepted by the webhook listener, handled on the next tick.
+    listener_events: UnboundedReceiver<AppEvent>,
 }
 
 impl App {
@@ -125,16 +912,124 @@ impl App {
         let mut font_system = FontSystem::new();
         let metrics = Metrics::new(config.appearance.font_size, config.appearance.font_size * config.appearance.line_height);
         let mut input_editor = Editor::new(Buffer::new(&mut font_system, metrics));
-        
+
+        let idle_monitor = IdleMonitor::new(&config.idle_lock, std::time::Instant::now()).unwrap_or_else(|e| {
+            log::warn!("Idle lock disabled: {}", e);
+            IdleMonitor::new(&crate::config::IdleLockConfig::default(), std::time::Instant::now())
+                .expect("default idle lock rules are valid")
+        });
+
+        let failure_focus = FailureFocus::new(&config.failure_focus).unwrap_or_else(|e| {
+            log::warn!("Failure focus disabled: {}", e);
+            FailureFocus::default()
+        });
+
+        let permissions = Arc::new(Mutex::new(PermissionService::load(&db_conn).unwrap_or_else(|e| {
+            log::warn!("Failed to load permission grants: {}", e);
+            PermissionService::default()
+        })));
+        let hooks = Hooks::new(&config.hooks, permissions.clone()).unwrap_or_else(|e| {
+            log::warn!("Hooks disabled: {}", e);
+            Hooks::default()
+        });
+        let prompt_chips = PromptChips::new(&config.appearance.warpish_prompt);
+        let ollama = OllamaManager::new(&config.ai);
+        let agent = crate::agent::provider(&config.ai);
+        let scroll_settings = ScrollSettings::from_config(&config.scroll);
+        let survey = SurveyScheduler::load(&db_conn, chrono::Utc::now().timestamp());
+        let quiz_bank = QuestionBank::load(&db_conn);
+        let evaluations = crate::db::load_agent_evaluations(&db_conn).unwrap_or_else(|e| {
+            log::warn!("Failed to load agent evaluations: {}", e);
+            Vec::new()
+        });
+        let agent_eval = AgentEvaluator::new().with_survey_stats(survey.stats()).with_evaluations(evaluations);
+        let ab_test = config.ai.ab_test.as_ref().map(|test| AbTest::new(test.model_a.clone(), test.model_b.clone()));
+        let usage = UsageMeter::default();
+        let background = BackgroundTasks::new();
+        let web_search = WebSearch::new(&config.web_search, &config.share.redaction_patterns).with_cancellation(background.token());
+        let prefetcher = Prefetcher::new(&config.editor.completions.prefetch, crate::prefetch::default_cache_dir())
+            .with_cancellation(background.token());
+        let prefetched_pending = prefetcher.available();
+        let snippets = SnippetLibrary::load(&drive_manager);
+        let prefixes = snippets.iter().map(|snippet| (snippet.prefix.clone(), snippet.name.clone())).collect();
+        completions_manager.configure(move |manager| manager.set_snippets(prefixes));
+        let mounts = completions_manager.mounts().clone();
+        let mut nl_detector = NaturalLanguageDetector::new();
+        if config.ai.detect_natural_language {
+            nl_detector = nl_detector.with_default_classifier();
+        }
+        match crate::db::load_nl_corrections(&db_conn) {
+            Ok(corrections) => nl_detector.learn(&corrections),
+            Err(e) => log::warn!("Failed to load input corrections: {}", e),
+        }
+        let palette_recent = crate::db::load_palette_usage(&db_conn, MAX_PALETTE_RECENT).unwrap_or_else(|e| {
+            log::warn!("Failed to load palette usage: {}", e);
+            Vec::new()
+        });
+        // Pick up the command line left unsent at the last shutdown.
+        match crate::db::load_draft(&db_conn, INPUT_DOCUMENT) {
+            Ok(Some(draft)) => {
+                input_editor.buffer_ref_mut().set_text(&mut font_system, &draft, AttrsList::new(Attrs::new()), Shaping::Advanced);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to load the input draft: {}", e),
+        }
+
+        let journal = Journal::new(default_journal_path(), std::time::Duration::from_millis(config.shutdown.journal_interval_ms));
+        let mode = match journal.recover() {
+            Some(recovered) if config.shutdown.restore_after_crash && !recovered.panes.is_empty() => AppMode::RestoreSession(RestoreSessionState { journal: recovered }),
+            _ => AppMode::Normal,
+        };
+        let crash_reports = if config.crash_reports.enabled { crash::pending_reports(&crash::default_crash_dir()) } else { Vec::new() };
+
+        let (listener_sender, listener_events) = tokio::sync::mpsc::unbounded_channel();
+        if config.webhooks.enabled {
+            let (webhooks_config, workflows) = (config.webhooks.clone(), drive_manager.workflows());
+            let sender = listener_sender.clone();
+            run_listener("webhook", background.token(), move || {
+                webhooks::spawn_listener(&webhooks_config, workflows, sender).unwrap_or_else(|e| {
+                    log::error!("Webhooks disabled: {}", e);
+                    None
+                })
+            });
+        }
+        if config.metrics.enabled {
+            let metrics_config = config.metrics.clone();
+            run_listener("metrics", background.token(), move || crate::metrics::spawn_endpoint(&metrics_config));
+        }
+        if config.lpc.enabled {
+            let lpc_config = config.lpc.clone();
+            run_listener("LPC", background.token(), move || {
+                crate::lpc::server::spawn_server(&lpc_config, listener_sender).unwrap_or_else(|e| {
+                    log::error!("LPC disabled: {}", e);
+                    None
+                })
+            });
+        }
+
+        let mut panes = panes;
+        let encodings = configured_encodings(&config.encoding);
+        for pane in &panes {
+            pane.set_encodings(encodings);
+        }
+        if let Some(capture) = crate::structured::StructuredCapture::from_config(&config.structured_output) {
+            let capture = Arc::new(capture);
+            for pane in &mut panes {
+                pane.structured_capture = Some(capture.clone());
+            }
+        }
+
         Self {
             panes,
             active_pane_idx: 0,
-            mode: AppMode::Normal,
+            mode,
             drive_manager,
             theme_manager,
+            base_theme: theme.clone(),
             active_theme: theme,
             config,
             autosuggestion: None,
+            directory_suggestions: Vec::new(),
             vim_state: None,
             input_editor,
             should_quit: false,
@@ -142,6 +1037,75 @@ impl App {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             completions_manager,
+            notifications: Arc::new(Mutex::new(Vec::new())),
+            idle_monitor,
+            failure_focus,
+            last_failure: None,
+            hooks,
+            ime_state: ImeState::default(),
+            event_proxy: None,
+            scroll_settings,
+            permissions,
+            git: GitMonitor::new(),
+            prompt_chips,
+            translator: Translator::new(usage.wrap(agent.clone(), "translation")),
+            tagger: BackgroundTagger::new(AutoTagger::from_config(&config.tagging, &config.ai)),
+            report_scheduler: ReportScheduler::new(
+                config.reports.clone(),
+                crate::stats::last_export(&crate::stats::report_dir(&config.reports)),
+            ),
+            quiz_generator: QuizGenerator::new(usage.wrap(agent.clone(), "quiz generation")),
+            commit_messages: CommitMessageGenerator::new(usage.wrap(agent.clone(), "commit messages")),
+            help_cache: HelpCache::new(),
+            command_explainer: AgentExplainer::new(usage.wrap(agent.clone(), "command explanations")),
+            agent_eval,
+            ab_test,
+            agent_sessions: HashMap::new(),
+            usage,
+            budget_warned: 0,
+            ollama,
+            pair: PairProgramming::new(usage.wrap(agent.clone(), "pair programming")),
+            agent,
+            git_branches_for: None,
+            host_corpora: HostCorpora::default(),
+            corpus_for: None,
+            survey,
+            quiz_bank,
+            deleted_blocks: Vec::new(),
+            live_share: None,
+            doctor: None,
+            pending_search: None,
+            web_search,
+            prefetcher,
+            prefetched_pending,
+            palette_recent,
+            syntax: SyntaxParserPool::new(),
+            background,
+            profile_theme: None,
+            watches: Vec::new(),
+            jobs: JobManager::new(),
+            ports: PortDetector::new(),
+            docker: DockerMonitor::new(),
+            kube: KubeMonitor::new(),
+            github: GitHubMonitor::new(),
+            http: HttpRunner::new(),
+            graphql: GraphQLExplorer::new(),
+            graphql_draft: None,
+            ws_consoles: HashMap::new(),
+            kube_rules: crate::k8s::destructive_rules(),
+            kube_confirmed: None,
+            mounts,
+            pasted_images: Vec::new(),
+            snippets,
+            snippet_session: None,
+            abbreviations: AbbreviationSet::load(),
+            nl_detector,
+            detected_query: None,
+            journal,
+            crash_reports,
+            show_perf_hud: config.perf.show_hud,
+            profile_capture: None,
+            listener_events,
         }
     }
 
@@ -173,9 +1137,75 @@ impl App {
         self.autosuggestion = None;
     }
 
+    /// Takes the autosuggestion, or its next word, into the input when
+    /// `motion` would move the cursor past the end of the input into it.
+    fn accept_autosuggestion(&mut self, motion: Motion) -> bool {
+        let (text, offset) = self.input_text_and_offset();
+        if offset < text.len() {
+            return false;
+        }
+        let Some(suggestion) = self.autosuggestion.take() else {
+            return false;
+        };
+        let Some(accepted) = accept_autosuggestion(&suggestion, motion) else {
+            self.autosuggestion = Some(suggestion);
+            return false;
+        };
+        self.input_editor.insert_string(accepted, None);
+        let rest = &suggestion[accepted.len()..];
+        self.autosuggestion = (!rest.is_empty()).then(|| rest.to_string());
+        true
+    }
+
     pub fn handle_event(&mut self, event: AppEvent) -> Result<(), AppError> {
         match event {
-            AppEvent::Key(key_event) => self.handle_key_event(key_event)?,
+            AppEvent::Key(key_event) => {
+                self.handle_key_event(key_event)?;
+                // Highlight as the user types rather than on the next tick.
+                self.sync_input_highlights();
+            }
+            AppEvent::Tick => {
+                self.check_idle_lock();
+                self.sync_dir_histories();
+                self.capture_timelines();
+                self.sync_ssh_sessions();
+                self.sync_pane_events();
+                self.sync_held_agent_commands();
+                self.sync_git_status();
+                self.sync_prefetch();
+                self.sync_input_highlights();
+                self.sync_profile_theme();
+                self.sync_prompt_chips();
+                self.sync_translations();
+                self.sync_block_tags();
+                self.sync_ollama();
+                self.sync_pairs();
+                self.sync_live_share();
+                self.sync_doctor();
+                self.sync_search();
+                self.sync_web_search();
+                self.sync_watches();
+                self.sync_jobs();
+                self.sync_ports();
+                self.sync_docker();
+                self.sync_kube();
+                self.sync_github();
+                self.sync_http();
+                self.sync_graphql();
+                self.sync_ws_consoles();
+                self.show_next_permission_prompt();
+                self.show_next_survey();
+                self.sync_quiz();
+                self.sync_quiz_generation();
+                self.sync_commit_message();
+                self.sync_command_explanation();
+                self.sync_usage();
+                self.sync_journal();
+                self.show_crash_reports();
+                self.sync_profile_capture();
+                self.sync_listener_events();
+                self.sync_report_schedule();
+            }
             AppEvent::Pty(data) => {
                 // Handle PTY data for the active pane
                 if let Some(pane) = self.panes.get_mut(self.active_pane_idx) {
@@ -197,83 +1227,5120 @@ impl App {
                     pane.pty_writer.write_all(suggestion.as_bytes())?;
                 }
             }
+            AppEvent::Suggestions(results) => self.completions_manager.receive(results),
             AppEvent::Error(e) => {
                 // Log error
                 log::error!("Application error: {}", e);
             }
+            AppEvent::Webhook(webhook) => self.handle_webhook(webhook)?,
+            AppEvent::Lpc(call) => {
+                let result = self.run_lpc_method(call.method);
+                call.reply.send(result).ok();
+            }
+            AppEvent::AgentCompleted { pane_id, prompt, response } => self.complete_agent_query(pane_id, prompt, response),
             _ => {}
         }
         Ok(())
     }
 
-    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<(), AppError> {
-        match self.mode {
-            AppMode::Normal => self.handle_normal_mode_keys(key_event)?,
-            AppMode::HistorySearch(_) => self.handle_history_mode_keys(key_event, &mut self.db_conn)?,
-            AppMode::AiPrompt => self.handle_ai_prompt_mode_keys(key_event)?,
-            _ => {}
+    /// Queues a message for the notification area.
+    pub fn notify(&self, message: String) {
+        self.notifications.lock().unwrap().push(message);
+    }
+
+    /// Runs a webhook-triggered workflow, or stages it in the input editor when
+    /// a warning rule requires the user to confirm it first.
+    fn handle_webhook(&mut self, webhook: WebhookEvent) -> Result<(), AppError> {
+        match webhook {
+            WebhookEvent::RunWorkflow { name, command, needs_confirmation } => {
+                if needs_confirmation {
+                    // Never over another prompt, nor over what the user is typing
+                    if self.mode == AppMode::Normal {
+                        self.mode = AppMode::ConfirmWebhook(ConfirmWebhookState { name, command });
+                    } else {
+                        self.notify(format!("Webhook workflow '{}' needs confirmation and was not run", name));
+                    }
+                } else if let Some(pane) = self.panes.get_mut(self.active_pane_idx) {
+                    pane.run_command(&command)?;
+                    self.notify(format!("Webhook ran workflow '{}'", name));
+                }
+            }
+            WebhookEvent::Notify { title, message } => {
+                self.notify(format!("{}: {}", title, message));
+            }
         }
         Ok(())
     }
 
-    fn handle_normal_mode_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+    fn lpc_pane_idx(&self, pane_id: Option<Uuid>) -> Result<usize, RpcError> {
+        match pane_id {
+            None => Ok(self.active_pane_idx),
+            Some(id) => self
+                .panes
+                .iter()
+                .position(|pane| pane.id == id)
+                .ok_or_else(|| RpcError::new(lpc::APPLICATION_ERROR, format!("No pane {}", id))),
+        }
+    }
+
+    /// Like `lpc_pane_idx`, but refuses panes locked after inactivity, whose
+    /// contents stay hidden until the user unlocks them.
+    fn lpc_unlocked_pane_idx(&self, pane_id: Option<Uuid>) -> Result<usize, RpcError> {
+        let pane_idx = self.lpc_pane_idx(pane_id)?;
+        let pane = &self.panes[pane_idx];
+        if self.idle_monitor.is_locked(pane.id) {
+            return Err(RpcError::new(lpc::APPLICATION_ERROR, format!("Pane {} is locked", pane.id)));
+        }
+        Ok(pane_idx)
+    }
+
+    /// Carries out a call from the LPC server.
+    fn run_lpc_method(&mut self, method: Method) -> Result<serde_json::Value, RpcError> {
+        let failed = |e: std::io::Error| RpcError::new(lpc::APPLICATION_ERROR, e.to_string());
+        match method {
+            Method::OpenTab { profile, cwd, command } => {
+                let count = self.panes.len();
+                self.open_pane(profile.as_deref());
+                if self.panes.len() == count {
+                    return Err(RpcError::new(lpc::APPLICATION_ERROR, "Could not open a pane"));
+                }
+                let pane = &mut self.panes[self.active_pane_idx];
+                if let Some(cwd) = cwd {
+                    pane.run_command(&format!("cd {}", shellwords::escape(&cwd.to_string_lossy()))).map_err(failed)?;
+                }
+                if let Some(command) = command {
+                    pane.run_command(&command).map_err(failed)?;
+                }
+                Ok(serde_json::json!({ "pane_id": pane.id }))
+            }
+            Method::RunCommand { command, pane_id } => {
+                let pane_idx = self.lpc_unlocked_pane_idx(pane_id)?;
+                let pane = &mut self.panes[pane_idx];
+                pane.run_command(&command).map_err(failed)?;
+                Ok(serde_json::json!({ "pane_id": pane.id }))
+            }
+            Method::GetBlocks { pane_id, limit } => {
+                let history = &self.panes[self.lpc_unlocked_pane_idx(pane_id)?].history;
+                let skip = limit.map_or(0, |limit| history.len().saturating_sub(limit));
+                let blocks: Vec<BlockInfo> = history
+                    .iter()
+                    .skip(skip)
+                    .map(|block| BlockInfo { id: block.id, command: block.command.clone(), output: block.output.clone() })
+                    .collect();
+                Ok(serde_json::to_value(blocks).unwrap_or_default())
+            }
+            Method::FocusPane { pane_id } => {
+                self.active_pane_idx = self.lpc_pane_idx(Some(pane_id))?;
+                Ok(serde_json::Value::Null)
+            }
+            Method::ListPanes => {
+                let panes: Vec<PaneInfo> = self
+                    .panes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pane)| !self.idle_monitor.is_locked(pane.id))
+                    .map(|(idx, pane)| PaneInfo { id: pane.id, cwd: pane.local_cwd(), focused: idx == self.active_pane_idx })
+                    .collect();
+                Ok(serde_json::to_value(panes).unwrap_or_default())
+            }
+        }
+    }
+
+    /// Locks sensitive panes once the user has been idle long enough.
+    fn check_idle_lock(&mut self) {
+        let activity: Vec<PaneActivity> = self
+            .panes
+            .iter()
+            .map(|pane| PaneActivity {
+                id: pane.id,
+                command: &pane.active_command,
+                privacy_zone: pane.privacy_zone,
+            })
+            .collect();
+        let newly_locked = self.idle_monitor.tick(std::time::Instant::now(), &activity);
+        if !newly_locked.is_empty() {
+            self.notify(format!("Locked {} sensitive pane(s) after inactivity", newly_locked.len()));
+        }
+    }
+
+    /// Marks or unmarks the active pane as a privacy zone.
+    pub fn toggle_privacy_zone(&mut self) {
+        if let Some(pane) = self.panes.get_mut(self.active_pane_idx) {
+            pane.privacy_zone = !pane.privacy_zone;
+            let state = if pane.privacy_zone { "marked" } else { "unmarked" };
+            self.notify(format!("Pane {} as a privacy zone", state));
+        }
+    }
+
+    /// Stops or resumes jumping to the active pane when its commands fail.
+    pub fn toggle_failure_focus_mute(&mut self) {
+        if let Some(pane) = self.panes.get_mut(self.active_pane_idx) {
+            pane.mute_failure_focus = !pane.mute_failure_focus;
+            let state = if pane.mute_failure_focus { "no longer take" } else { "take" };
+            self.notify(format!("Failed commands in this pane will {} focus", state));
+        }
+    }
+
+    fn input_is_empty(&self) -> bool {
+        self.input_editor.buffer_ref().lines.iter().all(|line| line.text().is_empty())
+    }
+
+    /// Picks up directory changes reported by each pane's shell.
+    fn sync_dir_histories(&mut self) {
+        let mut moves = Vec::new();
+        let mut active_moved = false;
+        for (idx, pane) in self.panes.iter_mut().enumerate() {
+            match pane.sync_dir_history() {
+                Ok(Some(dir)) => {
+                    active_moved |= idx == self.active_pane_idx;
+                    moves.push(HookEvent::CwdChange { pane_id: pane.id, dir });
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Failed to change directory: {}", e),
+            }
+        }
+        if active_moved {
+            self.refresh_directory_suggestions();
+        }
+        for event in moves {
+            self.emit_hook(&event);
+        }
+    }
+
+    /// Offers the commands run most often in the active pane's directory.
+    fn refresh_directory_suggestions(&mut self) {
+        self.directory_suggestions.clear();
+        if !self.config.editor.directory_suggestions {
+            return;
+        }
+        let Some(dir) = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd) else {
+            return;
+        };
+        match crate::db::top_directory_commands(&self.db_conn, &dir.to_string_lossy(), MAX_DIRECTORY_SUGGESTIONS) {
+            Ok(commands) => self.directory_suggestions = commands,
+            Err(e) => log::warn!("Failed to load the commands run in {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Types the `index`th command usually run in this directory.
+    fn insert_directory_suggestion(&mut self, index: usize) {
+        if let Some(command) = self.directory_suggestions.get(index).cloned() {
+            self.input_editor.insert_string(command, None);
+        }
+    }
+
+    fn emit_hook(&mut self, event: &HookEvent) {
+        for notice in self.hooks.emit(event) {
+            self.notify(notice);
+        }
+    }
+
+    /// Tells hooks about commands starting and finishing and about watched
+    /// output in every pane, then follows failed commands.
+    fn sync_pane_events(&mut self) {
+        let mut events = Vec::new();
+        let mut finished = Vec::new();
+        let mut agent_finished = Vec::new();
+        let mut agent_steps = Vec::new();
+        let now = chrono::Utc::now().timestamp();
+        for (idx, pane) in self.panes.iter_mut().enumerate() {
+            let directory = pane.local_cwd();
+            for command in pane.take_started_commands() {
+                if let Some(directory) = &directory {
+                    if let Err(e) = crate::db::record_directory_command(&self.db_conn, &directory.to_string_lossy(), &command, now) {
+                        log::warn!("Failed to record the command's directory: {}", e);
+                    }
+                }
+                // The suggestions are for arriving in a directory, not for every prompt there.
+                if idx == self.active_pane_idx {
+                    self.directory_suggestions.clear();
+                }
+                events.push(HookEvent::CommandStart { pane_id: pane.id, command });
+            }
+            let lines = pane.take_output_lines();
+            if self.hooks.watches_output() {
+                events.extend(self.hooks.match_output(pane.id, &lines));
+            }
+            if let Some(exit) = pane.take_finished_command() {
+                events.push(HookEvent::CommandFinish { pane_id: pane.id, command: pane.active_command.clone(), exit_code: exit.status });
+                finished.push((idx, exit.status));
+                let agent_command = pane.agent_loop.as_ref().and_then(|agent_loop| agent_loop.running_command());
+                if agent_command == Some(pane.active_command.as_str()) {
+                    let outcome = if exit.status == 0 { Outcome::CommandSucceeded } else { Outcome::CommandFailed };
+                    agent_finished.push((pane.id, outcome));
+                }
+                // The finished command's output becomes its block, which the exit is recorded on.
+                if let Some(step) = pane.new_block() {
+                    agent_steps.push((idx, step));
+                }
+                if let Some(block) = pane.history.last() {
+                    self.tagger.request(block);
+                }
+                pane.record_exit(exit.status, std::time::Instant::now());
+            }
+        }
+        for (pane_id, outcome) in agent_finished {
+            self.record_agent_outcome(pane_id, outcome);
+        }
+        for (idx, step) in agent_steps {
+            if let Err(e) = self.apply_agent_step(idx, step) {
+                log::warn!("The agent could not take its next step: {}", e);
+            }
+        }
+        for event in &events {
+            self.emit_hook(event);
+        }
+        self.follow_failures(finished);
+    }
+
+    fn capture_timelines(&mut self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        for pane in &mut self.panes {
+            pane.capture_timeline(now);
+        }
+    }
+
+    /// Looks for `ssh` in each pane's foreground, and turns off local file
+    /// completion while the active pane is remote.
+    fn sync_ssh_sessions(&mut self) {
+        if self.config.ssh.detect_foreground {
+            let now = std::time::Instant::now();
+            for pane in &mut self.panes {
+                pane.sync_ssh_session(now);
+            }
+        }
+        let active = self.panes.get(self.active_pane_idx);
+        let remote = active.is_some_and(|pane| pane.ssh.session().is_some());
+        let distro = active.and_then(|pane| pane.target.wsl_distro()).map(String::from);
+        let file_completion = !remote || self.config.ssh.remote_file_completion;
+        self.completions_manager.configure(move |manager| {
+            manager.set_file_completion(file_completion);
+            manager.set_wsl_distro(distro);
+        });
+        self.sync_host_corpora();
+    }
+
+    /// Focuses, or offers to jump to, panes where a command just failed
+    /// while another pane had focus. `finished` holds pane indices and exit
+    /// statuses.
+    fn follow_failures(&mut self, finished: Vec<(usize, i32)>) {
+        let mut failures = Vec::new();
+        for (idx, status) in finished {
+            let Some(pane) = self.panes.get(idx) else {
+                continue;
+            };
+            if idx == self.active_pane_idx || pane.mute_failure_focus {
+                continue;
+            }
+            let directory = pane.local_cwd();
+            let command = FinishedCommand { command: &pane.active_command, directory: directory.as_deref(), status };
+            let action = self.failure_focus.decide(&command);
+            if action != FailureFocusAction::Ignore {
+                failures.push((idx, pane.id, pane.active_command.clone(), status, action));
+            }
+        }
+        for (idx, pane_id, command, status, action) in failures {
+            self.last_failure = Some(pane_id);
+            // Never pull focus out from under an open overlay.
+            if self.mode != AppMode::Normal {
+                self.notify(format!("`{}` failed with status {} in pane {} (Alt+J to jump)", command, status, idx + 1));
+                continue;
+            }
+            match action {
+                FailureFocusAction::Switch => {
+                    self.active_pane_idx = idx;
+                    self.notify(format!("`{}` failed with status {}", command, status));
+                }
+                _ => self.mode = AppMode::FailureJump(FailureJumpState { pane_id, command, status }),
+            }
+        }
+    }
+
+    /// Focuses the pane a command last failed in, if it is still open.
+    pub fn jump_to_failure(&mut self) {
+        let Some(pane_id) = self.last_failure else {
+            self.notify("No command has failed in another pane".to_string());
+            return;
+        };
+        match self.panes.iter().position(|pane| pane.id == pane_id) {
+            Some(idx) => self.active_pane_idx = idx,
+            None => {
+                self.last_failure = None;
+                self.notify("The pane with the failed command was closed".to_string());
+            }
+        }
+    }
+
+    fn handle_failure_jump_keys(&mut self, key: KeyEvent) {
+        let AppMode::FailureJump(state) = &self.mode else {
+            return;
+        };
+        let pane_id = state.pane_id;
         match key.code {
-            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => self.should_quit = true,
-            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => self.enter_history_mode(),
-            KeyCode::Char('g') if key.modifiers == KeyModifiers::CONTROL => self.enter_ai_prompt_mode(),
+            KeyCode::Enter => {
+                self.mode = AppMode::Normal;
+                self.last_failure = Some(pane_id);
+                self.jump_to_failure();
+            }
+            KeyCode::Char('m') => {
+                self.mode = AppMode::Normal;
+                if let Some(pane) = self.panes.iter_mut().find(|pane| pane.id == pane_id) {
+                    pane.mute_failure_focus = true;
+                    self.notify("Failed commands in that pane will no longer take focus".to_string());
+                }
+            }
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+    }
+
+    /// Harvests the commands of the host the active pane is connected to,
+    /// asking before its history is read, and completes from them.
+    fn sync_host_corpora(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        if self.host_corpora.take_finished(&self.db_conn).iter().any(|host| Some(host) == self.corpus_for.as_ref()) {
+            self.corpus_for = None;
+        }
+        let target = self.panes.get(self.active_pane_idx).and_then(|pane| pane.ssh.target().cloned());
+        if let Some(target) = &target {
+            if self.config.ssh.remote_command_completion && self.host_corpora.needs_harvest(&self.db_conn, &target.host, now) {
+                let status = self.permissions.lock().unwrap().request(PermissionRequest {
+                    requester: Requester::SshCompletions(target.host.clone()),
+                    capability: Capability::ShellHistory,
+                    reason: "Suggest commands you have run on this host".to_string(),
+                });
+                if status != PermissionStatus::Pending {
+                    self.host_corpora.spawn_harvest(target.clone(), status == PermissionStatus::Granted, now);
+                }
+            }
+        }
+        let host = target.map(|target| target.host);
+        if host == self.corpus_for {
+            return;
+        }
+        let corpus = host.as_deref().and_then(|host| self.host_corpora.get(&self.db_conn, host).cloned());
+        self.completions_manager.configure(move |manager| manager.set_remote_corpus(corpus));
+        self.corpus_for = host;
+    }
+
+    /// Keeps the active pane's git status fresh and gives its branches to
+    /// completions.
+    fn sync_git_status(&mut self) {
+        let cwd = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd);
+        if let Some(cwd) = &cwd {
+            self.git.request(cwd, std::time::Instant::now());
+        }
+        if self.git.poll() {
+            self.git_branches_for = None;
+        }
+        if cwd == self.git_branches_for {
+            return;
+        }
+        let branches = cwd.as_deref().and_then(|cwd| self.git.repo(cwd)).map(|repo| repo.branches.clone()).unwrap_or_default();
+        self.completions_manager.configure(move |manager| manager.set_git_branches(branches));
+        self.git_branches_for = cwd;
+    }
+
+    /// Fetches stale completion lists while the user is idle and gives new
+    /// ones to completions.
+    fn sync_prefetch(&mut self) {
+        let now = std::time::Instant::now();
+        let unix_now = chrono::Utc::now().timestamp();
+        self.prefetcher.tick(self.idle_monitor.idle_for(now), now, unix_now);
+        self.prefetched_pending.extend(self.prefetcher.poll(unix_now));
+        if self.prefetched_pending.is_empty() {
+            return;
+        }
+        for source in self.prefetched_pending.drain(..) {
+            if let Some(names) = self.prefetcher.list(source) {
+                let names = names.to_vec();
+                self.completions_manager.configure(move |manager| manager.set_packages(source, names));
+            }
+        }
+    }
+
+    /// Re-parses the command line with the grammar of the pane's shell, which
+    /// only rebuilds the edited part of its tree.
+    fn sync_input_highlights(&mut self) {
+        let source = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        if let Err(e) = self.syntax.update(INPUT_DOCUMENT, Grammar::for_shell(&self.shell()), &source) {
+            log::debug!("Failed to highlight the input: {}", e);
+        }
+    }
+
+    /// Breaks the command line down part by part, reading the help pages of
+    /// its commands in the background.
+    pub fn explain_command(&mut self) {
+        self.sync_input_highlights();
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        let parts = self.syntax.command_parts(INPUT_DOCUMENT);
+        if command.trim().is_empty() || parts.is_empty() {
+            self.notify("Type a command to explain".to_string());
+            return;
+        }
+        for key in crate::explain::help_keys(&command, &parts) {
+            self.help_cache.request(&self.db_conn, &key);
+        }
+        let agent = HashMap::new();
+        let explained = crate::explain::explain(&command, &parts, &self.help_cache, &agent);
+        self.mode = AppMode::ExplainCommand(ExplainCommandState { command, parts, explained, agent, asking_agent: false });
+    }
+
+    /// Fills in help pages and agent explanations as they arrive. A
+    /// subcommand's page is requested once its command's page shows it is
+    /// one.
+    fn sync_command_explanation(&mut self) {
+        let mut changed = self.help_cache.poll(&self.db_conn);
+        for (command, explanations) in self.command_explainer.poll() {
+            if let AppMode::ExplainCommand(state) = &mut self.mode {
+                if state.command == command {
+                    state.agent.extend(explanations);
+                    state.asking_agent = false;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return;
+        }
+        if let AppMode::ExplainCommand(state) = &mut self.mode {
+            for key in crate::explain::help_keys(&state.command, &state.parts) {
+                self.help_cache.request(&self.db_conn, &key);
+            }
+            state.explained = crate::explain::explain(&state.command, &state.parts, &self.help_cache, &state.agent);
+        }
+    }
+
+    fn handle_explain_command_keys(&mut self, key: KeyEvent) {
+        let AppMode::ExplainCommand(state) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => self.mode = AppMode::Normal,
+            KeyCode::Char('a') if !state.asking_agent => {
+                let mut unknown: Vec<String> = Vec::new();
+                for part in state.explained.iter().filter(|part| part.description.is_none() && !part.pending) {
+                    if !unknown.contains(&part.text) {
+                        unknown.push(part.text.clone());
+                    }
+                }
+                if unknown.is_empty() {
+                    self.notify("Every part is already explained".to_string());
+                    return;
+                }
+                state.asking_agent = true;
+                self.command_explainer.ask(&state.command, unknown, self.config.ai.base_model.clone());
+            }
+            _ => {}
+        }
+    }
+
+    pub fn open_notebook(&mut self, notebook: Notebook) {
+        let tasks = task_items(&notebook.content);
+        self.mode = AppMode::Notebook(NotebookState { notebook, tasks, selected_idx: 0 });
+    }
+
+    fn handle_notebook_keys(&mut self, key: KeyEvent) {
+        let AppMode::Notebook(state) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.mode = AppMode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                if state.selected_idx + 1 < state.tasks.len() {
+                    state.selected_idx += 1;
+                }
+            }
+            // Ticks the task off, or back on, and saves the notebook
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                let Some(task) = state.tasks.get(state.selected_idx) else {
+                    return;
+                };
+                let Some(content) = toggle_task(&state.notebook.content, task.line) else {
+                    return;
+                };
+                let notebook = Notebook { name: state.notebook.name.clone(), content };
+                match self.drive_manager.update_notebook(&notebook) {
+                    Ok(_) => {
+                        state.tasks = task_items(&notebook.content);
+                        state.notebook = notebook;
+                    }
+                    Err(e) => self.notify(format!("Could not save '{}': {}", notebook.name, e)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the filled-in refactor on its file, relative to the active
+    /// pane's directory.
+    fn run_refactor(&self, state: &RefactorState) -> Result<DiffPatch, SyntaxError> {
+        let cwd = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd).unwrap_or_default();
+        let mut tree = syntax_tree::load(&cwd.join(state.values[0].trim()))?;
+        let (target, name) = (state.values[1].trim(), state.values[2].trim());
+        match state.kind {
+            RefactorKind::RenameSymbol => tree.transform(&mut RenameSymbol::new(target, name)),
+            RefactorKind::ExtractFunction => {
+                let (first, last) = target.split_once('-').unwrap_or((target, target));
+                let (Ok(first), Ok(last)) = (first.trim().parse::<usize>(), last.trim().parse::<usize>()) else {
+                    return Err(SyntaxError::TransformError(format!("`{}` is not a line range", target)));
+                };
+                let mut extract = ExtractFunction::lines(&tree.source, first, last.max(first), name);
+                tree.transform(&mut extract)
+            }
+        }
+    }
+
+    fn handle_refactor_keys(&mut self, key: KeyEvent) {
+        let AppMode::Refactor(state) = &mut self.mode else {
+            return;
+        };
+        let idx = state.selected_idx;
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up | KeyCode::BackTab => state.selected_idx = idx.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => state.selected_idx = (idx + 1).min(state.values.len() - 1),
+            KeyCode::Char(c) => state.values[idx].push(c),
+            KeyCode::Backspace => {
+                state.values[idx].pop();
+            }
+            KeyCode::Enter => {
+                if let Some(empty) = state.values.iter().position(|value| value.trim().is_empty()) {
+                    state.selected_idx = empty;
+                    state.error = Some(format!("{} is needed", state.kind.labels()[empty]));
+                    return;
+                }
+                let state = state.clone();
+                match self.run_refactor(&state) {
+                    Ok(patch) => {
+                        self.mode = AppMode::DiffReview(DiffReviewState {
+                            explanation: patch.description.clone(),
+                            files: vec![patch],
+                            current_file_idx: 0,
+                            current_hunk_idx: 0,
+                            refactor: Some(RefactorState { error: None, ..state }),
+                        });
+                    }
+                    Err(e) => {
+                        if let AppMode::Refactor(state) = &mut self.mode {
+                            state.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_diff_review_keys(&mut self, key: KeyEvent) {
+        let AppMode::DiffReview(state) = &mut self.mode else {
+            return;
+        };
+        let hunks = state.files.get(state.current_file_idx).map_or(0, |file| file.hunks.len());
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.current_hunk_idx = state.current_hunk_idx.saturating_sub(1),
+            KeyCode::Down => state.current_hunk_idx = (state.current_hunk_idx + 1).min(hunks.saturating_sub(1)),
+            KeyCode::Left => {
+                state.current_file_idx = state.current_file_idx.saturating_sub(1);
+                state.current_hunk_idx = 0;
+            }
+            KeyCode::Right => {
+                state.current_file_idx = (state.current_file_idx + 1).min(state.files.len().saturating_sub(1));
+                state.current_hunk_idx = 0;
+            }
+            KeyCode::Char('r') | KeyCode::Char('R') => {
+                if let Some(refactor) = state.refactor.take() {
+                    self.mode = AppMode::Refactor(refactor);
+                }
+            }
+            KeyCode::Enter => {
+                let files = state.files.clone();
+                self.mode = AppMode::Normal;
+                let mut written = 0;
+                for patch in &files {
+                    match patch.apply() {
+                        Ok(()) => written += 1,
+                        Err(e) => self.notify(e.to_string()),
+                    }
+                }
+                if written > 0 {
+                    self.notify(format!("Applied changes to {} file{}", written, if written == 1 { "" } else { "s" }));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Refreshes the custom prompt chips for the active pane's directory.
+    fn sync_prompt_chips(&mut self) {
+        if self.config.appearance.prompt_mode != PromptMode::Warpish {
+            return;
+        }
+        let cwd = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd);
+        self.prompt_chips.refresh(cwd.as_deref(), std::time::Instant::now());
+        self.prompt_chips.poll();
+    }
+
+    pub fn open_ollama_panel(&mut self) {
+        self.mode = AppMode::Ollama(OllamaPanelState { models: None, selected_idx: 0, pull_input: None });
+        self.ollama.refresh();
+    }
+
+    /// Applies finished Ollama requests: listings go to the panel and pull
+    /// progress to the pull's block.
+    fn sync_ollama(&mut self) {
+        for event in self.ollama.poll() {
+            match event {
+                OllamaEvent::Models(models) => {
+                    if let AppMode::Ollama(state) = &mut self.mode {
+                        let count = models.as_ref().map_or(0, Vec::len);
+                        state.selected_idx = state.selected_idx.min(count.saturating_sub(1));
+                        state.models = Some(models);
+                    }
+                }
+                OllamaEvent::PullProgress { block_id, progress } => {
+                    let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == block_id);
+                    if let Some(block) = block {
+                        record_progress(&mut block.output, &progress);
+                    }
+                }
+                OllamaEvent::PullFinished { block_id, model, result } => {
+                    let message = match result {
+                        Ok(()) => format!("Pulled {}", model),
+                        Err(e) => format!("Pulling {} failed: {}", model, e),
+                    };
+                    let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == block_id);
+                    if let Some(block) = block {
+                        block.output.push_str(&format!("\n{}", message));
+                    }
+                    self.notify(message);
+                    if matches!(self.mode, AppMode::Ollama(_)) {
+                        self.ollama.refresh();
+                    }
+                }
+                OllamaEvent::Deleted { model, result } => {
+                    match result {
+                        Ok(()) => self.notify(format!("Removed {}", model)),
+                        Err(e) => self.notify(format!("Removing {} failed: {}", model, e)),
+                    }
+                    if matches!(self.mode, AppMode::Ollama(_)) {
+                        self.ollama.refresh();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pulls `model` into a new block of the active pane that shows its progress.
+    fn pull_ollama_model(&mut self, model: String) {
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let block = Block::new(format!("ollama pull {}", model), String::new());
+        self.ollama.pull(block.id, model);
+        pane.history.push(block);
+    }
+
+    /// Assigns `model` to `role` and saves the choice to the user config file.
+    fn assign_model_role(&mut self, role: ModelRole, model: String) {
+        self.config.ai.model_roles.set(role, model.clone());
+        let key = format!("ai.model_roles.{}", role.name());
+        let saved = crate::config::layers::user_config_file()
+            .ok_or_else(|| AppError::Config("No config directory".to_string()))
+            .and_then(|path| crate::config::layers::write_value(&path, &key, toml::Value::String(model.clone())));
+        match saved {
+            Ok(()) => self.notify(format!("Using {} for {}", model, role.name())),
+            Err(e) => self.notify(format!("Using {} for {} this session only: {}", model, role.name(), e)),
+        }
+    }
+
+    fn handle_ollama_keys(&mut self, key: KeyEvent) {
+        let AppMode::Ollama(state) = &mut self.mode else {
+            return;
+        };
+        if let Some(name) = &mut state.pull_input {
+            match key.code {
+                KeyCode::Esc => state.pull_input = None,
+                KeyCode::Backspace => {
+                    name.pop();
+                }
+                KeyCode::Char(c) if !c.is_whitespace() => name.push(c),
+                KeyCode::Enter if !name.is_empty() => {
+                    let model = name.clone();
+                    state.pull_input = None;
+                    self.pull_ollama_model(model);
+                }
+                _ => {}
+            }
+            return;
+        }
+        let models = match &state.models {
+            Some(Ok(models)) => models.as_slice(),
+            _ => &[],
+        };
+        let selected = models.get(state.selected_idx).map(|model| model.name.clone());
+        let role = match key.code {
+            KeyCode::Char('c') => Some(ModelRole::Completions),
+            KeyCode::Char('s') => Some(ModelRole::Summarization),
+            KeyCode::Char('a') => Some(ModelRole::Agent),
+            _ => None,
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(models.len().saturating_sub(1)),
+            KeyCode::Char('p') => state.pull_input = Some(String::new()),
+            KeyCode::Char('r') => self.ollama.refresh(),
+            KeyCode::Char('d') => {
+                if let Some(model) = selected {
+                    self.ollama.delete(model);
+                }
+            }
             _ => {
-                self.handle_input(key, &mut Clipboard::new().unwrap());
+                if let (Some(role), Some(model)) = (role, selected) {
+                    self.assign_model_role(role, model);
+                }
             }
         }
+    }
+
+    pub fn open_encoding_picker(&mut self) {
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        let current = pane.encodings().output;
+        let selected_idx = PaneEncoding::ALL.iter().position(|encoding| *encoding == current).unwrap_or(0);
+        self.mode = AppMode::EncodingPicker(EncodingPickerState { pane_idx: self.active_pane_idx, selected_idx });
+    }
+
+    /// Enter sets both directions to the selected encoding; `i` and `o` set
+    /// only the input or the output.
+    fn handle_encoding_picker_keys(&mut self, key: KeyEvent) {
+        let AppMode::EncodingPicker(state) = &mut self.mode else {
+            return;
+        };
+        let selected = PaneEncoding::ALL[state.selected_idx];
+        let pane_idx = state.pane_idx;
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(PaneEncoding::ALL.len() - 1),
+            KeyCode::Enter | KeyCode::Char('i') | KeyCode::Char('o') => {
+                self.mode = AppMode::Normal;
+                let Some(pane) = self.panes.get(pane_idx) else {
+                    return;
+                };
+                let mut encodings = pane.encodings();
+                match key.code {
+                    KeyCode::Char('i') => encodings.input = selected,
+                    KeyCode::Char('o') => encodings.output = selected,
+                    _ => encodings = PaneEncodings { input: selected, output: selected },
+                }
+                pane.set_encodings(encodings);
+                self.notify(format!(
+                    "Pane input is {}, output is {}",
+                    encodings.input.label(),
+                    encodings.output.label()
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens an agent pane to the right of the active pane that watches its
+    /// blocks and suggests next steps.
+    pub fn open_pair_pane(&mut self) {
+        let Some(event_proxy) = self.event_proxy.clone() else {
+            return;
+        };
+        let Some(shell_pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        if self.pair.session_for(shell_pane.id).is_some() {
+            self.notify("This pane is already paired".to_string());
+            return;
+        }
+        let (shell_pane_id, seen_blocks) = (shell_pane.id, shell_pane.history.len());
+        let (cols, rows) = shell_pane.size();
+        let mut agent_pane = Pane::new(cols, rows, &self.shell(), event_proxy);
+        agent_pane.set_encodings(configured_encodings(&self.config.encoding));
+        agent_pane.enter_agent_mode("Pair programming".to_string(), self.config.ai.base_model.clone());
+        if let Some(agent_state) = &mut agent_pane.agent_state {
+            agent_state.status = crate::app::pane::AgentStatus::WaitingForInput;
+        }
+        self.pair.sessions.push(PairSession::new(shell_pane_id, agent_pane.id, seen_blocks));
+        self.panes.insert(self.active_pane_idx + 1, agent_pane);
+        if !self.config.pair.enabled {
+            self.notify("Pair opened; suggestions are turned off in the config".to_string());
+        }
+    }
+
+    /// The kill switch: pauses or resumes suggestions from every pair.
+    pub fn toggle_pair_suggestions(&mut self) {
+        let message = if self.pair.toggle_paused() { "Pair suggestions paused" } else { "Pair suggestions resumed" };
+        self.notify(message.to_string());
+    }
+
+    /// Runs the environment checks of `warpish doctor` in the background and
+    /// shows the report in a block of the active pane.
+    pub fn run_doctor(&mut self) {
+        if self.doctor.is_some() {
+            self.notify("Doctor is already running".to_string());
+            return;
+        }
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let block = Block::new("warpish doctor".to_string(), "Running checks…".to_string());
+        let (report_tx, report_rx) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            report_tx.send(crate::doctor::run()).ok();
+        });
+        self.doctor = Some((block.id, report_rx));
+        pane.history.push(block);
+    }
+
+    fn sync_doctor(&mut self) {
+        let Some(report) = self.doctor.as_ref().and_then(|(_, report_rx)| report_rx.try_recv().ok()) else {
+            return;
+        };
+        let Some((block_id, _)) = self.doctor.take() else {
+            return;
+        };
+        let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == block_id);
+        if let Some(block) = block {
+            block.output = report.render();
+        }
+        let message = if report.healthy() { "Doctor: all checks passed" } else { "Doctor: some checks failed" };
+        self.notify(message.to_string());
+    }
+
+    /// Feeds new shell blocks to their pairs and shows finished suggestions
+    /// in the agent panes.
+    fn sync_pairs(&mut self) {
+        let panes = &self.panes;
+        self.pair.sessions.retain(|session| {
+            let open = |id: Uuid| panes.iter().any(|pane| pane.id == id);
+            open(session.shell_pane_id) && open(session.agent_pane_id)
+        });
+        for session in &mut self.pair.sessions {
+            if let Some(shell_pane) = self.panes.iter().find(|pane| pane.id == session.shell_pane_id) {
+                session.observe(&shell_pane.history, self.config.pair.context_blocks);
+            }
+        }
+        self.pair.request(&self.config.pair, &self.config.ai.base_model, chrono::Utc::now().timestamp());
+        for suggestion in self.pair.poll() {
+            let agent_state = self
+                .panes
+                .iter_mut()
+                .find(|pane| pane.id == suggestion.agent_pane_id)
+                .and_then(|pane| pane.agent_state.as_mut());
+            if let Some(agent_state) = agent_state {
+                agent_state.conversation.push((crate::pair::PAIR_PROMPT.to_string(), suggestion.response));
+            }
+        }
+    }
+
+    /// Shares the active pane live and copies the invite link.
+    pub fn start_live_share(&mut self) {
+        if let Some(share) = &self.live_share {
+            self.notify(format!("Already sharing a pane: {}", share.url));
+            return;
+        }
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        match LiveShare::start(pane.id, &self.config.share.live_address, pane.output_tap.clone(), self.permissions.clone()) {
+            Ok(share) => {
+                if let Ok(mut clipboard) = Clipboard::new() {
+                    clipboard.set_text(share.url.clone()).ok();
+                }
+                self.notify(format!("Sharing pane live: {} (link copied)", share.url));
+                self.live_share = Some(share);
+            }
+            Err(e) => self.notify(format!("Failed to share pane: {}", e)),
+        }
+    }
+
+    pub fn stop_live_share(&mut self) {
+        if self.live_share.take().is_some() {
+            self.notify("Stopped sharing the pane".to_string());
+        }
+    }
+
+    /// Asks before collaborators type, and writes what approved
+    /// collaborators typed to the shared pane.
+    fn sync_live_share(&mut self) {
+        let Some(share) = &mut self.live_share else {
+            return;
+        };
+        let Some(pane) = self.panes.iter_mut().find(|pane| pane.id == share.pane_id) else {
+            self.stop_live_share();
+            return;
+        };
+        let now = chrono::Utc::now().timestamp();
+        let keystrokes = share.sync(now);
+        if !keystrokes.is_empty() {
+            if let Err(e) = pane.pty_writer.write_all(&keystrokes) {
+                log::warn!("Failed to write collaborator input: {}", e);
+            }
+        }
+    }
+
+    pub fn open_share_activity(&mut self) {
+        if self.live_share.is_none() {
+            self.notify("No pane is shared live".to_string());
+            return;
+        }
+        self.mode = AppMode::ShareActivity(ShareActivityState { selected_idx: 0 });
+    }
+
+    fn handle_share_activity_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::ShareActivity(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let Some(share) = &mut self.live_share else {
+            self.mode = AppMode::Normal;
+            return Ok(());
+        };
+        let collaborators = share.collaborators();
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(collaborators.len().saturating_sub(1)),
+            KeyCode::Char('r') => {
+                if let Some(collaborator) = collaborators.get(state.selected_idx).filter(|collaborator| collaborator.can_type) {
+                    share.revoke_input(collaborator.id, chrono::Utc::now().timestamp());
+                }
+            }
+            KeyCode::Char('s') => {
+                self.mode = AppMode::Normal;
+                self.stop_live_share();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Lists the active repository's changed files for review.
+    pub fn open_code_review(&mut self) {
+        let cwd = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd);
+        let Some(status) = cwd.as_deref().and_then(|cwd| self.git.status(cwd)) else {
+            self.notify("Not in a git repository".to_string());
+            return;
+        };
+        if !status.is_dirty() {
+            self.notify(format!("No changes on {}", status.branch));
+            return;
+        }
+        let files = status.files.iter().map(|file| format!("{:>2} {}", file.state.marker(), file.path)).collect();
+        self.mode = AppMode::CodeReview(CodeReviewState { selected_file_idx: 0, selected_hunk_idx: 0, files, hunks: Vec::new() });
+    }
+
+    /// Asks the agent for a message for the staged changes in the active
+    /// pane's repository, with the Drive "Commit Message" prompt if there is
+    /// one.
+    pub fn generate_commit_message(&mut self) {
+        let cwd = self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd);
+        let Some(cwd) = cwd else {
+            self.notify("Not in a git repository".to_string());
+            return;
+        };
+        if self.git.status(&cwd).is_some_and(|status| status.staged() == 0) {
+            self.notify("Nothing is staged; stage changes with `git add` first".to_string());
+            return;
+        }
+        let prompt = self
+            .drive_manager
+            .prompts()
+            .into_iter()
+            .find(|prompt| prompt.name == crate::agent::commit::TEMPLATE_NAME)
+            .unwrap_or_else(crate::agent::commit::template);
+        self.notify("Writing a commit message for the staged changes…".to_string());
+        self.commit_messages.generate(cwd, prompt, self.config.ai.conventional_commits, self.config.ai.base_model.clone());
+    }
+
+    /// Puts a generated `git commit` command in the input for review.
+    fn sync_commit_message(&mut self) {
+        for result in self.commit_messages.poll() {
+            match result {
+                Ok(commit) => {
+                    self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &commit.command, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                    if commit.unconventional {
+                        self.notify("The message does not follow Conventional Commits; edit it before committing".to_string());
+                    }
+                }
+                Err(e) => self.notify(format!("Could not write a commit message: {}", e)),
+            }
+        }
+    }
+
+    fn handle_code_review_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::CodeReview(state) = &mut self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Up => state.selected_file_idx = state.selected_file_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_file_idx = (state.selected_file_idx + 1).min(state.files.len().saturating_sub(1)),
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn line_height(&self) -> f32 {
+        self.config.appearance.font_size * self.config.appearance.line_height
+    }
+
+    /// Scrolls the active pane's block history, animating unless reduced
+    /// motion is in effect.
+    pub fn scroll_active_pane(&mut self, input: ScrollInput, now: std::time::Instant) {
+        let line_height = self.line_height();
+        let settings = self.scroll_settings;
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let lines: usize = pane.history.iter().map(|block| history_rows(pane, block, &self.config.blocks)).sum();
+        pane.scroll.set_max_offset(lines as f32 * line_height);
+        pane.scroll.apply(input, line_height, now, &settings);
+    }
+
+    /// The minimap of a pane's whole scrollback: where its blocks start,
+    /// which commands failed and, while blocks are being searched, where
+    /// the matches are.
+    pub fn minimap(&self, pane_idx: usize) -> Option<Minimap> {
+        let pane = self.panes.get(pane_idx)?;
+        let query = match &self.mode {
+            AppMode::Search(state) => match SearchDomain::parse(&state.query) {
+                (SearchDomain::Blocks, text) => Some(text),
+                _ => None,
+            },
+            _ => None,
+        };
+        let blocks = pane.history.iter().map(|block| MinimapBlock {
+            command: &block.command,
+            lines: fold::visible_output(&block.output, pane.output_view(block, &self.config.blocks), &self.config.blocks).text.lines().collect(),
+            rows: history_rows(pane, block, &self.config.blocks),
+            failed: pane.block_exits.get(&block.id).is_some_and(|exit| exit.status != 0),
+        });
+        Some(Minimap::layout(blocks, query))
+    }
+
+    /// The command line to pin to the top of a pane, while the output of
+    /// its block fills the top of the viewport.
+    pub fn sticky_header(&self, pane_idx: usize) -> Option<StickyHeader> {
+        let pane = self.panes.get(pane_idx)?;
+        let top_row = (pane.scroll.offset() / self.line_height()) as usize;
+        let block_index = sticky::pinned_block(pane.history.iter().map(|block| history_rows(pane, block, &self.config.blocks)), top_row)?;
+        let block = &pane.history[block_index];
+        let exit = pane.block_exits.get(&block.id);
+        Some(StickyHeader {
+            block_index,
+            command: block.command.clone(),
+            status: sticky::status_label(exit.map(|exit| exit.status), exit.and_then(|exit| exit.duration)),
+            failed: exit.is_some_and(|exit| exit.status != 0),
+        })
+    }
+
+    /// Focuses a pane and scrolls it to the block under `fraction` of the
+    /// way down its minimap.
+    pub fn jump_to_minimap(&mut self, pane_idx: usize, fraction: f32, now: std::time::Instant) {
+        let Some(minimap) = self.minimap(pane_idx) else {
+            return;
+        };
+        let Some(row) = minimap.block_at(fraction).and_then(|block_idx| minimap.block_row(block_idx)) else {
+            return;
+        };
+        let line_height = self.line_height();
+        let settings = self.scroll_settings;
+        self.active_pane_idx = pane_idx;
+        let pane = &mut self.panes[pane_idx];
+        pane.scroll.set_max_offset(minimap.total_rows() as f32 * line_height);
+        pane.scroll.scroll_to(row as f32 * line_height, now, &settings);
+    }
+
+    /// Advances scroll animations; returns whether another frame is needed.
+    pub fn tick_scroll(&mut self, now: std::time::Instant) -> bool {
+        let settings = self.scroll_settings;
+        self.panes.iter_mut().fold(false, |animating, pane| pane.scroll.tick(now, &settings) || animating)
+    }
+
+    /// Moves the active pane one step back or forward in its directory history.
+    pub fn navigate_dir_history(&mut self, forward: bool) -> Result<(), AppError> {
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return Ok(());
+        };
+        let moved = {
+            let mut history = pane.dir_history.lock().unwrap();
+            if forward { history.forward() } else { history.back() }
+        };
+        if moved.is_some() {
+            pane.sync_dir_history()?;
+        }
+        Ok(())
+    }
+
+    /// Opens the dropdown of the active pane's directory history, most recent first.
+    pub fn open_dir_history_menu(&mut self) {
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        let history = pane.dir_history.lock().unwrap();
+        if history.entries().is_empty() {
+            return;
+        }
+        let entries: Vec<PathBuf> = history.entries().iter().rev().cloned().collect();
+        let selected_idx = entries.len() - 1 - history.position();
+        drop(history);
+        self.mode = AppMode::DirHistory(DirHistoryMenuState {
+            pane_idx: self.active_pane_idx,
+            entries,
+            selected_idx,
+        });
+    }
+
+    fn handle_dir_history_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::DirHistory(state) = &mut self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(state.entries.len().saturating_sub(1)),
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Enter => {
+                let pane_idx = state.pane_idx;
+                // Entries are listed newest first.
+                let index = state.entries.len() - 1 - state.selected_idx;
+                self.mode = AppMode::Normal;
+                if let Some(pane) = self.panes.get_mut(pane_idx) {
+                    let moved = pane.dir_history.lock().unwrap().jump(index);
+                    if moved.is_some() {
+                        pane.sync_dir_history()?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Asks for a capability on behalf of an agent tool. A pending request is
+    /// shown to the user on the next tick; call again after they answer.
+    pub fn request_capability(&self, tool: &str, capability: Capability, reason: String) -> PermissionStatus {
+        self.permissions.lock().unwrap().request(PermissionRequest {
+            requester: Requester::AgentTool(tool.to_string()),
+            capability,
+            reason,
+        })
+    }
+
+    /// Opens the next queued permission prompt unless another modal is showing.
+    fn show_next_permission_prompt(&mut self) {
+        if self.mode != AppMode::Normal {
+            return;
+        }
+        if let Some(request) = self.permissions.lock().unwrap().next_prompt() {
+            self.mode = AppMode::PermissionPrompt(PermissionPromptState { request, selected_idx: 0 });
+        }
+    }
+
+    fn handle_permission_prompt_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::PermissionPrompt(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let choices = state.request.choices();
+        let choice = match key.code {
+            KeyCode::Left => {
+                state.selected_idx = state.selected_idx.saturating_sub(1);
+                None
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                state.selected_idx = (state.selected_idx + 1) % choices.len();
+                None
+            }
+            KeyCode::Enter => choices.get(state.selected_idx).copied(),
+            KeyCode::Char('y') => Some(PermissionChoice::AllowOnce),
+            KeyCode::Char('a') => Some(PermissionChoice::AlwaysAllow),
+            KeyCode::Char('n') | KeyCode::Esc => Some(PermissionChoice::Deny),
+            KeyCode::Char('x') => Some(PermissionChoice::AlwaysDeny),
+            _ => None,
+        }
+        .filter(|choice| choices.contains(choice));
+        if let Some(choice) = choice {
+            let request = state.request.clone();
+            self.mode = AppMode::Normal;
+            let now = chrono::Utc::now().timestamp();
+            self.permissions.lock().unwrap().resolve(&self.db_conn, &request, choice, now)?;
+            self.notify(format!("{}: {} {}", request.requester, choice.label().to_lowercase(), request.capability.label()));
+        }
+        Ok(())
+    }
+
+    /// Asks a survey question when one is due and nothing else is showing.
+    fn show_next_survey(&mut self) {
+        if self.mode != AppMode::Normal || !self.input_is_empty() {
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        if let Some(question) = self.survey.next_question(&self.config.survey, now) {
+            self.mode = AppMode::Survey(SurveyPromptState { question: question.clone(), selected_idx: 0, quit_after: false });
+        }
+    }
+
+    pub fn open_quiz(&mut self, quiz: Quiz) {
+        if quiz.questions.is_empty() {
+            self.notify("The quiz has no questions".to_string());
+            return;
+        }
+        self.mode = AppMode::Quiz(QuizState { session: QuizSession::new(quiz, chrono::Utc::now()), selected_idx: 0 });
+    }
+
+    /// Drills the bank's due questions, filling an empty bank with the shell
+    /// basics first.
+    pub fn start_drill(&mut self) {
+        if self.quiz_bank.is_empty() {
+            let set = QuestionSet { name: "shell basics".to_string(), questions: crate::mcq::shell_basics().questions };
+            if let Err(e) = self.quiz_bank.add_set(&self.db_conn, &set) {
+                self.notify(format!("Failed to store the quiz questions: {}", e));
+                return;
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+        let quiz = self.quiz_bank.due(now, DRILL_LENGTH);
+        if quiz.questions.is_empty() {
+            let hours = self.quiz_bank.next_due().map_or(0, |due_at| (due_at - now + 3599) / 3600);
+            self.notify(format!("Nothing to review; the next question is due in {} hour(s)", hours));
+            return;
+        }
+        self.open_quiz(quiz);
+    }
+
+    /// Asks for a YAML or JSON question set and adds it to the bank.
+    pub fn import_quiz_questions(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Import Quiz Questions")
+            .add_filter("Question set", &["yaml", "yml", "json"])
+            .pick_file()
+        else {
+            return;
+        };
+        match QuestionSet::import(&path).and_then(|set| self.quiz_bank.add_set(&self.db_conn, &set)) {
+            Ok(count) => self.notify(format!("Imported {} question(s) from {}", count, path.display())),
+            Err(e) => self.notify(format!("Failed to import {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Saves every bank question as one set, YAML or JSON by the extension
+    /// chosen.
+    pub fn export_quiz_questions(&mut self) {
+        if self.quiz_bank.is_empty() {
+            self.notify("The quiz bank is empty".to_string());
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export Quiz Questions")
+            .set_file_name("questions.yaml")
+            .add_filter("Question set", &["yaml", "yml", "json"])
+            .save_file()
+        else {
+            return;
+        };
+        let name = path.file_stem().map_or("questions".to_string(), |stem| stem.to_string_lossy().into_owned());
+        match self.quiz_bank.to_set(&name).export(&path) {
+            Ok(()) => self.notify(format!("Exported {} question(s) to {}", self.quiz_bank.len(), path.display())),
+            Err(e) => self.notify(format!("Failed to export to {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Asks the agent for questions about the tools used most in the
+    /// history.
+    pub fn generate_history_quiz(&mut self) {
+        let history = crate::db::get_all_history(&mut self.db_conn).unwrap_or_else(|e| {
+            log::warn!("Failed to read the command history: {}", e);
+            Vec::new()
+        });
+        let samples = sample_history(&history, 3, 10);
+        if samples.is_empty() {
+            self.notify("There is no history to build a quiz from yet".to_string());
+            return;
+        }
+        let tools: Vec<&str> = samples.iter().map(|(tool, _)| tool.as_str()).collect();
+        self.notify(format!("Asking the agent for questions about {}…", tools.join(", ")));
+        self.quiz_generator.generate(&samples, GENERATED_QUESTIONS, self.config.ai.base_model.clone());
+    }
+
+    /// Adds generated questions to the bank and starts a quiz on them,
+    /// unless something else is open by then.
+    fn sync_quiz_generation(&mut self) {
+        for result in self.quiz_generator.poll() {
+            let set = match result {
+                Ok(set) => set,
+                Err(e) => {
+                    self.notify(format!("Could not generate a quiz: {}", e));
+                    continue;
+                }
+            };
+            match self.quiz_bank.add_set(&self.db_conn, &set) {
+                Ok(_) if matches!(self.mode, AppMode::Normal) => {
+                    let mut quiz = Quiz::new();
+                    for question in set.questions {
+                        quiz.add_question(question);
+                    }
+                    self.open_quiz(quiz);
+                }
+                Ok(count) => self.notify(format!("Added {} question(s) about your history to the quiz bank", count)),
+                Err(e) => self.notify(format!("Failed to store the generated questions: {}", e)),
+            }
+        }
+    }
+
+    /// Runs out the clock on the question being shown.
+    fn sync_quiz(&mut self) {
+        if let AppMode::Quiz(state) = &mut self.mode {
+            state.session.check_time(chrono::Utc::now());
+        }
+    }
+
+    fn handle_quiz_keys(&mut self, key: KeyEvent) {
+        let AppMode::Quiz(state) = &mut self.mode else {
+            return;
+        };
+        let now = chrono::Utc::now();
+        let option_count = state.session.question().map_or(0, |question| question.options.len());
+        if state.session.outcome.is_some() {
+            // The outcome and explanation stay up until the next key.
+            match key.code {
+                KeyCode::Esc => self.finish_quiz(),
+                KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Right => {
+                    state.selected_idx = 0;
+                    if !state.session.next(now) {
+                        self.finish_quiz();
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => self.finish_quiz(),
+            KeyCode::Up | KeyCode::Char('k') => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => state.selected_idx = (state.selected_idx + 1).min(option_count.saturating_sub(1)),
+            KeyCode::Enter => {
+                state.session.answer(state.selected_idx, now);
+            }
+            KeyCode::Char(c) => {
+                if let Some(n) = c.to_digit(10).filter(|n| (1..=option_count as u32).contains(n)) {
+                    state.selected_idx = n as usize - 1;
+                    state.session.answer(state.selected_idx, now);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Closes the quiz, reschedules the bank questions it asked and adds
+    /// its results to the active pane as a block.
+    fn finish_quiz(&mut self) {
+        let AppMode::Quiz(mut state) = std::mem::replace(&mut self.mode, AppMode::Normal) else {
+            return;
+        };
+        let quiz = &mut state.session.quiz;
+        let end_time = *quiz.end_time.get_or_insert_with(chrono::Utc::now);
+        self.quiz_bank.record(&self.db_conn, quiz, end_time.timestamp());
+        let summary = QuizSummary::of(quiz);
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.history.push(Block {
+            tags: vec!["quiz".to_string()],
+            ..Block::new(format!("quiz: {}/{} correct", summary.correct, summary.total), summary.render(quiz))
+        });
+    }
+
+    /// Quits, first asking for confirmation while jobs are running, then
+    /// asking the exit question when one is due.
+    pub fn request_quit(&mut self) {
+        if self.config.shutdown.confirm_running_jobs {
+            let jobs: Vec<String> = self.panes.iter().filter_map(Pane::running_job).collect();
+            if !jobs.is_empty() {
+                self.mode = AppMode::ConfirmClose(ConfirmCloseState { pane_idx: None, jobs });
+                return;
+            }
+        }
+        self.quit();
+    }
+
+    fn quit(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        match self.survey.exit_question(&self.config.survey, now) {
+            Some(question) => {
+                self.mode = AppMode::Survey(SurveyPromptState { question: question.clone(), selected_idx: 0, quit_after: true });
+            }
+            None => self.should_quit = true,
+        }
+    }
+
+    fn handle_survey_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::Survey(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let option_count = state.question.options.len();
+        let answer = match key.code {
+            KeyCode::Up => {
+                state.selected_idx = state.selected_idx.saturating_sub(1);
+                return Ok(());
+            }
+            KeyCode::Down => {
+                state.selected_idx = (state.selected_idx + 1).min(option_count.saturating_sub(1));
+                return Ok(());
+            }
+            KeyCode::Enter => Some(state.selected_idx),
+            KeyCode::Char(c) => match c.to_digit(10) {
+                Some(n) if (1..=option_count as u32).contains(&n) => Some(n as usize - 1),
+                _ => return Ok(()),
+            },
+            KeyCode::Esc => None,
+            _ => return Ok(()),
+        };
+        let AppMode::Survey(state) = std::mem::replace(&mut self.mode, AppMode::Normal) else {
+            return Ok(());
+        };
+        self.survey.record(&self.db_conn, &state.question, answer, chrono::Utc::now().timestamp());
+        if let Some(answer) = answer {
+            // Point users who have not tried the feature at how to use it.
+            match &state.question.explanation {
+                Some(explanation) if state.question.category == AWARENESS && answer > 0 => self.notify(explanation.clone()),
+                _ => self.notify("Thanks for the feedback".to_string()),
+            }
+        }
+        if state.quit_after {
+            self.should_quit = true;
+        }
+        Ok(())
+    }
+
+    fn handle_permission_audit_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::PermissionAudit(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let grants = self.permissions.lock().unwrap().grants();
+        match key.code {
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(grants.len().saturating_sub(1)),
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Delete | KeyCode::Char('r') => {
+                if let Some(grant) = grants.get(state.selected_idx) {
+                    state.selected_idx = state.selected_idx.min(grants.len().saturating_sub(2));
+                    self.permissions.lock().unwrap().revoke(&self.db_conn, &grant.requester, grant.capability)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn shell(&self) -> String {
+        self.config.shell()
+    }
+
+    /// Opens a scratch pane in a fresh temp directory and focuses it.
+    pub fn open_scratch_pane(&mut self) {
+        let Some(event_proxy) = self.event_proxy.clone() else {
+            return;
+        };
+        let session = match ScratchSession::create(&self.config.scratch) {
+            Ok(session) => session,
+            Err(e) => {
+                self.notify(format!("Could not open scratch pane: {}", e));
+                return;
+            }
+        };
+        let (cols, rows) = self.panes.get(self.active_pane_idx).map(Pane::size).unwrap_or((80, 24));
+        let message = format!("Scratch pane opened in {}", session.dir().display());
+        let mut pane = Pane::new_scratch(cols, rows, &self.shell(), session, event_proxy);
+        pane.structured_capture = self.panes.iter().find_map(|pane| pane.structured_capture.clone());
+        pane.set_encodings(configured_encodings(&self.config.encoding));
+        self.panes.push(pane);
+        self.active_pane_idx = self.panes.len() - 1;
+        self.notify(message);
+    }
+
+    fn handle_new_pane_keys(&mut self, key: KeyEvent) {
+        let AppMode::NewPane(state) = &mut self.mode else {
+            return;
+        };
+        let entries = self.config.profiles.len() + 1;
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(entries - 1),
+            KeyCode::Enter => {
+                let profile = state.selected_idx.checked_sub(1).and_then(|idx| self.config.profiles.get(idx)).map(|profile| profile.name.clone());
+                self.mode = AppMode::Normal;
+                self.open_pane(profile.as_deref());
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens a pane with the default shell, or with the launch profile
+    /// `profile`, and focuses it.
+    pub fn open_pane(&mut self, profile: Option<&str>) {
+        let Some(event_proxy) = self.event_proxy.clone() else {
+            return;
+        };
+        let (cols, rows) = self.panes.get(self.active_pane_idx).map(Pane::size).unwrap_or((80, 24));
+        let mut pane = match profile {
+            Some(name) => {
+                let Some(profile) = crate::profiles::find(&self.config.profiles, name) else {
+                    self.notify(format!("No profile named '{}'", name));
+                    return;
+                };
+                Pane::new_from_profile(cols, rows, profile, &self.shell(), event_proxy)
+            }
+            None => Pane::new(cols, rows, &self.shell(), event_proxy),
+        };
+        pane.structured_capture = self.panes.iter().find_map(|pane| pane.structured_capture.clone());
+        pane.set_encodings(configured_encodings(&self.config.encoding));
+        self.panes.push(pane);
+        self.active_pane_idx = self.panes.len() - 1;
+    }
+
+    /// Uses the focused pane's profile theme, or the usual theme again once
+    /// a pane without one has focus.
+    fn sync_profile_theme(&mut self) {
+        let wanted = self
+            .panes
+            .get(self.active_pane_idx)
+            .and_then(|pane| pane.profile.as_deref())
+            .and_then(|name| crate::profiles::find(&self.config.profiles, name))
+            .and_then(|profile| profile.theme.clone());
+        if wanted == self.profile_theme {
+            return;
+        }
+        self.active_theme = match wanted.as_deref().map(|name| (name, self.theme_manager.themes.get(name))) {
+            Some((_, Some(theme))) => theme.clone(),
+            Some((name, None)) => {
+                log::warn!("Profile theme '{}' is not in themes/", name);
+                self.base_theme.clone()
+            }
+            None => self.base_theme.clone(),
+        };
+        self.profile_theme = wanted;
+    }
+
+    /// Lists the installed WSL distros to open a pane into.
+    pub fn open_wsl_picker(&mut self) {
+        match crate::wsl::list_distros() {
+            Ok(distros) if distros.is_empty() => self.notify("No WSL distros are installed".to_string()),
+            Ok(distros) => self.mode = AppMode::WslPicker(WslPickerState { distros, selected_idx: 0 }),
+            Err(e) => self.notify(format!("Could not list WSL distros: {}", e)),
+        }
+    }
+
+    fn handle_wsl_picker_keys(&mut self, key: KeyEvent) {
+        let AppMode::WslPicker(state) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(state.distros.len().saturating_sub(1)),
+            KeyCode::Enter => {
+                let distro = state.distros[state.selected_idx].clone();
+                self.mode = AppMode::Normal;
+                self.open_wsl_pane(&distro);
+            }
+            _ => {}
+        }
+    }
+
+    /// The watch re-running commands in the pane at `pane_idx`, if any.
+    pub fn watch_for(&self, pane_idx: usize) -> Option<&CommandWatch> {
+        let pane_id = self.panes.get(pane_idx)?.id;
+        self.watches.iter().find(|watch| watch.pane_id == pane_id)
+    }
+
+    /// Asks which files should re-run the active pane's latest command.
+    pub fn watch_last_block(&mut self) {
+        let command = self
+            .panes
+            .get(self.active_pane_idx)
+            .and_then(|pane| pane.history.iter().rev().find(|block| !block.command.is_empty()))
+            .map(|block| block.command.clone());
+        match command {
+            Some(command) => self.open_watch_glob(self.active_pane_idx, &command),
+            None => self.notify("No command to watch".to_string()),
+        }
+    }
+
+    fn open_watch_glob(&mut self, pane_idx: usize, command: &str) {
+        if command.is_empty() {
+            self.notify("No command to watch".to_string());
+            return;
+        }
+        self.mode = AppMode::WatchGlob(WatchGlobState {
+            pane_idx,
+            command: command.to_string(),
+            glob: self.config.watch.glob.clone(),
+        });
+    }
+
+    fn handle_watch_glob_keys(&mut self, key: KeyEvent) {
+        let AppMode::WatchGlob(state) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Backspace => {
+                state.glob.pop();
+            }
+            KeyCode::Char(c) => state.glob.push(c),
+            KeyCode::Enter => {
+                let state = state.clone();
+                self.mode = AppMode::Normal;
+                self.start_watch(state.pane_idx, &state.command, &state.glob);
+            }
+            _ => {}
+        }
+    }
+
+    /// Re-runs `command` in the pane whenever files matching `glob` under
+    /// its directory change, replacing the pane's previous watch.
+    fn start_watch(&mut self, pane_idx: usize, command: &str, glob: &str) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        let Some(root) = pane.local_cwd() else {
+            self.notify("Watch mode needs a pane in a local directory".to_string());
+            return;
+        };
+        let pane_id = pane.id;
+        let debounce = Duration::from_millis(self.config.watch.debounce_ms);
+        match CommandWatch::new(pane_id, command, &root, glob, debounce) {
+            Ok(watch) => {
+                self.watches.retain(|watch| watch.pane_id != pane_id);
+                self.watches.push(watch);
+                self.notify(format!("Re-running `{}` when {} changes in {}", command, glob, root.display()));
+            }
+            Err(e) => self.notify(format!("Failed to watch {}: {}", root.display(), e)),
+        }
+    }
+
+    pub fn stop_watch(&mut self, pane_idx: usize) {
+        let Some(pane_id) = self.panes.get(pane_idx).map(|pane| pane.id) else {
+            return;
+        };
+        match self.watches.iter().position(|watch| watch.pane_id == pane_id) {
+            Some(idx) => {
+                let watch = self.watches.remove(idx);
+                self.notify(format!("Stopped watching `{}` after {} run(s)", watch.command, watch.runs));
+            }
+            None => self.notify("This pane is not watching files".to_string()),
+        }
+    }
+
+    /// Re-runs watched commands whose files changed, once the shell is idle.
+    /// Watches end with their pane.
+    fn sync_watches(&mut self) {
+        let now = std::time::Instant::now();
+        let panes = &mut self.panes;
+        self.watches.retain_mut(|watch| {
+            let Some(pane) = panes.iter_mut().find(|pane| pane.id == watch.pane_id) else {
+                return false;
+            };
+            if pane.running_job().is_some() {
+                watch.discard();
+            } else if watch.poll(now) {
+                if let Err(e) = pane.run_command(&watch.command) {
+                    log::warn!("Failed to re-run watched command: {}", e);
+                }
+            }
+            true
+        });
+    }
+
+    /// The `(pane, shell pid)` pairs whose children are listed as jobs.
+    fn job_shells(&self) -> Vec<(Uuid, u32)> {
+        self.panes.iter().filter_map(|pane| Some((pane.id, pane.shell_pid()?))).collect()
+    }
+
+    pub fn open_jobs_panel(&mut self) {
+        let shells = self.job_shells();
+        self.jobs.refresh(&shells, std::time::Instant::now());
+        self.mode = AppMode::Jobs(JobsPanelState { selected_idx: 0 });
+    }
+
+    /// Runs the command line outside the shell, leaving the pane free; its
+    /// output is kept for the jobs panel to re-attach.
+    pub fn run_input_detached(&mut self) {
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        if command.trim().is_empty() {
+            self.notify("Type a command to run in the background".to_string());
+            return;
+        }
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        match self.jobs.spawn_detached(pane.id, &command, pane.local_cwd().as_deref()) {
+            Ok(pid) => {
+                self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced);
+                self.completions_manager.add_to_history(command.clone());
+                self.notify(format!("Started `{}` in the background (pid {})", command, pid));
+            }
+            Err(e) => self.notify(e.to_string()),
+        }
+    }
+
+    fn handle_jobs_keys(&mut self, key: KeyEvent) {
+        let AppMode::Jobs(state) = &mut self.mode else {
+            return;
+        };
+        let count = self.jobs.jobs().len();
+        let Some(job) = self.jobs.jobs().get(state.selected_idx).cloned() else {
+            if key.code == KeyCode::Esc {
+                self.mode = AppMode::Normal;
+            }
+            return;
+        };
+        let result = match key.code {
+            KeyCode::Esc => {
+                self.mode = AppMode::Normal;
+                Ok(())
+            }
+            KeyCode::Up => {
+                state.selected_idx = state.selected_idx.saturating_sub(1);
+                Ok(())
+            }
+            KeyCode::Down => {
+                state.selected_idx = (state.selected_idx + 1).min(count - 1);
+                Ok(())
+            }
+            KeyCode::Char('s') => self.jobs.signal(job.pid, JobSignal::Suspend),
+            KeyCode::Char('r') => self.jobs.signal(job.pid, JobSignal::Resume),
+            KeyCode::Char('k') => self.jobs.signal(job.pid, JobSignal::Kill),
+            KeyCode::Char('d') if job.kind == JobKind::Detached => {
+                self.jobs.dismiss(job.pid);
+                if let AppMode::Jobs(state) = &mut self.mode {
+                    state.selected_idx = state.selected_idx.min(self.jobs.jobs().len().saturating_sub(1));
+                }
+                Ok(())
+            }
+            KeyCode::Char('a') => {
+                self.mode = AppMode::Normal;
+                self.attach_job(job.pid, job.pane_id)
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            self.notify(e.to_string());
+        }
+    }
+
+    /// Shows a detached job's output, so far and from now on, in a new block
+    /// in the pane it was started from.
+    fn attach_job(&mut self, pid: u32, pane_id: Uuid) -> Result<(), crate::jobs::JobError> {
+        let command = self.jobs.detached_command(pid).unwrap_or_default().to_string();
+        let block = Block::new(command, String::new());
+        self.jobs.attach(pid, block.id)?;
+        let pane_idx = self.panes.iter().position(|pane| pane.id == pane_id).unwrap_or(self.active_pane_idx);
+        if let Some(pane) = self.panes.get_mut(pane_idx) {
+            pane.history.push(block);
+            self.active_pane_idx = pane_idx;
+        }
+        Ok(())
+    }
+
+    /// Refreshes the job list while the panel is open and copies new output
+    /// of attached jobs into their blocks.
+    fn sync_jobs(&mut self) {
+        if matches!(self.mode, AppMode::Jobs(_)) {
+            let shells = self.job_shells();
+            self.jobs.refresh(&shells, std::time::Instant::now());
+        }
+        for update in self.jobs.attached_output() {
+            let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == update.block_id);
+            if let Some(block) = block {
+                block.output = update.text;
+            }
+        }
+    }
+
+    /// Looks for services the panes started, in their recent output and
+    /// among the sockets their processes listen on.
+    fn sync_ports(&mut self) {
+        let panes = &self.panes;
+        let scan = || {
+            panes
+                .iter()
+                .map(|pane| {
+                    let mut output: String = pane.history.iter().rev().take(3).map(|block| block.output.as_str()).collect::<Vec<_>>().join("\n");
+                    output.push('\n');
+                    output.push_str(&pane.current_vte.lock().unwrap().get_grid().as_ref().to_string());
+                    PaneScan { pane_id: pane.id, shell_pid: pane.shell_pid(), output }
+                })
+                .collect()
+        };
+        self.ports.request(scan, std::time::Instant::now());
+        self.ports.poll();
+    }
+
+    pub fn open_ports_panel(&mut self) {
+        if self.ports.services().is_empty() {
+            self.notify("No local services found in the panes".to_string());
+            return;
+        }
+        self.mode = AppMode::Ports(PortsPanelState { selected_idx: 0 });
+    }
+
+    fn handle_ports_keys(&mut self, key: KeyEvent) {
+        let AppMode::Ports(state) = &mut self.mode else {
+            return;
+        };
+        let Some(service) = self.ports.services().get(state.selected_idx).cloned() else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(self.ports.services().len() - 1),
+            KeyCode::Enter | KeyCode::Char('o') => {
+                self.mode = AppMode::Normal;
+                if let Err(e) = webbrowser::open(&service.url) {
+                    self.notify(format!("Failed to open {}: {}", service.url, e));
+                }
+            }
+            KeyCode::Char('k') => {
+                self.mode = AppMode::Normal;
+                match service.pid {
+                    Some(pid) => match crate::ports::stop_owner(pid) {
+                        Ok(()) => self.notify(format!("Stopped process {} serving port {}", pid, service.port)),
+                        Err(e) => self.notify(format!("Failed to stop process {}: {}", pid, e)),
+                    },
+                    None => self.notify(format!("The process serving port {} is not known", service.port)),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Keeps the Docker listing fresh while the panel is open or a `docker`
+    /// command is being typed, and feeds container names to completions.
+    fn sync_docker(&mut self) {
+        let typing_docker = self.input_editor.buffer_ref().lines.first().is_some_and(|line| line.text().trim_start().starts_with("docker "));
+        if matches!(self.mode, AppMode::Docker(_)) || typing_docker {
+            self.docker.request(std::time::Instant::now(), false);
+        }
+        if self.docker.poll() {
+            self.completions_manager.set_docker_containers(self.docker.container_names());
+        }
+    }
+
+    pub fn open_docker_panel(&mut self) {
+        self.docker.request(std::time::Instant::now(), true);
+        self.mode = AppMode::Docker(DockerPanelState { view: DockerView::Containers, selected_idx: 0 });
+    }
+
+    fn handle_docker_keys(&mut self, key: KeyEvent) {
+        let AppMode::Docker(state) = &mut self.mode else {
+            return;
+        };
+        let (containers, count) = match self.docker.snapshot() {
+            Some(Ok(snapshot)) => (
+                snapshot.containers.clone(),
+                match state.view {
+                    DockerView::Containers => snapshot.containers.len(),
+                    DockerView::Images => snapshot.images.len(),
+                },
+            ),
+            _ => (Vec::new(), 0),
+        };
+        let container = match state.view {
+            DockerView::Containers => containers.get(state.selected_idx).cloned(),
+            DockerView::Images => None,
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(count.saturating_sub(1)),
+            KeyCode::Tab => {
+                state.view = match state.view {
+                    DockerView::Containers => DockerView::Images,
+                    DockerView::Images => DockerView::Containers,
+                };
+                state.selected_idx = 0;
+            }
+            KeyCode::Char('r') => self.docker.request(std::time::Instant::now(), true),
+            KeyCode::Enter | KeyCode::Char('e') => {
+                let Some(container) = container else {
+                    return;
+                };
+                if !container.is_running() {
+                    self.notify(format!("{} is not running", container.name));
+                    return;
+                }
+                self.mode = AppMode::Normal;
+                self.open_pane(None);
+                let result = self.panes.last_mut().map(|pane| pane.run_command(&docker::exec_command(&container)));
+                if let Some(Err(e)) = result {
+                    self.notify(format!("Failed to open a shell in {}: {}", container.name, e));
+                }
+            }
+            KeyCode::Char('l') => {
+                let Some(container) = container else {
+                    return;
+                };
+                self.mode = AppMode::Normal;
+                self.tail_container_logs(&container);
+            }
+            _ => {}
+        }
+    }
+
+    /// Follows a container's logs into a new block in the active pane, as a
+    /// detached job that the jobs panel can stop.
+    fn tail_container_logs(&mut self, container: &docker::Container) {
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        let (pane_id, cwd) = (pane.id, pane.local_cwd());
+        let result = self
+            .jobs
+            .spawn_detached(pane_id, &docker::logs_command(container, 200), cwd.as_deref())
+            .and_then(|pid| self.attach_job(pid, pane_id));
+        if let Err(e) = result {
+            self.notify(format!("Failed to follow the logs of {}: {}", container.name, e));
+        }
+    }
+
+    /// Follows the current Kubernetes context, and lists its resources for
+    /// completions while a `kubectl` command is being typed.
+    fn sync_kube(&mut self) {
+        let now = std::time::Instant::now();
+        self.kube.refresh(now);
+        let typing_kubectl = self.input_editor.buffer_ref().lines.first().is_some_and(|line| line.text().trim_start().starts_with("kubectl "));
+        if typing_kubectl {
+            self.kube.request_resources(now);
+        }
+        if self.kube.poll() {
+            self.completions_manager.set_kube_resources(self.kube.resources().clone());
+        }
+    }
+
+    /// Why the command line needs confirming, when it is a destructive
+    /// `kubectl` command in a production context.
+    pub fn kube_warning(&self) -> Option<String> {
+        let context = self.kube.context()?;
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        crate::k8s::warning(&command, context, &self.config.k8s.production_contexts, &self.kube_rules)
+    }
+
+    /// Holds back the first Enter on a destructive `kubectl` command in a
+    /// production context. Returns whether the key was held back.
+    fn hold_kube_command(&mut self) -> bool {
+        let Some(warning) = self.kube_warning() else {
+            return false;
+        };
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        if self.kube_confirmed.as_deref() == Some(command.as_str()) {
+            self.kube_confirmed = None;
+            return false;
+        }
+        self.notify(format!("{}. Press Enter again to run it.", warning));
+        self.kube_confirmed = Some(command);
+        true
+    }
+
+    /// Shows the pull requests and issues fetched for the palette as soon as
+    /// they arrive.
+    fn sync_github(&mut self) {
+        if self.github.poll() && matches!(&self.mode, AppMode::Search(state) if SearchDomain::parse(&state.query).0 == SearchDomain::Actions) {
+            self.refresh_search();
+        }
+    }
+
+    fn input_is_http_request(&self) -> bool {
+        http::is_http_command(&self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n"))
+    }
+
+    /// Sends the `http` request on the command line from a new block, so
+    /// the shell never sees it.
+    fn run_http_input(&mut self) {
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.history.push(Block::new(command.clone(), String::new()));
+        let block_idx = pane.history.len() - 1;
+        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced);
+        self.completions_manager.add_to_history(command);
+        self.send_http_request(self.active_pane_idx, block_idx);
+    }
+
+    /// Sends, or sends again, the request a block was created for.
+    fn send_http_request(&mut self, pane_idx: usize, block_idx: usize) {
+        let Some(block) = self.panes.get_mut(pane_idx).and_then(|pane| pane.history.get_mut(block_idx)) else {
+            return;
+        };
+        *block.structured.lock().unwrap() = None;
+        match http::parse_request(&block.command, &self.config.http.variables) {
+            Ok(request) => {
+                block.output = format!("Sending {} {}…\n", request.method, request.url);
+                self.http.run(block.id, request, Duration::from_secs(self.config.http.timeout_secs));
+            }
+            Err(e) => block.output = format!("{}\n", e),
+        }
+    }
+
+    /// Shows responses in the blocks that sent them, with JSON bodies
+    /// available to the JSON viewer.
+    fn sync_http(&mut self) {
+        for (block_id, response) in self.http.poll() {
+            let Some(block) = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == block_id) else {
+                continue;
+            };
+            match response {
+                Ok(response) => {
+                    block.output = response.render();
+                    *block.structured.lock().unwrap() = response.json().map(StructuredData::Json);
+                }
+                Err(e) => block.output = format!("{}\n", e),
+            }
+        }
+    }
+
+    /// Appends a request to the configured collection in the personal
+    /// workspace.
+    fn save_http_request(&mut self, command: &str) {
+        let dir = self.drive_manager.personal_ws.path.join("http");
+        match http::save_to_collection(&dir, &self.config.http.collection, command) {
+            Ok(path) => self.notify(format!("Saved request to {}", path.display())),
+            Err(e) => self.notify(format!("Failed to save request: {}", e)),
+        }
+    }
+
+    fn active_ws_console(&self) -> Option<&WebSocketConsole> {
+        self.ws_consoles.get(&self.panes.get(self.active_pane_idx)?.id)
+    }
+
+    fn input_ws_console_url(&self) -> Option<String> {
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        ws_console::console_url(&command).map(str::to_string)
+    }
+
+    /// Turns the active pane into a console for the `ws <url>` on the
+    /// command line, until `/close`.
+    fn open_ws_console(&mut self) {
+        let (Some(url), Some(pane)) = (self.input_ws_console_url(), self.panes.get(self.active_pane_idx)) else {
+            return;
+        };
+        let max_delay = Duration::from_secs(self.config.websocket.max_reconnect_delay_secs);
+        self.ws_consoles.insert(pane.id, WebSocketConsole::connect(&url, self.config.websocket.reconnect, max_delay));
+        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced);
+        self.notify(format!("Connecting to {}. Lines you enter are sent as text frames; /close ends the console.", url));
+    }
+
+    fn send_ws_console_input(&mut self) {
+        let line = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        let Some(pane_id) = self.panes.get(self.active_pane_idx).map(|pane| pane.id) else {
+            return;
+        };
+        let input = match ConsoleInput::parse(&line) {
+            Ok(input) => input,
+            Err(e) => {
+                self.notify(e);
+                return;
+            }
+        };
+        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced);
+        match input {
+            ConsoleInput::Close => {
+                if let Some(console) = self.ws_consoles.remove(&pane_id) {
+                    self.notify(format!("Closed the console for {}", console.url));
+                }
+            }
+            ConsoleInput::Filter(filter) => {
+                if let Some(console) = self.ws_consoles.get_mut(&pane_id) {
+                    console.filter = filter;
+                }
+                self.show_ws_frames(pane_id);
+            }
+            input => {
+                if let Some(console) = self.ws_consoles.get(&pane_id) {
+                    console.send(input);
+                }
+            }
+        }
+    }
+
+    fn ws_frame_block(frame: &Frame) -> Block {
+        Block {
+            tags: vec![WS_FRAME_TAG.to_string()],
+            ..Block::new(frame.header(), format!("{}\n", frame.body()))
+        }
+    }
+
+    /// Replaces a pane's frame blocks with the frames its console's filter
+    /// lets through.
+    fn show_ws_frames(&mut self, pane_id: Uuid) {
+        let (Some(console), Some(pane)) = (self.ws_consoles.get(&pane_id), self.panes.iter_mut().find(|pane| pane.id == pane_id)) else {
+            return;
+        };
+        pane.history.retain(|block| !block.tags.iter().any(|tag| tag == WS_FRAME_TAG));
+        pane.history.extend(console.visible_frames().map(Self::ws_frame_block));
+        if !console.filter.is_empty() {
+            let shown = console.visible_frames().count();
+            self.notify(format!("Showing {} matching frame(s); /filter with nothing after it shows all", shown));
+        }
+    }
+
+    /// Adds frames the consoles sent or received as blocks, and reports
+    /// connection changes.
+    fn sync_ws_consoles(&mut self) {
+        let mut messages = Vec::new();
+        for (pane_id, console) in self.ws_consoles.iter_mut() {
+            for event in console.poll() {
+                match event {
+                    ConsoleEvent::Connected => messages.push(format!("Connected to {}", console.url)),
+                    ConsoleEvent::Disconnected { reason, retry_in: Some(delay) } => {
+                        messages.push(format!("Disconnected from {}: {}. Reconnecting in {} s", console.url, reason, delay.as_secs()))
+                    }
+                    ConsoleEvent::Disconnected { reason, retry_in: None } => messages.push(format!("Disconnected from {}: {}", console.url, reason)),
+                    ConsoleEvent::Frame(frame) if console.filter.matches(&frame) => {
+                        if let Some(pane) = self.panes.iter_mut().find(|pane| pane.id == *pane_id) {
+                            pane.history.push(Self::ws_frame_block(&frame));
+                        }
+                    }
+                    ConsoleEvent::Frame(_) => {}
+                }
+            }
+        }
+        for message in messages {
+            self.notify(message);
+        }
+    }
+
+    pub fn open_graphql_explorer(&mut self) {
+        let state = self.graphql_draft.take().unwrap_or_else(|| GraphQLExplorerState {
+            endpoint: self.config.graphql.endpoint.clone(),
+            query: String::new(),
+            variables: BTreeMap::new(),
+            focus: if self.config.graphql.endpoint.is_empty() { GraphQLFocus::Endpoint } else { GraphQLFocus::Query },
+            selected_completion: 0,
+            saved_idx: None,
+        });
+        self.graphql.introspect(&state.endpoint, self.config.graphql.credential.as_deref());
+        self.mode = AppMode::GraphQLExplorer(state);
+    }
+
+    /// Fields completing the name at the end of the explorer's query, with
+    /// their types.
+    pub fn graphql_completions(&self) -> Vec<(String, String)> {
+        let AppMode::GraphQLExplorer(state) = &self.mode else {
+            return Vec::new();
+        };
+        match (state.focus, self.graphql.schema(&state.endpoint)) {
+            (GraphQLFocus::Query, Some(Ok(schema))) => {
+                schema.completions(&state.query).into_iter().map(|field| (field.name.clone(), field.type_ref.to_string())).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn graphql_queries_dir(&self) -> PathBuf {
+        self.drive_manager.personal_ws.path.join("graphql")
+    }
+
+    fn handle_graphql_keys(&mut self, key: KeyEvent) {
+        let completions = self.graphql_completions();
+        let credential = self.config.graphql.credential.clone();
+        let AppMode::GraphQLExplorer(state) = &mut self.mode else {
+            return;
+        };
+        let variable_count = state.variable_definitions().len();
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => {
+                self.graphql_draft = Some(state.clone());
+                self.mode = AppMode::Normal;
+            }
+            KeyCode::Char('r') if control => self.run_graphql_query(),
+            KeyCode::Char('s') if control => self.save_graphql_query(),
+            KeyCode::Char('o') if control => self.open_next_saved_query(),
+            KeyCode::Up if !completions.is_empty() => state.selected_completion = state.selected_completion.saturating_sub(1),
+            KeyCode::Down if !completions.is_empty() => state.selected_completion = (state.selected_completion + 1).min(completions.len() - 1),
+            KeyCode::Tab if !completions.is_empty() => {
+                let (name, _) = &completions[state.selected_completion.min(completions.len() - 1)];
+                let typed = state.query.chars().rev().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').count();
+                state.query.push_str(&name[typed..]);
+                state.selected_completion = 0;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                if state.focus == GraphQLFocus::Endpoint {
+                    self.graphql.introspect(&state.endpoint, credential.as_deref());
+                }
+                let order: Vec<GraphQLFocus> = [GraphQLFocus::Endpoint, GraphQLFocus::Query]
+                    .into_iter()
+                    .chain((0..variable_count).map(GraphQLFocus::Variable))
+                    .collect();
+                let current = order.iter().position(|focus| *focus == state.focus).unwrap_or(0);
+                let next = if key.code == KeyCode::Tab { current + 1 } else { current + order.len() - 1 };
+                state.focus = order[next % order.len()];
+            }
+            KeyCode::Enter => match state.focus {
+                GraphQLFocus::Endpoint => {
+                    self.graphql.introspect(&state.endpoint, credential.as_deref());
+                    state.focus = GraphQLFocus::Query;
+                }
+                GraphQLFocus::Query => state.query.push('\n'),
+                GraphQLFocus::Variable(_) => self.run_graphql_query(),
+            },
+            KeyCode::Backspace => {
+                match state.focus {
+                    GraphQLFocus::Endpoint => state.endpoint.pop(),
+                    GraphQLFocus::Query => state.query.pop(),
+                    GraphQLFocus::Variable(idx) => {
+                        let name = state.variable_definitions().get(idx).map(|definition| definition.name.clone()).unwrap_or_default();
+                        state.variables.get_mut(&name).and_then(|value| value.pop())
+                    }
+                };
+                state.selected_completion = 0;
+            }
+            KeyCode::Char(c) if !control => {
+                match state.focus {
+                    GraphQLFocus::Endpoint => state.endpoint.push(c),
+                    GraphQLFocus::Query => state.query.push(c),
+                    GraphQLFocus::Variable(idx) => {
+                        if let Some(definition) = state.variable_definitions().get(idx) {
+                            state.variables.entry(definition.name.clone()).or_default().push(c);
+                        }
+                    }
+                }
+                state.selected_completion = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the explorer's query into a new block in the active pane, whose
+    /// JSON result the structured viewers can show.
+    fn run_graphql_query(&mut self) {
+        let AppMode::GraphQLExplorer(state) = &self.mode else {
+            return;
+        };
+        let state = state.clone();
+        if state.endpoint.is_empty() || state.query.trim().is_empty() {
+            self.notify("Enter an endpoint and a query to run".to_string());
+            return;
+        }
+        let variables = match schema::variables_json(&state.variable_definitions(), &state.variables) {
+            Ok(variables) => variables,
+            Err(e) => {
+                self.notify(e.to_string());
+                return;
+            }
+        };
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let block = Block::new(state.query.clone(), format!("Querying {}…\n", state.endpoint));
+        self.graphql.execute(block.id, &state.endpoint, self.config.graphql.credential.as_deref(), &state.query, variables);
+        pane.history.push(block);
+        self.graphql_draft = Some(state);
+        self.mode = AppMode::Normal;
+    }
+
+    /// Saves the explorer's query to Drive under its operation name.
+    fn save_graphql_query(&mut self) {
+        let AppMode::GraphQLExplorer(state) = &self.mode else {
+            return;
+        };
+        let Some(name) = graphql_explorer::operation_name(&state.query) else {
+            self.notify("Name the query, e.g. `query ActiveUsers { ... }`, to save it".to_string());
+            return;
+        };
+        let query = SavedQuery { name, endpoint: state.endpoint.clone(), query: state.query.clone(), variables: state.variables.clone() };
+        match graphql_explorer::save_query(&self.graphql_queries_dir(), &query) {
+            Ok(_) => self.notify(format!("Saved query {}", query.name)),
+            Err(e) => self.notify(format!("Failed to save query: {}", e)),
+        }
+    }
+
+    /// Replaces the explorer's query with the next saved one.
+    fn open_next_saved_query(&mut self) {
+        let queries = graphql_explorer::saved_queries(&self.graphql_queries_dir());
+        let credential = self.config.graphql.credential.clone();
+        let AppMode::GraphQLExplorer(state) = &mut self.mode else {
+            return;
+        };
+        if queries.is_empty() {
+            self.notify("No saved queries yet; Ctrl+S saves one".to_string());
+            return;
+        }
+        let idx = state.saved_idx.map_or(0, |idx| (idx + 1) % queries.len());
+        let saved = queries[idx].clone();
+        *state = GraphQLExplorerState {
+            endpoint: saved.endpoint,
+            query: saved.query,
+            variables: saved.variables,
+            focus: GraphQLFocus::Query,
+            selected_completion: 0,
+            saved_idx: Some(idx),
+        };
+        self.graphql.introspect(&state.endpoint, credential.as_deref());
+    }
+
+    /// Shows query results in the blocks that ran them.
+    fn sync_graphql(&mut self) {
+        for (block_id, result) in self.graphql.poll() {
+            let Some(block) = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == block_id) else {
+                continue;
+            };
+            match result {
+                Ok(data) => {
+                    block.output = format!("{}\n", serde_json::to_string_pretty(&data).unwrap_or_default());
+                    *block.structured.lock().unwrap() = Some(StructuredData::Json(data));
+                }
+                Err(e) => block.output = format!("{}\n", e),
+            }
+        }
+    }
+
+    /// Checks a pull request's branch out in the active pane, which must be
+    /// in a clone of its repository.
+    fn check_out_pull_request(&mut self, item: &GitHubItem) {
+        let Some(command) = item.checkout_command() else {
+            return;
+        };
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let cwd = pane.local_cwd().unwrap_or_default();
+        let remotes = Integration::new("git").execute(&["-C", &cwd.to_string_lossy(), "remote", "-v"]).unwrap_or_default();
+        if !crate::github::has_remote(&remotes, &item.repository) {
+            self.notify(format!("Open a pane in a clone of {} to check out {}", item.repository, item.reference));
+            return;
+        }
+        if let Err(e) = pane.run_command(&command) {
+            self.notify(format!("Failed to check out {}: {}", item.reference, e));
+        }
+    }
+
+    fn open_pull_request_picker(&mut self, pane_idx: usize, block_idx: usize) {
+        self.github.request(&self.config.github.credential, std::time::Instant::now());
+        if self.github.pull_requests().next().is_none() {
+            self.notify("No open pull requests found; check the GitHub token in the keychain".to_string());
+            return;
+        }
+        self.mode = AppMode::PullRequestPicker(PullRequestPickerState { pane_idx, block_idx, selected_idx: 0 });
+    }
+
+    fn handle_pull_request_picker_keys(&mut self, key: KeyEvent) {
+        let AppMode::PullRequestPicker(state) = &mut self.mode else {
+            return;
+        };
+        let count = self.github.pull_requests().count();
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(count.saturating_sub(1)),
+            KeyCode::Enter => {
+                let state = state.clone();
+                self.mode = AppMode::Normal;
+                let pull_request = self.github.pull_requests().nth(state.selected_idx).cloned();
+                let block = self.panes.get(state.pane_idx).and_then(|pane| pane.history.get(state.block_idx));
+                if let (Some(pull_request), Some(block)) = (pull_request, block) {
+                    let body = ShareContent::from_block(&block.command, &block.output).body;
+                    self.comment_on_pull_request(&pull_request, &body);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens the only location in a block's output in the editor, or lets
+    /// the user pick one when there are several.
+    fn open_block_locations(&mut self, pane_idx: usize, output: &str) {
+        let mut locations: Vec<FileLocation> = Vec::new();
+        for found in editor::find_locations(output) {
+            if !locations.contains(&found.location) {
+                locations.push(found.location);
+            }
+        }
+        match locations.len() {
+            0 => self.notify("No file locations in this block".to_string()),
+            1 => self.open_in_editor(pane_idx, &locations[0]),
+            _ => self.mode = AppMode::LocationPicker(LocationPickerState { pane_idx, locations, selected_idx: 0 }),
+        }
+    }
+
+    fn handle_location_picker_keys(&mut self, key: KeyEvent) {
+        let AppMode::LocationPicker(state) = &mut self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(state.locations.len().saturating_sub(1)),
+            KeyCode::Enter => {
+                let state = state.clone();
+                self.mode = AppMode::Normal;
+                if let Some(location) = state.locations.get(state.selected_idx) {
+                    self.open_in_editor(state.pane_idx, location);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts stepping through a block's stack traces at the first frame in
+    /// the user's code, showing library frames if there is none.
+    fn open_stack_trace(&mut self, pane_idx: usize, block_idx: usize) {
+        let Some(block) = self.panes.get_mut(pane_idx).and_then(|pane| pane.history.get_mut(block_idx)) else {
+            return;
+        };
+        let first = stacktrace::step_frame(&block.stack_traces, None, true, block.library_frames_folded).or_else(|| {
+            block.library_frames_folded = false;
+            stacktrace::step_frame(&block.stack_traces, None, true, false)
+        });
+        match first {
+            Some(frame_idx) => self.mode = AppMode::StackTrace(StackTraceState { pane_idx, block_idx, frame_idx }),
+            None => self.notify("No stack trace in this block".to_string()),
+        }
+    }
+
+    fn handle_stack_trace_keys(&mut self, key: KeyEvent) {
+        let AppMode::StackTrace(state) = &mut self.mode else {
+            return;
+        };
+        let Some(block) = self.panes.get_mut(state.pane_idx).and_then(|pane| pane.history.get_mut(state.block_idx)) else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        let step = |forward: bool, folded: bool| stacktrace::step_frame(&block.stack_traces, Some(state.frame_idx), forward, folded);
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Down | KeyCode::Char('n') | KeyCode::Char('j') => {
+                state.frame_idx = step(true, block.library_frames_folded).unwrap_or(state.frame_idx);
+            }
+            KeyCode::Up | KeyCode::Char('p') | KeyCode::Char('k') => {
+                state.frame_idx = step(false, block.library_frames_folded).unwrap_or(state.frame_idx);
+            }
+            KeyCode::Char('f') => {
+                let folded = !block.library_frames_folded;
+                // Folding may hide the selected frame; move to a visible one.
+                let frame_idx = step(true, folded).or_else(|| step(false, folded));
+                block.library_frames_folded = folded;
+                if let Some(frame_idx) = frame_idx {
+                    state.frame_idx = frame_idx;
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('e') => {
+                let location = stacktrace::frames(&block.stack_traces).nth(state.frame_idx).and_then(|frame| frame.location.clone());
+                let pane_idx = state.pane_idx;
+                match location {
+                    Some(location) => self.open_in_editor(pane_idx, &location),
+                    None => self.notify("This frame has no source location".to_string()),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Opens `location`, relative to the pane's directory, with the
+    /// configured editor. Terminal editors get a new pane.
+    fn open_in_editor(&mut self, pane_idx: usize, location: &FileLocation) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        if pane.ssh.session().is_some() {
+            self.notify(format!("{} is on another machine", location));
+            return;
+        }
+        let Some(path) = location.resolve(pane.local_cwd().as_deref()) else {
+            self.notify(format!("{} not found", location.path));
+            return;
+        };
+        self.launch_editor(&path, location);
+    }
+
+    /// Opens `path`, where `location` was found, with the configured editor.
+    fn launch_editor(&mut self, path: &Path, location: &FileLocation) {
+        let env_editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).ok();
+        let launch = editor::template(&self.config.open_in_editor, env_editor.as_deref())
+            .and_then(|(_, template)| editor::launch(&template, path, location));
+        match launch {
+            Ok(EditorLaunch::Terminal(command)) => {
+                self.open_pane(None);
+                if let Some(Err(e)) = self.panes.last_mut().map(|pane| pane.run_command(&command)) {
+                    self.notify(format!("Failed to open {}: {}", location, e));
+                }
+            }
+            Ok(launch) => {
+                if let Err(e) = editor::open(&launch) {
+                    self.notify(format!("Failed to open {}: {}", location, e));
+                }
+            }
+            Err(e) => self.notify(format!("Failed to open {}: {}", location, e)),
+        }
+    }
+
+    pub fn open_abbreviations(&mut self) {
+        self.mode = AppMode::Abbreviations(AbbreviationsState { selected_idx: 0, prompt: None });
+    }
+
+    fn handle_abbreviations_keys(&mut self, key: KeyEvent) {
+        let AppMode::Abbreviations(state) = &mut self.mode else {
+            return;
+        };
+        if state.prompt.is_some() {
+            self.handle_abbreviation_prompt_keys(key);
+            return;
+        }
+        let selected = self.abbreviations.iter().nth(state.selected_idx).map(|(name, expansion)| (name.to_string(), expansion.to_string()));
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => state.selected_idx = (state.selected_idx + 1).min(self.abbreviations.len().saturating_sub(1)),
+            KeyCode::Char('a') => state.prompt = Some(AbbreviationPrompt::Add(String::new())),
+            KeyCode::Enter | KeyCode::Char('e') => {
+                if let Some((name, expansion)) = selected {
+                    state.prompt = Some(AbbreviationPrompt::Edit { input: format!("{}={}", name, expansion), name });
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete if selected.is_some() => state.prompt = Some(AbbreviationPrompt::ConfirmDelete),
+            KeyCode::Char('i') => self.import_aliases(),
+            KeyCode::Char('x') => self.export_abbreviations(),
+            _ => {}
+        }
+    }
+
+    fn handle_abbreviation_prompt_keys(&mut self, key: KeyEvent) {
+        let AppMode::Abbreviations(state) = &mut self.mode else {
+            return;
+        };
+        let Some(prompt) = state.prompt.as_mut() else {
+            return;
+        };
+        let input = match prompt {
+            AbbreviationPrompt::Add(input) | AbbreviationPrompt::Edit { input, .. } => input,
+            AbbreviationPrompt::ConfirmDelete => {
+                state.prompt = None;
+                let selected = self.abbreviations.iter().nth(state.selected_idx).map(|(name, _)| name.to_string());
+                if let (KeyCode::Char('y'), Some(name)) = (key.code, selected) {
+                    self.abbreviations.remove(&name);
+                    state.selected_idx = state.selected_idx.min(self.abbreviations.len().saturating_sub(1));
+                    self.save_abbreviations();
+                }
+                return;
+            }
+        };
+        match key.code {
+            KeyCode::Esc => state.prompt = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Enter => {
+                let Some((name, expansion)) = input.split_once('=').map(|(name, expansion)| (name.trim().to_string(), expansion.to_string())) else {
+                    self.notify("Type the abbreviation as name=expansion".to_string());
+                    return;
+                };
+                let mut abbreviations = self.abbreviations.clone();
+                if let AbbreviationPrompt::Edit { name: old, .. } = prompt {
+                    abbreviations.remove(old);
+                }
+                if let Err(e) = abbreviations.insert(&name, &expansion) {
+                    self.notify(e.to_string());
+                    return;
+                }
+                state.prompt = None;
+                state.selected_idx = abbreviations.iter().position(|(other, _)| other == name).unwrap_or(0);
+                self.abbreviations = abbreviations;
+                self.save_abbreviations();
+            }
+            _ => {}
+        }
+    }
+
+    fn save_abbreviations(&mut self) {
+        if let Err(e) = self.abbreviations.save() {
+            self.notify(format!("Failed to save abbreviations: {}", e));
+        }
+    }
+
+    /// Adds the aliases from the user's shell startup files, keeping any
+    /// abbreviation already defined with the same name.
+    fn import_aliases(&mut self) {
+        let Some(home) = dirs::home_dir() else {
+            return;
+        };
+        let mut aliases = Vec::new();
+        for path in abbreviations::alias_files(&home) {
+            if let Ok(content) = std::fs::read_to_string(&path) {
+                aliases.extend(abbreviations::parse_aliases(&content));
+            }
+        }
+        let added = self.abbreviations.import(aliases);
+        self.save_abbreviations();
+        self.notify(format!("Imported {} aliases as abbreviations", added));
+    }
+
+    /// Writes the abbreviations as aliases for the configured shell, so they
+    /// also work outside the terminal.
+    fn export_abbreviations(&mut self) {
+        let shell = self.shell();
+        let path = abbreviations::export_path(&shell);
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, self.abbreviations.export(&shell)));
+        match written {
+            Ok(()) => self.notify(format!("Exported to {}; add `{}` to your shell's startup file", path.display(), abbreviations::source_line(&shell, &path))),
+            Err(e) => self.notify(format!("Failed to export abbreviations: {}", e)),
+        }
+    }
+
+    /// Expands the abbreviation before the cursor, if it is in command
+    /// position.
+    fn expand_abbreviation(&mut self) {
+        let (text, offset) = self.input_text_and_offset();
+        let Some((range, expansion)) = self.abbreviations.expansion_at(&text, offset) else {
+            return;
+        };
+        let cursor = range.start + expansion.len();
+        let expanded = format!("{}{}{}", &text[..range.start], expansion, &text[range.end..]);
+        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &expanded, AttrsList::new(Attrs::new()), Shaping::Advanced);
+        self.input_editor.set_cursor(input_cursor_at(&expanded, cursor));
+    }
+
+    /// Opens the file browser in the active pane's directory, over SFTP when
+    /// the pane is on another machine.
+    pub fn open_file_browser(&mut self) {
+        let pane = self.panes.get(self.active_pane_idx);
+        let root = match pane.and_then(|pane| pane.ssh.session()) {
+            Some(session) => {
+                let user = session.user.map(|user| format!("{}@", user)).unwrap_or_default();
+                let cwd = session.cwd.map_or_else(|| "/".to_string(), |cwd| cwd.to_string_lossy().into_owned());
+                format!("sftp://{}{}{}", user, session.host, cwd)
+            }
+            None => pane
+                .and_then(|pane| pane.local_cwd())
+                .or_else(dirs::home_dir)
+                .map_or_else(|| "/".to_string(), |cwd| cwd.to_string_lossy().into_owned()),
+        };
+        self.browse(&root);
+    }
+
+    /// Shows `root` in the file browser, keeping the browser as it was if
+    /// `root` cannot be listed.
+    fn browse(&mut self, root: &str) {
+        match FileTree::open(&self.mounts, root) {
+            Ok(tree) => {
+                let rows = tree.rows();
+                self.mode = AppMode::FileBrowser(FileBrowserState { tree, rows, selected_idx: 0, preview: None, prompt: None });
+                self.refresh_file_preview();
+            }
+            Err(e) => self.notify(format!("Failed to list {}: {}", root, e)),
+        }
+    }
+
+    fn refresh_file_preview(&mut self) {
+        let AppMode::FileBrowser(state) = &mut self.mode else {
+            return;
+        };
+        state.preview = state
+            .rows
+            .get(state.selected_idx)
+            .map(|row| browser::preview(&self.mounts, row, &mut self.syntax, &self.theme.colors.primary.background));
+    }
+
+    fn close_file_browser(&mut self) {
+        self.mode = AppMode::Normal;
+        self.syntax.close(browser::PREVIEW_DOCUMENT);
+    }
+
+    fn handle_file_browser_keys(&mut self, key: KeyEvent) {
+        let AppMode::FileBrowser(state) = &mut self.mode else {
+            return;
+        };
+        if state.prompt.is_some() {
+            self.handle_file_browser_prompt_keys(key);
+            return;
+        }
+        let Some(row) = state.rows.get(state.selected_idx).cloned() else {
+            match key.code {
+                KeyCode::Char('g') => state.prompt = Some(FileBrowserPrompt::Root(state.tree.root.clone())),
+                KeyCode::Esc => self.close_file_browser(),
+                _ => {}
+            }
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => self.close_file_browser(),
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.selected_idx = state.selected_idx.saturating_sub(1);
+                self.refresh_file_preview();
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.selected_idx = (state.selected_idx + 1).min(state.rows.len() - 1);
+                self.refresh_file_preview();
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') if row.is_dir => {
+                if row.expanded && key.code != KeyCode::Enter {
+                    return;
+                }
+                match state.tree.toggle(&self.mounts, &row.path) {
+                    Ok(()) => state.rows = state.tree.rows(),
+                    Err(e) => self.notify(format!("Failed to list {}: {}", row.path, e)),
+                }
+            }
+            KeyCode::Enter | KeyCode::Char('o') => self.open_browser_file(&row.path),
+            KeyCode::Left | KeyCode::Char('h') => {
+                if row.is_dir && row.expanded {
+                    if state.tree.toggle(&self.mounts, &row.path).is_ok() {
+                        state.rows = state.tree.rows();
+                    }
+                } else if let Some(parent) = state.rows[..state.selected_idx].iter().rposition(|other| other.depth + 1 == row.depth) {
+                    state.selected_idx = parent;
+                    self.refresh_file_preview();
+                }
+            }
+            KeyCode::Char('i') => {
+                let path = shellwords::escape(&row.path);
+                self.close_file_browser();
+                self.input_editor.insert_string(&path, None);
+            }
+            KeyCode::Char('r') => state.prompt = Some(FileBrowserPrompt::Rename(row.path)),
+            KeyCode::Char('c') if row.is_dir => self.notify("Only files can be copied".to_string()),
+            KeyCode::Char('c') => state.prompt = Some(FileBrowserPrompt::Copy(row.path)),
+            KeyCode::Char('d') | KeyCode::Delete => state.prompt = Some(FileBrowserPrompt::ConfirmDelete),
+            KeyCode::Char('f') if row.is_dir => self.notify("Only files can be formatted".to_string()),
+            KeyCode::Char('f') => self.format_browser_file(&row.path),
+            KeyCode::Char('g') => state.prompt = Some(FileBrowserPrompt::Root(state.tree.root.clone())),
+            KeyCode::Char('R') | KeyCode::F(5) => self.refresh_file_tree(None),
+            _ => {}
+        }
+    }
+
+    fn handle_file_browser_prompt_keys(&mut self, key: KeyEvent) {
+        let AppMode::FileBrowser(state) = &mut self.mode else {
+            return;
+        };
+        let Some(row) = state.rows.get(state.selected_idx).cloned() else {
+            state.prompt = None;
+            return;
+        };
+        let Some(prompt) = state.prompt.as_mut() else {
+            return;
+        };
+        let input = match prompt {
+            FileBrowserPrompt::Rename(input) | FileBrowserPrompt::Copy(input) | FileBrowserPrompt::Root(input) => input,
+            FileBrowserPrompt::ConfirmDelete => {
+                state.prompt = None;
+                if key.code == KeyCode::Char('y') {
+                    match self.mounts.remove(&row.path) {
+                        Ok(()) => self.refresh_file_tree(None),
+                        Err(e) => self.notify(format!("Failed to delete {}: {}", row.path, e)),
+                    }
+                }
+                return;
+            }
+            FileBrowserPrompt::ConfirmFormat(formatted) => {
+                let content = std::mem::take(&mut formatted.content);
+                state.prompt = None;
+                if key.code == KeyCode::Char('y') {
+                    match self.mounts.write(&row.path, content.as_bytes()) {
+                        Ok(()) => self.refresh_file_preview(),
+                        Err(e) => self.notify(format!("Failed to write {}: {}", row.path, e)),
+                    }
+                }
+                return;
+            }
+        };
+        match key.code {
+            KeyCode::Esc => state.prompt = None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Enter => {
+                let Some(prompt) = state.prompt.take() else {
+                    return;
+                };
+                match prompt {
+                    FileBrowserPrompt::Rename(to) if to != row.path => match self.mounts.rename(&row.path, &to) {
+                        Ok(()) => self.refresh_file_tree(Some(&to)),
+                        Err(e) => self.notify(format!("Failed to rename {}: {}", row.path, e)),
+                    },
+                    FileBrowserPrompt::Copy(to) if to != row.path => match self.mounts.copy(&row.path, &to) {
+                        Ok(()) => self.refresh_file_tree(Some(&to)),
+                        Err(e) => self.notify(format!("Failed to copy {}: {}", row.path, e)),
+                    },
+                    FileBrowserPrompt::Root(root) => self.browse(&root),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the file's formatter and shows what it would change before
+    /// anything is written.
+    fn format_browser_file(&mut self, path: &str) {
+        let content = match self.mounts.read(path).map(String::from_utf8) {
+            Ok(Ok(content)) => content,
+            Ok(Err(_)) => {
+                self.notify(format!("{} is not a text file", path));
+                return;
+            }
+            Err(e) => {
+                self.notify(format!("Failed to read {}: {}", path, e));
+                return;
+            }
+        };
+        let language = Path::new(path).extension().map(|extension| extension.to_string_lossy().into_owned());
+        let block = CodeBlock { content, language };
+        match formatter::format(&self.config.format, &block, Some(Path::new(path))) {
+            Ok(formatted) if formatted.unchanged() => self.notify(format!("{} is already formatted", path)),
+            Ok(formatted) => {
+                if let AppMode::FileBrowser(state) = &mut self.mode {
+                    state.prompt = Some(FileBrowserPrompt::ConfirmFormat(formatted));
+                }
+            }
+            Err(e) => self.notify(format!("Failed to format {}: {}", path, e)),
+        }
+    }
+
+    /// Lists the browser's directories again after a change, selecting
+    /// `select` if it is shown.
+    fn refresh_file_tree(&mut self, select: Option<&str>) {
+        let AppMode::FileBrowser(state) = &mut self.mode else {
+            return;
+        };
+        if let Err(e) = state.tree.refresh(&self.mounts) {
+            let message = format!("Failed to list {}: {}", state.tree.root, e);
+            self.notify(message);
+            return;
+        }
+        state.rows = state.tree.rows();
+        let selected = select.and_then(|path| state.rows.iter().position(|row| row.path == path));
+        state.selected_idx = selected.unwrap_or(state.selected_idx).min(state.rows.len().saturating_sub(1));
+        self.refresh_file_preview();
+    }
+
+    /// Opens a browsed file in the editor. Files on other filesystems are
+    /// copied to a temporary directory first, so edits stay local.
+    fn open_browser_file(&mut self, path: &str) {
+        let location = FileLocation { path: path.to_string(), line: 1, column: None };
+        if !self.mounts.is_mounted(path) {
+            self.launch_editor(Path::new(path), &location);
+            return;
+        }
+        let name = Path::new(path).file_name().map_or_else(|| "file".into(), |name| name.to_os_string());
+        let local = std::env::temp_dir().join(format!("warpish-browser-{}", Uuid::new_v4())).join(name);
+        let copied = self.mounts.read(path).and_then(|data| {
+            std::fs::create_dir_all(local.parent().expect("joined above"))?;
+            std::fs::write(&local, data)
+        });
+        match copied {
+            Ok(()) => {
+                self.notify(format!("Opened a copy of {}", path));
+                self.launch_editor(&local, &location);
+            }
+            Err(e) => self.notify(format!("Failed to open {}: {}", path, e)),
+        }
+    }
+
+    /// Posts `body`, with secrets redacted as for sharing, as a comment on
+    /// the pull request.
+    fn comment_on_pull_request(&self, pull_request: &GitHubItem, body: &str) {
+        let redactor = match Redactor::new(&self.config.share.redaction_patterns) {
+            Ok(redactor) => redactor,
+            Err(e) => {
+                self.notify(format!("Not posting the comment: {}", e));
+                return;
+            }
+        };
+        let (body, redactions) = redactor.redact(body);
+        let credential = self.config.github.credential.clone();
+        let (subject, reference) = (pull_request.id.clone(), pull_request.reference.clone());
+        let notifications = self.notifications.clone();
+        thread::spawn(move || {
+            let message = match crate::github::add_comment(&credential, &subject, &body) {
+                Ok(url) if redactions > 0 => format!("Commented on {} with {} secret(s) redacted: {}", reference, redactions, url),
+                Ok(url) => format!("Commented on {}: {}", reference, url),
+                Err(e) => format!("Failed to comment on {}: {}", reference, e),
+            };
+            notifications.lock().unwrap().push(message);
+        });
+    }
+
+    /// Opens a pane into `distro` and focuses it, in the active pane's
+    /// directory when the distro can see it.
+    pub fn open_wsl_pane(&mut self, distro: &str) {
+        let Some(event_proxy) = self.event_proxy.clone() else {
+            return;
+        };
+        let active = self.panes.get(self.active_pane_idx);
+        let (cols, rows) = active.map(Pane::size).unwrap_or((80, 24));
+        let cwd = active
+            .and_then(Pane::local_cwd)
+            .and_then(|cwd| crate::wsl::to_wsl_path(&cwd.to_string_lossy(), distro))
+            .map(PathBuf::from);
+        let mut pane = Pane::new_wsl(cols, rows, distro, cwd.as_deref(), event_proxy);
+        pane.set_encodings(configured_encodings(&self.config.encoding));
+        self.panes.push(pane);
+        self.active_pane_idx = self.panes.len() - 1;
+        self.notify(format!("Opened a pane in WSL ({})", distro));
+    }
+
+    /// Closes the active pane, first asking for confirmation while a job is
+    /// running in it.
+    pub fn close_active_pane(&mut self) {
+        let pane_idx = self.active_pane_idx;
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        if self.config.shutdown.confirm_running_jobs && self.panes.len() > 1 {
+            if let Some(job) = pane.running_job() {
+                self.mode = AppMode::ConfirmClose(ConfirmCloseState { pane_idx: Some(pane_idx), jobs: vec![job] });
+                return;
+            }
+        }
+        self.close_pane(pane_idx);
+    }
+
+    /// Closes a pane. A scratch pane with blocks worth keeping first asks
+    /// which of them to save to Drive.
+    fn close_pane(&mut self, pane_idx: usize) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        if pane.scratch.is_some() {
+            let block_indices: Vec<usize> = pane
+                .history
+                .iter()
+                .enumerate()
+                .filter(|(_, block)| is_interesting(block))
+                .map(|(idx, _)| idx)
+                .collect();
+            if !block_indices.is_empty() {
+                self.mode = AppMode::ScratchClose(ScratchCloseState {
+                    pane_idx,
+                    selected: vec![true; block_indices.len()],
+                    block_indices,
+                    cursor: 0,
+                });
+                return;
+            }
+        }
+        self.remove_pane(pane_idx);
+    }
+
+    /// Drops a pane, which for scratch panes also deletes the temp directory.
+    fn remove_pane(&mut self, pane_idx: usize) {
+        if self.panes.len() <= 1 {
+            self.notify("Cannot close the last pane".to_string());
+            return;
+        }
+        let pane = self.panes.remove(pane_idx);
+        // Dropping a console closes its connection.
+        self.ws_consoles.remove(&pane.id);
+        if self.active_pane_idx >= pane_idx && self.active_pane_idx > 0 {
+            self.active_pane_idx -= 1;
+        }
+    }
+
+    /// Handles the requests the listeners accepted since the last tick.
+    fn sync_listener_events(&mut self) {
+        while let Ok(event) = self.listener_events.try_recv() {
+            if let Err(e) = self.handle_event(event) {
+                log::warn!("Failed to handle a listener request: {}", e);
+            }
+        }
+    }
+
+    /// Journals the panes, their recent blocks and the unsent input every
+    /// few seconds, with secrets redacted and nothing from privacy-zone,
+    /// locked or SSH panes. Waits while the last run's journal is being
+    /// offered, so it isn't replaced before the user decides.
+    fn sync_journal(&mut self) {
+        let now = std::time::Instant::now();
+        if matches!(self.mode, AppMode::RestoreSession(_)) || !self.journal.is_due(now) {
+            return;
+        }
+        let redactor = match Redactor::new(&self.config.share.redaction_patterns) {
+            Ok(redactor) => redactor,
+            Err(e) => {
+                log::warn!("Not journaling the session: {}", e);
+                return;
+            }
+        };
+        let sensitive = |pane: &Pane| pane.privacy_zone || pane.ssh.session().is_some() || self.idle_monitor.is_locked(pane.id);
+        let panes = self
+            .panes
+            .iter()
+            .filter(|pane| pane.scratch.is_none())
+            .map(|pane| JournaledPane {
+                cwd: pane.local_cwd(),
+                profile: pane.profile.clone(),
+                blocks: if sensitive(pane) {
+                    Vec::new()
+                } else {
+                    pane.history[pane.history.len().saturating_sub(MAX_JOURNALED_BLOCKS)..]
+                        .iter()
+                        .map(|block| {
+                            let tail = JournaledBlock::new(&block.command, &block.output);
+                            JournaledBlock { command: redactor.redact(&tail.command).0, output: redactor.redact(&tail.output).0 }
+                        })
+                        .collect()
+                },
+            })
+            .collect();
+        let input = match self.panes.get(self.active_pane_idx) {
+            Some(pane) if sensitive(pane) => String::new(),
+            _ => redactor.redact(&self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n")).0,
+        };
+        let snapshot = SessionJournal {
+            saved_at: chrono::Utc::now().timestamp(),
+            panes,
+            active_pane: self.active_pane_idx,
+            input,
+        };
+        if let Err(e) = self.journal.write(snapshot, now) {
+            log::warn!("Failed to journal the session: {}", e);
+        }
+    }
+
+    fn handle_restore_session_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::RestoreSession(state) = &self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let journal = state.journal.clone();
+                self.mode = AppMode::Normal;
+                self.restore_session(&journal);
+            }
+            KeyCode::Char('n') | KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Starts sampling the app for a flamegraph, for `perf.profile_seconds`.
+    fn start_profile_capture(&mut self) {
+        if self.profile_capture.is_some() {
+            self.notify("A flamegraph is already being captured".to_string());
+            return;
+        }
+        let seconds = self.config.perf.profile_seconds;
+        match ProfileCapture::start(std::time::Duration::from_secs(seconds)) {
+            Ok(capture) => {
+                self.profile_capture = Some(capture);
+                self.notify(format!("Capturing a flamegraph for {}s", seconds));
+            }
+            Err(e) => self.notify(format!("Failed to start profiling: {}", e)),
+        }
+    }
+
+    /// Writes the flamegraph once the capture's interval is over.
+    fn sync_profile_capture(&mut self) {
+        if !self.profile_capture.as_ref().is_some_and(|capture| capture.is_done(std::time::Instant::now())) {
+            return;
+        }
+        let Some(capture) = self.profile_capture.take() else {
+            return;
+        };
+        match capture.finish(&default_profile_dir()) {
+            Ok(path) => self.notify(format!("Flamegraph saved to {}", path.display())),
+            Err(e) => self.notify(format!("Failed to save the flamegraph: {}", e)),
+        }
+    }
+
+    /// Offers the crash reports found at startup, after the offer to restore
+    /// the session if there is one.
+    fn show_crash_reports(&mut self) {
+        if matches!(self.mode, AppMode::Normal) && !self.crash_reports.is_empty() {
+            self.mode = AppMode::CrashReport(CrashReportState { reports: std::mem::take(&mut self.crash_reports) });
+        }
+    }
+
+    fn handle_crash_report_keys(&mut self, key: KeyEvent) {
+        let AppMode::CrashReport(state) = &self.mode else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char('v') | KeyCode::Enter => {
+                let path = state.reports[0].path.display().to_string();
+                self.launch_editor(Path::new(&path), &FileLocation { path: path.clone(), line: 1, column: None });
+            }
+            KeyCode::Char('s') => {
+                let Some(url) = self.config.crash_reports.submit_url.clone() else {
+                    self.notify("Set crash_reports.submit_url to submit crash reports".to_string());
+                    return;
+                };
+                let report = state.reports[0].report.clone();
+                self.dismiss_crash_reports();
+                let notifications = self.notifications.clone();
+                thread::spawn(move || {
+                    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                        Ok(runtime) => runtime,
+                        Err(e) => {
+                            log::error!("Failed to start crash report runtime: {}", e);
+                            return;
+                        }
+                    };
+                    let message = match runtime.block_on(crash::submit(&report, &url)) {
+                        Ok(()) => "Crash report submitted, thank you".to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    notifications.lock().unwrap().push(message);
+                });
+            }
+            KeyCode::Char('n') | KeyCode::Esc => self.dismiss_crash_reports(),
+            _ => {}
+        }
+    }
+
+    /// Stops offering the reports shown; they stay in the crash directory.
+    fn dismiss_crash_reports(&mut self) {
+        if let AppMode::CrashReport(state) = std::mem::replace(&mut self.mode, AppMode::Normal) {
+            for pending in &state.reports {
+                if let Err(e) = crash::mark_seen(&pending.path) {
+                    log::warn!("Failed to mark {} seen: {}", pending.path.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Replaces the panes opened at startup with the journaled ones, each
+    /// in its directory with its recent blocks, and puts back the unsent
+    /// input.
+    fn restore_session(&mut self, journal: &SessionJournal) {
+        let Some(event_proxy) = self.event_proxy.clone() else {
+            return;
+        };
+        let (cols, rows) = self.panes.get(self.active_pane_idx).map(Pane::size).unwrap_or((80, 24));
+        let mut restored = Vec::new();
+        for journaled in &journal.panes {
+            let profile = journaled.profile.as_deref().and_then(|name| crate::profiles::find(&self.config.profiles, name));
+            let mut pane = match (profile, &journaled.cwd) {
+                (Some(profile), _) => Pane::new_from_profile(cols, rows, profile, &self.shell(), event_proxy.clone()),
+                (None, Some(cwd)) if cwd.is_dir() => Pane::new_in_dir(cols, rows, &self.shell(), cwd, event_proxy.clone()),
+                (None, _) => Pane::new(cols, rows, &self.shell(), event_proxy.clone()),
+            };
+            pane.structured_capture = self.panes.iter().find_map(|pane| pane.structured_capture.clone());
+            pane.set_encodings(configured_encodings(&self.config.encoding));
+            pane.history.extend(journaled.blocks.iter().map(|block| Block::new(block.command.clone(), block.output.clone())));
+            restored.push(pane);
+        }
+        if restored.is_empty() {
+            return;
+        }
+        let count = restored.len();
+        let mut fresh = std::mem::replace(&mut self.panes, restored);
+        self.active_pane_idx = journal.active_pane.min(count - 1);
+        let timeout = std::time::Duration::from_millis(self.config.shutdown.hangup_timeout_ms);
+        let mut shells: Vec<_> = fresh.iter_mut().map(Pane::shell_mut).collect();
+        if let Err(e) = hang_up(&mut shells, timeout) {
+            log::warn!("Failed to stop the shells opened at startup: {}", e);
+        }
+        if !journal.input.is_empty() {
+            self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &journal.input, AttrsList::new(Attrs::new()), Shaping::Advanced);
+        }
+        self.notify(format!("Restored {} pane(s) from the previous session", count));
+    }
+
+    fn handle_confirm_close_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::ConfirmClose(state) = &self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let pane_idx = state.pane_idx;
+                self.mode = AppMode::Normal;
+                match pane_idx {
+                    Some(pane_idx) => self.close_pane(pane_idx),
+                    None => self.quit(),
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_confirm_webhook_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::ConfirmWebhook(state) = &self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => {
+                let (name, command) = (state.name.clone(), state.command.clone());
+                self.mode = AppMode::Normal;
+                if let Some(pane) = self.panes.get_mut(self.active_pane_idx) {
+                    pane.run_command(&command)?;
+                    self.notify(format!("Webhook ran workflow '{}'", name));
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Stops everything before the process exits: background fetches are
+    /// cancelled, the session and the unsent command line are saved, the
+    /// database is flushed and the shells are hung up on. Running it twice
+    /// does nothing the second time.
+    pub fn shutdown(&mut self) {
+        if self.background.is_cancelled() {
+            return;
+        }
+        self.background.cancel();
+        self.jobs.kill_detached();
+
+        let tabs = self
+            .panes
+            .iter()
+            .map(|pane| pane.local_cwd().map(|cwd| cwd.display().to_string()).unwrap_or_default())
+            .collect();
+        let profiles = self.panes.iter().map(|pane| pane.profile.clone()).collect();
+        let session = Session { id: LAST_SESSION_ID, name: "last".to_string(), tabs, profiles };
+        if let Err(e) = session.save() {
+            log::warn!("Failed to save the session: {}", e);
+        }
+        let draft = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        if let Err(e) = crate::db::save_draft(&self.db_conn, INPUT_DOCUMENT, &draft, chrono::Utc::now().timestamp()) {
+            log::warn!("Failed to save the input draft: {}", e);
+        }
+        if let Err(e) = crate::db::flush(&self.db_conn) {
+            log::warn!("Failed to flush the database: {}", e);
+        }
+        // Nothing to recover from after a clean exit.
+        if let Err(e) = self.journal.finish() {
+            log::warn!("Failed to remove the session journal: {}", e);
+        }
+
+        let timeout = std::time::Duration::from_millis(self.config.shutdown.hangup_timeout_ms);
+        let mut shells: Vec<_> = self.panes.iter_mut().map(Pane::shell_mut).collect();
+        match hang_up(&mut shells, timeout) {
+            Ok(termination) if termination.killed > 0 => {
+                log::info!("Killed {} shell(s) that ignored SIGHUP", termination.killed);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Failed to stop the shells: {}", e),
+        }
+    }
+
+    fn handle_scratch_close_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::ScratchClose(state) = &mut self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Up => state.cursor = state.cursor.saturating_sub(1),
+            KeyCode::Down => state.cursor = (state.cursor + 1).min(state.selected.len().saturating_sub(1)),
+            KeyCode::Char(' ') => {
+                if let Some(selected) = state.selected.get_mut(state.cursor) {
+                    *selected = !*selected;
+                }
+            }
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Enter => {
+                let state = state.clone();
+                self.mode = AppMode::Normal;
+                self.save_scratch_blocks(&state);
+                self.remove_pane(state.pane_idx);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Saves the selected blocks of a closing scratch pane as one notebook.
+    fn save_scratch_blocks(&mut self, state: &ScratchCloseState) {
+        let Some(pane) = self.panes.get(state.pane_idx) else {
+            return;
+        };
+        let blocks: Vec<_> = state
+            .block_indices
+            .iter()
+            .zip(&state.selected)
+            .filter(|(_, selected)| **selected)
+            .filter_map(|(idx, _)| pane.history.get(*idx))
+            .collect();
+        if blocks.is_empty() {
+            return;
+        }
+        let name = format!("scratch-{}", chrono::Local::now().format("%Y-%m-%d-%H%M"));
+        let notebook = blocks_to_notebook(&blocks, name);
+        match self.drive_manager.save_notebook(&notebook) {
+            Ok(path) => self.notify(format!("Saved {} block(s) to {}", blocks.len(), path.display())),
+            Err(e) => self.notify(format!("Failed to save scratch blocks: {}", e)),
+        }
+    }
+
+    /// Handles a key before any mode sees it, returning whether the key was
+    /// used up. A key press on a locked pane only unlocks it and is never
+    /// forwarded; any other press counts as activity. In normal mode Alt+P
+    /// marks the pane as a privacy zone, Alt+F folds the current block and
+    /// Alt+Shift+F every block. The window calls this too, so panes lock and
+    /// fold the same in both front ends.
+    pub fn handle_global_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        if let Some(pane_id) = self.panes.get(self.active_pane_idx).map(|pane| pane.id) {
+            if self.idle_monitor.is_locked(pane_id) {
+                if let Err(e) = self.idle_monitor.unlock(pane_id, &SystemAuthenticator, std::time::Instant::now()) {
+                    self.notify(format!("Pane remains locked: {}", e));
+                }
+                return true;
+            }
+        }
+        self.idle_monitor.record_activity(std::time::Instant::now());
+
+        if !matches!(self.mode, AppMode::Normal) {
+            return false;
+        }
+        match (code, modifiers) {
+            (KeyCode::Char('p'), KeyModifiers::ALT) => self.toggle_privacy_zone(),
+            (KeyCode::Char('f'), KeyModifiers::ALT) => self.toggle_fold_current(),
+            (KeyCode::Char('f' | 'F'), _) if modifiers == KeyModifiers::ALT | KeyModifiers::SHIFT => self.toggle_fold_all(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<(), AppError> {
+        if self.handle_global_key(key_event.code, key_event.modifiers) {
+            return Ok(());
+        }
+
+        // Alt+C takes back the last input sent to the agent as a question.
+        if key_event.code == KeyCode::Char('c') && key_event.modifiers == KeyModifiers::ALT && self.detected_query.is_some() {
+            self.run_detected_query_as_command();
+            return Ok(());
+        }
+
+        match self.mode {
+            AppMode::Normal => self.handle_normal_mode_keys(key_event)?,
+            AppMode::Search(_) => self.handle_search_keys(key_event)?,
+            AppMode::AiPrompt => self.handle_ai_prompt_mode_keys(key_event)?,
+            AppMode::ActivityReport(_) => self.handle_activity_report_keys(key_event)?,
+            AppMode::AgentApproval(_) => self.handle_agent_approval_keys(key_event)?,
+            AppMode::ScratchClose(_) => self.handle_scratch_close_keys(key_event)?,
+            AppMode::ConfirmClose(_) => self.handle_confirm_close_keys(key_event)?,
+            AppMode::ConfirmWebhook(_) => self.handle_confirm_webhook_keys(key_event)?,
+            AppMode::RestoreSession(_) => self.handle_restore_session_keys(key_event)?,
+            AppMode::CrashReport(_) => self.handle_crash_report_keys(key_event),
+            AppMode::DirHistory(_) => self.handle_dir_history_keys(key_event)?,
+            AppMode::CodeReview(_) => self.handle_code_review_keys(key_event)?,
+            AppMode::PermissionPrompt(_) => self.handle_permission_prompt_keys(key_event)?,
+            AppMode::PermissionAudit(_) => self.handle_permission_audit_keys(key_event)?,
+            AppMode::Survey(_) => self.handle_survey_keys(key_event)?,
+            AppMode::WorkflowFill(_) => self.handle_workflow_fill_keys(key_event)?,
+            AppMode::BlockSelect(_) => self.handle_block_select_keys(key_event)?,
+            AppMode::ShareActivity(_) => self.handle_share_activity_keys(key_event)?,
+            AppMode::Scrub(_) => self.handle_scrub_keys(key_event),
+            AppMode::FailureJump(_) => self.handle_failure_jump_keys(key_event),
+            AppMode::Ollama(_) => self.handle_ollama_keys(key_event),
+            AppMode::EncodingPicker(_) => self.handle_encoding_picker_keys(key_event),
+            AppMode::WslPicker(_) => self.handle_wsl_picker_keys(key_event),
+            AppMode::NewPane(_) => self.handle_new_pane_keys(key_event),
+            AppMode::WatchGlob(_) => self.handle_watch_glob_keys(key_event),
+            AppMode::Jobs(_) => self.handle_jobs_keys(key_event),
+            AppMode::Ports(_) => self.handle_ports_keys(key_event),
+            AppMode::Docker(_) => self.handle_docker_keys(key_event),
+            AppMode::PullRequestPicker(_) => self.handle_pull_request_picker_keys(key_event),
+            AppMode::LocationPicker(_) => self.handle_location_picker_keys(key_event),
+            AppMode::StackTrace(_) => self.handle_stack_trace_keys(key_event),
+            AppMode::GraphQLExplorer(_) => self.handle_graphql_keys(key_event),
+            AppMode::FileBrowser(_) => self.handle_file_browser_keys(key_event),
+            AppMode::Abbreviations(_) => self.handle_abbreviations_keys(key_event),
+            AppMode::Quiz(_) => self.handle_quiz_keys(key_event),
+            AppMode::ExplainCommand(_) => self.handle_explain_command_keys(key_event),
+            AppMode::Notebook(_) => self.handle_notebook_keys(key_event),
+            AppMode::Refactor(_) => self.handle_refactor_keys(key_event),
+            AppMode::DiffReview(_) => self.handle_diff_review_keys(key_event),
+            AppMode::WebSearch(_) => self.handle_web_search_keys(key_event),
+            AppMode::AgentManagement(_) => self.handle_agent_management_keys(key_event)?,
+            AppMode::BlockMenu(_) => self.handle_block_menu_keys(key_event)?,
+            AppMode::Agent(_) => self.handle_agent_keys(key_event),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_normal_mode_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => self.request_quit(),
+            KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => self.open_search(""),
+            KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => self.open_search(">"),
+            KeyCode::Char('g') if key.modifiers == KeyModifiers::CONTROL => self.enter_ai_prompt_mode(),
+            KeyCode::Char('s') if key.modifiers == KeyModifiers::ALT => self.open_scratch_pane(),
+            KeyCode::Char('w') if key.modifiers == KeyModifiers::ALT => self.close_active_pane(),
+            KeyCode::Char('n') if key.modifiers == KeyModifiers::ALT => self.mode = AppMode::NewPane(NewPaneMenuState { selected_idx: 0 }),
+            KeyCode::Char('b') if key.modifiers == KeyModifiers::ALT => self.open_block_select(),
+            KeyCode::Char('t') if key.modifiers == KeyModifiers::ALT => self.open_scrubber(),
+            KeyCode::Char('j') if key.modifiers == KeyModifiers::ALT => self.jump_to_failure(),
+            KeyCode::Char('k') if key.modifiers == KeyModifiers::ALT => self.mode = AppMode::PermissionAudit(PermissionAuditState { selected_idx: 0 }),
+            KeyCode::Char('o') if key.modifiers == KeyModifiers::ALT => self.open_ports_panel(),
+            // With an empty command line, Alt+arrows walk the directory history as in fish.
+            KeyCode::Left if key.modifiers == KeyModifiers::ALT && self.input_is_empty() => self.navigate_dir_history(false)?,
+            KeyCode::Right if key.modifiers == KeyModifiers::ALT && self.input_is_empty() => self.navigate_dir_history(true)?,
+            KeyCode::Down if key.modifiers == KeyModifiers::ALT && self.input_is_empty() => self.open_dir_history_menu(),
+            KeyCode::Char(c @ '1'..='9') if key.modifiers == KeyModifiers::ALT && self.input_is_empty() && !self.directory_suggestions.is_empty() => {
+                self.insert_directory_suggestion(c as usize - '1' as usize)
+            }
+            KeyCode::Enter if key.modifiers == KeyModifiers::ALT && !self.input_is_empty() => self.run_input_detached(),
+            KeyCode::Enter if key.modifiers.is_empty() && !self.completions_manager.ui.is_visible && self.hold_kube_command() => {}
+            KeyCode::Enter if key.modifiers.is_empty() && !self.completions_manager.ui.is_visible && self.active_ws_console().is_some() => self.send_ws_console_input(),
+            KeyCode::Enter if key.modifiers.is_empty() && !self.completions_manager.ui.is_visible && self.input_ws_console_url().is_some() => self.open_ws_console(),
+            KeyCode::Enter if key.modifiers.is_empty() && !self.completions_manager.ui.is_visible && self.input_is_http_request() => self.run_http_input(),
+            _ => {
+                self.handle_input(key, &mut Clipboard::new().unwrap());
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the search overlay with `query` already typed; an empty query
+    /// searches history, and a prefix such as `>` picks another domain.
+    pub fn open_search(&mut self, query: &str) {
+        self.mode = AppMode::Search(SearchState { query: query.to_string(), selected_idx: 0, results: Vec::new() });
+        self.refresh_search();
+    }
+
+    /// Re-runs the overlay's query, loading only the queried domain's data.
+    /// History is matched off the UI thread and shown by `sync_search`.
+    fn refresh_search(&mut self) {
+        let AppMode::Search(state) = &self.mode else {
+            return;
+        };
+        let query = state.query.clone();
+        let mut sources = SearchSources::default();
+        // A newer query abandons the history search still running.
+        self.pending_search = None;
+        let (domain, text) = SearchDomain::parse(&query);
+        match domain {
+            SearchDomain::History => {
+                let history = crate::db::get_all_history(&mut self.db_conn).unwrap_or_default();
+                self.pending_search = Some(search_history(history, text));
+                return;
+            }
+            SearchDomain::Actions => {
+                self.github.request(&self.config.github.credential, std::time::Instant::now());
+                sources.palette = self.palette_items();
+                sources.palette_ranking = PaletteRanking {
+                    pinned: self.config.palette.pinned.clone(),
+                    recent: self.palette_recent.clone(),
+                };
+            }
+            SearchDomain::Files => {}
+            SearchDomain::Blocks => {
+                // Blocks in locked privacy zones stay hidden until the pane is unlocked.
+                sources.blocks = self
+                    .panes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pane)| !self.idle_monitor.is_locked(pane.id))
+                    .flat_map(|(pane_idx, pane)| {
+                        pane.history.iter().enumerate().map(move |(block_idx, block)| (pane_idx, block_idx, block))
+                    })
+                    .collect();
+            }
+            SearchDomain::Settings => sources.config = Some(&self.config),
+        }
+        let results = search(&query, &sources);
+        if let AppMode::Search(state) = &mut self.mode {
+            state.results = results;
+            state.selected_idx = 0;
+        }
+    }
+
+    /// The built-in actions, the launch profiles, the workflows in Drive, the
+    /// prompt library and the user's pull requests and issues on GitHub.
+    fn palette_items(&self) -> Vec<PaletteItem> {
+        let actions = APP_ACTIONS.iter().map(|(name, description, action)| PaletteItem::Action {
+            name: name.to_string(),
+            description: description.to_string(),
+            action: action.to_string(),
+        });
+        let shell = self.shell();
+        let profiles = self.config.profiles.iter().map(|profile| PaletteItem::Profile {
+            name: profile.name.clone(),
+            description: crate::profiles::description(profile, &shell),
+        });
+        actions
+            .chain(profiles)
+            .chain(self.drive_manager.workflows().into_iter().map(PaletteItem::Workflow))
+            .chain(self.drive_manager.notebooks().into_iter().map(PaletteItem::Notebook))
+            .chain(crate::agent::prompts::all(self.drive_manager.prompts()).into_iter().map(PaletteItem::Prompt))
+            .chain(self.github.items().iter().cloned().map(PaletteItem::GitHub))
+            .collect()
+    }
+
+    /// Shows the history search's results once they are matched.
+    fn sync_search(&mut self) {
+        let Some(results) = self.pending_search.as_ref().and_then(PendingSearch::try_take) else {
+            return;
+        };
+        self.pending_search = None;
+        if let AppMode::Search(state) = &mut self.mode {
+            state.results = results;
+            state.selected_idx = 0;
+        }
+    }
+
+    fn handle_search_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::Search(state) = &mut self.mode else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => {
+                if state.selected_idx + 1 < state.results.len() {
+                    state.selected_idx += 1;
+                }
+            }
+            // Tab switches domain, keeping the text typed so far.
+            KeyCode::Tab => {
+                let (domain, text) = SearchDomain::parse(&state.query);
+                let prefix = domain.next().prefix().map(String::from).unwrap_or_default();
+                state.query = format!("{}{}", prefix, text);
+                self.refresh_search();
+            }
+            KeyCode::Enter => {
+                if let Some(result) = state.results.get(state.selected_idx).cloned() {
+                    self.mode = AppMode::Normal;
+                    self.activate_search_result(result.target)?;
+                }
+            }
+            KeyCode::Char('p') if key.modifiers == KeyModifiers::CONTROL => {
+                if let Some(SearchTarget::Palette(item)) = state.results.get(state.selected_idx).map(|result| &result.target) {
+                    let key = item.key();
+                    self.toggle_palette_pin(key);
+                    self.refresh_search();
+                }
+            }
+            KeyCode::Backspace => {
+                state.query.pop();
+                self.refresh_search();
+            }
+            KeyCode::Char(c) => {
+                state.query.push(c);
+                self.refresh_search();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Pins a palette item as a favorite, or unpins it, and saves the
+    /// favorites to the user config.
+    fn toggle_palette_pin(&mut self, key: String) {
+        let pinned = &mut self.config.palette.pinned;
+        match pinned.iter().position(|pinned| *pinned == key) {
+            Some(idx) => {
+                pinned.remove(idx);
+            }
+            None => pinned.push(key),
+        }
+        let value = toml::Value::Array(pinned.iter().cloned().map(toml::Value::String).collect());
+        let saved = crate::config::layers::user_config_file()
+            .ok_or_else(|| AppError::Config("No config directory".to_string()))
+            .and_then(|path| crate::config::layers::write_value(&path, "palette.pinned", value));
+        if let Err(e) = saved {
+            self.notify(format!("Favorites kept for this session only: {}", e));
+        }
+    }
+
+    /// Moves a palette item to the front of the recently used list.
+    fn record_palette_use(&mut self, item: &PaletteItem) {
+        let key = item.key();
+        self.palette_recent.retain(|recent| *recent != key);
+        self.palette_recent.insert(0, key.clone());
+        self.palette_recent.truncate(MAX_PALETTE_RECENT);
+        if let Err(e) = crate::db::record_palette_use(&self.db_conn, &key, chrono::Utc::now().timestamp()) {
+            log::warn!("Failed to record palette use: {}", e);
+        }
+    }
+
+    fn activate_search_result(&mut self, target: SearchTarget) -> Result<(), AppError> {
+        if let SearchTarget::Palette(item) = &target {
+            self.record_palette_use(item);
+        }
+        match target {
+            SearchTarget::Command(command) => {
+                self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &command, AttrsList::new(Attrs::new()), Shaping::Advanced);
+            }
+            SearchTarget::Palette(PaletteItem::Action { action, .. }) => self.run_palette_action(&action)?,
+            SearchTarget::Palette(PaletteItem::Profile { name, .. }) => self.open_pane(Some(&name)),
+            SearchTarget::Palette(PaletteItem::GitHub(item)) => match item.kind {
+                GitHubItemKind::PullRequest => self.check_out_pull_request(&item),
+                GitHubItemKind::Issue => {
+                    if let Err(e) = webbrowser::open(&item.url) {
+                        self.notify(format!("Failed to open {}: {}", item.url, e));
+                    }
+                }
+            },
+            SearchTarget::Palette(PaletteItem::Workflow(workflow)) if !workflow.arguments.is_empty() => {
+                self.mode = AppMode::WorkflowFill(WorkflowFillState::new(workflow));
+            }
+            SearchTarget::Palette(PaletteItem::Workflow(workflow)) => {
+                self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &workflow.command, AttrsList::new(Attrs::new()), Shaping::Advanced);
+            }
+            SearchTarget::Palette(PaletteItem::Notebook(notebook)) => self.open_notebook(notebook),
+            SearchTarget::Palette(PaletteItem::Prompt(prompt)) => self.ask_with_prompt(&prompt),
+            SearchTarget::File(path) => self.input_editor.insert_string(&path, None),
+            SearchTarget::Block { pane_idx, block_idx } => {
+                self.active_pane_idx = pane_idx;
+                self.open_block_menu(block_idx, None);
+            }
+            SearchTarget::Setting { path, value, source } => {
+                self.notify(format!("{} = {} (from {})", path, value, source));
+            }
+            SearchTarget::Category(name) => self.open_search(&format!(">{}: ", name)),
+        }
+        Ok(())
+    }
+
+    fn handle_workflow_fill_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::WorkflowFill(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let idx = state.selected_idx;
+        let Some(argument) = state.workflow.arguments.get(idx).cloned() else {
+            self.mode = AppMode::Normal;
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up | KeyCode::BackTab => state.select(idx.saturating_sub(1)),
+            // Tab takes the first matching path before moving on.
+            KeyCode::Tab if !state.suggestions.is_empty() && state.suggestions[0] != state.values[idx] => {
+                state.values[idx] = state.suggestions[0].clone();
+                state.refresh_suggestions();
+            }
+            KeyCode::Down | KeyCode::Tab => state.select(idx + 1),
+            KeyCode::Left | KeyCode::Right => {
+                if let Some(value) = argument.step(&state.values[idx], key.code == KeyCode::Right) {
+                    state.values[idx] = value;
+                }
+            }
+            KeyCode::Char(' ') if argument.arg_type == ArgumentType::Boolean => {
+                if let Some(value) = argument.step(&state.values[idx], true) {
+                    state.values[idx] = value;
+                }
+            }
+            KeyCode::Char(c) if matches!(argument.arg_type, ArgumentType::Text | ArgumentType::Path)
+                || (argument.arg_type == ArgumentType::Integer && (c.is_ascii_digit() || c == '-')) =>
+            {
+                state.values[idx].push(c);
+                state.refresh_suggestions();
+            }
+            KeyCode::Backspace => {
+                state.values[idx].pop();
+                state.refresh_suggestions();
+            }
+            KeyCode::Enter => {
+                let values: HashMap<String, String> = state
+                    .workflow
+                    .arguments
+                    .iter()
+                    .zip(&state.values)
+                    .map(|(argument, value)| (argument.name.clone(), value.clone()))
+                    .collect();
+                match state.workflow.render_command(&values) {
+                    Ok(command) => {
+                        self.mode = AppMode::Normal;
+                        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &command, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                    }
+                    Err(e) => {
+                        // Jump to the argument that needs fixing.
+                        if let DriveError::InvalidArgument(name, _) | DriveError::MissingArgument(name) = &e {
+                            if let Some(position) = state.workflow.arguments.iter().position(|argument| &argument.name == name) {
+                                state.select(position);
+                            }
+                        }
+                        state.error = Some(e.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn run_palette_action(&mut self, action: &str) -> Result<(), AppError> {
+        match action {
+            "scratch.open" => self.open_scratch_pane(),
+            "pane.close" => self.close_active_pane(),
+            "pane.privacy" => self.toggle_privacy_zone(),
+            "dirhistory.open" => self.open_dir_history_menu(),
+            "git.review" => self.open_code_review(),
+            "git.commit_message" => self.generate_commit_message(),
+            "input.explain" => self.explain_command(),
+            "refactor.rename" => self.mode = AppMode::Refactor(RefactorState::new(RefactorKind::RenameSymbol)),
+            "refactor.extract" => self.mode = AppMode::Refactor(RefactorState::new(RefactorKind::ExtractFunction)),
+            "blocks.select" => self.open_block_select(),
+            "blocks.undo_delete" => self.undo_block_delete(),
+            "blocks.pdf" => self.export_pane_pdf(),
+            "blocks.markdown" => self.export_pane_document(ExportFormat::Markdown),
+            "blocks.html" => self.export_pane_document(ExportFormat::Html),
+            "blocks.gist" => self.upload_pane_gist(),
+            "watch.start" => self.watch_last_block(),
+            "watch.stop" => self.stop_watch(self.active_pane_idx),
+            "jobs.panel" => self.open_jobs_panel(),
+            "jobs.run_detached" => self.run_input_detached(),
+            "ports.panel" => self.open_ports_panel(),
+            "docker.panel" => self.open_docker_panel(),
+            "graphql.explorer" => self.open_graphql_explorer(),
+            "files.browse" => self.open_file_browser(),
+            "abbreviations.manage" => self.open_abbreviations(),
+            "nl.run_as_command" => self.run_detected_query_as_command(),
+            "agent.evaluation" => self.show_agent_evaluation(),
+            "agent.usage" => self.show_agent_usage(),
+            "stats.daily" => self.open_activity_report(ReportPeriod::Daily)?,
+            "stats.weekly" => self.open_activity_report(ReportPeriod::Weekly)?,
+            "quiz.start" => self.start_drill(),
+            "quiz.generate" => self.generate_history_quiz(),
+            "quiz.import" => self.import_quiz_questions(),
+            "quiz.export" => self.export_quiz_questions(),
+            "notebook.pdf" => self.export_notebook_pdf(),
+            "pane.timeline" => self.open_scrubber(),
+            "pane.encoding" => self.open_encoding_picker(),
+            "pane.wsl" => self.open_wsl_picker(),
+            "pane.new" => self.mode = AppMode::NewPane(NewPaneMenuState { selected_idx: 0 }),
+            "pane.jump_failure" => self.jump_to_failure(),
+            "pane.mute_failures" => self.toggle_failure_focus_mute(),
+            "share.live" => self.start_live_share(),
+            "share.live_stop" => self.stop_live_share(),
+            "share.activity" => self.open_share_activity(),
+            "permissions.audit" => self.mode = AppMode::PermissionAudit(PermissionAuditState { selected_idx: 0 }),
+            "agent.manage" => self.enter_agent_management()?,
+            "ai.prompt" => self.enter_ai_prompt_mode(),
+            "ai.ask_selection" => self.ask_ai_about_input_selection(),
+            "ai.ollama" => self.open_ollama_panel(),
+            "pair.open" => self.open_pair_pane(),
+            "pair.toggle" => self.toggle_pair_suggestions(),
+            "app.doctor" => self.run_doctor(),
+            "web.search" => self.search_web_for_input_selection(),
+            "perf.hud" => self.show_perf_hud = !self.show_perf_hud,
+            "perf.profile" => self.start_profile_capture(),
+            _ => log::warn!("Unknown palette action '{}'", action),
+        }
+        Ok(())
+    }
+
+    fn handle_agent_keys(&mut self, key: KeyEvent) {
+        if key.code == KeyCode::Backspace && key.modifiers.is_empty() {
+            self.detach_last_context();
+        }
+    }
+
+    fn handle_ai_prompt_mode_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_activity_report_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        if let AppMode::ActivityReport(state) = &mut self.mode {
+            match key.code {
+                KeyCode::Esc => self.mode = AppMode::Normal,
+                KeyCode::Up => state.scroll = state.scroll.saturating_sub(1),
+                KeyCode::Down => {
+                    let max_scroll = state.markdown.lines().count().saturating_sub(1);
+                    state.scroll = (state.scroll + 1).min(max_scroll);
+                }
+                KeyCode::Char('e') => self.export_activity_report()?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Generates an activity summary and shows it in the report pane.
+    pub fn open_activity_report(&mut self, period: ReportPeriod) -> Result<(), AppError> {
+        let summary = self.activity_summary(period)?;
+        self.mode = AppMode::ActivityReport(ActivityReportState {
+            period,
+            markdown: summary.to_markdown(),
+            summary,
+            scroll: 0,
+            exported_to: None,
+        });
+        Ok(())
+    }
+
+    fn activity_summary(&mut self, period: ReportPeriod) -> Result<ActivitySummary, AppError> {
+        let records = self.activity_records();
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        crate::stats::generate_report(&mut self.db_conn, &records, period, now).map_err(|e| AppError::Other(e.to_string()))
+    }
+
+    /// Every pane's finished commands as activity records: the directory the
+    /// block ran in, exit status and run time from the pane's exits, finish
+    /// time from its timeline.
+    fn activity_records(&self) -> Vec<ActivityRecord> {
+        self.panes
+            .iter()
+            .flat_map(|pane| {
+                let finished: HashMap<Uuid, i64> = pane.timeline.ticks().iter().map(|tick| (tick.block_id, tick.at)).collect();
+                pane.history.iter().filter_map(move |block| {
+                    let exit = pane.block_exits.get(&block.id)?;
+                    let finished_at = finished.get(&block.id)?;
+                    Some(ActivityRecord {
+                        command: block.command.clone(),
+                        directory: block.cwd.as_ref().map(|cwd| cwd.display().to_string()).unwrap_or_default(),
+                        failed: exit.status != 0,
+                        duration: exit.duration,
+                        timestamp: (finished_at / 1000).max(0) as u64,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    fn export_activity_report(&mut self) -> Result<(), AppError> {
+        if let AppMode::ActivityReport(state) = &mut self.mode {
+            let path = state
+                .summary
+                .export(&self.report_scheduler.export_dir())
+                .map_err(|e| AppError::Other(e.to_string()))?;
+            state.exported_to = Some(path);
+        }
+        Ok(())
+    }
+
+    /// Exports the scheduled activity report when one is due.
+    fn sync_report_schedule(&mut self) {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let Some(period) = self.report_scheduler.due(now) else {
+            return;
+        };
+        // Marked first so a failing export waits for the next period
+        self.report_scheduler.mark_run(now);
+        let exported = self
+            .activity_summary(period)
+            .and_then(|summary| summary.export(&self.report_scheduler.export_dir()).map_err(|e| AppError::Other(e.to_string())));
+        match exported {
+            Ok(path) => self.notify(format!("{} activity report saved to {}", period.label(), path.display())),
+            Err(e) => log::warn!("Failed to export the {} activity report: {}", period.label().to_lowercase(), e),
+        }
+    }
+
+    /// Starts an agent task in the active pane that may run commands after approval.
+    pub fn start_agent_task(&mut self, task: String) -> Result<(), AppError> {
+        let pane_idx = self.active_pane_idx;
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return Ok(());
+        };
+        let model = match &mut self.ab_test {
+            Some(test) => test.route(),
+            None => self.config.ai.base_model.clone(),
+        };
+        pane.enter_agent_mode(task.clone(), model.clone());
+        let conversation = pane.agent_state.as_ref().map_or_else(String::new, |agent_state| agent_state.id.to_string());
+        let agent = self.usage.wrap(self.agent.clone(), &conversation);
+        self.agent_eval.start_evaluation();
+        let mut agent_loop = AgentLoop::new(task.clone(), Box::new(SimulatedPlanner::new(agent, model.clone())), default_command_rules());
+        let step = agent_loop.advance();
+        pane.agent_loop = Some(agent_loop);
+        let evaluation = self.agent_eval.evaluate_response(&model, &task, None);
+        if let Err(e) = crate::db::save_agent_evaluation(&self.db_conn, &evaluation) {
+            log::warn!("Failed to save the agent evaluation: {}", e);
+        }
+        self.agent_sessions.insert(pane.id, evaluation.session_id);
+        self.apply_agent_step(pane_idx, step)
+    }
+
+    /// Acts on the next step of a pane's agent loop. Commands that write
+    /// files or reach the network wait for the user to grant that first.
+    pub fn apply_agent_step(&mut self, pane_idx: usize, step: AgentStep) -> Result<(), AppError> {
+        let status = match &step {
+            AgentStep::Execute(command) => self.command_capability_status(command),
+            _ => PermissionStatus::Granted,
+        };
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return Ok(());
+        };
+        match step {
+            AgentStep::Execute(command) if status == PermissionStatus::Pending => {
+                pane.agent_command_held = Some(command);
+                self.mode = AppMode::Normal;
+            }
+            AgentStep::Execute(_) if status == PermissionStatus::Denied => {
+                let Some(agent_loop) = &mut pane.agent_loop else {
+                    return Ok(());
+                };
+                let step = agent_loop.refuse("the user did not grant what the command needs");
+                return self.apply_agent_step(pane_idx, step);
+            }
+            AgentStep::AwaitingApproval(pending) => {
+                self.mode = AppMode::AgentApproval(AgentApprovalState {
+                    pane_idx,
+                    pending,
+                    selected_idx: 0,
+                });
+            }
+            AgentStep::Execute(command) => {
+                pane.run_agent_command(&command)?;
+                self.mode = AppMode::Normal;
+            }
+            AgentStep::Finished(summary) => {
+                if let Some(agent_state) = &mut pane.agent_state {
+                    agent_state.status = crate::app::pane::AgentStatus::Done;
+                    agent_state
+                        .conversation
+                        .push((agent_state.task_summary.clone(), AgentResponse::Clarification(summary)));
+                }
+                pane.agent_loop = None;
+                self.mode = AppMode::Normal;
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks for every capability an agent command needs, as one status.
+    fn command_capability_status(&self, command: &str) -> PermissionStatus {
+        crate::agent::tools::required_capabilities(command)
+            .into_iter()
+            .map(|capability| self.request_capability("run_command", capability, format!("Run `{}`", command)))
+            .fold(PermissionStatus::Granted, |status, next| match (status, next) {
+                (PermissionStatus::Denied, _) | (_, PermissionStatus::Denied) => PermissionStatus::Denied,
+                (PermissionStatus::Pending, _) | (_, PermissionStatus::Pending) => PermissionStatus::Pending,
+                _ => PermissionStatus::Granted,
+            })
+    }
+
+    /// Retries agent commands held for a capability once the user answered.
+    fn sync_held_agent_commands(&mut self) {
+        for pane_idx in 0..self.panes.len() {
+            let pane = &mut self.panes[pane_idx];
+            if pane.agent_loop.is_none() {
+                pane.agent_command_held = None;
+                continue;
+            }
+            let Some(command) = pane.agent_command_held.take() else {
+                continue;
+            };
+            if let Err(e) = self.apply_agent_step(pane_idx, AgentStep::Execute(command)) {
+                log::warn!("The agent could not take its next step: {}", e);
+            }
+        }
+    }
+
+    fn handle_agent_approval_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::AgentApproval(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let decision = match key.code {
+            KeyCode::Left => {
+                state.selected_idx = state.selected_idx.saturating_sub(1);
+                None
+            }
+            KeyCode::Right | KeyCode::Tab => {
+                state.selected_idx = (state.selected_idx + 1) % ApprovalDecision::ALL.len();
+                None
+            }
+            KeyCode::Enter => Some(ApprovalDecision::ALL[state.selected_idx]),
+            KeyCode::Char('y') => Some(ApprovalDecision::Approve),
+            KeyCode::Char('n') | KeyCode::Esc => Some(ApprovalDecision::Deny),
+            KeyCode::Char('a') => Some(ApprovalDecision::AlwaysAllow),
+            _ => None,
+        };
+        if let Some(decision) = decision {
+            let pane_idx = state.pane_idx;
+            if decision == ApprovalDecision::Deny {
+                if let Some(pane_id) = self.panes.get(pane_idx).map(|pane| pane.id) {
+                    self.record_agent_outcome(pane_id, Outcome::CommandDenied);
+                }
+            }
+            let step = self
+                .panes
+                .get_mut(pane_idx)
+                .and_then(|pane| pane.agent_loop.as_mut())
+                .map(|agent_loop| agent_loop.resolve(decision));
+            match step {
+                Some(step) => self.apply_agent_step(pane_idx, step)?,
+                None => self.mode = AppMode::Normal,
+            }
+        }
+        Ok(())
+    }
+
+    /// Scores the agent task a pane is working on by what became of it.
+    fn record_agent_outcome(&mut self, pane_id: Uuid, outcome: Outcome) {
+        let Some(session_id) = self.agent_sessions.get(&pane_id) else {
+            return;
+        };
+        if let Some(evaluation) = self.agent_eval.record_outcome(session_id, outcome) {
+            if let Err(e) = crate::db::save_agent_evaluation(&self.db_conn, evaluation) {
+                log::warn!("Failed to save the agent evaluation: {}", e);
+            }
+        }
+    }
+
+    /// Shows each model's metrics as a block, with the A/B comparison when
+    /// one is running.
+    pub fn show_agent_evaluation(&mut self) {
+        let mut output = self.agent_eval.render();
+        if let Some(test) = &self.ab_test {
+            output.push_str(&format!("\n\nA/B test\n{}", test.report(&self.agent_eval).render()));
+        }
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.history.push(Block {
+            tags: vec!["evaluation".to_string()],
+            ..Block::new("agent evaluation".to_string(), output)
+        });
+    }
+
+    /// Stores the usage of finished agent requests and warns as spend nears
+    /// the monthly budget.
+    fn sync_usage(&mut self) {
+        let records = self.usage.take();
+        if records.is_empty() {
+            return;
+        }
+        for record in &records {
+            if let Err(e) = crate::db::save_usage(&self.db_conn, record) {
+                log::warn!("Failed to save agent usage: {}", e);
+            }
+        }
+        let Some(budget) = self.config.ai.monthly_budget else {
+            return;
+        };
+        let spent = match crate::db::usage_cost_since(&self.db_conn, month_start(chrono::Local::now())) {
+            Ok(spent) => spent,
+            Err(e) => {
+                log::warn!("Failed to total agent usage: {}", e);
+                return;
+            }
+        };
+        // A new month starts from no warning again.
+        let level = budget_level(spent, budget);
+        if level > self.budget_warned {
+            let share = if level == 2 { 100.0 } else { BUDGET_WARNING_SHARE * 100.0 };
+            self.notify(format!("Agent spend this month is ${:.2}, past {:.0}% of the ${:.2} budget", spent, share, budget));
+        }
+        self.budget_warned = level;
+    }
+
+    /// Shows token usage and estimated cost by day, week and conversation
+    /// as a block.
+    pub fn show_agent_usage(&mut self) {
+        let now = chrono::Local::now();
+        let records = crate::db::load_usage_since(&self.db_conn, report_start(now)).unwrap_or_else(|e| {
+            log::warn!("Failed to load agent usage: {}", e);
+            Vec::new()
+        });
+        let output = UsageReport::new(&records, now, self.config.ai.monthly_budget).render();
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.history.push(Block {
+            tags: vec!["usage".to_string()],
+            ..Block::new("agent usage".to_string(), output)
+        });
+    }
+
+    /// Shows the agent's answer to `prompt` in its pane and persists the exchange.
+    pub fn complete_agent_query(&mut self, pane_id: Uuid, prompt: String, response: AgentResponse) {
+        let Some(pane_idx) = self.panes.iter().position(|pane| pane.id == pane_id) else {
+            return;
+        };
+        if let Some(agent_state) = &mut self.panes[pane_idx].agent_state {
+            agent_state.status = crate::app::pane::AgentStatus::WaitingForInput;
+        }
+        if let Err(e) = self.record_agent_exchange(pane_idx, prompt, response) {
+            log::warn!("Failed to save the agent exchange: {}", e);
+        }
+    }
+
+    /// Adds an exchange to a pane's agent conversation and persists it.
+    pub fn record_agent_exchange(&mut self, pane_idx: usize, prompt: String, response: AgentResponse) -> Result<(), AppError> {
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return Ok(());
+        };
+        let tool_calls = pane
+            .agent_loop
+            .as_ref()
+            .map(|agent_loop| {
+                agent_loop
+                    .transcript()
+                    .iter()
+                    .filter_map(|turn| match turn {
+                        crate::agent::tools::Turn::CommandOutput { command, .. } => Some(command.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let Some(agent_state) = &mut pane.agent_state else {
+            return Ok(());
+        };
+        let attached_blocks = agent_state
+            .attachments
+            .iter()
+            .map(|attachment| match attachment {
+                Attachment::Block { command, output } => format!("$ {}\n{}", command, output),
+                Attachment::File { path } | Attachment::Image { path } => path.display().to_string(),
+                Attachment::Selection { text, .. } => text.clone(),
+            })
+            .collect();
+        let record = AgentMessageRecord {
+            prompt: prompt.clone(),
+            response: response.clone(),
+            tool_calls,
+            attached_blocks,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        agent_state.conversation.push((prompt, response));
+        crate::db::append_agent_message(
+            &mut self.db_conn,
+            &agent_state.id.to_string(),
+            &agent_state.task_summary,
+            &agent_state.model_used,
+            &record,
+        )
+        .map_err(|e| AppError::Other(e.to_string()))
+    }
+
+    /// Attaches context to the active pane's agent query, entering agent mode if needed.
+    pub fn attach_context(&mut self, attachment: Attachment) {
+        let model = self.config.ai.base_model.clone();
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.enter_agent_mode(String::new(), model);
+        if let Some(agent_state) = &mut pane.agent_state {
+            agent_state.attachments.attach(attachment);
+            self.mode = AppMode::Agent(agent_state.clone());
+        }
+    }
+
+    /// Attaches a completed block from the active pane's history.
+    pub fn attach_block(&mut self, block_idx: usize) {
+        let block = self
+            .panes
+            .get(self.active_pane_idx)
+            .and_then(|pane| pane.history.get(block_idx))
+            .map(|block| Attachment::Block {
+                command: block.command.clone(),
+                output: block.output.clone(),
+            });
+        if let Some(attachment) = block {
+            self.attach_context(attachment);
+        }
+    }
+
+    pub fn attach_file(&mut self, path: PathBuf) {
+        self.attach_context(Attachment::File { path });
+    }
+
+    pub fn attach_selection(&mut self, text: String, source: Option<String>) {
+        if !text.trim().is_empty() {
+            self.attach_context(Attachment::Selection { text, source });
+        }
+    }
+
+    /// Handles a file dropped on the window: attached to the agent query in
+    /// agent mode, otherwise typed into the command line as a shell word.
+    pub fn drop_file(&mut self, path: PathBuf) {
+        if matches!(self.mode, AppMode::Agent(_)) {
+            if paste::is_image(&path) {
+                self.attach_context(Attachment::Image { path });
+            } else {
+                self.attach_file(path);
+            }
+        } else {
+            self.input_editor.insert_string(paste::escaped_path(&path), None);
+        }
+    }
+
+    /// Saves a pasted clipboard image to a temp file. It is attached to the
+    /// agent query when the conversation's model takes images, and otherwise
+    /// its path is typed into the command line.
+    pub fn paste_image(&mut self, image: arboard::ImageData) {
+        let path = match paste::save_image(image.width, image.height, &image.bytes, &paste::paste_dir()) {
+            Ok(path) => path,
+            Err(e) => {
+                self.notify(format!("Could not save the pasted image: {}", e));
+                return;
+            }
+        };
+        if let AppMode::Agent(state) = &self.mode {
+            if state.model_used.supports_images() {
+                self.attach_context(Attachment::Image { path });
+            } else {
+                self.notify(format!("{} does not accept images; saved it to {}", state.model_used.to_string(), path.display()));
+            }
+            return;
+        }
+        self.input_editor.insert_string(paste::escaped_path(&path), None);
+        self.pasted_images.push(path);
+    }
+
+    /// Chips for pasted images whose paths are still on the command line.
+    pub fn pasted_image_chips(&self) -> Vec<Chip> {
+        if self.pasted_images.is_empty() {
+            return Vec::new();
+        }
+        let input = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        self.pasted_images
+            .iter()
+            .filter(|path| input.contains(paste::escaped_path(path).trim_end()))
+            .map(|path| Attachment::Image { path: path.clone() }.chip())
+            .collect()
+    }
+
+    pub fn detach_context(&mut self, index: usize) {
+        if let Some(agent_state) = self
+            .panes
+            .get_mut(self.active_pane_idx)
+            .and_then(|pane| pane.agent_state.as_mut())
+        {
+            agent_state.attachments.detach(index);
+            if let AppMode::Agent(state) = &mut self.mode {
+                *state = agent_state.clone();
+            }
+        }
+    }
+
+    /// Takes the last chip off the agent query while its question is empty,
+    /// as Backspace does. Returns whether one was removed.
+    pub fn detach_last_context(&mut self) -> bool {
+        let last = match &self.mode {
+            AppMode::Agent(state) if state.current_input.is_empty() => state.attachments.len().checked_sub(1),
+            _ => None,
+        };
+        if let Some(index) = last {
+            self.detach_context(index);
+        }
+        last.is_some()
+    }
+
+    /// Opens the agent with `selection` quoted into the configured question
+    /// template and the block it came from attached as context.
+    pub fn ask_ai_about_selection(&mut self, selection: &str, block_idx: Option<usize>) {
+        if let Some(block_idx) = block_idx {
+            self.attach_block(block_idx);
+        }
+        let question = render_ask_ai_prompt(&self.config.ai.ask_ai_template, selection);
+        let model = self.config.ai.base_model.clone();
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.enter_agent_mode(question.clone(), model);
+        if let Some(agent_state) = &mut pane.agent_state {
+            agent_state.current_input = question;
+            self.mode = AppMode::Agent(agent_state.clone());
+        }
+    }
+
+    /// Opens agent mode with `prompt` filled in from the input selection, the
+    /// active pane's last block and its working directory, ready to edit and
+    /// send.
+    pub fn ask_with_prompt(&mut self, prompt: &Prompt) {
+        let selection = self.input_editor.copy_selection().filter(|s| !s.trim().is_empty());
+        let model = self.config.ai.base_model.clone();
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let context = crate::agent::prompts::PromptContext {
+            selection,
+            last_block_output: pane.history.iter().rev().find(|block| !block.command.is_empty()).map(|block| block.output.clone()),
+            cwd: pane.local_cwd(),
+        };
+        let question = match crate::agent::prompts::render(prompt, &context) {
+            Ok(question) => question,
+            Err(e) => {
+                self.notify(format!("Can't use '{}': {}", prompt.name, e));
+                return;
+            }
+        };
+        pane.enter_agent_mode(question.clone(), model);
+        if let Some(agent_state) = &mut pane.agent_state {
+            agent_state.current_input = question;
+            self.mode = AppMode::Agent(agent_state.clone());
+        }
+    }
+
+    /// "Ask AI" for the text currently selected in the input editor.
+    pub fn ask_ai_about_input_selection(&mut self) {
+        match self.input_editor.copy_selection().filter(|s| !s.trim().is_empty()) {
+            Some(selection) => self.ask_ai_about_selection(&selection, None),
+            None => self.notify("Select text to ask the agent about".to_string()),
+        }
+    }
+
+    /// "Search Web" for the text currently selected in the input editor.
+    pub fn search_web_for_input_selection(&mut self) {
+        match self.input_editor.copy_selection().filter(|s| !s.trim().is_empty()) {
+            Some(selection) => self.open_web_search(&selection),
+            None => self.notify("Select text to search the web for".to_string()),
+        }
+    }
+
+    /// Asks which provider to search `selection` with.
+    pub fn open_web_search(&mut self, selection: &str) {
+        match self.web_search.query_for(selection) {
+            Ok(query) => {
+                self.mode = AppMode::WebSearch(WebSearchState { query, selected_idx: 0, view: WebSearchView::Providers })
+            }
+            Err(e) => self.notify(e.to_string()),
+        }
+    }
+
+    fn handle_web_search_keys(&mut self, key: KeyEvent) {
+        let AppMode::WebSearch(state) = &mut self.mode else {
+            return;
+        };
+        let item_count = match &state.view {
+            WebSearchView::Providers => self.web_search.providers().len(),
+            WebSearchView::Results(Ok(results)) => results.len(),
+            _ => 0,
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_idx = (state.selected_idx + 1).min(item_count.saturating_sub(1)),
+            KeyCode::Enter => match &state.view {
+                WebSearchView::Providers => {
+                    let Some(provider) = self.web_search.providers().get(state.selected_idx) else {
+                        return;
+                    };
+                    match self.web_search.search(&provider.name, &state.query) {
+                        Ok(SearchStarted::InBrowser) => self.mode = AppMode::Normal,
+                        Ok(SearchStarted::Inline) => {
+                            state.selected_idx = 0;
+                            state.view = WebSearchView::Loading;
+                        }
+                        Err(e) => {
+                            self.mode = AppMode::Normal;
+                            self.notify(e.to_string());
+                        }
+                    }
+                }
+                WebSearchView::Results(Ok(results)) => {
+                    if let Some(result) = results.get(state.selected_idx) {
+                        if let Err(e) = webbrowser::open(&result.url) {
+                            self.notify(format!("Failed to open the browser: {}", e));
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Shows inline results for the search still on screen; results of
+    /// searches closed in the meantime are dropped.
+    fn sync_web_search(&mut self) {
+        for (query, results) in self.web_search.poll() {
+            if let AppMode::WebSearch(state) = &mut self.mode {
+                if state.query == query && state.view == WebSearchView::Loading {
+                    state.view = WebSearchView::Results(results);
+                }
+            }
+        }
+    }
+
+    pub fn open_block_menu(&mut self, block_idx: usize, selection: Option<String>) {
+        let block = self.panes.get(self.active_pane_idx).and_then(|pane| pane.history.get(block_idx));
+        let has_csv_summary = block.is_some_and(|block| block.csv_summary.is_some());
+        let is_csv = has_csv_summary || block.is_some_and(|block| crate::structured::csv::detect(&block.output).is_some());
+        let has_translation = block.is_some_and(|block| block.translation.is_some());
+        let watching = self.watch_for(self.active_pane_idx).is_some();
+        let is_http = block.is_some_and(|block| http::is_http_command(&block.command));
+        let has_locations = block.is_some_and(|block| !editor::find_locations(&block.output).is_empty());
+        let has_stack_trace = block.is_some_and(|block| !block.stack_traces.is_empty());
+        let library_frames_folded = block.map_or(true, |block| block.library_frames_folded);
+        let line_count = block.map_or(0, |block| block.output.lines().count());
+        let output_view = match (self.panes.get(self.active_pane_idx), block) {
+            (Some(pane), Some(block)) => pane.output_view(block, &self.config.blocks),
+            _ => OutputView::Full,
+        };
+        self.mode = AppMode::BlockMenu(BlockMenuState {
+            pane_idx: self.active_pane_idx,
+            block_idx,
+            selected_action_idx: 0,
+            selection: selection.filter(|s| !s.trim().is_empty()),
+            is_csv,
+            has_csv_summary,
+            has_translation,
+            watching,
+            is_http,
+            has_locations,
+            has_stack_trace,
+            library_frames_folded,
+            output_view,
+            foldable: line_count > self.config.blocks.fold_lines,
+            huge_output: fold::is_huge(line_count, &self.config.blocks),
+        });
+    }
+
+    /// The block fold keys act on: the one at the top of the active pane's
+    /// viewport, or the last one when the view is past every block.
+    fn current_block_idx(&self) -> Option<usize> {
+        let pane = self.panes.get(self.active_pane_idx)?;
+        let top_row = (pane.scroll.offset() / self.line_height()) as usize;
+        let mut end = 0;
+        pane.history
+            .iter()
+            .position(|block| {
+                end += history_rows(pane, block, &self.config.blocks);
+                top_row < end
+            })
+            .or_else(|| pane.history.len().checked_sub(1))
+    }
+
+    /// Collapses the current block's output, or shows all of it again.
+    pub fn toggle_fold_current(&mut self) {
+        let Some(block_idx) = self.current_block_idx() else {
+            return;
+        };
+        let pane = &self.panes[self.active_pane_idx];
+        let view = match pane.output_view(&pane.history[block_idx], &self.config.blocks) {
+            OutputView::Full => OutputView::Collapsed,
+            OutputView::Collapsed | OutputView::Tail => OutputView::Full,
+        };
+        self.set_output_view(self.active_pane_idx, block_idx, view);
+    }
+
+    /// Collapses every long output in the active pane, or expands them all
+    /// when they already are.
+    pub fn toggle_fold_all(&mut self) {
+        let config = &self.config.blocks;
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        let foldable: Vec<&Block> = pane.history.iter().filter(|block| block.output.lines().count() > config.fold_lines).collect();
+        let view = if foldable.iter().all(|block| pane.output_view(block, config) == OutputView::Collapsed) {
+            OutputView::Full
+        } else {
+            OutputView::Collapsed
+        };
+        let ids: Vec<Uuid> = foldable.iter().map(|block| block.id).collect();
+        for id in ids {
+            pane.output_views.insert(id, view);
+        }
+    }
+
+    /// Shows a block's output in full, collapsed or only its last lines,
+    /// instead of as the config decides.
+    fn set_output_view(&mut self, pane_idx: usize, block_idx: usize, view: OutputView) {
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return;
+        };
+        if let Some(block) = pane.history.get(block_idx) {
+            pane.output_views.insert(block.id, view);
+        }
+    }
+
+    /// Summarizes a block's output in the user's locale, from the cache when
+    /// the same output was translated before.
+    fn translate_block(&mut self, pane_idx: usize, block_idx: usize) {
+        let Some(block) = self.panes.get_mut(pane_idx).and_then(|pane| pane.history.get_mut(block_idx)) else {
+            return;
+        };
+        if block.output.trim().is_empty() {
+            self.notify("Block has no output to translate".to_string());
+            return;
+        }
+        let lang = std::env::var("LANG").ok();
+        let locale = user_locale(self.config.ai.locale.as_deref(), lang.as_deref());
+        let model = self.config.ai.base_model.clone();
+        match self.translator.translate(&self.db_conn, block.id, &block.command, &block.output, &locale, model) {
+            Some(translation) => block.translation = Some(translation),
+            None => self.notify(format!("Translating output into {}…", locale)),
+        }
+    }
+
+    /// Adds the tags the tagger found to their blocks.
+    fn sync_block_tags(&mut self) {
+        for found in self.tagger.poll() {
+            let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == found.block_id);
+            if let Some(block) = block {
+                for tag in found.tags {
+                    if !block.tags.contains(&tag) {
+                        block.tags.push(tag);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attaches finished translations to their blocks.
+    fn sync_translations(&mut self) {
+        for result in self.translator.poll(&self.db_conn) {
+            let block = self.panes.iter_mut().flat_map(|pane| pane.history.iter_mut()).find(|block| block.id == result.block_id);
+            if let Some(block) = block {
+                block.translation = Some(result.translation);
+            }
+        }
+    }
+
+    /// Computes column statistics for a CSV block and shows them as a footer.
+    fn summarize_csv_block(&mut self, pane_idx: usize, block_idx: usize) {
+        let Some(block) = self.panes.get_mut(pane_idx).and_then(|pane| pane.history.get_mut(block_idx)) else {
+            return;
+        };
+        match crate::structured::csv::detect(&block.output) {
+            Some(StructuredData::Table { columns, rows }) => {
+                block.csv_summary = Some(crate::structured::csv::summarize(&columns, &rows));
+                block.csv_summary_folded = false;
+            }
+            _ => self.notify("Block output is not CSV".to_string()),
+        }
+    }
+
+    fn handle_block_menu_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::BlockMenu(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let item_count = state.items().len();
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_action_idx = state.selected_action_idx.saturating_sub(1),
+            KeyCode::Down => state.selected_action_idx = (state.selected_action_idx + 1).min(item_count - 1),
+            KeyCode::Enter => {
+                let state = state.clone();
+                let Some(block) = self
+                    .panes
+                    .get(state.pane_idx)
+                    .and_then(|pane| pane.history.get(state.block_idx))
+                    .cloned()
+                else {
+                    self.mode = AppMode::Normal;
+                    return Ok(());
+                };
+                self.mode = AppMode::Normal;
+                match state.items()[state.selected_action_idx] {
+                    "Copy Command" => {
+                        if let Ok(mut clipboard) = Clipboard::new() {
+                            clipboard.set_text(block.command).ok();
+                        }
+                    }
+                    "Copy Output" => {
+                        if let Ok(mut clipboard) = Clipboard::new() {
+                            clipboard.set_text(block.output).ok();
+                        }
+                    }
+                    "Re-input Command" => {
+                        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &block.command, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                    }
+                    "Share" => self.share_block(&block.command, &block.output, None),
+                    "Export Markdown" => self.export_blocks_document(state.pane_idx, &[state.block_idx], ExportFormat::Markdown),
+                    "Export HTML" => self.export_blocks_document(state.pane_idx, &[state.block_idx], ExportFormat::Html),
+                    "Upload to Gist" => self.upload_blocks_gist(state.pane_idx, &[state.block_idx]),
+                    "Comment on Pull Request" => self.open_pull_request_picker(state.pane_idx, state.block_idx),
+                    "Open in Editor" => self.open_block_locations(state.pane_idx, &block.output),
+                    "Step Through Stack Trace" => self.open_stack_trace(state.pane_idx, state.block_idx),
+                    "Show Library Frames" | "Hide Library Frames" => {
+                        if let Some(block) = self.panes.get_mut(state.pane_idx).and_then(|pane| pane.history.get_mut(state.block_idx)) {
+                            block.library_frames_folded = !block.library_frames_folded;
+                        }
+                    }
+                    "Expand Output" | "Show Full Output" => self.set_output_view(state.pane_idx, state.block_idx, OutputView::Full),
+                    "Collapse Output" => self.set_output_view(state.pane_idx, state.block_idx, OutputView::Collapsed),
+                    "Show Last Lines" => self.set_output_view(state.pane_idx, state.block_idx, OutputView::Tail),
+                    "Watch and Re-run" => self.open_watch_glob(state.pane_idx, &block.command),
+                    "Stop Watching" => self.stop_watch(state.pane_idx),
+                    "Re-run Request" => self.send_http_request(state.pane_idx, state.block_idx),
+                    "Save to Collection" => self.save_http_request(&block.command),
+                    "Search Web for Selection" => self.open_web_search(state.selection.as_deref().unwrap_or_default()),
+                    "Attach Selection to Agent" => {
+                        self.active_pane_idx = state.pane_idx;
+                        self.attach_selection(state.selection.unwrap_or_default(), Some(format!("block `{}`", block.command)));
+                    }
+                    "Summarize CSV" => self.summarize_csv_block(state.pane_idx, state.block_idx),
+                    "Toggle CSV Summary" => {
+                        if let Some(block) = self.panes.get_mut(state.pane_idx).and_then(|pane| pane.history.get_mut(state.block_idx)) {
+                            block.csv_summary_folded = !block.csv_summary_folded;
+                        }
+                    }
+                    "Translate Output" => self.translate_block(state.pane_idx, state.block_idx),
+                    "Toggle Translation" => {
+                        if let Some(translation) = self.panes.get_mut(state.pane_idx).and_then(|pane| pane.history.get_mut(state.block_idx)).and_then(|block| block.translation.as_mut()) {
+                            translation.folded = !translation.folded;
+                        }
+                    }
+                    // The block goes along as an attachment, so without a
+                    // selection its output isn't quoted into the question too
+                    "Ask AI" => {
+                        self.active_pane_idx = state.pane_idx;
+                        self.attach_block(state.block_idx);
+                    }
+                    "Ask AI about Selection" => {
+                        self.active_pane_idx = state.pane_idx;
+                        self.ask_ai_about_selection(state.selection.as_deref().unwrap_or_default(), Some(state.block_idx));
+                    }
+                    other => log::warn!("Unknown block action '{}'", other),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Starts scrubbing the active pane's timeline from its latest moment.
+    pub fn open_scrubber(&mut self) {
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        match pane.timeline.range() {
+            Some((_, end)) => self.mode = AppMode::Scrub(ScrubState { pane_idx: self.active_pane_idx, at: end }),
+            None => self.notify("Nothing recorded for this pane yet".to_string()),
+        }
+    }
+
+    /// Moves the scrubber a fraction of the way along the timeline, as when
+    /// dragging it with the mouse.
+    pub fn scrub_to_fraction(&mut self, fraction: f32) {
+        let AppMode::Scrub(state) = &mut self.mode else {
+            return;
+        };
+        if let Some(at) = self.panes.get(state.pane_idx).and_then(|pane| pane.timeline.at_fraction(fraction)) {
+            state.at = at;
+        }
+    }
+
+    fn handle_scrub_keys(&mut self, key: KeyEvent) {
+        let AppMode::Scrub(state) = &mut self.mode else {
+            return;
+        };
+        let Some(timeline) = self.panes.get(state.pane_idx).map(|pane| &pane.timeline) else {
+            self.mode = AppMode::Normal;
+            return;
+        };
+        let jump = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Esc | KeyCode::Enter => self.mode = AppMode::Normal,
+            KeyCode::Left if jump => state.at = timeline.jump_block(state.at, false),
+            KeyCode::Right if jump => state.at = timeline.jump_block(state.at, true),
+            KeyCode::Left => state.at = timeline.step(state.at, false),
+            KeyCode::Right => state.at = timeline.step(state.at, true),
+            KeyCode::Home => state.at = timeline.range().map_or(state.at, |(start, _)| start),
+            KeyCode::End => state.at = timeline.range().map_or(state.at, |(_, end)| end),
+            _ => {}
+        }
+    }
+
+    /// Opens the active pane's block list for multi-select, starting at the
+    /// latest block.
+    pub fn open_block_select(&mut self) {
+        let Some(pane) = self.panes.get(self.active_pane_idx) else {
+            return;
+        };
+        if pane.history.is_empty() {
+            self.notify("No blocks to select".to_string());
+            return;
+        }
+        self.mode = AppMode::BlockSelect(BlockSelectState {
+            pane_idx: self.active_pane_idx,
+            selection: BlockSelection::new(pane.history.len() - 1),
+            tag_input: None,
+        });
+    }
+
+    fn handle_block_select_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::BlockSelect(state) = &mut self.mode else {
+            return Ok(());
+        };
+        let count = self.panes.get(state.pane_idx).map_or(0, |pane| pane.history.len());
+        if let Some(tag) = &mut state.tag_input {
+            match key.code {
+                KeyCode::Esc => state.tag_input = None,
+                KeyCode::Backspace => {
+                    tag.pop();
+                }
+                KeyCode::Char(c) if !c.is_whitespace() => tag.push(c),
+                KeyCode::Enter => {
+                    let tag = tag.clone();
+                    let state = state.clone();
+                    self.mode = AppMode::Normal;
+                    self.tag_blocks(state.pane_idx, &state.selection.indices(), tag);
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        let mode = if key.modifiers.contains(KeyModifiers::SHIFT) {
+            SelectMode::Extend
+        } else if key.modifiers.contains(KeyModifiers::CONTROL) {
+            SelectMode::Keep
+        } else {
+            SelectMode::Replace
+        };
+        match key.code {
+            KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selection.move_cursor(-1, count, mode),
+            KeyCode::Down => state.selection.move_cursor(1, count, mode),
+            KeyCode::Char(' ') => state.selection.toggle(),
+            KeyCode::Char('a') if key.modifiers == KeyModifiers::CONTROL => state.selection.select_all(count),
+            KeyCode::Char('z') if key.modifiers == KeyModifiers::CONTROL => self.undo_block_delete(),
+            KeyCode::Char('u') => self.undo_block_delete(),
+            KeyCode::Char('t') if !state.selection.is_empty() => state.tag_input = Some(String::new()),
+            KeyCode::Char('d') | KeyCode::Delete if !state.selection.is_empty() => {
+                let (pane_idx, indices) = (state.pane_idx, state.selection.indices());
+                self.delete_blocks(pane_idx, &indices);
+                let count = self.panes.get(pane_idx).map_or(0, |pane| pane.history.len());
+                match &mut self.mode {
+                    AppMode::BlockSelect(state) if count > 0 => state.selection.reset(count),
+                    _ => self.mode = AppMode::Normal,
+                }
+            }
+            KeyCode::Char(action @ ('e' | 'p' | 'm' | 'h' | 'g' | 'r' | 'i')) if !state.selection.is_empty() => {
+                let (pane_idx, indices) = (state.pane_idx, state.selection.indices());
+                self.mode = AppMode::Normal;
+                match action {
+                    'e' => self.export_blocks(pane_idx, &indices),
+                    'p' => self.export_blocks_pdf(pane_idx, &indices),
+                    'm' => self.export_blocks_document(pane_idx, &indices, ExportFormat::Markdown),
+                    'h' => self.export_blocks_document(pane_idx, &indices, ExportFormat::Html),
+                    'g' => self.upload_blocks_gist(pane_idx, &indices),
+                    'r' => self.rerun_blocks(pane_idx, &indices)?,
+                    _ => self.send_blocks_to_agent(pane_idx, &indices),
+                }
+            }
+            _ => {}
+        }
         Ok(())
     }
 
-    fn handle_history_mode_keys(&mut self, key: KeyEvent, db_conn: &mut rusqlite::Connection) -> Result<(), AppError> {
-        if let AppMode::HistorySearch(state) = &mut self.mode {
-            match key.code {
-                KeyCode::Esc | KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
-                    self.mode = AppMode::Normal;
-                }
-                KeyCode::Char(c) => {
-                    state.query.push(c);
-                    let all_history = crate::db::get_all_history(db_conn).unwrap_or_default();
-                    let matcher = SkimMatcherV2::default();
-                    state.filtered_list = all_history
-                        .into_iter()
-                        .filter(|item| matcher.fuzzy_match(item, &state.query).is_some())
-                        .collect();
-                    state.selected_idx = 0;
-                }
-                KeyCode::Backspace => {
-                    state.query.pop();
-                    let all_history = crate::db::get_all_history(db_conn).unwrap_or_default();
-                    let matcher = SkimMatcherV2::default();
-                    state.filtered_list = all_history
-                        .into_iter()
-                        .filter(|item| matcher.fuzzy_match(item, &state.query).is_some())
-                        .collect();
-                    state.selected_idx = 0;
+    fn tag_blocks(&mut self, pane_idx: usize, indices: &[usize], tag: String) {
+        if tag.is_empty() {
+            return;
+        }
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return;
+        };
+        for block in indices.iter().filter_map(|idx| pane.history.get_mut(*idx)) {
+            if !block.tags.contains(&tag) {
+                block.tags.push(tag.clone());
+            }
+        }
+        self.notify(format!("Tagged {} block(s) '{}'", indices.len(), tag));
+    }
+
+    /// Removes blocks from a pane, remembering them for undo.
+    fn delete_blocks(&mut self, pane_idx: usize, indices: &[usize]) {
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return;
+        };
+        let blocks = remove_indices(&mut pane.history, indices);
+        if blocks.is_empty() {
+            return;
+        }
+        let message = format!("Deleted {} block(s); press u in the block list to undo", blocks.len());
+        self.deleted_blocks.push(DeletedBlocks { pane_id: pane.id, blocks });
+        self.notify(message);
+    }
+
+    /// Restores the most recent batch delete whose pane is still open.
+    pub fn undo_block_delete(&mut self) {
+        while let Some(deleted) = self.deleted_blocks.pop() {
+            if let Some(pane) = self.panes.iter_mut().find(|pane| pane.id == deleted.pane_id) {
+                let count = deleted.blocks.len();
+                restore_indices(&mut pane.history, deleted.blocks);
+                let history_len = pane.history.len();
+                if let AppMode::BlockSelect(state) = &mut self.mode {
+                    state.selection.reset(history_len);
                 }
-                _ => {}
+                self.notify(format!("Restored {} block(s)", count));
+                return;
+            }
+        }
+        self.notify("Nothing to undo".to_string());
+    }
+
+    /// Saves the blocks to Drive as one notebook.
+    fn export_blocks(&mut self, pane_idx: usize, indices: &[usize]) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        let blocks: Vec<_> = indices.iter().filter_map(|idx| pane.history.get(*idx)).collect();
+        let name = format!("blocks-{}", chrono::Local::now().format("%Y-%m-%d-%H%M"));
+        let notebook = blocks_to_notebook(&blocks, name);
+        match self.drive_manager.save_notebook(&notebook) {
+            Ok(path) => self.notify(format!("Exported {} block(s) to {}", blocks.len(), path.display())),
+            Err(e) => self.notify(format!("Failed to export blocks: {}", e)),
+        }
+    }
+
+    /// Prints the blocks to a PDF file chosen in a save dialog.
+    fn export_blocks_pdf(&mut self, pane_idx: usize, indices: &[usize]) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        let blocks: Vec<_> = indices.iter().filter_map(|idx| pane.history.get(*idx)).collect();
+        if blocks.is_empty() {
+            self.notify("No blocks to export".to_string());
+            return;
+        }
+        let name = format!("blocks-{}", chrono::Local::now().format("%Y-%m-%d-%H%M"));
+        let style = PdfStyle::new(&self.active_theme, &self.config.appearance);
+        let document = PdfDocument::from_blocks(style, &name, &blocks);
+        self.save_pdf(&name, &document);
+    }
+
+    /// Prints every block in the active pane to PDF.
+    pub fn export_pane_pdf(&mut self) {
+        let count = self.panes.get(self.active_pane_idx).map_or(0, |pane| pane.history.len());
+        self.export_blocks_pdf(self.active_pane_idx, &(0..count).collect::<Vec<_>>());
+    }
+
+    /// Saves the blocks as a Markdown or standalone HTML file chosen in a
+    /// save dialog.
+    fn export_blocks_document(&mut self, pane_idx: usize, indices: &[usize], format: ExportFormat) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        let blocks: Vec<_> = indices.iter().filter_map(|idx| pane.history.get(*idx)).collect();
+        if blocks.is_empty() {
+            self.notify("No blocks to export".to_string());
+            return;
+        }
+        let name = format!("blocks-{}", chrono::Local::now().format("%Y-%m-%d-%H%M"));
+        let document = match format {
+            ExportFormat::Markdown => export::to_markdown(&name, &blocks),
+            ExportFormat::Html => {
+                let style = HtmlStyle::new(&self.active_theme);
+                export::to_html(&name, &blocks, &style, Grammar::for_shell(&self.shell()))
+            }
+        };
+        let Some(path) = rfd::FileDialog::new()
+            .set_title(format!("Export to {}", format.label()))
+            .set_file_name(format!("{}.{}", name, format.extension()))
+            .add_filter(format.label(), &[format.extension()])
+            .save_file()
+        else {
+            return;
+        };
+        match std::fs::write(&path, document) {
+            Ok(()) => self.notify(format!("Exported {} block(s) to {}", blocks.len(), path.display())),
+            Err(e) => self.notify(format!("Failed to save {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Uploads the blocks as a Markdown gist to the first Gist share target.
+    fn upload_blocks_gist(&mut self, pane_idx: usize, indices: &[usize]) {
+        let Some(pane) = self.panes.get(pane_idx) else {
+            return;
+        };
+        let blocks: Vec<_> = indices.iter().filter_map(|idx| pane.history.get(*idx)).collect();
+        if blocks.is_empty() {
+            self.notify("No blocks to upload".to_string());
+            return;
+        }
+        let service = ShareService::new(self.config.share.clone(), Keychain::new());
+        let Some(target) = service.gist_target().map(str::to_string) else {
+            self.notify("Add a gist target to the share config to upload blocks".to_string());
+            return;
+        };
+        let name = format!("blocks-{}", chrono::Local::now().format("%Y-%m-%d-%H%M"));
+        let content = export::to_share_content(&name, &blocks);
+        let what = format!("{} block(s)", blocks.len());
+        self.share_content(content, Some(target), what);
+    }
+
+    /// Exports every block in the active pane.
+    pub fn export_pane_document(&mut self, format: ExportFormat) {
+        let count = self.panes.get(self.active_pane_idx).map_or(0, |pane| pane.history.len());
+        self.export_blocks_document(self.active_pane_idx, &(0..count).collect::<Vec<_>>(), format);
+    }
+
+    /// Uploads every block in the active pane as a gist.
+    pub fn upload_pane_gist(&mut self) {
+        let count = self.panes.get(self.active_pane_idx).map_or(0, |pane| pane.history.len());
+        self.upload_blocks_gist(self.active_pane_idx, &(0..count).collect::<Vec<_>>());
+    }
+
+    /// Asks for a Drive notebook and prints it to PDF.
+    pub fn export_notebook_pdf(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export Notebook to PDF")
+            .set_directory(&self.drive_manager.personal_ws.path)
+            .add_filter("Notebook", &["md"])
+            .pick_file()
+        else {
+            return;
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                self.notify(format!("Failed to read {}: {}", path.display(), e));
+                return;
             }
+        };
+        let name = path.file_stem().map_or("notebook".to_string(), |stem| stem.to_string_lossy().into_owned());
+        let style = PdfStyle::new(&self.active_theme, &self.config.appearance);
+        let document = PdfDocument::from_notebook(style, &Notebook { name: name.clone(), content });
+        self.save_pdf(&name, &document);
+    }
+
+    fn save_pdf(&mut self, name: &str, document: &PdfDocument) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Save PDF")
+            .set_file_name(format!("{}.pdf", name))
+            .add_filter("PDF", &["pdf"])
+            .save_file()
+        else {
+            return;
+        };
+        match std::fs::write(&path, document.render()) {
+            Ok(()) => self.notify(format!("Saved {}", path.display())),
+            Err(e) => self.notify(format!("Failed to save {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Runs the blocks' commands again, one after another, in their pane.
+    fn rerun_blocks(&mut self, pane_idx: usize, indices: &[usize]) -> Result<(), AppError> {
+        let Some(pane) = self.panes.get_mut(pane_idx) else {
+            return Ok(());
+        };
+        let commands: Vec<String> = indices
+            .iter()
+            .filter_map(|idx| pane.history.get(*idx))
+            .map(|block| block.command.clone())
+            .filter(|command| !command.is_empty())
+            .collect();
+        // The shell reads the queued lines in order, starting each command
+        // when the previous one exits.
+        for command in &commands {
+            pane.run_command(command)?;
         }
+        self.active_pane_idx = pane_idx;
+        self.notify(format!("Re-running {} command(s)", commands.len()));
         Ok(())
     }
 
-    fn handle_ai_prompt_mode_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+    /// Attaches every selected block to the agent query as context.
+    fn send_blocks_to_agent(&mut self, pane_idx: usize, indices: &[usize]) {
+        self.active_pane_idx = pane_idx;
+        for idx in indices {
+            self.attach_block(*idx);
+        }
+    }
+
+    /// Uploads a redacted block export in the background and copies the link.
+    pub fn share_block(&self, command: &str, output: &str, target: Option<String>) {
+        self.share_content(ShareContent::from_block(command, output), target, "block".to_string());
+    }
+
+    /// Uploads `content` in the background, naming it `what` in the
+    /// notification, and copies the link.
+    fn share_content(&self, content: ShareContent, target: Option<String>, what: String) {
+        let service = ShareService::new(self.config.share.clone(), Keychain::new());
+        let notifications = self.notifications.clone();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Failed to start share runtime: {}", e);
+                    return;
+                }
+            };
+            let message = match runtime.block_on(service.share(&content, target.as_deref())) {
+                Ok(outcome) => {
+                    if let Ok(mut clipboard) = Clipboard::new() {
+                        clipboard.set_text(outcome.url.clone()).ok();
+                    }
+                    match outcome.redactions {
+                        0 => format!("Shared {}: {} (link copied)", what, outcome.url),
+                        n => format!("Shared {} with {} secret(s) redacted: {} (link copied)", what, n, outcome.url),
+                    }
+                }
+                Err(e) => format!("Sharing failed: {}", e),
+            };
+            notifications.lock().unwrap().push(message);
+        });
+    }
+
+    pub fn enter_agent_management(&mut self) -> Result<(), AppError> {
+        let conversations = crate::db::search_agent_conversations(&mut self.db_conn, "")
+            .map_err(|e| AppError::Other(e.to_string()))?;
+        self.mode = AppMode::AgentManagement(AgentManagementState {
+            query: String::new(),
+            selected_idx: 0,
+            conversations,
+        });
+        Ok(())
+    }
+
+    fn handle_agent_management_keys(&mut self, key: KeyEvent) -> Result<(), AppError> {
+        let AppMode::AgentManagement(state) = &mut self.mode else {
+            return Ok(());
+        };
         match key.code {
             KeyCode::Esc => self.mode = AppMode::Normal,
+            KeyCode::Up => state.selected_idx = state.selected_idx.saturating_sub(1),
+            KeyCode::Down => {
+                state.selected_idx = (state.selected_idx + 1).min(state.conversations.len().saturating_sub(1));
+            }
+            KeyCode::Enter => {
+                if let Some(conversation) = state.conversations.get(state.selected_idx) {
+                    let id = conversation.id.clone();
+                    self.resume_agent_conversation(&id)?;
+                }
+            }
+            KeyCode::Char(c) => {
+                state.query.push(c);
+                state.conversations = crate::db::search_agent_conversations(&mut self.db_conn, &state.query)
+                    .map_err(|e| AppError::Other(e.to_string()))?;
+                state.selected_idx = 0;
+            }
+            KeyCode::Backspace => {
+                state.query.pop();
+                state.conversations = crate::db::search_agent_conversations(&mut self.db_conn, &state.query)
+                    .map_err(|e| AppError::Other(e.to_string()))?;
+                state.selected_idx = 0;
+            }
             _ => {}
         }
         Ok(())
     }
 
-    fn enter_history_mode(&mut self) {
-        self.mode = AppMode::HistorySearch(HistorySearchState {
-            query: String::new(),
-            selected_idx: 0,
-            filtered_list: vec![],
-        });
+    /// Reopens a stored conversation in the active pane so follow-up queries
+    /// carry its history as context.
+    pub fn resume_agent_conversation(&mut self, conversation_id: &str) -> Result<(), AppError> {
+        let summary = crate::db::get_agent_conversation(&mut self.db_conn, conversation_id)
+            .map_err(|e| AppError::Other(e.to_string()))?
+            .ok_or_else(|| AppError::Other(format!("Conversation {} not found", conversation_id)))?;
+        let messages = crate::db::load_agent_messages(&mut self.db_conn, conversation_id)
+            .map_err(|e| AppError::Other(e.to_string()))?;
+        let id = Uuid::parse_str(&summary.id).map_err(|e| AppError::Other(e.to_string()))?;
+
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return Ok(());
+        };
+        pane.exit_agent_mode();
+        pane.enter_agent_mode(String::new(), summary.model.clone());
+        if let Some(agent_state) = &mut pane.agent_state {
+            agent_state.id = id;
+            agent_state.task_summary = summary.title.clone();
+            agent_state.status = crate::app::pane::AgentStatus::WaitingForInput;
+            agent_state.conversation = messages
+                .into_iter()
+                .map(|message| (message.prompt, message.response))
+                .collect();
+            agent_state.is_follow_up = true;
+            self.mode = AppMode::Agent(agent_state.clone());
+        }
+        Ok(())
     }
 
     fn enter_ai_prompt_mode(&mut self) {
@@ -292,6 +6359,7 @@ impl App {
         };
 
         if text_changed {
+            self.sync_snippet();
             let text_after = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<String>();
             if text_before != text_after {
                 self.undo_stack.push(text_before);
@@ -337,6 +6405,12 @@ impl App {
                     *text_changed = true;
                     return None;
                 }
+                CompletionsAction::ExpandSnippet(prefix) => {
+                    self.completions_manager.ui.hide();
+                    self.expand_snippet_at_cursor(&prefix);
+                    *text_changed = true;
+                    return None;
+                }
                 CompletionsAction::Close => {
                     self.completions_manager.ui.hide();
                     return None;
@@ -350,243 +6424,400 @@ impl App {
             }
         }
 
-        // --- High-priority actions (copy, paste, cut) ---
-        if super_key && key.physical_key == winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyC) {
-            self.input_editor.copy_selection();
-            return None;
-        }
-        if super_key && key.physical_key == winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyX) {
-            self.input_editor.cut_selection();
-            return None;
-        }
-        if super_key && key.physical_key == winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyV) {
-            if let Ok(text) = clipboard.get_text() {
-                self.input_editor.insert_string(text, None);
-            }
-            return None;
-        }
+        let mods = InputModifiers { ctrl, alt, shift, super_key };
+        let command = resolve_modern_key(key.physical_key, mods, self.ime_state);
 
-        // --- Main keybinding logic ---
-        let action = match (key.physical_key, ctrl, alt, shift) {
-            // --- Cursor Movement ---
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowLeft), false, false, false) => CursorMove::Left,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowRight), false, false, false) => CursorMove::Right,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowUp), false, false, false) => CursorMove::Up,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowDown), false, false, false) => CursorMove::Down,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowLeft), false, true, false) => CursorMove::WordLeft,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowRight), false, true, false) => CursorMove::WordRight,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyA), true, _, _) | (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Home), _, _, _) => CursorMove::LineStart,
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyE), true, _, _) | (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::End), _, _, _) => CursorMove::LineEnd,
-
-            // --- Deletion ---
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Backspace), false, false, false) => {
-                self.input_editor.delete_char_back();
+        // --- Tab for completions ---
+        if command == EditorCommand::Tab && self.completions_manager.ui.is_visible {
+            let completions_action = self.completions_manager.handle_key_event(winit::keyboard::KeyCode::Tab);
+            if let CompletionsAction::ExpandSnippet(prefix) = &completions_action {
+                self.completions_manager.ui.hide();
+                self.expand_snippet_at_cursor(prefix);
                 *text_changed = true;
                 return None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Delete), false, false, false) => {
-                self.input_editor.delete_char_forward();
+            if let CompletionsAction::Accept(replacement) = completions_action {
+                // Replace current word with suggestion
+                let current_text = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<String>();
+                let cursor_pos = self.input_editor.buffer_ref().cursor().index;
+
+                if let Some((word_start, word_end)) = find_word_boundaries(&current_text, cursor_pos) {
+                    let before_word = &current_text[..word_start];
+                    let after_word = &current_text[word_end..];
+                    let new_text = format!("{}{}{}", before_word, replacement, after_word);
+
+                    self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &new_text, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                    self.input_editor.set_cursor(Cursor::new(word_start, 0));
+                }
+
+                self.completions_manager.ui.hide();
                 *text_changed = true;
-                return None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Backspace), false, true, false) => {
-                self.input_editor.delete_word_back();
-                *text_changed = true;
+            return None;
+        }
+
+        // --- Tab through snippet stops, or expand the snippet prefix before the cursor ---
+        if command == EditorCommand::Tab {
+            if let Some(session) = &mut self.snippet_session {
+                let step = session.advance();
+                self.apply_snippet_step(step);
                 return None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyD), false, true, false) => {
-                self.input_editor.delete_word_forward();
+            let (text, offset) = self.input_text_and_offset();
+            let word = text[..offset].rsplit(char::is_whitespace).next().unwrap_or_default();
+            if self.snippets.get(word).is_some() {
+                let word = word.to_string();
+                self.expand_snippet_at_cursor(&word);
                 *text_changed = true;
                 return None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyK), true, _, _) => {
-                self.input_editor.delete_to_line_end();
-                *text_changed = true;
-                return None;
+        }
+
+        self.apply_editor_command(command, key.text.as_deref(), clipboard, text_changed)
+    }
+
+    /// The input's lines joined by `\n`, and the cursor's byte offset in them.
+    fn input_text_and_offset(&self) -> (String, usize) {
+        let buffer = self.input_editor.buffer_ref();
+        let text = buffer.lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        let cursor = buffer.cursor();
+        let offset = buffer.lines.iter().take(cursor.line).map(|line| line.text().len() + 1).sum::<usize>() + cursor.index;
+        (text, offset.min(text.len()))
+    }
+
+    /// Replaces the word before the cursor with the snippet that has
+    /// `prefix`, selecting its first tab stop.
+    fn expand_snippet_at_cursor(&mut self, prefix: &str) {
+        let Some(snippet) = self.snippets.get(prefix) else {
+            return;
+        };
+        let builtins = Builtins {
+            clipboard: Clipboard::new().and_then(|mut clipboard| clipboard.get_text()).unwrap_or_default(),
+            cwd: self.panes.get(self.active_pane_idx).and_then(Pane::local_cwd).map(|cwd| cwd.display().to_string()).unwrap_or_default(),
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+        };
+        let template = match Template::parse(&snippet.body, &builtins) {
+            Ok(template) => template,
+            Err(e) => {
+                let message = format!("Snippet '{}' is invalid: {}", snippet.name, e);
+                self.notify(message);
+                return;
             }
+        };
+        let (text, offset) = self.input_text_and_offset();
+        let word = text[..offset].rsplit(char::is_whitespace).next().unwrap_or_default();
+        let session = SnippetSession::new(template, &text[..offset - word.len()], &text[offset..]);
+        let expanded = session.text();
+        self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &expanded, AttrsList::new(Attrs::new()), Shaping::Advanced);
+        let step = session.step();
+        self.snippet_session = Some(session);
+        self.apply_snippet_step(step);
+    }
 
-            // --- Selection ---
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyA), true, _, true) | (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyA), _, _, _,) if super_key => {
-                self.input_editor.select_all();
-                *text_changed = true;
-                return None;
+    /// Selects the current tab stop, or places the cursor and ends the snippet.
+    fn apply_snippet_step(&mut self, step: Step) {
+        let (text, _) = self.input_text_and_offset();
+        match step {
+            Step::Select(range) => {
+                self.input_editor.set_cursor(input_cursor_at(&text, range.end));
+                self.input_editor.set_selection(if range.is_empty() {
+                    Selection::None
+                } else {
+                    Selection::Normal(input_cursor_at(&text, range.start))
+                });
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowLeft), false, false, true) => {
-                self.input_editor.select_left();
-                *text_changed = true;
-                return None;
+            Step::Finish(offset) => {
+                self.input_editor.set_cursor(input_cursor_at(&text, offset));
+                self.input_editor.set_selection(Selection::None);
+                self.snippet_session = None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowRight), false, false, true) => {
-                self.input_editor.select_right();
-                *text_changed = true;
-                return None;
+        }
+    }
+
+    /// Copies what was typed into a tab stop to its mirrors. An edit outside
+    /// the stop ends the snippet.
+    fn sync_snippet(&mut self) {
+        let Some(session) = &mut self.snippet_session else {
+            return;
+        };
+        let (text, _) = self.input_text_and_offset();
+        match session.sync(&text) {
+            Some(synced) if synced != text => {
+                let cursor = session.cursor();
+                self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &synced, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                self.input_editor.set_cursor(input_cursor_at(&synced, cursor));
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowLeft), false, true, true) => {
-                self.input_editor.select_word_left();
-                *text_changed = true;
-                return None;
+            Some(_) => {}
+            None => self.snippet_session = None,
+        }
+    }
+
+    /// Applies a resolved editor command to the input editor. Returns the
+    /// command line when the input was submitted.
+    fn apply_editor_command(&mut self, command: EditorCommand, text: Option<&str>, clipboard: &mut Clipboard, text_changed: &mut bool) -> Option<String> {
+        match command {
+            EditorCommand::Move(motion) => {
+                if self.accept_autosuggestion(motion) {
+                    *text_changed = true;
+                } else if !self.move_input_cursor_visually(motion) {
+                    self.input_editor.move_cursor(cursor_move(motion));
+                }
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::ArrowRight), false, true, true) => {
-                self.input_editor.select_word_right();
-                *text_changed = true;
+            EditorCommand::Select(motion) => match motion {
+                Motion::Left => self.input_editor.select_left(),
+                Motion::Right => self.input_editor.select_right(),
+                Motion::WordLeft => self.input_editor.select_word_left(),
+                Motion::WordRight => self.input_editor.select_word_right(),
+                Motion::LineStart => self.input_editor.select_line_start(),
+                Motion::LineEnd => self.input_editor.select_line_end(),
+                Motion::Up | Motion::Down => {}
+            },
+            EditorCommand::SelectAll => self.input_editor.select_all(),
+            EditorCommand::DeleteBack => self.input_editor.delete_char_back(),
+            EditorCommand::DeleteForward => self.input_editor.delete_char_forward(),
+            EditorCommand::DeleteWordBack => self.input_editor.delete_word_back(),
+            EditorCommand::DeleteWordForward => self.input_editor.delete_word_forward(),
+            EditorCommand::DeleteToLineEnd => self.input_editor.delete_to_line_end(),
+            EditorCommand::Copy => {
+                self.input_editor.copy_selection();
                 return None;
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Home), false, false, true) => {
-                self.input_editor.select_line_start();
-                *text_changed = true;
-                return None;
+            EditorCommand::Cut => self.input_editor.cut_selection(),
+            EditorCommand::Paste => {
+                if let Ok(text) = clipboard.get_text() {
+                    self.input_editor.insert_string(text, None);
+                } else if let Ok(image) = clipboard.get_image() {
+                    self.paste_image(image);
+                }
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::End), false, false, true) => {
-                self.input_editor.select_line_end();
-                *text_changed = true;
+            EditorCommand::Undo => {
+                if let Some(text) = self.undo_stack.pop() {
+                    let current_text = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<String>();
+                    self.redo_stack.push(current_text);
+                    self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &text, AttrsList::new(Attrs::new()), Shaping::Advanced);
+                }
                 return None;
             }
-            
-            // --- Enter / Newline ---
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Enter), _, _, true) => {
-                self.input_editor.insert_string("\n", None);
-                *text_changed = true;
-                return None;
+            EditorCommand::Newline => self.input_editor.insert_string("\n", None),
+            EditorCommand::Tab => self.input_editor.insert_string("\t", None),
+            EditorCommand::InsertText => {
+                let Some(text) = text else {
+                    return None;
+                };
+                let closing = match text {
+                    "(" => Some(")"),
+                    "[" => Some("]"),
+                    "{" => Some("}"),
+                    "\"" => Some("\""),
+                    "'" => Some("'"),
+                    _ => None,
+                };
+                if text == " " {
+                    self.expand_abbreviation();
+                }
+                self.input_editor.insert_string(text.to_string(), None);
+                if let (true, Some(closing)) = (self.config.editor.autocomplete_pairs, closing) {
+                    self.input_editor.insert_string(closing.to_string(), None);
+                    self.input_editor.move_cursor(CursorMove::Left);
+                }
             }
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Enter), false, false, false) => {
+            EditorCommand::Submit => {
+                self.expand_abbreviation();
                 let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+                // Like the shell's PS2 prompt, an unfinished command goes on to a new line.
+                if let Some(pending) = continuation(&command) {
+                    self.continue_input_line(&pending);
+                    *text_changed = true;
+                    return None;
+                }
                 self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced); // Clears the editor
-                
+                self.pasted_images.clear();
+                self.snippet_session = None;
+                self.detected_query = None;
+
+                if self.config.ai.detect_natural_language && self.nl_detector.is_natural_language(&command) {
+                    self.ask_detected_query(command);
+                    return None;
+                }
+
                 // Add command to completions history
                 if !command.trim().is_empty() {
                     self.completions_manager.add_to_history(command.clone());
                 }
-                
+
                 return Some(command);
             }
-            
-            // --- Tab for completions ---
-            (winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Tab), false, false, false) => {
-                if self.completions_manager.ui.is_visible {
-                    // Handle tab completion
-                    let completions_action = self.completions_manager.handle_key_event(winit::keyboard::KeyCode::Tab);
-                    if let CompletionsAction::Accept(replacement) = completions_action {
-                        // Replace current word with suggestion
-                        let current_text = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<String>();
-                        let cursor_pos = self.input_editor.buffer_ref().cursor().index;
-                        
-                        if let Some((word_start, word_end)) = find_word_boundaries(&current_text, cursor_pos) {
-                            let before_word = &current_text[..word_start];
-                            let after_word = &current_text[word_end..];
-                            let new_text = format!("{}{}{}", before_word, replacement, after_word);
-                            
-                            self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &new_text, AttrsList::new(Attrs::new()), Shaping::Advanced);
-                            self.input_editor.set_cursor(Cursor::new(word_start, 0));
-                        }
-                        
-                        self.completions_manager.ui.hide();
-                        *text_changed = true;
-                    }
-                    return None;
-                }
-                self.input_editor.insert_string("\t", None);
-                *text_changed = true;
-                return None;
-            }
-            
-            // --- Text Input ---
-            _ => {
-                if let Some(text) = &key.text {
-                    if self.config.editor.autocomplete_pairs {
-                        let pair = match text.as_str() {
-                            "(" => Some(")"),
-                            "[" => Some("]"),
-                            "{" => Some("}"),
-                            "\"" => Some("\""),
-                            "'" => Some("'"),
-                            _ => None,
-                        };
-                        if let Some(closing) = pair {
-                            self.input_editor.insert_string(text.to_string(), None);
-                            self.input_editor.insert_string(closing.to_string(), None);
-                            self.input_editor.move_cursor(CursorMove::Left);
-                        } else {
-                            self.input_editor.insert_string(text.to_string(), None);
-                        }
-                    } else {
-                        self.input_editor.insert_string(text.to_string(), None);
-                    }
-                }
-                *text_changed = true;
-                return None;
-            }
-        };
-        
-        // If we reach here, it means a CursorMove action was returned
-        self.input_editor.move_cursor(action);
+            EditorCommand::Ignore => return None,
+        }
         *text_changed = true;
         None
     }
 
+    /// Hands input that reads like a question to the agent, keeping it so
+    /// Alt+C can run it as a command after all.
+    fn ask_detected_query(&mut self, query: String) {
+        if let Err(e) = self.start_agent_task(query.clone()) {
+            self.notify(format!("Failed to ask the agent: {}", e));
+            return;
+        }
+        self.notify("Sent to the agent as a question (Alt+C: run it as a command)".to_string());
+        self.detected_query = Some(query);
+    }
+
+    /// Runs the last input taken for a question as a command after all, and
+    /// takes it, and inputs scored like it, as commands from now on.
+    pub fn run_detected_query_as_command(&mut self) {
+        let Some(input) = self.detected_query.take() else {
+            return;
+        };
+        let correction = Correction { input: input.clone(), is_command: true };
+        if let Err(e) = crate::db::save_nl_correction(&self.db_conn, &correction, chrono::Utc::now().timestamp()) {
+            log::warn!("Failed to save the input correction: {}", e);
+        }
+        self.nl_detector.learn(&[correction]);
+        let Some(pane) = self.panes.get_mut(self.active_pane_idx) else {
+            return;
+        };
+        pane.agent_loop = None;
+        pane.exit_agent_mode();
+        self.mode = AppMode::Normal;
+        if let Err(e) = pane.run_command(&input) {
+            self.notify(format!("Failed to send command to the shell: {}", e));
+        }
+    }
+
+    /// Starts a new input line at the current line's indentation, one level
+    /// deeper inside an unfinished `if`, loop or group.
+    fn continue_input_line(&mut self, pending: &Continuation) {
+        let cursor = self.input_editor.buffer_ref().cursor();
+        let current: String = self
+            .input_editor
+            .buffer_ref()
+            .lines
+            .get(cursor.line)
+            .map(|line| line.text().chars().take_while(|c| *c == ' ' || *c == '\t').collect())
+            .unwrap_or_default();
+        let indent = match pending {
+            // Heredoc bodies and quoted text are taken literally.
+            Continuation::Heredoc(_) | Continuation::Quote(_) => String::new(),
+            Continuation::Keyword(_) | Continuation::Group(_) => format!("{}  ", current),
+            Continuation::Backslash | Continuation::Operator(_) => current,
+        };
+        self.input_editor.insert_string(format!("\n{}", indent), None);
+    }
+
+    /// What the command line is waiting for before Enter will run it.
+    pub fn input_continuation(&self) -> Option<Continuation> {
+        let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
+        continuation(&command)
+    }
+
+    /// Moves the input cursor left or right in display order when its line
+    /// contains right-to-left text, over whole grapheme clusters so it
+    /// never stops between a letter and its marks. Returns false for other
+    /// motions, plain left-to-right lines, and at the line's edges, where
+    /// the editor's own motion wraps to the neighbouring line.
+    fn move_input_cursor_visually(&mut self, motion: Motion) -> bool {
+        let direction = match motion {
+            Motion::Left => VisualDirection::Left,
+            Motion::Right => VisualDirection::Right,
+            _ => return false,
+        };
+        let cursor = self.input_editor.buffer_ref().cursor();
+        let Some(line) = self.input_editor.buffer_ref().lines.get(cursor.line) else {
+            return false;
+        };
+        let text = line.text();
+        if !bidi::has_rtl(text) {
+            return false;
+        }
+        let index = text[..cursor.index.min(text.len())].chars().count();
+        let target = BidiLine::new(text, None).move_cursor(index, direction);
+        if target == index {
+            return false;
+        }
+        let byte_index = text.char_indices().nth(target).map_or(text.len(), |(i, _)| i);
+        let byte_index = clip_to_grapheme(text, byte_index, if target > index { Bias::Right } else { Bias::Left });
+        self.input_editor.set_cursor(Cursor::new(cursor.line, byte_index));
+        true
+    }
+
     /// New handler for Vim mode.
     fn handle_vim_input(&mut self, state: &mut VimState, key: &KeyEvent, clipboard: &mut Clipboard, text_changed: &mut bool) -> Option<String> {
-        // --- Parse the key into a high-level Vim action ---
-        let vim_action = state.handle_key(key);
-        // --- Execute the Vim action using cosmic-text actions ---
-        match vim_action {
-            VimAction::EnterInsertMode => state.mode = VimMode::Insert,
-            VimAction::EnterNormalMode => state.mode = VimMode::Normal,
-            VimAction::EnterVisualMode => {
-                state.mode = VimMode::Visual;
-                self.input_editor.select_all(); // Simplified for now
-            }
-            VimAction::Move(motion) => {
-                let action = match motion {
-                    VimMotion::Left => CursorMove::Left,
-                    VimMotion::Right => CursorMove::Right,
-                    VimMotion::Up => CursorMove::Up,
-                    VimMotion::Down => CursorMove::Down,
-                    VimMotion::WordForward => CursorMove::WordRight,
-                    VimMotion::WordBackward => CursorMove::WordLeft,
-                    VimMotion::LineStart => CursorMove::LineStart,
-                    VimMotion::LineEnd => CursorMove::LineEnd,
-                };
-                self.input_editor.move_cursor(action);
-            }
-            VimAction::Delete(_motion) => {
-                self.input_editor.delete_word_forward(); // Simplified example
-            }
-            VimAction::Paste => { if let Ok(text) = clipboard.get_text() { self.input_editor.insert_string(text, None); } }
-            VimAction::Undo => {
-                if let Some(text) = self.undo_stack.pop() {
-                    let current_text = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<String>();
-                    self.redo_stack.push(current_text);
-                    self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, &text, AttrsList::new(Attrs::new()), Shaping::Advanced);
-                }
+        for command in resolve_vim_key(state, key.physical_key, self.ime_state) {
+            if let Some(submitted) = self.apply_editor_command(command, key.text.as_deref(), clipboard, text_changed) {
+                return Some(submitted);
             }
-            _ => {}
         }
-        // If we're in Insert mode, handle text input like normal
-        if state.mode == VimMode::Insert {
-            if let Some(text) = &key.text {
-                self.input_editor.insert_string(text.to_string(), None);
-            }
-            if key.physical_key == winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Backspace) {
-                self.input_editor.delete_char_back();
+        None
+    }
+}
+
+/// Rows a history block takes: its command line plus at least two rows
+/// of output, counting a row for each "N lines" marker its view adds.
+fn history_rows(pane: &Pane, block: &Block, config: &BlocksConfig) -> usize {
+    let view = pane.output_view(block, config);
+    1 + fold::visible_output(&block.output, view, config).rows().max(2)
+}
+
+/// Runs a listener on a thread and runtime of its own until it stops or the
+/// app shuts down. `start` is called on the runtime and returns the
+/// listener's task, or `None` when it couldn't be started.
+fn run_listener<F>(what: &'static str, cancel: CancellationToken, start: F)
+where
+    F: FnOnce() -> Option<tokio::task::JoinHandle<()>> + Send + 'static,
+{
+    thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::error!("Failed to start the {} listener runtime: {}", what, e);
+                return;
             }
-        }
-        // In Vim mode, Enter only executes from Normal mode (simplified)
-        if state.mode == VimMode::Normal && key.physical_key == winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Enter) {
-            let command = self.input_editor.buffer_ref().lines.iter().map(|line| line.text()).collect::<Vec<_>>().join("\n");
-            self.input_editor.buffer_ref_mut().set_text(&mut self.input_editor.font_system, "", AttrsList::new(Attrs::new()), Shaping::Advanced);
-            
-            // Add command to completions history
-            if !command.trim().is_empty() {
-                self.completions_manager.add_to_history(command.clone());
+        };
+        runtime.block_on(async move {
+            let Some(listener) = start() else {
+                return;
+            };
+            tokio::select! {
+                _ = listener => {}
+                _ = cancel.cancelled() => {}
             }
-            
-            return Some(command);
-        }
-        *text_changed = true;
-        None
+        });
+    });
+}
+
+/// The encodings new panes start with; unknown names fall back to UTF-8.
+fn configured_encodings(config: &EncodingConfig) -> PaneEncodings {
+    let parse = |label: &str| {
+        PaneEncoding::from_label(label).unwrap_or_else(|| {
+            log::warn!("Unknown encoding '{}', using UTF-8", label);
+            PaneEncoding::Utf8
+        })
+    };
+    PaneEncodings { input: parse(&config.input), output: parse(&config.output) }
+}
+
+fn cursor_move(motion: Motion) -> CursorMove {
+    match motion {
+        Motion::Left => CursorMove::Left,
+        Motion::Right => CursorMove::Right,
+        Motion::Up => CursorMove::Up,
+        Motion::Down => CursorMove::Down,
+        Motion::WordLeft => CursorMove::WordLeft,
+        Motion::WordRight => CursorMove::WordRight,
+        Motion::LineStart => CursorMove::LineStart,
+        Motion::LineEnd => CursorMove::LineEnd,
     }
 }
 
+/// The editor cursor at a byte offset into the input's lines joined by `\n`.
+fn input_cursor_at(text: &str, offset: usize) -> Cursor {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Cursor::new(before.matches('\n').count(), before.len() - line_start)
+}
+
 /// Helper function to find word boundaries for completion replacement
 fn find_word_boundaries(text: &str, cursor_pos: usize) -> Option<(usize, usize)> {
     if cursor_pos > text.len() {
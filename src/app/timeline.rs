@@ -0,0 +1,184 @@
+//! A pane's history over time, for scrubbing back to how its screen looked.
+//!
+//! Completed blocks are marked on the timeline when they are sealed, and the
+//! live screen is snapshotted while it changes. The screen at a moment is
+//! rebuilt from the blocks finished by then plus the last snapshot taken
+//! since the latest of them.
+
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+/// Minimum milliseconds between two snapshots of the live screen.
+const SNAPSHOT_INTERVAL_MS: i64 = 1000;
+
+/// Snapshots kept per pane; the oldest are dropped first.
+const MAX_SNAPSHOTS: usize = 600;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub at: i64,
+    pub screen: String,
+}
+
+/// When a block was sealed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockTick {
+    pub at: i64,
+    pub block_id: Uuid,
+}
+
+/// The pane as it was at one moment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<'a> {
+    /// Blocks finished by then, oldest first.
+    pub blocks: Vec<Uuid>,
+    /// The live screen below them.
+    pub screen: &'a str,
+}
+
+/// Times are milliseconds since the Unix epoch.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    snapshots: VecDeque<Snapshot>,
+    ticks: Vec<BlockTick>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether enough time has passed since the last snapshot for another.
+    pub fn is_due(&self, at: i64) -> bool {
+        self.snapshots.back().is_none_or(|last| at - last.at >= SNAPSHOT_INTERVAL_MS)
+    }
+
+    /// Records the live screen unless it is unchanged or was recorded moments ago.
+    pub fn capture(&mut self, at: i64, screen: &str) {
+        if !self.is_due(at) || self.snapshots.back().is_some_and(|last| last.screen == screen) {
+            return;
+        }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot { at, screen: screen.to_string() });
+    }
+
+    /// Marks a block as sealed; the live screen starts over after it.
+    pub fn mark_block(&mut self, block_id: Uuid, at: i64) {
+        self.ticks.push(BlockTick { at, block_id });
+    }
+
+    pub fn ticks(&self) -> &[BlockTick] {
+        &self.ticks
+    }
+
+    /// The earliest and latest recorded moments.
+    pub fn range(&self) -> Option<(i64, i64)> {
+        let times = self.snapshots.iter().map(|snapshot| snapshot.at).chain(self.ticks.iter().map(|tick| tick.at));
+        times.fold(None, |range, at| match range {
+            None => Some((at, at)),
+            Some((start, end)) => Some((start.min(at), end.max(at))),
+        })
+    }
+
+    pub fn frame(&self, at: i64) -> Frame<'_> {
+        let finished: Vec<&BlockTick> = self.ticks.iter().filter(|tick| tick.at <= at).collect();
+        let since = finished.last().map_or(i64::MIN, |tick| tick.at);
+        let screen = self
+            .snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.at <= at)
+            .filter(|snapshot| snapshot.at > since)
+            .map_or("", |snapshot| snapshot.screen.as_str());
+        Frame { blocks: finished.iter().map(|tick| tick.block_id).collect(), screen }
+    }
+
+    /// The next recorded moment after `at`, or the one before it.
+    pub fn step(&self, at: i64, forward: bool) -> i64 {
+        let times = self.snapshots.iter().map(|snapshot| snapshot.at).chain(self.ticks.iter().map(|tick| tick.at));
+        Self::nearest(times, at, forward)
+    }
+
+    /// The next block boundary after `at`, or the one before it.
+    pub fn jump_block(&self, at: i64, forward: bool) -> i64 {
+        Self::nearest(self.ticks.iter().map(|tick| tick.at), at, forward)
+    }
+
+    fn nearest(times: impl Iterator<Item = i64>, at: i64, forward: bool) -> i64 {
+        let nearest = if forward {
+            times.filter(|time| *time > at).min()
+        } else {
+            times.filter(|time| *time < at).max()
+        };
+        nearest.unwrap_or(at)
+    }
+
+    /// The moment a fraction of the way along the timeline, for dragging.
+    pub fn at_fraction(&self, fraction: f32) -> Option<i64> {
+        let (start, end) = self.range()?;
+        Some(start + ((end - start) as f64 * f64::from(fraction.clamp(0.0, 1.0))).round() as i64)
+    }
+
+    /// A text scrubber `width` cells wide: `|` marks block boundaries and
+    /// `●` the moment shown.
+    pub fn bar(&self, at: i64, width: usize) -> String {
+        let Some((start, end)) = self.range().filter(|_| width > 0) else {
+            return String::new();
+        };
+        let cell = |time: i64| match end - start {
+            0 => width - 1,
+            span => ((time - start) as f64 / span as f64 * (width - 1) as f64).round() as usize,
+        };
+        let mut cells = vec!['─'; width];
+        for tick in &self.ticks {
+            cells[cell(tick.at)] = '|';
+        }
+        cells[cell(at.clamp(start, end))] = '●';
+        cells.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_rebuild_blocks_and_screen() {
+        let mut timeline = Timeline::new();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        timeline.capture(1000, "$ make");
+        timeline.capture(1500, "$ make\nbuilding");
+        timeline.capture(2000, "$ make\nbuilding");
+        timeline.capture(3000, "$ make\nbuilt");
+        timeline.mark_block(first, 3500);
+        timeline.capture(5000, "$ ls");
+        timeline.mark_block(second, 6000);
+
+        // Snapshots moments apart are merged.
+        assert_eq!(timeline.frame(1800), Frame { blocks: vec![], screen: "$ make" });
+        assert_eq!(timeline.frame(2500).screen, "$ make\nbuilding");
+        assert_eq!(timeline.frame(3200).screen, "$ make\nbuilt");
+        assert_eq!(timeline.frame(4000), Frame { blocks: vec![first], screen: "" });
+        assert_eq!(timeline.frame(5000), Frame { blocks: vec![first], screen: "$ ls" });
+        assert_eq!(timeline.frame(9000).blocks, vec![first, second]);
+    }
+
+    #[test]
+    fn test_stepping_and_bar() {
+        let mut timeline = Timeline::new();
+        timeline.capture(0, "a");
+        timeline.mark_block(Uuid::new_v4(), 4000);
+        timeline.capture(6000, "b");
+        timeline.mark_block(Uuid::new_v4(), 8000);
+
+        assert_eq!(timeline.step(0, true), 4000);
+        assert_eq!(timeline.step(4000, false), 0);
+        assert_eq!(timeline.jump_block(0, true), 4000);
+        assert_eq!(timeline.jump_block(4000, true), 8000);
+        assert_eq!(timeline.jump_block(8000, true), 8000);
+        assert_eq!(timeline.at_fraction(0.75), Some(6000));
+        assert_eq!(timeline.bar(6000, 9), "────|─●─|");
+    }
+}
@@ -36,7 +36,7 @@ impl CodeBlock {
 
     /// Diffs the code block against another string.
     pub fn diff(&self, other: &str) -> String {
-        let diff = TextDiff::from_lines(&self.content, other);
+        let diff = TextDiff::from_lines(self.content.as_str(), other);
         let mut result = String::new();
 
         for change in diff.iter_all_changes() {
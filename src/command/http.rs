@@ -0,0 +1,291 @@
+//! HTTP Requests
+//!
+//! The `http` built-in sends a request written in a small DSL and shows the
+//! response in a block:
+//!
+//! ```text
+//! @host = https://api.example.com
+//! http POST {{host}}/users
+//! Authorization: Bearer {{token}}
+//! Content-Type: application/json
+//!
+//! {"name": "Ada"}
+//! ```
+//!
+//! `@name = value` lines define variables; `{{name}}` is replaced by the
+//! variable, a configured variable or an environment variable, in that
+//! order. The request line is the method and URL, `GET` if only a URL is
+//! given. Header lines follow until a blank line, and the rest is the body.
+//! Saved requests go to `.http` files, one per collection, separated by
+//! `###` lines as in other REST clients.
+
+use super::CommandError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+lazy_static! {
+    static ref VARIABLE: Regex = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_.-]*)\s*\}\}").unwrap();
+}
+
+const METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub elapsed: Duration,
+}
+
+impl HttpResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    /// The body as JSON, when it is JSON.
+    pub fn json(&self) -> Option<serde_json::Value> {
+        let declared = self.header("content-type").is_some_and(|content_type| content_type.contains("json"));
+        let looks_like = self.body.trim_start().starts_with(['{', '[']);
+        if declared || looks_like {
+            serde_json::from_str(&self.body).ok()
+        } else {
+            None
+        }
+    }
+
+    /// The status line, headers and body, with JSON pretty-printed.
+    pub fn render(&self) -> String {
+        let mut text = format!("HTTP {} {} ({} ms)\n", self.status, self.reason, self.elapsed.as_millis());
+        for (name, value) in &self.headers {
+            text.push_str(&format!("{}: {}\n", name, value));
+        }
+        let body = match self.json() {
+            Some(json) => serde_json::to_string_pretty(&json).unwrap_or_else(|_| self.body.clone()),
+            None => self.body.clone(),
+        };
+        if !body.is_empty() {
+            text.push('\n');
+            text.push_str(body.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Whether `text` is an `http` command, possibly after variable lines.
+pub fn is_http_command(text: &str) -> bool {
+    text.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('@')).is_some_and(|line| line == "http" || line.starts_with("http "))
+}
+
+/// Replaces `{{name}}` with the first of `variables` that defines it.
+fn substitute(text: &str, variables: &[&BTreeMap<String, String>]) -> Result<String, CommandError> {
+    let mut missing = None;
+    let result = VARIABLE.replace_all(text, |captures: &regex::Captures| {
+        let name = &captures[1];
+        match variables.iter().find_map(|scope| scope.get(name)).cloned().or_else(|| std::env::var(name).ok()) {
+            Some(value) => value,
+            None => {
+                missing.get_or_insert_with(|| name.to_string());
+                String::new()
+            }
+        }
+    });
+    match missing {
+        Some(name) => Err(CommandError::ParseError(format!("Undefined variable {{{{{}}}}}", name))),
+        None => Ok(result.into_owned()),
+    }
+}
+
+/// Parses an `http` command, filling in variables from the command itself,
+/// then `configured`, then the environment.
+pub fn parse_request(text: &str, configured: &BTreeMap<String, String>) -> Result<HttpRequest, CommandError> {
+    let mut inline = BTreeMap::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.peek().map(|line| line.trim()) {
+        if line.is_empty() {
+            lines.next();
+        } else if let Some(definition) = line.strip_prefix('@') {
+            let (name, value) = definition
+                .split_once('=')
+                .ok_or_else(|| CommandError::ParseError(format!("Expected `@name = value`, found `{}`", line)))?;
+            let value = substitute(value.trim(), &[&inline, configured])?;
+            inline.insert(name.trim().to_string(), value);
+            lines.next();
+        } else {
+            break;
+        }
+    }
+    let scopes = [&inline, configured];
+
+    let request_line = lines.next().ok_or_else(|| CommandError::ParseError("Missing request line".to_string()))?;
+    // Substituted first, as `{{ name }}` may contain spaces.
+    let request_line = substitute(request_line, &scopes)?;
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("http") {
+        return Err(CommandError::ParseError("Requests start with `http`".to_string()));
+    }
+    let (method, url) = match (parts.next(), parts.next()) {
+        (Some(method), Some(url)) if METHODS.contains(&method.to_uppercase().as_str()) => (method.to_uppercase(), url.to_string()),
+        (Some(url), None) => ("GET".to_string(), url.to_string()),
+        _ => return Err(CommandError::ParseError(format!("Expected `http [METHOD] URL`, found `{}`", request_line.trim()))),
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(CommandError::ParseError(format!("Not an http(s) URL: {}", url)));
+    }
+
+    let mut headers = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| CommandError::ParseError(format!("Expected `Name: value`, found `{}`", line.trim())))?;
+        headers.push((name.trim().to_string(), substitute(value.trim(), &scopes)?));
+    }
+    let body = lines.collect::<Vec<_>>().join("\n");
+    let body = if body.trim().is_empty() { None } else { Some(substitute(body.trim_end(), &scopes)?) };
+    Ok(HttpRequest { method, url, headers, body })
+}
+
+/// Sends `request`, giving up after `timeout`.
+pub async fn send(client: &reqwest::Client, request: &HttpRequest, timeout: Duration) -> Result<HttpResponse, CommandError> {
+    let method = reqwest::Method::from_bytes(request.method.as_bytes()).map_err(|e| CommandError::ParseError(e.to_string()))?;
+    let mut builder = client.request(method, &request.url).timeout(timeout);
+    for (name, value) in &request.headers {
+        builder = builder.header(name, value);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.body(body.clone());
+    }
+    let started = Instant::now();
+    let response = builder.send().await.map_err(|e| CommandError::ExecutionFailed(e.to_string()))?;
+    let status = response.status();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+        .collect();
+    let body = response.text().await.map_err(|e| CommandError::ExecutionFailed(e.to_string()))?;
+    Ok(HttpResponse {
+        status: status.as_u16(),
+        reason: status.canonical_reason().unwrap_or_default().to_string(),
+        headers,
+        body,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// Appends `command` to the collection's `.http` file in `dir` and returns
+/// the file's path.
+pub fn save_to_collection(dir: &Path, collection: &str, command: &str) -> std::io::Result<PathBuf> {
+    use std::io::Write;
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.http", collection));
+    let separator = if path.exists() { "\n" } else { "" };
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    write!(file, "{}### {}\n{}\n", separator, chrono::Local::now().format("%Y-%m-%d %H:%M"), command.trim_end())?;
+    Ok(path)
+}
+
+/// Sends requests on a background thread, reporting each response with the
+/// id of the block that shows it.
+pub struct HttpRunner {
+    requests: Sender<(Uuid, HttpRequest, Duration)>,
+    results: Receiver<(Uuid, Result<HttpResponse, CommandError>)>,
+}
+
+impl Default for HttpRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpRunner {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<(Uuid, HttpRequest, Duration)>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Failed to start HTTP runtime: {}", e);
+                    return;
+                }
+            };
+            let client = reqwest::Client::new();
+            for (block_id, request, timeout) in request_rx {
+                let response = runtime.block_on(send(&client, &request, timeout));
+                if result_tx.send((block_id, response)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { requests, results }
+    }
+
+    pub fn run(&self, block_id: Uuid, request: HttpRequest, timeout: Duration) {
+        self.requests.send((block_id, request, timeout)).ok();
+    }
+
+    /// Takes the responses that arrived since the last poll.
+    pub fn poll(&self) -> Vec<(Uuid, Result<HttpResponse, CommandError>)> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_parsed_with_variables() {
+        let configured = BTreeMap::from([("token".to_string(), "s3cret".to_string()), ("host".to_string(), "http://ignored".to_string())]);
+        let text = "@host = https://api.example.com\n@id = 42\nhttp post {{host}}/users/{{ id }}\nAuthorization: Bearer {{token}}\nContent-Type: application/json\n\n{\"id\": {{id}}}\n";
+        assert!(is_http_command(text));
+        let request = parse_request(text, &configured).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "https://api.example.com/users/42");
+        assert_eq!(request.headers[0], ("Authorization".to_string(), "Bearer s3cret".to_string()));
+        assert_eq!(request.body.as_deref(), Some("{\"id\": 42}"));
+
+        let request = parse_request("http https://example.com/health", &BTreeMap::new()).unwrap();
+        assert_eq!((request.method.as_str(), request.body), ("GET", None));
+        assert!(parse_request("http GET {{warpish_test_undefined}}/x", &BTreeMap::new()).is_err());
+        assert!(parse_request("http GET ftp://example.com", &BTreeMap::new()).is_err());
+        assert!(!is_http_command("httpie GET example.com"));
+    }
+
+    #[test]
+    fn test_responses_render_pretty_json() {
+        let response = HttpResponse {
+            status: 201,
+            reason: "Created".to_string(),
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: "{\"id\":42}".to_string(),
+            elapsed: Duration::from_millis(87),
+        };
+        assert_eq!(response.render(), "HTTP 201 Created (87 ms)\ncontent-type: application/json\n\n{\n  \"id\": 42\n}\n");
+        assert_eq!(response.json(), Some(serde_json::json!({"id": 42})));
+
+        let dir = tempfile::tempdir().unwrap();
+        save_to_collection(dir.path(), "users", "http GET https://example.com/a").unwrap();
+        let path = save_to_collection(dir.path(), "users", "http GET https://example.com/b\n").unwrap();
+        let saved = std::fs::read_to_string(path).unwrap();
+        assert_eq!(saved.matches("### ").count(), 2);
+        assert!(saved.ends_with("\nhttp GET https://example.com/b\n"));
+    }
+}
@@ -3,6 +3,8 @@
 //! This module handles command parsing, execution, and management
 //! for the terminal, including built-in commands and external process execution.
 
+pub mod http;
+
 use std::collections::HashMap;
 use std::process::{Command as StdCommand, Stdio};
 use serde::{Deserialize, Serialize};
@@ -48,6 +50,16 @@ impl CommandParser {
             usage: "clear".to_string(),
             examples: vec!["clear".to_string()],
         });
+
+        builtin_commands.insert("http".to_string(), CommandInfo {
+            name: "http".to_string(),
+            description: "Send an HTTP request and show the response".to_string(),
+            usage: "http [METHOD] URL, then `Name: value` header lines, a blank line and the body".to_string(),
+            examples: vec![
+                "http https://api.github.com/zen".to_string(),
+                "http POST {{host}}/users\nContent-Type: application/json\n\n{\"name\": \"Ada\"}".to_string(),
+            ],
+        });
         
         Self { builtin_commands }
     }
@@ -69,10 +81,23 @@ impl CommandParser {
         match command {
             "help" => self.handle_help(args),
             "clear" => Ok("\x1b[2J\x1b[H".to_string()),
+            "http" => self.handle_http(args),
             _ => Err(CommandError::NotFound(command.to_string())),
         }
     }
     
+    /// Sends a one-line request and waits for the response. The app sends
+    /// multi-line requests itself, without blocking, through `http::HttpRunner`.
+    fn handle_http(&self, args: &[String]) -> Result<String, CommandError> {
+        let request = http::parse_request(&format!("http {}", args.join(" ")), &Default::default())?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| CommandError::ExecutionFailed(e.to_string()))?;
+        let response = runtime.block_on(http::send(&reqwest::Client::new(), &request, std::time::Duration::from_secs(30)))?;
+        Ok(response.render())
+    }
+
     fn handle_help(&self, args: &[String]) -> Result<String, CommandError> {
         if args.is_empty() {
             let mut help_text = String::from("Available commands:\n");
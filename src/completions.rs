@@ -1,9 +1,13 @@
-use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use crate::fuzzy_match::fuzzy_match;
 use std::{collections::HashMap, fs, path::Path};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use crate::k8s::{completion_kind, KubeResources, ResourceKind};
+use crate::prefetch::PrefetchSource;
+use crate::ssh::corpus::HostCorpus;
+use crate::virtual_fs::MountTable;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Suggestion {
@@ -24,10 +28,47 @@ pub enum SuggestionType {
     History,
     AiGenerated,
     Workflow,
+    Host,
+    /// Expands into a template with tab stops rather than replacing the word.
+    Snippet,
+}
+
+impl SuggestionType {
+    /// Short tag shown next to a suggestion in the completions popup.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            SuggestionType::Command => "[CMD]",
+            SuggestionType::Subcommand => "[SUB]",
+            SuggestionType::Flag => "[FLAG]",
+            SuggestionType::Argument => "[ARG]",
+            SuggestionType::FilePath => "[FILE]",
+            SuggestionType::History => "[HIST]",
+            SuggestionType::AiGenerated => "[AI]",
+            SuggestionType::Workflow => "[WF]",
+            SuggestionType::Host => "[HOST]",
+            SuggestionType::Snippet => "[SNIP]",
+        }
+    }
+
+    /// Glyph at the start of a suggestion's row in the popup.
+    pub fn icon(&self) -> &'static str {
+        match self {
+            SuggestionType::Command => "❯",
+            SuggestionType::Subcommand => "↳",
+            SuggestionType::Flag => "⚑",
+            SuggestionType::Argument => "•",
+            SuggestionType::FilePath => "📄",
+            SuggestionType::History => "🕘",
+            SuggestionType::AiGenerated => "✨",
+            SuggestionType::Workflow => "⚡",
+            SuggestionType::Host => "🖥",
+            SuggestionType::Snippet => "✂",
+        }
+    }
 }
 
 /// A trait for any object that can provide completion suggestions.
-trait Completer {
+pub trait Completer {
     fn suggest(&self, context: &str) -> Vec<Suggestion>;
 }
 
@@ -73,7 +114,7 @@ impl Completer for CommandSpec {
 }
 
 /// A completer for file and directory paths.
-struct FilePathCompleter;
+pub struct FilePathCompleter;
 
 impl Completer for FilePathCompleter {
     fn suggest(&self, context: &str) -> Vec<Suggestion> {
@@ -113,6 +154,250 @@ impl Completer for FilePathCompleter {
     }
 }
 
+/// `git` subcommands whose arguments are completed with branch names.
+const GIT_BRANCH_SUBCOMMANDS: &[&str] = &["checkout", "switch", "merge", "rebase", "branch", "push", "pull", "diff", "log"];
+
+/// `docker` subcommands whose arguments are completed with container names.
+const DOCKER_CONTAINER_SUBCOMMANDS: &[&str] = &["exec", "logs", "stop", "start", "restart", "rm", "inspect", "attach", "kill"];
+
+/// Subcommands whose arguments are completed with a prefetched list.
+const PACKAGE_SUBCOMMANDS: &[(&str, &[&str], PrefetchSource)] = &[
+    ("brew", &["install", "info", "home", "reinstall", "upgrade", "uninstall"], PrefetchSource::BrewFormulae),
+    ("pip", &["show", "uninstall", "install"], PrefetchSource::PipPackages),
+    ("pip3", &["show", "uninstall", "install"], PrefetchSource::PipPackages),
+    ("cargo", &["add", "install", "info", "search"], PrefetchSource::Crates),
+];
+
+/// Commands whose arguments are completed with remote hostnames.
+const SSH_COMMANDS: &[&str] = &["ssh", "scp", "rsync", "mosh"];
+
+/// Where a known host was discovered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostSource {
+    SshConfig,
+    KnownHosts,
+    EtcHosts,
+}
+
+/// A remote host that can be offered as a completion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshHost {
+    pub name: String,
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub source: HostSource,
+}
+
+impl SshHost {
+    fn description(&self) -> String {
+        let target = match (&self.user, &self.hostname) {
+            (Some(user), Some(hostname)) => Some(format!("{}@{}", user, hostname)),
+            (Some(user), None) => Some(format!("{}@{}", user, self.name)),
+            (None, Some(hostname)) => Some(hostname.clone()),
+            (None, None) => None,
+        };
+        let source = match self.source {
+            HostSource::SshConfig => "~/.ssh/config",
+            HostSource::KnownHosts => "known_hosts",
+            HostSource::EtcHosts => "/etc/hosts",
+        };
+        match target {
+            Some(target) => format!("{} ({})", target, source),
+            None => source.to_string(),
+        }
+    }
+}
+
+/// A completer for `ssh`, `scp`, `rsync` and `mosh` hostnames, sourced from
+/// `~/.ssh/config`, `~/.ssh/known_hosts` and `/etc/hosts`.
+pub struct SshCompleter {
+    hosts: Vec<SshHost>,
+}
+
+impl SshCompleter {
+    /// Loads hosts from the user's SSH files and the system hosts file.
+    pub fn load() -> Self {
+        let ssh_dir = dirs::home_dir().map(|home| home.join(".ssh"));
+        let read = |path: Option<std::path::PathBuf>| {
+            path.and_then(|p| fs::read_to_string(p).ok()).unwrap_or_default()
+        };
+        let config = read(ssh_dir.as_ref().map(|d| d.join("config")));
+        let known_hosts = read(ssh_dir.as_ref().map(|d| d.join("known_hosts")));
+        let etc_hosts = read(Some(std::path::PathBuf::from("/etc/hosts")));
+        Self::from_sources(&config, &known_hosts, &etc_hosts)
+    }
+
+    /// Builds a completer from raw file contents. Hosts found in earlier
+    /// sources take precedence over duplicates in later ones.
+    pub fn from_sources(config: &str, known_hosts: &str, etc_hosts: &str) -> Self {
+        let mut hosts: Vec<SshHost> = Vec::new();
+        let candidates = parse_ssh_config(config)
+            .into_iter()
+            .chain(parse_known_hosts(known_hosts))
+            .chain(parse_etc_hosts(etc_hosts));
+        for host in candidates {
+            if !hosts.iter().any(|h| h.name == host.name) {
+                hosts.push(host);
+            }
+        }
+        Self { hosts }
+    }
+
+    pub fn hosts(&self) -> &[SshHost] {
+        &self.hosts
+    }
+
+    pub fn handles(command: &str) -> bool {
+        SSH_COMMANDS.contains(&command)
+    }
+
+    /// Suggests hosts for the word being typed. A `user@` prefix is kept in the
+    /// replacement, and `scp`/`rsync` targets get a trailing `:` for the path.
+    pub fn suggest_for_command(&self, command: &str, context: &str) -> Vec<Suggestion> {
+        if context.starts_with('-') || context.contains(':') || context.contains('/') {
+            return Vec::new();
+        }
+        let (user_prefix, partial) = match context.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, context),
+        };
+        let path_suffix = if command == "scp" || command == "rsync" { ":" } else { "" };
+
+        self.hosts
+            .iter()
+            .filter(|host| host.name.starts_with(partial))
+            .map(|host| {
+                let target = match user_prefix {
+                    Some(user) => format!("{}@{}", user, host.name),
+                    None => host.name.clone(),
+                };
+                Suggestion {
+                    display: target.clone(),
+                    replacement: format!("{}{}", target, path_suffix),
+                    description: Some(host.description()),
+                    suggestion_type: SuggestionType::Host,
+                    confidence: match host.source {
+                        HostSource::SshConfig => 0.9,
+                        HostSource::KnownHosts => 0.8,
+                        HostSource::EtcHosts => 0.7,
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+impl Completer for SshCompleter {
+    fn suggest(&self, context: &str) -> Vec<Suggestion> {
+        self.suggest_for_command("ssh", context)
+    }
+}
+
+fn is_host_pattern(name: &str) -> bool {
+    name.contains(|c| c == '*' || c == '?' || c == '!')
+}
+
+/// Parses `Host` blocks from an OpenSSH client config. Wildcard patterns are
+/// skipped since they can't be connected to directly.
+pub fn parse_ssh_config(content: &str) -> Vec<SshHost> {
+    let mut hosts: Vec<SshHost> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((key, value)) => (key.to_lowercase(), value.trim().trim_start_matches('=').trim()),
+            None => continue,
+        };
+        match key.as_str() {
+            "host" => {
+                current.clear();
+                for name in value.split_whitespace().filter(|n| !is_host_pattern(n)) {
+                    current.push(hosts.len());
+                    hosts.push(SshHost {
+                        name: name.to_string(),
+                        hostname: None,
+                        user: None,
+                        source: HostSource::SshConfig,
+                    });
+                }
+            }
+            "match" => current.clear(),
+            "hostname" => {
+                for &idx in &current {
+                    hosts[idx].hostname = Some(value.to_string());
+                }
+            }
+            "user" => {
+                for &idx in &current {
+                    hosts[idx].user = Some(value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    hosts
+}
+
+/// Parses hostnames from a `known_hosts` file. Hashed entries are skipped and
+/// `[host]:port` entries are reduced to the bare host.
+pub fn parse_known_hosts(content: &str) -> Vec<SshHost> {
+    let mut hosts = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('|') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let mut names = fields.next().unwrap_or("");
+        if names.starts_with('@') {
+            // Marker lines such as `@cert-authority` carry the names second.
+            names = fields.next().unwrap_or("");
+        }
+        for name in names.split(',') {
+            let name = match name.strip_prefix('[') {
+                Some(rest) => rest.split(']').next().unwrap_or(rest),
+                None => name,
+            };
+            if !name.is_empty() && !is_host_pattern(name) {
+                hosts.push(SshHost {
+                    name: name.to_string(),
+                    hostname: None,
+                    user: None,
+                    source: HostSource::KnownHosts,
+                });
+            }
+        }
+    }
+    hosts
+}
+
+/// Parses hostnames and aliases from `/etc/hosts`, keeping the address as the
+/// host's description.
+pub fn parse_etc_hosts(content: &str) -> Vec<SshHost> {
+    let mut hosts = Vec::new();
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let address = match fields.next() {
+            Some(address) => address,
+            None => continue,
+        };
+        for name in fields {
+            hosts.push(SshHost {
+                name: name.to_string(),
+                hostname: Some(address.to_string()),
+                user: None,
+                source: HostSource::EtcHosts,
+            });
+        }
+    }
+    hosts
+}
+
 /// AI-powered completer that uses LLM for intelligent suggestions
 pub struct AiCompleter {
     client: Arc<Mutex<Option<reqwest::Client>>>,
@@ -166,12 +451,15 @@ Suggestions:",
         });
 
         if let Some(client) = &*self.client.lock().unwrap() {
-            match client.post(&self.api_url)
+            let started = std::time::Instant::now();
+            crate::metrics::METRICS.ai_requests.inc();
+            let response = client.post(&self.api_url)
                 .json(&request_body)
                 .timeout(Duration::from_secs(5))
                 .send()
-                .await
-            {
+                .await;
+            crate::metrics::METRICS.ai_latency.observe(started.elapsed().as_secs_f64());
+            match response {
                 Ok(res) => {
                     if res.status().is_success() {
                         if let Ok(ai_response) = res.json::<serde_json::Value>().await {
@@ -217,10 +505,28 @@ Suggestions:",
 pub struct CompletionManager {
     specs: HashMap<String, Box<dyn Completer + Send + Sync>>,
     file_completer: FilePathCompleter,
+    ssh_completer: SshCompleter,
     ai_completer: AiCompleter,
-    matcher: SkimMatcherV2,
     history: Vec<String>,
     suggestion_cache: Arc<Mutex<HashMap<String, (Vec<Suggestion>, std::time::Instant)>>>,
+    // Off while the active pane is in a remote session, where local paths are wrong
+    file_completion: bool,
+    // Local branches of the active pane's repository, from the git status monitor
+    git_branches: Vec<String>,
+    // Containers on the local Docker daemon, from the Docker monitor
+    docker_containers: Vec<String>,
+    // Pods, deployments and namespaces of the current Kubernetes context
+    kube_resources: KubeResources,
+    // Commands and history of the host the active pane is connected to
+    remote_corpus: Option<HostCorpus>,
+    // Formulae, packages and crates fetched in the background
+    packages: HashMap<PrefetchSource, Vec<String>>,
+    // The distro of the active pane when it runs in WSL, whose paths are Linux paths
+    wsl_distro: Option<String>,
+    // SFTP hosts, archives and other filesystems that paths can be on
+    mounts: Arc<MountTable>,
+    // Prefixes and names of the snippets in the input editor
+    snippets: Vec<(String, String)>,
 }
 
 impl CompletionManager {
@@ -267,13 +573,117 @@ impl CompletionManager {
         Self {
             specs,
             file_completer: FilePathCompleter,
+            ssh_completer: SshCompleter::load(),
             ai_completer: AiCompleter::new(),
-            matcher: SkimMatcherV2::default(),
             history: Vec::new(),
             suggestion_cache: Arc::new(Mutex::new(HashMap::new())),
+            file_completion: true,
+            git_branches: Vec::new(),
+            docker_containers: Vec::new(),
+            kube_resources: KubeResources::default(),
+            remote_corpus: None,
+            packages: HashMap::new(),
+            wsl_distro: None,
+            mounts: Arc::new(MountTable::new()),
+            snippets: Vec::new(),
         }
     }
 
+    /// Turns local file path suggestions on or off.
+    pub fn set_file_completion(&mut self, enabled: bool) {
+        self.file_completion = enabled;
+    }
+
+    /// Sets the branch names offered after `git checkout` and similar.
+    pub fn set_git_branches(&mut self, branches: Vec<String>) {
+        self.git_branches = branches;
+    }
+
+    /// Sets the container names offered after `docker exec` and similar.
+    pub fn set_docker_containers(&mut self, names: Vec<String>) {
+        self.docker_containers = names;
+    }
+
+    /// Sets the resource names offered after `kubectl logs` and similar.
+    pub fn set_kube_resources(&mut self, resources: KubeResources) {
+        self.kube_resources = resources;
+    }
+
+    /// Completes commands and history from a remote host instead of this
+    /// machine, or from this machine again with `None`.
+    pub fn set_remote_corpus(&mut self, corpus: Option<HostCorpus>) {
+        self.remote_corpus = corpus;
+    }
+
+    /// Completes file paths as the shell of a WSL distro sees them, or as
+    /// this machine does again with `None`.
+    pub fn set_wsl_distro(&mut self, distro: Option<String>) {
+        self.wsl_distro = distro;
+    }
+
+    /// Lists the files under a Linux path through the distro's Windows path,
+    /// then offers them as Linux paths.
+    fn wsl_file_suggestions(&self, distro: &str, word: &str) -> Vec<Suggestion> {
+        let windows_word = crate::wsl::to_windows_path(word, distro).unwrap_or_else(|| word.to_string());
+        let mut suggestions = self.file_completer.suggest(&windows_word);
+        for suggestion in &mut suggestions {
+            if let Some(path) = crate::wsl::to_wsl_path(&suggestion.replacement, distro) {
+                let is_dir = suggestion.replacement.ends_with('/');
+                suggestion.replacement = if is_dir && path != "/" { format!("{}/", path) } else { path };
+            }
+        }
+        suggestions
+    }
+
+    /// The filesystems paths like `sftp://host/etc/` and `release.zip!/bin/`
+    /// are listed from, shared with the file browser.
+    pub fn mounts(&self) -> &Arc<MountTable> {
+        &self.mounts
+    }
+
+    /// Lists the entries of the mounted directory `word` is in.
+    fn mount_suggestions(&self, word: &str) -> Vec<Suggestion> {
+        // `sftp://host` and `release.zip!` are the roots of their mounts.
+        let (dir, partial) = match word.rsplit_once('/') {
+            Some((dir, partial)) if dir != "sftp:/" && !word.ends_with('!') => (format!("{}/", dir), partial),
+            _ => (word.to_string(), ""),
+        };
+        let Ok(entries) = self.mounts.entries(&dir) else {
+            return Vec::new();
+        };
+        let mut suggestions = Vec::new();
+        for entry in entries {
+            let Some(name) = entry.path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !name.starts_with(partial) {
+                continue;
+            }
+            let mut replacement = entry.path.to_string_lossy().to_string();
+            if entry.is_dir {
+                replacement.push('/');
+            }
+            suggestions.push(Suggestion {
+                display: name.to_string(),
+                replacement,
+                description: Some(if entry.is_dir { "Directory" } else { "File" }.to_string()),
+                suggestion_type: SuggestionType::FilePath,
+                confidence: 0.7,
+            });
+        }
+        suggestions
+    }
+
+    /// Sets the snippets offered by prefix, as `(prefix, name)` pairs.
+    pub fn set_snippets(&mut self, snippets: Vec<(String, String)>) {
+        self.snippets = snippets;
+    }
+
+    /// Sets the names offered after `brew install`, `cargo add` and similar.
+    pub fn set_packages(&mut self, source: PrefetchSource, names: Vec<String>) {
+        self.packages.insert(source, names);
+    }
+
     /// Add a command to history for context
     pub fn add_to_history(&mut self, command: String) {
         self.history.push(command);
@@ -294,27 +704,101 @@ impl CompletionManager {
 
         // 1. Command completion (highest priority)
         if words.len() <= 1 {
-            for (cmd_name, _) in &self.specs {
+            // Connected to a host with a known corpus, its commands replace the local ones
+            let commands: Vec<(&String, String)> = match &self.remote_corpus {
+                Some(corpus) => corpus.commands.iter().map(|name| (name, format!("Command on {}", corpus.host))).collect(),
+                None => self.specs.keys().map(|name| (name, "Command".to_string())).collect(),
+            };
+            for (cmd_name, description) in commands {
                 if cmd_name.starts_with(current_word) {
                     all_suggestions.push(Suggestion {
                         display: cmd_name.clone(),
                         replacement: cmd_name.clone(),
-                        description: Some("Command".to_string()),
+                        description: Some(description),
                         suggestion_type: SuggestionType::Command,
                         confidence: 0.95,
                     });
                 }
             }
+        } else if SshCompleter::handles(command) {
+            // 2. Remote hostnames for ssh-like commands
+            all_suggestions.extend(self.ssh_completer.suggest_for_command(command, current_word));
+        } else if command == "git" && words.len() - usize::from(!current_word.is_empty()) >= 2 && GIT_BRANCH_SUBCOMMANDS.contains(&words[1]) {
+            // 2. Branch names for git subcommands that take one
+            for branch in self.git_branches.iter().filter(|branch| branch.starts_with(current_word)) {
+                all_suggestions.push(Suggestion {
+                    display: branch.clone(),
+                    replacement: branch.clone(),
+                    description: Some("Branch".to_string()),
+                    suggestion_type: SuggestionType::Argument,
+                    confidence: 0.9,
+                });
+            }
+        } else if command == "docker" && words.len() - usize::from(!current_word.is_empty()) >= 2 && DOCKER_CONTAINER_SUBCOMMANDS.contains(&words[1]) && !current_word.starts_with('-') {
+            // 2. Container names for docker subcommands that take one
+            for name in self.docker_containers.iter().filter(|name| name.starts_with(current_word)) {
+                all_suggestions.push(Suggestion {
+                    display: name.clone(),
+                    replacement: name.clone(),
+                    description: Some("Container".to_string()),
+                    suggestion_type: SuggestionType::Argument,
+                    confidence: 0.9,
+                });
+            }
+        } else if let Some(kind) = self.kube_kind(&words, current_word) {
+            // 2. Resource names in the current Kubernetes context
+            for name in self.kube_resources.names(kind).iter().filter(|name| name.starts_with(current_word)) {
+                all_suggestions.push(Suggestion {
+                    display: name.clone(),
+                    replacement: name.clone(),
+                    description: Some(kind.label().to_string()),
+                    suggestion_type: SuggestionType::Argument,
+                    confidence: 0.9,
+                });
+            }
+        } else if let Some(names) = self.package_names(&words, current_word) {
+            // 2. Prefetched formulae, packages and crates
+            for name in names.iter().filter(|name| name.starts_with(current_word)) {
+                all_suggestions.push(Suggestion {
+                    display: name.clone(),
+                    replacement: name.clone(),
+                    description: Some("Package".to_string()),
+                    suggestion_type: SuggestionType::Argument,
+                    confidence: 0.9,
+                });
+            }
+        } else if self.mounts.is_mounted(current_word) {
+            // 2. Paths on SFTP hosts, in archives and on other mounts
+            all_suggestions.extend(self.mount_suggestions(current_word));
         } else if let Some(spec) = self.specs.get(command) {
             // 2. Command-specific completions
             all_suggestions.extend(spec.suggest(current_word));
-        } else {
+        } else if self.file_completion {
             // 3. File path completion for unknown commands
-            all_suggestions.extend(self.file_completer.suggest(current_word));
+            match &self.wsl_distro {
+                Some(distro) => all_suggestions.extend(self.wsl_file_suggestions(distro, current_word)),
+                None => all_suggestions.extend(self.file_completer.suggest(current_word)),
+            }
         }
 
-        // 4. History-based suggestions
-        for hist_cmd in &self.history {
+        // Snippets whose prefix is being typed, wherever the word is
+        if !current_word.is_empty() {
+            for (prefix, name) in &self.snippets {
+                if prefix.starts_with(current_word) {
+                    all_suggestions.push(Suggestion {
+                        display: prefix.clone(),
+                        replacement: prefix.clone(),
+                        description: Some(name.clone()),
+                        suggestion_type: SuggestionType::Snippet,
+                        confidence: 0.9,
+                    });
+                }
+            }
+        }
+
+        // 4. History-based suggestions, from the remote host when connected
+        let history = self.remote_corpus.as_ref().map_or(&self.history, |corpus| &corpus.history);
+        for hist_cmd in history {
             if hist_cmd.starts_with(&text_before_cursor) && hist_cmd != text_before_cursor {
                 let suffix = &hist_cmd[text_before_cursor.len()..];
                 if !suffix.is_empty() {
@@ -332,7 +816,7 @@ impl CompletionManager {
         // 5. Fuzzy filter and sort
         let mut scored: Vec<(i64, Suggestion)> = all_suggestions.into_iter()
             .filter_map(|s| {
-                let score = self.matcher.fuzzy_match(&s.display, current_word).unwrap_or(0);
+                let score = fuzzy_match(&s.display, current_word).map_or(0, |found| found.score);
                 Some((score, s))
             })
             .collect();
@@ -354,6 +838,8 @@ impl CompletionManager {
                         SuggestionType::AiGenerated => 5,
                         SuggestionType::Argument => 6,
                         SuggestionType::Workflow => 7,
+                        SuggestionType::Host => 8,
+                        SuggestionType::Snippet => 9,
                     };
                     type_priority(&a.suggestion_type).cmp(&type_priority(&b.suggestion_type))
                 })
@@ -363,6 +849,25 @@ impl CompletionManager {
         result
     }
 
+    /// The kind of Kubernetes resource the argument being typed names.
+    fn kube_kind(&self, words: &[&str], current_word: &str) -> Option<ResourceKind> {
+        if words.first() != Some(&"kubectl") || current_word.starts_with('-') {
+            return None;
+        }
+        completion_kind(&words[..words.len() - usize::from(!current_word.is_empty())])
+    }
+
+    /// The prefetched list for the argument being typed, if it takes one.
+    fn package_names(&self, words: &[&str], current_word: &str) -> Option<&Vec<String>> {
+        if words.len() - usize::from(!current_word.is_empty()) < 2 {
+            return None;
+        }
+        let (_, _, source) = PACKAGE_SUBCOMMANDS
+            .iter()
+            .find(|(command, subcommands, _)| *command == words[0] && subcommands.contains(&words[1]))?;
+        self.packages.get(source)
+    }
+
     /// Get AI-powered suggestions asynchronously
     pub async fn get_ai_suggestions(&self, line: &str, cursor_pos: usize) -> Vec<Suggestion> {
         let text_before_cursor = &line[..cursor_pos];
@@ -409,4 +914,194 @@ impl CompletionManager {
         
         suggestions
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SSH_CONFIG: &str = "\
+Host *
+    ServerAliveInterval 60
+
+Host web1 web2
+    HostName web.example.com
+    User deploy
+
+Host bastion
+    HostName=10.0.0.1
+";
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let hosts = parse_ssh_config(SSH_CONFIG);
+        let names: Vec<&str> = hosts.iter().map(|h| h.name.as_str()).collect();
+        assert_eq!(names, vec!["web1", "web2", "bastion"]);
+        assert_eq!(hosts[1].hostname.as_deref(), Some("web.example.com"));
+        assert_eq!(hosts[1].user.as_deref(), Some("deploy"));
+        assert_eq!(hosts[2].hostname.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_parse_known_hosts() {
+        let content = "github.com,140.82.112.3 ssh-ed25519 AAAA\n\
+                       [git.internal]:2222 ssh-rsa AAAA\n\
+                       |1|abc=|def= ssh-rsa AAAA\n";
+        let names: Vec<String> = parse_known_hosts(content).into_iter().map(|h| h.name).collect();
+        assert_eq!(names, vec!["github.com", "140.82.112.3", "git.internal"]);
+    }
+
+    #[test]
+    fn test_ssh_suggestions_with_user_and_path_suffix() {
+        let completer = SshCompleter::from_sources(SSH_CONFIG, "web1 ssh-rsa AAAA", "127.0.0.1 localhost");
+        assert_eq!(completer.hosts().len(), 4);
+
+        let suggestions = completer.suggest_for_command("ssh", "root@we");
+        let replacements: Vec<&str> = suggestions.iter().map(|s| s.replacement.as_str()).collect();
+        assert_eq!(replacements, vec!["root@web1", "root@web2"]);
+        assert!(suggestions[0].description.as_ref().unwrap().contains("deploy@web.example.com"));
+
+        let suggestions = completer.suggest_for_command("scp", "bas");
+        assert_eq!(suggestions[0].replacement, "bastion:");
+
+        assert!(completer.suggest_for_command("scp", "bastion:/tmp").is_empty());
+    }
+
+    #[test]
+    fn test_file_completion_can_be_turned_off() {
+        let mut manager = CompletionManager::new();
+        let has_paths = |manager: &CompletionManager| {
+            manager.get_suggestions("cat src/", 8).iter().any(|s| s.suggestion_type == SuggestionType::FilePath)
+        };
+        assert!(has_paths(&manager));
+        manager.set_file_completion(false);
+        assert!(!has_paths(&manager));
+    }
+
+    #[test]
+    fn test_git_branches_complete_after_checkout() {
+        let mut manager = CompletionManager::new();
+        manager.set_git_branches(vec!["main".to_string(), "feature/search".to_string()]);
+        let branches = |line: &str| {
+            manager
+                .get_suggestions(line, line.len())
+                .into_iter()
+                .filter(|s| s.description.as_deref() == Some("Branch"))
+                .map(|s| s.replacement)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(branches("git checkout fea"), vec!["feature/search"]);
+        assert_eq!(branches("git switch ").len(), 2);
+        assert!(branches("git checkout").is_empty());
+        assert!(branches("git add ").is_empty());
+    }
+
+    #[test]
+    fn test_docker_containers_complete_after_exec() {
+        let mut manager = CompletionManager::new();
+        manager.set_docker_containers(vec!["web".to_string(), "worker".to_string(), "db".to_string()]);
+        let containers = |line: &str| {
+            manager
+                .get_suggestions(line, line.len())
+                .into_iter()
+                .filter(|s| s.description.as_deref() == Some("Container"))
+                .map(|s| s.replacement)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(containers("docker exec w").len(), 2);
+        assert_eq!(containers("docker logs d"), vec!["db"]);
+        assert!(containers("docker build ").is_empty());
+    }
+
+    #[test]
+    fn test_kube_resources_complete_by_kind() {
+        let mut manager = CompletionManager::new();
+        manager.set_kube_resources(KubeResources {
+            pods: vec!["web-7d9f".to_string(), "worker-5c2a".to_string()],
+            deployments: vec!["web".to_string()],
+            namespaces: vec!["payments".to_string()],
+        });
+        let names = |line: &str| manager.get_suggestions(line, line.len()).into_iter().filter(|s| s.suggestion_type == SuggestionType::Argument).map(|s| s.replacement).collect::<Vec<_>>();
+        assert_eq!(names("kubectl logs we"), vec!["web-7d9f"]);
+        assert_eq!(names("kubectl rollout restart deploy "), vec!["web"]);
+        assert_eq!(names("kubectl get pods -n "), vec!["payments"]);
+    }
+
+    #[test]
+    fn test_prefetched_packages_complete_arguments() {
+        let mut manager = CompletionManager::new();
+        manager.set_packages(PrefetchSource::Crates, vec!["serde".to_string(), "serde_json".to_string(), "tokio".to_string()]);
+        let packages = |line: &str| {
+            manager
+                .get_suggestions(line, line.len())
+                .into_iter()
+                .filter(|s| s.description.as_deref() == Some("Package"))
+                .count()
+        };
+        assert_eq!(packages("cargo add ser"), 2);
+        assert_eq!(packages("cargo add serde tok"), 1);
+        assert_eq!(packages("cargo build "), 0);
+        assert_eq!(packages("brew install "), 0);
+    }
+
+    #[test]
+    fn test_mounted_paths_complete() {
+        use crate::virtual_fs::{FileSystem, InMemoryFileSystem};
+
+        let manager = CompletionManager::new();
+        let mut memory = InMemoryFileSystem::new();
+        memory.write(Path::new("/etc/hosts"), b"").unwrap();
+        memory.write(Path::new("/etc/hostname"), b"").unwrap();
+        memory.write(Path::new("/etc/nginx/nginx.conf"), b"").unwrap();
+        manager.mounts().mount("mem:", Box::new(memory));
+        let replacements = |line: &str| {
+            let mut replacements: Vec<String> = manager
+                .get_suggestions(line, line.len())
+                .into_iter()
+                .filter(|s| s.suggestion_type == SuggestionType::FilePath)
+                .map(|s| s.replacement)
+                .collect();
+            replacements.sort();
+            replacements
+        };
+        assert_eq!(replacements("cat mem:/etc/host"), vec!["mem:/etc/hostname", "mem:/etc/hosts"]);
+        assert_eq!(replacements("cd mem:/etc/"), vec!["mem:/etc/hostname", "mem:/etc/hosts", "mem:/etc/nginx/"]);
+        assert_eq!(replacements("ls mem:"), vec!["mem:/etc/"]);
+    }
+
+    #[test]
+    fn test_snippet_prefixes_complete() {
+        let mut manager = CompletionManager::new();
+        manager.set_snippets(vec![("dkr".to_string(), "Docker run".to_string())]);
+        let snippets: Vec<Suggestion> = manager
+            .get_suggestions("sudo dk", 7)
+            .into_iter()
+            .filter(|s| s.suggestion_type == SuggestionType::Snippet)
+            .collect();
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].replacement, "dkr");
+        assert_eq!(snippets[0].description.as_deref(), Some("Docker run"));
+    }
+
+    #[test]
+    fn test_remote_corpus_replaces_local_commands() {
+        let mut manager = CompletionManager::new();
+        manager.add_to_history("cargo test".to_string());
+        manager.set_remote_corpus(Some(HostCorpus {
+            host: "web1".to_string(),
+            commands: vec!["kubectl".to_string(), "kubeadm".to_string()],
+            history: vec!["kubectl get pods".to_string()],
+            harvested_at: 0,
+        }));
+        let replacements = |manager: &CompletionManager, line: &str| {
+            let mut replacements: Vec<String> = manager.get_suggestions(line, line.len()).into_iter().map(|s| s.replacement).collect();
+            replacements.sort();
+            replacements
+        };
+        assert_eq!(replacements(&manager, "kube"), vec!["kubeadm", "kubectl", "kubectl get pods"]);
+        assert!(replacements(&manager, "car").is_empty());
+
+        manager.set_remote_corpus(None);
+        assert_eq!(replacements(&manager, "car"), vec!["cargo", "cargo test"]);
+    }
+}
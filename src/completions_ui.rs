@@ -1,14 +1,23 @@
 use crate::completions::{CompletionManager, Suggestion, SuggestionType};
+use crate::fuzzy_match::fuzzy_match;
+use crate::k8s::KubeResources;
+use crate::virtual_fs::MountTable;
 use cosmic_text::{Attrs, Buffer, Color, Editor, FontSystem, Metrics, Shaping};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc::{error::TryRecvError, unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Rows the popup shows at once; it scrolls to keep the selection in view.
+pub const MAX_VISIBLE_SUGGESTIONS: usize = 10;
 
 #[derive(Debug, Clone)]
 pub struct CompletionsUI {
     pub suggestions: Vec<Suggestion>,
     pub selected_index: usize,
     pub is_visible: bool,
-    pub position: (f32, f32),
+    /// The word before the cursor being completed.
+    pub word: String,
+    /// The first suggestion in view.
+    pub scroll_offset: usize,
     pub max_width: f32,
     pub max_height: f32,
 }
@@ -19,27 +28,31 @@ impl CompletionsUI {
             suggestions: Vec::new(),
             selected_index: 0,
             is_visible: false,
-            position: (0.0, 0.0),
+            word: String::new(),
+            scroll_offset: 0,
             max_width: 600.0,
             max_height: 400.0,
         }
     }
 
-    pub fn show(&mut self, suggestions: Vec<Suggestion>, position: (f32, f32)) {
+    pub fn show(&mut self, suggestions: Vec<Suggestion>, word: String) {
         self.suggestions = suggestions;
         self.selected_index = 0;
+        self.scroll_offset = 0;
         self.is_visible = true;
-        self.position = position;
+        self.word = word;
     }
 
     pub fn hide(&mut self) {
         self.is_visible = false;
         self.suggestions.clear();
+        self.scroll_offset = 0;
     }
 
     pub fn next(&mut self) {
         if !self.suggestions.is_empty() {
             self.selected_index = (self.selected_index + 1) % self.suggestions.len();
+            self.scroll_to_selection();
         }
     }
 
@@ -50,7 +63,57 @@ impl CompletionsUI {
             } else {
                 self.selected_index - 1
             };
+            self.scroll_to_selection();
+        }
+    }
+
+    fn scroll_to_selection(&mut self) {
+        if self.selected_index < self.scroll_offset {
+            self.scroll_offset = self.selected_index;
+        } else if self.selected_index >= self.scroll_offset + MAX_VISIBLE_SUGGESTIONS {
+            self.scroll_offset = self.selected_index + 1 - MAX_VISIBLE_SUGGESTIONS;
+        }
+    }
+
+    /// The suggestions in view, with their indices.
+    pub fn visible_suggestions(&self) -> impl Iterator<Item = (usize, &Suggestion)> {
+        self.suggestions
+            .iter()
+            .enumerate()
+            .skip(self.scroll_offset)
+            .take(MAX_VISIBLE_SUGGESTIONS)
+    }
+
+    /// How many suggestions are scrolled out of view above and below.
+    pub fn hidden_counts(&self) -> (usize, usize) {
+        let below = self
+            .suggestions
+            .len()
+            .saturating_sub(self.scroll_offset + MAX_VISIBLE_SUGGESTIONS);
+        (self.scroll_offset, below)
+    }
+
+    /// Byte offsets of the characters of `suggestion.display` that the
+    /// word matched.
+    pub fn highlights(&self, suggestion: &Suggestion) -> Vec<usize> {
+        fuzzy_match(&suggestion.display, &self.word).map_or_else(Vec::new, |found| found.positions)
+    }
+
+    /// What accepting the selected suggestion would add after the cursor,
+    /// shown as ghost text. Nothing for snippets, which expand into a
+    /// template, or for suggestions that don't continue the word.
+    pub fn ghost_text(&self) -> Option<&str> {
+        if !self.is_visible {
+            return None;
+        }
+        let suggestion = self.get_selected_suggestion()?;
+        if suggestion.suggestion_type == SuggestionType::Snippet {
+            return None;
         }
+        suggestion
+            .replacement
+            .strip_prefix(self.word.as_str())
+            .filter(|rest| !rest.is_empty())
     }
 
     pub fn get_selected_suggestion(&self) -> Option<&Suggestion> {
@@ -90,16 +153,7 @@ impl CompletionsUI {
             }
             
             // Add type indicator
-            let type_indicator = match suggestion.suggestion_type {
-                SuggestionType::Command => "[CMD]",
-                SuggestionType::Subcommand => "[SUB]",
-                SuggestionType::Flag => "[FLAG]",
-                SuggestionType::Argument => "[ARG]",
-                SuggestionType::FilePath => "[FILE]",
-                SuggestionType::History => "[HIST]",
-                SuggestionType::AiGenerated => "[AI]",
-                SuggestionType::Workflow => "[WF]",
-            };
+            let type_indicator = suggestion.suggestion_type.tag();
             text.push_str(" ");
             text.push_str(type_indicator);
             
@@ -127,10 +181,100 @@ impl CompletionsUI {
     }
 }
 
+/// Suggestions for the update numbered `generation`.
+#[derive(Debug, Clone)]
+pub struct SuggestionResults {
+    pub generation: u64,
+    pub suggestions: Vec<Suggestion>,
+}
+
+type Deliver = Box<dyn Fn(SuggestionResults) + Send>;
+type Configure = Box<dyn FnOnce(&mut CompletionManager) + Send>;
+
+/// Messages to the suggestion service, handled in the order sent.
+enum Request {
+    Update { generation: u64, text: String, cursor: usize },
+    /// Drops the update waiting or in flight.
+    Cancel,
+    Configure(Configure),
+    Deliver(Deliver),
+}
+
+/// Owns the `CompletionManager` on its own thread. Updates that arrive
+/// while one is being worked on replace it: the work in flight is dropped
+/// at its next await and only the newest text is looked up. Results go to
+/// the callback given to `CompletionsManager::on_results`, usually the
+/// event loop proxy, so the UI thread never waits on the manager.
+fn run_service(mut manager: CompletionManager, mut requests: UnboundedReceiver<Request>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            log::error!("Failed to start the suggestion service: {}", e);
+            return;
+        }
+    };
+    runtime.block_on(async move {
+        let mut deliver: Option<Deliver> = None;
+        let mut update = None;
+        let mut interrupted = None;
+        loop {
+            // Take everything already sent before looking anything up, so
+            // only the newest update is worked on.
+            let request = match interrupted.take() {
+                Some(request) => Some(request),
+                None if update.is_some() => match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                },
+                None => match requests.recv().await {
+                    Some(request) => Some(request),
+                    None => return,
+                },
+            };
+            match request {
+                Some(Request::Update { generation, text, cursor }) => update = Some((generation, text, cursor)),
+                Some(Request::Cancel) => update = None,
+                Some(Request::Configure(configure)) => configure(&mut manager),
+                Some(Request::Deliver(callback)) => deliver = Some(callback),
+                None => {
+                    let Some((generation, text, cursor)) = update.take() else {
+                        continue;
+                    };
+                    let started = std::time::Instant::now();
+                    let outcome = tokio::select! {
+                        suggestions = manager.get_all_suggestions(&text, cursor) => Ok(suggestions),
+                        request = requests.recv() => Err(request),
+                    };
+                    match outcome {
+                        Ok(suggestions) => {
+                            crate::metrics::METRICS.completion_latency.observe(started.elapsed().as_secs_f64());
+                            if let Some(deliver) = &deliver {
+                                deliver(SuggestionResults { generation, suggestions });
+                            }
+                        }
+                        // Retried after the request unless it supersedes the update.
+                        Err(Some(request)) => {
+                            update = Some((generation, text, cursor));
+                            interrupted = Some(request);
+                        }
+                        Err(None) => return,
+                    }
+                }
+            }
+        }
+    });
+}
+
 /// Manager for handling completions in the main application
 #[derive(Clone)]
 pub struct CompletionsManager {
-    pub completion_manager: Arc<Mutex<CompletionManager>>,
+    requests: UnboundedSender<Request>,
+    mounts: Arc<MountTable>,
+    /// The number of the latest update; older results are ignored.
+    generation: u64,
+    /// The word before the cursor in the latest update.
+    word: String,
     pub ui: CompletionsUI,
     pub is_enabled: bool,
     pub trigger_chars: Vec<char>,
@@ -139,8 +283,20 @@ pub struct CompletionsManager {
 
 impl CompletionsManager {
     pub fn new() -> Self {
+        let manager = CompletionManager::new();
+        let mounts = manager.mounts().clone();
+        let (requests, receiver) = unbounded_channel();
+        if let Err(e) = std::thread::Builder::new()
+            .name("suggestions".into())
+            .spawn(move || run_service(manager, receiver))
+        {
+            log::error!("Failed to spawn the suggestion service: {}", e);
+        }
         Self {
-            completion_manager: Arc::new(Mutex::new(CompletionManager::new())),
+            requests,
+            mounts,
+            generation: 0,
+            word: String::new(),
             ui: CompletionsUI::new(),
             is_enabled: true,
             trigger_chars: vec![' ', '\t', '/', '-', '.'],
@@ -148,6 +304,22 @@ impl CompletionsManager {
         }
     }
 
+    /// Sends results to `deliver`, on the service's thread. The app posts
+    /// them to its event loop, which hands them to `receive`.
+    pub fn on_results(&self, deliver: impl Fn(SuggestionResults) + Send + 'static) {
+        self.requests.send(Request::Deliver(Box::new(deliver))).ok();
+    }
+
+    /// Changes the `CompletionManager` before any later update is looked up.
+    pub fn configure(&self, configure: impl FnOnce(&mut CompletionManager) + Send + 'static) {
+        self.requests.send(Request::Configure(Box::new(configure))).ok();
+    }
+
+    /// The virtual filesystems paths are completed from.
+    pub fn mounts(&self) -> &Arc<MountTable> {
+        &self.mounts
+    }
+
     pub fn should_trigger_completion(&self, current_text: &str, cursor_pos: usize) -> bool {
         if !self.is_enabled || current_text.len() < self.min_trigger_length {
             return false;
@@ -166,22 +338,35 @@ impl CompletionsManager {
         false
     }
 
-    pub async fn update_suggestions(&mut self, current_text: &str, cursor_pos: usize) {
+    /// Asks for suggestions for `current_text`, superseding any asked for
+    /// before. Returns at once; the results arrive through `on_results`.
+    pub fn update_suggestions(&mut self, current_text: &str, cursor_pos: usize) {
+        self.generation += 1;
+        let before_cursor = current_text.get(..cursor_pos).unwrap_or(current_text);
+        self.word = before_cursor.rsplit(char::is_whitespace).next().unwrap_or("").to_string();
         if !self.should_trigger_completion(current_text, cursor_pos) {
             self.ui.hide();
+            self.requests.send(Request::Cancel).ok();
             return;
         }
+        self.requests
+            .send(Request::Update {
+                generation: self.generation,
+                text: current_text.to_string(),
+                cursor: cursor_pos,
+            })
+            .ok();
+    }
 
-        let completion_manager = self.completion_manager.clone();
-        let suggestions = completion_manager.lock().await.get_all_suggestions(current_text, cursor_pos).await;
-
-        if suggestions.is_empty() {
+    /// Shows suggestions from the service, unless newer ones were asked for.
+    pub fn receive(&mut self, results: SuggestionResults) {
+        if results.generation != self.generation {
+            return;
+        }
+        if results.suggestions.is_empty() {
             self.ui.hide();
         } else {
-            // Calculate position for the completions UI
-            // This would typically be below the cursor
-            let position = (50.0, 100.0); // Placeholder position
-            self.ui.show(suggestions, position);
+            self.ui.show(results.suggestions, self.word.clone());
         }
     }
 
@@ -199,20 +384,13 @@ impl CompletionsManager {
                 self.ui.previous();
                 CompletionsAction::Navigate
             }
-            winit::keyboard::KeyCode::Enter => {
-                if let Some(suggestion) = self.ui.get_selected_suggestion() {
-                    CompletionsAction::Accept(suggestion.replacement.clone())
-                } else {
-                    CompletionsAction::None
+            winit::keyboard::KeyCode::Enter | winit::keyboard::KeyCode::Tab => match self.ui.get_selected_suggestion() {
+                Some(suggestion) if suggestion.suggestion_type == SuggestionType::Snippet => {
+                    CompletionsAction::ExpandSnippet(suggestion.replacement.clone())
                 }
-            }
-            winit::keyboard::KeyCode::Tab => {
-                if let Some(suggestion) = self.ui.get_selected_suggestion() {
-                    CompletionsAction::Accept(suggestion.replacement.clone())
-                } else {
-                    CompletionsAction::None
-                }
-            }
+                Some(suggestion) => CompletionsAction::Accept(suggestion.replacement.clone()),
+                None => CompletionsAction::None,
+            },
             winit::keyboard::KeyCode::Escape => {
                 self.ui.hide();
                 CompletionsAction::Close
@@ -222,10 +400,15 @@ impl CompletionsManager {
     }
 
     pub fn add_to_history(&self, command: String) {
-        let completion_manager = self.completion_manager.clone();
-        tokio::spawn(async move {
-            completion_manager.lock().await.add_to_history(command);
-        });
+        self.configure(move |manager| manager.add_to_history(command));
+    }
+
+    pub fn set_docker_containers(&self, names: Vec<String>) {
+        self.configure(move |manager| manager.set_docker_containers(names));
+    }
+
+    pub fn set_kube_resources(&self, resources: KubeResources) {
+        self.configure(move |manager| manager.set_kube_resources(resources));
     }
 }
 
@@ -234,5 +417,110 @@ pub enum CompletionsAction {
     None,
     Navigate,
     Accept(String),
+    /// Replaces the word with the snippet that has this prefix.
+    ExpandSnippet(String),
     Close,
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn connected() -> (CompletionsManager, mpsc::Receiver<SuggestionResults>) {
+        let manager = CompletionsManager::new();
+        let (sender, results) = mpsc::channel();
+        manager.on_results(move |batch| {
+            sender.send(batch).ok();
+        });
+        (manager, results)
+    }
+
+    fn branches(results: &SuggestionResults) -> Vec<&str> {
+        results
+            .suggestions
+            .iter()
+            .filter(|s| s.description.as_deref() == Some("Branch"))
+            .map(|s| s.replacement.as_str())
+            .collect()
+    }
+
+    fn suggestion(name: &str, suggestion_type: SuggestionType) -> Suggestion {
+        Suggestion {
+            display: name.to_string(),
+            replacement: name.to_string(),
+            description: None,
+            suggestion_type,
+            confidence: 0.9,
+        }
+    }
+
+    #[test]
+    fn test_popup_scrolls_and_previews_the_selection() {
+        let mut ui = CompletionsUI::new();
+        let names: Vec<String> = (0..15).map(|i| format!("branch-{:02}", i)).collect();
+        ui.show(names.iter().map(|name| suggestion(name, SuggestionType::Argument)).collect(), "bra".to_string());
+        assert_eq!(ui.hidden_counts(), (0, 5));
+        assert_eq!(ui.ghost_text(), Some("nch-00"));
+        assert_eq!(ui.highlights(&ui.suggestions[0]), vec![0, 1, 2]);
+
+        for _ in 0..12 {
+            ui.next();
+        }
+        assert_eq!(ui.selected_index, 12);
+        assert_eq!(ui.hidden_counts(), (3, 2));
+        assert_eq!(ui.visible_suggestions().next().map(|(i, _)| i), Some(3));
+        for _ in 0..9 {
+            ui.previous();
+        }
+        assert_eq!(ui.scroll_offset, 3);
+        ui.previous();
+        assert_eq!(ui.scroll_offset, 2);
+        // Wrapping to the end scrolls down to it
+        for _ in 0..3 {
+            ui.previous();
+        }
+        assert_eq!(ui.selected_index, 14);
+        assert_eq!(ui.hidden_counts(), (5, 0));
+
+        ui.show(vec![suggestion("gco", SuggestionType::Snippet)], "gc".to_string());
+        assert_eq!(ui.ghost_text(), None);
+    }
+
+    #[test]
+    fn test_configuration_applies_before_later_updates() {
+        let (mut manager, results) = connected();
+        let names = vec!["main".to_string(), "feature/a".to_string(), "feature/b".to_string()];
+        manager.configure(move |completions| completions.set_git_branches(names));
+        manager.update_suggestions("git checkout ", 13);
+        let batch = results.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(batch.generation, 1);
+        assert_eq!(branches(&batch), vec!["main", "feature/a", "feature/b"]);
+        manager.receive(batch);
+        assert!(manager.ui.is_visible);
+    }
+
+    #[test]
+    fn test_stale_updates_are_dropped() {
+        let (mut manager, results) = connected();
+        // Holds the service so both updates are waiting when it wakes.
+        let (release, wait) = mpsc::channel::<()>();
+        manager.configure(move |completions| {
+            wait.recv().ok();
+            completions.set_git_branches(vec!["main".to_string(), "dev".to_string(), "docs".to_string()]);
+        });
+        manager.update_suggestions("git switch ", 11);
+        manager.update_suggestions("git checkout ", 13);
+        release.send(()).unwrap();
+        let batch = results.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(batch.generation, 2);
+        assert_eq!(branches(&batch), vec!["main", "dev", "docs"]);
+        assert!(results.recv_timeout(Duration::from_millis(200)).is_err());
+
+        // Results for text that has since changed aren't shown.
+        manager.update_suggestions("git checkout ma", 15);
+        assert_eq!(manager.generation, 3);
+        manager.receive(batch);
+        assert!(!manager.ui.is_visible);
+    }
+}
@@ -0,0 +1,469 @@
+//! Layered configuration loading.
+//!
+//! Configuration is assembled from several TOML files, lowest precedence
+//! first: the system file, `~/.warpish.toml`, the user file in the XDG config
+//! directory, and finally the project-local `terminal.toml` and nearest
+//! `.warpish.toml`. Project files come with whatever repository is checked
+//! out, so they may only set the keys in `PROJECT_KEYS`. Tables are merged
+//! key by key; any other value in a later layer replaces the earlier one.
+//! `WARPISH_<SECTION>__<KEY>` environment
+//! variables override the merged result. The origin of every value is
+//! recorded so it can be reported through `Config::sources()`.
+
+use super::Config;
+use crate::error::AppError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Prefix of environment variables that override config values.
+pub const ENV_PREFIX: &str = "WARPISH_";
+/// Separates nested keys in override variable names.
+const ENV_PATH_SEPARATOR: &str = "__";
+const PROJECT_FILE_NAME: &str = ".warpish.toml";
+const LEGACY_FILE_NAME: &str = "terminal.toml";
+
+/// The keys, and the tables of keys, a project file may set: looks and
+/// editing only. Anything that runs commands, sends data elsewhere or
+/// listens on a port (hooks, share targets, AI endpoints, custom prompt
+/// chips, ...) is left to the user's own files.
+const PROJECT_KEYS: &[&str] = &[
+    "appearance.font_size",
+    "appearance.line_height",
+    "appearance.font_family",
+    "appearance.font_weight",
+    "appearance.use_ligatures",
+    "appearance.prompt_mode",
+    "appearance.input_position",
+    "appearance.cursor",
+    "appearance.theme",
+    "appearance.warpish_prompt.chips",
+    "appearance.warpish_prompt.same_line",
+    "blocks",
+    "editor",
+    "encoding",
+    "palette",
+    "scroll",
+    "watch",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigLayer {
+    System,
+    Home,
+    User,
+    Project,
+}
+
+impl ConfigLayer {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConfigLayer::System => "system",
+            ConfigLayer::Home => "home",
+            ConfigLayer::User => "user",
+            ConfigLayer::Project => "project",
+        }
+    }
+}
+
+/// Where a config value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File { layer: ConfigLayer, path: PathBuf },
+    Environment(String),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File { layer, path } => write!(f, "{} ({})", layer.label(), path.display()),
+            ConfigSource::Environment(name) => write!(f, "environment ({})", name),
+        }
+    }
+}
+
+/// The source of every leaf value in a loaded config, keyed by dotted path
+/// (e.g. `appearance.font_size`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigSources {
+    entries: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigSources {
+    pub fn get(&self, path: &str) -> Option<&ConfigSource> {
+        self.entries.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ConfigSource)> {
+        self.entries.iter().map(|(path, source)| (path.as_str(), source))
+    }
+
+    /// Only the values that were set explicitly rather than defaulted.
+    pub fn overridden(&self) -> impl Iterator<Item = (&str, &ConfigSource)> {
+        self.iter().filter(|(_, source)| **source != ConfigSource::Default)
+    }
+
+    fn record(&mut self, path: String, value: &toml::Value, source: &ConfigSource) {
+        // A replaced table or value invalidates everything recorded beneath it.
+        let nested = format!("{}.", path);
+        self.entries.retain(|key, _| !key.starts_with(&nested));
+        match value {
+            toml::Value::Table(table) => {
+                for (key, value) in table {
+                    self.record(join(&path, key), value, source);
+                }
+            }
+            _ => {
+                self.entries.insert(path, source.clone());
+            }
+        }
+    }
+
+    fn fill_defaults(&mut self, prefix: &str, table: &toml::Table) {
+        for (key, value) in table {
+            let path = join(prefix, key);
+            match value {
+                toml::Value::Table(table) => self.fill_defaults(&path, table),
+                _ => {
+                    self.entries.entry(path).or_insert(ConfigSource::Default);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConfigSources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, source) in self.iter() {
+            writeln!(f, "{} = {}", path, source)?;
+        }
+        Ok(())
+    }
+}
+
+/// The config files consulted by `load_config`, in precedence order.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigPaths {
+    pub files: Vec<(ConfigLayer, PathBuf)>,
+}
+
+impl ConfigPaths {
+    /// Resolves the standard locations for the current user and directory.
+    pub fn discover() -> Self {
+        let mut files = Vec::new();
+        if cfg!(unix) {
+            files.push((ConfigLayer::System, PathBuf::from("/etc/warpish").join(LEGACY_FILE_NAME)));
+        }
+        let home = dirs::home_dir();
+        if let Some(home) = &home {
+            files.push((ConfigLayer::Home, home.join(PROJECT_FILE_NAME)));
+        }
+        if let Some(user) = user_config_file() {
+            files.push((ConfigLayer::User, user));
+        }
+        if let Ok(cwd) = std::env::current_dir() {
+            files.push((ConfigLayer::Project, cwd.join(LEGACY_FILE_NAME)));
+            if let Some(project) = find_project_config(&cwd, home.as_deref()) {
+                files.push((ConfigLayer::Project, project));
+            }
+        }
+        Self { files }
+    }
+}
+
+/// The user file in the XDG config directory, which settings changed from
+/// inside the app are written to.
+pub fn user_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("warpish").join(LEGACY_FILE_NAME))
+}
+
+/// Sets the dotted `key` in the TOML file at `path`, creating the file if
+/// needed and keeping every other key.
+pub fn write_value(path: &Path, key: &str, value: toml::Value) -> Result<(), AppError> {
+    let mut table: toml::Table = match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text)
+            .map_err(|e| AppError::Config(format!("Failed to parse {}: {}", path.display(), e)))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+        Err(e) => return Err(AppError::Config(format!("Failed to read {}: {}", path.display(), e))),
+    };
+    let key: Vec<String> = key.split('.').map(str::to_string).collect();
+    insert_path(&mut table, &key, value);
+    let text = toml::to_string_pretty(&table)
+        .map_err(|e| AppError::Config(format!("Failed to serialize {}: {}", path.display(), e)))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| AppError::Config(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    std::fs::write(path, text).map_err(|e| AppError::Config(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Finds the nearest `.warpish.toml` in `start` or its ancestors, stopping
+/// before the home directory (whose file is its own layer).
+pub fn find_project_config(start: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .take_while(|dir| Some(*dir) != home)
+        .map(|dir| dir.join(PROJECT_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Loads and merges every existing file in `paths`, then applies overrides
+/// from `env`. Missing files are skipped; unreadable or invalid ones fail.
+pub fn load_layered<I>(paths: &ConfigPaths, env: I) -> Result<Config, AppError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut merged = toml::Table::new();
+    let mut sources = ConfigSources::default();
+
+    for (layer, path) in &paths.files {
+        if !path.is_file() {
+            continue;
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Config(format!("Failed to read {}: {}", path.display(), e)))?;
+        let table: toml::Table = toml::from_str(&text)
+            .map_err(|e| AppError::Config(format!("Failed to parse {}: {}", path.display(), e)))?;
+        let table = match layer {
+            ConfigLayer::Project => project_keys_only(table, "", path),
+            _ => table,
+        };
+        let source = ConfigSource::File { layer: *layer, path: path.clone() };
+        merge(&mut merged, table, "", &source, &mut sources);
+        log::debug!("Loaded {} config from {}", layer.label(), path.display());
+    }
+
+    // Overrides may only target keys the config actually has, so unrelated
+    // `WARPISH_*` variables (such as keychain credentials) are left alone.
+    let effective = effective_table(&merged)?;
+    let mut env: Vec<(String, String)> = env.into_iter().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+    env.sort();
+    for (name, raw) in env {
+        let path: Vec<String> = name[ENV_PREFIX.len()..]
+            .to_lowercase()
+            .split(ENV_PATH_SEPARATOR)
+            .map(str::to_string)
+            .collect();
+        let Some(current) = lookup(&effective, &path) else {
+            continue;
+        };
+        if current.is_table() {
+            continue;
+        }
+        let value = parse_env_value(&raw, current);
+        let mut overlay = toml::Table::new();
+        insert_path(&mut overlay, &path, value);
+        merge(&mut merged, overlay, "", &ConfigSource::Environment(name), &mut sources);
+    }
+
+    let mut config: Config = toml::Value::Table(merged.clone())
+        .try_into()
+        .map_err(|e| AppError::Config(format!("Invalid configuration: {}", e)))?;
+    sources.fill_defaults("", &effective_table(&merged)?);
+    config.sources = sources;
+    Ok(config)
+}
+
+/// Deep-merges `overlay` into `base`, recording the source of each value.
+fn merge(base: &mut toml::Table, overlay: toml::Table, prefix: &str, source: &ConfigSource, sources: &mut ConfigSources) {
+    for (key, value) in overlay {
+        let path = join(prefix, &key);
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge(existing, table, &path, source, sources);
+            }
+            (_, value) => {
+                sources.record(path, &value, source);
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Keeps the keys of a project file that `PROJECT_KEYS` allows, warning
+/// about each one dropped.
+fn project_keys_only(table: toml::Table, prefix: &str, path: &Path) -> toml::Table {
+    table
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let dotted = join(prefix, &key);
+            if PROJECT_KEYS.iter().any(|allowed| dotted == *allowed || dotted.starts_with(&format!("{}.", allowed))) {
+                return Some((key, value));
+            }
+            let nested = format!("{}.", dotted);
+            match value {
+                toml::Value::Table(table) if PROJECT_KEYS.iter().any(|allowed| allowed.starts_with(&nested)) => {
+                    Some((key, toml::Value::Table(project_keys_only(table, &dotted, path))))
+                }
+                _ => {
+                    log::warn!("Ignoring {} in {}: project config may not set it", dotted, path.display());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The merged files with every serde default filled in.
+fn effective_table(merged: &toml::Table) -> Result<toml::Table, AppError> {
+    let config: Config = toml::Value::Table(merged.clone())
+        .try_into()
+        .map_err(|e| AppError::Config(format!("Invalid configuration: {}", e)))?;
+    toml::Table::try_from(&config).map_err(|e| AppError::Config(format!("Failed to serialize configuration: {}", e)))
+}
+
+fn lookup<'a>(table: &'a toml::Table, path: &[String]) -> Option<&'a toml::Value> {
+    let (first, rest) = path.split_first()?;
+    let value = table.get(first)?;
+    match (rest.is_empty(), value) {
+        (true, value) => Some(value),
+        (false, toml::Value::Table(table)) => lookup(table, rest),
+        (false, _) => None,
+    }
+}
+
+fn insert_path(table: &mut toml::Table, path: &[String], value: toml::Value) {
+    match path {
+        [] => {}
+        [key] => {
+            table.insert(key.clone(), value);
+        }
+        [key, rest @ ..] => {
+            let child = table
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let toml::Value::Table(child) = child {
+                insert_path(child, rest, value);
+            }
+        }
+    }
+}
+
+/// Parses an override as a TOML value unless the existing value is a string,
+/// in which case the raw text is used verbatim.
+fn parse_env_value(raw: &str, current: &toml::Value) -> toml::Value {
+    if current.is_str() {
+        return toml::Value::String(raw.to_string());
+    }
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_layers_merge_with_precedence_and_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = write(dir.path(), "user.toml", "[appearance]\nfont_size = 16.0\nfont_family = \"Fira Code\"\n");
+        let project = write(dir.path(), "project.toml", "[appearance]\nfont_size = 12.0\n");
+        let paths = ConfigPaths {
+            files: vec![
+                (ConfigLayer::System, dir.path().join("missing.toml")),
+                (ConfigLayer::User, user.clone()),
+                (ConfigLayer::Project, project.clone()),
+            ],
+        };
+
+        let config = load_layered(&paths, Vec::new()).unwrap();
+        assert_eq!(config.appearance.font_size, 12.0);
+        assert_eq!(config.appearance.font_family, "Fira Code");
+        assert_eq!(
+            config.sources().get("appearance.font_size"),
+            Some(&ConfigSource::File { layer: ConfigLayer::Project, path: project })
+        );
+        assert_eq!(
+            config.sources().get("appearance.font_family"),
+            Some(&ConfigSource::File { layer: ConfigLayer::User, path: user })
+        );
+        assert_eq!(config.sources().get("appearance.line_height"), Some(&ConfigSource::Default));
+    }
+
+    #[test]
+    fn test_project_files_set_allowed_keys_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = write(
+            dir.path(),
+            PROJECT_FILE_NAME,
+            "[appearance]\nfont_size = 12.0\n\n\
+             [appearance.warpish_prompt]\nsame_line = false\ncustom_chips = [{ name = \"x\", type = \"command\", command = \"curl evil\" }]\n\n\
+             [ai]\nollama_url = \"http://evil.example/api/generate\"\n\n\
+             [hooks]\nshell = [{ event = \"command_finish\", run = \"curl evil\" }]\n",
+        );
+        let paths = ConfigPaths { files: vec![(ConfigLayer::Project, project)] };
+
+        let config = load_layered(&paths, Vec::new()).unwrap();
+        assert_eq!(config.appearance.font_size, 12.0);
+        assert!(!config.appearance.warpish_prompt.same_line);
+        assert!(config.appearance.warpish_prompt.custom_chips.is_empty());
+        assert!(!config.ai.ollama_url.contains("evil"));
+        assert!(config.hooks.shell.is_empty());
+        assert_eq!(config.sources().get("ai.ollama_url"), Some(&ConfigSource::Default));
+    }
+
+    #[test]
+    fn test_environment_overrides_known_keys_only() {
+        let env = vec![
+            ("WARPISH_APPEARANCE__FONT_SIZE".to_string(), "18".to_string()),
+            ("WARPISH_METRICS__ENABLED".to_string(), "true".to_string()),
+            ("WARPISH_AI__OLLAMA_MODEL".to_string(), "42".to_string()),
+            ("WARPISH_GITHUB_TOKEN".to_string(), "secret".to_string()),
+        ];
+        let config = load_layered(&ConfigPaths::default(), env).unwrap();
+
+        assert_eq!(config.appearance.font_size, 18.0);
+        assert!(config.metrics.enabled);
+        assert_eq!(config.ai.ollama_model, "42");
+        assert_eq!(
+            config.sources().get("metrics.enabled"),
+            Some(&ConfigSource::Environment("WARPISH_METRICS__ENABLED".to_string()))
+        );
+        assert!(config.sources().get("github_token").is_none());
+        assert_eq!(config.sources().overridden().count(), 3);
+    }
+
+    #[test]
+    fn test_write_value_keeps_other_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(dir.path(), "user.toml", "[appearance]\nfont_size = 16.0\n");
+        write_value(&path, "ai.model_roles.agent", toml::Value::String("llama3".to_string())).unwrap();
+        write_value(&dir.path().join("new/user.toml"), "ai.locale", toml::Value::String("de".to_string())).unwrap();
+
+        let paths = ConfigPaths { files: vec![(ConfigLayer::User, path)] };
+        let config = load_layered(&paths, Vec::new()).unwrap();
+        assert_eq!(config.appearance.font_size, 16.0);
+        assert_eq!(config.ai.model_roles.agent.as_deref(), Some("llama3"));
+        assert!(dir.path().join("new/user.toml").is_file());
+    }
+
+    #[test]
+    fn test_find_project_config_stops_at_home() {
+        let home = tempfile::tempdir().unwrap();
+        let nested = home.path().join("work/app/src");
+        std::fs::create_dir_all(&nested).unwrap();
+        write(home.path(), PROJECT_FILE_NAME, "");
+        assert_eq!(find_project_config(&nested, Some(home.path())), None);
+
+        let project = write(&home.path().join("work/app"), PROJECT_FILE_NAME, "");
+        assert_eq!(find_project_config(&nested, Some(home.path())), Some(project));
+    }
+}
@@ -1,9 +1,12 @@
+pub mod layers;
 pub mod theme;
 
 use crate::agent::model::ModelId;
 use crate::error::AppError;
+use crate::rules::Rule;
+use layers::{ConfigPaths, ConfigSources};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -20,6 +23,50 @@ pub struct AiConfig {
     pub enable_ai_completions: bool,
     #[serde(default = "default_ai_timeout")]
     pub ai_timeout_seconds: u64,
+    /// Token budget for attachments serialized into agent requests.
+    #[serde(default = "default_context_token_budget")]
+    pub context_token_budget: usize,
+    /// Question used by "Ask AI"; `{{selection}}` is replaced by the quoted selection.
+    #[serde(default = "default_ask_ai_template")]
+    pub ask_ai_template: String,
+    /// Language "Translate Output" summarizes into, e.g. `de-DE`; `LANG` when unset.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Local Ollama models assigned to each role; unset roles use `ollama_model`.
+    #[serde(default)]
+    pub model_roles: ModelRoles,
+    /// YAML fixture, or directory of them, replayed instead of asking a model.
+    #[serde(default)]
+    pub agent_script: Option<PathBuf>,
+    /// Sends input that reads like a question to the agent instead of the shell.
+    #[serde(default)]
+    pub detect_natural_language: bool,
+    /// Two models agent tasks alternate between, to compare their metrics.
+    #[serde(default)]
+    pub ab_test: Option<AbTestConfig>,
+    /// Agent spend per month, in US dollars, to warn at 80% of and at.
+    #[serde(default)]
+    pub monthly_budget: Option<f64>,
+    /// Whether generated commit messages follow Conventional Commits; when
+    /// unset, repositories with a commitlint or commitizen config do.
+    #[serde(default)]
+    pub conventional_commits: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AbTestConfig {
+    pub model_a: ModelId,
+    pub model_b: ModelId,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ModelRoles {
+    #[serde(default)]
+    pub completions: Option<String>,
+    #[serde(default)]
+    pub summarization: Option<String>,
+    #[serde(default)]
+    pub agent: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -38,6 +85,35 @@ pub struct CompletionsConfig {
     pub show_type_indicators: bool,
     #[serde(default = "default_cache_duration")]
     pub cache_duration_seconds: u64,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+}
+
+/// Lists fetched ahead of time so first-use completions are fast.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrefetchConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Any of `brew_formulae`, `pip_packages` and `crates`.
+    #[serde(default = "default_prefetch_sources")]
+    pub sources: Vec<String>,
+    /// Seconds without a key press before a fetch may start.
+    #[serde(default = "default_prefetch_idle_seconds")]
+    pub idle_seconds: u64,
+    /// Seconds between the starts of two fetches.
+    #[serde(default = "default_prefetch_min_interval")]
+    pub min_interval_seconds: u64,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sources: default_prefetch_sources(),
+            idle_seconds: default_prefetch_idle_seconds(),
+            min_interval_seconds: default_prefetch_min_interval(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -52,6 +128,762 @@ pub struct EditorConfig {
     pub vim_enabled: bool,
     #[serde(default = "default_completions")]
     pub completions: CompletionsConfig,
+    /// Wraps long command lines at the window edge instead of scrolling.
+    #[serde(default = "default_true")]
+    pub soft_wrap: bool,
+    /// Numbers the lines of multi-line commands in a gutter.
+    #[serde(default = "default_true")]
+    pub line_numbers: bool,
+    /// Offers the commands usually run in a directory on entering it.
+    #[serde(default = "default_true")]
+    pub directory_suggestions: bool,
+}
+
+/// Maps a command prefix to a tag applied to matching blocks.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TagRule {
+    pub prefix: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TaggingConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_tag_rules")]
+    pub rules: Vec<TagRule>,
+    #[serde(default)]
+    pub ai_classifier: bool,
+}
+
+impl Default for TaggingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: default_tag_rules(),
+            ai_classifier: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlocksConfig {
+    /// Output lines left visible when a block is folded.
+    #[serde(default = "default_fold_lines")]
+    pub fold_lines: usize,
+    #[serde(default)]
+    pub fold_by_default: bool,
+    #[serde(default = "default_true")]
+    pub show_minimap: bool,
+    /// Pin a block's command line to the top of the pane while its output
+    /// is scrolled through.
+    #[serde(default = "default_true")]
+    pub sticky_header: bool,
+    /// Collapse successful commands that print more lines than this. 0
+    /// leaves them expanded.
+    #[serde(default = "default_auto_collapse_lines")]
+    pub auto_collapse_lines: usize,
+    /// Lines shown when only the end of a huge output is shown.
+    #[serde(default = "default_tail_lines")]
+    pub tail_lines: usize,
+    /// Outputs longer than this start out showing only their last lines.
+    #[serde(default = "default_huge_output_lines")]
+    pub huge_output_lines: usize,
+}
+
+impl Default for BlocksConfig {
+    fn default() -> Self {
+        Self {
+            fold_lines: default_fold_lines(),
+            fold_by_default: false,
+            show_minimap: true,
+            sticky_header: true,
+            auto_collapse_lines: default_auto_collapse_lines(),
+            tail_lines: default_tail_lines(),
+            huge_output_lines: default_huge_output_lines(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhooksConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_webhook_port")]
+    pub port: u16,
+    /// Bearer token callers must present. Generated and stored locally when unset.
+    pub token: Option<String>,
+    /// Names of Drive workflows that webhooks may trigger.
+    #[serde(default)]
+    pub allowed_workflows: Vec<String>,
+    /// Rules for webhook-triggered commands, on top of the built-in ones.
+    #[serde(default)]
+    pub command_rules: Vec<Rule>,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_webhook_port(),
+            token: None,
+            allowed_workflows: Vec::new(),
+            command_rules: Vec::new(),
+        }
+    }
+}
+
+/// The local JSON-RPC server that `warpish-cli` and editors talk to.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LpcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// The socket, or pipe name on Windows. A per-user path by default.
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+}
+
+/// A destination for shared block exports.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShareTargetConfig {
+    Gist {
+        #[serde(default = "default_gist_target_name")]
+        name: String,
+        #[serde(default)]
+        public: bool,
+        /// Keychain entry holding the GitHub token.
+        #[serde(default = "default_github_credential")]
+        credential: String,
+    },
+    Paste {
+        name: String,
+        url: String,
+        /// Keychain entry holding a bearer token, if the endpoint needs one.
+        credential: Option<String>,
+    },
+}
+
+impl ShareTargetConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            ShareTargetConfig::Gist { name, .. } | ShareTargetConfig::Paste { name, .. } => name,
+        }
+    }
+}
+
+/// A JSON search API that returns results to show inline.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchApiConfig {
+    /// Google's Custom Search JSON API, for the engine `engine_id`.
+    GoogleCustomSearch { engine_id: String },
+    /// The Brave Search API.
+    Brave,
+}
+
+/// A web search provider for "Search Web for Selection".
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SearchProviderConfig {
+    pub name: String,
+    /// Page opened in the browser; `{query}` is replaced by the encoded
+    /// selection, e.g. `https://docs.example.com/search?q={query}`.
+    pub url: String,
+    /// Fetches results inline instead, once `credential` is in the keychain.
+    #[serde(default)]
+    pub api: Option<SearchApiConfig>,
+    /// Keychain entry holding the API key.
+    #[serde(default)]
+    pub credential: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PaletteConfig {
+    /// Favorite palette items, listed first, e.g. `action:scratch.open` or
+    /// `workflow:Undo last commit`.
+    #[serde(default)]
+    pub pinned: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSearchConfig {
+    #[serde(default = "default_search_providers")]
+    pub providers: Vec<SearchProviderConfig>,
+    /// Listed first when choosing a provider.
+    pub default_provider: Option<String>,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        Self { providers: default_search_providers(), default_provider: None }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShareConfig {
+    #[serde(default = "default_share_targets")]
+    pub targets: Vec<ShareTargetConfig>,
+    pub default_target: Option<String>,
+    /// Extra regexes redacted in addition to the built-in secret patterns.
+    #[serde(default)]
+    pub redaction_patterns: Vec<String>,
+    /// Address the live share server listens on. Port 0 picks a free port;
+    /// use `0.0.0.0` to let collaborators on other machines connect.
+    #[serde(default = "default_live_share_address")]
+    pub live_address: String,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            targets: default_share_targets(),
+            default_target: None,
+            redaction_patterns: Vec::new(),
+            live_address: default_live_share_address(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_metrics_port")]
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_metrics_port(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PerfConfig {
+    /// Show the performance HUD at startup.
+    #[serde(default)]
+    pub show_hud: bool,
+    /// How long a flamegraph capture samples for.
+    #[serde(default = "default_profile_seconds")]
+    pub profile_seconds: u64,
+}
+
+impl Default for PerfConfig {
+    fn default() -> Self {
+        Self {
+            show_hud: false,
+            profile_seconds: default_profile_seconds(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StructuredOutputConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_structured_timeout")]
+    pub timeout_seconds: u64,
+}
+
+impl Default for StructuredOutputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_seconds: default_structured_timeout(),
+        }
+    }
+}
+
+/// Locks a pane when its running command matches `pattern` (a regex).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SensitivePaneRule {
+    pub pattern: String,
+    /// Overrides the global idle timeout for matching panes.
+    pub timeout_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IdleLockConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_idle_timeout")]
+    pub timeout_seconds: u64,
+    #[serde(default)]
+    pub require_os_auth: bool,
+    #[serde(default = "default_sensitive_pane_rules")]
+    pub rules: Vec<SensitivePaneRule>,
+}
+
+impl Default for IdleLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_seconds: default_idle_timeout(),
+            require_os_auth: false,
+            rules: default_sensitive_pane_rules(),
+        }
+    }
+}
+
+/// What to do when a command fails in a pane other than the focused one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureFocusAction {
+    /// Move focus to the pane straight away.
+    Switch,
+    /// Ask before moving focus.
+    #[default]
+    Prompt,
+    /// Leave focus where it is.
+    Ignore,
+}
+
+/// Overrides the action for failures matching both `command` and
+/// `directory` (regexes); a rule without either matches every failure.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FailureFocusRule {
+    pub command: Option<String>,
+    pub directory: Option<String>,
+    pub action: FailureFocusAction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FailureFocusConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub action: FailureFocusAction,
+    /// Exit statuses that are not failures; 130 is a command stopped with Ctrl+C.
+    #[serde(default = "default_ignored_exit_codes")]
+    pub ignored_exit_codes: Vec<i32>,
+    /// Checked in order; the first matching rule decides.
+    #[serde(default = "default_failure_focus_rules")]
+    pub rules: Vec<FailureFocusRule>,
+}
+
+impl Default for FailureFocusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: FailureFocusAction::default(),
+            ignored_exit_codes: default_ignored_exit_codes(),
+            rules: default_failure_focus_rules(),
+        }
+    }
+}
+
+/// A lifecycle event hooks can subscribe to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEventKind {
+    CommandStart,
+    CommandFinish,
+    CwdChange,
+    OutputMatch,
+}
+
+/// Runs a shell command, shows a notification, or both when `event` fires.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShellHookConfig {
+    pub event: HookEventKind,
+    /// Regex matched against the command line or the new directory. For
+    /// `output_match` it is the pattern output lines are watched for, and is
+    /// required.
+    pub pattern: Option<String>,
+    /// For `command_finish`, only fire when the command failed.
+    #[serde(default)]
+    pub failed_only: bool,
+    /// Run with `sh -c`; the event is passed in `WARPISH_*` variables.
+    pub run: Option<String>,
+    /// `{command}`, `{exit_code}`, `{cwd}` and `{line}` are filled in.
+    pub notify: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub shell: Vec<ShellHookConfig>,
+    /// Lua scripts loaded at startup; they subscribe with `hooks.on`.
+    #[serde(default)]
+    pub scripts: Vec<PathBuf>,
+}
+
+/// The agent pane that pairs with a shell pane and suggests next steps.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PairConfig {
+    /// Master switch; when off, pair panes still open but stay quiet.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Least time between two suggestions in one pair.
+    #[serde(default = "default_pair_interval")]
+    pub min_interval_seconds: u64,
+    /// Suggestions one pair may make in any hour.
+    #[serde(default = "default_pair_hourly_limit")]
+    pub max_per_hour: usize,
+    /// How many of the shell pane's latest blocks the agent sees.
+    #[serde(default = "default_pair_context_blocks")]
+    pub context_blocks: usize,
+}
+
+impl Default for PairConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_interval_seconds: default_pair_interval(),
+            max_per_hour: default_pair_hourly_limit(),
+            context_blocks: default_pair_context_blocks(),
+        }
+    }
+}
+
+/// The character encodings new panes talk to their shell in, for legacy
+/// systems that do not speak UTF-8. Either can be changed per pane.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EncodingConfig {
+    /// What typed input is encoded as: `utf-8`, `latin-1` or `shift-jis`.
+    #[serde(default = "default_encoding")]
+    pub input: String,
+    /// What the shell's output is decoded from.
+    #[serde(default = "default_encoding")]
+    pub output: String,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        Self { input: default_encoding(), output: default_encoding() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScratchConfig {
+    /// Variables passed through from the terminal's environment; everything
+    /// else is dropped from a scratch pane's shell.
+    #[serde(default = "default_scratch_inherited_env")]
+    pub inherited_env: Vec<String>,
+}
+
+impl Default for ScratchConfig {
+    fn default() -> Self {
+        Self {
+            inherited_env: default_scratch_inherited_env(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrollCurve {
+    Linear,
+    EaseOutQuad,
+    #[default]
+    EaseOutCubic,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScrollConfig {
+    /// Animate scrolling; when false every scroll jumps to its destination.
+    #[serde(default = "default_true")]
+    pub smooth: bool,
+    #[serde(default = "default_lines_per_notch")]
+    pub lines_per_notch: f32,
+    #[serde(default = "default_scroll_animation_ms")]
+    pub animation_ms: u64,
+    #[serde(default)]
+    pub curve: ScrollCurve,
+    /// How quickly trackpad momentum dies away, per second.
+    #[serde(default = "default_scroll_friction")]
+    pub friction: f32,
+    /// Overrides the system's reduced-motion preference when set.
+    #[serde(default)]
+    pub reduced_motion: Option<bool>,
+}
+
+impl Default for ScrollConfig {
+    fn default() -> Self {
+        Self {
+            smooth: true,
+            lines_per_notch: default_lines_per_notch(),
+            animation_ms: default_scroll_animation_ms(),
+            curve: ScrollCurve::default(),
+            friction: default_scroll_friction(),
+            reduced_motion: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SshSessionConfig {
+    /// Check the pane's foreground process for `ssh`, so remote sessions are
+    /// noticed even when the remote shell sends no OSC 7 reports.
+    #[serde(default = "default_true")]
+    pub detect_foreground: bool,
+    /// Keep completing file paths in remote sessions. The paths come from
+    /// the local filesystem, so this is off by default.
+    #[serde(default)]
+    pub remote_file_completion: bool,
+    /// Complete commands from the remote host's `PATH`, listed over a second
+    /// connection and kept locally. Its shell history is only read with
+    /// permission.
+    #[serde(default = "default_true")]
+    pub remote_command_completion: bool,
+}
+
+impl Default for SshSessionConfig {
+    fn default() -> Self {
+        Self {
+            detect_foreground: true,
+            remote_file_completion: false,
+            remote_command_completion: true,
+        }
+    }
+}
+
+/// Occasional one-question prompts about features and satisfaction. Answers
+/// stay in the local database.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SurveyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fewest days between two prompts.
+    #[serde(default = "default_survey_interval_days")]
+    pub interval_days: u32,
+    /// Ask one question when quitting, if a prompt is due.
+    #[serde(default = "default_true")]
+    pub exit_survey: bool,
+}
+
+impl Default for SurveyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_days: default_survey_interval_days(),
+            exit_survey: true,
+        }
+    }
+}
+
+/// A named way to open a pane, e.g. "prod ssh" or "docker shell".
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProfileConfig {
+    pub name: String,
+    /// Replaces `user.shell`.
+    pub shell: Option<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Typed into the shell once it starts, e.g. `ssh prod-db-1`.
+    pub startup_command: Option<String>,
+    /// Where the shell starts; `~` is the home directory.
+    pub cwd: Option<String>,
+    /// A theme from `themes/`, used while the pane has focus.
+    pub theme: Option<String>,
+    /// Shown before the name in menus, e.g. an emoji.
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    /// Ask before closing a pane, or quitting, while a job is running.
+    #[serde(default = "default_true")]
+    pub confirm_running_jobs: bool,
+    /// How long shells get to exit after SIGHUP before they are killed.
+    #[serde(default = "default_hangup_timeout")]
+    pub hangup_timeout_ms: u64,
+    /// Offer to restore the panes, recent blocks and unsent input of a run
+    /// that crashed or was killed.
+    #[serde(default = "default_true")]
+    pub restore_after_crash: bool,
+    /// How often the session is journaled for that.
+    #[serde(default = "default_journal_interval")]
+    pub journal_interval_ms: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            confirm_running_jobs: true,
+            hangup_timeout_ms: default_hangup_timeout(),
+            restore_after_crash: true,
+            journal_interval_ms: default_journal_interval(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CrashReportConfig {
+    /// Save a report when the app panics and offer it on the next launch.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Where submitted reports are posted as JSON. Without one, reports
+    /// can only be viewed.
+    #[serde(default)]
+    pub submit_url: Option<String>,
+}
+
+impl Default for CrashReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            submit_url: None,
+        }
+    }
+}
+
+/// Pull requests and issues from GitHub in the palette.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GitHubConfig {
+    /// Keychain entry holding a GitHub token with `repo` scope.
+    #[serde(default = "default_github_credential")]
+    pub credential: String,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            credential: default_github_credential(),
+        }
+    }
+}
+
+/// The `http` built-in.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpConfig {
+    /// The collection, under the personal workspace's `http` directory,
+    /// that saved requests are appended to.
+    #[serde(default = "default_http_collection")]
+    pub collection: String,
+    #[serde(default = "default_http_timeout_secs")]
+    pub timeout_secs: u64,
+    /// `{{name}}` values shared by every request, e.g. hosts and tokens.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            collection: default_http_collection(),
+            timeout_secs: default_http_timeout_secs(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+/// The GraphQL query explorer.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GraphQLConfig {
+    /// The endpoint the explorer opens with.
+    #[serde(default)]
+    pub endpoint: String,
+    /// Keychain entry holding a bearer token for the endpoint.
+    #[serde(default)]
+    pub credential: Option<String>,
+}
+
+/// The WebSocket console opened with `ws <url>`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSocketConsoleConfig {
+    /// Reconnects when the connection drops, waiting twice as long after
+    /// each failed attempt.
+    #[serde(default = "default_true")]
+    pub reconnect: bool,
+    #[serde(default = "default_max_reconnect_delay_secs")]
+    pub max_reconnect_delay_secs: u64,
+}
+
+impl Default for WebSocketConsoleConfig {
+    fn default() -> Self {
+        Self {
+            reconnect: true,
+            max_reconnect_delay_secs: default_max_reconnect_delay_secs(),
+        }
+    }
+}
+
+/// Opening `file:line:col` locations from block output in an editor.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OpenInEditorConfig {
+    /// `vscode`, `vscode-url`, `cursor`, `zed`, `sublime`, `idea`, `nvim`,
+    /// `vim`, `emacs`, `helix`, `nano`, or a name from `templates`. Taken
+    /// from `$VISUAL` or `$EDITOR` when unset.
+    pub editor: Option<String>,
+    /// Extra or replacement editors by name.
+    #[serde(default)]
+    pub templates: BTreeMap<String, EditorTemplateConfig>,
+}
+
+/// A command like `code --goto {path}:{line}:{column}`, or a URL like
+/// `vscode://file/{path}:{line}:{column}`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EditorTemplateConfig {
+    pub command: String,
+    /// Runs the command in a new pane, for editors like `nvim`.
+    #[serde(default)]
+    pub terminal: bool,
+}
+
+/// Code formatters, run from the file browser.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct FormatConfig {
+    /// Extra or replacement formatter commands by language or extension,
+    /// e.g. `python: ruff format --stdin-filename {path} -`. The code is
+    /// piped to the command and read back from its stdout.
+    #[serde(default)]
+    pub formatters: BTreeMap<String, String>,
+}
+
+/// Kubernetes context awareness.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct K8sConfig {
+    /// Contexts or clusters whose name contains one of these, ignoring case,
+    /// count as production: destructive `kubectl` commands there need a
+    /// second Enter.
+    #[serde(default = "default_k8s_production_contexts")]
+    pub production_contexts: Vec<String>,
+}
+
+impl Default for K8sConfig {
+    fn default() -> Self {
+        Self {
+            production_contexts: default_k8s_production_contexts(),
+        }
+    }
+}
+
+/// Watch mode, which re-runs a block's command when files change.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchConfig {
+    /// The glob offered for new watches, relative to the pane's directory.
+    #[serde(default = "default_watch_glob")]
+    pub glob: String,
+    /// How long matching files must stay unchanged before the command re-runs.
+    #[serde(default = "default_watch_debounce")]
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            glob: default_watch_glob(),
+            debounce_ms: default_watch_debounce(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub enum ReportSchedule {
+    #[default]
+    Off,
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReportsConfig {
+    #[serde(default)]
+    pub schedule: ReportSchedule,
+    pub export_dir: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -130,6 +962,36 @@ pub struct WarpishPromptConfig {
     pub chips: Vec<String>,
     #[serde(default = "default_true")]
     pub same_line: bool,
+    /// Chips filled by the user's own scripts; list a chip's name in `chips`
+    /// to show it.
+    #[serde(default)]
+    pub custom_chips: Vec<CustomChipConfig>,
+}
+
+/// Where a custom prompt chip gets its text.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChipSource {
+    /// A shell command; the first line it prints is shown.
+    Command { command: String },
+    /// A Lua chunk returning the text; `cwd` holds the pane's directory.
+    Lua { script: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CustomChipConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: ChipSource,
+    /// Drawn before the text.
+    pub icon: Option<String>,
+    /// Hex color of the chip, e.g. `#e5c07b`.
+    pub color: Option<String>,
+    #[serde(default = "default_chip_refresh")]
+    pub refresh_seconds: u64,
+    /// The script is stopped, and its last value kept, after this long.
+    #[serde(default = "default_chip_timeout")]
+    pub timeout_ms: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
@@ -166,7 +1028,90 @@ pub struct Config {
     pub editor: EditorConfig,
     #[serde(default)]
     pub appearance: AppearanceConfig,
+    #[serde(default)]
+    pub blocks: BlocksConfig,
+    #[serde(default)]
+    pub tagging: TaggingConfig,
+    #[serde(default)]
+    pub reports: ReportsConfig,
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+    #[serde(default)]
+    pub lpc: LpcConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub perf: PerfConfig,
+    #[serde(default)]
+    pub share: ShareConfig,
+    #[serde(default)]
+    pub web_search: WebSearchConfig,
+    #[serde(default)]
+    pub palette: PaletteConfig,
+    #[serde(default)]
+    pub structured_output: StructuredOutputConfig,
+    #[serde(default)]
+    pub idle_lock: IdleLockConfig,
+    #[serde(default)]
+    pub failure_focus: FailureFocusConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub pair: PairConfig,
+    #[serde(default)]
+    pub encoding: EncodingConfig,
+    #[serde(default)]
+    pub scratch: ScratchConfig,
+    #[serde(default)]
+    pub scroll: ScrollConfig,
+    #[serde(default)]
+    pub ssh: SshSessionConfig,
+    #[serde(default)]
+    pub survey: SurveyConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    #[serde(default)]
+    pub crash_reports: CrashReportConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    #[serde(default)]
+    pub k8s: K8sConfig,
+    #[serde(default)]
+    pub github: GitHubConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub graphql: GraphQLConfig,
+    #[serde(default)]
+    pub websocket: WebSocketConsoleConfig,
+    #[serde(default)]
+    pub open_in_editor: OpenInEditorConfig,
+    #[serde(default)]
+    pub format: FormatConfig,
+    #[serde(default)]
+    pub profiles: Vec<ProfileConfig>,
     pub user: Option<UserConfig>,
+    /// Where each value was loaded from; filled in by `load_config`.
+    #[serde(skip)]
+    pub sources: ConfigSources,
+}
+
+impl Config {
+    /// Reports which layer (default, file or environment) set each value.
+    pub fn sources(&self) -> &ConfigSources {
+        &self.sources
+    }
+
+    /// The shell new panes run: `user.shell`, or the platform's default.
+    pub fn shell(&self) -> String {
+        self.user.as_ref().and_then(|user| user.shell.clone()).unwrap_or_else(|| {
+            if cfg!(windows) {
+                "powershell.exe".to_string()
+            } else {
+                "bash".to_string()
+            }
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -185,6 +1130,73 @@ fn default_opacity() -> f32 { 1.0 }
 fn default_ollama_url() -> String { "http://localhost:11434/api/generate".to_string() }
 fn default_ollama_model() -> String { "codellama".to_string() }
 fn default_ai_timeout() -> u64 { 5 }
+fn default_context_token_budget() -> usize { 4000 }
+fn default_ask_ai_template() -> String { "What does this mean, and how do I fix it if it is a problem?\n\n{{selection}}".to_string() }
+fn default_fold_lines() -> usize { 10 }
+fn default_auto_collapse_lines() -> usize { 200 }
+fn default_tail_lines() -> usize { 100 }
+fn default_huge_output_lines() -> usize { 5000 }
+fn default_webhook_port() -> u16 { 7878 }
+fn default_metrics_port() -> u16 { 9464 }
+fn default_profile_seconds() -> u64 { 10 }
+fn default_structured_timeout() -> u64 { 5 }
+fn default_idle_timeout() -> u64 { 300 }
+fn default_survey_interval_days() -> u32 { 14 }
+fn default_hangup_timeout() -> u64 { 2000 }
+fn default_journal_interval() -> u64 { 5000 }
+fn default_watch_glob() -> String { "**/*".to_string() }
+fn default_watch_debounce() -> u64 { 300 }
+fn default_k8s_production_contexts() -> Vec<String> { vec!["prod".to_string(), "production".to_string()] }
+fn default_sensitive_pane_rules() -> Vec<SensitivePaneRule> {
+    vec![SensitivePaneRule {
+        pattern: r"^(ssh|mosh)\s+.*\bprod".to_string(),
+        timeout_seconds: None,
+    }]
+}
+fn default_ignored_exit_codes() -> Vec<i32> { vec![130] }
+fn default_failure_focus_rules() -> Vec<FailureFocusRule> {
+    // These exit with 1 for "no match" or "files differ", which is rarely worth a jump.
+    vec![FailureFocusRule {
+        command: Some(r"^(grep|rg|diff|cmp|test|\[)(\s|$)".to_string()),
+        directory: None,
+        action: FailureFocusAction::Ignore,
+    }]
+}
+fn default_pair_interval() -> u64 { 30 }
+fn default_pair_hourly_limit() -> usize { 20 }
+fn default_pair_context_blocks() -> usize { 5 }
+fn default_encoding() -> String { "utf-8".to_string() }
+fn default_scratch_inherited_env() -> Vec<String> {
+    ["PATH", "HOME", "USER", "LOGNAME", "LANG", "TERM", "SHELL"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+fn default_lines_per_notch() -> f32 { 3.0 }
+fn default_scroll_animation_ms() -> u64 { 150 }
+fn default_scroll_friction() -> f32 { 4.0 }
+fn default_gist_target_name() -> String { "gist".to_string() }
+fn default_github_credential() -> String { "github_token".to_string() }
+fn default_http_collection() -> String { "requests".to_string() }
+fn default_http_timeout_secs() -> u64 { 30 }
+fn default_max_reconnect_delay_secs() -> u64 { 30 }
+fn default_share_targets() -> Vec<ShareTargetConfig> {
+    vec![ShareTargetConfig::Gist {
+        name: default_gist_target_name(),
+        public: false,
+        credential: default_github_credential(),
+    }]
+}
+fn default_search_providers() -> Vec<SearchProviderConfig> {
+    [("duckduckgo", "https://duckduckgo.com/?q={query}"), ("google", "https://www.google.com/search?q={query}")]
+        .iter()
+        .map(|(name, url)| SearchProviderConfig { name: name.to_string(), url: url.to_string(), api: None, credential: None })
+        .collect()
+}
+fn default_live_share_address() -> String { "127.0.0.1:0".to_string() }
+fn default_prefetch_sources() -> Vec<String> { vec!["brew_formulae".to_string(), "pip_packages".to_string(), "crates".to_string()] }
+fn default_prefetch_idle_seconds() -> u64 { 30 }
+fn default_prefetch_min_interval() -> u64 { 300 }
 fn default_trigger_chars() -> Vec<char> { vec![' ', '\t', '/', '-', '.'] }
 fn default_min_trigger_length() -> usize { 1 }
 fn default_max_suggestions() -> usize { 15 }
@@ -198,29 +1210,40 @@ fn default_prompt_mode() -> PromptMode { PromptMode::Simple }
 fn default_input_position() -> InputPosition { InputPosition::Bottom }
 fn default_cursor() -> CursorConfig { CursorConfig::default() }
 fn default_warpish_prompt() -> WarpishPromptConfig { WarpishPromptConfig::default() }
+fn default_chip_refresh() -> u64 { 10 }
+fn default_chip_timeout() -> u64 { 1000 }
 fn default_prompt_chips() -> Vec<String> { vec!["cwd".to_string(), "git".to_string(), "time".to_string()] }
 fn default_theme() -> ThemeConfig { ThemeConfig::default() }
 fn default_theme_name() -> String { "default".to_string() }
 fn default_os_theme_mode() -> OsThemeMode { OsThemeMode::System }
 fn default_theme_path() -> Option<String> { None }
+pub fn default_tag_rules() -> Vec<TagRule> {
+    [
+        ("cargo build", "build"), ("make", "build"), ("npm run build", "build"), ("docker build", "build"),
+        ("cargo test", "test"), ("npm test", "test"), ("pytest", "test"), ("go test", "test"),
+        ("kubectl", "infra"), ("terraform", "infra"), ("helm", "infra"), ("ansible-playbook", "infra"),
+        ("git push", "deploy"), ("fly deploy", "deploy"), ("vercel", "deploy"),
+    ]
+    .into_iter()
+    .map(|(prefix, tag)| TagRule { prefix: prefix.to_string(), tag: tag.to_string() })
+    .collect()
+}
 
 fn default_window_size() -> WindowSizeConfig { WindowSizeConfig::default() }
 
 // Type alias for compatibility with existing code
 pub type TextConfig = AppearanceConfig;
 
+/// Loads the layered configuration (see [`layers`]) for the current user
+/// and working directory.
 pub fn load_config() -> Result<Config, AppError> {
-    let config_str = fs::read_to_string("terminal.toml")
-        .map_err(|e| AppError::Config(format!("Failed to read terminal.toml: {}", e)))?;
-    
-    let mut config: Config = toml::from_str(&config_str)
-        .map_err(|e| AppError::Config(format!("Failed to parse terminal.toml: {}", e)))?;
+    let mut config = layers::load_layered(&ConfigPaths::discover(), std::env::vars())?;
 
     if config.ai_api_key.is_none() {
         config.ai_api_key = std::env::var("AI_API_KEY").ok();
     }
-    
-    println!("Configuration file 'terminal.toml' loaded.");
+
+    log::info!("Configuration loaded ({} value(s) overridden).", config.sources().overridden().count());
 
     Ok(config)
-}
\ No newline at end of file
+}
@@ -58,6 +58,22 @@ pub struct AnsiColors {
     pub white: String,
 }
 
+/// Colors for the highlighted command line. Unset roles fall back to the
+/// ANSI palette.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SyntaxColors {
+    pub command: Option<String>,
+    pub flag: Option<String>,
+    pub string: Option<String>,
+    pub variable: Option<String>,
+    pub operator: Option<String>,
+    pub keyword: Option<String>,
+    pub comment: Option<String>,
+    /// Underlines input that does not parse.
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Colors {
     pub primary: ColorPalette,
@@ -70,6 +86,7 @@ pub struct Colors {
     pub normal: AnsiColors,
     pub bright: AnsiColors,
     pub dim: Option<AnsiColors>,
+    pub syntax: Option<SyntaxColors>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -0,0 +1,323 @@
+//! Crash reports
+//!
+//! A panic hook writes a report — the panic message and location, a
+//! backtrace, the last few hundred log lines and a hash of the config — to
+//! the crash directory before the process goes down, and puts a terminal
+//! left in raw mode back the way it was. On the next launch the app offers
+//! to view or submit each report it hasn't shown yet. Panics on other
+//! threads, such as a pane's reader, are reported the same way.
+
+use crate::config::Config;
+use crate::share::Redactor;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Log lines kept for the next report.
+pub const MAX_RECENT_LOGS: usize = 200;
+
+static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+#[derive(Error, Debug)]
+pub enum CrashError {
+    #[error("Submitting the report failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Report rejected with status {0}")]
+    Rejected(u16),
+}
+
+/// Passes records to `env_logger` as `RUST_LOG` asks, and keeps info and
+/// above, whatever `RUST_LOG` says, for crash reports.
+struct CapturingLogger {
+    inner: env_logger::Logger,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= log::Level::Info || self.inner.matches(record) {
+            remember_log(format!(
+                "{} {} {}: {}",
+                chrono::Local::now().format("%H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Sets up logging in place of `env_logger::init`.
+pub fn init_logging() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(log::LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(CapturingLogger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+fn remember_log(line: String) {
+    let Ok(mut logs) = RECENT_LOGS.lock() else {
+        return;
+    };
+    if logs.len() == MAX_RECENT_LOGS {
+        logs.pop_front();
+    }
+    logs.push_back(line);
+}
+
+/// The kept log lines, oldest first. Empty rather than waiting if the
+/// panic happened while they were being written.
+pub fn recent_logs() -> Vec<String> {
+    RECENT_LOGS
+        .try_lock()
+        .map(|logs| logs.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Identifies the config a crash happened under without including it, as
+/// it can hold keys. FNV-1a over its serialized form.
+pub fn config_hash(config: &Config) -> String {
+    let serialized = serde_json::to_vec(config).unwrap_or_default();
+    let hash = serialized
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrashReport {
+    /// Unix seconds.
+    pub at: i64,
+    pub version: String,
+    pub os: String,
+    pub thread: String,
+    pub message: String,
+    /// `file:line:column` of the panic.
+    pub location: Option<String>,
+    pub backtrace: String,
+    pub recent_logs: Vec<String>,
+    pub config_hash: String,
+}
+
+impl CrashReport {
+    pub fn from_panic(info: &PanicHookInfo, config_hash: &str) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+        Self {
+            at: chrono::Utc::now().timestamp(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("<unnamed>")
+                .to_string(),
+            message,
+            location: info.location().map(|location| location.to_string()),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            recent_logs: recent_logs(),
+            config_hash: config_hash.to_string(),
+        }
+    }
+
+    /// Writes the report to a new file in `dir`.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let data = serde_yaml::to_string(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let path = dir.join(format!(
+            "crash-{}-{}.yml",
+            self.at,
+            uuid::Uuid::new_v4().simple()
+        ));
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+
+    /// The report as sent, with secrets in the log lines redacted.
+    fn redacted(&self) -> Self {
+        let Ok(redactor) = Redactor::new(&[]) else {
+            return self.clone();
+        };
+        Self {
+            recent_logs: self
+                .recent_logs
+                .iter()
+                .map(|line| redactor.redact(line).0)
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Posts `report` as JSON to `url`.
+pub async fn submit(report: &CrashReport, url: &str) -> Result<(), CrashError> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(&report.redacted())
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(CrashError::Rejected(response.status().as_u16()));
+    }
+    Ok(())
+}
+
+pub fn default_crash_dir() -> PathBuf {
+    let mut path = dirs::config_dir().unwrap_or_else(std::env::temp_dir);
+    path.push("warpish_terminal");
+    path.push("crashes");
+    path
+}
+
+/// A saved report that hasn't been offered yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingReport {
+    pub path: PathBuf,
+    pub report: CrashReport,
+}
+
+/// The reports in `dir` not yet marked seen, newest first.
+pub fn pending_reports(dir: &Path) -> Vec<PendingReport> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut reports: Vec<PendingReport> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "yml"))
+        .filter_map(|path| {
+            let data = fs::read_to_string(&path).ok()?;
+            match serde_yaml::from_str(&data) {
+                Ok(report) => Some(PendingReport { path, report }),
+                Err(e) => {
+                    log::warn!(
+                        "Ignoring an unreadable crash report {}: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        b.report
+            .at
+            .cmp(&a.report.at)
+            .then_with(|| b.path.cmp(&a.path))
+    });
+    reports
+}
+
+/// Keeps the report on disk but stops offering it.
+pub fn mark_seen(path: &Path) -> io::Result<()> {
+    fs::rename(path, path.with_extension("seen"))
+}
+
+/// Saves a report for every panic, then runs the previous hook.
+pub fn install_panic_hook(dir: PathBuf, config_hash: String) {
+    let original_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        match CrashReport::from_panic(info, &config_hash).save(&dir) {
+            Ok(path) => eprintln!(
+                "Warpish Terminal crashed. A report was saved to {}",
+                path.display()
+            ),
+            Err(e) => eprintln!(
+                "Warpish Terminal crashed and the report could not be saved: {}",
+                e
+            ),
+        }
+        original_hook(info);
+    }));
+}
+
+/// Leaves raw mode and the alternate screen if the app was drawing in a
+/// terminal, so the shell it was started from is usable again.
+fn restore_terminal() {
+    if crossterm::terminal::is_raw_mode_enabled().unwrap_or(false) {
+        let _ = crossterm::terminal::disable_raw_mode();
+        let _ = crossterm::execute!(
+            io::stdout(),
+            crossterm::terminal::LeaveAlternateScreen,
+            crossterm::cursor::Show
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(at: i64) -> CrashReport {
+        CrashReport {
+            at,
+            version: "0.1.0".to_string(),
+            os: "linux x86_64".to_string(),
+            thread: "main".to_string(),
+            message: "index out of bounds".to_string(),
+            location: Some("src/app/state.rs:10:5".to_string()),
+            backtrace: String::new(),
+            recent_logs: vec!["12:00:00.000 INFO warpish: started".to_string()],
+            config_hash: "0123456789abcdef".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pending_reports_until_seen() {
+        let dir = std::env::temp_dir().join(format!("warpish-crash-test-{}", uuid::Uuid::new_v4()));
+        assert!(pending_reports(&dir).is_empty());
+
+        let older = report(1_700_000_000).save(&dir).unwrap();
+        let newer = report(1_700_000_100).save(&dir).unwrap();
+        let pending = pending_reports(&dir);
+        assert_eq!(
+            pending
+                .iter()
+                .map(|pending| &pending.path)
+                .collect::<Vec<_>>(),
+            vec![&newer, &older]
+        );
+        assert_eq!(pending[0].report, report(1_700_000_100));
+
+        mark_seen(&newer).unwrap();
+        let pending = pending_reports(&dir);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].path, older);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_submitted_logs_are_redacted() {
+        let mut report = report(1_700_000_000);
+        report
+            .recent_logs
+            .push("12:00:01.000 DEBUG http: API_KEY=sk-live-123456".to_string());
+        let redacted = report.redacted();
+        assert_eq!(redacted.recent_logs[0], report.recent_logs[0]);
+        assert!(!redacted.recent_logs[1].contains("sk-live-123456"));
+        assert_eq!(redacted.message, report.message);
+    }
+}
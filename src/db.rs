@@ -1,11 +1,45 @@
-use rusqlite::{Connection, Result};
+use crate::agent::client::AgentResponse;
+use crate::agent::model::ModelId;
+use crate::agent_mode_eval::usage::UsageRecord;
+use crate::agent_mode_eval::AgentEvaluation;
+use crate::explain::CommandHelp;
+use crate::mcq::bank::Review;
+use crate::mcq::survey::SurveyResponse;
+use crate::mcq::MultipleChoiceQuestion;
+use crate::natural_language_detection::Correction;
+use crate::permissions::{Capability, PermissionGrant, Requester};
+use crate::ssh::corpus::HostCorpus;
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use std::env;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// The history database, relative to the directory Warpish starts in.
+pub const DB_PATH: &str = "./warpish_history.db";
+
+/// Every table `establish_connection` creates.
+const SCHEMA_TABLES: &[&str] = &[
+    "commands",
+    "agent_conversations",
+    "agent_messages",
+    "permission_grants",
+    "host_corpora",
+    "host_corpus_entries",
+    "survey_responses",
+    "output_translations",
+    "palette_usage",
+    "input_drafts",
+    "directory_commands",
+    "nl_corrections",
+    "quiz_questions",
+    "quiz_reviews",
+    "agent_evaluations",
+    "agent_usage",
+    "command_help",
+];
+
 pub fn establish_connection() -> Result<Connection> {
     // Simplified - use a fixed path for the DB file
-    let db_path = "./warpish_history.db";
-    let conn = Connection::open(db_path)?;
+    let conn = Connection::open(DB_PATH)?;
     
     // Ensure the table exists
     conn.execute(
@@ -16,10 +50,31 @@ pub fn establish_connection() -> Result<Connection> {
         )",
         [],
     )?;
+
+    create_agent_tables(&conn)?;
+    create_permission_tables(&conn)?;
+    create_host_corpus_tables(&conn)?;
+    create_survey_tables(&conn)?;
+    create_translation_tables(&conn)?;
+    create_palette_tables(&conn)?;
+    create_draft_tables(&conn)?;
+    create_directory_command_tables(&conn)?;
+    create_nl_correction_tables(&conn)?;
+    create_quiz_tables(&conn)?;
+    create_evaluation_tables(&conn)?;
+    create_usage_tables(&conn)?;
+    create_command_help_tables(&conn)?;
     
     Ok(conn)
 }
 
+/// The tables a database made by an older version does not have yet.
+pub fn missing_tables(conn: &Connection) -> Result<Vec<&'static str>> {
+    let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?;
+    let existing = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>>>()?;
+    Ok(SCHEMA_TABLES.iter().copied().filter(|table| !existing.iter().any(|name| name == table)).collect())
+}
+
 pub fn create_command(
     conn: &mut Connection,
     command_text: &str,
@@ -74,3 +129,811 @@ pub fn get_all_history(conn: &mut Connection) -> Result<Vec<String>> {
     
     Ok(results)
 }
+
+/// Returns `(command, timestamp)` pairs recorded at or after `since`, oldest first.
+pub fn get_history_since(conn: &mut Connection, since: i64) -> Result<Vec<(String, i64)>> {
+    let mut stmt = conn.prepare(
+        "SELECT command, timestamp FROM commands WHERE timestamp >= ? ORDER BY timestamp ASC"
+    )?;
+
+    let rows = stmt.query_map([since], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+    let mut results = Vec::new();
+    for row_result in rows {
+        results.push(row_result?);
+    }
+
+    Ok(results)
+}
+
+/// One agent exchange as stored in the `agent_messages` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentMessageRecord {
+    pub prompt: String,
+    pub response: AgentResponse,
+    /// Commands the agent ran while producing the response.
+    pub tool_calls: Vec<String>,
+    /// Text of the blocks attached as context.
+    pub attached_blocks: Vec<String>,
+    pub created_at: i64,
+}
+
+/// A row of the agent management list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub model: ModelId,
+    pub updated_at: i64,
+    pub message_count: usize,
+}
+
+pub fn create_agent_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_conversations (
+            id TEXT PRIMARY KEY,
+            title TEXT NOT NULL,
+            model TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_messages (
+            id INTEGER PRIMARY KEY,
+            conversation_id TEXT NOT NULL REFERENCES agent_conversations(id) ON DELETE CASCADE,
+            prompt TEXT NOT NULL,
+            response TEXT NOT NULL,
+            tool_calls TEXT NOT NULL,
+            attached_blocks TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(column: usize, text: &str) -> Result<T> {
+    serde_json::from_str(text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+/// Appends an exchange to a conversation, creating the conversation on first use.
+pub fn append_agent_message(
+    conn: &mut Connection,
+    conversation_id: &str,
+    title: &str,
+    model: &ModelId,
+    message: &AgentMessageRecord,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO agent_conversations (id, title, model, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+         ON CONFLICT(id) DO UPDATE SET model = excluded.model, updated_at = excluded.updated_at",
+        params![conversation_id, title, to_json(model)?, message.created_at],
+    )?;
+    tx.execute(
+        "INSERT INTO agent_messages (conversation_id, prompt, response, tool_calls, attached_blocks, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            conversation_id,
+            message.prompt,
+            to_json(&message.response)?,
+            to_json(&message.tool_calls)?,
+            to_json(&message.attached_blocks)?,
+            message.created_at
+        ],
+    )?;
+    tx.commit()
+}
+
+/// Selects conversation summaries; callers add the `WHERE` and `GROUP BY c.id`.
+const AGENT_CONVERSATION_SUMMARY: &str = "SELECT c.id, c.title, c.model, c.updated_at, COUNT(m.id)
+     FROM agent_conversations c
+     LEFT JOIN agent_messages m ON m.conversation_id = c.id";
+
+fn agent_conversation_summary(row: &rusqlite::Row) -> Result<AgentConversationSummary> {
+    let model: String = row.get(2)?;
+    Ok(AgentConversationSummary {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        model: from_json(2, &model)?,
+        updated_at: row.get(3)?,
+        message_count: row.get::<_, i64>(4)? as usize,
+    })
+}
+
+/// Escapes `%`, `_` and the escape character itself for a `LIKE ... ESCAPE '\'`
+/// pattern, so they match literally.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Lists conversations, most recent first, whose title or prompts contain `query`.
+pub fn search_agent_conversations(conn: &mut Connection, query: &str) -> Result<Vec<AgentConversationSummary>> {
+    let mut stmt = conn.prepare(&format!(
+        "{}
+         WHERE c.title LIKE ?1 ESCAPE '\\'
+            OR EXISTS (SELECT 1 FROM agent_messages s WHERE s.conversation_id = c.id AND s.prompt LIKE ?1 ESCAPE '\\')
+         GROUP BY c.id
+         ORDER BY c.updated_at DESC",
+        AGENT_CONVERSATION_SUMMARY
+    ))?;
+
+    let pattern = format!("%{}%", escape_like(query));
+    let rows = stmt.query_map([pattern], agent_conversation_summary)?;
+
+    let mut results = Vec::new();
+    for row_result in rows {
+        results.push(row_result?);
+    }
+
+    Ok(results)
+}
+
+pub fn get_agent_conversation(conn: &mut Connection, conversation_id: &str) -> Result<Option<AgentConversationSummary>> {
+    conn.query_row(
+        &format!("{} WHERE c.id = ?1 GROUP BY c.id", AGENT_CONVERSATION_SUMMARY),
+        [conversation_id],
+        agent_conversation_summary,
+    )
+    .optional()
+}
+
+/// Loads the exchanges of a conversation in the order they happened.
+pub fn load_agent_messages(conn: &mut Connection, conversation_id: &str) -> Result<Vec<AgentMessageRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT prompt, response, tool_calls, attached_blocks, created_at
+         FROM agent_messages WHERE conversation_id = ? ORDER BY id ASC"
+    )?;
+
+    let rows = stmt.query_map([conversation_id], |row| {
+        let response: String = row.get(1)?;
+        let tool_calls: String = row.get(2)?;
+        let attached_blocks: String = row.get(3)?;
+        Ok(AgentMessageRecord {
+            prompt: row.get(0)?,
+            response: from_json(1, &response)?,
+            tool_calls: from_json(2, &tool_calls)?,
+            attached_blocks: from_json(3, &attached_blocks)?,
+            created_at: row.get(4)?,
+        })
+    })?;
+
+    let mut results = Vec::new();
+    for row_result in rows {
+        results.push(row_result?);
+    }
+
+    Ok(results)
+}
+
+pub fn delete_agent_conversation(conn: &mut Connection, conversation_id: &str) -> Result<()> {
+    conn.execute("DELETE FROM agent_messages WHERE conversation_id = ?", [conversation_id])?;
+    conn.execute("DELETE FROM agent_conversations WHERE id = ?", [conversation_id])?;
+    Ok(())
+}
+
+pub fn create_permission_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS permission_grants (
+            requester_kind TEXT NOT NULL,
+            requester_name TEXT NOT NULL,
+            capability TEXT NOT NULL,
+            allowed INTEGER NOT NULL,
+            decided_at INTEGER NOT NULL,
+            PRIMARY KEY (requester_kind, requester_name, capability)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Stores a remembered permission decision, replacing any earlier one.
+pub fn save_permission_grant(conn: &Connection, grant: &PermissionGrant) -> Result<()> {
+    conn.execute(
+        "INSERT INTO permission_grants (requester_kind, requester_name, capability, allowed, decided_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(requester_kind, requester_name, capability)
+         DO UPDATE SET allowed = excluded.allowed, decided_at = excluded.decided_at",
+        params![
+            grant.requester.kind(),
+            grant.requester.name(),
+            grant.capability.key(),
+            grant.allowed,
+            grant.decided_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Loads remembered permission decisions. Rows naming an unknown requester
+/// kind or capability are skipped.
+pub fn load_permission_grants(conn: &Connection) -> Result<Vec<PermissionGrant>> {
+    let mut stmt = conn.prepare(
+        "SELECT requester_kind, requester_name, capability, allowed, decided_at FROM permission_grants"
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, bool>(3)?,
+            row.get::<_, i64>(4)?,
+        ))
+    })?;
+
+    let mut results = Vec::new();
+    for row_result in rows {
+        let (kind, name, capability, allowed, decided_at) = row_result?;
+        if let (Some(requester), Some(capability)) = (Requester::from_parts(&kind, name), Capability::from_key(&capability)) {
+            results.push(PermissionGrant { requester, capability, allowed, remembered: true, decided_at });
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn delete_permission_grant(conn: &Connection, requester: &Requester, capability: Capability) -> Result<()> {
+    conn.execute(
+        "DELETE FROM permission_grants WHERE requester_kind = ?1 AND requester_name = ?2 AND capability = ?3",
+        params![requester.kind(), requester.name(), capability.key()],
+    )?;
+    Ok(())
+}
+
+pub fn create_host_corpus_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS host_corpora (
+            host TEXT PRIMARY KEY,
+            harvested_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS host_corpus_entries (
+            host TEXT NOT NULL REFERENCES host_corpora(host) ON DELETE CASCADE,
+            kind TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            entry TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Replaces the stored commands and history for a host.
+pub fn save_host_corpus(conn: &Connection, corpus: &HostCorpus) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM host_corpus_entries WHERE host = ?1", params![corpus.host])?;
+    tx.execute(
+        "INSERT INTO host_corpora (host, harvested_at) VALUES (?1, ?2)
+         ON CONFLICT(host) DO UPDATE SET harvested_at = excluded.harvested_at",
+        params![corpus.host, corpus.harvested_at],
+    )?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO host_corpus_entries (host, kind, position, entry) VALUES (?1, ?2, ?3, ?4)"
+        )?;
+        for (kind, entries) in [("command", &corpus.commands), ("history", &corpus.history)] {
+            for (position, entry) in entries.iter().enumerate() {
+                insert.execute(params![corpus.host, kind, position as i64, entry])?;
+            }
+        }
+    }
+    tx.commit()
+}
+
+pub fn load_host_corpus(conn: &Connection, host: &str) -> Result<Option<HostCorpus>> {
+    let harvested_at = conn
+        .query_row("SELECT harvested_at FROM host_corpora WHERE host = ?1", params![host], |row| row.get::<_, i64>(0))
+        .optional()?;
+    let Some(harvested_at) = harvested_at else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT kind, entry FROM host_corpus_entries WHERE host = ?1 ORDER BY position"
+    )?;
+    let rows = stmt.query_map(params![host], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+    let mut corpus = HostCorpus { host: host.to_string(), harvested_at, ..HostCorpus::default() };
+    for row_result in rows {
+        let (kind, entry) = row_result?;
+        match kind.as_str() {
+            "command" => corpus.commands.push(entry),
+            "history" => corpus.history.push(entry),
+            _ => {}
+        }
+    }
+    Ok(Some(corpus))
+}
+
+pub fn create_survey_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS survey_responses (
+            id INTEGER PRIMARY KEY,
+            question_id TEXT NOT NULL,
+            category TEXT NOT NULL,
+            selected_answer INTEGER,
+            option_count INTEGER NOT NULL,
+            responded_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Stores an answered or dismissed survey prompt.
+pub fn save_survey_response(conn: &Connection, response: &SurveyResponse) -> Result<()> {
+    conn.execute(
+        "INSERT INTO survey_responses (question_id, category, selected_answer, option_count, responded_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            response.question_id,
+            response.category,
+            response.selected_answer.map(|answer| answer as i64),
+            response.option_count as i64,
+            response.responded_at
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn load_survey_responses(conn: &Connection) -> Result<Vec<SurveyResponse>> {
+    let mut stmt = conn.prepare(
+        "SELECT question_id, category, selected_answer, option_count, responded_at
+         FROM survey_responses ORDER BY responded_at"
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(SurveyResponse {
+            question_id: row.get(0)?,
+            category: row.get(1)?,
+            selected_answer: row.get::<_, Option<i64>>(2)?.map(|answer| answer as usize),
+            option_count: row.get::<_, i64>(3)? as usize,
+            responded_at: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn create_translation_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS output_translations (
+            output_hash TEXT NOT NULL,
+            locale TEXT NOT NULL,
+            summary TEXT NOT NULL,
+            PRIMARY KEY (output_hash, locale)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn save_translation(conn: &Connection, output_hash: &str, locale: &str, summary: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO output_translations (output_hash, locale, summary) VALUES (?1, ?2, ?3)
+         ON CONFLICT(output_hash, locale) DO UPDATE SET summary = excluded.summary",
+        params![output_hash, locale, summary],
+    )?;
+    Ok(())
+}
+
+pub fn load_translation(conn: &Connection, output_hash: &str, locale: &str) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT summary FROM output_translations WHERE output_hash = ?1 AND locale = ?2",
+        params![output_hash, locale],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn create_palette_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS palette_usage (
+            item_key TEXT PRIMARY KEY,
+            used_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn record_palette_use(conn: &Connection, item_key: &str, used_at: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO palette_usage (item_key, used_at) VALUES (?1, ?2)
+         ON CONFLICT(item_key) DO UPDATE SET used_at = excluded.used_at",
+        params![item_key, used_at],
+    )?;
+    Ok(())
+}
+
+/// Keys of the palette items used most recently, newest first.
+pub fn load_palette_usage(conn: &Connection, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT item_key FROM palette_usage ORDER BY used_at DESC LIMIT ?1")?;
+    let rows = stmt.query_map(params![limit as i64], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn create_draft_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS input_drafts (
+            editor TEXT PRIMARY KEY,
+            text TEXT NOT NULL,
+            saved_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Keeps the unsent text of `editor` for the next launch; empty text
+/// removes the draft.
+pub fn save_draft(conn: &Connection, editor: &str, text: &str, saved_at: i64) -> Result<()> {
+    if text.trim().is_empty() {
+        conn.execute("DELETE FROM input_drafts WHERE editor = ?1", params![editor])?;
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO input_drafts (editor, text, saved_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(editor) DO UPDATE SET text = excluded.text, saved_at = excluded.saved_at",
+        params![editor, text, saved_at],
+    )?;
+    Ok(())
+}
+
+pub fn load_draft(conn: &Connection, editor: &str) -> Result<Option<String>> {
+    conn.query_row("SELECT text FROM input_drafts WHERE editor = ?1", params![editor], |row| row.get(0))
+        .optional()
+}
+
+pub fn create_directory_command_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS directory_commands (
+            directory TEXT NOT NULL,
+            command TEXT NOT NULL,
+            run_count INTEGER NOT NULL,
+            last_run INTEGER NOT NULL,
+            PRIMARY KEY (directory, command)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Counts a run of `command` in `directory`.
+pub fn record_directory_command(conn: &Connection, directory: &str, command: &str, run_at: i64) -> Result<()> {
+    if command.trim().is_empty() {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO directory_commands (directory, command, run_count, last_run) VALUES (?1, ?2, 1, ?3)
+         ON CONFLICT(directory, command) DO UPDATE SET run_count = run_count + 1, last_run = excluded.last_run",
+        params![directory, command.trim(), run_at],
+    )?;
+    Ok(())
+}
+
+/// The commands run most often in `directory`, the most recent first among
+/// equally frequent ones.
+pub fn top_directory_commands(conn: &Connection, directory: &str, limit: usize) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT command FROM directory_commands WHERE directory = ?1 ORDER BY run_count DESC, last_run DESC LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![directory, limit as i64], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn create_nl_correction_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS nl_corrections (
+            input TEXT PRIMARY KEY,
+            is_command INTEGER NOT NULL,
+            corrected_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Keeps the user's verdict on an input, replacing any earlier one.
+pub fn save_nl_correction(conn: &Connection, correction: &Correction, corrected_at: i64) -> Result<()> {
+    conn.execute(
+        "INSERT INTO nl_corrections (input, is_command, corrected_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(input) DO UPDATE SET is_command = excluded.is_command, corrected_at = excluded.corrected_at",
+        params![correction.input.trim(), correction.is_command, corrected_at],
+    )?;
+    Ok(())
+}
+
+pub fn load_nl_corrections(conn: &Connection) -> Result<Vec<Correction>> {
+    let mut stmt = conn.prepare("SELECT input, is_command FROM nl_corrections ORDER BY corrected_at")?;
+    let rows = stmt.query_map([], |row| Ok(Correction { input: row.get(0)?, is_command: row.get(1)? }))?;
+    rows.collect()
+}
+
+pub fn create_quiz_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS quiz_questions (
+            id TEXT PRIMARY KEY,
+            set_name TEXT NOT NULL,
+            question TEXT NOT NULL,
+            added_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS quiz_reviews (
+            question_id TEXT PRIMARY KEY,
+            ease REAL NOT NULL,
+            interval_days INTEGER NOT NULL,
+            repetitions INTEGER NOT NULL,
+            due_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Stores a bank question as JSON, replacing one with the same id but
+/// keeping its place in the bank.
+pub fn save_quiz_question(conn: &Connection, set_name: &str, question: &MultipleChoiceQuestion) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    conn.execute(
+        "INSERT INTO quiz_questions (id, set_name, question, added_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(id) DO UPDATE SET set_name = excluded.set_name, question = excluded.question",
+        params![question.id, set_name, to_json(question)?, now],
+    )?;
+    Ok(())
+}
+
+/// Bank questions with their set names, in the order they were added.
+pub fn load_quiz_questions(conn: &Connection) -> Result<Vec<(String, MultipleChoiceQuestion)>> {
+    let mut stmt = conn.prepare("SELECT set_name, question FROM quiz_questions ORDER BY added_at, rowid")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, from_json(1, &row.get::<_, String>(1)?)?)))?;
+    rows.collect()
+}
+
+pub fn save_quiz_review(conn: &Connection, question_id: &str, review: &Review) -> Result<()> {
+    conn.execute(
+        "INSERT INTO quiz_reviews (question_id, ease, interval_days, repetitions, due_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(question_id) DO UPDATE SET ease = excluded.ease, interval_days = excluded.interval_days,
+             repetitions = excluded.repetitions, due_at = excluded.due_at",
+        params![question_id, review.ease, review.interval_days, review.repetitions, review.due_at],
+    )?;
+    Ok(())
+}
+
+pub fn load_quiz_reviews(conn: &Connection) -> Result<Vec<(String, Review)>> {
+    let mut stmt = conn.prepare("SELECT question_id, ease, interval_days, repetitions, due_at FROM quiz_reviews")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get(0)?, Review { ease: row.get(1)?, interval_days: row.get(2)?, repetitions: row.get(3)?, due_at: row.get(4)? }))
+    })?;
+    rows.collect()
+}
+
+pub fn create_evaluation_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_evaluations (
+            session_id TEXT PRIMARY KEY,
+            model TEXT NOT NULL,
+            response_quality REAL,
+            execution_ms INTEGER NOT NULL,
+            user_satisfaction REAL,
+            outcomes TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            context TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Stores an evaluation, replacing it as its outcomes come in.
+pub fn save_agent_evaluation(conn: &Connection, evaluation: &AgentEvaluation) -> Result<()> {
+    conn.execute(
+        "INSERT INTO agent_evaluations
+            (session_id, model, response_quality, execution_ms, user_satisfaction, outcomes, created_at, context)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(session_id) DO UPDATE SET response_quality = excluded.response_quality,
+             user_satisfaction = excluded.user_satisfaction, outcomes = excluded.outcomes",
+        params![
+            evaluation.session_id,
+            evaluation.model,
+            evaluation.response_quality,
+            evaluation.execution_time as i64,
+            evaluation.user_satisfaction,
+            to_json(&evaluation.outcomes)?,
+            evaluation.timestamp as i64,
+            evaluation.context,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn load_agent_evaluations(conn: &Connection) -> Result<Vec<AgentEvaluation>> {
+    let mut stmt = conn.prepare(
+        "SELECT session_id, model, response_quality, execution_ms, user_satisfaction, outcomes, created_at, context
+         FROM agent_evaluations ORDER BY created_at",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(AgentEvaluation {
+            session_id: row.get(0)?,
+            model: row.get(1)?,
+            response_quality: row.get(2)?,
+            execution_time: row.get::<_, i64>(3)? as u64,
+            user_satisfaction: row.get(4)?,
+            outcomes: from_json(5, &row.get::<_, String>(5)?)?,
+            timestamp: row.get::<_, i64>(6)? as u64,
+            context: row.get(7)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn create_usage_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agent_usage (
+            id INTEGER PRIMARY KEY,
+            conversation TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            prompt_tokens INTEGER NOT NULL,
+            completion_tokens INTEGER NOT NULL,
+            cost REAL NOT NULL,
+            created_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX IF NOT EXISTS agent_usage_created_at ON agent_usage (created_at)", [])?;
+    Ok(())
+}
+
+pub fn save_usage(conn: &Connection, record: &UsageRecord) -> Result<()> {
+    conn.execute(
+        "INSERT INTO agent_usage (conversation, provider, model, prompt_tokens, completion_tokens, cost, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            record.conversation,
+            record.provider,
+            record.model,
+            record.prompt_tokens,
+            record.completion_tokens,
+            record.cost,
+            record.created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Usage since `since`, oldest first, each with its agent conversation's
+/// title, or the conversation key when it is not an agent conversation.
+pub fn load_usage_since(conn: &Connection, since: i64) -> Result<Vec<(String, UsageRecord)>> {
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(c.title, u.conversation), u.conversation, u.provider, u.model, u.prompt_tokens,
+                u.completion_tokens, u.cost, u.created_at
+         FROM agent_usage u LEFT JOIN agent_conversations c ON c.id = u.conversation
+         WHERE u.created_at >= ?1 ORDER BY u.created_at",
+    )?;
+    let rows = stmt.query_map(params![since], |row| {
+        Ok((
+            row.get(0)?,
+            UsageRecord {
+                conversation: row.get(1)?,
+                provider: row.get(2)?,
+                model: row.get(3)?,
+                prompt_tokens: row.get(4)?,
+                completion_tokens: row.get(5)?,
+                cost: row.get(6)?,
+                created_at: row.get(7)?,
+            },
+        ))
+    })?;
+    rows.collect()
+}
+
+/// Estimated agent spend since `since`, in US dollars.
+pub fn usage_cost_since(conn: &Connection, since: i64) -> Result<f64> {
+    conn.query_row("SELECT COALESCE(SUM(cost), 0.0) FROM agent_usage WHERE created_at >= ?1", params![since], |row| row.get(0))
+}
+
+pub fn create_command_help_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS command_help (
+            command TEXT PRIMARY KEY,
+            help TEXT,
+            fetched_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Caches the parsed help for `command`, or that it has none.
+pub fn save_command_help(conn: &Connection, command: &str, help: Option<&CommandHelp>, fetched_at: i64) -> Result<()> {
+    let help = help.map(to_json).transpose()?;
+    conn.execute(
+        "INSERT INTO command_help (command, help, fetched_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(command) DO UPDATE SET help = excluded.help, fetched_at = excluded.fetched_at",
+        params![command, help, fetched_at],
+    )?;
+    Ok(())
+}
+
+/// The cached help for `command` if it was read since `since`: `Some(None)`
+/// when it had none.
+pub fn load_command_help(conn: &Connection, command: &str, since: i64) -> Result<Option<Option<CommandHelp>>> {
+    conn.query_row(
+        "SELECT help FROM command_help WHERE command = ?1 AND fetched_at >= ?2",
+        params![command, since],
+        |row| row.get::<_, Option<String>>(0)?.map(|help| from_json(0, &help)).transpose(),
+    )
+    .optional()
+}
+
+/// Writes everything in the write-ahead log back to the database file, so
+/// nothing is left to recover on the next start.
+pub fn flush(conn: &Connection) -> Result<()> {
+    conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); PRAGMA optimize;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_connection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_agent_tables(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_agent_conversation_round_trip() {
+        let mut conn = memory_connection();
+        let message = AgentMessageRecord {
+            prompt: "list all docker containers".to_string(),
+            response: AgentResponse::SuggestCommand {
+                explanation: "Use docker ps".to_string(),
+                command: "docker ps -a".to_string(),
+            },
+            tool_calls: vec!["docker ps -a".to_string()],
+            attached_blocks: vec!["$ docker ps\nCONTAINER ID".to_string()],
+            created_at: 100,
+        };
+        append_agent_message(&mut conn, "c1", "Docker", &ModelId::Gpt4o, &message).unwrap();
+        append_agent_message(&mut conn, "c2", "Git", &ModelId::Auto, &AgentMessageRecord { created_at: 200, ..message.clone() }).unwrap();
+
+        let all = search_agent_conversations(&mut conn, "").unwrap();
+        assert_eq!(all.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec!["c2", "c1"]);
+        assert_eq!(all[1].model, ModelId::Gpt4o);
+        assert_eq!(all[1].message_count, 1);
+
+        assert_eq!(search_agent_conversations(&mut conn, "Git").unwrap().len(), 1);
+        assert_eq!(search_agent_conversations(&mut conn, "containers").unwrap().len(), 2);
+        assert!(search_agent_conversations(&mut conn, "100%").unwrap().is_empty());
+        assert!(search_agent_conversations(&mut conn, "G_t").unwrap().is_empty());
+        assert_eq!(get_agent_conversation(&mut conn, "c1").unwrap().map(|c| c.title), Some("Docker".to_string()));
+        assert_eq!(load_agent_messages(&mut conn, "c1").unwrap(), vec![message]);
+
+        delete_agent_conversation(&mut conn, "c1").unwrap();
+        assert!(get_agent_conversation(&mut conn, "c1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_top_directory_commands() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_directory_command_tables(&conn).unwrap();
+        for (command, run_at) in [("cargo test", 1), ("cargo build", 2), ("cargo test", 3), ("git status", 4), ("ls", 5), ("  ", 6)] {
+            record_directory_command(&conn, "/src/app", command, run_at).unwrap();
+        }
+        record_directory_command(&conn, "/tmp", "rm -rf *", 7).unwrap();
+        assert_eq!(top_directory_commands(&conn, "/src/app", 3).unwrap(), vec!["cargo test", "ls", "git status"]);
+        assert!(top_directory_commands(&conn, "/home", 3).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! Directory History
+//!
+//! This module keeps a fish-style, per-pane history of working directories
+//! that can be walked back and forward like browser history. The pane learns
+//! its shell's directory from OSC 7 reports; moving through the history asks
+//! the pane to `cd` to the chosen entry, and the following report confirms
+//! the move without truncating the forward entries.
+
+use mlua::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How many directories each pane remembers.
+const MAX_ENTRIES: usize = 100;
+
+/// Makes bash report its directory with OSC 7 before each prompt; fish and
+/// most zsh setups already do.
+pub const BASH_OSC7_PROMPT_COMMAND: &str = r#"printf '\e]7;file://%s%s\a' "$HOSTNAME" "$PWD""#;
+
+#[derive(Debug, Clone, Default)]
+pub struct DirHistory {
+    entries: Vec<PathBuf>,
+    position: usize,
+    /// A directory the user navigated to that the shell has not reached yet.
+    pending: Option<PathBuf>,
+}
+
+impl DirHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that the shell is now in `dir`. Arriving somewhere new drops
+    /// any forward entries, as following a link does in a browser.
+    pub fn visit(&mut self, dir: &Path) {
+        if self.pending.as_deref() == Some(dir) {
+            self.pending = None;
+        }
+        if self.current() == Some(dir) {
+            return;
+        }
+        self.entries.truncate(self.position + 1);
+        self.entries.push(dir.to_path_buf());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.position = self.entries.len() - 1;
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.entries.get(self.position).map(PathBuf::as_path)
+    }
+
+    /// All remembered directories, oldest first.
+    pub fn entries(&self) -> &[PathBuf] {
+        &self.entries
+    }
+
+    /// The index of the current directory in `entries`.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.position + 1 < self.entries.len()
+    }
+
+    /// Moves to the previous directory and returns it.
+    pub fn back(&mut self) -> Option<PathBuf> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.jump(self.position - 1)
+    }
+
+    /// Moves to the next directory and returns it.
+    pub fn forward(&mut self) -> Option<PathBuf> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.jump(self.position + 1)
+    }
+
+    /// Moves to the entry at `index` without changing the entries.
+    pub fn jump(&mut self, index: usize) -> Option<PathBuf> {
+        let dir = self.entries.get(index)?.clone();
+        self.position = index;
+        self.pending = Some(dir.clone());
+        Some(dir)
+    }
+
+    /// Takes the directory the pane should `cd` to, if navigation happened.
+    pub fn take_pending(&mut self) -> Option<PathBuf> {
+        self.pending.take()
+    }
+}
+
+/// The shell command that moves to `dir`, quoted for POSIX shells.
+pub fn cd_command(dir: &Path) -> String {
+    format!("cd -- '{}'", dir.display().to_string().replace('\'', r"'\''"))
+}
+
+/// Exposes a pane's history to Lua as the global `dirs` table:
+/// `dirs.list()`, `dirs.current()`, `dirs.position()` (1-based),
+/// `dirs.back()`, `dirs.forward()` and `dirs.jump(index)`. Navigation
+/// functions return the new directory, or nil if there is none.
+pub fn register_lua(lua: &Lua, history: Arc<Mutex<DirHistory>>) -> LuaResult<()> {
+    let to_string = |dir: Option<PathBuf>| dir.map(|dir| dir.display().to_string());
+    let dirs = lua.create_table()?;
+
+    let h = history.clone();
+    dirs.set(
+        "list",
+        lua.create_function(move |_, ()| {
+            Ok(h.lock().unwrap().entries().iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>())
+        })?,
+    )?;
+    let h = history.clone();
+    dirs.set(
+        "current",
+        lua.create_function(move |_, ()| Ok(to_string(h.lock().unwrap().current().map(Path::to_path_buf))))?,
+    )?;
+    let h = history.clone();
+    dirs.set("position", lua.create_function(move |_, ()| Ok(h.lock().unwrap().position() + 1))?)?;
+    let h = history.clone();
+    dirs.set("back", lua.create_function(move |_, ()| Ok(to_string(h.lock().unwrap().back())))?)?;
+    let h = history.clone();
+    dirs.set("forward", lua.create_function(move |_, ()| Ok(to_string(h.lock().unwrap().forward())))?)?;
+    let h = history;
+    dirs.set(
+        "jump",
+        lua.create_function(move |_, index: usize| {
+            Ok(to_string(index.checked_sub(1).and_then(|index| h.lock().unwrap().jump(index))))
+        })?,
+    )?;
+
+    lua.globals().set("dirs", dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_and_forward_like_a_browser() {
+        let mut history = DirHistory::new();
+        for dir in ["/home/me", "/home/me/src", "/tmp"] {
+            history.visit(Path::new(dir));
+        }
+        assert_eq!(history.back(), Some(PathBuf::from("/home/me/src")));
+        history.visit(Path::new("/home/me/src"));
+        assert_eq!(history.back(), Some(PathBuf::from("/home/me")));
+        assert_eq!(history.take_pending(), Some(PathBuf::from("/home/me")));
+        history.visit(Path::new("/home/me"));
+        assert_eq!(history.forward(), Some(PathBuf::from("/home/me/src")));
+        history.visit(Path::new("/home/me/src"));
+        assert_eq!(history.entries().len(), 3);
+
+        // Going somewhere new drops the forward entry.
+        history.visit(Path::new("/var/log"));
+        assert_eq!(history.entries(), &[PathBuf::from("/home/me"), PathBuf::from("/home/me/src"), PathBuf::from("/var/log")]);
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn test_lua_navigation() {
+        let history = Arc::new(Mutex::new(DirHistory::new()));
+        history.lock().unwrap().visit(Path::new("/a"));
+        history.lock().unwrap().visit(Path::new("/b"));
+        let lua = crate::scripting::sandboxed().unwrap();
+        register_lua(&lua, history.clone()).unwrap();
+
+        let (back, count): (String, usize) = lua.load("return dirs.back(), #dirs.list()").eval().unwrap();
+        assert_eq!((back.as_str(), count), ("/a", 2));
+        assert_eq!(history.lock().unwrap().take_pending(), Some(PathBuf::from("/a")));
+        let missing: Option<String> = lua.load("return dirs.jump(5)").eval().unwrap();
+        assert_eq!(missing, None);
+        assert_eq!(cd_command(Path::new("/it's")), r"cd -- '/it'\''s'");
+    }
+}
@@ -0,0 +1,342 @@
+//! Doctor
+//!
+//! `warpish doctor` checks that the environment Warpish runs in is healthy:
+//! the configured shell and its integration, fonts, a GPU adapter, the config
+//! file, the history database and the AI provider. Each failed check says how
+//! to fix it. The same report is available from the palette, where it runs in
+//! the background and lands in a block.
+
+use crate::agent::scripted::ScriptedAgent;
+use crate::config::{load_config, Config};
+use crate::db::{missing_tables, DB_PATH};
+use crate::focus::FailureFocus;
+use crate::idle::IdleMonitor;
+use crate::ollama::{ModelRole, OllamaClient};
+use crate::pty::encoding::PaneEncoding;
+use std::path::Path;
+use std::time::Duration;
+
+/// How a check came out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Works, but not as well as it could.
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "[ok]  ",
+            CheckStatus::Warn => "[warn]",
+            CheckStatus::Fail => "[fail]",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or failure.
+    pub fix: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Pass, detail: detail.into(), fix: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Warn, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Fail, detail: detail.into(), fix: Some(fix.into()) }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DoctorReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    /// Whether nothing failed; warnings are allowed.
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.status != CheckStatus::Fail)
+    }
+
+    /// The report as printed by `warpish doctor`.
+    pub fn render(&self) -> String {
+        let name_width = self.checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+        let mut text = String::new();
+        for check in &self.checks {
+            text.push_str(&format!("{} {:<name_width$}  {}\n", check.status.label(), check.name, check.detail));
+            if let Some(fix) = &check.fix {
+                text.push_str(&format!("       {:<name_width$}  fix: {}\n", "", fix));
+            }
+        }
+        let count = |status| self.checks.iter().filter(|check| check.status == status).count();
+        text.push_str(&format!(
+            "\n{} passed, {} warning(s), {} failed\n",
+            count(CheckStatus::Pass),
+            count(CheckStatus::Warn),
+            count(CheckStatus::Fail)
+        ));
+        text
+    }
+}
+
+/// Runs every check. The config is loaded first; later checks use the
+/// defaults when it cannot be.
+pub fn run() -> DoctorReport {
+    let (config_check, config) = check_config();
+    let config = config.unwrap_or_default();
+    DoctorReport {
+        checks: vec![
+            config_check,
+            check_shell(&config),
+            check_fonts(&config),
+            check_gpu(),
+            check_database(Path::new(DB_PATH)),
+            check_ai(&config),
+        ],
+    }
+}
+
+fn check_config() -> (CheckResult, Option<Config>) {
+    const NAME: &str = "Config";
+    let location = crate::config::layers::user_config_file()
+        .map_or("the config file".to_string(), |path| path.display().to_string());
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            let fix = format!("Correct {}, or move it aside to start from the defaults", location);
+            return (CheckResult::fail(NAME, e.to_string(), fix), None);
+        }
+    };
+    let mut problems = Vec::new();
+    for label in [&config.encoding.input, &config.encoding.output] {
+        if PaneEncoding::from_label(label).is_none() {
+            problems.push(format!("unknown encoding '{}'", label));
+        }
+    }
+    if let Err(e) = IdleMonitor::new(&config.idle_lock, std::time::Instant::now()) {
+        problems.push(e.to_string());
+    }
+    if let Err(e) = FailureFocus::new(&config.failure_focus) {
+        problems.push(e.to_string());
+    }
+    let check = if problems.is_empty() {
+        CheckResult::pass(NAME, format!("loaded ({} value(s) set)", config.sources().overridden().count()))
+    } else {
+        CheckResult::warn(NAME, problems.join("; "), format!("Correct these in {}; until then the defaults are used", location))
+    };
+    (check, Some(config))
+}
+
+fn check_shell(config: &Config) -> CheckResult {
+    const NAME: &str = "Shell integration";
+    let shell = config.shell();
+    let path = match which::which(&shell) {
+        Ok(path) => path,
+        Err(_) => return CheckResult::fail(NAME, format!("{} not found", shell), "Install it, or set user.shell to a shell on your PATH"),
+    };
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    if name != "bash" {
+        return CheckResult::warn(
+            NAME,
+            format!("{} does not report exit codes or directory changes", name),
+            "Set user.shell = \"bash\" for failure jumps, hooks and directory history",
+        );
+    }
+    let rc = dirs::home_dir().map(|home| home.join(".bashrc"));
+    let rc_text = rc.as_ref().and_then(|rc| std::fs::read_to_string(rc).ok()).unwrap_or_default();
+    match overwritten_prompt_command(&rc_text) {
+        Some(line) => CheckResult::warn(
+            NAME,
+            format!("~/.bashrc line {} replaces PROMPT_COMMAND, which Warpish sets", line),
+            "Append instead, e.g. PROMPT_COMMAND=\"your_command; $PROMPT_COMMAND\"",
+        ),
+        None => CheckResult::pass(NAME, format!("bash at {}", path.display())),
+    }
+}
+
+/// The 1-based line of `rc` that assigns `PROMPT_COMMAND` without keeping
+/// its previous value, if any.
+fn overwritten_prompt_command(rc: &str) -> Option<usize> {
+    rc.lines()
+        .position(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+            line.strip_prefix("PROMPT_COMMAND=").is_some_and(|value| !value.contains("PROMPT_COMMAND"))
+        })
+        .map(|idx| idx + 1)
+}
+
+fn check_fonts(config: &Config) -> CheckResult {
+    const NAME: &str = "Fonts";
+    let family = &config.appearance.font_family;
+    let font_system = cosmic_text::FontSystem::new();
+    let faces: Vec<_> = font_system.db().faces().collect();
+    if faces.iter().any(|face| face.families.iter().any(|(name, _)| name.eq_ignore_ascii_case(family))) {
+        return CheckResult::pass(NAME, format!("{} is installed", family));
+    }
+    let mut monospace: Vec<&str> = faces
+        .iter()
+        .filter(|face| face.monospaced)
+        .filter_map(|face| face.families.first().map(|(name, _)| name.as_str()))
+        .collect();
+    monospace.sort_unstable();
+    monospace.dedup();
+    let fix = match monospace.len() {
+        0 => format!("Install {}, or any monospace font", family),
+        _ => format!("Install {}, or set appearance.font_family to one of: {}", family, monospace.iter().take(5).cloned().collect::<Vec<_>>().join(", ")),
+    };
+    CheckResult::warn(NAME, format!("{} not found; falling back to the bundled JetBrains Mono", family), fix)
+}
+
+fn check_gpu() -> CheckResult {
+    const NAME: &str = "GPU";
+    let instance = wgpu::Instance::default();
+    let Some(adapter) = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())) else {
+        return CheckResult::fail(
+            NAME,
+            "no graphics adapter found",
+            "Update your graphics drivers; on Linux install Vulkan or Mesa drivers (e.g. mesa-vulkan-drivers)",
+        );
+    };
+    let info = adapter.get_info();
+    if info.device_type == wgpu::DeviceType::Cpu {
+        return CheckResult::warn(
+            NAME,
+            format!("{} renders in software", info.name),
+            "Install drivers for your GPU, or set WGPU_BACKEND to another backend",
+        );
+    }
+    CheckResult::pass(NAME, format!("{} ({:?})", info.name, info.backend))
+}
+
+fn check_database(path: &Path) -> CheckResult {
+    const NAME: &str = "Database";
+    if !path.exists() {
+        return CheckResult::pass(NAME, format!("{} is created on first start", path.display()));
+    }
+    let fix_corrupt = format!("Move {} aside; a new one is created on the next start", path.display());
+    let conn = match rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(e) => return CheckResult::fail(NAME, format!("cannot open {}: {}", path.display(), e), fix_corrupt),
+    };
+    let integrity = conn.query_row("PRAGMA quick_check", [], |row| row.get::<_, String>(0));
+    match integrity {
+        Ok(result) if result == "ok" => {}
+        Ok(result) => return CheckResult::fail(NAME, format!("{} is damaged: {}", path.display(), result), fix_corrupt),
+        Err(e) => return CheckResult::fail(NAME, format!("cannot read {}: {}", path.display(), e), fix_corrupt),
+    }
+    match missing_tables(&conn) {
+        Ok(missing) if missing.is_empty() => CheckResult::pass(NAME, format!("{} is up to date", path.display())),
+        Ok(missing) => CheckResult::warn(
+            NAME,
+            format!("{} lacks {}", path.display(), missing.join(", ")),
+            "Start Warpish once from this directory; missing tables are created at startup",
+        ),
+        Err(e) => CheckResult::fail(NAME, format!("cannot read {}: {}", path.display(), e), fix_corrupt),
+    }
+}
+
+fn check_ai(config: &Config) -> CheckResult {
+    const NAME: &str = "AI provider";
+    let script = std::env::var_os(crate::agent::AGENT_SCRIPT_ENV)
+        .map(std::path::PathBuf::from)
+        .or_else(|| config.ai.agent_script.clone());
+    if let Some(script) = script {
+        return match ScriptedAgent::load(&script) {
+            Ok(_) => CheckResult::pass(NAME, format!("replaying {}", script.display())),
+            Err(e) => CheckResult::fail(NAME, e.to_string(), "Fix the script, or unset ai.agent_script and WARPISH_AGENT_SCRIPT"),
+        };
+    }
+    let client = OllamaClient::new(&config.ai);
+    let timeout = Duration::from_secs(config.ai.ai_timeout_seconds.max(1));
+    let listing = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+        .and_then(|runtime| {
+            runtime.block_on(async {
+                match tokio::time::timeout(timeout, client.list_models()).await {
+                    Ok(result) => result.map_err(|e| e.to_string()),
+                    Err(_) => Err(format!("no answer within {}s", timeout.as_secs())),
+                }
+            })
+        });
+    let models = match listing {
+        Ok(models) => models,
+        Err(e) => {
+            return CheckResult::fail(
+                NAME,
+                format!("Ollama at {} is not reachable: {}", crate::ollama::base_url(&config.ai.ollama_url), e),
+                "Start it with `ollama serve`, or point ai.ollama_url at your server",
+            )
+        }
+    };
+    let mut wanted: Vec<&str> = ModelRole::ALL.iter().map(|role| config.ai.model_for(*role)).collect();
+    wanted.sort_unstable();
+    wanted.dedup();
+    // `codellama` is installed as `codellama:latest`.
+    let installed = |name: &str| models.iter().any(|model| model.name == name || model.name == format!("{}:latest", name));
+    let missing: Vec<&str> = wanted.into_iter().filter(|name| !installed(name)).collect();
+    match missing.as_slice() {
+        [] => CheckResult::pass(NAME, format!("Ollama is up with {} model(s)", models.len())),
+        missing => CheckResult::warn(
+            NAME,
+            format!("configured model(s) not installed: {}", missing.join(", ")),
+            format!("Run `ollama pull {}`, or pick installed models in Local Models", missing[0]),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lists_fixes() {
+        let report = DoctorReport {
+            checks: vec![
+                CheckResult::pass("GPU", "Apple M2 (Metal)"),
+                CheckResult::fail("AI provider", "Ollama is not reachable", "Start it with `ollama serve`"),
+            ],
+        };
+        assert!(!report.healthy());
+        assert_eq!(
+            report.render(),
+            "[ok]   GPU          Apple M2 (Metal)\n\
+             [fail] AI provider  Ollama is not reachable\n\
+             \x20                   fix: Start it with `ollama serve`\n\
+             \n1 passed, 0 warning(s), 1 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_finds_prompt_command_overwrites_and_old_databases() {
+        let rc = "# PROMPT_COMMAND=ignored\nPROMPT_COMMAND+='history -a'\nexport PROMPT_COMMAND=\"history -a; $PROMPT_COMMAND\"\n";
+        assert_eq!(overwritten_prompt_command(rc), None);
+        assert_eq!(overwritten_prompt_command(&format!("{}export PROMPT_COMMAND='history -a'\n", rc)), Some(4));
+
+        let dir = std::env::temp_dir().join(format!("warpish-doctor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        crate::db::create_agent_tables(&conn).unwrap();
+        drop(conn);
+        let check = check_database(&path);
+        let fresh = check_database(&dir.join("new.db"));
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(check.status, CheckStatus::Warn);
+        assert!(check.detail.contains("commands") && !check.detail.contains("agent_messages"));
+        assert_eq!(fresh.status, CheckStatus::Pass);
+    }
+}
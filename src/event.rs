@@ -4,6 +4,9 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use uuid::Uuid;
 use crate::agent::client::AgentResponse;
+use crate::completions_ui::SuggestionResults;
+use crate::lpc::protocol::Call;
+use crate::webhooks::WebhookEvent;
 
 /// Application events that drive state changes.
 #[derive(Debug)]
@@ -20,10 +23,13 @@ pub enum AppEvent {
     ToggleSettings, // New event for settings hotkey
     ToggleAgentMode, // New event
     ToggleFollowUp, // New event
-    AgentCompleted { pane_id: Uuid, response: AgentResponse },
+    AgentCompleted { pane_id: Uuid, prompt: String, response: AgentResponse },
+    Suggestions(SuggestionResults), // Completions from the suggestion service
     CodebaseUpdate, // New event for codebase status update
     ShellExit,
     Error(String), // New event for handling errors from async tasks
+    Webhook(WebhookEvent),
+    Lpc(Call),
 }
 
 /// An asynchronous event handler.
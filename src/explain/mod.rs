@@ -0,0 +1,654 @@
+//! Command Explanations
+//!
+//! Breaks a command line into the parts the shell grammar finds — commands,
+//! flags, arguments, redirects and operators — and says what each one does,
+//! as explainshell does. Commands and flags are described from man pages,
+//! or from `--help` when a command has no man page. Both are parsed once on
+//! a background thread and kept in the database, so a command's help is
+//! read again only when the cached copy is a month old. Parts neither
+//! explains can be handed to the agent.
+
+use crate::agent::client::AgentProvider;
+use crate::agent::model::ModelId;
+use crate::agent::translate::response_text;
+use crate::db;
+use crate::syntax_parser::{CommandPart, CommandPartKind};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a cached help page is trusted, as tools get upgraded.
+const HELP_MAX_AGE_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// How long `--help` may run before it is killed.
+const HELP_TIMEOUT: Duration = Duration::from_secs(2);
+
+const SHELL_BUILTINS: &[(&str, &str)] = &[
+    ("cd", "Changes the working directory"),
+    ("export", "Sets environment variables for this shell and the commands it runs"),
+    ("source", "Runs a script in the current shell"),
+    (".", "Runs a script in the current shell"),
+    ("alias", "Defines or lists aliases"),
+    ("unset", "Removes variables or functions"),
+    ("exit", "Exits the shell"),
+    ("eval", "Runs its arguments as a shell command"),
+    ("exec", "Replaces the shell with a command"),
+    ("set", "Sets shell options or positional parameters"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("|", "Pipes the output of the command before into the input of the one after"),
+    ("|&", "Pipes both the output and errors of the command before into the one after"),
+    ("&&", "Runs the next command only if the one before succeeds"),
+    ("||", "Runs the next command only if the one before fails"),
+    (";", "Runs the next command after the one before, whether or not it succeeds"),
+    ("&", "Runs the command before in the background"),
+];
+
+/// A flag from a help page, with its spellings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HelpOption {
+    /// Such as `-a` and `--all`.
+    pub flags: Vec<String>,
+    pub description: String,
+}
+
+/// What a man page or `--help` says about a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandHelp {
+    pub summary: Option<String>,
+    pub options: Vec<HelpOption>,
+    /// Subcommands listed with a description, such as cargo's.
+    pub subcommands: Vec<(String, String)>,
+}
+
+impl CommandHelp {
+    /// Parses a man page rendered as plain text, or `--help` output.
+    pub fn parse(text: &str) -> Self {
+        let text = strip_overstrike(text);
+        let lines: Vec<&str> = text.lines().collect();
+        let mut help = CommandHelp { summary: summary(&lines), ..Default::default() };
+        // Subcommands are only looked for under a heading that mentions
+        // commands, until the next `Heading:` or man page SECTION.
+        let mut in_commands = false;
+        let mut i = 0;
+        while i < lines.len() {
+            let line = lines[i];
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            if indent == 0 && !trimmed.is_empty() {
+                let is_section = trimmed.ends_with(':') || trimmed.chars().all(|c| !c.is_lowercase());
+                if trimmed.to_lowercase().contains("command") {
+                    in_commands = true;
+                } else if is_section {
+                    in_commands = false;
+                }
+            }
+            if trimmed.starts_with('-') {
+                let (spec, description) = split_columns(trimmed);
+                let mut description = description.map(str::to_string).unwrap_or_default();
+                // Man pages put the description on the lines below, indented further.
+                while i + 1 < lines.len()
+                    && indent_of(lines[i + 1]).is_some_and(|next| next > indent)
+                    && !lines[i + 1].trim_start().starts_with('-')
+                {
+                    i += 1;
+                    description.push(' ');
+                    description.push_str(lines[i].trim());
+                }
+                let flags = flags(spec);
+                let description = collapse(&description);
+                if !flags.is_empty() && !description.is_empty() {
+                    help.options.push(HelpOption { flags, description });
+                }
+            } else if indent > 0 && in_commands {
+                // A subcommand, perhaps with aliases, as in `build, b    Compile the package`.
+                if let (spec, Some(description)) = split_columns(trimmed) {
+                    let mut names = spec.split(',').map(str::trim);
+                    let name = names.next().unwrap_or_default();
+                    if is_subcommand_word(name)
+                        && names.all(is_subcommand_word)
+                        && !help.subcommands.iter().any(|(known, _)| known == name)
+                    {
+                        help.subcommands.push((name.to_string(), collapse(description)));
+                    }
+                }
+            }
+            i += 1;
+        }
+        help
+    }
+
+    /// The description of `flag`, such as `-a` or `--color`.
+    pub fn option(&self, flag: &str) -> Option<&str> {
+        self.options.iter().find(|option| option.flags.iter().any(|known| known == flag)).map(|option| option.description.as_str())
+    }
+
+    pub fn subcommand(&self, name: &str) -> Option<&str> {
+        self.subcommands.iter().find(|(known, _)| known == name).map(|(_, description)| description.as_str())
+    }
+}
+
+/// Removes the backspace overstrikes man uses for bold and underline.
+fn strip_overstrike(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\u{8}' {
+            plain.pop();
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn indent_of(line: &str) -> Option<usize> {
+    let trimmed = line.trim_start();
+    (!trimmed.is_empty()).then(|| line.len() - trimmed.len())
+}
+
+/// Splits `text` at its first run of two or more spaces or a tab, as help
+/// pages separate a flag or subcommand from its description.
+fn split_columns(text: &str) -> (&str, Option<&str>) {
+    let gap = text.find("  ").into_iter().chain(text.find('\t')).min();
+    match gap {
+        Some(gap) => {
+            let description = text[gap..].trim();
+            (text[..gap].trim(), (!description.is_empty()).then_some(description))
+        }
+        None => (text.trim(), None),
+    }
+}
+
+/// The flags in a spec such as `-c, --color[=WHEN]` or `--jobs <N>`.
+fn flags(spec: &str) -> Vec<String> {
+    spec.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| word.starts_with('-') && word.len() > 1)
+        .map(|word| word.split(['=', '[', '<']).next().unwrap_or(word).to_string())
+        .filter(|flag| flag.trim_start_matches('-').chars().next().is_some_and(|c| c.is_alphanumeric()))
+        .collect()
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The man page's NAME line after its dash, or the first sentence of the
+/// paragraph after the usage lines of `--help` output.
+fn summary(lines: &[&str]) -> Option<String> {
+    if let Some(name) = lines.iter().position(|line| line.trim() == "NAME") {
+        let line = lines[name + 1..].iter().map(|line| line.trim()).find(|line| !line.is_empty())?;
+        let (_, description) = line.split_once(" - ").or_else(|| line.split_once(" \\- "))?;
+        return Some(collapse(description));
+    }
+    let mut lines = lines.iter().skip_while(|line| line.trim().is_empty()).peekable();
+    if lines.peek().is_some_and(|line| line.trim_start().to_lowercase().starts_with("usage")) {
+        lines.next();
+        // The usage may go on over indented lines.
+        while lines.next_if(|line| indent_of(line).is_some_and(|indent| indent > 0)).is_some() {}
+    }
+    let paragraph: Vec<&str> =
+        lines.skip_while(|line| line.trim().is_empty()).take_while(|line| !line.trim().is_empty()).map(|line| line.trim()).collect();
+    let paragraph = collapse(&paragraph.join(" "));
+    if paragraph.is_empty() || paragraph.ends_with(':') || paragraph.starts_with('-') {
+        return None;
+    }
+    let sentence = paragraph.find(". ").map_or(paragraph.as_str(), |end| &paragraph[..=end]);
+    Some(sentence.split_once(" - ").map_or(sentence, |(_, description)| description).to_string())
+}
+
+/// The man page for `key`, where `git commit` is read from `git-commit`,
+/// falling back to `--help` when `allow_help_flag` is set.
+pub fn fetch_help(key: &str, allow_help_flag: bool) -> Option<CommandHelp> {
+    let page = key.replace(' ', "-");
+    let man = Command::new("man")
+        .arg(&page)
+        .env("MANPAGER", "cat")
+        .env("MANWIDTH", "120")
+        .env("MANOPT", "--no-justification --no-hyphenation")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success() && !output.stdout.is_empty());
+    if let Some(output) = man {
+        return Some(CommandHelp::parse(&String::from_utf8_lossy(&output.stdout)));
+    }
+    if !allow_help_flag || key.contains('/') {
+        return None;
+    }
+    let mut words = key.split(' ');
+    let mut child = Command::new(words.next()?)
+        .args(words)
+        .arg("--help")
+        .env("PAGER", "cat")
+        .env("MANPAGER", "cat")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let (output_tx, output) = channel();
+    thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).ok();
+        output_tx.send(text).ok();
+    });
+    let text = output.recv_timeout(HELP_TIMEOUT);
+    child.kill().ok();
+    child.wait().ok();
+    text.ok().filter(|text| !text.trim().is_empty()).map(|text| CommandHelp::parse(&text))
+}
+
+/// Help pages by command, from the database or read in the background.
+/// `git commit` is the key of git's `commit` subcommand.
+pub struct HelpCache {
+    helps: HashMap<String, Option<CommandHelp>>,
+    pending: HashSet<String>,
+    result_tx: Sender<(String, Option<CommandHelp>)>,
+    results: Receiver<(String, Option<CommandHelp>)>,
+}
+
+impl Default for HelpCache {
+    fn default() -> Self {
+        let (result_tx, results) = channel();
+        Self { helps: HashMap::new(), pending: HashSet::new(), result_tx, results }
+    }
+}
+
+impl HelpCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The help for `key`, once read; `None` too when it has none.
+    pub fn get(&self, key: &str) -> Option<&CommandHelp> {
+        self.helps.get(key).and_then(Option::as_ref)
+    }
+
+    pub fn is_pending(&self, key: &str) -> bool {
+        self.pending.contains(key)
+    }
+
+    /// Starts reading the help for `key` unless it is known or cached. A
+    /// subcommand is only run with `--help` when its command lists it.
+    pub fn request(&mut self, conn: &Connection, key: &str) {
+        if self.helps.contains_key(key) || self.pending.contains(key) {
+            return;
+        }
+        let since = now() - HELP_MAX_AGE_SECS;
+        match db::load_command_help(conn, key, since) {
+            Ok(Some(help)) => {
+                self.helps.insert(key.to_string(), help);
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Failed to read the cached help for {}: {}", key, e),
+        }
+        let allow_help_flag = match key.split_once(' ') {
+            Some((command, subcommand)) => self.get(command).is_some_and(|help| help.subcommand(subcommand).is_some()),
+            None => true,
+        };
+        self.pending.insert(key.to_string());
+        let key = key.to_string();
+        let result_tx = self.result_tx.clone();
+        thread::spawn(move || {
+            let help = fetch_help(&key, allow_help_flag);
+            result_tx.send((key, help)).ok();
+        });
+    }
+
+    /// Stores the help pages read since the last poll, returning whether
+    /// there were any.
+    pub fn poll(&mut self, conn: &Connection) -> bool {
+        let mut changed = false;
+        for (key, help) in self.results.try_iter() {
+            if let Err(e) = db::save_command_help(conn, &key, help.as_ref(), now()) {
+                log::warn!("Failed to cache the help for {}: {}", key, e);
+            }
+            self.pending.remove(&key);
+            self.helps.insert(key, help);
+            changed = true;
+        }
+        changed
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Whether `word` could name a subcommand, such as `commit` or `run-script`.
+fn is_subcommand_word(word: &str) -> bool {
+    word.chars().next().is_some_and(|c| c.is_ascii_lowercase()) && word.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// The help pages explaining `parts` needs: each command's, and its first
+/// argument's as a subcommand.
+pub fn help_keys(source: &str, parts: &[CommandPart]) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        if part.kind != CommandPartKind::Name {
+            continue;
+        }
+        let name = &source[part.range.clone()];
+        if SHELL_BUILTINS.iter().any(|(builtin, _)| *builtin == name) {
+            continue;
+        }
+        keys.push(name.to_string());
+        if let Some(next) = parts.get(i + 1).filter(|next| next.kind == CommandPartKind::Argument) {
+            let word = &source[next.range.clone()];
+            if is_subcommand_word(word) {
+                keys.push(format!("{} {}", name, word));
+            }
+        }
+    }
+    keys
+}
+
+/// A part of the command line and what it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplainedPart {
+    pub text: String,
+    pub kind: CommandPartKind,
+    pub description: Option<String>,
+    /// Whether the description came from the agent.
+    pub from_agent: bool,
+    /// Whether the help that may describe it is still being read.
+    pub pending: bool,
+}
+
+/// Describes each of `parts`, from `helps` or from what the shell does,
+/// falling back to `agent`'s explanations by part text.
+pub fn explain(source: &str, parts: &[CommandPart], helps: &HelpCache, agent: &HashMap<String, String>) -> Vec<ExplainedPart> {
+    let mut command: Option<&str> = None;
+    let mut subcommand: Option<String> = None;
+    let mut explained: Vec<ExplainedPart> = Vec::new();
+    for part in parts {
+        let text = &source[part.range.clone()];
+        let mut pending = false;
+        let description = match part.kind {
+            CommandPartKind::Name => {
+                command = Some(text);
+                subcommand = None;
+                pending = helps.is_pending(text);
+                SHELL_BUILTINS
+                    .iter()
+                    .find(|(builtin, _)| *builtin == text)
+                    .map(|(_, description)| description.to_string())
+                    .or_else(|| helps.get(text).and_then(|help| help.summary.clone()))
+            }
+            CommandPartKind::Argument => {
+                let name = command.unwrap_or_default();
+                let first = explained.last().is_some_and(|last| last.kind == CommandPartKind::Name);
+                let key = format!("{} {}", name, text);
+                if first && is_subcommand_word(text) && (helps.get(&key).is_some() || helps.get(name).and_then(|help| help.subcommand(text)).is_some()) {
+                    pending = helps.is_pending(&key);
+                    let description = helps
+                        .get(&key)
+                        .and_then(|help| help.summary.clone())
+                        .or_else(|| helps.get(name).and_then(|help| help.subcommand(text)).map(str::to_string));
+                    subcommand = Some(key);
+                    description
+                } else if text.starts_with('-') {
+                    pending = helps.is_pending(name) || subcommand.as_deref().is_some_and(|key| helps.is_pending(key));
+                    let lookup = |flag: &str| {
+                        subcommand.as_deref().and_then(|key| helps.get(key)).and_then(|help| help.option(flag)).or_else(|| helps.get(name).and_then(|help| help.option(flag)))
+                    };
+                    describe_flag(text, lookup)
+                } else {
+                    None
+                }
+            }
+            CommandPartKind::Redirect => Some(describe_redirect(text)),
+            CommandPartKind::Assignment => {
+                let (variable, value) = text.split_once('=').unwrap_or((text, ""));
+                Some(format!("Sets {} to {} for the command", variable, if value.is_empty() { "nothing" } else { value }))
+            }
+            CommandPartKind::Operator => OPERATORS.iter().find(|(operator, _)| *operator == text).map(|(_, description)| description.to_string()),
+        };
+        let from_agent = description.is_none() && agent.contains_key(text);
+        let description = description.or_else(|| agent.get(text).cloned());
+        explained.push(ExplainedPart { text: text.to_string(), kind: part.kind, description, from_agent, pending: pending && !from_agent });
+    }
+    explained
+}
+
+/// Describes `--flag=value`, `-x` or combined short flags such as `-xzf`
+/// from each flag's description.
+fn describe_flag<'a>(text: &str, lookup: impl Fn(&str) -> Option<&'a str>) -> Option<String> {
+    let flag = text.split('=').next().unwrap_or(text);
+    if let Some(description) = lookup(flag) {
+        return Some(description.to_string());
+    }
+    if flag.starts_with("--") || flag.len() <= 2 {
+        return None;
+    }
+    let described: Vec<String> =
+        flag[1..].chars().map(|c| format!("-{}", c)).map(|short| lookup(&short).map(|description| format!("{}: {}", short, description))).collect::<Option<_>>()?;
+    Some(described.join("; "))
+}
+
+fn describe_redirect(text: &str) -> String {
+    let fd: String = text.chars().take_while(char::is_ascii_digit).collect();
+    let rest = text[fd.len()..].trim_start();
+    let stream = match fd.as_str() {
+        "" | "1" if !rest.starts_with('<') => "output",
+        "" | "0" => "input",
+        "2" => "errors",
+        _ => "the stream",
+    };
+    let target = |op: &str| rest[op.len()..].trim().to_string();
+    if rest.starts_with("<<<") {
+        return format!("Feeds {} to the command as input", target("<<<"));
+    }
+    if rest.starts_with("<<") {
+        return "Feeds the lines that follow, up to the end marker, to the command as input".to_string();
+    }
+    if rest.starts_with("&>>") {
+        return format!("Appends both output and errors to {}", target("&>>"));
+    }
+    if rest.starts_with("&>") {
+        return format!("Writes both output and errors to {}, replacing it", target("&>"));
+    }
+    if rest.starts_with(">&") {
+        let to = match target(">&").as_str() {
+            "1" => "output".to_string(),
+            "2" => "errors".to_string(),
+            other => other.to_string(),
+        };
+        return format!("Sends {} to the same place as {}", stream, to);
+    }
+    if rest.starts_with(">>") {
+        return format!("Appends {} to {}", stream, target(">>"));
+    }
+    if rest.starts_with('>') {
+        return format!("Writes {} to {}, replacing it", stream, target(">"));
+    }
+    if rest.starts_with('<') {
+        return format!("Reads input from {}", target("<"));
+    }
+    "Redirects the command's input or output".to_string()
+}
+
+pub fn agent_prompt(command: &str, unknown: &[&str]) -> String {
+    let parts: Vec<String> = unknown.iter().map(|part| format!("- {}", part)).collect();
+    format!(
+        "Explain what each of these parts of the shell command `{}` does, in one short sentence each. Reply with one \
+         line per part, in the form `part: explanation`, and nothing else.\n\n{}",
+        command,
+        parts.join("\n")
+    )
+}
+
+/// The agent's explanations of `unknown`, by part, from its reply.
+pub fn parse_agent_reply(reply: &str, unknown: &[&str]) -> HashMap<String, String> {
+    let mut explanations = HashMap::new();
+    for line in reply.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).unwrap_or(line);
+        for part in unknown {
+            let rest = line.strip_prefix(&format!("`{}`", part)).or_else(|| line.strip_prefix(part));
+            let Some(explanation) = rest.and_then(|rest| rest.trim_start().strip_prefix(':')) else {
+                continue;
+            };
+            if !explanation.trim().is_empty() && !explanations.contains_key(*part) {
+                explanations.insert(part.to_string(), explanation.trim().to_string());
+            }
+        }
+    }
+    explanations
+}
+
+/// Asks the agent about the parts nothing else explains, on a background
+/// thread.
+pub struct AgentExplainer {
+    agent: Arc<dyn AgentProvider>,
+    result_tx: Sender<(String, HashMap<String, String>)>,
+    results: Receiver<(String, HashMap<String, String>)>,
+}
+
+impl AgentExplainer {
+    pub fn new(agent: Arc<dyn AgentProvider>) -> Self {
+        let (result_tx, results) = channel();
+        Self { agent, result_tx, results }
+    }
+
+    pub fn ask(&self, command: &str, unknown: Vec<String>, model: ModelId) {
+        let agent = self.agent.clone();
+        let result_tx = self.result_tx.clone();
+        let command = command.to_string();
+        thread::spawn(move || {
+            let unknown: Vec<&str> = unknown.iter().map(String::as_str).collect();
+            let reply = response_text(agent.process_query(&agent_prompt(&command, &unknown), &[], &[], model));
+            result_tx.send((command, parse_agent_reply(&reply, &unknown))).ok();
+        });
+    }
+
+    /// Takes the finished answers, with the command each is about.
+    pub fn poll(&self) -> Vec<(String, HashMap<String, String>)> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LS_MAN: &str = "LS(1)                User Commands                LS(1)
+
+NAME
+       ls - list directory contents
+
+DESCRIPTION
+       -a, --all
+              do not ignore entries starting with .
+
+       -l     use a long listing format
+
+       --color[=WHEN]
+              color the output; WHEN can be 'always',
+              'auto', or 'never'
+";
+
+    const CARGO_HELP: &str = "Rust's package manager
+
+Usage: cargo [OPTIONS] [COMMAND]
+
+Options:
+  -V, --version             Print version info and exit
+  -q, --quiet               Do not print cargo log messages
+  -j, --jobs <N>            Number of parallel jobs
+
+Commands:
+    build, b    Compile the current package
+    test, t     Run the tests
+";
+
+    fn part(source: &str, text: &str, kind: CommandPartKind) -> CommandPart {
+        let start = source.find(text).unwrap();
+        CommandPart { range: start..start + text.len(), kind }
+    }
+
+    #[test]
+    fn test_parses_man_pages_and_help_output() {
+        let ls = CommandHelp::parse(&LS_MAN.replace("NAME", "N\u{8}NA\u{8}AM\u{8}ME\u{8}E"));
+        assert_eq!(ls.summary.as_deref(), Some("list directory contents"));
+        assert_eq!(ls.option("--all"), Some("do not ignore entries starting with ."));
+        assert_eq!(ls.option("-l"), Some("use a long listing format"));
+        assert_eq!(ls.option("--color"), Some("color the output; WHEN can be 'always', 'auto', or 'never'"));
+
+        let cargo = CommandHelp::parse(CARGO_HELP);
+        assert_eq!(cargo.summary.as_deref(), Some("Rust's package manager"));
+        assert_eq!(cargo.option("-j"), Some("Number of parallel jobs"));
+        assert_eq!(cargo.subcommand("test"), Some("Run the tests"));
+        assert_eq!(cargo.subcommand("build,"), None);
+    }
+
+    #[test]
+    fn test_explains_flags_subcommands_and_shell_syntax() {
+        let mut helps = HelpCache::new();
+        helps.helps.insert("ls".to_string(), Some(CommandHelp::parse(LS_MAN)));
+        helps.helps.insert("cargo".to_string(), Some(CommandHelp::parse(CARGO_HELP)));
+        helps.helps.insert("cargo test".to_string(), None);
+        helps.pending.insert("wc".to_string());
+
+        let source = "ls -la --color=auto 2>&1 | cargo test -q > out.txt && wc";
+        let parts = vec![
+            part(source, "ls", CommandPartKind::Name),
+            part(source, "-la", CommandPartKind::Argument),
+            part(source, "--color=auto", CommandPartKind::Argument),
+            part(source, "2>&1", CommandPartKind::Redirect),
+            part(source, "|", CommandPartKind::Operator),
+            part(source, "cargo", CommandPartKind::Name),
+            part(source, "test", CommandPartKind::Argument),
+            part(source, "-q", CommandPartKind::Argument),
+            part(source, "> out.txt", CommandPartKind::Redirect),
+            part(source, "&&", CommandPartKind::Operator),
+            part(source, "wc", CommandPartKind::Name),
+        ];
+        assert_eq!(help_keys(source, &parts), vec!["ls", "cargo", "cargo test", "wc"]);
+
+        let explained = explain(source, &parts, &helps, &HashMap::new());
+        let descriptions: Vec<Option<&str>> = explained.iter().map(|part| part.description.as_deref()).collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                Some("list directory contents"),
+                Some("-l: use a long listing format; -a: do not ignore entries starting with ."),
+                Some("color the output; WHEN can be 'always', 'auto', or 'never'"),
+                Some("Sends errors to the same place as output"),
+                Some("Pipes the output of the command before into the input of the one after"),
+                Some("Rust's package manager"),
+                Some("Run the tests"),
+                Some("Do not print cargo log messages"),
+                Some("Writes output to out.txt, replacing it"),
+                Some("Runs the next command only if the one before succeeds"),
+                None,
+            ]
+        );
+        assert!(explained[10].pending);
+    }
+
+    #[test]
+    fn test_agent_fills_in_unknown_parts() {
+        let unknown = ["wc", "-Z"];
+        assert!(agent_prompt("wc -Z", &unknown).contains("- -Z"));
+        let reply = "Sure!\n- `wc`: Counts lines, words and bytes\n-Z: Not a wc flag\nwc: ignored duplicate";
+        let explanations = parse_agent_reply(reply, &unknown);
+        assert_eq!(explanations.get("wc").map(String::as_str), Some("Counts lines, words and bytes"));
+        assert_eq!(explanations.get("-Z").map(String::as_str), Some("Not a wc flag"));
+
+        let source = "wc -Z";
+        let parts = vec![part(source, "wc", CommandPartKind::Name), part(source, "-Z", CommandPartKind::Argument)];
+        let explained = explain(source, &parts, &HelpCache::new(), &explanations);
+        assert!(explained.iter().all(|part| part.from_agent && part.description.is_some()));
+    }
+}
@@ -0,0 +1,151 @@
+//! Failure Focus
+//!
+//! This module decides what happens when a command fails in a pane the user
+//! is not looking at: focus can move there straight away, the user can be
+//! asked whether to jump to it, or nothing happens. Rules matched against the
+//! failed command and the pane's directory let noisy panes opt out.
+
+use crate::config::{FailureFocusAction, FailureFocusConfig, FailureFocusRule};
+use regex::Regex;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FocusError {
+    #[error("Invalid failure focus pattern '{0}': {1}")]
+    Pattern(String, regex::Error),
+}
+
+/// A command that finished in an unfocused pane.
+#[derive(Debug, Clone, Copy)]
+pub struct FinishedCommand<'a> {
+    pub command: &'a str,
+    pub directory: Option<&'a Path>,
+    pub status: i32,
+}
+
+struct CompiledRule {
+    command: Option<Regex>,
+    directory: Option<Regex>,
+    action: FailureFocusAction,
+}
+
+impl CompiledRule {
+    fn matches(&self, finished: &FinishedCommand) -> bool {
+        let command = self.command.as_ref().is_none_or(|pattern| pattern.is_match(finished.command));
+        let directory = self.directory.as_ref().is_none_or(|pattern| {
+            finished.directory.is_some_and(|dir| pattern.is_match(&dir.to_string_lossy()))
+        });
+        command && directory
+    }
+}
+
+fn compile(pattern: &Option<String>) -> Result<Option<Regex>, FocusError> {
+    pattern
+        .as_deref()
+        .map(|pattern| Regex::new(pattern).map_err(|e| FocusError::Pattern(pattern.to_string(), e)))
+        .transpose()
+}
+
+/// Picks what to do about commands failing outside the focused pane.
+pub struct FailureFocus {
+    enabled: bool,
+    action: FailureFocusAction,
+    ignored_exit_codes: Vec<i32>,
+    rules: Vec<CompiledRule>,
+}
+
+impl Default for FailureFocus {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: FailureFocusAction::Ignore,
+            ignored_exit_codes: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl FailureFocus {
+    pub fn new(config: &FailureFocusConfig) -> Result<Self, FocusError> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule: &FailureFocusRule| {
+                Ok(CompiledRule {
+                    command: compile(&rule.command)?,
+                    directory: compile(&rule.directory)?,
+                    action: rule.action,
+                })
+            })
+            .collect::<Result<Vec<_>, FocusError>>()?;
+        Ok(Self {
+            enabled: config.enabled,
+            action: config.action,
+            ignored_exit_codes: config.ignored_exit_codes.clone(),
+            rules,
+        })
+    }
+
+    /// Returns the action for `finished`; successes and ignored exit
+    /// statuses always leave focus alone.
+    pub fn decide(&self, finished: &FinishedCommand) -> FailureFocusAction {
+        if !self.enabled || finished.status == 0 || self.ignored_exit_codes.contains(&finished.status) {
+            return FailureFocusAction::Ignore;
+        }
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(finished))
+            .map_or(self.action, |rule| rule.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished<'a>(command: &'a str, directory: &'a str, status: i32) -> FinishedCommand<'a> {
+        FinishedCommand { command, directory: Some(Path::new(directory)), status }
+    }
+
+    #[test]
+    fn test_rules_pick_action_for_failures() {
+        let config = FailureFocusConfig {
+            enabled: true,
+            action: FailureFocusAction::Prompt,
+            rules: vec![
+                FailureFocusRule {
+                    command: Some(r"^cargo test".to_string()),
+                    directory: None,
+                    action: FailureFocusAction::Switch,
+                },
+                FailureFocusRule {
+                    command: None,
+                    directory: Some(r"/scratch(/|$)".to_string()),
+                    action: FailureFocusAction::Ignore,
+                },
+            ],
+            ..FailureFocusConfig::default()
+        };
+        let focus = FailureFocus::new(&config).unwrap();
+
+        assert_eq!(focus.decide(&finished("cargo test", "/src/app", 101)), FailureFocusAction::Switch);
+        assert_eq!(focus.decide(&finished("make", "/src/app", 2)), FailureFocusAction::Prompt);
+        assert_eq!(focus.decide(&finished("make", "/tmp/scratch", 2)), FailureFocusAction::Ignore);
+        // Successes and Ctrl+C never move focus.
+        assert_eq!(focus.decide(&finished("cargo test", "/src/app", 0)), FailureFocusAction::Ignore);
+        assert_eq!(focus.decide(&finished("cargo test", "/src/app", 130)), FailureFocusAction::Ignore);
+    }
+
+    #[test]
+    fn test_default_rules_skip_search_commands() {
+        let config = FailureFocusConfig { enabled: true, ..FailureFocusConfig::default() };
+        let focus = FailureFocus::new(&config).unwrap();
+
+        assert_eq!(focus.decide(&finished("grep TODO src", "/src", 1)), FailureFocusAction::Ignore);
+        assert_eq!(focus.decide(&finished("[ -f Cargo.lock ]", "/src", 1)), FailureFocusAction::Ignore);
+        assert_eq!(focus.decide(&finished("greppy", "/src", 1)), FailureFocusAction::Prompt);
+        assert!(FailureFocus::new(&FailureFocusConfig::default()).unwrap().decide(&finished("make", "/", 1))
+            == FailureFocusAction::Ignore);
+    }
+}
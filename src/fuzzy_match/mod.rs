@@ -1 +1,329 @@
-//! fuzzy_match module
+//! Fuzzy Matching
+//!
+//! Scores how well a query matches a candidate as a subsequence, for the
+//! search overlay, the palette and completions. Each query character is
+//! matched to a character of the candidate and the alignment with the
+//! best score wins: a match scores more at the start of a word, a path
+//! segment or a camelCase hump, more again when it follows on from the
+//! previous match, and skipped characters cost a little. The positions of
+//! that alignment come back for highlighting. Queries are smart-case: one
+//! without capitals matches either case.
+//!
+//! Lists such as history or the files of a repository can run to tens of
+//! thousands of candidates, so `match_all` splits them across rayon's
+//! threads and `spawn_match_all` does so off the calling thread.
+
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+/// After a `/` or `\`: the start of a path segment.
+const BONUS_PATH: i64 = 10;
+/// At the start, or after whitespace or punctuation such as `-` or `.`.
+const BONUS_BOUNDARY: i64 = 8;
+/// A capital after a lowercase letter, or a digit after a letter.
+const BONUS_CAMEL: i64 = 7;
+/// A match directly after the previous one.
+const BONUS_CONSECUTIVE: i64 = 4;
+/// A match in the last path segment, so `main` prefers `src/main.rs` to
+/// `main/src/lib.rs`.
+const BONUS_FILENAME: i64 = 2;
+/// The first query character's bonus counts this many times.
+const FIRST_CHAR_MULTIPLIER: i64 = 2;
+/// Candidates per unit of work when matching in parallel.
+const CHUNK_SIZE: usize = 1024;
+
+/// Below any real score; unreachable cells of the alignment table.
+const UNMATCHED: i64 = i64::MIN / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Byte offsets in the candidate of the characters matched, ascending.
+    pub positions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Separator,
+    PathSeparator,
+    Lower,
+    Upper,
+    Digit,
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        '/' | '\\' => CharClass::PathSeparator,
+        c if c.is_uppercase() => CharClass::Upper,
+        c if c.is_numeric() => CharClass::Digit,
+        c if c.is_alphabetic() => CharClass::Lower,
+        _ => CharClass::Separator,
+    }
+}
+
+/// The bonus for matching a character of class `current` after one of
+/// class `previous`, or at the start.
+fn bonus(previous: Option<CharClass>, current: CharClass) -> i64 {
+    use CharClass::*;
+    match (previous, current) {
+        (_, Separator | PathSeparator) => 0,
+        (None, _) | (Some(Separator), _) => BONUS_BOUNDARY,
+        (Some(PathSeparator), _) => BONUS_PATH,
+        (Some(Lower), Upper) | (Some(Lower | Upper), Digit) => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// The best alignment of `query` in `choice`, or `None` when `choice`
+/// doesn't contain its characters in order. An empty query matches
+/// everything with a score of 0.
+pub fn fuzzy_match(choice: &str, query: &str) -> Option<FuzzyMatch> {
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    let respect_case = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if respect_case { c } else { fold_case(c) };
+    let query: Vec<char> = query.chars().map(fold).collect();
+    let chars: Vec<(usize, char)> = choice.char_indices().collect();
+
+    // Only the span from the earliest possible first match to the latest
+    // possible last match can take part in an alignment.
+    let mut first = None;
+    let mut matched = 0;
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        if fold(c) == query[matched] {
+            first.get_or_insert(i);
+            matched += 1;
+            if matched == query.len() {
+                break;
+            }
+        }
+    }
+    if matched < query.len() {
+        return None;
+    }
+    let first = first?;
+    let last = chars
+        .iter()
+        .rposition(|&(_, c)| fold(c) == query[query.len() - 1])?;
+    let filename = chars
+        .iter()
+        .rposition(|&(_, c)| char_class(c) == CharClass::PathSeparator)
+        .map(|i| i + 1);
+
+    let (n, m) = (last - first + 1, query.len());
+    let bonuses: Vec<i64> = (first..=last)
+        .map(|i| {
+            let previous = i.checked_sub(1).map(|p| char_class(chars[p].1));
+            let in_filename = filename.is_some_and(|start| i >= start);
+            bonus(previous, char_class(chars[i].1)) + if in_filename { BONUS_FILENAME } else { 0 }
+        })
+        .collect();
+    // `scores[j * n + i]`: the best alignment of `query[..=j]` with `query[j]`
+    // at `first + i`, reached from the match at `from[j * n + i]`.
+    let mut scores = vec![UNMATCHED; n * m];
+    let mut from = vec![0; n * m];
+    for j in 0..m {
+        // The best match of `query[j - 1]` two or more characters back,
+        // less the gap between it and `i`.
+        let mut gapped = (UNMATCHED, 0);
+        for i in 0..n {
+            if j > 0 && i >= 2 {
+                let start = scores[(j - 1) * n + i - 2] + GAP_START;
+                gapped = if start >= gapped.0 + GAP_EXTENSION {
+                    (start, i - 2)
+                } else {
+                    (gapped.0 + GAP_EXTENSION, gapped.1)
+                };
+            }
+            if fold(chars[first + i].1) != query[j] {
+                continue;
+            }
+            if j == 0 {
+                scores[i] = SCORE_MATCH + bonuses[i] * FIRST_CHAR_MULTIPLIER;
+                continue;
+            }
+            let consecutive = if i >= 1 {
+                (scores[(j - 1) * n + i - 1] + BONUS_CONSECUTIVE, i - 1)
+            } else {
+                (UNMATCHED, 0)
+            };
+            let (best, previous) = if consecutive.0 >= gapped.0 {
+                consecutive
+            } else {
+                gapped
+            };
+            if best > UNMATCHED / 2 {
+                scores[j * n + i] = best + SCORE_MATCH + bonuses[i];
+                from[j * n + i] = previous;
+            }
+        }
+    }
+
+    // The best end, the earliest on a tie.
+    let (end, score) = (0..n)
+        .map(|i| (i, scores[(m - 1) * n + i]))
+        .max_by_key(|&(i, score)| (score, Reverse(i)))?;
+    if score <= UNMATCHED / 2 {
+        return None;
+    }
+    let mut positions = Vec::with_capacity(m);
+    let mut i = end;
+    for j in (0..m).rev() {
+        positions.push(chars[first + i].0);
+        i = from[j * n + i];
+    }
+    positions.reverse();
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Matches `query` against every choice, in parallel. Returns the index
+/// and match of each choice that matched, best first and in their
+/// original order on a tie.
+pub fn match_all<T: AsRef<str> + Sync>(choices: &[T], query: &str) -> Vec<(usize, FuzzyMatch)> {
+    match_chunks(choices, query, &AtomicBool::new(false))
+}
+
+/// `match_all`, skipping the chunks not yet started once `cancelled` is set.
+fn match_chunks<T: AsRef<str> + Sync>(
+    choices: &[T],
+    query: &str,
+    cancelled: &AtomicBool,
+) -> Vec<(usize, FuzzyMatch)> {
+    let mut matches: Vec<(usize, FuzzyMatch)> = choices
+        .par_chunks(CHUNK_SIZE)
+        .enumerate()
+        .flat_map_iter(|(chunk_idx, chunk)| {
+            let skip = cancelled.load(Ordering::Relaxed);
+            chunk
+                .iter()
+                .enumerate()
+                .filter(move |_| !skip)
+                .filter_map(move |(i, choice)| {
+                    Some((
+                        chunk_idx * CHUNK_SIZE + i,
+                        fuzzy_match(choice.as_ref(), query)?,
+                    ))
+                })
+        })
+        .collect();
+    matches.sort_by_key(|(idx, m)| (Reverse(m.score), *idx));
+    matches
+}
+
+/// A `match_all` running on rayon's threads, so the caller can poll for
+/// it between frames. Dropping it cancels what hasn't started, which is
+/// how a newer query supersedes an older one.
+pub struct PendingMatches<T> {
+    choices: Arc<[T]>,
+    cancelled: Arc<AtomicBool>,
+    results: Receiver<Vec<(usize, FuzzyMatch)>>,
+}
+
+impl<T> PendingMatches<T> {
+    /// The choices being matched, which the indices refer to.
+    pub fn choices(&self) -> &[T] {
+        &self.choices
+    }
+
+    /// The matches, once they are ready.
+    pub fn try_take(&self) -> Option<Vec<(usize, FuzzyMatch)>> {
+        self.results.try_recv().ok()
+    }
+}
+
+impl<T> Drop for PendingMatches<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn spawn_match_all<T: AsRef<str> + Send + Sync + 'static>(
+    choices: Arc<[T]>,
+    query: &str,
+) -> PendingMatches<T> {
+    let (sender, results) = mpsc::channel();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (work, flag, query) = (choices.clone(), cancelled.clone(), query.to_string());
+    rayon::spawn(move || {
+        let matches = match_chunks(&work, &query, &flag);
+        if !flag.load(Ordering::Relaxed) {
+            sender.send(matches).ok();
+        }
+    });
+    PendingMatches {
+        choices,
+        cancelled,
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best<'a>(query: &str, choices: &[&'a str]) -> &'a str {
+        choices[match_all(choices, query)[0].0]
+    }
+
+    #[test]
+    fn test_boundaries_score_higher() {
+        assert_eq!(
+            best("main", &["domain/src/lib.rs", "src/main.rs"]),
+            "src/main.rs"
+        );
+        assert_eq!(best("gco", &["gecko", "git checkout"]), "git checkout");
+        assert_eq!(best("fm", &["formatting", "fileManager"]), "fileManager");
+        assert_eq!(best("sr", &["user", "src"]), "src");
+        assert!(fuzzy_match("abc", "abd").is_none());
+        assert!(fuzzy_match("Cargo.toml", "cargo").is_some());
+        assert!(fuzzy_match("cargo.toml", "Cargo").is_none());
+    }
+
+    #[test]
+    fn test_positions_follow_the_best_alignment() {
+        let found = fuzzy_match("src/fuzzy_match/mod.rs", "fmm").unwrap();
+        assert_eq!(found.positions, vec![4, 10, 16]);
+        let found = fuzzy_match("café noir", "én").unwrap();
+        assert_eq!(found.positions, vec![3, 6]);
+        assert_eq!(
+            fuzzy_match("anything", ""),
+            Some(FuzzyMatch {
+                score: 0,
+                positions: Vec::new()
+            })
+        );
+    }
+
+    #[test]
+    fn test_batches_match_in_the_background() {
+        let choices: Arc<[String]> = (0..5000)
+            .map(|i| format!("cargo test --package crate{}", i))
+            .collect();
+        let pending = spawn_match_all(choices.clone(), "crate42");
+        let matches = loop {
+            if let Some(matches) = pending.try_take() {
+                break matches;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(matches, match_all(&choices, "crate42"));
+        assert_eq!(
+            pending.choices()[matches[0].0],
+            "cargo test --package crate42"
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Git Status
+//!
+//! This module reports the branch, upstream distance and working tree changes
+//! of the repository a pane is in. Status comes from `git status
+//! --porcelain=v2`, run on a background thread so a large repository never
+//! stalls a frame. Results are cached per directory and refreshed when the
+//! file watcher sees the repository change. They feed the `git` prompt chip,
+//! branch name completions and the code review view.
+
+use crate::watcher::FileWatcher;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Shortest time between two refreshes of the same directory, so a burst of
+/// file events, such as a checkout, costs one `git status`.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Error, Debug)]
+pub enum GitError {
+    #[error("Failed to run git: {0}")]
+    Spawn(std::io::Error),
+    #[error("git {0} failed: {1}")]
+    Command(String, String),
+}
+
+/// How a file differs from the last commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    /// Changes are in the index.
+    Staged,
+    /// Changes are only in the working tree.
+    Modified,
+    /// Both staged and further unstaged changes.
+    StagedAndModified,
+    Untracked,
+    /// Left unmerged by a merge, rebase or cherry-pick.
+    Conflicted,
+}
+
+impl FileState {
+    /// The marker shown next to the file, as in `git status --short`.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            FileState::Staged => "A",
+            FileState::Modified => "M",
+            FileState::StagedAndModified => "AM",
+            FileState::Untracked => "?",
+            FileState::Conflicted => "U",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// Relative to the repository root.
+    pub path: String,
+    pub state: FileState,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitStatus {
+    /// The branch name, or the short commit id when HEAD is detached.
+    pub branch: String,
+    pub detached: bool,
+    pub upstream: Option<String>,
+    pub ahead: usize,
+    pub behind: usize,
+    pub files: Vec<ChangedFile>,
+}
+
+impl GitStatus {
+    /// Parses the output of `git status --porcelain=v2 --branch`.
+    pub fn parse(output: &str) -> Self {
+        let mut status = GitStatus::default();
+        let mut oid = String::new();
+        for line in output.lines() {
+            if let Some(header) = line.strip_prefix("# ") {
+                let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+                match key {
+                    "branch.oid" => oid = value.to_string(),
+                    "branch.head" => status.branch = value.to_string(),
+                    "branch.upstream" => status.upstream = Some(value.to_string()),
+                    "branch.ab" => {
+                        for part in value.split_whitespace() {
+                            let count = part[1..].parse().unwrap_or(0);
+                            if part.starts_with('+') {
+                                status.ahead = count;
+                            } else {
+                                status.behind = count;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            let file = match line.split_once(' ') {
+                // Ordinary and renamed entries: "1 XY ..." and "2 XY ...".
+                // The path is the last field, after the original for renames.
+                Some(("1", rest)) => rest.splitn(8, ' ').nth(7).map(|path| (rest, path)),
+                Some(("2", rest)) => rest.splitn(9, ' ').nth(8).map(|path| (rest, path.split('\t').next().unwrap_or(path))),
+                Some(("u", rest)) => {
+                    let path = rest.splitn(10, ' ').nth(9).unwrap_or_default();
+                    status.files.push(ChangedFile { path: path.to_string(), state: FileState::Conflicted });
+                    None
+                }
+                Some(("?", path)) => {
+                    status.files.push(ChangedFile { path: path.to_string(), state: FileState::Untracked });
+                    None
+                }
+                _ => None,
+            };
+            if let Some((rest, path)) = file {
+                let mut xy = rest.chars();
+                let staged = xy.next().is_some_and(|c| c != '.');
+                let modified = xy.next().is_some_and(|c| c != '.');
+                let state = match (staged, modified) {
+                    (true, true) => FileState::StagedAndModified,
+                    (true, false) => FileState::Staged,
+                    _ => FileState::Modified,
+                };
+                status.files.push(ChangedFile { path: path.to_string(), state });
+            }
+        }
+        if status.branch == "(detached)" {
+            status.detached = true;
+            status.branch = oid.chars().take(7).collect();
+        }
+        status
+    }
+
+    fn count(&self, matches: impl Fn(FileState) -> bool) -> usize {
+        self.files.iter().filter(|file| matches(file.state)).count()
+    }
+
+    pub fn staged(&self) -> usize {
+        self.count(|state| matches!(state, FileState::Staged | FileState::StagedAndModified))
+    }
+
+    pub fn modified(&self) -> usize {
+        self.count(|state| matches!(state, FileState::Modified | FileState::StagedAndModified))
+    }
+
+    pub fn untracked(&self) -> usize {
+        self.count(|state| state == FileState::Untracked)
+    }
+
+    pub fn conflicted(&self) -> usize {
+        self.count(|state| state == FileState::Conflicted)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// The prompt chip, e.g. ` on main ↑1 ↓2 +1 !3 ?2 `. Counts that are
+    /// zero are left out.
+    pub fn chip(&self) -> String {
+        let mut chip = format!(" on {}", self.branch);
+        for (symbol, count) in [
+            ("↑", self.ahead),
+            ("↓", self.behind),
+            ("=", self.conflicted()),
+            ("+", self.staged()),
+            ("!", self.modified()),
+            ("?", self.untracked()),
+        ] {
+            if count > 0 {
+                chip.push_str(&format!(" {}{}", symbol, count));
+            }
+        }
+        chip.push(' ');
+        chip
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, GitError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output().map_err(GitError::Spawn)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(GitError::Command(args.join(" "), stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The root of the work tree containing `dir`, or `None` outside a
+/// repository.
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    let root = git(dir, &["rev-parse", "--show-toplevel"]).ok()?;
+    Some(PathBuf::from(root.trim()))
+}
+
+/// The status of the repository containing `dir`.
+pub fn read_status(dir: &Path) -> Result<GitStatus, GitError> {
+    git(dir, &["status", "--porcelain=v2", "--branch"]).map(|output| GitStatus::parse(&output))
+}
+
+/// The changes staged in the repository containing `dir`, as a patch.
+pub fn staged_diff(dir: &Path) -> Result<String, GitError> {
+    git(dir, &["diff", "--staged", "--no-color", "--no-ext-diff"])
+}
+
+/// Local branch names, most recently committed to first.
+pub fn local_branches(dir: &Path) -> Result<Vec<String>, GitError> {
+    let output = git(dir, &["for-each-ref", "--sort=-committerdate", "--format=%(refname:short)", "refs/heads"])?;
+    Ok(output.lines().map(str::to_string).collect())
+}
+
+/// What the background thread found for one directory.
+#[derive(Debug, Clone, Default)]
+pub struct RepoInfo {
+    pub root: PathBuf,
+    pub status: GitStatus,
+    pub branches: Vec<String>,
+}
+
+fn read_repo(dir: &Path) -> Option<RepoInfo> {
+    let root = repo_root(dir)?;
+    let status = read_status(dir).map_err(|e| log::warn!("{}", e)).ok()?;
+    let branches = local_branches(dir).unwrap_or_default();
+    Some(RepoInfo { root, status, branches })
+}
+
+#[derive(Debug, Default)]
+struct CacheEntry {
+    /// `None` once the directory is known not to be in a repository.
+    info: Option<RepoInfo>,
+    refreshed_at: Option<Instant>,
+    stale: bool,
+    in_flight: bool,
+}
+
+/// Caches the git status of the directories panes are in, refreshing them
+/// on a background thread.
+pub struct GitMonitor {
+    cache: HashMap<PathBuf, CacheEntry>,
+    requests: Sender<PathBuf>,
+    results: Receiver<(PathBuf, Option<RepoInfo>)>,
+    watcher: Option<FileWatcher>,
+    watched: HashSet<PathBuf>,
+}
+
+impl GitMonitor {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<PathBuf>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            for dir in request_rx {
+                let info = read_repo(&dir);
+                if result_tx.send((dir, info)).is_err() {
+                    break;
+                }
+            }
+        });
+        let watcher = FileWatcher::new().map_err(|e| log::warn!("Git status will not refresh on changes: {}", e)).ok();
+        Self { cache: HashMap::new(), requests, results, watcher, watched: HashSet::new() }
+    }
+
+    /// Asks for the status of `dir`, unless a fresh one is cached or a
+    /// refresh is already running.
+    pub fn request(&mut self, dir: &Path, now: Instant) {
+        let entry = self.cache.entry(dir.to_path_buf()).or_insert_with(|| CacheEntry { stale: true, ..CacheEntry::default() });
+        let throttled = entry.refreshed_at.is_some_and(|at| now.saturating_duration_since(at) < REFRESH_INTERVAL);
+        if !entry.stale || entry.in_flight || throttled {
+            return;
+        }
+        entry.in_flight = true;
+        entry.refreshed_at = Some(now);
+        self.requests.send(dir.to_path_buf()).ok();
+    }
+
+    /// Takes finished refreshes and marks directories stale when the
+    /// watcher reports changes in their repository. Returns whether any
+    /// cached status changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((dir, info)) = self.results.try_recv() {
+            if let (Some(info), Some(watcher)) = (&info, &mut self.watcher) {
+                if self.watched.insert(info.root.clone()) {
+                    if let Err(e) = watcher.watch(&info.root) {
+                        log::warn!("{}", e);
+                    }
+                }
+            }
+            let entry = self.cache.entry(dir).or_default();
+            changed |= entry.info.as_ref().map(|info| &info.status) != info.as_ref().map(|info| &info.status);
+            entry.info = info;
+            entry.in_flight = false;
+            entry.stale = false;
+        }
+        let mut touched = Vec::new();
+        if let Some(watcher) = &self.watcher {
+            while let Ok(event) = watcher.events().try_recv() {
+                if let Ok(event) = event {
+                    touched.extend(event.paths);
+                }
+            }
+        }
+        for path in touched {
+            // Lock files come and go with every git command, including ours.
+            if path.extension().is_some_and(|ext| ext == "lock") {
+                continue;
+            }
+            for entry in self.cache.values_mut() {
+                if entry.info.as_ref().is_some_and(|info| path.starts_with(&info.root)) {
+                    entry.stale = true;
+                }
+            }
+        }
+        changed
+    }
+
+    /// The cached repository information for `dir`, if it is in one.
+    pub fn repo(&self, dir: &Path) -> Option<&RepoInfo> {
+        self.cache.get(dir)?.info.as_ref()
+    }
+
+    pub fn status(&self, dir: &Path) -> Option<&GitStatus> {
+        self.repo(dir).map(|info| &info.status)
+    }
+}
+
+impl Default for GitMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_porcelain_v2() {
+        let output = "\
+# branch.oid 3c78d0d9a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +2 -1
+1 M. N... 100644 100644 100644 aaaa bbbb src/lib.rs
+1 .M N... 100644 100644 100644 aaaa bbbb README.md
+1 MM N... 100644 100644 100644 aaaa bbbb src/main.rs
+2 R. N... 100644 100644 100644 aaaa bbbb R100 src/new name.rs\tsrc/old.rs
+u UU N... 100644 100644 100644 100644 aaaa bbbb cccc Cargo.toml
+? notes.txt
+";
+        let status = GitStatus::parse(output);
+        assert_eq!(status.branch, "main");
+        assert_eq!(status.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((status.ahead, status.behind), (2, 1));
+        assert_eq!(status.files[3], ChangedFile { path: "src/new name.rs".to_string(), state: FileState::Staged });
+        assert_eq!((status.staged(), status.modified(), status.untracked(), status.conflicted()), (3, 2, 1, 1));
+        assert_eq!(status.chip(), " on main ↑2 ↓1 =1 +3 !2 ?1 ");
+
+        let detached = GitStatus::parse("# branch.oid 3c78d0d9a1b2\n# branch.head (detached)\n");
+        assert!(detached.detached);
+        assert_eq!(detached.chip(), " on 3c78d0d ");
+    }
+
+    #[test]
+    fn test_status_of_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |args: &[&str]| git(dir.path(), args).unwrap();
+        run(&["init", "-q", "-b", "main"]);
+        std::fs::write(dir.path().join("a.txt"), "one").unwrap();
+        run(&["add", "a.txt"]);
+        run(&["-c", "user.name=t", "-c", "user.email=t@example.com", "commit", "-q", "-m", "init"]);
+        run(&["branch", "feature"]);
+        std::fs::write(dir.path().join("a.txt"), "two").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "new").unwrap();
+
+        let info = read_repo(dir.path()).unwrap();
+        assert_eq!(info.status.branch, "main");
+        assert_eq!((info.status.modified(), info.status.untracked()), (1, 1));
+        let mut branches = info.branches;
+        branches.sort();
+        assert_eq!(branches, vec!["feature", "main"]);
+    }
+}
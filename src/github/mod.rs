@@ -0,0 +1,286 @@
+//! GitHub
+//!
+//! Lists the user's open pull requests and the open issues assigned to them
+//! through GitHub's GraphQL API, for the command palette, and posts block
+//! output as pull request comments. The token is read from the keychain,
+//! by default under the same name gist sharing uses. Checking out a pull request fetches its head from
+//! GitHub, which works for pull requests from forks too, but only in a clone
+//! of the pull request's repository.
+
+use crate::graphql::{GraphQLClient, GraphQLError};
+use crate::keychain::{Keychain, KeychainError};
+use serde::Deserialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const GRAPHQL_URL: &str = "https://api.github.com/graphql";
+/// How long a listing is shown before the palette lists again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(120);
+
+const VIEWER_QUERY: &str = r#"
+query {
+  viewer {
+    pullRequests(first: 50, states: OPEN, orderBy: {field: UPDATED_AT, direction: DESC}) {
+      nodes { id number title url headRefName repository { nameWithOwner } }
+    }
+  }
+  search(query: "is:open is:issue assignee:@me archived:false sort:updated-desc", type: ISSUE, first: 50) {
+    nodes { ... on Issue { id number title url repository { nameWithOwner } } }
+  }
+}
+"#;
+
+const ADD_COMMENT_MUTATION: &str = r#"
+mutation($subjectId: ID!, $body: String!) {
+  addComment(input: {subjectId: $subjectId, body: $body}) {
+    commentEdge { node { url } }
+  }
+}
+"#;
+
+#[derive(Error, Debug)]
+pub enum GitHubError {
+    #[error("GitHub token unavailable: {0}")]
+    Token(#[from] KeychainError),
+    #[error(transparent)]
+    Api(#[from] GraphQLError),
+    #[error("Failed to start the GitHub client: {0}")]
+    Runtime(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitHubItemKind {
+    PullRequest,
+    Issue,
+}
+
+/// An open pull request or issue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitHubItem {
+    pub kind: GitHubItemKind,
+    /// The GraphQL node id, which comments are posted to.
+    pub id: String,
+    pub number: u64,
+    pub title: String,
+    pub url: String,
+    /// `owner/name`.
+    pub repository: String,
+    /// `owner/name#number`.
+    pub reference: String,
+    /// The pull request's branch.
+    pub head_ref: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ViewerData {
+    viewer: Viewer,
+    search: Connection<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Viewer {
+    pull_requests: Connection<Node>,
+}
+
+#[derive(Deserialize)]
+struct Connection<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Node {
+    id: String,
+    number: u64,
+    title: String,
+    url: String,
+    head_ref_name: Option<String>,
+    repository: Repository,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    name_with_owner: String,
+}
+
+impl GitHubItem {
+    fn from_node(kind: GitHubItemKind, node: Node) -> Self {
+        Self {
+            kind,
+            reference: format!("{}#{}", node.repository.name_with_owner, node.number),
+            id: node.id,
+            number: node.number,
+            title: node.title,
+            url: node.url,
+            repository: node.repository.name_with_owner,
+            head_ref: node.head_ref_name,
+        }
+    }
+
+    /// The command that checks the pull request's head out as its branch.
+    pub fn checkout_command(&self) -> Option<String> {
+        let branch = self.head_ref.as_deref()?;
+        Some(format!(
+            "git fetch https://github.com/{}.git pull/{}/head && git checkout -B {} FETCH_HEAD",
+            self.repository, self.number, branch
+        ))
+    }
+}
+
+/// Pull requests first, then issues. Search results that are not issues
+/// come back as empty objects and are skipped.
+fn parse_items(data: ViewerData) -> Vec<GitHubItem> {
+    let pull_requests = data.viewer.pull_requests.nodes.into_iter().map(|node| GitHubItem::from_node(GitHubItemKind::PullRequest, node));
+    let issues = data
+        .search
+        .nodes
+        .into_iter()
+        .filter_map(|node| serde_json::from_value(node).ok())
+        .map(|node| GitHubItem::from_node(GitHubItemKind::Issue, node));
+    pull_requests.chain(issues).collect()
+}
+
+/// Whether `remotes`, the output of `git remote -v`, points at `repository`
+/// on GitHub, over HTTPS or SSH.
+pub fn has_remote(remotes: &str, repository: &str) -> bool {
+    let repository = repository.to_lowercase();
+    remotes.lines().filter_map(|line| line.split_whitespace().nth(1)).any(|url| {
+        let url = url.to_lowercase();
+        let path = url.strip_suffix(".git").unwrap_or(&url).trim_end_matches('/');
+        ["github.com/", "github.com:"].iter().any(|host| path.ends_with(&format!("{}{}", host, repository)))
+    })
+}
+
+fn client(credential: &str) -> Result<GraphQLClient, GitHubError> {
+    let token = Keychain::new().get(credential)?;
+    Ok(GraphQLClient::new(GRAPHQL_URL).with_token(token))
+}
+
+fn runtime() -> Result<tokio::runtime::Runtime, GitHubError> {
+    Ok(tokio::runtime::Builder::new_current_thread().enable_all().build()?)
+}
+
+/// The user's open pull requests and assigned issues.
+pub fn fetch_items(credential: &str) -> Result<Vec<GitHubItem>, GitHubError> {
+    let client = client(credential)?;
+    let data: ViewerData = runtime()?.block_on(client.query(VIEWER_QUERY, serde_json::json!({})))?;
+    Ok(parse_items(data))
+}
+
+/// Posts `body` as a comment on the pull request or issue `subject_id` and
+/// returns the comment's URL.
+pub fn add_comment(credential: &str, subject_id: &str, body: &str) -> Result<String, GitHubError> {
+    let client = client(credential)?;
+    let variables = serde_json::json!({ "subjectId": subject_id, "body": body });
+    let data: serde_json::Value = runtime()?.block_on(client.query(ADD_COMMENT_MUTATION, variables))?;
+    Ok(data["addComment"]["commentEdge"]["node"]["url"].as_str().unwrap_or_default().to_string())
+}
+
+/// Keeps the palette's pull requests and issues, listing them again on a
+/// background thread once they are a couple of minutes old.
+pub struct GitHubMonitor {
+    items: Vec<GitHubItem>,
+    requests: Sender<String>,
+    results: Receiver<Result<Vec<GitHubItem>, GitHubError>>,
+    requested_at: Option<Instant>,
+}
+
+impl Default for GitHubMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GitHubMonitor {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<String>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            for credential in request_rx {
+                if result_tx.send(fetch_items(&credential)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { items: Vec::new(), requests, results, requested_at: None }
+    }
+
+    /// Lists again with the token stored as `credential`, unless the last
+    /// listing is recent.
+    pub fn request(&mut self, credential: &str, now: Instant) {
+        if self.requested_at.is_some_and(|at| now.saturating_duration_since(at) < REFRESH_INTERVAL) {
+            return;
+        }
+        self.requested_at = Some(now);
+        self.requests.send(credential.to_string()).ok();
+    }
+
+    /// Takes a finished listing. Returns whether the items changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(result) = self.results.try_recv() {
+            match result {
+                Ok(items) => {
+                    changed |= items != self.items;
+                    self.items = items;
+                }
+                // Without a token the palette simply has no GitHub items.
+                Err(e) => log::debug!("Could not list GitHub pull requests and issues: {}", e),
+            }
+        }
+        changed
+    }
+
+    pub fn items(&self) -> &[GitHubItem] {
+        &self.items
+    }
+
+    pub fn pull_requests(&self) -> impl Iterator<Item = &GitHubItem> {
+        self.items.iter().filter(|item| item.kind == GitHubItemKind::PullRequest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viewer_data_becomes_items() {
+        let data: ViewerData = serde_json::from_str(
+            r#"{
+                "viewer": {"pullRequests": {"nodes": [
+                    {"id": "PR_1", "number": 42, "title": "Fix resize", "url": "https://github.com/acme/term/pull/42",
+                     "headRefName": "fix-resize", "repository": {"nameWithOwner": "acme/term"}}
+                ]}},
+                "search": {"nodes": [
+                    {"id": "I_7", "number": 7, "title": "Crash on start", "url": "https://github.com/acme/term/issues/7",
+                     "repository": {"nameWithOwner": "acme/term"}},
+                    {}
+                ]}
+            }"#,
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+        let items = parse_items(data);
+        assert_eq!(items.len(), 2);
+        assert_eq!((items[0].kind, items[0].reference.as_str()), (GitHubItemKind::PullRequest, "acme/term#42"));
+        assert_eq!(
+            items[0].checkout_command().unwrap(),
+            "git fetch https://github.com/acme/term.git pull/42/head && git checkout -B fix-resize FETCH_HEAD"
+        );
+        assert_eq!(items[1].kind, GitHubItemKind::Issue);
+        assert_eq!(items[1].checkout_command(), None);
+    }
+
+    #[test]
+    fn test_remotes_match_the_repository() {
+        let https = "origin\thttps://github.com/Acme/Term.git (fetch)\norigin\thttps://github.com/Acme/Term.git (push)\n";
+        assert!(has_remote(https, "acme/term"));
+        assert!(has_remote("upstream\tgit@github.com:acme/term (fetch)\n", "acme/term"));
+        assert!(!has_remote("origin\tgit@github.com:acme/terminal.git (fetch)\n", "acme/term"));
+        assert!(!has_remote("origin\thttps://github.com/other/acme/term.git (fetch)\n", "acme/term"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Query Explorer
+//!
+//! Saved queries and the background work behind the explorer overlay.
+//! Queries are saved as JSON files, one per query, in a Drive directory so
+//! they sync with the rest of the workspace. Introspection and queries run
+//! on a worker thread; the schema of the last endpoint introspected is kept
+//! for completions.
+
+use super::schema::Schema;
+use super::GraphQLClient;
+use crate::keychain::Keychain;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use uuid::Uuid;
+
+lazy_static! {
+    static ref OPERATION_NAME: Regex = Regex::new(r"^\s*(?:query|mutation|subscription)\s+([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    pub endpoint: String,
+    pub query: String,
+    /// The variable form's values, as typed.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+/// The name after `query`, `mutation` or `subscription`, if any.
+pub fn operation_name(query: &str) -> Option<String> {
+    OPERATION_NAME.captures(query).map(|captures| captures[1].to_string())
+}
+
+/// Writes `query` to `dir`, replacing a saved query of the same name.
+pub fn save_query(dir: &Path, query: &SavedQuery) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let file_name: String = query.name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}.json", file_name));
+    std::fs::write(&path, serde_json::to_string_pretty(query)?)?;
+    Ok(path)
+}
+
+/// The queries saved in `dir`, by name. Unreadable files are skipped.
+pub fn saved_queries(dir: &Path) -> Vec<SavedQuery> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut queries: Vec<SavedQuery> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|entry| serde_json::from_str(&std::fs::read_to_string(entry.path()).ok()?).ok())
+        .collect();
+    queries.sort_by(|a, b| a.name.cmp(&b.name));
+    queries
+}
+
+enum Job {
+    Introspect { endpoint: String, credential: Option<String> },
+    Execute { block_id: Uuid, endpoint: String, credential: Option<String>, query: String, variables: serde_json::Value },
+}
+
+/// The `data` of a query, or why it failed, for the block that ran it.
+pub type QueryResult = (Uuid, Result<serde_json::Value, String>);
+
+enum JobResult {
+    Schema(String, Result<Schema, String>),
+    Query(QueryResult),
+}
+
+fn client(endpoint: &str, credential: Option<&str>) -> Result<GraphQLClient, String> {
+    let client = GraphQLClient::new(endpoint);
+    match credential {
+        Some(credential) => Ok(client.with_token(Keychain::new().get(credential).map_err(|e| e.to_string())?)),
+        None => Ok(client),
+    }
+}
+
+fn run(runtime: &tokio::runtime::Runtime, job: Job) -> JobResult {
+    match job {
+        Job::Introspect { endpoint, credential } => {
+            let schema = client(&endpoint, credential.as_deref())
+                .and_then(|client| runtime.block_on(client.introspect()).map_err(|e| e.to_string()));
+            JobResult::Schema(endpoint, schema)
+        }
+        Job::Execute { block_id, endpoint, credential, query, variables } => {
+            let data = client(&endpoint, credential.as_deref())
+                .and_then(|client| runtime.block_on(client.query(&query, variables)).map_err(|e| e.to_string()));
+            JobResult::Query((block_id, data))
+        }
+    }
+}
+
+/// Introspects endpoints and runs queries on a background thread.
+pub struct GraphQLExplorer {
+    schema: Option<(String, Result<Schema, String>)>,
+    jobs: Sender<Job>,
+    results: Receiver<JobResult>,
+}
+
+impl Default for GraphQLExplorer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GraphQLExplorer {
+    pub fn new() -> Self {
+        let (jobs, job_rx) = channel::<Job>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Failed to start GraphQL runtime: {}", e);
+                    return;
+                }
+            };
+            for job in job_rx {
+                if result_tx.send(run(&runtime, job)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { schema: None, jobs, results }
+    }
+
+    /// Introspects `endpoint`, unless its schema is already loaded or
+    /// loading.
+    pub fn introspect(&mut self, endpoint: &str, credential: Option<&str>) {
+        if endpoint.is_empty() || self.schema.as_ref().is_some_and(|(loaded, _)| loaded == endpoint) {
+            return;
+        }
+        self.schema = Some((endpoint.to_string(), Err("Loading schema...".to_string())));
+        self.jobs.send(Job::Introspect { endpoint: endpoint.to_string(), credential: credential.map(str::to_string) }).ok();
+    }
+
+    pub fn execute(&self, block_id: Uuid, endpoint: &str, credential: Option<&str>, query: &str, variables: serde_json::Value) {
+        let job = Job::Execute {
+            block_id,
+            endpoint: endpoint.to_string(),
+            credential: credential.map(str::to_string),
+            query: query.to_string(),
+            variables,
+        };
+        self.jobs.send(job).ok();
+    }
+
+    /// The schema of `endpoint`, or why it is not available.
+    pub fn schema(&self, endpoint: &str) -> Option<&Result<Schema, String>> {
+        self.schema.as_ref().filter(|(loaded, _)| loaded == endpoint).map(|(_, schema)| schema)
+    }
+
+    /// Keeps introspected schemas and returns the queries that finished.
+    pub fn poll(&mut self) -> Vec<QueryResult> {
+        let mut finished = Vec::new();
+        while let Ok(result) = self.results.try_recv() {
+            match result {
+                // A schema for an endpoint that is no longer shown is dropped.
+                JobResult::Schema(endpoint, schema) => {
+                    if self.schema.as_ref().is_some_and(|(loaded, _)| *loaded == endpoint) {
+                        self.schema = Some((endpoint, schema));
+                    }
+                }
+                JobResult::Query(result) => finished.push(result),
+            }
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_are_saved_by_name() {
+        assert_eq!(operation_name("  query ActiveUsers($first: Int) { users { id } }").as_deref(), Some("ActiveUsers"));
+        assert_eq!(operation_name("{ users { id } }"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut query = SavedQuery {
+            name: "Active Users".to_string(),
+            endpoint: "https://api.example.com/graphql".to_string(),
+            query: "query ActiveUsers { users { id } }".to_string(),
+            variables: BTreeMap::from([("first".to_string(), "10".to_string())]),
+        };
+        let path = save_query(dir.path(), &query).unwrap();
+        assert_eq!(path.file_name().unwrap(), "Active_Users.json");
+        query.query = "query ActiveUsers { users { id name } }".to_string();
+        save_query(dir.path(), &query).unwrap();
+        std::fs::write(dir.path().join("broken.json"), "{").unwrap();
+        assert_eq!(saved_queries(dir.path()), vec![query]);
+    }
+}
@@ -3,6 +3,9 @@
 //! This module provides utilities for interacting with GraphQL APIs,
 //! including a client for sending queries and mutations.
 
+pub mod explorer;
+pub mod schema;
+
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -13,6 +16,8 @@ pub enum GraphQLError {
     Network(reqwest::Error),
     #[error("GraphQL error: {0}")]
     GraphQL(String),
+    #[error("Invalid variable: {0}")]
+    Variable(String),
 }
 
 #[derive(Serialize)]
@@ -35,6 +40,7 @@ struct GraphQLErrorDetail {
 pub struct GraphQLClient {
     client: Client,
     endpoint: String,
+    token: Option<String>,
 }
 
 impl GraphQLClient {
@@ -42,9 +48,16 @@ impl GraphQLClient {
         Self {
             client: Client::new(),
             endpoint: endpoint.to_string(),
+            token: None,
         }
     }
 
+    /// Sends `token` as a bearer token with every request.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
     pub async fn query<V: Serialize, T: for<'de> Deserialize<'de>>(
         &self,
         query: &str,
@@ -55,13 +68,15 @@ impl GraphQLClient {
             variables,
         };
 
-        let response = self
-            .client
-            .post(&self.endpoint)
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(GraphQLError::Network)?;
+        // Some APIs, GitHub's among them, reject requests without a user agent.
+        let mut request = self.client.post(&self.endpoint).header("User-Agent", "warpish-terminal").json(&request_body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(GraphQLError::Network)?;
+        if !response.status().is_success() {
+            return Err(GraphQLError::GraphQL(format!("request failed with status {}", response.status())));
+        }
 
         let response_body: GraphQLResponse<T> = response
             .json()
@@ -83,6 +98,12 @@ impl GraphQLClient {
             Err(GraphQLError::GraphQL("No data in response".to_string()))
         }
     }
+
+    /// Reads the API's schema.
+    pub async fn introspect(&self) -> Result<schema::Schema, GraphQLError> {
+        let data: schema::IntrospectionData = self.query(schema::INTROSPECTION_QUERY, serde_json::json!({})).await?;
+        Ok(data.into())
+    }
 }
 
 #[cfg(test)]
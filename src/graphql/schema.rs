@@ -0,0 +1,322 @@
+//! Schema Introspection
+//!
+//! Reads an API's schema with the standard introspection query and answers
+//! the query explorer's questions about it: which fields can be selected
+//! where the cursor is, and what the operation's variables should hold.
+
+use super::GraphQLError;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+lazy_static! {
+    static ref VARIABLE_DEFINITION: Regex = Regex::new(r"\$([A-Za-z_][A-Za-z0-9_]*)\s*:\s*([\[\]A-Za-z0-9_!]+)").unwrap();
+}
+
+pub const INTROSPECTION_QUERY: &str = r#"
+query IntrospectionQuery {
+  __schema {
+    queryType { name }
+    mutationType { name }
+    types {
+      name
+      fields(includeDeprecated: false) { name type { ...TypeRef } }
+    }
+  }
+}
+
+fragment TypeRef on __Type {
+  kind
+  name
+  ofType { kind name ofType { kind name ofType { kind name ofType { kind name } } } }
+}
+"#;
+
+/// A field's type, with its list and non-null wrappers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypeRef {
+    pub kind: String,
+    pub name: Option<String>,
+    pub of_type: Option<Box<TypeRef>>,
+}
+
+impl TypeRef {
+    /// The named type under any wrappers.
+    pub fn named(&self) -> Option<&str> {
+        match &self.name {
+            Some(name) => Some(name),
+            None => self.of_type.as_deref()?.named(),
+        }
+    }
+}
+
+impl fmt::Display for TypeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind.as_str(), self.of_type.as_deref()) {
+            ("NON_NULL", Some(inner)) => write!(f, "{}!", inner),
+            ("LIST", Some(inner)) => write!(f, "[{}]", inner),
+            _ => write!(f, "{}", self.name.as_deref().unwrap_or("?")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_ref: TypeRef,
+}
+
+#[derive(Deserialize)]
+struct TypeName {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct FullType {
+    name: Option<String>,
+    fields: Option<Vec<Field>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSchema {
+    query_type: TypeName,
+    mutation_type: Option<TypeName>,
+    types: Vec<FullType>,
+}
+
+/// The `data` of an introspection query.
+#[derive(Deserialize)]
+pub struct IntrospectionData {
+    #[serde(rename = "__schema")]
+    schema: RawSchema,
+}
+
+/// The object types of a schema and their fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub query_type: String,
+    pub mutation_type: Option<String>,
+    types: BTreeMap<String, Vec<Field>>,
+}
+
+impl From<IntrospectionData> for Schema {
+    fn from(data: IntrospectionData) -> Self {
+        let types = data
+            .schema
+            .types
+            .into_iter()
+            .filter_map(|full_type| Some((full_type.name?, full_type.fields?)))
+            .collect();
+        Self {
+            query_type: data.schema.query_type.name,
+            mutation_type: data.schema.mutation_type.map(|mutation_type| mutation_type.name),
+            types,
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl Schema {
+    pub fn type_count(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn fields(&self, type_name: &str) -> &[Field] {
+        self.types.get(type_name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    fn field_type(&self, type_name: &str, field: &str) -> Option<&str> {
+        self.fields(type_name).iter().find(|candidate| candidate.name == field)?.type_ref.named()
+    }
+
+    /// The type an operation's top-level selection set selects from.
+    /// Subscriptions are not offered.
+    fn root_type(&self, operation: &str) -> Option<&str> {
+        match operation {
+            "" | "query" => Some(&self.query_type),
+            "mutation" => self.mutation_type.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The type whose fields can be selected at the end of `text`, found by
+    /// following the open selection sets. Arguments, strings and comments
+    /// are skipped.
+    fn type_at(&self, text: &str) -> Option<String> {
+        let mut stack: Vec<Option<String>> = Vec::new();
+        let mut operation = String::new();
+        let (mut word, mut previous_word) = (String::new(), String::new());
+        let mut paren_depth = 0usize;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+                '"' => while chars.next().is_some_and(|c| c != '"') {},
+                '(' => paren_depth += 1,
+                ')' => paren_depth = paren_depth.saturating_sub(1),
+                '{' if paren_depth == 0 => {
+                    let selected = if previous_word == "on" {
+                        // `... on Type {` and `fragment Name on Type {`
+                        Some(word.clone())
+                    } else if let Some(parent) = stack.last() {
+                        parent.as_deref().and_then(|parent| self.field_type(parent, &word)).map(str::to_string)
+                    } else {
+                        self.root_type(&operation).map(str::to_string)
+                    };
+                    stack.push(selected);
+                    word.clear();
+                }
+                '}' if paren_depth == 0 => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        operation.clear();
+                    }
+                }
+                c if is_name_char(c) && paren_depth == 0 => {
+                    let mut name = c.to_string();
+                    while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+                        name.push(c);
+                    }
+                    if stack.is_empty() && operation.is_empty() {
+                        operation = name.clone();
+                    }
+                    previous_word = std::mem::replace(&mut word, name);
+                }
+                _ => {}
+            }
+        }
+        if paren_depth > 0 {
+            return None;
+        }
+        stack.pop().flatten()
+    }
+
+    /// Fields that complete the name being typed at the end of `text`.
+    pub fn completions(&self, text: &str) -> Vec<&Field> {
+        let prefix_len = text.chars().rev().take_while(|&c| is_name_char(c)).map(char::len_utf8).sum::<usize>();
+        let (before, prefix) = text.split_at(text.len() - prefix_len);
+        let Some(type_name) = self.type_at(before) else {
+            return Vec::new();
+        };
+        self.fields(&type_name)
+            .iter()
+            .filter(|field| field.name.starts_with(prefix) && field.name != prefix)
+            .collect()
+    }
+}
+
+/// A variable an operation declares, e.g. `$id: ID!`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableDefinition {
+    pub name: String,
+    pub type_name: String,
+}
+
+/// The variables declared by the first operation in `query`.
+pub fn variable_definitions(query: &str) -> Vec<VariableDefinition> {
+    let header = query.split('{').next().unwrap_or_default();
+    VARIABLE_DEFINITION
+        .captures_iter(header)
+        .map(|captures| VariableDefinition { name: captures[1].to_string(), type_name: captures[2].to_string() })
+        .collect()
+}
+
+/// The variables object for a request, from the explorer's form. Values
+/// are converted to the declared scalar type; lists and input objects are
+/// written as JSON. Empty values are left out.
+pub fn variables_json(definitions: &[VariableDefinition], values: &BTreeMap<String, String>) -> Result<serde_json::Value, GraphQLError> {
+    let mut variables = serde_json::Map::new();
+    for definition in definitions {
+        let Some(value) = values.get(&definition.name).map(|value| value.trim()).filter(|value| !value.is_empty()) else {
+            continue;
+        };
+        let invalid = || GraphQLError::Variable(format!("${} is not a valid {}", definition.name, definition.type_name));
+        let json = match definition.type_name.trim_end_matches('!') {
+            "Int" => value.parse::<i64>().map_err(|_| invalid())?.into(),
+            "Float" => value.parse::<f64>().map_err(|_| invalid())?.into(),
+            "Boolean" => value.parse::<bool>().map_err(|_| invalid())?.into(),
+            "String" | "ID" => value.into(),
+            // Lists, input objects and custom scalars
+            _ => serde_json::from_str(value).unwrap_or_else(|_| value.into()),
+        };
+        variables.insert(definition.name.clone(), json);
+    }
+    Ok(serde_json::Value::Object(variables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        let data: IntrospectionData = serde_json::from_value(serde_json::json!({
+            "__schema": {
+                "queryType": {"name": "Query"},
+                "mutationType": {"name": "Mutation"},
+                "types": [
+                    {"name": "Query", "fields": [
+                        {"name": "user", "type": {"kind": "OBJECT", "name": "User", "ofType": null}},
+                        {"name": "users", "type": {"kind": "NON_NULL", "name": null, "ofType":
+                            {"kind": "LIST", "name": null, "ofType": {"kind": "OBJECT", "name": "User", "ofType": null}}}}
+                    ]},
+                    {"name": "Mutation", "fields": [
+                        {"name": "deleteUser", "type": {"kind": "SCALAR", "name": "Boolean", "ofType": null}}
+                    ]},
+                    {"name": "User", "fields": [
+                        {"name": "id", "type": {"kind": "SCALAR", "name": "ID", "ofType": null}},
+                        {"name": "name", "type": {"kind": "SCALAR", "name": "String", "ofType": null}},
+                        {"name": "friends", "type": {"kind": "LIST", "name": null, "ofType": {"kind": "OBJECT", "name": "User", "ofType": null}}}
+                    ]},
+                    {"name": "ID", "fields": null}
+                ]
+            }
+        }))
+        .unwrap();
+        data.into()
+    }
+
+    fn names(fields: Vec<&Field>) -> Vec<&str> {
+        fields.into_iter().map(|field| field.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_completions_follow_the_selection_sets() {
+        let schema = schema();
+        assert_eq!(schema.type_count(), 3);
+        assert_eq!(names(schema.completions("{ us")), vec!["user", "users"]);
+        assert_eq!(names(schema.completions("query Q($id: ID!) { user(id: $id) { friends { n")), vec!["name"]);
+        assert_eq!(names(schema.completions("query { users { id } user { # a comment {\n ")), vec!["id", "name", "friends"]);
+        assert_eq!(names(schema.completions("mutation { d")), vec!["deleteUser"]);
+        assert_eq!(names(schema.completions("{ ... on User { i")), vec!["id"]);
+        assert!(schema.completions("{ user(id: \"us").is_empty());
+        assert!(schema.completions("{ unknown { ").is_empty());
+        assert_eq!(schema.fields("Query")[1].type_ref.to_string(), "[User]!");
+    }
+
+    #[test]
+    fn test_variables_are_typed_from_their_definitions() {
+        let definitions = variable_definitions("query Users($first: Int!, $active: Boolean, $ids: [ID!], $name: String) { users { id } }");
+        assert_eq!(definitions.len(), 4);
+        assert_eq!((definitions[2].name.as_str(), definitions[2].type_name.as_str()), ("ids", "[ID!]"));
+        let values = BTreeMap::from([
+            ("first".to_string(), "10".to_string()),
+            ("active".to_string(), "true".to_string()),
+            ("ids".to_string(), "[\"1\", \"2\"]".to_string()),
+            ("name".to_string(), String::new()),
+        ]);
+        assert_eq!(
+            variables_json(&definitions, &values).unwrap(),
+            serde_json::json!({"first": 10, "active": true, "ids": ["1", "2"]})
+        );
+        let invalid = BTreeMap::from([("first".to_string(), "ten".to_string())]);
+        assert!(variables_json(&definitions, &invalid).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Hook scripts. Each script runs in a shared Lua state with a global
+//! `hooks` table:
+//!
+//! - `hooks.on(event, fn)` subscribes to `"command_start"`,
+//!   `"command_finish"`, `"cwd_change"` or `"output_match"`;
+//! - `hooks.on_output(pattern, fn)` watches output for a regex;
+//! - `hooks.notify(text)` shows a notification.
+//!
+//! Scripts have no `io` or `os` library; they write files, fetch URLs and
+//! copy to the clipboard through the `permissions` table, which asks the user
+//! first (see [`crate::permissions::register_lua`]).
+//!
+//! Callbacks get a table of the event's fields (`event`, `pane`, `command`,
+//! `exit_code`, `cwd`, `pattern`, `line`). A callback that runs too long is
+//! stopped so a broken script cannot freeze the terminal.
+
+use super::{HookError, HookEvent, HookEventKind};
+use crate::permissions::{self, PermissionService};
+use mlua::prelude::*;
+use mlua::HookTriggers;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long one event's callbacks may run in total.
+const DISPATCH_BUDGET: Duration = Duration::from_millis(100);
+
+/// Registry table of event name to callbacks.
+const SUBSCRIPTIONS: &str = "warpish_hook_subscriptions";
+
+/// Registry table of output callbacks, as `{ pattern, fn }` pairs.
+const OUTPUT: &str = "warpish_hook_output";
+
+pub struct LuaHooks {
+    lua: Lua,
+    notices: Arc<Mutex<Vec<String>>>,
+}
+
+impl LuaHooks {
+    pub fn new(permissions: Arc<Mutex<PermissionService>>) -> Result<Self, HookError> {
+        let lua = crate::scripting::sandboxed()?;
+        permissions::register_lua(&lua, "hook scripts", permissions)?;
+        lua.set_named_registry_value(SUBSCRIPTIONS, lua.create_table()?)?;
+        lua.set_named_registry_value(OUTPUT, lua.create_table()?)?;
+        let notices = Arc::new(Mutex::new(Vec::new()));
+
+        let hooks = lua.create_table()?;
+        hooks.set(
+            "on",
+            lua.create_function(|lua, (event, callback): (String, LuaFunction)| {
+                if HookEventKind::from_name(&event).is_none() {
+                    return Err(LuaError::RuntimeError(format!("unknown hook event '{}'", event)));
+                }
+                let subscriptions: LuaTable = lua.named_registry_value(SUBSCRIPTIONS)?;
+                let callbacks = match subscriptions.get::<_, Option<LuaTable>>(event.as_str())? {
+                    Some(callbacks) => callbacks,
+                    None => {
+                        let callbacks = lua.create_table()?;
+                        subscriptions.set(event.as_str(), callbacks.clone())?;
+                        callbacks
+                    }
+                };
+                callbacks.push(callback)
+            })?,
+        )?;
+        hooks.set(
+            "on_output",
+            lua.create_function(|lua, (pattern, callback): (String, LuaFunction)| {
+                if let Err(e) = regex::Regex::new(&pattern) {
+                    return Err(LuaError::RuntimeError(format!("invalid pattern '{}': {}", pattern, e)));
+                }
+                let entry = lua.create_table()?;
+                entry.set("pattern", pattern)?;
+                entry.set("fn", callback)?;
+                lua.named_registry_value::<LuaTable>(OUTPUT)?.push(entry)
+            })?,
+        )?;
+        let sink = notices.clone();
+        hooks.set(
+            "notify",
+            lua.create_function(move |_, text: String| {
+                sink.lock().unwrap().push(text);
+                Ok(())
+            })?,
+        )?;
+        lua.globals().set("hooks", hooks)?;
+        Ok(Self { lua, notices })
+    }
+
+    /// Runs a script, which subscribes its callbacks.
+    pub fn load(&self, name: &str, source: &str) -> Result<(), HookError> {
+        self.lua.load(source).set_name(name).exec()?;
+        Ok(())
+    }
+
+    /// The patterns scripts watch output for.
+    pub fn output_patterns(&self) -> Result<Vec<String>, HookError> {
+        let output: LuaTable = self.lua.named_registry_value(OUTPUT)?;
+        let patterns = output
+            .sequence_values::<LuaTable>()
+            .map(|entry| entry?.get::<_, String>("pattern"))
+            .collect::<LuaResult<_>>()?;
+        Ok(patterns)
+    }
+
+    /// Calls the callbacks subscribed to `event` and returns the
+    /// notifications they asked for. Failing callbacks are logged.
+    pub fn dispatch(&self, event: &HookEvent) -> Vec<String> {
+        if let Err(e) = self.call(event) {
+            log::warn!("Hook script failed on {}: {}", event.kind().name(), e);
+        }
+        std::mem::take(&mut *self.notices.lock().unwrap())
+    }
+
+    fn call(&self, event: &HookEvent) -> LuaResult<()> {
+        let callbacks: Vec<LuaFunction> = match event {
+            HookEvent::OutputMatch { pattern, .. } => {
+                let output: LuaTable = self.lua.named_registry_value(OUTPUT)?;
+                output
+                    .sequence_values::<LuaTable>()
+                    .filter_map(|entry| {
+                        let entry = entry.ok()?;
+                        if entry.get::<_, String>("pattern").ok()? != *pattern {
+                            return None;
+                        }
+                        entry.get("fn").ok()
+                    })
+                    .collect()
+            }
+            _ => {
+                let subscriptions: LuaTable = self.lua.named_registry_value(SUBSCRIPTIONS)?;
+                match subscriptions.get::<_, Option<LuaTable>>(event.kind().name())? {
+                    Some(callbacks) => callbacks.sequence_values().collect::<LuaResult<_>>()?,
+                    None => Vec::new(),
+                }
+            }
+        };
+        if callbacks.is_empty() {
+            return Ok(());
+        }
+        let fields = self.lua.create_table()?;
+        for (name, value) in event.fields() {
+            fields.set(name, value)?;
+        }
+        if let HookEvent::CommandFinish { exit_code, .. } = event {
+            fields.set("exit_code", *exit_code)?;
+        }
+        let deadline = Instant::now() + DISPATCH_BUDGET;
+        self.lua.set_hook(HookTriggers::new().every_nth_instruction(1000), move |_, _| {
+            if Instant::now() >= deadline {
+                return Err(LuaError::runtime("hook took too long"));
+            }
+            Ok(())
+        });
+        let result = callbacks.into_iter().try_for_each(|callback| callback.call::<_, ()>(fields.clone()));
+        self.lua.remove_hook();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_scripts_subscribe_and_notify() {
+        let hooks = LuaHooks::new(Arc::default()).unwrap();
+        hooks
+            .load(
+                "test",
+                r#"
+                hooks.on("command_finish", function(e)
+                    if e.exit_code ~= 0 then hooks.notify(e.command .. " failed") end
+                end)
+                hooks.on_output("error: (.*)", function(e) hooks.notify("saw " .. e.line) end)
+                hooks.on("cwd_change", function(e) while true do end end)
+                "#,
+            )
+            .unwrap();
+        let pane_id = Uuid::new_v4();
+
+        assert_eq!(hooks.output_patterns().unwrap(), vec!["error: (.*)"]);
+        let finished = HookEvent::CommandFinish { pane_id, command: "make".to_string(), exit_code: 2 };
+        assert_eq!(hooks.dispatch(&finished), vec!["make failed"]);
+        let line = HookEvent::OutputMatch { pane_id, pattern: "error: (.*)".to_string(), line: "error: boom".to_string() };
+        assert_eq!(hooks.dispatch(&line), vec!["saw error: boom"]);
+        // A runaway callback is stopped rather than hanging.
+        assert!(hooks.dispatch(&HookEvent::CwdChange { pane_id, dir: "/tmp".into() }).is_empty());
+        assert!(hooks.load("bad", r#"hooks.on("nope", print)"#).is_err());
+        assert!(hooks.load("escape", r#"io.open("/tmp/x", "w")"#).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! Event Hooks
+//!
+//! This module lets scripts and plugins react to what happens in the panes:
+//! a command starting, a command finishing with its exit code, the shell
+//! changing directory, and output lines matching a watched pattern. Events
+//! reach three kinds of subscriber — shell hooks defined in the config, Lua
+//! scripts (see [`lua`]), and Rust plugins implementing [`HookHandler`] —
+//! and each can answer with a notification for the user.
+
+pub mod lua;
+
+use crate::config::{HookEventKind, HooksConfig, ShellHookConfig};
+use crate::permissions::PermissionService;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use thiserror::Error;
+use uuid::Uuid;
+
+use self::lua::LuaHooks;
+
+#[derive(Error, Debug)]
+pub enum HookError {
+    #[error("Invalid hook pattern '{0}': {1}")]
+    Pattern(String, regex::Error),
+    #[error("An output_match hook needs a pattern")]
+    MissingPattern,
+    #[error("Failed to read hook script {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Hook script failed: {0}")]
+    Lua(#[from] mlua::Error),
+}
+
+impl HookEventKind {
+    pub const ALL: [HookEventKind; 4] =
+        [HookEventKind::CommandStart, HookEventKind::CommandFinish, HookEventKind::CwdChange, HookEventKind::OutputMatch];
+
+    /// The name scripts and the config use for the event.
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEventKind::CommandStart => "command_start",
+            HookEventKind::CommandFinish => "command_finish",
+            HookEventKind::CwdChange => "cwd_change",
+            HookEventKind::OutputMatch => "output_match",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+/// Something that happened in a pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookEvent {
+    CommandStart { pane_id: Uuid, command: String },
+    CommandFinish { pane_id: Uuid, command: String, exit_code: i32 },
+    CwdChange { pane_id: Uuid, dir: PathBuf },
+    /// `line` matched the watched `pattern`.
+    OutputMatch { pane_id: Uuid, pattern: String, line: String },
+}
+
+impl HookEvent {
+    pub fn kind(&self) -> HookEventKind {
+        match self {
+            HookEvent::CommandStart { .. } => HookEventKind::CommandStart,
+            HookEvent::CommandFinish { .. } => HookEventKind::CommandFinish,
+            HookEvent::CwdChange { .. } => HookEventKind::CwdChange,
+            HookEvent::OutputMatch { .. } => HookEventKind::OutputMatch,
+        }
+    }
+
+    pub fn pane_id(&self) -> Uuid {
+        match self {
+            HookEvent::CommandStart { pane_id, .. }
+            | HookEvent::CommandFinish { pane_id, .. }
+            | HookEvent::CwdChange { pane_id, .. }
+            | HookEvent::OutputMatch { pane_id, .. } => *pane_id,
+        }
+    }
+
+    /// The event's fields by name, as given to scripts and shell hooks.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("event", self.kind().name().to_string()), ("pane", self.pane_id().to_string())];
+        match self {
+            HookEvent::CommandStart { command, .. } => fields.push(("command", command.clone())),
+            HookEvent::CommandFinish { command, exit_code, .. } => {
+                fields.push(("command", command.clone()));
+                fields.push(("exit_code", exit_code.to_string()));
+            }
+            HookEvent::CwdChange { dir, .. } => fields.push(("cwd", dir.display().to_string())),
+            HookEvent::OutputMatch { pattern, line, .. } => {
+                fields.push(("pattern", pattern.clone()));
+                fields.push(("line", line.clone()));
+            }
+        }
+        fields
+    }
+}
+
+/// A Rust plugin subscribed to every event.
+pub trait HookHandler {
+    /// Returns a notification to show, if any.
+    fn on_event(&mut self, event: &HookEvent) -> Option<String>;
+}
+
+struct ShellHook {
+    config: ShellHookConfig,
+    pattern: Option<Regex>,
+}
+
+impl ShellHook {
+    fn new(config: &ShellHookConfig) -> Result<Self, HookError> {
+        let pattern = config
+            .pattern
+            .as_deref()
+            .map(|pattern| Regex::new(pattern).map_err(|e| HookError::Pattern(pattern.to_string(), e)))
+            .transpose()?;
+        if config.event == HookEventKind::OutputMatch && pattern.is_none() {
+            return Err(HookError::MissingPattern);
+        }
+        Ok(Self { config: config.clone(), pattern })
+    }
+
+    fn matches(&self, event: &HookEvent) -> bool {
+        if event.kind() != self.config.event {
+            return false;
+        }
+        let pattern = self.pattern.as_ref();
+        match event {
+            HookEvent::CommandStart { command, .. } => pattern.is_none_or(|pattern| pattern.is_match(command)),
+            HookEvent::CommandFinish { command, exit_code, .. } => {
+                (!self.config.failed_only || *exit_code != 0) && pattern.is_none_or(|pattern| pattern.is_match(command))
+            }
+            HookEvent::CwdChange { dir, .. } => pattern.is_none_or(|pattern| pattern.is_match(&dir.to_string_lossy())),
+            HookEvent::OutputMatch { pattern: watched, .. } => self.config.pattern.as_ref() == Some(watched),
+        }
+    }
+
+    /// Starts the hook's command and returns its notification, if it has one.
+    fn fire(&self, event: &HookEvent) -> Option<String> {
+        let fields = event.fields();
+        if let Some(run) = &self.config.run {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", run]).stdin(Stdio::null()).stdout(Stdio::null());
+            for (name, value) in &fields {
+                cmd.env(format!("WARPISH_{}", name.to_uppercase()), value);
+            }
+            let run = run.clone();
+            thread::spawn(move || match cmd.status() {
+                Ok(status) if !status.success() => log::warn!("Hook '{}' exited with {}", run, status),
+                Err(e) => log::warn!("Failed to run hook '{}': {}", run, e),
+                Ok(_) => {}
+            });
+        }
+        let template = self.config.notify.as_ref()?;
+        Some(fields.iter().fold(template.clone(), |text, (name, value)| text.replace(&format!("{{{}}}", name), value)))
+    }
+}
+
+lazy_static! {
+    static ref ESCAPE_SEQUENCE: Regex =
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[@-Z\\-_]").unwrap();
+}
+
+/// Longest partial line kept while waiting for its end; progress bars that
+/// redraw with `\r` never send one.
+const MAX_PARTIAL_LINE: usize = 64 * 1024;
+
+/// Splits a pane's output into lines without escape sequences, holding a
+/// partial line back until the rest of it arrives.
+#[derive(Debug, Default)]
+pub struct OutputLines {
+    partial: Vec<u8>,
+}
+
+impl OutputLines {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(bytes);
+        let Some(end) = self.partial.iter().rposition(|byte| *byte == b'\n') else {
+            if self.partial.len() > MAX_PARTIAL_LINE {
+                self.partial.clear();
+            }
+            return Vec::new();
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .map(|line| ESCAPE_SEQUENCE.replace_all(line, "").trim_end_matches('\r').to_string())
+            .collect()
+    }
+}
+
+/// Delivers pane events to every subscriber.
+#[derive(Default)]
+pub struct Hooks {
+    shell: Vec<ShellHook>,
+    handlers: Vec<Box<dyn HookHandler>>,
+    lua: Option<LuaHooks>,
+    /// Patterns output is scanned for, with the text they were given as.
+    output_patterns: Vec<(String, Regex)>,
+}
+
+impl Hooks {
+    /// Compiles the configured shell hooks and loads the hook scripts, which
+    /// ask `permissions` before touching files, the network or the clipboard.
+    pub fn new(config: &HooksConfig, permissions: Arc<Mutex<PermissionService>>) -> Result<Self, HookError> {
+        let mut hooks = Self {
+            shell: config.shell.iter().map(ShellHook::new).collect::<Result<_, _>>()?,
+            ..Self::default()
+        };
+        let patterns: Vec<String> = hooks
+            .shell
+            .iter()
+            .filter(|hook| hook.config.event == HookEventKind::OutputMatch)
+            .filter_map(|hook| hook.config.pattern.clone())
+            .collect();
+        if !config.scripts.is_empty() {
+            let lua = LuaHooks::new(permissions)?;
+            for path in &config.scripts {
+                let source = std::fs::read_to_string(path).map_err(|e| HookError::Io(path.clone(), e))?;
+                lua.load(&path.display().to_string(), &source)?;
+            }
+            hooks.lua = Some(lua);
+        }
+        let script_patterns = hooks.lua.as_ref().map(LuaHooks::output_patterns).transpose()?.unwrap_or_default();
+        for pattern in patterns.into_iter().chain(script_patterns) {
+            hooks.watch_output(&pattern)?;
+        }
+        Ok(hooks)
+    }
+
+    pub fn subscribe(&mut self, handler: Box<dyn HookHandler>) {
+        self.handlers.push(handler);
+    }
+
+    /// Starts reporting output lines matching `pattern` as
+    /// [`HookEvent::OutputMatch`].
+    pub fn watch_output(&mut self, pattern: &str) -> Result<(), HookError> {
+        if self.output_patterns.iter().any(|(watched, _)| watched == pattern) {
+            return Ok(());
+        }
+        let regex = Regex::new(pattern).map_err(|e| HookError::Pattern(pattern.to_string(), e))?;
+        self.output_patterns.push((pattern.to_string(), regex));
+        Ok(())
+    }
+
+    /// Whether any subscriber watches output, so panes need scanning.
+    pub fn watches_output(&self) -> bool {
+        !self.output_patterns.is_empty()
+    }
+
+    /// Delivers `event` and returns the notifications subscribers asked for.
+    pub fn emit(&mut self, event: &HookEvent) -> Vec<String> {
+        let mut notices: Vec<String> =
+            self.shell.iter().filter(|hook| hook.matches(event)).filter_map(|hook| hook.fire(event)).collect();
+        notices.extend(self.handlers.iter_mut().filter_map(|handler| handler.on_event(event)));
+        if let Some(lua) = &self.lua {
+            notices.extend(lua.dispatch(event));
+        }
+        notices
+    }
+
+    /// An output match for every watched pattern each line matches.
+    pub fn match_output(&self, pane_id: Uuid, lines: &[String]) -> Vec<HookEvent> {
+        lines
+            .iter()
+            .flat_map(|line| {
+                self.output_patterns.iter().filter(|(_, regex)| regex.is_match(line)).map(|(pattern, _)| {
+                    HookEvent::OutputMatch { pane_id, pattern: pattern.clone(), line: line.clone() }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell_hook(event: HookEventKind, pattern: Option<&str>, notify: &str) -> ShellHookConfig {
+        ShellHookConfig {
+            event,
+            pattern: pattern.map(str::to_string),
+            failed_only: false,
+            run: None,
+            notify: Some(notify.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_shell_hooks_filter_and_fill_notifications() {
+        let mut failed = shell_hook(HookEventKind::CommandFinish, Some("^cargo "), "{command} exited {exit_code}");
+        failed.failed_only = true;
+        let config = HooksConfig {
+            shell: vec![failed, shell_hook(HookEventKind::OutputMatch, Some(r"panicked at"), "panic: {line}")],
+            scripts: Vec::new(),
+        };
+        let mut hooks = Hooks::new(&config, Arc::default()).unwrap();
+        let pane_id = Uuid::new_v4();
+        let finish = |command: &str, exit_code| HookEvent::CommandFinish { pane_id, command: command.to_string(), exit_code };
+
+        assert_eq!(hooks.emit(&finish("cargo test", 101)), vec!["cargo test exited 101"]);
+        assert!(hooks.emit(&finish("cargo test", 0)).is_empty());
+        assert!(hooks.emit(&finish("make", 2)).is_empty());
+
+        let mut output = OutputLines::default();
+        assert!(output.push(b"thread 'main' \x1b[1mpanicked").is_empty());
+        let lines = output.push(b" at src/main.rs\r\nok\n");
+        let matches = hooks.match_output(pane_id, &lines);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(hooks.emit(&matches[0]), vec!["panic: thread 'main' panicked at src/main.rs"]);
+    }
+
+    #[test]
+    fn test_output_hook_needs_pattern() {
+        let config = HooksConfig { shell: vec![shell_hook(HookEventKind::OutputMatch, None, "")], scripts: Vec::new() };
+        assert!(matches!(Hooks::new(&config, Arc::default()), Err(HookError::MissingPattern)));
+    }
+}
@@ -0,0 +1,223 @@
+//! Idle Lock
+//!
+//! This module tracks user inactivity and locks panes that are flagged as
+//! sensitive — panes marked as privacy zones, or panes whose running command
+//! matches a configured rule such as an SSH session to production. A locked
+//! pane hides its contents until the user presses a key and, if configured,
+//! passes an operating-system authentication prompt.
+
+use crate::config::{IdleLockConfig, SensitivePaneRule};
+use regex::Regex;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum IdleError {
+    #[error("Invalid sensitive pane pattern '{0}': {1}")]
+    Pattern(String, regex::Error),
+    #[error("Authentication failed")]
+    AuthenticationFailed,
+    #[error("OS authentication is not supported on this platform")]
+    Unsupported,
+    #[error("Failed to run authentication prompt: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// What the lock monitor needs to know about a pane.
+#[derive(Debug, Clone, Copy)]
+pub struct PaneActivity<'a> {
+    pub id: Uuid,
+    pub command: &'a str,
+    /// Set when the user marked the pane as a privacy zone.
+    pub privacy_zone: bool,
+}
+
+/// Asks the operating system to confirm the user's identity.
+pub trait Authenticator: Send {
+    fn authenticate(&self, reason: &str) -> Result<(), IdleError>;
+}
+
+/// Uses the platform's native authentication prompt.
+pub struct SystemAuthenticator;
+
+impl Authenticator for SystemAuthenticator {
+    fn authenticate(&self, reason: &str) -> Result<(), IdleError> {
+        let status = if cfg!(target_os = "macos") {
+            let script = format!(
+                "do shell script \"true\" with prompt \"{}\" with administrator privileges",
+                reason.replace('"', "'")
+            );
+            std::process::Command::new("osascript").args(["-e", &script]).status()?
+        } else if cfg!(target_os = "linux") {
+            std::process::Command::new("pkexec").arg("true").status()?
+        } else {
+            return Err(IdleError::Unsupported);
+        };
+        if status.success() {
+            Ok(())
+        } else {
+            Err(IdleError::AuthenticationFailed)
+        }
+    }
+}
+
+struct CompiledRule {
+    pattern: Regex,
+    timeout: Option<Duration>,
+}
+
+/// Locks sensitive panes after a period of inactivity.
+pub struct IdleMonitor {
+    enabled: bool,
+    timeout: Duration,
+    require_os_auth: bool,
+    rules: Vec<CompiledRule>,
+    last_activity: Instant,
+    locked: HashSet<Uuid>,
+}
+
+impl IdleMonitor {
+    pub fn new(config: &IdleLockConfig, now: Instant) -> Result<Self, IdleError> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule: &SensitivePaneRule| {
+                Regex::new(&rule.pattern)
+                    .map(|pattern| CompiledRule {
+                        pattern,
+                        timeout: rule.timeout_seconds.map(Duration::from_secs),
+                    })
+                    .map_err(|e| IdleError::Pattern(rule.pattern.clone(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            enabled: config.enabled,
+            timeout: Duration::from_secs(config.timeout_seconds),
+            require_os_auth: config.require_os_auth,
+            rules,
+            last_activity: now,
+            locked: HashSet::new(),
+        })
+    }
+
+    /// Resets the idle timer. Called on every key press.
+    pub fn record_activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    /// How long since the last key press.
+    pub fn idle_for(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_activity)
+    }
+
+    /// Returns how long `pane` may sit idle before it is locked, or `None`
+    /// if the pane is not sensitive.
+    pub fn lock_timeout(&self, pane: &PaneActivity) -> Option<Duration> {
+        let rule_timeout = self
+            .rules
+            .iter()
+            .filter(|rule| rule.pattern.is_match(pane.command))
+            .map(|rule| rule.timeout.unwrap_or(self.timeout))
+            .min();
+        match (pane.privacy_zone, rule_timeout) {
+            (true, Some(timeout)) => Some(timeout.min(self.timeout)),
+            (true, None) => Some(self.timeout),
+            (false, timeout) => timeout,
+        }
+    }
+
+    /// Locks every sensitive pane whose timeout has elapsed and returns the
+    /// ids of panes that were newly locked.
+    pub fn tick(&mut self, now: Instant, panes: &[PaneActivity]) -> Vec<Uuid> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let idle = now.saturating_duration_since(self.last_activity);
+        let mut newly_locked = Vec::new();
+        for pane in panes {
+            if self.locked.contains(&pane.id) {
+                continue;
+            }
+            if matches!(self.lock_timeout(pane), Some(timeout) if idle >= timeout) {
+                self.locked.insert(pane.id);
+                newly_locked.push(pane.id);
+            }
+        }
+        newly_locked
+    }
+
+    pub fn is_locked(&self, pane_id: Uuid) -> bool {
+        self.locked.contains(&pane_id)
+    }
+
+    /// Unlocks a pane after the user's key press, running the OS prompt first
+    /// when `require_os_auth` is set.
+    pub fn unlock(&mut self, pane_id: Uuid, authenticator: &dyn Authenticator, now: Instant) -> Result<(), IdleError> {
+        if !self.locked.contains(&pane_id) {
+            return Ok(());
+        }
+        if self.require_os_auth {
+            authenticator.authenticate("Unlock sensitive terminal pane")?;
+        }
+        self.locked.remove(&pane_id);
+        self.record_activity(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Deny;
+
+    impl Authenticator for Deny {
+        fn authenticate(&self, _reason: &str) -> Result<(), IdleError> {
+            Err(IdleError::AuthenticationFailed)
+        }
+    }
+
+    fn config(require_os_auth: bool) -> IdleLockConfig {
+        IdleLockConfig {
+            enabled: true,
+            timeout_seconds: 300,
+            require_os_auth,
+            rules: vec![SensitivePaneRule {
+                pattern: r"^ssh\s+.*prod".to_string(),
+                timeout_seconds: Some(60),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_locks_only_sensitive_panes_after_timeout() {
+        let start = Instant::now();
+        let mut monitor = IdleMonitor::new(&config(false), start).unwrap();
+        let prod = PaneActivity { id: Uuid::new_v4(), command: "ssh deploy@prod-db-1", privacy_zone: false };
+        let local = PaneActivity { id: Uuid::new_v4(), command: "cargo build", privacy_zone: false };
+        let private = PaneActivity { id: Uuid::new_v4(), command: "vim notes.md", privacy_zone: true };
+        let panes = [prod, local, private];
+
+        assert!(monitor.tick(start + Duration::from_secs(59), &panes).is_empty());
+        assert_eq!(monitor.tick(start + Duration::from_secs(60), &panes), vec![prod.id]);
+        assert_eq!(monitor.tick(start + Duration::from_secs(300), &panes), vec![private.id]);
+        assert!(!monitor.is_locked(local.id));
+
+        monitor.unlock(prod.id, &SystemAuthenticator, start + Duration::from_secs(301)).unwrap();
+        assert!(!monitor.is_locked(prod.id));
+        assert!(monitor.tick(start + Duration::from_secs(302), &panes).is_empty());
+    }
+
+    #[test]
+    fn test_unlock_requires_authentication_when_configured() {
+        let start = Instant::now();
+        let mut monitor = IdleMonitor::new(&config(true), start).unwrap();
+        let pane = PaneActivity { id: Uuid::new_v4(), command: "ssh prod", privacy_zone: false };
+        monitor.tick(start + Duration::from_secs(60), &[pane]);
+
+        assert!(monitor.unlock(pane.id, &Deny, start).is_err());
+        assert!(monitor.is_locked(pane.id));
+    }
+}
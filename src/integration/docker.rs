@@ -0,0 +1,318 @@
+//! Docker
+//!
+//! Lists containers and images for the Docker panel and for completions.
+//! The Engine API is spoken directly over the daemon's Unix socket as plain
+//! HTTP/1.0, so the daemon closes the connection once it has replied. Where
+//! there is no socket, such as Docker Desktop's named pipe on Windows, the
+//! `docker` CLI is run through [`Integration`] instead.
+//! Following logs and `docker exec` need a terminal and are left to the CLI.
+
+use super::{Integration, IntegrationError};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// `running`, `exited`, `paused`, ...
+    pub state: String,
+    /// Human readable, e.g. `Up 2 hours`.
+    pub status: String,
+}
+
+impl Container {
+    pub fn is_running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub id: String,
+    /// `repository:tag`, or `<none>` for dangling images.
+    pub tag: String,
+    pub size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiContainer {
+    id: String,
+    names: Vec<String>,
+    image: String,
+    state: String,
+    status: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiImage {
+    id: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    size: u64,
+}
+
+/// One line of `docker ps --format '{{json .}}'`.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CliContainer {
+    #[serde(rename = "ID")]
+    id: String,
+    names: String,
+    image: String,
+    state: String,
+    status: String,
+}
+
+/// The daemon's socket from `DOCKER_HOST`, or the default one, if it exists.
+pub fn socket_path() -> Option<PathBuf> {
+    let path = match std::env::var("DOCKER_HOST") {
+        Ok(host) => PathBuf::from(host.strip_prefix("unix://")?),
+        Err(_) => PathBuf::from(DEFAULT_SOCKET),
+    };
+    path.exists().then_some(path)
+}
+
+/// The status code and body of an HTTP response, decoding a chunked body.
+pub fn parse_response(raw: &[u8]) -> Result<(u16, Vec<u8>), IntegrationError> {
+    let malformed = || IntegrationError::Protocol("malformed HTTP response".to_string());
+    let split = raw.windows(4).position(|window| window == b"\r\n\r\n").ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let body = &raw[split + 4..];
+    let status = head.split_whitespace().nth(1).and_then(|code| code.parse().ok()).ok_or_else(malformed)?;
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if !chunked {
+        return Ok((status, body.to_vec()));
+    }
+    let mut decoded = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|window| window == b"\r\n").ok_or_else(malformed)?;
+        let size_text = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size_text.split(';').next().unwrap_or_default().trim(), 16).map_err(|_| malformed())?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok((status, decoded));
+        }
+        decoded.extend_from_slice(rest.get(..size).ok_or_else(malformed)?);
+        rest = rest.get(size + 2..).ok_or_else(malformed)?;
+    }
+}
+
+#[cfg(unix)]
+fn api_get(socket: &std::path::Path, path: &str) -> Result<Vec<u8>, IntegrationError> {
+    use std::io::{Read, Write};
+    let mut stream = std::os::unix::net::UnixStream::connect(socket).map_err(IntegrationError::ExecutionFailed)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(IntegrationError::ExecutionFailed)?;
+    write!(stream, "GET {} HTTP/1.0\r\nHost: docker\r\n\r\n", path).map_err(IntegrationError::ExecutionFailed)?;
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).map_err(IntegrationError::ExecutionFailed)?;
+    let (status, body) = parse_response(&raw)?;
+    if status != 200 {
+        return Err(IntegrationError::Protocol(format!("Docker API returned {}: {}", status, String::from_utf8_lossy(&body).trim())));
+    }
+    Ok(body)
+}
+
+fn parse_api_containers(body: &[u8]) -> Result<Vec<Container>, IntegrationError> {
+    let containers: Vec<ApiContainer> = serde_json::from_slice(body).map_err(|e| IntegrationError::Protocol(e.to_string()))?;
+    Ok(containers
+        .into_iter()
+        .map(|container| Container {
+            id: container.id.chars().take(12).collect(),
+            // The API names containers with a leading slash.
+            name: container.names.first().map(|name| name.trim_start_matches('/').to_string()).unwrap_or_default(),
+            image: container.image,
+            state: container.state,
+            status: container.status,
+        })
+        .collect())
+}
+
+fn parse_api_images(body: &[u8]) -> Result<Vec<Image>, IntegrationError> {
+    let images: Vec<ApiImage> = serde_json::from_slice(body).map_err(|e| IntegrationError::Protocol(e.to_string()))?;
+    Ok(images
+        .into_iter()
+        .map(|image| Image {
+            id: image.id.trim_start_matches("sha256:").chars().take(12).collect(),
+            tag: image.repo_tags.and_then(|tags| tags.into_iter().next()).unwrap_or_else(|| "<none>".to_string()),
+            size: image.size,
+        })
+        .collect())
+}
+
+pub fn parse_cli_containers(output: &str) -> Vec<Container> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<CliContainer>(line).ok())
+        .map(|container| Container {
+            id: container.id,
+            name: container.names.split(',').next().unwrap_or_default().to_string(),
+            image: container.image,
+            state: container.state,
+            status: container.status,
+        })
+        .collect()
+}
+
+/// Every container, running or not.
+pub fn containers() -> Result<Vec<Container>, IntegrationError> {
+    #[cfg(unix)]
+    if let Some(socket) = socket_path() {
+        return parse_api_containers(&api_get(&socket, "/containers/json?all=1")?);
+    }
+    let output = Integration::new("docker").execute(&["ps", "--all", "--format", "{{json .}}"])?;
+    Ok(parse_cli_containers(&output))
+}
+
+/// Tagged and dangling images. Without the socket, images are not listed.
+pub fn images() -> Result<Vec<Image>, IntegrationError> {
+    #[cfg(unix)]
+    if let Some(socket) = socket_path() {
+        return parse_api_images(&api_get(&socket, "/images/json")?);
+    }
+    Ok(Vec::new())
+}
+
+/// The command that follows a container's logs, starting from the last
+/// `tail` lines.
+pub fn logs_command(container: &Container, tail: usize) -> String {
+    format!("docker logs --follow --tail {} {}", tail, container.name)
+}
+
+/// The command for an interactive shell inside a container.
+pub fn exec_command(container: &Container) -> String {
+    // Not every image has bash.
+    format!("docker exec -it {} sh -c 'command -v bash >/dev/null && exec bash || exec sh'", container.name)
+}
+
+/// Containers and images as of one refresh.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DockerSnapshot {
+    pub containers: Vec<Container>,
+    pub images: Vec<Image>,
+}
+
+/// Lists containers and images on a background thread, at most every few
+/// seconds, so completions always have live container names.
+pub struct DockerMonitor {
+    snapshot: Option<Result<DockerSnapshot, String>>,
+    requests: Sender<()>,
+    results: Receiver<Result<DockerSnapshot, String>>,
+    last_refresh: Option<Instant>,
+}
+
+impl Default for DockerMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DockerMonitor {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<()>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            for () in request_rx {
+                let snapshot = containers()
+                    .and_then(|containers| Ok(DockerSnapshot { containers, images: images()? }))
+                    .map_err(|e| e.to_string());
+                if result_tx.send(snapshot).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { snapshot: None, requests, results, last_refresh: None }
+    }
+
+    /// Starts a refresh unless one ran in the last few seconds; `force`
+    /// refreshes anyway, for the panel's refresh key.
+    pub fn request(&mut self, now: Instant, force: bool) {
+        if !force && self.last_refresh.is_some_and(|at| now.saturating_duration_since(at) < REFRESH_INTERVAL) {
+            return;
+        }
+        self.last_refresh = Some(now);
+        self.requests.send(()).ok();
+    }
+
+    /// Takes finished refreshes. Returns whether the container names changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(snapshot) = self.results.try_recv() {
+            changed |= self.container_names() != snapshot.as_ref().map(|snapshot| names(&snapshot.containers)).unwrap_or_default();
+            self.snapshot = Some(snapshot);
+        }
+        changed
+    }
+
+    /// The last listing, or why it failed; `None` until the first one.
+    pub fn snapshot(&self) -> Option<&Result<DockerSnapshot, String>> {
+        self.snapshot.as_ref()
+    }
+
+    pub fn container_names(&self) -> Vec<String> {
+        match &self.snapshot {
+            Some(Ok(snapshot)) => names(&snapshot.containers),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn names(containers: &[Container]) -> Vec<String> {
+    containers.iter().map(|container| container.name.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_responses_are_decoded() {
+        let body = r#"[{"Id":"4f2a9c0e8b1d77aa","Names":["/web"],"Image":"nginx:1.25","State":"running","Status":"Up 2 hours"}]"#;
+        let chunked = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            10,
+            &body[..10],
+            body.len() - 10,
+            &body[10..]
+        );
+        let (status, decoded) = parse_response(chunked.as_bytes()).unwrap();
+        assert_eq!((status, decoded.as_slice()), (200, body.as_bytes()));
+        let containers = parse_api_containers(&decoded).unwrap();
+        assert_eq!(containers[0].id, "4f2a9c0e8b1d");
+        assert_eq!(containers[0].name, "web");
+        assert!(containers[0].is_running());
+
+        let images = br#"[{"Id":"sha256:0123456789abcdef","RepoTags":null,"Size":1024},{"Id":"sha256:fedcba9876543210","RepoTags":["redis:7"],"Size":2048}]"#;
+        let plain = [b"HTTP/1.0 200 OK\r\n\r\n".as_slice(), images].concat();
+        let (_, body) = parse_response(&plain).unwrap();
+        let tags: Vec<String> = parse_api_images(&body).unwrap().into_iter().map(|image| image.tag).collect();
+        assert_eq!(tags, vec!["<none>", "redis:7"]);
+        assert!(parse_response(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_cli_listing_is_parsed() {
+        let output = concat!(
+            r#"{"ID":"4f2a9c0e8b1d","Image":"postgres:16","Names":"db,legacy_db","State":"exited","Status":"Exited (0) 3 days ago"}"#,
+            "\n",
+            "not json\n"
+        );
+        let containers = parse_cli_containers(output);
+        assert_eq!(containers.len(), 1);
+        assert_eq!((containers[0].name.as_str(), containers[0].is_running()), ("db", false));
+        assert_eq!(logs_command(&containers[0], 200), "docker logs --follow --tail 200 db");
+    }
+}
@@ -0,0 +1,240 @@
+//! Open in Editor
+//!
+//! Finds `file:line:col` locations in block output, such as compiler
+//! errors and stack trace frames, and opens them in the user's editor.
+//! Each editor is a template with `{path}`, `{line}` and `{column}`
+//! placeholders. A template that is a single `scheme://` URL is handed to
+//! the system's URL handler; anything else is a command, run detached for
+//! GUI editors or in a new pane for terminal ones. The editor is taken from
+//! the config, then `$VISUAL` or `$EDITOR`, and falls back to VS Code.
+
+use super::{Integration, IntegrationError};
+use crate::config::{EditorTemplateConfig, OpenInEditorConfig};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    /// `src/main.rs:12:5`, `/app/index.js:3`, `C:\src\lib.rs:7:1`. An
+    /// extension is required so that `host:8080` and timestamps are skipped.
+    static ref PATH_LINE: Regex =
+        Regex::new(r"((?:[A-Za-z]:[\\/]|~/|\.{1,2}/|/)?[\w.+@-]+(?:[\\/][\w.+@-]+)*\.[A-Za-z]\w*):(\d+)(?::(\d+))?").unwrap();
+    /// Python tracebacks: `File "app/models.py", line 42, in save`.
+    static ref PYTHON_FRAME: Regex = Regex::new(r#"File "([^"]+)", line (\d+)"#).unwrap();
+}
+
+/// The editors known without configuration: name, template, and whether
+/// it runs inside a terminal.
+const BUILTIN_EDITORS: &[(&str, &str, bool)] = &[
+    ("vscode", "code --goto {path}:{line}:{column}", false),
+    ("vscode-url", "vscode://file/{path}:{line}:{column}", false),
+    ("cursor", "cursor --goto {path}:{line}:{column}", false),
+    ("zed", "zed {path}:{line}:{column}", false),
+    ("sublime", "subl {path}:{line}:{column}", false),
+    ("idea", "idea --line {line} --column {column} {path}", false),
+    ("nvim", "nvim +call\\ cursor({line},{column}) {path}", true),
+    ("vim", "vim +call\\ cursor({line},{column}) {path}", true),
+    ("emacs", "emacs -nw +{line}:{column} {path}", true),
+    ("helix", "hx {path}:{line}:{column}", true),
+    ("nano", "nano +{line},{column} {path}", true),
+];
+
+/// `$EDITOR` binaries whose builtin is named differently.
+const EDITOR_ALIASES: &[(&str, &str)] = &[("code", "vscode"), ("subl", "sublime"), ("hx", "helix"), ("vi", "vim")];
+
+const DEFAULT_EDITOR: &str = "vscode";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLocation {
+    /// As printed, so possibly relative to the command's directory.
+    pub path: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+impl FileLocation {
+    /// The file on this machine, relative paths taken from `cwd`, if it
+    /// exists.
+    pub fn resolve(&self, cwd: Option<&Path>) -> Option<PathBuf> {
+        let path = match self.path.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()?.join(rest),
+            None => PathBuf::from(&self.path),
+        };
+        let path = match cwd {
+            Some(cwd) if path.is_relative() => cwd.join(path),
+            _ => path,
+        };
+        path.is_file().then_some(path)
+    }
+}
+
+impl std::fmt::Display for FileLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "{}:{}:{}", self.path, self.line, column),
+            None => write!(f, "{}:{}", self.path, self.line),
+        }
+    }
+}
+
+/// A location and the byte range of `output` it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocationMatch {
+    pub start: usize,
+    pub end: usize,
+    pub location: FileLocation,
+}
+
+/// Every location in `output`, in order. Ranges never overlap.
+pub fn find_locations(output: &str) -> Vec<LocationMatch> {
+    let mut found: Vec<LocationMatch> = Vec::new();
+    for captures in PATH_LINE.captures_iter(output) {
+        let whole = captures.get(0).unwrap();
+        // The host and port of a URL, or the tail of `file:///...`.
+        if output[..whole.start()].ends_with(['/', ':']) {
+            continue;
+        }
+        let Some(line) = captures[2].parse().ok().filter(|line| *line > 0) else {
+            continue;
+        };
+        found.push(LocationMatch {
+            start: whole.start(),
+            end: whole.end(),
+            location: FileLocation { path: captures[1].to_string(), line, column: captures.get(3).and_then(|column| column.as_str().parse().ok()) },
+        });
+    }
+    for captures in PYTHON_FRAME.captures_iter(output) {
+        let whole = captures.get(0).unwrap();
+        if found.iter().any(|other| other.start < whole.end() && whole.start() < other.end) {
+            continue;
+        }
+        if let Ok(line) = captures[2].parse() {
+            found.push(LocationMatch {
+                start: whole.start(),
+                end: whole.end(),
+                location: FileLocation { path: captures[1].to_string(), line, column: None },
+            });
+        }
+    }
+    found.sort_by_key(|found| found.start);
+    found
+}
+
+/// The template for the configured editor, or the one named by `$VISUAL`
+/// or `$EDITOR` (passed in as `env_editor`), or VS Code's.
+pub fn template(config: &OpenInEditorConfig, env_editor: Option<&str>) -> Result<(String, EditorTemplateConfig), IntegrationError> {
+    let name = match (&config.editor, env_editor) {
+        (Some(name), _) => name.clone(),
+        (None, Some(command)) => {
+            // `$EDITOR` may carry flags, e.g. `code --wait`.
+            let binary = command.split_whitespace().next().unwrap_or_default();
+            let binary = Path::new(binary).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            EDITOR_ALIASES.iter().find(|(alias, _)| *alias == binary).map_or(binary, |(_, name)| name.to_string())
+        }
+        (None, None) => DEFAULT_EDITOR.to_string(),
+    };
+    if let Some(template) = config.templates.get(&name) {
+        return Ok((name, template.clone()));
+    }
+    match BUILTIN_EDITORS.iter().find(|(builtin, _, _)| *builtin == name) {
+        Some((_, command, terminal)) => Ok((name, EditorTemplateConfig { command: command.to_string(), terminal: *terminal })),
+        // An unknown `$EDITOR` is a reason to fall back, an unknown configured editor is not.
+        None if config.editor.is_none() => template(&OpenInEditorConfig::default(), Some(DEFAULT_EDITOR)),
+        None => Err(IntegrationError::NotFound(format!("editor template `{}`", name))),
+    }
+}
+
+/// How a template opens one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditorLaunch {
+    Url(String),
+    /// A program and its arguments, started without a terminal.
+    Command(Vec<String>),
+    /// A shell command for a new pane.
+    Terminal(String),
+}
+
+/// Fills in `template` for `location`, which has been resolved to `path`.
+/// Columns default to 1 for editors that need one.
+pub fn launch(template: &EditorTemplateConfig, path: &Path, location: &FileLocation) -> Result<EditorLaunch, IntegrationError> {
+    let path = path.to_string_lossy();
+    let fill = |text: &str, path: &str| {
+        text.replace("{path}", path)
+            .replace("{line}", &location.line.to_string())
+            .replace("{column}", &location.column.unwrap_or(1).to_string())
+    };
+    let command = template.command.trim();
+    if !command.contains(char::is_whitespace) && command.contains("://") {
+        // `vscode://file/{path}` and the like expect no second slash.
+        return Ok(EditorLaunch::Url(fill(command, path.trim_start_matches('/'))));
+    }
+    let words = shellwords::split(command).map_err(|_| IntegrationError::Protocol(format!("unbalanced quotes in `{}`", command)))?;
+    if words.is_empty() {
+        return Err(IntegrationError::Protocol("empty editor template".to_string()));
+    }
+    // Placeholders are filled per word, so paths with spaces stay one argument.
+    let argv: Vec<String> = words.iter().map(|word| fill(word, &path)).collect();
+    if template.terminal {
+        return Ok(EditorLaunch::Terminal(argv.iter().map(|arg| shellwords::escape(arg)).collect::<Vec<_>>().join(" ")));
+    }
+    Ok(EditorLaunch::Command(argv))
+}
+
+/// Opens a URL or starts a GUI editor. Terminal editors are run in a pane
+/// by the caller.
+pub fn open(launch: &EditorLaunch) -> Result<(), IntegrationError> {
+    match launch {
+        EditorLaunch::Url(url) => webbrowser::open(url).map_err(IntegrationError::ExecutionFailed),
+        EditorLaunch::Command(argv) => {
+            let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+            Integration::new(&argv[0]).spawn(&args)
+        }
+        EditorLaunch::Terminal(command) => Err(IntegrationError::Protocol(format!("`{}` needs a terminal", command))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locations_are_found_in_common_output() {
+        let output = concat!(
+            "error[E0308]: mismatched types\n",
+            "  --> src/app/state.rs:120:9\n",
+            "    at handler (/srv/app/index.js:3:14)\n",
+            "  File \"app/models.py\", line 42, in save\n",
+            "listening on http://localhost:8080 at 12:30:01\n",
+        );
+        let locations: Vec<String> = find_locations(output).into_iter().map(|found| found.location.to_string()).collect();
+        assert_eq!(locations, vec!["src/app/state.rs:120:9", "/srv/app/index.js:3:14", "app/models.py:42"]);
+
+        let found = &find_locations(output)[0];
+        assert_eq!(&output[found.start..found.end], "src/app/state.rs:120:9");
+    }
+
+    #[test]
+    fn test_templates_are_filled_in() {
+        let location = FileLocation { path: "src/main.rs".to_string(), line: 12, column: None };
+        let path = Path::new("/work/my project/src/main.rs");
+
+        let config = OpenInEditorConfig::default();
+        let (name, vscode) = template(&config, Some("/usr/bin/code --wait")).unwrap();
+        assert_eq!(name, "vscode");
+        assert_eq!(
+            launch(&vscode, path, &location).unwrap(),
+            EditorLaunch::Command(vec!["code".to_string(), "--goto".to_string(), "/work/my project/src/main.rs:12:1".to_string()])
+        );
+
+        let (_, helix) = template(&config, Some("hx")).unwrap();
+        assert_eq!(launch(&helix, path, &location).unwrap(), EditorLaunch::Terminal("hx /work/my\\ project/src/main.rs:12:1".to_string()));
+
+        let config = OpenInEditorConfig { editor: Some("vscode-url".to_string()), ..Default::default() };
+        let (_, url) = template(&config, Some("nvim")).unwrap();
+        assert_eq!(launch(&url, Path::new("/a.rs"), &location).unwrap(), EditorLaunch::Url("vscode://file/a.rs:12:1".to_string()));
+
+        let config = OpenInEditorConfig { editor: Some("notepad++".to_string()), ..Default::default() };
+        assert!(template(&config, None).is_err());
+        assert_eq!(template(&OpenInEditorConfig::default(), Some("ed")).unwrap().0, "vscode");
+    }
+}
@@ -0,0 +1,113 @@
+//! Code Formatters
+//!
+//! Runs the formatter for a file's language — rustfmt, black or prettier
+//! out of the box — with the code on stdin and the formatted code read
+//! from stdout, so nothing is written until the diff has been seen. The
+//! language comes from `CodeBlock::language`, a file extension or a fence
+//! name like `python`. A command is a template whose `{path}` is the file
+//! being formatted, for formatters such as prettier that infer the syntax
+//! from it.
+
+use super::{Integration, IntegrationError};
+use crate::code::CodeBlock;
+use crate::config::FormatConfig;
+use std::path::Path;
+
+/// The formatters known without configuration: languages, then command.
+const BUILTIN_FORMATTERS: &[(&[&str], &str)] = &[
+    (&["rust", "rs"], "rustfmt --edition 2021 --emit stdout"),
+    (&["python", "py", "pyi"], "black --quiet -"),
+    (
+        &[
+            "javascript", "js", "jsx", "mjs", "cjs", "typescript", "ts", "tsx", "json", "css", "scss", "less", "html",
+            "vue", "markdown", "md", "yaml", "yml", "graphql",
+        ],
+        "prettier --stdin-filepath {path}",
+    ),
+];
+
+/// A formatter's run over a code block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formatted {
+    /// The program that ran, e.g. `rustfmt`.
+    pub formatter: String,
+    pub content: String,
+    /// `CodeBlock::diff` from the block to `content`.
+    pub diff: String,
+}
+
+impl Formatted {
+    /// Whether the code was already formatted.
+    pub fn unchanged(&self) -> bool {
+        !self.diff.lines().any(|line| line.starts_with(['-', '+']))
+    }
+}
+
+/// The command template for `language`: the configured one, else the
+/// builtin. Names are matched ignoring case.
+pub fn formatter(config: &FormatConfig, language: &str) -> Option<String> {
+    let language = language.trim().to_lowercase();
+    if let Some((_, command)) = config.formatters.iter().find(|(name, _)| name.to_lowercase() == language) {
+        return Some(command.clone());
+    }
+    BUILTIN_FORMATTERS.iter().find(|(languages, _)| languages.contains(&language.as_str())).map(|(_, command)| command.to_string())
+}
+
+/// The program and arguments for `template`, with `{path}` filled in per
+/// word so paths with spaces stay one argument.
+pub fn command(template: &str, path: &Path) -> Result<Vec<String>, IntegrationError> {
+    let words = shellwords::split(template).map_err(|_| IntegrationError::Protocol(format!("unbalanced quotes in `{}`", template)))?;
+    if words.is_empty() {
+        return Err(IntegrationError::Protocol("empty formatter command".to_string()));
+    }
+    let path = path.to_string_lossy();
+    Ok(words.iter().map(|word| word.replace("{path}", &path)).collect())
+}
+
+/// Formats `block`, which was read from `path`. Without a path, prettier
+/// and the like are given a made-up name with the block's extension.
+pub fn format(config: &FormatConfig, block: &CodeBlock, path: Option<&Path>) -> Result<Formatted, IntegrationError> {
+    let language = block.language.as_deref().unwrap_or_default();
+    let template = formatter(config, language)
+        .ok_or_else(|| IntegrationError::NotFound(format!("a formatter for `{}`", if language.is_empty() { "plain text" } else { language })))?;
+    let stand_in = Path::new("stdin").with_extension(language);
+    let argv = command(&template, path.unwrap_or(&stand_in))?;
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let content = Integration::new(&argv[0]).execute_with_input(&args, &block.content)?;
+    Ok(Formatted { formatter: argv[0].clone(), diff: block.diff(&content), content })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_formatter_by_language() {
+        let mut config = FormatConfig::default();
+        assert_eq!(formatter(&config, "RS").as_deref(), Some("rustfmt --edition 2021 --emit stdout"));
+        assert_eq!(formatter(&config, "tsx").as_deref(), Some("prettier --stdin-filepath {path}"));
+        assert_eq!(formatter(&config, "cobol"), None);
+
+        config.formatters = BTreeMap::from([("python".to_string(), "ruff format --stdin-filename {path} -".to_string())]);
+        let template = formatter(&config, "Python").unwrap();
+        assert_eq!(
+            command(&template, Path::new("/src/my app/main.py")).unwrap(),
+            ["ruff", "format", "--stdin-filename", "/src/my app/main.py", "-"]
+        );
+    }
+
+    #[test]
+    fn test_format_pipes_code_through_the_command() {
+        let config = FormatConfig { formatters: BTreeMap::from([("txt".to_string(), "tr a-z A-Z".to_string())]) };
+        let block = CodeBlock { content: "keep\nshout\n".to_string(), language: Some("txt".to_string()) };
+        let formatted = format(&config, &block, None).unwrap();
+        assert_eq!(formatted.formatter, "tr");
+        assert_eq!(formatted.content, "KEEP\nSHOUT\n");
+        assert!(formatted.diff.contains("-shout\n") && formatted.diff.contains("+SHOUT\n"));
+        assert!(!formatted.unchanged());
+
+        let plain = CodeBlock { content: String::new(), language: None };
+        assert!(matches!(format(&config, &plain, None), Err(IntegrationError::NotFound(_))));
+    }
+}
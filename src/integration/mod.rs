@@ -3,6 +3,11 @@
 //! This module provides a framework for integrating with external tools
 //! and services, such as language servers, debuggers, and other developer tools.
 
+pub mod docker;
+pub mod editor;
+pub mod format;
+
+use std::io::Write;
 use std::process::{Command, Stdio};
 use thiserror::Error;
 
@@ -14,6 +19,10 @@ pub enum IntegrationError {
     ExecutionFailed(std::io::Error),
     #[error("Command returned non-zero exit code: {0}")]
     NonZeroExit(i32),
+    #[error("Command failed: {0}")]
+    Failed(String),
+    #[error("Unexpected response: {0}")]
+    Protocol(String),
 }
 
 pub struct Integration {
@@ -31,20 +40,66 @@ impl Integration {
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::NotFound {
-                    IntegrationError::NotFound(self.command.clone())
-                } else {
-                    IntegrationError::ExecutionFailed(e)
-                }
-            })?;
+            .map_err(|e| self.spawn_error(e))?;
+
+        if !output.status.success() {
+            return Err(IntegrationError::NonZeroExit(output.status.code().unwrap_or(1)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Runs the command with `input` on stdin and returns its stdout. When
+    /// it fails, what it printed to stderr is the error.
+    pub fn execute_with_input(&self, args: &[&str], input: &str) -> Result<String, IntegrationError> {
+        let mut child = Command::new(&self.command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+        // Written from another thread so a large input cannot deadlock
+        // against a full stdout pipe.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output().map_err(IntegrationError::ExecutionFailed)?;
+        // A command may exit without reading all of its input.
+        writer.join().ok();
 
         if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if !stderr.is_empty() {
+                return Err(IntegrationError::Failed(stderr));
+            }
             return Err(IntegrationError::NonZeroExit(output.status.code().unwrap_or(1)));
         }
 
         Ok(String::from_utf8_lossy(&output.stdout).to_string())
     }
+
+    /// Starts the command without waiting for it, for GUI programs that
+    /// outlive the call. The child is reaped on a background thread.
+    pub fn spawn(&self, args: &[&str]) -> Result<(), IntegrationError> {
+        let mut child = Command::new(&self.command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+
+    fn spawn_error(&self, e: std::io::Error) -> IntegrationError {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            IntegrationError::NotFound(self.command.clone())
+        } else {
+            IntegrationError::ExecutionFailed(e)
+        }
+    }
 }
 
 #[cfg(test)]
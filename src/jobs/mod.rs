@@ -0,0 +1,345 @@
+//! Background Jobs
+//!
+//! Tracks the processes started in panes so they can be watched and managed
+//! from the jobs panel. Two kinds of job are listed: the shell's own — every
+//! process whose parent is a pane's shell, whether in the foreground,
+//! backgrounded with `&` or stopped with Ctrl+Z — and detached commands,
+//! which are started outside the shell with their output captured, so the
+//! pane stays free while they run and their output can be re-attached to a
+//! block at any time. CPU and memory figures come from `sysinfo` and are
+//! refreshed at most once a second.
+
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessStatus, System, SystemExt};
+use thiserror::Error;
+use uuid::Uuid;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum JobError {
+    #[error("Failed to start job: {0}")]
+    Spawn(#[from] std::io::Error),
+    #[error("No job with pid {0}")]
+    NotFound(u32),
+    #[error("Failed to signal job: {0}")]
+    Signal(std::io::Error),
+    #[error("Only detached jobs can be re-attached; use fg in the pane")]
+    NotDetached,
+    #[error("Signals are not supported on this platform")]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    /// Started by a pane's shell.
+    Shell,
+    /// Started outside the shell, with its output captured.
+    Detached,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// Detached jobs stay listed after exiting, with their exit code if they
+    /// were not killed by a signal.
+    Exited(Option<i32>),
+}
+
+impl JobState {
+    pub fn label(&self) -> String {
+        match self {
+            JobState::Running => "running".to_string(),
+            JobState::Stopped => "stopped".to_string(),
+            JobState::Exited(Some(code)) => format!("exited {}", code),
+            JobState::Exited(None) => "killed".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobSignal {
+    Suspend,
+    Resume,
+    Kill,
+}
+
+/// A job as listed in the panel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobInfo {
+    pub pid: u32,
+    pub pane_id: Uuid,
+    pub command: String,
+    pub kind: JobKind,
+    pub state: JobState,
+    /// Percent of one core.
+    pub cpu: f32,
+    /// Resident memory in bytes.
+    pub memory: u64,
+}
+
+struct DetachedJob {
+    pane_id: Uuid,
+    command: String,
+    child: Child,
+    output: Arc<Mutex<Vec<u8>>>,
+    exit: Option<Option<i32>>,
+    /// The block the output is re-attached to, and how much of the output
+    /// it already shows.
+    attached: Option<(Uuid, usize)>,
+}
+
+/// Output for a block a detached job is attached to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachedOutput {
+    pub block_id: Uuid,
+    pub text: String,
+}
+
+pub struct JobManager {
+    system: System,
+    last_refresh: Option<Instant>,
+    detached: Vec<DetachedJob>,
+    jobs: Vec<JobInfo>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self { system: System::new(), last_refresh: None, detached: Vec::new(), jobs: Vec::new() }
+    }
+
+    /// The jobs found by the last refresh.
+    pub fn jobs(&self) -> &[JobInfo] {
+        &self.jobs
+    }
+
+    /// Starts `command` outside the shell, capturing stdout and stderr.
+    pub fn spawn_detached(&mut self, pane_id: Uuid, command: &str, cwd: Option<&Path>) -> Result<u32, JobError> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut cmd = Command::new(shell);
+        cmd.args([flag, command]).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        if let Some(cwd) = cwd {
+            cmd.current_dir(cwd);
+        }
+        // Its own process group, so signals reach everything `sh -c` starts.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut child = cmd.spawn()?;
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stdout = child.stdout.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+        let stderr = child.stderr.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+        for mut pipe in stdout.into_iter().chain(stderr) {
+            let output = output.clone();
+            thread::spawn(move || {
+                let mut chunk = [0u8; 4096];
+                while let Ok(read @ 1..) = pipe.read(&mut chunk) {
+                    output.lock().unwrap().extend_from_slice(&chunk[..read]);
+                }
+            });
+        }
+        let pid = child.id();
+        self.detached.push(DetachedJob { pane_id, command: command.to_string(), child, output, exit: None, attached: None });
+        self.last_refresh = None;
+        Ok(pid)
+    }
+
+    /// Re-lists the jobs of the given `(pane, shell pid)` pairs and the
+    /// detached jobs, unless the list was refreshed within the last second.
+    pub fn refresh(&mut self, shells: &[(Uuid, u32)], now: Instant) {
+        if self.last_refresh.is_some_and(|last| now.duration_since(last) < REFRESH_INTERVAL) {
+            return;
+        }
+        self.last_refresh = Some(now);
+        self.system.refresh_processes();
+        for job in &mut self.detached {
+            if job.exit.is_none() {
+                if let Ok(Some(status)) = job.child.try_wait() {
+                    job.exit = Some(status.code());
+                }
+            }
+        }
+
+        let mut jobs = Vec::new();
+        for (pid, process) in self.system.processes() {
+            let Some(parent) = process.parent() else {
+                continue;
+            };
+            let Some((pane_id, _)) = shells.iter().find(|(_, shell)| parent.as_u32() == *shell) else {
+                continue;
+            };
+            jobs.push(JobInfo {
+                pid: pid.as_u32(),
+                pane_id: *pane_id,
+                command: process.cmd().join(" "),
+                kind: JobKind::Shell,
+                state: if process.status() == ProcessStatus::Stop { JobState::Stopped } else { JobState::Running },
+                cpu: process.cpu_usage(),
+                memory: process.memory(),
+            });
+        }
+        for job in &self.detached {
+            let pid = job.child.id();
+            let process = self.system.process(Pid::from_u32(pid)).filter(|_| job.exit.is_none());
+            let state = match (job.exit, process) {
+                (Some(code), _) => JobState::Exited(code),
+                (None, Some(process)) if process.status() == ProcessStatus::Stop => JobState::Stopped,
+                _ => JobState::Running,
+            };
+            jobs.push(JobInfo {
+                pid,
+                pane_id: job.pane_id,
+                command: job.command.clone(),
+                kind: JobKind::Detached,
+                state,
+                cpu: process.map_or(0.0, |process| process.cpu_usage()),
+                memory: process.map_or(0, |process| process.memory()),
+            });
+        }
+        jobs.sort_by_key(|job| job.pid);
+        self.jobs = jobs;
+    }
+
+    /// Stops, continues or terminates a job and its process group.
+    #[cfg(unix)]
+    pub fn signal(&mut self, pid: u32, signal: JobSignal) -> Result<(), JobError> {
+        if !self.jobs.iter().any(|job| job.pid == pid && !matches!(job.state, JobState::Exited(_))) {
+            return Err(JobError::NotFound(pid));
+        }
+        let signal = match signal {
+            JobSignal::Suspend => libc::SIGSTOP,
+            JobSignal::Resume => libc::SIGCONT,
+            JobSignal::Kill => libc::SIGTERM,
+        };
+        let pid = pid as libc::pid_t;
+        // SAFETY: getpgid(2) and kill(2) take no pointers.
+        let target = if unsafe { libc::getpgid(pid) } == pid { -pid } else { pid };
+        if unsafe { libc::kill(target, signal) } != 0 {
+            return Err(JobError::Signal(std::io::Error::last_os_error()));
+        }
+        self.last_refresh = None;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn signal(&mut self, _pid: u32, _signal: JobSignal) -> Result<(), JobError> {
+        Err(JobError::Unsupported)
+    }
+
+    /// Sends a detached job's output to `block_id`, starting with everything
+    /// captured so far.
+    pub fn attach(&mut self, pid: u32, block_id: Uuid) -> Result<(), JobError> {
+        if self.jobs.iter().any(|job| job.pid == pid && job.kind == JobKind::Shell) {
+            return Err(JobError::NotDetached);
+        }
+        let job = self.detached.iter_mut().find(|job| job.child.id() == pid).ok_or(JobError::NotFound(pid))?;
+        job.attached = Some((block_id, 0));
+        Ok(())
+    }
+
+    /// The command of a detached job, for the block it is attached to.
+    pub fn detached_command(&self, pid: u32) -> Option<&str> {
+        self.detached.iter().find(|job| job.child.id() == pid).map(|job| job.command.as_str())
+    }
+
+    /// The full output of attached jobs that printed more since last asked.
+    pub fn attached_output(&mut self) -> Vec<AttachedOutput> {
+        let mut updates = Vec::new();
+        for job in &mut self.detached {
+            let Some((block_id, shown)) = &mut job.attached else {
+                continue;
+            };
+            let output = job.output.lock().unwrap();
+            if output.len() != *shown {
+                *shown = output.len();
+                updates.push(AttachedOutput { block_id: *block_id, text: String::from_utf8_lossy(&output).into_owned() });
+            }
+        }
+        updates
+    }
+
+    /// Forgets a detached job that has exited.
+    pub fn dismiss(&mut self, pid: u32) {
+        self.detached.retain(|job| job.child.id() != pid || job.exit.is_none());
+        self.jobs.retain(|job| job.pid != pid || !matches!(job.state, JobState::Exited(_)));
+    }
+
+    /// Stops every detached job on quit; shell jobs end with their shells.
+    pub fn kill_detached(&mut self) {
+        for job in self.detached.iter_mut().filter(|job| job.exit.is_none()) {
+            job.child.kill().ok();
+            job.child.wait().ok();
+        }
+    }
+}
+
+/// A byte count for the panel, e.g. `12.5 MB`.
+pub fn format_memory(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detached_output_is_reattached() {
+        let mut jobs = JobManager::new();
+        let pane_id = Uuid::new_v4();
+        let pid = jobs.spawn_detached(pane_id, "echo built; echo warning >&2", None).unwrap();
+        let block_id = Uuid::new_v4();
+        jobs.attach(pid, block_id).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut output = String::new();
+        while Instant::now() < deadline && !(output.contains("built") && output.contains("warning")) {
+            if let Some(update) = jobs.attached_output().pop() {
+                assert_eq!(update.block_id, block_id);
+                output = update.text;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(output.contains("built\n") && output.contains("warning\n"));
+        assert!(jobs.attached_output().is_empty());
+
+        let mut at = Instant::now();
+        while jobs.jobs().iter().all(|job| job.state != JobState::Exited(Some(0))) && Instant::now() < deadline {
+            at += REFRESH_INTERVAL;
+            jobs.refresh(&[], at);
+            thread::sleep(Duration::from_millis(20));
+        }
+        let job = jobs.jobs().iter().find(|job| job.pid == pid).unwrap();
+        assert_eq!((job.kind, job.state, job.pane_id), (JobKind::Detached, JobState::Exited(Some(0)), pane_id));
+        jobs.dismiss(pid);
+        assert!(jobs.jobs().is_empty());
+    }
+
+    #[test]
+    fn test_memory_is_formatted() {
+        assert_eq!(format_memory(512), "512 B");
+        assert_eq!(format_memory(1536), "1.5 KB");
+        assert_eq!(format_memory(12 * 1024 * 1024 + 512 * 1024), "12.5 MB");
+    }
+}
@@ -0,0 +1,381 @@
+//! Kubernetes
+//!
+//! Reads the kubeconfig `kubectl` itself uses, `KUBECONFIG` or
+//! `~/.kube/config`, to show the current context and namespace in the
+//! prompt. Files listed in `KUBECONFIG` are merged the way `kubectl` merges
+//! them: the first file to set a value wins. In contexts that look like
+//! production, `kubectl` commands matching the destructive command rules
+//! are held for a second confirmation. Pod, deployment and namespace names
+//! for completions are listed with `kubectl` on a background thread.
+
+use crate::integration::{Integration, IntegrationError};
+use crate::rules::{Action, Rule, RuleSet};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+/// How often the kubeconfig is checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// How long listed resources are offered before they are listed again.
+const RESOURCES_TTL: Duration = Duration::from_secs(30);
+
+/// Subcommands whose first argument is a pod.
+const POD_SUBCOMMANDS: &[&str] = &["logs", "exec", "attach", "port-forward", "cp"];
+/// Subcommands taking a resource type, then names of that type.
+const TYPED_SUBCOMMANDS: &[&str] = &["get", "describe", "delete", "edit", "label", "annotate", "patch", "scale", "autoscale", "expose", "set"];
+/// Flags whose value is the next word.
+const VALUE_FLAGS: &[&str] = &["-n", "--namespace", "--context", "-c", "--container", "-l", "--selector", "-o", "--output"];
+
+#[derive(Error, Debug)]
+pub enum K8sError {
+    #[error("Failed to read kubeconfig: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid kubeconfig: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct Kubeconfig {
+    #[serde(default)]
+    current_context: Option<String>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+}
+
+#[derive(Deserialize)]
+struct NamedContext {
+    name: String,
+    #[serde(default)]
+    context: ContextFields,
+}
+
+#[derive(Deserialize, Default)]
+struct ContextFields {
+    #[serde(default)]
+    cluster: String,
+    namespace: Option<String>,
+}
+
+/// The context `kubectl` talks to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KubeContext {
+    pub name: String,
+    pub cluster: String,
+    pub namespace: String,
+}
+
+impl KubeContext {
+    /// Whether the context or its cluster matches one of `patterns`,
+    /// ignoring case.
+    pub fn is_production(&self, patterns: &[String]) -> bool {
+        let name = self.name.to_lowercase();
+        let cluster = self.cluster.to_lowercase();
+        patterns.iter().map(|pattern| pattern.to_lowercase()).any(|pattern| name.contains(&pattern) || cluster.contains(&pattern))
+    }
+
+    /// The prompt chip.
+    pub fn chip(&self) -> String {
+        format!(" ⎈ {}:{} ", self.name, self.namespace)
+    }
+}
+
+/// The kubeconfig files `kubectl` reads, in order.
+pub fn kubeconfig_paths() -> Vec<PathBuf> {
+    match std::env::var_os("KUBECONFIG") {
+        Some(paths) if !paths.is_empty() => std::env::split_paths(&paths).collect(),
+        _ => dirs::home_dir().map(|home| home.join(".kube").join("config")).into_iter().collect(),
+    }
+}
+
+/// The current context of the merged kubeconfig `documents`, if one is set
+/// and defined.
+pub fn parse_context(documents: &[String]) -> Result<Option<KubeContext>, K8sError> {
+    let configs = documents
+        .iter()
+        .filter(|document| !document.trim().is_empty())
+        .map(|document| serde_yaml::from_str::<Kubeconfig>(document))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(current) = configs.iter().find_map(|config| config.current_context.clone()).filter(|name| !name.is_empty()) else {
+        return Ok(None);
+    };
+    let context = configs.into_iter().flat_map(|config| config.contexts).find(|context| context.name == current);
+    Ok(context.map(|context| KubeContext {
+        name: context.name,
+        cluster: context.context.cluster,
+        namespace: context.context.namespace.unwrap_or_else(|| "default".to_string()),
+    }))
+}
+
+/// Rules matching `kubectl` commands that change or remove resources.
+pub fn destructive_rules() -> RuleSet {
+    let mut rules = RuleSet::new();
+    let defaults = [
+        ("kubectl_delete", "Deletes resources", "kubectl delete"),
+        ("kubectl_drain", "Evicts every pod from a node", "kubectl drain"),
+        ("kubectl_cordon", "Stops scheduling pods onto a node", "kubectl cordon"),
+        ("kubectl_scale", "Changes the replica count", "kubectl scale"),
+        ("kubectl_apply", "Applies changes to resources", "kubectl apply"),
+        ("kubectl_replace", "Replaces resources", "kubectl replace"),
+        ("kubectl_patch", "Patches resources", "kubectl patch"),
+        ("kubectl_edit", "Edits resources in place", "kubectl edit"),
+        ("kubectl_rollout", "Restarts or rolls back workloads", "kubectl rollout"),
+    ];
+    for (name, description, target) in defaults {
+        rules.add_rule(Rule {
+            name: name.to_string(),
+            description: description.to_string(),
+            action: Action::Warn,
+            target: target.to_string(),
+        });
+    }
+    rules
+}
+
+/// A warning for `command` when it is destructive and `context` looks like
+/// production.
+pub fn warning(command: &str, context: &KubeContext, production: &[String], rules: &RuleSet) -> Option<String> {
+    // Rules match on single spaces, as in `kubectl delete`.
+    let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !context.is_production(production) || rules.evaluate(&command) == Action::Allow {
+        return None;
+    }
+    Some(format!("This changes resources in the production context {}", context.name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    Pod,
+    Deployment,
+    Namespace,
+}
+
+impl ResourceKind {
+    fn from_type(name: &str) -> Option<Self> {
+        match name {
+            "pod" | "pods" | "po" => Some(ResourceKind::Pod),
+            "deployment" | "deployments" | "deploy" => Some(ResourceKind::Deployment),
+            "namespace" | "namespaces" | "ns" => Some(ResourceKind::Namespace),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResourceKind::Pod => "Pod",
+            ResourceKind::Deployment => "Deployment",
+            ResourceKind::Namespace => "Namespace",
+        }
+    }
+
+    fn kubectl_type(&self) -> &'static str {
+        match self {
+            ResourceKind::Pod => "pods",
+            ResourceKind::Deployment => "deployments",
+            ResourceKind::Namespace => "namespaces",
+        }
+    }
+}
+
+/// What the next word of a `kubectl` command line names, given the words
+/// before it, starting with `kubectl`.
+pub fn completion_kind(words: &[&str]) -> Option<ResourceKind> {
+    let last = *words.last()?;
+    if last == "-n" || last == "--namespace" {
+        return Some(ResourceKind::Namespace);
+    }
+    let mut args = Vec::new();
+    let mut skip_value = false;
+    for word in words.iter().skip(1) {
+        if skip_value {
+            skip_value = false;
+        } else if word.starts_with('-') {
+            skip_value = VALUE_FLAGS.contains(word);
+        } else {
+            args.push(*word);
+        }
+    }
+    match args.as_slice() {
+        [subcommand, ..] if POD_SUBCOMMANDS.contains(subcommand) => Some(ResourceKind::Pod),
+        ["rollout", _, kind, ..] => ResourceKind::from_type(kind),
+        [subcommand, kind, ..] if TYPED_SUBCOMMANDS.contains(subcommand) => ResourceKind::from_type(kind),
+        _ => None,
+    }
+}
+
+/// Resource names in the current context and namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KubeResources {
+    pub pods: Vec<String>,
+    pub deployments: Vec<String>,
+    pub namespaces: Vec<String>,
+}
+
+impl KubeResources {
+    pub fn names(&self, kind: ResourceKind) -> &[String] {
+        match kind {
+            ResourceKind::Pod => &self.pods,
+            ResourceKind::Deployment => &self.deployments,
+            ResourceKind::Namespace => &self.namespaces,
+        }
+    }
+}
+
+fn list(context: &KubeContext, kind: ResourceKind) -> Result<Vec<String>, IntegrationError> {
+    let output = Integration::new("kubectl").execute(&[
+        "--context",
+        &context.name,
+        "--namespace",
+        &context.namespace,
+        "--request-timeout=5s",
+        "get",
+        kind.kubectl_type(),
+        "--output",
+        "jsonpath={.items[*].metadata.name}",
+    ])?;
+    Ok(output.split_whitespace().map(str::to_string).collect())
+}
+
+fn fetch_resources(context: &KubeContext) -> Result<KubeResources, IntegrationError> {
+    Ok(KubeResources {
+        pods: list(context, ResourceKind::Pod)?,
+        deployments: list(context, ResourceKind::Deployment)?,
+        // Listing namespaces is often forbidden to namespaced users.
+        namespaces: list(context, ResourceKind::Namespace).unwrap_or_default(),
+    })
+}
+
+/// Follows the current context as the kubeconfig changes, and lists its
+/// resources on a background thread when completions need them.
+pub struct KubeMonitor {
+    context: Option<KubeContext>,
+    modified: Vec<Option<SystemTime>>,
+    checked_at: Option<Instant>,
+    resources: KubeResources,
+    /// The context the resources were listed in, and when.
+    fetched: Option<(KubeContext, Instant)>,
+    requests: Sender<KubeContext>,
+    results: Receiver<(KubeContext, Result<KubeResources, IntegrationError>)>,
+}
+
+impl Default for KubeMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KubeMonitor {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<KubeContext>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            for context in request_rx {
+                let resources = fetch_resources(&context);
+                if result_tx.send((context, resources)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { context: None, modified: Vec::new(), checked_at: None, resources: KubeResources::default(), fetched: None, requests, results }
+    }
+
+    pub fn context(&self) -> Option<&KubeContext> {
+        self.context.as_ref()
+    }
+
+    /// Re-reads the kubeconfig when one of its files changed, checking at
+    /// most every couple of seconds.
+    pub fn refresh(&mut self, now: Instant) {
+        if self.checked_at.is_some_and(|at| now.saturating_duration_since(at) < CHECK_INTERVAL) {
+            return;
+        }
+        self.checked_at = Some(now);
+        let paths = kubeconfig_paths();
+        let modified: Vec<Option<SystemTime>> = paths.iter().map(|path| path.metadata().and_then(|meta| meta.modified()).ok()).collect();
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        let documents: Vec<String> = paths.iter().filter_map(|path| std::fs::read_to_string(path).ok()).collect();
+        self.context = match parse_context(&documents) {
+            Ok(context) => context,
+            Err(e) => {
+                log::warn!("Ignoring kubeconfig: {}", e);
+                None
+            }
+        };
+    }
+
+    /// Lists the current context's resources unless they were listed
+    /// recently.
+    pub fn request_resources(&mut self, now: Instant) {
+        let Some(context) = self.context.clone() else {
+            return;
+        };
+        let fresh = self.fetched.as_ref().is_some_and(|(fetched, at)| *fetched == context && now.saturating_duration_since(*at) < RESOURCES_TTL);
+        if fresh {
+            return;
+        }
+        self.fetched = Some((context.clone(), now));
+        self.requests.send(context).ok();
+    }
+
+    /// Takes finished listings. Returns whether the resources changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((context, resources)) = self.results.try_recv() {
+            if self.context.as_ref() != Some(&context) {
+                continue;
+            }
+            match resources {
+                Ok(resources) => {
+                    changed |= resources != self.resources;
+                    self.resources = resources;
+                }
+                Err(e) => log::debug!("Could not list Kubernetes resources: {}", e),
+            }
+        }
+        changed
+    }
+
+    pub fn resources(&self) -> &KubeResources {
+        &self.resources
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merged_context_and_warnings() {
+        let first = "current-context: prod-eu\ncontexts:\n- name: staging\n  context:\n    cluster: staging\n".to_string();
+        let second = "current-context: staging\ncontexts:\n- name: prod-eu\n  context:\n    cluster: gke-eu\n    namespace: payments\n".to_string();
+        let context = parse_context(&[first, String::new(), second]).unwrap().unwrap();
+        assert_eq!(context, KubeContext { name: "prod-eu".to_string(), cluster: "gke-eu".to_string(), namespace: "payments".to_string() });
+        assert_eq!(context.chip(), " ⎈ prod-eu:payments ");
+        assert_eq!(parse_context(&["contexts: []\n".to_string()]).unwrap(), None);
+
+        let production = vec!["PROD".to_string()];
+        let rules = destructive_rules();
+        assert!(warning("kubectl  delete pod web-1", &context, &production, &rules).is_some());
+        assert!(warning("kubectl get pods", &context, &production, &rules).is_none());
+        let staging = KubeContext { name: "staging".to_string(), cluster: "staging".to_string(), namespace: "default".to_string() };
+        assert!(warning("kubectl delete pod web-1", &staging, &production, &rules).is_none());
+    }
+
+    #[test]
+    fn test_completion_kind_follows_the_command() {
+        assert_eq!(completion_kind(&["kubectl", "logs"]), Some(ResourceKind::Pod));
+        assert_eq!(completion_kind(&["kubectl", "-n", "web", "exec", "-it"]), Some(ResourceKind::Pod));
+        assert_eq!(completion_kind(&["kubectl", "get", "deploy"]), Some(ResourceKind::Deployment));
+        assert_eq!(completion_kind(&["kubectl", "rollout", "restart", "deployment"]), Some(ResourceKind::Deployment));
+        assert_eq!(completion_kind(&["kubectl", "get", "pods", "--namespace"]), Some(ResourceKind::Namespace));
+        assert_eq!(completion_kind(&["kubectl", "delete", "ns"]), Some(ResourceKind::Namespace));
+        assert_eq!(completion_kind(&["kubectl", "get"]), None);
+        assert_eq!(completion_kind(&["kubectl", "apply", "-f"]), None);
+    }
+}
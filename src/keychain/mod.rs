@@ -0,0 +1,90 @@
+//! Keychain Access
+//!
+//! This module stores and retrieves credentials (API tokens for share targets
+//! and other integrations) in the operating system keychain. Environment
+//! variables named `WARPISH_<KEY>` take precedence so credentials can also be
+//! supplied in CI or headless sessions.
+
+use thiserror::Error;
+
+/// Service name under which all credentials are stored.
+const SERVICE: &str = "warpish";
+
+#[derive(Error, Debug)]
+pub enum KeychainError {
+    #[error("No credential stored for '{0}'")]
+    NotFound(String),
+    #[error("Keychain error: {0}")]
+    Backend(#[from] keyring::Error),
+}
+
+pub struct Keychain {
+    service: String,
+}
+
+impl Keychain {
+    pub fn new() -> Self {
+        Self {
+            service: SERVICE.to_string(),
+        }
+    }
+
+    /// Returns the credential for `key`, checking the environment first.
+    pub fn get(&self, key: &str) -> Result<String, KeychainError> {
+        if let Ok(value) = std::env::var(env_var_name(key)) {
+            if !value.is_empty() {
+                return Ok(value);
+            }
+        }
+        match keyring::Entry::new(&self.service, key)?.get_password() {
+            Ok(value) => Ok(value),
+            Err(keyring::Error::NoEntry) => Err(KeychainError::NotFound(key.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<(), KeychainError> {
+        keyring::Entry::new(&self.service, key)?.set_password(value)?;
+        Ok(())
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), KeychainError> {
+        match keyring::Entry::new(&self.service, key)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Default for Keychain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Environment variable that overrides the keychain entry for `key`.
+pub fn env_var_name(key: &str) -> String {
+    let normalized: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    format!("WARPISH_{}", normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(env_var_name("github_token"), "WARPISH_GITHUB_TOKEN");
+        assert_eq!(env_var_name("paste.work-token"), "WARPISH_PASTE_WORK_TOKEN");
+    }
+
+    #[test]
+    fn test_environment_overrides_keychain() {
+        std::env::set_var("WARPISH_TEST_SHARE_TOKEN", "from-env");
+        assert_eq!(Keychain::new().get("test_share_token").unwrap(), "from-env");
+        std::env::remove_var("WARPISH_TEST_SHARE_TOKEN");
+    }
+}
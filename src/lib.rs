@@ -2,12 +2,13 @@
 pub mod app;
 pub mod ui;
 pub mod agent;
-pub mod pty;
+pub use warpish_core::pty;
 pub mod config;
 pub mod error;
 pub mod event;
 pub mod completions;
 pub mod completions_ui;
+pub mod prefetch;
 
 // Editor and input modules
 pub mod vim;
@@ -15,19 +16,26 @@ pub mod keybindings;
 
 // Data and persistence modules
 pub mod db;
-pub mod drive;
+pub use warpish_workflows::drive;
 pub mod session;
+pub mod keychain;
+pub mod stats;
 
 // AI and language processing modules
 pub mod agent_mode_eval;
 pub mod natural_language_detection;
 pub mod mcq;
+pub mod ollama;
+pub mod pair;
 
 // Content processing modules
 pub mod markdown_parser;
 pub mod syntax_parser;
 pub mod languages;
 pub mod code;
+pub mod tagging;
+pub mod structured;
+pub mod pdf;
 
 // System and utility modules
 pub mod assets;
@@ -35,19 +43,46 @@ pub mod asset_macro;
 pub mod command;
 pub mod fuzzy_match;
 pub mod string_offset;
-pub mod sum_tree;
+pub use warpish_sum_tree as sum_tree;
 pub mod syntax_tree;
 pub mod virtual_fs;
 pub mod watcher;
 pub mod scripting;
+pub mod idle;
+pub mod scratch;
+pub mod snippets;
+pub mod abbreviations;
+pub mod dirhistory;
+pub mod permissions;
+pub mod ssh;
+pub mod search;
+pub mod git;
+pub mod focus;
+pub mod prompt_chips;
+pub mod hooks;
+pub mod doctor;
+pub mod shutdown;
+pub mod crash;
+pub mod wsl;
+pub mod profiles;
+pub mod jobs;
+pub mod ports;
 
 // Network and communication modules
 pub mod websocket;
 pub mod graphql;
+pub mod github;
 pub mod serve_wasm;
 pub mod lpc;
+pub mod webhooks;
+pub mod metrics;
+pub mod perf;
+pub mod share;
+pub mod websearch;
+pub mod explain;
 
 // Integration and resources
 pub mod integration;
+pub mod k8s;
 pub mod resources;
 pub mod rules;
@@ -0,0 +1,48 @@
+//! A blocking client for the LPC server, used by `warpish-cli`.
+
+use super::protocol::{Request, Response};
+use super::LpcError;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+// Named pipes open like files.
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+pub struct LpcClient {
+    reader: BufReader<Stream>,
+    writer: Stream,
+    next_id: u64,
+}
+
+impl LpcClient {
+    pub fn connect(path: &Path) -> Result<Self, LpcError> {
+        #[cfg(unix)]
+        let stream = Stream::connect(path)?;
+        #[cfg(windows)]
+        let stream = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self { writer: stream.try_clone()?, reader: BufReader::new(stream), next_id: 1 })
+    }
+
+    /// Calls `method` and waits for its result.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, LpcError> {
+        let request = Request::new(self.next_id, method, params);
+        self.next_id += 1;
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "the terminal closed the connection").into());
+        }
+        let response: Response = serde_json::from_str(&line)?;
+        match response.error {
+            Some(error) => Err(error.into()),
+            None => Ok(response.result.unwrap_or_default()),
+        }
+    }
+}
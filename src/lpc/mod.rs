@@ -1,4 +1,64 @@
+//! Local Procedure Calls
+//!
+//! A JSON-RPC 2.0 server on a Unix domain socket, or a named pipe on
+//! Windows, through which editors, CI scripts and `warpish-cli` control the
+//! running terminal: opening panes, running commands, reading blocks and
+//! moving focus. Each line carries one request or response. Only the user
+//! can reach the socket, which is created owner-only inside an owner-only
+//! directory. The server is opt-in.
+//!
+//! The shell translation helpers live here too.
+
 pub mod translator;
 pub mod detector;
 pub mod validator;
-pub mod runtime;
\ No newline at end of file
+pub mod runtime;
+pub mod protocol;
+pub mod server;
+pub mod client;
+
+use crate::config::LpcConfig;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Overrides where clients and the server meet, for tests and scripts.
+pub const SOCKET_ENV: &str = "WARPISH_LPC_SOCKET";
+
+#[derive(Error, Debug)]
+pub enum LpcError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Rpc(#[from] protocol::RpcError),
+    #[error("Another Warpish instance is already listening on {0}")]
+    InUse(PathBuf),
+    #[error("Refusing to listen in {0}, which belongs to another user")]
+    UnsafeDirectory(PathBuf),
+}
+
+fn user_name() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_else(|_| "user".to_string())
+}
+
+/// Where the server listens: `WARPISH_LPC_SOCKET`, the configured path, or
+/// a socket in a directory of Warpish's own: `warpish` in the user's runtime
+/// directory, or a per-user one in the temp directory (a per-user pipe on
+/// Windows).
+pub fn socket_path(config: &LpcConfig) -> PathBuf {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return PathBuf::from(path);
+    }
+    if let Some(path) = &config.socket_path {
+        return path.clone();
+    }
+    if cfg!(windows) {
+        PathBuf::from(format!(r"\\.\pipe\warpish-lpc-{}", user_name()))
+    } else {
+        dirs::runtime_dir()
+            .map(|dir| dir.join("warpish"))
+            .unwrap_or_else(|| std::env::temp_dir().join(format!("warpish-{}", user_name())))
+            .join("lpc.sock")
+    }
+}
@@ -0,0 +1,166 @@
+//! The JSON-RPC 2.0 messages the LPC server speaks, one per line.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The terminal could not do what was asked, e.g. an unknown pane.
+pub const APPLICATION_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self { jsonrpc: "2.0".to_string(), id: Some(id.into()), method: method.to_string(), params }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} ({code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn new(id: Value, result: Result<Value, RpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self { jsonrpc: "2.0".to_string(), id, result, error }
+    }
+}
+
+/// The methods the terminal exposes, with their parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Method {
+    /// Opens a pane, optionally with a launch profile, in a directory, or
+    /// running a command. Returns `{"pane_id": ...}`.
+    OpenTab {
+        #[serde(default)]
+        profile: Option<String>,
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        #[serde(default)]
+        command: Option<String>,
+    },
+    /// Runs a command in a pane, the focused one by default.
+    RunCommand {
+        command: String,
+        #[serde(default)]
+        pane_id: Option<Uuid>,
+    },
+    /// The last `limit` blocks of a pane, the focused one by default.
+    GetBlocks {
+        #[serde(default)]
+        pane_id: Option<Uuid>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    FocusPane { pane_id: Uuid },
+    ListPanes,
+}
+
+impl Method {
+    /// Reads a request's method and parameters.
+    pub fn parse(request: &Request) -> Result<Self, RpcError> {
+        const NAMES: [&str; 5] = ["open_tab", "run_command", "get_blocks", "focus_pane", "list_panes"];
+        if !NAMES.contains(&request.method.as_str()) {
+            return Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", request.method)));
+        }
+        // Omitted parameters are empty, and a method without parameters has
+        // no `params` at all as far as serde is concerned.
+        let params = match &request.params {
+            Value::Null => serde_json::json!({}),
+            params => params.clone(),
+        };
+        let tagged = if request.method == "list_panes" && params == serde_json::json!({}) {
+            serde_json::json!({ "method": request.method })
+        } else {
+            serde_json::json!({ "method": request.method, "params": params })
+        };
+        serde_json::from_value(tagged).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneInfo {
+    pub id: Uuid,
+    pub cwd: Option<PathBuf>,
+    pub focused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockInfo {
+    pub id: Uuid,
+    pub command: String,
+    pub output: String,
+}
+
+/// A method call for the app to carry out, delivered as `AppEvent::Lpc`.
+#[derive(Debug)]
+pub struct Call {
+    pub method: Method,
+    pub reply: oneshot::Sender<Result<Value, RpcError>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(line: &str) -> Request {
+        serde_json::from_str(line).unwrap()
+    }
+
+    #[test]
+    fn test_methods_are_parsed_from_requests() {
+        assert_eq!(
+            Method::parse(&request(r#"{"jsonrpc":"2.0","id":1,"method":"run_command","params":{"command":"cargo test"}}"#)),
+            Ok(Method::RunCommand { command: "cargo test".to_string(), pane_id: None })
+        );
+        assert_eq!(Method::parse(&request(r#"{"jsonrpc":"2.0","id":2,"method":"list_panes"}"#)), Ok(Method::ListPanes));
+        assert_eq!(Method::parse(&request(r#"{"jsonrpc":"2.0","id":3,"method":"list_panes","params":{}}"#)), Ok(Method::ListPanes));
+        assert_eq!(
+            Method::parse(&request(r#"{"jsonrpc":"2.0","id":4,"method":"open_tab","params":{}}"#)),
+            Ok(Method::OpenTab { profile: None, cwd: None, command: None })
+        );
+        assert_eq!(Method::parse(&request(r#"{"jsonrpc":"2.0","id":5,"method":"quit"}"#)).unwrap_err().code, METHOD_NOT_FOUND);
+        assert_eq!(Method::parse(&request(r#"{"jsonrpc":"2.0","id":6,"method":"focus_pane","params":{}}"#)).unwrap_err().code, INVALID_PARAMS);
+
+        let response = serde_json::to_string(&Response::new(7.into(), Err(RpcError::new(APPLICATION_ERROR, "No such pane")))).unwrap();
+        assert_eq!(response, r#"{"jsonrpc":"2.0","id":7,"error":{"code":-32000,"message":"No such pane"}}"#);
+    }
+}
@@ -0,0 +1,177 @@
+//! The LPC server. Requests are read a line at a time and handed to the app
+//! as [`Call`]s; each response is written back once the app replies.
+
+use super::protocol::{Call, Method, Request, Response, RpcError, APPLICATION_ERROR, INVALID_REQUEST, PARSE_ERROR};
+use super::{socket_path, LpcError};
+use crate::config::LpcConfig;
+use crate::event::AppEvent;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+
+/// Hands a call to the app. Returns false once the app has gone away.
+pub type Dispatch = Arc<dyn Fn(Call) -> bool + Send + Sync>;
+
+async fn call(request: &Request, dispatch: &Dispatch) -> Result<Value, RpcError> {
+    let method = Method::parse(request)?;
+    let (reply, response) = oneshot::channel();
+    let unavailable = || RpcError::new(APPLICATION_ERROR, "The terminal is shutting down");
+    if !dispatch(Call { method, reply }) {
+        return Err(unavailable());
+    }
+    response.await.map_err(|_| unavailable())?
+}
+
+/// The response to one line, or `None` for a notification.
+async fn respond(line: &str, dispatch: &Dispatch) -> Option<Response> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return Some(Response::new(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string())))),
+    };
+    let request: Request = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => return Some(Response::new(Value::Null, Err(RpcError::new(INVALID_REQUEST, e.to_string())))),
+    };
+    if request.jsonrpc != "2.0" {
+        let error = RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported");
+        return Some(Response::new(request.id.unwrap_or_default(), Err(error)));
+    }
+    let result = call(&request, dispatch).await;
+    request.id.map(|id| Response::new(id, result))
+}
+
+/// Answers requests on one connection until the client hangs up.
+pub async fn handle_connection<S: AsyncRead + AsyncWrite>(stream: S, dispatch: Dispatch) -> Result<(), LpcError> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, &dispatch).await {
+            let mut text = serde_json::to_string(&response)?;
+            text.push('\n');
+            writer.write_all(text.as_bytes()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Binds the socket, replacing one left behind by a terminal that exited
+/// without cleaning up. A missing directory is created private to the user;
+/// an existing one is left as it is, but must belong to the user, as whoever
+/// owns it can replace the socket.
+#[cfg(unix)]
+pub fn bind(path: &std::path::Path) -> Result<tokio::net::UnixListener, LpcError> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    if let Some(dir) = path.parent() {
+        match std::fs::metadata(dir) {
+            Ok(metadata) if metadata.uid() != users::get_effective_uid() => {
+                return Err(LpcError::UnsafeDirectory(dir.to_path_buf()));
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(LpcError::InUse(path.to_path_buf()));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+pub async fn serve(listener: tokio::net::UnixListener, dispatch: Dispatch) -> Result<(), LpcError> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let dispatch = dispatch.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, dispatch).await {
+                log::debug!("LPC connection ended: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+pub async fn serve(pipe_name: String, dispatch: Dispatch) -> Result<(), LpcError> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    let mut server = ServerOptions::new().first_pipe_instance(true).reject_remote_clients(true).create(&pipe_name)?;
+    loop {
+        server.connect().await?;
+        // A new instance waits for the next client while this one is served.
+        let connected = std::mem::replace(&mut server, ServerOptions::new().reject_remote_clients(true).create(&pipe_name)?);
+        let dispatch = dispatch.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(connected, dispatch).await {
+                log::debug!("LPC connection ended: {}", e);
+            }
+        });
+    }
+}
+
+/// Starts the server on the current tokio runtime if it is enabled,
+/// forwarding calls to the app as `AppEvent::Lpc`.
+pub fn spawn_server(config: &LpcConfig, events: UnboundedSender<AppEvent>) -> Result<Option<tokio::task::JoinHandle<()>>, LpcError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let path = socket_path(config);
+    let dispatch: Dispatch = Arc::new(move |call| events.send(AppEvent::Lpc(call)).is_ok());
+    #[cfg(unix)]
+    let server = serve(bind(&path)?, dispatch);
+    #[cfg(windows)]
+    let server = serve(path.to_string_lossy().into_owned(), dispatch);
+    log::info!("[LPC] Listening on {}", path.display());
+    Ok(Some(tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("LPC server stopped: {}", e);
+        }
+    })))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::lpc::client::LpcClient;
+    use crate::lpc::protocol::{METHOD_NOT_FOUND, PaneInfo};
+
+    #[test]
+    fn test_client_calls_reach_the_app() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lpc").join("lpc.sock");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(async { bind(&path) }).unwrap();
+        let created = std::fs::metadata(path.parent().unwrap()).unwrap().permissions().mode();
+        assert_eq!(created & 0o777, 0o700);
+        // Stands in for the app: one pane, which is focused.
+        let pane = uuid::Uuid::new_v4();
+        let dispatch: Dispatch = Arc::new(move |call: Call| {
+            let result = match call.method {
+                Method::ListPanes => Ok(serde_json::json!([PaneInfo { id: pane, cwd: None, focused: true }])),
+                Method::FocusPane { pane_id } => Err(RpcError::new(APPLICATION_ERROR, format!("No pane {}", pane_id))),
+                _ => Ok(Value::Null),
+            };
+            call.reply.send(result).is_ok()
+        });
+        runtime.spawn(serve(listener, dispatch));
+        assert!(matches!(runtime.block_on(async { bind(&path) }), Err(LpcError::InUse(_))));
+
+        let mut client = LpcClient::connect(&path).unwrap();
+        let panes: Vec<PaneInfo> = serde_json::from_value(client.call("list_panes", Value::Null).unwrap()).unwrap();
+        assert_eq!(panes[0].id, pane);
+        let missing = client.call("focus_pane", serde_json::json!({ "pane_id": uuid::Uuid::new_v4() }));
+        assert!(matches!(missing, Err(LpcError::Rpc(error)) if error.code == APPLICATION_ERROR));
+        assert!(matches!(client.call("quit", Value::Null), Err(LpcError::Rpc(error)) if error.code == METHOD_NOT_FOUND));
+    }
+}
@@ -13,7 +13,7 @@ pub struct ShellTranslator {
 
 impl ShellTranslator {
     pub fn new() -> Self {
-        let mut rules = HashMap::new();
+        let mut rules: HashMap<String, fn(&ShellCommand) -> ShellCommand> = HashMap::new();
         rules.insert("bash_to_fish".into(), Self::bash_to_fish);
         rules.insert("zsh_to_bash".into(), Self::zsh_to_bash);
         rules.insert("powershell_to_bash".into(), Self::powershell_to_bash);
@@ -21,6 +21,7 @@ pub enum Block {
     Quote(QuoteBlock),
     ThematicBreak,
     Html(HtmlBlock),
+    FootnoteDefinition(FootnoteDefinition),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -54,6 +55,10 @@ pub struct ListBlock {
 pub struct ListItem {
     pub content: Vec<Block>,
     pub marker: String,
+    /// Whether a task list item (`- [ ]` or `- [x]`) is done; `None` for
+    /// items without a checkbox.
+    #[serde(default)]
+    pub checked: Option<bool>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -86,17 +91,27 @@ pub struct HtmlBlock {
     pub content: String,
 }
 
+/// The text of a footnote, `[^label]: ...`, wherever it appears in the
+/// source. Renderers gather them below the document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FootnoteDefinition {
+    pub label: String,
+    pub content: Vec<Inline>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Inline {
     Text(TextInline),
     Emphasis(EmphasisInline),
     Strong(StrongInline),
+    Strikethrough(StrikethroughInline),
     Code(CodeInline),
     Link(LinkInline),
     Image(ImageInline),
     LineBreak,
     SoftBreak,
     Html(HtmlInline),
+    FootnoteReference(FootnoteReference),
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -114,6 +129,11 @@ pub struct StrongInline {
     pub content: Vec<Inline>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrikethroughInline {
+    pub content: Vec<Inline>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CodeInline {
     pub content: String,
@@ -124,6 +144,9 @@ pub struct LinkInline {
     pub content: Vec<Inline>,
     pub url: String,
     pub title: Option<String>,
+    /// Set for a bare URL in the text, which is its own link text.
+    #[serde(default)]
+    pub autolink: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -138,6 +161,12 @@ pub struct HtmlInline {
     pub content: String,
 }
 
+/// A `[^label]` reference to a footnote.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FootnoteReference {
+    pub label: String,
+}
+
 impl Document {
     pub fn new() -> Self {
         Self {
@@ -153,6 +182,25 @@ impl Document {
     pub fn add_metadata(&mut self, key: String, value: String) {
         self.metadata.insert(key, value);
     }
+
+    /// The labels of the footnotes that are referenced, in order of first
+    /// reference, with their definitions if the document has them.
+    pub fn footnotes(&self) -> Vec<(String, Option<&FootnoteDefinition>)> {
+        let mut labels = Vec::new();
+        for block in &self.blocks {
+            block.collect_footnote_references(&mut labels);
+        }
+        labels
+            .into_iter()
+            .map(|label| {
+                let definition = self.blocks.iter().find_map(|block| match block {
+                    Block::FootnoteDefinition(definition) if definition.label == label => Some(definition),
+                    _ => None,
+                });
+                (label, definition)
+            })
+            .collect()
+    }
 }
 
 impl Block {
@@ -167,6 +215,43 @@ impl Block {
     pub fn is_table(&self) -> bool {
         matches!(self, Block::Table(_))
     }
+
+    fn collect_footnote_references(&self, labels: &mut Vec<String>) {
+        match self {
+            Block::Heading(heading) => collect_inline_references(&heading.content, labels),
+            Block::Paragraph(paragraph) => collect_inline_references(&paragraph.content, labels),
+            Block::List(list) => {
+                for block in list.items.iter().flat_map(|item| item.content.iter()) {
+                    block.collect_footnote_references(labels);
+                }
+            }
+            Block::Table(table) => {
+                for cell in table.headers.iter().chain(table.rows.iter().flatten()) {
+                    collect_inline_references(&cell.content, labels);
+                }
+            }
+            Block::Quote(quote) => {
+                for block in &quote.content {
+                    block.collect_footnote_references(labels);
+                }
+            }
+            Block::FootnoteDefinition(definition) => collect_inline_references(&definition.content, labels),
+            Block::CodeBlock(_) | Block::ThematicBreak | Block::Html(_) => {}
+        }
+    }
+}
+
+fn collect_inline_references(inlines: &[Inline], labels: &mut Vec<String>) {
+    for inline in inlines {
+        match inline {
+            Inline::FootnoteReference(reference) if !labels.contains(&reference.label) => labels.push(reference.label.clone()),
+            Inline::Emphasis(EmphasisInline { content })
+            | Inline::Strong(StrongInline { content })
+            | Inline::Strikethrough(StrikethroughInline { content })
+            | Inline::Link(LinkInline { content, .. }) => collect_inline_references(content, labels),
+            _ => {}
+        }
+    }
 }
 
 impl HeadingBlock {
@@ -253,6 +338,18 @@ impl LinkInline {
             content,
             url,
             title: None,
+            autolink: false,
+        }
+    }
+
+    /// A link for a bare URL; `www.` addresses link to `http://`.
+    pub fn autolink(text: String) -> Self {
+        let url = if text.starts_with("www.") { format!("http://{}", text) } else { text.clone() };
+        Self {
+            content: vec![Inline::Text(TextInline::new(text))],
+            url,
+            title: None,
+            autolink: true,
         }
     }
     
@@ -0,0 +1,330 @@
+//! HTML Renderer for Markdown
+//!
+//! This module renders markdown AST to an HTML fragment for export. Colors
+//! come from the theme variant that suits the page background, translated
+//! from the theme's ANSI codes to CSS. A block can be rendered with the other
+//! variant, on that variant's own background, such as captured terminal
+//! output kept dark on a light page.
+
+use super::{ast::*, themes::{MarkdownTheme, ThemeVariant}};
+use std::collections::HashMap;
+
+/// The xterm colors for the 16 standard ANSI color indices.
+const STANDARD_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// The backdrop for a block rendered with a variant other than the page's.
+fn variant_background(variant: ThemeVariant) -> &'static str {
+    match variant {
+        ThemeVariant::Dark => "#1e1e1e",
+        ThemeVariant::Light => "#ffffff",
+    }
+}
+
+/// The CSS color for an index into the xterm 256-color palette.
+fn xterm_color(index: u8) -> String {
+    match index {
+        0..=15 => STANDARD_COLORS[index as usize].to_string(),
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            format!("#{:02x}{:02x}{:02x}", level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        232..=255 => {
+            let gray = 8 + (index - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+/// CSS declarations equivalent to an SGR escape such as `\x1b[1;38;5;81m`.
+fn sgr_to_css(sgr: &str) -> Vec<String> {
+    let params: Vec<u16> = sgr
+        .trim_start_matches("\x1b[")
+        .trim_end_matches('m')
+        .split(';')
+        .filter_map(|param| param.parse().ok())
+        .collect();
+    let mut css = Vec::new();
+    let mut params = params.into_iter();
+    while let Some(param) = params.next() {
+        match param {
+            1 => css.push("font-weight:bold".to_string()),
+            3 => css.push("font-style:italic".to_string()),
+            4 => css.push("text-decoration:underline".to_string()),
+            30..=37 => css.push(format!("color:{}", STANDARD_COLORS[param as usize - 30])),
+            90..=97 => css.push(format!("color:{}", STANDARD_COLORS[param as usize - 90 + 8])),
+            40..=47 => css.push(format!("background:{}", STANDARD_COLORS[param as usize - 40])),
+            100..=107 => css.push(format!("background:{}", STANDARD_COLORS[param as usize - 100 + 8])),
+            38 | 48 => {
+                let property = if param == 38 { "color" } else { "background" };
+                match params.next() {
+                    Some(5) => {
+                        if let Some(index) = params.next() {
+                            css.push(format!("{}:{}", property, xterm_color(index as u8)));
+                        }
+                    }
+                    Some(2) => {
+                        let rgb: Vec<u16> = params.by_ref().take(3).collect();
+                        if let [r, g, b] = rgb[..] {
+                            css.push(format!("{}:#{:02x}{:02x}{:02x}", property, r, g, b));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    css
+}
+
+/// A `style` attribute combining the CSS for several SGR escapes.
+fn style(sgrs: &[&str]) -> String {
+    let css: Vec<String> = sgrs.iter().flat_map(|sgr| sgr_to_css(sgr)).collect();
+    if css.is_empty() {
+        String::new()
+    } else {
+        format!(" style=\"{}\"", css.join(";"))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+pub struct HtmlRenderer {
+    theme: MarkdownTheme,
+    background: String,
+    overrides: HashMap<usize, ThemeVariant>,
+}
+
+impl HtmlRenderer {
+    /// Renders for a page with the given `#rrggbb` background, pairing
+    /// `theme` with it.
+    pub fn new(theme: MarkdownTheme, background: &str) -> Self {
+        let theme = match ThemeVariant::for_background(background) {
+            Some(variant) => theme.paired(variant),
+            None => theme,
+        };
+        Self { theme, background: background.to_string(), overrides: HashMap::new() }
+    }
+
+    /// Renders the top-level block at `index` with `variant`'s theme.
+    pub fn with_block_variant(mut self, index: usize, variant: ThemeVariant) -> Self {
+        self.overrides.insert(index, variant);
+        self
+    }
+
+    pub fn render(&self, document: &Document) -> String {
+        let mut output = format!(
+            "<div class=\"markdown\" style=\"background:{}\"><div{}>\n",
+            escape(&self.background),
+            style(&[&self.theme.text_color])
+        );
+        for (index, block) in document.blocks.iter().enumerate() {
+            // Footnotes are gathered below the document
+            if matches!(block, Block::FootnoteDefinition(_)) {
+                continue;
+            }
+            match self.overrides.get(&index).filter(|variant| **variant != self.theme.variant) {
+                Some(variant) => {
+                    let theme = self.theme.paired(*variant);
+                    output.push_str(&format!(
+                        "<div style=\"background:{};padding:0.5em\"><div{}>",
+                        variant_background(*variant),
+                        style(&[&theme.text_color])
+                    ));
+                    render_block(&theme, block, &mut output);
+                    output.push_str("</div></div>\n");
+                }
+                None => {
+                    render_block(&self.theme, block, &mut output);
+                    output.push('\n');
+                }
+            }
+        }
+        let footnotes: Vec<&FootnoteDefinition> = document.footnotes().into_iter().filter_map(|(_, definition)| definition).collect();
+        if !footnotes.is_empty() {
+            output.push_str("<section class=\"footnotes\"><hr>");
+            for definition in footnotes {
+                render_block(&self.theme, &Block::FootnoteDefinition(definition.clone()), &mut output);
+            }
+            output.push_str("</section>\n");
+        }
+        output.push_str("</div></div>\n");
+        output
+    }
+}
+
+fn render_block(theme: &MarkdownTheme, block: &Block, output: &mut String) {
+    match block {
+        Block::Heading(heading) => {
+            let level = heading.level.clamp(1, 6);
+            let color = &theme.heading_colors[level as usize - 1];
+            output.push_str(&format!("<h{}{}>", level, style(&[color])));
+            render_inlines(theme, &heading.content, output);
+            output.push_str(&format!("</h{}>", level));
+        }
+        Block::Paragraph(paragraph) => {
+            output.push_str("<p>");
+            render_inlines(theme, &paragraph.content, output);
+            output.push_str("</p>");
+        }
+        Block::CodeBlock(code_block) => {
+            let class = code_block
+                .language
+                .as_ref()
+                .map(|language| format!(" class=\"language-{}\"", escape(language)))
+                .unwrap_or_default();
+            output.push_str(&format!(
+                "<pre{}><code{}>{}</code></pre>",
+                style(&[&theme.code_background, &theme.code_color]),
+                class,
+                escape(&code_block.code)
+            ));
+        }
+        Block::List(list) => {
+            let tag = if list.ordered { "ol" } else { "ul" };
+            let start = list.start.filter(|_| list.ordered).map(|start| format!(" start=\"{}\"", start)).unwrap_or_default();
+            output.push_str(&format!("<{}{}>", tag, start));
+            for item in &list.items {
+                output.push_str("<li>");
+                match item.checked {
+                    Some(true) => output.push_str("<input type=\"checkbox\" checked disabled> "),
+                    Some(false) => output.push_str("<input type=\"checkbox\" disabled> "),
+                    None => {}
+                }
+                for block in &item.content {
+                    render_block(theme, block, output);
+                }
+                output.push_str("</li>");
+            }
+            output.push_str(&format!("</{}>", tag));
+        }
+        Block::Table(table) => {
+            let border = sgr_to_css(&theme.table_border_color)
+                .into_iter()
+                .find_map(|css| css.strip_prefix("color:").map(str::to_string))
+                .unwrap_or_else(|| "currentColor".to_string());
+            let cell = |tag: &str, content: &[Inline], output: &mut String| {
+                output.push_str(&format!("<{} style=\"border:1px solid {};padding:0.2em 0.5em\">", tag, border));
+                render_inlines(theme, content, output);
+                output.push_str(&format!("</{}>", tag));
+            };
+            output.push_str("<table style=\"border-collapse:collapse\"><tr>");
+            for header in &table.headers {
+                cell("th", &header.content, output);
+            }
+            output.push_str("</tr>");
+            for row in &table.rows {
+                output.push_str("<tr>");
+                for data in row {
+                    cell("td", &data.content, output);
+                }
+                output.push_str("</tr>");
+            }
+            output.push_str("</table>");
+        }
+        Block::Quote(quote) => {
+            output.push_str(&format!("<blockquote{}>", style(&[&theme.quote_color])));
+            for block in &quote.content {
+                render_block(theme, block, output);
+            }
+            output.push_str("</blockquote>");
+        }
+        Block::ThematicBreak => output.push_str("<hr>"),
+        // Raw HTML from terminal output is shown, not interpreted.
+        Block::Html(html) => output.push_str(&format!("<pre>{}</pre>", escape(&html.content))),
+        Block::FootnoteDefinition(definition) => {
+            let label = escape(&definition.label);
+            output.push_str(&format!("<p id=\"fn-{}\"><sup>{}</sup> ", label, label));
+            render_inlines(theme, &definition.content, output);
+            output.push_str(&format!(" <a href=\"#fnref-{}\">↩</a></p>", label));
+        }
+    }
+}
+
+fn render_inlines(theme: &MarkdownTheme, inlines: &[Inline], output: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => output.push_str(&escape(&text.content)),
+            Inline::Emphasis(emphasis) => {
+                output.push_str("<em>");
+                render_inlines(theme, &emphasis.content, output);
+                output.push_str("</em>");
+            }
+            Inline::Strong(strong) => {
+                output.push_str("<strong>");
+                render_inlines(theme, &strong.content, output);
+                output.push_str("</strong>");
+            }
+            Inline::Strikethrough(strikethrough) => {
+                output.push_str("<del>");
+                render_inlines(theme, &strikethrough.content, output);
+                output.push_str("</del>");
+            }
+            Inline::Code(code) => {
+                let code_style = style(&[&theme.code_background, &theme.code_color]);
+                output.push_str(&format!("<code{}>{}</code>", code_style, escape(&code.content)));
+            }
+            Inline::Link(link) => {
+                output.push_str(&format!("<a href=\"{}\"{}>", escape(&link.url), style(&[&theme.link_color])));
+                render_inlines(theme, &link.content, output);
+                output.push_str("</a>");
+            }
+            Inline::Image(image) => {
+                output.push_str(&format!("<img src=\"{}\" alt=\"{}\">", escape(&image.url), escape(&image.alt)));
+            }
+            Inline::LineBreak => output.push_str("<br>"),
+            Inline::SoftBreak => output.push(' '),
+            Inline::Html(html) => output.push_str(&escape(&html.content)),
+            Inline::FootnoteReference(reference) => {
+                let label = escape(&reference.label);
+                output.push_str(&format!("<sup><a href=\"#fn-{}\" id=\"fnref-{}\">{}</a></sup>", label, label, label));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown_parser::MarkdownProcessor;
+
+    #[test]
+    fn test_sgr_to_css() {
+        assert_eq!(sgr_to_css("\x1b[1;38;5;81m"), vec!["font-weight:bold", "color:#5fd7ff"]);
+        assert_eq!(sgr_to_css("\x1b[48;5;235m"), vec!["background:#262626"]);
+        assert_eq!(sgr_to_css("\x1b[97m"), vec!["color:#ffffff"]);
+        assert_eq!(sgr_to_css("\x1b[38;2;10;20;30m"), vec!["color:#0a141e"]);
+    }
+
+    #[test]
+    fn test_light_page_with_dark_block_override() {
+        let document = MarkdownProcessor::new().parse("# Build\n\n```console\n$ make <all>\n```").unwrap();
+        let html = HtmlRenderer::new(MarkdownTheme::default(), "#ffffff")
+            .with_block_variant(1, ThemeVariant::Dark)
+            .render(&document);
+        // The page uses the light partner of the default theme.
+        assert!(html.contains("<div style=\"color:#000000\">"));
+        assert!(html.contains("<h1 style=\"color:#0000ee\">Build</h1>"));
+        // The code block keeps the dark theme on its own backdrop.
+        assert!(html.contains("<div style=\"background:#1e1e1e;padding:0.5em\"><div style=\"color:#ffffff\">"));
+        assert!(html.contains("<pre style=\"background:#000000;color:#00ff00\"><code class=\"language-console\">"));
+        assert!(html.contains("$ make &lt;all&gt;</code></pre>"));
+    }
+
+    #[test]
+    fn test_gfm_extensions() {
+        let source = "[^why]: It is cached.\n\n- [x] ~~build~~ fast[^why]\n- [ ] see https://ci.example.com\n";
+        let document = MarkdownProcessor::new().parse(source).unwrap();
+        let html = HtmlRenderer::new(MarkdownTheme::default(), "#1e1e1e").render(&document);
+        assert!(html.contains("<li><input type=\"checkbox\" checked disabled> <p><del>build</del> fast<sup><a href=\"#fn-why\" id=\"fnref-why\">why</a></sup></p></li>"));
+        assert!(html.contains("<input type=\"checkbox\" disabled> <p>see <a href=\"https://ci.example.com\""));
+        // The definition moves from the top to below the document.
+        assert!(html.find("<section class=\"footnotes\"><hr><p id=\"fn-why\"><sup>why</sup> It is cached.") > html.find("</ul>"));
+    }
+}
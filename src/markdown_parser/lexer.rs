@@ -12,18 +12,24 @@ pub enum Token {
     CodeBlockStart { language: Option<String> },
     CodeBlockEnd,
     CodeBlockContent(String),
-    ListItemStart { marker: String, ordered: bool },
+    /// `indent` is the width of the whitespace before the marker, which
+    /// decides how deeply the item is nested.
+    ListItemStart { marker: String, ordered: bool, checked: Option<bool>, indent: usize },
     TableSeparator,
     TableRow(Vec<String>),
     QuoteStart,
     ThematicBreak,
+    FootnoteDefinitionStart(String),
     
     // Inline tokens
     Text(String),
     Bold(String),
     Italic(String),
+    Strikethrough(String),
     Code(String),
     Link { text: String, url: String, title: Option<String> },
+    Autolink(String),
+    FootnoteReference(String),
     Image { alt: String, url: String, title: Option<String> },
     
     // Structural tokens
@@ -52,6 +58,11 @@ pub struct MarkdownLexer {
     line: usize,
     column: usize,
     tokens: Vec<TokenWithPosition>,
+    /// The width of the current line's leading whitespace.
+    indent: usize,
+    /// Set when lexing the text of a single element, where nothing is a
+    /// block element.
+    inline_only: bool,
 }
 
 impl MarkdownLexer {
@@ -62,14 +73,29 @@ impl MarkdownLexer {
             line: 1,
             column: 1,
             tokens: Vec::new(),
+            indent: 0,
+            inline_only: false,
         }
     }
     
     pub fn tokenize(&mut self, input: &str) -> Result<Vec<Token>, MarkdownError> {
+        self.inline_only = false;
+        self.scan_all(input)
+    }
+    
+    /// Tokenizes the text of a heading, table cell or emphasis, where
+    /// only inline elements are recognised.
+    pub fn tokenize_inline(&mut self, input: &str) -> Result<Vec<Token>, MarkdownError> {
+        self.inline_only = true;
+        self.scan_all(input)
+    }
+    
+    fn scan_all(&mut self, input: &str) -> Result<Vec<Token>, MarkdownError> {
         self.input = input.to_string();
         self.position = 0;
         self.line = 1;
         self.column = 1;
+        self.indent = 0;
         self.tokens.clear();
         
         while !self.is_at_end() {
@@ -81,11 +107,12 @@ impl MarkdownLexer {
     }
     
     fn scan_token(&mut self) -> Result<(), MarkdownError> {
-        let start_pos = self.position;
+        // Block elements are only recognised at the start of a line
+        let at_line_start = self.column == 1 && !self.inline_only;
         
         // Skip whitespace at line start to check for block elements
-        if self.column == 1 {
-            self.skip_whitespace();
+        if at_line_start {
+            self.indent = self.skip_whitespace();
         }
         
         let ch = self.advance();
@@ -96,7 +123,7 @@ impl MarkdownLexer {
                 self.line += 1;
                 self.column = 1;
             }
-            '#' if self.column == 1 || self.previous_char() == '\n' => {
+            '#' if at_line_start => {
                 self.scan_heading()?;
             }
             '`' => {
@@ -106,6 +133,13 @@ impl MarkdownLexer {
                     self.scan_inline_code()?;
                 }
             }
+            '-' | '*' | '+' if self.is_list_marker(at_line_start) => {
+                let marker = ch.to_string();
+                self.scan_list_item(marker, false)?;
+            }
+            '0'..='9' if at_line_start && self.ordered_marker_length().is_some() => {
+                self.scan_ordered_list_item()?;
+            }
             '*' => {
                 if self.check('*') {
                     self.scan_bold()?;
@@ -120,6 +154,12 @@ impl MarkdownLexer {
                     self.scan_italic()?;
                 }
             }
+            '~' if self.check('~') => {
+                self.scan_strikethrough()?;
+            }
+            '[' if self.check('^') && self.footnote_label().is_some() => {
+                self.scan_footnote(at_line_start)?;
+            }
             '[' => {
                 self.scan_link_or_image()?;
             }
@@ -127,19 +167,23 @@ impl MarkdownLexer {
                 self.advance(); // consume '['
                 self.scan_image()?;
             }
-            '-' if self.is_list_marker() => {
-                self.scan_list_item()?;
-            }
-            '|' => {
+            '|' if at_line_start => {
                 self.scan_table_row()?;
             }
-            '>' if self.column == 1 => {
+            '>' if at_line_start => {
                 self.scan_quote()?;
             }
+            _ if self.autolink_length(self.position - 1).is_some() => {
+                self.scan_autolink()?;
+            }
             _ => {
-                // Go back and scan as text
-                self.position = start_pos;
-                self.scan_text()?;
+                // Take the character literally and continue as text, so a
+                // special character that starts no element still advances
+                let mut text = ch.to_string();
+                while !self.is_at_end() && !self.is_special_char() && self.autolink_length(self.position).is_none() {
+                    text.push(self.advance());
+                }
+                self.add_token(Token::Text(text));
             }
         }
         
@@ -241,6 +285,92 @@ impl MarkdownLexer {
         Ok(())
     }
     
+    fn scan_strikethrough(&mut self) -> Result<(), MarkdownError> {
+        self.advance(); // consume second ~
+        
+        let content = self.scan_until_double_char('~');
+        
+        if !self.is_at_end() {
+            self.advance(); // consume first closing ~
+            self.advance(); // consume second closing ~
+        }
+        
+        self.add_token(Token::Strikethrough(content));
+        Ok(())
+    }
+    
+    /// The label of a `[^label]` footnote starting after the current `[`.
+    fn footnote_label(&self) -> Option<String> {
+        let label: String = self
+            .input
+            .chars()
+            .skip(self.position + 1)
+            .take_while(|ch| *ch != ']' && !ch.is_whitespace())
+            .collect();
+        let closed = self.check_ahead(label.chars().count() + 1, ']');
+        (closed && !label.is_empty()).then_some(label)
+    }
+    
+    fn scan_footnote(&mut self, at_line_start: bool) -> Result<(), MarkdownError> {
+        self.advance(); // consume '^'
+        let label = self.scan_until_char(']');
+        self.advance(); // consume ']'
+        
+        // `[^label]:` at the start of a line defines the footnote
+        if at_line_start && self.check(':') {
+            self.advance(); // consume ':'
+            if self.check(' ') {
+                self.advance(); // consume space
+            }
+            self.add_token(Token::FootnoteDefinitionStart(label));
+        } else {
+            self.add_token(Token::FootnoteReference(label));
+        }
+        
+        Ok(())
+    }
+    
+    /// The length in characters of a bare URL starting at `start`, if one
+    /// does: `http://`, `https://` or `www.` at a word boundary, running to
+    /// whitespace, `<` or a bracket, without trailing punctuation or an
+    /// unbalanced `)`.
+    fn autolink_length(&self, start: usize) -> Option<usize> {
+        if !matches!(self.input.chars().nth(start), Some('h' | 'w')) {
+            return None;
+        }
+        if start > 0 && self.input.chars().nth(start - 1).is_some_and(|ch| ch.is_alphanumeric()) {
+            return None;
+        }
+        let rest: Vec<char> = self.input.chars().skip(start).take_while(|ch| !ch.is_whitespace() && !matches!(ch, '<' | '[' | ']')).collect();
+        let text: String = rest.iter().collect();
+        let prefix = ["https://", "http://", "www."].into_iter().find(|prefix| text.starts_with(prefix))?;
+        
+        let mut length = rest.len();
+        loop {
+            match rest[..length].last() {
+                Some('?' | '!' | '.' | ',' | ':' | ';' | '*' | '_' | '~' | '\'' | '"') => length -= 1,
+                Some(')') if rest[..length].iter().filter(|ch| **ch == ')').count() > rest[..length].iter().filter(|ch| **ch == '(').count() => {
+                    length -= 1
+                }
+                _ => break,
+            }
+        }
+        
+        let host = &rest[prefix.len().min(length)..length];
+        host.first().is_some_and(|ch| ch.is_alphanumeric()).then_some(length)
+    }
+    
+    fn scan_autolink(&mut self) -> Result<(), MarkdownError> {
+        let length = self.autolink_length(self.position - 1).unwrap_or(1);
+        let mut url = self.previous_char().to_string();
+        for _ in 1..length {
+            url.push(self.advance());
+        }
+        
+        self.add_token(Token::Autolink(url));
+        Ok(())
+    }
+    
     fn scan_link_or_image(&mut self) -> Result<(), MarkdownError> {
         let text = self.scan_until_char(']');
         
@@ -289,14 +419,49 @@ impl MarkdownLexer {
         Ok(())
     }
     
-    fn scan_list_item(&mut self) -> Result<(), MarkdownError> {
-        let marker = self.previous_char().to_string();
+    /// The length of an ordered list marker, digits then `.` or `)`, that
+    /// starts at the previous character and is followed by a space.
+    fn ordered_marker_length(&self) -> Option<usize> {
+        let digits = 1 + self.input.chars().skip(self.position).take_while(|ch| ch.is_ascii_digit()).count();
+        let delimiter = self.input.chars().nth(self.position + digits - 1);
+        let spaced = self.check_ahead(digits, ' ');
+        (digits <= 9 && matches!(delimiter, Some('.' | ')')) && spaced).then_some(digits + 1)
+    }
+    
+    fn scan_ordered_list_item(&mut self) -> Result<(), MarkdownError> {
+        let length = self.ordered_marker_length().unwrap_or(1);
+        let mut marker = self.previous_char().to_string();
+        for _ in 1..length {
+            marker.push(self.advance());
+        }
         
+        self.scan_list_item(marker, true)
+    }
+    
+    fn scan_list_item(&mut self, marker: String, ordered: bool) -> Result<(), MarkdownError> {
         if self.check(' ') {
             self.advance(); // consume space
         }
         
-        self.add_token(Token::ListItemStart { marker, ordered: false });
+        // A task list item starts with a checkbox, `[ ]` or `[x]`
+        let mut checked = None;
+        if self.check('[') && self.check_ahead(2, ']') && (self.check_ahead(3, ' ') || self.check_ahead(3, '\n') || self.position + 3 >= self.input.len()) {
+            checked = match self.input.chars().nth(self.position + 1) {
+                Some(' ') => Some(false),
+                Some('x' | 'X') => Some(true),
+                _ => None,
+            };
+        }
+        if checked.is_some() {
+            self.advance(); // consume '['
+            self.advance(); // consume the mark
+            self.advance(); // consume ']'
+            if self.check(' ') {
+                self.advance(); // consume space
+            }
+        }
+        
+        self.add_token(Token::ListItemStart { marker, ordered, checked, indent: self.indent });
         Ok(())
     }
     
@@ -376,18 +541,20 @@ impl MarkdownLexer {
     }
     
     fn is_special_char(&self) -> bool {
-        matches!(self.peek(), '*' | '_' | '`' | '[' | ']' | '!' | '(' | ')' | '\n' | '|' | '#' | '>' | '-')
+        matches!(self.peek(), '*' | '_' | '`' | '[' | ']' | '!' | '(' | ')' | '\n' | '|' | '#' | '>' | '-' | '~')
     }
     
-    fn is_list_marker(&self) -> bool {
-        (self.column == 1 || self.previous_char() == '\n') && 
-        (self.check(' ') || self.check_ahead(1, ' '))
+    fn is_list_marker(&self, at_line_start: bool) -> bool {
+        at_line_start && self.check(' ')
     }
     
-    fn skip_whitespace(&mut self) {
+    /// Skips spaces and tabs, returning their width with tabs as four.
+    fn skip_whitespace(&mut self) -> usize {
+        let mut width = 0;
         while self.check(' ') || self.check('\t') {
-            self.advance();
+            width += if self.advance() == '\t' { 4 } else { 1 };
         }
+        width
     }
     
     fn advance(&mut self) -> char {
@@ -483,4 +650,52 @@ mod tests {
         assert!(tokens.iter().any(|t| matches!(t, Token::Bold(_))));
         assert!(tokens.iter().any(|t| matches!(t, Token::Italic(_))));
     }
+    
+    #[test]
+    fn test_nested_and_ordered_list_items() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("1. build\n   * [x] test | lint\n2) ship\n").unwrap();
+        let items: Vec<(String, bool, usize)> = tokens
+            .iter()
+            .filter_map(|t| match t {
+                Token::ListItemStart { marker, ordered, indent, .. } => Some((marker.clone(), *ordered, *indent)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(items, vec![("1.".to_string(), true, 0), ("*".to_string(), false, 3), ("2)".to_string(), true, 0)]);
+        assert!(tokens.contains(&Token::Text("test ".to_string())));
+        
+        let tokens = lexer.tokenize_inline("# not a heading - **bold**").unwrap();
+        assert_eq!(tokens[0], Token::Text("# not a heading ".to_string()));
+        assert!(tokens.contains(&Token::Bold("bold".to_string())));
+    }
+    
+    #[test]
+    fn test_gfm_extensions() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("- [x] done\n- [ ] ~~old~~ see https://example.com/a_(b).\n").unwrap();
+        
+        assert!(matches!(tokens[0], Token::ListItemStart { checked: Some(true), .. }));
+        assert!(tokens.iter().any(|t| matches!(t, Token::ListItemStart { checked: Some(false), .. })));
+        assert!(tokens.contains(&Token::Strikethrough("old".to_string())));
+        assert!(tokens.contains(&Token::Autolink("https://example.com/a_(b)".to_string())));
+        assert!(tokens.contains(&Token::Text(".".to_string())));
+        
+        let tokens = lexer.tokenize("Fast[^1] and cheap.\n\n[^1]: Mostly. Not ~ www or [link].").unwrap();
+        assert!(tokens.contains(&Token::FootnoteReference("1".to_string())));
+        assert!(tokens.contains(&Token::FootnoteDefinitionStart("1".to_string())));
+        assert!(!tokens.iter().any(|t| matches!(t, Token::Autolink(_) | Token::Strikethrough(_))));
+    }
+    
+    #[test]
+    fn test_dash_starts_list_only_at_line_start() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("a - b\n-x\n- item\nx ] y").unwrap();
+        
+        assert_eq!(tokens.iter().filter(|t| matches!(t, Token::ListItemStart { .. })).count(), 1);
+        assert!(tokens.contains(&Token::Text("- b".to_string())));
+        assert!(tokens.contains(&Token::Text("-x".to_string())));
+        // A special character that starts nothing is kept as text
+        assert!(tokens.contains(&Token::Text("] y".to_string())));
+    }
 }
@@ -8,15 +8,19 @@ use std::collections::HashMap;
 use std::fmt;
 
 pub mod ast;
+pub mod html;
 pub mod lexer;
 pub mod parser;
 pub mod renderer;
+pub mod tasks;
 pub mod themes;
 
 pub use ast::*;
+pub use html::*;
 pub use lexer::*;
 pub use parser::*;
 pub use renderer::*;
+pub use tasks::*;
 pub use themes::*;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -88,6 +92,12 @@ impl MarkdownProcessor {
         }
     }
     
+    /// Renders with the theme variant that suits the app theme's background.
+    pub fn with_background(mut self, background: &str) -> Self {
+        self.renderer = self.renderer.with_background(background);
+        self
+    }
+
     pub fn parse(&mut self, input: &str) -> Result<Document, MarkdownError> {
         let tokens = self.lexer.tokenize(input)?;
         self.parser.parse(tokens)
@@ -129,6 +139,28 @@ mod tests {
         assert!(result.is_ok());
     }
     
+    #[test]
+    fn test_light_background_uses_light_theme() {
+        let input = "# Title\n\nUse `make`.";
+        let dark = MarkdownProcessor::new().process(input).unwrap();
+        let light = MarkdownProcessor::new().with_background("#ffffff").process(input).unwrap();
+        assert!(dark.contains(&MarkdownTheme::default().code_color));
+        assert!(light.contains(&MarkdownTheme::github().code_color));
+        assert!(!light.contains(&MarkdownTheme::default().code_background));
+        assert_eq!(MarkdownProcessor::new().with_background("#282a36").process(input).unwrap(), dark);
+    }
+
+    #[test]
+    fn test_gfm_processing() {
+        let mut processor = MarkdownProcessor::new();
+        let rendered = processor.process("- [x] ~~draft~~ at https://example.com[^1]\n- [ ] review\n\n[^1]: The staging site.").unwrap();
+        assert!(rendered.contains("☑ ") && rendered.contains("☐ "));
+        assert!(rendered.contains("\x1b[9mdraft\x1b[0m"));
+        assert!(rendered.contains("https://example.com\x1b[0m"));
+        assert!(!rendered.contains("(https://example.com)"));
+        assert!(rendered.trim_end().ends_with("[1]\x1b[0m The staging site."));
+    }
+
     #[test]
     fn test_table_processing() {
         let mut processor = MarkdownProcessor::new();
@@ -2,7 +2,7 @@
 //! 
 //! This module converts tokens into an Abstract Syntax Tree (AST).
 
-use super::{ast::*, lexer::Token, MarkdownError};
+use super::{ast::*, lexer::{MarkdownLexer, Token}, MarkdownError};
 
 pub struct MarkdownParser {
     tokens: Vec<Token>,
@@ -26,7 +26,8 @@ impl MarkdownParser {
         while !self.is_at_end() {
             match self.parse_block()? {
                 Some(block) => document.add_block(block),
-                None => break,
+                // Skipped tokens such as blank lines produce no block
+                None => continue,
             }
         }
         
@@ -36,14 +37,15 @@ impl MarkdownParser {
     fn parse_block(&mut self) -> Result<Option<Block>, MarkdownError> {
         match self.peek() {
             Token::Heading { level, content } => {
+                let (level, content) = (*level, content.clone());
                 self.advance();
-                let inline_content = self.parse_inline_content(content)?;
-                Ok(Some(Block::Heading(HeadingBlock::new(*level, inline_content))))
+                let inline_content = self.parse_inline_content(&content)?;
+                Ok(Some(Block::Heading(HeadingBlock::new(level, inline_content))))
             }
             Token::CodeBlockStart { language } => {
+                let lang = language.clone();
                 self.advance();
                 let mut code_lines = Vec::new();
-                let lang = language.clone();
                 
                 // Collect code content
                 while !self.is_at_end() {
@@ -69,8 +71,8 @@ impl MarkdownParser {
                 
                 Ok(Some(Block::CodeBlock(code_block)))
             }
-            Token::ListItemStart { marker, ordered } => {
-                self.parse_list(*ordered)
+            Token::ListItemStart { ordered, indent, .. } => {
+                self.parse_list(*ordered, *indent)
             }
             Token::TableRow(cells) => {
                 self.parse_table(cells.clone())
@@ -82,8 +84,17 @@ impl MarkdownParser {
                 self.advance();
                 Ok(Some(Block::ThematicBreak))
             }
-            Token::Text(_) | Token::Bold(_) | Token::Italic(_) | Token::Code(_) | 
-            Token::Link { .. } | Token::Image { .. } => {
+            Token::FootnoteDefinitionStart(label) => {
+                let label = label.clone();
+                self.advance();
+                let content = match self.parse_paragraph()? {
+                    Some(Block::Paragraph(paragraph)) => paragraph.content,
+                    _ => Vec::new(),
+                };
+                Ok(Some(Block::FootnoteDefinition(FootnoteDefinition { label, content })))
+            }
+            Token::Text(_) | Token::Bold(_) | Token::Italic(_) | Token::Strikethrough(_) | Token::Code(_) | 
+            Token::Link { .. } | Token::Autolink(_) | Token::Image { .. } | Token::FootnoteReference(_) => {
                 self.parse_paragraph()
             }
             Token::LineBreak => {
@@ -102,51 +113,18 @@ impl MarkdownParser {
         let mut inline_elements = Vec::new();
         
         while !self.is_at_end() {
-            match self.peek() {
-                Token::Text(content) => {
-                    inline_elements.push(Inline::Text(TextInline::new(content.clone())));
-                    self.advance();
-                }
-                Token::Bold(content) => {
-                    let inner_content = self.parse_inline_content(content)?;
-                    inline_elements.push(Inline::Strong(StrongInline { content: inner_content }));
-                    self.advance();
-                }
-                Token::Italic(content) => {
-                    let inner_content = self.parse_inline_content(content)?;
-                    inline_elements.push(Inline::Emphasis(EmphasisInline { content: inner_content }));
-                    self.advance();
+            if let Token::LineBreak = self.peek() {
+                // Check if this is the end of the paragraph
+                if self.check_ahead(1, &Token::LineBreak) || self.is_block_start_ahead() {
+                    break;
                 }
-                Token::Code(content) => {
-                    inline_elements.push(Inline::Code(CodeInline { content: content.clone() }));
-                    self.advance();
-                }
-                Token::Link { text, url, title } => {
-                    let text_content = self.parse_inline_content(text)?;
-                    let mut link = LinkInline::new(text_content, url.clone());
-                    if let Some(title) = title {
-                        link = link.with_title(title.clone());
-                    }
-                    inline_elements.push(Inline::Link(link));
-                    self.advance();
-                }
-                Token::Image { alt, url, title } => {
-                    let mut image = ImageInline::new(alt.clone(), url.clone());
-                    if let Some(title) = title {
-                        image = image.with_title(title.clone());
-                    }
-                    inline_elements.push(Inline::Image(image));
-                    self.advance();
-                }
-                Token::LineBreak => {
-                    // Check if this is the end of the paragraph
-                    if self.check_ahead(1, &Token::LineBreak) || self.is_block_start_ahead() {
-                        break;
-                    }
-                    inline_elements.push(Inline::SoftBreak);
-                    self.advance();
-                }
-                _ => break,
+                inline_elements.push(Inline::SoftBreak);
+                self.advance();
+                continue;
+            }
+            match self.parse_inline()? {
+                Some(inline) => inline_elements.push(inline),
+                None => break,
             }
         }
         
@@ -157,19 +135,71 @@ impl MarkdownParser {
         }
     }
     
-    fn parse_list(&mut self, ordered: bool) -> Result<Option<Block>, MarkdownError> {
+    /// Parses the inline element at the current token, or returns `None`
+    /// without advancing if the token is not one.
+    fn parse_inline(&mut self) -> Result<Option<Inline>, MarkdownError> {
+        let inline = match self.peek() {
+            Token::Text(content) => Inline::Text(TextInline::new(content.clone())),
+            Token::Bold(content) => Inline::Strong(StrongInline { content: self.parse_inline_content(content)? }),
+            Token::Italic(content) => Inline::Emphasis(EmphasisInline { content: self.parse_inline_content(content)? }),
+            Token::Strikethrough(content) => {
+                Inline::Strikethrough(StrikethroughInline { content: self.parse_inline_content(content)? })
+            }
+            Token::Code(content) => Inline::Code(CodeInline { content: content.clone() }),
+            Token::Link { text, url, title } => {
+                let mut link = LinkInline::new(self.parse_inline_content(text)?, url.clone());
+                if let Some(title) = title {
+                    link = link.with_title(title.clone());
+                }
+                Inline::Link(link)
+            }
+            Token::Autolink(url) => Inline::Link(LinkInline::autolink(url.clone())),
+            Token::Image { alt, url, title } => {
+                let mut image = ImageInline::new(alt.clone(), url.clone());
+                if let Some(title) = title {
+                    image = image.with_title(title.clone());
+                }
+                Inline::Image(image)
+            }
+            Token::FootnoteReference(label) => Inline::FootnoteReference(FootnoteReference { label: label.clone() }),
+            _ => return Ok(None),
+        };
+        self.advance();
+        Ok(Some(inline))
+    }
+    
+    /// Parses the items at `indent`, nesting the more deeply indented ones
+    /// under the item before them. A less indented item, or one of the
+    /// other kind, ends the list.
+    fn parse_list(&mut self, ordered: bool, indent: usize) -> Result<Option<Block>, MarkdownError> {
         let mut list = ListBlock::new(ordered);
         
         while !self.is_at_end() {
             match self.peek() {
-                Token::ListItemStart { marker, .. } => {
+                Token::ListItemStart { indent: item_indent, .. } if *item_indent >= indent + 2 && !list.items.is_empty() => {
+                    let (nested_ordered, nested_indent) = match self.peek() {
+                        Token::ListItemStart { ordered, indent, .. } => (*ordered, *indent),
+                        _ => unreachable!(),
+                    };
+                    if let Some(nested) = self.parse_list(nested_ordered, nested_indent)? {
+                        if let Some(item) = list.items.last_mut() {
+                            item.content.push(nested);
+                        }
+                    }
+                }
+                Token::ListItemStart { marker, checked, ordered: item_ordered, indent: item_indent }
+                    if *item_ordered == ordered && *item_indent < indent + 2 && *item_indent + 1 >= indent =>
+                {
+                    let (item_marker, checked) = (marker.clone(), *checked);
+                    if list.items.is_empty() && ordered {
+                        list.start = item_marker.trim_end_matches(['.', ')']).parse().ok();
+                    }
                     self.advance();
-                    let item_marker = marker.clone();
                     
                     // Parse the content of the list item
                     let mut item_content = Vec::new();
                     
-                    // For now, treat list item content as a single paragraph
+                    // The item's own text is a single paragraph; nested lists follow it
                     if let Some(Block::Paragraph(para)) = self.parse_paragraph()? {
                         item_content.push(Block::Paragraph(para));
                     }
@@ -177,13 +207,22 @@ impl MarkdownParser {
                     list.add_item(ListItem {
                         content: item_content,
                         marker: item_marker,
+                        checked,
                     });
                 }
+                // The next item follows on the next line
+                Token::LineBreak if matches!(self.tokens.get(self.current + 1), Some(Token::ListItemStart { .. })) => {
+                    self.advance();
+                }
                 _ => break,
             }
         }
         
-        Ok(Some(Block::List(list)))
+        if list.items.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Block::List(list)))
+        }
     }
     
     fn parse_table(&mut self, first_row: Vec<String>) -> Result<Option<Block>, MarkdownError> {
@@ -241,10 +280,22 @@ impl MarkdownParser {
         Ok(Some(Block::Quote(QuoteBlock { content: quote_content })))
     }
     
+    /// Parses the text of a heading, table cell or inline element into
+    /// inline elements, nesting as deep as the markup does.
     fn parse_inline_content(&self, content: &str) -> Result<Vec<Inline>, MarkdownError> {
-        // Simple implementation - just return as text
-        // In a full implementation, this would parse inline markdown
-        Ok(vec![Inline::Text(TextInline::new(content.to_string()))])
+        let mut parser = MarkdownParser::new();
+        parser.tokens = MarkdownLexer::new().tokenize_inline(content)?;
+        
+        let mut inlines = Vec::new();
+        while !parser.is_at_end() {
+            match parser.parse_inline()? {
+                Some(inline) => inlines.push(inline),
+                None => {
+                    parser.advance();
+                }
+            }
+        }
+        Ok(inlines)
     }
     
     fn is_block_start_ahead(&self) -> bool {
@@ -258,7 +309,8 @@ impl MarkdownParser {
             Token::ListItemStart { .. } | 
             Token::TableRow(_) | 
             Token::QuoteStart | 
-            Token::ThematicBreak
+            Token::ThematicBreak |
+            Token::FootnoteDefinitionStart(_)
         )
     }
     
@@ -290,7 +342,7 @@ impl MarkdownParser {
     }
     
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len() || matches!(self.peek(), Token::Eof)
+        self.current >= self.tokens.len() || matches!(self.tokens[self.current], Token::Eof)
     }
 }
 
@@ -342,4 +394,76 @@ fn main() {}
         assert_eq!(document.blocks.len(), 1);
         assert!(matches!(document.blocks[0], Block::CodeBlock(_)));
     }
+    
+    #[test]
+    fn test_parse_nested_lists() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("3. build\n   - debug\n   - release\n     1. strip\n4. ship\n- done").unwrap();
+        
+        let mut parser = MarkdownParser::new();
+        let document = parser.parse(tokens).unwrap();
+        
+        assert_eq!(document.blocks.len(), 2);
+        let Block::List(outer) = &document.blocks[0] else { panic!("Expected list") };
+        assert!(outer.ordered);
+        assert_eq!(outer.start, Some(3));
+        assert_eq!(outer.items.len(), 2);
+        let Block::List(inner) = &outer.items[0].content[1] else { panic!("Expected nested list") };
+        assert!(!inner.ordered);
+        assert_eq!(inner.items.len(), 2);
+        assert!(matches!(&inner.items[1].content[1], Block::List(innermost) if innermost.ordered && innermost.items.len() == 1));
+        assert!(matches!(&document.blocks[1], Block::List(list) if !list.ordered && list.items.len() == 1));
+    }
+    
+    #[test]
+    fn test_parse_inline_content_in_headings_and_cells() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("## Use **`cargo` build** *fast* - [docs](https://docs.rs)\n\n| *a* | b |").unwrap();
+        
+        let mut parser = MarkdownParser::new();
+        let document = parser.parse(tokens).unwrap();
+        
+        let Block::Heading(heading) = &document.blocks[0] else { panic!("Expected heading") };
+        let Inline::Strong(strong) = &heading.content[1] else { panic!("Expected strong") };
+        assert!(matches!(&strong.content[0], Inline::Code(code) if code.content == "cargo"));
+        assert!(matches!(&heading.content[3], Inline::Emphasis(_)));
+        assert!(heading.content.iter().any(|inline| matches!(inline, Inline::Link(link) if link.url == "https://docs.rs")));
+        
+        let Block::Table(table) = &document.blocks[1] else { panic!("Expected table") };
+        assert!(matches!(table.headers[0].content[0], Inline::Emphasis(_)));
+    }
+    
+    #[test]
+    fn test_parse_gfm_extensions() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("- [ ] ship www.example.com\n- [x] ~~test~~[^ci]\n\n[^ci]: On every push.").unwrap();
+        
+        let mut parser = MarkdownParser::new();
+        let document = parser.parse(tokens).unwrap();
+        
+        let Block::List(list) = &document.blocks[0] else { panic!("Expected list") };
+        assert_eq!(list.items.iter().map(|item| item.checked).collect::<Vec<_>>(), vec![Some(false), Some(true)]);
+        let Block::Paragraph(first) = &list.items[0].content[0] else { panic!("Expected paragraph") };
+        assert!(matches!(&first.content[1], Inline::Link(link) if link.autolink && link.url == "http://www.example.com"));
+        let Block::Paragraph(second) = &list.items[1].content[0] else { panic!("Expected paragraph") };
+        assert!(matches!(second.content[0], Inline::Strikethrough(_)));
+        
+        assert!(matches!(&document.blocks[1], Block::FootnoteDefinition(definition) if definition.label == "ci"));
+        let footnotes = document.footnotes();
+        assert_eq!(footnotes.len(), 1);
+        assert!(matches!(footnotes[0], (ref label, Some(_)) if label == "ci"));
+    }
+    
+    #[test]
+    fn test_parse_continues_past_blank_lines() {
+        let mut lexer = MarkdownLexer::new();
+        let tokens = lexer.tokenize("# Title\n\n\nFirst paragraph.\n\nSecond paragraph.").unwrap();
+        
+        let mut parser = MarkdownParser::new();
+        let document = parser.parse(tokens).unwrap();
+        
+        assert_eq!(document.blocks.len(), 3);
+        assert!(matches!(document.blocks[0], Block::Heading(_)));
+        assert!(matches!(document.blocks[2], Block::Paragraph(_)));
+    }
 }
@@ -2,15 +2,18 @@
 //! 
 //! This module renders markdown AST to terminal-formatted text with colors and styling.
 
-use super::{ast::*, themes::MarkdownTheme, MarkdownConfig, MarkdownError};
+use super::{ast::*, themes::{MarkdownTheme, ThemeVariant}, MarkdownConfig, MarkdownError};
 use std::fmt::Write;
+use crate::string_offset;
 
 /// ANSI color codes for terminal styling
+#[allow(dead_code)]
 mod ansi {
     pub const RESET: &str = "\x1b[0m";
     pub const BOLD: &str = "\x1b[1m";
     pub const ITALIC: &str = "\x1b[3m";
     pub const UNDERLINE: &str = "\x1b[4m";
+    pub const STRIKETHROUGH: &str = "\x1b[9m";
     
     // Colors
     pub const BLACK: &str = "\x1b[30m";
@@ -43,6 +46,78 @@ mod ansi {
     pub const BG_WHITE: &str = "\x1b[47m";
 }
 
+/// Bullets for unordered list items, by nesting depth.
+const BULLETS: [&str; 3] = ["•", "◦", "▪"];
+
+/// The width of `text` in terminal columns, not counting ANSI escapes.
+fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        width += string_offset::display_width(&rest[..start]);
+        // Skip the rest of the escape, up to its final letter
+        let end = rest[start..].find(|ch: char| ch.is_ascii_alphabetic()).map_or(rest.len(), |end| start + end + 1);
+        rest = &rest[end..];
+    }
+    width + string_offset::display_width(rest)
+}
+
+/// The styles set by the escapes in `text` that are still active at its
+/// end, up to the last reset.
+fn active_styles(text: &str, active: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        let end = rest[start..].find(|ch: char| ch.is_ascii_alphabetic()).map_or(rest.len(), |end| start + end + 1);
+        let escape = &rest[start..end];
+        if escape == ansi::RESET {
+            active.clear();
+        } else {
+            active.push_str(escape);
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// Word-wraps ANSI-styled `text` to `width` columns, breaking at spaces.
+/// A word wider than `width` gets a line of its own. Styles are reset at
+/// the end of a wrapped line and set again at the start of the next, so
+/// whatever prefixes the next line is unstyled.
+fn wrap(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    let mut active = String::new();
+    for line in text.split('\n') {
+        let mut current = String::new();
+        let mut current_width = 0;
+        for (i, word) in line.split(' ').enumerate() {
+            let word_width = display_width(word);
+            if i > 0 && current_width > 0 && current_width + 1 + word_width > width {
+                if !active.is_empty() {
+                    current.push_str(ansi::RESET);
+                }
+                lines.push(std::mem::replace(&mut current, active.clone()));
+                current_width = 0;
+            } else if i > 0 {
+                current.push(' ');
+                current_width += 1;
+            }
+            current.push_str(word);
+            current_width += word_width;
+            active_styles(word, &mut active);
+        }
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+/// `text` with `first` before its first line and `rest` before the others.
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.split('\n')
+        .enumerate()
+        .map(|(i, line)| format!("{}{}", if i == 0 { first } else { rest }, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub struct TerminalRenderer {
     config: MarkdownConfig,
     theme: MarkdownTheme,
@@ -60,69 +135,98 @@ impl TerminalRenderer {
         self.theme = theme;
         self
     }
+
+    /// Swaps the theme for its partner when it does not suit `background`,
+    /// the app theme's `#rrggbb` background color.
+    pub fn with_background(mut self, background: &str) -> Self {
+        if let Some(variant) = ThemeVariant::for_background(background) {
+            self.theme = self.theme.paired(variant);
+        }
+        self
+    }
+
+    pub fn theme(&self) -> &MarkdownTheme {
+        &self.theme
+    }
     
     pub fn render(&self, document: &Document) -> Result<String, MarkdownError> {
         let mut output = String::new();
         
-        for block in &document.blocks {
-            self.render_block(block, &mut output)?;
+        // Footnotes go below the document, in the order they are referenced
+        for block in document.blocks.iter().filter(|block| !matches!(block, Block::FootnoteDefinition(_))) {
+            self.render_block(block, self.config.max_width, &mut output)?;
             output.push('\n');
         }
         
+        for (_, definition) in document.footnotes() {
+            if let Some(definition) = definition {
+                self.render_footnote_definition(definition, self.config.max_width, &mut output)?;
+                output.push('\n');
+            }
+        }
+        
         Ok(output)
     }
     
-    fn render_block(&self, block: &Block, output: &mut String) -> Result<(), MarkdownError> {
+    /// Renders `block`, wrapping its text to `width` columns if set.
+    fn render_block(&self, block: &Block, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
         match block {
-            Block::Heading(heading) => self.render_heading(heading, output),
-            Block::Paragraph(paragraph) => self.render_paragraph(paragraph, output),
+            Block::Heading(heading) => self.render_heading(heading, width, output),
+            Block::Paragraph(paragraph) => self.render_paragraph(paragraph, width, output),
             Block::CodeBlock(code_block) => self.render_code_block(code_block, output),
-            Block::List(list) => self.render_list(list, output),
+            Block::List(list) => self.render_list(list, 0, width, output),
             Block::Table(table) => self.render_table(table, output),
-            Block::Quote(quote) => self.render_quote(quote, output),
+            Block::Quote(quote) => self.render_quote(quote, width, output),
             Block::ThematicBreak => self.render_thematic_break(output),
             Block::Html(html) => self.render_html(html, output),
+            Block::FootnoteDefinition(definition) => self.render_footnote_definition(definition, width, output),
+        }
+    }
+    
+    /// Renders `inlines` as one run of text wrapped to `width`.
+    fn render_wrapped(&self, inlines: &[Inline], width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
+        let mut text = String::new();
+        for inline in inlines {
+            self.render_inline(inline, &mut text)?;
+        }
+        
+        match width {
+            Some(width) => output.push_str(&wrap(&text, width)),
+            None => output.push_str(&text),
         }
+        Ok(())
     }
     
-    fn render_heading(&self, heading: &HeadingBlock, output: &mut String) -> Result<(), MarkdownError> {
-        let color = match heading.level {
-            1 => ansi::BRIGHT_BLUE,
-            2 => ansi::BRIGHT_GREEN,
-            3 => ansi::BRIGHT_YELLOW,
-            4 => ansi::BRIGHT_MAGENTA,
-            5 => ansi::BRIGHT_CYAN,
-            6 => ansi::BRIGHT_WHITE,
-            _ => ansi::BRIGHT_WHITE,
-        };
+    fn render_heading(&self, heading: &HeadingBlock, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
+        let color = &self.theme.heading_colors[heading.level.clamp(1, 6) as usize - 1];
         
         let prefix = "#".repeat(heading.level as usize);
         
-        write!(output, "{}{}{} ", ansi::BOLD, color, prefix)
-            .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+        let mut text = format!("{}{}{} ", ansi::BOLD, color, prefix);
         
         for inline in &heading.content {
-            self.render_inline(inline, output)?;
+            self.render_inline(inline, &mut text)?;
         }
         
-        write!(output, "{}", ansi::RESET)
-            .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+        text.push_str(ansi::RESET);
+        
+        match width {
+            Some(width) => output.push_str(&wrap(&text, width)),
+            None => output.push_str(&text),
+        }
         
         Ok(())
     }
     
-    fn render_paragraph(&self, paragraph: &ParagraphBlock, output: &mut String) -> Result<(), MarkdownError> {
-        for inline in &paragraph.content {
-            self.render_inline(inline, output)?;
-        }
-        Ok(())
+    fn render_paragraph(&self, paragraph: &ParagraphBlock, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
+        self.render_wrapped(&paragraph.content, width, output)
     }
     
     fn render_code_block(&self, code_block: &CodeBlock, output: &mut String) -> Result<(), MarkdownError> {
         let lines: Vec<&str> = code_block.code.lines().collect();
         
         // Render top border
-        write!(output, "{}{}", ansi::BG_BLACK, ansi::BRIGHT_WHITE)
+        write!(output, "{}{}", self.theme.code_background, self.theme.text_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         if let Some(language) = &code_block.language {
@@ -136,7 +240,7 @@ impl TerminalRenderer {
         // Fill the rest of the line
         if let Some(max_width) = self.config.max_width {
             let current_len = if let Some(lang) = &code_block.language {
-                7 + lang.len() // "┌─ " + language + " "
+                7 + string_offset::display_width(lang) // "┌─ " + language + " "
             } else {
                 12 // "┌─ code "
             };
@@ -153,7 +257,7 @@ impl TerminalRenderer {
         
         // Render code lines
         for (i, line) in lines.iter().enumerate() {
-            write!(output, "{}{}", ansi::BG_BLACK, ansi::BRIGHT_WHITE)
+            write!(output, "{}{}", self.theme.code_background, self.theme.text_color)
                 .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             
             if code_block.line_numbers {
@@ -165,12 +269,12 @@ impl TerminalRenderer {
             }
             
             // TODO: Add syntax highlighting here
-            write!(output, "{}{}", ansi::BRIGHT_GREEN, line)
+            write!(output, "{}{}", self.theme.code_color, line)
                 .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             
             // Fill the rest of the line
             if let Some(max_width) = self.config.max_width {
-                let line_len = line.len() + if code_block.line_numbers { 6 } else { 2 };
+                let line_len = string_offset::display_width(line) + if code_block.line_numbers { 6 } else { 2 };
                 if line_len < max_width {
                     let padding = " ".repeat(max_width - line_len - 1);
                     write!(output, "{}", padding)
@@ -183,7 +287,7 @@ impl TerminalRenderer {
         }
         
         // Render bottom border
-        write!(output, "{}{}", ansi::BG_BLACK, ansi::BRIGHT_WHITE)
+        write!(output, "{}{}", self.theme.code_background, self.theme.text_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         write!(output, "└")
@@ -201,21 +305,33 @@ impl TerminalRenderer {
         Ok(())
     }
     
-    fn render_list(&self, list: &ListBlock, output: &mut String) -> Result<(), MarkdownError> {
+    /// Renders a list nested `depth` lists deep. Each item's text hangs
+    /// under its marker, and nested lists are indented to that text.
+    fn render_list(&self, list: &ListBlock, depth: usize, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
         for (i, item) in list.items.iter().enumerate() {
-            let marker = if list.ordered {
-                format!("{}. ", i + 1)
-            } else {
-                "• ".to_string()
+            let marker = match item.checked {
+                Some(true) => "☑ ".to_string(),
+                Some(false) => "☐ ".to_string(),
+                None if list.ordered => format!("{}. ", list.start.unwrap_or(1) as usize + i),
+                None => format!("{} ", BULLETS[depth % BULLETS.len()]),
             };
+            let hang = string_offset::display_width(&marker);
+            let inner_width = width.map(|width| width.saturating_sub(hang).max(1));
             
-            write!(output, "{}{}{}", ansi::BRIGHT_CYAN, marker, ansi::RESET)
-                .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
-            
-            for block in &item.content {
-                self.render_block(block, output)?;
+            let mut body = String::new();
+            for (j, block) in item.content.iter().enumerate() {
+                if j > 0 {
+                    body.push('\n');
+                }
+                match block {
+                    Block::List(nested) => self.render_list(nested, depth + 1, inner_width, &mut body)?,
+                    block => self.render_block(block, inner_width, &mut body)?,
+                }
             }
             
+            let first = format!("{}{}{}", self.theme.list_marker_color, marker, ansi::RESET);
+            output.push_str(&prefix_lines(&body, &first, &" ".repeat(hang)));
+            
             if i < list.items.len() - 1 {
                 output.push('\n');
             }
@@ -248,7 +364,7 @@ impl TerminalRenderer {
         }
         
         // Render top border
-        write!(output, "{}", ansi::BRIGHT_WHITE)
+        write!(output, "{}", self.theme.table_border_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         write!(output, "┌")
@@ -268,7 +384,7 @@ impl TerminalRenderer {
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         // Render headers
-        write!(output, "{}", ansi::BRIGHT_WHITE)
+        write!(output, "{}", self.theme.table_border_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         write!(output, "│")
@@ -284,7 +400,7 @@ impl TerminalRenderer {
             
             let current_width = self.calculate_inline_width(&header.content);
             let padding = " ".repeat(col_widths[i] - current_width + 1);
-            write!(output, "{}{}", padding, ansi::BRIGHT_WHITE)
+            write!(output, "{}{}", padding, self.theme.table_border_color)
                 .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             
             write!(output, "│")
@@ -295,7 +411,7 @@ impl TerminalRenderer {
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         // Render separator
-        write!(output, "{}", ansi::BRIGHT_WHITE)
+        write!(output, "{}", self.theme.table_border_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         write!(output, "├")
@@ -316,7 +432,7 @@ impl TerminalRenderer {
         
         // Render rows
         for row in &table.rows {
-            write!(output, "{}", ansi::BRIGHT_WHITE)
+            write!(output, "{}", self.theme.table_border_color)
                 .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             
             write!(output, "│")
@@ -332,7 +448,7 @@ impl TerminalRenderer {
                 
                 let current_width = self.calculate_inline_width(&cell.content);
                 let padding = " ".repeat(col_widths[i] - current_width + 1);
-                write!(output, "{}{}", padding, ansi::BRIGHT_WHITE)
+                write!(output, "{}{}", padding, self.theme.table_border_color)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
                 
                 write!(output, "│")
@@ -344,7 +460,7 @@ impl TerminalRenderer {
         }
         
         // Render bottom border
-        write!(output, "{}", ansi::BRIGHT_WHITE)
+        write!(output, "{}", self.theme.table_border_color)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         write!(output, "└")
@@ -366,15 +482,19 @@ impl TerminalRenderer {
         Ok(())
     }
     
-    fn render_quote(&self, quote: &QuoteBlock, output: &mut String) -> Result<(), MarkdownError> {
-        write!(output, "{}{}", ansi::BRIGHT_BLACK, "│ ")
-            .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+    fn render_quote(&self, quote: &QuoteBlock, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
+        let inner_width = width.map(|width| width.saturating_sub(2).max(1));
         
-        for block in &quote.content {
-            self.render_block(block, output)?;
+        let mut body = String::new();
+        for (i, block) in quote.content.iter().enumerate() {
+            if i > 0 {
+                body.push('\n');
+            }
+            self.render_block(block, inner_width, &mut body)?;
         }
         
-        write!(output, "{}", ansi::RESET)
+        let bar = format!("{}│ ", self.theme.quote_color);
+        write!(output, "{}{}", prefix_lines(&body, &bar, &bar), ansi::RESET)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         Ok(())
@@ -384,7 +504,7 @@ impl TerminalRenderer {
         let width = self.config.max_width.unwrap_or(80);
         let line = "─".repeat(width);
         
-        writeln!(output, "{}{}{}", ansi::BRIGHT_BLACK, line, ansi::RESET)
+        writeln!(output, "{}{}{}", self.theme.quote_color, line, ansi::RESET)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         Ok(())
@@ -392,12 +512,25 @@ impl TerminalRenderer {
     
     fn render_html(&self, html: &HtmlBlock, output: &mut String) -> Result<(), MarkdownError> {
         // For now, just render as plain text with a different color
-        write!(output, "{}{}{}", ansi::BRIGHT_BLACK, html.content, ansi::RESET)
+        write!(output, "{}{}{}", self.theme.quote_color, html.content, ansi::RESET)
             .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
         
         Ok(())
     }
     
+    fn render_footnote_definition(&self, definition: &FootnoteDefinition, width: Option<usize>, output: &mut String) -> Result<(), MarkdownError> {
+        let label = format!("[{}] ", definition.label);
+        let hang = string_offset::display_width(&label);
+        
+        let mut body = String::new();
+        self.render_wrapped(&definition.content, width.map(|width| width.saturating_sub(hang).max(1)), &mut body)?;
+        
+        let first = format!("{}[{}]{} ", self.theme.link_color, definition.label, ansi::RESET);
+        output.push_str(&prefix_lines(&body, &first, &" ".repeat(hang)));
+        
+        Ok(())
+    }
+    
     fn render_inline(&self, inline: &Inline, output: &mut String) -> Result<(), MarkdownError> {
         match inline {
             Inline::Text(text) => {
@@ -405,7 +538,7 @@ impl TerminalRenderer {
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
             Inline::Emphasis(emphasis) => {
-                write!(output, "{}", ansi::ITALIC)
+                write!(output, "{}", self.theme.emphasis_color)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
                 
                 for inline in &emphasis.content {
@@ -416,7 +549,7 @@ impl TerminalRenderer {
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
             Inline::Strong(strong) => {
-                write!(output, "{}", ansi::BOLD)
+                write!(output, "{}", self.theme.strong_color)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
                 
                 for inline in &strong.content {
@@ -426,13 +559,24 @@ impl TerminalRenderer {
                 write!(output, "{}", ansi::RESET)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
+            Inline::Strikethrough(strikethrough) => {
+                write!(output, "{}", ansi::STRIKETHROUGH)
+                    .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+                
+                for inline in &strikethrough.content {
+                    self.render_inline(inline, output)?;
+                }
+                
+                write!(output, "{}", ansi::RESET)
+                    .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+            }
             Inline::Code(code) => {
-                write!(output, "{}{}`{}`{}", ansi::BG_BLACK, ansi::BRIGHT_GREEN, code.content, ansi::RESET)
+                write!(output, "{}{}`{}`{}", self.theme.code_background, self.theme.code_color, code.content, ansi::RESET)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
             Inline::Link(link) => {
                 if self.config.link_highlighting {
-                    write!(output, "{}{}", ansi::UNDERLINE, ansi::BRIGHT_BLUE)
+                    write!(output, "{}{}", ansi::UNDERLINE, self.theme.link_color)
                         .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
                 }
                 
@@ -440,7 +584,13 @@ impl TerminalRenderer {
                     self.render_inline(inline, output)?;
                 }
                 
-                if self.config.link_highlighting {
+                // A bare URL is already shown in full
+                if link.autolink {
+                    if self.config.link_highlighting {
+                        write!(output, "{}", ansi::RESET)
+                            .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+                    }
+                } else if self.config.link_highlighting {
                     write!(output, "{} ({})", ansi::RESET, link.url)
                         .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
                 } else {
@@ -461,7 +611,11 @@ impl TerminalRenderer {
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
             Inline::Html(html) => {
-                write!(output, "{}{}{}", ansi::BRIGHT_BLACK, html.content, ansi::RESET)
+                write!(output, "{}{}{}", self.theme.quote_color, html.content, ansi::RESET)
+                    .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
+            }
+            Inline::FootnoteReference(reference) => {
+                write!(output, "{}[{}]{}", self.theme.link_color, reference.label, ansi::RESET)
                     .map_err(|e| MarkdownError::RenderError(e.to_string()))?;
             }
         }
@@ -474,23 +628,91 @@ impl TerminalRenderer {
         
         for inline in inlines {
             match inline {
-                Inline::Text(text) => width += text.content.len(),
+                Inline::Text(text) => width += string_offset::display_width(&text.content),
                 Inline::Emphasis(emphasis) => width += self.calculate_inline_width(&emphasis.content),
                 Inline::Strong(strong) => width += self.calculate_inline_width(&strong.content),
-                Inline::Code(code) => width += code.content.len() + 2, // backticks
+                Inline::Strikethrough(strikethrough) => width += self.calculate_inline_width(&strikethrough.content),
+                Inline::Code(code) => width += string_offset::display_width(&code.content) + 2, // backticks
+                Inline::Link(link) if link.autolink => width += self.calculate_inline_width(&link.content),
                 Inline::Link(link) => {
                     width += self.calculate_inline_width(&link.content);
-                    width += link.url.len() + 3; // " ()"
+                    width += string_offset::display_width(&link.url) + 3; // " ()"
                 }
                 Inline::Image(image) => {
-                    width += image.alt.len() + image.url.len() + 12; // "[Image: ] ()"
+                    width += string_offset::display_width(&image.alt) + string_offset::display_width(&image.url) + 12; // "[Image: ] ()"
                 }
                 Inline::LineBreak => width += 0,
                 Inline::SoftBreak => width += 1,
-                Inline::Html(html) => width += html.content.len(),
+                Inline::Html(html) => width += string_offset::display_width(&html.content),
+                Inline::FootnoteReference(reference) => width += string_offset::display_width(&reference.label) + 2, // "[]"
             }
         }
         
         width
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown_parser::MarkdownProcessor;
+
+    /// The rendered lines without their ANSI escapes.
+    fn plain_lines(rendered: &str) -> Vec<String> {
+        let mut plain = String::new();
+        let mut chars = rendered.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\x1b' {
+                chars.by_ref().find(|ch| ch.is_ascii_alphabetic());
+            } else {
+                plain.push(ch);
+            }
+        }
+        plain.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_wrap_counts_display_width_and_keeps_styles() {
+        let text = format!("日本語 {}bold text{} end", ansi::BOLD, ansi::RESET);
+        assert_eq!(display_width(&text), 20);
+        assert_eq!(wrap(&text, 11), format!("日本語 {}bold{}\n{}text{} end", ansi::BOLD, ansi::RESET, ansi::BOLD, ansi::RESET));
+        assert_eq!(wrap("unbreakable-word", 4), "unbreakable-word");
+    }
+
+    #[test]
+    fn test_nested_lists_hang_under_their_markers() {
+        let config = MarkdownConfig { max_width: Some(24), ..MarkdownConfig::default() };
+        let rendered = MarkdownProcessor::with_config(config)
+            .process("> Quoted text that is long enough to wrap.\n\n9. build the release binaries\n   - strip symbols from every binary\n10. ship")
+            .unwrap();
+        assert_eq!(
+            plain_lines(&rendered),
+            vec![
+                "│ Quoted text that is",
+                "│ long enough to wrap.",
+                "9. build the release",
+                "   binaries",
+                "   ◦ strip symbols from",
+                "     every binary",
+                "10. ship",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_columns_line_up_with_wide_characters() {
+        let cell = |text: &str| TableCell { content: vec![Inline::Text(TextInline { content: text.to_string() })] };
+        let mut table = TableBlock::new();
+        table.add_header(cell("Who"));
+        table.add_header(cell("Note"));
+        table.add_row(vec![cell("👨\u{200d}👩\u{200d}👧"), cell("日本")]);
+        table.add_row(vec![cell("cafe\u{301}"), cell("ok")]);
+        let mut document = Document::new();
+        document.add_block(Block::Table(table));
+
+        let rendered = TerminalRenderer::new(&MarkdownConfig::default()).render(&document).unwrap();
+        let widths: Vec<usize> = plain_lines(&rendered).iter().filter(|line| !line.is_empty()).map(|line| string_offset::display_width(line)).collect();
+        assert_eq!(widths.len(), 6);
+        assert!(widths.iter().all(|width| *width == widths[0]), "{:?}", widths);
+    }
+}
@@ -0,0 +1,95 @@
+//! Task Lists
+//!
+//! Finds and toggles the `- [ ]` checkboxes of task list items in markdown
+//! source, so a notebook can be ticked off and saved without rewriting the
+//! rest of it. Lines inside fenced code blocks are never tasks.
+
+/// A task list item in markdown source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskItem {
+    /// The zero-based line the item is on.
+    pub line: usize,
+    pub checked: bool,
+    pub text: String,
+}
+
+/// The byte offset of the checkbox mark on `line`, and whether it is
+/// checked, if the line is a task list item.
+fn checkbox(line: &str) -> Option<(usize, bool)> {
+    let indent = line.len() - line.trim_start().len();
+    let item = &line[indent..];
+    let digits = item.chars().take_while(char::is_ascii_digit).count();
+    let marker = match item[digits..].chars().next()? {
+        '.' | ')' if digits > 0 => digits + 1,
+        '-' | '*' | '+' if digits == 0 => 1,
+        _ => return None,
+    };
+    let rest = item[marker..].strip_prefix(" [")?;
+    let mut chars = rest.chars();
+    let checked = match chars.next()? {
+        ' ' => false,
+        'x' | 'X' => true,
+        _ => return None,
+    };
+    if chars.next() != Some(']') || !matches!(chars.next(), None | Some(' ' | '\r' | '\n')) {
+        return None;
+    }
+    Some((indent + marker + 2, checked))
+}
+
+/// The lines of `source` outside fenced code blocks, with their numbers.
+fn prose_lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut in_code = false;
+    source.split_inclusive('\n').enumerate().filter(move |(_, line)| {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            return false;
+        }
+        !in_code
+    })
+}
+
+pub fn task_items(source: &str) -> Vec<TaskItem> {
+    prose_lines(source)
+        .filter_map(|(line, text)| {
+            let (mark, checked) = checkbox(text)?;
+            Some(TaskItem { line, checked, text: text[mark + 2..].trim().to_string() })
+        })
+        .collect()
+}
+
+/// `source` with the task on `line` checked or unchecked, or `None` if that
+/// line is not a task.
+pub fn toggle_task(source: &str, line: usize) -> Option<String> {
+    let (_, text) = prose_lines(source).find(|(number, _)| *number == line)?;
+    let (mark, checked) = checkbox(text)?;
+    let start = text.as_ptr() as usize - source.as_ptr() as usize + mark;
+    let mut toggled = source.to_string();
+    toggled.replace_range(start..start + 1, if checked { " " } else { "x" });
+    Some(toggled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = "# Release\n\n- [ ] tag\n  * [X] bump version\n- not a task\n```\n- [ ] in code\n```\n10. [ ]\n";
+
+    #[test]
+    fn test_task_items_skip_code_blocks() {
+        let tasks = task_items(NOTEBOOK);
+        assert_eq!(tasks.iter().map(|task| task.line).collect::<Vec<_>>(), vec![2, 3, 8]);
+        assert_eq!(tasks[1], TaskItem { line: 3, checked: true, text: "bump version".to_string() });
+        assert!(tasks[2].text.is_empty());
+    }
+
+    #[test]
+    fn test_toggle_task_flips_only_that_checkbox() {
+        let toggled = toggle_task(NOTEBOOK, 2).unwrap();
+        assert_eq!(toggled, NOTEBOOK.replacen("- [ ] tag", "- [x] tag", 1));
+        assert_eq!(toggle_task(&toggled, 2).unwrap(), NOTEBOOK);
+        assert_eq!(toggle_task(NOTEBOOK, 3).unwrap(), NOTEBOOK.replace("[X]", "[ ]"));
+        assert_eq!(toggle_task(NOTEBOOK, 6), None);
+        assert_eq!(toggle_task(NOTEBOOK, 4), None);
+    }
+}
@@ -1,12 +1,47 @@
 //! Markdown Themes
 //! 
-//! This module provides theming support for markdown rendering.
+//! This module provides theming support for markdown rendering. Every theme
+//! is made for either a dark or a light background and has a partner for the
+//! other, so output stays readable whichever the app theme is.
 
 use serde::{Deserialize, Serialize};
 
+/// The kind of background a theme is made for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeVariant {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeVariant {
+    /// The variant that reads well on `background`, a `#rrggbb` color. Above
+    /// a relative luminance of 0.179 dark text has the better contrast.
+    pub fn for_background(background: &str) -> Option<Self> {
+        let luminance = relative_luminance(background)?;
+        Some(if luminance > 0.179 { ThemeVariant::Light } else { ThemeVariant::Dark })
+    }
+}
+
+/// The WCAG relative luminance of a `#rrggbb` color, from 0 for black to 1
+/// for white.
+pub fn relative_luminance(hex: &str) -> Option<f32> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| -> Option<f32> {
+        let value = u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()? as f32 / 255.0;
+        Some(if value <= 0.03928 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) })
+    };
+    Some(0.2126 * channel(0)? + 0.7152 * channel(2)? + 0.0722 * channel(4)?)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarkdownTheme {
     pub name: String,
+    #[serde(default)]
+    pub variant: ThemeVariant,
     pub heading_colors: [String; 6],
     pub text_color: String,
     pub emphasis_color: String,
@@ -23,23 +58,24 @@ impl Default for MarkdownTheme {
     fn default() -> Self {
         Self {
             name: "default".to_string(),
+            variant: ThemeVariant::Dark,
             heading_colors: [
-                "\\x1b[94m".to_string(),  // bright blue
-                "\\x1b[92m".to_string(),  // bright green
-                "\\x1b[93m".to_string(),  // bright yellow
-                "\\x1b[95m".to_string(),  // bright magenta
-                "\\x1b[96m".to_string(),  // bright cyan
-                "\\x1b[97m".to_string(),  // bright white
+                "\x1b[94m".to_string(),  // bright blue
+                "\x1b[92m".to_string(),  // bright green
+                "\x1b[93m".to_string(),  // bright yellow
+                "\x1b[95m".to_string(),  // bright magenta
+                "\x1b[96m".to_string(),  // bright cyan
+                "\x1b[97m".to_string(),  // bright white
             ],
-            text_color: "\\x1b[97m".to_string(),        // bright white
-            emphasis_color: "\\x1b[3m".to_string(),     // italic
-            strong_color: "\\x1b[1m".to_string(),       // bold
-            code_color: "\\x1b[92m".to_string(),        // bright green
-            code_background: "\\x1b[40m".to_string(),   // black background
-            link_color: "\\x1b[94m".to_string(),        // bright blue
-            quote_color: "\\x1b[90m".to_string(),       // bright black
-            table_border_color: "\\x1b[97m".to_string(), // bright white
-            list_marker_color: "\\x1b[96m".to_string(),  // bright cyan
+            text_color: "\x1b[97m".to_string(),        // bright white
+            emphasis_color: "\x1b[3m".to_string(),     // italic
+            strong_color: "\x1b[1m".to_string(),       // bold
+            code_color: "\x1b[92m".to_string(),        // bright green
+            code_background: "\x1b[40m".to_string(),   // black background
+            link_color: "\x1b[94m".to_string(),        // bright blue
+            quote_color: "\x1b[90m".to_string(),       // bright black
+            table_border_color: "\x1b[97m".to_string(), // bright white
+            list_marker_color: "\x1b[96m".to_string(),  // bright cyan
         }
     }
 }
@@ -48,115 +84,120 @@ impl MarkdownTheme {
     pub fn monokai() -> Self {
         Self {
             name: "monokai".to_string(),
+            variant: ThemeVariant::Dark,
             heading_colors: [
-                "\\x1b[38;5;81m".to_string(),   // cyan
-                "\\x1b[38;5;118m".to_string(),  // green
-                "\\x1b[38;5;227m".to_string(),  // yellow
-                "\\x1b[38;5;141m".to_string(),  // purple
-                "\\x1b[38;5;208m".to_string(),  // orange
-                "\\x1b[38;5;15m".to_string(),   // white
+                "\x1b[38;5;81m".to_string(),   // cyan
+                "\x1b[38;5;118m".to_string(),  // green
+                "\x1b[38;5;227m".to_string(),  // yellow
+                "\x1b[38;5;141m".to_string(),  // purple
+                "\x1b[38;5;208m".to_string(),  // orange
+                "\x1b[38;5;15m".to_string(),   // white
             ],
-            text_color: "\\x1b[38;5;15m".to_string(),     // white
-            emphasis_color: "\\x1b[3;38;5;15m".to_string(), // italic white
-            strong_color: "\\x1b[1;38;5;15m".to_string(),   // bold white
-            code_color: "\\x1b[38;5;118m".to_string(),      // green
-            code_background: "\\x1b[48;5;235m".to_string(), // dark grey
-            link_color: "\\x1b[38;5;81m".to_string(),       // cyan
-            quote_color: "\\x1b[38;5;102m".to_string(),     // grey
-            table_border_color: "\\x1b[38;5;15m".to_string(), // white
-            list_marker_color: "\\x1b[38;5;208m".to_string(),  // orange
+            text_color: "\x1b[38;5;15m".to_string(),     // white
+            emphasis_color: "\x1b[3;38;5;15m".to_string(), // italic white
+            strong_color: "\x1b[1;38;5;15m".to_string(),   // bold white
+            code_color: "\x1b[38;5;118m".to_string(),      // green
+            code_background: "\x1b[48;5;235m".to_string(), // dark grey
+            link_color: "\x1b[38;5;81m".to_string(),       // cyan
+            quote_color: "\x1b[38;5;102m".to_string(),     // grey
+            table_border_color: "\x1b[38;5;15m".to_string(), // white
+            list_marker_color: "\x1b[38;5;208m".to_string(),  // orange
         }
     }
     
     pub fn solarized_dark() -> Self {
         Self {
             name: "solarized_dark".to_string(),
+            variant: ThemeVariant::Dark,
             heading_colors: [
-                "\\x1b[38;5;33m".to_string(),   // blue
-                "\\x1b[38;5;64m".to_string(),   // green
-                "\\x1b[38;5;136m".to_string(),  // yellow
-                "\\x1b[38;5;125m".to_string(),  // magenta
-                "\\x1b[38;5;37m".to_string(),   // cyan
-                "\\x1b[38;5;230m".to_string(),  // base3
+                "\x1b[38;5;33m".to_string(),   // blue
+                "\x1b[38;5;64m".to_string(),   // green
+                "\x1b[38;5;136m".to_string(),  // yellow
+                "\x1b[38;5;125m".to_string(),  // magenta
+                "\x1b[38;5;37m".to_string(),   // cyan
+                "\x1b[38;5;230m".to_string(),  // base3
             ],
-            text_color: "\\x1b[38;5;230m".to_string(),      // base3
-            emphasis_color: "\\x1b[3;38;5;230m".to_string(), // italic base3
-            strong_color: "\\x1b[1;38;5;230m".to_string(),   // bold base3
-            code_color: "\\x1b[38;5;64m".to_string(),       // green
-            code_background: "\\x1b[48;5;235m".to_string(), // base02
-            link_color: "\\x1b[38;5;33m".to_string(),       // blue
-            quote_color: "\\x1b[38;5;244m".to_string(),     // base0
-            table_border_color: "\\x1b[38;5;244m".to_string(), // base0
-            list_marker_color: "\\x1b[38;5;166m".to_string(),  // orange
+            text_color: "\x1b[38;5;230m".to_string(),      // base3
+            emphasis_color: "\x1b[3;38;5;230m".to_string(), // italic base3
+            strong_color: "\x1b[1;38;5;230m".to_string(),   // bold base3
+            code_color: "\x1b[38;5;64m".to_string(),       // green
+            code_background: "\x1b[48;5;235m".to_string(), // base02
+            link_color: "\x1b[38;5;33m".to_string(),       // blue
+            quote_color: "\x1b[38;5;244m".to_string(),     // base0
+            table_border_color: "\x1b[38;5;244m".to_string(), // base0
+            list_marker_color: "\x1b[38;5;166m".to_string(),  // orange
         }
     }
     
     pub fn solarized_light() -> Self {
         Self {
             name: "solarized_light".to_string(),
+            variant: ThemeVariant::Light,
             heading_colors: [
-                "\\x1b[38;5;33m".to_string(),   // blue
-                "\\x1b[38;5;64m".to_string(),   // green
-                "\\x1b[38;5;136m".to_string(),  // yellow
-                "\\x1b[38;5;125m".to_string(),  // magenta
-                "\\x1b[38;5;37m".to_string(),   // cyan
-                "\\x1b[38;5;235m".to_string(),  // base02
+                "\x1b[38;5;33m".to_string(),   // blue
+                "\x1b[38;5;64m".to_string(),   // green
+                "\x1b[38;5;136m".to_string(),  // yellow
+                "\x1b[38;5;125m".to_string(),  // magenta
+                "\x1b[38;5;37m".to_string(),   // cyan
+                "\x1b[38;5;235m".to_string(),  // base02
             ],
-            text_color: "\\x1b[38;5;235m".to_string(),      // base02
-            emphasis_color: "\\x1b[3;38;5;235m".to_string(), // italic base02
-            strong_color: "\\x1b[1;38;5;235m".to_string(),   // bold base02
-            code_color: "\\x1b[38;5;64m".to_string(),       // green
-            code_background: "\\x1b[48;5;230m".to_string(), // base3
-            link_color: "\\x1b[38;5;33m".to_string(),       // blue
-            quote_color: "\\x1b[38;5;244m".to_string(),     // base0
-            table_border_color: "\\x1b[38;5;244m".to_string(), // base0
-            list_marker_color: "\\x1b[38;5;166m".to_string(),  // orange
+            text_color: "\x1b[38;5;235m".to_string(),      // base02
+            emphasis_color: "\x1b[3;38;5;235m".to_string(), // italic base02
+            strong_color: "\x1b[1;38;5;235m".to_string(),   // bold base02
+            code_color: "\x1b[38;5;64m".to_string(),       // green
+            code_background: "\x1b[48;5;230m".to_string(), // base3
+            link_color: "\x1b[38;5;33m".to_string(),       // blue
+            quote_color: "\x1b[38;5;244m".to_string(),     // base0
+            table_border_color: "\x1b[38;5;244m".to_string(), // base0
+            list_marker_color: "\x1b[38;5;166m".to_string(),  // orange
         }
     }
     
     pub fn github() -> Self {
         Self {
             name: "github".to_string(),
+            variant: ThemeVariant::Light,
             heading_colors: [
-                "\\x1b[38;5;4m".to_string(),    // blue
-                "\\x1b[38;5;2m".to_string(),    // green
-                "\\x1b[38;5;3m".to_string(),    // yellow
-                "\\x1b[38;5;5m".to_string(),    // magenta
-                "\\x1b[38;5;6m".to_string(),    // cyan
-                "\\x1b[38;5;8m".to_string(),    // grey
+                "\x1b[38;5;4m".to_string(),    // blue
+                "\x1b[38;5;2m".to_string(),    // green
+                "\x1b[38;5;3m".to_string(),    // yellow
+                "\x1b[38;5;5m".to_string(),    // magenta
+                "\x1b[38;5;6m".to_string(),    // cyan
+                "\x1b[38;5;8m".to_string(),    // grey
             ],
-            text_color: "\\x1b[38;5;0m".to_string(),        // black
-            emphasis_color: "\\x1b[3;38;5;0m".to_string(),   // italic black
-            strong_color: "\\x1b[1;38;5;0m".to_string(),     // bold black
-            code_color: "\\x1b[38;5;1m".to_string(),        // red
-            code_background: "\\x1b[48;5;7m".to_string(),   // light grey
-            link_color: "\\x1b[38;5;4m".to_string(),        // blue
-            quote_color: "\\x1b[38;5;8m".to_string(),       // grey
-            table_border_color: "\\x1b[38;5;8m".to_string(), // grey
-            list_marker_color: "\\x1b[38;5;0m".to_string(),  // black
+            text_color: "\x1b[38;5;0m".to_string(),        // black
+            emphasis_color: "\x1b[3;38;5;0m".to_string(),   // italic black
+            strong_color: "\x1b[1;38;5;0m".to_string(),     // bold black
+            code_color: "\x1b[38;5;1m".to_string(),        // red
+            code_background: "\x1b[48;5;7m".to_string(),   // light grey
+            link_color: "\x1b[38;5;4m".to_string(),        // blue
+            quote_color: "\x1b[38;5;8m".to_string(),       // grey
+            table_border_color: "\x1b[38;5;8m".to_string(), // grey
+            list_marker_color: "\x1b[38;5;0m".to_string(),  // black
         }
     }
     
     pub fn dracula() -> Self {
         Self {
             name: "dracula".to_string(),
+            variant: ThemeVariant::Dark,
             heading_colors: [
-                "\\x1b[38;5;141m".to_string(),  // purple
-                "\\x1b[38;5;84m".to_string(),   // green
-                "\\x1b[38;5;228m".to_string(),  // yellow
-                "\\x1b[38;5;212m".to_string(),  // pink
-                "\\x1b[38;5;117m".to_string(),  // cyan
-                "\\x1b[38;5;15m".to_string(),   // white
+                "\x1b[38;5;141m".to_string(),  // purple
+                "\x1b[38;5;84m".to_string(),   // green
+                "\x1b[38;5;228m".to_string(),  // yellow
+                "\x1b[38;5;212m".to_string(),  // pink
+                "\x1b[38;5;117m".to_string(),  // cyan
+                "\x1b[38;5;15m".to_string(),   // white
             ],
-            text_color: "\\x1b[38;5;15m".to_string(),       // white
-            emphasis_color: "\\x1b[3;38;5;15m".to_string(),  // italic white
-            strong_color: "\\x1b[1;38;5;15m".to_string(),    // bold white
-            code_color: "\\x1b[38;5;84m".to_string(),       // green
-            code_background: "\\x1b[48;5;235m".to_string(), // dark grey
-            link_color: "\\x1b[38;5;117m".to_string(),      // cyan
-            quote_color: "\\x1b[38;5;102m".to_string(),     // grey
-            table_border_color: "\\x1b[38;5;15m".to_string(), // white
-            list_marker_color: "\\x1b[38;5;212m".to_string(),  // pink
+            text_color: "\x1b[38;5;15m".to_string(),       // white
+            emphasis_color: "\x1b[3;38;5;15m".to_string(),  // italic white
+            strong_color: "\x1b[1;38;5;15m".to_string(),    // bold white
+            code_color: "\x1b[38;5;84m".to_string(),       // green
+            code_background: "\x1b[48;5;235m".to_string(), // dark grey
+            link_color: "\x1b[38;5;117m".to_string(),      // cyan
+            quote_color: "\x1b[38;5;102m".to_string(),     // grey
+            table_border_color: "\x1b[38;5;15m".to_string(), // white
+            list_marker_color: "\x1b[38;5;212m".to_string(),  // pink
         }
     }
     
@@ -171,6 +212,20 @@ impl MarkdownTheme {
         }
     }
     
+    /// This theme's counterpart for the other kind of background; the theme
+    /// itself when it already suits `variant`.
+    pub fn paired(&self, variant: ThemeVariant) -> Self {
+        if self.variant == variant {
+            return self.clone();
+        }
+        match (self.name.as_str(), variant) {
+            ("solarized_dark", _) => Self::solarized_light(),
+            ("solarized_light", _) => Self::solarized_dark(),
+            (_, ThemeVariant::Light) => Self::github(),
+            (_, ThemeVariant::Dark) => Self::default(),
+        }
+    }
+
     pub fn available_themes() -> Vec<String> {
         vec![
             "default".to_string(),
@@ -202,6 +257,19 @@ mod tests {
         let unknown = MarkdownTheme::by_name("unknown");
         assert_eq!(unknown.name, "default");
     }
+
+    #[test]
+    fn test_theme_pairs_with_background() {
+        assert_eq!(ThemeVariant::for_background("#1e1e1e"), Some(ThemeVariant::Dark));
+        assert_eq!(ThemeVariant::for_background("#fdf6e3"), Some(ThemeVariant::Light));
+        assert_eq!(ThemeVariant::for_background("not a color"), None);
+
+        let dark = MarkdownTheme::solarized_dark();
+        assert_eq!(dark.paired(ThemeVariant::Light).name, "solarized_light");
+        assert_eq!(dark.paired(ThemeVariant::Dark).name, "solarized_dark");
+        assert_eq!(MarkdownTheme::dracula().paired(ThemeVariant::Light).name, "github");
+        assert_eq!(MarkdownTheme::github().paired(ThemeVariant::Dark).name, "default");
+    }
     
     #[test]
     fn test_available_themes() {
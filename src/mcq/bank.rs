@@ -0,0 +1,307 @@
+//! Question bank and spaced repetition.
+//!
+//! Question sets are imported from YAML or JSON files and kept in the local
+//! database, each question with an SM-2 review schedule. A drill asks the
+//! questions that are due, most overdue first, then ones never asked. A right
+//! answer pushes the next review out by a growing interval; a wrong one, or
+//! running out of time, brings the question back the same day.
+
+use super::{MultipleChoiceQuestion, QuestionResponse, Quiz};
+use crate::db;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// How soon a missed question is asked again.
+const RELEARN_SECS: i64 = 10 * 60;
+
+/// The lowest ease SM-2 allows, so hard questions still spread out.
+const MIN_EASE: f32 = 1.3;
+
+#[derive(Error, Debug)]
+pub enum BankError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML parsing error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("JSON parsing error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("{0} is not a .yaml, .yml or .json file")]
+    UnknownFormat(String),
+    #[error("Question '{id}': {reason}")]
+    Invalid { id: String, reason: String },
+}
+
+/// Questions imported or exported together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuestionSet {
+    pub name: String,
+    pub questions: Vec<MultipleChoiceQuestion>,
+}
+
+impl QuestionSet {
+    /// Reads a set from YAML or JSON, by the file's extension.
+    pub fn import(path: &Path) -> Result<Self, BankError> {
+        let content = fs::read_to_string(path)?;
+        let set: Self = match Format::of(path)? {
+            Format::Yaml => serde_yaml::from_str(&content)?,
+            Format::Json => serde_json::from_str(&content)?,
+        };
+        set.validate()?;
+        Ok(set)
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), BankError> {
+        let content = match Format::of(path)? {
+            Format::Yaml => serde_yaml::to_string(self)?,
+            Format::Json => serde_json::to_string_pretty(self)?,
+        };
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Checks that every question can be asked and has a unique id.
+    pub fn validate(&self) -> Result<(), BankError> {
+        let mut ids = HashSet::new();
+        for question in &self.questions {
+            let invalid = |reason: &str| Err(BankError::Invalid { id: question.id.clone(), reason: reason.to_string() });
+            if question.id.trim().is_empty() {
+                return invalid("the id is empty");
+            }
+            if !ids.insert(question.id.as_str()) {
+                return invalid("the id is used twice");
+            }
+            if question.options.len() < 2 {
+                return invalid("there must be at least two options");
+            }
+            if question.correct_answer >= question.options.len() {
+                return invalid("correct_answer is not one of the options");
+            }
+        }
+        Ok(())
+    }
+}
+
+enum Format {
+    Yaml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, BankError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Ok(Format::Yaml),
+            Some("json") => Ok(Format::Json),
+            _ => Err(BankError::UnknownFormat(path.display().to_string())),
+        }
+    }
+}
+
+/// When a question is next asked, by the SM-2 algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Review {
+    pub ease: f32,
+    pub interval_days: u32,
+    /// Right answers in a row.
+    pub repetitions: u32,
+    pub due_at: i64,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Self { ease: 2.5, interval_days: 0, repetitions: 0, due_at: 0 }
+    }
+}
+
+impl Review {
+    /// The schedule after an answer graded `quality`, from 0 (no answer) to
+    /// 5 (right at once).
+    pub fn after(self, quality: u8, now: i64) -> Self {
+        let q = f32::from(5 - quality.min(5));
+        let ease = (self.ease + 0.1 - q * (0.08 + q * 0.02)).max(MIN_EASE);
+        if quality < 3 {
+            return Self { ease, interval_days: 0, repetitions: 0, due_at: now + RELEARN_SECS };
+        }
+        let interval_days = match self.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (self.interval_days.max(1) as f32 * ease).round() as u32,
+        };
+        Self { ease, interval_days, repetitions: self.repetitions + 1, due_at: now + i64::from(interval_days) * DAY_SECS }
+    }
+}
+
+/// Grades an answer for SM-2: 5 when right within a third of the time
+/// limit, 4 within two thirds, 3 when slower, 1 when wrong and 0 when time
+/// ran out.
+pub fn quality(response: &QuestionResponse, question: &MultipleChoiceQuestion) -> u8 {
+    let limit = question.time_limit.unwrap_or(30);
+    match (response.is_correct, response.selected_answer) {
+        (false, None) => 0,
+        (false, Some(_)) => 1,
+        (true, _) if response.response_time * 3 <= limit => 5,
+        (true, _) if response.response_time * 3 <= limit * 2 => 4,
+        (true, _) => 3,
+    }
+}
+
+/// Every stored question, with its set and its review schedule.
+#[derive(Debug, Clone, Default)]
+pub struct QuestionBank {
+    questions: Vec<(String, MultipleChoiceQuestion)>,
+    reviews: HashMap<String, Review>,
+}
+
+impl QuestionBank {
+    pub fn load(conn: &Connection) -> Self {
+        let questions = db::load_quiz_questions(conn).unwrap_or_else(|e| {
+            log::warn!("Failed to load quiz questions: {}", e);
+            Vec::new()
+        });
+        let reviews = db::load_quiz_reviews(conn).unwrap_or_else(|e| {
+            log::warn!("Failed to load quiz reviews: {}", e);
+            Vec::new()
+        });
+        Self { questions, reviews: reviews.into_iter().collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.questions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.questions.is_empty()
+    }
+
+    /// Stores `set`, replacing questions with the same ids but keeping their
+    /// schedules. Returns how many questions it has.
+    pub fn add_set(&mut self, conn: &Connection, set: &QuestionSet) -> Result<usize, BankError> {
+        set.validate()?;
+        for question in &set.questions {
+            db::save_quiz_question(conn, &set.name, question)?;
+            match self.questions.iter_mut().find(|(_, stored)| stored.id == question.id) {
+                Some(stored) => *stored = (set.name.clone(), question.clone()),
+                None => self.questions.push((set.name.clone(), question.clone())),
+            }
+        }
+        Ok(set.questions.len())
+    }
+
+    /// All questions as one set named `name`, for export.
+    pub fn to_set(&self, name: &str) -> QuestionSet {
+        QuestionSet { name: name.to_string(), questions: self.questions.iter().map(|(_, question)| question.clone()).collect() }
+    }
+
+    /// Up to `limit` questions to drill at `now`: due ones, most overdue
+    /// first, then ones never asked, in the order they were added.
+    pub fn due(&self, now: i64, limit: usize) -> Quiz {
+        let mut due: Vec<(&MultipleChoiceQuestion, i64)> = self
+            .questions
+            .iter()
+            .filter_map(|(_, question)| self.reviews.get(&question.id).map(|review| (question, review.due_at)))
+            .filter(|(_, due_at)| *due_at <= now)
+            .collect();
+        due.sort_by_key(|(_, due_at)| *due_at);
+        let new = self.questions.iter().map(|(_, question)| question).filter(|question| !self.reviews.contains_key(&question.id));
+        let mut quiz = Quiz::new();
+        for question in due.into_iter().map(|(question, _)| question).chain(new).take(limit) {
+            quiz.add_question(question.clone());
+        }
+        quiz
+    }
+
+    /// When the next stored question comes due, if any has been asked.
+    pub fn next_due(&self) -> Option<i64> {
+        self.reviews.values().map(|review| review.due_at).min()
+    }
+
+    /// Reschedules the questions answered in `quiz`.
+    pub fn record(&mut self, conn: &Connection, quiz: &Quiz, now: i64) {
+        for response in &quiz.responses {
+            if !self.questions.iter().any(|(_, question)| question.id == response.question_id) {
+                continue;
+            }
+            let Some(question) = quiz.questions.iter().find(|question| question.id == response.question_id) else {
+                continue;
+            };
+            let review = self.reviews.get(&question.id).copied().unwrap_or_default().after(quality(response, question), now);
+            if let Err(e) = db::save_quiz_review(conn, &question.id, &review) {
+                log::warn!("Failed to save the review of {}: {}", question.id, e);
+            }
+            self.reviews.insert(question.id.clone(), review);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcq::{shell_basics, QuizSession};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_sm2_intervals_grow_and_reset() {
+        let review = Review::default().after(5, 0);
+        assert_eq!((review.interval_days, review.due_at), (1, DAY_SECS));
+        let review = review.after(4, 0);
+        assert_eq!(review.interval_days, 6);
+        let review = review.after(4, 0);
+        assert_eq!(review.interval_days, 16);
+        let missed = review.after(1, 100);
+        assert_eq!((missed.repetitions, missed.due_at), (0, 100 + RELEARN_SECS));
+        assert!(missed.ease < review.ease && missed.ease >= MIN_EASE);
+    }
+
+    #[test]
+    fn test_import_validates_and_export_round_trips() {
+        let dir = std::env::temp_dir().join(format!("warpish-quiz-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let set = QuestionSet { name: "shell".to_string(), questions: shell_basics().questions };
+        for file in ["set.yaml", "set.json"] {
+            set.export(&dir.join(file)).unwrap();
+            assert_eq!(QuestionSet::import(&dir.join(file)).unwrap(), set);
+        }
+        fs::write(
+            dir.join("bad.yml"),
+            "name: bad\nquestions:\n  - id: q\n    question: Which?\n    options: [a, b]\n    correct_answer: 2\n    difficulty: Easy\n    category: x\n",
+        )
+        .unwrap();
+        let error = QuestionSet::import(&dir.join("bad.yml")).unwrap_err();
+        assert!(matches!(error, BankError::Invalid { ref id, .. } if id == "q"), "{}", error);
+        assert!(matches!(set.export(&dir.join("set.txt")), Err(BankError::UnknownFormat(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_missed_questions_come_back_first() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::create_quiz_tables(&conn).unwrap();
+        let mut bank = QuestionBank::default();
+        assert_eq!(bank.add_set(&conn, &QuestionSet { name: "shell".to_string(), questions: shell_basics().questions }).unwrap(), 5);
+
+        // Answer the first question right and the second wrong.
+        let start = Utc.timestamp_opt(1_000_000, 0).unwrap();
+        let mut session = QuizSession::new(bank.due(start.timestamp(), 2), start);
+        session.answer(1, start);
+        session.next(start);
+        session.answer(0, start);
+        bank.record(&conn, &session.quiz, start.timestamp());
+
+        let now = start.timestamp() + RELEARN_SECS;
+        let ids: Vec<String> = bank.due(now, 10).questions.into_iter().map(|question| question.id).collect();
+        assert_eq!(ids, vec!["shell.last_status", "shell.stderr", "shell.and", "shell.xargs"]);
+
+        let reloaded = QuestionBank::load(&conn);
+        assert_eq!(reloaded.len(), 5);
+        assert_eq!(reloaded.next_due(), Some(now));
+        let tomorrow = reloaded.due(start.timestamp() + DAY_SECS, 2);
+        assert_eq!(tomorrow.questions[1].id, "shell.hidden");
+    }
+}
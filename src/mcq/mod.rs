@@ -1,13 +1,20 @@
 //! Multiple Choice Question Handling
 //! 
 //! This module provides functionality to parse, present, and evaluate multiple choice questions in a terminal environment.
+//!
+//! In the app a quiz runs as a [`QuizSession`], one question at a time with
+//! the clock passed in, and ends in a [`QuizSummary`] shown as a block.
+//! `Quiz::conduct` is the same quiz on stdin. Questions to drill come from
+//! the spaced-repetition bank in [`bank`].
+
+pub mod bank;
+pub mod survey;
 
-use std::collections::HashMap;
 use std::io::{self, Write};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MultipleChoiceQuestion {
     pub id: String,
     pub question: String,
@@ -19,17 +26,18 @@ pub struct MultipleChoiceQuestion {
     pub time_limit: Option<u32>, // in seconds
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum QuestionDifficulty {
     Easy,
     Medium,
     Hard,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct QuestionResponse {
     pub question_id: String,
-    pub selected_answer: usize,
+    /// `None` when the time limit ran out first.
+    pub selected_answer: Option<usize>,
     pub is_correct: bool,
     pub response_time: u32, // in seconds
     pub timestamp: DateTime<Utc>,
@@ -102,6 +110,7 @@ impl MultipleChoiceQuestion {
 }
 
 /// A struct to manage quizzing sessions
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Quiz {
     pub questions: Vec<MultipleChoiceQuestion>,
     pub responses: Vec<QuestionResponse>,
@@ -147,7 +156,7 @@ impl Quiz {
                 
                 let response = QuestionResponse {
                     question_id: question.id.clone(),
-                    selected_answer: selected,
+                    selected_answer: Some(selected),
                     is_correct,
                     response_time,
                     timestamp: question_end,
@@ -173,47 +182,7 @@ impl Quiz {
 
     fn print_results(&self) {
         println!("\n=== Quiz Results ===");
-        
-        let correct_count = self.responses.iter().filter(|r| r.is_correct).count();
-        let total_questions = self.responses.len();
-        let score_percentage = if total_questions > 0 {
-            (correct_count as f32 / total_questions as f32) * 100.0
-        } else {
-            0.0
-        };
-        
-        println!("Score: {}/{} ({:.1}%)", correct_count, total_questions, score_percentage);
-        
-        if let (Some(start), Some(end)) = (self.start_time, self.end_time) {
-            let total_time = (end - start).num_seconds();
-            println!("Total time: {} seconds", total_time);
-        }
-        
-        let avg_response_time = if !self.responses.is_empty() {
-            self.responses.iter().map(|r| r.response_time).sum::<u32>() / self.responses.len() as u32
-        } else {
-            0
-        };
-        
-        println!("Average response time: {} seconds", avg_response_time);
-        
-        // Performance by difficulty
-        let mut difficulty_stats = HashMap::new();
-        for response in &self.responses {
-            if let Some(question) = self.questions.iter().find(|q| q.id == response.question_id) {
-                let stats = difficulty_stats.entry(question.difficulty.clone()).or_insert((0, 0));
-                stats.1 += 1; // total
-                if response.is_correct {
-                    stats.0 += 1; // correct
-                }
-            }
-        }
-        
-        println!("\nPerformance by difficulty:");
-        for (difficulty, (correct, total)) in difficulty_stats {
-            let percentage = if total > 0 { (correct as f32 / total as f32) * 100.0 } else { 0.0 };
-            println!("  {:?}: {}/{} ({:.1}%)", difficulty, correct, total, percentage);
-        }
+        println!("{}", QuizSummary::of(self).render(self));
     }
     
     pub fn get_score(&self) -> f32 {
@@ -233,6 +202,210 @@ impl Default for Quiz {
     }
 }
 
+/// How the question on screen was settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Correct,
+    Incorrect,
+    TimedOut,
+}
+
+/// A quiz shown one question at a time. After each answer the outcome stays
+/// up until [`QuizSession::next`] moves on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuizSession {
+    pub quiz: Quiz,
+    pub current: usize,
+    pub question_started: DateTime<Utc>,
+    /// Set once the current question is answered or out of time.
+    pub outcome: Option<Outcome>,
+}
+
+impl QuizSession {
+    pub fn new(mut quiz: Quiz, now: DateTime<Utc>) -> Self {
+        quiz.start_time = Some(now);
+        Self { quiz, current: 0, question_started: now, outcome: None }
+    }
+
+    /// The question on screen, or `None` once the quiz is over.
+    pub fn question(&self) -> Option<&MultipleChoiceQuestion> {
+        self.quiz.questions.get(self.current)
+    }
+
+    /// The seconds left to answer, for questions with a time limit.
+    pub fn time_left(&self, now: DateTime<Utc>) -> Option<f32> {
+        let limit = self.question()?.time_limit?;
+        let elapsed = (now - self.question_started).num_milliseconds() as f32 / 1000.0;
+        Some((limit as f32 - elapsed).max(0.0))
+    }
+
+    /// Records `selected` for the current question, unless it is already
+    /// settled.
+    pub fn answer(&mut self, selected: usize, now: DateTime<Utc>) -> Option<Outcome> {
+        let question = self.question()?;
+        if self.outcome.is_some() || selected >= question.options.len() {
+            return None;
+        }
+        let outcome = if question.evaluate(selected) { Outcome::Correct } else { Outcome::Incorrect };
+        self.settle(Some(selected), outcome, now);
+        Some(outcome)
+    }
+
+    /// Settles the current question as timed out once its limit has passed.
+    /// Returns whether that happened.
+    pub fn check_time(&mut self, now: DateTime<Utc>) -> bool {
+        if self.outcome.is_some() || self.time_left(now) != Some(0.0) {
+            return false;
+        }
+        self.settle(None, Outcome::TimedOut, now);
+        true
+    }
+
+    fn settle(&mut self, selected: Option<usize>, outcome: Outcome, now: DateTime<Utc>) {
+        let Some(question) = self.question() else {
+            return;
+        };
+        let response = QuestionResponse {
+            question_id: question.id.clone(),
+            selected_answer: selected,
+            is_correct: outcome == Outcome::Correct,
+            response_time: (now - self.question_started).num_seconds().max(0) as u32,
+            timestamp: now,
+        };
+        self.quiz.responses.push(response);
+        self.outcome = Some(outcome);
+    }
+
+    /// Moves to the next question. Returns `false` when there is none, which
+    /// ends the quiz.
+    pub fn next(&mut self, now: DateTime<Utc>) -> bool {
+        self.current += 1;
+        self.outcome = None;
+        self.question_started = now;
+        if self.question().is_none() {
+            self.quiz.end_time = Some(now);
+            return false;
+        }
+        true
+    }
+}
+
+/// The results of a finished, or abandoned, quiz.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuizSummary {
+    pub correct: usize,
+    /// Questions answered or timed out.
+    pub total: usize,
+    pub total_secs: i64,
+    pub average_response_secs: u32,
+    /// Correct and total answers per difficulty, easiest first.
+    pub by_difficulty: Vec<(QuestionDifficulty, usize, usize)>,
+}
+
+impl QuizSummary {
+    pub fn of(quiz: &Quiz) -> Self {
+        let difficulty_of = |response: &QuestionResponse| quiz.questions.iter().find(|q| q.id == response.question_id).map(|q| q.difficulty.clone());
+        let by_difficulty = [QuestionDifficulty::Easy, QuestionDifficulty::Medium, QuestionDifficulty::Hard]
+            .into_iter()
+            .map(|difficulty| {
+                let responses: Vec<&QuestionResponse> = quiz.responses.iter().filter(|r| difficulty_of(r).as_ref() == Some(&difficulty)).collect();
+                let correct = responses.iter().filter(|r| r.is_correct).count();
+                (difficulty, correct, responses.len())
+            })
+            .filter(|(_, _, total)| *total > 0)
+            .collect();
+        Self {
+            correct: quiz.responses.iter().filter(|r| r.is_correct).count(),
+            total: quiz.responses.len(),
+            total_secs: match (quiz.start_time, quiz.end_time) {
+                (Some(start), Some(end)) => (end - start).num_seconds(),
+                _ => 0,
+            },
+            average_response_secs: match quiz.responses.len() {
+                0 => 0,
+                n => quiz.responses.iter().map(|r| r.response_time).sum::<u32>() / n as u32,
+            },
+            by_difficulty,
+        }
+    }
+
+    pub fn percentage(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.correct as f32 / self.total as f32 * 100.0
+        }
+    }
+
+    /// The score, a line per difficulty and a line per question, marked
+    /// ✓, ✗ or ⏱.
+    pub fn render(&self, quiz: &Quiz) -> String {
+        let filled = (self.percentage() / 10.0).round() as usize;
+        let mut text = format!(
+            "Score  {}{}  {}/{} ({:.1}%)\nTime   {}s, {}s per question\n",
+            "█".repeat(filled),
+            "░".repeat(10 - filled),
+            self.correct,
+            self.total,
+            self.percentage(),
+            self.total_secs,
+            self.average_response_secs
+        );
+        if !self.by_difficulty.is_empty() {
+            text.push('\n');
+        }
+        for (difficulty, correct, total) in &self.by_difficulty {
+            text.push_str(&format!("{:<7}{}/{}\n", format!("{:?}", difficulty), correct, total));
+        }
+        text.push('\n');
+        for response in &quiz.responses {
+            let Some(question) = quiz.questions.iter().find(|q| q.id == response.question_id) else {
+                continue;
+            };
+            let line = match response.selected_answer {
+                _ if response.is_correct => format!("✓ {}", question.question),
+                Some(selected) => format!(
+                    "✗ {} (answered {}; correct: {})",
+                    question.question,
+                    question.options.get(selected).map_or("?", String::as_str),
+                    question.get_correct_answer_text()
+                ),
+                None => format!("⏱ {} (out of time; correct: {})", question.question, question.get_correct_answer_text()),
+            };
+            text.push_str(&line);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// A short quiz on everyday shell commands, for drilling without a bank.
+pub fn shell_basics() -> Quiz {
+    let question = |id: &str, text: &str, options: &[&str], correct: usize, difficulty: QuestionDifficulty, explanation: &str| {
+        MultipleChoiceQuestion::new(
+            id.to_string(),
+            text.to_string(),
+            options.iter().map(|option| option.to_string()).collect(),
+            correct,
+            difficulty,
+            "shell".to_string(),
+        )
+        .with_explanation(explanation.to_string())
+        .with_time_limit(30)
+    };
+    let mut quiz = Quiz::new();
+    for q in [
+        question("shell.hidden", "Which command lists hidden files too?", &["ls -h", "ls -a", "ls -r", "ls -s"], 1, QuestionDifficulty::Easy, "`-a` shows entries starting with a dot."),
+        question("shell.last_status", "What does `$?` expand to?", &["The shell's PID", "The last argument", "The last exit status", "The number of arguments"], 2, QuestionDifficulty::Easy, "`$?` holds the exit status of the last command."),
+        question("shell.stderr", "How do you send both stdout and stderr to `out.log`?", &["cmd > out.log 2>&1", "cmd 2>&1 > out.log", "cmd | out.log", "cmd >> out.log 2"], 0, QuestionDifficulty::Medium, "Redirections apply left to right, so stdout must point at the file before stderr is duplicated onto it."),
+        question("shell.and", "When does `b` run in `a && b`?", &["Always", "Only if `a` fails", "Only if `a` succeeds", "In parallel with `a`"], 2, QuestionDifficulty::Easy, "`&&` runs the right side only after a zero exit status."),
+        question("shell.xargs", "What does `find . -name '*.log' -print0 | xargs -0 rm` handle that plain `xargs` does not?", &["Files in subdirectories", "File names with spaces or newlines", "Read-only files", "More than 100 files"], 1, QuestionDifficulty::Hard, "NUL separators keep names with whitespace intact."),
+    ] {
+        quiz.add_question(q);
+    }
+    quiz
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +442,33 @@ mod tests {
         assert_eq!(quiz.questions.len(), 1);
     }
     
+    #[test]
+    fn test_session_records_answers_time_outs_and_summary() {
+        let start = Utc::now();
+        let mut session = QuizSession::new(shell_basics(), start);
+        let later = |secs| start + chrono::Duration::seconds(secs);
+
+        assert_eq!(session.time_left(later(10)), Some(20.0));
+        assert_eq!(session.answer(1, later(5)), Some(Outcome::Correct));
+        assert_eq!(session.answer(0, later(6)), None);
+        assert!(session.next(later(6)));
+
+        assert!(!session.check_time(later(20)));
+        assert!(session.check_time(later(40)));
+        assert_eq!(session.outcome, Some(Outcome::TimedOut));
+        assert!(session.next(later(40)));
+        assert_eq!(session.answer(2, later(45)), Some(Outcome::Incorrect));
+        while session.next(later(50)) {}
+
+        let summary = QuizSummary::of(&session.quiz);
+        assert_eq!((summary.correct, summary.total, summary.total_secs), (1, 3, 50));
+        assert_eq!(summary.by_difficulty, vec![(QuestionDifficulty::Easy, 1, 2), (QuestionDifficulty::Medium, 0, 1)]);
+        let rendered = summary.render(&session.quiz);
+        assert!(rendered.starts_with("Score  ███░░░░░░░  1/3 (33.3%)"));
+        assert!(rendered.contains("⏱ What does `$?` expand to?"));
+        assert!(rendered.contains("✗ How do you send both stdout and stderr to `out.log`? (answered cmd | out.log; correct: cmd > out.log 2>&1)"));
+    }
+
     #[test]
     fn test_question_with_explanation() {
         let question = MultipleChoiceQuestion::new(
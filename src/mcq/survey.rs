@@ -0,0 +1,256 @@
+//! Feature discovery and satisfaction prompts.
+//!
+//! When the user has opted in, a single question is asked now and then: does
+//! the user know about a feature, and how satisfied are they. After an
+//! awareness question is answered, its explanation tells the user how to try
+//! the feature. Responses, including dismissals, are kept in the local
+//! database and summarized for the agent evaluation metrics.
+
+use super::{MultipleChoiceQuestion, QuestionDifficulty};
+use crate::config::SurveyConfig;
+use crate::db;
+use rusqlite::Connection;
+
+pub const AWARENESS: &str = "awareness";
+pub const SATISFACTION: &str = "satisfaction";
+pub const EXIT: &str = "exit";
+
+/// Seconds the app must have been open before a prompt interrupts it.
+const MIN_SESSION_SECS: i64 = 10 * 60;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// One shown question and what became of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurveyResponse {
+    pub question_id: String,
+    pub category: String,
+    /// The chosen option, or `None` when the prompt was dismissed.
+    pub selected_answer: Option<usize>,
+    pub option_count: usize,
+    pub responded_at: i64,
+}
+
+impl SurveyResponse {
+    /// The answer on a scale from 0 to 1. Options are listed best first.
+    pub fn score(&self) -> Option<f32> {
+        let answer = self.selected_answer?;
+        if self.option_count < 2 {
+            return None;
+        }
+        Some(1.0 - answer as f32 / (self.option_count - 1) as f32)
+    }
+}
+
+/// Totals over every stored response.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SurveyStats {
+    pub shown: u32,
+    pub answered: u32,
+    /// Mean score of the answered satisfaction and exit questions.
+    pub satisfaction: Option<f32>,
+}
+
+impl SurveyStats {
+    pub fn from_responses(responses: &[SurveyResponse]) -> Self {
+        let scores: Vec<f32> = responses
+            .iter()
+            .filter(|response| response.category != AWARENESS)
+            .filter_map(SurveyResponse::score)
+            .collect();
+        Self {
+            shown: responses.len() as u32,
+            answered: responses.iter().filter(|response| response.selected_answer.is_some()).count() as u32,
+            satisfaction: (!scores.is_empty()).then(|| scores.iter().sum::<f32>() / scores.len() as f32),
+        }
+    }
+
+    /// The share of prompts that were answered rather than dismissed.
+    pub fn response_rate(&self) -> f32 {
+        if self.shown == 0 {
+            0.0
+        } else {
+            self.answered as f32 / self.shown as f32
+        }
+    }
+}
+
+fn question(id: &str, category: &str, text: &str, options: &[&str], explanation: Option<&str>) -> MultipleChoiceQuestion {
+    let question = MultipleChoiceQuestion::new(
+        id.to_string(),
+        text.to_string(),
+        options.iter().map(|option| option.to_string()).collect(),
+        0,
+        QuestionDifficulty::Easy,
+        category.to_string(),
+    );
+    match explanation {
+        Some(explanation) => question.with_explanation(explanation.to_string()),
+        None => question,
+    }
+}
+
+/// The built-in questions, in the order they are asked.
+pub fn default_questions() -> Vec<MultipleChoiceQuestion> {
+    let awareness = ["Yes, I use it", "Yes, but I haven't tried it", "No"];
+    let satisfaction = ["Very satisfied", "Satisfied", "Neutral", "Dissatisfied", "Very dissatisfied"];
+    vec![
+        question(
+            "awareness.search",
+            AWARENESS,
+            "Did you know one search box covers history, actions, files, blocks and settings?",
+            &awareness,
+            Some("Press Ctrl+R, then start the query with > for actions, @ for files, # for blocks or : for settings."),
+        ),
+        question(
+            "awareness.dirhistory",
+            AWARENESS,
+            "Did you know each pane remembers the directories it has visited?",
+            &awareness,
+            Some("With an empty command line, Alt+Left and Alt+Right step through them and Alt+Down lists them."),
+        ),
+        question(
+            "awareness.scratch",
+            AWARENESS,
+            "Did you know you can open a throwaway shell in a temporary directory?",
+            &awareness,
+            Some("Press Alt+S for a scratch pane; when it closes you can save its blocks to Drive."),
+        ),
+        question(
+            "awareness.privacy",
+            AWARENESS,
+            "Did you know a pane can lock itself while you are away?",
+            &awareness,
+            Some("Press Alt+P to make the active pane a privacy zone."),
+        ),
+        question(
+            "awareness.review",
+            AWARENESS,
+            "Did you know you can list the files changed in a repository from the terminal?",
+            &awareness,
+            Some("Press Ctrl+P and run Review Changes."),
+        ),
+        question("satisfaction.overall", SATISFACTION, "How satisfied are you with Warpish?", &satisfaction, None),
+        question("exit.session", EXIT, "How did this session go?", &["Great", "Fine", "Frustrating"], None),
+    ]
+}
+
+/// Decides when a prompt is shown and which question it asks.
+#[derive(Debug, Clone)]
+pub struct SurveyScheduler {
+    questions: Vec<MultipleChoiceQuestion>,
+    responses: Vec<SurveyResponse>,
+    started_at: i64,
+}
+
+impl SurveyScheduler {
+    pub fn new(questions: Vec<MultipleChoiceQuestion>, responses: Vec<SurveyResponse>, started_at: i64) -> Self {
+        Self { questions, responses, started_at }
+    }
+
+    /// Loads the stored responses for a session starting at `now`.
+    pub fn load(conn: &Connection, now: i64) -> Self {
+        let responses = db::load_survey_responses(conn).unwrap_or_else(|e| {
+            log::warn!("Failed to load survey responses: {}", e);
+            Vec::new()
+        });
+        Self::new(default_questions(), responses, now)
+    }
+
+    /// Whether enough time has passed since the last prompt.
+    fn is_due(&self, config: &SurveyConfig, now: i64) -> bool {
+        let interval = i64::from(config.interval_days) * DAY_SECS;
+        config.enabled && self.responses.iter().all(|response| now - response.responded_at >= interval)
+    }
+
+    /// The question to ask during the session, if one is due. Awareness
+    /// questions are asked once each; satisfaction is asked again later.
+    pub fn next_question(&self, config: &SurveyConfig, now: i64) -> Option<&MultipleChoiceQuestion> {
+        if now - self.started_at < MIN_SESSION_SECS || !self.is_due(config, now) {
+            return None;
+        }
+        let asked = |question: &MultipleChoiceQuestion| self.responses.iter().any(|response| response.question_id == question.id);
+        self.questions
+            .iter()
+            .find(|question| question.category == AWARENESS && !asked(question))
+            .or_else(|| self.questions.iter().find(|question| question.category == SATISFACTION))
+    }
+
+    /// The question to ask when quitting, if one is due.
+    pub fn exit_question(&self, config: &SurveyConfig, now: i64) -> Option<&MultipleChoiceQuestion> {
+        if !config.exit_survey || !self.is_due(config, now) {
+            return None;
+        }
+        self.questions.iter().find(|question| question.category == EXIT)
+    }
+
+    /// Stores the answer to a shown question, or its dismissal.
+    pub fn record(&mut self, conn: &Connection, question: &MultipleChoiceQuestion, selected_answer: Option<usize>, now: i64) {
+        let response = SurveyResponse {
+            question_id: question.id.clone(),
+            category: question.category.clone(),
+            selected_answer,
+            option_count: question.options.len(),
+            responded_at: now,
+        };
+        if let Err(e) = db::save_survey_response(conn, &response) {
+            log::warn!("Failed to save survey response: {}", e);
+        }
+        self.responses.push(response);
+    }
+
+    pub fn stats(&self) -> SurveyStats {
+        SurveyStats::from_responses(&self.responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> SurveyConfig {
+        SurveyConfig { enabled: true, ..SurveyConfig::default() }
+    }
+
+    #[test]
+    fn test_prompts_are_opt_in_and_rate_limited() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::create_survey_tables(&conn).unwrap();
+        let mut scheduler = SurveyScheduler::new(default_questions(), Vec::new(), 0);
+        let later = MIN_SESSION_SECS;
+        assert!(scheduler.next_question(&SurveyConfig::default(), later).is_none());
+        assert!(scheduler.next_question(&enabled(), later - 1).is_none());
+
+        let question = scheduler.next_question(&enabled(), later).unwrap().clone();
+        assert_eq!(question.id, "awareness.search");
+        scheduler.record(&conn, &question, None, later);
+        assert!(scheduler.next_question(&enabled(), later + DAY_SECS).is_none());
+        assert!(scheduler.exit_question(&enabled(), later + DAY_SECS).is_none());
+
+        // A dismissed question is not asked again.
+        let next = later + 14 * DAY_SECS;
+        assert_eq!(scheduler.next_question(&enabled(), next).unwrap().id, "awareness.dirhistory");
+        let reloaded = SurveyScheduler::load(&conn, 0);
+        assert_eq!(reloaded.stats(), SurveyStats { shown: 1, answered: 0, satisfaction: None });
+    }
+
+    #[test]
+    fn test_stats_score_answers() {
+        let response = |id: &str, category: &str, answer: Option<usize>, options: usize| SurveyResponse {
+            question_id: id.to_string(),
+            category: category.to_string(),
+            selected_answer: answer,
+            option_count: options,
+            responded_at: 0,
+        };
+        let stats = SurveyStats::from_responses(&[
+            response("awareness.search", AWARENESS, Some(2), 3),
+            response("satisfaction.overall", SATISFACTION, Some(1), 5),
+            response("exit.session", EXIT, Some(0), 3),
+            response("awareness.scratch", AWARENESS, None, 3),
+        ]);
+        assert_eq!(stats.shown, 4);
+        assert_eq!(stats.response_rate(), 0.75);
+        assert_eq!(stats.satisfaction, Some(0.875));
+    }
+}
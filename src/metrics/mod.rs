@@ -0,0 +1,108 @@
+//! Internal Metrics
+//!
+//! This module registers process-wide counters and latency histograms for
+//! the terminal (frames rendered, PTY throughput, AI and completion latency,
+//! blocks created) in a Prometheus registry and serves them in the text
+//! format on an optional localhost `/metrics` endpoint.
+
+use crate::config::MetricsConfig;
+use lazy_static::lazy_static;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
+use warp::Filter;
+
+/// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::new();
+}
+
+pub struct Metrics {
+    registry: Registry,
+    pub frames_rendered: IntCounter,
+    pub pty_bytes: IntCounter,
+    pub blocks_created: IntCounter,
+    pub ai_requests: IntCounter,
+    /// Seconds, as observed with `Duration::as_secs_f64`.
+    pub ai_latency: Histogram,
+    pub completion_latency: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help).expect("metric names are valid");
+            registry.register(Box::new(counter.clone())).expect("metric names are unique");
+            counter
+        };
+        let histogram = |name: &str, help: &str| {
+            let opts = HistogramOpts::new(name, help).buckets(LATENCY_BUCKETS.to_vec());
+            let histogram = Histogram::with_opts(opts).expect("metric names are valid");
+            registry.register(Box::new(histogram.clone())).expect("metric names are unique");
+            histogram
+        };
+        Self {
+            frames_rendered: counter("warpish_frames_rendered_total", "Frames rendered by the GPU renderer."),
+            pty_bytes: counter("warpish_pty_bytes_total", "Bytes read from PTYs."),
+            blocks_created: counter("warpish_blocks_created_total", "Command blocks created."),
+            ai_requests: counter("warpish_ai_requests_total", "Requests sent to the AI backend."),
+            ai_latency: histogram("warpish_ai_latency_seconds", "Latency of AI backend requests."),
+            completion_latency: histogram("warpish_completion_latency_seconds", "Time to compute completion suggestions."),
+            registry,
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut out) {
+            log::warn!("[Metrics] Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(out).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serves `/metrics` on localhost until the task is dropped.
+pub async fn serve(port: u16) {
+    let route = warp::get().and(warp::path("metrics")).and(warp::path::end()).map(|| {
+        warp::reply::with_header(METRICS.render(), "content-type", TextEncoder::new().format_type())
+    });
+
+    log::info!("[Metrics] Serving on 127.0.0.1:{}/metrics", port);
+    warp::serve(route).run(([127, 0, 0, 1], port)).await;
+}
+
+/// Starts the endpoint on the current tokio runtime if it is enabled.
+pub fn spawn_endpoint(config: &MetricsConfig) -> Option<tokio::task::JoinHandle<()>> {
+    config.enabled.then(|| tokio::spawn(serve(config.port)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_format() {
+        let metrics = Metrics::new();
+        metrics.frames_rendered.inc();
+        metrics.pty_bytes.inc_by(512);
+        metrics.ai_latency.observe(0.03);
+        metrics.ai_latency.observe(3.0);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE warpish_frames_rendered_total counter\nwarpish_frames_rendered_total 1\n"));
+        assert!(text.contains("warpish_pty_bytes_total 512\n"));
+        assert!(text.contains("warpish_ai_latency_seconds_bucket{le=\"0.025\"} 0\n"));
+        assert!(text.contains("warpish_ai_latency_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(text.contains("warpish_ai_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("warpish_ai_latency_seconds_count 2\n"));
+        assert!(text.contains("warpish_completion_latency_seconds_count 0\n"));
+    }
+}
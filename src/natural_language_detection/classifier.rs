@@ -0,0 +1,117 @@
+//! Embedded Input Classifier
+//!
+//! A logistic regression over the words and shapes of an input, shipped in
+//! `model.txt`. Each line of the model is a feature and its weight; the
+//! probability that an input is natural language is the sigmoid of the bias
+//! plus the weights of the features it has. Features are words (`word=list`),
+//! the first word (`first=make`), token shapes such as flags and paths, and
+//! the number of words, so `make help` and `find all rust files` come out on
+//! the right sides despite sharing words with the other class.
+
+use super::InputClassifier;
+use std::collections::HashMap;
+use thiserror::Error;
+
+const EMBEDDED_MODEL: &str = include_str!("model.txt");
+
+#[derive(Error, Debug)]
+pub enum ClassifierError {
+    #[error("line {0} of the model is not `feature<TAB>weight`")]
+    InvalidLine(usize),
+    #[error("the model has no bias")]
+    MissingBias,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinearClassifier {
+    bias: f32,
+    weights: HashMap<String, f32>,
+}
+
+impl LinearClassifier {
+    /// The model built into the binary.
+    pub fn embedded() -> Self {
+        Self::parse(EMBEDDED_MODEL).expect("the embedded model is valid")
+    }
+
+    /// Reads a model of `feature<TAB>weight` lines, one of them `bias`.
+    pub fn parse(model: &str) -> Result<Self, ClassifierError> {
+        let mut bias = None;
+        let mut weights = HashMap::new();
+        for (i, line) in model.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (feature, weight) = line
+                .split_once('\t')
+                .and_then(|(feature, weight)| Some((feature, weight.trim().parse::<f32>().ok()?)))
+                .ok_or(ClassifierError::InvalidLine(i + 1))?;
+            if feature == "bias" {
+                bias = Some(weight);
+            } else {
+                weights.insert(feature.to_string(), weight);
+            }
+        }
+        Ok(Self { bias: bias.ok_or(ClassifierError::MissingBias)?, weights })
+    }
+}
+
+impl InputClassifier for LinearClassifier {
+    fn natural_language_probability(&self, input: &str) -> f32 {
+        let z = features(input).iter().fold(self.bias, |z, feature| z + self.weights.get(feature).copied().unwrap_or(0.0));
+        1.0 / (1.0 + (-z).exp())
+    }
+}
+
+/// The features of `input`, repeated as often as they occur.
+fn features(input: &str) -> Vec<String> {
+    let lower = input.trim().to_lowercase();
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    let mut features = Vec::new();
+    if let Some(first) = words.first() {
+        features.push(format!("first={}", first.trim_end_matches('?')));
+    }
+    for word in &words {
+        let bare = word.trim_matches(|c| matches!(c, '?' | ',' | '.' | '!'));
+        if word.starts_with('-') {
+            features.push("shape=flag".to_string());
+        } else if word.contains('/') || word.starts_with('.') || word.starts_with('~') {
+            features.push("shape=path".to_string());
+        } else if word.contains(|c| "|&;<>$=*\"'".contains(c)) {
+            features.push("shape=operator".to_string());
+        } else if !bare.is_empty() && bare.chars().all(|c| c.is_ascii_digit()) {
+            features.push("shape=number".to_string());
+        } else if !bare.is_empty() {
+            features.push(format!("word={}", bare));
+        }
+    }
+    if lower.ends_with('?') {
+        features.push("shape=question".to_string());
+    }
+    features.push(format!("length={}", words.len().min(6)));
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_model_separates_commands_from_queries() {
+        let classifier = LinearClassifier::embedded();
+        for command in ["make help", "git status", "ls -la", "cargo test --workspace", "npm run lint"] {
+            assert!(classifier.natural_language_probability(command) < 0.5, "{}", command);
+        }
+        for query in ["find all rust files", "how do I list files?", "show me disk space", "delete the build folder"] {
+            assert!(classifier.natural_language_probability(query) > 0.5, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_parse_model() {
+        let classifier = LinearClassifier::parse("# comment\nbias\t-1.0\nword=please\t3.0\n").unwrap();
+        assert!(classifier.natural_language_probability("please") > 0.8);
+        assert!(matches!(LinearClassifier::parse("word=x\t1"), Err(ClassifierError::MissingBias)));
+        assert!(matches!(LinearClassifier::parse("bias\tx"), Err(ClassifierError::InvalidLine(1))));
+    }
+}
@@ -0,0 +1,226 @@
+//! Query Language Detection
+//!
+//! Finds the language a query is written in, so the agent can answer in it.
+//! Scripts settle Japanese, Chinese, Korean and Russian outright. Latin-script
+//! languages are told apart by a naive Bayes model over character trigrams,
+//! counted from the short samples below when first needed; the samples lean
+//! on the words people use when asking about their terminal.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    Spanish,
+    German,
+    French,
+    Portuguese,
+    Italian,
+    Japanese,
+    Chinese,
+    Korean,
+    Russian,
+}
+
+impl Language {
+    /// The ISO 639-1 code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Spanish => "es",
+            Language::German => "de",
+            Language::French => "fr",
+            Language::Portuguese => "pt",
+            Language::Italian => "it",
+            Language::Japanese => "ja",
+            Language::Chinese => "zh",
+            Language::Korean => "ko",
+            Language::Russian => "ru",
+        }
+    }
+
+    /// The English name, as the agent is told it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Spanish => "Spanish",
+            Language::German => "German",
+            Language::French => "French",
+            Language::Portuguese => "Portuguese",
+            Language::Italian => "Italian",
+            Language::Japanese => "Japanese",
+            Language::Chinese => "Chinese",
+            Language::Korean => "Korean",
+            Language::Russian => "Russian",
+        }
+    }
+}
+
+/// Fewer letters than this say too little to pick a language.
+const MIN_LETTERS: usize = 6;
+
+const SAMPLES: &[(Language, &str)] = &[
+    (
+        Language::English,
+        "how do i list all the files in this folder and show the hidden ones too? what is using port eight thousand \
+         and why does my build keep failing with this error? please explain what the command does, then delete the old \
+         logs, find the largest directories and tell me which process uses the most memory. can you help me undo the \
+         last commit without losing my changes, and where should i put the configuration file for the server?",
+    ),
+    (
+        Language::Spanish,
+        "¿cómo puedo listar todos los archivos de esta carpeta y ver también los ocultos? ¿qué proceso está usando el \
+         puerto y por qué falla la compilación con este error? por favor explica qué hace el comando, luego borra los \
+         registros antiguos, busca los directorios más grandes y dime cuál proceso usa más memoria. ¿puedes ayudarme a \
+         deshacer el último commit sin perder mis cambios, y dónde debo poner el archivo de configuración del servidor?",
+    ),
+    (
+        Language::German,
+        "wie kann ich alle dateien in diesem ordner auflisten und auch die versteckten anzeigen? welcher prozess benutzt \
+         den port und warum schlägt mein build mit diesem fehler fehl? bitte erkläre, was der befehl macht, dann lösche \
+         die alten protokolle, finde die größten verzeichnisse und sag mir, welcher prozess den meisten speicher \
+         verwendet. kannst du mir helfen, den letzten commit rückgängig zu machen, ohne meine änderungen zu verlieren, \
+         und wo soll ich die konfigurationsdatei für den server ablegen?",
+    ),
+    (
+        Language::French,
+        "comment puis-je lister tous les fichiers de ce dossier et voir aussi les fichiers cachés? quel processus utilise \
+         le port et pourquoi ma compilation échoue avec cette erreur? s'il te plaît explique ce que fait la commande, \
+         ensuite supprime les anciens journaux, trouve les plus grands répertoires et dis-moi quel processus utilise le \
+         plus de mémoire. peux-tu m'aider à annuler le dernier commit sans perdre mes modifications, et où dois-je mettre \
+         le fichier de configuration du serveur?",
+    ),
+    (
+        Language::Portuguese,
+        "como posso listar todos os arquivos desta pasta e ver também os ocultos? qual processo está usando a porta e \
+         por que a minha compilação falha com este erro? por favor explique o que o comando faz, depois apague os \
+         registros antigos, encontre os maiores diretórios e me diga qual processo usa mais memória. você pode me ajudar \
+         a desfazer o último commit sem perder as minhas alterações, e onde devo colocar o arquivo de configuração do \
+         servidor?",
+    ),
+    (
+        Language::Italian,
+        "come posso elencare tutti i file in questa cartella e vedere anche quelli nascosti? quale processo sta usando la \
+         porta e perché la mia compilazione fallisce con questo errore? per favore spiega cosa fa il comando, poi \
+         cancella i vecchi log, trova le cartelle più grandi e dimmi quale processo usa più memoria. puoi aiutarmi ad \
+         annullare l'ultimo commit senza perdere le mie modifiche, e dove devo mettere il file di configurazione del \
+         server?",
+    ),
+];
+
+/// Trigram counts per Latin-script language, with their totals, and the
+/// number of distinct trigrams across all of them.
+struct Model {
+    languages: Vec<(Language, HashMap<String, u32>, u32)>,
+    vocabulary: usize,
+}
+
+fn model() -> &'static Model {
+    static MODEL: OnceLock<Model> = OnceLock::new();
+    MODEL.get_or_init(|| {
+        let mut vocabulary = std::collections::HashSet::new();
+        let languages = SAMPLES
+            .iter()
+            .map(|(language, sample)| {
+                let mut counts = HashMap::new();
+                for trigram in trigrams(sample) {
+                    vocabulary.insert(trigram.clone());
+                    *counts.entry(trigram).or_insert(0) += 1;
+                }
+                let total = counts.values().sum();
+                (*language, counts, total)
+            })
+            .collect();
+        Model { languages, vocabulary: vocabulary.len() }
+    })
+}
+
+/// Character trigrams of each lowercased word, padded with spaces so word
+/// starts and ends count. Digits and punctuation are left out.
+fn trigrams(text: &str) -> Vec<String> {
+    let mut trigrams = Vec::new();
+    for word in text.split(|c: char| !c.is_alphabetic() && c != '\'') {
+        if word.is_empty() {
+            continue;
+        }
+        let padded: Vec<char> = format!(" {} ", word.to_lowercase()).chars().collect();
+        trigrams.extend(padded.windows(3).map(|window| window.iter().collect()));
+    }
+    trigrams
+}
+
+/// The language of `text`, or `None` when it has too few letters to tell.
+pub fn detect_language(text: &str) -> Option<Language> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() < MIN_LETTERS / 2 {
+        return None;
+    }
+    // Kana only appears in Japanese, which also uses Han characters.
+    let count = |range: &[(u32, u32)]| letters.iter().filter(|c| range.iter().any(|(lo, hi)| (*lo..=*hi).contains(&(**c as u32)))).count();
+    let kana = count(&[(0x3040, 0x30ff)]);
+    let han = count(&[(0x4e00, 0x9fff)]);
+    let hangul = count(&[(0xac00, 0xd7af), (0x1100, 0x11ff)]);
+    let cyrillic = count(&[(0x0400, 0x04ff)]);
+    let half = letters.len() / 2;
+    if kana > 0 && kana + han > half {
+        return Some(Language::Japanese);
+    }
+    if han > half {
+        return Some(Language::Chinese);
+    }
+    if hangul > half {
+        return Some(Language::Korean);
+    }
+    if cyrillic > half {
+        return Some(Language::Russian);
+    }
+    if letters.len() < MIN_LETTERS {
+        return None;
+    }
+
+    let model = model();
+    let trigrams = trigrams(text);
+    model
+        .languages
+        .iter()
+        .map(|(language, counts, total)| {
+            let denominator = (*total as usize + model.vocabulary) as f64;
+            let score: f64 = trigrams
+                .iter()
+                .map(|trigram| ((counts.get(trigram).copied().unwrap_or(0) + 1) as f64 / denominator).ln())
+                .sum();
+            (*language, score)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(language, _)| language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_latin_languages() {
+        for (query, language) in [
+            ("how can I see which ports are open?", Language::English),
+            ("¿cómo veo los puertos abiertos en mi máquina?", Language::Spanish),
+            ("wie finde ich heraus, welche Ports offen sind?", Language::German),
+            ("comment supprimer les fichiers temporaires?", Language::French),
+            ("como eu vejo quais portas estão abertas?", Language::Portuguese),
+            ("come posso vedere quali porte sono aperte?", Language::Italian),
+        ] {
+            assert_eq!(detect_language(query), Some(language), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_detects_scripts_and_gives_up_on_short_text() {
+        assert_eq!(detect_language("隠しファイルを表示するには?"), Some(Language::Japanese));
+        assert_eq!(detect_language("如何查看隐藏文件"), Some(Language::Chinese));
+        assert_eq!(detect_language("숨김 파일을 보려면 어떻게 하나요"), Some(Language::Korean));
+        assert_eq!(detect_language("как посмотреть скрытые файлы"), Some(Language::Russian));
+        assert_eq!(detect_language("ls -la"), None);
+        assert_eq!(Language::German.code(), "de");
+    }
+}
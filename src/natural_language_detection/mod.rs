@@ -2,10 +2,27 @@
 //! 
 //! This module provides advanced natural language detection and processing capabilities,
 //! distinguishing between command input and natural language queries with high accuracy.
+//!
+//! Keyword scoring decides by default. An [`InputClassifier`] can take its
+//! place; with the `nl-classifier` feature a small linear model is built in.
+//! Corrections such as "this was a command" are remembered per input and
+//! move the threshold the probability has to clear.
+
+#[cfg(feature = "nl-classifier")]
+pub mod classifier;
+pub mod language;
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use regex::Regex;
+
+/// Words that open a question or a request rather than a command.
+const QUESTION_WORDS: &[&str] = &["how", "what", "what's", "why", "when", "where", "who", "which", "can", "could", "would", "should", "please", "is", "are"];
+
+/// Short words common in sentences and rare in command lines.
+const STOPWORDS: &[&str] = &["a", "an", "the", "to", "in", "of", "on", "for", "with", "me", "my", "i", "all", "this", "that", "it", "is", "are", "and", "from", "by"];
+
+/// How far corrections can move the threshold from its default of 0.5.
+const THRESHOLD_RANGE: (f32, f32) = (0.2, 0.8);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum InputType {
@@ -27,6 +44,19 @@ pub struct LanguageDetectionResult {
     pub complexity: f32, // 0.0 to 1.0 (simple to complex)
 }
 
+/// Scores how likely an input is to be natural language rather than a
+/// command, from 0 to 1.
+pub trait InputClassifier: Send + Sync {
+    fn natural_language_probability(&self, input: &str) -> f32;
+}
+
+/// The user's verdict on how an input should have been taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    pub input: String,
+    pub is_command: bool,
+}
+
 pub struct NaturalLanguageDetector {
     // Common command patterns
     command_patterns: Vec<String>,
@@ -34,6 +64,12 @@ pub struct NaturalLanguageDetector {
     nl_indicators: Vec<String>,
     // Intent classification patterns
     intent_patterns: HashMap<String, Vec<String>>,
+    /// Replaces keyword scoring when set.
+    classifier: Option<Box<dyn InputClassifier>>,
+    /// Probability above which an input counts as natural language.
+    threshold: f32,
+    /// Corrected inputs, trimmed and lowercased, and whether each is a command.
+    corrections: HashMap<String, bool>,
 }
 
 impl NaturalLanguageDetector {
@@ -87,8 +123,95 @@ impl NaturalLanguageDetector {
             command_patterns,
             nl_indicators,
             intent_patterns,
+            classifier: None,
+            threshold: 0.5,
+            corrections: HashMap::new(),
+        }
+    }
+
+    /// Decides with `classifier` instead of keyword scoring.
+    pub fn with_classifier(mut self, classifier: Box<dyn InputClassifier>) -> Self {
+        self.classifier = Some(classifier);
+        self
+    }
+
+    /// Decides with the built-in model.
+    #[cfg(feature = "nl-classifier")]
+    pub fn with_default_classifier(self) -> Self {
+        self.with_classifier(Box::new(classifier::LinearClassifier::embedded()))
+    }
+
+    /// Keeps keyword scoring and warns; the built-in model needs the
+    /// `nl-classifier` feature.
+    #[cfg(not(feature = "nl-classifier"))]
+    pub fn with_default_classifier(self) -> Self {
+        log::warn!("Built without the nl-classifier feature; detecting natural language by keywords");
+        self
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// How likely `input` is to be natural language, from 0 to 1.
+    pub fn natural_language_probability(&self, input: &str) -> f32 {
+        match &self.classifier {
+            Some(classifier) => classifier.natural_language_probability(input),
+            None => self.keyword_probability(&input.to_lowercase()),
         }
     }
+
+    /// Whether `input` should go to the agent rather than the shell. An
+    /// input the user corrected before is taken the way they said.
+    pub fn is_natural_language(&self, input: &str) -> bool {
+        if let Some(is_command) = self.corrections.get(&correction_key(input)) {
+            return !is_command;
+        }
+        self.natural_language_probability(input) > self.threshold
+    }
+
+    /// Remembers `corrections` and moves the threshold to the one that
+    /// would have classified the most of all corrections so far right.
+    pub fn learn(&mut self, corrections: &[Correction]) {
+        for correction in corrections {
+            self.corrections.insert(correction_key(&correction.input), correction.is_command);
+        }
+        let scored: Vec<(f32, bool)> = self
+            .corrections
+            .iter()
+            .map(|(input, is_command)| (self.natural_language_probability(input), *is_command))
+            .collect();
+        self.threshold = fit_threshold(&scored, 0.5);
+    }
+
+    /// Scores an input by its shell syntax, its first word and how much it
+    /// reads like a sentence.
+    fn keyword_probability(&self, input: &str) -> f32 {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        let Some(first) = words.first() else {
+            return 0.0;
+        };
+        if words.iter().any(|word| looks_like_shell_syntax(word)) {
+            return 0.05;
+        }
+        if input.trim_end().ends_with('?') {
+            return 0.95;
+        }
+        // `make help` and `git status` name a command and say little else.
+        let is_command = self.command_patterns.iter().any(|command| command == first);
+        if is_command && words.len() <= 3 {
+            return 0.1;
+        }
+        let stopwords = words.iter().filter(|word| STOPWORDS.contains(word)).count() as f32 / words.len() as f32;
+        let mut probability = 0.5 * self.calculate_nl_score(&words) + stopwords;
+        if !is_command {
+            probability += 0.3;
+        }
+        if QUESTION_WORDS.contains(first) {
+            probability += 0.3;
+        }
+        probability.min(1.0)
+    }
     
     pub fn detect(&self, input: &str) -> LanguageDetectionResult {
         let input_lower = input.to_lowercase();
@@ -106,39 +229,27 @@ impl NaturalLanguageDetector {
             };
         }
         
-        // Advanced tokenization and preprocessing
-        let tokens = self.tokenize_advanced(&input_lower);
-        let processed_input = self.preprocess_input(&input_lower);
-        
         // Check if first word is a known command
         let first_word = words[0];
         let is_command = self.command_patterns.contains(&first_word.to_string());
-        
-        // Advanced natural language scoring
-        let nl_score = self.calculate_advanced_nl_score(&tokens, &processed_input);
-        let command_score = if is_command { 1.0 } else { self.calculate_command_similarity(&first_word) };
-        
-        // Determine input type with more sophisticated logic
-        let input_type = self.determine_input_type(&input_lower, command_score, nl_score);
-        
-        // Advanced intent detection with contextual understanding
-        let intent = self.detect_advanced_intent(&processed_input, &tokens);
-        
-        // Enhanced entity extraction with named entity recognition
-        let entities = self.extract_advanced_entities(&processed_input, &tokens);
-        
-        // Advanced sentiment analysis
-        let sentiment = self.analyze_advanced_sentiment(&processed_input, &tokens);
-        
-        // Calculate complexity with linguistic features
-        let complexity = self.calculate_advanced_complexity(&processed_input, &tokens);
-        
-        // Language detection beyond English
+
+        let nl_score = self.natural_language_probability(input);
+        let command_score = if is_command { 1.0 } else { 1.0 - nl_score };
+
+        let input_type = if self.is_natural_language(input) {
+            InputType::NaturalLanguage
+        } else {
+            InputType::Command
+        };
+        let intent = self.detect_intent(&input_lower);
+        let entities = self.extract_entities(&input_lower);
+        let sentiment = self.analyze_sentiment(&input_lower);
+        let complexity = self.calculate_complexity(&input_lower);
         let detected_language = self.detect_language(&input_lower);
-        
+
         LanguageDetectionResult {
             input_type,
-            confidence: self.calculate_advanced_confidence(command_score, nl_score, &processed_input, &tokens),
+            confidence: self.calculate_confidence(command_score, nl_score, &input_lower),
             detected_language,
             intent,
             entities,
@@ -147,6 +258,10 @@ impl NaturalLanguageDetector {
         }
     }
     
+    fn detect_language(&self, input: &str) -> Option<String> {
+        language::detect_language(input).map(|language| language.code().to_string())
+    }
+
     fn calculate_nl_score(&self, words: &[&str]) -> f32 {
         let mut score = 0.0;
         let total_words = words.len() as f32;
@@ -203,27 +318,6 @@ impl NaturalLanguageDetector {
         entities
     }
     
-    fn determine_input_type(&self, input: &str, command_score: f32, nl_score: f32) -> InputType {
-        // Check for question patterns
-        if input.contains('?') || input.starts_with("what") || input.starts_with("how") || input.starts_with("why") {
-            return InputType::Question;
-        }
-        
-        // Check for request patterns
-        if input.contains("please") || input.starts_with("can you") || input.starts_with("could you") {
-            return InputType::Request;
-        }
-        
-        // Standard classification
-        if command_score > 0.7 && nl_score < 0.3 {
-            InputType::Command
-        } else if nl_score > 0.5 {
-            InputType::NaturalLanguage
-        } else {
-            InputType::Mixed
-        }
-    }
-    
     fn analyze_sentiment(&self, input: &str) -> Option<f32> {
         let positive_words = ["good", "great", "excellent", "amazing", "love", "like", "awesome", "fantastic"];
         let negative_words = ["bad", "terrible", "awful", "hate", "dislike", "horrible", "worst", "sucks"];
@@ -291,6 +385,34 @@ impl NaturalLanguageDetector {
     }
 }
 
+fn correction_key(input: &str) -> String {
+    input.trim().to_lowercase()
+}
+
+/// Flags, paths, pipes, redirections and variables only appear in commands.
+fn looks_like_shell_syntax(word: &str) -> bool {
+    word.starts_with('-') || word.starts_with("./") || word.starts_with('~') || word.contains(|c| "|&;<>$=/\\".contains(c))
+}
+
+/// The threshold misclassifying the fewest of `scored` probabilities and
+/// verdicts, preferring the one nearest `preferred` among equals.
+fn fit_threshold(scored: &[(f32, bool)], preferred: f32) -> f32 {
+    if scored.is_empty() {
+        return preferred;
+    }
+    let errors = |threshold: f32| scored.iter().filter(|(probability, is_command)| (*probability > threshold) == *is_command).count();
+    let mut candidates: Vec<f32> = scored
+        .iter()
+        .flat_map(|(probability, _)| [probability - 0.01, *probability])
+        .map(|threshold| threshold.clamp(THRESHOLD_RANGE.0, THRESHOLD_RANGE.1))
+        .collect();
+    candidates.push(preferred);
+    candidates
+        .into_iter()
+        .min_by(|a, b| errors(*a).cmp(&errors(*b)).then((a - preferred).abs().total_cmp(&(b - preferred).abs())))
+        .unwrap_or(preferred)
+}
+
 impl Default for NaturalLanguageDetector {
     fn default() -> Self {
         Self::new()
@@ -310,6 +432,9 @@ mod tests {
         
         let result = detector.detect("how do I list files?");
         assert!(matches!(result.input_type, InputType::NaturalLanguage));
+
+        let result = detector.detect("make help");
+        assert!(matches!(result.input_type, InputType::Command));
         
         let result = detector.detect("git status");
         assert!(matches!(result.input_type, InputType::Command));
@@ -325,4 +450,18 @@ mod tests {
         let result = detector.detect("show me disk space");
         assert_eq!(result.intent, Some("system_info".to_string()));
     }
+
+    #[test]
+    fn test_corrections_override_and_move_threshold() {
+        let mut detector = NaturalLanguageDetector::new();
+        assert!(detector.is_natural_language("deploy the staging branch"));
+        detector.learn(&[Correction { input: "Deploy the staging branch".to_string(), is_command: true }]);
+        assert!(!detector.is_natural_language("deploy the staging branch "));
+        assert!(detector.threshold() > 0.5);
+
+        let scored = [(0.3, false), (0.45, false), (0.6, true)];
+        let threshold = fit_threshold(&scored, 0.5);
+        assert!(threshold < 0.3 && threshold >= THRESHOLD_RANGE.0);
+        assert_eq!(fit_threshold(&[], 0.5), 0.5);
+    }
 }
@@ -0,0 +1,330 @@
+//! Ollama Model Management
+//!
+//! This module talks to a local Ollama server to list the installed models,
+//! pull new ones and delete old ones. Requests run on background threads and
+//! report back through `OllamaEvent`s, so a multi-gigabyte pull streams its
+//! progress into a block without blocking the UI.
+
+use crate::config::{AiConfig, ModelRoles};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum OllamaError {
+    #[error("Ollama request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Ollama returned an invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Ollama: {0}")]
+    Api(String),
+    #[error("Failed to start Ollama runtime: {0}")]
+    Runtime(#[from] std::io::Error),
+}
+
+/// What a locally assigned model is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelRole {
+    Completions,
+    Summarization,
+    Agent,
+}
+
+impl ModelRole {
+    pub const ALL: [ModelRole; 3] = [ModelRole::Completions, ModelRole::Summarization, ModelRole::Agent];
+
+    /// The key under `ai.model_roles` in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModelRole::Completions => "completions",
+            ModelRole::Summarization => "summarization",
+            ModelRole::Agent => "agent",
+        }
+    }
+}
+
+impl ModelRoles {
+    pub fn get(&self, role: ModelRole) -> Option<&str> {
+        match role {
+            ModelRole::Completions => self.completions.as_deref(),
+            ModelRole::Summarization => self.summarization.as_deref(),
+            ModelRole::Agent => self.agent.as_deref(),
+        }
+    }
+
+    pub fn set(&mut self, role: ModelRole, model: String) {
+        let slot = match role {
+            ModelRole::Completions => &mut self.completions,
+            ModelRole::Summarization => &mut self.summarization,
+            ModelRole::Agent => &mut self.agent,
+        };
+        *slot = Some(model);
+    }
+}
+
+impl AiConfig {
+    /// The model used for `role`, falling back to `ollama_model`.
+    pub fn model_for(&self, role: ModelRole) -> &str {
+        self.model_roles.get(role).unwrap_or(&self.ollama_model)
+    }
+}
+
+/// A model in the local Ollama library.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct InstalledModel {
+    pub name: String,
+    /// Size on disk in bytes.
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: String,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<InstalledModel>,
+}
+
+/// One line of a streamed `/api/pull` response.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl PullProgress {
+    /// A human-readable line for the pull's block.
+    pub fn line(&self) -> String {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => format!(
+                "{} {:>3}% ({} / {})",
+                self.status,
+                completed * 100 / total,
+                format_size(completed),
+                format_size(total)
+            ),
+            _ => self.status.clone(),
+        }
+    }
+}
+
+/// Appends `progress` to a pull's output, replacing the previous line while
+/// the same layer is still downloading.
+pub fn record_progress(output: &mut String, progress: &PullProgress) {
+    let last_start = output.rfind('\n').map_or(0, |idx| idx + 1);
+    let same_status = !progress.status.is_empty() && output[last_start..].starts_with(&progress.status);
+    if same_status {
+        output.truncate(last_start);
+    } else if !output.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(&progress.line());
+}
+
+/// Formats a byte count with a binary unit, e.g. `3.8 GB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The server root for an `ollama_url` such as
+/// `http://localhost:11434/api/generate`.
+pub fn base_url(api_url: &str) -> String {
+    match api_url.find("/api/") {
+        Some(idx) => api_url[..idx].to_string(),
+        None => api_url.trim_end_matches('/').to_string(),
+    }
+}
+
+/// A client for the model management endpoints of an Ollama server.
+#[derive(Clone)]
+pub struct OllamaClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl OllamaClient {
+    pub fn new(config: &AiConfig) -> Self {
+        Self { client: reqwest::Client::new(), base_url: base_url(&config.ollama_url) }
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<InstalledModel>, OllamaError> {
+        let response = self.client.get(format!("{}/api/tags", self.base_url)).send().await?;
+        let response = error_for_status(response).await?;
+        let mut models = response.json::<TagsResponse>().await?.models;
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models)
+    }
+
+    /// Pulls `model`, calling `on_progress` for every status line.
+    pub async fn pull(&self, model: &str, mut on_progress: impl FnMut(PullProgress)) -> Result<(), OllamaError> {
+        let response = self
+            .client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&serde_json::json!({ "name": model, "stream": true }))
+            .send()
+            .await?;
+        let response = error_for_status(response).await?;
+        let mut stream = response.bytes_stream();
+        let mut pending = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            pending.extend_from_slice(&chunk?);
+            while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let progress: PullProgress = serde_json::from_slice(&line)?;
+                if let Some(error) = progress.error {
+                    return Err(OllamaError::Api(error));
+                }
+                on_progress(progress);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn delete(&self, model: &str) -> Result<(), OllamaError> {
+        let response = self
+            .client
+            .delete(format!("{}/api/delete", self.base_url))
+            .json(&serde_json::json!({ "name": model }))
+            .send()
+            .await?;
+        error_for_status(response).await?;
+        Ok(())
+    }
+}
+
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, OllamaError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|value| value.get("error").and_then(|error| error.as_str()).map(str::to_string))
+        .unwrap_or_else(|| format!("{} {}", status, body.trim()));
+    Err(OllamaError::Api(message))
+}
+
+/// A finished or progressing background request.
+#[derive(Debug)]
+pub enum OllamaEvent {
+    Models(Result<Vec<InstalledModel>, String>),
+    PullProgress { block_id: Uuid, progress: PullProgress },
+    PullFinished { block_id: Uuid, model: String, result: Result<(), String> },
+    Deleted { model: String, result: Result<(), String> },
+}
+
+/// Runs Ollama requests on background threads.
+pub struct OllamaManager {
+    client: OllamaClient,
+    event_tx: Sender<OllamaEvent>,
+    events: Receiver<OllamaEvent>,
+}
+
+impl OllamaManager {
+    pub fn new(config: &AiConfig) -> Self {
+        let (event_tx, events) = channel();
+        Self { client: OllamaClient::new(config), event_tx, events }
+    }
+
+    fn spawn<F>(&self, task: impl FnOnce(OllamaClient, Sender<OllamaEvent>) -> F + Send + 'static)
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        let client = self.client.clone();
+        let event_tx = self.event_tx.clone();
+        thread::spawn(move || match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime.block_on(task(client, event_tx)),
+            Err(e) => log::error!("{}", OllamaError::Runtime(e)),
+        });
+    }
+
+    pub fn refresh(&self) {
+        self.spawn(|client, event_tx| async move {
+            let models = client.list_models().await.map_err(|e| e.to_string());
+            event_tx.send(OllamaEvent::Models(models)).ok();
+        });
+    }
+
+    /// Pulls `model`, reporting progress for the block `block_id`.
+    pub fn pull(&self, block_id: Uuid, model: String) {
+        self.spawn(move |client, event_tx| async move {
+            let progress_tx = event_tx.clone();
+            let result = client
+                .pull(&model, |progress| {
+                    progress_tx.send(OllamaEvent::PullProgress { block_id, progress }).ok();
+                })
+                .await
+                .map_err(|e| e.to_string());
+            event_tx.send(OllamaEvent::PullFinished { block_id, model, result }).ok();
+        });
+    }
+
+    pub fn delete(&self, model: String) {
+        self.spawn(move |client, event_tx| async move {
+            let result = client.delete(&model).await.map_err(|e| e.to_string());
+            event_tx.send(OllamaEvent::Deleted { model, result }).ok();
+        });
+    }
+
+    pub fn poll(&self) -> Vec<OllamaEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_progress_replaces_same_layer() {
+        let lines = [
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a07","total":4661211424,"completed":1048576}"#,
+            r#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a07","total":4661211424,"completed":2330605712}"#,
+            r#"{"status":"success"}"#,
+        ];
+        let mut output = String::new();
+        for line in lines {
+            record_progress(&mut output, &serde_json::from_str(line).unwrap());
+        }
+        assert_eq!(output, "pulling manifest\npulling 6a0746a1ec1a  50% (2.2 GB / 4.3 GB)\nsuccess");
+    }
+
+    #[test]
+    fn test_models_sizes_and_roles() {
+        let tags: TagsResponse = serde_json::from_str(
+            r#"{"models":[{"name":"llama3:8b","model":"llama3:8b","size":4661224676,"modified_at":"2024-05-01T10:00:00Z","digest":"365c"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(tags.models[0].name, "llama3:8b");
+        assert_eq!(format_size(tags.models[0].size), "4.3 GB");
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(base_url("http://localhost:11434/api/generate"), "http://localhost:11434");
+
+        let mut config = AiConfig { ollama_model: "codellama".to_string(), ..AiConfig::default() };
+        config.model_roles.set(ModelRole::Agent, "llama3:8b".to_string());
+        assert_eq!(config.model_for(ModelRole::Agent), "llama3:8b");
+        assert_eq!(config.model_for(ModelRole::Completions), "codellama");
+    }
+}
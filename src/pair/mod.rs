@@ -0,0 +1,245 @@
+//! Pair Programming
+//!
+//! A pair is a shell pane with an agent pane opened beside it. Every block
+//! the shell pane finishes becomes context for the agent, which offers a fix
+//! or a next step without being asked. Suggestions are throttled per pair,
+//! and a global kill switch silences every pair at once.
+
+use crate::agent::client::{AgentProvider, AgentResponse};
+use crate::agent::model::ModelId;
+use crate::app::pane::Block;
+use crate::config::PairConfig;
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use uuid::Uuid;
+
+/// What the agent is asked each time the shell pane moves on.
+pub const PAIR_PROMPT: &str = "fix this if the last command failed, otherwise suggest the next step";
+
+/// Output beyond this many characters of a block is not sent.
+const MAX_BLOCK_CHARS: usize = 4000;
+
+const HOUR_SECONDS: i64 = 3600;
+
+/// A shell pane and the agent pane watching it.
+#[derive(Debug, Clone)]
+pub struct PairSession {
+    pub shell_pane_id: Uuid,
+    pub agent_pane_id: Uuid,
+    /// Shell blocks already turned into context.
+    seen_blocks: usize,
+    context: VecDeque<String>,
+    /// Whether blocks arrived since the agent was last asked.
+    fresh: bool,
+    in_flight: bool,
+    /// When suggestions were asked for during the last hour, oldest first.
+    asked_at: VecDeque<i64>,
+}
+
+impl PairSession {
+    /// Pairs the panes, ignoring the `seen_blocks` the shell pane already has.
+    pub fn new(shell_pane_id: Uuid, agent_pane_id: Uuid, seen_blocks: usize) -> Self {
+        Self {
+            shell_pane_id,
+            agent_pane_id,
+            seen_blocks,
+            context: VecDeque::new(),
+            fresh: false,
+            in_flight: false,
+            asked_at: VecDeque::new(),
+        }
+    }
+
+    /// Takes the blocks the shell pane finished since the last look, keeping
+    /// the latest `keep` as context.
+    pub fn observe(&mut self, history: &[Block], keep: usize) {
+        // Deleted blocks shrink the history; carry on from its new end.
+        self.seen_blocks = self.seen_blocks.min(history.len());
+        for block in &history[self.seen_blocks..] {
+            let output: String = block.output.chars().take(MAX_BLOCK_CHARS).collect();
+            self.context.push_back(format!("$ {}\n{}", block.command, output));
+            self.fresh = true;
+        }
+        while self.context.len() > keep {
+            self.context.pop_front();
+        }
+        self.seen_blocks = history.len();
+    }
+
+    /// Whether the agent may be asked now: there is something new, no
+    /// request is running, and the pair is within its throttle.
+    pub fn is_due(&self, config: &PairConfig, now: i64) -> bool {
+        let rested = self
+            .asked_at
+            .back()
+            .is_none_or(|at| now - at >= config.min_interval_seconds as i64);
+        let within_hour = self.asked_at.iter().filter(|at| now - **at < HOUR_SECONDS).count() < config.max_per_hour;
+        self.fresh && !self.in_flight && rested && within_hour
+    }
+
+    fn start(&mut self, now: i64) -> Vec<String> {
+        while self.asked_at.front().is_some_and(|at| now - at >= HOUR_SECONDS) {
+            self.asked_at.pop_front();
+        }
+        self.asked_at.push_back(now);
+        self.fresh = false;
+        self.in_flight = true;
+        self.context.iter().cloned().collect()
+    }
+}
+
+/// A suggestion for the agent pane `agent_pane_id`.
+pub struct Suggestion {
+    pub agent_pane_id: Uuid,
+    pub response: AgentResponse,
+}
+
+/// Formats a suggestion for the agent pane.
+pub fn describe(response: &AgentResponse) -> String {
+    match response {
+        AgentResponse::SuggestCommand { explanation, command } => format!("{}\n  $ {}", explanation, command),
+        AgentResponse::RequestToRunCommand { explanation, command_to_run } => {
+            format!("{}\n  $ {}", explanation, command_to_run)
+        }
+        AgentResponse::ProposeCodeChange { explanation, diffs } => {
+            let files: Vec<&str> = diffs.iter().map(|diff| diff.file_path.as_str()).collect();
+            format!("{}\n  changes {}", explanation, files.join(", "))
+        }
+        AgentResponse::Clarification(text) => text.clone(),
+    }
+}
+
+/// Every open pair, with the requests running for them.
+pub struct PairProgramming {
+    pub sessions: Vec<PairSession>,
+    paused: bool,
+    agent: Arc<dyn AgentProvider>,
+    result_tx: Sender<(Uuid, AgentResponse)>,
+    results: Receiver<(Uuid, AgentResponse)>,
+}
+
+impl PairProgramming {
+    pub fn new(agent: Arc<dyn AgentProvider>) -> Self {
+        let (result_tx, results) = channel();
+        Self { sessions: Vec::new(), paused: false, agent, result_tx, results }
+    }
+
+    /// The pair `pane_id` belongs to, on either side.
+    pub fn session_for(&self, pane_id: Uuid) -> Option<&PairSession> {
+        self.sessions
+            .iter()
+            .find(|session| session.shell_pane_id == pane_id || session.agent_pane_id == pane_id)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Flips the kill switch and returns whether pairs are now paused.
+    /// Blocks finished while paused are not suggested on afterwards.
+    pub fn toggle_paused(&mut self) -> bool {
+        self.paused = !self.paused;
+        for session in &mut self.sessions {
+            session.fresh = false;
+        }
+        self.paused
+    }
+
+    /// Asks the agent about every pair that is due, on background threads.
+    pub fn request(&mut self, config: &PairConfig, model: &ModelId, now: i64) {
+        if self.paused || !config.enabled {
+            return;
+        }
+        for session in self.sessions.iter_mut().filter(|session| session.is_due(config, now)) {
+            let context = session.start(now);
+            let agent_pane_id = session.agent_pane_id;
+            let model = model.clone();
+            let agent = self.agent.clone();
+            let result_tx = self.result_tx.clone();
+            thread::spawn(move || {
+                let response = agent.process_query(PAIR_PROMPT, &[], &context, model);
+                result_tx.send((agent_pane_id, response)).ok();
+            });
+        }
+    }
+
+    /// Takes finished requests. Answers that are only clarifying questions,
+    /// and any that arrive while paused, are dropped.
+    pub fn poll(&mut self) -> Vec<Suggestion> {
+        let mut suggestions = Vec::new();
+        while let Ok((agent_pane_id, response)) = self.results.try_recv() {
+            if let Some(session) = self.sessions.iter_mut().find(|session| session.agent_pane_id == agent_pane_id) {
+                session.in_flight = false;
+            }
+            if self.paused || matches!(response, AgentResponse::Clarification(_)) {
+                continue;
+            }
+            suggestions.push(Suggestion { agent_pane_id, response });
+        }
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(command: &str, output: &str) -> Block {
+        Block::new(command.to_string(), output.to_string())
+    }
+
+    #[test]
+    fn test_session_throttles_suggestions() {
+        let config = PairConfig { min_interval_seconds: 30, max_per_hour: 2, context_blocks: 2, ..PairConfig::default() };
+        let mut history = vec![block("ls", "src")];
+        let mut session = PairSession::new(Uuid::new_v4(), Uuid::new_v4(), history.len());
+        session.observe(&history, config.context_blocks);
+        assert!(!session.is_due(&config, 0));
+
+        history.push(block("cargo build", "error[E0425]"));
+        history.push(block("cargo test", "test result: FAILED"));
+        history.push(block("git status", "clean"));
+        session.observe(&history, config.context_blocks);
+        assert!(session.is_due(&config, 0));
+        assert_eq!(session.start(0), vec!["$ cargo test\ntest result: FAILED", "$ git status\nclean"]);
+        session.in_flight = false;
+
+        history.push(block("make", "ok"));
+        session.observe(&history, config.context_blocks);
+        assert!(!session.is_due(&config, 10));
+        session.start(40);
+        session.in_flight = false;
+        session.fresh = true;
+        // Two suggestions in the last hour is the limit.
+        assert!(!session.is_due(&config, 100));
+        assert!(session.is_due(&config, HOUR_SECONDS + 1));
+    }
+
+    #[test]
+    fn test_kill_switch_silences_pairs() {
+        let config = PairConfig::default();
+        let mut pairs = PairProgramming::new(Arc::new(crate::agent::client::SimulatedAgent::new()));
+        let agent_pane_id = Uuid::new_v4();
+        pairs.sessions.push(PairSession::new(Uuid::new_v4(), agent_pane_id, 0));
+        pairs.sessions[0].observe(&[block("cargo build", "error: could not compile")], config.context_blocks);
+
+        assert!(pairs.toggle_paused());
+        pairs.request(&config, &ModelId::Auto, 0);
+        assert!(!pairs.sessions[0].in_flight);
+
+        assert!(!pairs.toggle_paused());
+        pairs.sessions[0].observe(&[block("cargo build", "error"), block("cargo build", "error: failed")], config.context_blocks);
+        pairs.request(&config, &ModelId::Auto, 0);
+        let suggestions = loop {
+            let suggestions = pairs.poll();
+            if !suggestions.is_empty() {
+                break suggestions;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(suggestions[0].agent_pane_id, agent_pane_id);
+        assert!(describe(&suggestions[0].response).contains("$ cargo clean && cargo build"));
+    }
+}
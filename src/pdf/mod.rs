@@ -0,0 +1,394 @@
+//! PDF Export
+//!
+//! Renders blocks and notebooks to paginated PDF files for printing and
+//! sharing. Pages are laid out headlessly on the same monospace grid the
+//! terminal uses, at the configured font size and line height, and painted in
+//! the active theme's colors. Text is set in the PDF's built-in Courier faces,
+//! so files need no embedded fonts; characters outside Windows-1252 print as
+//! `?`, and box-drawing characters are approximated with ASCII.
+
+use crate::app::pane::Block;
+use crate::config::AppearanceConfig;
+use crate::drive::Notebook;
+use crate::ui::theme::{CustomColor, Theme};
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 40.0;
+/// Courier advances every glyph by 600/1000 of the font size.
+const CHAR_WIDTH: f32 = 0.6;
+/// Screen font sizes are in pixels; PDF sizes are in points.
+const POINTS_PER_PIXEL: f32 = 0.75;
+const TAB_WIDTH: usize = 8;
+
+/// Colors and metrics for an export.
+#[derive(Debug, Clone)]
+pub struct PdfStyle {
+    /// Font size in points.
+    pub font_size: f32,
+    /// Distance between baselines, as a multiple of the font size.
+    pub line_height: f32,
+    pub background: CustomColor,
+    pub foreground: CustomColor,
+    /// Commands and headings.
+    pub accent: CustomColor,
+    /// Page numbers and notes.
+    pub muted: CustomColor,
+    /// Shading behind code.
+    pub code_background: CustomColor,
+}
+
+impl PdfStyle {
+    pub fn new(theme: &Theme, appearance: &AppearanceConfig) -> Self {
+        Self {
+            font_size: appearance.font_size * POINTS_PER_PIXEL,
+            line_height: appearance.line_height,
+            background: theme.background,
+            foreground: theme.foreground,
+            accent: theme.accent,
+            muted: mix(theme.foreground, theme.background, 0.5),
+            code_background: mix(theme.foreground, theme.background, 0.08),
+        }
+    }
+
+    /// How many characters fit on a line.
+    fn columns(&self) -> usize {
+        ((PAGE_WIDTH - 2.0 * MARGIN) / (self.font_size * CHAR_WIDTH)).floor().max(1.0) as usize
+    }
+
+    fn leading(&self) -> f32 {
+        self.font_size * self.line_height
+    }
+
+    /// How many lines fit on a page, leaving room for the page number.
+    fn lines_per_page(&self) -> usize {
+        (((PAGE_HEIGHT - 2.0 * MARGIN) / self.leading()).floor() as usize).saturating_sub(2).max(1)
+    }
+}
+
+impl Default for PdfStyle {
+    /// Black on white, for when the theme is not at hand.
+    fn default() -> Self {
+        Self {
+            font_size: 10.0,
+            line_height: 1.2,
+            background: CustomColor(255, 255, 255),
+            foreground: CustomColor(0, 0, 0),
+            accent: CustomColor(0, 90, 160),
+            muted: CustomColor(128, 128, 128),
+            code_background: CustomColor(235, 235, 235),
+        }
+    }
+}
+
+/// Blends `amount` of `color` into `base`.
+pub(crate) fn mix(color: CustomColor, base: CustomColor, amount: f32) -> CustomColor {
+    let blend = |a: u8, b: u8| (a as f32 * amount + b as f32 * (1.0 - amount)).round() as u8;
+    CustomColor(blend(color.0, base.0), blend(color.1, base.1), blend(color.2, base.2))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    text: String,
+    color: CustomColor,
+    bold: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    spans: Vec<Span>,
+    /// Drawn over the code background.
+    shaded: bool,
+}
+
+/// A document being laid out into lines that fit the page width.
+pub struct PdfDocument {
+    style: PdfStyle,
+    lines: Vec<Line>,
+}
+
+impl PdfDocument {
+    pub fn new(style: PdfStyle) -> Self {
+        Self { style, lines: Vec::new() }
+    }
+
+    /// A titled document of blocks, each its command followed by its output.
+    pub fn from_blocks(style: PdfStyle, title: &str, blocks: &[&Block]) -> Self {
+        let mut document = Self::new(style);
+        document.push(&[(title, document.style.accent, true)], false);
+        for block in blocks {
+            document.push(&[], false);
+            document.block(block);
+        }
+        document
+    }
+
+    /// A notebook, with its fenced code shaded.
+    pub fn from_notebook(style: PdfStyle, notebook: &Notebook) -> Self {
+        let mut document = Self::new(style);
+        let mut in_code = false;
+        for line in notebook.content.lines() {
+            if line.trim_start().starts_with("```") {
+                in_code = !in_code;
+                continue;
+            }
+            if in_code {
+                document.push(&[(line, document.style.foreground, false)], true);
+            } else if let Some(heading) = line.strip_prefix('#') {
+                let heading = heading.trim_start_matches('#').trim();
+                document.push(&[(heading, document.style.accent, true)], false);
+            } else {
+                document.push(&[(line, document.style.foreground, false)], false);
+            }
+        }
+        document
+    }
+
+    fn block(&mut self, block: &Block) {
+        let (accent, foreground, muted) = (self.style.accent, self.style.foreground, self.style.muted);
+        self.push(&[("$ ", accent, true), (&block.command, accent, true)], true);
+        for line in block.output.trim_end().lines() {
+            self.push(&[(line, foreground, false)], true);
+        }
+        if !block.tags.is_empty() {
+            let tags: Vec<String> = block.tags.iter().map(|tag| format!("#{}", tag)).collect();
+            self.push(&[(&tags.join(" "), muted, false)], false);
+        }
+    }
+
+    /// Adds one line of styled runs, wrapping it at the page width.
+    fn push(&mut self, runs: &[(&str, CustomColor, bool)], shaded: bool) {
+        let mut cells: Vec<(char, CustomColor, bool)> = Vec::new();
+        for (text, color, bold) in runs {
+            for c in text.chars() {
+                if c == '\t' {
+                    let spaces = TAB_WIDTH - cells.len() % TAB_WIDTH;
+                    cells.extend(std::iter::repeat_n((' ', *color, *bold), spaces));
+                } else if !c.is_control() {
+                    cells.push((c, *color, *bold));
+                }
+            }
+        }
+        if cells.is_empty() {
+            self.lines.push(Line { spans: Vec::new(), shaded });
+            return;
+        }
+        for row in cells.chunks(self.style.columns()) {
+            let mut spans: Vec<Span> = Vec::new();
+            for (c, color, bold) in row {
+                match spans.last_mut() {
+                    Some(span) if span.color == *color && span.bold == *bold => span.text.push(*c),
+                    _ => spans.push(Span { text: c.to_string(), color: *color, bold: *bold }),
+                }
+            }
+            self.lines.push(Line { spans, shaded });
+        }
+    }
+
+    /// Writes the document as a PDF file's bytes.
+    pub fn render(&self) -> Vec<u8> {
+        let pages: Vec<&[Line]> = if self.lines.is_empty() {
+            vec![&[]]
+        } else {
+            self.lines.chunks(self.style.lines_per_page()).collect()
+        };
+        let mut writer = PdfWriter::default();
+        // Objects 1 to 4 are the catalog, the page tree and the two fonts;
+        // each page takes two more, its dictionary and its content stream.
+        let page_ids: Vec<usize> = (0..pages.len()).map(|idx| 5 + idx * 2).collect();
+        writer.object(1, b"<< /Type /Catalog /Pages 2 0 R >>");
+        let kids: Vec<String> = page_ids.iter().map(|id| format!("{} 0 R", id)).collect();
+        writer.object(2, format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()).as_bytes());
+        writer.object(3, b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>");
+        writer.object(4, b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier-Bold /Encoding /WinAnsiEncoding >>");
+        for (idx, lines) in pages.iter().enumerate() {
+            let page_id = page_ids[idx];
+            writer.object(
+                page_id,
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                    PAGE_WIDTH,
+                    PAGE_HEIGHT,
+                    page_id + 1
+                )
+                .as_bytes(),
+            );
+            let content = self.page_content(lines, idx + 1, pages.len());
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"\nendstream");
+            writer.object(page_id + 1, &stream);
+        }
+        writer.finish()
+    }
+
+    fn page_content(&self, lines: &[Line], page: usize, page_count: usize) -> Vec<u8> {
+        let style = &self.style;
+        let leading = style.leading();
+        let baseline = |row: usize| PAGE_HEIGHT - MARGIN - style.font_size - row as f32 * leading;
+        let mut content = Vec::new();
+        content.extend(format!("{} 0 0 {} {} re f\n", fill(style.background), PAGE_WIDTH, PAGE_HEIGHT).into_bytes());
+        for (row, line) in lines.iter().enumerate().filter(|(_, line)| line.shaded) {
+            // From just under this baseline up to just under the previous one
+            let bottom = baseline(row) - (leading - style.font_size) / 2.0 - style.font_size * 0.2;
+            content.extend(
+                format!("{} {} {:.2} {} {:.2} re f\n", fill(style.code_background), MARGIN - 4.0, bottom, PAGE_WIDTH - 2.0 * MARGIN + 8.0, leading)
+                    .into_bytes(),
+            );
+        }
+        content.extend_from_slice(b"BT\n");
+        for (row, line) in lines.iter().enumerate() {
+            let mut x = MARGIN;
+            for span in &line.spans {
+                let font = if span.bold { "F2" } else { "F1" };
+                content.extend(
+                    format!("/{} {} Tf {} 1 0 0 1 {:.2} {:.2} Tm ", font, style.font_size, fill(span.color), x, baseline(row))
+                        .into_bytes(),
+                );
+                content.extend(pdf_string(&span.text));
+                content.extend_from_slice(b" Tj\n");
+                x += span.text.chars().count() as f32 * style.font_size * CHAR_WIDTH;
+            }
+        }
+        let number = format!("{} / {}", page, page_count);
+        let number_x = PAGE_WIDTH - MARGIN - number.len() as f32 * style.font_size * CHAR_WIDTH;
+        content.extend(
+            format!("/F1 {} Tf {} 1 0 0 1 {:.2} {:.2} Tm ", style.font_size, fill(style.muted), number_x, MARGIN / 2.0).into_bytes(),
+        );
+        content.extend(pdf_string(&number));
+        content.extend_from_slice(b" Tj\nET");
+        content
+    }
+}
+
+/// Sets the fill color, which PDF also uses for text.
+fn fill(color: CustomColor) -> String {
+    let channel = |value: u8| format!("{:.3}", value as f32 / 255.0);
+    format!("{} {} {} rg", channel(color.0), channel(color.1), channel(color.2))
+}
+
+/// A literal string in Windows-1252, with delimiters and high bytes escaped.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = vec![b'('];
+    for c in text.chars() {
+        match win_ansi(c) {
+            byte @ (b'(' | b')' | b'\\') => bytes.extend_from_slice(&[b'\\', byte]),
+            byte if byte >= 0x80 => bytes.extend(format!("\\{:03o}", byte).into_bytes()),
+            byte => bytes.push(byte),
+        }
+    }
+    bytes.push(b')');
+    bytes
+}
+
+/// The Windows-1252 byte for `c`, approximating what it cannot show.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        '„' => 0x84,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '™' => 0x99,
+        '─' | '━' | '═' | '╌' => b'-',
+        '│' | '┃' | '║' | '╎' => b'|',
+        '\u{2500}'..='\u{257f}' => b'+',
+        _ => b'?',
+    }
+}
+
+/// Collects numbered objects and writes them with their cross-reference
+/// table.
+#[derive(Default)]
+struct PdfWriter {
+    output: Vec<u8>,
+    /// Byte offsets of objects 1, 2, ... in `output`.
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    /// Appends object `id`; ids must be added in order starting at 1.
+    fn object(&mut self, id: usize, body: &[u8]) {
+        if self.output.is_empty() {
+            self.output.extend_from_slice(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n");
+        }
+        debug_assert_eq!(id, self.offsets.len() + 1);
+        self.offsets.push(self.output.len());
+        self.output.extend(format!("{} 0 obj\n", id).into_bytes());
+        self.output.extend_from_slice(body);
+        self.output.extend_from_slice(b"\nendobj\n");
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.output.len();
+        self.output.extend(format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1).into_bytes());
+        for offset in &self.offsets {
+            self.output.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+        }
+        self.output.extend(
+            format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", self.offsets.len() + 1, xref).into_bytes(),
+        );
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(command: &str, output: &str) -> Block {
+        Block {
+            tags: vec!["build".to_string()],
+            ..Block::new(command.to_string(), output.to_string())
+        }
+    }
+
+    #[test]
+    fn test_blocks_wrap_and_paginate() {
+        let style = PdfStyle::default();
+        let long_output = "x".repeat(style.columns() + 5);
+        let many_lines = vec!["line"; style.lines_per_page()].join("\n");
+        let (first, second) = (block("cargo build", &long_output), block("seq 100", &many_lines));
+        let document = PdfDocument::from_blocks(style.clone(), "Build (log)", &[&first, &second]);
+
+        // Title, blank, command, two wrapped output rows, tags, blank, ...
+        assert_eq!(document.lines[2].spans[0].text, "$ cargo build");
+        assert!(document.lines[2].spans[0].bold && document.lines[2].shaded);
+        assert_eq!(document.lines[3].spans[0].text.len(), style.columns());
+        assert_eq!(document.lines[4].spans[0].text, "xxxxx");
+        assert_eq!(document.lines[5].spans[0].text, "#build");
+
+        let pdf = document.render();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(Build \\(log\\)) Tj"));
+        // The cross-reference table points at each object's byte offset.
+        let xref: usize = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        assert!(pdf[xref..].starts_with(b"xref"));
+        let entry = &pdf[xref..].split(|byte| *byte == b'\n').nth(6).unwrap()[..10];
+        let fourth: usize = std::str::from_utf8(entry).unwrap().parse().unwrap();
+        assert!(pdf[fourth..].starts_with(b"4 0 obj"));
+    }
+
+    #[test]
+    fn test_notebook_code_is_shaded_and_encoded() {
+        let notebook = Notebook {
+            name: "deploy".to_string(),
+            content: "# Deploy\n\nRun it:\n\n```sh\nmake deploy\t# café → 日本\n```\n".to_string(),
+        };
+        let document = PdfDocument::from_notebook(PdfStyle::default(), &notebook);
+        let rows: Vec<(&str, bool)> = document.lines.iter().map(|line| (line.spans.first().map_or("", |span| span.text.as_str()), line.shaded)).collect();
+        assert_eq!(rows, vec![("Deploy", false), ("", false), ("Run it:", false), ("", false), ("make deploy     # café → 日本", true)]);
+        assert_eq!(pdf_string("café → ─"), b"(caf\\351 ? -)".to_vec());
+    }
+}
@@ -0,0 +1,456 @@
+//! Performance HUD
+//!
+//! The renderer times each frame's phases — shaping text (layout),
+//! rasterizing and uploading glyphs, and submitting and presenting the
+//! frame (draw) — and, where the GPU supports timestamp queries, how long
+//! the frame's render pass took on the GPU. `PerfStats` keeps the last
+//! second or so of frames along with PTY throughput and the app's memory,
+//! and renders them as the lines of a toggleable overlay. A
+//! `ProfileCapture` samples the whole process with `pprof` for a fixed
+//! interval and writes a flamegraph.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, ProcessExt, System, SystemExt};
+use thiserror::Error;
+
+/// Frames kept for the averages and the FPS count.
+const FRAME_WINDOW: Duration = Duration::from_secs(1);
+/// How often the app's memory is read; it is costly to read every frame.
+const MEMORY_INTERVAL: Duration = Duration::from_secs(1);
+/// Samples per second taken by a profile capture.
+const PROFILE_FREQUENCY: i32 = 1000;
+
+#[derive(Error, Debug)]
+pub enum PerfError {
+    #[error("Profiler failed: {0}")]
+    Profiler(#[from] pprof::Error),
+    #[error("Failed to write the flamegraph: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePhase {
+    /// Shaping text into lines and glyphs.
+    Layout,
+    /// Rasterizing glyphs and uploading them to the GPU.
+    GlyphUpload,
+    /// Submitting the frame and presenting it.
+    Draw,
+}
+
+/// Where one frame's time went.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    pub total: Duration,
+    pub layout: Duration,
+    pub glyph_upload: Duration,
+    pub draw: Duration,
+    /// The render pass on the GPU, when timestamp queries are supported.
+    pub gpu: Option<Duration>,
+}
+
+/// Adds up the phases of the frame being rendered.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameTimer {
+    started: Instant,
+    timing: FrameTiming,
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            timing: FrameTiming::default(),
+        }
+    }
+
+    pub fn begin(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn add(&mut self, phase: FramePhase, elapsed: Duration) {
+        match phase {
+            FramePhase::Layout => self.timing.layout += elapsed,
+            FramePhase::GlyphUpload => self.timing.glyph_upload += elapsed,
+            FramePhase::Draw => self.timing.draw += elapsed,
+        }
+    }
+
+    pub fn finish(&mut self, gpu: Option<Duration>) -> FrameTiming {
+        FrameTiming {
+            total: self.started.elapsed(),
+            gpu,
+            ..self.timing
+        }
+    }
+}
+
+/// Recent frames, PTY throughput and memory, for the HUD.
+pub struct PerfStats {
+    frames: VecDeque<(Instant, FrameTiming)>,
+    /// Total PTY bytes read, as sampled each frame.
+    pty_samples: VecDeque<(Instant, u64)>,
+    system: System,
+    memory: Option<u64>,
+    memory_read_at: Option<Instant>,
+}
+
+impl Default for PerfStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PerfStats {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            pty_samples: VecDeque::new(),
+            system: System::new(),
+            memory: None,
+            memory_read_at: None,
+        }
+    }
+
+    /// Records a frame rendered at `now` and the PTY byte count then.
+    pub fn record(&mut self, now: Instant, timing: FrameTiming, pty_bytes: u64) {
+        self.frames.push_back((now, timing));
+        self.pty_samples.push_back((now, pty_bytes));
+        while self
+            .frames
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > FRAME_WINDOW)
+        {
+            self.frames.pop_front();
+        }
+        // One sample older than the window is kept to measure across it
+        while self.pty_samples.len() > 2 && now.duration_since(self.pty_samples[1].0) > FRAME_WINDOW
+        {
+            self.pty_samples.pop_front();
+        }
+    }
+
+    /// Frames rendered in the last second.
+    pub fn fps(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The mean of each phase over the last second. The GPU time is the
+    /// mean of the frames that have one.
+    pub fn average(&self) -> FrameTiming {
+        let count = self.frames.len().max(1) as u32;
+        let mut sum = FrameTiming::default();
+        let mut gpu = (Duration::ZERO, 0u32);
+        for (_, timing) in &self.frames {
+            sum.total += timing.total;
+            sum.layout += timing.layout;
+            sum.glyph_upload += timing.glyph_upload;
+            sum.draw += timing.draw;
+            if let Some(time) = timing.gpu {
+                gpu = (gpu.0 + time, gpu.1 + 1);
+            }
+        }
+        FrameTiming {
+            total: sum.total / count,
+            layout: sum.layout / count,
+            glyph_upload: sum.glyph_upload / count,
+            draw: sum.draw / count,
+            gpu: (gpu.1 > 0).then(|| gpu.0 / gpu.1),
+        }
+    }
+
+    /// The slowest frame of the last second.
+    pub fn worst(&self) -> Duration {
+        self.frames
+            .iter()
+            .map(|(_, timing)| timing.total)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Bytes read from PTYs per second, over the last second.
+    pub fn pty_throughput(&self) -> f64 {
+        match (self.pty_samples.front(), self.pty_samples.back()) {
+            (Some((first_at, first)), Some((last_at, last))) if last_at > first_at => {
+                (last - first) as f64 / last_at.duration_since(*first_at).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The app's resident memory in bytes, read at most once a second.
+    pub fn memory(&mut self, now: Instant) -> Option<u64> {
+        if self
+            .memory_read_at
+            .is_none_or(|read_at| now.duration_since(read_at) >= MEMORY_INTERVAL)
+        {
+            self.memory_read_at = Some(now);
+            let pid = Pid::from(std::process::id() as usize);
+            self.system.refresh_process(pid);
+            self.memory = self.system.process(pid).map(|process| process.memory());
+        }
+        self.memory
+    }
+
+    /// The HUD's lines.
+    pub fn lines(&mut self, now: Instant) -> Vec<String> {
+        let average = self.average();
+        let mut lines = vec![
+            format!(
+                "{} fps  {} avg  {} worst",
+                self.fps(),
+                millis(average.total),
+                millis(self.worst())
+            ),
+            format!("layout  {}", millis(average.layout)),
+            format!("glyphs  {}", millis(average.glyph_upload)),
+            format!("draw    {}", millis(average.draw)),
+        ];
+        if let Some(gpu) = average.gpu {
+            lines.push(format!("gpu     {}", millis(gpu)));
+        }
+        lines.push(format!("pty     {}/s", bytes(self.pty_throughput())));
+        if let Some(memory) = self.memory(now) {
+            lines.push(format!("memory  {}", bytes(memory as f64)));
+        }
+        lines
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn bytes(count: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = count;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Times the frame's render pass on the GPU with timestamp queries. The
+/// result is read back a frame or more later, without waiting on the GPU;
+/// frames rendered while a readback is pending aren't timed.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Whether this frame's timestamps were resolved and need reading.
+    resolved: bool,
+    in_flight: bool,
+    mapped: Arc<AtomicBool>,
+    last: Option<Duration>,
+}
+
+impl GpuTimer {
+    const SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+    /// A timer, if the device was created with timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("frame timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("frame timestamps resolve"),
+                size: Self::SIZE,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("frame timestamps readback"),
+                size: Self::SIZE,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            resolved: false,
+            in_flight: false,
+            mapped: Arc::new(AtomicBool::new(false)),
+            last: None,
+        })
+    }
+
+    /// Picks up a finished readback. Call before rendering a frame.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        if !self.in_flight {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        if !self.mapped.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        {
+            let data = self.readback.slice(..).get_mapped_range();
+            let start = u64::from_le_bytes(data[0..8].try_into().unwrap_or_default());
+            let end = u64::from_le_bytes(data[8..16].try_into().unwrap_or_default());
+            let nanos = end.saturating_sub(start) as f64 * f64::from(self.period);
+            self.last = Some(Duration::from_nanos(nanos as u64));
+        }
+        self.readback.unmap();
+        self.in_flight = false;
+    }
+
+    /// The writes for the frame's render pass, unless the last readback
+    /// is still pending.
+    pub fn timestamp_writes(&self) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        (!self.in_flight).then_some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        })
+    }
+
+    /// Copies the pass's timestamps out for reading. Call after the pass.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.in_flight {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, Self::SIZE);
+        self.resolved = true;
+    }
+
+    /// Starts reading the timestamps back. Call after submitting.
+    pub fn read_back(&mut self) {
+        if !std::mem::take(&mut self.resolved) {
+            return;
+        }
+        let mapped = self.mapped.clone();
+        self.readback
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                mapped.store(result.is_ok(), Ordering::Release);
+            });
+        self.in_flight = true;
+    }
+
+    /// The most recent frame's GPU time.
+    pub fn last(&self) -> Option<Duration> {
+        self.last
+    }
+}
+
+/// Samples the process for a fixed interval to make a flamegraph.
+pub struct ProfileCapture {
+    guard: pprof::ProfilerGuard<'static>,
+    until: Instant,
+}
+
+impl ProfileCapture {
+    pub fn start(duration: Duration) -> Result<Self, PerfError> {
+        Ok(Self {
+            guard: pprof::ProfilerGuardBuilder::default()
+                .frequency(PROFILE_FREQUENCY)
+                .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+                .build()?,
+            until: Instant::now() + duration,
+        })
+    }
+
+    pub fn is_done(&self, now: Instant) -> bool {
+        now >= self.until
+    }
+
+    /// Stops sampling and writes the flamegraph to a new SVG in `dir`.
+    pub fn finish(self, dir: &Path) -> Result<PathBuf, PerfError> {
+        let report = self.guard.report().build()?;
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "flamegraph-{}.svg",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        report.flamegraph(fs::File::create(&path)?)?;
+        Ok(path)
+    }
+}
+
+pub fn default_profile_dir() -> PathBuf {
+    let mut path = dirs::data_dir().unwrap_or_else(std::env::temp_dir);
+    path.push("warpish_terminal");
+    path.push("profiles");
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(total_ms: u64, gpu_ms: Option<u64>) -> FrameTiming {
+        FrameTiming {
+            total: Duration::from_millis(total_ms),
+            layout: Duration::from_millis(total_ms / 2),
+            glyph_upload: Duration::from_millis(1),
+            draw: Duration::from_millis(2),
+            gpu: gpu_ms.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn test_frames_older_than_a_second_drop_out() {
+        let mut stats = PerfStats::new();
+        let start = Instant::now();
+        stats.record(start, frame(40, None), 0);
+        stats.record(start + Duration::from_millis(500), frame(10, Some(4)), 0);
+        stats.record(start + Duration::from_millis(900), frame(20, None), 0);
+        assert_eq!(stats.fps(), 3);
+        assert_eq!(stats.worst(), Duration::from_millis(40));
+
+        stats.record(start + Duration::from_millis(1200), frame(30, Some(6)), 0);
+        assert_eq!(stats.fps(), 3);
+        let average = stats.average();
+        assert_eq!(average.total, Duration::from_millis(20));
+        assert_eq!(average.layout, Duration::from_millis(10));
+        // Only the frames that were timed on the GPU count toward its mean
+        assert_eq!(average.gpu, Some(Duration::from_millis(5)));
+        assert_eq!(stats.worst(), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_pty_throughput_over_the_last_second() {
+        let mut stats = PerfStats::new();
+        let start = Instant::now();
+        assert_eq!(stats.pty_throughput(), 0.0);
+        stats.record(start, FrameTiming::default(), 1_000);
+        stats.record(
+            start + Duration::from_millis(500),
+            FrameTiming::default(),
+            3_000,
+        );
+        assert_eq!(stats.pty_throughput(), 4_000.0);
+        stats.record(
+            start + Duration::from_millis(2500),
+            FrameTiming::default(),
+            3_000,
+        );
+        assert_eq!(stats.pty_throughput(), 0.0);
+        assert_eq!(bytes(1536.0), "1.5 KiB");
+        assert_eq!(bytes(12.0), "12 B");
+    }
+}
@@ -0,0 +1,469 @@
+//! Capability Permissions
+//!
+//! This module decides whether a plugin, agent tool or other feature may use a
+//! sensitive capability: writing files, reaching the network, touching the
+//! clipboard, reading a remote host's shell history or letting a live share
+//! collaborator type into a pane.
+//! The first request for a capability is held while the user is asked.
+//! "Always" and "never" answers are stored in the database so the question is
+//! not repeated; one-time answers last until the terminal exits. Every
+//! decision is listed in the permissions audit view, where it can be revoked.
+
+use crate::db;
+use mlua::prelude::*;
+use rusqlite::Connection;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Capability {
+    FilesystemWrite,
+    Network,
+    Clipboard,
+    ShellHistory,
+    TerminalInput,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::FilesystemWrite,
+        Capability::Network,
+        Capability::Clipboard,
+        Capability::ShellHistory,
+        Capability::TerminalInput,
+    ];
+
+    /// The name used in the database and the Lua API.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Capability::FilesystemWrite => "fs_write",
+            Capability::Network => "network",
+            Capability::Clipboard => "clipboard",
+            Capability::ShellHistory => "shell_history",
+            Capability::TerminalInput => "terminal_input",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|capability| capability.key() == key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Capability::FilesystemWrite => "write files",
+            Capability::Network => "access the network",
+            Capability::Clipboard => "use the clipboard",
+            Capability::ShellHistory => "read your shell history",
+            Capability::TerminalInput => "type into your terminal",
+        }
+    }
+}
+
+/// Who is asking for a capability.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Requester {
+    Plugin(String),
+    AgentTool(String),
+    /// Completions for SSH sessions on the named host.
+    SshCompletions(String),
+    /// A collaborator in a live shared session, known by the session's
+    /// token and the id the session gave them when they joined, as names
+    /// and ids repeat across sessions.
+    Collaborator { session: String, user_id: u32, name: String },
+}
+
+impl Requester {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Requester::Plugin(_) => "plugin",
+            Requester::AgentTool(_) => "agent_tool",
+            Requester::SshCompletions(_) => "ssh_completions",
+            Requester::Collaborator { .. } => "collaborator",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Requester::Plugin(name)
+            | Requester::AgentTool(name)
+            | Requester::SshCompletions(name)
+            | Requester::Collaborator { name, .. } => name,
+        }
+    }
+
+    pub fn from_parts(kind: &str, name: String) -> Option<Self> {
+        match kind {
+            "plugin" => Some(Requester::Plugin(name)),
+            "agent_tool" => Some(Requester::AgentTool(name)),
+            "ssh_completions" => Some(Requester::SshCompletions(name)),
+            _ => None,
+        }
+    }
+
+    /// Whether answers for this requester may be stored. Collaborators'
+    /// last only as long as the session they joined.
+    pub fn can_remember(&self) -> bool {
+        !matches!(self, Requester::Collaborator { .. })
+    }
+}
+
+impl fmt::Display for Requester {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requester::Plugin(name) => write!(f, "Plugin '{}'", name),
+            Requester::AgentTool(name) => write!(f, "Agent tool '{}'", name),
+            Requester::SshCompletions(host) => write!(f, "Completions on '{}'", host),
+            Requester::Collaborator { user_id, name, .. } => write!(f, "Collaborator {} (#{})", name, user_id),
+        }
+    }
+}
+
+/// The user's answer to a permission prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionChoice {
+    AllowOnce,
+    AlwaysAllow,
+    Deny,
+    AlwaysDeny,
+}
+
+impl PermissionChoice {
+    pub const ALL: [PermissionChoice; 4] = [
+        PermissionChoice::AllowOnce,
+        PermissionChoice::AlwaysAllow,
+        PermissionChoice::Deny,
+        PermissionChoice::AlwaysDeny,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PermissionChoice::AllowOnce => "Allow this session",
+            PermissionChoice::AlwaysAllow => "Always allow",
+            PermissionChoice::Deny => "Deny",
+            PermissionChoice::AlwaysDeny => "Never allow",
+        }
+    }
+
+    pub fn allowed(&self) -> bool {
+        matches!(self, PermissionChoice::AllowOnce | PermissionChoice::AlwaysAllow)
+    }
+
+    /// Whether the choice is stored in the database.
+    pub fn remembered(&self) -> bool {
+        matches!(self, PermissionChoice::AlwaysAllow | PermissionChoice::AlwaysDeny)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionStatus {
+    Granted,
+    Denied,
+    /// The user has been asked; try again once they answer.
+    Pending,
+}
+
+impl PermissionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PermissionStatus::Granted => "granted",
+            PermissionStatus::Denied => "denied",
+            PermissionStatus::Pending => "pending",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionRequest {
+    pub requester: Requester,
+    pub capability: Capability,
+    /// Why the requester needs it, shown in the prompt.
+    pub reason: String,
+}
+
+impl PermissionRequest {
+    /// The answers the prompt offers.
+    pub fn choices(&self) -> &'static [PermissionChoice] {
+        if self.requester.can_remember() {
+            &PermissionChoice::ALL
+        } else {
+            &[PermissionChoice::AllowOnce, PermissionChoice::Deny]
+        }
+    }
+}
+
+/// A decision the user made, as listed in the audit view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionGrant {
+    pub requester: Requester,
+    pub capability: Capability,
+    pub allowed: bool,
+    /// Stored in the database rather than kept for this session only.
+    pub remembered: bool,
+    pub decided_at: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct PermissionService {
+    decisions: HashMap<(Requester, Capability), PermissionGrant>,
+    pending: VecDeque<PermissionRequest>,
+}
+
+impl PermissionService {
+    /// Creates the service with the decisions remembered in the database.
+    pub fn load(conn: &Connection) -> rusqlite::Result<Self> {
+        let decisions = db::load_permission_grants(conn)?
+            .into_iter()
+            .map(|grant| ((grant.requester.clone(), grant.capability), grant))
+            .collect();
+        Ok(Self { decisions, pending: VecDeque::new() })
+    }
+
+    /// Answers from earlier decisions, or queues a prompt for the user.
+    pub fn request(&mut self, request: PermissionRequest) -> PermissionStatus {
+        if let Some(grant) = self.decisions.get(&(request.requester.clone(), request.capability)) {
+            return if grant.allowed { PermissionStatus::Granted } else { PermissionStatus::Denied };
+        }
+        let already_asked = self
+            .pending
+            .iter()
+            .any(|pending| pending.requester == request.requester && pending.capability == request.capability);
+        if !already_asked {
+            self.pending.push_back(request);
+        }
+        PermissionStatus::Pending
+    }
+
+    /// Takes the next request waiting for a prompt. Requests repeated while
+    /// an earlier prompt for them was showing are already answered, and are
+    /// dropped.
+    pub fn next_prompt(&mut self) -> Option<PermissionRequest> {
+        while let Some(request) = self.pending.pop_front() {
+            if !self.decisions.contains_key(&(request.requester.clone(), request.capability)) {
+                return Some(request);
+            }
+        }
+        None
+    }
+
+    /// Records the user's answer, storing it if they asked to be remembered.
+    pub fn resolve(
+        &mut self,
+        conn: &Connection,
+        request: &PermissionRequest,
+        choice: PermissionChoice,
+        now: i64,
+    ) -> rusqlite::Result<PermissionStatus> {
+        let grant = PermissionGrant {
+            requester: request.requester.clone(),
+            capability: request.capability,
+            allowed: choice.allowed(),
+            remembered: choice.remembered() && request.requester.can_remember(),
+            decided_at: now,
+        };
+        if grant.remembered {
+            db::save_permission_grant(conn, &grant)?;
+        }
+        self.decisions.insert((grant.requester.clone(), grant.capability), grant);
+        Ok(if choice.allowed() { PermissionStatus::Granted } else { PermissionStatus::Denied })
+    }
+
+    /// Forgets a decision so the requester is asked again next time.
+    pub fn revoke(&mut self, conn: &Connection, requester: &Requester, capability: Capability) -> rusqlite::Result<()> {
+        if let Some(grant) = self.decisions.remove(&(requester.clone(), capability)) {
+            if grant.remembered {
+                db::delete_permission_grant(conn, requester, capability)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the session-only decisions and waiting prompts of requesters
+    /// that are gone, such as the collaborators of a live share that ended.
+    pub fn forget(&mut self, gone: impl Fn(&Requester) -> bool) {
+        self.decisions.retain(|(requester, _), grant| grant.remembered || !gone(requester));
+        self.pending.retain(|request| !gone(&request.requester));
+    }
+
+    /// All decisions, grouped by requester.
+    pub fn grants(&self) -> Vec<PermissionGrant> {
+        let mut grants: Vec<PermissionGrant> = self.decisions.values().cloned().collect();
+        grants.sort_by(|a, b| (&a.requester, a.capability).cmp(&(&b.requester, b.capability)));
+        grants
+    }
+}
+
+/// How long `permissions.fetch` waits for a response.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Exposes permission requests to a plugin as the global `permissions`
+/// table, with the capabilities they guard:
+///
+/// - `permissions.request(capability, reason)` returns `"granted"`,
+///   `"denied"` or `"pending"`; capabilities are `"fs_write"`, `"network"`
+///   and `"clipboard"`;
+/// - `permissions.write_file(path, text)` and `permissions.set_clipboard(text)`
+///   act once `fs_write` or `clipboard` is granted, and return the status;
+/// - `permissions.fetch(url)` returns the status and, once `network` is
+///   granted, the response body.
+///
+/// A pending request should be retried after the user answers.
+pub fn register_lua(lua: &Lua, plugin: &str, service: Arc<Mutex<PermissionService>>) -> LuaResult<()> {
+    let permissions = lua.create_table()?;
+    let requester = Requester::Plugin(plugin.to_string());
+    let ask = Arc::new(move |capability: Capability, reason: String| {
+        service.lock().unwrap().request(PermissionRequest { requester: requester.clone(), capability, reason })
+    });
+    let request = ask.clone();
+    permissions.set(
+        "request",
+        lua.create_function(move |_, (capability, reason): (String, Option<String>)| {
+            let capability = Capability::from_key(&capability)
+                .ok_or_else(|| LuaError::RuntimeError(format!("unknown capability '{}'", capability)))?;
+            Ok(request(capability, reason.unwrap_or_default()).as_str())
+        })?,
+    )?;
+    let request = ask.clone();
+    permissions.set(
+        "write_file",
+        lua.create_function(move |_, (path, text): (String, String)| {
+            let status = request(Capability::FilesystemWrite, format!("Write {}", path));
+            if status == PermissionStatus::Granted {
+                std::fs::write(&path, text).map_err(LuaError::external)?;
+            }
+            Ok(status.as_str())
+        })?,
+    )?;
+    let request = ask.clone();
+    permissions.set(
+        "set_clipboard",
+        lua.create_function(move |_, text: String| {
+            let status = request(Capability::Clipboard, "Copy text to the clipboard".to_string());
+            if status == PermissionStatus::Granted {
+                arboard::Clipboard::new()
+                    .and_then(|mut clipboard| clipboard.set_text(text))
+                    .map_err(LuaError::external)?;
+            }
+            Ok(status.as_str())
+        })?,
+    )?;
+    permissions.set(
+        "fetch",
+        lua.create_function(move |_, url: String| {
+            let status = ask(Capability::Network, format!("Fetch {}", url));
+            if status != PermissionStatus::Granted {
+                return Ok((status.as_str(), None));
+            }
+            let body = fetch(url).map_err(LuaError::external)?;
+            Ok((status.as_str(), Some(body)))
+        })?,
+    )?;
+    lua.globals().set("permissions", permissions)
+}
+
+/// Fetches `url` on a thread of its own, as scripts may run inside another
+/// runtime.
+fn fetch(url: String) -> Result<String, FetchError> {
+    thread::spawn(move || -> Result<String, FetchError> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(async {
+            let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+            Ok::<_, FetchError>(client.get(url).send().await?.error_for_status()?.text().await?)
+        })
+    })
+    .join()
+    .unwrap_or_else(|_| Err("the request panicked".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory_connection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        db::create_permission_tables(&conn).unwrap();
+        conn
+    }
+
+    fn request(requester: Requester, capability: Capability) -> PermissionRequest {
+        PermissionRequest { requester, capability, reason: String::new() }
+    }
+
+    #[test]
+    fn test_first_request_prompts_once_and_remembered_choice_persists() {
+        let conn = memory_connection();
+        let mut service = PermissionService::load(&conn).unwrap();
+        let plugin = Requester::Plugin("gist-sync".to_string());
+
+        assert_eq!(service.request(request(plugin.clone(), Capability::Network)), PermissionStatus::Pending);
+        assert_eq!(service.request(request(plugin.clone(), Capability::Network)), PermissionStatus::Pending);
+        let prompt = service.next_prompt().unwrap();
+        assert!(service.next_prompt().is_none());
+
+        // Asked again while the prompt is showing: answered by the prompt.
+        assert_eq!(service.request(request(plugin.clone(), Capability::Network)), PermissionStatus::Pending);
+        service.resolve(&conn, &prompt, PermissionChoice::AlwaysAllow, 100).unwrap();
+        assert!(service.next_prompt().is_none());
+        assert_eq!(service.request(request(plugin.clone(), Capability::Network)), PermissionStatus::Granted);
+
+        let mut reloaded = PermissionService::load(&conn).unwrap();
+        assert_eq!(reloaded.request(request(plugin.clone(), Capability::Network)), PermissionStatus::Granted);
+        assert_eq!(reloaded.grants().len(), 1);
+        assert!(reloaded.grants()[0].remembered);
+
+        reloaded.revoke(&conn, &plugin, Capability::Network).unwrap();
+        assert!(PermissionService::load(&conn).unwrap().grants().is_empty());
+    }
+
+    #[test]
+    fn test_one_time_choices_are_not_stored() {
+        let conn = memory_connection();
+        let mut service = PermissionService::load(&conn).unwrap();
+        let tool = Requester::AgentTool("apply_patch".to_string());
+
+        service.request(request(tool.clone(), Capability::FilesystemWrite));
+        let prompt = service.next_prompt().unwrap();
+        assert_eq!(service.resolve(&conn, &prompt, PermissionChoice::Deny, 100).unwrap(), PermissionStatus::Denied);
+        assert_eq!(service.request(request(tool, Capability::FilesystemWrite)), PermissionStatus::Denied);
+        assert!(service.next_prompt().is_none());
+
+        // Collaborators cannot be remembered, even when the answer says so.
+        let sam = |session: &str| Requester::Collaborator { session: session.to_string(), user_id: 1, name: "sam".to_string() };
+        let collaborator = request(sam("first"), Capability::TerminalInput);
+        assert_eq!(collaborator.choices(), [PermissionChoice::AllowOnce, PermissionChoice::Deny]);
+        service.resolve(&conn, &collaborator, PermissionChoice::AlwaysAllow, 100).unwrap();
+        assert!(PermissionService::load(&conn).unwrap().grants().is_empty());
+
+        // The same name and id in a later session is someone else
+        assert_eq!(service.request(request(sam("second"), Capability::TerminalInput)), PermissionStatus::Pending);
+        service.forget(|requester| matches!(requester, Requester::Collaborator { session, .. } if session == "first"));
+        assert_eq!(service.request(request(sam("first"), Capability::TerminalInput)), PermissionStatus::Pending);
+        assert_eq!(service.grants().len(), 1);
+    }
+
+    #[test]
+    fn test_lua_writes_only_once_granted() {
+        let conn = memory_connection();
+        let service = Arc::new(Mutex::new(PermissionService::load(&conn).unwrap()));
+        let lua = crate::scripting::sandboxed().unwrap();
+        register_lua(&lua, "notes", service.clone()).unwrap();
+        let path = std::env::temp_dir().join(format!("warpish-permissions-{}", uuid::Uuid::new_v4()));
+        lua.globals().set("path", path.display().to_string()).unwrap();
+
+        let write = "return permissions.write_file(path, 'hello')";
+        assert_eq!(lua.load(write).eval::<String>().unwrap(), "pending");
+        assert!(!path.exists());
+        let prompt = service.lock().unwrap().next_prompt().unwrap();
+        service.lock().unwrap().resolve(&conn, &prompt, PermissionChoice::AllowOnce, 100).unwrap();
+        assert_eq!(lua.load(write).eval::<String>().unwrap(), "granted");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+        std::fs::remove_file(&path).unwrap();
+
+        // Without `io` and `os`, the permissions table is the only way out.
+        assert!(lua.load("return io == nil and os == nil and dofile == nil").eval::<bool>().unwrap());
+    }
+}
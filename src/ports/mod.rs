@@ -0,0 +1,355 @@
+//! Localhost Service Detection
+//!
+//! Finds the local services commands in panes are serving — a dev server on
+//! port 3000, an `ssh -L` forward, a database started in the foreground — so
+//! the prompt can show their URLs and the ports panel can open them or stop
+//! the process that owns them. Two sources are combined: URLs printed in a
+//! pane's output, such as `Local: http://localhost:5173/`, and the listening
+//! TCP sockets owned by the pane shell's descendants. A port printed in the
+//! output only counts while something is listening on it, so a stopped
+//! server's URL does not linger. Scans run on a background thread at most
+//! every couple of seconds.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use sysinfo::{PidExt, ProcessExt, System, SystemExt};
+use uuid::Uuid;
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    /// `http://localhost:3000/path` and the loopback and wildcard spellings
+    /// servers print for it.
+    static ref LOCAL_URL: Regex = Regex::new(
+        r"(?i)\b(https?)://(?:localhost|127\.0\.0\.1|0\.0\.0\.0|\[::1?\]):(\d{2,5})(/[^\s'\x22<>]*)?"
+    )
+    .expect("invalid local URL pattern");
+}
+
+/// A port something in a pane is listening on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub pane_id: Uuid,
+    pub port: u16,
+    pub url: String,
+    /// The listening process, when the process table shows it.
+    pub pid: Option<u32>,
+}
+
+/// A listening TCP socket and the process that owns it, if known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Listener {
+    pub port: u16,
+    pub pid: Option<u32>,
+}
+
+/// What a scan looks at for one pane.
+#[derive(Debug, Clone)]
+pub struct PaneScan {
+    pub pane_id: Uuid,
+    pub shell_pid: Option<u32>,
+    /// Recent output, searched for printed URLs.
+    pub output: String,
+}
+
+/// The local URLs in `text` by port, normalized to `localhost` since
+/// `0.0.0.0` and `[::]` are not addresses a browser can open.
+pub fn urls_in_output(text: &str) -> Vec<(u16, String)> {
+    let mut urls: Vec<(u16, String)> = Vec::new();
+    for captures in LOCAL_URL.captures_iter(text) {
+        let Ok(port) = captures[2].parse::<u16>() else {
+            continue;
+        };
+        if urls.iter().any(|(seen, _)| *seen == port) {
+            continue;
+        }
+        let path = captures.get(3).map_or("", |path| path.as_str().trim_end_matches(['.', ',', ')']));
+        urls.push((port, format!("{}://localhost:{}{}", captures[1].to_ascii_lowercase(), port, path)));
+    }
+    urls
+}
+
+/// Listening ports from `/proc/net/tcp` and `tcp6`, as `(port, inode)`.
+pub fn parse_proc_net_tcp(table: &str) -> Vec<(u16, u64)> {
+    const LISTEN: &str = "0A";
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&LISTEN) {
+                return None;
+            }
+            let port = u16::from_str_radix(fields.get(1)?.rsplit(':').next()?, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            Some((port, inode))
+        })
+        .collect()
+}
+
+/// Listening sockets from `lsof -F pn` output, where a `p` line names the
+/// process of the `n` lines after it.
+pub fn parse_lsof(output: &str) -> Vec<Listener> {
+    let mut listeners = Vec::new();
+    let mut pid = None;
+    for line in output.lines() {
+        if let Some(value) = line.strip_prefix('p') {
+            pid = value.parse().ok();
+        } else if let (Some(name), Some(pid)) = (line.strip_prefix('n'), pid) {
+            if let Some(port) = name.rsplit(':').next().and_then(|port| port.parse().ok()) {
+                let listener = Listener { port, pid: Some(pid) };
+                if !listeners.contains(&listener) {
+                    listeners.push(listener);
+                }
+            }
+        }
+    }
+    listeners
+}
+
+/// Every listening socket, with its owner where it is one of `pids`, or
+/// `None` where the platform has no way to tell.
+#[cfg(target_os = "linux")]
+pub fn listening_ports(pids: &HashSet<u32>) -> Option<Vec<Listener>> {
+    let mut inodes = HashMap::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(table) = std::fs::read_to_string(table) {
+            inodes.extend(parse_proc_net_tcp(&table).into_iter().map(|(port, inode)| (inode, port)));
+        }
+    }
+    let mut listeners = Vec::new();
+    for pid in pids {
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target.to_str().and_then(|target| target.strip_prefix("socket:[")?.strip_suffix(']')?.parse::<u64>().ok());
+            if let Some(port) = inode.and_then(|inode| inodes.get(&inode)) {
+                let listener = Listener { port: *port, pid: Some(*pid) };
+                if !listeners.contains(&listener) {
+                    listeners.push(listener);
+                }
+            }
+        }
+    }
+    // Sockets of other users' processes, such as a container proxy, are
+    // listed without an owner.
+    for port in inodes.into_values() {
+        if !listeners.iter().any(|listener| listener.port == port) {
+            listeners.push(Listener { port, pid: None });
+        }
+    }
+    Some(listeners)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn listening_ports(_pids: &HashSet<u32>) -> Option<Vec<Listener>> {
+    let output = std::process::Command::new("lsof")
+        .args(["-nP", "-iTCP", "-sTCP:LISTEN", "-F", "pn"])
+        .output()
+        .ok()?;
+    Some(parse_lsof(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(not(unix))]
+pub fn listening_ports(_pids: &HashSet<u32>) -> Option<Vec<Listener>> {
+    None
+}
+
+/// Every process below `root`, itself included.
+fn descendants(system: &System, root: u32) -> HashSet<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (pid, process) in system.processes() {
+        if let Some(parent) = process.parent() {
+            children.entry(parent.as_u32()).or_default().push(pid.as_u32());
+        }
+    }
+    let mut found = HashSet::from([root]);
+    let mut stack = vec![root];
+    while let Some(pid) = stack.pop() {
+        for child in children.get(&pid).into_iter().flatten() {
+            if found.insert(*child) {
+                stack.push(*child);
+            }
+        }
+    }
+    found
+}
+
+/// Combines what one pane printed with the sockets listening now, of
+/// which those owned by processes in `tree` belong to the pane. Without a
+/// socket list, printed URLs are trusted as they are.
+pub fn pane_services(pane_id: Uuid, printed: &[(u16, String)], listening: Option<&[Listener]>, tree: &HashSet<u32>) -> Vec<Service> {
+    let Some(listening) = listening else {
+        return printed.iter().map(|(port, url)| Service { pane_id, port: *port, url: url.clone(), pid: None }).collect();
+    };
+    let mut services: Vec<Service> = Vec::new();
+    for listener in listening {
+        let printed_url = printed.iter().find(|(port, _)| *port == listener.port).map(|(_, url)| url.clone());
+        let owned = listener.pid.is_some_and(|pid| tree.contains(&pid));
+        // A printed port may be served from outside the pane, such as by a container.
+        if owned || printed_url.is_some() {
+            services.push(Service {
+                pane_id,
+                port: listener.port,
+                url: printed_url.unwrap_or_else(|| format!("http://localhost:{}", listener.port)),
+                pid: listener.pid,
+            });
+        }
+    }
+    services.sort_by_key(|service| service.port);
+    services.dedup_by_key(|service| service.port);
+    services
+}
+
+fn scan(system: &mut System, panes: &[PaneScan]) -> Vec<Service> {
+    system.refresh_processes();
+    let trees: Vec<HashSet<u32>> = panes
+        .iter()
+        .map(|pane| pane.shell_pid.map(|shell| descendants(system, shell)).unwrap_or_default())
+        .collect();
+    let pids: HashSet<u32> = trees.iter().flatten().copied().collect();
+    let listening = listening_ports(&pids);
+    let mut services = Vec::new();
+    for (pane, tree) in panes.iter().zip(&trees) {
+        services.extend(pane_services(pane.pane_id, &urls_in_output(&pane.output), listening.as_deref(), tree));
+    }
+    services
+}
+
+/// Scans panes for services on a background thread.
+pub struct PortDetector {
+    services: Vec<Service>,
+    requests: Sender<Vec<PaneScan>>,
+    results: Receiver<Vec<Service>>,
+    last_scan: Option<Instant>,
+    in_flight: bool,
+}
+
+impl Default for PortDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PortDetector {
+    pub fn new() -> Self {
+        let (requests, request_rx) = channel::<Vec<PaneScan>>();
+        let (result_tx, results) = channel();
+        thread::spawn(move || {
+            let mut system = System::new();
+            for panes in request_rx {
+                if result_tx.send(scan(&mut system, &panes)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { services: Vec::new(), requests, results, last_scan: None, in_flight: false }
+    }
+
+    /// Starts a scan unless one is running or ran in the last two seconds.
+    /// `panes` is only called when a scan starts.
+    pub fn request(&mut self, panes: impl FnOnce() -> Vec<PaneScan>, now: Instant) {
+        if self.in_flight || self.last_scan.is_some_and(|at| now.saturating_duration_since(at) < SCAN_INTERVAL) {
+            return;
+        }
+        self.last_scan = Some(now);
+        self.in_flight = self.requests.send(panes()).is_ok();
+    }
+
+    /// Takes a finished scan. Returns whether the services changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(services) = self.results.try_recv() {
+            changed |= services != self.services;
+            self.services = services;
+            self.in_flight = false;
+        }
+        changed
+    }
+
+    pub fn services(&self) -> &[Service] {
+        &self.services
+    }
+
+    pub fn pane_services(&self, pane_id: Uuid) -> impl Iterator<Item = &Service> {
+        self.services.iter().filter(move |service| service.pane_id == pane_id)
+    }
+}
+
+/// Asks the process serving a port to exit.
+#[cfg(unix)]
+pub fn stop_owner(pid: u32) -> std::io::Result<()> {
+    // SAFETY: kill(2) takes no pointers.
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn stop_owner(pid: u32) -> std::io::Result<()> {
+    let status = std::process::Command::new("taskkill").args(["/PID", &pid.to_string()]).status()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("taskkill exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// The prompt chip for a pane's services, e.g. ` ⇄ :3000 :5173 `.
+pub fn chip<'a>(services: impl Iterator<Item = &'a Service>) -> Option<String> {
+    let ports: Vec<String> = services.map(|service| format!(":{}", service.port)).collect();
+    (!ports.is_empty()).then(|| format!(" ⇄ {} ", ports.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printed_urls_and_socket_tables_are_parsed() {
+        let output = "  VITE v5.0.0  ready\n  ➜  Local:   http://localhost:5173/\n  \
+                      ➜  Network: http://192.168.1.4:5173/\nListening on http://0.0.0.0:8000/docs.\n\
+                      again at http://127.0.0.1:5173/";
+        assert_eq!(
+            urls_in_output(output),
+            vec![(5173, "http://localhost:5173/".to_string()), (8000, "http://localhost:8000/docs".to_string())]
+        );
+
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+                   0: 00000000:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 48213 1\n   \
+                   1: 0100007F:A1B2 0100007F:0BB8 01 00000000:00000000 00:00000000 00000000  1000        0 48999 1\n";
+        assert_eq!(parse_proc_net_tcp(tcp), vec![(3000, 48213)]);
+
+        let lsof = "p4242\nn*:3000\nn[::1]:3000\np777\nn127.0.0.1:5432\n";
+        assert_eq!(parse_lsof(lsof), vec![Listener { port: 3000, pid: Some(4242) }, Listener { port: 5432, pid: Some(777) }]);
+    }
+
+    #[test]
+    fn test_printed_ports_need_a_listener() {
+        let pane_id = Uuid::new_v4();
+        let printed = vec![(5173, "http://localhost:5173/".to_string()), (8080, "http://localhost:8080".to_string())];
+        let listening = [
+            Listener { port: 3000, pid: Some(10) },
+            Listener { port: 5173, pid: None },
+            // Another pane's, or nobody's business.
+            Listener { port: 9000, pid: Some(99) },
+        ];
+        // 8080 was printed by a server that has since stopped.
+        let tree = HashSet::from([1, 10]);
+        let services = pane_services(pane_id, &printed, Some(&listening), &tree);
+        let urls: Vec<(&str, Option<u32>)> = services.iter().map(|service| (service.url.as_str(), service.pid)).collect();
+        assert_eq!(urls, vec![("http://localhost:3000", Some(10)), ("http://localhost:5173/", None)]);
+        assert_eq!(chip(services.iter()).as_deref(), Some(" ⇄ :3000 :5173 "));
+
+        // Without a socket list, printed URLs are all there is.
+        assert_eq!(pane_services(pane_id, &printed, None, &tree).len(), 2);
+    }
+}
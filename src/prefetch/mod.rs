@@ -0,0 +1,365 @@
+//! Completion Prefetch
+//!
+//! Some completions need lists that are slow to produce: `brew formulae`
+//! takes seconds, and the crates.io index is behind a network round trip.
+//! This module fetches them ahead of time while the user is idle, one at a
+//! time and no more often than the configured interval, and keeps them on
+//! disk so the first completion of a session is already fast. A list is
+//! fetched again once it is older than its source's maximum age.
+
+use crate::config::PrefetchConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+const CRATES_API_URL: &str = "https://crates.io/api/v1/crates";
+/// Pages of the most downloaded crates fetched, 100 crates each.
+const CRATES_PAGES: u32 = 5;
+/// crates.io asks crawlers for at most one request per second.
+const CRATES_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Error, Debug)]
+pub enum PrefetchError {
+    #[error("Failed to run {0}: {1}")]
+    Spawn(&'static str, std::io::Error),
+    #[error("{0} failed: {1}")]
+    Command(&'static str, String),
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Cache error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid cache file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+/// A list completions can offer before it has been asked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefetchSource {
+    /// Every Homebrew formula, for `brew install` and friends.
+    BrewFormulae,
+    /// The packages installed with pip.
+    PipPackages,
+    /// The most downloaded crates on crates.io.
+    Crates,
+}
+
+impl PrefetchSource {
+    pub const ALL: [PrefetchSource; 3] = [PrefetchSource::BrewFormulae, PrefetchSource::PipPackages, PrefetchSource::Crates];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrefetchSource::BrewFormulae => "brew_formulae",
+            PrefetchSource::PipPackages => "pip_packages",
+            PrefetchSource::Crates => "crates",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.name() == name)
+    }
+
+    /// Seconds before a cached list is fetched again. Installed pip packages
+    /// change with every install; the others drift slowly.
+    pub fn max_age_secs(&self) -> i64 {
+        match self {
+            PrefetchSource::BrewFormulae => 7 * DAY_SECS,
+            PrefetchSource::PipPackages => DAY_SECS,
+            PrefetchSource::Crates => 7 * DAY_SECS,
+        }
+    }
+
+    /// The list, fetched now. Runs for seconds; call it off the UI thread.
+    /// Cancelling `cancel` abandons a crates.io fetch between requests.
+    pub fn fetch(&self, cancel: &CancellationToken) -> Result<Vec<String>, PrefetchError> {
+        match self {
+            PrefetchSource::BrewFormulae => Ok(parse_names(&run("brew", &["formulae"])?)),
+            PrefetchSource::PipPackages => Ok(parse_pip_freeze(&run("python3", &["-m", "pip", "list", "--format=freeze"])?)),
+            PrefetchSource::Crates => {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                runtime.block_on(async {
+                    tokio::select! {
+                        names = fetch_crates() => names,
+                        _ = cancel.cancelled() => Err(PrefetchError::Cancelled),
+                    }
+                })
+            }
+        }
+    }
+}
+
+fn run(program: &'static str, args: &[&str]) -> Result<String, PrefetchError> {
+    let output = Command::new(program).args(args).output().map_err(|e| PrefetchError::Spawn(program, e))?;
+    if !output.status.success() {
+        return Err(PrefetchError::Command(program, String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_names(output: &str) -> Vec<String> {
+    let mut names: Vec<String> = output.lines().map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// `name==version` lines; editable installs appear as `-e <url>` and are skipped.
+fn parse_pip_freeze(output: &str) -> Vec<String> {
+    let names = output
+        .lines()
+        .filter(|line| !line.starts_with('-') && !line.starts_with('#'))
+        .filter_map(|line| line.split(['=', '@', ' ']).next())
+        .collect::<Vec<_>>()
+        .join("\n");
+    parse_names(&names)
+}
+
+#[derive(Deserialize)]
+struct CratesPage {
+    crates: Vec<CrateEntry>,
+}
+
+#[derive(Deserialize)]
+struct CrateEntry {
+    name: String,
+}
+
+/// Crates keep their download order, so the popular ones are offered first.
+async fn fetch_crates() -> Result<Vec<String>, PrefetchError> {
+    let client = reqwest::Client::builder().user_agent("warpish-terminal").timeout(Duration::from_secs(30)).build()?;
+    let mut names = Vec::new();
+    for page in 1..=CRATES_PAGES {
+        if page > 1 {
+            tokio::time::sleep(CRATES_REQUEST_INTERVAL).await;
+        }
+        let page: CratesPage = client
+            .get(CRATES_API_URL)
+            .query(&[("sort", "downloads"), ("per_page", "100"), ("page", &page.to_string())])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        names.extend(page.crates.into_iter().map(|entry| entry.name));
+    }
+    Ok(names)
+}
+
+/// A fetched list as stored in the cache directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedList {
+    pub source: PrefetchSource,
+    pub fetched_at: i64,
+    pub items: Vec<String>,
+}
+
+impl CachedList {
+    pub fn is_stale(&self, now: i64) -> bool {
+        now - self.fetched_at > self.source.max_age_secs()
+    }
+
+    fn path(cache_dir: &Path, source: PrefetchSource) -> PathBuf {
+        cache_dir.join(format!("{}.json", source.name()))
+    }
+
+    pub fn load(cache_dir: &Path, source: PrefetchSource) -> Result<Option<Self>, PrefetchError> {
+        match std::fs::read_to_string(Self::path(cache_dir, source)) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> Result<(), PrefetchError> {
+        std::fs::create_dir_all(cache_dir)?;
+        std::fs::write(Self::path(cache_dir, self.source), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+/// Where lists are cached: `~/.cache/warpish/completions` on Linux.
+pub fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("warpish").join("completions"))
+}
+
+/// Keeps the prefetched lists fresh, fetching at most one at a time while
+/// the user is idle.
+pub struct Prefetcher {
+    sources: Vec<PrefetchSource>,
+    idle_after: Duration,
+    min_interval: Duration,
+    cache_dir: Option<PathBuf>,
+    lists: HashMap<PrefetchSource, CachedList>,
+    in_flight: Option<PrefetchSource>,
+    last_started: Option<Instant>,
+    /// Sources whose fetch failed, e.g. brew on Linux. Tried again next session.
+    failed: HashSet<PrefetchSource>,
+    result_tx: Sender<(PrefetchSource, Result<Vec<String>, PrefetchError>)>,
+    results: Receiver<(PrefetchSource, Result<Vec<String>, PrefetchError>)>,
+    cancel: CancellationToken,
+}
+
+impl Prefetcher {
+    /// Loads the cached lists, stale or not, so completions can use them
+    /// straight away.
+    pub fn new(config: &PrefetchConfig, cache_dir: Option<PathBuf>) -> Self {
+        let sources = if config.enabled {
+            config
+                .sources
+                .iter()
+                .filter_map(|name| {
+                    let source = PrefetchSource::from_name(name);
+                    if source.is_none() {
+                        log::warn!("Unknown prefetch source '{}'", name);
+                    }
+                    source
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut lists = HashMap::new();
+        if let Some(cache_dir) = &cache_dir {
+            for &source in &sources {
+                match CachedList::load(cache_dir, source) {
+                    Ok(Some(list)) => {
+                        lists.insert(source, list);
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Failed to load cached {}: {}", source.name(), e),
+                }
+            }
+        }
+        let (result_tx, results) = channel();
+        Self {
+            sources,
+            idle_after: Duration::from_secs(config.idle_seconds),
+            min_interval: Duration::from_secs(config.min_interval_seconds),
+            cache_dir,
+            lists,
+            in_flight: None,
+            last_started: None,
+            failed: HashSet::new(),
+            result_tx,
+            results,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Stops fetching once `cancel` is cancelled, e.g. on shutdown.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The sources with a list, cached or fetched.
+    pub fn available(&self) -> Vec<PrefetchSource> {
+        self.lists.keys().copied().collect()
+    }
+
+    pub fn list(&self, source: PrefetchSource) -> Option<&[String]> {
+        self.lists.get(&source).map(|list| list.items.as_slice())
+    }
+
+    /// The next source to fetch: one never fetched before any stale one.
+    fn next_due(&self, now: i64) -> Option<PrefetchSource> {
+        let due = |source: &&PrefetchSource| !self.failed.contains(source) && self.lists.get(source).is_none_or(|list| list.is_stale(now));
+        let mut due: Vec<PrefetchSource> = self.sources.iter().filter(due).copied().collect();
+        due.sort_by_key(|source| self.lists.get(source).map(|list| list.fetched_at));
+        due.into_iter().next()
+    }
+
+    /// Starts fetching the next stale list if the user has been idle long
+    /// enough and the last fetch started long enough ago.
+    pub fn tick(&mut self, idle_for: Duration, now: Instant, unix_now: i64) {
+        if self.in_flight.is_some() || idle_for < self.idle_after || self.cancel.is_cancelled() {
+            return;
+        }
+        if self.last_started.is_some_and(|started| now.duration_since(started) < self.min_interval) {
+            return;
+        }
+        let Some(source) = self.next_due(unix_now) else {
+            return;
+        };
+        self.in_flight = Some(source);
+        self.last_started = Some(now);
+        let result_tx = self.result_tx.clone();
+        let cancel = self.cancel.clone();
+        thread::spawn(move || {
+            result_tx.send((source, source.fetch(&cancel))).ok();
+        });
+    }
+
+    /// Stores and caches finished fetches. Returns the sources whose list changed.
+    pub fn poll(&mut self, unix_now: i64) -> Vec<PrefetchSource> {
+        let mut updated = Vec::new();
+        while let Ok((source, result)) = self.results.try_recv() {
+            self.in_flight = None;
+            let items = match result {
+                Ok(items) => items,
+                Err(e) => {
+                    log::info!("Not prefetching {}: {}", source.name(), e);
+                    self.failed.insert(source);
+                    continue;
+                }
+            };
+            let list = CachedList { source, fetched_at: unix_now, items };
+            if let Some(cache_dir) = &self.cache_dir {
+                if let Err(e) = list.save(cache_dir) {
+                    log::warn!("Failed to cache {}: {}", source.name(), e);
+                }
+            }
+            self.lists.insert(source, list);
+            updated.push(source);
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_command_output() {
+        assert_eq!(parse_names("wget\n\ngit\nffmpeg\n"), vec!["ffmpeg", "git", "wget"]);
+        let freeze = "requests==2.31.0\n-e git+https://example.com/tool.git#egg=tool\nmypkg @ file:///src/mypkg\nurllib3==2.0.7\n";
+        assert_eq!(parse_pip_freeze(freeze), vec!["mypkg", "requests", "urllib3"]);
+    }
+
+    #[test]
+    fn test_fetches_missing_then_stale_lists_from_cache() {
+        let cache_dir = std::env::temp_dir().join(format!("warpish-prefetch-test-{}", std::process::id()));
+        let now = 10 * DAY_SECS;
+        CachedList { source: PrefetchSource::Crates, fetched_at: now - 8 * DAY_SECS, items: vec!["serde".to_string()] }
+            .save(&cache_dir)
+            .unwrap();
+        CachedList { source: PrefetchSource::BrewFormulae, fetched_at: now, items: vec!["wget".to_string()] }
+            .save(&cache_dir)
+            .unwrap();
+
+        let mut prefetcher = Prefetcher::new(&PrefetchConfig::default(), Some(cache_dir.clone()));
+        assert_eq!(prefetcher.list(PrefetchSource::Crates), Some(&["serde".to_string()][..]));
+        assert_eq!(prefetcher.next_due(now), Some(PrefetchSource::PipPackages));
+        prefetcher.failed.insert(PrefetchSource::PipPackages);
+        assert_eq!(prefetcher.next_due(now), Some(PrefetchSource::Crates));
+        prefetcher.failed.insert(PrefetchSource::Crates);
+        assert_eq!(prefetcher.next_due(now), None);
+
+        // Busy users are never interrupted by a fetch.
+        prefetcher.failed.clear();
+        prefetcher.tick(Duration::from_secs(1), Instant::now(), now);
+        assert!(prefetcher.in_flight.is_none());
+        std::fs::remove_dir_all(&cache_dir).ok();
+    }
+}
@@ -0,0 +1,84 @@
+//! Launch Profiles
+//!
+//! A profile is a named way to open a pane — "prod ssh" runs `ssh prod-db-1`
+//! in a red theme, "docker shell" starts `docker exec` in a project
+//! directory. Profiles live in the `profiles` section of the config and are
+//! listed in the new pane menu and the command palette. Each pane remembers
+//! the profile it was opened with, so the theme can follow focus and the
+//! saved session can reopen it the same way.
+
+use crate::config::ProfileConfig;
+use portable_pty::CommandBuilder;
+use std::path::{Path, PathBuf};
+
+pub fn find<'a>(profiles: &'a [ProfileConfig], name: &str) -> Option<&'a ProfileConfig> {
+    profiles.iter().find(|profile| profile.name == name)
+}
+
+/// The name as shown in menus, after the icon if there is one.
+pub fn label(profile: &ProfileConfig) -> String {
+    match &profile.icon {
+        Some(icon) => format!("{} {}", icon, profile.name),
+        None => profile.name.clone(),
+    }
+}
+
+/// What the profile runs, for the palette.
+pub fn description(profile: &ProfileConfig, default_shell: &str) -> String {
+    let shell = profile.shell.as_deref().unwrap_or(default_shell);
+    match &profile.startup_command {
+        Some(command) => format!("{}, then {}", shell, command),
+        None => shell.to_string(),
+    }
+}
+
+/// The profile's directory, with a leading `~` for the home directory.
+pub fn expand_cwd(cwd: &str, home: Option<&Path>) -> PathBuf {
+    match (cwd.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(cwd),
+    }
+}
+
+/// The command that starts the profile's shell. The startup command is
+/// typed into the shell afterwards, so it runs with the user's rc files
+/// loaded and leaves the shell open when it exits.
+pub fn command(profile: &ProfileConfig, default_shell: &str) -> CommandBuilder {
+    let mut cmd = CommandBuilder::new(profile.shell.as_deref().unwrap_or(default_shell));
+    cmd.env("TERM_PROGRAM", "WarpishTerminal");
+    for (name, value) in &profile.env {
+        cmd.env(name, value);
+    }
+    let cwd = match &profile.cwd {
+        Some(cwd) => expand_cwd(cwd, dirs::home_dir().as_deref()),
+        None => std::env::current_dir().unwrap_or_default(),
+    };
+    cmd.cwd(cwd);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_are_labelled_and_expanded() {
+        let profile = ProfileConfig {
+            name: "prod ssh".to_string(),
+            startup_command: Some("ssh prod-db-1".to_string()),
+            icon: Some("🔥".to_string()),
+            ..ProfileConfig::default()
+        };
+        let profiles = vec![ProfileConfig { name: "docker shell".to_string(), ..ProfileConfig::default() }, profile];
+        let profile = find(&profiles, "prod ssh").unwrap();
+        assert_eq!(label(profile), "🔥 prod ssh");
+        assert_eq!(description(profile, "zsh"), "zsh, then ssh prod-db-1");
+        assert_eq!(label(&profiles[0]), "docker shell");
+
+        let home = Path::new("/home/me");
+        assert_eq!(expand_cwd("~/src/app", Some(home)), PathBuf::from("/home/me/src/app"));
+        assert_eq!(expand_cwd("~", Some(home)), PathBuf::from("/home/me"));
+        assert_eq!(expand_cwd("~other/src", Some(home)), PathBuf::from("~other/src"));
+        assert_eq!(expand_cwd("/srv/app", None), PathBuf::from("/srv/app"));
+    }
+}
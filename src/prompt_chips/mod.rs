@@ -0,0 +1,242 @@
+//! Custom Prompt Chips
+//!
+//! This module fills the Warpish prompt chips users define themselves, each
+//! backed by a shell command or a Lua chunk. Chips run on background threads
+//! with a timeout and their last value is cached, so the prompt is drawn with
+//! whatever is known and a slow script never holds up a frame. A chip is
+//! refreshed when its interval has passed or the active pane moves to
+//! another directory.
+
+use crate::config::{ChipSource, CustomChipConfig, WarpishPromptConfig};
+use mlua::HookTriggers;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often a running command is checked for having finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Error, Debug)]
+pub enum ChipError {
+    #[error("Failed to run chip command: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Chip timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Chip command exited with status {0}")]
+    Failed(i32),
+    #[error("Chip script failed: {0}")]
+    Lua(#[from] mlua::Error),
+}
+
+/// A chip's cached value, ready to draw.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipValue {
+    pub text: String,
+    pub color: Option<String>,
+}
+
+/// Runs `source` in `dir`, giving up after `timeout`, and returns the first
+/// line of what it produced.
+pub fn run_chip(source: &ChipSource, dir: Option<&Path>, timeout: Duration) -> Result<String, ChipError> {
+    let output = match source {
+        ChipSource::Command { command } => run_command(command, dir, timeout)?,
+        ChipSource::Lua { script } => run_lua(script, dir, timeout)?,
+    };
+    Ok(output.lines().next().unwrap_or_default().trim().to_string())
+}
+
+fn run_command(command: &str, dir: Option<&Path>, timeout: Duration) -> Result<String, ChipError> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let mut cmd = Command::new(shell);
+    cmd.args([flag, command]).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::null());
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let mut child = cmd.spawn()?;
+    // Read on another thread so a chatty command cannot fill the pipe and stall.
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(ChipError::Timeout(timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if !status.success() {
+        return Err(ChipError::Failed(status.code().unwrap_or(-1)));
+    }
+    Ok(reader.join().unwrap_or_else(|_| Ok(String::new()))?)
+}
+
+fn run_lua(script: &str, dir: Option<&Path>, timeout: Duration) -> Result<String, ChipError> {
+    let lua = crate::scripting::sandboxed()?;
+    let deadline = Instant::now() + timeout;
+    lua.set_hook(HookTriggers::new().every_nth_instruction(1000), move |_, _| {
+        if Instant::now() >= deadline {
+            return Err(mlua::Error::runtime("timed out"));
+        }
+        Ok(())
+    });
+    lua.globals().set("cwd", dir.map(|dir| dir.display().to_string()))?;
+    match lua.load(script).eval::<Option<String>>() {
+        Err(_) if Instant::now() >= deadline => Err(ChipError::Timeout(timeout)),
+        result => Ok(result?.unwrap_or_default()),
+    }
+}
+
+struct ChipEntry {
+    config: CustomChipConfig,
+    value: Option<String>,
+    /// The directory the cached value was produced in.
+    dir: Option<PathBuf>,
+    refreshed_at: Option<Instant>,
+    in_flight: bool,
+}
+
+/// Caches the custom chips shown in the prompt, refreshing them on
+/// background threads.
+pub struct PromptChips {
+    chips: Vec<ChipEntry>,
+    result_tx: Sender<(usize, Option<PathBuf>, Result<String, ChipError>)>,
+    results: Receiver<(usize, Option<PathBuf>, Result<String, ChipError>)>,
+}
+
+impl PromptChips {
+    /// Keeps only the custom chips the prompt lists.
+    pub fn new(config: &WarpishPromptConfig) -> Self {
+        let chips = config
+            .custom_chips
+            .iter()
+            .filter(|chip| config.chips.contains(&chip.name))
+            .map(|chip| ChipEntry { config: chip.clone(), value: None, dir: None, refreshed_at: None, in_flight: false })
+            .collect();
+        let (result_tx, results) = channel();
+        Self { chips, result_tx, results }
+    }
+
+    /// Starts a refresh of every chip whose interval has passed, or whose
+    /// value was produced in a directory other than `dir`.
+    pub fn refresh(&mut self, dir: Option<&Path>, now: Instant) {
+        for (idx, chip) in self.chips.iter_mut().enumerate() {
+            let interval = Duration::from_secs(chip.config.refresh_seconds);
+            let fresh = chip.refreshed_at.is_some_and(|at| now.saturating_duration_since(at) < interval);
+            if chip.in_flight || (fresh && chip.dir.as_deref() == dir) {
+                continue;
+            }
+            chip.in_flight = true;
+            chip.refreshed_at = Some(now);
+            let source = chip.config.source.clone();
+            let timeout = Duration::from_millis(chip.config.timeout_ms);
+            let dir = dir.map(Path::to_path_buf);
+            let result_tx = self.result_tx.clone();
+            thread::spawn(move || {
+                let result = run_chip(&source, dir.as_deref(), timeout);
+                result_tx.send((idx, dir, result)).ok();
+            });
+        }
+    }
+
+    /// Takes finished refreshes. A chip that failed keeps its last value.
+    /// Returns whether any chip changed.
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok((idx, dir, result)) = self.results.try_recv() {
+            let Some(chip) = self.chips.get_mut(idx) else {
+                continue;
+            };
+            chip.in_flight = false;
+            chip.dir = dir;
+            match result {
+                Ok(text) => {
+                    changed |= chip.value.as_ref() != Some(&text);
+                    chip.value = Some(text);
+                }
+                Err(e) => log::debug!("Prompt chip '{}' failed: {}", chip.config.name, e),
+            }
+        }
+        changed
+    }
+
+    /// The chip named `name` with its icon, or `None` before its first
+    /// value arrives or while it prints nothing.
+    pub fn get(&self, name: &str) -> Option<ChipValue> {
+        let chip = self.chips.iter().find(|chip| chip.config.name == name)?;
+        let text = chip.value.as_deref().filter(|text| !text.is_empty())?;
+        let text = match &chip.config.icon {
+            Some(icon) => format!("{} {}", icon, text),
+            None => text.to_string(),
+        };
+        Some(ChipValue { text, color: chip.config.color.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip(name: &str, source: ChipSource) -> CustomChipConfig {
+        CustomChipConfig { name: name.to_string(), source, icon: None, color: None, refresh_seconds: 10, timeout_ms: 200 }
+    }
+
+    #[test]
+    fn test_run_chip_sources() {
+        let timeout = Duration::from_millis(500);
+        let command = ChipSource::Command { command: "echo ' v1.2 '; echo extra".to_string() };
+        assert_eq!(run_chip(&command, None, timeout).unwrap(), "v1.2");
+        let lua = ChipSource::Lua { script: "return 'in ' .. cwd".to_string() };
+        assert_eq!(run_chip(&lua, Some(Path::new("/tmp")), timeout).unwrap(), "in /tmp");
+        let shell_out = ChipSource::Lua { script: "return os.execute('true')".to_string() };
+        assert!(matches!(run_chip(&shell_out, None, timeout), Err(ChipError::Lua(_))));
+
+        let slow = ChipSource::Command { command: "sleep 5".to_string() };
+        assert!(matches!(run_chip(&slow, None, Duration::from_millis(50)), Err(ChipError::Timeout(_))));
+        let spin = ChipSource::Lua { script: "while true do end".to_string() };
+        assert!(matches!(run_chip(&spin, None, Duration::from_millis(50)), Err(ChipError::Timeout(_))));
+    }
+
+    #[test]
+    fn test_chips_are_cached_and_keep_last_value() {
+        let mut config = WarpishPromptConfig {
+            chips: vec!["node".to_string()],
+            same_line: true,
+            custom_chips: vec![
+                chip("node", ChipSource::Lua { script: "return cwd == '/a' and 'v20' or error('no node')".to_string() }),
+                chip("unlisted", ChipSource::Command { command: "echo hidden".to_string() }),
+            ],
+        };
+        config.custom_chips[0].icon = Some("⬢".to_string());
+        let mut chips = PromptChips::new(&config);
+        let wait = |chips: &mut PromptChips| {
+            while chips.chips.iter().any(|chip| chip.in_flight) {
+                chips.poll();
+                thread::sleep(POLL_INTERVAL);
+            }
+        };
+        let now = Instant::now();
+        assert_eq!(chips.get("node"), None);
+
+        chips.refresh(Some(Path::new("/a")), now);
+        wait(&mut chips);
+        assert_eq!(chips.get("node").unwrap().text, "⬢ v20");
+        assert_eq!(chips.get("unlisted"), None);
+
+        // Moving directory refreshes early; the failure keeps the last value.
+        chips.refresh(Some(Path::new("/b")), now + Duration::from_secs(1));
+        assert!(chips.chips[0].in_flight);
+        wait(&mut chips);
+        assert_eq!(chips.get("node").unwrap().text, "⬢ v20");
+    }
+}
@@ -1 +0,0 @@
-pub mod vte_handler;
\ No newline at end of file
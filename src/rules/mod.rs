@@ -38,13 +38,19 @@ impl RuleSet {
         self.rules.get(name)
     }
 
+    /// Returns the most restrictive action among the rules matching `target`.
     pub fn evaluate(&self, target: &str) -> Action {
+        let mut result = Action::Allow;
         for rule in self.rules.values() {
             if target.contains(&rule.target) {
-                return rule.action.clone();
+                match rule.action {
+                    Action::Deny => return Action::Deny,
+                    Action::Warn => result = Action::Warn,
+                    Action::Allow => {}
+                }
             }
         }
-        Action::Allow
+        result
     }
 }
 
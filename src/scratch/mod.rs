@@ -0,0 +1,147 @@
+//! Scratch Panes
+//!
+//! This module sets up throwaway shell sessions for trying out snippets. A
+//! scratch session runs in its own temporary directory with a private
+//! `HISTFILE` and a reduced environment, so nothing typed there lands in the
+//! user's shell history or working tree. The directory is deleted when the
+//! session is dropped, which happens when its pane is closed.
+
+use crate::app::pane::Block;
+use crate::config::ScratchConfig;
+use crate::drive::Notebook;
+use portable_pty::CommandBuilder;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ScratchError {
+    #[error("Failed to create scratch directory: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Commands that only look around, and so are not worth keeping.
+const NAVIGATION_COMMANDS: &[&str] = &["cd", "ls", "ll", "pwd", "clear", "exit", "history"];
+
+/// A temporary working directory and environment for a scratch pane's shell.
+#[derive(Debug)]
+pub struct ScratchSession {
+    dir: PathBuf,
+    env: Vec<(String, String)>,
+}
+
+impl ScratchSession {
+    /// Creates a session under the system temporary directory, inheriting
+    /// the configured variables from this process's environment.
+    pub fn create(config: &ScratchConfig) -> Result<Self, ScratchError> {
+        Self::create_in(&std::env::temp_dir(), config, std::env::vars())
+    }
+
+    pub fn create_in(
+        base: &Path,
+        config: &ScratchConfig,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ScratchError> {
+        let dir = base.join(format!("warpish-scratch-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+
+        let mut env: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(name, _)| config.inherited_env.contains(name))
+            .collect();
+        env.sort();
+        let dir_str = dir.display().to_string();
+        env.push(("HISTFILE".to_string(), dir.join(".history").display().to_string()));
+        env.push(("TMPDIR".to_string(), dir_str.clone()));
+        env.push(("WARPISH_SCRATCH".to_string(), dir_str));
+        Ok(Self { dir, env })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The variables the scratch shell starts with; nothing else is inherited.
+    pub fn env(&self) -> &[(String, String)] {
+        &self.env
+    }
+
+    /// Builds the shell command for the scratch pane.
+    pub fn command(&self, shell: &str) -> CommandBuilder {
+        let mut cmd = CommandBuilder::new(shell);
+        cmd.env_clear();
+        for (name, value) in &self.env {
+            cmd.env(name, value);
+        }
+        cmd.env("TERM_PROGRAM", "WarpishTerminal");
+        cmd.cwd(&self.dir);
+        cmd
+    }
+}
+
+impl Drop for ScratchSession {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            log::warn!("Failed to remove scratch directory {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+/// Whether a block is worth offering to save when its scratch pane closes:
+/// it ran something other than a navigation command and printed output.
+pub fn is_interesting(block: &Block) -> bool {
+    let program = block.command.split_whitespace().next().unwrap_or("");
+    !program.is_empty() && !NAVIGATION_COMMANDS.contains(&program) && !block.output.trim().is_empty()
+}
+
+/// Converts saved scratch blocks into a single Drive notebook.
+pub fn blocks_to_notebook(blocks: &[&Block], name: String) -> Notebook {
+    let mut content = format!("# {}\n", name);
+    for block in blocks {
+        content.push_str(&format!("\n```sh\n{}\n```\n", block.command));
+        let output = block.output.trim_end();
+        if !output.is_empty() {
+            content.push_str(&format!("\n```\n{}\n```\n", output));
+        }
+    }
+    Notebook { name, content }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(command: &str, output: &str) -> Block {
+        Block::new(command.to_string(), output.to_string())
+    }
+
+    #[test]
+    fn test_session_isolates_env_and_cleans_up() {
+        let base = tempfile::tempdir().unwrap();
+        let vars = vec![
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("HISTFILE".to_string(), "/home/me/.bash_history".to_string()),
+            ("AWS_SECRET_ACCESS_KEY".to_string(), "secret".to_string()),
+        ];
+        let session = ScratchSession::create_in(base.path(), &ScratchConfig::default(), vars).unwrap();
+        let dir = session.dir().to_path_buf();
+        assert!(dir.is_dir());
+
+        let names: Vec<&str> = session.env().iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["PATH", "HISTFILE", "TMPDIR", "WARPISH_SCRATCH"]);
+        assert_eq!(session.env()[1].1, dir.join(".history").display().to_string());
+
+        drop(session);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_interesting_blocks_become_notebook() {
+        let blocks = [block("ls -la", "a b"), block("jq .name package.json", "\"demo\"\n"), block("true", "")];
+        let interesting: Vec<&Block> = blocks.iter().filter(|block| is_interesting(block)).collect();
+        assert_eq!(interesting.len(), 1);
+
+        let notebook = blocks_to_notebook(&interesting, "scratch".to_string());
+        assert_eq!(notebook.content, "# scratch\n\n```sh\njq .name package.json\n```\n\n```\n\"demo\"\n```\n");
+    }
+}
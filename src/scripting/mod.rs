@@ -1,9 +1,26 @@
 use mlua::prelude::*;
+use mlua::{LuaOptions, StdLib};
+
+/// Creates a Lua state for user scripts: without the `io`, `os`, `package`
+/// and `debug` libraries or the base functions that load files. Scripts
+/// write files, reach the network and use the clipboard only through the
+/// `permissions` API, which asks the user first.
+pub fn sandboxed() -> LuaResult<Lua> {
+    let lua = Lua::new_with(
+        StdLib::COROUTINE | StdLib::TABLE | StdLib::STRING | StdLib::UTF8 | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile"] {
+        globals.set(name, LuaNil)?;
+    }
+    Ok(lua)
+}
 
 /// Initializes the Lua scripting environment.
 pub fn initialize_lua() -> LuaResult<Lua> {
     println!("[Scripting] Initializing Lua 5.4 environment...");
-    let lua = Lua::new();
+    let lua = sandboxed()?;
 
     // Expose some application functions to Lua scripts
     let globals = lua.globals();
@@ -0,0 +1,486 @@
+//! Unified Search
+//!
+//! This module backs the single search overlay. The first character of the
+//! query picks what is searched: `>` for actions and workflows, `@` for
+//! files, `#` for blocks in the open panes and `:` for settings; anything
+//! else searches command history. Each domain is served by the provider it
+//! already had: the history table, Drive workflows, the file path completer,
+//! pane blocks and the config's value sources.
+//!
+//! Under `>`, workflows are grouped into categories by their tags. An empty
+//! query lists pinned favorites, then recently used items, then the
+//! categories; `>git: ` browses one category.
+//!
+//! Results carry the positions the query matched in their titles for
+//! highlighting. History can hold tens of thousands of commands, so the
+//! overlay matches it off the UI thread with `search_history`.
+
+use crate::app::pane::Block;
+use crate::app::state::PaletteItem;
+use crate::github::GitHubItemKind;
+use crate::completions::{Completer, FilePathCompleter};
+use crate::config::Config;
+use crate::fuzzy_match::{fuzzy_match, match_all, spawn_match_all, FuzzyMatch, PendingMatches};
+use std::cmp::Reverse;
+
+/// Most results listed for a query.
+const MAX_RESULTS: usize = 50;
+
+/// Recently used palette items listed when the palette is opened empty.
+const MAX_RECENT: usize = 5;
+/// Score added to pinned palette items, enough to rank them above any match.
+const PINNED_BONUS: i64 = 1000;
+/// Score added to the most recently used palette item, less for older ones.
+const RECENT_BONUS: i64 = 100;
+
+/// Built-in actions offered under `>`, as (name, description, action).
+pub const APP_ACTIONS: &[(&str, &str, &str)] = &[
+    ("Open Scratch Pane", "A throwaway shell in a temporary directory", "scratch.open"),
+    ("Close Pane", "Close the active pane", "pane.close"),
+    ("Toggle Privacy Zone", "Lock this pane while the terminal is idle", "pane.privacy"),
+    ("Directory History", "Jump to a recently visited directory", "dirhistory.open"),
+    ("Review Changes", "List the files changed in this repository", "git.review"),
+    ("Generate Commit Message", "Have the agent write a commit message for the staged changes", "git.commit_message"),
+    ("Select Blocks", "Select several blocks to delete, tag, export, re-run or send to the agent", "blocks.select"),
+    ("Restore Deleted Blocks", "Undo the last batch delete of blocks", "blocks.undo_delete"),
+    ("Export Blocks to PDF", "Print every block in this pane to a PDF file", "blocks.pdf"),
+    ("Export Blocks to Markdown", "Save every block in this pane as a Markdown file", "blocks.markdown"),
+    ("Export Blocks to HTML", "Save every block in this pane as a standalone HTML page", "blocks.html"),
+    ("Upload Blocks to Gist", "Upload every block in this pane as a GitHub Gist and copy the link", "blocks.gist"),
+    ("Watch and Re-run", "Re-run the last command whenever matching files change", "watch.start"),
+    ("Stop Watching", "Stop re-running this pane's command on file changes", "watch.stop"),
+    ("Jobs", "Show the processes running in panes, with CPU and memory", "jobs.panel"),
+    ("Run in Background", "Run the command line as a detached job and keep the pane free", "jobs.run_detached"),
+    ("Local Services", "Open or stop the dev servers and forwarded ports the panes are serving", "ports.panel"),
+    ("Docker", "List containers and images, follow container logs or open a shell in a container", "docker.panel"),
+    ("GraphQL Explorer", "Write GraphQL queries with completions from the API's schema and save them to Drive", "graphql.explorer"),
+    ("Browse Files", "Browse, preview, rename, copy and delete files here, over SFTP or inside archives", "files.browse"),
+    ("Manage Abbreviations", "Add, edit, import and export abbreviations that expand on Space", "abbreviations.manage"),
+    ("Explain Command", "Break down the command in the input part by part, from man pages and --help", "input.explain"),
+    ("Rename Symbol", "Rename a variable, function or type everywhere in a Rust or Python file", "refactor.rename"),
+    ("Extract Function", "Move lines of a Rust or Python function into a new function", "refactor.extract"),
+    ("This Was a Command", "Run the last input sent to the agent as a command, and remember it", "nl.run_as_command"),
+    ("Agent Evaluation", "Compare how often each model's commands and patches worked out", "agent.evaluation"),
+    ("Agent Usage", "Tokens and estimated cost of agent requests by day, week and conversation", "agent.usage"),
+    ("Daily Activity Report", "Top commands, failures and busiest projects over the last day", "stats.daily"),
+    ("Weekly Activity Report", "Top commands, failures and busiest projects over the last week", "stats.weekly"),
+    ("Start Quiz", "Drill the quiz questions that are due, missed ones first", "quiz.start"),
+    ("Quiz Me on My History", "Have the agent write questions about the tools you use most", "quiz.generate"),
+    ("Import Quiz Questions", "Add a YAML or JSON question set to the quiz bank", "quiz.import"),
+    ("Export Quiz Questions", "Save the quiz bank as a YAML or JSON question set", "quiz.export"),
+    ("Export Notebook to PDF", "Print a Drive notebook to a PDF file", "notebook.pdf"),
+    ("Jump to Failed Command", "Focus the pane where a command last failed", "pane.jump_failure"),
+    ("Toggle Failure Focus", "Stop or resume jumping to this pane when its commands fail", "pane.mute_failures"),
+    ("Scrub Pane Timeline", "Step back through the active pane to see its screen as it was", "pane.timeline"),
+    ("Set Pane Encoding", "Talk to this pane's shell in Latin-1, Shift_JIS or UTF-8", "pane.encoding"),
+    ("New Pane", "Open a pane with the default shell or a launch profile", "pane.new"),
+    ("Open WSL Pane", "Open a pane into a WSL distro, in the current directory", "pane.wsl"),
+    ("Share Pane Live", "Let invited collaborators follow and type into the active pane", "share.live"),
+    ("Stop Live Sharing", "End the live shared session", "share.live_stop"),
+    ("Live Share Activity", "See who is in the shared session and what they did", "share.activity"),
+    ("Review Permissions", "See and revoke plugin and agent permissions", "permissions.audit"),
+    ("Agent Conversations", "Search past agent conversations", "agent.manage"),
+    ("Ask AI", "Open the AI prompt", "ai.prompt"),
+    ("Ask AI about Selection", "Ask the agent about the text selected in the command line", "ai.ask_selection"),
+    ("Local Models", "Pull, remove and assign roles to Ollama models", "ai.ollama"),
+    ("Pair with Agent", "Open an agent beside this pane that suggests next steps as you work", "pair.open"),
+    ("Toggle Pair Suggestions", "Pause or resume suggestions from every pair agent", "pair.toggle"),
+    ("Run Doctor", "Check the shell, fonts, GPU, config, database and AI provider", "app.doctor"),
+    ("Toggle Performance HUD", "Show FPS, frame time by phase, PTY throughput and memory", "perf.hud"),
+    ("Capture Flamegraph", "Profile the app for a few seconds and save a flamegraph", "perf.profile"),
+    ("Search Web for Selection", "Look the selected text up with a web search provider", "web.search"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchDomain {
+    History,
+    Actions,
+    Files,
+    Blocks,
+    Settings,
+}
+
+impl SearchDomain {
+    pub const ALL: [SearchDomain; 5] = [
+        SearchDomain::History,
+        SearchDomain::Actions,
+        SearchDomain::Files,
+        SearchDomain::Blocks,
+        SearchDomain::Settings,
+    ];
+
+    /// The character that selects the domain; history needs none.
+    pub fn prefix(&self) -> Option<char> {
+        match self {
+            SearchDomain::History => None,
+            SearchDomain::Actions => Some('>'),
+            SearchDomain::Files => Some('@'),
+            SearchDomain::Blocks => Some('#'),
+            SearchDomain::Settings => Some(':'),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchDomain::History => "history",
+            SearchDomain::Actions => "actions",
+            SearchDomain::Files => "files",
+            SearchDomain::Blocks => "blocks",
+            SearchDomain::Settings => "settings",
+        }
+    }
+
+    /// Splits a query into its domain and the text to search for.
+    pub fn parse(query: &str) -> (Self, &str) {
+        let mut chars = query.chars();
+        let domain = chars.next().and_then(|c| Self::ALL.into_iter().find(|domain| domain.prefix() == Some(c)));
+        match domain {
+            Some(domain) => (domain, chars.as_str().trim_start()),
+            None => (SearchDomain::History, query),
+        }
+    }
+
+    /// The domain after this one, for cycling with Tab.
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|domain| domain == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// What choosing a result does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTarget {
+    /// Puts a command from history on the command line.
+    Command(String),
+    /// Runs an action or inserts a workflow.
+    Palette(PaletteItem),
+    /// Inserts a path at the cursor.
+    File(String),
+    /// Opens the block's menu.
+    Block { pane_idx: usize, block_idx: usize },
+    /// Shows a setting's value and where it came from.
+    Setting { path: String, value: String, source: String },
+    /// Lists the palette items in a category.
+    Category(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub title: String,
+    pub detail: String,
+    pub target: SearchTarget,
+    /// Byte offsets in `title` of the characters the query matched.
+    pub highlights: Vec<usize>,
+}
+
+/// The data searched, gathered by the app when the query changes. Only the
+/// queried domain's source needs filling in.
+#[derive(Default)]
+pub struct SearchSources<'a> {
+    /// Commands, most recent first.
+    pub history: Vec<String>,
+    pub palette: Vec<PaletteItem>,
+    pub palette_ranking: PaletteRanking,
+    /// `(pane index, block index, block)` for every block in every pane.
+    pub blocks: Vec<(usize, usize, &'a Block)>,
+    pub config: Option<&'a Config>,
+}
+
+/// Pinned and recently used palette items, by `PaletteItem::key`.
+#[derive(Debug, Clone, Default)]
+pub struct PaletteRanking {
+    pub pinned: Vec<String>,
+    /// Newest first.
+    pub recent: Vec<String>,
+}
+
+impl PaletteRanking {
+    fn bonus(&self, key: &str) -> i64 {
+        if self.pinned.iter().any(|pinned| pinned == key) {
+            return PINNED_BONUS;
+        }
+        self.recent.iter().position(|recent| recent == key).map_or(0, |i| (RECENT_BONUS - 10 * i as i64).max(0))
+    }
+}
+
+/// Every category of `items` with the number of items in it, by name.
+pub fn palette_categories(items: &[PaletteItem]) -> Vec<(String, usize)> {
+    let mut counts = std::collections::BTreeMap::new();
+    for category in items.iter().flat_map(PaletteItem::categories) {
+        *counts.entry(category).or_insert(0) += 1;
+    }
+    counts.into_iter().collect()
+}
+
+/// `(name, description, kind)` of a palette item.
+fn palette_fields(item: &PaletteItem) -> (&str, &str, &'static str) {
+    match item {
+        PaletteItem::Workflow(workflow) => (workflow.name.as_str(), workflow.description.as_str(), "Workflow"),
+        PaletteItem::Notebook(notebook) => (notebook.name.as_str(), "", "Notebook"),
+        PaletteItem::Action { name, description, .. } => (name.as_str(), description.as_str(), "Action"),
+        PaletteItem::Profile { name, description } => (name.as_str(), description.as_str(), "Profile"),
+        PaletteItem::Prompt(prompt) => (prompt.name.as_str(), prompt.description.as_str(), "Prompt"),
+        PaletteItem::GitHub(item) => match item.kind {
+            GitHubItemKind::PullRequest => (item.title.as_str(), item.reference.as_str(), "Pull Request"),
+            GitHubItemKind::Issue => (item.title.as_str(), item.reference.as_str(), "Issue"),
+        },
+    }
+}
+
+fn palette_result(item: &PaletteItem, ranking: &PaletteRanking, highlights: Vec<usize>) -> SearchResult {
+    let (name, description, kind) = palette_fields(item);
+    let pinned = if ranking.pinned.contains(&item.key()) { ", pinned" } else { "" };
+    SearchResult {
+        title: name.to_string(),
+        detail: format!("[{}{}] {}", kind, pinned, description),
+        target: SearchTarget::Palette(item.clone()),
+        highlights,
+    }
+}
+
+fn history_result(command: &str, found: FuzzyMatch) -> (i64, SearchResult) {
+    (found.score, SearchResult {
+        title: command.to_string(),
+        detail: String::new(),
+        target: SearchTarget::Command(command.to_string()),
+        highlights: found.positions,
+    })
+}
+
+/// Matches actions and workflows, within a category when the text starts
+/// with one, e.g. `git: undo`.
+fn search_palette(text: &str, sources: &SearchSources) -> Vec<(i64, SearchResult)> {
+    let ranking = &sources.palette_ranking;
+    let categories = palette_categories(&sources.palette);
+    let (category, text) = match text.split_once(':') {
+        Some((name, rest)) if categories.iter().any(|(category, _)| category == name.trim()) => (Some(name.trim()), rest.trim_start()),
+        _ => (None, text),
+    };
+    if text.is_empty() && category.is_none() {
+        // Favorites first, then recent items, then the categories to browse.
+        let find = |key: &String| sources.palette.iter().find(|item| &item.key() == key);
+        let recent = ranking.recent.iter().filter(|key| !ranking.pinned.contains(key)).take(MAX_RECENT);
+        let items = ranking.pinned.iter().chain(recent).filter_map(find).map(|item| palette_result(item, ranking, Vec::new()));
+        let categories = categories.into_iter().map(|(name, count)| SearchResult {
+            title: format!("{}:", name),
+            detail: format!("[Category] {} items", count),
+            target: SearchTarget::Category(name),
+            highlights: Vec::new(),
+        });
+        return items.chain(categories).enumerate().map(|(i, result)| (-(i as i64), result)).collect();
+    }
+    sources
+        .palette
+        .iter()
+        .filter(|item| category.is_none_or(|category| item.categories().iter().any(|c| c == category)))
+        .filter_map(|item| {
+            let (name, description, _) = palette_fields(item);
+            let found = fuzzy_match(&format!("{} {}", name, description), text)?;
+            // Only the name is shown as the title.
+            let highlights = found.positions.into_iter().filter(|&position| position < name.len()).collect();
+            Some((found.score + ranking.bonus(&item.key()), palette_result(item, ranking, highlights)))
+        })
+        .collect()
+}
+
+/// Runs a query against the domain its prefix selects.
+pub fn search(query: &str, sources: &SearchSources) -> Vec<SearchResult> {
+    let (domain, text) = SearchDomain::parse(query);
+    let mut scored: Vec<(i64, SearchResult)> = match domain {
+        SearchDomain::History => match_all(&sources.history, text)
+            .into_iter()
+            .map(|(idx, found)| history_result(&sources.history[idx], found))
+            .collect(),
+        SearchDomain::Actions => search_palette(text, sources),
+        SearchDomain::Files => FilePathCompleter
+            .suggest(text)
+            .into_iter()
+            .map(|suggestion| {
+                (0, SearchResult {
+                    title: suggestion.replacement.clone(),
+                    detail: suggestion.description.unwrap_or_default(),
+                    target: SearchTarget::File(suggestion.replacement),
+                    highlights: Vec::new(),
+                })
+            })
+            .collect(),
+        SearchDomain::Blocks => sources
+            .blocks
+            .iter()
+            .rev()
+            .filter_map(|&(pane_idx, block_idx, block)| {
+                let output_line = block
+                    .output
+                    .lines()
+                    .find(|line| !text.is_empty() && line.to_lowercase().contains(&text.to_lowercase()));
+                let found = fuzzy_match(&block.command, text)
+                    .or(output_line.map(|_| FuzzyMatch { score: 0, positions: Vec::new() }))?;
+                Some((found.score, SearchResult {
+                    title: block.command.clone(),
+                    detail: output_line.unwrap_or_else(|| block.output.lines().next().unwrap_or_default()).trim().to_string(),
+                    target: SearchTarget::Block { pane_idx, block_idx },
+                    highlights: found.positions,
+                }))
+            })
+            .collect(),
+        SearchDomain::Settings => sources
+            .config
+            .map(settings)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(path, value, source)| {
+                let found = fuzzy_match(&path, text)?;
+                Some((found.score, SearchResult {
+                    title: format!("{} = {}", path, value),
+                    detail: source.clone(),
+                    target: SearchTarget::Setting { path, value, source },
+                    highlights: found.positions,
+                }))
+            })
+            .collect(),
+    };
+    scored.sort_by_key(|(score, _)| Reverse(*score));
+    scored.into_iter().take(MAX_RESULTS).map(|(_, result)| result).collect()
+}
+
+/// A history search running on the matcher's threads.
+pub struct PendingSearch(PendingMatches<String>);
+
+impl PendingSearch {
+    /// The results, once the matching is done.
+    pub fn try_take(&self) -> Option<Vec<SearchResult>> {
+        let history = self.0.choices();
+        let matches = self.0.try_take()?;
+        Some(matches.into_iter().take(MAX_RESULTS).map(|(idx, found)| history_result(&history[idx], found).1).collect())
+    }
+}
+
+/// Starts matching `text` against `history`, most recent first, without
+/// waiting for it. Dropping the result abandons the search.
+pub fn search_history(history: Vec<String>, text: &str) -> PendingSearch {
+    PendingSearch(spawn_match_all(history.into(), text))
+}
+
+/// Every leaf setting as `(dotted path, value, source)`.
+fn settings(config: &Config) -> Vec<(String, String, String)> {
+    fn walk(prefix: &str, value: &toml::Value, config: &Config, out: &mut Vec<(String, String, String)>) {
+        match value {
+            toml::Value::Table(table) => {
+                for (key, value) in table {
+                    let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                    walk(&path, value, config, out);
+                }
+            }
+            value => {
+                let source = config.sources().get(prefix).map_or_else(|| "default".to_string(), ToString::to_string);
+                out.push((prefix.to_string(), value.to_string(), source));
+            }
+        }
+    }
+    let mut out = Vec::new();
+    match toml::Value::try_from(config) {
+        Ok(value) => walk("", &value, config, &mut out),
+        Err(e) => log::warn!("Failed to list settings: {}", e),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::layers::{load_layered, ConfigPaths};
+    use crate::drive::Workflow;
+
+    fn block(command: &str, output: &str) -> Block {
+        Block::new(command.to_string(), output.to_string())
+    }
+
+    #[test]
+    fn test_prefix_selects_domain() {
+        assert_eq!(SearchDomain::parse("git st"), (SearchDomain::History, "git st"));
+        assert_eq!(SearchDomain::parse("> scratch"), (SearchDomain::Actions, "scratch"));
+        assert_eq!(SearchDomain::parse("@src/"), (SearchDomain::Files, "src/"));
+        assert_eq!(SearchDomain::parse("#error"), (SearchDomain::Blocks, "error"));
+        assert_eq!(SearchDomain::parse(":font"), (SearchDomain::Settings, "font"));
+        assert_eq!(SearchDomain::Settings.next(), SearchDomain::History);
+    }
+
+    #[test]
+    fn test_each_domain_uses_its_source() {
+        let blocks = [block("cargo build", "error[E0308]: mismatched types"), block("ls", "Cargo.toml")];
+        let config = load_layered(&ConfigPaths::default(), Vec::new()).unwrap();
+        let sources = SearchSources {
+            history: vec!["git status".to_string(), "git stash".to_string(), "ls -la".to_string()],
+            palette: vec![PaletteItem::Action {
+                name: "Open Scratch Pane".to_string(),
+                description: String::new(),
+                action: "scratch.open".to_string(),
+            }],
+            blocks: blocks.iter().enumerate().map(|(i, block)| (0, i, block)).collect(),
+            config: Some(&config),
+            ..SearchSources::default()
+        };
+
+        let titles = |query: &str| search(query, &sources).into_iter().map(|result| result.title).collect::<Vec<_>>();
+        assert_eq!(titles("gst"), vec!["git status", "git stash"]);
+        assert_eq!(titles(">scratch"), vec!["Open Scratch Pane"]);
+        assert_eq!(titles("#mismatched"), vec!["cargo build"]);
+        let results = search(":appearance.font_size", &sources);
+        assert!(matches!(&results[0].target, SearchTarget::Setting { path, .. } if path == "appearance.font_size"));
+        assert_eq!(results[0].detail, "default");
+    }
+
+    #[test]
+    fn test_history_is_searched_in_the_background() {
+        let mut history: Vec<String> = (0..20_000).map(|i| format!("ssh host{}", i)).collect();
+        history.push("git status".to_string());
+        let pending = search_history(history, "gst");
+        let results = loop {
+            if let Some(results) = pending.try_take() {
+                break results;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "git status");
+        assert_eq!(results[0].highlights, vec![0, 4, 5]);
+    }
+
+    fn workflow(name: &str, tags: &[&str]) -> PaletteItem {
+        PaletteItem::Workflow(Workflow {
+            name: name.to_string(),
+            command: String::new(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            description: String::new(),
+            arguments: Vec::new(),
+            source_url: None,
+            author_url: None,
+            shells: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_palette_browses_categories_with_favorites_first() {
+        let sources = SearchSources {
+            palette: vec![
+                workflow("Undo last commit", &["git"]),
+                workflow("Delete merged branches", &["Git"]),
+                workflow("Upgrade formulae", &["homebrew"]),
+                PaletteItem::Action { name: "Close Pane".to_string(), description: String::new(), action: "pane.close".to_string() },
+            ],
+            palette_ranking: PaletteRanking {
+                pinned: vec!["workflow:Upgrade formulae".to_string()],
+                recent: vec!["action:pane.close".to_string(), "workflow:Upgrade formulae".to_string()],
+            },
+            ..SearchSources::default()
+        };
+        let titles = |query: &str| search(query, &sources).into_iter().map(|result| result.title).collect::<Vec<_>>();
+
+        assert_eq!(titles(">"), vec!["Upgrade formulae", "Close Pane", "actions:", "git:", "homebrew:"]);
+        assert_eq!(search(">", &sources)[3].detail, "[Category] 2 items");
+        assert_eq!(titles(">git: "), vec!["Undo last commit", "Delete merged branches"]);
+        assert_eq!(titles(">git: undo"), vec!["Undo last commit"]);
+        // Pinned items outrank better matches.
+        assert_eq!(titles(">e")[0], "Upgrade formulae");
+    }
+}
@@ -0,0 +1,224 @@
+//! Crash recovery journal
+//!
+//! While the app runs, its panes in order, their working directories and
+//! profiles, the tail of each pane's recent blocks and the unsent command
+//! line are written to a journal every few seconds. A clean shutdown
+//! deletes it, so a journal found at startup means the last run crashed or
+//! was killed, and the app offers to restore it. Blocks of privacy-zone,
+//! locked and SSH panes are left out, and secrets in the rest are redacted
+//! as for sharing.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Blocks kept per pane, the most recent ones.
+pub const MAX_JOURNALED_BLOCKS: usize = 20;
+/// Bytes of output kept per block, from its end.
+pub const MAX_OUTPUT_TAIL: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JournaledBlock {
+    pub command: String,
+    pub output: String,
+}
+
+impl JournaledBlock {
+    pub fn new(command: &str, output: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            output: tail(output, MAX_OUTPUT_TAIL).to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct JournaledPane {
+    pub cwd: Option<PathBuf>,
+    /// The launch profile the pane was opened with.
+    pub profile: Option<String>,
+    /// Oldest first.
+    pub blocks: Vec<JournaledBlock>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct SessionJournal {
+    /// Unix seconds.
+    pub saved_at: i64,
+    pub panes: Vec<JournaledPane>,
+    pub active_pane: usize,
+    /// The command line typed but not run.
+    pub input: String,
+}
+
+/// The last `max` bytes of `text`, starting at a line when one starts in
+/// them and never inside a character.
+pub fn tail(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut start = text.len() - max;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let rest = &text[start..];
+    match rest.find('\n') {
+        Some(newline) if newline + 1 < rest.len() => &rest[newline + 1..],
+        _ => rest,
+    }
+}
+
+pub fn default_journal_path() -> PathBuf {
+    let mut path = dirs::config_dir().unwrap_or_else(std::env::temp_dir);
+    path.push("warpish_terminal");
+    path.push("sessions");
+    path.push("journal.yml");
+    path
+}
+
+/// Writes snapshots of the session to a file, at most once per interval
+/// and only when something changed.
+pub struct Journal {
+    path: PathBuf,
+    interval: Duration,
+    last_written: Option<Instant>,
+    last: Option<SessionJournal>,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            last_written: None,
+            last: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The journal a run that didn't shut down cleanly left behind.
+    pub fn recover(&self) -> Option<SessionJournal> {
+        let data = fs::read_to_string(&self.path).ok()?;
+        match serde_yaml::from_str(&data) {
+            Ok(journal) => Some(journal),
+            Err(e) => {
+                log::warn!("Ignoring an unreadable session journal: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Whether a snapshot taken now would be written.
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_written
+            .is_none_or(|written| now.duration_since(written) >= self.interval)
+    }
+
+    /// Writes `snapshot` unless it matches the last one written. The file is
+    /// replaced whole, so a crash mid-write leaves the previous journal, and
+    /// only the user can read it.
+    pub fn write(&mut self, snapshot: SessionJournal, now: Instant) -> io::Result<()> {
+        self.last_written = Some(now);
+        let unchanged = self.last.as_ref().is_some_and(|last| {
+            last.panes == snapshot.panes
+                && last.active_pane == snapshot.active_pane
+                && last.input == snapshot.input
+        });
+        if unchanged {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = serde_yaml::to_string(&snapshot)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let partial = self.path.with_extension("yml.partial");
+        // Created afresh, so the mode applies
+        match fs::remove_file(&partial) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&partial)?.write_all(data.as_bytes())?;
+        fs::rename(&partial, &self.path)?;
+        self.last = Some(snapshot);
+        Ok(())
+    }
+
+    /// Deletes the journal on a clean shutdown.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.last = None;
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_tails_start_at_a_line() {
+        assert_eq!(tail("short", 10), "short");
+        assert_eq!(tail("one\ntwo\nthree", 9), "three");
+        assert_eq!(tail("abcdef", 3), "def");
+        // Never inside a character
+        assert_eq!(tail("ééé", 3), "é");
+        let block = JournaledBlock::new("yes", &"y\n".repeat(MAX_OUTPUT_TAIL));
+        assert!(block.output.len() <= MAX_OUTPUT_TAIL);
+        assert!(block.output.starts_with("y\n"));
+    }
+
+    #[test]
+    fn test_journal_round_trip() {
+        let dir =
+            std::env::temp_dir().join(format!("warpish-journal-test-{}", uuid::Uuid::new_v4()));
+        let mut journal = Journal::new(dir.join("journal.yml"), Duration::from_secs(5));
+        assert_eq!(journal.recover(), None);
+
+        let snapshot = SessionJournal {
+            saved_at: 1_700_000_000,
+            panes: vec![JournaledPane {
+                cwd: Some(PathBuf::from("/tmp")),
+                profile: Some("work".to_string()),
+                blocks: vec![JournaledBlock::new("ls", "a\nb\n")],
+            }],
+            active_pane: 0,
+            input: "git sta".to_string(),
+        };
+        let start = Instant::now();
+        assert!(journal.is_due(start));
+        journal.write(snapshot.clone(), start).unwrap();
+        assert!(!journal.is_due(start + Duration::from_secs(1)));
+        assert!(journal.is_due(start + Duration::from_secs(5)));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(journal.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A later run finds what this one left
+        let next_run = Journal::new(journal.path().to_path_buf(), Duration::from_secs(5));
+        assert_eq!(next_run.recover(), Some(snapshot));
+
+        journal.finish().unwrap();
+        assert_eq!(journal.recover(), None);
+        journal.finish().unwrap();
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! This module handles session creation, restoration, and management.
 
+pub mod journal;
 pub mod sqlite;
 
 use serde::{Deserialize, Serialize};
@@ -9,11 +10,18 @@ use std::fs;
 use std::path::PathBuf;
 use uuid::Uuid;
 
+/// The id the layout at the last shutdown is saved under, replacing the
+/// one saved before it.
+pub const LAST_SESSION_ID: Uuid = Uuid::nil();
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Session {
     pub id: Uuid,
     pub name: String,
     pub tabs: Vec<String>,
+    /// The launch profile each tab was opened with, by index.
+    #[serde(default)]
+    pub profiles: Vec<Option<String>>,
 }
 
 impl Session {
@@ -22,6 +30,7 @@ impl Session {
             id: Uuid::new_v4(),
             name: name.to_string(),
             tabs: vec!["default".to_string()],
+            profiles: vec![None],
         }
     }
 
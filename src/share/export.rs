@@ -0,0 +1,239 @@
+//! Block Export
+//!
+//! Turns blocks into documents that can be read outside the terminal: a
+//! Markdown file, for issues, notebooks and gists, or a standalone HTML page
+//! with its styles inlined, so it opens the same from disk or an attachment.
+//! In HTML, commands are highlighted with the same tree-sitter grammar as the
+//! input editor and everything is painted in the active theme's colors.
+//! Markdown fences blocks as `console`, which Markdown renderers highlight as
+//! a shell session, prompt, command and output alike.
+
+use super::ShareContent;
+use crate::app::pane::Block;
+use crate::pdf::mix;
+use crate::syntax_parser::{Grammar, HighlightKind, SyntaxParserPool};
+use crate::ui::theme::{CustomColor, Theme};
+
+const COMMAND_DOCUMENT: &str = "export";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+        }
+    }
+}
+
+/// Colors for an HTML export.
+#[derive(Debug, Clone)]
+pub struct HtmlStyle {
+    pub background: CustomColor,
+    pub foreground: CustomColor,
+    /// Headings and the prompt.
+    pub accent: CustomColor,
+    /// Tags and comments.
+    pub muted: CustomColor,
+    /// Shading behind each block.
+    pub code_background: CustomColor,
+    keyword: CustomColor,
+    function: CustomColor,
+    flag: CustomColor,
+    string: CustomColor,
+    constant: CustomColor,
+    variable: CustomColor,
+}
+
+impl HtmlStyle {
+    pub fn new(theme: &Theme) -> Self {
+        let ansi = &theme.terminal_colors.normal;
+        Self {
+            background: theme.background,
+            foreground: theme.foreground,
+            accent: theme.accent,
+            muted: mix(theme.foreground, theme.background, 0.5),
+            code_background: mix(theme.foreground, theme.background, 0.08),
+            keyword: ansi.magenta,
+            function: ansi.blue,
+            flag: ansi.yellow,
+            string: ansi.green,
+            constant: ansi.cyan,
+            variable: ansi.red,
+        }
+    }
+
+    /// The same mapping from highlight kinds to ANSI colors as the input
+    /// editor uses when the theme sets no syntax colors.
+    fn highlight_color(&self, kind: HighlightKind) -> CustomColor {
+        match kind {
+            HighlightKind::Keyword => self.keyword,
+            HighlightKind::Function => self.function,
+            HighlightKind::Type | HighlightKind::Flag => self.flag,
+            HighlightKind::String => self.string,
+            HighlightKind::Constant | HighlightKind::Operator => self.constant,
+            HighlightKind::Comment => self.muted,
+            HighlightKind::Variable => self.variable,
+            HighlightKind::Property => self.foreground,
+        }
+    }
+}
+
+fn css(color: CustomColor) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A backtick fence longer than any run of backticks in `text`, so output
+/// that contains fences of its own cannot close the block early.
+fn fence_for(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// The blocks as Markdown, one fenced `console` block each.
+pub fn to_markdown(title: &str, blocks: &[&Block]) -> String {
+    let mut markdown = format!("# {}\n", title);
+    for block in blocks {
+        let body = format!("$ {}\n{}", block.command, block.output.trim_end());
+        let fence = fence_for(&body);
+        markdown.push_str(&format!("\n{}console\n{}\n{}\n", fence, body, fence));
+        if !block.tags.is_empty() {
+            let tags: Vec<String> = block.tags.iter().map(|tag| format!("`#{}`", tag)).collect();
+            markdown.push_str(&format!("\n{}\n", tags.join(" ")));
+        }
+    }
+    markdown
+}
+
+/// The command with its highlights as `<span>`s. Falls back to plain text
+/// if the grammar cannot be loaded.
+fn highlighted_command(pool: &mut SyntaxParserPool, grammar: Grammar, command: &str, style: &HtmlStyle) -> String {
+    let spans = match pool.update(COMMAND_DOCUMENT, grammar, command) {
+        Ok(spans) => spans,
+        Err(e) => {
+            log::warn!("Exporting without highlighting: {}", e);
+            return escape(command);
+        }
+    };
+    let mut html = String::new();
+    let mut at = 0;
+    for span in spans {
+        html.push_str(&escape(&command[at..span.range.start]));
+        html.push_str(&format!(
+            "<span style=\"color:{}\">{}</span>",
+            css(style.highlight_color(span.kind)),
+            escape(&command[span.range.clone()])
+        ));
+        at = span.range.end;
+    }
+    html.push_str(&escape(&command[at..]));
+    html
+}
+
+/// The blocks as a standalone HTML page, with commands highlighted for
+/// `grammar`.
+pub fn to_html(title: &str, blocks: &[&Block], style: &HtmlStyle, grammar: Grammar) -> String {
+    let mut pool = SyntaxParserPool::new();
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{title}</title>\n<style>\n\
+         body {{ background: {background}; color: {foreground}; font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; margin: 2em; }}\n\
+         h1 {{ color: {accent}; font-size: 1.25em; }}\n\
+         pre {{ background: {code_background}; padding: 0.75em 1em; border-radius: 6px; white-space: pre-wrap; overflow-wrap: anywhere; }}\n\
+         .prompt {{ color: {accent}; font-weight: bold; user-select: none; }}\n\
+         .command {{ font-weight: bold; }}\n\
+         .tags {{ color: {muted}; font-size: 0.85em; }}\n\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        title = escape(title),
+        background = css(style.background),
+        foreground = css(style.foreground),
+        accent = css(style.accent),
+        code_background = css(style.code_background),
+        muted = css(style.muted),
+    );
+    for block in blocks {
+        html.push_str(&format!(
+            "<pre><span class=\"prompt\">$ </span><span class=\"command\">{}</span>\n{}</pre>\n",
+            highlighted_command(&mut pool, grammar, &block.command, style),
+            escape(block.output.trim_end())
+        ));
+        if !block.tags.is_empty() {
+            let tags: Vec<String> = block.tags.iter().map(|tag| format!("#{}", escape(tag))).collect();
+            html.push_str(&format!("<p class=\"tags\">{}</p>\n", tags.join(" ")));
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// The blocks as Markdown, ready to upload as a gist.
+pub fn to_share_content(title: &str, blocks: &[&Block]) -> ShareContent {
+    ShareContent {
+        title: title.to_string(),
+        file_name: format!("{}.md", title),
+        body: to_markdown(title, blocks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(command: &str, output: &str, tags: &[&str]) -> Block {
+        Block {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Block::new(command.to_string(), output.to_string())
+        }
+    }
+
+    #[test]
+    fn test_markdown_fences_outlast_the_output() {
+        let readme = block("cat README.md", "Build with:\n```sh\ncargo build\n```\n\n", &["docs"]);
+        let markdown = to_markdown("Setup", &[&block("pwd", "/src/app\n", &[]), &readme]);
+        assert_eq!(
+            markdown,
+            "# Setup\n\n```console\n$ pwd\n/src/app\n```\n\n\
+             ````console\n$ cat README.md\nBuild with:\n```sh\ncargo build\n```\n````\n\n`#docs`\n"
+        );
+    }
+
+    #[test]
+    fn test_html_is_escaped_and_highlighted() {
+        let style = HtmlStyle {
+            background: CustomColor(0, 0, 0),
+            foreground: CustomColor(255, 255, 255),
+            accent: CustomColor(1, 1, 1),
+            muted: CustomColor(2, 2, 2),
+            code_background: CustomColor(3, 3, 3),
+            keyword: CustomColor(4, 4, 4),
+            function: CustomColor(5, 5, 5),
+            flag: CustomColor(6, 6, 6),
+            string: CustomColor(7, 7, 7),
+            constant: CustomColor(8, 8, 8),
+            variable: CustomColor(9, 9, 9),
+        };
+        let html = to_html("<logs>", &[&block("grep -n main src", "<fn main>\n", &["a&b"])], &style, Grammar::Bash);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>&lt;logs&gt;</title>"));
+        assert!(html.contains("<span style=\"color:#050505\">grep</span>"));
+        assert!(html.contains("<span style=\"color:#060606\">-n</span>"));
+        assert!(html.contains("\n&lt;fn main&gt;</pre>"));
+        assert!(html.contains("<p class=\"tags\">#a&amp;b</p>"));
+    }
+}
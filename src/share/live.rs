@@ -0,0 +1,551 @@
+//! Live Session Sharing
+//!
+//! A shared pane is served over a WebSocket so invited collaborators can
+//! follow it, and type into it once the host approves. The invite link
+//! carries a random session token in its path. Binary frames carry the pane's
+//! PTY output to collaborators and their keystrokes back; text frames carry
+//! JSON [`ShareMessage`]s for joining, cursors and input access. Joins,
+//! access changes and the commands collaborators type are kept in the
+//! session's activity log.
+
+use super::ShareError;
+use crate::permissions::{Capability, PermissionRequest, PermissionService, PermissionStatus, Requester};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use uuid::Uuid;
+
+/// Cursor colors handed out to collaborators in the order they join.
+const CURSOR_COLORS: [&str; 6] = ["#e06c75", "#61afef", "#98c379", "#c678dd", "#e5c07b", "#56b6c2"];
+
+/// The most activity entries kept for one session.
+const MAX_ACTIVITY: usize = 500;
+
+const MAX_NAME_LEN: usize = 32;
+
+/// Where a pane's PTY reader sends a copy of its output while it is shared.
+pub type OutputTap = Arc<Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collaborator {
+    pub id: u32,
+    pub name: String,
+    pub color: String,
+    /// Whether the host has allowed them to type into the pane.
+    pub can_type: bool,
+    /// Their cursor in the pane, as (row, column).
+    pub cursor: Option<(u16, u16)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivityEntry {
+    pub at: i64,
+    pub user_id: u32,
+    pub name: String,
+    pub action: String,
+}
+
+/// Control messages, sent as JSON text frames.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShareMessage {
+    /// The first message from a collaborator.
+    Join { name: String },
+    /// Asks the host to allow typing into the pane.
+    RequestInput,
+    /// The collaborator's cursor moved.
+    Cursor { row: u16, col: u16 },
+    /// Answers `join` with the collaborator's id and everyone present.
+    Welcome { user_id: u32, collaborators: Vec<Collaborator> },
+    /// Someone joined, or their access or cursor changed.
+    Presence { collaborator: Collaborator },
+    Left { user_id: u32 },
+    Activity { entry: ActivityEntry },
+    Error { message: String },
+}
+
+/// A frame queued for one collaborator's socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outgoing {
+    Output(Vec<u8>),
+    Control(ShareMessage),
+}
+
+/// The collaborators of a shared pane and what they are doing, shared
+/// between the server's connections and the app.
+#[derive(Debug, Default)]
+pub struct ShareHub {
+    next_id: u32,
+    collaborators: BTreeMap<u32, Collaborator>,
+    peers: HashMap<u32, mpsc::UnboundedSender<Outgoing>>,
+    activity: Vec<ActivityEntry>,
+    /// Collaborators waiting for the host to answer their input request.
+    input_requests: Vec<u32>,
+    /// Approved keystrokes not yet written to the PTY.
+    keystrokes: Vec<u8>,
+    /// What each collaborator has typed since their last Enter.
+    lines: HashMap<u32, String>,
+    /// Collaborators who left since the app last looked.
+    departed: Vec<u32>,
+}
+
+impl ShareHub {
+    fn join(&mut self, name: &str, peer: mpsc::UnboundedSender<Outgoing>, now: i64) -> Result<u32, String> {
+        let name: String = name.trim().chars().filter(|c| !c.is_control()).take(MAX_NAME_LEN).collect();
+        if name.is_empty() {
+            return Err("A name is required to join".to_string());
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        let collaborator = Collaborator {
+            id,
+            name,
+            color: CURSOR_COLORS[(id as usize - 1) % CURSOR_COLORS.len()].to_string(),
+            can_type: false,
+            cursor: None,
+        };
+        self.collaborators.insert(id, collaborator.clone());
+        peer.send(Outgoing::Control(ShareMessage::Welcome {
+            user_id: id,
+            collaborators: self.collaborators(),
+        }))
+        .ok();
+        self.broadcast(ShareMessage::Presence { collaborator });
+        self.peers.insert(id, peer);
+        self.log(id, "joined", now);
+        Ok(id)
+    }
+
+    fn leave(&mut self, id: u32, now: i64) {
+        if !self.collaborators.contains_key(&id) {
+            return;
+        }
+        self.log(id, "left", now);
+        self.collaborators.remove(&id);
+        self.peers.remove(&id);
+        self.lines.remove(&id);
+        self.input_requests.retain(|request| *request != id);
+        self.departed.push(id);
+        self.broadcast(ShareMessage::Left { user_id: id });
+    }
+
+    fn receive(&mut self, id: u32, message: ShareMessage, now: i64) {
+        match message {
+            ShareMessage::RequestInput => {
+                let can_type = self.collaborators.get(&id).is_some_and(|collaborator| collaborator.can_type);
+                if !can_type && !self.input_requests.contains(&id) {
+                    self.input_requests.push(id);
+                    self.log(id, "asked to type", now);
+                }
+            }
+            ShareMessage::Cursor { row, col } => {
+                if let Some(collaborator) = self.collaborators.get_mut(&id) {
+                    collaborator.cursor = Some((row, col));
+                    let collaborator = collaborator.clone();
+                    self.broadcast(ShareMessage::Presence { collaborator });
+                }
+            }
+            _ => self.reject(id, "Unexpected message".to_string()),
+        }
+    }
+
+    /// Queues a collaborator's keystrokes for the PTY if they may type.
+    fn type_input(&mut self, id: u32, data: &[u8], now: i64) {
+        if !self.collaborators.get(&id).is_some_and(|collaborator| collaborator.can_type) {
+            self.reject(id, "The host has not allowed you to type".to_string());
+            return;
+        }
+        self.keystrokes.extend_from_slice(data);
+        let line = self.lines.entry(id).or_default();
+        let mut entered = Vec::new();
+        for c in String::from_utf8_lossy(data).chars() {
+            match c {
+                '\r' | '\n' => entered.push(std::mem::take(line)),
+                '\x7f' | '\x08' => {
+                    line.pop();
+                }
+                c if !c.is_control() => line.push(c),
+                _ => {}
+            }
+        }
+        for line in entered.iter().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            self.log(id, format!("typed `{}`", line), now);
+        }
+    }
+
+    fn reject(&self, id: u32, message: String) {
+        if let Some(peer) = self.peers.get(&id) {
+            peer.send(Outgoing::Control(ShareMessage::Error { message })).ok();
+        }
+    }
+
+    /// Lets a collaborator type into the pane, or stops them.
+    pub fn set_input_access(&mut self, id: u32, allowed: bool, now: i64) {
+        self.input_requests.retain(|request| *request != id);
+        let Some(collaborator) = self.collaborators.get_mut(&id) else {
+            return;
+        };
+        collaborator.can_type = allowed;
+        let collaborator = collaborator.clone();
+        self.broadcast(ShareMessage::Presence { collaborator });
+        self.log(id, if allowed { "was allowed to type" } else { "was not allowed to type" }, now);
+    }
+
+    pub fn broadcast_output(&self, data: &[u8]) {
+        for peer in self.peers.values() {
+            peer.send(Outgoing::Output(data.to_vec())).ok();
+        }
+    }
+
+    fn broadcast(&self, message: ShareMessage) {
+        for peer in self.peers.values() {
+            peer.send(Outgoing::Control(message.clone())).ok();
+        }
+    }
+
+    fn log(&mut self, id: u32, action: impl Into<String>, now: i64) {
+        let Some(collaborator) = self.collaborators.get(&id) else {
+            return;
+        };
+        let entry = ActivityEntry {
+            at: now,
+            user_id: id,
+            name: collaborator.name.clone(),
+            action: action.into(),
+        };
+        if self.activity.len() == MAX_ACTIVITY {
+            self.activity.remove(0);
+        }
+        self.activity.push(entry.clone());
+        self.broadcast(ShareMessage::Activity { entry });
+    }
+
+    /// Takes the collaborators who asked to type since the last call.
+    pub fn take_input_requests(&mut self) -> Vec<Collaborator> {
+        let requests = std::mem::take(&mut self.input_requests);
+        requests.iter().filter_map(|id| self.collaborators.get(id).cloned()).collect()
+    }
+
+    /// Takes the ids of the collaborators who left since the last call.
+    pub fn take_departures(&mut self) -> Vec<u32> {
+        std::mem::take(&mut self.departed)
+    }
+
+    pub fn take_keystrokes(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.keystrokes)
+    }
+
+    pub fn collaborators(&self) -> Vec<Collaborator> {
+        self.collaborators.values().cloned().collect()
+    }
+
+    pub fn activity(&self) -> &[ActivityEntry] {
+        &self.activity
+    }
+}
+
+/// The permission prompt asking whether a collaborator of the session with
+/// the given token may type.
+pub fn input_request(session: &str, collaborator: &Collaborator) -> PermissionRequest {
+    PermissionRequest {
+        requester: Requester::Collaborator {
+            session: session.to_string(),
+            user_id: collaborator.id,
+            name: collaborator.name.clone(),
+        },
+        capability: Capability::TerminalInput,
+        reason: "Type into the pane you are sharing".to_string(),
+    }
+}
+
+/// Whether `requester` is a collaborator of the session with the given
+/// token, and has the given id if one is passed.
+fn is_collaborator(requester: &Requester, token: &str, id: Option<u32>) -> bool {
+    matches!(requester, Requester::Collaborator { session, user_id, .. }
+        if session == token && id.map_or(true, |id| id == *user_id))
+}
+
+/// A pane being shared live. Dropping it ends the session and forgets the
+/// collaborators' input approvals.
+pub struct LiveShare {
+    pub pane_id: Uuid,
+    /// The invite link for collaborators.
+    pub url: String,
+    token: String,
+    hub: Arc<Mutex<ShareHub>>,
+    tap: OutputTap,
+    permissions: Arc<Mutex<PermissionService>>,
+    /// Collaborators whose input request is waiting on a permission prompt.
+    awaiting: Vec<Collaborator>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl LiveShare {
+    /// Serves the pane on `address` from a background thread, copying its
+    /// output from `tap`. Input requests are asked about through
+    /// `permissions`.
+    pub fn start(
+        pane_id: Uuid,
+        address: &str,
+        tap: OutputTap,
+        permissions: Arc<Mutex<PermissionService>>,
+    ) -> Result<Self, ShareError> {
+        let listener = std::net::TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let token = Uuid::new_v4().simple().to_string();
+        let url = format!("ws://{}/{}", listener.local_addr()?, token);
+
+        let (output_tx, output_rx) = mpsc::unbounded_channel();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let hub = Arc::new(Mutex::new(ShareHub::default()));
+        let server_hub = hub.clone();
+        let server_token = token.clone();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Failed to start live share runtime: {}", e);
+                    return;
+                }
+            };
+            runtime.block_on(serve(listener, server_token, server_hub, output_rx, shutdown_rx));
+        });
+        *tap.lock().unwrap() = Some(output_tx);
+        Ok(Self { pane_id, url, token, hub, tap, permissions, awaiting: Vec::new(), shutdown: Some(shutdown) })
+    }
+
+    /// Asks the user about new input requests, applies their answers,
+    /// forgets the approvals of collaborators who left, and returns the
+    /// approved keystrokes to write to the PTY.
+    pub fn sync(&mut self, now: i64) -> Vec<u8> {
+        let mut hub = self.hub.lock().unwrap();
+        let mut permissions = self.permissions.lock().unwrap();
+        let departed = hub.take_departures();
+        if !departed.is_empty() {
+            self.awaiting.retain(|collaborator| !departed.contains(&collaborator.id));
+            permissions.forget(|requester| departed.iter().any(|id| is_collaborator(requester, &self.token, Some(*id))));
+        }
+        self.awaiting.extend(hub.take_input_requests());
+        let token = &self.token;
+        self.awaiting.retain(|collaborator| match permissions.request(input_request(token, collaborator)) {
+            PermissionStatus::Pending => true,
+            status => {
+                hub.set_input_access(collaborator.id, status == PermissionStatus::Granted, now);
+                false
+            }
+        });
+        hub.take_keystrokes()
+    }
+
+    /// Stops a collaborator typing and forgets their approval; they have to
+    /// ask again.
+    pub fn revoke_input(&mut self, id: u32, now: i64) {
+        self.hub.lock().unwrap().set_input_access(id, false, now);
+        self.permissions.lock().unwrap().forget(|requester| is_collaborator(requester, &self.token, Some(id)));
+    }
+
+    pub fn collaborators(&self) -> Vec<Collaborator> {
+        self.hub.lock().unwrap().collaborators()
+    }
+
+    pub fn activity(&self) -> Vec<ActivityEntry> {
+        self.hub.lock().unwrap().activity().to_vec()
+    }
+}
+
+impl Drop for LiveShare {
+    fn drop(&mut self) {
+        *self.tap.lock().unwrap() = None;
+        if let Ok(mut permissions) = self.permissions.lock() {
+            permissions.forget(|requester| is_collaborator(requester, &self.token, None));
+        }
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+async fn serve(
+    listener: std::net::TcpListener,
+    token: String,
+    hub: Arc<Mutex<ShareHub>>,
+    mut output: mpsc::UnboundedReceiver<Vec<u8>>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to serve live share: {}", e);
+            return;
+        }
+    };
+    let path = format!("/{}", token);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            Some(data) = output.recv() => hub.lock().unwrap().broadcast_output(&data),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(handle_collaborator(stream, path.clone(), hub.clone()));
+                }
+                Err(e) => log::warn!("Failed to accept live share connection: {}", e),
+            },
+        }
+    }
+}
+
+async fn handle_collaborator(stream: TcpStream, path: String, hub: Arc<Mutex<ShareHub>>) {
+    // The handshake callback's signature is fixed by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let check_link = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        if request.uri().path() == path {
+            Ok(response)
+        } else {
+            let mut rejection = ErrorResponse::new(Some("Invalid session link".to_string()));
+            *rejection.status_mut() = StatusCode::FORBIDDEN;
+            Err(rejection)
+        }
+    };
+    let socket = match accept_hdr_async(stream, check_link).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::info!("Rejected live share connection: {}", e);
+            return;
+        }
+    };
+    let (mut sink, mut source) = socket.split();
+
+    let name = match source.next().await {
+        Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+            Ok(ShareMessage::Join { name }) => name,
+            _ => String::new(),
+        },
+        _ => return,
+    };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let joined = hub.lock().unwrap().join(&name, tx, chrono::Utc::now().timestamp());
+    let id = match joined {
+        Ok(id) => id,
+        Err(message) => {
+            let error = serde_json::to_string(&ShareMessage::Error { message }).unwrap_or_default();
+            sink.send(Message::Text(error)).await.ok();
+            return;
+        }
+    };
+
+    let writer = tokio::spawn(async move {
+        while let Some(outgoing) = rx.recv().await {
+            let message = match outgoing {
+                Outgoing::Output(data) => Message::Binary(data),
+                Outgoing::Control(message) => Message::Text(serde_json::to_string(&message).unwrap_or_default()),
+            };
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+    while let Some(Ok(message)) = source.next().await {
+        let now = chrono::Utc::now().timestamp();
+        match message {
+            Message::Binary(data) => hub.lock().unwrap().type_input(id, &data, now),
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(message) => hub.lock().unwrap().receive(id, message, now),
+                Err(e) => hub.lock().unwrap().reject(id, format!("Invalid message: {}", e)),
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    hub.lock().unwrap().leave(id, chrono::Utc::now().timestamp());
+    writer.abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::WebSocketClient;
+
+    #[test]
+    fn test_input_needs_approval_and_is_logged() {
+        let mut hub = ShareHub::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let id = hub.join(" sam ", tx, 10).unwrap();
+        assert!(hub.join("\n", mpsc::unbounded_channel().0, 10).is_err());
+        assert!(matches!(rx.try_recv(), Ok(Outgoing::Control(ShareMessage::Welcome { user_id, .. })) if user_id == id));
+
+        hub.type_input(id, b"rm -rf /\r", 11);
+        assert!(hub.take_keystrokes().is_empty());
+        hub.receive(id, ShareMessage::RequestInput, 12);
+        hub.receive(id, ShareMessage::RequestInput, 12);
+        assert_eq!(hub.take_input_requests().len(), 1);
+
+        hub.set_input_access(id, true, 13);
+        hub.type_input(id, b"ls -lx\x7fa\r", 14);
+        assert_eq!(hub.take_keystrokes(), b"ls -lx\x7fa\r");
+        let actions: Vec<&str> = hub.activity().iter().map(|entry| entry.action.as_str()).collect();
+        assert_eq!(actions, ["joined", "asked to type", "was allowed to type", "typed `ls -la`"]);
+        assert_eq!(hub.activity()[0].name, "sam");
+
+        assert!(hub.take_departures().is_empty());
+        hub.leave(id, 15);
+        assert_eq!(hub.take_departures(), [id]);
+    }
+
+    #[tokio::test]
+    async fn test_collaborator_types_into_shared_pane() {
+        let tap = OutputTap::default();
+        let permissions = Arc::new(Mutex::new(PermissionService::default()));
+        let mut share = LiveShare::start(Uuid::new_v4(), "127.0.0.1:0", tap.clone(), permissions.clone()).unwrap();
+        let (base, _token) = share.url.rsplit_once('/').unwrap();
+        assert!(WebSocketClient::connect(&format!("{}/wrong", base)).await.is_err());
+
+        let mut client = WebSocketClient::connect(&share.url).await.unwrap();
+        client.send(r#"{"type":"join","name":"sam"}"#).await.unwrap();
+        let welcome = client.recv().await.unwrap().unwrap();
+        assert!(welcome.to_text().unwrap().contains("\"type\":\"welcome\""));
+
+        tap.lock().unwrap().as_ref().unwrap().send(b"$ ".to_vec()).unwrap();
+        loop {
+            match client.recv().await.unwrap().unwrap() {
+                Message::Binary(data) => break assert_eq!(data, b"$ "),
+                _ => continue,
+            }
+        }
+
+        client.send(r#"{"type":"request_input"}"#).await.unwrap();
+        client.send_binary(b"pwd\r").await.unwrap();
+        let mut keystrokes = Vec::new();
+        for _ in 0..100 {
+            keystrokes.extend(share.sync(0));
+            let prompt = permissions.lock().unwrap().next_prompt();
+            if let Some(prompt) = prompt {
+                let conn = rusqlite::Connection::open_in_memory().unwrap();
+                permissions.lock().unwrap().resolve(&conn, &prompt, crate::permissions::PermissionChoice::AllowOnce, 0).unwrap();
+                // The next sync applies the answer; input sent after it is accepted.
+                share.sync(0);
+                client.send_binary(b"pwd\r").await.unwrap();
+            }
+            if !keystrokes.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(keystrokes, b"pwd\r");
+        assert!(share.collaborators()[0].can_type);
+        assert_eq!(permissions.lock().unwrap().grants().len(), 1);
+
+        // Ending the share forgets the approval, so a later share's first
+        // "sam" has to ask again
+        drop(share);
+        assert!(tap.lock().unwrap().is_none());
+        assert!(permissions.lock().unwrap().grants().is_empty());
+    }
+}
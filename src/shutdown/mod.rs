@@ -0,0 +1,140 @@
+//! Shutdown
+//!
+//! Quitting runs in a fixed order so that nothing is lost and nothing is
+//! left behind: background tasks are cancelled first, so none of them write
+//! after the database is flushed; the session layout and the unsent command
+//! line are saved; the database is flushed; and finally every pane's shell
+//! is sent SIGHUP, as a terminal emulator closing its window would, and
+//! killed if it has not exited once the timeout passes. Shells get the
+//! timeout together rather than one after another, so quitting with many
+//! panes open takes no longer than with one.
+
+use portable_pty::Child;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The cancellation token every background task is handed a child of, so
+/// that shutdown stops them all at once.
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundTasks {
+    root: CancellationToken,
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token for one task, cancelled with the rest on shutdown.
+    pub fn token(&self) -> CancellationToken {
+        self.root.child_token()
+    }
+
+    pub fn cancel(&self) {
+        self.root.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.root.is_cancelled()
+    }
+}
+
+/// How the shells were stopped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Termination {
+    /// Shells that exited on their own or after SIGHUP.
+    pub exited: usize,
+    /// Shells that ignored SIGHUP and were killed.
+    pub killed: usize,
+}
+
+/// Sends SIGHUP to each child, waits up to `timeout` for all of them to
+/// exit, then kills the ones still running.
+pub fn hang_up(children: &mut [&mut (dyn Child + Send + Sync)], timeout: Duration) -> io::Result<Termination> {
+    let mut running = Vec::new();
+    let mut termination = Termination::default();
+    for (idx, child) in children.iter_mut().enumerate() {
+        if child.try_wait()?.is_some() {
+            termination.exited += 1;
+            continue;
+        }
+        send_hangup(&mut **child)?;
+        running.push(idx);
+    }
+    let deadline = Instant::now() + timeout;
+    while !running.is_empty() && Instant::now() < deadline {
+        thread::sleep(EXIT_POLL_INTERVAL);
+        let mut still_running = Vec::new();
+        for idx in running {
+            if children[idx].try_wait()?.is_some() {
+                termination.exited += 1;
+            } else {
+                still_running.push(idx);
+            }
+        }
+        running = still_running;
+    }
+    for idx in running {
+        children[idx].kill()?;
+        children[idx].wait()?;
+        termination.killed += 1;
+    }
+    Ok(termination)
+}
+
+#[cfg(unix)]
+fn send_hangup(child: &mut (dyn Child + Send + Sync)) -> io::Result<()> {
+    let Some(pid) = child.process_id() else {
+        return Ok(());
+    };
+    // SAFETY: kill(2) takes no pointers; the pid is our own child, not yet reaped.
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGHUP) } != 0 {
+        let error = io::Error::last_os_error();
+        // The child exited between the check and the signal.
+        if error.raw_os_error() != Some(libc::ESRCH) {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Windows has no hangup signal; closing the pseudo-console ends the shell,
+/// and the kill after the timeout covers the rest.
+#[cfg(not(unix))]
+fn send_hangup(_child: &mut (dyn Child + Send + Sync)) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_shells_that_ignore_hangup_are_killed() {
+        let mut polite = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut stubborn = Command::new("sh").args(["-c", "trap '' HUP; exec sleep 30"]).spawn().unwrap();
+        let mut finished = Command::new("true").spawn().unwrap();
+        finished.wait().unwrap();
+        // Let the shell install its trap before the signal arrives.
+        thread::sleep(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let termination = hang_up(&mut [&mut polite, &mut stubborn, &mut finished], Duration::from_millis(500)).unwrap();
+        assert_eq!(termination, Termination { exited: 2, killed: 1 });
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_cancelling_stops_every_task() {
+        let tasks = BackgroundTasks::new();
+        let (prefetch, search) = (tasks.token(), tasks.token());
+        assert!(!prefetch.is_cancelled());
+        tasks.cancel();
+        assert!(prefetch.is_cancelled() && search.is_cancelled() && tasks.is_cancelled());
+    }
+}
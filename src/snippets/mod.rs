@@ -0,0 +1,345 @@
+//! Snippets
+//!
+//! Snippets are command templates expanded into the input editor, either by
+//! typing a snippet's prefix and pressing Tab or by picking it from the
+//! completions popup. Bodies use the familiar editor syntax: `$1` and
+//! `${2:default}` are tab stops visited in order with `$0` last, a stop used
+//! more than once mirrors what is typed into its first occurrence, and
+//! `$CLIPBOARD`, `$CWD` and `$DATE` are filled in when the snippet expands.
+//! Any other `$NAME` is a shell variable and is left alone; `\$` and `\}`
+//! write a literal `$` and `}`.
+//!
+//! Snippets are read from `snippets.yaml` in the config directory and from
+//! Drive, where a YAML file with a `body` is a snippet.
+
+use crate::drive::{DriveManager, Snippet};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SnippetError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("YAML parsing error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Invalid body for snippet '{0}': {1}")]
+    InvalidBody(String, String),
+}
+
+/// Values of the built-in variables at the moment a snippet expands.
+#[derive(Debug, Clone, Default)]
+pub struct Builtins {
+    pub clipboard: String,
+    pub cwd: String,
+    pub date: String,
+}
+
+impl Builtins {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "CLIPBOARD" => Some(&self.clipboard),
+            "CWD" => Some(&self.cwd),
+            "DATE" => Some(&self.date),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Stop(u32),
+}
+
+/// A parsed snippet body, with its built-ins already filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+    /// The default of each stop, from the first `${n:default}` that gives one.
+    defaults: BTreeMap<u32, String>,
+}
+
+impl Template {
+    pub fn parse(body: &str, builtins: &Builtins) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut defaults = BTreeMap::new();
+        let mut text = String::new();
+        let chars: Vec<char> = body.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if matches!(chars.get(i + 1), Some('$' | '}' | '\\')) => {
+                    text.push(chars[i + 1]);
+                    i += 2;
+                    continue;
+                }
+                '$' if chars.get(i + 1).is_some_and(char::is_ascii_digit) => {
+                    let digits: String = chars[i + 1..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                    i += 1 + digits.len();
+                    let stop = digits.parse().map_err(|_| format!("tab stop ${} is too large", digits))?;
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Stop(stop));
+                    defaults.entry(stop).or_insert_with(String::new);
+                    continue;
+                }
+                '$' if chars.get(i + 1) == Some(&'{') && chars.get(i + 2).is_some_and(char::is_ascii_digit) => {
+                    let digits: String = chars[i + 2..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                    let stop = digits.parse().map_err(|_| format!("tab stop ${{{}}} is too large", digits))?;
+                    i += 2 + digits.len();
+                    let mut default = String::new();
+                    match chars.get(i) {
+                        Some('}') => {}
+                        Some(':') => {
+                            i += 1;
+                            let mut raw = String::new();
+                            while i < chars.len() && chars[i] != '}' {
+                                if chars[i] == '\\' && matches!(chars.get(i + 1), Some('$' | '}' | '\\')) {
+                                    raw.push(chars[i]);
+                                    i += 1;
+                                }
+                                raw.push(chars[i]);
+                                i += 1;
+                            }
+                            if i == chars.len() {
+                                return Err(format!("placeholder ${{{}: is not closed", stop));
+                            }
+                            // Defaults may use built-ins, as in `${1:$CLIPBOARD}`.
+                            default = Template::parse(&raw, builtins)?.render(&BTreeMap::new());
+                        }
+                        _ => return Err(format!("expected `}}` or `:` after ${{{}", stop)),
+                    }
+                    i += 1;
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                    segments.push(Segment::Stop(stop));
+                    let entry = defaults.entry(stop).or_insert_with(String::new);
+                    if entry.is_empty() {
+                        *entry = default;
+                    }
+                    continue;
+                }
+                '$' => {
+                    let braced = chars.get(i + 1) == Some(&'{');
+                    let start = i + 1 + usize::from(braced);
+                    let name: String = chars[start..].iter().take_while(|c| c.is_ascii_uppercase() || **c == '_').collect();
+                    let closed = !braced || chars.get(start + name.len()) == Some(&'}');
+                    if let (true, Some(value)) = (closed, builtins.get(&name)) {
+                        text.push_str(value);
+                        i = start + name.len() + usize::from(braced);
+                        continue;
+                    }
+                    text.push('$');
+                }
+                c => text.push(c),
+            }
+            i += 1;
+        }
+        segments.push(Segment::Text(text));
+        segments.retain(|segment| !matches!(segment, Segment::Text(text) if text.is_empty()));
+        Ok(Self { segments, defaults })
+    }
+
+    fn render(&self, values: &BTreeMap<u32, String>) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Stop(stop) => values.get(stop).map_or("", String::as_str),
+            })
+            .collect()
+    }
+}
+
+/// Where the cursor goes after a snippet expands or Tab moves on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Selects the text of a tab stop, so typing replaces its default.
+    Select(Range<usize>),
+    /// Places the cursor and ends the snippet.
+    Finish(usize),
+}
+
+/// An expanded snippet being filled in. Offsets are bytes into the whole
+/// command line, which is the text before the snippet, the snippet, and
+/// the text after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetSession {
+    template: Template,
+    values: BTreeMap<u32, String>,
+    /// Stops in the order Tab visits them, `$0` last.
+    order: Vec<u32>,
+    current: usize,
+    before: String,
+    after: String,
+}
+
+impl SnippetSession {
+    pub fn new(template: Template, before: &str, after: &str) -> Self {
+        let mut order: Vec<u32> = template.defaults.keys().copied().filter(|stop| *stop != 0).collect();
+        if template.defaults.contains_key(&0) {
+            order.push(0);
+        }
+        let values = template.defaults.clone();
+        Self { template, values, order, current: 0, before: before.to_string(), after: after.to_string() }
+    }
+
+    /// The whole command line with the snippet's current values.
+    pub fn text(&self) -> String {
+        format!("{}{}{}", self.before, self.template.render(&self.values), self.after)
+    }
+
+    /// The first occurrence of a stop, where it is typed into.
+    fn stop_range(&self, stop: u32) -> Range<usize> {
+        let mut offset = self.before.len();
+        for segment in &self.template.segments {
+            let len = match segment {
+                Segment::Text(text) => text.len(),
+                Segment::Stop(other) if *other == stop => return offset..offset + self.values[other].len(),
+                Segment::Stop(other) => self.values[other].len(),
+            };
+            offset += len;
+        }
+        offset..offset
+    }
+
+    /// Where the cursor goes for the current stop.
+    pub fn step(&self) -> Step {
+        match self.order.get(self.current) {
+            Some(0) => Step::Finish(self.stop_range(0).start),
+            Some(stop) => Step::Select(self.stop_range(*stop)),
+            None => Step::Finish(self.before.len() + self.template.render(&self.values).len()),
+        }
+    }
+
+    /// Moves to the next stop.
+    pub fn advance(&mut self) -> Step {
+        self.current += 1;
+        self.step()
+    }
+
+    /// Reads what was typed into the current stop from the edited command
+    /// line and returns the line with its mirrors updated. Returns `None`
+    /// when the edit was outside the stop, which ends the snippet.
+    pub fn sync(&mut self, text: &str) -> Option<String> {
+        let stop = *self.order.get(self.current)?;
+        let full = self.text();
+        let range = self.stop_range(stop);
+        let (head, tail) = (&full[..range.start], &full[range.end..]);
+        if text.len() < head.len() + tail.len() || !text.starts_with(head) || !text.ends_with(tail) {
+            return None;
+        }
+        self.values.insert(stop, text[head.len()..text.len() - tail.len()].to_string());
+        Some(self.text())
+    }
+
+    /// The end of the current stop, where the cursor stays while typing.
+    pub fn cursor(&self) -> usize {
+        self.order.get(self.current).map_or(self.before.len(), |stop| self.stop_range(*stop).end)
+    }
+}
+
+/// The snippets available in the input editor.
+#[derive(Debug, Clone, Default)]
+pub struct SnippetLibrary {
+    snippets: Vec<Snippet>,
+}
+
+impl SnippetLibrary {
+    /// Loads `snippets.yaml` and the snippets in Drive. Snippets that do not
+    /// parse are skipped with a warning; a Drive snippet replaces a file
+    /// snippet with the same prefix.
+    pub fn load(drive: &DriveManager) -> Self {
+        let path = snippets_path();
+        let mut snippets = match fs::read_to_string(&path) {
+            Ok(content) => parse_file(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        snippets.extend(drive.snippets());
+        let mut library = Self::default();
+        for snippet in snippets {
+            if let Err(e) = library.add(snippet) {
+                log::warn!("{}", e);
+            }
+        }
+        library
+    }
+
+    /// Adds a snippet after checking its body, replacing any snippet with
+    /// the same prefix.
+    pub fn add(&mut self, snippet: Snippet) -> Result<(), SnippetError> {
+        Template::parse(&snippet.body, &Builtins::default()).map_err(|e| SnippetError::InvalidBody(snippet.name.clone(), e))?;
+        self.snippets.retain(|existing| existing.prefix != snippet.prefix);
+        self.snippets.push(snippet);
+        Ok(())
+    }
+
+    pub fn get(&self, prefix: &str) -> Option<&Snippet> {
+        self.snippets.iter().find(|snippet| snippet.prefix == prefix)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Snippet> {
+        self.snippets.iter()
+    }
+}
+
+/// Reads a list of snippets, as in `snippets.yaml`.
+pub fn parse_file(content: &str) -> Result<Vec<Snippet>, SnippetError> {
+    Ok(serde_yaml::from_str(content)?)
+}
+
+pub fn snippets_path() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| Path::new(".").to_path_buf()).join("warpish").join("snippets.yaml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtins() -> Builtins {
+        Builtins { clipboard: "abc123".to_string(), cwd: "/srv/app".to_string(), date: "2024-05-01".to_string() }
+    }
+
+    #[test]
+    fn test_body_parses_stops_and_builtins() {
+        let template = Template::parse("cd $CWD && git cherry-pick ${1:$CLIPBOARD} # ${2} \\$1 $HOME ${HOME:-/}", &builtins()).unwrap();
+        let session = SnippetSession::new(template, "", "");
+        assert_eq!(session.text(), "cd /srv/app && git cherry-pick abc123 #  $1 $HOME ${HOME:-/}");
+        assert_eq!(session.step(), Step::Select(31..37));
+
+        assert!(Template::parse("echo ${1:oops", &builtins()).is_err());
+        assert!(Template::parse("echo ${1x}", &builtins()).is_err());
+    }
+
+    #[test]
+    fn test_stops_are_visited_in_order_and_mirrored() {
+        let template = Template::parse("docker run --name ${1:web} $2 && docker logs -f $1$0 ", &Builtins::default()).unwrap();
+        let mut session = SnippetSession::new(template, "sudo ", "");
+        assert_eq!(session.text(), "sudo docker run --name web  && docker logs -f web ");
+        assert_eq!(session.step(), Step::Select(23..26));
+
+        // Typing over the selected default updates its mirror.
+        assert_eq!(session.sync("sudo docker run --name api  && docker logs -f web ").as_deref(), Some("sudo docker run --name api  && docker logs -f api "));
+        assert_eq!(session.cursor(), 26);
+        assert_eq!(session.advance(), Step::Select(27..27));
+        assert_eq!(session.sync("sudo docker run --name api nginx && docker logs -f api ").as_deref(), Some("sudo docker run --name api nginx && docker logs -f api "));
+        assert_eq!(session.advance(), Step::Finish(54));
+        assert_eq!(session.sync("x"), None);
+    }
+
+    #[test]
+    fn test_file_snippets_load() {
+        let snippets = parse_file("- name: Checkout\n  prefix: gco\n  body: git checkout ${1:main}\n").unwrap();
+        let mut library = SnippetLibrary::default();
+        for snippet in snippets {
+            library.add(snippet).unwrap();
+        }
+        assert_eq!(library.get("gco").map(|snippet| snippet.name.as_str()), Some("Checkout"));
+        let broken = Snippet { name: "Broken".to_string(), prefix: "b".to_string(), body: "${1:".to_string(), description: String::new() };
+        assert!(library.add(broken).is_err());
+    }
+}
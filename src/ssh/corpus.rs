@@ -0,0 +1,219 @@
+//! Per-host command corpora for completions in SSH sessions.
+//!
+//! A second `ssh` connection lists the commands on the remote `PATH` and,
+//! when the user allows it, the tail of the remote shell history. The result
+//! is stored locally, so later sessions on the host complete its commands
+//! straight away, and is harvested again once it is a week old.
+
+use super::SshTarget;
+use crate::db;
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Seconds before a stored corpus is harvested again.
+const MAX_AGE_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Most history entries kept per host.
+const MAX_HISTORY: usize = 1000;
+
+/// Separates the command list from the history in the harvest output.
+const HISTORY_MARKER: &str = "__WARPISH_HISTORY__";
+
+/// Lists the executables on the remote `PATH`. Both scripts run under `sh`
+/// whatever the login shell is.
+const COMMANDS_SCRIPT: &str = r#"IFS=:; for d in $PATH; do ls -1 "$d" 2>/dev/null; done"#;
+/// Prints the ends of the bash, zsh and fish history files.
+const HISTORY_SCRIPT: &str = r#"for f in "$HOME/.bash_history" "$HOME/.zsh_history" "$HOME/.local/share/fish/fish_history"; do tail -n 1000 "$f" 2>/dev/null; done"#;
+
+/// What completions know about one remote host.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostCorpus {
+    pub host: String,
+    /// Executables on the remote `PATH`, sorted.
+    pub commands: Vec<String>,
+    /// Remote shell history, most recent first. Empty unless permitted.
+    pub history: Vec<String>,
+    pub harvested_at: i64,
+}
+
+impl HostCorpus {
+    pub fn is_stale(&self, now: i64) -> bool {
+        now - self.harvested_at > MAX_AGE_SECS
+    }
+
+    /// Reads the output of the harvest script.
+    pub fn parse(host: &str, output: &str, now: i64) -> Self {
+        let (commands, history) = output.split_once(HISTORY_MARKER).unwrap_or((output, ""));
+        let mut commands: Vec<String> = commands
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(str::to_string)
+            .collect();
+        commands.sort();
+        commands.dedup();
+
+        // History files are oldest first; keep the latest use of each command.
+        let mut seen = HashSet::new();
+        let history = history
+            .lines()
+            .rev()
+            .filter_map(history_command)
+            .filter(|command| seen.insert(command.clone()))
+            .take(MAX_HISTORY)
+            .collect();
+        Self { host: host.to_string(), commands, history, harvested_at: now }
+    }
+}
+
+/// The command in one line of a bash, zsh or fish history file. Timestamps
+/// and fish's metadata lines yield nothing.
+fn history_command(line: &str) -> Option<String> {
+    let command = if let Some(rest) = line.strip_prefix("- cmd: ") {
+        rest
+    } else if line.starts_with(": ") && line.contains(';') {
+        // zsh extended history: ": <start>:<elapsed>;<command>"
+        line.split_once(';')?.1
+    } else if line.starts_with("  ") || (line.starts_with('#') && line[1..].chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    } else {
+        line
+    };
+    let command = command.trim();
+    (!command.is_empty()).then(|| command.to_string())
+}
+
+/// The `ssh` command line that harvests a host. Batch mode makes it fail
+/// instead of asking for a password the user would never see.
+pub fn harvest_command(target: &SshTarget, include_history: bool) -> Vec<String> {
+    let mut argv: Vec<String> = ["ssh", "-o", "BatchMode=yes", "-o", "ConnectTimeout=5"].map(String::from).to_vec();
+    if let Some(port) = target.port {
+        argv.extend(["-p".to_string(), port.to_string()]);
+    }
+    if let Some(user) = &target.user {
+        argv.extend(["-l".to_string(), user.clone()]);
+    }
+    let mut script = COMMANDS_SCRIPT.to_string();
+    if include_history {
+        script.push_str(&format!("; echo {}; {}", HISTORY_MARKER, HISTORY_SCRIPT));
+    }
+    argv.extend([target.host.clone(), format!("sh -c '{}'", script)]);
+    argv
+}
+
+/// Harvests a host over a fresh connection.
+pub fn harvest(target: &SshTarget, include_history: bool, now: i64) -> std::io::Result<HostCorpus> {
+    let argv = harvest_command(target, include_history);
+    let output = Command::new(&argv[0]).args(&argv[1..]).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(std::io::Error::other(stderr));
+    }
+    Ok(HostCorpus::parse(&target.host, &String::from_utf8_lossy(&output.stdout), now))
+}
+
+/// The corpora of the hosts seen this session, loaded from the database on
+/// first use and harvested in the background.
+#[derive(Debug, Default)]
+pub struct HostCorpora {
+    loaded: HashMap<String, Option<HostCorpus>>,
+    in_flight: HashSet<String>,
+    finished: Arc<Mutex<Vec<HostCorpus>>>,
+}
+
+impl HostCorpora {
+    /// The stored corpus for `host`, if it has ever been harvested.
+    pub fn get(&mut self, conn: &Connection, host: &str) -> Option<&HostCorpus> {
+        self.loaded
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                db::load_host_corpus(conn, host).unwrap_or_else(|e| {
+                    log::warn!("Failed to load commands for {}: {}", host, e);
+                    None
+                })
+            })
+            .as_ref()
+    }
+
+    /// Whether `host` has no corpus, or an old one, and none is being harvested.
+    pub fn needs_harvest(&mut self, conn: &Connection, host: &str, now: i64) -> bool {
+        !self.in_flight.contains(host) && self.get(conn, host).is_none_or(|corpus| corpus.is_stale(now))
+    }
+
+    pub fn spawn_harvest(&mut self, target: SshTarget, include_history: bool, now: i64) {
+        self.in_flight.insert(target.host.clone());
+        let finished = self.finished.clone();
+        // A host that refuses stays in flight, so it is tried again next
+        // session rather than on every tick.
+        thread::spawn(move || match harvest(&target, include_history, now) {
+            Ok(corpus) => finished.lock().unwrap().push(corpus),
+            Err(e) => log::warn!("Failed to list commands on {}: {}", target.host, e),
+        });
+    }
+
+    /// Stores finished harvests. Returns the hosts whose corpus changed.
+    pub fn take_finished(&mut self, conn: &Connection) -> Vec<String> {
+        let finished = std::mem::take(&mut *self.finished.lock().unwrap());
+        finished
+            .into_iter()
+            .map(|corpus| {
+                self.in_flight.remove(&corpus.host);
+                if let Err(e) = db::save_host_corpus(conn, &corpus) {
+                    log::warn!("Failed to save commands for {}: {}", corpus.host, e);
+                }
+                let host = corpus.host.clone();
+                self.loaded.insert(host.clone(), Some(corpus));
+                host
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_harvest_output() {
+        let output = "\
+ls
+git
+kubectl
+git
+__WARPISH_HISTORY__
+#1700000000
+kubectl get pods
+: 1700000100:0;git status
+- cmd: kubectl logs web
+  when: 1700000200
+kubectl get pods
+";
+        let corpus = HostCorpus::parse("web1", output, 100);
+        assert_eq!(corpus.commands, vec!["git", "kubectl", "ls"]);
+        assert_eq!(corpus.history, vec!["kubectl get pods", "kubectl logs web", "git status"]);
+        assert!(!corpus.is_stale(100 + MAX_AGE_SECS));
+        assert!(corpus.is_stale(101 + MAX_AGE_SECS));
+    }
+
+    #[test]
+    fn test_corpus_round_trips_through_database() {
+        let conn = Connection::open_in_memory().unwrap();
+        db::create_host_corpus_tables(&conn).unwrap();
+        let mut corpora = HostCorpora::default();
+        assert!(corpora.needs_harvest(&conn, "web1", 0));
+
+        let target = SshTarget { user: Some("deploy".to_string()), host: "web1".to_string(), port: Some(2222) };
+        let argv = harvest_command(&target, false);
+        assert_eq!(argv[..7], ["ssh", "-o", "BatchMode=yes", "-o", "ConnectTimeout=5", "-p", "2222"]);
+        assert!(!argv.last().unwrap().contains(HISTORY_MARKER));
+
+        corpora.finished.lock().unwrap().push(HostCorpus::parse("web1", "kubectl\n", 50));
+        assert_eq!(corpora.take_finished(&conn), vec!["web1"]);
+        let mut reloaded = HostCorpora::default();
+        assert_eq!(reloaded.get(&conn, "web1").unwrap().commands, vec!["kubectl"]);
+        assert!(!reloaded.needs_harvest(&conn, "web1", 100));
+    }
+}
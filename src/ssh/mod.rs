@@ -0,0 +1,256 @@
+//! SSH Sessions
+//!
+//! This module works out whether a pane's shell is running on another
+//! machine. Two signals are combined: the host in the shell's OSC 7 reports,
+//! which remote shells with integration send along with their directory, and
+//! the pane's foreground process, which is `ssh` while a connection is open
+//! even when the remote shell reports nothing. The resulting session is shown
+//! in the prompt, stamped on blocks and used to switch off local file
+//! completion.
+
+pub mod corpus;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often the foreground process is inspected.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `ssh` options that take a value, from the OpenSSH manual.
+const OPTIONS_WITH_VALUE: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+
+/// The destination of an `ssh` command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl SshTarget {
+    /// Reads the destination from an `ssh` command line, `argv[0]` included.
+    /// Returns `None` when the program is not `ssh` or has no destination.
+    pub fn from_command(argv: &[String]) -> Option<Self> {
+        let (program, args) = argv.split_first()?;
+        if Path::new(program).file_name()? != "ssh" {
+            return None;
+        }
+        let (mut user, mut port) = (None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                let destination = args.next()?;
+                return Self::parse_destination(destination, user, port);
+            }
+            let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+                return Self::parse_destination(arg, user, port);
+            };
+            for (i, flag) in flags.char_indices() {
+                if !OPTIONS_WITH_VALUE.contains(flag) {
+                    continue;
+                }
+                let attached = &flags[i + flag.len_utf8()..];
+                let value = if attached.is_empty() { args.next()?.as_str() } else { attached };
+                match flag {
+                    'l' => user = Some(value.to_string()),
+                    'p' => port = value.parse().ok(),
+                    _ => {}
+                }
+                break;
+            }
+        }
+        None
+    }
+
+    /// Parses `[user@]host` or `ssh://[user@]host[:port]`; values from the
+    /// URL win over `-l` and `-p`.
+    fn parse_destination(destination: &str, user: Option<String>, port: Option<u16>) -> Option<Self> {
+        let (destination, is_url) = match destination.strip_prefix("ssh://") {
+            Some(rest) => (rest.trim_end_matches('/'), true),
+            None => (destination, false),
+        };
+        let (user, rest) = match destination.rsplit_once('@') {
+            Some((name, rest)) => (Some(name.to_string()), rest),
+            None => (user, destination),
+        };
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, url_port)) if is_url => (host, url_port.parse().ok().or(port)),
+            _ => (rest, port),
+        };
+        (!host.is_empty()).then(|| Self { user, host: host.to_string(), port })
+    }
+}
+
+/// A pane's connection to another machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSession {
+    pub user: Option<String>,
+    pub host: String,
+    /// The remote directory, once the remote shell has reported one.
+    pub cwd: Option<PathBuf>,
+}
+
+impl RemoteSession {
+    /// `user@host`, or just the host when the user is not known.
+    pub fn label(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RemoteSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.cwd {
+            Some(cwd) => write!(f, "{}:{}", self.label(), cwd.display()),
+            None => f.write_str(&self.label()),
+        }
+    }
+}
+
+/// Whether an OSC 7 host names this machine. Hosts are compared without
+/// their domain, since shells report either form.
+pub fn is_local_host(host: &str, local_host: &str) -> bool {
+    let short = |name: &str| name.split('.').next().unwrap_or_default().to_ascii_lowercase();
+    host.is_empty() || host == "localhost" || short(host) == short(local_host)
+}
+
+/// The name of this machine, as shells report it in OSC 7.
+pub fn local_hostname() -> String {
+    hostname::get().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Reads a process's command line. Linux has it in `/proc`; other Unix
+/// systems are asked through `ps`, which loses quoting around spaces.
+pub fn process_command_line(pid: u32) -> Option<Vec<String>> {
+    if cfg!(target_os = "linux") {
+        let raw = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        Some(
+            raw.split(|byte| *byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect(),
+        )
+    } else if cfg!(unix) {
+        let output = std::process::Command::new("ps")
+            .args(["-o", "command=", "-p", &pid.to_string()])
+            .output()
+            .ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).split_whitespace().map(str::to_string).collect())
+    } else {
+        None
+    }
+}
+
+/// Tracks one pane's remote session from its OSC 7 reports and foreground
+/// process.
+#[derive(Debug, Clone, Default)]
+pub struct SshDetector {
+    local_host: String,
+    /// The `ssh` command in the foreground when last inspected.
+    foreground: Option<SshTarget>,
+    /// The host and directory from the latest remote OSC 7 report.
+    reported: Option<(String, PathBuf)>,
+    last_probe: Option<Instant>,
+}
+
+impl SshDetector {
+    pub fn new(local_host: String) -> Self {
+        Self { local_host, ..Self::default() }
+    }
+
+    /// Records an OSC 7 report. Returns whether the directory is on this
+    /// machine, and so belongs in the pane's local directory history.
+    pub fn observe_cwd(&mut self, host: Option<&str>, cwd: &Path) -> bool {
+        match host.filter(|host| !is_local_host(host, &self.local_host)) {
+            Some(host) => {
+                self.reported = Some((host.to_string(), cwd.to_path_buf()));
+                false
+            }
+            None => {
+                self.reported = None;
+                true
+            }
+        }
+    }
+
+    /// Whether the foreground process is due to be inspected again.
+    pub fn probe_due(&mut self, now: Instant) -> bool {
+        if self.last_probe.is_some_and(|last| now.saturating_duration_since(last) < PROBE_INTERVAL) {
+            return false;
+        }
+        self.last_probe = Some(now);
+        true
+    }
+
+    /// Records the foreground process's command line. When an `ssh` that was
+    /// running has gone, the remote shell's reports go with it.
+    pub fn observe_foreground(&mut self, argv: &[String]) {
+        let target = SshTarget::from_command(argv);
+        if self.foreground.is_some() && target.is_none() {
+            self.reported = None;
+        }
+        self.foreground = target;
+    }
+
+    /// The `ssh` command in the pane's foreground, if any.
+    pub fn target(&self) -> Option<&SshTarget> {
+        self.foreground.as_ref()
+    }
+
+    /// The remote session, preferring what the remote shell reports over
+    /// the `ssh` command line, which may name an alias.
+    pub fn session(&self) -> Option<RemoteSession> {
+        let user = self.foreground.as_ref().and_then(|target| target.user.clone());
+        match (&self.reported, &self.foreground) {
+            (Some((host, cwd)), _) => Some(RemoteSession { user, host: host.clone(), cwd: Some(cwd.clone()) }),
+            (None, Some(target)) => Some(RemoteSession { user, host: target.host.clone(), cwd: None }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(command: &str) -> Vec<String> {
+        command.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_target_from_ssh_command_line() {
+        let target = |command: &str| SshTarget::from_command(&argv(command));
+        assert_eq!(
+            target("ssh -p 2222 -i ~/.ssh/id deploy@web1"),
+            Some(SshTarget { user: Some("deploy".to_string()), host: "web1".to_string(), port: Some(2222) })
+        );
+        assert_eq!(
+            target("/usr/bin/ssh -A -lroot -o ServerAliveInterval=60 bastion uptime"),
+            Some(SshTarget { user: Some("root".to_string()), host: "bastion".to_string(), port: None })
+        );
+        assert_eq!(target("ssh ssh://me@db:2200").unwrap().port, Some(2200));
+        assert_eq!(target("ssh -v"), None);
+        assert_eq!(target("sshd -D"), None);
+        assert_eq!(target("vim notes.txt"), None);
+    }
+
+    #[test]
+    fn test_session_from_reports_and_foreground() {
+        let mut detector = SshDetector::new("laptop.local".to_string());
+        assert!(detector.observe_cwd(Some("laptop"), Path::new("/home/me")));
+        assert!(detector.session().is_none());
+
+        detector.observe_foreground(&argv("ssh deploy@web1"));
+        assert_eq!(detector.session().unwrap().to_string(), "deploy@web1");
+
+        assert!(!detector.observe_cwd(Some("web1.example.com"), Path::new("/srv/app")));
+        assert_eq!(detector.session().unwrap().to_string(), "deploy@web1.example.com:/srv/app");
+
+        // The connection closes: the local shell is back in the foreground.
+        detector.observe_foreground(&argv("-bash"));
+        assert!(detector.session().is_none());
+    }
+}
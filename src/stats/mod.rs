@@ -0,0 +1,435 @@
+//! Activity Statistics
+//!
+//! This module aggregates command blocks and shell history into a daily or
+//! weekly activity summary: top commands, failure rate, busiest projects and
+//! the longest running commands. Summaries render as a markdown report that
+//! can be shown in a pane or exported to disk.
+
+use crate::config::{ReportSchedule, ReportsConfig};
+use chrono::{Local, TimeZone};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+const TOP_N: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum StatsError {
+    #[error("Failed to read history: {0}")]
+    History(#[from] rusqlite::Error),
+    #[error("Failed to write report: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    Daily,
+    Weekly,
+}
+
+impl ReportPeriod {
+    pub fn duration_secs(&self) -> u64 {
+        match self {
+            ReportPeriod::Daily => DAY_SECS,
+            ReportPeriod::Weekly => 7 * DAY_SECS,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportPeriod::Daily => "Daily",
+            ReportPeriod::Weekly => "Weekly",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandCount {
+    pub command: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectActivity {
+    pub directory: String,
+    pub commands: usize,
+    pub failures: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongCommand {
+    pub command: String,
+    pub directory: String,
+    pub duration: Duration,
+}
+
+/// A finished command as the summary sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityRecord {
+    pub command: String,
+    /// The directory the command ran in, empty when unknown.
+    pub directory: String,
+    pub failed: bool,
+    pub duration: Option<Duration>,
+    /// When the command finished, in unix seconds.
+    pub timestamp: u64,
+}
+
+/// Aggregated activity for a single reporting window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivitySummary {
+    pub period: ReportPeriod,
+    pub start: u64,
+    pub end: u64,
+    pub total_commands: usize,
+    pub blocks_recorded: usize,
+    pub failures: usize,
+    pub top_commands: Vec<CommandCount>,
+    pub busiest_projects: Vec<ProjectActivity>,
+    pub longest_commands: Vec<LongCommand>,
+}
+
+impl ActivitySummary {
+    /// Builds a summary of the `period` ending at `now` (unix seconds).
+    ///
+    /// History entries are the authoritative record of what ran and drive the
+    /// command counts; records carry exit status, timing and working directory.
+    /// When no history is available the records are counted instead.
+    pub fn build(records: &[ActivityRecord], history: &[(String, u64)], period: ReportPeriod, now: u64) -> Self {
+        let start = now.saturating_sub(period.duration_secs());
+        let in_window = |timestamp: u64| timestamp >= start && timestamp <= now;
+
+        let records: Vec<&ActivityRecord> = records.iter().filter(|r| in_window(r.timestamp)).collect();
+        let history: Vec<&str> = history
+            .iter()
+            .filter(|(_, timestamp)| in_window(*timestamp))
+            .map(|(command, _)| command.as_str())
+            .collect();
+
+        let commands: Vec<&str> = if history.is_empty() {
+            records.iter().map(|r| r.command.as_str()).collect()
+        } else {
+            history
+        };
+
+        let mut command_counts: HashMap<String, usize> = HashMap::new();
+        for command in &commands {
+            if let Some(program) = program_name(command) {
+                *command_counts.entry(program).or_insert(0) += 1;
+            }
+        }
+        let mut top_commands: Vec<CommandCount> = command_counts
+            .into_iter()
+            .map(|(command, count)| CommandCount { command, count })
+            .collect();
+        top_commands.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.command.cmp(&b.command)));
+        top_commands.truncate(TOP_N);
+
+        let failures = records.iter().filter(|r| r.failed).count();
+
+        let mut projects: HashMap<&str, ProjectActivity> = HashMap::new();
+        for record in &records {
+            let entry = projects
+                .entry(record.directory.as_str())
+                .or_insert_with(|| ProjectActivity {
+                    directory: record.directory.clone(),
+                    commands: 0,
+                    failures: 0,
+                });
+            entry.commands += 1;
+            if record.failed {
+                entry.failures += 1;
+            }
+        }
+        let mut busiest_projects: Vec<ProjectActivity> = projects.into_values().collect();
+        busiest_projects.sort_by(|a, b| b.commands.cmp(&a.commands).then_with(|| a.directory.cmp(&b.directory)));
+        busiest_projects.truncate(TOP_N);
+
+        let mut longest_commands: Vec<LongCommand> = records
+            .iter()
+            .filter_map(|r| {
+                r.duration.map(|duration| LongCommand {
+                    command: r.command.clone(),
+                    directory: r.directory.clone(),
+                    duration,
+                })
+            })
+            .collect();
+        longest_commands.sort_by(|a, b| b.duration.cmp(&a.duration));
+        longest_commands.truncate(TOP_N);
+
+        Self {
+            period,
+            start,
+            end: now,
+            total_commands: commands.len(),
+            blocks_recorded: records.len(),
+            failures,
+            top_commands,
+            busiest_projects,
+            longest_commands,
+        }
+    }
+
+    /// Fraction of recorded blocks in the window that exited with an error.
+    pub fn failure_rate(&self) -> f64 {
+        if self.blocks_recorded == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.blocks_recorded as f64
+        }
+    }
+
+    /// Renders the summary as a markdown document.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "# {} Activity Report\n\n_{} – {}_\n\n",
+            self.period.label(),
+            format_timestamp(self.start),
+            format_timestamp(self.end)
+        ));
+
+        out.push_str("## Overview\n\n");
+        out.push_str(&format!("- **Commands run:** {}\n", self.total_commands));
+        out.push_str(&format!("- **Failures:** {}\n", self.failures));
+        out.push_str(&format!(
+            "- **Failure rate:** {:.1}%\n\n",
+            self.failure_rate() * 100.0
+        ));
+
+        out.push_str("## Top Commands\n\n");
+        if self.top_commands.is_empty() {
+            out.push_str("_No commands recorded._\n\n");
+        } else {
+            out.push_str("| Command | Runs |\n|---|---|\n");
+            for entry in &self.top_commands {
+                out.push_str(&format!("| `{}` | {} |\n", entry.command, entry.count));
+            }
+            out.push('\n');
+        }
+
+        out.push_str("## Busiest Projects\n\n");
+        if self.busiest_projects.is_empty() {
+            out.push_str("_No project activity recorded._\n\n");
+        } else {
+            out.push_str("| Directory | Commands | Failures |\n|---|---|---|\n");
+            for project in &self.busiest_projects {
+                out.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    project.directory, project.commands, project.failures
+                ));
+            }
+            out.push('\n');
+        }
+
+        out.push_str("## Longest Commands\n\n");
+        if self.longest_commands.is_empty() {
+            out.push_str("_No timed commands recorded._\n");
+        } else {
+            for (i, entry) in self.longest_commands.iter().enumerate() {
+                out.push_str(&format!(
+                    "{}. `{}` — {} ({})\n",
+                    i + 1,
+                    entry.command,
+                    format_duration(entry.duration),
+                    entry.directory
+                ));
+            }
+        }
+
+        out
+    }
+
+    /// Writes the markdown report into `dir` and returns the file path.
+    pub fn export(&self, dir: &Path) -> Result<PathBuf, StatsError> {
+        std::fs::create_dir_all(dir)?;
+        let date = Local
+            .timestamp_opt(self.end as i64, 0)
+            .single()
+            .map(|dt| dt.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| self.end.to_string());
+        let file_name = format!("activity-{}-{}.md", self.period.label().to_lowercase(), date);
+        let path = dir.join(file_name);
+        std::fs::write(&path, self.to_markdown())?;
+        Ok(path)
+    }
+}
+
+/// Reads history from the database and summarizes it together with `records`.
+pub fn generate_report(
+    conn: &mut rusqlite::Connection,
+    records: &[ActivityRecord],
+    period: ReportPeriod,
+    now: u64,
+) -> Result<ActivitySummary, StatsError> {
+    let since = now.saturating_sub(period.duration_secs());
+    let history = crate::db::get_history_since(conn, since as i64)?
+        .into_iter()
+        .map(|(command, timestamp)| (command, timestamp.max(0) as u64))
+        .collect::<Vec<_>>();
+    Ok(ActivitySummary::build(records, &history, period, now))
+}
+
+/// Decides when a scheduled report should be generated.
+pub struct ReportScheduler {
+    config: ReportsConfig,
+    last_run: Option<u64>,
+}
+
+impl ReportScheduler {
+    pub fn new(config: ReportsConfig, last_run: Option<u64>) -> Self {
+        Self { config, last_run }
+    }
+
+    pub fn period(&self) -> Option<ReportPeriod> {
+        match self.config.schedule {
+            ReportSchedule::Off => None,
+            ReportSchedule::Daily => Some(ReportPeriod::Daily),
+            ReportSchedule::Weekly => Some(ReportPeriod::Weekly),
+        }
+    }
+
+    /// Returns the period to report on if a scheduled report is due at `now`.
+    pub fn due(&self, now: u64) -> Option<ReportPeriod> {
+        let period = self.period()?;
+        match self.last_run {
+            Some(last) if now.saturating_sub(last) < period.duration_secs() => None,
+            _ => Some(period),
+        }
+    }
+
+    pub fn mark_run(&mut self, now: u64) {
+        self.last_run = Some(now);
+    }
+
+    pub fn export_dir(&self) -> PathBuf {
+        report_dir(&self.config)
+    }
+}
+
+/// Directory reports are exported to, defaulting to the user's data directory.
+pub fn report_dir(config: &ReportsConfig) -> PathBuf {
+    config.export_dir.clone().unwrap_or_else(|| {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("warpish")
+            .join("reports")
+    })
+}
+
+/// When a report was last exported to `dir`, from the newest report file's
+/// modification time, so a restart doesn't export the scheduled one again.
+pub fn last_export(dir: &Path) -> Option<u64> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("activity-") && name.ends_with(".md")
+        })
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .filter_map(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+        .max()
+}
+
+/// Extracts the program name from a command line, skipping `sudo` and env assignments.
+fn program_name(command: &str) -> Option<String> {
+    command
+        .split_whitespace()
+        .find(|word| *word != "sudo" && !word.contains('='))
+        .map(|word| word.rsplit('/').next().unwrap_or(word).to_string())
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(command: &str, dir: &str, failed: bool, secs: u64, timestamp: u64) -> ActivityRecord {
+        ActivityRecord {
+            command: command.to_string(),
+            directory: dir.to_string(),
+            failed,
+            duration: Some(Duration::from_secs(secs)),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_summary_aggregates_blocks() {
+        let now = 10 * DAY_SECS;
+        let records = vec![
+            record("cargo build", "/src/app", false, 40, now - 10),
+            record("cargo test", "/src/app", true, 90, now - 20),
+            record("git status", "/src/lib", false, 1, now - 30),
+            record("make", "/old", true, 500, now - 2 * DAY_SECS),
+        ];
+        let summary = ActivitySummary::build(&records, &[], ReportPeriod::Daily, now);
+
+        assert_eq!(summary.total_commands, 3);
+        assert_eq!(summary.failures, 1);
+        assert!((summary.failure_rate() - 1.0 / 3.0).abs() < f64::EPSILON);
+        assert_eq!(summary.top_commands[0], CommandCount { command: "cargo".to_string(), count: 2 });
+        assert_eq!(summary.busiest_projects[0].directory, "/src/app");
+        assert_eq!(summary.busiest_projects[0].failures, 1);
+        assert_eq!(summary.longest_commands[0].command, "cargo test");
+    }
+
+    #[test]
+    fn test_history_drives_command_counts() {
+        let now = 10 * DAY_SECS;
+        let history = vec![
+            ("sudo apt update".to_string(), now - 100),
+            ("/usr/bin/git pull".to_string(), now - 50),
+            ("git push".to_string(), now - 40),
+            ("git log".to_string(), now - 8 * DAY_SECS),
+        ];
+        let summary = ActivitySummary::build(&[], &history, ReportPeriod::Weekly, now);
+
+        assert_eq!(summary.total_commands, 3);
+        assert_eq!(summary.top_commands[0], CommandCount { command: "git".to_string(), count: 2 });
+        assert_eq!(summary.top_commands[1].command, "apt");
+        assert!(summary.to_markdown().contains("# Weekly Activity Report"));
+    }
+
+    #[test]
+    fn test_scheduler_due() {
+        let config = ReportsConfig { schedule: ReportSchedule::Daily, export_dir: None };
+        let mut scheduler = ReportScheduler::new(config, None);
+        assert_eq!(scheduler.due(DAY_SECS), Some(ReportPeriod::Daily));
+        scheduler.mark_run(DAY_SECS);
+        assert_eq!(scheduler.due(DAY_SECS + 60), None);
+        assert_eq!(scheduler.due(2 * DAY_SECS), Some(ReportPeriod::Daily));
+        assert_eq!(ReportScheduler::new(ReportsConfig::default(), None).due(DAY_SECS), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(last_export(dir.path()), None);
+        let summary = ActivitySummary::build(&[], &[], ReportPeriod::Daily, DAY_SECS);
+        let path = summary.export(dir.path()).unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("activity-daily-19"));
+        assert!(last_export(dir.path()).is_some());
+    }
+}
@@ -0,0 +1,128 @@
+//! Offsets by grapheme cluster and widths in terminal columns.
+//!
+//! A grapheme cluster is what a reader sees as one character: `é` written
+//! as `e` and a combining accent, a flag made of two regional indicators,
+//! or a family emoji joined with zero-width joiners. Cursors should move
+//! over whole clusters, and a cluster takes the columns of its first
+//! character, or two for an emoji asked for with variation selector 16.
+//! Byte offsets inside a cluster are clipped to its edges.
+
+use crate::sum_tree::Bias;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+const EMOJI_PRESENTATION: char = '\u{fe0f}';
+const REGIONAL_INDICATORS: std::ops::RangeInclusive<char> = '\u{1f1e6}'..='\u{1f1ff}';
+
+/// The terminal columns `grapheme`, one cluster, takes.
+pub fn grapheme_width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return 0;
+    };
+    let width = first.width().unwrap_or(0);
+    let flag = REGIONAL_INDICATORS.contains(&first) && chars.next().is_some();
+    if width > 0 && (flag || grapheme.contains(EMOJI_PRESENTATION)) {
+        2
+    } else {
+        width
+    }
+}
+
+/// The terminal columns `s` takes.
+pub fn display_width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+/// The `n`th grapheme cluster of `s` and its byte offset.
+pub fn nth_grapheme(s: &str, n: usize) -> Option<(usize, &str)> {
+    s.grapheme_indices(true).nth(n)
+}
+
+/// `offset` moved out of the middle of a grapheme cluster, to its start
+/// with `Bias::Left` or its end with `Bias::Right`, and into `s`.
+pub fn clip_to_grapheme(s: &str, offset: usize, bias: Bias) -> usize {
+    if offset >= s.len() {
+        return s.len();
+    }
+    let (start, grapheme) = s
+        .grapheme_indices(true)
+        .take_while(|(start, _)| *start <= offset)
+        .last()
+        .unwrap_or((0, ""));
+    if start == offset || bias == Bias::Left {
+        start
+    } else {
+        start + grapheme.len()
+    }
+}
+
+/// The terminal columns taken by the bytes of `s` in `range`, widened to
+/// whole grapheme clusters.
+pub fn width_between(s: &str, range: Range<usize>) -> usize {
+    let start = clip_to_grapheme(s, range.start, Bias::Left);
+    let end = clip_to_grapheme(s, range.end.max(range.start), Bias::Right);
+    display_width(&s[start..end])
+}
+
+/// The longest start of `s` that fits in `width` columns without cutting
+/// a grapheme cluster; a wide character that would straddle the edge is
+/// left out.
+pub fn clip_to_width(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (start, grapheme) in s.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &s[..start];
+        }
+    }
+    s
+}
+
+/// `s` followed by enough spaces to fill `width` columns, like `{:<width$}`
+/// but counting columns rather than chars.
+pub fn pad_to_width(s: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        s,
+        " ".repeat(width.saturating_sub(display_width(s)))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "👨\u{200d}👩\u{200d}👧";
+
+    #[test]
+    fn test_widths_of_clusters() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width(FAMILY), 2);
+        assert_eq!(display_width("🇯🇵"), 2);
+        assert_eq!(display_width("\u{2764}\u{fe0f}"), 2);
+        assert_eq!(width_between("a日本", 2..5), 4);
+        assert_eq!(pad_to_width("日本", 6), "日本  ");
+    }
+
+    #[test]
+    fn test_grapheme_offsets() {
+        let text = format!("ae\u{301}{}z", FAMILY);
+        assert_eq!(nth_grapheme(&text, 1), Some((1, "e\u{301}")));
+        assert_eq!(nth_grapheme(&text, 2), Some((4, FAMILY)));
+        assert_eq!(nth_grapheme(&text, 3), Some((4 + FAMILY.len(), "z")));
+        assert_eq!(nth_grapheme(&text, 4), None);
+
+        assert_eq!(clip_to_grapheme(&text, 2, Bias::Left), 1);
+        assert_eq!(clip_to_grapheme(&text, 2, Bias::Right), 4);
+        assert_eq!(clip_to_grapheme(&text, 8, Bias::Right), 4 + FAMILY.len());
+        assert_eq!(clip_to_grapheme(&text, 4, Bias::Right), 4);
+
+        assert_eq!(clip_to_width(&text, 3), "ae\u{301}");
+        assert_eq!(clip_to_width(&text, 4), format!("ae\u{301}{}", FAMILY));
+        assert_eq!(clip_to_width("日本語", 5), "日本");
+    }
+}
@@ -2,9 +2,18 @@
 //! 
 //! This module provides utilities for efficient string manipulation,
 //! including operations like substring extraction, search, replace, and more.
+//! `StringManipulator` keeps its text in a [`Rope`], so edits cost
+//! O(log n), and offsets that land inside a multi-byte character are
+//! clipped to its edges instead of panicking. [`grapheme`] measures text
+//! by grapheme cluster and terminal column for cursors and layout.
 
-use std::ops::{Range, RangeBounds};
-use std::collections::HashMap;
+pub mod grapheme;
+pub mod rope;
+
+pub use grapheme::{clip_to_grapheme, clip_to_width, display_width, grapheme_width, nth_grapheme, pad_to_width, width_between};
+pub use rope::{Point, Rope};
+
+use std::ops::{Bound, Range, RangeBounds};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -48,64 +57,57 @@ impl StringOffset {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StringManipulator {
-    content: String,
+    content: Rope,
     offsets: Vec<StringOffset>,
 }
 
 impl StringManipulator {
     pub fn new(content: String) -> Self {
         Self {
-            content,
+            content: Rope::from(content),
             offsets: Vec::new(),
         }
     }
     
-    pub fn substring(&self, range: impl RangeBounds<usize>) -> &str {
-        let start = match range.start_bound() {
-            std::ops::Bound::Included(&bound) => bound,
-            std::ops::Bound::Excluded(&bound) => bound + 1,
-            std::ops::Bound::Unbounded => 0,
-        };
-        
-        let end = match range.end_bound() {
-            std::ops::Bound::Included(&bound) => bound + 1,
-            std::ops::Bound::Excluded(&bound) => bound,
-            std::ops::Bound::Unbounded => self.content.len(),
-        };
-        
-        &self.content[start..end]
+    /// The text in a byte range, widened to whole characters.
+    pub fn substring(&self, range: impl RangeBounds<usize>) -> String {
+        self.content.slice(byte_range(range, self.content.len()))
     }
     
     pub fn find_all(&self, pattern: &str) -> Vec<StringOffset> {
-        let mut offsets = Vec::new();
-        let mut start = 0;
-        
-        while let Some(pos) = self.content[start..].find(pattern) {
-            let absolute_pos = start + pos;
-            offsets.push(StringOffset::new(absolute_pos, absolute_pos + pattern.len()));
-            start = absolute_pos + pattern.len();
+        if pattern.is_empty() {
+            return Vec::new();
         }
-        
-        offsets
+        self.content
+            .to_string()
+            .match_indices(pattern)
+            .map(|(start, _)| StringOffset::new(start, start + pattern.len()))
+            .collect()
     }
     
     pub fn replace_all(&mut self, from: &str, to: &str) {
-        self.content = self.content.replace(from, to);
+        for offset in self.find_all(from).iter().rev() {
+            self.content.replace(offset.start..offset.end, to);
+        }
     }
     
     pub fn insert_at(&mut self, offset: usize, text: &str) {
         if offset <= self.content.len() {
-            self.content.insert_str(offset, text);
+            self.content.insert(offset, text);
         }
     }
     
     pub fn delete_range(&mut self, range: Range<usize>) {
         if range.start <= self.content.len() && range.end <= self.content.len() {
-            self.content.drain(range);
+            self.content.delete(range);
         }
     }
     
-    pub fn content(&self) -> &str {
+    pub fn content(&self) -> String {
+        self.content.to_string()
+    }
+    
+    pub fn rope(&self) -> &Rope {
         &self.content
     }
     
@@ -117,26 +119,32 @@ impl StringManipulator {
         self.content.is_empty()
     }
     
-    pub fn lines(&self) -> Vec<&str> {
-        self.content.lines().collect()
+    pub fn lines(&self) -> Vec<String> {
+        self.line_offsets()
+            .into_iter()
+            .map(|line| self.content.slice(line.start..line.end))
+            .collect()
     }
     
     pub fn line_offsets(&self) -> Vec<StringOffset> {
-        let mut offsets = Vec::new();
-        let mut start = 0;
-        
-        for line in self.content.lines() {
-            let end = start + line.len();
-            offsets.push(StringOffset::new(start, end));
-            start = end + 1; // +1 for newline character
+        // Like `str::lines`, a trailing newline doesn't start a line.
+        let mut rows = self.content.line_count();
+        if self.content.max_point().column == 0 {
+            rows -= 1;
         }
-        
-        offsets
+        (0..rows)
+            .map(|row| {
+                let line = self.content.line_range(row);
+                let end = if self.content.slice(line.end.saturating_sub(1)..line.end) == "\r" { line.end - 1 } else { line.end };
+                StringOffset::new(line.start, end)
+            })
+            .collect()
     }
     
     pub fn word_offsets(&self) -> Vec<StringOffset> {
+        let content = self.content.to_string();
         let mut offsets = Vec::new();
-        let mut chars = self.content.char_indices().peekable();
+        let mut chars = content.char_indices().peekable();
         
         while let Some((start, ch)) = chars.next() {
             if ch.is_alphabetic() {
@@ -159,32 +167,61 @@ impl StringManipulator {
     }
     
     pub fn validate_utf8(&self) -> bool {
-        std::str::from_utf8(self.content.as_bytes()).is_ok()
+        self.content.chunks().all(validate_utf8)
     }
     
     pub fn char_count(&self) -> usize {
-        self.content.chars().count()
+        self.content.char_count()
     }
     
     pub fn byte_count(&self) -> usize {
         self.content.len()
     }
+    
+    pub fn byte_to_char(&self, offset: usize) -> usize {
+        self.content.offset_to_char(offset)
+    }
+    
+    pub fn char_to_byte(&self, char_offset: usize) -> usize {
+        self.content.char_to_offset(char_offset)
+    }
+    
+    pub fn byte_to_point(&self, offset: usize) -> Point {
+        self.content.offset_to_point(offset)
+    }
+    
+    pub fn point_to_byte(&self, point: Point) -> usize {
+        self.content.point_to_offset(point)
+    }
 }
 
-// Convenience functions
-pub fn substring(s: &str, range: impl RangeBounds<usize>) -> &str {
+/// `range` as bytes of a `len`-byte text, clamped to it.
+fn byte_range(range: impl RangeBounds<usize>, len: usize) -> Range<usize> {
     let start = match range.start_bound() {
-        std::ops::Bound::Included(&bound) => bound,
-        std::ops::Bound::Excluded(&bound) => bound + 1,
-        std::ops::Bound::Unbounded => 0,
+        Bound::Included(&bound) => bound,
+        Bound::Excluded(&bound) => bound + 1,
+        Bound::Unbounded => 0,
     };
     
     let end = match range.end_bound() {
-        std::ops::Bound::Included(&bound) => bound + 1,
-        std::ops::Bound::Excluded(&bound) => bound,
-        std::ops::Bound::Unbounded => s.len(),
+        Bound::Included(&bound) => bound + 1,
+        Bound::Excluded(&bound) => bound,
+        Bound::Unbounded => len,
     };
     
+    start.min(len)..end.clamp(start.min(len), len)
+}
+
+// Convenience functions
+/// `s` in a byte range, widened to whole characters.
+pub fn substring(s: &str, range: impl RangeBounds<usize>) -> &str {
+    let Range { mut start, mut end } = byte_range(range, s.len());
+    while !s.is_char_boundary(start) {
+        start -= 1;
+    }
+    while !s.is_char_boundary(end) {
+        end += 1;
+    }
     &s[start..end]
 }
 
@@ -272,5 +309,24 @@ mod tests {
         assert_eq!(parts, vec!["one", "two", "three"]);
         
         assert!(validate_utf8("Valid UTF-8 text"));
+        assert_eq!(substring("añb", 2..4), "ñb");
+    }
+    
+    #[test]
+    fn test_multi_byte_edits() {
+        let mut manipulator = StringManipulator::new("naïve\r\ncafé ✓\n".to_string());
+        assert_eq!(manipulator.substring(3..4), "ï");
+        assert_eq!(manipulator.lines(), vec!["naïve", "café ✓"]);
+        assert_eq!(manipulator.char_count(), 14);
+        assert_eq!(manipulator.byte_to_char(8), 7);
+        assert_eq!(manipulator.char_to_byte(7), 8);
+        assert_eq!(manipulator.byte_to_point(13), Point::new(1, 5));
+        assert_eq!(manipulator.point_to_byte(Point::new(1, 99)), 17);
+        
+        manipulator.insert_at(4, "!");
+        manipulator.delete_range(12..13);
+        assert_eq!(manipulator.content(), "naï!ve\r\ncaf ✓\n");
+        manipulator.replace_all("✓", "ok");
+        assert_eq!(manipulator.rope().to_string(), "naï!ve\r\ncaf ok\n");
     }
 }
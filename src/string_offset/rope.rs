@@ -0,0 +1,417 @@
+//! A rope: text kept as small chunks in a `SumTree`, each summarized by its
+//! bytes, chars and lines. Edits rebuild only the chunks they touch, and
+//! converting between byte offsets, char offsets and row/column points
+//! takes a seek and a scan of one chunk. Offsets that fall inside a
+//! character are clipped to its edges rather than panicking.
+
+use crate::sum_tree::{Bias, Dimension, Item, SumTree, Summary};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::Range;
+
+/// The most bytes a chunk holds; shorter chunks left by edits are merged
+/// with a neighbour once under half of this.
+const MAX_CHUNK: usize = 128;
+
+/// A row and a byte column, both from 0.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Point {
+    pub row: usize,
+    pub column: usize,
+}
+
+impl Point {
+    pub fn new(row: usize, column: usize) -> Self {
+        Self { row, column }
+    }
+
+    /// Where `text` ends, measured from its start.
+    fn of(text: &str) -> Self {
+        match text.rfind('\n') {
+            Some(newline) => Self {
+                row: text.matches('\n').count(),
+                column: text.len() - newline - 1,
+            },
+            None => Self {
+                row: 0,
+                column: text.len(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CharOffset(pub usize);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextSummary {
+    pub bytes: usize,
+    pub chars: usize,
+    /// Where the text ends.
+    pub lines: Point,
+}
+
+impl TextSummary {
+    fn of(text: &str) -> Self {
+        Self {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            lines: Point::of(text),
+        }
+    }
+}
+
+impl Summary for TextSummary {
+    fn add_summary(&mut self, summary: &Self) {
+        self.bytes += summary.bytes;
+        self.chars += summary.chars;
+        self.lines.add_summary(summary);
+    }
+}
+
+impl Dimension<TextSummary> for usize {
+    fn add_summary(&mut self, summary: &TextSummary) {
+        *self += summary.bytes;
+    }
+}
+
+impl Dimension<TextSummary> for CharOffset {
+    fn add_summary(&mut self, summary: &TextSummary) {
+        self.0 += summary.chars;
+    }
+}
+
+impl Dimension<TextSummary> for Point {
+    fn add_summary(&mut self, summary: &TextSummary) {
+        if summary.lines.row > 0 {
+            self.row += summary.lines.row;
+            self.column = summary.lines.column;
+        } else {
+            self.column += summary.lines.column;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk(String);
+
+impl Item for Chunk {
+    type Summary = TextSummary;
+
+    fn summary(&self) -> TextSummary {
+        TextSummary::of(&self.0)
+    }
+}
+
+/// `text` cut into chunks at character boundaries.
+fn chunks(text: &str) -> SumTree<Chunk> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(MAX_CHUNK);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(Chunk(rest[..end].to_string()));
+        rest = &rest[end..];
+    }
+    chunks.into_iter().collect()
+}
+
+#[derive(Clone, Default)]
+pub struct Rope {
+    chunks: SumTree<Chunk>,
+}
+
+impl Rope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.chunks.summary().bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn char_count(&self) -> usize {
+        self.chunks.summary().chars
+    }
+
+    /// Lines, counting the one after the last newline even when empty.
+    pub fn line_count(&self) -> usize {
+        self.chunks.summary().lines.row + 1
+    }
+
+    /// Where the text ends.
+    pub fn max_point(&self) -> Point {
+        self.chunks.summary().lines
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &str> {
+        self.chunks.iter().map(|chunk| chunk.0.as_str())
+    }
+
+    /// `offset` moved out of the middle of a character, to its start with
+    /// `Bias::Left` or its end with `Bias::Right`, and into the text.
+    pub fn clip_offset(&self, offset: usize, bias: Bias) -> usize {
+        if offset >= self.len() {
+            return self.len();
+        }
+        let mut cursor = self.chunks.cursor::<usize>();
+        cursor.seek(&offset, Bias::Right);
+        let (Some(chunk), start) = (cursor.item(), *cursor.start()) else {
+            return self.len();
+        };
+        let mut local = offset - start;
+        while !chunk.0.is_char_boundary(local) {
+            match bias {
+                Bias::Left => local -= 1,
+                Bias::Right => local += 1,
+            }
+        }
+        start + local
+    }
+
+    /// Replaces the bytes in `range`, widened to whole characters, with
+    /// `text`.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        let start = self.clip_offset(range.start, Bias::Left);
+        let end = self.clip_offset(range.end.max(range.start), Bias::Right);
+        let mut cursor = self.chunks.cursor::<usize>();
+        let mut edited = cursor.slice(&start, Bias::Right);
+
+        let mut middle = String::new();
+        if let Some(chunk) = cursor.item() {
+            middle.push_str(&chunk.0[..start - *cursor.start()]);
+        }
+        middle.push_str(text);
+        cursor.seek(&end, Bias::Right);
+        if let Some(chunk) = cursor.item() {
+            if *cursor.start() < end || middle.len() < MAX_CHUNK / 2 {
+                middle.push_str(&chunk.0[end - *cursor.start()..]);
+                cursor.next();
+            }
+        }
+        if middle.len() < MAX_CHUNK / 2 {
+            if let Some(last) = edited.last().cloned() {
+                edited = edited.slice(0..edited.len() - 1);
+                middle.insert_str(0, &last.0);
+            }
+        }
+        edited.append(chunks(&middle));
+        edited.append(cursor.suffix());
+        self.chunks = edited;
+    }
+
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        self.replace(offset..offset, text);
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) {
+        self.replace(range, "");
+    }
+
+    /// The text in the byte `range`, widened to whole characters.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        let start = self.clip_offset(range.start, Bias::Left);
+        let end = self.clip_offset(range.end.max(range.start), Bias::Right);
+        let mut text = String::with_capacity(end - start);
+        let mut cursor = self.chunks.cursor::<usize>();
+        cursor.seek(&start, Bias::Right);
+        while let Some(chunk) = cursor.item() {
+            let chunk_start = *cursor.start();
+            if chunk_start >= end {
+                break;
+            }
+            text.push_str(
+                &chunk.0[start.saturating_sub(chunk_start)..(end - chunk_start).min(chunk.0.len())],
+            );
+            cursor.next();
+        }
+        text
+    }
+
+    /// The chunk holding byte `offset`, with where it starts in `D`, and
+    /// `offset` within it. At the end, the last chunk.
+    fn chunk_at<D: Dimension<TextSummary>>(&self, offset: usize) -> Option<(&str, D, usize)> {
+        let offset = self.clip_offset(offset, Bias::Left);
+        let mut cursor = self.chunks.cursor::<(usize, D)>();
+        cursor.seek(&offset, Bias::Left);
+        let chunk = cursor.item()?;
+        let (start, position) = cursor.start().clone();
+        Some((&chunk.0, position, offset - start))
+    }
+
+    pub fn offset_to_char(&self, offset: usize) -> usize {
+        self.chunk_at::<CharOffset>(offset)
+            .map_or(0, |(chunk, start, local)| {
+                start.0 + chunk[..local].chars().count()
+            })
+    }
+
+    pub fn char_to_offset(&self, char_offset: usize) -> usize {
+        let mut cursor = self.chunks.cursor::<(CharOffset, usize)>();
+        cursor.seek(&CharOffset(char_offset), Bias::Left);
+        let Some(chunk) = cursor.item() else {
+            return self.len();
+        };
+        let (CharOffset(chars), start) = *cursor.start();
+        start
+            + chunk
+                .0
+                .char_indices()
+                .nth(char_offset - chars)
+                .map_or(chunk.0.len(), |(local, _)| local)
+    }
+
+    pub fn offset_to_point(&self, offset: usize) -> Point {
+        self.chunk_at::<Point>(offset)
+            .map_or_else(Point::default, |(chunk, mut start, local)| {
+                start.add_summary(&TextSummary::of(&chunk[..local]));
+                start
+            })
+    }
+
+    /// The offset of `point`; a column past the end of its row is clipped
+    /// to the row's end.
+    pub fn point_to_offset(&self, point: Point) -> usize {
+        if point >= self.max_point() {
+            return self.len();
+        }
+        let mut cursor = self.chunks.cursor::<(Point, usize)>();
+        cursor.seek(&point, Bias::Right);
+        let Some(chunk) = cursor.item() else {
+            return self.len();
+        };
+        let (mut position, start) = *cursor.start();
+        for (local, c) in chunk.0.char_indices() {
+            if position >= point || c == '\n' && position.row == point.row {
+                return start + local;
+            }
+            if c == '\n' {
+                position = Point::new(position.row + 1, 0);
+            } else {
+                position.column += c.len_utf8();
+            }
+        }
+        start + chunk.0.len()
+    }
+
+    /// The bytes of line `row`, without its newline.
+    pub fn line_range(&self, row: usize) -> Range<usize> {
+        self.point_to_offset(Point::new(row, 0))..self.point_to_offset(Point::new(row, usize::MAX))
+    }
+}
+
+impl From<&str> for Rope {
+    fn from(text: &str) -> Self {
+        Self {
+            chunks: chunks(text),
+        }
+    }
+}
+
+impl From<String> for Rope {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl PartialEq for Rope {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.to_string() == other.to_string()
+    }
+}
+
+impl Serialize for Rope {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Rope::from(String::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_match_a_string() {
+        let mut text = "héllo wörld ✓\n".repeat(40);
+        let mut rope = Rope::from(text.as_str());
+        assert!(rope.chunks().count() > 1);
+
+        // Offsets inside `é` and `✓` are widened to the whole character.
+        rope.replace(2..2, "X");
+        text.replace_range(1..3, "X");
+        rope.delete(text.len() - 3..text.len() - 2);
+        text.replace_range(text.len() - 4..text.len() - 1, "");
+        assert_eq!(rope.slice(0..2), "hX");
+        assert_eq!(rope.slice(1..7), "Xllo w");
+
+        let floor = |text: &str, at: usize| {
+            (0..=at)
+                .rev()
+                .find(|at| text.is_char_boundary(*at))
+                .unwrap()
+        };
+        for i in 0..200 {
+            let at = floor(&text, (i * 37) % text.len());
+            rope.insert(at, "ß");
+            text.insert(at, 'ß');
+        }
+        let range = floor(&text, 100)..floor(&text, 1000);
+        rope.delete(range.clone());
+        text.replace_range(range, "");
+
+        assert_eq!(rope.to_string(), text);
+        assert_eq!(
+            (rope.len(), rope.char_count()),
+            (text.len(), text.chars().count())
+        );
+        assert_eq!(rope.line_count(), text.split('\n').count());
+    }
+
+    #[test]
+    fn test_coordinate_conversions() {
+        let rope = Rope::from("ab\nçd✓\n\nlast".repeat(20).as_str());
+        let text = rope.to_string();
+        for (offset, _) in text.char_indices().chain([(text.len(), ' ')]) {
+            let row = text[..offset].matches('\n').count();
+            let column = offset - text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+            let point = rope.offset_to_point(offset);
+            assert_eq!(point, Point::new(row, column), "at {}", offset);
+            assert_eq!(rope.point_to_offset(point), offset);
+
+            let chars = text[..offset].chars().count();
+            assert_eq!(rope.offset_to_char(offset), chars);
+            assert_eq!(rope.char_to_offset(chars), offset);
+        }
+        assert_eq!(rope.point_to_offset(Point::new(1, 100)), 9);
+        assert_eq!(rope.line_range(1), 3..9);
+        assert_eq!(rope.slice(rope.line_range(3)), "lastab");
+        assert_eq!(rope.offset_to_point(4), Point::new(1, 0));
+    }
+}
@@ -0,0 +1,186 @@
+//! CSV Summaries
+//!
+//! Recognises block output that is comma-, tab- or semicolon-separated and
+//! computes count, sum and mean for each numeric column locally. The result
+//! is shown as a collapsible footer under the block.
+
+use super::StructuredData;
+use crate::string_offset::{display_width, pad_to_width};
+
+const DELIMITERS: [char; 3] = [',', '\t', ';'];
+
+/// Statistics for one numeric column. Empty cells are not counted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub name: String,
+    pub count: usize,
+    pub sum: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvSummary {
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Splits `text` into records, honouring double-quoted fields (which may
+/// contain the delimiter, newlines and `""` escapes).
+fn parse_records(text: &str, delimiter: char) -> Option<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if in_quotes => in_quotes = false,
+            '"' if field.is_empty() => in_quotes = true,
+            c if in_quotes => field.push(c),
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    if in_quotes {
+        return None;
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    Some(records)
+}
+
+/// Returns the output as a table if every line splits into the same number
+/// (at least two) of fields with one of the supported delimiters. The first
+/// record is used as the header unless it is entirely numeric.
+pub fn detect(output: &str) -> Option<StructuredData> {
+    DELIMITERS.iter().find_map(|&delimiter| {
+        if !output.contains(delimiter) {
+            return None;
+        }
+        let mut records = parse_records(output, delimiter)?;
+        let width = records.first()?.len();
+        if records.len() < 2 || width < 2 || records.iter().any(|record| record.len() != width) {
+            return None;
+        }
+        let columns = if records[0].iter().all(|field| parse_number(field).is_some()) {
+            (1..=width).map(|i| format!("column {}", i)).collect()
+        } else {
+            records.remove(0).into_iter().map(|name| name.trim().to_string()).collect()
+        };
+        Some(StructuredData::Table { columns, rows: records })
+    })
+}
+
+fn parse_number(field: &str) -> Option<f64> {
+    field.trim().parse::<f64>().ok().filter(|value| value.is_finite())
+}
+
+/// Computes statistics for every column whose non-empty cells are all numbers.
+pub fn summarize(columns: &[String], rows: &[Vec<String>]) -> CsvSummary {
+    let columns = columns
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| {
+            let cells: Vec<&str> = rows
+                .iter()
+                .filter_map(|row| row.get(i).map(|cell| cell.trim()))
+                .filter(|cell| !cell.is_empty())
+                .collect();
+            let values: Vec<f64> = cells.iter().map(|cell| parse_number(cell)).collect::<Option<_>>()?;
+            if values.is_empty() {
+                return None;
+            }
+            let sum: f64 = values.iter().sum();
+            Some(ColumnStats {
+                name: name.clone(),
+                count: values.len(),
+                sum,
+                mean: sum / values.len() as f64,
+            })
+        })
+        .collect();
+    CsvSummary { rows: rows.len(), columns }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+impl CsvSummary {
+    /// The footer text shown under the block; a single line when folded.
+    pub fn footer(&self, folded: bool) -> String {
+        let heading = format!("CSV summary: {} rows, {} numeric columns", self.rows, self.columns.len());
+        if folded || self.columns.is_empty() {
+            return format!("▸ {}", heading);
+        }
+        let name_width = self.columns.iter().map(|column| display_width(&column.name)).max().unwrap_or(0).max(6);
+        let mut footer = format!("▾ {}\n  {:<name_width$}  {:>8}  {:>14}  {:>14}", heading, "column", "count", "sum", "mean");
+        for column in &self.columns {
+            footer.push_str(&format!(
+                "\n  {}  {:>8}  {:>14}  {:>14}",
+                pad_to_width(&column.name, name_width),
+                column.count,
+                format_number(column.sum),
+                format_number(column.mean)
+            ));
+        }
+        footer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_csv_with_quoted_fields() {
+        let output = "name,price,qty\n\"Widget, large\",10.50,2\nGadget,4,\n\"Say \"\"hi\"\"\",1.5,3\n";
+        let Some(StructuredData::Table { columns, rows }) = detect(output) else {
+            panic!("expected a table");
+        };
+        assert_eq!(columns, vec!["name", "price", "qty"]);
+        assert_eq!(rows[0][0], "Widget, large");
+        assert_eq!(rows[2][0], "Say \"hi\"");
+
+        let summary = summarize(&columns, &rows);
+        assert_eq!(summary.rows, 3);
+        assert_eq!(summary.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["price", "qty"]);
+        assert_eq!(summary.columns[0].sum, 16.0);
+        assert_eq!((summary.columns[1].count, summary.columns[1].mean), (2, 2.5));
+        assert_eq!(
+            summary.footer(false),
+            "▾ CSV summary: 3 rows, 2 numeric columns\n  column     count             sum            mean\n  price          3              16            5.33\n  qty            2               5            2.50"
+        );
+        assert_eq!(summary.footer(true), "▸ CSV summary: 3 rows, 2 numeric columns");
+    }
+
+    #[test]
+    fn test_ignores_non_tabular_output() {
+        assert!(detect("Compiling foo v0.1.0\nFinished dev, 2 targets\n").is_none());
+        assert!(detect("total 8\n-rw-r--r-- 1 me me 0 a.txt\n").is_none());
+        assert!(detect("a,b\n").is_none());
+        let Some(StructuredData::Table { columns, .. }) = detect("1\t2\n3\t4\n") else {
+            panic!("expected a table");
+        };
+        assert_eq!(columns, vec!["column 1", "column 2"]);
+    }
+}
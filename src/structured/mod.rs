@@ -0,0 +1,304 @@
+//! Structured Output Capture
+//!
+//! This module recognises common read-only CLI invocations and, alongside the
+//! normal human-readable run, executes a machine-readable variant of the same
+//! command (`docker ps --format '{{json .}}'`, `kubectl get -o json`,
+//! `cargo metadata --format-version 1`). The parsed result is attached to the
+//! block to power the table and JSON viewers; the terminal output the user
+//! sees is unchanged.
+
+pub mod csv;
+pub mod stacktrace;
+
+use crate::config::StructuredOutputConfig;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StructuredError {
+    #[error("Failed to run structured command: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Structured command timed out")]
+    Timeout,
+    #[error("Structured command exited with status {0}")]
+    Failed(i32),
+    #[error("Failed to parse structured output: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Machine-readable data captured for a block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StructuredData {
+    Table { columns: Vec<String>, rows: Vec<Vec<String>> },
+    Json(serde_json::Value),
+}
+
+/// Knows how to obtain and parse machine-readable output for one CLI.
+pub trait OutputAdapter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the machine-readable variant of `argv`, or `None` if the
+    /// invocation is not supported or already requests a specific format.
+    fn structured_command(&self, argv: &[String]) -> Option<Vec<String>>;
+
+    fn parse(&self, stdout: &str) -> Result<StructuredData, StructuredError>;
+}
+
+fn has_flag(argv: &[String], flags: &[&str]) -> bool {
+    argv.iter()
+        .any(|arg| flags.iter().any(|flag| arg == flag || arg.starts_with(&format!("{}=", flag))))
+}
+
+/// `docker ps` / `docker images` → one JSON object per line.
+pub struct DockerAdapter;
+
+impl OutputAdapter for DockerAdapter {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn structured_command(&self, argv: &[String]) -> Option<Vec<String>> {
+        let subcommand = argv.get(1)?.as_str();
+        if argv[0] != "docker" || !matches!(subcommand, "ps" | "images" | "volume" | "network") {
+            return None;
+        }
+        if matches!(subcommand, "volume" | "network") && argv.get(2).map(String::as_str) != Some("ls") {
+            return None;
+        }
+        if has_flag(argv, &["--format", "-q", "--quiet"]) {
+            return None;
+        }
+        let mut command = argv.to_vec();
+        command.extend(["--format".to_string(), "{{json .}}".to_string()]);
+        Some(command)
+    }
+
+    fn parse(&self, stdout: &str) -> Result<StructuredData, StructuredError> {
+        let objects = stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<serde_json::Value>)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(table_from_objects(&objects))
+    }
+}
+
+/// `kubectl get ...` → `-o json` list of resources.
+pub struct KubectlAdapter;
+
+impl OutputAdapter for KubectlAdapter {
+    fn name(&self) -> &'static str {
+        "kubectl"
+    }
+
+    fn structured_command(&self, argv: &[String]) -> Option<Vec<String>> {
+        if argv[0] != "kubectl" || argv.get(1).map(String::as_str) != Some("get") || argv.len() < 3 {
+            return None;
+        }
+        if has_flag(argv, &["-o", "--output", "-w", "--watch"]) || argv.iter().any(|arg| arg.starts_with("-o")) {
+            return None;
+        }
+        let mut command = argv.to_vec();
+        command.extend(["-o".to_string(), "json".to_string()]);
+        Some(command)
+    }
+
+    fn parse(&self, stdout: &str) -> Result<StructuredData, StructuredError> {
+        let value: serde_json::Value = serde_json::from_str(stdout)?;
+        let items = match value.get("items").and_then(|items| items.as_array()) {
+            Some(items) => items.clone(),
+            None => vec![value.clone()],
+        };
+        let columns = vec!["NAMESPACE", "KIND", "NAME", "STATUS", "CREATED"];
+        let rows = items
+            .iter()
+            .map(|item| {
+                let metadata = &item["metadata"];
+                vec![
+                    json_text(&metadata["namespace"]),
+                    json_text(&item["kind"]),
+                    json_text(&metadata["name"]),
+                    json_text(&item["status"]["phase"]),
+                    json_text(&metadata["creationTimestamp"]),
+                ]
+            })
+            .collect();
+        Ok(StructuredData::Table {
+            columns: columns.into_iter().map(String::from).collect(),
+            rows,
+        })
+    }
+}
+
+/// `cargo metadata` and `cargo tree` → `cargo metadata --format-version 1`.
+pub struct CargoMetadataAdapter;
+
+impl OutputAdapter for CargoMetadataAdapter {
+    fn name(&self) -> &'static str {
+        "cargo"
+    }
+
+    fn structured_command(&self, argv: &[String]) -> Option<Vec<String>> {
+        if argv[0] != "cargo" || !matches!(argv.get(1).map(String::as_str), Some("metadata") | Some("tree")) {
+            return None;
+        }
+        let mut command = vec![
+            "cargo".to_string(),
+            "metadata".to_string(),
+            "--format-version".to_string(),
+            "1".to_string(),
+        ];
+        if argv[1] == "tree" {
+            command.push("--no-deps".to_string());
+        } else {
+            command.extend(argv[2..].iter().filter(|arg| !arg.starts_with("--format-version")).cloned());
+        }
+        Some(command)
+    }
+
+    fn parse(&self, stdout: &str) -> Result<StructuredData, StructuredError> {
+        Ok(StructuredData::Json(serde_json::from_str(stdout)?))
+    }
+}
+
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Builds a table from JSON objects, using the first object's keys as columns.
+fn table_from_objects(objects: &[serde_json::Value]) -> StructuredData {
+    let columns: Vec<String> = objects
+        .first()
+        .and_then(|first| first.as_object())
+        .map(|first| first.keys().cloned().collect())
+        .unwrap_or_default();
+    let rows = objects
+        .iter()
+        .map(|object| columns.iter().map(|column| json_text(&object[column])).collect())
+        .collect();
+    StructuredData::Table { columns, rows }
+}
+
+/// Finds the adapter for a command line and runs its structured variant.
+pub struct StructuredCapture {
+    adapters: Vec<Box<dyn OutputAdapter>>,
+    timeout: Duration,
+}
+
+impl StructuredCapture {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            adapters: vec![Box::new(DockerAdapter), Box::new(KubectlAdapter), Box::new(CargoMetadataAdapter)],
+            timeout,
+        }
+    }
+
+    /// Returns a capture for the configured timeout, or `None` when disabled.
+    pub fn from_config(config: &StructuredOutputConfig) -> Option<Self> {
+        config
+            .enabled
+            .then(|| Self::new(Duration::from_secs(config.timeout_seconds)))
+    }
+
+    pub fn register(&mut self, adapter: Box<dyn OutputAdapter>) {
+        self.adapters.push(adapter);
+    }
+
+    /// Returns the adapter and structured argv for a command line. Pipelines,
+    /// redirections and command lists are never re-run.
+    pub fn plan(&self, command_line: &str) -> Option<(&dyn OutputAdapter, Vec<String>)> {
+        if command_line.contains(['|', '>', '<', ';', '&', '`', '$']) {
+            return None;
+        }
+        let argv = shellwords::split(command_line).ok()?;
+        if argv.is_empty() {
+            return None;
+        }
+        self.adapters
+            .iter()
+            .find_map(|adapter| adapter.structured_command(&argv).map(|command| (adapter.as_ref(), command)))
+    }
+
+    /// Runs the structured variant of `command_line` in `working_directory`.
+    /// Returns `None` when no adapter applies.
+    pub async fn capture(
+        &self,
+        command_line: &str,
+        working_directory: &str,
+    ) -> Option<Result<StructuredData, StructuredError>> {
+        let (adapter, argv) = self.plan(command_line)?;
+        let run = tokio::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .current_dir(working_directory)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let result = match tokio::time::timeout(self.timeout, run).await {
+            Err(_) => Err(StructuredError::Timeout),
+            Ok(Err(e)) => Err(StructuredError::Io(e)),
+            Ok(Ok(output)) if !output.status.success() => {
+                Err(StructuredError::Failed(output.status.code().unwrap_or(-1)))
+            }
+            Ok(Ok(output)) => adapter.parse(&String::from_utf8_lossy(&output.stdout)),
+        };
+        if let Err(e) = &result {
+            log::debug!("Structured capture via '{}' failed: {}", adapter.name(), e);
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(command: &str) -> Option<Vec<String>> {
+        StructuredCapture::new(Duration::from_secs(1))
+            .plan(command)
+            .map(|(_, argv)| argv)
+    }
+
+    #[test]
+    fn test_plans_structured_variants() {
+        assert_eq!(plan("docker ps -a").unwrap(), vec!["docker", "ps", "-a", "--format", "{{json .}}"]);
+        assert_eq!(plan("kubectl get pods -n web").unwrap().last().unwrap(), "json");
+        assert_eq!(plan("cargo tree").unwrap(), vec!["cargo", "metadata", "--format-version", "1", "--no-deps"]);
+    }
+
+    #[test]
+    fn test_skips_unsupported_or_unsafe_commands() {
+        assert!(plan("docker run ubuntu").is_none());
+        assert!(plan("docker ps --format '{{.ID}}'").is_none());
+        assert!(plan("kubectl get pods -o wide").is_none());
+        assert!(plan("kubectl delete pod web").is_none());
+        assert!(plan("docker ps | grep web").is_none());
+        assert!(plan("ls -la").is_none());
+    }
+
+    #[test]
+    fn test_parse_docker_lines_into_table() {
+        let stdout = "{\"ID\":\"abc\",\"Names\":\"web\"}\n{\"ID\":\"def\",\"Names\":\"db\"}\n";
+        let data = DockerAdapter.parse(stdout).unwrap();
+        assert_eq!(
+            data,
+            StructuredData::Table {
+                columns: vec!["ID".to_string(), "Names".to_string()],
+                rows: vec![vec!["abc".to_string(), "web".to_string()], vec!["def".to_string(), "db".to_string()]],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_kubectl_list() {
+        let stdout = r#"{"items":[{"kind":"Pod","metadata":{"name":"web-1","namespace":"prod"},"status":{"phase":"Running"}}]}"#;
+        match KubectlAdapter.parse(stdout).unwrap() {
+            StructuredData::Table { rows, .. } => assert_eq!(rows[0][..4], ["prod", "Pod", "web-1", "Running"]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Stack Traces
+//!
+//! Recognises Python, Node, Rust and Java stack traces in block output.
+//! Each runtime has a [`TraceAnalyzer`] that parses one frame at a time and
+//! decides whether it belongs to the user's code or to the standard library
+//! and dependencies. Library frames are folded away by default, and frames
+//! can be stepped through and opened in the editor.
+
+use crate::integration::editor::FileLocation;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref PYTHON_FRAME: Regex = Regex::new(r#"^\s*File "([^"]+)", line (\d+)(?:, in (.+))?$"#).unwrap();
+    static ref NODE_FRAME: Regex = Regex::new(r"^\s*at (?:(.+?) \()?(.+?):(\d+):(\d+)\)?$").unwrap();
+    static ref JAVA_FRAME: Regex = Regex::new(r"^\s*at ([\w$.<>/]+)\(([^)]*)\)$").unwrap();
+    static ref JAVA_SOURCE: Regex = Regex::new(r"^([\w$]+\.(?:java|kt|scala|groovy)):(\d+)$").unwrap();
+    static ref RUST_FRAME: Regex = Regex::new(r"^\s*\d+: (\S+)$").unwrap();
+    static ref RUST_SOURCE: Regex = Regex::new(r"^\s*at (.+):(\d+):(\d+)$").unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Python,
+    Node,
+    Rust,
+    Java,
+}
+
+/// One frame as read by an analyzer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedFrame {
+    pub function: Option<String>,
+    pub location: Option<FileLocation>,
+    /// Output lines the frame spans, e.g. two for Python's source line.
+    pub lines: usize,
+}
+
+/// Knows the frame format of one runtime.
+pub trait TraceAnalyzer: Send + Sync {
+    fn runtime(&self) -> Runtime;
+
+    /// The frame that starts at `lines[0]`, if that line starts one.
+    fn frame(&self, lines: &[&str]) -> Option<ParsedFrame>;
+
+    /// Whether the frame is in the standard library or a dependency rather
+    /// than in the user's code.
+    fn is_library(&self, frame: &ParsedFrame) -> bool;
+}
+
+fn path_contains(frame: &ParsedFrame, needles: &[&str]) -> bool {
+    frame.location.as_ref().is_some_and(|location| needles.iter().any(|needle| location.path.contains(needle)))
+}
+
+fn function_starts_with(frame: &ParsedFrame, prefixes: &[&str]) -> bool {
+    frame.function.as_ref().is_some_and(|function| prefixes.iter().any(|prefix| function.starts_with(prefix)))
+}
+
+/// `File "app/models.py", line 42, in save`, usually followed by the
+/// source line.
+pub struct PythonAnalyzer;
+
+impl TraceAnalyzer for PythonAnalyzer {
+    fn runtime(&self) -> Runtime {
+        Runtime::Python
+    }
+
+    fn frame(&self, lines: &[&str]) -> Option<ParsedFrame> {
+        let captures = PYTHON_FRAME.captures(lines.first()?)?;
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let has_source = lines.get(1).is_some_and(|next| {
+            !next.trim().is_empty() && indent(next) > indent(lines[0]) && !PYTHON_FRAME.is_match(next)
+        });
+        Some(ParsedFrame {
+            function: captures.get(3).map(|function| function.as_str().to_string()),
+            location: Some(FileLocation { path: captures[1].to_string(), line: captures[2].parse().ok()?, column: None }),
+            lines: if has_source { 2 } else { 1 },
+        })
+    }
+
+    fn is_library(&self, frame: &ParsedFrame) -> bool {
+        path_contains(frame, &["site-packages", "dist-packages", "/lib/python"])
+            || frame.location.as_ref().is_some_and(|location| location.path.starts_with('<'))
+    }
+}
+
+/// `at handler (/srv/app/index.js:3:14)` or `at /srv/app/index.js:3:14`.
+pub struct NodeAnalyzer;
+
+impl TraceAnalyzer for NodeAnalyzer {
+    fn runtime(&self) -> Runtime {
+        Runtime::Node
+    }
+
+    fn frame(&self, lines: &[&str]) -> Option<ParsedFrame> {
+        let captures = NODE_FRAME.captures(lines.first()?)?;
+        Some(ParsedFrame {
+            function: captures.get(1).map(|function| function.as_str().to_string()),
+            location: Some(FileLocation {
+                path: captures[2].trim_start_matches("file://").to_string(),
+                line: captures[3].parse().ok()?,
+                column: captures[4].parse().ok(),
+            }),
+            lines: 1,
+        })
+    }
+
+    fn is_library(&self, frame: &ParsedFrame) -> bool {
+        path_contains(frame, &["node_modules"])
+            || frame.location.as_ref().is_some_and(|location| location.path.starts_with("node:") || location.path.starts_with("internal/"))
+    }
+}
+
+/// `  12: app::main` from `RUST_BACKTRACE=1`, usually followed by
+/// `at src/main.rs:4:5`.
+pub struct RustAnalyzer;
+
+impl TraceAnalyzer for RustAnalyzer {
+    fn runtime(&self) -> Runtime {
+        Runtime::Rust
+    }
+
+    fn frame(&self, lines: &[&str]) -> Option<ParsedFrame> {
+        let captures = RUST_FRAME.captures(lines.first()?)?;
+        let function = captures[1].to_string();
+        let source = lines.get(1).and_then(|next| RUST_SOURCE.captures(next));
+        // Plain `1: foo` lines are too common to take without a path or a `::`.
+        if source.is_none() && !function.contains("::") {
+            return None;
+        }
+        Some(ParsedFrame {
+            function: Some(function),
+            location: source.as_ref().and_then(|source| {
+                Some(FileLocation { path: source[1].to_string(), line: source[2].parse().ok()?, column: source[3].parse().ok() })
+            }),
+            lines: if source.is_some() { 2 } else { 1 },
+        })
+    }
+
+    fn is_library(&self, frame: &ParsedFrame) -> bool {
+        path_contains(frame, &["/rustc/", "/.cargo/registry/", "/library/std/", "/library/core/"])
+            || function_starts_with(frame, &["std::", "core::", "alloc::", "tokio::", "__rust", "rust_begin_unwind", "_start", "__libc"])
+            || (frame.location.is_none() && frame.function.as_ref().is_some_and(|function| function.starts_with('<')))
+    }
+}
+
+/// `at com.example.Orders.place(Orders.java:42)`.
+pub struct JavaAnalyzer;
+
+impl TraceAnalyzer for JavaAnalyzer {
+    fn runtime(&self) -> Runtime {
+        Runtime::Java
+    }
+
+    fn frame(&self, lines: &[&str]) -> Option<ParsedFrame> {
+        let captures = JAVA_FRAME.captures(lines.first()?)?;
+        // `Native Method` and `Unknown Source` frames have no location.
+        let location = JAVA_SOURCE.captures(&captures[2]).and_then(|source| {
+            Some(FileLocation { path: source[1].to_string(), line: source[2].parse().ok()?, column: None })
+        });
+        Some(ParsedFrame { function: Some(captures[1].to_string()), location, lines: 1 })
+    }
+
+    fn is_library(&self, frame: &ParsedFrame) -> bool {
+        function_starts_with(
+            frame,
+            &["java.", "javax.", "jdk.", "sun.", "com.sun.", "kotlin.", "kotlinx.", "scala.", "org.junit.", "org.springframework.", "org.apache."],
+        // JDK modules prefix the class, as in `java.base/java.lang.Thread.run`.
+        ) || frame.function.as_ref().is_some_and(|function| function.contains('/') && !function.starts_with("app/"))
+    }
+}
+
+/// Java comes before Node: both start frames with `at`, but only Node puts
+/// a space before the parenthesis.
+const ANALYZERS: &[&dyn TraceAnalyzer] = &[&PythonAnalyzer, &JavaAnalyzer, &NodeAnalyzer, &RustAnalyzer];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    /// The output line the frame starts on.
+    pub line_idx: usize,
+    pub line_count: usize,
+    pub function: Option<String>,
+    pub location: Option<FileLocation>,
+    pub user_code: bool,
+}
+
+/// Consecutive frames of one runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackTrace {
+    pub runtime: Runtime,
+    pub frames: Vec<TraceFrame>,
+}
+
+/// Every stack trace in `output`, in order.
+pub fn analyze(output: &str) -> Vec<StackTrace> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut traces: Vec<StackTrace> = Vec::new();
+    let mut in_trace = false;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let Some((analyzer, parsed)) = ANALYZERS.iter().find_map(|analyzer| analyzer.frame(&lines[idx..]).map(|parsed| (analyzer, parsed))) else {
+            in_trace = false;
+            idx += 1;
+            continue;
+        };
+        let frame = TraceFrame {
+            line_idx: idx,
+            line_count: parsed.lines,
+            user_code: !analyzer.is_library(&parsed),
+            function: parsed.function,
+            location: parsed.location,
+        };
+        match traces.last_mut() {
+            Some(trace) if in_trace && trace.runtime == analyzer.runtime() => trace.frames.push(frame),
+            _ => traces.push(StackTrace { runtime: analyzer.runtime(), frames: vec![frame] }),
+        }
+        in_trace = true;
+        idx += parsed.lines;
+    }
+    traces
+}
+
+/// Every frame of every trace, in output order.
+pub fn frames(traces: &[StackTrace]) -> impl Iterator<Item = &TraceFrame> {
+    traces.iter().flat_map(|trace| trace.frames.iter())
+}
+
+/// The frame after (or before) `from`, or the first (or last) one when
+/// there is none yet. Library frames are skipped while they are folded.
+pub fn step_frame(traces: &[StackTrace], from: Option<usize>, forward: bool, fold_library: bool) -> Option<usize> {
+    let visible: Vec<usize> = frames(traces)
+        .enumerate()
+        .filter(|(_, frame)| frame.user_code || !fold_library)
+        .map(|(idx, _)| idx)
+        .collect();
+    match (from, forward) {
+        (None, true) => visible.first().copied(),
+        (None, false) => visible.last().copied(),
+        (Some(from), true) => visible.iter().copied().find(|idx| *idx > from).or(Some(from).filter(|from| visible.contains(from))),
+        (Some(from), false) => visible.iter().rev().copied().find(|idx| *idx < from).or(Some(from).filter(|from| visible.contains(from))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Text,
+    UserFrame,
+    LibraryFrame,
+    /// Stands in for a run of folded library frames.
+    Folded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayLine {
+    pub text: String,
+    pub kind: LineKind,
+    /// The index, among [`frames`], of the frame the line belongs to.
+    pub frame: Option<usize>,
+}
+
+/// The output as shown, with runs of library frames replaced by a single
+/// line while `fold_library` is set.
+pub fn display_lines(output: &str, traces: &[StackTrace], fold_library: bool) -> Vec<DisplayLine> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut owner: Vec<Option<usize>> = vec![None; lines.len()];
+    let all: Vec<&TraceFrame> = frames(traces).collect();
+    for (frame_idx, frame) in all.iter().enumerate() {
+        for slot in owner.iter_mut().skip(frame.line_idx).take(frame.line_count) {
+            *slot = Some(frame_idx);
+        }
+    }
+    let mut display: Vec<DisplayLine> = Vec::new();
+    let mut hidden = 0;
+    for (line_idx, (line, frame_idx)) in lines.iter().zip(&owner).enumerate() {
+        let frame = frame_idx.map(|idx| all[idx]);
+        if fold_library && frame.is_some_and(|frame| !frame.user_code) {
+            // Frames are counted once, on their first line.
+            hidden += usize::from(frame.is_some_and(|frame| frame.line_idx == line_idx));
+            continue;
+        }
+        if hidden > 0 {
+            display.push(folded_line(hidden));
+            hidden = 0;
+        }
+        let kind = match frame {
+            Some(frame) if frame.user_code => LineKind::UserFrame,
+            Some(_) => LineKind::LibraryFrame,
+            None => LineKind::Text,
+        };
+        display.push(DisplayLine { text: line.to_string(), kind, frame: *frame_idx });
+    }
+    if hidden > 0 {
+        display.push(folded_line(hidden));
+    }
+    display
+}
+
+fn folded_line(hidden: usize) -> DisplayLine {
+    DisplayLine { text: format!("    … {} library frame(s) hidden", hidden), kind: LineKind::Folded, frame: None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PYTHON: &str = concat!(
+        "Traceback (most recent call last):\n",
+        "  File \"/app/main.py\", line 12, in <module>\n",
+        "    run()\n",
+        "  File \"/usr/lib/python3.12/site-packages/click/core.py\", line 1157, in __call__\n",
+        "    return self.main(*args, **kwargs)\n",
+        "  File \"/usr/lib/python3.12/site-packages/click/core.py\", line 1078, in main\n",
+        "    rv = self.invoke(ctx)\n",
+        "  File \"/app/models.py\", line 42, in save\n",
+        "    raise ValueError(\"missing id\")\n",
+        "ValueError: missing id\n",
+    );
+
+    #[test]
+    fn test_traces_are_recognised_per_runtime() {
+        let traces = analyze(PYTHON);
+        assert_eq!(traces.len(), 1);
+        let user: Vec<bool> = traces[0].frames.iter().map(|frame| frame.user_code).collect();
+        assert_eq!(user, vec![true, false, false, true]);
+        assert_eq!(traces[0].frames[3].location.as_ref().map(ToString::to_string).as_deref(), Some("/app/models.py:42"));
+
+        let node = "TypeError: x is undefined\n    at handler (/srv/app/index.js:3:14)\n    at Layer.handle (/srv/app/node_modules/express/lib/router/layer.js:95:5)\n    at node:internal/process/task_queues:95:5\n";
+        let java = "java.lang.IllegalStateException: closed\n\tat com.example.Orders.place(Orders.java:42)\n\tat java.base/java.lang.Thread.run(Thread.java:833)\n\tat jdk.internal.reflect.NativeMethodAccessorImpl.invoke0(Native Method)\n";
+        let rust = "stack backtrace:\n   0: rust_begin_unwind\n             at /rustc/90b35a623/library/std/src/panicking.rs:645:5\n   1: app::orders::place\n             at ./src/orders.rs:17:9\n   2: core::ops::function::FnOnce::call_once\n";
+        for (output, runtime, user) in [
+            (node, Runtime::Node, vec![true, false, false]),
+            (java, Runtime::Java, vec![true, false, false]),
+            (rust, Runtime::Rust, vec![false, true, false]),
+        ] {
+            let traces = analyze(output);
+            assert_eq!(traces.len(), 1, "{:?}", runtime);
+            assert_eq!(traces[0].runtime, runtime);
+            assert_eq!(traces[0].frames.iter().map(|frame| frame.user_code).collect::<Vec<_>>(), user, "{:?}", runtime);
+        }
+        assert!(analyze("  1: first step\n  2: second step\n").is_empty());
+    }
+
+    #[test]
+    fn test_library_frames_fold_and_are_skipped() {
+        let traces = analyze(PYTHON);
+        let folded = display_lines(PYTHON, &traces, true);
+        let texts: Vec<&str> = folded.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts[3], "    … 2 library frame(s) hidden");
+        assert_eq!(folded.len(), 10 - 4 + 1);
+        assert_eq!(folded[4].kind, LineKind::UserFrame);
+        assert_eq!(folded[4].frame, Some(3));
+        assert_eq!(display_lines(PYTHON, &traces, false).len(), 10);
+
+        assert_eq!(step_frame(&traces, None, true, true), Some(0));
+        assert_eq!(step_frame(&traces, Some(0), true, true), Some(3));
+        assert_eq!(step_frame(&traces, Some(0), true, false), Some(1));
+        assert_eq!(step_frame(&traces, Some(3), true, true), Some(3));
+        assert_eq!(step_frame(&traces, None, false, true), Some(3));
+    }
+}
@@ -1,5 +1,20 @@
-use std::collections::HashSet;
+//! Syntax Parsing
+//!
+//! Two layers of syntax support. `SyntaxParser` splits a shell command line
+//! into command, flag and argument tokens using the commands on `PATH`.
+//! `SyntaxParserPool` keeps tree-sitter parsers per grammar and re-parses
+//! documents incrementally as they are edited, producing the highlight
+//! spans the renderer colors the input editor with and the ranges it
+//! underlines as invalid syntax. `simple_commands` lists every command a
+//! line would run, for vetting agent commands.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::ops::Range;
+use std::path::Path;
+use thiserror::Error;
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, Tree};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum TokenType {
@@ -74,4 +89,526 @@ impl SyntaxParser {
         }
         tokens
     }
-} 
\ No newline at end of file
+} 
+
+/// Command lines: commands, flags, strings, variables, pipes and redirects.
+/// The bundled bash query has no captures for flags or pipes.
+const BASH_HIGHLIGHT_QUERY: &str = r#"
+(comment) @comment
+[(string) (raw_string) (heredoc_body)] @string
+(command_name) @function
+(command argument: (word) @flag (#match? @flag "^-"))
+[(simple_expansion) (expansion) (variable_name)] @variable
+["|" "|&" "&&" "||" ">" ">>" "<" "&>"] @operator
+["if" "then" "else" "elif" "fi" "case" "esac" "for" "while" "until" "do" "done" "in" "function"
+ "export" "local" "declare" "readonly" "unset"] @keyword
+"#;
+
+const FISH_HIGHLIGHT_QUERY: &str = r#"
+(comment) @comment
+[(double_quote_string) (single_quote_string)] @string
+(command name: (word) @function)
+(command argument: (word) @flag (#match? @flag "^-"))
+(variable_expansion) @variable
+["|" "&&" "||"] @operator
+["if" "else" "end" "for" "in" "while" "function" "begin" "switch" "case"] @keyword
+"#;
+
+/// A tree-sitter grammar the parser pool can highlight with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Grammar {
+    /// Also used for zsh, whose command lines it parses well enough.
+    Bash,
+    Fish,
+    Rust,
+    Python,
+}
+
+impl Grammar {
+    /// The grammar for a file in a code review, by its extension.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "sh" | "bash" | "zsh" => Some(Grammar::Bash),
+            "fish" => Some(Grammar::Fish),
+            "rs" => Some(Grammar::Rust),
+            "py" | "pyi" => Some(Grammar::Python),
+            _ => None,
+        }
+    }
+
+    /// The grammar for the command line of `shell`, a path or a name.
+    pub fn for_shell(shell: &str) -> Self {
+        match Path::new(shell).file_stem().and_then(|name| name.to_str()) {
+            Some("fish") => Grammar::Fish,
+            _ => Grammar::Bash,
+        }
+    }
+
+    fn language(&self) -> tree_sitter::Language {
+        match self {
+            Grammar::Bash => tree_sitter_bash::language(),
+            Grammar::Fish => tree_sitter_fish::language(),
+            Grammar::Rust => tree_sitter_rust::language(),
+            Grammar::Python => tree_sitter_python::language(),
+        }
+    }
+
+    fn highlight_query(&self) -> &'static str {
+        match self {
+            Grammar::Bash => BASH_HIGHLIGHT_QUERY,
+            Grammar::Fish => FISH_HIGHLIGHT_QUERY,
+            Grammar::Rust => tree_sitter_rust::HIGHLIGHT_QUERY,
+            Grammar::Python => tree_sitter_python::HIGHLIGHT_QUERY,
+        }
+    }
+}
+
+/// What a highlighted span of source is, for the renderer to color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightKind {
+    Keyword,
+    Function,
+    Type,
+    String,
+    Constant,
+    Comment,
+    Variable,
+    Property,
+    Operator,
+    /// A command-line option such as `-la` or `--force`.
+    Flag,
+}
+
+impl HighlightKind {
+    /// Maps a highlight query capture such as `function.method` by its first part.
+    fn from_capture(name: &str) -> Option<Self> {
+        match name.split('.').next()? {
+            "keyword" => Some(HighlightKind::Keyword),
+            "function" => Some(HighlightKind::Function),
+            "type" | "constructor" | "attribute" => Some(HighlightKind::Type),
+            "string" | "escape" => Some(HighlightKind::String),
+            "number" | "constant" | "boolean" => Some(HighlightKind::Constant),
+            "comment" => Some(HighlightKind::Comment),
+            "variable" => Some(HighlightKind::Variable),
+            "property" | "label" => Some(HighlightKind::Property),
+            "operator" => Some(HighlightKind::Operator),
+            "flag" => Some(HighlightKind::Flag),
+            _ => None,
+        }
+    }
+}
+
+/// A highlighted byte range of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub range: Range<usize>,
+    pub kind: HighlightKind,
+}
+
+/// What a part of a command line does, for explaining it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPartKind {
+    /// The program a simple command runs.
+    Name,
+    /// An argument of the nearest `Name` before it.
+    Argument,
+    /// A redirect such as `> out.txt` or `2>&1`.
+    Redirect,
+    /// A variable set for a command, such as `RUST_LOG=debug`.
+    Assignment,
+    /// `|`, `&&`, `||`, `;` or `&`.
+    Operator,
+}
+
+/// A byte range of a command line and what it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandPart {
+    pub range: Range<usize>,
+    pub kind: CommandPartKind,
+}
+
+/// A simple command run anywhere in a command line, including inside
+/// `$(...)`, backticks and subshells, with its words unquoted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub argv: Vec<String>,
+    /// Redirects applied to it without their spaces, as `>out.log` or `2>&1`.
+    pub redirects: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum SyntaxParseError {
+    #[error("Incompatible grammar: {0}")]
+    Language(#[from] tree_sitter::LanguageError),
+    #[error("Invalid highlight query: {0}")]
+    Query(#[from] tree_sitter::QueryError),
+    #[error("Parsing was cancelled")]
+    Cancelled,
+}
+
+/// The row and byte column of `byte` in `text`.
+fn point_at(text: &str, byte: usize) -> Point {
+    let before = &text[..byte];
+    let row = before.matches('\n').count();
+    let column = before.rfind('\n').map_or(byte, |newline| byte - newline - 1);
+    Point { row, column }
+}
+
+/// The single edit that turns `old` into `new`: everything between their
+/// common prefix and common suffix was replaced.
+fn edit_between(old: &str, new: &str) -> Option<InputEdit> {
+    if old == new {
+        return None;
+    }
+    let mut prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old.bytes().rev().zip(new.bytes().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    Some(InputEdit {
+        start_byte: prefix,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: point_at(old, prefix),
+        old_end_position: point_at(old, old_end),
+        new_end_position: point_at(new, new_end),
+    })
+}
+
+struct Highlighter {
+    parser: Parser,
+    query: Query,
+}
+
+struct Document {
+    grammar: Grammar,
+    source: String,
+    tree: Tree,
+    highlights: Vec<HighlightSpan>,
+    errors: Vec<Range<usize>>,
+}
+
+/// Keeps a tree-sitter parser per grammar and a parsed tree per open
+/// document, such as the input editor or a file under review. Each update
+/// re-parses incrementally, so only the edited part of the tree is rebuilt.
+#[derive(Default)]
+pub struct SyntaxParserPool {
+    highlighters: HashMap<Grammar, Highlighter>,
+    documents: HashMap<String, Document>,
+}
+
+impl SyntaxParserPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the text of the document `key` and returns its highlights.
+    pub fn update(&mut self, key: &str, grammar: Grammar, source: &str) -> Result<&[HighlightSpan], SyntaxParseError> {
+        let highlighter = match self.highlighters.entry(grammar) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let mut parser = Parser::new();
+                parser.set_language(grammar.language())?;
+                let query = Query::new(grammar.language(), grammar.highlight_query())?;
+                entry.insert(Highlighter { parser, query })
+            }
+        };
+        let old_tree = match self.documents.remove(key) {
+            Some(document) if document.grammar == grammar && document.source == source => {
+                return Ok(&self.documents.entry(key.to_string()).or_insert(document).highlights);
+            }
+            Some(document) if document.grammar == grammar => edit_between(&document.source, source).map(|edit| {
+                let mut tree = document.tree;
+                tree.edit(&edit);
+                tree
+            }),
+            _ => None,
+        };
+        let tree = highlighter.parser.parse(source, old_tree.as_ref()).ok_or(SyntaxParseError::Cancelled)?;
+        let highlights = highlight(&highlighter.query, &tree, source);
+        let errors = syntax_errors(&tree, source);
+        let document = Document { grammar, source: source.to_string(), tree, highlights, errors };
+        Ok(&self.documents.entry(key.to_string()).or_insert(document).highlights)
+    }
+
+    /// The highlights from the last update of `key`.
+    pub fn highlights(&self, key: &str) -> &[HighlightSpan] {
+        self.documents.get(key).map_or(&[], |document| &document.highlights)
+    }
+
+    /// The byte ranges of `key` that do not parse, from its last update.
+    pub fn errors(&self, key: &str) -> &[Range<usize>] {
+        self.documents.get(key).map_or(&[], |document| &document.errors)
+    }
+
+    /// The parts of the command line in `key`, in order, from its last
+    /// update. Only the outermost commands are split up; a `$(...)` is one
+    /// argument.
+    pub fn command_parts(&self, key: &str) -> Vec<CommandPart> {
+        let mut parts = Vec::new();
+        if let Some(document) = self.documents.get(key) {
+            collect_command_parts(document.tree.root_node(), &mut parts);
+        }
+        parts
+    }
+
+    /// Forgets a document that was closed.
+    pub fn close(&mut self, key: &str) {
+        self.documents.remove(key);
+    }
+}
+
+/// Runs the highlight query, keeping the first capture where captures
+/// overlap, as the query files order their patterns by precedence.
+fn highlight(query: &Query, tree: &Tree, source: &str) -> Vec<HighlightSpan> {
+    let mut cursor = QueryCursor::new();
+    let mut spans: Vec<HighlightSpan> = Vec::new();
+    for (query_match, capture_idx) in cursor.captures(query, tree.root_node(), source.as_bytes()) {
+        let capture = query_match.captures[capture_idx];
+        let Some(kind) = HighlightKind::from_capture(&query.capture_names()[capture.index as usize]) else {
+            continue;
+        };
+        let range = capture.node.byte_range();
+        if spans.last().is_some_and(|last| range.start < last.range.end) {
+            continue;
+        }
+        spans.push(HighlightSpan { range, kind });
+    }
+    spans
+}
+
+fn collect_command_parts(node: Node, parts: &mut Vec<CommandPart>) {
+    let kind = match node.kind() {
+        "command" => {
+            let mut cursor = node.walk();
+            if !cursor.goto_first_child() {
+                return;
+            }
+            loop {
+                let child = cursor.node();
+                let kind = match (cursor.field_name(), child.kind()) {
+                    (Some("name"), _) => Some(CommandPartKind::Name),
+                    (Some("argument"), _) => Some(CommandPartKind::Argument),
+                    (_, "variable_assignment") => Some(CommandPartKind::Assignment),
+                    (_, "file_redirect" | "heredoc_redirect" | "herestring_redirect") => Some(CommandPartKind::Redirect),
+                    _ => None,
+                };
+                if let Some(kind) = kind {
+                    parts.push(CommandPart { range: child.byte_range(), kind });
+                }
+                if !cursor.goto_next_sibling() {
+                    return;
+                }
+            }
+        }
+        "file_redirect" | "heredoc_redirect" | "herestring_redirect" => CommandPartKind::Redirect,
+        "variable_assignment" => CommandPartKind::Assignment,
+        "|" | "|&" | "&&" | "||" | ";" | "&" if !node.is_named() => CommandPartKind::Operator,
+        _ => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                collect_command_parts(child, parts);
+            }
+            return;
+        }
+    };
+    parts.push(CommandPart { range: node.byte_range(), kind });
+}
+
+/// Every simple command a bash command line runs, in order, or `None` when
+/// the line does not parse.
+pub fn simple_commands(source: &str) -> Option<Vec<SimpleCommand>> {
+    let mut parser = Parser::new();
+    parser.set_language(tree_sitter_bash::language()).ok()?;
+    let tree = parser.parse(source, None)?;
+    if tree.root_node().has_error() {
+        return None;
+    }
+    let mut commands = Vec::new();
+    collect_simple_commands(tree.root_node(), source, &mut commands);
+    Some(commands)
+}
+
+fn collect_simple_commands(node: Node, source: &str, commands: &mut Vec<SimpleCommand>) {
+    let text = |node: Node| &source[node.byte_range()];
+    let mut cursor = node.walk();
+    match node.kind() {
+        "command" => {
+            let mut command = SimpleCommand { argv: Vec::new(), redirects: Vec::new() };
+            // Substitutions in the words run first, so they come first
+            let mut nested = Vec::new();
+            for child in node.children(&mut cursor) {
+                match child.kind() {
+                    "file_redirect" | "heredoc_redirect" | "herestring_redirect" => {
+                        command.redirects.push(text(child).split_whitespace().collect());
+                    }
+                    "variable_assignment" => {}
+                    _ => command.argv.push(unquote(text(child))),
+                }
+                collect_simple_commands(child, source, &mut nested);
+            }
+            commands.extend(nested);
+            commands.push(command);
+        }
+        "redirected_statement" => {
+            let mut body = Vec::new();
+            let mut redirects = Vec::new();
+            for child in node.children(&mut cursor) {
+                if child.kind().ends_with("_redirect") {
+                    redirects.push(text(child).split_whitespace().collect::<String>());
+                }
+                collect_simple_commands(child, source, &mut body);
+            }
+            for command in &mut body {
+                command.redirects.extend(redirects.iter().cloned());
+            }
+            commands.extend(body);
+        }
+        _ => {
+            for child in node.children(&mut cursor) {
+                collect_simple_commands(child, source, commands);
+            }
+        }
+    }
+}
+
+/// A word as the shell passes it on, quotes removed. Words that expand at
+/// run time, such as `$(...)`, are kept as written.
+fn unquote(word: &str) -> String {
+    match shellwords::split(word).as_deref() {
+        Ok([single]) => single.clone(),
+        _ => word.to_string(),
+    }
+}
+
+/// The ranges of error nodes, and of the character before each missing
+/// token, such as the closing quote of `echo "hi`. Only subtrees that
+/// contain an error are walked.
+fn syntax_errors(tree: &Tree, source: &str) -> Vec<Range<usize>> {
+    let mut errors = Vec::new();
+    let mut cursor = tree.walk();
+    if !cursor.node().has_error() {
+        return errors;
+    }
+    loop {
+        let node = cursor.node();
+        let descend = if node.is_error() {
+            errors.push(node.byte_range());
+            false
+        } else if node.is_missing() {
+            let start = node.start_byte();
+            if let Some((before, _)) = source[..start].char_indices().next_back() {
+                errors.push(before..start);
+            }
+            false
+        } else {
+            node.has_error()
+        };
+        if descend && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return errors;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_between_versions() {
+        let edit = edit_between("echo hi\nls -la", "echo hello\nls -la").unwrap();
+        assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (6, 7, 10));
+        assert_eq!(edit.new_end_position, Point { row: 0, column: 10 });
+        // Repeated characters are not counted twice.
+        let edit = edit_between("aaa", "aaaa").unwrap();
+        assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (3, 3, 4));
+        let edit = edit_between("echo é\n", "echo è\n").unwrap();
+        assert_eq!((edit.start_byte, edit.old_end_byte, edit.start_position), (5, 7, Point { row: 0, column: 5 }));
+        assert!(edit_between("ls", "ls").is_none());
+    }
+
+    #[test]
+    fn test_simple_commands_unquote_words_and_keep_redirects() {
+        let commands = simple_commands("FOO=1 grep 'a b' \"$(cat list)\" > out.txt 2>&1").unwrap();
+        let argvs: Vec<&[String]> = commands.iter().map(|command| command.argv.as_slice()).collect();
+        assert_eq!(argvs, [vec!["cat".to_string(), "list".to_string()], vec!["grep".to_string(), "a b".to_string(), "$(cat list)".to_string()]]);
+        assert_eq!(commands[1].redirects, [">out.txt", "2>&1"]);
+        assert!(simple_commands("echo 'unterminated").is_none());
+    }
+
+    #[test]
+    fn test_incremental_update_highlights_shell_input() {
+        let mut pool = SyntaxParserPool::new();
+        let kinds = |spans: &[HighlightSpan], source: &str| {
+            spans.iter().map(|span| (source[span.range.clone()].to_string(), span.kind)).collect::<Vec<_>>()
+        };
+        let source = "echo \"hi\" # greet";
+        let spans = pool.update("input", Grammar::Bash, source).unwrap().to_vec();
+        assert!(kinds(&spans, source).contains(&("echo".to_string(), HighlightKind::Function)));
+        assert!(kinds(&spans, source).contains(&("\"hi\"".to_string(), HighlightKind::String)));
+        assert!(kinds(&spans, source).contains(&("# greet".to_string(), HighlightKind::Comment)));
+
+        let source = "echo \"hi there\" # greet";
+        let spans = pool.update("input", Grammar::Bash, source).unwrap().to_vec();
+        assert!(kinds(&spans, source).contains(&("\"hi there\"".to_string(), HighlightKind::String)));
+        assert_eq!(pool.highlights("input"), spans.as_slice());
+        pool.close("input");
+        assert!(pool.highlights("input").is_empty());
+    }
+
+    #[test]
+    fn test_flags_pipes_and_syntax_errors() {
+        let mut pool = SyntaxParserPool::new();
+        let source = "ls -la $HOME | grep --count x";
+        let spans = pool.update("input", Grammar::for_shell("/usr/bin/zsh"), source).unwrap().to_vec();
+        let kind_of = |text: &str| spans.iter().find(|span| &source[span.range.clone()] == text).map(|span| span.kind);
+        assert_eq!(kind_of("-la"), Some(HighlightKind::Flag));
+        assert_eq!(kind_of("--count"), Some(HighlightKind::Flag));
+        assert_eq!(kind_of("$HOME"), Some(HighlightKind::Variable));
+        assert_eq!(kind_of("|"), Some(HighlightKind::Operator));
+        assert_eq!(kind_of("x"), None);
+        assert!(pool.errors("input").is_empty());
+
+        pool.update("input", Grammar::Bash, "ls | | wc").unwrap();
+        assert!(!pool.errors("input").is_empty());
+        assert_eq!(Grammar::for_shell("fish"), Grammar::Fish);
+    }
+
+    #[test]
+    fn test_command_parts_follow_the_tree() {
+        use CommandPartKind::*;
+        let mut pool = SyntaxParserPool::new();
+        let source = "RUST_LOG=debug cargo test -q 2>&1 | grep -v ok && echo $(date)";
+        pool.update("input", Grammar::Bash, source).unwrap();
+        let parts: Vec<(&str, CommandPartKind)> =
+            pool.command_parts("input").into_iter().map(|part| (&source[part.range], part.kind)).collect();
+        assert_eq!(
+            parts,
+            vec![
+                ("RUST_LOG=debug", Assignment),
+                ("cargo", Name),
+                ("test", Argument),
+                ("-q", Argument),
+                ("2>&1", Redirect),
+                ("|", Operator),
+                ("grep", Name),
+                ("-v", Argument),
+                ("ok", Argument),
+                ("&&", Operator),
+                ("echo", Name),
+                ("$(date)", Argument),
+            ]
+        );
+        assert!(pool.command_parts("missing").is_empty());
+    }
+}
@@ -86,7 +86,7 @@ impl SyntaxTree {
         visitor.visit_tree(self)
     }
     
-    pub fn transform<T: Transformer>(&mut self, transformer: &mut T) -> Result<(), SyntaxError> {
+    pub fn transform<T: Transformer>(&mut self, transformer: &mut T) -> Result<DiffPatch, SyntaxError> {
         transformer.transform_tree(self)
     }
     
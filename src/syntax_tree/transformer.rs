@@ -1,29 +1,705 @@
-// Transformer module for syntax tree transformation
+//! Syntax Tree Transformers
+//!
+//! Automated refactors of one file, found with tree-sitter queries:
+//! renaming a symbol everywhere it is spelled and extracting selected
+//! statements into a new function. A transformer rewrites the tree's source
+//! and returns the change as a `DiffPatch`, which is reviewed hunk by hunk
+//! like an agent's change before anything is written. Rust and Python are
+//! supported.
+
+use super::{Language, NodeType, Span, SyntaxError, SyntaxNode, SyntaxTree};
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use tree_sitter::{Node, Parser, Query, QueryCursor, Tree};
+
+/// The tree metadata key holding the path of the file the source is from.
+pub const PATH_METADATA: &str = "path";
+
+/// Unchanged lines shown around each change in a patch.
+const CONTEXT_LINES: usize = 3;
 
 pub trait Transformer {
-    type Input;
-    type Output;
-    
-    fn transform(&self, input: Self::Input) -> Self::Output;
+    /// Rewrites the tree's source and returns what changed.
+    fn transform_tree(&mut self, tree: &mut SyntaxTree) -> Result<DiffPatch, SyntaxError>;
+}
+
+/// A run of lines that were all kept, removed or added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub tag: ChangeTag,
+    pub original_text: String,
+    pub new_text: String,
+}
+
+/// A change to one file: the whole text before and after, and its hunks.
+/// Kept hunks are cut to the lines around the changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffPatch {
+    pub file_path: String,
+    pub description: String,
+    pub original: String,
+    pub modified: String,
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl DiffPatch {
+    pub fn new(file_path: String, description: String, original: String, modified: String) -> Self {
+        let hunks = diff_hunks(&original, &modified);
+        Self { file_path, description, original, modified, hunks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.original == self.modified
+    }
+
+    /// Writes the modified text, unless the file changed since the patch
+    /// was made.
+    pub fn apply(&self) -> Result<(), SyntaxError> {
+        let current = fs::read_to_string(&self.file_path)
+            .map_err(|e| SyntaxError::TransformError(format!("Could not read {}: {}", self.file_path, e)))?;
+        if current != self.original {
+            return Err(SyntaxError::TransformError(format!("{} changed since the patch was made", self.file_path)));
+        }
+        fs::write(&self.file_path, &self.modified)
+            .map_err(|e| SyntaxError::TransformError(format!("Could not write {}: {}", self.file_path, e)))
+    }
+}
+
+/// Reads a Rust or Python file into a tree the transformers can rewrite.
+pub fn load(path: &Path) -> Result<SyntaxTree, SyntaxError> {
+    let language = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("rs") => Language::Rust,
+        Some("py") => Language::Python,
+        _ => return Err(SyntaxError::TransformError(format!("Refactoring {} is not supported", path.display()))),
+    };
+    let source = fs::read_to_string(path)
+        .map_err(|e| SyntaxError::TransformError(format!("Could not read {}: {}", path.display(), e)))?;
+    let root = SyntaxNode::new(NodeType::Root, Span::new(0, source.len()), None, Vec::new());
+    let metadata = HashMap::from([(PATH_METADATA.to_string(), path.display().to_string())]);
+    Ok(SyntaxTree::new(root, language, source).with_metadata(metadata))
+}
+
+fn diff_hunks(original: &str, modified: &str) -> Vec<DiffHunk> {
+    let diff = TextDiff::from_lines(original, modified);
+    let mut runs: Vec<(ChangeTag, Vec<&str>)> = Vec::new();
+    for change in diff.iter_all_changes() {
+        match runs.last_mut() {
+            Some((tag, lines)) if *tag == change.tag() => lines.push(change.value()),
+            _ => runs.push((change.tag(), vec![change.value()])),
+        }
+    }
+
+    let last = runs.len().saturating_sub(1);
+    runs.iter()
+        .enumerate()
+        .filter_map(|(i, (tag, lines))| {
+            if *tag != ChangeTag::Equal {
+                let text = lines.concat();
+                let (original_text, new_text) =
+                    if *tag == ChangeTag::Delete { (text, String::new()) } else { (String::new(), text) };
+                return Some(DiffHunk { tag: *tag, original_text, new_text });
+            }
+            let head = if i == 0 { 0 } else { CONTEXT_LINES };
+            let tail = if i == last { 0 } else { CONTEXT_LINES };
+            let text = if lines.len() <= head + tail {
+                lines.concat()
+            } else if head > 0 && tail > 0 {
+                format!("{}⋮\n{}", lines[..head].concat(), lines[lines.len() - tail..].concat())
+            } else {
+                lines[..head].concat() + &lines[lines.len() - tail..].concat()
+            };
+            (!text.is_empty()).then(|| DiffHunk { tag: ChangeTag::Equal, original_text: text.clone(), new_text: text })
+        })
+        .collect()
+}
+
+fn grammar(language: &Language) -> Result<tree_sitter::Language, SyntaxError> {
+    match language {
+        Language::Rust => Ok(tree_sitter_rust::language()),
+        Language::Python => Ok(tree_sitter_python::language()),
+        other => Err(SyntaxError::TransformError(format!("Refactoring {:?} is not supported", other))),
+    }
+}
+
+/// Parses the tree's source, refusing files that do not parse cleanly so a
+/// refactor never works from a guess.
+fn parse(tree: &SyntaxTree) -> Result<Tree, SyntaxError> {
+    let mut parser = Parser::new();
+    parser.set_language(grammar(&tree.language)?).map_err(|e| SyntaxError::ParseError(e.to_string()))?;
+    let parsed = parser
+        .parse(&tree.source, None)
+        .ok_or_else(|| SyntaxError::ParseError("The parser gave up".to_string()))?;
+    if parsed.root_node().has_error() {
+        return Err(SyntaxError::ParseError("The file has syntax errors".to_string()));
+    }
+    Ok(parsed)
+}
+
+fn query(language: &Language, source: &str) -> Result<Query, SyntaxError> {
+    Query::new(grammar(language)?, source).map_err(|e| SyntaxError::TransformError(e.to_string()))
+}
+
+/// The nodes `query` captures under `node`.
+fn captures<'t>(query: &Query, node: Node<'t>, source: &'t str) -> Vec<Node<'t>> {
+    let mut cursor = QueryCursor::new();
+    cursor.matches(query, node, source.as_bytes()).flat_map(|m| m.captures.iter().map(|c| c.node).collect::<Vec<_>>()).collect()
+}
+
+fn text<'s>(node: Node, source: &'s str) -> &'s str {
+    &source[node.byte_range()]
+}
+
+/// Applies non-overlapping replacements to `source`.
+fn apply_edits(source: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut result = source.to_string();
+    for (range, replacement) in edits {
+        result.replace_range(range, &replacement);
+    }
+    result
+}
+
+/// Replaces the tree's source with `modified` and returns the patch.
+fn finish(tree: &mut SyntaxTree, description: String, modified: String) -> DiffPatch {
+    let path = tree.metadata.get(PATH_METADATA).cloned().unwrap_or_default();
+    let original = std::mem::replace(&mut tree.source, modified.clone());
+    DiffPatch::new(path, description, original, modified)
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for",
+    "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static",
+    "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or",
+    "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+fn check_identifier(name: &str, language: &Language) -> Result<(), SyntaxError> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') && chars.all(|c| c.is_alphanumeric() || c == '_');
+    let keywords = if *language == Language::Rust { RUST_KEYWORDS } else { PYTHON_KEYWORDS };
+    if !valid || keywords.contains(&name) {
+        return Err(SyntaxError::TransformError(format!("`{}` is not a valid name", name)));
+    }
+    Ok(())
+}
+
+/// Every node a symbol's name can be spelled in.
+fn name_query(language: &Language) -> &'static str {
+    match language {
+        Language::Rust => {
+            "[(identifier) (type_identifier) (field_identifier) (shorthand_field_identifier)] @name"
+        }
+        _ => "(identifier) @name",
+    }
+}
+
+/// Renames every identifier spelled `from` in the file to `to`. The rename
+/// is by name, not by binding: shadowed variables of the same name are
+/// renamed too, which the review shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameSymbol {
+    pub from: String,
+    pub to: String,
+}
+
+impl RenameSymbol {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self { from: from.into(), to: to.into() }
+    }
+}
+
+impl Transformer for RenameSymbol {
+    fn transform_tree(&mut self, tree: &mut SyntaxTree) -> Result<DiffPatch, SyntaxError> {
+        check_identifier(&self.to, &tree.language)?;
+        let parsed = parse(tree)?;
+        let names = query(&tree.language, name_query(&tree.language))?;
+
+        let mut edits = Vec::new();
+        for node in captures(&names, parsed.root_node(), &tree.source) {
+            let name = text(node, &tree.source);
+            if name == self.to {
+                return Err(SyntaxError::TransformError(format!("`{}` is already used in this file", self.to)));
+            }
+            if name == self.from {
+                edits.push((node.byte_range(), self.to.clone()));
+            }
+        }
+        if edits.is_empty() {
+            return Err(SyntaxError::TransformError(format!("Nothing is named `{}` in this file", self.from)));
+        }
+
+        let description = format!("Rename `{}` to `{}` ({} occurrences)", self.from, self.to, edits.len());
+        let modified = apply_edits(&tree.source, edits);
+        Ok(finish(tree, description, modified))
+    }
+}
+
+/// What extraction needs to know about a language's grammar.
+struct ExtractRules {
+    /// Nodes whose children are statements.
+    bodies: &'static [&'static str],
+    functions: &'static [&'static str],
+    loops: &'static [&'static str],
+    /// Patterns that bind names, captured as `@pattern`; Rust captures the
+    /// declared type as `@type`.
+    bindings: &'static str,
+    /// Exits that would leave the new function instead of the caller.
+    returns: &'static [&'static str],
+    loop_exits: &'static [&'static str],
 }
 
-pub struct SyntaxTreeTransformer {
-    // Placeholder for transformer implementation
+const RUST_RULES: ExtractRules = ExtractRules {
+    bodies: &["block"],
+    functions: &["function_item", "closure_expression"],
+    loops: &["loop_expression", "while_expression", "for_expression"],
+    bindings: "(let_declaration pattern: (_) @pattern type: (_)? @type)
+               (parameter pattern: (_) @pattern type: (_) @type)
+               (for_expression pattern: (_) @pattern)
+               (assignment_expression left: (identifier) @pattern)
+               (compound_assignment_expr left: (identifier) @pattern)",
+    returns: &["return_expression", "try_expression", "self"],
+    loop_exits: &["break_expression", "continue_expression"],
+};
+
+const PYTHON_RULES: ExtractRules = ExtractRules {
+    bodies: &["block", "module"],
+    functions: &["function_definition", "lambda"],
+    loops: &["for_statement", "while_statement"],
+    bindings: "(assignment left: (_) @pattern)
+               (augmented_assignment left: (_) @pattern)
+               (for_statement left: (_) @pattern)
+               (parameters (identifier) @pattern)
+               (default_parameter name: (_) @pattern)
+               (typed_parameter (identifier) @pattern)
+               (typed_default_parameter name: (_) @pattern)",
+    returns: &["return_statement", "yield"],
+    loop_exits: &["break_statement", "continue_statement"],
+};
+
+/// A name bound at `position`, with its declared type when there is one.
+#[derive(Debug, Clone)]
+struct Binding {
+    name: String,
+    position: usize,
+    ty: Option<String>,
+    mutable: bool,
+}
+
+/// The names a pattern binds: plain identifiers, through tuples and lists,
+/// but not attribute or index targets.
+fn pattern_names<'t>(node: Node<'t>, names: &mut Vec<Node<'t>>) {
+    match node.kind() {
+        "identifier" => names.push(node),
+        "tuple_pattern" | "mut_pattern" | "pattern_list" | "tuple" | "list" | "list_pattern" | "parenthesized_expression" => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                pattern_names(child, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn walk<'t>(node: Node<'t>, visit: &mut impl FnMut(Node<'t>)) {
+    visit(node);
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(child, visit);
+    }
+}
+
+/// Whether `node` reads or writes a variable, as opposed to naming a field,
+/// attribute or keyword argument.
+fn is_variable(node: Node) -> bool {
+    if node.kind() == "shorthand_field_identifier" {
+        return true;
+    }
+    if node.kind() != "identifier" {
+        return false;
+    }
+    let Some(parent) = node.parent() else { return true };
+    let field_of = |field: &str| parent.child_by_field_name(field).is_some_and(|child| child.id() == node.id());
+    !match parent.kind() {
+        "attribute" => field_of("attribute"),
+        "keyword_argument" => field_of("name"),
+        _ => false,
+    }
+}
+
+fn line_start(source: &str, position: usize) -> usize {
+    source[..position].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+fn indentation(source: &str, position: usize) -> &str {
+    let start = line_start(source, position);
+    let line = &source[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// `text` with `from` indentation swapped for `to` on every line.
+fn reindent(text: &str, from: &str, to: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", to, line.strip_prefix(from).unwrap_or(line.trim_start()))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Moves whole statements into a new function and calls it in their place.
+/// Variables bound before the selection and used in it become parameters;
+/// variables bound in it and used after it are returned. In Rust those
+/// variables need declared types, and parameters are passed by value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractFunction {
+    /// Byte range of the selection; it is widened to the statements it
+    /// touches.
+    pub selection: Range<usize>,
+    pub name: String,
+}
+
+impl ExtractFunction {
+    pub fn new(selection: Range<usize>, name: impl Into<String>) -> Self {
+        Self { selection, name: name.into() }
+    }
+
+    /// Selects lines `first..=last`, counted from 1.
+    pub fn lines(source: &str, first: usize, last: usize, name: impl Into<String>) -> Self {
+        let offsets: Vec<usize> =
+            std::iter::once(0).chain(source.match_indices('\n').map(|(i, _)| i + 1)).chain([source.len()]).collect();
+        let start = offsets[first.saturating_sub(1).min(offsets.len() - 1)];
+        let end = offsets[last.min(offsets.len() - 1)];
+        Self::new(start..end, name)
+    }
+}
+
+fn fail<T>(message: impl Into<String>) -> Result<T, SyntaxError> {
+    Err(SyntaxError::TransformError(message.into()))
+}
+
+impl Transformer for ExtractFunction {
+    fn transform_tree(&mut self, tree: &mut SyntaxTree) -> Result<DiffPatch, SyntaxError> {
+        check_identifier(&self.name, &tree.language)?;
+        let rust = tree.language == Language::Rust;
+        let rules = if rust { &RUST_RULES } else { &PYTHON_RULES };
+        let parsed = parse(tree)?;
+        let source = tree.source.as_str();
+        let root = parsed.root_node();
+
+        let selected = source.get(self.selection.clone()).unwrap_or_default();
+        let start = self.selection.start + (selected.len() - selected.trim_start().len());
+        let end = self.selection.start + selected.trim_end().len();
+        if start >= end {
+            return fail("Nothing is selected");
+        }
+
+        // The innermost statement list holding the whole selection, and the
+        // statements in it the selection touches.
+        let mut body = root.descendant_for_byte_range(start, end);
+        while let Some(node) = body {
+            if rules.bodies.contains(&node.kind()) && node.start_byte() <= start && node.end_byte() >= end {
+                break;
+            }
+            body = node.parent();
+        }
+        let Some(body) = body else { return fail("Select whole statements") };
+        let mut cursor = body.walk();
+        let statements: Vec<Node> =
+            body.named_children(&mut cursor).filter(|node| node.end_byte() > start && node.start_byte() < end).collect();
+        let (Some(first), Some(last)) = (statements.first(), statements.last()) else {
+            return fail("Select whole statements");
+        };
+        let (start, end) = (first.start_byte(), last.end_byte());
+        if rust && body.named_child(body.named_child_count().saturating_sub(1)).is_some_and(|tail| tail.id() == last.id()) {
+            let statement = last.kind() == "expression_statement" || last.kind().ends_with("_declaration") || last.kind().ends_with("_item");
+            if !statement {
+                return fail("The selection ends in the block's value; select the statements before it");
+            }
+        }
+        if !source[line_start(source, start)..start].trim().is_empty() {
+            return fail("The selection must start on its own line");
+        }
+
+        let scope = {
+            let mut node = Some(body);
+            while let Some(candidate) = node {
+                if rules.functions.contains(&candidate.kind()) {
+                    break;
+                }
+                node = candidate.parent();
+            }
+            node.unwrap_or(root)
+        };
+        if rust && scope.id() == root.id() {
+            return fail("Only statements inside a function can be extracted");
+        }
+
+        let mut exit = None;
+        walk(root, &mut |node| {
+            if exit.is_some() || node.start_byte() < start || node.end_byte() > end {
+                return;
+            }
+            if rules.returns.contains(&node.kind()) {
+                exit = Some(node.kind());
+            } else if rules.loop_exits.contains(&node.kind()) {
+                let mut ancestor = node.parent();
+                while let Some(candidate) = ancestor {
+                    if rules.loops.contains(&candidate.kind()) || rules.functions.contains(&candidate.kind()) {
+                        break;
+                    }
+                    ancestor = candidate.parent();
+                }
+                if ancestor.is_none_or(|ancestor| ancestor.start_byte() < start) {
+                    exit = Some(node.kind());
+                }
+            }
+        });
+        if let Some(kind) = exit {
+            return fail(format!("The selection contains `{}`, which would leave the new function", kind.trim_end_matches("_expression").trim_end_matches("_statement")));
+        }
+
+        let bindings_query = query(&tree.language, rules.bindings)?;
+        let type_index = bindings_query.capture_index_for_name("type");
+        let mut bindings: Vec<Binding> = Vec::new();
+        let mut cursor = QueryCursor::new();
+        for m in cursor.matches(&bindings_query, scope, source.as_bytes()) {
+            let ty = m.captures.iter().find(|c| Some(c.index) == type_index).map(|c| text(c.node, source).to_string());
+            for capture in m.captures.iter().filter(|c| Some(c.index) != type_index) {
+                let mutable = capture.node.kind() == "mut_pattern" || capture.node.parent().is_some_and(|p| p.kind() == "assignment_expression" || p.kind() == "compound_assignment_expr");
+                let mut names = Vec::new();
+                pattern_names(capture.node, &mut names);
+                // A type only belongs to a pattern that is a single name.
+                let ty = if names.len() == 1 { ty.clone() } else { None };
+                bindings.extend(names.into_iter().map(|name| Binding {
+                    name: text(name, source).to_string(),
+                    position: name.start_byte(),
+                    ty: ty.clone(),
+                    mutable,
+                }));
+            }
+        }
+
+        let mut used_in = Vec::new();
+        let mut used_after = Vec::new();
+        walk(scope, &mut |node| {
+            if !is_variable(node) {
+                return;
+            }
+            let name = text(node, source).to_string();
+            if node.start_byte() >= start && node.end_byte() <= end && !used_in.contains(&name) {
+                used_in.push(name);
+            } else if node.start_byte() >= end {
+                used_after.push(name);
+            }
+        });
+        let before = |name: &str| bindings.iter().rfind(|b| b.name == name && b.position < start);
+        let params: Vec<&Binding> = used_in.iter().filter_map(|name| before(name)).collect();
+        let mut returns: Vec<&Binding> = Vec::new();
+        for binding in bindings.iter().filter(|b| b.position >= start && b.position < end) {
+            if used_after.contains(&binding.name) {
+                returns.retain(|r| r.name != binding.name);
+                returns.push(binding);
+            }
+        }
+        let reassigned = |name: &str| bindings.iter().any(|b| b.name == name && b.position >= start && b.position < end && b.mutable);
+
+        // A return that reassigns an outer variable keeps that variable's type.
+        let return_type = |binding: &Binding| binding.ty.clone().or_else(|| before(&binding.name).and_then(|b| b.ty.clone()));
+        if rust {
+            let untyped: Vec<&str> = params
+                .iter()
+                .filter(|b| b.ty.is_none())
+                .map(|b| b.name.as_str())
+                .chain(returns.iter().filter(|b| return_type(b).is_none()).map(|b| b.name.as_str()))
+                .collect();
+            if !untyped.is_empty() {
+                return fail(format!("Give `{}` a type to extract this", untyped.join("`, `")));
+            }
+        }
+
+        let indent = indentation(source, start).to_string();
+        let body_start = line_start(source, start);
+        let arguments = params.iter().map(|b| b.name.as_str()).collect::<Vec<_>>().join(", ");
+        let return_names: Vec<&str> = returns.iter().map(|b| b.name.as_str()).collect();
+        let return_list = match return_names.as_slice() {
+            [only] => only.to_string(),
+            names if rust => format!("({})", names.join(", ")),
+            names => names.join(", "),
+        };
+
+        let (definition, call, anchor) = if rust {
+            let function = scope;
+            let in_impl = function
+                .parent()
+                .filter(|parent| parent.kind() == "declaration_list")
+                .and_then(|list| list.parent())
+                .filter(|item| item.kind() == "impl_item");
+            let (callee, anchor) = match in_impl {
+                Some(item) if item.child_by_field_name("trait").is_some() => (self.name.clone(), item),
+                Some(_) => (format!("Self::{}", self.name), function),
+                None => (self.name.clone(), function),
+            };
+            let outer = indentation(source, anchor.start_byte()).to_string();
+            let signature = params
+                .iter()
+                .map(|b| format!("{}{}: {}", if reassigned(&b.name) { "mut " } else { "" }, b.name, b.ty.as_deref().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let types: Vec<String> = returns.iter().filter_map(|b| return_type(b)).collect();
+            let output = match types.as_slice() {
+                [] => String::new(),
+                [only] => format!(" -> {}", only),
+                types => format!(" -> ({})", types.join(", ")),
+            };
+            let mut definition = format!(
+                "\n\n{outer}fn {}({}){} {{\n{}\n",
+                self.name,
+                signature,
+                output,
+                reindent(&source[body_start..end], &indent, &format!("{outer}    ")),
+            );
+            if !returns.is_empty() {
+                definition.push_str(&format!("{outer}    {}\n", return_list));
+            }
+            definition.push_str(&format!("{outer}}}"));
+
+            let invocation = format!("{}({})", callee, arguments);
+            let call = match returns.as_slice() {
+                [] => format!("{};", invocation),
+                [only] if before(&only.name).is_some() => format!("{} = {};", only.name, invocation),
+                returns => {
+                    let pattern: Vec<String> =
+                        returns.iter().map(|b| format!("{}{}", if b.mutable && b.ty.is_some() { "mut " } else { "" }, b.name)).collect();
+                    let pattern = if pattern.len() == 1 { pattern[0].clone() } else { format!("({})", pattern.join(", ")) };
+                    format!("let {} = {};", pattern, invocation)
+                }
+            };
+            (definition, call, anchor.end_byte())
+        } else {
+            let mut definition = format!(
+                "def {}({}):\n{}\n",
+                self.name,
+                arguments,
+                reindent(&source[body_start..end], &indent, "    ")
+            );
+            if !returns.is_empty() {
+                definition.push_str(&format!("    return {}\n", return_list));
+            }
+            let invocation = format!("{}({})", self.name, arguments);
+            let call = if returns.is_empty() { invocation } else { format!("{} = {}", return_list, invocation) };
+            // New functions go at module level, after the code they came from.
+            let mut top = first.to_owned();
+            while let Some(parent) = top.parent().filter(|parent| parent.id() != root.id()) {
+                top = parent;
+            }
+            if body.id() == root.id() {
+                (format!("{}\n\n", definition), call, body_start)
+            } else {
+                (format!("\n\n\n{}", definition.trim_end()), call, top.end_byte())
+            }
+        };
+
+        let edits = vec![(body_start..end, format!("{}{}", indent, call)), (anchor..anchor, definition)];
+        let description = format!("Extract {} statement{} into `{}`", statements.len(), if statements.len() == 1 { "" } else { "s" }, self.name);
+        let modified = apply_edits(source, edits);
+        Ok(finish(tree, description, modified))
+    }
 }
 
-impl SyntaxTreeTransformer {
-    pub fn new() -> Self {
-        Self {
-            // Initialize transformer
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(language: Language, source: &str) -> SyntaxTree {
+        let root = SyntaxNode::new(NodeType::Root, Span::new(0, source.len()), None, Vec::new());
+        SyntaxTree::new(root, language, source.to_string())
+    }
+
+    #[test]
+    fn test_rename_covers_every_spelling() {
+        let source = "struct Point { x: i32 }\n\nfn norm(p: &Point) -> i32 {\n    let x = p.x;\n    x * x\n}\n";
+        let mut rust = tree(Language::Rust, source);
+        let patch = rust.transform(&mut RenameSymbol::new("x", "dx")).unwrap();
+        assert_eq!(rust.source, "struct Point { dx: i32 }\n\nfn norm(p: &Point) -> i32 {\n    let dx = p.dx;\n    dx * dx\n}\n");
+        assert!(patch.description.contains("5 occurrences"));
+        assert!(patch.hunks.iter().any(|hunk| hunk.tag == ChangeTag::Insert && hunk.new_text.contains("let dx = p.dx;")));
+
+        let error = tree(Language::Rust, source).transform(&mut RenameSymbol::new("x", "p")).unwrap_err();
+        assert_eq!(error, SyntaxError::TransformError("`p` is already used in this file".to_string()));
+        assert!(tree(Language::Python, "y = 1\n").transform(&mut RenameSymbol::new("x", "z")).is_err());
+        assert!(tree(Language::Go, "").transform(&mut RenameSymbol::new("x", "z")).is_err());
+    }
+
+    #[test]
+    fn test_extract_rust_statements() {
+        let source = "\
+impl Shop {
+    fn total(&self, prices: &[u32]) -> u32 {
+        let tax: u32 = 20;
+        let mut sum: u32 = 0;
+        for price in prices {
+            sum += price;
         }
+        let total: u32 = sum + sum * tax / 100;
+        total
     }
 }
+";
+        let mut rust = tree(Language::Rust, source);
+        let mut extract = ExtractFunction::lines(source, 4, 8, "with_tax");
+        let patch = rust.transform(&mut extract).unwrap();
+        assert_eq!(patch.description, "Extract 3 statements into `with_tax`");
+        assert!(rust.source.contains("        let total = Self::with_tax(prices, tax);\n        total\n"));
+        assert!(rust.source.contains(
+            "\n\n    fn with_tax(prices: &[u32], tax: u32) -> u32 {\n        let mut sum: u32 = 0;\n        for price in prices {\n            sum += price;\n        }\n        let total: u32 = sum + sum * tax / 100;\n        total\n    }\n}\n"
+        ));
+
+        let mut returning = ExtractFunction::lines(source, 4, 9, "f");
+        assert!(tree(Language::Rust, source).transform(&mut returning).is_err());
+        let untyped = "fn f() {\n    let a = 1;\n    let b = a + 1;\n    println!(\"{}\", b);\n}\n";
+        let error = tree(Language::Rust, untyped).transform(&mut ExtractFunction::lines(untyped, 3, 3, "g")).unwrap_err();
+        assert_eq!(error, SyntaxError::TransformError("Give `a`, `b` a type to extract this".to_string()));
+    }
+
+    #[test]
+    fn test_extract_python_statements() {
+        let source = "\
+def report(rows):
+    total = 0
+    for row in rows:
+        total += row.amount
+    count = len(rows)
+    print(total / count)
+";
+        let mut python = tree(Language::Python, source);
+        python.transform(&mut ExtractFunction::lines(source, 2, 5, "summarize")).unwrap();
+        assert_eq!(
+            python.source,
+            "\
+def report(rows):
+    total, count = summarize(rows)
+    print(total / count)
+
 
-impl Transformer for SyntaxTreeTransformer {
-    type Input = ();
-    type Output = ();
-    
-    fn transform(&self, _input: Self::Input) -> Self::Output {
-        // Placeholder implementation
+def summarize(rows):
+    total = 0
+    for row in rows:
+        total += row.amount
+    count = len(rows)
+    return total, count
+"
+        );
     }
 }
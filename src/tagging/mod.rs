@@ -0,0 +1,318 @@
+//! Block Tagging
+//!
+//! This module automatically tags command blocks so that tag filters are
+//! useful without manual curation. Tags come from pluggable `BlockTagger`
+//! implementations: prefix rules from the config and an optional AI classifier.
+//! Blocks are tagged in the background as their commands finish.
+
+use crate::app::pane::Block;
+use crate::config::{AiConfig, TagRule, TaggingConfig};
+use crate::ollama::ModelRole;
+use futures::future::{BoxFuture, FutureExt};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Categories the AI classifier is allowed to assign.
+pub const AI_CATEGORIES: &[&str] = &["build", "test", "deploy", "infra"];
+
+/// A source of tags for a block.
+pub trait BlockTagger: Send + Sync {
+    /// A short name used in logs.
+    fn name(&self) -> &'static str;
+
+    /// Returns the tags that should be applied to `block`.
+    fn tag<'a>(&'a self, block: &'a Block) -> BoxFuture<'a, Vec<String>>;
+}
+
+/// Tags blocks whose command starts with a configured prefix.
+pub struct RuleTagger {
+    rules: Vec<TagRule>,
+}
+
+impl RuleTagger {
+    pub fn new(rules: Vec<TagRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn matching_tags(&self, command: &str) -> Vec<String> {
+        let command = command.trim_start();
+        let mut tags: Vec<String> = Vec::new();
+        for rule in &self.rules {
+            let matches = command == rule.prefix
+                || (command.starts_with(&rule.prefix)
+                    && (rule.prefix.ends_with(' ')
+                        || command[rule.prefix.len()..].starts_with(char::is_whitespace)));
+            if matches && !tags.contains(&rule.tag) {
+                tags.push(rule.tag.clone());
+            }
+        }
+        tags
+    }
+}
+
+impl BlockTagger for RuleTagger {
+    fn name(&self) -> &'static str {
+        "rules"
+    }
+
+    fn tag<'a>(&'a self, block: &'a Block) -> BoxFuture<'a, Vec<String>> {
+        futures::future::ready(self.matching_tags(&block.command)).boxed()
+    }
+}
+
+/// Asks the configured model to classify a block into one of `AI_CATEGORIES`.
+pub struct AiTagger {
+    client: reqwest::Client,
+    api_url: String,
+    model: String,
+    timeout: Duration,
+}
+
+impl AiTagger {
+    pub fn new(config: &AiConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: config.ollama_url.clone(),
+            model: config.model_for(ModelRole::Summarization).to_string(),
+            timeout: Duration::from_secs(config.ai_timeout_seconds),
+        }
+    }
+
+    async fn classify(&self, block: &Block) -> Vec<String> {
+        let output_tail: String = block
+            .output
+            .lines()
+            .rev()
+            .take(20)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect::<Vec<_>>()
+            .join("\n");
+        let prompt = format!(
+            "Classify the following shell command into exactly one of: {}, none.\n\
+             Reply with the single category word only.\n\n\
+             Command: {}\nOutput (tail):\n{}",
+            AI_CATEGORIES.join(", "),
+            block.command,
+            output_tail
+        );
+        let request_body = serde_json::json!({
+            "model": self.model,
+            "prompt": prompt,
+            "stream": false,
+            "options": { "temperature": 0.0 }
+        });
+
+        let started = std::time::Instant::now();
+        crate::metrics::METRICS.ai_requests.inc();
+        let response = self
+            .client
+            .post(&self.api_url)
+            .json(&request_body)
+            .timeout(self.timeout)
+            .send()
+            .await;
+        crate::metrics::METRICS.ai_latency.observe(started.elapsed().as_secs_f64());
+
+        match response {
+            Ok(res) if res.status().is_success() => match res.json::<serde_json::Value>().await {
+                Ok(body) => body["response"]
+                    .as_str()
+                    .and_then(parse_category)
+                    .map(|category| vec![category])
+                    .unwrap_or_default(),
+                Err(e) => {
+                    log::warn!("AI tagger returned an unreadable response: {}", e);
+                    Vec::new()
+                }
+            },
+            Ok(res) => {
+                log::warn!("AI tagger request failed with status {}", res.status());
+                Vec::new()
+            }
+            Err(e) => {
+                log::warn!("AI tagger request failed: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl BlockTagger for AiTagger {
+    fn name(&self) -> &'static str {
+        "ai"
+    }
+
+    fn tag<'a>(&'a self, block: &'a Block) -> BoxFuture<'a, Vec<String>> {
+        self.classify(block).boxed()
+    }
+}
+
+/// Extracts a known category from a model reply, tolerating punctuation and casing.
+pub fn parse_category(response: &str) -> Option<String> {
+    let word = response
+        .split_whitespace()
+        .next()?
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase();
+    AI_CATEGORIES
+        .iter()
+        .find(|category| **category == word)
+        .map(|category| category.to_string())
+}
+
+/// Runs every registered tagger over a block and applies the union of their tags.
+pub struct AutoTagger {
+    taggers: Vec<Box<dyn BlockTagger>>,
+}
+
+impl AutoTagger {
+    pub fn new() -> Self {
+        Self { taggers: Vec::new() }
+    }
+
+    /// Builds the tagger pipeline described by the config.
+    pub fn from_config(tagging: &TaggingConfig, ai: &AiConfig) -> Self {
+        let mut tagger = Self::new();
+        if !tagging.enabled {
+            return tagger;
+        }
+        tagger.register(Box::new(RuleTagger::new(tagging.rules.clone())));
+        if tagging.ai_classifier {
+            tagger.register(Box::new(AiTagger::new(ai)));
+        }
+        tagger
+    }
+
+    pub fn register(&mut self, tagger: Box<dyn BlockTagger>) {
+        self.taggers.push(tagger);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taggers.is_empty()
+    }
+
+    pub async fn apply(&self, block: &mut Block) {
+        for tagger in &self.taggers {
+            let tags = tagger.tag(block).await;
+            if !tags.is_empty() {
+                log::debug!("Tagger '{}' tagged '{}' with {:?}", tagger.name(), block.command, tags);
+            }
+            for tag in tags {
+                if !block.tags.contains(&tag) {
+                    block.tags.push(tag);
+                }
+            }
+        }
+    }
+}
+
+impl Default for AutoTagger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tags a finished block, identified by its id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTags {
+    pub block_id: Uuid,
+    pub tags: Vec<String>,
+}
+
+/// Runs the tagger pipeline off the UI thread, since the AI classifier
+/// waits on a model. One worker thread tags blocks in the order they were
+/// requested; it stops when the `BackgroundTagger` is dropped.
+pub struct BackgroundTagger {
+    is_empty: bool,
+    requests: Sender<Block>,
+    results: Receiver<BlockTags>,
+}
+
+impl BackgroundTagger {
+    pub fn new(tagger: AutoTagger) -> Self {
+        let (requests, request_rx) = channel::<Block>();
+        let (result_tx, results) = channel();
+        let is_empty = tagger.is_empty();
+        if !is_empty {
+            thread::spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        log::warn!("Failed to start the block tagger runtime: {}", e);
+                        return;
+                    }
+                };
+                for mut block in request_rx {
+                    runtime.block_on(tagger.apply(&mut block));
+                    if block.tags.is_empty() {
+                        continue;
+                    }
+                    if result_tx.send(BlockTags { block_id: block.id, tags: block.tags }).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Self { is_empty, requests, results }
+    }
+
+    /// Queues `block` for tagging; its tags arrive through `poll`.
+    pub fn request(&self, block: &Block) {
+        if self.is_empty || block.command.trim().is_empty() {
+            return;
+        }
+        self.requests.send(block.clone()).ok();
+    }
+
+    /// Takes the tags found since the last call.
+    pub fn poll(&self) -> Vec<BlockTags> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_tag_rules;
+
+    #[test]
+    fn test_rule_tagger_matches_prefix_on_word_boundary() {
+        let tagger = RuleTagger::new(default_tag_rules());
+        assert_eq!(tagger.matching_tags("cargo test --all"), vec!["test"]);
+        assert_eq!(tagger.matching_tags("kubectl get pods"), vec!["infra"]);
+        assert!(tagger.matching_tags("cargotest").is_empty());
+    }
+
+    #[test]
+    fn test_parse_category() {
+        assert_eq!(parse_category("Deploy."), Some("deploy".to_string()));
+        assert_eq!(parse_category("  build\n"), Some("build".to_string()));
+        assert_eq!(parse_category("none"), None);
+        assert_eq!(parse_category(""), None);
+    }
+
+    #[test]
+    fn test_auto_tagger_applies_rule_tags() {
+        let tagging = TaggingConfig::default();
+        let tagger = AutoTagger::from_config(&tagging, &AiConfig::default());
+        let mut block = Block::new("docker build -t app .".to_string(), String::new());
+        futures::executor::block_on(tagger.apply(&mut block));
+        assert_eq!(block.tags, vec!["build"]);
+
+        let background = BackgroundTagger::new(tagger);
+        let block = Block::new("cargo test --all".to_string(), String::new());
+        background.request(&block);
+        let untagged = Block::new("echo hi".to_string(), String::new());
+        background.request(&untagged);
+        let infra = Block::new("kubectl apply -f app.yaml".to_string(), String::new());
+        background.request(&infra);
+        let found = background.results.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(found, BlockTags { block_id: block.id, tags: vec!["test".to_string()] });
+        let found = background.results.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(found, BlockTags { block_id: infra.id, tags: vec!["infra".to_string()] });
+    }
+}
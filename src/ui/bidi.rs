@@ -0,0 +1,242 @@
+//! Bidirectional Text
+//!
+//! This module lays out lines that mix left-to-right and right-to-left
+//! scripts such as Arabic and Hebrew. Programs write text in logical order;
+//! the Unicode Bidirectional Algorithm (UAX #9) decides the order it is shown
+//! in. Cell-based renderers use [`BidiLine`] to reorder a row's cells and
+//! mirror brackets inside right-to-left runs. The GPU renderer shapes rows
+//! with cosmic-text, which reorders on its own, so only the input editor's
+//! cursor motion comes from here there: Left and Right follow what is on
+//! screen rather than the order of the characters in memory.
+
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+use unicode_bidi_mirroring::get_mirrored;
+
+/// A direction the cursor moves on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisualDirection {
+    Left,
+    Right,
+}
+
+/// Whether the text has any right-to-left characters, in which case it
+/// needs reordering at all.
+pub fn has_rtl(text: &str) -> bool {
+    text.chars().any(|c| matches!(bidi_class(c), BidiClass::R | BidiClass::AL | BidiClass::AN))
+}
+
+/// Whether rule X9 removes the character: embedding controls and boundary
+/// neutrals take no part in reordering.
+pub fn is_removed_by_x9(c: char) -> bool {
+    matches!(
+        bidi_class(c),
+        BidiClass::RLE | BidiClass::LRE | BidiClass::RLO | BidiClass::LRO | BidiClass::PDF | BidiClass::BN
+    )
+}
+
+/// One line of text with its resolved embedding levels and display order.
+/// Positions are character indices, not byte offsets.
+#[derive(Debug, Clone)]
+pub struct BidiLine {
+    chars: Vec<char>,
+    levels: Vec<Level>,
+    paragraph_level: Level,
+    /// Logical index of the character at each visual position.
+    visual_to_logical: Vec<usize>,
+    /// Visual position of each logical character.
+    logical_to_visual: Vec<usize>,
+}
+
+impl BidiLine {
+    /// Resolves `text`, which must not contain a line break. With no base
+    /// level the paragraph direction comes from its first strong character.
+    pub fn new(text: &str, base: Option<Level>) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let info = BidiInfo::new(text, base);
+        let Some(paragraph) = info.paragraphs.first() else {
+            let paragraph_level = base.unwrap_or_else(Level::ltr);
+            return Self {
+                chars,
+                levels: Vec::new(),
+                paragraph_level,
+                visual_to_logical: Vec::new(),
+                logical_to_visual: Vec::new(),
+            };
+        };
+        let levels = info.reordered_levels_per_char(paragraph, paragraph.range.clone());
+        let visual_to_logical = BidiInfo::reorder_visual(&levels);
+        let mut logical_to_visual = vec![0; visual_to_logical.len()];
+        for (visual, &logical) in visual_to_logical.iter().enumerate() {
+            logical_to_visual[logical] = visual;
+        }
+        Self { chars, levels, paragraph_level: paragraph.level, visual_to_logical, logical_to_visual }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    pub fn paragraph_level(&self) -> Level {
+        self.paragraph_level
+    }
+
+    /// Resolved levels per character, after the line rules (L1) are applied.
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// Logical character indices from left to right on screen.
+    pub fn visual_order(&self) -> &[usize] {
+        &self.visual_to_logical
+    }
+
+    /// Whether the character at a logical index sits in a right-to-left run.
+    pub fn is_rtl_at(&self, index: usize) -> bool {
+        self.levels.get(index).is_some_and(|level| level.is_rtl())
+    }
+
+    /// The character as displayed: brackets and similar pairs are mirrored
+    /// inside right-to-left runs (rule L4).
+    pub fn display_char(&self, index: usize) -> char {
+        let c = self.chars[index];
+        if self.is_rtl_at(index) {
+            get_mirrored(c).unwrap_or(c)
+        } else {
+            c
+        }
+    }
+
+    /// The line as it reads from left to right on screen.
+    pub fn visual_text(&self) -> String {
+        self.visual_to_logical.iter().map(|&index| self.display_char(index)).collect()
+    }
+
+    /// Rearranges per-character items, such as terminal cells, into display
+    /// order. `items` must have one entry per character.
+    pub fn reorder<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        self.visual_to_logical.iter().map(|&index| items[index].clone()).collect()
+    }
+
+    /// Moves a cursor one cell left or right on screen and returns its new
+    /// logical position. The cursor covers the character it would insert
+    /// before; the end of the line sits past the paragraph's trailing edge,
+    /// so every position is reachable from either side.
+    pub fn move_cursor(&self, cursor: usize, direction: VisualDirection) -> usize {
+        let len = self.len();
+        if len == 0 {
+            return 0;
+        }
+        // Where the end-of-line position sits on screen: after the last cell
+        // for a left-to-right paragraph, before the first for right-to-left.
+        let end_slot = if self.paragraph_level.is_rtl() { -1 } else { len as isize };
+        let slot = if cursor >= len { end_slot } else { self.logical_to_visual[cursor] as isize };
+        let target = match direction {
+            VisualDirection::Left => slot - 1,
+            VisualDirection::Right => slot + 1,
+        };
+        // Stepping off either side from the end position stays put.
+        if slot == end_slot && !(0..len as isize).contains(&target) {
+            return cursor.min(len);
+        }
+        if target == end_slot {
+            len
+        } else if (0..len as isize).contains(&target) {
+            self.visual_to_logical[target as usize]
+        } else {
+            cursor.min(len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cases in the format of the Unicode `BidiCharacterTest.txt`. The full
+    /// file from the UCD can replace this excerpt without code changes.
+    const CONFORMANCE_DATA: &str = include_str!("testdata/BidiCharacterTest.txt");
+
+    fn parse_hex_chars(field: &str) -> String {
+        field
+            .split_whitespace()
+            .map(|hex| char::from_u32(u32::from_str_radix(hex, 16).unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_conformance_data() {
+        let mut cases = 0;
+        for (line_no, line) in CONFORMANCE_DATA.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(';').collect();
+            assert_eq!(fields.len(), 5, "line {}", line_no + 1);
+            let text = parse_hex_chars(fields[0]);
+            let base = match fields[1] {
+                "0" => Some(Level::ltr()),
+                "1" => Some(Level::rtl()),
+                _ => None,
+            };
+            let bidi = BidiLine::new(&text, base);
+            let context = format!("line {}: {}", line_no + 1, line);
+
+            assert_eq!(bidi.paragraph_level().number().to_string(), fields[2], "{}", context);
+            let levels: Vec<String> = text
+                .chars()
+                .zip(bidi.levels())
+                .map(|(c, level)| if is_removed_by_x9(c) { "x".to_string() } else { level.number().to_string() })
+                .collect();
+            assert_eq!(levels.join(" "), fields[3], "{}", context);
+            let chars: Vec<char> = text.chars().collect();
+            let order: Vec<String> = bidi
+                .visual_order()
+                .iter()
+                .filter(|&&index| !is_removed_by_x9(chars[index]))
+                .map(|index| index.to_string())
+                .collect();
+            assert_eq!(order.join(" "), fields[4], "{}", context);
+            cases += 1;
+        }
+        assert!(cases > 0);
+    }
+
+    #[test]
+    fn test_visual_text_mirrors_brackets() {
+        let bidi = BidiLine::new("ls (\u{5E9}\u{5DC}\u{5D5}\u{5DD})", Some(Level::ltr()));
+        assert_eq!(bidi.visual_text(), "ls (\u{5DD}\u{5D5}\u{5DC}\u{5E9})");
+        let bidi = BidiLine::new("\u{5D0}(\u{5D1})", None);
+        assert_eq!(bidi.visual_text(), "(\u{5D1})\u{5D0}");
+        assert!(!has_rtl("plain ascii"));
+    }
+
+    #[test]
+    fn test_cursor_moves_visually_through_mixed_line() {
+        // "abc אבג": on screen "abc גבא".
+        let bidi = BidiLine::new("abc \u{5D0}\u{5D1}\u{5D2}", Some(Level::ltr()));
+        let mut cursor = 0;
+        let mut visited = vec![cursor];
+        for _ in 0..8 {
+            cursor = bidi.move_cursor(cursor, VisualDirection::Right);
+            visited.push(cursor);
+        }
+        assert_eq!(visited, vec![0, 1, 2, 3, 6, 5, 4, 7, 7]);
+        for expected in [4, 5, 6, 3] {
+            cursor = bidi.move_cursor(cursor, VisualDirection::Left);
+            assert_eq!(cursor, expected);
+        }
+
+        // In a right-to-left paragraph the end of the line is on the left.
+        let bidi = BidiLine::new("\u{5D0}\u{5D1} ab", None);
+        assert_eq!(bidi.visual_order(), &[3, 4, 2, 1, 0]);
+        assert_eq!(bidi.move_cursor(0, VisualDirection::Left), 1);
+        assert_eq!(bidi.move_cursor(3, VisualDirection::Left), 5);
+        assert_eq!(bidi.move_cursor(5, VisualDirection::Right), 3);
+        assert_eq!(bidi.move_cursor(5, VisualDirection::Left), 5);
+    }
+}
@@ -1,6 +1,5 @@
 use ratatui::{prelude::*, widgets::*};
-use fuzzy_matcher::skim::SkimMatcherV2;
-use fuzzy_matcher::FuzzyMatcher;
+use crate::fuzzy_match::match_all;
 
 pub struct CommandPalette {
     pub input: String,
@@ -18,13 +17,10 @@ impl CommandPalette {
     }
 
     pub fn update_matches(&mut self) {
-        let matcher = SkimMatcherV2::default();
-        self.matches = self
-            .all_commands
-            .iter()
-            .filter_map(|cmd| matcher.fuzzy_match(cmd, &self.input).map(|score| (cmd.clone(), score)))
+        self.matches = match_all(&self.all_commands, &self.input)
+            .into_iter()
+            .map(|(idx, found)| (self.all_commands[idx].clone(), found.score))
             .collect();
-        self.matches.sort_by(|a, b| b.1.cmp(&a.1));
     }
 
     pub fn render<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
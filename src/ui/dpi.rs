@@ -0,0 +1,70 @@
+//! Display Scaling
+//!
+//! Font sizes in the config are logical pixels, as on a standard-density
+//! display, while the renderer draws in device pixels. Text is therefore
+//! shaped at the window's scale factor: 14px text on a 2x display is shaped
+//! at 28 device pixels rather than scaled up afterwards. When the window is
+//! dragged to a monitor with a different scale factor, the metrics are
+//! recomputed, text is re-shaped and the grid is resized to the cells that
+//! fit, which for the same logical window size is the same grid as before.
+
+/// Font metrics in device pixels at one scale factor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaledMetrics {
+    pub scale_factor: f64,
+    pub font_size: f32,
+    /// Whole device pixels, so row edges fall on pixel boundaries and glyphs
+    /// do not blur across them.
+    pub line_height: f32,
+}
+
+impl ScaledMetrics {
+    /// `font_size` in logical pixels; `line_height` as a multiple of it.
+    pub fn new(font_size: f32, line_height: f32, scale_factor: f64) -> Self {
+        // Some platforms report 0 or NaN before the window is on a monitor.
+        let scale_factor = if scale_factor.is_finite() && scale_factor > 0.0 { scale_factor } else { 1.0 };
+        let font_size = font_size * scale_factor as f32;
+        Self { scale_factor, font_size, line_height: (font_size * line_height).round().max(1.0) }
+    }
+}
+
+/// The columns and rows of `cell` sized cells that fit in a surface of
+/// `width` by `height` device pixels. Never less than one cell, so a
+/// minimized window does not hand the shell an empty grid.
+pub fn grid_size(width: u32, height: u32, cell_width: f32, cell_height: f32) -> (u16, u16) {
+    let fit = |length: u32, cell: f32| {
+        if cell > 0.0 {
+            (length as f32 / cell).floor().clamp(1.0, u16::MAX as f32) as u16
+        } else {
+            1
+        }
+    };
+    (fit(width, cell_width), fit(height, cell_height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_follow_the_scale_factor() {
+        let standard = ScaledMetrics::new(14.0, 1.2, 1.0);
+        assert_eq!((standard.font_size, standard.line_height), (14.0, 17.0));
+        let retina = ScaledMetrics::new(14.0, 1.2, 2.0);
+        assert_eq!((retina.font_size, retina.line_height), (28.0, 34.0));
+        // Fractional scales keep the exact font size but snap the rows.
+        let laptop = ScaledMetrics::new(14.0, 1.2, 1.25);
+        assert_eq!((laptop.font_size, laptop.line_height), (17.5, 21.0));
+        assert_eq!(ScaledMetrics::new(14.0, 1.2, 0.0), standard);
+    }
+
+    #[test]
+    fn test_grid_is_unchanged_across_monitors() {
+        // An 800x600 logical window, with the M advance measured at each scale.
+        assert_eq!(grid_size(800, 600, 8.4, 17.0), (95, 35));
+        assert_eq!(grid_size(1600, 1200, 16.8, 34.0), (95, 35));
+        assert_eq!(grid_size(1000, 750, 10.5, 21.0), (95, 35));
+        assert_eq!(grid_size(0, 0, 8.4, 17.0), (1, 1));
+        assert_eq!(grid_size(800, 600, 0.0, 0.0), (1, 1));
+    }
+}
@@ -0,0 +1,236 @@
+//! Block Minimap
+//!
+//! This module computes the marks drawn in the scrollbar minimap: block
+//! boundaries, failed commands and search hits, each positioned by the
+//! fraction of the session's total height at which it appears. Positions
+//! account for folded blocks so the minimap matches what is on screen.
+//! Blocks come in as `MinimapBlock`s with the output their view shows.
+
+/// Columns the strip takes at a pane's right edge.
+pub const STRIP_COLUMNS: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkKind {
+    BlockBoundary,
+    Error,
+    SearchHit,
+}
+
+impl MarkKind {
+    /// Which mark shows when several fall on the same row of the strip.
+    fn priority(self) -> u8 {
+        match self {
+            MarkKind::BlockBoundary => 0,
+            MarkKind::SearchHit => 1,
+            MarkKind::Error => 2,
+        }
+    }
+}
+
+/// A block as the minimap lays it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimapBlock<'a> {
+    pub command: &'a str,
+    /// The output lines shown, after folding.
+    pub lines: Vec<&'a str>,
+    /// Rows the block takes on screen.
+    pub rows: usize,
+    pub failed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinimapMark {
+    /// Position within the scrollbar, from 0.0 (top) to 1.0 (bottom).
+    pub position: f32,
+    pub kind: MarkKind,
+    pub block_index: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Minimap {
+    pub marks: Vec<MinimapMark>,
+    /// Start row of each block, used to map scrollbar positions back to blocks.
+    block_starts: Vec<usize>,
+    total_rows: usize,
+}
+
+impl Minimap {
+    /// Lays out `blocks` and collects their marks. `search_query` adds a mark
+    /// for every visible output line (or command) containing the query.
+    pub fn layout<'a>(
+        blocks: impl IntoIterator<Item = MinimapBlock<'a>>,
+        search_query: Option<&str>,
+    ) -> Self {
+        let query = search_query
+            .filter(|q| !q.is_empty())
+            .map(|q| q.to_lowercase());
+        let mut block_starts = Vec::new();
+        let mut raw_marks: Vec<(usize, MarkKind, usize)> = Vec::new();
+        let mut row = 0;
+
+        for (index, block) in blocks.into_iter().enumerate() {
+            block_starts.push(row);
+            raw_marks.push((row, MarkKind::BlockBoundary, index));
+            if block.failed {
+                raw_marks.push((row, MarkKind::Error, index));
+            }
+
+            if let Some(query) = &query {
+                if block.command.to_lowercase().contains(query) {
+                    raw_marks.push((row, MarkKind::SearchHit, index));
+                }
+                for (offset, line) in block.lines.iter().enumerate() {
+                    if line.to_lowercase().contains(query) {
+                        raw_marks.push((row + 1 + offset, MarkKind::SearchHit, index));
+                    }
+                }
+            }
+
+            row += block.rows;
+        }
+
+        let total_rows = row.max(1);
+        let marks = raw_marks
+            .into_iter()
+            .map(|(row, kind, block_index)| MinimapMark {
+                position: row as f32 / total_rows as f32,
+                kind,
+                block_index,
+            })
+            .collect();
+
+        Self {
+            marks,
+            block_starts,
+            total_rows,
+        }
+    }
+
+    pub fn total_rows(&self) -> usize {
+        self.total_rows
+    }
+
+    pub fn marks_of(&self, kind: MarkKind) -> impl Iterator<Item = &MinimapMark> {
+        self.marks.iter().filter(move |mark| mark.kind == kind)
+    }
+
+    /// Returns the block under a scrollbar position, for click-to-jump.
+    pub fn block_at(&self, position: f32) -> Option<usize> {
+        if self.block_starts.is_empty() {
+            return None;
+        }
+        let row = (position.clamp(0.0, 1.0) * self.total_rows as f32) as usize;
+        match self.block_starts.binary_search(&row) {
+            Ok(index) => Some(index),
+            Err(insert_at) => Some(insert_at.saturating_sub(1)),
+        }
+    }
+
+    /// Returns the row a block starts at, for scrolling it into view.
+    pub fn block_row(&self, block_index: usize) -> Option<usize> {
+        self.block_starts.get(block_index).copied()
+    }
+
+    /// The mark drawn in each of `rows` rows of a strip, the most important
+    /// one where marks share a row.
+    pub fn strip(&self, rows: usize) -> Vec<Option<MarkKind>> {
+        let mut strip: Vec<Option<MarkKind>> = vec![None; rows];
+        for mark in &self.marks {
+            let row = ((mark.position * rows as f32) as usize).min(rows.saturating_sub(1));
+            let Some(cell) = strip.get_mut(row) else {
+                continue;
+            };
+            if cell.is_none_or(|kind| kind.priority() < mark.kind.priority()) {
+                *cell = Some(mark.kind);
+            }
+        }
+        strip
+    }
+
+    /// Index of the next error block after `current`, wrapping around.
+    pub fn next_error(&self, current: Option<usize>) -> Option<usize> {
+        let errors: Vec<usize> = self.marks_of(MarkKind::Error).map(|m| m.block_index).collect();
+        let after = current.map_or(0, |c| c + 1);
+        errors
+            .iter()
+            .copied()
+            .find(|&index| index >= after)
+            .or_else(|| errors.first().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::fold::{self, OutputView};
+    use crate::config::BlocksConfig;
+
+    /// A block as the pane lays it out, with its output shown in `view`.
+    fn block<'a>(command: &'a str, output: &'a str, view: OutputView, config: &BlocksConfig, failed: bool) -> MinimapBlock<'a> {
+        let visible = fold::visible_output(output, view, config);
+        MinimapBlock {
+            command,
+            lines: visible.text.lines().collect(),
+            rows: 1 + visible.rows(),
+            failed,
+        }
+    }
+
+    #[test]
+    fn test_marks_and_positions() {
+        let config = BlocksConfig::default();
+        let blocks = vec![
+            block("ls", "a\nb\nc", OutputView::Full, &config, false),
+            block("make", "error: oops", OutputView::Full, &config, true),
+        ];
+        let minimap = Minimap::layout(blocks, Some("error"));
+
+        assert_eq!(minimap.total_rows(), 6);
+        assert_eq!(minimap.marks_of(MarkKind::BlockBoundary).count(), 2);
+        let error = minimap.marks_of(MarkKind::Error).next().unwrap();
+        assert_eq!(error.block_index, 1);
+        assert!((error.position - 4.0 / 6.0).abs() < f32::EPSILON);
+        assert_eq!(minimap.marks_of(MarkKind::SearchHit).count(), 1);
+        assert_eq!(minimap.block_at(0.1), Some(0));
+        assert_eq!(minimap.block_at(0.9), Some(1));
+        assert_eq!(minimap.next_error(Some(1)), Some(1));
+    }
+
+    #[test]
+    fn test_folding_shrinks_layout() {
+        let config = BlocksConfig { fold_lines: 5, ..BlocksConfig::default() };
+        let output = "x\n".repeat(100);
+        let minimap = Minimap::layout([block("seq 100", &output, OutputView::Collapsed, &config, false)], None);
+        assert_eq!(minimap.total_rows(), 7);
+        let minimap = Minimap::layout([block("seq 100", &output, OutputView::Full, &config, false)], None);
+        assert_eq!(minimap.total_rows(), 101);
+    }
+
+    #[test]
+    fn test_strip_prefers_errors() {
+        let block = |command, lines, rows, failed| MinimapBlock {
+            command,
+            lines,
+            rows,
+            failed,
+        };
+        let blocks = vec![
+            block("ls", vec!["a", "b"], 3, false),
+            block("grep needle", vec!["needle"], 3, true),
+            block("cat", vec!["x", "needle", "y"], 4, false),
+        ];
+        let minimap = Minimap::layout(blocks, Some("needle"));
+        assert_eq!(minimap.total_rows(), 10);
+        assert_eq!(
+            minimap.strip(5),
+            vec![
+                Some(MarkKind::BlockBoundary),
+                Some(MarkKind::Error),
+                Some(MarkKind::SearchHit),
+                Some(MarkKind::BlockBoundary),
+                Some(MarkKind::SearchHit),
+            ]
+        );
+        assert!(minimap.strip(0).is_empty());
+    }
+}
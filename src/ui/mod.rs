@@ -1,4 +1,9 @@
 pub mod renderer;
 pub mod theme;
+pub mod bidi;
+pub mod dpi;
 pub mod blocks;
+pub mod minimap;
+pub mod scroll;
+pub mod sticky;
 pub mod terminal_ui;
@@ -1 +1,3704 @@
-use crate::{drive::{DriveObject, Notebook, Prompt, Workflow}, app::{state::{App, AppMode, PaletteItem, PromptMode, InputPosition, CursorShape}, pane::{AgentState}}, agent::client::AgentResponse, pty::vte_handler::VteState, config::{TextConfig, theme::Theme}, };use cosmic_text::{Attrs, Buffer, Color, Editor, FontSystem, Metrics, Shaping, SwashCache, Weight, Style as FontStyle, AttrsList, Edit};use winit::window::Window;use std::time::Duration;use similar::ChangeTag;use crate::vim::{VimMode};use vte::ansi::Color as VteColor;use crate::app::state::Grid;use crate::pty::vte_handler::GridCoords;fn hex_to_color(hex: &str) -> Color {    let hex = hex.trim_start_matches('#');    let (r, g, b) = match hex.len() {        6 => (            u8::from_str_radix(&hex[0..2], 16).unwrap_or(255),            u8::from_str_radix(&hex[2..4], 16).unwrap_or(255),            u8::from_str_radix(&hex[4..6], 16).unwrap_or(255),        ),        _ => (255, 255, 255),    };    Color::rgb(r, g, b)}fn to_cosmic_color(c: VteColor, theme: &Theme) -> Color {    match c {        VteColor::Named(c) => match c {            vte::ansi::NamedColor::Black => hex_to_color(&theme.colors.normal.black),            vte::ansi::NamedColor::Red => hex_to_color(&theme.colors.normal.red),            vte::ansi::NamedColor::Green => hex_to_color(&theme.colors.normal.green),            vte::ansi::NamedColor::Yellow => hex_to_color(&theme.colors.normal.yellow),            vte::ansi::NamedColor::Blue => hex_to_color(&theme.colors.normal.blue),            vte::ansi::NamedColor::Magenta => hex_to_color(&theme.colors.normal.magenta),            vte::ansi::NamedColor::Cyan => hex_to_color(&theme.colors.normal.cyan),            vte::ansi::NamedColor::White => hex_to_color(&theme.colors.normal.white),            vte::ansi::NamedColor::BrightBlack => hex_to_color(&theme.colors.bright.black),            vte::ansi::NamedColor::BrightRed => hex_to_color(&theme.colors.bright.red),            vte::ansi::NamedColor::BrightGreen => hex_to_color(&theme.colors.bright.green),            vte::ansi::NamedColor::BrightYellow => hex_to_color(&theme.colors.bright.yellow),            vte::ansi::NamedColor::BrightBlue => hex_to_color(&theme.colors.bright.blue),            vte::ansi::NamedColor::BrightMagenta => hex_to_color(&theme.colors.bright.magenta),            vte::ansi::NamedColor::BrightCyan => hex_to_color(&theme.colors.bright.cyan),            vte::ansi::NamedColor::BrightWhite => hex_to_color(&theme.colors.bright.white),            _ => hex_to_color(&theme.colors.primary.foreground),        },        VteColor::Spec(rgb) => Color::rgb(rgb.r, rgb.g, rgb.b),        VteColor::Indexed(idx) => {            let r = (idx & 0xE0) >> 5;            let g = (idx & 0x1C) >> 2;            let b = idx & 0x03;            Color::rgb(r * 36, g * 36, b * 72)        }        VteColor::Default => hex_to_color(&theme.colors.primary.foreground),    }}pub struct Renderer<'a> {    surface: wgpu::Surface<'static>,    device: wgpu::Device,    queue: wgpu::Queue,    config: wgpu::SurfaceConfiguration,    font_system: FontSystem,    swash_cache: SwashCache,    buffer: Buffer,    editor: Editor<'a>,    pub char_width: f32,    pub char_height: f32,}impl<'a> Renderer<'a> {    pub async fn new(window: &Window, font_data: Vec<u8>, text_config: &TextConfig) -> Self {        let size = window.inner_size();        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());        let surface = instance.create_surface(window).unwrap();        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions::default()).await.unwrap();        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await.unwrap();        let surface_caps = surface.get_capabilities(&adapter);        let surface_format = surface_caps.formats.iter().copied().find(|f| f.is_srgb()).unwrap_or(surface_caps.formats[0]);        let composite_alpha_mode = surface_caps.alpha_modes            .iter()            .copied()            .find(|&m| m == wgpu::CompositeAlphaMode::Auto || m == wgpu::CompositeAlphaMode::PreMultiplied)            .unwrap_or(surface_caps.alpha_modes[0]);        let config = wgpu::SurfaceConfiguration {            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,            format: surface_format,            width: size.width,            height: size.height,            present_mode: wgpu::PresentMode::AutoVsync,            alpha_mode: composite_alpha_mode,            view_formats: vec![],            desired_maximum_frame_latency: 2,        };        surface.configure(&device, &config);        let mut font_system = FontSystem::new();        let swash_cache = SwashCache::new();        font_system.db_mut().load_font_data(font_data);        let attrs = Attrs::new();        let metrics = Metrics::new(text_config.font_size, text_config.font_size * text_config.line_height);        let shaping = if text_config.use_ligatures { Shaping::Advanced } else { Shaping::Basic };        let mut buffer = Buffer::new(&mut font_system, metrics);        buffer.set_size(&mut font_system, Some(size.width as f32), Some(size.height as f32));        // buffer.set_shaping(&mut font_system, shaping); // Removed as per cosmic-text 0.11 API        let editor = Editor::new(buffer);        let mut buffer_mono = Buffer::new(&mut font_system, metrics);        buffer_mono.set_text(&mut font_system, "M", attrs, Shaping::Advanced);        let char_width = buffer_mono.layout_runs().next().map_or(text_config.font_size, |run| run.glyphs.first().map_or(0.0, |g| g.w));        Self {            surface, device, queue, config, font_system, swash_cache, buffer: editor.buffer().clone(), editor,            char_width, char_height: text_config.font_size * text_config.line_height,        }    }    pub fn sync_with_vte(&mut self, vte_state: &VteState, theme: &Theme) {        let grid = vte_state.get_grid();        let mut text = String::new();        let mut attrs_list = AttrsList::new(Attrs::new());        for row in grid.rows_iter() {            for cell in row {                text.push(cell.c);                let mut attrs = Attrs::new().color(to_cosmic_color(cell.fg, theme));                if cell.flags.contains(vte::ansi::Flags::BOLD) {                    attrs = attrs.weight(Weight::BOLD);                }                if cell.flags.contains(vte::ansi::Flags::ITALIC) {                    attrs = attrs.style(FontStyle::Italic);                }                let start = text.len() - 1;                attrs_list.add_span(start..text.len(), attrs);            }            text.push('\n');        }        self.editor.buffer_mut().set_text(&mut self.font_system, &text, attrs_list, Shaping::Advanced);        self.editor.shape_as_needed(&mut self.font_system, true);    }    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) -> (u16, u16) {        if new_size.width > 0 && new_size.height > 0 {            self.config.width = new_size.width;            self.config.height = new_size.height;            self.surface.configure(&self.device, &self.config);            self.editor.buffer_mut().set_size(&mut self.font_system, Some(new_size.width as f32), Some(new_size.height as f32));            self.editor.shape_as_needed(&mut self.font_system, true);        }        let cols = (new_size.width as f32 / self.char_width).floor() as u16;        let rows = (new_size.height as f32 / self.char_height).floor() as u16;        (cols, rows)    }    pub fn render(&mut self, app: &mut App, time_since_start: Duration) -> Result<(), wgpu::SurfaceError> {        let output = self.surface.get_current_texture()?;        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });        {            let bg = hex_to_color(&app.theme.colors.primary.background);            let alpha = app.config.appearance.opacity;            let clear_color = if alpha < 1.0 {                wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 }            } else {                wgpu::Color {                    r: bg.r() as f64 / 255.0,                    g: bg.g() as f64 / 255.0,                    b: bg.b() as f64 / 255.0,                    a: 1.0,                }            };            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {                label: None,                color_attachments: &[Some(wgpu::RenderPassColorAttachment {                    view: &view,                    resolve_target: None,                    ops: wgpu::Operations {                        load: wgpu::LoadOp::Clear(clear_color),                        store: wgpu::StoreOp::Store,                    },                })],                ..Default::default()            });            let (win_width, win_height) = (self.config.width as f32, self.config.height as f32);            let num_panes = app.panes.len();            let pane_width = win_width / num_panes as f32;            for (pane_idx, pane) in app.panes.iter().enumerate() {                let pane_x = pane_idx as f32 * pane_width;                let mut y_offset = 0.0;                // --- 1. RENDER HISTORICAL BLOCKS ---                for (block_idx, block) in pane.history.iter().enumerate() {                    // Render prompt and command                    let cmd_text = format!("> {}", block.command);                    let mut cmd_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    cmd_buffer.set_size(&mut self.font_system, Some(pane_width), Some(self.char_height * 1.2));                    cmd_buffer.set_text(&mut self.font_system, &cmd_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(cmd_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    y_offset += self.char_height * 1.2;                    // Render output                    let mut output_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    output_buffer.set_size(&mut self.font_system, Some(pane_width), Some(self.char_height * 4.0));                    output_buffer.set_text(&mut self.font_system, &block.output, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(output_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    y_offset += self.editor.buffer().total_height().max(self.char_height * 2.0);                    // Render "..." menu icon                    let mut menu_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    menu_buffer.set_size(&mut self.font_system, Some(30.0), Some(self.char_height * 1.2));                    menu_buffer.set_text(&mut self.font_system, "⋯", Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(menu_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                }                // --- 2. RENDER THE LIVE VTE GRID ---                let vte_state = pane.current_vte.lock().unwrap();                self.sync_with_vte(&vte_state, &app.theme);                self.editor.buffer_mut().set_size(&mut self.font_system, Some(pane_width), Some(win_height - y_offset));                self.editor.shape_as_needed(&mut self.font_system, true);                self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                // --- 3. RENDER BLOCK CONTEXT MENU (if active) ---                if let AppMode::BlockMenu(state) = &app.mode {                    if state.pane_idx == pane_idx {                        let menu_items = ["Copy Command", "Copy Output", "Re-input Command"];                        let menu_width = 220.0;                        let menu_height = self.char_height * menu_items.len() as f32 * 1.2 + 20.0;                        let menu_x = pane_x + pane_width - menu_width - 10.0;                        let menu_y = 40.0 + (state.block_idx as f32) * self.char_height * 2.0;                        let mut menu_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                        menu_buffer.set_size(&mut self.font_system, Some(menu_width), Some(menu_height));                        let mut menu_text = String::new();                        for (i, item) in menu_items.iter().enumerate() {                            if i == state.selected_action_idx {                                menu_text.push_str(&format!("> {}\n", item));                            } else {                                menu_text.push_str(&format!("  {}\n", item));                            }                        }        menu_buffer.set_text(&mut self.font_system, &menu_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                        self.editor.set_buffer(menu_buffer);                        self.editor.shape_as_needed(&mut self.font_system, true);                        self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                        self.editor.set_buffer(self.buffer.clone());                    }                }            }            // Restore the main buffer for overlays/cursor            self.editor.set_buffer(self.buffer.clone());            // --- PROMPT RENDERING LOGIC ---            let mut terminal_y_offset = 0.0;            if app.config.appearance.prompt_mode == PromptMode::Warpish {                let mut prompt_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                prompt_buffer.set_size(&mut self.font_system, Some(self.config.width as f32), Some(self.char_height * 2.0));                // --- Build the prompt string from chips (with placeholder data) ---                let mut prompt_text = String::new();                for chip in &app.config.appearance.warpish_prompt.chips {                    let chip_text = match chip.as_str() {                        "cwd" => " /users/dev/warpish_terminal ", // Placeholder                        "git" => " on main [!] ", // Placeholder                        "time" => " 12:34 PM ", // Placeholder                        _ => " unknown_chip "                    };                    prompt_text.push_str(chip_text);                }                prompt_text.push('>');                prompt_buffer.set_text(&mut self.font_system, &prompt_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                self.editor.set_buffer(prompt_buffer);                self.editor.shape_as_needed(&mut self.font_system, true);                self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                if !app.config.appearance.warpish_prompt.same_line {                    terminal_y_offset = self.char_height;                }            }            // --- Draw main terminal text, respecting the offset ---            self.editor.set_buffer(self.buffer.clone());            self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);            // --- RENDER CURSOR (NEW) ---            let grid = app.panes[app.active_pane_idx].current_vte.lock().unwrap().get_grid();            if !grid.cursor_hidden() {                let is_blinking_on = if !app.config.appearance.cursor.blink {                    true                } else {                    (time_since_start.as_millis() / 500) % 2 == 0                };                if is_blinking_on {                    self.render_cursor(app, &grid.cursor_position(), &mut render_pass);                }            }            // --- RENDER AGENT MODE UI ---            if let AppMode::Agent(state) = &app.mode {                let mut agent_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                agent_buffer.set_size(&mut self.font_system, Some(self.config.width as f32), Some(self.config.height as f32));                let mut text = String::new();                for (query, response) in &state.conversation {                    text.push_str(&format!("> {}\n", query));                    match response {                        AgentResponse::SuggestCommand { explanation, command } => {                            text.push_str(&format!("🤖 {}\n", explanation));                            text.push_str(&format!("[Suggested Command: {}\n\n", command));                        }                        AgentResponse::RequestToRunCommand { explanation, command_to_run } => {                            text.push_str(&format!("🤖 {}\n", explanation));                            text.push_str(&format!("[Press ENTER to run `{}` or ESC to cancel]\n\n", command_to_run));                        }                        AgentResponse::Clarification(c) => text.push_str(&format!("🤖 {}\n\n", c)),                    }                }                agent_buffer.set_text(&mut self.font_system, &text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                self.editor.set_buffer(agent_buffer);                self.editor.shape_as_needed(&mut self.font_system, true);                self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                // --- Render the special Agent Input Bar at the bottom ---                let mut input_text = format!("✨ {}", state.current_input);                self.render_input_bar(app, (0.0, self.config.height as f32 - self.char_height * 1.5), &mut render_pass);            } else {                // --- RENDER NORMAL/PINNED MODES ---                // --- RENDER OVERLAYS (Settings, Palette, etc.) ---                if let AppMode::Settings(state) = &app.mode {                    let prompt_mode_text = format!("\n\nPrompt Mode: {:?} (Press Enter to Toggle)", app.config.appearance.prompt_mode);                    let mut ui_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    ui_buffer.set_size(&mut self.font_system, Some(self.config.width as f32 - 50.0), Some(self.config.height as f32 - 50.0));                    let mut text = prompt_mode_text.clone();                    for (i, item) in state.filtered_list.iter().take(10).enumerate() {                        let (name, desc, kind) = match item {                            PaletteItem::Workflow(w) => (w.name.as_str(), w.description.as_str(), "Workflow"),                            PaletteItem::Notebook(n) => (n.name.as_str(), "", "Notebook"),                            PaletteItem::Action { name, description, .. } => (name.as_str(), description.as_str(), "Action"),                        };                        let line = if i == state.selected_idx {                            format!("> [{}] {} - {}\n", kind, name, desc)                        } else {                            format!("  [{}] {} - {}\n", kind, name, desc)                        };                        text.push_str(&line);                    }                    ui_buffer.set_text(&mut self.font_system, &text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(ui_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                } else if let AppMode::CommandPalette(state) = &app.mode {                    self.render_command_palette(app, state, &mut render_pass);                } else if let AppMode::HistorySearch(state) = &app.mode {                    self.render_history_search(app, state, &mut render_pass);                } else if let AppMode::Drive(state) = &app.mode {                    let (width, height) = (self.config.width as f32, self.config.height as f32);                    let padding = 20.0;                    let pane_split_x = width * 0.4;                    // --- Draw background overlay ---                    let mut bg_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));                    bg_buffer.set_text(&mut self.font_system, "█", Attrs::new().color(Color::rgba(20, 20, 20, 230)), Shaping::Advanced);                    self.editor.set_buffer(bg_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // --- Draw Left Pane (File Tree) ---                    let mut left_pane_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    left_pane_buffer.set_size(&mut self.font_system, Some(pane_split_x - padding * 2.0), Some(height - padding * 2.0));                    let mut tree_text = String::new();                    for (i, (name, depth)) in state.flat_items.iter().enumerate() {                        let indent = "  ".repeat(*depth);                        let line = if i == state.selected_idx {                            format!("> {}{}\n", indent, name)                        } else {                            format!("  {}{}\n", indent, name)                        };                        tree_text.push_str(&line);                    }                    left_pane_buffer.set_text(&mut self.font_system, &tree_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    left_pane_buffer.set_wrap(&mut self.font_system, cosmic_text::Wrap::Word);                    self.editor.set_buffer(left_pane_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // --- Draw Right Pane (Content Preview) ---                    let mut right_pane_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    right_pane_buffer.set_size(&mut self.font_system, Some(width - pane_split_x - padding), Some(height - padding * 2.0));                    // Find the actual object corresponding to the selected index                    let mut current_idx = 0;                    let mut selected_object: Option<&DriveObject> = None;                    let mut idx = 1; // skip workspace titles                    for obj in app.drive_manager.personal_ws.objects.iter() {                        if idx == state.selected_idx {                            selected_object = Some(obj);                            break;                        }                        idx += 1;                    }                    if selected_object.is_none() {                        for ws in &app.drive_manager.team_workspaces {                            idx += 1; // skip team workspace title                            for obj in ws.objects.iter() {                                if idx == state.selected_idx {                                    selected_object = Some(obj);                                    break;                                }                                idx += 1;                            }                            if selected_object.is_some() { break; }                        }                    }                    let mut preview_text = "Select an item to preview".to_string();                    if let Some(obj) = selected_object {                        preview_text = match obj {                            DriveObject::Workflow(w, m) => format!("Name: {}\n\nDescription: {}\n\nCommand:\n{}", w.name, w.description, w.command),                            DriveObject::Notebook(n, m) => format!("Name: {}\n\n---\n\n{}", n.name, n.content),                            DriveObject::Prompt(p, m) => format!("Prompt: {}\n\n{}", p.name, p.content),                            DriveObject::EnvVars(e, m) => format!("Env: {}\n\n{:?}", e.name, e.vars),                        };                    }                    right_pane_buffer.set_text(&mut self.font_system, &preview_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    right_pane_buffer.set_wrap(&mut self.font_system, cosmic_text::Wrap::Word);                    self.editor.set_buffer(right_pane_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // IMPORTANT: Restore the original terminal buffer for the next frame                    self.editor.set_buffer(self.buffer.clone());                }                // --- Render Workflow Overlay (existing code) ---                else if let AppMode::Workflow(state) = &app.mode {                    let (width, height) = (self.config.width as f32, self.config.height as f32);                    let padding = 50.0;                    // Create a separate buffer for the UI overlay                    let mut ui_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    ui_buffer.set_size(&mut self.font_system, Some(width - padding * 2.0), Some(height - padding * 2.0));                    // --- Argument Editing UI ---                    if let Some(exec_state) = &state.execution_state {                        let mut text = format!("Workflow: {}\n\n", exec_state.workflow.name);                        for (i, arg) in exec_state.workflow.arguments.iter().enumerate() {                            let cursor = if i == exec_state.selected_arg_idx { ">" } else { " " };                            text.push_str(&format!("{}[{}]: {}\n", cursor, arg.description, exec_state.argument_values[i]));                        }                        ui_buffer.set_text(&mut self.font_system, &text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                                        // --- Workflow Browser UI ---                    } else {                        let mut text = format!("Search: {}\n\n", state.query);                        for (i, workflow) in state.filtered_workflows.iter().take(10).enumerate() { // Limit to 10 results                            let line = if i == state.selected_workflow_idx {                                format!("> {} - {}\n", workflow.name, workflow.description)                            } else {                                format!("  {} - {}\n", workflow.name, workflow.description)                            };                            text.push_str(&line);                        }                        ui_buffer.set_text(&mut self.font_system, &text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    }                    // Prepare and draw the UI buffer                    self.editor.set_buffer(ui_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    // We "fake" a background by drawing a huge block character behind the text                    self.editor.buffer_mut().set_text(&mut self.font_system, "█", Attrs::new().color(Color::rgba(20, 20, 20, 200)).font_size(height), Shaping::Advanced);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // Now draw the actual UI text on top                    self.editor.shape_as_needed(&mut self.font_system, true); // Reshape with the UI text                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // IMPORTANT: Restore the original terminal buffer                    self.editor.set_buffer(self.buffer.clone());                } else if let AppMode::AgentManagement = &app.mode {                    // Draw overlay background                    let mut panel_text = "--- Agent Management ---\n\n".to_string();                    for pane in &app.panes {                        if let Some(agent_state) = &pane.agent_state {                            panel_text.push_str(&format!(                                "[{:?}] {} (Cancel)\n",                                agent_state.status, agent_state.task_summary                            ));                        }                    }                    let mut panel_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    panel_buffer.set_size(&mut self.font_system, Some(self.config.width as f32 * 0.6), Some(self.config.height as f32 * 0.6));                    panel_buffer.set_text(&mut self.font_system, &panel_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(panel_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                } else if let AppMode::DiffReview(state) = &app.mode {                    // Draw background overlay                    let (width, height) = (self.config.width as f32, self.config.height as f32);                    let padding = 40.0;                    let mut bg_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));                    bg_buffer.set_text(&mut self.font_system, "█", Attrs::new().color(Color::rgba(20, 20, 20, 230)).font_size(height * 2.0), Shaping::Advanced);                    self.editor.set_buffer(bg_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // --- Draw Header and File Tabs ---                    let mut header_text = format!("{}\n\n", state.explanation);                    for (i, file) in state.files.iter().enumerate() {                        let tab = if i == state.current_file_idx {                            format!("> {} <", file.file_path)                        } else {                            file.file_path.clone()                        };                        header_text.push_str(&format!("{}   ", tab));                    }                    let mut header_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    header_buffer.set_size(&mut self.font_system, Some(width - padding * 2.0), Some(60.0));                    header_buffer.set_text(&mut self.font_system, &header_text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(header_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    // --- Draw Side-by-Side Diff for the current file ---                    let file = &state.files[state.current_file_idx];                    let mut left_text = String::new();                    let mut right_text = String::new();                    let mut left_spans = AttrsList::new(Attrs::new());                    let mut right_spans = AttrsList::new(Attrs::new());                    let mut left_offset = 0;                    let mut right_offset = 0;                    for (i, hunk) in file.hunks.iter().enumerate() {                        let prefix = if i == state.current_hunk_idx { "> " } else { "  " };                        match hunk.tag {                            ChangeTag::Delete => {                                left_text.push_str(&format!("{}{}", prefix, hunk.original_text));                                left_spans.add_span(left_offset..left_offset + prefix.len() + hunk.original_text.len(), Attrs::new().color(Color::rgb(255, 80, 80)));                                left_offset += prefix.len() + hunk.original_text.len();                                right_text.push('\n');                                right_offset += 1;                            }                            ChangeTag::Insert => {                                left_text.push('\n');                                left_offset += 1;                                right_text.push_str(&format!("{}{}", prefix, hunk.new_text));                                right_spans.add_span(right_offset..right_offset + prefix.len() + hunk.new_text.len(), Attrs::new().color(Color::rgb(80, 255, 80)));                                right_offset += prefix.len() + hunk.new_text.len();                            }                            ChangeTag::Equal => {                                left_text.push_str(&format!("{}{}", prefix, hunk.original_text));                                left_spans.add_span(left_offset..left_offset + prefix.len() + hunk.original_text.len(), Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)));                                left_offset += prefix.len() + hunk.original_text.len();                                right_text.push_str(&format!("{}{}", prefix, hunk.new_text));                                right_spans.add_span(right_offset..right_offset + prefix.len() + hunk.new_text.len(), Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)));                                right_offset += prefix.len() + hunk.new_text.len();                            }                        }                        left_text.push('\n');                        left_offset += 1;                        right_text.push('\n');                        right_offset += 1;                    }                    // --- Render the two panes ---                    let pane_width = (width - padding * 3.0) / 2.0;                    let pane_height = height - padding * 4.0 - 60.0;                    let mut left_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    left_buffer.set_size(&mut self.font_system, Some(pane_width), Some(pane_height));                    left_buffer.set_text(&mut self.font_system, &left_text, left_spans, Shaping::Advanced);                    self.editor.set_buffer(left_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    let mut right_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    right_buffer.set_size(&mut self.font_system, Some(pane_width), Some(pane_height));                    right_buffer.set_text(&mut self.font_system, &right_text, right_spans, Shaping::Advanced);                    self.editor.set_buffer(right_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    self.editor.set_buffer(self.buffer.clone());                    // --- Render instructions ---                    let instructions = "UP/DOWN: Select hunk   LEFT/RIGHT: Switch file   ENTER: Apply all   R: Refine   E: Edit   ESC: Cancel";                    let mut instr_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());                    instr_buffer.set_size(&mut self.font_system, Some(width - padding * 2.0), Some(30.0));                    instr_buffer.set_text(&mut self.font_system, instructions, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);                    self.editor.set_buffer(instr_buffer);                    self.editor.shape_as_needed(&mut self.font_system, true);                    self.editor.draw(&mut self.font_system, &mut self.swash_cache, &mut render_pass);                    self.editor.set_buffer(self.buffer.clone());                }            }        }                self.queue.submit(Some(encoder.finish()));        output.present();        Ok(())    }    fn render_input_bar(&mut self, app: &App, pos: (f32, f32), render_pass: &mut wgpu::RenderPass<'a>) {        let (x, y) = pos;        let mut display_text = String::new();        if let Some(vim_state) = &app.vim_state {            let mode_indicator = match vim_state.mode {                VimMode::Normal => "  NORMAL ",                VimMode::Insert => "  INSERT ",                VimMode::Visual => "  VISUAL ",            };            display_text.push_str(mode_indicator);        }        // Draw the user's actual input        self.editor.set_buffer(app.input_editor.buffer().clone());        self.editor.shape_as_needed(&mut self.font_system, true);        self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        // Draw the autosuggestion as ghost text        if let Some(suggestion) = &app.autosuggestion {            // Calculate where the user's text ends            let mut last_run_x = 0.0;            let mut last_run_y = 0.0;            for run in self.editor.buffer().layout_runs() {                last_run_x = run.line_x + run.line_w;                last_run_y = run.line_y;            }            let ghost_color = Color::rgba(128, 128, 128, 128); // A dim grey            let mut ghost_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());            ghost_buffer.set_text(&mut self.font_system, suggestion, Attrs::new().color(ghost_color), Shaping::Advanced);            self.editor.set_buffer(ghost_buffer);            self.editor.shape_as_needed(&mut self.font_system, true);            self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        }    }    fn render_cursor(&mut self, app: &App, pos: &GridCoords, render_pass: &mut wgpu::RenderPass<'a>) {        let (row, col) = (pos.y as f32, pos.x as f32);        let x = col * self.char_width;        let y = row * self.char_height;        let cursor_shape = if let Some(vim_state) = &app.vim_state {            match vim_state.mode {                VimMode::Insert => CursorShape::Bar,                _ => CursorShape::Block,            }        } else {            app.config.appearance.cursor.shape.clone()        };        let cursor_char = match cursor_shape {            CursorShape::Block => "█",            CursorShape::Bar => "▎",            CursorShape::Underline => " ", // Special case for underline        };        let cursor_color = hex_to_color(&app.theme.colors.cursor.cursor);        let mut cursor_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());        cursor_buffer.set_size(&mut self.font_system, Some(self.char_width), Some(self.char_height));        if cursor_shape == CursorShape::Underline {             let underline_y = y + self.char_height - (self.char_height / 4.0);             cursor_buffer.set_text(&mut self.font_system, "▀", Attrs::new().color(cursor_color), Shaping::Advanced);             self.editor.set_buffer(cursor_buffer);             self.editor.shape_as_needed(&mut self.font_system, true);             self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        } else {             cursor_buffer.set_text(&mut self.font_system, cursor_char, Attrs::new().color(cursor_color), Shaping::Advanced);             self.editor.set_buffer(cursor_buffer);             self.editor.shape_as_needed(&mut self.font_system, true);             self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        }    }    fn render_history_search(&mut self, app: &App, state: &crate::app::state::HistorySearchState, render_pass: &mut wgpu::RenderPass<'a>) {        let (width, height) = (self.config.width as f32, self.config.height as f32);        let padding = 50.0;        // Draw background        let mut bg_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());        bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));        bg_buffer.set_text(&mut self.font_system, "█", Attrs::new().color(Color::rgba(20, 20, 20, 230)).font_size(height * 2.0), Shaping::Advanced);        self.editor.set_buffer(bg_buffer);        self.editor.shape_as_needed(&mut self.font_system, true);        self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        // Draw UI text        let mut ui_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());        ui_buffer.set_size(&mut self.font_system, Some(width - padding * 2.0), Some(height - padding * 2.0));        let mut text = format!("Search History: {}\n\n", state.query);        for (i, item) in state.filtered_list.iter().take(10).enumerate() {            let line = if i == state.selected_idx {                format!("> {}\n", item)            } else {                format!("  {}\n", item)            };            text.push_str(&line);        }        ui_buffer.set_text(&mut self.font_system, &text, Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)), Shaping::Advanced);        self.editor.set_buffer(ui_buffer);        self.editor.shape_as_needed(&mut self.font_system, true);        self.editor.draw(&mut self.font_system, &mut self.swash_cache, render_pass);        self.editor.set_buffer(self.buffer.clone());    }}
+use crate::app::state::Grid;
+use crate::app::state::INPUT_DOCUMENT;
+use crate::perf::{FramePhase, FrameTimer, GpuTimer, PerfStats};
+use crate::pty::vte_handler::GridCoords;
+use crate::syntax_parser::HighlightKind;
+use crate::ui::dpi::{grid_size, ScaledMetrics};
+use crate::ui::minimap::{MarkKind, STRIP_COLUMNS};
+use crate::vim::VimMode;
+use crate::{
+    agent::client::AgentResponse,
+    app::{
+        pane::AgentState,
+        state::{
+            App, AppMode, CursorShape, InputPosition, PaletteItem, PromptMode, RefactorKind,
+            WebSearchView,
+        },
+    },
+    completions_ui::CompletionsUI,
+    config::{
+        theme::{Colors, Theme},
+        TextConfig,
+    },
+    drive::{DriveObject, Notebook, Prompt, Workflow},
+    pty::vte_handler::VteState,
+};
+
+use cosmic_text::{
+    Attrs, AttrsList, Buffer, Color, Edit, Editor, FontSystem, Metrics, Shaping,
+    Style as FontStyle, SwashCache, Weight, Wrap,
+};
+
+use similar::ChangeTag;
+use std::time::{Duration, Instant};
+use vte::ansi::Color as VteColor;
+use winit::window::Window;
+
+fn hex_to_color(hex: &str) -> Color {
+    let hex = hex.trim_start_matches('#');
+    let (r, g, b) = match hex.len() {
+        6 => (
+            u8::from_str_radix(&hex[0..2], 16).unwrap_or(255),
+            u8::from_str_radix(&hex[2..4], 16).unwrap_or(255),
+            u8::from_str_radix(&hex[4..6], 16).unwrap_or(255),
+        ),
+        _ => (255, 255, 255),
+    };
+    Color::rgb(r, g, b)
+}
+
+fn highlight_color(kind: HighlightKind, theme: &Theme) -> Color {
+    let colors = &theme.colors;
+    // The theme's syntax colors win; the ANSI palette fills in the rest
+    let syntax = colors.syntax.as_ref().and_then(|syntax| match kind {
+        HighlightKind::Function => syntax.command.as_ref(),
+        HighlightKind::Flag => syntax.flag.as_ref(),
+        HighlightKind::String => syntax.string.as_ref(),
+        HighlightKind::Variable => syntax.variable.as_ref(),
+        HighlightKind::Operator => syntax.operator.as_ref(),
+        HighlightKind::Keyword => syntax.keyword.as_ref(),
+        HighlightKind::Comment => syntax.comment.as_ref(),
+        _ => None,
+    });
+    hex_to_color(syntax.unwrap_or(match kind {
+        HighlightKind::Keyword => &colors.normal.magenta,
+        HighlightKind::Function => &colors.normal.blue,
+        HighlightKind::Type | HighlightKind::Flag => &colors.normal.yellow,
+        HighlightKind::String => &colors.normal.green,
+        HighlightKind::Constant | HighlightKind::Operator => &colors.normal.cyan,
+        HighlightKind::Comment => &colors.bright.black,
+        HighlightKind::Variable => &colors.normal.red,
+        HighlightKind::Property => &colors.primary.foreground,
+    }))
+}
+
+/// The terminal grid as text, each cell colored from `theme` and in bold or
+/// italic as the shell asked.
+pub(crate) fn grid_rich_text(vte_state: &VteState, theme: &Theme) -> (String, AttrsList) {
+    let grid = vte_state.get_grid();
+    let mut text = String::new();
+    let mut attrs_list = AttrsList::new(Attrs::new());
+    for row in grid.rows_iter() {
+        for cell in row {
+            let start = text.len();
+            text.push(cell.c);
+            let mut attrs = Attrs::new().color(to_cosmic_color(cell.fg, theme));
+            if cell.flags.contains(vte::ansi::Flags::BOLD) {
+                attrs = attrs.weight(Weight::BOLD);
+            }
+            if cell.flags.contains(vte::ansi::Flags::ITALIC) {
+                attrs = attrs.style(FontStyle::Italic);
+            }
+            attrs_list.add_span(start..text.len(), attrs);
+        }
+        text.push('\n');
+    }
+    (text, attrs_list)
+}
+
+/// The completions popup's rows as colored spans, with the columns each
+/// takes: icon, word with its matched characters highlighted, description
+/// and type tag, then a footer counting suggestions scrolled out of view.
+pub(crate) fn completion_rows(
+    ui: &CompletionsUI,
+    colors: &Colors,
+) -> Vec<(Vec<(String, Attrs<'static>)>, usize)> {
+    let text_attrs = Attrs::new().color(hex_to_color(&colors.primary.foreground));
+    let selected_attrs = Attrs::new().color(hex_to_color(&colors.selection.text));
+    let detail_attrs = Attrs::new().color(hex_to_color(&colors.bright.black));
+    let match_attrs = Attrs::new()
+        .color(hex_to_color(&colors.normal.yellow))
+        .weight(Weight::BOLD);
+    // Each row as spans, with the columns it takes
+    let mut rows: Vec<(Vec<(String, Attrs)>, usize)> = Vec::new();
+    for (i, suggestion) in ui.visible_suggestions() {
+        let attrs = if i == ui.selected_index {
+            selected_attrs
+        } else {
+            text_attrs
+        };
+        let mut spans = vec![(
+            format!(
+                " {} ",
+                crate::string_offset::pad_to_width(suggestion.suggestion_type.icon(), 2)
+            ),
+            attrs,
+        )];
+        // The characters the word matched stand out
+        let mut start = 0;
+        for position in ui.highlights(suggestion) {
+            let Some(ch) = suggestion
+                .display
+                .get(position..)
+                .and_then(|rest| rest.chars().next())
+            else {
+                continue;
+            };
+            spans.push((suggestion.display[start..position].to_string(), attrs));
+            spans.push((ch.to_string(), match_attrs));
+            start = position + ch.len_utf8();
+        }
+        spans.push((suggestion.display[start..].to_string(), attrs));
+        if let Some(description) = &suggestion.description {
+            spans.push((format!("  {}", description), detail_attrs));
+        }
+        spans.push((
+            format!("  {} ", suggestion.suggestion_type.tag()),
+            detail_attrs,
+        ));
+        let width = spans
+            .iter()
+            .map(|(text, _)| crate::string_offset::display_width(text))
+            .sum();
+        rows.push((spans, width));
+    }
+    // Past ten suggestions the list scrolls with the selection
+    let (above, below) = ui.hidden_counts();
+    if above + below > 0 {
+        let footer = format!(" ↑{}  ↓{} ", above, below);
+        let width = crate::string_offset::display_width(&footer);
+        rows.push((vec![(footer, detail_attrs)], width));
+    }
+    rows
+}
+
+fn to_cosmic_color(c: VteColor, theme: &Theme) -> Color {
+    match c {
+        VteColor::Named(c) => match c {
+            vte::ansi::NamedColor::Black => hex_to_color(&theme.colors.normal.black),
+            vte::ansi::NamedColor::Red => hex_to_color(&theme.colors.normal.red),
+            vte::ansi::NamedColor::Green => hex_to_color(&theme.colors.normal.green),
+            vte::ansi::NamedColor::Yellow => hex_to_color(&theme.colors.normal.yellow),
+            vte::ansi::NamedColor::Blue => hex_to_color(&theme.colors.normal.blue),
+            vte::ansi::NamedColor::Magenta => hex_to_color(&theme.colors.normal.magenta),
+            vte::ansi::NamedColor::Cyan => hex_to_color(&theme.colors.normal.cyan),
+            vte::ansi::NamedColor::White => hex_to_color(&theme.colors.normal.white),
+            vte::ansi::NamedColor::BrightBlack => hex_to_color(&theme.colors.bright.black),
+            vte::ansi::NamedColor::BrightRed => hex_to_color(&theme.colors.bright.red),
+            vte::ansi::NamedColor::BrightGreen => hex_to_color(&theme.colors.bright.green),
+            vte::ansi::NamedColor::BrightYellow => hex_to_color(&theme.colors.bright.yellow),
+            vte::ansi::NamedColor::BrightBlue => hex_to_color(&theme.colors.bright.blue),
+            vte::ansi::NamedColor::BrightMagenta => hex_to_color(&theme.colors.bright.magenta),
+            vte::ansi::NamedColor::BrightCyan => hex_to_color(&theme.colors.bright.cyan),
+            vte::ansi::NamedColor::BrightWhite => hex_to_color(&theme.colors.bright.white),
+            _ => hex_to_color(&theme.colors.primary.foreground),
+        },
+        VteColor::Spec(rgb) => Color::rgb(rgb.r, rgb.g, rgb.b),
+        VteColor::Indexed(idx) => {
+            let r = (idx & 0xE0) >> 5;
+            let g = (idx & 0x1C) >> 2;
+            let b = idx & 0x03;
+            Color::rgb(r * 36, g * 36, b * 72)
+        }
+        VteColor::Default => hex_to_color(&theme.colors.primary.foreground),
+    }
+}
+
+/// The advance of `M`, the width of a grid cell.
+fn measure_char_width(font_system: &mut FontSystem, metrics: Metrics) -> f32 {
+    let mut buffer = Buffer::new(font_system, metrics);
+    buffer.set_text(font_system, "M", Attrs::new(), Shaping::Advanced);
+    buffer
+        .layout_runs()
+        .next()
+        .map_or(metrics.font_size, |run| {
+            run.glyphs.first().map_or(0.0, |g| g.w)
+        })
+}
+
+pub struct Renderer<'a> {
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    font_system: FontSystem,
+    swash_cache: SwashCache,
+    buffer: Buffer,
+    editor: Editor<'a>,
+    pub char_width: f32,
+    pub char_height: f32,
+    // Logical font size and line height from the config, scaled by the window's scale factor
+    font_size: f32,
+    line_height: f32,
+    pub scale_factor: f64,
+    // Frame timing for the performance HUD
+    frame_timer: FrameTimer,
+    perf_stats: PerfStats,
+    gpu_timer: Option<GpuTimer>,
+}
+
+impl<'a> Renderer<'a> {
+    pub async fn new(window: &Window, font_data: Vec<u8>, text_config: &TextConfig) -> Self {
+        let size = window.inner_size();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let surface = instance.create_surface(window).unwrap();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .unwrap();
+        // Timestamp queries, where supported, time frames on the GPU for the performance HUD
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .unwrap_or(surface_caps.formats[0]);
+        let composite_alpha_mode = surface_caps
+            .alpha_modes
+            .iter()
+            .copied()
+            .find(|&m| {
+                m == wgpu::CompositeAlphaMode::Auto || m == wgpu::CompositeAlphaMode::PreMultiplied
+            })
+            .unwrap_or(surface_caps.alpha_modes[0]);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: composite_alpha_mode,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(&device, &config);
+        let mut font_system = FontSystem::new();
+        let swash_cache = SwashCache::new();
+        font_system.db_mut().load_font_data(font_data);
+        let scaled = ScaledMetrics::new(
+            text_config.font_size,
+            text_config.line_height,
+            window.scale_factor(),
+        );
+        let metrics = Metrics::new(scaled.font_size, scaled.line_height);
+        let shaping = if text_config.use_ligatures {
+            Shaping::Advanced
+        } else {
+            Shaping::Basic
+        };
+        let mut buffer = Buffer::new(&mut font_system, metrics);
+        buffer.set_size(
+            &mut font_system,
+            Some(size.width as f32),
+            Some(size.height as f32),
+        );
+        // buffer.set_shaping(&mut font_system, shaping); // Removed as per cosmic-text 0.11 API
+        let editor = Editor::new(buffer);
+        let char_width = measure_char_width(&mut font_system, metrics);
+        Self {
+            surface,
+            device,
+            queue,
+            config,
+            font_system,
+            swash_cache,
+            buffer: editor.buffer().clone(),
+            editor,
+            char_width,
+            char_height: scaled.line_height,
+            font_size: text_config.font_size,
+            line_height: text_config.line_height,
+            scale_factor: scaled.scale_factor,
+            frame_timer: FrameTimer::new(),
+            perf_stats: PerfStats::new(),
+            gpu_timer,
+        }
+    }
+
+    pub fn sync_with_vte(&mut self, vte_state: &VteState, theme: &Theme) {
+        let (text, attrs_list) = grid_rich_text(vte_state, theme);
+        self.editor.buffer_mut().set_text(
+            &mut self.font_system,
+            &text,
+            attrs_list,
+            Shaping::Advanced,
+        );
+        self.shape_editor();
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) -> (u16, u16) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+            self.editor.buffer_mut().set_size(
+                &mut self.font_system,
+                Some(new_size.width as f32),
+                Some(new_size.height as f32),
+            );
+            self.shape_editor();
+        }
+        grid_size(
+            new_size.width,
+            new_size.height,
+            self.char_width,
+            self.char_height,
+        )
+    }
+
+    /// Re-shapes text for a new scale factor, as when the window moves to a
+    /// monitor with a different DPI, and returns the grid that now fits.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) -> (u16, u16) {
+        let scaled = ScaledMetrics::new(self.font_size, self.line_height, scale_factor);
+        let metrics = Metrics::new(scaled.font_size, scaled.line_height);
+        self.scale_factor = scaled.scale_factor;
+        self.char_width = measure_char_width(&mut self.font_system, metrics);
+        self.char_height = scaled.line_height;
+        self.buffer.set_metrics(&mut self.font_system, metrics);
+        self.editor
+            .buffer_mut()
+            .set_metrics(&mut self.font_system, metrics);
+        self.shape_editor();
+        grid_size(
+            self.config.width,
+            self.config.height,
+            self.char_width,
+            self.char_height,
+        )
+    }
+
+    pub fn render(
+        &mut self,
+        app: &mut App,
+        time_since_start: Duration,
+    ) -> Result<(), wgpu::SurfaceError> {
+        crate::metrics::METRICS.frames_rendered.inc();
+        self.frame_timer.begin();
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.poll(&self.device);
+        }
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let bg = hex_to_color(&app.theme.colors.primary.background);
+            let alpha = app.config.appearance.opacity;
+            let clear_color = if alpha < 1.0 {
+                wgpu::Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: 0.0,
+                }
+            } else {
+                wgpu::Color {
+                    r: bg.r() as f64 / 255.0,
+                    g: bg.g() as f64 / 255.0,
+                    b: bg.b() as f64 / 255.0,
+                    a: 1.0,
+                }
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                timestamp_writes: if app.show_perf_hud {
+                    self.gpu_timer.as_ref().and_then(GpuTimer::timestamp_writes)
+                } else {
+                    None
+                },
+                ..Default::default()
+            });
+            let (win_width, win_height) = (self.config.width as f32, self.config.height as f32);
+            let num_panes = app.panes.len();
+            let pane_width = win_width / num_panes as f32;
+            for (pane_idx, pane) in app.panes.iter().enumerate() {
+                let pane_x = pane_idx as f32 * pane_width;
+                // Blocks move by the pane's pixel scroll offset; the live grid stays row-based.
+                let mut y_offset = -pane.scroll.offset();
+                // Locked panes show only a notice until the user unlocks them
+                if app.idle_monitor.is_locked(pane.id) {
+                    let mut lock_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    lock_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(self.char_height * 2.4),
+                    );
+                    lock_buffer.set_text(
+                        &mut self.font_system,
+                        "🔒 Pane locked after inactivity\nPress any key to unlock",
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(lock_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    continue;
+                }
+                // A pair's agent pane lists its latest suggestions instead of blocks
+                if let (Some(session), Some(agent_state)) = (
+                    app.pair
+                        .session_for(pane.id)
+                        .filter(|session| session.agent_pane_id == pane.id),
+                    &pane.agent_state,
+                ) {
+                    let watched = app
+                        .panes
+                        .iter()
+                        .position(|pane| pane.id == session.shell_pane_id)
+                        .map_or(0, |idx| idx + 1);
+                    let status = if app.pair.is_paused() || !app.config.pair.enabled {
+                        "paused"
+                    } else {
+                        "watching"
+                    };
+                    let mut pair_text = format!("🤝 Pair · {} pane {}\n", status, watched);
+                    if agent_state.conversation.is_empty() {
+                        pair_text.push_str("\nSuggestions appear here as you run commands.");
+                    }
+                    for (_, response) in agent_state.conversation.iter().rev().take(5).rev() {
+                        pair_text.push_str(&format!("\n{}\n", crate::pair::describe(response)));
+                    }
+                    let mut pair_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    pair_buffer.set_size(&mut self.font_system, Some(pane_width), Some(win_height));
+                    pair_buffer.set_text(
+                        &mut self.font_system,
+                        &pair_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(pair_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    continue;
+                }
+                // Scrubbing shows the pane as it was at the chosen moment
+                let frame = match &app.mode {
+                    AppMode::Scrub(state) if state.pane_idx == pane_idx => {
+                        Some(pane.timeline.frame(state.at))
+                    }
+                    _ => None,
+                };
+                // --- 1. RENDER HISTORICAL BLOCKS ---
+                for (block_idx, block) in pane.history.iter().enumerate() {
+                    if frame
+                        .as_ref()
+                        .is_some_and(|frame| !frame.blocks.contains(&block.id))
+                    {
+                        continue;
+                    }
+                    // Render prompt and command
+                    let mut cmd_text = match &block.remote {
+                        Some(remote) => format!("[{}] > {}", remote.label(), block.command),
+                        None => format!("> {}", block.command),
+                    };
+                    if !block.tags.is_empty() {
+                        cmd_text.push_str(&format!("  #{}", block.tags.join(" #")));
+                    }
+                    // Mark blocks picked for a batch action
+                    if let AppMode::BlockSelect(state) = &app.mode {
+                        if state.pane_idx == pane_idx {
+                            let cursor = if state.selection.cursor() == block_idx {
+                                ">"
+                            } else {
+                                " "
+                            };
+                            let mark = if state.selection.contains(block_idx) {
+                                "[x]"
+                            } else {
+                                "[ ]"
+                            };
+                            cmd_text = format!("{}{} {}", cursor, mark, cmd_text);
+                        }
+                    }
+                    let mut cmd_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    cmd_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(self.char_height * 1.2),
+                    );
+                    cmd_buffer.set_text(
+                        &mut self.font_system,
+                        &cmd_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(cmd_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    y_offset += self.char_height * 1.2;
+                    // Render output
+                    let mut output_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    // File locations are colored as links; the block menu opens them
+                    let output_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let link_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.normal.blue));
+                    let mut output_spans = Vec::new();
+                    // Stack traces fold library frames and color the user's frames
+                    let trace_lines = if block.stack_traces.is_empty() {
+                        Vec::new()
+                    } else {
+                        crate::structured::stacktrace::display_lines(
+                            &block.output,
+                            &block.stack_traces,
+                            block.library_frames_folded,
+                        )
+                    };
+                    // Collapsed and huge outputs draw only part of their lines, with a marker for the rest
+                    let shown = crate::app::fold::visible_output(
+                        &block.output,
+                        pane.output_view(block, &app.config.blocks),
+                        &app.config.blocks,
+                    );
+                    // Tall enough for the shown lines and their markers
+                    let output_rows = if trace_lines.is_empty() {
+                        shown.rows()
+                    } else {
+                        trace_lines.len()
+                    };
+                    output_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(self.char_height * output_rows.max(2) as f32),
+                    );
+                    let before_marker = shown.before_marker();
+                    let after_marker = shown.after_marker();
+                    let marker_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    if trace_lines.is_empty() {
+                        if let Some(marker) = &before_marker {
+                            output_spans.push((marker.as_str(), marker_attrs));
+                        }
+                        let mut last = 0;
+                        for found in crate::integration::editor::find_locations(shown.text) {
+                            output_spans.push((&shown.text[last..found.start], output_attrs));
+                            output_spans.push((&shown.text[found.start..found.end], link_attrs));
+                            last = found.end;
+                        }
+                        output_spans.push((&shown.text[last..], output_attrs));
+                        if let Some(marker) = &after_marker {
+                            output_spans.push((marker.as_str(), marker_attrs));
+                        }
+                    } else {
+                        let selected_frame = match &app.mode {
+                            AppMode::StackTrace(state)
+                                if state.pane_idx == pane_idx && state.block_idx == block_idx =>
+                            {
+                                Some(state.frame_idx)
+                            }
+                            _ => None,
+                        };
+                        for line in &trace_lines {
+                            let attrs = match line.kind {
+                                _ if selected_frame.is_some() && line.frame == selected_frame => {
+                                    Attrs::new().color(hex_to_color(&app.theme.colors.normal.cyan))
+                                }
+                                crate::structured::stacktrace::LineKind::UserFrame => Attrs::new()
+                                    .color(hex_to_color(&app.theme.colors.normal.yellow)),
+                                crate::structured::stacktrace::LineKind::LibraryFrame
+                                | crate::structured::stacktrace::LineKind::Folded => {
+                                    Attrs::new().color(hex_to_color(&app.theme.colors.bright.black))
+                                }
+                                crate::structured::stacktrace::LineKind::Text => output_attrs,
+                            };
+                            output_spans.push((line.text.as_str(), attrs));
+                            output_spans.push(("\n", output_attrs));
+                        }
+                    }
+                    output_buffer.set_rich_text(
+                        &mut self.font_system,
+                        output_spans,
+                        output_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(output_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    y_offset += self
+                        .editor
+                        .buffer()
+                        .total_height()
+                        .max(self.char_height * 2.0);
+                    // Render the CSV summary footer, if requested
+                    if let Some(summary) = &block.csv_summary {
+                        let footer_text = summary.footer(block.csv_summary_folded);
+                        let footer_lines = footer_text.lines().count() as f32;
+                        let mut footer_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        footer_buffer.set_size(
+                            &mut self.font_system,
+                            Some(pane_width),
+                            Some(self.char_height * 1.2 * footer_lines),
+                        );
+                        footer_buffer.set_text(
+                            &mut self.font_system,
+                            &footer_text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.normal.cyan)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(footer_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                        y_offset += self.char_height * 1.2 * footer_lines;
+                    }
+                    // Render the translation footer, if requested
+                    if let Some(translation) = &block.translation {
+                        let footer_text = translation.footer();
+                        let footer_lines = footer_text.lines().count() as f32;
+                        let mut footer_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        footer_buffer.set_size(
+                            &mut self.font_system,
+                            Some(pane_width),
+                            Some(self.char_height * 1.2 * footer_lines),
+                        );
+                        footer_buffer.set_text(
+                            &mut self.font_system,
+                            &footer_text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.normal.magenta)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(footer_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                        y_offset += self.char_height * 1.2 * footer_lines;
+                    }
+                    // Render "..." menu icon
+                    let mut menu_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    menu_buffer.set_size(
+                        &mut self.font_system,
+                        Some(30.0),
+                        Some(self.char_height * 1.2),
+                    );
+                    menu_buffer.set_text(
+                        &mut self.font_system,
+                        "⋯",
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(menu_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                }
+                // --- 2. RENDER THE LIVE VTE GRID (or its snapshot while scrubbing) ---
+                if let Some(frame) = &frame {
+                    let mut snapshot_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    snapshot_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(win_height - y_offset),
+                    );
+                    snapshot_buffer.set_text(
+                        &mut self.font_system,
+                        frame.screen,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(snapshot_buffer);
+                } else {
+                    let vte_state = pane.current_vte.lock().unwrap();
+                    self.sync_with_vte(&vte_state, &app.theme);
+                    self.editor.buffer_mut().set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(win_height - y_offset),
+                    );
+                }
+                self.shape_editor();
+                self.draw_editor(&mut render_pass);
+                // Collaborator cursors, each in its own color
+                if let Some(share) = app
+                    .live_share
+                    .as_ref()
+                    .filter(|share| share.pane_id == pane.id)
+                {
+                    for collaborator in share.collaborators() {
+                        let Some((row, col)) = collaborator.cursor else {
+                            continue;
+                        };
+                        let marker = format!(
+                            "{}{}▏{}",
+                            "\n".repeat(row as usize),
+                            " ".repeat(col as usize),
+                            collaborator.name
+                        );
+                        let mut cursor_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        cursor_buffer.set_size(
+                            &mut self.font_system,
+                            Some(pane_width),
+                            Some(win_height - y_offset),
+                        );
+                        cursor_buffer.set_text(
+                            &mut self.font_system,
+                            &marker,
+                            Attrs::new().color(hex_to_color(&collaborator.color)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(cursor_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                    }
+                    self.editor.set_buffer(self.buffer.clone());
+                }
+                // The command whose output fills the top of the pane stays pinned there, with its status and duration
+                if app.config.blocks.sticky_header {
+                    if let Some(header) = app.sticky_header(pane_idx) {
+                        let colors = &app.theme.colors;
+                        let indent = " ".repeat((pane_x / self.char_width) as usize);
+                        let columns =
+                            ((pane_width / self.char_width) as usize).saturating_sub(STRIP_COLUMNS);
+                        let status_width = crate::string_offset::display_width(&header.status);
+                        let command = crate::string_offset::clip_to_width(
+                            &header.command,
+                            columns.saturating_sub(status_width + 5),
+                        );
+                        let gap = columns.saturating_sub(
+                            crate::string_offset::display_width(command) + status_width + 4,
+                        );
+                        let header_attrs = Attrs::new()
+                            .color(hex_to_color(&colors.primary.foreground))
+                            .weight(Weight::BOLD);
+                        let status_attrs = Attrs::new().color(hex_to_color(if header.failed {
+                            &colors.normal.red
+                        } else {
+                            &colors.normal.green
+                        }));
+                        let layers = [
+                            vec![(
+                                format!("{}{}", indent, "█".repeat(columns)),
+                                Attrs::new().color(hex_to_color(&colors.selection.background)),
+                            )],
+                            vec![
+                                (
+                                    format!("{} $ {}{}", indent, command, " ".repeat(gap)),
+                                    header_attrs,
+                                ),
+                                (format!("{} ", header.status), status_attrs),
+                            ],
+                        ];
+                        for layer in layers {
+                            let mut header_buffer =
+                                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                            header_buffer.set_wrap(&mut self.font_system, Wrap::None);
+                            header_buffer.set_size(
+                                &mut self.font_system,
+                                Some(win_width),
+                                Some(self.char_height),
+                            );
+                            header_buffer.set_rich_text(
+                                &mut self.font_system,
+                                layer.iter().map(|(text, attrs)| (text.as_str(), *attrs)),
+                                header_attrs,
+                                Shaping::Advanced,
+                            );
+                            self.editor.set_buffer(header_buffer);
+                            self.shape_editor();
+                            self.draw_editor(&mut render_pass);
+                        }
+                    }
+                }
+                // Minimap of the whole scrollback along the pane's right edge, with the rows on screen shaded
+                if app.config.blocks.show_minimap && !pane.history.is_empty() {
+                    if let Some(minimap) = app.minimap(pane_idx) {
+                        let colors = &app.theme.colors;
+                        let rows = (win_height / self.char_height) as usize;
+                        let content_height =
+                            (minimap.total_rows() as f32 * self.char_height).max(1.0);
+                        let first_visible =
+                            (pane.scroll.offset() / content_height * rows as f32) as usize;
+                        let last_visible = ((pane.scroll.offset() + win_height) / content_height
+                            * rows as f32)
+                            .ceil() as usize;
+                        let indent = " ".repeat(
+                            (((pane_x + pane_width) / self.char_width) as usize)
+                                .saturating_sub(STRIP_COLUMNS),
+                        );
+                        let default_attrs =
+                            Attrs::new().color(hex_to_color(&colors.primary.foreground));
+                        let mut strip_spans: Vec<(String, Attrs)> = Vec::new();
+                        for (row, mark) in minimap.strip(rows).into_iter().enumerate() {
+                            let color = match mark {
+                                Some(MarkKind::Error) => Some(&colors.normal.red),
+                                Some(MarkKind::SearchHit) => Some(&colors.normal.yellow),
+                                Some(MarkKind::BlockBoundary) => Some(&colors.bright.black),
+                                None if (first_visible..last_visible.max(first_visible + 1))
+                                    .contains(&row) =>
+                                {
+                                    Some(&colors.selection.background)
+                                }
+                                None => None,
+                            };
+                            let cell = if color.is_some() { "█" } else { " " };
+                            strip_spans.push((
+                                format!("{}{}\n", indent, cell),
+                                color.map_or(default_attrs, |color| {
+                                    Attrs::new().color(hex_to_color(color))
+                                }),
+                            ));
+                        }
+                        let mut strip_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        strip_buffer.set_wrap(&mut self.font_system, Wrap::None);
+                        strip_buffer.set_size(
+                            &mut self.font_system,
+                            Some(win_width),
+                            Some(win_height),
+                        );
+                        strip_buffer.set_rich_text(
+                            &mut self.font_system,
+                            strip_spans
+                                .iter()
+                                .map(|(text, attrs)| (text.as_str(), *attrs)),
+                            default_attrs,
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(strip_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                    }
+                }
+                // --- 3. RENDER BLOCK CONTEXT MENU (if active) ---
+                if let AppMode::BlockMenu(state) = &app.mode {
+                    if state.pane_idx == pane_idx {
+                        let menu_items = state.items();
+                        let menu_width = 220.0;
+                        let menu_height = self.char_height * menu_items.len() as f32 * 1.2 + 20.0;
+                        let menu_x = pane_x + pane_width - menu_width - 10.0;
+                        let menu_y = 40.0 + (state.block_idx as f32) * self.char_height * 2.0;
+                        let mut menu_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        menu_buffer.set_size(
+                            &mut self.font_system,
+                            Some(menu_width),
+                            Some(menu_height),
+                        );
+                        let mut menu_text = String::new();
+                        for (i, item) in menu_items.iter().enumerate() {
+                            if i == state.selected_action_idx {
+                                menu_text.push_str(&format!("> {}\n", item));
+                            } else {
+                                menu_text.push_str(&format!("  {}\n", item));
+                            }
+                        }
+                        menu_buffer.set_text(
+                            &mut self.font_system,
+                            &menu_text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(menu_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                        self.editor.set_buffer(self.buffer.clone());
+                    }
+                }
+                // --- 4. RENDER SCRATCH PANE SAVE PROMPT (if active) ---
+                if let AppMode::ScratchClose(state) = &app.mode {
+                    if state.pane_idx == pane_idx {
+                        let pane = &app.panes[pane_idx];
+                        let prompt_width = 420.0;
+                        let prompt_height =
+                            self.char_height * (state.block_indices.len() + 3) as f32 * 1.2 + 20.0;
+                        let mut prompt_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        prompt_buffer.set_size(
+                            &mut self.font_system,
+                            Some(prompt_width),
+                            Some(prompt_height),
+                        );
+                        let mut prompt_text =
+                            String::from("Save blocks to Drive before closing?\n");
+                        for (i, block_idx) in state.block_indices.iter().enumerate() {
+                            let cursor = if i == state.cursor { ">" } else { " " };
+                            let check = if state.selected[i] { "[x]" } else { "[ ]" };
+                            let command = pane
+                                .history
+                                .get(*block_idx)
+                                .map(|block| block.command.as_str())
+                                .unwrap_or("");
+                            prompt_text.push_str(&format!("{} {} {}\n", cursor, check, command));
+                        }
+                        prompt_text.push_str("Space: toggle  Enter: save and close  Esc: cancel");
+                        prompt_buffer.set_text(
+                            &mut self.font_system,
+                            &prompt_text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(prompt_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                        self.editor.set_buffer(self.buffer.clone());
+                    }
+                }
+            }
+            // Restore the main buffer for overlays/cursor
+            self.editor.set_buffer(self.buffer.clone());
+            // --- PROMPT RENDERING LOGIC ---
+            let mut terminal_y_offset = 0.0;
+            if app.config.appearance.prompt_mode == PromptMode::Warpish {
+                let mut prompt_buffer =
+                    Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                prompt_buffer.set_size(
+                    &mut self.font_system,
+                    Some(self.config.width as f32),
+                    Some(self.char_height * 2.0),
+                );
+                // --- Build the prompt from chips (with placeholder data) ---
+                let remote = app
+                    .panes
+                    .get(app.active_pane_idx)
+                    .and_then(|pane| pane.ssh.session());
+                let cwd_chip = match &remote {
+                    Some(remote) => format!(" {} ", remote),
+                    None => app
+                        .panes
+                        .get(app.active_pane_idx)
+                        .and_then(|pane| {
+                            pane.dir_history
+                                .lock()
+                                .unwrap()
+                                .current()
+                                .map(|dir| format!(" {} ", dir.display()))
+                        })
+                        .unwrap_or_else(|| " ~ ".to_string()),
+                };
+                let ssh_chip = remote
+                    .as_ref()
+                    .map(|remote| format!(" ssh {} ", remote.label()))
+                    .unwrap_or_default();
+                let git_chip = app
+                    .panes
+                    .get(app.active_pane_idx)
+                    .and_then(|pane| pane.local_cwd())
+                    .and_then(|dir| app.git.status(&dir).map(|status| status.chip()))
+                    .unwrap_or_default();
+                let encodings = app
+                    .panes
+                    .get(app.active_pane_idx)
+                    .map(|pane| pane.encodings())
+                    .unwrap_or_default();
+                let encoding_chip = format!(" {} ", encodings.label());
+                let prompt_attrs =
+                    Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                // Custom chips carry their own color, so the prompt is built as spans
+                let mut prompt_spans: Vec<(String, Attrs)> = Vec::new();
+                for chip in &app.config.appearance.warpish_prompt.chips {
+                    let chip_text = match chip.as_str() {
+                        "cwd" => cwd_chip.clone(),
+                        "ssh" => ssh_chip.clone(),
+                        "git" => git_chip.clone(),
+                        "encoding" => encoding_chip.clone(),
+                        "k8s" => match app.kube.context() {
+                            Some(context) => context.chip(),
+                            None => continue,
+                        },
+                        "time" => " 12:34 PM ".to_string(), // Placeholder
+                        name => match app.prompt_chips.get(name) {
+                            Some(custom) => {
+                                let attrs = custom.color.as_deref().map_or(prompt_attrs, |color| {
+                                    prompt_attrs.color(hex_to_color(color))
+                                });
+                                prompt_spans.push((format!(" {} ", custom.text), attrs));
+                                continue;
+                            }
+                            // Not loaded yet, or printed nothing
+                            None if app
+                                .config
+                                .appearance
+                                .warpish_prompt
+                                .custom_chips
+                                .iter()
+                                .any(|custom| custom.name == name) =>
+                            {
+                                continue
+                            }
+                            None => " unknown_chip ".to_string(),
+                        },
+                    };
+                    prompt_spans.push((chip_text, prompt_attrs));
+                }
+                // A pane that is not in UTF-8 always says so
+                if !encodings.is_utf8()
+                    && !app
+                        .config
+                        .appearance
+                        .warpish_prompt
+                        .chips
+                        .iter()
+                        .any(|chip| chip == "encoding")
+                {
+                    prompt_spans.push((encoding_chip.clone(), prompt_attrs));
+                }
+                // So does a pane that re-runs a command on file changes
+                if let Some(watch) = app.watch_for(app.active_pane_idx) {
+                    prompt_spans.push((watch.chip(), prompt_attrs));
+                }
+                // And one serving local ports
+                if let Some(ports_chip) = app
+                    .panes
+                    .get(app.active_pane_idx)
+                    .and_then(|pane| crate::ports::chip(app.ports.pane_services(pane.id)))
+                {
+                    prompt_spans.push((ports_chip, prompt_attrs));
+                }
+                // A destructive kubectl command in production is flagged as it is typed
+                if let Some(warning) = app.kube_warning() {
+                    prompt_spans.push((
+                        format!(" ⚠ {} ", warning),
+                        prompt_attrs.color(hex_to_color(&app.theme.colors.normal.red)),
+                    ));
+                }
+                prompt_spans.push((">".to_string(), prompt_attrs));
+                prompt_buffer.set_rich_text(
+                    &mut self.font_system,
+                    prompt_spans
+                        .iter()
+                        .map(|(text, attrs)| (text.as_str(), *attrs)),
+                    prompt_attrs,
+                    Shaping::Advanced,
+                );
+                self.editor.set_buffer(prompt_buffer);
+                self.shape_editor();
+                self.draw_editor(&mut render_pass);
+                if !app.config.appearance.warpish_prompt.same_line {
+                    terminal_y_offset = self.char_height;
+                }
+                // --- Directory history dropdown under the cwd chip ---
+                if let AppMode::DirHistory(state) = &app.mode {
+                    let menu_width = 420.0;
+                    let menu_height = self.char_height * state.entries.len() as f32 * 1.2 + 20.0;
+                    let mut menu_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    menu_buffer.set_size(
+                        &mut self.font_system,
+                        Some(menu_width),
+                        Some(menu_height),
+                    );
+                    let mut menu_text = String::new();
+                    for (i, dir) in state.entries.iter().enumerate() {
+                        let marker = if i == state.selected_idx { ">" } else { " " };
+                        menu_text.push_str(&format!("{} {}\n", marker, dir.display()));
+                    }
+                    menu_buffer.set_text(
+                        &mut self.font_system,
+                        &menu_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(menu_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    self.editor.set_buffer(self.buffer.clone());
+                }
+            }
+            // --- Draw main terminal text, respecting the offset ---
+            self.editor.set_buffer(self.buffer.clone());
+            self.draw_editor(&mut render_pass);
+            // --- RENDER CURSOR (NEW) ---
+            let grid = app.panes[app.active_pane_idx]
+                .current_vte
+                .lock()
+                .unwrap()
+                .get_grid();
+            if !grid.cursor_hidden() {
+                let is_blinking_on = if !app.config.appearance.cursor.blink {
+                    true
+                } else {
+                    (time_since_start.as_millis() / 500) % 2 == 0
+                };
+                if is_blinking_on {
+                    self.render_cursor(app, &grid.cursor_position(), &mut render_pass);
+                }
+            }
+            // --- RENDER AGENT MODE UI ---
+            if let AppMode::Agent(state) = &app.mode {
+                let mut agent_buffer =
+                    Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                agent_buffer.set_size(
+                    &mut self.font_system,
+                    Some(self.config.width as f32),
+                    Some(self.config.height as f32),
+                );
+                let mut text = String::new();
+                if !state.attachments.is_empty() {
+                    let chips: Vec<String> = state
+                        .attachments
+                        .chips()
+                        .iter()
+                        .map(|chip| chip.to_string())
+                        .collect();
+                    text.push_str(&format!("📎 {}\n\n", chips.join(" ")));
+                }
+                for (query, response) in &state.conversation {
+                    text.push_str(&format!("> {}\n", query));
+                    match response {
+                        AgentResponse::SuggestCommand {
+                            explanation,
+                            command,
+                        } => {
+                            text.push_str(&format!("🤖 {}\n", explanation));
+                            text.push_str(&format!("[Suggested Command: {}\n\n", command));
+                        }
+                        AgentResponse::RequestToRunCommand {
+                            explanation,
+                            command_to_run,
+                        } => {
+                            text.push_str(&format!("🤖 {}\n", explanation));
+                            text.push_str(&format!(
+                                "[Press ENTER to run `{}` or ESC to cancel]\n\n",
+                                command_to_run
+                            ));
+                        }
+                        AgentResponse::Clarification(c) => text.push_str(&format!("🤖 {}\n\n", c)),
+                    }
+                }
+                agent_buffer.set_text(
+                    &mut self.font_system,
+                    &text,
+                    Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                    Shaping::Advanced,
+                );
+                self.editor.set_buffer(agent_buffer);
+                self.shape_editor();
+                self.draw_editor(&mut render_pass);
+                // --- Render the special Agent Input Bar at the bottom ---
+                let mut input_text = format!("✨ {}", state.current_input);
+                self.render_input_bar(
+                    app,
+                    (0.0, self.config.height as f32 - self.char_height * 1.5),
+                    &mut render_pass,
+                );
+            } else {
+                // --- RENDER NORMAL/PINNED MODES ---
+                // --- RENDER OVERLAYS (Settings, Palette, etc.) ---
+                if let AppMode::Settings(state) = &app.mode {
+                    let prompt_mode_text = format!(
+                        "\n\nPrompt Mode: {:?} (Press Enter to Toggle)",
+                        app.config.appearance.prompt_mode
+                    );
+                    let mut ui_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    ui_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 - 50.0),
+                        Some(self.config.height as f32 - 50.0),
+                    );
+                    let mut text = prompt_mode_text.clone();
+                    for (i, item) in state.filtered_list.iter().take(10).enumerate() {
+                        let (name, desc, kind) = match item {
+                            PaletteItem::Workflow(w) => {
+                                (w.name.as_str(), w.description.as_str(), "Workflow")
+                            }
+                            PaletteItem::Notebook(n) => (n.name.as_str(), "", "Notebook"),
+                            PaletteItem::Action {
+                                name, description, ..
+                            } => (name.as_str(), description.as_str(), "Action"),
+                            PaletteItem::Profile { name, description } => {
+                                (name.as_str(), description.as_str(), "Profile")
+                            }
+                            PaletteItem::Prompt(p) => {
+                                (p.name.as_str(), p.description.as_str(), "Prompt")
+                            }
+                            PaletteItem::GitHub(item) => (
+                                item.title.as_str(),
+                                item.reference.as_str(),
+                                if item.kind == crate::github::GitHubItemKind::PullRequest {
+                                    "Pull Request"
+                                } else {
+                                    "Issue"
+                                },
+                            ),
+                        };
+                        let line = if i == state.selected_idx {
+                            format!("> [{}] {} - {}\n", kind, name, desc)
+                        } else {
+                            format!("  [{}] {} - {}\n", kind, name, desc)
+                        };
+                        text.push_str(&line);
+                    }
+                    ui_buffer.set_text(
+                        &mut self.font_system,
+                        &text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(ui_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Search(state) = &app.mode {
+                    self.render_search(app, state, &mut render_pass);
+                } else if let AppMode::CodeReview(state) = &app.mode {
+                    let padding = 50.0;
+                    let mut review_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    review_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 - padding * 2.0),
+                        Some(self.config.height as f32 - padding * 2.0),
+                    );
+                    let mut review_text = "Changed files\n\n".to_string();
+                    for (i, file) in state.files.iter().enumerate() {
+                        let marker = if i == state.selected_file_idx {
+                            ">"
+                        } else {
+                            " "
+                        };
+                        review_text.push_str(&format!("{} {}\n", marker, file));
+                    }
+                    review_buffer.set_text(
+                        &mut self.font_system,
+                        &review_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(review_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    self.editor.set_buffer(self.buffer.clone());
+                } else if let AppMode::Drive(state) = &app.mode {
+                    let (width, height) = (self.config.width as f32, self.config.height as f32);
+                    let padding = 20.0;
+                    let pane_split_x = width * 0.4;
+                    // --- Draw background overlay ---
+                    let mut bg_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));
+                    bg_buffer.set_text(
+                        &mut self.font_system,
+                        "█",
+                        Attrs::new().color(Color::rgba(20, 20, 20, 230)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(bg_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    // --- Draw Left Pane (File Tree) ---
+                    let mut left_pane_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    left_pane_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_split_x - padding * 2.0),
+                        Some(height - padding * 2.0),
+                    );
+                    let mut tree_text = String::new();
+                    for (i, (name, depth)) in state.flat_items.iter().enumerate() {
+                        let indent = "  ".repeat(*depth);
+                        let line = if i == state.selected_idx {
+                            format!("> {}{}\n", indent, name)
+                        } else {
+                            format!("  {}{}\n", indent, name)
+                        };
+                        tree_text.push_str(&line);
+                    }
+                    left_pane_buffer.set_text(
+                        &mut self.font_system,
+                        &tree_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    left_pane_buffer.set_wrap(&mut self.font_system, cosmic_text::Wrap::Word);
+                    self.editor.set_buffer(left_pane_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    // --- Draw Right Pane (Content Preview) ---
+                    let mut right_pane_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    right_pane_buffer.set_size(
+                        &mut self.font_system,
+                        Some(width - pane_split_x - padding),
+                        Some(height - padding * 2.0),
+                    );
+                    // Find the actual object corresponding to the selected index
+                    let mut current_idx = 0;
+                    let mut selected_object: Option<&DriveObject> = None;
+                    let mut idx = 1; // skip workspace titles
+                    for obj in app.drive_manager.personal_ws.objects.iter() {
+                        if idx == state.selected_idx {
+                            selected_object = Some(obj);
+                            break;
+                        }
+                        idx += 1;
+                    }
+                    if selected_object.is_none() {
+                        for ws in &app.drive_manager.team_workspaces {
+                            idx += 1; // skip team workspace title
+                            for obj in ws.objects.iter() {
+                                if idx == state.selected_idx {
+                                    selected_object = Some(obj);
+                                    break;
+                                }
+                                idx += 1;
+                            }
+                            if selected_object.is_some() {
+                                break;
+                            }
+                        }
+                    }
+                    let mut preview_text = "Select an item to preview".to_string();
+                    if let Some(obj) = selected_object {
+                        preview_text = match obj {
+                            DriveObject::Workflow(w, m) => format!(
+                                "Name: {}\n\nDescription: {}\n\nCommand:\n{}",
+                                w.name, w.description, w.command
+                            ),
+                            DriveObject::Notebook(n, m) => {
+                                format!("Name: {}\n\n---\n\n{}", n.name, n.content)
+                            }
+                            DriveObject::Prompt(p, m) => {
+                                format!("Prompt: {}\n\n{}", p.name, p.content)
+                            }
+                            DriveObject::EnvVars(e, m) => {
+                                format!("Env: {}\n\n{:?}", e.name, e.vars)
+                            }
+                            DriveObject::Snippet(s, m) => format!(
+                                "Snippet: {} ({} + Tab)\n\n{}\n\n{}",
+                                s.name, s.prefix, s.description, s.body
+                            ),
+                        };
+                    }
+                    right_pane_buffer.set_text(
+                        &mut self.font_system,
+                        &preview_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    right_pane_buffer.set_wrap(&mut self.font_system, cosmic_text::Wrap::Word);
+                    self.editor.set_buffer(right_pane_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    // IMPORTANT: Restore the original terminal buffer for the next frame
+                    self.editor.set_buffer(self.buffer.clone());
+                }
+                // --- Render Workflow Overlay (existing code) ---
+                else if let AppMode::Workflow(state) = &app.mode {
+                    let (width, height) = (self.config.width as f32, self.config.height as f32);
+                    let padding = 50.0;
+                    // Create a separate buffer for the UI overlay
+                    let mut ui_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    ui_buffer.set_size(
+                        &mut self.font_system,
+                        Some(width - padding * 2.0),
+                        Some(height - padding * 2.0),
+                    );
+                    // --- Argument Editing UI ---
+                    if let Some(exec_state) = &state.execution_state {
+                        let mut text = format!("Workflow: {}\n\n", exec_state.workflow.name);
+                        for (i, arg) in exec_state.workflow.arguments.iter().enumerate() {
+                            let cursor = if i == exec_state.selected_arg_idx {
+                                ">"
+                            } else {
+                                " "
+                            };
+                            text.push_str(&format!(
+                                "{}[{}]: {}\n",
+                                cursor, arg.description, exec_state.argument_values[i]
+                            ));
+                        }
+                        ui_buffer.set_text(
+                            &mut self.font_system,
+                            &text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                            Shaping::Advanced,
+                        );
+                    // --- Workflow Browser UI ---
+                    } else {
+                        let mut text = format!("Search: {}\n\n", state.query);
+                        for (i, workflow) in state.filtered_workflows.iter().take(10).enumerate() {
+                            // Limit to 10 results
+                            let line = if i == state.selected_workflow_idx {
+                                format!("> {} - {}\n", workflow.name, workflow.description)
+                            } else {
+                                format!("  {} - {}\n", workflow.name, workflow.description)
+                            };
+                            text.push_str(&line);
+                        }
+                        ui_buffer.set_text(
+                            &mut self.font_system,
+                            &text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                            Shaping::Advanced,
+                        );
+                    }
+                    // Prepare and draw the UI buffer
+                    self.editor.set_buffer(ui_buffer);
+                    self.shape_editor();
+                    // We "fake" a background by drawing a huge block character behind the text
+                    self.editor.buffer_mut().set_text(
+                        &mut self.font_system,
+                        "█",
+                        Attrs::new()
+                            .color(Color::rgba(20, 20, 20, 200))
+                            .font_size(height),
+                        Shaping::Advanced,
+                    );
+                    self.draw_editor(&mut render_pass);
+                    // Now draw the actual UI text on top
+                    self.shape_editor(); // Reshape with the UI text
+                    self.draw_editor(&mut render_pass);
+                    // IMPORTANT: Restore the original terminal buffer
+                    self.editor.set_buffer(self.buffer.clone());
+                } else if let AppMode::AgentManagement(state) = &app.mode {
+                    // Draw overlay background
+                    let mut panel_text = "--- Agent Management ---\n\n".to_string();
+                    for pane in &app.panes {
+                        if let Some(agent_state) = &pane.agent_state {
+                            panel_text.push_str(&format!(
+                                "[{:?}] {} (Cancel)\n",
+                                agent_state.status, agent_state.task_summary
+                            ));
+                        }
+                    }
+                    panel_text.push_str(&format!(
+                        "\n--- Past Conversations ---\nSearch: {}\n\n",
+                        state.query
+                    ));
+                    for (i, conversation) in state.conversations.iter().enumerate() {
+                        let marker = if i == state.selected_idx { ">" } else { " " };
+                        panel_text.push_str(&format!(
+                            "{} {} ({} messages, {})\n",
+                            marker,
+                            conversation.title,
+                            conversation.message_count,
+                            conversation.model.to_string()
+                        ));
+                    }
+                    let mut panel_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    panel_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.config.height as f32 * 0.6),
+                    );
+                    panel_buffer.set_text(
+                        &mut self.font_system,
+                        &panel_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(panel_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::WorkflowFill(state) = &app.mode {
+                    // Workflow arguments, each drawn as its type's control
+                    let mut form_text = format!("Workflow: {}\n\n", state.workflow.name);
+                    for (i, argument) in state.workflow.arguments.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        form_text.push_str(&format!(
+                            "{} {}: {}\n",
+                            cursor,
+                            argument.description,
+                            state.display_value(i)
+                        ));
+                        if i == state.selected_idx {
+                            for path in &state.suggestions {
+                                form_text.push_str(&format!("      {}\n", path));
+                            }
+                        }
+                    }
+                    if let Some(error) = &state.error {
+                        form_text.push_str(&format!("\n{}\n", error));
+                    }
+                    form_text.push_str("\nUp/Down: field  Left/Right/Space: change  Tab: complete path  Enter: insert  Esc: cancel");
+                    let mut form_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    form_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.7),
+                        Some(self.config.height as f32 * 0.6),
+                    );
+                    form_buffer.set_text(
+                        &mut self.font_system,
+                        &form_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(form_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::FailureJump(state) = &app.mode {
+                    // Offer to jump to the pane where a command failed
+                    let jump_text = format!(
+"`{}` failed with status {} in another pane\n\nEnter: jump to it  m: don't jump to that pane again  Esc: stay here",
+state.command,
+state.status
+);
+                    let mut jump_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    jump_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32),
+                        Some(self.char_height * 3.6),
+                    );
+                    jump_buffer.set_text(
+                        &mut self.font_system,
+                        &jump_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(jump_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Scrub(state) = &app.mode {
+                    // The timeline, with block boundaries as ticks
+                    if let Some(timeline) = app.panes.get(state.pane_idx).map(|pane| &pane.timeline)
+                    {
+                        let time = chrono::DateTime::from_timestamp_millis(state.at)
+                            .map(|at| {
+                                at.with_timezone(&chrono::Local)
+                                    .format("%H:%M:%S")
+                                    .to_string()
+                            })
+                            .unwrap_or_default();
+                        let finished = timeline
+                            .ticks()
+                            .iter()
+                            .filter(|tick| tick.at <= state.at)
+                            .count();
+                        let scrub_text = format!(
+"{}\n{}  after block {} of {}\nLeft/Right: step  Shift+Left/Right: previous/next block  Home/End: start/end  drag to scrub  Esc: back to now",
+timeline.bar(state.at, 60),
+time,
+finished,
+timeline.ticks().len()
+);
+                        let mut scrub_buffer =
+                            Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                        scrub_buffer.set_size(
+                            &mut self.font_system,
+                            Some(self.config.width as f32),
+                            Some(self.char_height * 3.6),
+                        );
+                        scrub_buffer.set_text(
+                            &mut self.font_system,
+                            &scrub_text,
+                            Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                            Shaping::Advanced,
+                        );
+                        self.editor.set_buffer(scrub_buffer);
+                        self.shape_editor();
+                        self.draw_editor(&mut render_pass);
+                    }
+                } else if let (AppMode::ShareActivity(state), Some(share)) =
+                    (&app.mode, &app.live_share)
+                {
+                    // Who is in the live session, what they may do, and what they did
+                    let mut activity_text =
+                        format!("Live share: {}\n\nCollaborators:\n", share.url);
+                    let collaborators = share.collaborators();
+                    if collaborators.is_empty() {
+                        activity_text.push_str("  Nobody has joined yet\n");
+                    }
+                    for (i, collaborator) in collaborators.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        let access = if collaborator.can_type {
+                            "can type"
+                        } else {
+                            "view only"
+                        };
+                        activity_text
+                            .push_str(&format!("{} {} ({})\n", cursor, collaborator.name, access));
+                    }
+                    activity_text.push_str("\nActivity:\n");
+                    let activity = share.activity();
+                    for entry in activity.iter().rev().take(12).rev() {
+                        let time = chrono::DateTime::from_timestamp(entry.at, 0)
+                            .map(|at| at.with_timezone(&chrono::Local).format("%H:%M").to_string())
+                            .unwrap_or_default();
+                        activity_text
+                            .push_str(&format!("  {} {} {}\n", time, entry.name, entry.action));
+                    }
+                    activity_text.push_str(
+                        "\nUp/Down: select  r: revoke typing  s: stop sharing  Esc: close",
+                    );
+                    let mut activity_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    activity_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.config.height as f32 * 0.7),
+                    );
+                    activity_buffer.set_text(
+                        &mut self.font_system,
+                        &activity_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(activity_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Ollama(state) = &app.mode {
+                    // Installed models with their size and the roles they fill
+                    let mut models_text = String::from("Local models (Ollama)\n\n");
+                    match &state.models {
+                        None => models_text.push_str("  Loading…\n"),
+                        Some(Err(e)) => {
+                            models_text.push_str(&format!("  Ollama is not reachable: {}\n", e))
+                        }
+                        Some(Ok(models)) if models.is_empty() => {
+                            models_text.push_str("  No models installed\n")
+                        }
+                        Some(Ok(models)) => {
+                            let name_width = models
+                                .iter()
+                                .map(|model| crate::string_offset::display_width(&model.name))
+                                .max()
+                                .unwrap_or(0);
+                            for (i, model) in models.iter().enumerate() {
+                                let cursor = if i == state.selected_idx { ">" } else { " " };
+                                let roles: Vec<&str> = crate::ollama::ModelRole::ALL
+                                    .iter()
+                                    .filter(|role| {
+                                        app.config.ai.model_roles.get(**role)
+                                            == Some(model.name.as_str())
+                                    })
+                                    .map(|role| role.name())
+                                    .collect();
+                                models_text.push_str(&format!(
+                                    "{} {}  {:>9}  {}\n",
+                                    cursor,
+                                    crate::string_offset::pad_to_width(&model.name, name_width),
+                                    crate::ollama::format_size(model.size),
+                                    roles.join(", ")
+                                ));
+                            }
+                            let total: u64 = models.iter().map(|model| model.size).sum();
+                            models_text.push_str(&format!(
+                                "\n  Disk usage: {}\n",
+                                crate::ollama::format_size(total)
+                            ));
+                        }
+                    }
+                    models_text.push_str("\nRoles:\n");
+                    for role in crate::ollama::ModelRole::ALL {
+                        models_text.push_str(&format!(
+                            "  {:<13} {}\n",
+                            role.name(),
+                            app.config.ai.model_for(role)
+                        ));
+                    }
+                    match &state.pull_input {
+Some(name) => models_text.push_str(&format!("\nPull model: {}_  Enter: pull  Esc: cancel", name)),
+None => models_text.push_str("\nUp/Down: select  p: pull  d: remove  c/s/a: use for completions/summarization/agent  r: refresh  Esc: close"),
+}
+
+                    let mut models_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    models_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.config.height as f32 * 0.7),
+                    );
+                    models_buffer.set_text(
+                        &mut self.font_system,
+                        &models_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(models_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::EncodingPicker(state) = &app.mode {
+                    // Encodings with the pane's current input and output marked
+                    let current = app
+                        .panes
+                        .get(state.pane_idx)
+                        .map(|pane| pane.encodings())
+                        .unwrap_or_default();
+                    let mut picker_text = String::from("Pane encoding\n\n");
+                    for (i, encoding) in crate::pty::encoding::PaneEncoding::ALL.iter().enumerate()
+                    {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        let mut marks = Vec::new();
+                        if current.input == *encoding {
+                            marks.push("input");
+                        }
+                        if current.output == *encoding {
+                            marks.push("output");
+                        }
+                        picker_text.push_str(&format!(
+                            "{} {:<10} {}\n",
+                            cursor,
+                            encoding.label(),
+                            marks.join(", ")
+                        ));
+                    }
+                    picker_text.push_str("\nUp/Down: select  Enter: input and output  i: input only  o: output only  Esc: close");
+                    let mut picker_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    picker_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.5),
+                        Some(self.char_height * 8.4),
+                    );
+                    picker_buffer.set_text(
+                        &mut self.font_system,
+                        &picker_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(picker_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::NewPane(state) = &app.mode {
+                    // The default shell, then each launch profile with its icon
+                    let mut menu_text = String::from("New pane\n\n");
+                    let entries = std::iter::once("Default shell".to_string())
+                        .chain(app.config.profiles.iter().map(crate::profiles::label));
+                    for (i, entry) in entries.enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        menu_text.push_str(&format!("{} {}\n", cursor, entry));
+                    }
+                    menu_text.push_str("\nUp/Down: select  Enter: open  Esc: close");
+                    let mut menu_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    menu_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.5),
+                        Some(self.char_height * (app.config.profiles.len() as f32 + 5.0)),
+                    );
+                    menu_buffer.set_text(
+                        &mut self.font_system,
+                        &menu_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(menu_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::GraphQLExplorer(state) = &app.mode {
+                    // Endpoint, query with completions, and the variable form
+                    use crate::app::state::GraphQLFocus;
+                    let caret = |focused: bool| if focused { "_" } else { "" };
+                    let schema_status = match app.graphql.schema(&state.endpoint) {
+                        None => String::new(),
+                        Some(Ok(schema)) => format!("  ({} types)", schema.type_count()),
+                        Some(Err(e)) => format!("  ({})", e),
+                    };
+                    let mut explorer_text = format!(
+                        "GraphQL explorer\n\nEndpoint: {}{}{}\n\n",
+                        state.endpoint,
+                        caret(state.focus == GraphQLFocus::Endpoint),
+                        schema_status
+                    );
+                    explorer_text.push_str(&state.query);
+                    explorer_text.push_str(caret(state.focus == GraphQLFocus::Query));
+                    explorer_text.push('\n');
+                    let completions = app.graphql_completions();
+                    for (i, (name, type_name)) in completions.iter().take(8).enumerate() {
+                        let cursor = if i == state.selected_completion {
+                            ">"
+                        } else {
+                            " "
+                        };
+                        explorer_text.push_str(&format!("  {} {}: {}\n", cursor, name, type_name));
+                    }
+                    let definitions = state.variable_definitions();
+                    if !definitions.is_empty() {
+                        explorer_text.push_str("\nVariables\n");
+                    }
+                    for (i, definition) in definitions.iter().enumerate() {
+                        let value = state
+                            .variables
+                            .get(&definition.name)
+                            .map(String::as_str)
+                            .unwrap_or_default();
+                        explorer_text.push_str(&format!(
+                            "  ${} ({}): {}{}\n",
+                            definition.name,
+                            definition.type_name,
+                            value,
+                            caret(state.focus == GraphQLFocus::Variable(i))
+                        ));
+                    }
+                    explorer_text.push_str("\nTab: complete or next field  Ctrl+R: run  Ctrl+S: save  Ctrl+O: open saved  Esc: close");
+                    let line_count = explorer_text.lines().count();
+                    let mut explorer_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    explorer_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.7),
+                        Some(self.char_height * (line_count as f32 + 1.0)),
+                    );
+                    explorer_buffer.set_text(
+                        &mut self.font_system,
+                        &explorer_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(explorer_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::PullRequestPicker(state) = &app.mode {
+                    // The open pull requests a block can be posted to
+                    let mut picker_text = String::from("Comment on pull request\n\n");
+                    let mut count = 0;
+                    for (i, pull_request) in app.github.pull_requests().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        picker_text.push_str(&format!(
+                            "{} {}  {}\n",
+                            cursor, pull_request.reference, pull_request.title
+                        ));
+                        count += 1;
+                    }
+                    picker_text.push_str(
+                        "\nUp/Down: select  Enter: post output as a comment  Esc: cancel",
+                    );
+                    let mut picker_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    picker_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (count as f32 + 4.0)),
+                    );
+                    picker_buffer.set_text(
+                        &mut self.font_system,
+                        &picker_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(picker_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::LocationPicker(state) = &app.mode {
+                    // The file locations in a block, to open in the editor
+                    let mut picker_text = String::from("Open in editor\n\n");
+                    for (i, location) in state.locations.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        picker_text.push_str(&format!("{} {}\n", cursor, location));
+                    }
+                    picker_text.push_str("\nUp/Down: select  Enter: open  Esc: cancel");
+                    let mut picker_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    picker_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (state.locations.len() as f32 + 4.0)),
+                    );
+                    picker_buffer.set_text(
+                        &mut self.font_system,
+                        &picker_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(picker_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::StackTrace(state) = &app.mode {
+                    // The selected frame of a block's stack traces
+                    let block = app
+                        .panes
+                        .get(state.pane_idx)
+                        .and_then(|pane| pane.history.get(state.block_idx));
+                    let frames: Vec<&crate::structured::stacktrace::TraceFrame> = block
+                        .map(|block| {
+                            crate::structured::stacktrace::frames(&block.stack_traces).collect()
+                        })
+                        .unwrap_or_default();
+                    let folded = block.is_some_and(|block| block.library_frames_folded);
+                    let frame_text = match frames.get(state.frame_idx) {
+Some(frame) => format!(
+"Frame {} of {}  {}  {}\nUp/Down: previous/next frame  f: {} library frames  Enter: open in editor  Esc: done",
+state.frame_idx + 1,
+frames.len(),
+frame.function.as_deref().unwrap_or("<unknown>"),
+frame.location.as_ref().map(ToString::to_string).unwrap_or_default(),
+if folded { "show" } else { "hide" }
+),
+None => "No frames\nEsc: done".to_string(),
+};
+
+                    let mut frame_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    frame_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32),
+                        Some(self.char_height * 3.6),
+                    );
+                    frame_buffer.set_text(
+                        &mut self.font_system,
+                        &frame_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(frame_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::FileBrowser(state) = &app.mode {
+                    // The tree around the selected entry, its preview, then the prompt or key hints
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let mut browser_spans: Vec<(String, Attrs)> =
+                        vec![(format!("Files  {}\n\n", state.tree.root), text_attrs)];
+                    for (i, row) in state
+                        .rows
+                        .iter()
+                        .enumerate()
+                        .skip(state.selected_idx.saturating_sub(7))
+                        .take(15)
+                    {
+                        let marker = match (row.is_dir, row.expanded) {
+                            (true, true) => "▾ ",
+                            (true, false) => "▸ ",
+                            _ => "  ",
+                        };
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        let attrs = if row.is_dir {
+                            text_attrs.color(hex_to_color(&app.theme.colors.normal.blue))
+                        } else {
+                            text_attrs
+                        };
+                        browser_spans.push((
+                            format!(
+                                "{} {}{}{}\n",
+                                cursor,
+                                "  ".repeat(row.depth),
+                                marker,
+                                row.name
+                            ),
+                            attrs,
+                        ));
+                    }
+                    if state.rows.is_empty() {
+                        browser_spans.push(("  (empty)\n".to_string(), muted_attrs));
+                    }
+                    browser_spans.push(("────────\n".to_string(), muted_attrs));
+                    match &state.preview {
+                        Some(crate::virtual_fs::browser::Preview::Text {
+                            text,
+                            spans,
+                            truncated,
+                        }) => {
+                            // The first 20 lines, colored span by span
+                            let end = text
+                                .match_indices('\n')
+                                .nth(19)
+                                .map_or(text.len(), |(i, _)| i);
+                            let mut at = 0;
+                            for span in spans {
+                                if span.range.start < at || span.range.end > end {
+                                    continue;
+                                }
+                                let color = match span.color {
+                                    crate::virtual_fs::browser::PreviewColor::Syntax(kind) => {
+                                        highlight_color(kind, &app.theme)
+                                    }
+                                    crate::virtual_fs::browser::PreviewColor::Terminal(color) => {
+                                        to_cosmic_color(color, &app.theme)
+                                    }
+                                };
+                                browser_spans
+                                    .push((text[at..span.range.start].to_string(), text_attrs));
+                                browser_spans.push((
+                                    text[span.range.clone()].to_string(),
+                                    text_attrs.color(color),
+                                ));
+                                at = span.range.end;
+                            }
+                            browser_spans.push((format!("{}\n", &text[at..end]), text_attrs));
+                            if *truncated || end < text.len() {
+                                browser_spans.push(("…\n".to_string(), muted_attrs));
+                            }
+                        }
+                        Some(crate::virtual_fs::browser::Preview::Directory(count)) => {
+                            browser_spans.push((format!("{} entries\n", count), muted_attrs))
+                        }
+                        Some(crate::virtual_fs::browser::Preview::Binary(size)) => browser_spans
+                            .push((format!("Binary file, {} bytes\n", size), muted_attrs)),
+                        Some(crate::virtual_fs::browser::Preview::Error(e)) => {
+                            browser_spans.push((
+                                format!("{}\n", e),
+                                text_attrs.color(hex_to_color(&app.theme.colors.normal.red)),
+                            ))
+                        }
+                        None => {}
+                    }
+                    if let Some(crate::app::state::FileBrowserPrompt::ConfirmFormat(formatted)) =
+                        &state.prompt
+                    {
+                        // The formatter's changes, removed lines red and added ones green
+                        browser_spans.push(("────────\n".to_string(), muted_attrs));
+                        let changed: Vec<&str> = formatted
+                            .diff
+                            .lines()
+                            .filter(|line| line.starts_with(['-', '+']))
+                            .collect();
+                        for line in changed.iter().take(20) {
+                            let color = if line.starts_with('-') {
+                                &app.theme.colors.normal.red
+                            } else {
+                                &app.theme.colors.normal.green
+                            };
+                            browser_spans.push((
+                                format!("{}\n", line),
+                                text_attrs.color(hex_to_color(color)),
+                            ));
+                        }
+                        if changed.len() > 20 {
+                            browser_spans.push((
+                                format!("… {} more changed lines\n", changed.len() - 20),
+                                muted_attrs,
+                            ));
+                        }
+                    }
+                    let selected_name = state
+                        .rows
+                        .get(state.selected_idx)
+                        .map_or("", |row| row.name.as_str());
+                    let prompt_text = match &state.prompt {
+Some(crate::app::state::FileBrowserPrompt::Rename(input)) => format!("\nRename to: {}_  Enter: rename  Esc: cancel", input),
+Some(crate::app::state::FileBrowserPrompt::Copy(input)) => format!("\nCopy to: {}_  Enter: copy  Esc: cancel", input),
+Some(crate::app::state::FileBrowserPrompt::Root(input)) => format!("\nBrowse: {}_  Enter: go  Esc: cancel", input),
+Some(crate::app::state::FileBrowserPrompt::ConfirmDelete) => format!("\nDelete {}?  y: delete  any other key: cancel", selected_name),
+Some(crate::app::state::FileBrowserPrompt::ConfirmFormat(formatted)) => format!("\nWrite {}'s changes to {}?  y: write  any other key: cancel", formatted.formatter, selected_name),
+None => "\nEnter: open/expand  Left: collapse  i: insert path  r: rename  c: copy  d: delete  f: format  g: go to  R: refresh  Esc: close".to_string(),
+};
+
+                    browser_spans.push((prompt_text, text_attrs));
+                    let mut browser_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    browser_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.char_height * 44.0),
+                    );
+                    browser_buffer.set_rich_text(
+                        &mut self.font_system,
+                        browser_spans
+                            .iter()
+                            .map(|(text, attrs)| (text.as_str(), *attrs)),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(browser_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Abbreviations(state) = &app.mode {
+                    // Each abbreviation and what it expands to, then the prompt or key hints
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let mut abbreviation_spans: Vec<(String, Attrs)> =
+                        vec![("Abbreviations\n\n".to_string(), text_attrs)];
+                    for (i, (name, expansion)) in app
+                        .abbreviations
+                        .iter()
+                        .enumerate()
+                        .skip(state.selected_idx.saturating_sub(9))
+                        .take(20)
+                    {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        abbreviation_spans.push((
+                            format!("{} {}", cursor, name),
+                            text_attrs.color(hex_to_color(&app.theme.colors.normal.blue)),
+                        ));
+                        abbreviation_spans.push((format!(" → {}\n", expansion), text_attrs));
+                    }
+                    if app.abbreviations.is_empty() {
+                        abbreviation_spans.push(("  No abbreviations yet. Press a to add one or i to import your shell aliases.\n".to_string(), muted_attrs));
+                    }
+                    let selected_name = app
+                        .abbreviations
+                        .iter()
+                        .nth(state.selected_idx)
+                        .map_or("", |(name, _)| name);
+                    let prompt_text = match &state.prompt {
+Some(crate::app::state::AbbreviationPrompt::Add(input)) => format!("\nAdd: {}_  (name=expansion)  Enter: add  Esc: cancel", input),
+Some(crate::app::state::AbbreviationPrompt::Edit { input, .. }) => format!("\nEdit: {}_  Enter: save  Esc: cancel", input),
+Some(crate::app::state::AbbreviationPrompt::ConfirmDelete) => format!("\nDelete {}?  y: delete  any other key: cancel", selected_name),
+None => "\na: add  e: edit  d: delete  i: import shell aliases  x: export for this shell  Esc: close".to_string(),
+};
+
+                    abbreviation_spans.push((prompt_text, muted_attrs));
+                    let mut abbreviations_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    abbreviations_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.char_height * 28.0),
+                    );
+                    abbreviations_buffer.set_rich_text(
+                        &mut self.font_system,
+                        abbreviation_spans
+                            .iter()
+                            .map(|(text, attrs)| (text.as_str(), *attrs)),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(abbreviations_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Quiz(state) = &app.mode {
+                    // The question, a countdown bar, the options, then the outcome or key hints
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let green_attrs =
+                        text_attrs.color(hex_to_color(&app.theme.colors.normal.green));
+                    let red_attrs = text_attrs.color(hex_to_color(&app.theme.colors.normal.red));
+                    let mut quiz_spans: Vec<(String, Attrs)> = Vec::new();
+                    if let Some(question) = state.session.question() {
+                        quiz_spans.push((
+                            format!(
+                                "Question {} of {}  ·  {}  ·  {:?}\n",
+                                state.session.current + 1,
+                                state.session.quiz.questions.len(),
+                                question.category,
+                                question.difficulty
+                            ),
+                            muted_attrs,
+                        ));
+                        if let (Some(limit), Some(left)) = (
+                            question.time_limit,
+                            state.session.time_left(chrono::Utc::now()),
+                        ) {
+                            let filled = ((left / limit.max(1) as f32) * 20.0).ceil() as usize;
+                            let bar_attrs = if left < 5.0 { red_attrs } else { muted_attrs };
+                            quiz_spans.push((
+                                format!(
+                                    "⏱ {}{} {:.0}s\n",
+                                    "█".repeat(filled),
+                                    "░".repeat(20 - filled.min(20)),
+                                    left
+                                ),
+                                bar_attrs,
+                            ));
+                        }
+                        quiz_spans.push((format!("\n{}\n\n", question.question), text_attrs));
+                        let answered = state
+                            .session
+                            .quiz
+                            .responses
+                            .last()
+                            .filter(|_| state.session.outcome.is_some())
+                            .and_then(|response| response.selected_answer);
+                        for (i, option) in question.options.iter().enumerate() {
+                            let cursor =
+                                if i == state.selected_idx && state.session.outcome.is_none() {
+                                    ">"
+                                } else {
+                                    " "
+                                };
+                            let (mark, attrs) = match state.session.outcome {
+                                Some(_) if i == question.correct_answer => ("✓", green_attrs),
+                                Some(_) if Some(i) == answered => ("✗", red_attrs),
+                                _ => (" ", text_attrs),
+                            };
+                            quiz_spans.push((
+                                format!("{} {} {}. {}\n", cursor, mark, i + 1, option),
+                                attrs,
+                            ));
+                        }
+                        match state.session.outcome {
+                            Some(outcome) => {
+                                let (verdict, attrs) = match outcome {
+                                    crate::mcq::Outcome::Correct => ("Correct!", green_attrs),
+                                    crate::mcq::Outcome::Incorrect => ("Incorrect.", red_attrs),
+                                    crate::mcq::Outcome::TimedOut => ("Out of time.", red_attrs),
+                                };
+                                quiz_spans.push((format!("\n{}\n", verdict), attrs));
+                                if let Some(explanation) = &question.explanation {
+                                    quiz_spans.push((format!("{}\n", explanation), text_attrs));
+                                }
+                                quiz_spans.push((
+                                    "\nEnter: next question  Esc: finish".to_string(),
+                                    muted_attrs,
+                                ));
+                            }
+                            None => quiz_spans.push((
+                                "\nUp/Down + Enter or 1-9: answer  Esc: finish".to_string(),
+                                muted_attrs,
+                            )),
+                        }
+                    }
+                    let mut quiz_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    quiz_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.7),
+                        Some(self.char_height * 24.0),
+                    );
+                    quiz_buffer.set_rich_text(
+                        &mut self.font_system,
+                        quiz_spans
+                            .iter()
+                            .map(|(text, attrs)| (text.as_str(), *attrs)),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(quiz_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::ExplainCommand(state) = &app.mode {
+                    // The command, then each part with what it does, from help pages or the agent
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let part_attrs = text_attrs.color(hex_to_color(&app.theme.colors.normal.cyan));
+                    let mut explain_spans: Vec<(String, Attrs)> =
+                        vec![(format!("{}\n\n", state.command), text_attrs)];
+                    let width = state
+                        .explained
+                        .iter()
+                        .map(|part| crate::string_offset::display_width(&part.text))
+                        .max()
+                        .unwrap_or(0)
+                        .min(24);
+                    for part in &state.explained {
+                        let label = match part.kind {
+                            crate::syntax_parser::CommandPartKind::Name => "command",
+                            crate::syntax_parser::CommandPartKind::Argument
+                                if part.text.starts_with('-') =>
+                            {
+                                "flag"
+                            }
+                            crate::syntax_parser::CommandPartKind::Argument => "argument",
+                            crate::syntax_parser::CommandPartKind::Redirect => "redirect",
+                            crate::syntax_parser::CommandPartKind::Assignment => "variable",
+                            crate::syntax_parser::CommandPartKind::Operator => "operator",
+                        };
+                        explain_spans.push((
+                            format!(
+                                "{}  ",
+                                crate::string_offset::pad_to_width(&part.text, width)
+                            ),
+                            part_attrs,
+                        ));
+                        explain_spans.push((format!("{:<9}", label), muted_attrs));
+                        match (&part.description, part.pending) {
+                            (Some(description), _) => explain_spans.push((
+                                format!(
+                                    "{}{}\n",
+                                    description,
+                                    if part.from_agent { "  (agent)" } else { "" }
+                                ),
+                                text_attrs,
+                            )),
+                            (None, true) => explain_spans
+                                .push(("Reading the help page…\n".to_string(), muted_attrs)),
+                            (None, false) => explain_spans
+                                .push(("No description found\n".to_string(), muted_attrs)),
+                        }
+                    }
+                    explain_spans.push((
+                        if state.asking_agent {
+                            "\nAsking the agent…  Esc: close".to_string()
+                        } else {
+                            "\na: ask the agent about unexplained parts  Esc: close".to_string()
+                        },
+                        muted_attrs,
+                    ));
+                    let mut explain_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    explain_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.char_height * 28.0),
+                    );
+                    explain_buffer.set_rich_text(
+                        &mut self.font_system,
+                        explain_spans
+                            .iter()
+                            .map(|(text, attrs)| (text.as_str(), *attrs)),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(explain_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::ActivityReport(state) = &app.mode {
+                    // The report from the scrolled-to line, and where it was exported
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let report_text: String = state
+                        .markdown
+                        .lines()
+                        .skip(state.scroll)
+                        .map(|line| format!("{}\n", line))
+                        .collect();
+                    let footer = match &state.exported_to {
+                        Some(path) => format!("\nSaved to {}  Esc: close", path.display()),
+                        None => "\n↑/↓: scroll  e: export  Esc: close".to_string(),
+                    };
+                    let report_spans = [
+                        (report_text.as_str(), text_attrs),
+                        (footer.as_str(), muted_attrs),
+                    ];
+                    let mut report_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    report_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.config.height as f32 * 0.8),
+                    );
+                    report_buffer.set_rich_text(
+                        &mut self.font_system,
+                        report_spans.iter().copied(),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(report_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Notebook(state) = &app.mode {
+                    // The notebook's source around the selected task, checkboxes drawn as boxes
+                    let text_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+                    let muted_attrs =
+                        Attrs::new().color(hex_to_color(&app.theme.colors.bright.black));
+                    let heading_attrs = text_attrs
+                        .color(hex_to_color(&app.theme.colors.normal.blue))
+                        .weight(Weight::BOLD);
+                    let selected_attrs =
+                        text_attrs.color(hex_to_color(&app.theme.colors.normal.cyan));
+                    let selected_line = state.tasks.get(state.selected_idx).map(|task| task.line);
+                    let first_line = selected_line.unwrap_or(0).saturating_sub(10);
+                    let mut notebook_spans: Vec<(String, Attrs)> =
+                        vec![(format!("{}\n\n", state.notebook.name), heading_attrs)];
+                    for (number, line) in state
+                        .notebook
+                        .content
+                        .lines()
+                        .enumerate()
+                        .skip(first_line)
+                        .take(24)
+                    {
+                        let task = state.tasks.iter().find(|task| task.line == number);
+                        let cursor = if Some(number) == selected_line {
+                            "> "
+                        } else {
+                            "  "
+                        };
+                        match task {
+                            Some(task) => {
+                                let indent = &line[..line.len() - line.trim_start().len()];
+                                let attrs = if Some(number) == selected_line {
+                                    selected_attrs
+                                } else if task.checked {
+                                    muted_attrs
+                                } else {
+                                    text_attrs
+                                };
+                                notebook_spans.push((
+                                    format!(
+                                        "{}{}{} {}\n",
+                                        cursor,
+                                        indent,
+                                        if task.checked { "☑" } else { "☐" },
+                                        task.text
+                                    ),
+                                    attrs,
+                                ));
+                            }
+                            None if line.starts_with('#') => notebook_spans
+                                .push((format!("{}{}\n", cursor, line), heading_attrs)),
+                            None => {
+                                notebook_spans.push((format!("{}{}\n", cursor, line), text_attrs))
+                            }
+                        }
+                    }
+                    let done = state.tasks.iter().filter(|task| task.checked).count();
+                    notebook_spans.push((
+                        if state.tasks.is_empty() {
+                            "\nNo tasks  Esc: close".to_string()
+                        } else {
+                            format!(
+                                "\n{}/{} done  ↑/↓: select  Space: toggle  Esc: close",
+                                done,
+                                state.tasks.len()
+                            )
+                        },
+                        muted_attrs,
+                    ));
+                    let mut notebook_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    notebook_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.char_height * 30.0),
+                    );
+                    notebook_buffer.set_rich_text(
+                        &mut self.font_system,
+                        notebook_spans
+                            .iter()
+                            .map(|(text, attrs)| (text.as_str(), *attrs)),
+                        text_attrs,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(notebook_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Docker(state) = &app.mode {
+                    // Containers or images, whichever view is selected
+                    let mut docker_text = String::from(match state.view {
+                        crate::app::state::DockerView::Containers => {
+                            "Docker containers  (Tab: images)\n\n"
+                        }
+                        crate::app::state::DockerView::Images => {
+                            "Docker images  (Tab: containers)\n\n"
+                        }
+                    });
+                    let mut rows = 0;
+                    match app.docker.snapshot() {
+                        None => docker_text.push_str("  Loading...\n"),
+                        Some(Err(e)) => {
+                            docker_text.push_str(&format!("  Docker is not available: {}\n", e))
+                        }
+                        Some(Ok(snapshot)) => match state.view {
+                            crate::app::state::DockerView::Containers => {
+                                rows = snapshot.containers.len();
+                                for (i, container) in snapshot.containers.iter().enumerate() {
+                                    let cursor = if i == state.selected_idx { ">" } else { " " };
+                                    docker_text.push_str(&format!(
+                                        "{} {}  {:<24} {:<28} {}\n",
+                                        cursor,
+                                        container.id,
+                                        container.name,
+                                        container.image,
+                                        container.status
+                                    ));
+                                }
+                            }
+                            crate::app::state::DockerView::Images => {
+                                rows = snapshot.images.len();
+                                for (i, image) in snapshot.images.iter().enumerate() {
+                                    let cursor = if i == state.selected_idx { ">" } else { " " };
+                                    docker_text.push_str(&format!(
+                                        "{} {}  {:<40} {:>9}\n",
+                                        cursor,
+                                        image.id,
+                                        image.tag,
+                                        crate::jobs::format_memory(image.size)
+                                    ));
+                                }
+                            }
+                        },
+                    }
+                    docker_text.push_str("\nUp/Down: select  Enter/e: shell in new pane  l: follow logs  r: refresh  Esc: close");
+                    let mut docker_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    docker_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.8),
+                        Some(self.char_height * (rows.max(1) as f32 + 4.0)),
+                    );
+                    docker_buffer.set_text(
+                        &mut self.font_system,
+                        &docker_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(docker_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Ports(state) = &app.mode {
+                    // Each service's URL, its pane and the process serving it
+                    let services = app.ports.services();
+                    let mut ports_text = String::from("Local services\n\n");
+                    for (i, service) in services.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        let pane = app
+                            .panes
+                            .iter()
+                            .position(|pane| pane.id == service.pane_id)
+                            .map_or(String::new(), |idx| format!("pane {}", idx + 1));
+                        let owner = service
+                            .pid
+                            .map_or(String::new(), |pid| format!(", pid {}", pid));
+                        ports_text.push_str(&format!(
+                            "{} {}  ({}{})\n",
+                            cursor, service.url, pane, owner
+                        ));
+                    }
+                    ports_text.push_str(
+                        "\nUp/Down: select  Enter: open in browser  k: stop process  Esc: close",
+                    );
+                    let mut ports_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    ports_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (services.len() as f32 + 4.0)),
+                    );
+                    ports_buffer.set_text(
+                        &mut self.font_system,
+                        &ports_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(ports_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Jobs(state) = &app.mode {
+                    // One row per job: pid, state, CPU, memory, then the command and its pane
+                    let jobs = app.jobs.jobs();
+                    let mut jobs_text = String::from("Jobs\n\n");
+                    if jobs.is_empty() {
+                        jobs_text.push_str("  No jobs are running\n");
+                    }
+                    for (i, job) in jobs.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        let pane = app
+                            .panes
+                            .iter()
+                            .position(|pane| pane.id == job.pane_id)
+                            .map_or("closed".to_string(), |idx| format!("pane {}", idx + 1));
+                        let detached = if job.kind == crate::jobs::JobKind::Detached {
+                            " (detached)"
+                        } else {
+                            ""
+                        };
+                        jobs_text.push_str(&format!(
+                            "{} {:>7}  {:<9} {:>5.1}%  {:>9}  {}{}  [{}]\n",
+                            cursor,
+                            job.pid,
+                            job.state.label(),
+                            job.cpu,
+                            crate::jobs::format_memory(job.memory),
+                            job.command,
+                            detached,
+                            pane
+                        ));
+                    }
+                    jobs_text.push_str("\nUp/Down: select  s: suspend  r: resume  k: kill  a: attach output  d: dismiss  Esc: close");
+                    let mut jobs_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    jobs_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.7),
+                        Some(self.char_height * (jobs.len().max(1) as f32 + 4.0)),
+                    );
+                    jobs_buffer.set_text(
+                        &mut self.font_system,
+                        &jobs_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(jobs_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::WatchGlob(state) = &app.mode {
+                    // The files that re-run the command, relative to the pane's directory
+                    let watch_text = format!(
+                        "Re-run `{}` when these files change:\n\n{}_\n\nEnter: watch  Esc: cancel",
+                        state.command, state.glob
+                    );
+                    let mut watch_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    watch_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.5),
+                        Some(self.char_height * 6.0),
+                    );
+                    watch_buffer.set_text(
+                        &mut self.font_system,
+                        &watch_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(watch_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::WslPicker(state) = &app.mode {
+                    // Installed distros; the new pane opens in the current directory
+                    let mut picker_text = String::from("Open a pane in WSL\n\n");
+                    for (i, distro) in state.distros.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        picker_text.push_str(&format!("{} {}\n", cursor, distro));
+                    }
+                    picker_text.push_str("\nUp/Down: select  Enter: open  Esc: close");
+                    let mut picker_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    picker_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.5),
+                        Some(self.char_height * (state.distros.len() as f32 + 4.0)),
+                    );
+                    picker_buffer.set_text(
+                        &mut self.font_system,
+                        &picker_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(picker_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::WebSearch(state) = &app.mode {
+                    // Providers for the query, then inline results from a search API
+                    let mut search_text = format!("Search the web for \"{}\"\n\n", state.query);
+                    let help = match &state.view {
+                        WebSearchView::Providers => {
+                            for (i, provider) in app.web_search.providers().iter().enumerate() {
+                                let cursor = if i == state.selected_idx { ">" } else { " " };
+                                let inline = if provider.api.is_some() {
+                                    "  (inline results)"
+                                } else {
+                                    ""
+                                };
+                                search_text
+                                    .push_str(&format!("{} {}{}\n", cursor, provider.name, inline));
+                            }
+                            "Up/Down: select  Enter: search  Esc: close"
+                        }
+                        WebSearchView::Loading => {
+                            search_text.push_str("Searching…\n");
+                            "Esc: close"
+                        }
+                        WebSearchView::Results(Err(e)) => {
+                            search_text.push_str(&format!("Search failed: {}\n", e));
+                            "Esc: close"
+                        }
+                        WebSearchView::Results(Ok(results)) => {
+                            if results.is_empty() {
+                                search_text.push_str("No results\n");
+                            }
+                            for (i, result) in results.iter().enumerate() {
+                                let cursor = if i == state.selected_idx { ">" } else { " " };
+                                search_text.push_str(&format!(
+                                    "{} {}\n    {}\n    {}\n",
+                                    cursor, result.title, result.url, result.snippet
+                                ));
+                            }
+                            "Up/Down: select  Enter: open in browser  Esc: close"
+                        }
+                    };
+                    search_text.push_str(&format!("\n{}", help));
+                    let mut search_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    search_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.config.height as f32 * 0.6),
+                    );
+                    search_buffer.set_text(
+                        &mut self.font_system,
+                        &search_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(search_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::BlockSelect(state) = &app.mode {
+                    // Batch actions for the selected blocks
+                    let help_text = match &state.tag_input {
+Some(tag) => format!("Tag {} block(s): #{}_", state.selection.indices().len(), tag),
+None => format!(
+"{} block(s) selected  Shift+Up/Down: extend  Ctrl+Up/Down: move  Space: toggle  Ctrl+A: all\nd: delete  u: undo delete  t: tag  e: export  p: PDF  m: Markdown  h: HTML  g: gist  r: re-run  i: send to agent  Esc: done",
+state.selection.indices().len()
+),
+};
+
+                    let mut help_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    help_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32),
+                        Some(self.char_height * 2.4),
+                    );
+                    help_buffer.set_text(
+                        &mut self.font_system,
+                        &help_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(help_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Survey(state) = &app.mode {
+                    // One-question survey; answers stay on this machine
+                    let mut prompt_text = format!("{}\n\n", state.question.question);
+                    for (i, option) in state.question.options.iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        prompt_text.push_str(&format!("{} {}. {}\n", cursor, i + 1, option));
+                    }
+                    prompt_text.push_str("\nEnter or 1-9: answer  Esc: skip  (stored locally)");
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (state.question.options.len() as f32 + 5.0)),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::ConfirmClose(state) = &app.mode {
+                    // Closing would interrupt running jobs
+                    let mut prompt_text = String::from(if state.pane_idx.is_some() {
+                        "Close this pane? A job is still running:\n\n"
+                    } else {
+                        "Quit? Jobs are still running:\n\n"
+                    });
+                    for job in &state.jobs {
+                        prompt_text.push_str(&format!("  {}\n", job));
+                    }
+                    prompt_text.push_str("\ny/Enter: close anyway  n/Esc: cancel");
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (state.jobs.len() as f32 + 5.0)),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::ConfirmWebhook(state) = &app.mode {
+                    // A warning rule matched the workflow's command
+                    let prompt_text = format!(
+                        "Run webhook workflow '{}'? A warning rule matched:\n\n  {}\n\ny/Enter: run  n/Esc: cancel",
+                        state.name, state.command
+                    );
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * 6.0),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::RestoreSession(state) = &app.mode {
+                    // The last run ended without shutting down
+                    let saved_at = chrono::DateTime::from_timestamp(state.journal.saved_at, 0)
+                        .map(|at| {
+                            at.with_timezone(&chrono::Local)
+                                .format("%Y-%m-%d %H:%M")
+                                .to_string()
+                        })
+                        .unwrap_or_default();
+                    let mut prompt_text = format!(
+                        "Restore previous session? It didn't close cleanly (last saved {}).\n\n",
+                        saved_at
+                    );
+                    for (i, pane) in state.journal.panes.iter().enumerate() {
+                        let cwd = pane
+                            .cwd
+                            .as_ref()
+                            .map(|cwd| cwd.display().to_string())
+                            .unwrap_or_else(|| "~".to_string());
+                        let profile = pane
+                            .profile
+                            .as_ref()
+                            .map(|profile| format!(" [{}]", profile))
+                            .unwrap_or_default();
+                        prompt_text.push_str(&format!(
+                            "  {} {}{}  ({} blocks)\n",
+                            if i == state.journal.active_pane {
+                                ">"
+                            } else {
+                                " "
+                            },
+                            cwd,
+                            profile,
+                            pane.blocks.len()
+                        ));
+                    }
+                    if !state.journal.input.is_empty() {
+                        prompt_text.push_str(&format!("\nUnsent input: {}\n", state.journal.input));
+                    }
+                    prompt_text.push_str("\ny/Enter: restore  n/Esc: start fresh");
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * (state.journal.panes.len() as f32 + 7.0)),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::CrashReport(state) = &app.mode {
+                    // Reports of crashes since the last launch
+                    let report = &state.reports[0].report;
+                    let at = chrono::DateTime::from_timestamp(report.at, 0)
+                        .map(|at| {
+                            at.with_timezone(&chrono::Local)
+                                .format("%Y-%m-%d %H:%M")
+                                .to_string()
+                        })
+                        .unwrap_or_default();
+                    let mut prompt_text = format!(
+                        "Warpish Terminal crashed at {}.\n\n{}\n",
+                        at, report.message
+                    );
+                    if let Some(location) = &report.location {
+                        prompt_text
+                            .push_str(&format!("at {} (thread '{}')\n", location, report.thread));
+                    }
+                    prompt_text
+                        .push_str(&format!("\nReport: {}\n", state.reports[0].path.display()));
+                    if state.reports.len() > 1 {
+                        prompt_text.push_str(&format!(
+                            "and {} earlier crash report(s)\n",
+                            state.reports.len() - 1
+                        ));
+                    }
+                    prompt_text.push_str(if app.config.crash_reports.submit_url.is_some() {
+                        "\nv/Enter: view  s: submit  n/Esc: dismiss"
+                    } else {
+                        "\nv/Enter: view  n/Esc: dismiss"
+                    });
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * 10.0),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::PermissionPrompt(state) = &app.mode {
+                    // Capability prompt for a plugin, agent tool or collaborator
+                    let mut prompt_text = format!(
+                        "{} wants to {}.\n",
+                        state.request.requester,
+                        state.request.capability.label()
+                    );
+                    if !state.request.reason.is_empty() {
+                        prompt_text.push_str(&format!("Reason: {}\n", state.request.reason));
+                    }
+                    prompt_text.push('\n');
+                    for (i, choice) in state.request.choices().iter().enumerate() {
+                        let label = if i == state.selected_idx {
+                            format!("[{}]", choice.label())
+                        } else {
+                            format!(" {} ", choice.label())
+                        };
+                        prompt_text.push_str(&format!("{}  ", label));
+                    }
+                    if state.request.requester.can_remember() {
+                        prompt_text.push_str("\n\ny: this session  a: always  n: deny  x: never");
+                    } else {
+                        prompt_text.push_str("\n\ny: this session  n: deny");
+                    }
+                    let mut prompt_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    prompt_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.char_height * 8.0),
+                    );
+                    prompt_buffer.set_text(
+                        &mut self.font_system,
+                        &prompt_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(prompt_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::PermissionAudit(state) = &app.mode {
+                    // Granted and denied capabilities, newest decisions included
+                    let grants = app.permissions.lock().unwrap().grants();
+                    let mut panel_text = "--- Permissions ---\n\n".to_string();
+                    if grants.is_empty() {
+                        panel_text
+                            .push_str("No plugin or agent tool has asked for a capability yet.\n");
+                    }
+                    for (i, grant) in grants.iter().enumerate() {
+                        let marker = if i == state.selected_idx { ">" } else { " " };
+                        let decision = if grant.allowed { "allowed" } else { "denied" };
+                        let scope = if grant.remembered {
+                            "always"
+                        } else {
+                            "this session"
+                        };
+                        let decided = chrono::DateTime::from_timestamp(grant.decided_at, 0)
+                            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                            .unwrap_or_default();
+                        panel_text.push_str(&format!(
+                            "{} {} - {}: {} ({}, {})\n",
+                            marker,
+                            grant.requester,
+                            grant.capability.label(),
+                            decision,
+                            scope,
+                            decided
+                        ));
+                    }
+                    panel_text.push_str("\nr: revoke  Esc: close");
+                    let mut panel_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    panel_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.6),
+                        Some(self.config.height as f32 * 0.6),
+                    );
+                    panel_buffer.set_text(
+                        &mut self.font_system,
+                        &panel_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(panel_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::Refactor(state) = &app.mode {
+                    // The refactor's fields, then why it last failed
+                    let mut form_text = format!(
+                        "{}\n\n",
+                        if state.kind == RefactorKind::RenameSymbol {
+                            "Rename Symbol"
+                        } else {
+                            "Extract Function"
+                        }
+                    );
+                    for (i, label) in state.kind.labels().iter().enumerate() {
+                        let cursor = if i == state.selected_idx { ">" } else { " " };
+                        form_text.push_str(&format!("{} {}: {}\n", cursor, label, state.values[i]));
+                    }
+                    if let Some(error) = &state.error {
+                        form_text.push_str(&format!("\n{}\n", error));
+                    }
+                    form_text.push_str("\nUp/Down: field  Enter: review changes  Esc: cancel");
+                    let mut form_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    form_buffer.set_size(
+                        &mut self.font_system,
+                        Some(self.config.width as f32 * 0.7),
+                        Some(self.config.height as f32 * 0.6),
+                    );
+                    form_buffer.set_text(
+                        &mut self.font_system,
+                        &form_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(form_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                } else if let AppMode::DiffReview(state) = &app.mode {
+                    // Draw background overlay
+                    let (width, height) = (self.config.width as f32, self.config.height as f32);
+                    let padding = 40.0;
+                    let mut bg_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));
+                    bg_buffer.set_text(
+                        &mut self.font_system,
+                        "█",
+                        Attrs::new()
+                            .color(Color::rgba(20, 20, 20, 230))
+                            .font_size(height * 2.0),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(bg_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    // --- Draw Header and File Tabs ---
+                    let mut header_text = format!("{}\n\n", state.explanation);
+                    for (i, file) in state.files.iter().enumerate() {
+                        let tab = if i == state.current_file_idx {
+                            format!("> {} <", file.file_path)
+                        } else {
+                            file.file_path.clone()
+                        };
+                        header_text.push_str(&format!("{}   ", tab));
+                    }
+                    let mut header_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    header_buffer.set_size(
+                        &mut self.font_system,
+                        Some(width - padding * 2.0),
+                        Some(60.0),
+                    );
+                    header_buffer.set_text(
+                        &mut self.font_system,
+                        &header_text,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(header_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    // --- Draw Side-by-Side Diff for the current file ---
+                    let file = &state.files[state.current_file_idx];
+                    let mut left_text = String::new();
+                    let mut right_text = String::new();
+                    let mut left_spans = AttrsList::new(Attrs::new());
+                    let mut right_spans = AttrsList::new(Attrs::new());
+                    let mut left_offset = 0;
+                    let mut right_offset = 0;
+                    for (i, hunk) in file.hunks.iter().enumerate() {
+                        let prefix = if i == state.current_hunk_idx {
+                            "> "
+                        } else {
+                            "  "
+                        };
+                        match hunk.tag {
+                            ChangeTag::Delete => {
+                                left_text.push_str(&format!("{}{}", prefix, hunk.original_text));
+                                left_spans.add_span(
+                                    left_offset
+                                        ..left_offset + prefix.len() + hunk.original_text.len(),
+                                    Attrs::new().color(Color::rgb(255, 80, 80)),
+                                );
+                                left_offset += prefix.len() + hunk.original_text.len();
+                                right_text.push('\n');
+                                right_offset += 1;
+                            }
+                            ChangeTag::Insert => {
+                                left_text.push('\n');
+                                left_offset += 1;
+                                right_text.push_str(&format!("{}{}", prefix, hunk.new_text));
+                                right_spans.add_span(
+                                    right_offset..right_offset + prefix.len() + hunk.new_text.len(),
+                                    Attrs::new().color(Color::rgb(80, 255, 80)),
+                                );
+                                right_offset += prefix.len() + hunk.new_text.len();
+                            }
+                            ChangeTag::Equal => {
+                                left_text.push_str(&format!("{}{}", prefix, hunk.original_text));
+                                left_spans.add_span(
+                                    left_offset
+                                        ..left_offset + prefix.len() + hunk.original_text.len(),
+                                    Attrs::new()
+                                        .color(hex_to_color(&app.theme.colors.primary.foreground)),
+                                );
+                                left_offset += prefix.len() + hunk.original_text.len();
+                                right_text.push_str(&format!("{}{}", prefix, hunk.new_text));
+                                right_spans.add_span(
+                                    right_offset..right_offset + prefix.len() + hunk.new_text.len(),
+                                    Attrs::new()
+                                        .color(hex_to_color(&app.theme.colors.primary.foreground)),
+                                );
+                                right_offset += prefix.len() + hunk.new_text.len();
+                            }
+                        }
+                        left_text.push('\n');
+                        left_offset += 1;
+                        right_text.push('\n');
+                        right_offset += 1;
+                    }
+                    // --- Render the two panes ---
+                    let pane_width = (width - padding * 3.0) / 2.0;
+                    let pane_height = height - padding * 4.0 - 60.0;
+                    let mut left_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    left_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(pane_height),
+                    );
+                    left_buffer.set_text(
+                        &mut self.font_system,
+                        &left_text,
+                        left_spans,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(left_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    let mut right_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    right_buffer.set_size(
+                        &mut self.font_system,
+                        Some(pane_width),
+                        Some(pane_height),
+                    );
+                    right_buffer.set_text(
+                        &mut self.font_system,
+                        &right_text,
+                        right_spans,
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(right_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    self.editor.set_buffer(self.buffer.clone());
+                    // --- Render instructions ---
+                    let instructions = "UP/DOWN: Select hunk   LEFT/RIGHT: Switch file   ENTER: Apply all   R: Refine   ESC: Cancel";
+                    let mut instr_buffer =
+                        Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+                    instr_buffer.set_size(
+                        &mut self.font_system,
+                        Some(width - padding * 2.0),
+                        Some(30.0),
+                    );
+                    instr_buffer.set_text(
+                        &mut self.font_system,
+                        instructions,
+                        Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+                        Shaping::Advanced,
+                    );
+                    self.editor.set_buffer(instr_buffer);
+                    self.shape_editor();
+                    self.draw_editor(&mut render_pass);
+                    self.editor.set_buffer(self.buffer.clone());
+                }
+            }
+            if app.show_perf_hud {
+                self.render_perf_hud(app, &mut render_pass);
+            }
+        }
+        if app.show_perf_hud {
+            if let Some(gpu_timer) = &mut self.gpu_timer {
+                gpu_timer.resolve(&mut encoder);
+            }
+        }
+        let started = Instant::now();
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        self.frame_timer.add(FramePhase::Draw, started.elapsed());
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.read_back();
+        }
+        let timing = self
+            .frame_timer
+            .finish(self.gpu_timer.as_ref().and_then(GpuTimer::last));
+        self.perf_stats.record(
+            Instant::now(),
+            timing,
+            crate::metrics::METRICS.pty_bytes.get(),
+        );
+        Ok(())
+    }
+
+    /// Shapes the editor's buffer, timed as layout for the performance HUD.
+    fn shape_editor(&mut self) {
+        let started = Instant::now();
+        self.editor.shape_as_needed(&mut self.font_system, true);
+        self.frame_timer.add(FramePhase::Layout, started.elapsed());
+    }
+
+    /// Draws the editor's buffer, timed as glyph upload for the performance HUD.
+    fn draw_editor(&mut self, render_pass: &mut wgpu::RenderPass<'_>) {
+        let started = Instant::now();
+        self.editor
+            .draw(&mut self.font_system, &mut self.swash_cache, render_pass);
+        self.frame_timer
+            .add(FramePhase::GlyphUpload, started.elapsed());
+    }
+
+    /// FPS, frame time by phase, PTY throughput and memory in the top right corner.
+    fn render_perf_hud(&mut self, app: &App, render_pass: &mut wgpu::RenderPass<'_>) {
+        let lines = self.perf_stats.lines(Instant::now());
+        let colors = &app.theme.colors;
+        let text_attrs = Attrs::new().color(hex_to_color(&colors.primary.foreground));
+        let background_attrs = Attrs::new().color(hex_to_color(&colors.primary.background));
+        let columns = (self.config.width as f32 / self.char_width) as usize;
+        let hud_width = (lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            + 2)
+        .min(columns);
+        let indent = " ".repeat(columns - hud_width);
+        let background: String = lines
+            .iter()
+            .map(|_| format!("{}{}\n", indent, "█".repeat(hud_width)))
+            .collect();
+        let text: String = lines
+            .iter()
+            .map(|line| format!("{} {}\n", indent, line))
+            .collect();
+        for (layer, attrs) in [(background, background_attrs), (text, text_attrs)] {
+            let mut layer_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            layer_buffer.set_wrap(&mut self.font_system, Wrap::None);
+            layer_buffer.set_size(&mut self.font_system, Some(self.config.width as f32), None);
+            layer_buffer.set_text(&mut self.font_system, &layer, attrs, Shaping::Advanced);
+            self.editor.set_buffer(layer_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+    }
+
+    fn render_input_bar(
+        &mut self,
+        app: &App,
+        pos: (f32, f32),
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        let (x, y) = pos;
+        let mut display_text = String::new();
+        if let Some(vim_state) = &app.vim_state {
+            let mode_indicator = match vim_state.mode {
+                VimMode::Normal => "  NORMAL ",
+                VimMode::Insert => "  INSERT ",
+                VimMode::Visual => "  VISUAL ",
+            };
+            display_text.push_str(mode_indicator);
+        }
+        // Multi-line commands get a gutter of line numbers, and long lines wrap short of it
+        let line_count = app.input_editor.buffer().lines.len();
+        let gutter_chars = if app.config.editor.line_numbers && line_count > 1 {
+            line_count.to_string().len() + 1
+        } else {
+            0
+        };
+        // Draw the user's actual input, colored by the syntax highlights
+        let mut input_buffer = app.input_editor.buffer().clone();
+        input_buffer.set_wrap(
+            &mut self.font_system,
+            if app.config.editor.soft_wrap {
+                Wrap::WordOrGlyph
+            } else {
+                Wrap::None
+            },
+        );
+        input_buffer.set_size(
+            &mut self.font_system,
+            Some(self.config.width as f32 - gutter_chars as f32 * self.char_width),
+            None,
+        );
+        let mut line_start = 0;
+        for line in input_buffer.lines.iter_mut() {
+            let line_end = line_start + line.text().len();
+            let mut attrs_list = AttrsList::new(
+                Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground)),
+            );
+            for span in app.syntax.highlights(INPUT_DOCUMENT) {
+                let (start, end) = (
+                    span.range.start.max(line_start),
+                    span.range.end.min(line_end),
+                );
+                if start < end {
+                    attrs_list.add_span(
+                        start - line_start..end - line_start,
+                        Attrs::new().color(highlight_color(span.kind, &app.theme)),
+                    );
+                }
+            }
+            line.set_attrs_list(attrs_list);
+            line_start = line_end + 1;
+        }
+        self.editor.set_buffer(input_buffer);
+        self.shape_editor();
+        self.draw_editor(render_pass);
+        let input_rows = self.editor.buffer().layout_runs().count().max(1);
+        if gutter_chars > 0 {
+            // Numbered once per line, on its first wrapped row
+            let mut gutter_text = String::new();
+            let mut last_line = None;
+            for run in self.editor.buffer().layout_runs() {
+                if last_line != Some(run.line_i) {
+                    gutter_text.push_str(&format!(
+                        "{:>width$}",
+                        run.line_i + 1,
+                        width = gutter_chars - 1
+                    ));
+                    last_line = Some(run.line_i);
+                }
+                gutter_text.push('\n');
+            }
+            let mut gutter_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            gutter_buffer.set_text(
+                &mut self.font_system,
+                &gutter_text,
+                Attrs::new().color(hex_to_color(&app.theme.colors.bright.black)),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(gutter_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+        // Underline input that does not parse, on a layer of blanks over the monospace text
+        let errors = app.syntax.errors(INPUT_DOCUMENT);
+        if !errors.is_empty() {
+            let error_color = app
+                .theme
+                .colors
+                .syntax
+                .as_ref()
+                .and_then(|syntax| syntax.error.clone())
+                .unwrap_or_else(|| app.theme.colors.normal.red.clone());
+            let mut underline_text = String::new();
+            let mut line_start = 0;
+            for line in app.input_editor.buffer().lines.iter() {
+                for (offset, grapheme) in
+                    unicode_segmentation::UnicodeSegmentation::grapheme_indices(line.text(), true)
+                {
+                    let byte = line_start + offset;
+                    let mark = if errors.iter().any(|error| error.contains(&byte)) {
+                        "_"
+                    } else {
+                        " "
+                    };
+                    underline_text
+                        .push_str(&mark.repeat(crate::string_offset::grapheme_width(grapheme)));
+                }
+                underline_text.push('\n');
+                line_start += line.text().len() + 1;
+            }
+            let mut underline_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            underline_buffer.set_size(
+                &mut self.font_system,
+                Some(self.config.width as f32),
+                Some(self.char_height * app.input_editor.buffer().lines.len() as f32),
+            );
+            underline_buffer.set_text(
+                &mut self.font_system,
+                &underline_text,
+                Attrs::new().color(hex_to_color(&error_color)),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(underline_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+        // Draw the selected completion, or else the autosuggestion once the cursor is at the end of the input, as dimmed ghost text after the cursor
+        let cursor = app.input_editor.buffer().cursor();
+        let lines = &app.input_editor.buffer().lines;
+        let cursor_line = lines.get(cursor.line).map(|line| line.text()).unwrap_or("");
+        let at_end = cursor.line + 1 >= lines.len() && cursor.index >= cursor_line.len();
+        if let Some(suggestion) = app
+            .completions_manager
+            .ui
+            .ghost_text()
+            .or(app.autosuggestion.as_deref().filter(|_| at_end))
+        {
+            let column = crate::string_offset::display_width(
+                cursor_line.get(..cursor.index).unwrap_or(cursor_line),
+            );
+            let ghost_color = app
+                .theme
+                .colors
+                .primary
+                .dim_foreground
+                .as_deref()
+                .unwrap_or(&app.theme.colors.bright.black);
+            let mut ghost_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            ghost_buffer.set_text(
+                &mut self.font_system,
+                &format!(
+                    "{}{}{}",
+                    "\n".repeat(cursor.line),
+                    " ".repeat(column),
+                    suggestion
+                ),
+                Attrs::new().color(hex_to_color(ghost_color)),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(ghost_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+        // Pasted images as chips, and what an unfinished command waits for, on the line below the input
+        let mut status = Vec::new();
+        let image_chips = app.pasted_image_chips();
+        if !image_chips.is_empty() {
+            let chips: Vec<String> = image_chips.iter().map(|chip| chip.to_string()).collect();
+            status.push(format!("📎 {}", chips.join(" ")));
+        }
+        if line_count > 1 {
+            if let Some(pending) = app.input_continuation() {
+                status.push(format!("↵ waiting for {}", pending.describe()));
+            }
+        }
+        if !status.is_empty() {
+            let mut status_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            status_buffer.set_text(
+                &mut self.font_system,
+                &format!("{}{}", "\n".repeat(input_rows), status.join("   ")),
+                Attrs::new().color(hex_to_color(&app.theme.colors.normal.cyan)),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(status_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+        // Otherwise, with nothing typed yet, the commands usually run in this directory
+        if status.is_empty()
+            && !app.directory_suggestions.is_empty()
+            && app
+                .input_editor
+                .buffer()
+                .lines
+                .iter()
+                .all(|line| line.text().is_empty())
+        {
+            let hints: Vec<String> = app
+                .directory_suggestions
+                .iter()
+                .enumerate()
+                .map(|(i, command)| format!("Alt+{} {}", i + 1, command))
+                .collect();
+            let mut suggestion_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            suggestion_buffer.set_text(
+                &mut self.font_system,
+                &format!(
+                    "{}usually run here:  {}",
+                    "\n".repeat(input_rows),
+                    hints.join("   ")
+                ),
+                Attrs::new().color(hex_to_color(&app.theme.colors.bright.black)),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(suggestion_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+        self.render_completions(app, render_pass);
+    }
+
+    fn render_cursor(
+        &mut self,
+        app: &App,
+        pos: &GridCoords,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        let (row, col) = (pos.y as f32, pos.x as f32);
+        let x = col * self.char_width;
+        let y = row * self.char_height;
+        let cursor_shape = if let Some(vim_state) = &app.vim_state {
+            match vim_state.mode {
+                VimMode::Insert => CursorShape::Bar,
+                _ => CursorShape::Block,
+            }
+        } else {
+            app.config.appearance.cursor.shape.clone()
+        };
+        let cursor_char = match cursor_shape {
+            CursorShape::Block => "█",
+            CursorShape::Bar => "▎",
+            CursorShape::Underline => " ", // Special case for underline
+        };
+        let cursor_color = hex_to_color(&app.theme.colors.cursor.cursor);
+        let mut cursor_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+        cursor_buffer.set_size(
+            &mut self.font_system,
+            Some(self.char_width),
+            Some(self.char_height),
+        );
+        if cursor_shape == CursorShape::Underline {
+            let underline_y = y + self.char_height - (self.char_height / 4.0);
+            cursor_buffer.set_text(
+                &mut self.font_system,
+                "▀",
+                Attrs::new().color(cursor_color),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(cursor_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        } else {
+            cursor_buffer.set_text(
+                &mut self.font_system,
+                cursor_char,
+                Attrs::new().color(cursor_color),
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(cursor_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+    }
+
+    /// The completions popup, under the cursor's line from the start of the word being completed, on a layer of blocks in the theme's colors
+    fn render_completions(&mut self, app: &App, render_pass: &mut wgpu::RenderPass<'a>) {
+        let ui = &app.completions_manager.ui;
+        if !ui.is_visible || ui.suggestions.is_empty() {
+            return;
+        }
+        let colors = &app.theme.colors;
+        let text_attrs = Attrs::new().color(hex_to_color(&colors.primary.foreground));
+        let rows = completion_rows(ui, colors);
+        // Moved left when it would run off the window
+        let columns = (self.config.width as f32 / self.char_width) as usize;
+        let popup_width = rows
+            .iter()
+            .map(|(_, width)| *width)
+            .max()
+            .unwrap_or(0)
+            .min(columns);
+        let cursor = app.input_editor.buffer().cursor();
+        let line = app
+            .input_editor
+            .buffer()
+            .lines
+            .get(cursor.line)
+            .map(|line| line.text())
+            .unwrap_or("");
+        let cursor_column =
+            crate::string_offset::display_width(line.get(..cursor.index).unwrap_or(line));
+        let indent = cursor_column
+            .saturating_sub(crate::string_offset::display_width(&ui.word))
+            .min(columns - popup_width);
+        let top = "\n".repeat(cursor.line + 1);
+        let background_attrs = Attrs::new().color(hex_to_color(&colors.primary.background));
+        let selection_attrs = Attrs::new().color(hex_to_color(&colors.selection.background));
+        let selected_row = ui.selected_index - ui.scroll_offset;
+        let mut background = vec![(top.clone(), background_attrs)];
+        for row in 0..rows.len() {
+            background.push((" ".repeat(indent), background_attrs));
+            background.push((
+                "█".repeat(popup_width),
+                if row == selected_row {
+                    selection_attrs
+                } else {
+                    background_attrs
+                },
+            ));
+            background.push(("\n".to_string(), background_attrs));
+        }
+        let mut text = vec![(top, text_attrs)];
+        for (spans, _) in rows {
+            text.push((" ".repeat(indent), text_attrs));
+            text.extend(spans);
+            text.push(("\n".to_string(), text_attrs));
+        }
+        for layer in [background, text] {
+            let mut layer_buffer =
+                Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+            layer_buffer.set_wrap(&mut self.font_system, Wrap::None);
+            layer_buffer.set_size(&mut self.font_system, Some(self.config.width as f32), None);
+            layer_buffer.set_rich_text(
+                &mut self.font_system,
+                layer.iter().map(|(text, attrs)| (text.as_str(), *attrs)),
+                text_attrs,
+                Shaping::Advanced,
+            );
+            self.editor.set_buffer(layer_buffer);
+            self.shape_editor();
+            self.draw_editor(render_pass);
+        }
+    }
+
+    fn render_search(
+        &mut self,
+        app: &App,
+        state: &crate::app::state::SearchState,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let padding = 50.0;
+        // Draw background
+        let mut bg_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+        bg_buffer.set_size(&mut self.font_system, Some(width), Some(height));
+        bg_buffer.set_text(
+            &mut self.font_system,
+            "█",
+            Attrs::new()
+                .color(Color::rgba(20, 20, 20, 230))
+                .font_size(height * 2.0),
+            Shaping::Advanced,
+        );
+        self.editor.set_buffer(bg_buffer);
+        self.shape_editor();
+        self.draw_editor(render_pass);
+        // Draw UI text
+        let mut ui_buffer = Buffer::new(&mut self.font_system, self.editor.buffer().metrics());
+        ui_buffer.set_size(
+            &mut self.font_system,
+            Some(width - padding * 2.0),
+            Some(height - padding * 2.0),
+        );
+        let (domain, _) = crate::search::SearchDomain::parse(&state.query);
+        let mut text = format!("Search {}: {}\n", domain.label(), state.query);
+        text.push_str(">actions  @files  #blocks  :settings  (Tab to switch)\n");
+        if domain == crate::search::SearchDomain::Actions {
+            text.push_str("Enter on a category to browse it  Ctrl+P: pin or unpin\n");
+        }
+        text.push_str("\n");
+        let text_attrs = Attrs::new().color(hex_to_color(&app.theme.colors.primary.foreground));
+        let match_attrs = text_attrs
+            .color(hex_to_color(&app.theme.colors.normal.yellow))
+            .weight(Weight::BOLD);
+        let mut spans: Vec<(String, Attrs)> = vec![(text, text_attrs)];
+        for (i, result) in state.results.iter().take(10).enumerate() {
+            let marker = if i == state.selected_idx { ">" } else { " " };
+            spans.push((format!("{} ", marker), text_attrs));
+            // The characters the query matched stand out in the title
+            let mut start = 0;
+            for &position in &result.highlights {
+                let Some(ch) = result
+                    .title
+                    .get(position..)
+                    .and_then(|rest| rest.chars().next())
+                else {
+                    continue;
+                };
+                spans.push((result.title[start..position].to_string(), text_attrs));
+                spans.push((ch.to_string(), match_attrs));
+                start = position + ch.len_utf8();
+            }
+            spans.push((result.title[start..].to_string(), text_attrs));
+            if !result.detail.is_empty() {
+                spans.push((format!("  —  {}", result.detail), text_attrs));
+            }
+            spans.push(("\n".to_string(), text_attrs));
+        }
+        ui_buffer.set_rich_text(
+            &mut self.font_system,
+            spans.iter().map(|(text, attrs)| (text.as_str(), *attrs)),
+            text_attrs,
+            Shaping::Advanced,
+        );
+        self.editor.set_buffer(ui_buffer);
+        self.shape_editor();
+        self.draw_editor(render_pass);
+        self.editor.set_buffer(self.buffer.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests;
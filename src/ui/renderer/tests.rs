@@ -0,0 +1,184 @@
+//! Snapshot tests for the text the renderer lays out: command output from
+//! the terminal grid, markdown rendered into that grid, and the completions
+//! popup, each colored with every bundled theme.
+
+use super::*;
+use crate::completions::{Suggestion, SuggestionType};
+use crate::config::theme::load_theme;
+use crate::markdown_parser::MarkdownProcessor;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+const STYLE_IDS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+const ANSI_FIXTURE: &str = "\x1b[1;32m✓\x1b[0m build \x1b[2m(0.42s)\x1b[22m\n\
+    \x1b[31merror\x1b[39m: \x1b[1mmismatched types\x1b[0m\n\
+    \x1b[34m  --> \x1b[0msrc/main.rs:\x1b[33m12\x1b[0m\n\
+    \x1b[90m# bright\x1b[0m \x1b[97;41m FAIL \x1b[0m \x1b[38;5;208m256\x1b[0m \x1b[38;2;10;20;30mrgb\x1b[0m\n\
+    \x1b[4;36mhttps://example.com\x1b[0m\t\x1b[7mrev\x1b[27m \x1b[3;9mgone\x1b[0m";
+
+const MARKDOWN_FIXTURE: &str = "# Deploy\n\nRun **cargo build** then *ship* it with `make`.\n\n- first\n- second\n";
+
+/// What a character is drawn with: its color, weight and slant.
+type CellStyle = (Option<Color>, Weight, FontStyle);
+
+fn bundled_themes() -> Vec<(String, Option<Theme>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("themes");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, load_theme(&path).ok())
+        })
+        .collect()
+}
+
+/// Feeds `ansi` through the terminal emulator, as the pane's reader thread
+/// does, and returns what the renderer would draw for the grid.
+fn grid_cells(ansi: &str, cols: u16, rows: u16, theme: &Theme) -> Vec<(char, CellStyle)> {
+    let mut vte_state = VteState::new(cols, rows);
+    vte_state.process(ansi.replace('\n', "\r\n").as_bytes());
+    let (text, attrs_list) = grid_rich_text(&vte_state, theme);
+    text.char_indices()
+        .map(|(index, ch)| {
+            let attrs = attrs_list.get_span(index);
+            (ch, (attrs.color_opt, attrs.weight, attrs.style))
+        })
+        .collect()
+}
+
+fn popup_cells(ui: &CompletionsUI, theme: &Theme) -> Vec<(char, CellStyle)> {
+    let mut cells = Vec::new();
+    for (spans, _) in completion_rows(ui, &theme.colors) {
+        for (text, attrs) in spans {
+            cells.extend(text.chars().map(|ch| (ch, (attrs.color_opt, attrs.weight, attrs.style))));
+        }
+        cells.push(('\n', (None, Weight::NORMAL, FontStyle::Normal)));
+    }
+    cells
+}
+
+fn style_name((color, weight, style): &CellStyle) -> String {
+    let mut name = match color {
+        Some(color) => format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b()),
+        None => "default".to_string(),
+    };
+    if *weight == Weight::BOLD {
+        name.push_str(" bold");
+    }
+    if *style == FontStyle::Italic {
+        name.push_str(" italic");
+    }
+    name
+}
+
+/// Calls `render` with every bundled theme. The text is written once; each
+/// theme gets a map of which style every character uses and a legend of
+/// those styles. Themes whose colors fall on the same map share it.
+fn snapshot(render: impl Fn(&Theme) -> Vec<(char, CellStyle)>) -> String {
+    let mut text = None;
+    let mut maps: Vec<String> = Vec::new();
+    let mut themes = String::new();
+    for (name, theme) in bundled_themes() {
+        let Some(theme) = theme else {
+            writeln!(themes, "{}: unavailable", name).unwrap();
+            continue;
+        };
+        let cells = render(&theme);
+        let rendered: String = cells.iter().map(|(ch, _)| *ch).collect();
+        let first = text.get_or_insert_with(|| rendered.clone());
+        assert_eq!(*first, rendered, "{} changed the text", name);
+
+        let mut styles: Vec<CellStyle> = Vec::new();
+        let map: String = cells
+            .iter()
+            .map(|(ch, style)| {
+                if *ch == '\n' {
+                    return '\n';
+                }
+                let id = styles.iter().position(|known| known == style).unwrap_or_else(|| {
+                    styles.push(*style);
+                    styles.len() - 1
+                });
+                STYLE_IDS[id] as char
+            })
+            .collect();
+        let map_id = maps.iter().position(|known| *known == map).unwrap_or_else(|| {
+            maps.push(map);
+            maps.len() - 1
+        });
+        let legend: Vec<String> = styles
+            .iter()
+            .enumerate()
+            .map(|(id, style)| format!("{}={}", STYLE_IDS[id] as char, style_name(style)))
+            .collect();
+        writeln!(themes, "{} (map {}): {}", name, map_id + 1, legend.join(" ")).unwrap();
+    }
+
+    let mut out = format!("--- text\n{}", text.unwrap_or_default());
+    for (id, map) in maps.iter().enumerate() {
+        write!(out, "--- map {}\n{}", id + 1, map).unwrap();
+    }
+    out.push_str("--- themes\n");
+    out.push_str(&themes);
+    out
+}
+
+/// Compares `actual` with `tests/snapshots/<name>.snap`. Set
+/// `UPDATE_SNAPSHOTS=1` to write it, for a new snapshot or after a reviewed
+/// change.
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots").join(format!("{}.snap", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("no snapshot at {}; run with UPDATE_SNAPSHOTS=1 to write it", path.display()));
+    if expected != actual {
+        let diff = similar::TextDiff::from_lines(expected.as_str(), actual);
+        panic!("snapshot {} changed:\n{}", name, diff.unified_diff().header("expected", "actual"));
+    }
+}
+
+#[test]
+fn test_ansi_output_snapshot() {
+    assert_snapshot("ansi_output", &snapshot(|theme| grid_cells(ANSI_FIXTURE, 48, 5, theme)));
+}
+
+#[test]
+fn test_markdown_snapshot() {
+    let rendered = MarkdownProcessor::new().process(MARKDOWN_FIXTURE).unwrap();
+    assert_snapshot("markdown", &snapshot(|theme| grid_cells(&rendered, 48, 8, theme)));
+}
+
+#[test]
+fn test_completions_popup_snapshot() {
+    let suggestion = |display: &str, description: Option<&str>, suggestion_type| Suggestion {
+        display: display.to_string(),
+        replacement: display.to_string(),
+        description: description.map(str::to_string),
+        suggestion_type,
+        confidence: 1.0,
+    };
+    let mut suggestions = vec![
+        suggestion("checkout", Some("Switch branches"), SuggestionType::Subcommand),
+        suggestion("cherry-pick", None, SuggestionType::Subcommand),
+        suggestion("--cached", None, SuggestionType::Flag),
+        suggestion("src/cache.rs", None, SuggestionType::FilePath),
+        suggestion("git checkout main", None, SuggestionType::History),
+    ];
+    // Enough to scroll, so the footer shows
+    suggestions.extend((0..8).map(|i| suggestion(&format!("ch{}", i), None, SuggestionType::Argument)));
+    let mut ui = CompletionsUI::new();
+    ui.show(suggestions, "ch".to_string());
+    ui.next();
+    assert_snapshot("completions", &snapshot(|theme| popup_cells(&ui, theme)));
+}
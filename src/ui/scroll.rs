@@ -0,0 +1,247 @@
+//! Smooth Scrolling
+//!
+//! This module keeps a pane's scroll position in pixels rather than rows.
+//! Mouse wheel notches animate towards their target along a configurable
+//! easing curve, and trackpad gestures carry on with decaying momentum after
+//! the fingers lift. The terminal grid stays row-based; the renderer only
+//! shifts content by the pixel offset. With reduced motion, every scroll
+//! jumps straight to its destination.
+
+use crate::config::{ScrollConfig, ScrollCurve};
+use std::time::{Duration, Instant};
+
+/// Momentum below this speed (pixels per second) stops.
+const MIN_VELOCITY: f32 = 20.0;
+
+/// A scroll gesture. Positive amounts scroll down, towards newer output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollInput {
+    /// Mouse wheel notches.
+    Lines(f32),
+    /// Precise trackpad movement.
+    Pixels(f32),
+    /// The trackpad gesture ended; momentum continues from its last speed.
+    GestureEnd,
+}
+
+impl ScrollCurve {
+    /// Maps animation progress `t` in `[0, 1]` to distance covered.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            ScrollCurve::Linear => t,
+            ScrollCurve::EaseOutQuad => 1.0 - (1.0 - t).powi(2),
+            ScrollCurve::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+        }
+    }
+}
+
+/// Scroll behaviour resolved from config and the system's motion preference.
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollSettings {
+    pub animate: bool,
+    pub lines_per_notch: f32,
+    pub duration: Duration,
+    pub curve: ScrollCurve,
+    pub friction: f32,
+}
+
+impl ScrollSettings {
+    pub fn from_config(config: &ScrollConfig) -> Self {
+        let reduced_motion = config.reduced_motion.unwrap_or_else(system_prefers_reduced_motion);
+        Self {
+            animate: config.smooth && !reduced_motion,
+            lines_per_notch: config.lines_per_notch,
+            duration: Duration::from_millis(config.animation_ms),
+            curve: config.curve,
+            friction: config.friction,
+        }
+    }
+}
+
+/// Asks the desktop whether animations should be reduced. Only macOS and
+/// GNOME expose the setting; everywhere else motion is allowed.
+pub fn system_prefers_reduced_motion() -> bool {
+    let read = |program: &str, args: &[&str]| {
+        std::process::Command::new(program)
+            .args(args)
+            .output()
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    if cfg!(target_os = "macos") {
+        read("defaults", &["read", "com.apple.universalaccess", "reduceMotion"]).as_deref() == Some("1")
+    } else if cfg!(target_os = "linux") {
+        read("gsettings", &["get", "org.gnome.desktop.interface", "enable-animations"]).as_deref() == Some("false")
+    } else {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Animation {
+    from: f32,
+    to: f32,
+    started: Instant,
+}
+
+/// A pane's pixel scroll position and any motion in progress.
+#[derive(Debug, Clone, Default)]
+pub struct SmoothScroll {
+    offset: f32,
+    max_offset: f32,
+    animation: Option<Animation>,
+    velocity: f32,
+    momentum_at: Option<Instant>,
+    last_pixels_at: Option<Instant>,
+}
+
+impl SmoothScroll {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current offset in pixels from the top of the pane's content.
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some() || self.momentum_at.is_some()
+    }
+
+    /// Limits scrolling to the content's height, stopping any motion past it.
+    pub fn set_max_offset(&mut self, max_offset: f32) {
+        self.max_offset = max_offset.max(0.0);
+        if let Some(animation) = &mut self.animation {
+            animation.to = animation.to.min(self.max_offset);
+        }
+        self.offset = self.offset.min(self.max_offset);
+    }
+
+    pub fn apply(&mut self, input: ScrollInput, line_height: f32, now: Instant, settings: &ScrollSettings) {
+        match input {
+            ScrollInput::Lines(notches) => {
+                let start = self.animation.map_or(self.offset, |animation| animation.to);
+                self.scroll_to(start + notches * settings.lines_per_notch * line_height, now, settings);
+            }
+            ScrollInput::Pixels(delta) => {
+                self.animation = None;
+                self.momentum_at = None;
+                self.offset = (self.offset + delta).clamp(0.0, self.max_offset);
+                let elapsed = self.last_pixels_at.map_or(Duration::from_millis(16), |at| now.saturating_duration_since(at));
+                self.velocity = delta / elapsed.as_secs_f32().max(0.001);
+                self.last_pixels_at = Some(now);
+            }
+            ScrollInput::GestureEnd => {
+                self.last_pixels_at = None;
+                if settings.animate && self.velocity.abs() > MIN_VELOCITY {
+                    self.momentum_at = Some(now);
+                } else {
+                    self.velocity = 0.0;
+                }
+            }
+        }
+    }
+
+    /// Moves to `offset`, eased like a wheel notch unless animation is off.
+    pub fn scroll_to(&mut self, offset: f32, now: Instant, settings: &ScrollSettings) {
+        let to = offset.clamp(0.0, self.max_offset);
+        self.velocity = 0.0;
+        self.momentum_at = None;
+        if settings.animate {
+            self.animation = Some(Animation { from: self.offset, to, started: now });
+        } else {
+            self.offset = to;
+        }
+    }
+
+    /// Advances any animation or momentum to `now`. Returns whether the
+    /// position is still moving, so the caller keeps redrawing.
+    pub fn tick(&mut self, now: Instant, settings: &ScrollSettings) -> bool {
+        if let Some(animation) = self.animation {
+            let elapsed = now.saturating_duration_since(animation.started);
+            let t = if settings.duration.is_zero() { 1.0 } else { elapsed.as_secs_f32() / settings.duration.as_secs_f32() };
+            self.offset = animation.from + (animation.to - animation.from) * settings.curve.apply(t);
+            if t >= 1.0 {
+                self.offset = animation.to;
+                self.animation = None;
+            }
+        }
+        if let Some(last) = self.momentum_at {
+            let dt = now.saturating_duration_since(last).as_secs_f32();
+            self.velocity *= (-settings.friction * dt).exp();
+            self.offset = (self.offset + self.velocity * dt).clamp(0.0, self.max_offset);
+            let at_edge = self.offset <= 0.0 || self.offset >= self.max_offset;
+            if self.velocity.abs() < MIN_VELOCITY || at_edge {
+                self.velocity = 0.0;
+                self.momentum_at = None;
+            } else {
+                self.momentum_at = Some(now);
+            }
+        }
+        self.is_animating()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(animate: bool) -> ScrollSettings {
+        ScrollSettings {
+            animate,
+            lines_per_notch: 3.0,
+            duration: Duration::from_millis(100),
+            curve: ScrollCurve::EaseOutCubic,
+            friction: 5.0,
+        }
+    }
+
+    #[test]
+    fn test_wheel_eases_to_target() {
+        let (settings, start) = (settings(true), Instant::now());
+        let mut scroll = SmoothScroll::new();
+        scroll.set_max_offset(1000.0);
+        scroll.apply(ScrollInput::Lines(1.0), 20.0, start, &settings);
+        assert_eq!(scroll.offset(), 0.0);
+
+        assert!(scroll.tick(start + Duration::from_millis(50), &settings));
+        assert_eq!(scroll.offset(), 60.0 * ScrollCurve::EaseOutCubic.apply(0.5));
+        assert!(!scroll.tick(start + Duration::from_millis(100), &settings));
+        assert_eq!(scroll.offset(), 60.0);
+    }
+
+    #[test]
+    fn test_reduced_motion_jumps() {
+        let (settings, now) = (settings(false), Instant::now());
+        let mut scroll = SmoothScroll::new();
+        scroll.set_max_offset(100.0);
+        scroll.apply(ScrollInput::Lines(2.0), 20.0, now, &settings);
+        assert_eq!(scroll.offset(), 100.0);
+        scroll.apply(ScrollInput::Pixels(-30.0), 20.0, now, &settings);
+        scroll.apply(ScrollInput::GestureEnd, 20.0, now, &settings);
+        assert_eq!(scroll.offset(), 70.0);
+        assert!(!scroll.is_animating());
+    }
+
+    #[test]
+    fn test_momentum_decays_and_stops_at_edge() {
+        let (settings, start) = (settings(true), Instant::now());
+        let mut scroll = SmoothScroll::new();
+        scroll.set_max_offset(150.0);
+        scroll.apply(ScrollInput::Pixels(10.0), 20.0, start, &settings);
+        scroll.apply(ScrollInput::Pixels(10.0), 20.0, start + Duration::from_millis(10), &settings);
+        scroll.apply(ScrollInput::GestureEnd, 20.0, start + Duration::from_millis(10), &settings);
+
+        let mut now = start + Duration::from_millis(10);
+        let mut last = scroll.offset();
+        while scroll.tick(now, &settings) {
+            assert!(scroll.offset() >= last);
+            last = scroll.offset();
+            now += Duration::from_millis(16);
+        }
+        // 1000 px/s with friction 5 would coast about 200 px, but the edge stops it.
+        assert_eq!(scroll.offset(), 150.0);
+    }
+}
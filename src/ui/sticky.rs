@@ -0,0 +1,101 @@
+//! Sticky Command Header
+//!
+//! While a block's output fills the top of a pane, the block's command line
+//! is pinned there with how the command ended and how long it ran, so that
+//! thousands of lines of output can be scrolled through without losing
+//! track of which command produced them.
+
+use std::time::Duration;
+
+/// The header pinned to the top of a pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StickyHeader {
+    pub block_index: usize,
+    pub command: String,
+    /// The exit status and duration, e.g. `✗ 2 · 1.4s`; empty when unknown.
+    pub status: String,
+    pub failed: bool,
+}
+
+/// The block whose header is pinned when `top_row` is the first row on
+/// screen: the block covering it, once its own command line has scrolled
+/// above it. `block_rows` are the rows each block takes, in order.
+pub fn pinned_block(block_rows: impl IntoIterator<Item = usize>, top_row: usize) -> Option<usize> {
+    let mut start = 0;
+    for (index, rows) in block_rows.into_iter().enumerate() {
+        let end = start + rows;
+        if top_row < end {
+            return (top_row > start).then_some(index);
+        }
+        start = end;
+    }
+    None
+}
+
+/// `✓ 1.2s` for a command that succeeded, `✗ 2 · 3m 04s` for one that
+/// failed with status 2. Either part is left out when it isn't known.
+pub fn status_label(status: Option<i32>, duration: Option<Duration>) -> String {
+    match (status, duration.map(format_duration)) {
+        (Some(0), Some(duration)) => format!("✓ {}", duration),
+        (Some(0), None) => "✓".to_string(),
+        (Some(status), Some(duration)) => format!("✗ {} · {}", status, duration),
+        (Some(status), None) => format!("✗ {}", status),
+        (None, Some(duration)) => duration,
+        (None, None) => String::new(),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else if duration >= Duration::from_secs(1) {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_pins_once_the_command_line_scrolls_away() {
+        let rows = [3, 100, 4];
+        // The first block's command line is on screen
+        assert_eq!(pinned_block(rows, 0), None);
+        assert_eq!(pinned_block(rows, 1), Some(0));
+        assert_eq!(pinned_block(rows, 3), None);
+        assert_eq!(pinned_block(rows, 4), Some(1));
+        assert_eq!(pinned_block(rows, 102), Some(1));
+        assert_eq!(pinned_block(rows, 104), Some(2));
+        // Past the last block the live grid is at the top
+        assert_eq!(pinned_block(rows, 107), None);
+        assert_eq!(pinned_block([], 5), None);
+    }
+
+    #[test]
+    fn test_status_labels() {
+        assert_eq!(
+            status_label(Some(0), Some(Duration::from_millis(1240))),
+            "✓ 1.2s"
+        );
+        assert_eq!(
+            status_label(Some(2), Some(Duration::from_secs(184))),
+            "✗ 2 · 3m 04s"
+        );
+        assert_eq!(status_label(Some(1), None), "✗ 1");
+        assert_eq!(
+            status_label(None, Some(Duration::from_millis(340))),
+            "340ms"
+        );
+        assert_eq!(
+            status_label(None, Some(Duration::from_secs(3720))),
+            "1h 02m"
+        );
+        assert_eq!(status_label(None, None), "");
+    }
+}
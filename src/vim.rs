@@ -7,7 +7,7 @@ pub enum VimMode {
     Visual,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VimAction {
     NoOp,
     EnterInsertMode,
@@ -22,7 +22,7 @@ pub enum VimAction {
     Redo,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VimMotion {
     Left, Right, Up, Down,
     WordForward, WordBackward,
@@ -45,9 +45,14 @@ impl Default for VimState {
 impl VimState {
     /// This is the core Vim command parser.
     pub fn handle_key(&mut self, key: &winit::event::KeyEvent) -> VimAction {
+        self.handle_physical_key(key.physical_key)
+    }
+
+    /// Parses a key by its physical code, independent of the keyboard layout.
+    pub fn handle_physical_key(&mut self, physical_key: PhysicalKey) -> VimAction {
         if self.mode == VimMode::Insert {
             // In Insert mode, only Escape does something special.
-            return if key.physical_key == PhysicalKey::Code(KeyCode::Escape) {
+            return if physical_key == PhysicalKey::Code(KeyCode::Escape) {
                 VimAction::EnterNormalMode
             } else {
                 VimAction::NoOp // Other keys are handled as text input directly
@@ -55,7 +60,7 @@ impl VimState {
         }
 
         // --- Normal and Visual Mode Logic ---
-        let key_char = match key.physical_key {
+        let key_char = match physical_key {
             PhysicalKey::Code(KeyCode::KeyA) => Some('a'),
             PhysicalKey::Code(KeyCode::KeyB) => Some('b'),
             PhysicalKey::Code(KeyCode::KeyD) => Some('d'),
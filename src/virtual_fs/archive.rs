@@ -0,0 +1,327 @@
+//! Zip and tar archives, browsed read-only.
+//!
+//! The whole archive is read into memory when it is mounted and its index
+//! parsed once; file contents are extracted on read. Zip entries may be
+//! stored or deflated, and tarballs may be gzipped. Paths inside an
+//! archive are absolute, so `release.zip!/bin/tool` is `/bin/tool`.
+
+use super::{DirEntry, FileSystem};
+use flate2::read::{DeflateDecoder, GzDecoder};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP_CENTRAL_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+const TAR_BLOCK: usize = 512;
+
+const ZIP_EXTENSIONS: &[&str] = &[".zip", ".jar", ".whl"];
+const TAR_EXTENSIONS: &[&str] = &[".tar", ".tar.gz", ".tgz"];
+
+/// Whether `path` names an archive this module can browse.
+pub fn is_archive(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    ZIP_EXTENSIONS.iter().chain(TAR_EXTENSIONS).any(|extension| path.ends_with(extension))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Deflate,
+    Unsupported(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Dir,
+    File { offset: usize, stored_size: usize, size: usize, compression: Compression },
+}
+
+pub struct ArchiveFileSystem {
+    data: Vec<u8>,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "archives are read-only")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_at(data: &[u8], offset: usize) -> io::Result<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).ok_or_else(|| invalid("truncated zip"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid("truncated zip"))
+}
+
+impl ArchiveFileSystem {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let name = path.to_string_lossy().to_ascii_lowercase();
+        if ZIP_EXTENSIONS.iter().any(|extension| name.ends_with(extension)) {
+            Self::from_zip(data)
+        } else if name.ends_with(".tar") {
+            Self::from_tar(data)
+        } else {
+            let mut tar = Vec::new();
+            GzDecoder::new(data.as_slice()).read_to_end(&mut tar)?;
+            Self::from_tar(tar)
+        }
+    }
+
+    fn empty() -> Self {
+        let mut entries = BTreeMap::new();
+        entries.insert(PathBuf::from("/"), Entry::Dir);
+        Self { data: Vec::new(), entries }
+    }
+
+    /// Adds `name`, and every directory above it that the archive does not
+    /// list itself.
+    fn insert(&mut self, name: &str, entry: Entry) {
+        let name = name.trim_start_matches("./").trim_matches('/');
+        if name.is_empty() {
+            return;
+        }
+        let path = Path::new("/").join(name);
+        for parent in path.ancestors().skip(1) {
+            self.entries.entry(parent.to_path_buf()).or_insert(Entry::Dir);
+        }
+        self.entries.insert(path, entry);
+    }
+
+    fn from_zip(data: Vec<u8>) -> io::Result<Self> {
+        // The end record is at most 22 bytes plus a 64 KiB comment from the end.
+        let search_from = data.len().saturating_sub(22 + u16::MAX as usize);
+        let end = (search_from..data.len().saturating_sub(21))
+            .rev()
+            .find(|&offset| u32_at(&data, offset).ok() == Some(ZIP_END_OF_CENTRAL_DIRECTORY))
+            .ok_or_else(|| invalid("not a zip archive"))?;
+        let count = u16_at(&data, end + 10)?;
+        let mut offset = u32_at(&data, end + 16)? as usize;
+        if count == u16::MAX || offset == u32::MAX as usize {
+            return Err(invalid("zip64 archives are not supported"));
+        }
+
+        let mut archive = Self::empty();
+        for _ in 0..count {
+            if u32_at(&data, offset)? != ZIP_CENTRAL_DIRECTORY_ENTRY {
+                return Err(invalid("corrupt zip central directory"));
+            }
+            let compression = match u16_at(&data, offset + 10)? {
+                0 => Compression::None,
+                8 => Compression::Deflate,
+                method => Compression::Unsupported(method),
+            };
+            let stored_size = u32_at(&data, offset + 20)? as usize;
+            let size = u32_at(&data, offset + 24)? as usize;
+            let name_len = u16_at(&data, offset + 28)? as usize;
+            let extra_len = u16_at(&data, offset + 30)? as usize;
+            let comment_len = u16_at(&data, offset + 32)? as usize;
+            let header = u32_at(&data, offset + 42)? as usize;
+            let name = data.get(offset + 46..offset + 46 + name_len).ok_or_else(|| invalid("truncated zip"))?;
+            let name = String::from_utf8_lossy(name).into_owned();
+            offset += 46 + name_len + extra_len + comment_len;
+
+            if name.ends_with('/') {
+                archive.insert(&name, Entry::Dir);
+                continue;
+            }
+            if u32_at(&data, header)? != ZIP_LOCAL_HEADER {
+                return Err(invalid("corrupt zip local header"));
+            }
+            // The local header's name and extra field may differ from the central directory's.
+            let start = header + 30 + u16_at(&data, header + 26)? as usize + u16_at(&data, header + 28)? as usize;
+            archive.insert(&name, Entry::File { offset: start, stored_size, size, compression });
+        }
+        archive.data = data;
+        Ok(archive)
+    }
+
+    fn from_tar(data: Vec<u8>) -> io::Result<Self> {
+        let mut archive = Self::empty();
+        let mut offset = 0;
+        let mut long_name = None;
+        while let Some(header) = data.get(offset..offset + TAR_BLOCK) {
+            // Two zero blocks end the archive; one is enough to stop.
+            if header.iter().all(|byte| *byte == 0) {
+                break;
+            }
+            let size = parse_octal(&header[124..136]).ok_or_else(|| invalid("corrupt tar header"))?;
+            let start = offset + TAR_BLOCK;
+            offset = start + size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+
+            let name = match long_name.take() {
+                Some(name) => name,
+                None => {
+                    let name = field(&header[0..100]);
+                    if &header[257..262] == b"ustar" && header[345] != 0 {
+                        format!("{}/{}", field(&header[345..500]), name)
+                    } else {
+                        name
+                    }
+                }
+            };
+            match header[156] {
+                b'5' => archive.insert(&name, Entry::Dir),
+                b'0' | 0 | b'7' => archive.insert(&name, Entry::File { offset: start, stored_size: size, size, compression: Compression::None }),
+                // GNU long name: the next entry's name is this entry's contents.
+                b'L' => {
+                    let contents = data.get(start..start + size).ok_or_else(|| invalid("truncated tar"))?;
+                    long_name = Some(field(contents));
+                }
+                // Links, devices and extended headers have nothing to browse.
+                _ => {}
+            }
+        }
+        archive.data = data;
+        Ok(archive)
+    }
+}
+
+/// A NUL-terminated tar header field.
+fn field(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn parse_octal(bytes: &[u8]) -> Option<usize> {
+    let text = field(bytes);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(text, 8).ok()
+}
+
+impl FileSystem for ArchiveFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let (offset, stored_size, size, compression) = match self.entries.get(path) {
+            Some(Entry::File { offset, stored_size, size, compression }) => (*offset, *stored_size, *size, *compression),
+            Some(Entry::Dir) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "is a directory")),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "File not found")),
+        };
+        let stored = self.data.get(offset..offset + stored_size).ok_or_else(|| invalid("truncated archive"))?;
+        match compression {
+            Compression::None => Ok(stored.to_vec()),
+            Compression::Deflate => {
+                let mut contents = Vec::with_capacity(size);
+                DeflateDecoder::new(stored).read_to_end(&mut contents)?;
+                Ok(contents)
+            }
+            Compression::Unsupported(method) => Err(io::Error::new(io::ErrorKind::Unsupported, format!("zip compression method {}", method))),
+        }
+    }
+
+    fn write(&mut self, _path: &Path, _data: &[u8]) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove(&mut self, _path: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn rename(&mut self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn entries(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        if self.entries.get(path) != Some(&Entry::Dir) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Directory not found"));
+        }
+        Ok(self
+            .entries
+            .iter()
+            .filter(|(child, _)| child.parent() == Some(path))
+            .map(|(child, entry)| DirEntry { path: child.clone(), is_dir: *entry == Entry::Dir })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let (mut data, mut central) = (Vec::new(), Vec::new());
+        for (name, contents) in files {
+            let header = data.len() as u32;
+            data.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+            data.extend_from_slice(&[0; 14]);
+            data.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            data.extend_from_slice(&0u16.to_le_bytes());
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(contents);
+
+            central.extend_from_slice(&ZIP_CENTRAL_DIRECTORY_ENTRY.to_le_bytes());
+            central.extend_from_slice(&[0; 16]);
+            central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 12]);
+            central.extend_from_slice(&header.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = data.len() as u32;
+        data.extend_from_slice(&central);
+        data.extend_from_slice(&ZIP_END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        data.extend_from_slice(&central_offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (name, contents) in files {
+            let mut header = [0u8; TAR_BLOCK];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            data.extend_from_slice(&header);
+            data.extend_from_slice(contents);
+            data.resize(data.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+        }
+        data.resize(data.len() + 2 * TAR_BLOCK, 0);
+        data
+    }
+
+    #[test]
+    fn test_zip_and_tar_archives_are_browsed() {
+        let files: &[(&str, &[u8])] = &[("src/main.rs", b"fn main() {}"), ("README.md", b"# Demo"), ("src/bin/tool.rs", b"")];
+        for archive in [ArchiveFileSystem::from_zip(zip(files)).unwrap(), ArchiveFileSystem::from_tar(tar(files)).unwrap()] {
+            let root = archive.entries(Path::new("/")).unwrap();
+            assert_eq!(
+                root,
+                vec![
+                    DirEntry { path: PathBuf::from("/README.md"), is_dir: false },
+                    DirEntry { path: PathBuf::from("/src"), is_dir: true },
+                ]
+            );
+            assert_eq!(archive.list(Path::new("/src")).unwrap(), vec![PathBuf::from("/src/bin"), PathBuf::from("/src/main.rs")]);
+            assert_eq!(archive.read(Path::new("/src/main.rs")).unwrap(), b"fn main() {}");
+            assert!(archive.read(Path::new("/src")).is_err());
+        }
+    }
+
+    #[test]
+    fn test_archives_are_read_only() {
+        let mut archive = ArchiveFileSystem::from_tar(tar(&[("a.txt", b"a")])).unwrap();
+        assert_eq!(archive.write(Path::new("/a.txt"), b"b").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(archive.remove(Path::new("/a.txt")).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(is_archive("dist/Release.TAR.GZ"));
+        assert!(!is_archive("notes.txt"));
+    }
+}
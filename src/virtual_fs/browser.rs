@@ -0,0 +1,330 @@
+//! File Browser
+//!
+//! The tree and previews behind the file browser overlay. The tree lists
+//! whatever the [`MountTable`] can, so local folders, SFTP hosts and
+//! archives browse alike. A directory is listed when it is first expanded
+//! and kept until the tree is refreshed, since listing a remote one is a
+//! round trip. Previews highlight source files with the syntax parser pool
+//! and show Markdown as the markdown renderer draws it.
+
+use super::{DirEntry, MountTable};
+use crate::markdown_parser::MarkdownProcessor;
+use crate::syntax_parser::{Grammar, HighlightKind, SyntaxParserPool};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use vte::ansi::{Color as TerminalColor, NamedColor, Rgb};
+
+/// The document the syntax parser pool highlights previews as.
+pub const PREVIEW_DOCUMENT: &str = "file-browser-preview";
+
+/// How much of a file is previewed.
+const PREVIEW_LIMIT: usize = 64 * 1024;
+
+/// How much of a file is checked for NUL bytes to tell binaries apart.
+const BINARY_SNIFF: usize = 8 * 1024;
+
+const NAMED_COLORS: [NamedColor; 16] = [
+    NamedColor::Black,
+    NamedColor::Red,
+    NamedColor::Green,
+    NamedColor::Yellow,
+    NamedColor::Blue,
+    NamedColor::Magenta,
+    NamedColor::Cyan,
+    NamedColor::White,
+    NamedColor::BrightBlack,
+    NamedColor::BrightRed,
+    NamedColor::BrightGreen,
+    NamedColor::BrightYellow,
+    NamedColor::BrightBlue,
+    NamedColor::BrightMagenta,
+    NamedColor::BrightCyan,
+    NamedColor::BrightWhite,
+];
+
+/// One visible line of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub path: String,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTree {
+    pub root: String,
+    /// The listed directories, by path.
+    listings: BTreeMap<String, Vec<DirEntry>>,
+    expanded: BTreeSet<String>,
+}
+
+fn entry_path(entry: &DirEntry) -> String {
+    entry.path.to_string_lossy().into_owned()
+}
+
+fn list(mounts: &MountTable, dir: &str) -> io::Result<Vec<DirEntry>> {
+    let mut entries = mounts.entries(dir)?;
+    // Directories first, then by name.
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.path.cmp(&b.path)));
+    Ok(entries)
+}
+
+impl FileTree {
+    pub fn open(mounts: &MountTable, root: &str) -> io::Result<Self> {
+        let mut listings = BTreeMap::new();
+        listings.insert(root.to_string(), list(mounts, root)?);
+        Ok(Self { root: root.to_string(), listings, expanded: BTreeSet::new() })
+    }
+
+    /// The rows of the tree, depth first, with the root's entries at depth 0.
+    pub fn rows(&self) -> Vec<TreeRow> {
+        let mut rows = Vec::new();
+        self.push_rows(&self.root, 0, &mut rows);
+        rows
+    }
+
+    fn push_rows(&self, dir: &str, depth: usize, rows: &mut Vec<TreeRow>) {
+        for entry in self.listings.get(dir).into_iter().flatten() {
+            let path = entry_path(entry);
+            let expanded = self.expanded.contains(&path);
+            rows.push(TreeRow {
+                name: entry.path.file_name().map_or_else(|| path.clone(), |name| name.to_string_lossy().into_owned()),
+                path: path.clone(),
+                depth,
+                is_dir: entry.is_dir,
+                expanded,
+            });
+            if expanded {
+                self.push_rows(&path, depth + 1, rows);
+            }
+        }
+    }
+
+    /// Expands or collapses the directory at `path`, listing it the first
+    /// time.
+    pub fn toggle(&mut self, mounts: &MountTable, path: &str) -> io::Result<()> {
+        if self.expanded.remove(path) {
+            return Ok(());
+        }
+        if !self.listings.contains_key(path) {
+            self.listings.insert(path.to_string(), list(mounts, path)?);
+        }
+        self.expanded.insert(path.to_string());
+        Ok(())
+    }
+
+    /// Lists the root and the expanded directories again, collapsing any
+    /// that are gone.
+    pub fn refresh(&mut self, mounts: &MountTable) -> io::Result<()> {
+        let root = list(mounts, &self.root)?;
+        self.listings.clear();
+        self.listings.insert(self.root.clone(), root);
+        for dir in std::mem::take(&mut self.expanded) {
+            if let Ok(entries) = list(mounts, &dir) {
+                self.listings.insert(dir.clone(), entries);
+                self.expanded.insert(dir);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a span of a preview is colored as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewColor {
+    Syntax(HighlightKind),
+    /// An SGR color from the markdown renderer.
+    Terminal(TerminalColor),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewSpan {
+    pub range: Range<usize>,
+    pub color: PreviewColor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preview {
+    Text { text: String, spans: Vec<PreviewSpan>, truncated: bool },
+    /// A directory and how many entries it has.
+    Directory(usize),
+    /// A binary file and its size.
+    Binary(usize),
+    Error(String),
+}
+
+/// Previews the file or directory `row`, rendering Markdown for a theme
+/// with the given `background`.
+pub fn preview(mounts: &MountTable, row: &TreeRow, syntax: &mut SyntaxParserPool, background: &str) -> Preview {
+    if row.is_dir {
+        return match mounts.entries(&row.path) {
+            Ok(entries) => Preview::Directory(entries.len()),
+            Err(e) => Preview::Error(e.to_string()),
+        };
+    }
+    let data = match mounts.read(&row.path) {
+        Ok(data) => data,
+        Err(e) => return Preview::Error(e.to_string()),
+    };
+    if data[..data.len().min(BINARY_SNIFF)].contains(&0) {
+        return Preview::Binary(data.len());
+    }
+    let truncated = data.len() > PREVIEW_LIMIT;
+    let text = String::from_utf8_lossy(&data[..data.len().min(PREVIEW_LIMIT)]).into_owned();
+
+    let path = Path::new(&row.path);
+    if path.extension().is_some_and(|extension| extension == "md" || extension == "markdown") {
+        if let Ok(rendered) = MarkdownProcessor::new().with_background(background).process(&text) {
+            let (text, spans) = strip_sgr(&rendered);
+            return Preview::Text { text, spans, truncated };
+        }
+    }
+    let spans = match Grammar::for_path(path).map(|grammar| syntax.update(PREVIEW_DOCUMENT, grammar, &text)) {
+        Some(Ok(highlights)) => {
+            highlights.iter().map(|span| PreviewSpan { range: span.range.clone(), color: PreviewColor::Syntax(span.kind) }).collect()
+        }
+        _ => Vec::new(),
+    };
+    Preview::Text { text, spans, truncated }
+}
+
+/// Splits text with SGR escape sequences into the plain text and its
+/// colored spans. Other escape sequences are dropped.
+fn strip_sgr(ansi: &str) -> (String, Vec<PreviewSpan>) {
+    let mut text = String::with_capacity(ansi.len());
+    let mut spans = Vec::new();
+    let mut color: Option<TerminalColor> = None;
+    let mut start = 0;
+    let mut rest = ansi;
+    while let Some(escape) = rest.find('\x1b') {
+        text.push_str(&rest[..escape]);
+        rest = &rest[escape + 1..];
+        let Some(sequence) = rest.strip_prefix('[') else {
+            continue;
+        };
+        let Some(end) = sequence.find(|c: char| ('\x40'..='\x7e').contains(&c)) else {
+            rest = "";
+            break;
+        };
+        rest = &sequence[end + 1..];
+        if !sequence[end..].starts_with('m') {
+            continue;
+        }
+        let next = sgr_color(&sequence[..end], color);
+        if next != color {
+            if let Some(color) = color.filter(|_| start < text.len()) {
+                spans.push(PreviewSpan { range: start..text.len(), color: PreviewColor::Terminal(color) });
+            }
+            color = next;
+            start = text.len();
+        }
+    }
+    text.push_str(rest);
+    if let Some(color) = color.filter(|_| start < text.len()) {
+        spans.push(PreviewSpan { range: start..text.len(), color: PreviewColor::Terminal(color) });
+    }
+    (text, spans)
+}
+
+/// The foreground color after the SGR parameters `params`.
+fn sgr_color(params: &str, mut color: Option<TerminalColor>) -> Option<TerminalColor> {
+    let mut codes = params.split(';').map(|code| code.parse::<u16>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        color = match code {
+            0 | 39 => None,
+            30..=37 => Some(TerminalColor::Named(NAMED_COLORS[usize::from(code - 30)])),
+            90..=97 => Some(TerminalColor::Named(NAMED_COLORS[usize::from(code - 90 + 8)])),
+            // Extended colors; backgrounds are parsed only to be skipped.
+            38 | 48 => {
+                let extended = match codes.next() {
+                    Some(5) => codes.next().map(|index| match index {
+                        0..=15 => TerminalColor::Named(NAMED_COLORS[usize::from(index)]),
+                        _ => TerminalColor::Indexed(index as u8),
+                    }),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(TerminalColor::Spec(Rgb { r: r as u8, g: g as u8, b: b as u8 })),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if code == 38 { extended.or(color) } else { color }
+            }
+            _ => color,
+        };
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_fs::{FileSystem, InMemoryFileSystem};
+
+    fn mounts() -> MountTable {
+        let mut memory = InMemoryFileSystem::new();
+        memory.write(Path::new("/README.md"), b"# Demo\n\nSee `src`.").unwrap();
+        memory.write(Path::new("/src/main.rs"), b"fn main() {}").unwrap();
+        memory.write(Path::new("/src/logo.png"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+        let mounts = MountTable::new();
+        mounts.mount("mem:", Box::new(memory));
+        mounts
+    }
+
+    #[test]
+    fn test_tree_expands_directories_in_place() {
+        let mounts = mounts();
+        let mut tree = FileTree::open(&mounts, "mem:/").unwrap();
+        let names = |tree: &FileTree| tree.rows().into_iter().map(|row| format!("{}{}", "  ".repeat(row.depth), row.name)).collect::<Vec<_>>();
+        assert_eq!(names(&tree), vec!["src", "README.md"]);
+
+        tree.toggle(&mounts, "mem:/src").unwrap();
+        assert_eq!(names(&tree), vec!["src", "  logo.png", "  main.rs", "README.md"]);
+        assert!(tree.rows()[0].expanded);
+
+        mounts.remove("mem:/src/logo.png").unwrap();
+        tree.refresh(&mounts).unwrap();
+        assert_eq!(names(&tree), vec!["src", "  main.rs", "README.md"]);
+        tree.toggle(&mounts, "mem:/src").unwrap();
+        assert_eq!(names(&tree), vec!["src", "README.md"]);
+    }
+
+    #[test]
+    fn test_previews_by_kind() {
+        let mounts = mounts();
+        let mut tree = FileTree::open(&mounts, "mem:/").unwrap();
+        tree.toggle(&mounts, "mem:/src").unwrap();
+        let rows = tree.rows();
+        let mut syntax = SyntaxParserPool::new();
+        let preview = |name: &str, syntax: &mut SyntaxParserPool| preview(&mounts, rows.iter().find(|row| row.name == name).unwrap(), syntax, "#000000");
+
+        assert_eq!(preview("src", &mut syntax), Preview::Directory(2));
+        assert_eq!(preview("logo.png", &mut syntax), Preview::Binary(10));
+        let Preview::Text { text, spans, truncated } = preview("main.rs", &mut syntax) else {
+            panic!("main.rs is text");
+        };
+        assert_eq!(text, "fn main() {}");
+        assert!(!truncated);
+        assert!(spans.iter().any(|span| span.color == PreviewColor::Syntax(HighlightKind::Keyword) && &text[span.range.clone()] == "fn"));
+        let Preview::Text { text, .. } = preview("README.md", &mut syntax) else {
+            panic!("README.md is text");
+        };
+        assert!(text.contains("Demo") && !text.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_sgr_colors_become_spans() {
+        let (text, spans) = strip_sgr("\x1b[1;34mtitle\x1b[0m plain \x1b[38;2;1;2;3mrgb\x1b[39m\x1b[2K");
+        assert_eq!(text, "title plain rgb");
+        assert_eq!(
+            spans,
+            vec![
+                PreviewSpan { range: 0..5, color: PreviewColor::Terminal(TerminalColor::Named(NamedColor::Blue)) },
+                PreviewSpan { range: 12..15, color: PreviewColor::Terminal(TerminalColor::Spec(Rgb { r: 1, g: 2, b: 3 })) },
+            ]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! The local disk.
+
+use super::{DirEntry, FileSystem};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Reads and writes straight through to `std::fs`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFileSystem;
+
+impl FileSystem for LocalFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()> {
+        fs::write(path, data)
+    }
+
+    fn entries(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            // Follows symlinks, so a link to a directory completes like one.
+            let is_dir = entry.path().is_dir();
+            entries.push(DirEntry { path: entry.path(), is_dir });
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        // Not `is_dir`, which would follow a symlink and empty its target.
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+}
@@ -2,16 +2,46 @@
 //!
 //! This module provides a virtual filesystem abstraction that can be used
 //! to interact with different filesystems in a unified way.
+//!
+//! A [`MountTable`] routes paths to the filesystem they live on, so that
+//! completions can list `sftp://host/etc/` or `release.zip!/bin/` like any
+//! local directory. SFTP hosts and archives mount themselves the first time
+//! a path on them is used; other filesystems are mounted under a prefix.
+
+pub mod archive;
+pub mod browser;
+pub mod local;
+pub mod sftp;
 
-use std::collections::HashMap;
-use std::io::{self, Read, Write};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use archive::ArchiveFileSystem;
+use local::LocalFileSystem;
+use sftp::SftpFileSystem;
+
+/// Something directly inside a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
 
 /// A trait for filesystem operations.
-pub trait FileSystem {
+pub trait FileSystem: Send {
     fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
     fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()>;
-    fn list(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    /// What is directly inside the directory at `path`.
+    fn entries(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+    /// Deletes a file, or a directory and everything in it.
+    fn remove(&mut self, path: &Path) -> io::Result<()>;
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn list(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self.entries(path)?.into_iter().map(|entry| entry.path).collect())
+    }
 }
 
 /// An in-memory filesystem for testing and temporary storage.
@@ -39,14 +69,188 @@ impl FileSystem for InMemoryFileSystem {
         Ok(())
     }
 
-    fn list(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
-        Ok(self.files.keys().filter(|p| p.starts_with(path)).cloned().collect())
+    /// Only files are stored; directories are implied by their paths.
+    fn entries(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut children = BTreeMap::new();
+        for file in self.files.keys() {
+            let Ok(rest) = file.strip_prefix(path) else {
+                continue;
+            };
+            let mut components = rest.components();
+            if let Some(first) = components.next() {
+                children.insert(path.join(first), components.next().is_some());
+            }
+        }
+        Ok(children.into_iter().map(|(path, is_dir)| DirEntry { path, is_dir }).collect())
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        let count = self.files.len();
+        self.files.retain(|file, _| !file.starts_with(path));
+        if self.files.len() == count {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "File not found"));
+        }
+        Ok(())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let moved: Vec<PathBuf> = self.files.keys().filter(|file| file.starts_with(from)).cloned().collect();
+        if moved.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "File not found"));
+        }
+        for file in moved {
+            let data = self.files.remove(&file).expect("listed above");
+            self.files.insert(to.join(file.strip_prefix(from).expect("listed above")), data);
+        }
+        Ok(())
+    }
+}
+
+/// The filesystem a path mounts on first use.
+enum ImplicitMount {
+    Sftp(crate::ssh::SshTarget),
+    Archive(PathBuf),
+}
+
+/// The implicit mount under `path`: its prefix, the path inside it and
+/// what to mount. `sftp://[user@]host[:port]/path` is on a host, and
+/// `<archive>!/path` inside a zip or tar archive.
+fn implicit_mount(path: &str) -> Option<(String, PathBuf, ImplicitMount)> {
+    if let Some(rest) = path.strip_prefix("sftp://") {
+        let (authority, inner) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let target = sftp::parse_authority(authority)?;
+        let inner = if inner.is_empty() { "/" } else { inner };
+        return Some((format!("sftp://{}", authority), PathBuf::from(inner), ImplicitMount::Sftp(target)));
+    }
+    let (archive, inner) = path.split_once("!/").or_else(|| path.strip_suffix('!').map(|archive| (archive, "")))?;
+    archive::is_archive(archive).then(|| (format!("{}!", archive), PathBuf::from(format!("/{}", inner)), ImplicitMount::Archive(PathBuf::from(archive))))
+}
+
+/// Whether `path` is on the mount at `prefix`: `sftp://web` holds
+/// `sftp://web/etc` but not `sftp://web-2/etc`.
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with(['/', ':']))
+}
+
+/// The prefix of the mount `path` is on, the path inside it, and what to
+/// mount there if nothing is yet. `None` for the local disk.
+fn route(mounts: &BTreeMap<String, SharedFileSystem>, path: &str) -> Option<(String, PathBuf, Option<ImplicitMount>)> {
+    match mounts.keys().filter(|prefix| is_under(path, prefix)).max_by_key(|prefix| prefix.len()) {
+        Some(prefix) => Some((prefix.clone(), PathBuf::from(format!("/{}", path[prefix.len()..].trim_start_matches('/'))), None)),
+        None => implicit_mount(path).map(|(prefix, inner, mount)| (prefix, inner, Some(mount))),
+    }
+}
+
+type SharedFileSystem = Arc<Mutex<Box<dyn FileSystem>>>;
+
+/// Routes paths to mounted filesystems, and everything else to the local
+/// disk. Paths inside a mount are absolute; the entries it lists are given
+/// back with the mount's prefix.
+#[derive(Default)]
+pub struct MountTable {
+    mounts: Mutex<BTreeMap<String, SharedFileSystem>>,
+}
+
+impl MountTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves paths starting with `prefix` from `fs`, replacing whatever was
+    /// mounted there.
+    pub fn mount(&self, prefix: &str, fs: Box<dyn FileSystem>) {
+        self.mounts.lock().unwrap().insert(prefix.to_string(), Arc::new(Mutex::new(fs)));
+    }
+
+    pub fn unmount(&self, prefix: &str) -> bool {
+        self.mounts.lock().unwrap().remove(prefix).is_some()
+    }
+
+    /// Whether `path` is on a mount rather than the local disk.
+    pub fn is_mounted(&self, path: &str) -> bool {
+        implicit_mount(path).is_some() || self.mounts.lock().unwrap().keys().any(|prefix| is_under(path, prefix))
+    }
+
+    /// Runs `f` on the filesystem `path` is on, with the path inside it.
+    /// Returns the mount's prefix along with the result; empty for the
+    /// local disk. The table is only locked while the mount is looked up,
+    /// so a slow SFTP host holds up only the callers using that host.
+    fn with_fs<T>(&self, path: &str, f: impl FnOnce(&mut dyn FileSystem, &Path) -> io::Result<T>) -> io::Result<(String, T)> {
+        let routed = {
+            let mut mounts = self.mounts.lock().unwrap();
+            match route(&mounts, path) {
+                None => None,
+                Some((prefix, inner, mount)) => {
+                    if let Some(mount) = mount {
+                        let fs: Box<dyn FileSystem> = match mount {
+                            ImplicitMount::Sftp(target) => Box::new(SftpFileSystem::new(target)),
+                            ImplicitMount::Archive(archive) => Box::new(ArchiveFileSystem::open(&archive)?),
+                        };
+                        mounts.insert(prefix.clone(), Arc::new(Mutex::new(fs)));
+                    }
+                    let fs = mounts.get(&prefix).expect("mounted above").clone();
+                    Some((prefix, inner, fs))
+                }
+            }
+        };
+        let Some((prefix, inner, fs)) = routed else {
+            return Ok((String::new(), f(&mut LocalFileSystem, Path::new(path))?));
+        };
+        let result = f(fs.lock().unwrap().as_mut(), &inner)?;
+        Ok((prefix, result))
+    }
+
+    pub fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.with_fs(path, |fs, inner| fs.read(inner)).map(|(_, data)| data)
+    }
+
+    pub fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.with_fs(path, |fs, inner| fs.write(inner, data)).map(|_| ())
+    }
+
+    /// Deletes a file, or a directory and everything in it.
+    pub fn remove(&self, path: &str) -> io::Result<()> {
+        self.with_fs(path, |fs, inner| fs.remove(inner)).map(|_| ())
+    }
+
+    /// Renames within one filesystem; moving between them is a copy.
+    pub fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let (from_mount, to_mount) = {
+            let mounts = self.mounts.lock().unwrap();
+            (route(&mounts, from), route(&mounts, to))
+        };
+        let to = match (from_mount, to_mount) {
+            (None, None) => PathBuf::from(to),
+            (Some((from_prefix, _, _)), Some((to_prefix, inner, _))) if from_prefix == to_prefix => inner,
+            _ => return Err(io::Error::other("cannot rename across filesystems")),
+        };
+        self.with_fs(from, |fs, inner| fs.rename(inner, &to)).map(|_| ())
+    }
+
+    /// Copies a file, which may be on another filesystem.
+    pub fn copy(&self, from: &str, to: &str) -> io::Result<()> {
+        let data = self.read(from)?;
+        self.write(to, &data)
+    }
+
+    /// What is directly inside `path`, as paths that can be passed back in.
+    pub fn entries(&self, path: &str) -> io::Result<Vec<DirEntry>> {
+        let (prefix, entries) = self.with_fs(path, |fs, inner| fs.entries(inner))?;
+        if prefix.is_empty() {
+            return Ok(entries);
+        }
+        let prefix = prefix.trim_end_matches('/');
+        Ok(entries
+            .into_iter()
+            .map(|entry| DirEntry { path: PathBuf::from(format!("{}{}", prefix, entry.path.display())), is_dir: entry.is_dir })
+            .collect())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::mpsc::{channel, Receiver, Sender};
 
     #[test]
     fn test_in_memory_file_system() {
@@ -58,4 +262,93 @@ mod tests {
         assert_eq!(fs.read(path).unwrap(), data);
         assert_eq!(fs.list(Path::new("/")).unwrap(), vec![path.to_path_buf()]);
     }
+
+    #[test]
+    fn test_mount_table_routes_paths() {
+        let mut memory = InMemoryFileSystem::new();
+        memory.write(Path::new("/notes/today.md"), b"# Today").unwrap();
+        memory.write(Path::new("/todo.txt"), b"ship it").unwrap();
+        let table = MountTable::new();
+        table.mount("mem:", Box::new(memory));
+
+        assert!(table.is_mounted("mem:/todo.txt"));
+        assert!(table.is_mounted("sftp://deploy@web-1:2222/var/log"));
+        assert!(table.is_mounted("release.tar.gz!/bin/"));
+        assert!(!table.is_mounted("src/main.rs"));
+        assert!(is_under("sftp://web/etc", "sftp://web"));
+        assert!(!is_under("sftp://web-2/etc", "sftp://web"));
+
+        assert_eq!(table.read("mem:/todo.txt").unwrap(), b"ship it");
+        table.write("mem:/notes/tomorrow.md", b"").unwrap();
+        let entries = table.entries("mem:/").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                DirEntry { path: PathBuf::from("mem:/notes"), is_dir: true },
+                DirEntry { path: PathBuf::from("mem:/todo.txt"), is_dir: false },
+            ]
+        );
+        let notes: Vec<PathBuf> = table.entries("mem:/notes").unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(notes, vec![PathBuf::from("mem:/notes/today.md"), PathBuf::from("mem:/notes/tomorrow.md")]);
+
+        table.copy("mem:/todo.txt", "mem:/notes/todo.txt").unwrap();
+        table.rename("mem:/notes", "mem:/archive").unwrap();
+        table.remove("mem:/todo.txt").unwrap();
+        let names: Vec<PathBuf> = table.entries("mem:/archive").unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(table.read("mem:/archive/todo.txt").unwrap(), b"ship it");
+        assert!(table.read("mem:/todo.txt").is_err());
+        assert!(table.rename("mem:/archive", "/tmp/archive").is_err());
+
+        assert!(table.unmount("mem:"));
+        assert!(!table.is_mounted("mem:/todo.txt"));
+    }
+
+    /// Lists nothing until told to, like a host that is slow to answer.
+    struct SlowFileSystem {
+        started: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl FileSystem for SlowFileSystem {
+        fn read(&self, _: &Path) -> io::Result<Vec<u8>> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+        fn write(&mut self, _: &Path, _: &[u8]) -> io::Result<()> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+        fn entries(&self, _: &Path) -> io::Result<Vec<DirEntry>> {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            Ok(Vec::new())
+        }
+        fn remove(&mut self, _: &Path) -> io::Result<()> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+        fn rename(&mut self, _: &Path, _: &Path) -> io::Result<()> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    #[test]
+    fn test_slow_mount_does_not_block_the_table() {
+        let (started, started_rx) = channel();
+        let (release_tx, release) = channel();
+        let table = Arc::new(MountTable::new());
+        table.mount("slow:", Box::new(SlowFileSystem { started: Mutex::new(started), release: Mutex::new(release) }));
+        table.mount("mem:", Box::new(InMemoryFileSystem::new()));
+
+        let listing = {
+            let table = table.clone();
+            std::thread::spawn(move || table.entries("slow:/"))
+        };
+        started_rx.recv().unwrap();
+        // The slow listing is in progress; other mounts still answer
+        assert!(table.is_mounted("slow:/etc"));
+        table.write("mem:/notes.txt", b"ok").unwrap();
+        assert_eq!(table.read("mem:/notes.txt").unwrap(), b"ok");
+
+        release_tx.send(()).unwrap();
+        assert!(listing.join().unwrap().unwrap().is_empty());
+    }
 }
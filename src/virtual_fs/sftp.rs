@@ -0,0 +1,189 @@
+//! Remote files over SFTP.
+//!
+//! Drives the system `sftp` client in batch mode, so hosts, keys and jump
+//! hosts come from the user's ssh config and agent. Batch mode never
+//! prompts; a host that needs a password fails instead of hanging.
+//! Directory listings are cached briefly, since completions list the same
+//! directory on every keystroke.
+
+use super::{DirEntry, FileSystem};
+use crate::ssh::SshTarget;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a directory listing is reused.
+const LISTING_TTL: Duration = Duration::from_secs(10);
+
+/// Reads `[user@]host[:port]`, as in `sftp://deploy@web-1:2222/`. The
+/// authority comes from what the user is typing, so one that `sftp` would
+/// take for an option is refused.
+pub fn parse_authority(authority: &str) -> Option<SshTarget> {
+    let (user, host_port) = match authority.rsplit_once('@') {
+        Some((user, rest)) => (Some(user.to_string()).filter(|user| !user.is_empty()), rest),
+        None => (None, authority),
+    };
+    let (host, port) = match host_port.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port.parse().ok()?)),
+        None => (host_port, None),
+    };
+    if host.is_empty() || host.starts_with('-') || user.as_ref().is_some_and(|user| user.starts_with('-')) {
+        return None;
+    }
+    Some(SshTarget { user, host: host.to_string(), port })
+}
+
+/// Quotes a path for an `sftp` batch command.
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy().replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads the entries of `dir` from the output of `ls -la`. The name is
+/// whatever follows the eight columns before it, so names with spaces
+/// survive.
+pub fn parse_listing(dir: &Path, output: &str) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    for line in output.lines() {
+        if line.starts_with("sftp>") {
+            continue;
+        }
+        let mut rest = line.trim_start();
+        for _ in 0..8 {
+            rest = rest.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start());
+        }
+        // Some servers list full paths rather than names. `.` and `..` have
+        // no file name, so they are skipped here too.
+        let Some(name) = Path::new(rest).file_name() else {
+            continue;
+        };
+        entries.push(DirEntry { path: dir.join(name), is_dir: line.starts_with('d') });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+pub struct SftpFileSystem {
+    target: SshTarget,
+    listings: Mutex<HashMap<PathBuf, (Instant, Vec<DirEntry>)>>,
+}
+
+impl SftpFileSystem {
+    pub fn new(target: SshTarget) -> Self {
+        Self { target, listings: Mutex::new(HashMap::new()) }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("sftp");
+        command.args(["-q", "-o", "BatchMode=yes", "-o", "ConnectTimeout=5"]);
+        if let Some(port) = self.target.port {
+            command.arg("-P").arg(port.to_string());
+        }
+        let destination = match &self.target.user {
+            Some(user) => format!("{}@{}", user, self.target.host),
+            None => self.target.host.clone(),
+        };
+        command.args(["-b", "-", "--", &destination]);
+        command
+    }
+
+    /// Runs a batch script, returning its output. `sftp -b` stops at the
+    /// first command that fails.
+    fn run(&self, script: &str) -> io::Result<String> {
+        let mut child = self.command().stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        child.stdin.take().expect("stdin is piped").write_all(script.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn temp_file() -> PathBuf {
+        std::env::temp_dir().join(format!("warpish-sftp-{}", uuid::Uuid::new_v4()))
+    }
+}
+
+impl FileSystem for SftpFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let local = Self::temp_file();
+        let result = self.run(&format!("get {} {}\n", quote(path), quote(&local))).and_then(|_| fs::read(&local));
+        let _ = fs::remove_file(&local);
+        result
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let local = Self::temp_file();
+        fs::write(&local, data)?;
+        let result = self.run(&format!("put {} {}\n", quote(&local), quote(path)));
+        let _ = fs::remove_file(&local);
+        self.listings.lock().unwrap().clear();
+        result.map(|_| ())
+    }
+
+    /// `sftp` has no recursive delete, so only empty directories can be
+    /// removed.
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        let result = self.run(&format!("rm {}\n", quote(path))).or_else(|_| self.run(&format!("rmdir {}\n", quote(path))));
+        self.listings.lock().unwrap().clear();
+        result.map(|_| ())
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let result = self.run(&format!("rename {} {}\n", quote(from), quote(to)));
+        self.listings.lock().unwrap().clear();
+        result.map(|_| ())
+    }
+
+    fn entries(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let cached = self.listings.lock().unwrap().get(path).filter(|(listed, _)| listed.elapsed() < LISTING_TTL).map(|(_, entries)| entries.clone());
+        if let Some(entries) = cached {
+            return Ok(entries);
+        }
+        let entries = parse_listing(path, &self.run(&format!("ls -la {}\n", quote(path)))?);
+        self.listings.lock().unwrap().insert(path.to_path_buf(), (Instant::now(), entries.clone()));
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authority_is_parsed() {
+        assert_eq!(
+            parse_authority("deploy@web-1:2222"),
+            Some(SshTarget { user: Some("deploy".to_string()), host: "web-1".to_string(), port: Some(2222) })
+        );
+        assert_eq!(parse_authority("web-1"), Some(SshTarget { user: None, host: "web-1".to_string(), port: None }));
+        assert_eq!(parse_authority("web-1:ssh"), None);
+        assert_eq!(parse_authority("deploy@"), None);
+        assert_eq!(parse_authority("-oProxyCommand=sh"), None);
+        assert_eq!(parse_authority("-F@web-1"), None);
+    }
+
+    #[test]
+    fn test_listing_is_parsed() {
+        let output = concat!(
+            "sftp> ls -la \"/var/log\"\n",
+            "drwxr-xr-x   10 root     root         4096 Oct  1 09:12 .\n",
+            "drwxr-xr-x   13 root     root         4096 Sep  3 11:40 ..\n",
+            "drwxr-x---    2 root     adm          4096 Oct  1 00:00 apache2\n",
+            "-rw-r-----    1 syslog   adm        183720 Oct  1 09:12 /var/log/syslog\n",
+            "-rw-r--r--    1 root     root          812 Jan 12  2024 old notes.txt\n",
+        );
+        let dir = Path::new("/var/log");
+        assert_eq!(
+            parse_listing(dir, output),
+            vec![
+                DirEntry { path: dir.join("apache2"), is_dir: true },
+                DirEntry { path: dir.join("old notes.txt"), is_dir: false },
+                DirEntry { path: dir.join("syslog"), is_dir: false },
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
 //! File System Monitoring and Change Detection
 //!
 //! This module provides file system monitoring and change detection using the `notify` crate.
+//! Commands can be re-run on changes; see [`watch`].
+
+pub mod watch;
 
 use notify::{RecommendedWatcher, RecursiveMode, Watcher as NotifyWatcher};
 use std::path::Path;
@@ -13,6 +16,8 @@ pub enum WatcherError {
     CreateFailed(notify::Error),
     #[error("Failed to watch path: {0}")]
     WatchFailed(notify::Error),
+    #[error("Invalid glob: {0}")]
+    Pattern(#[from] glob::PatternError),
 }
 
 pub struct FileWatcher {
@@ -0,0 +1,135 @@
+//! Watch Mode
+//!
+//! Re-runs a block's command in its pane whenever files matching a glob
+//! under the pane's directory change. Changes are debounced: the command
+//! runs once matching files have been quiet for the debounce interval, so
+//! saving several files at once, or an editor writing a swap file before the
+//! real one, gives one run rather than a burst. Files in hidden directories
+//! such as `.git` never match, and changes made while the command is still
+//! running are dropped, since they are usually its own output.
+
+use super::{FileWatcher, WatcherError};
+use glob::Pattern;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// A command re-run on file changes in one pane.
+pub struct CommandWatch {
+    pub pane_id: Uuid,
+    pub command: String,
+    pub root: PathBuf,
+    pub glob: String,
+    /// How many times the command has been re-run.
+    pub runs: usize,
+    pattern: Pattern,
+    debounce: Duration,
+    watcher: FileWatcher,
+    /// The latest matching change not yet acted on.
+    last_change: Option<Instant>,
+}
+
+impl CommandWatch {
+    pub fn new(pane_id: Uuid, command: &str, root: &Path, glob: &str, debounce: Duration) -> Result<Self, WatcherError> {
+        let pattern = Pattern::new(glob)?;
+        // Events carry canonical paths, such as /private/var for /var on macOS.
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let mut watcher = FileWatcher::new()?;
+        watcher.watch(&root)?;
+        Ok(Self {
+            pane_id,
+            command: command.to_string(),
+            root,
+            glob: glob.to_string(),
+            runs: 0,
+            pattern,
+            debounce,
+            watcher,
+            last_change: None,
+        })
+    }
+
+    /// Whether a change to `path` should re-run the command.
+    pub fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let hidden = relative
+            .components()
+            .any(|component| matches!(component, Component::Normal(name) if name.to_string_lossy().starts_with('.')));
+        !hidden && self.pattern.matches_path(relative)
+    }
+
+    /// Notes a change to `path` at `now`.
+    pub fn record(&mut self, path: &Path, now: Instant) {
+        if self.matches(path) {
+            self.last_change = Some(now);
+        }
+    }
+
+    /// Whether the command should run now, counting the run if so.
+    pub fn due(&mut self, now: Instant) -> bool {
+        match self.last_change {
+            Some(changed) if now.duration_since(changed) >= self.debounce => {
+                self.last_change = None;
+                self.runs += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes the file events that arrived since the last poll and returns
+    /// whether the command should run now.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let paths: Vec<PathBuf> = self.watcher.events().try_iter().flatten().flat_map(|event| event.paths).collect();
+        for path in paths {
+            self.record(&path, now);
+        }
+        self.due(now)
+    }
+
+    /// Drops the changes seen so far, while the command is running.
+    pub fn discard(&mut self) {
+        self.watcher.events().try_iter().for_each(drop);
+        self.last_change = None;
+    }
+
+    /// The prompt chip for the watched pane.
+    pub fn chip(&self) -> String {
+        format!(" ↻ {} ({} run(s)) ", self.glob, self.runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes_are_filtered_and_debounced() {
+        let dir = tempfile::tempdir().unwrap();
+        let debounce = Duration::from_millis(300);
+        let mut watch = CommandWatch::new(Uuid::new_v4(), "cargo test", dir.path(), "**/*.rs", debounce).unwrap();
+        let root = watch.root.clone();
+        assert!(watch.matches(&root.join("src/main.rs")));
+        assert!(watch.matches(&root.join("lib.rs")));
+        assert!(!watch.matches(&root.join("Cargo.toml")));
+        assert!(!watch.matches(&root.join(".git/hooks/pre-commit.rs")));
+        assert!(!watch.matches(Path::new("/elsewhere/main.rs")));
+
+        let start = Instant::now();
+        watch.record(&root.join("README.md"), start);
+        assert!(!watch.due(start + debounce));
+        // A burst of saves runs once, after the last one settles.
+        watch.record(&root.join("src/a.rs"), start);
+        watch.record(&root.join("src/b.rs"), start + Duration::from_millis(200));
+        assert!(!watch.due(start + Duration::from_millis(400)));
+        assert!(watch.due(start + Duration::from_millis(500)));
+        assert!(!watch.due(start + Duration::from_secs(5)));
+        assert_eq!(watch.runs, 1);
+
+        watch.record(&root.join("src/a.rs"), start);
+        watch.discard();
+        assert!(!watch.due(start + Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,356 @@
+//! Webhook Triggers
+//!
+//! This module provides an opt-in localhost webhook listener so that external
+//! tools and CI jobs can trigger configured workflows or post notifications
+//! into the terminal. Requests must carry the local token as a bearer token,
+//! and workflow commands are vetted like agent commands, by the configured
+//! rules and by their argv, before they are handed to the application.
+//!
+//! Routes:
+//! - `POST /hooks/workflow/<name>` with `{"arguments": {"name": "value"}}`
+//! - `POST /hooks/notify` with `{"title": "...", "message": "..."}`
+
+use crate::agent::tools::{default_command_rules, vet_command};
+use crate::config::WebhooksConfig;
+use crate::drive::{DriveError, Workflow};
+use crate::event::AppEvent;
+use crate::rules::{Action, RuleSet};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::mpsc::UnboundedSender;
+use warp::Filter;
+
+/// Largest request body the listener accepts.
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("Missing or invalid webhook token")]
+    Unauthorized,
+    #[error("Unknown webhook route: {0}")]
+    NotFound(String),
+    #[error("Workflow '{0}' is not enabled for webhooks")]
+    WorkflowNotAllowed(String),
+    #[error("Command blocked by rules: {0}")]
+    Blocked(String),
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+    #[error("Workflow error: {0}")]
+    Workflow(#[from] DriveError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl WebhookError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            WebhookError::Unauthorized => 401,
+            WebhookError::NotFound(_) => 404,
+            WebhookError::WorkflowNotAllowed(_) | WebhookError::Blocked(_) => 403,
+            WebhookError::InvalidPayload(_) | WebhookError::Workflow(_) => 400,
+            WebhookError::Io(_) => 500,
+        }
+    }
+}
+
+/// An accepted webhook request, delivered to the app as `AppEvent::Webhook`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookEvent {
+    RunWorkflow {
+        name: String,
+        command: String,
+        /// Set when a `Warn` rule matched; the app should confirm before running.
+        needs_confirmation: bool,
+    },
+    Notify {
+        title: String,
+        message: String,
+    },
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct WorkflowPayload {
+    #[serde(default)]
+    arguments: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotifyPayload {
+    #[serde(default = "default_notification_title")]
+    title: String,
+    message: String,
+}
+
+fn default_notification_title() -> String {
+    "Webhook".to_string()
+}
+
+/// Validates and interprets webhook requests independently of the HTTP layer.
+pub struct WebhookHandler {
+    token: String,
+    allowed_workflows: Vec<String>,
+    workflows: Vec<Workflow>,
+    rules: RuleSet,
+}
+
+impl WebhookHandler {
+    pub fn new(token: String, allowed_workflows: Vec<String>, workflows: Vec<Workflow>, rules: RuleSet) -> Self {
+        Self {
+            token,
+            allowed_workflows,
+            workflows,
+            rules,
+        }
+    }
+
+    /// Handles a request to `/hooks/<path>` with the given `Authorization` header.
+    pub fn handle(&self, path: &str, authorization: Option<&str>, body: &[u8]) -> Result<WebhookEvent, WebhookError> {
+        if !self.is_authorized(authorization) {
+            return Err(WebhookError::Unauthorized);
+        }
+
+        let path = path.trim_matches('/');
+        if path == "notify" {
+            let payload: NotifyPayload = serde_json::from_slice(body)?;
+            return Ok(WebhookEvent::Notify {
+                title: payload.title,
+                message: payload.message,
+            });
+        }
+
+        let name = path
+            .strip_prefix("workflow/")
+            .ok_or_else(|| WebhookError::NotFound(path.to_string()))?;
+        if !self.allowed_workflows.iter().any(|allowed| allowed == name) {
+            return Err(WebhookError::WorkflowNotAllowed(name.to_string()));
+        }
+        let workflow = self
+            .workflows
+            .iter()
+            .find(|w| w.name == name)
+            .ok_or_else(|| WebhookError::NotFound(path.to_string()))?;
+
+        let payload: WorkflowPayload = if body.is_empty() {
+            WorkflowPayload::default()
+        } else {
+            serde_json::from_slice(body)?
+        };
+        let command = workflow.render_command(&payload.arguments)?;
+
+        let needs_confirmation = match vet_command(&self.rules, &command) {
+            Action::Deny => return Err(WebhookError::Blocked(command)),
+            Action::Warn => true,
+            Action::Allow => false,
+        };
+
+        Ok(WebhookEvent::RunWorkflow {
+            name: name.to_string(),
+            command,
+            needs_confirmation,
+        })
+    }
+
+    fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(presented) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        // Compare without short-circuiting so timing does not leak the token.
+        let expected = self.token.as_bytes();
+        let presented = presented.trim().as_bytes();
+        expected.len() == presented.len()
+            && expected
+                .iter()
+                .zip(presented)
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// Returns the configured token, or the one stored in the config directory,
+/// generating and saving a new one on first use.
+pub fn load_or_create_token(config: &WebhooksConfig) -> Result<String, WebhookError> {
+    if let Some(token) = &config.token {
+        return Ok(token.clone());
+    }
+    let path = token_path();
+    if let Ok(token) = std::fs::read_to_string(&path) {
+        let token = token.trim().to_string();
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // A blank token file is replaced rather than rewritten, so the new token
+    // only ever lands in a file created readable by the user alone.
+    if path.exists() {
+        std::fs::remove_file(&path)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+fn token_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("warpish")
+        .join("webhook_token")
+}
+
+/// Starts the listener on the current tokio runtime if webhooks are enabled.
+/// Commands are vetted by the built-in command rules and the configured ones.
+pub fn spawn_listener(
+    config: &WebhooksConfig,
+    workflows: Vec<Workflow>,
+    events: UnboundedSender<AppEvent>,
+) -> Result<Option<tokio::task::JoinHandle<()>>, WebhookError> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let token = load_or_create_token(config)?;
+    let mut rules = default_command_rules();
+    for rule in &config.command_rules {
+        rules.add_rule(rule.clone());
+    }
+    let handler = Arc::new(WebhookHandler::new(token, config.allowed_workflows.clone(), workflows, rules));
+    Ok(Some(tokio::spawn(serve(handler, config.port, events))))
+}
+
+/// Runs the webhook listener on localhost, forwarding accepted requests as app events.
+pub async fn serve(handler: Arc<WebhookHandler>, port: u16, events: UnboundedSender<AppEvent>) {
+    let routes = warp::post()
+        .and(warp::path("hooks"))
+        .and(warp::path::tail())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::bytes())
+        .map(move |tail: warp::path::Tail, authorization: Option<String>, body: warp::hyper::body::Bytes| {
+            match handler.handle(tail.as_str(), authorization.as_deref(), &body) {
+                Ok(event) => {
+                    log::info!("Accepted webhook: {:?}", event);
+                    events.send(AppEvent::Webhook(event)).ok();
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "status": "accepted" })),
+                        warp::http::StatusCode::ACCEPTED,
+                    )
+                }
+                Err(e) => {
+                    log::warn!("Rejected webhook '{}': {}", tail.as_str(), e);
+                    let status = warp::http::StatusCode::from_u16(e.status_code())
+                        .unwrap_or(warp::http::StatusCode::BAD_REQUEST);
+                    warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": e.to_string() })), status)
+                }
+            }
+        });
+
+    log::info!("[Webhooks] Listening on 127.0.0.1:{}", port);
+    warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drive::Argument;
+    use crate::rules::Rule;
+
+    fn workflow(name: &str, command: &str, argument: &str, default_value: &str) -> Workflow {
+        Workflow {
+            name: name.to_string(),
+            command: command.to_string(),
+            tags: vec![],
+            description: name.to_string(),
+            arguments: vec![Argument {
+                name: argument.to_string(),
+                description: "Target".to_string(),
+                default_value: Some(default_value.to_string()),
+                ..Argument::default()
+            }],
+            source_url: None,
+            author_url: None,
+            shells: vec![],
+        }
+    }
+
+    fn handler() -> WebhookHandler {
+        let workflows = vec![
+            workflow("deploy", "./deploy.sh {{env}}", "env", "staging"),
+            workflow("clean", "rm -rf {{dir}}", "dir", "/tmp/build"),
+        ];
+        let mut rules = RuleSet::new();
+        rules.add_rule(Rule {
+            name: "no_prod".to_string(),
+            description: "Production deploys are manual".to_string(),
+            action: Action::Deny,
+            target: "production".to_string(),
+        });
+        WebhookHandler::new("secret".to_string(), vec!["deploy".to_string(), "clean".to_string()], workflows, rules)
+    }
+
+    #[test]
+    fn test_requires_token() {
+        let handler = handler();
+        assert!(matches!(handler.handle("notify", None, b"{}"), Err(WebhookError::Unauthorized)));
+        assert!(matches!(
+            handler.handle("notify", Some("Bearer wrong!"), b"{}"),
+            Err(WebhookError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_workflow_trigger_renders_and_checks_rules() {
+        let handler = handler();
+        let auth = Some("Bearer secret");
+        assert_eq!(
+            handler.handle("/workflow/deploy", auth, b"").unwrap(),
+            WebhookEvent::RunWorkflow {
+                name: "deploy".to_string(),
+                command: "./deploy.sh staging".to_string(),
+                needs_confirmation: false,
+            }
+        );
+        let blocked = handler.handle("workflow/deploy", auth, br#"{"arguments":{"env":"production"}}"#);
+        assert!(matches!(blocked, Err(WebhookError::Blocked(_))));
+        let not_allowed = handler.handle("workflow/other", auth, b"");
+        assert_eq!(not_allowed.unwrap_err().status_code(), 403);
+    }
+
+    #[test]
+    fn test_workflow_trigger_vets_argv() {
+        let handler = handler();
+        let auth = Some("Bearer secret");
+        assert!(matches!(
+            handler.handle("workflow/clean", auth, b""),
+            Ok(WebhookEvent::RunWorkflow { needs_confirmation: true, .. })
+        ));
+        let blocked = handler.handle("workflow/clean", auth, br#"{"arguments":{"dir":"/"}}"#);
+        assert!(matches!(blocked, Err(WebhookError::Blocked(_))));
+    }
+
+    #[test]
+    fn test_notify() {
+        let event = handler()
+            .handle("notify", Some("Bearer secret"), br#"{"message":"CI passed"}"#)
+            .unwrap();
+        assert_eq!(
+            event,
+            WebhookEvent::Notify {
+                title: "Webhook".to_string(),
+                message: "CI passed".to_string(),
+            }
+        );
+    }
+}
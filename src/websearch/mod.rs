@@ -0,0 +1,298 @@
+//! Web Search
+//!
+//! "Search Web for Selection" looks selected text up with one of the
+//! configured providers. Most providers are URL templates opened in the
+//! browser — Google, DuckDuckGo or an internal docs site. A provider with a
+//! search API and an API key in the keychain is queried over HTTP instead,
+//! and its results are listed inline. Selections pass through the share
+//! redactor first, so secrets in terminal output never reach a search engine.
+
+use crate::config::{SearchApiConfig, SearchProviderConfig, WebSearchConfig};
+use crate::keychain::{Keychain, KeychainError};
+use crate::share::{Redactor, ShareError};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+const GOOGLE_API_URL: &str = "https://www.googleapis.com/customsearch/v1";
+const BRAVE_API_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Search engines ignore most of a longer query.
+const MAX_QUERY_CHARS: usize = 200;
+
+#[derive(Error, Debug)]
+pub enum WebSearchError {
+    #[error("Unknown search provider '{0}'")]
+    UnknownProvider(String),
+    #[error("Nothing to search for")]
+    EmptyQuery,
+    #[error("Missing credential: {0}")]
+    Credential(#[from] KeychainError),
+    #[error("Invalid redaction pattern: {0}")]
+    Redaction(#[from] ShareError),
+    #[error("Search request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Search failed: {0}")]
+    Api(String),
+    #[error("Failed to open the browser: {0}")]
+    Browser(#[from] std::io::Error),
+    #[error("Search was cancelled")]
+    Cancelled,
+}
+
+/// A result listed inline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// Collapses the whitespace of a selection that spans lines, and shortens it.
+pub fn prepare_query(selection: &str) -> String {
+    let query = selection.split_whitespace().collect::<Vec<_>>().join(" ");
+    query.chars().take(MAX_QUERY_CHARS).collect()
+}
+
+/// The provider's page for `query`.
+pub fn search_url(template: &str, query: &str) -> String {
+    template.replace("{query}", &utf8_percent_encode(query, NON_ALPHANUMERIC).to_string())
+}
+
+#[derive(Deserialize)]
+struct GoogleResponse {
+    #[serde(default)]
+    items: Vec<GoogleItem>,
+}
+
+#[derive(Deserialize)]
+struct GoogleItem {
+    title: String,
+    link: String,
+    #[serde(default)]
+    snippet: String,
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    #[serde(default)]
+    web: Option<BraveWeb>,
+}
+
+#[derive(Deserialize)]
+struct BraveWeb {
+    #[serde(default)]
+    results: Vec<BraveItem>,
+}
+
+#[derive(Deserialize)]
+struct BraveItem {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+}
+
+fn parse_results(api: &SearchApiConfig, body: &str) -> Result<Vec<WebResult>, WebSearchError> {
+    let invalid = |e: serde_json::Error| WebSearchError::Api(format!("unexpected response: {}", e));
+    let results = match api {
+        SearchApiConfig::GoogleCustomSearch { .. } => serde_json::from_str::<GoogleResponse>(body)
+            .map_err(invalid)?
+            .items
+            .into_iter()
+            .map(|item| WebResult { title: item.title, url: item.link, snippet: item.snippet })
+            .collect(),
+        SearchApiConfig::Brave => serde_json::from_str::<BraveResponse>(body)
+            .map_err(invalid)?
+            .web
+            .map(|web| web.results)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| WebResult { title: item.title, url: item.url, snippet: strip_tags(&item.description) })
+            .collect(),
+    };
+    Ok(results)
+}
+
+/// Brave marks matches in snippets with `<strong>`.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+async fn fetch(api: &SearchApiConfig, key: &str, query: &str) -> Result<Vec<WebResult>, WebSearchError> {
+    let client = reqwest::Client::builder().timeout(SEARCH_TIMEOUT).build()?;
+    let request = match api {
+        SearchApiConfig::GoogleCustomSearch { engine_id } => {
+            client.get(GOOGLE_API_URL).query(&[("key", key), ("cx", engine_id.as_str()), ("q", query)])
+        }
+        SearchApiConfig::Brave => client
+            .get(BRAVE_API_URL)
+            .query(&[("q", query)])
+            .header("Accept", "application/json")
+            .header("X-Subscription-Token", key),
+    };
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(WebSearchError::Api(format!("{} {}", status, body.trim())));
+    }
+    parse_results(api, &body)
+}
+
+/// What a search did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStarted {
+    /// The provider's page was opened in the browser.
+    InBrowser,
+    /// Results are being fetched; they arrive through `poll`.
+    Inline,
+}
+
+/// Runs searches, fetching inline results on background threads.
+pub struct WebSearch {
+    providers: Vec<SearchProviderConfig>,
+    redaction_patterns: Vec<String>,
+    result_tx: Sender<(String, Result<Vec<WebResult>, String>)>,
+    results: Receiver<(String, Result<Vec<WebResult>, String>)>,
+    cancel: CancellationToken,
+}
+
+impl WebSearch {
+    /// `redaction_patterns` are the share redactor's extra patterns.
+    pub fn new(config: &WebSearchConfig, redaction_patterns: &[String]) -> Self {
+        let mut providers = config.providers.clone();
+        if let Some(default) = &config.default_provider {
+            providers.sort_by_key(|provider| &provider.name != default);
+        }
+        let (result_tx, results) = channel();
+        Self {
+            providers,
+            redaction_patterns: redaction_patterns.to_vec(),
+            result_tx,
+            results,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Abandons inline searches still in flight once `cancel` is cancelled.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// The providers, the default one first.
+    pub fn providers(&self) -> &[SearchProviderConfig] {
+        &self.providers
+    }
+
+    /// The query sent for `selection`: redacted, on one line and shortened.
+    pub fn query_for(&self, selection: &str) -> Result<String, WebSearchError> {
+        let (redacted, _) = Redactor::new(&self.redaction_patterns)?.redact(selection);
+        let query = prepare_query(&redacted);
+        if query.is_empty() {
+            return Err(WebSearchError::EmptyQuery);
+        }
+        Ok(query)
+    }
+
+    /// Searches `query` with the provider `name`. Providers with an API and
+    /// a stored key fetch inline; the rest open in the browser.
+    pub fn search(&self, name: &str, query: &str) -> Result<SearchStarted, WebSearchError> {
+        let provider = self
+            .providers
+            .iter()
+            .find(|provider| provider.name == name)
+            .ok_or_else(|| WebSearchError::UnknownProvider(name.to_string()))?;
+        let key = match (&provider.api, &provider.credential) {
+            (Some(_), Some(credential)) => match Keychain::new().get(credential) {
+                Ok(key) => Some(key),
+                Err(KeychainError::NotFound(_)) => None,
+                Err(e) => return Err(e.into()),
+            },
+            _ => None,
+        };
+        let (Some(api), Some(key)) = (provider.api.clone(), key) else {
+            webbrowser::open(&search_url(&provider.url, query))?;
+            return Ok(SearchStarted::InBrowser);
+        };
+        let query = query.to_string();
+        let result_tx = self.result_tx.clone();
+        let cancel = self.cancel.clone();
+        thread::spawn(move || {
+            let result = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime
+                    .block_on(async {
+                        tokio::select! {
+                            results = fetch(&api, &key, &query) => results,
+                            _ = cancel.cancelled() => Err(WebSearchError::Cancelled),
+                        }
+                    })
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            result_tx.send((query, result)).ok();
+        });
+        Ok(SearchStarted::Inline)
+    }
+
+    /// Takes finished inline searches, with the query each was for.
+    pub fn poll(&self) -> Vec<(String, Result<Vec<WebResult>, String>)> {
+        self.results.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_are_redacted_and_encoded() {
+        let mut config = WebSearchConfig::default();
+        config.providers.push(SearchProviderConfig {
+            name: "docs".to_string(),
+            url: "https://docs.example.com/search?q={query}&lang=en".to_string(),
+            api: None,
+            credential: None,
+        });
+        config.default_provider = Some("docs".to_string());
+        let search = WebSearch::new(&config, &[]);
+        assert_eq!(search.providers()[0].name, "docs");
+
+        let query = search.query_for("error: token=ghp_abc123\n   connection refused (os error 111)").unwrap();
+        assert_eq!(query, "error: token=[REDACTED] connection refused (os error 111)");
+        assert_eq!(
+            search_url(&search.providers()[0].url, "E0425 cannot find"),
+            "https://docs.example.com/search?q=E0425%20cannot%20find&lang=en"
+        );
+        assert!(matches!(search.query_for(" \n "), Err(WebSearchError::EmptyQuery)));
+    }
+
+    #[test]
+    fn test_parses_api_results() {
+        let google = r#"{"kind":"customsearch#search","items":[{"title":"E0425 - Error codes index","link":"https://doc.rust-lang.org/error_codes/E0425.html","snippet":"An unresolved name was used."}]}"#;
+        let brave = r#"{"type":"search","web":{"results":[{"title":"Rust E0425","url":"https://example.com/e0425","description":"<strong>E0425</strong> means an unresolved name"}]}}"#;
+        let google_api = SearchApiConfig::GoogleCustomSearch { engine_id: "abc".to_string() };
+
+        let results = parse_results(&google_api, google).unwrap();
+        assert_eq!(results[0].url, "https://doc.rust-lang.org/error_codes/E0425.html");
+        let results = parse_results(&SearchApiConfig::Brave, brave).unwrap();
+        assert_eq!(results[0].snippet, "E0425 means an unresolved name");
+        // No matches leaves the result list out entirely.
+        assert!(parse_results(&google_api, r#"{"kind":"customsearch#search"}"#).unwrap().is_empty());
+    }
+}
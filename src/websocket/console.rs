@@ -0,0 +1,435 @@
+//! WebSocket Console
+//!
+//! An interactive console for debugging WebSocket servers. A session runs
+//! on its own thread, reconnecting with backoff when the connection drops,
+//! and reports every frame sent and received, pings and pongs included.
+//! Lines typed into the console are sent as text frames, except for the
+//! commands `/binary <hex>`, `/ping [text]`, `/filter [words]` and `/close`.
+//! A line starting with `//` is sent as text without its first slash.
+
+use super::WebSocketClient;
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc as async_mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// The most frames a console keeps for re-filtering.
+const MAX_FRAMES: usize = 1000;
+/// How much of a binary frame is shown as hex.
+const PREVIEW_BYTES: usize = 64;
+const FIRST_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Text,
+    Binary,
+    Ping,
+    Pong,
+    Close,
+}
+
+impl FrameKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            FrameKind::Text => "text",
+            FrameKind::Binary => "binary",
+            FrameKind::Ping => "ping",
+            FrameKind::Pong => "pong",
+            FrameKind::Close => "close",
+        }
+    }
+
+    fn from_label(label: &str) -> Option<Self> {
+        [FrameKind::Text, FrameKind::Binary, FrameKind::Ping, FrameKind::Pong, FrameKind::Close]
+            .into_iter()
+            .find(|kind| kind.label() == label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub at: DateTime<Local>,
+    pub direction: Direction,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+    /// For a pong, how long after the last ping it arrived.
+    pub round_trip: Option<Duration>,
+}
+
+impl Frame {
+    fn from_message(direction: Direction, message: &Message) -> Option<Self> {
+        let (kind, payload) = match message {
+            Message::Text(text) => (FrameKind::Text, text.as_bytes().to_vec()),
+            Message::Binary(data) => (FrameKind::Binary, data.clone()),
+            Message::Ping(data) => (FrameKind::Ping, data.clone()),
+            Message::Pong(data) => (FrameKind::Pong, data.clone()),
+            Message::Close(close) => (FrameKind::Close, close.as_ref().map(|close| close.reason.as_bytes().to_vec()).unwrap_or_default()),
+            Message::Frame(_) => return None,
+        };
+        Some(Self { at: Local::now(), direction, kind, payload, round_trip: None })
+    }
+
+    /// The time, direction, kind and size, e.g. `12:03:04.123 ← text, 12 bytes`.
+    pub fn header(&self) -> String {
+        let arrow = match self.direction {
+            Direction::Incoming => "←",
+            Direction::Outgoing => "→",
+        };
+        let mut header = format!("{} {} {}, {} bytes", self.at.format("%H:%M:%S%.3f"), arrow, self.kind.label(), self.payload.len());
+        if let Some(round_trip) = self.round_trip {
+            header.push_str(&format!(", {} ms", round_trip.as_millis()));
+        }
+        header
+    }
+
+    /// Text payloads as they are, binary ones as hex.
+    pub fn body(&self) -> String {
+        match self.kind {
+            FrameKind::Binary => {
+                let preview: Vec<String> = self.payload.iter().take(PREVIEW_BYTES).map(|byte| format!("{:02x}", byte)).collect();
+                let mut body = preview.join(" ");
+                if self.payload.len() > PREVIEW_BYTES {
+                    body.push_str(&format!(" … ({} more bytes)", self.payload.len() - PREVIEW_BYTES));
+                }
+                body
+            }
+            _ => String::from_utf8_lossy(&self.payload).into_owned(),
+        }
+    }
+}
+
+/// Which frames the console shows, from the words after `/filter`: `in` or
+/// `out`, frame kinds (`control` for ping, pong and close), and any other
+/// words as text the frame must contain, ignoring case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameFilter {
+    direction: Option<Direction>,
+    kinds: Vec<FrameKind>,
+    pattern: Option<String>,
+}
+
+impl FrameFilter {
+    pub fn parse(text: &str) -> Self {
+        let mut filter = Self::default();
+        let mut words = Vec::new();
+        for word in text.split_whitespace() {
+            match word {
+                "in" => filter.direction = Some(Direction::Incoming),
+                "out" => filter.direction = Some(Direction::Outgoing),
+                "control" => filter.kinds.extend([FrameKind::Ping, FrameKind::Pong, FrameKind::Close]),
+                _ => match FrameKind::from_label(word) {
+                    Some(kind) => filter.kinds.push(kind),
+                    None => words.push(word),
+                },
+            }
+        }
+        if !words.is_empty() {
+            filter.pattern = Some(words.join(" ").to_lowercase());
+        }
+        filter
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, frame: &Frame) -> bool {
+        self.direction.is_none_or(|direction| direction == frame.direction)
+            && (self.kinds.is_empty() || self.kinds.contains(&frame.kind))
+            && self.pattern.as_ref().is_none_or(|pattern| frame.body().to_lowercase().contains(pattern))
+    }
+}
+
+/// A line typed into the console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleInput {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Filter(FrameFilter),
+    Close,
+}
+
+impl ConsoleInput {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        if let Some(text) = line.strip_prefix("//") {
+            return Ok(ConsoleInput::Text(format!("/{}", text)));
+        }
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(ConsoleInput::Text(line.to_string()));
+        };
+        let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "binary" => {
+                let digits: String = rest.chars().filter(|c| !c.is_whitespace()).collect();
+                hex::decode(digits).map(ConsoleInput::Binary).map_err(|_| "Expected hex bytes, e.g. `/binary 0a ff 10`".to_string())
+            }
+            "ping" => Ok(ConsoleInput::Ping(rest.as_bytes().to_vec())),
+            "filter" => Ok(ConsoleInput::Filter(FrameFilter::parse(rest))),
+            "close" => Ok(ConsoleInput::Close),
+            _ => Err(format!("Unknown command /{}; use /binary, /ping, /filter or /close, or // to send a leading slash", name)),
+        }
+    }
+}
+
+/// The URL of a `ws <url>` command, which opens a console.
+pub fn console_url(command: &str) -> Option<&str> {
+    let url = command.trim().strip_prefix("ws ")?.trim();
+    (url.starts_with("ws://") || url.starts_with("wss://")).then_some(url)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleEvent {
+    Connected,
+    Frame(Frame),
+    /// The connection failed or dropped; `retry_in` is when the next
+    /// attempt is made, if reconnecting.
+    Disconnected { reason: String, retry_in: Option<Duration> },
+}
+
+enum Command {
+    Send(Message),
+    Close,
+}
+
+enum Ended {
+    Closed,
+    Dropped(String),
+}
+
+async fn session(client: &mut WebSocketClient, commands: &mut async_mpsc::UnboundedReceiver<Command>, events: &Sender<ConsoleEvent>) -> Ended {
+    let mut ping_sent: Option<Instant> = None;
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                None | Some(Command::Close) => {
+                    client.close().await.ok();
+                    return Ended::Closed;
+                }
+                Some(Command::Send(message)) => {
+                    if matches!(message, Message::Ping(_)) {
+                        ping_sent = Some(Instant::now());
+                    }
+                    let frame = Frame::from_message(Direction::Outgoing, &message);
+                    if let Err(e) = client.send_message(message).await {
+                        return Ended::Dropped(e.to_string());
+                    }
+                    if let Some(frame) = frame {
+                        events.send(ConsoleEvent::Frame(frame)).ok();
+                    }
+                }
+            },
+            message = client.recv() => match message {
+                None => return Ended::Dropped("connection closed".to_string()),
+                Some(Err(e)) => return Ended::Dropped(e.to_string()),
+                Some(Ok(message)) => {
+                    if let Some(mut frame) = Frame::from_message(Direction::Incoming, &message) {
+                        if frame.kind == FrameKind::Pong {
+                            frame.round_trip = ping_sent.take().map(|at| at.elapsed());
+                        }
+                        if events.send(ConsoleEvent::Frame(frame)).is_err() {
+                            return Ended::Closed;
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+async fn run(url: String, reconnect: bool, max_delay: Duration, mut commands: async_mpsc::UnboundedReceiver<Command>, events: Sender<ConsoleEvent>) {
+    let mut delay = FIRST_RECONNECT_DELAY;
+    loop {
+        let reason = match WebSocketClient::connect(&url).await {
+            Ok(mut client) => {
+                delay = FIRST_RECONNECT_DELAY;
+                if events.send(ConsoleEvent::Connected).is_err() {
+                    return;
+                }
+                match session(&mut client, &mut commands, &events).await {
+                    Ended::Closed => return,
+                    Ended::Dropped(reason) => reason,
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+        let retry_in = reconnect.then_some(delay);
+        if events.send(ConsoleEvent::Disconnected { reason, retry_in }).is_err() || !reconnect {
+            return;
+        }
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                command = commands.recv() => match command {
+                    None | Some(Command::Close) => return,
+                    // Frames typed while disconnected are dropped.
+                    Some(Command::Send(_)) => {}
+                },
+            }
+        }
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+/// A console's connection and the frames it has seen.
+pub struct WebSocketConsole {
+    pub url: String,
+    pub filter: FrameFilter,
+    frames: VecDeque<Frame>,
+    commands: async_mpsc::UnboundedSender<Command>,
+    events: Receiver<ConsoleEvent>,
+}
+
+impl WebSocketConsole {
+    /// Connects to `url` on a background thread. When `reconnect` is set,
+    /// dropped connections are retried with doubling delays up to
+    /// `max_delay`.
+    pub fn connect(url: &str, reconnect: bool, max_delay: Duration) -> Self {
+        let (commands, command_rx) = async_mpsc::unbounded_channel();
+        let (event_tx, events) = channel();
+        let session_url = url.to_string();
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => runtime,
+                Err(e) => {
+                    log::error!("Failed to start WebSocket console runtime: {}", e);
+                    return;
+                }
+            };
+            runtime.block_on(run(session_url, reconnect, max_delay, command_rx, event_tx));
+        });
+        Self { url: url.to_string(), filter: FrameFilter::default(), frames: VecDeque::new(), commands, events }
+    }
+
+    /// Sends a text, binary or ping frame; other input is handled by the
+    /// caller.
+    pub fn send(&self, input: ConsoleInput) {
+        let message = match input {
+            ConsoleInput::Text(text) => Message::Text(text),
+            ConsoleInput::Binary(data) => Message::Binary(data),
+            ConsoleInput::Ping(data) => Message::Ping(data),
+            ConsoleInput::Filter(_) | ConsoleInput::Close => return,
+        };
+        self.commands.send(Command::Send(message)).ok();
+    }
+
+    pub fn close(&self) {
+        self.commands.send(Command::Close).ok();
+    }
+
+    /// Takes the session's events, keeping their frames for re-filtering.
+    pub fn poll(&mut self) -> Vec<ConsoleEvent> {
+        let events: Vec<ConsoleEvent> = self.events.try_iter().collect();
+        for event in &events {
+            if let ConsoleEvent::Frame(frame) = event {
+                if self.frames.len() == MAX_FRAMES {
+                    self.frames.pop_front();
+                }
+                self.frames.push_back(frame.clone());
+            }
+        }
+        events
+    }
+
+    /// The kept frames the filter lets through, oldest first.
+    pub fn visible_frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().filter(|frame| self.filter.matches(frame))
+    }
+}
+
+impl Drop for WebSocketConsole {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    #[test]
+    fn test_input_and_filters_are_parsed() {
+        assert_eq!(ConsoleInput::parse("hello"), Ok(ConsoleInput::Text("hello".to_string())));
+        assert_eq!(ConsoleInput::parse("//path"), Ok(ConsoleInput::Text("/path".to_string())));
+        assert_eq!(ConsoleInput::parse("/binary 0a ff"), Ok(ConsoleInput::Binary(vec![0x0a, 0xff])));
+        assert!(ConsoleInput::parse("/binary zz").is_err());
+        assert!(ConsoleInput::parse("/unknown").is_err());
+        assert_eq!(console_url("ws wss://echo.example.com/socket"), Some("wss://echo.example.com/socket"));
+        assert_eq!(console_url("ws https://example.com"), None);
+
+        let frame = |direction, kind, payload: &[u8]| Frame { at: Local::now(), direction, kind, payload: payload.to_vec(), round_trip: None };
+        let ConsoleInput::Filter(filter) = ConsoleInput::parse("/filter in text Error").unwrap() else {
+            panic!("expected a filter");
+        };
+        assert!(filter.matches(&frame(Direction::Incoming, FrameKind::Text, b"an error occurred")));
+        assert!(!filter.matches(&frame(Direction::Outgoing, FrameKind::Text, b"an error occurred")));
+        assert!(!filter.matches(&frame(Direction::Incoming, FrameKind::Binary, b"error")));
+        assert!(FrameFilter::parse("control").matches(&frame(Direction::Incoming, FrameKind::Pong, b"")));
+        assert!(FrameFilter::parse("").is_empty());
+
+        let binary = frame(Direction::Incoming, FrameKind::Binary, &[7u8; PREVIEW_BYTES + 2]);
+        assert!(binary.body().starts_with("07 07 "));
+        assert!(binary.body().ends_with("… (2 more bytes)"));
+        assert!(binary.header().contains("← binary, 66 bytes"));
+    }
+
+    #[test]
+    fn test_console_sends_and_receives_frames() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let addr = listener.local_addr().unwrap();
+        runtime.spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = accept_async(stream).await.unwrap();
+            while let Some(Ok(message)) = socket.next().await {
+                if message.is_text() || message.is_binary() {
+                    socket.send(message).await.unwrap();
+                }
+            }
+        });
+
+        let mut console = WebSocketConsole::connect(&format!("ws://{}", addr), false, Duration::from_secs(1));
+        let mut events = Vec::new();
+        let mut wait_for = |count: usize, console: &mut WebSocketConsole| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while events.len() < count && Instant::now() < deadline {
+                events.extend(console.poll());
+                thread::sleep(Duration::from_millis(10));
+            }
+            events.clone()
+        };
+        assert_eq!(wait_for(1, &mut console), vec![ConsoleEvent::Connected]);
+        console.send(ConsoleInput::Text("hello".to_string()));
+        console.send(ConsoleInput::Ping(b"p".to_vec()));
+        let events = wait_for(5, &mut console);
+        let frames: Vec<(Direction, FrameKind, String)> = events[1..]
+            .iter()
+            .filter_map(|event| match event {
+                ConsoleEvent::Frame(frame) => Some((frame.direction, frame.kind, frame.body())),
+                _ => None,
+            })
+            .collect();
+        assert!(frames.contains(&(Direction::Incoming, FrameKind::Text, "hello".to_string())));
+        assert!(frames.contains(&(Direction::Incoming, FrameKind::Pong, "p".to_string())));
+        assert_eq!(frames.len(), 4);
+
+        console.filter = FrameFilter::parse("in pong");
+        let pongs: Vec<&Frame> = console.visible_frames().collect();
+        assert_eq!(pongs.len(), 1);
+        assert!(pongs[0].round_trip.is_some());
+    }
+}
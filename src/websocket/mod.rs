@@ -2,6 +2,8 @@
 //!
 //! This module provides real-time communication support using WebSockets.
 
+pub mod console;
+
 use futures_util::{StreamExt, SinkExt};
 use tokio::net::TcpStream;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, MaybeTlsStream, WebSocketStream};
@@ -34,6 +36,21 @@ impl WebSocketClient {
             .map_err(WebSocketError::SocketError)
     }
 
+    pub async fn send_binary(&mut self, data: &[u8]) -> Result<(), WebSocketError> {
+        self.socket
+            .send(Message::Binary(data.to_vec()))
+            .await
+            .map_err(WebSocketError::SocketError)
+    }
+
+    pub async fn send_message(&mut self, message: Message) -> Result<(), WebSocketError> {
+        self.socket.send(message).await.map_err(WebSocketError::SocketError)
+    }
+
+    pub async fn close(&mut self) -> Result<(), WebSocketError> {
+        self.socket.close(None).await.map_err(WebSocketError::SocketError)
+    }
+
     pub async fn recv(&mut self) -> Option<Result<Message, WebSocketError>> {
         self.socket.next().await.map(|res| res.map_err(WebSocketError::SocketError))
     }
@@ -0,0 +1,117 @@
+//! Windows Subsystem for Linux
+//!
+//! On Windows, panes run in a ConPTY pseudo-console, which is what
+//! `portable_pty`'s native PTY system opens there. A pane can also target a
+//! WSL distribution: its shell is started through `wsl.exe`, reports Linux
+//! paths such as `/home/me/project`, and is `cd`'d with Linux paths. Code
+//! running on the Windows side — git status, prompt chips, file completions
+//! — needs the same directory as a Windows path, `\\wsl.localhost\Ubuntu\
+//! home\me\project`, and Windows drives appear in WSL under `/mnt`. This
+//! module translates between the two.
+
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+/// Env vars passed from Windows into the distro; `/u` forwards them only
+/// from Windows to WSL. Shell integration relies on `PROMPT_COMMAND`.
+pub const WSLENV: &str = "TERM_PROGRAM/u:PROMPT_COMMAND/u";
+
+#[derive(Error, Debug)]
+pub enum WslError {
+    #[error("WSL is not available: {0}")]
+    Unavailable(std::io::Error),
+    #[error("wsl.exe failed: {0}")]
+    Command(String),
+}
+
+/// The distros installed, as listed by `wsl.exe --list --quiet`.
+pub fn list_distros() -> Result<Vec<String>, WslError> {
+    let output = Command::new("wsl.exe").args(["--list", "--quiet"]).output().map_err(WslError::Unavailable)?;
+    if !output.status.success() {
+        return Err(WslError::Command(decode_output(&output.stderr).trim().to_string()));
+    }
+    Ok(parse_distro_list(&output.stdout))
+}
+
+/// `wsl.exe` writes UTF-16LE unless `WSL_UTF8=1` is set.
+fn decode_output(bytes: &[u8]) -> String {
+    let utf16 = bytes.starts_with(&[0xFF, 0xFE]) || (bytes.len() >= 2 && bytes[1] == 0);
+    if !utf16 {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+    String::from_utf16_lossy(&units).trim_start_matches('\u{feff}').to_string()
+}
+
+pub fn parse_distro_list(output: &[u8]) -> Vec<String> {
+    decode_output(output).lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect()
+}
+
+/// The Linux path inside `distro` for a Windows path: drive paths map to
+/// `/mnt/<drive>` and `\\wsl.localhost\<distro>` paths to the distro's own
+/// filesystem. `None` for paths WSL cannot see, such as network shares or
+/// another distro's files.
+pub fn to_wsl_path(path: &str, distro: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        let rest = path[2..].trim_end_matches('/');
+        return Some(format!("/mnt/{}{}", (bytes[0] as char).to_ascii_lowercase(), rest));
+    }
+    let unc = path.strip_prefix("//wsl.localhost/").or_else(|| path.strip_prefix("//wsl$/"))?;
+    let (name, rest) = unc.split_once('/').unwrap_or((unc, ""));
+    if !name.eq_ignore_ascii_case(distro) {
+        return None;
+    }
+    Some(format!("/{}", rest.trim_end_matches('/')))
+}
+
+/// The Windows path for a Linux path inside `distro`. `None` for relative
+/// paths.
+pub fn to_windows_path(path: &str, distro: &str) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    if let Some(drive_path) = rest.strip_prefix("mnt/") {
+        let (drive, rest) = drive_path.split_once('/').unwrap_or((drive_path, ""));
+        if drive.len() == 1 && drive.as_bytes()[0].is_ascii_alphabetic() {
+            return Some(format!("{}:\\{}", drive.to_ascii_uppercase(), rest.replace('/', "\\")));
+        }
+    }
+    Some(format!(r"\\wsl.localhost\{}\{}", distro, rest.replace('/', "\\")))
+}
+
+/// The `wsl.exe` arguments that start a login shell in `distro`, in `cwd`
+/// if it is given as a Linux path.
+pub fn shell_args(distro: &str, cwd: Option<&Path>) -> Vec<String> {
+    let cwd = cwd.map_or_else(|| "~".to_string(), |cwd| cwd.display().to_string());
+    vec!["--distribution".to_string(), distro.to_string(), "--cd".to_string(), cwd]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_translate_both_ways() {
+        assert_eq!(to_wsl_path(r"C:\Users\me\src", "Ubuntu").as_deref(), Some("/mnt/c/Users/me/src"));
+        assert_eq!(to_wsl_path("D:/", "Ubuntu").as_deref(), Some("/mnt/d"));
+        assert_eq!(to_wsl_path(r"\\wsl.localhost\Ubuntu\home\me", "ubuntu").as_deref(), Some("/home/me"));
+        assert_eq!(to_wsl_path(r"\\wsl$\Ubuntu", "Ubuntu").as_deref(), Some("/"));
+        assert_eq!(to_wsl_path(r"\\wsl.localhost\Debian\home\me", "Ubuntu"), None);
+        assert_eq!(to_wsl_path(r"\\fileserver\share", "Ubuntu"), None);
+
+        assert_eq!(to_windows_path("/mnt/c/Users/me/src", "Ubuntu").as_deref(), Some(r"C:\Users\me\src"));
+        assert_eq!(to_windows_path("/home/me/project", "Ubuntu").as_deref(), Some(r"\\wsl.localhost\Ubuntu\home\me\project"));
+        // A directory under /mnt that is not a drive stays in the distro.
+        assert_eq!(to_windows_path("/mnt/wslg", "Ubuntu").as_deref(), Some(r"\\wsl.localhost\Ubuntu\mnt\wslg"));
+        assert_eq!(to_windows_path("src", "Ubuntu"), None);
+    }
+
+    #[test]
+    fn test_distro_list_is_decoded() {
+        let utf16: Vec<u8> = "\u{feff}Ubuntu-22.04\r\ndocker-desktop\r\n\r\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(parse_distro_list(&utf16), vec!["Ubuntu-22.04", "docker-desktop"]);
+        assert_eq!(parse_distro_list(b"Debian\nkali-linux\n"), vec!["Debian", "kali-linux"]);
+        assert!(parse_distro_list(b"").is_empty());
+    }
+}